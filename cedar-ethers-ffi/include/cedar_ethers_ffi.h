@@ -0,0 +1,74 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+/*
+ * C ABI of the Cedar engine, with the u256 and address extensions.
+ *
+ * Functions which can fail return NULL and, if `error` is not NULL, set
+ * `*error` to a message, which the caller frees with `cedar_string_free`.
+ * Handles may be shared between threads, as long as they are not freed while
+ * in use.
+ */
+
+#ifndef CEDAR_ETHERS_FFI_H
+#define CEDAR_ETHERS_FFI_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A parsed policy set */
+typedef struct CedarPolicySet CedarPolicySet;
+
+/* Loaded entities, with the schema they were parsed with */
+typedef struct CedarEntities CedarEntities;
+
+/*
+ * Parse a policy set in the Cedar syntax, whose policies have the ids
+ * policy0, policy1 and so on, in order.
+ */
+CedarPolicySet *cedar_policy_set_new(const char *policies, char **error);
+
+/* Free a policy set. Does nothing if `policies` is NULL. */
+void cedar_policy_set_free(CedarPolicySet *policies);
+
+/*
+ * Load entities in the JSON format, parsed with `schema`, a schema in the
+ * JSON format, unless it is NULL. The schema is also used to parse the
+ * contexts of requests authorized with the entities.
+ */
+CedarEntities *cedar_entities_new(const char *entities, const char *schema, char **error);
+
+/* Free entities. Does nothing if `entities` is NULL. */
+void cedar_entities_free(CedarEntities *entities);
+
+/*
+ * Authorize a request, a JSON object with the optional fields `principal`,
+ * `action`, `resource` and `context`, returning the response as a JSON object
+ * like {"decision":"Deny","diagnostics":{"reason":["policy0"],"errors":[]}},
+ * which the caller frees with `cedar_string_free`.
+ */
+char *cedar_authorize(const CedarPolicySet *policies, const CedarEntities *entities,
+                      const char *request, char **error);
+
+/* Free a string returned by this library. Does nothing if `s` is NULL. */
+void cedar_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CEDAR_ETHERS_FFI_H */
@@ -0,0 +1,307 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A C ABI for embedding the Cedar engine, with the `u256` and `address`
+//! extensions, in programs written in C or C++, such as signing daemons.
+//! The declarations are in `include/cedar_ethers_ffi.h`.
+//!
+//! A policy set is parsed once with [`cedar_policy_set_new`], and entities
+//! are loaded once with [`cedar_entities_new`], and then any number of
+//! requests are authorized against them with [`cedar_authorize`], which
+//! takes and returns JSON. The handles are freed with
+//! [`cedar_policy_set_free`] and [`cedar_entities_free`], and the strings
+//! returned by this library with [`cedar_string_free`].
+//!
+//! Functions which can fail return `NULL` and, if their `error` argument is
+//! not `NULL`, set `*error` to a message, which the caller frees with
+//! [`cedar_string_free`]. Panics are caught and reported in the same way,
+//! rather than unwinding into the caller.
+#![deny(unsafe_op_in_unsafe_fn, clippy::undocumented_unsafe_blocks)]
+#![warn(rust_2018_idioms, clippy::pedantic, clippy::nursery)]
+#![deny(missing_docs, missing_debug_implementations)]
+
+use std::{
+    ffi::{c_char, CStr, CString},
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr,
+    str::FromStr,
+};
+
+use cedar_policy::{
+    frontend::is_authorized::InterfaceResponse, Authorizer, Context, Entities, EntityUid,
+    PolicySet, Request, Schema,
+};
+use serde::Deserialize;
+
+/// A parsed policy set
+#[derive(Debug)]
+pub struct CedarPolicySet(PolicySet);
+
+/// Loaded entities, with the schema they were parsed with, which is also used
+/// to parse the contexts of requests
+#[derive(Debug)]
+pub struct CedarEntities {
+    entities: Entities,
+    schema: Option<Schema>,
+}
+
+/// A request in the JSON format taken by [`cedar_authorize`]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonRequest {
+    #[serde(default)]
+    principal: Option<serde_json::Value>,
+    #[serde(default)]
+    action: Option<serde_json::Value>,
+    #[serde(default)]
+    resource: Option<serde_json::Value>,
+    #[serde(default)]
+    context: Option<serde_json::Value>,
+}
+
+/// Run `f`, reporting its error, or a panic, in `error`
+///
+/// # Safety
+///
+/// `error` must be `NULL` or valid for writes.
+unsafe fn run<T>(error: *mut *mut c_char, f: impl FnOnce() -> Result<T, String>) -> Option<T> {
+    let result = catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(ToString::to_string)
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        Err(format!("internal error: {message}"))
+    });
+    match result {
+        Ok(value) => Some(value),
+        Err(message) => {
+            if !error.is_null() {
+                // SAFETY: `error` is not null, and the caller guarantees that
+                // it is valid for writes
+                unsafe { *error = into_c_string(&message) };
+            }
+            None
+        }
+    }
+}
+
+/// `s` as a string to return to C, which is freed with [`cedar_string_free`]
+fn into_c_string(s: &str) -> *mut c_char {
+    // interior nul bytes cannot occur in the JSON and messages returned, but
+    // replace them rather than fail
+    CString::new(s.replace('\0', "\u{FFFD}"))
+        .unwrap_or_default()
+        .into_raw()
+}
+
+/// The string argument `name` at `s`
+///
+/// # Safety
+///
+/// `s` must be `NULL` or a nul-terminated string which outlives `'a`.
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err(format!("`{name}` is NULL"));
+    }
+    // SAFETY: `s` is not null, and the caller guarantees that it is a
+    // nul-terminated string
+    unsafe { CStr::from_ptr(s) }
+        .to_str()
+        .map_err(|e| format!("`{name}` is not UTF-8: {e}"))
+}
+
+/// Parse the policy set `policies`, in the Cedar syntax, whose policies have
+/// the ids `policy0`, `policy1` and so on, in order. Returns `NULL` if it
+/// does not parse.
+///
+/// # Safety
+///
+/// `policies` must be a nul-terminated string, and `error` must be `NULL` or
+/// valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn cedar_policy_set_new(
+    policies: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut CedarPolicySet {
+    // SAFETY: the caller guarantees that `error` is `NULL` or valid for
+    // writes, and that `policies` is a nul-terminated string
+    unsafe {
+        run(error, || {
+            let policies = str_arg(policies, "policies")?;
+            PolicySet::from_str(policies)
+                .map(|policies| Box::into_raw(Box::new(CedarPolicySet(policies))))
+                .map_err(|e| format!("failed to parse policies: {e}"))
+        })
+    }
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Free a policy set returned by [`cedar_policy_set_new`]. Does nothing if
+/// `policies` is `NULL`.
+///
+/// # Safety
+///
+/// `policies` must be `NULL` or returned by [`cedar_policy_set_new`], and not
+/// already freed.
+#[no_mangle]
+pub unsafe extern "C" fn cedar_policy_set_free(policies: *mut CedarPolicySet) {
+    if !policies.is_null() {
+        // SAFETY: the caller guarantees that `policies` was returned by
+        // `cedar_policy_set_new`, so was made by `Box::into_raw`, and is not
+        // already freed
+        drop(unsafe { Box::from_raw(policies) });
+    }
+}
+
+/// Load the entities `entities`, in the JSON format. Returns `NULL` if the
+/// entities or schema do not parse.
+///
+/// Unless it is `NULL`, the entities are parsed with the schema `schema`, in
+/// the JSON format, which is also used to parse the contexts of requests
+/// authorized with the entities.
+///
+/// # Safety
+///
+/// `entities` must be a nul-terminated string, `schema` must be `NULL` or a
+/// nul-terminated string, and `error` must be `NULL` or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn cedar_entities_new(
+    entities: *const c_char,
+    schema: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut CedarEntities {
+    // SAFETY: the caller guarantees that `error` is `NULL` or valid for
+    // writes, that `entities` is a nul-terminated string, and that `schema`
+    // is `NULL` or a nul-terminated string
+    unsafe {
+        run(error, || {
+            let entities = str_arg(entities, "entities")?;
+            let schema = if schema.is_null() {
+                None
+            } else {
+                let schema = str_arg(schema, "schema")?;
+                Some(Schema::from_str(schema).map_err(|e| format!("failed to parse schema: {e}"))?)
+            };
+            let entities = Entities::from_json_str(entities, schema.as_ref())
+                .map_err(|e| format!("failed to parse entities: {e}"))?;
+            Ok(Box::into_raw(Box::new(CedarEntities { entities, schema })))
+        })
+    }
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Free entities returned by [`cedar_entities_new`]. Does nothing if
+/// `entities` is `NULL`.
+///
+/// # Safety
+///
+/// `entities` must be `NULL` or returned by [`cedar_entities_new`], and not
+/// already freed.
+#[no_mangle]
+pub unsafe extern "C" fn cedar_entities_free(entities: *mut CedarEntities) {
+    if !entities.is_null() {
+        // SAFETY: the caller guarantees that `entities` was returned by
+        // `cedar_entities_new`, so was made by `Box::into_raw`, and is not
+        // already freed
+        drop(unsafe { Box::from_raw(entities) });
+    }
+}
+
+/// Authorize the request `request` against `policies` and `entities`.
+///
+/// The request is a JSON object with the optional fields `principal`,
+/// `action` and `resource`, which are entity uids like
+/// `{ "type": "Address", "id": "0x..." }`, and `context`, which is a JSON
+/// object in the format of entity attributes, with extension values like
+/// `{ "__extn": { "fn": "u256", "arg": "1000" } }`.
+///
+/// Returns the response as a JSON object like
+/// `{ "decision": "Deny", "diagnostics": { "reason": ["policy0"], "errors": [] } }`,
+/// which the caller frees with [`cedar_string_free`], or `NULL` if the
+/// request does not parse.
+///
+/// # Safety
+///
+/// `policies` and `entities` must be returned by [`cedar_policy_set_new`]
+/// and [`cedar_entities_new`], and not freed, `request` must be a
+/// nul-terminated string, and `error` must be `NULL` or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn cedar_authorize(
+    policies: *const CedarPolicySet,
+    entities: *const CedarEntities,
+    request: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut c_char {
+    // SAFETY: the caller guarantees that `error` is `NULL` or valid for
+    // writes, that `request` is a nul-terminated string, and that `policies`
+    // and `entities` are live handles if they are not null
+    unsafe {
+        run(error, || {
+            let policies = policies
+                .as_ref()
+                .ok_or_else(|| "`policies` is NULL".to_string())?;
+            let entities = entities
+                .as_ref()
+                .ok_or_else(|| "`entities` is NULL".to_string())?;
+            let request: JsonRequest = serde_json::from_str(str_arg(request, "request")?)
+                .map_err(|e| format!("failed to parse request: {e}"))?;
+            let uid = |json: Option<serde_json::Value>, name: &str| {
+                json.map(|json| {
+                    EntityUid::from_json(json).map_err(|e| format!("failed to parse {name}: {e}"))
+                })
+                .transpose()
+            };
+            let principal = uid(request.principal, "principal")?;
+            let action = uid(request.action, "action")?;
+            let resource = uid(request.resource, "resource")?;
+            let context = match request.context {
+                None => Context::empty(),
+                Some(json) => Context::from_json_value(
+                    json,
+                    entities
+                        .schema
+                        .as_ref()
+                        .and_then(|schema| Some((schema, action.as_ref()?))),
+                )
+                .map_err(|e| format!("failed to parse context: {e}"))?,
+            };
+            let request = Request::new(principal, action, resource, context);
+            let response: InterfaceResponse = Authorizer::new()
+                .is_authorized(&request, &policies.0, &entities.entities)
+                .into();
+            serde_json::to_string(&response)
+                .map(|response| into_c_string(&response))
+                .map_err(|e| format!("failed to serialize response: {e}"))
+        })
+    }
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Free a string returned by this library. Does nothing if `s` is `NULL`.
+///
+/// # Safety
+///
+/// `s` must be `NULL` or returned by this library, and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn cedar_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        // SAFETY: the caller guarantees that `s` was returned by this
+        // library, so was made by `CString::into_raw`, and is not already
+        // freed
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
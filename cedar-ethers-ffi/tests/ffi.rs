@@ -0,0 +1,113 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    ffi::{c_char, CStr, CString},
+    ptr,
+};
+
+use cedar_ethers_ffi::*;
+use serde_json::{json, Value};
+
+const POLICIES: &str = r#"
+forbid (principal, action == Action::"0x095ea7b3", resource)
+when { context.args.arg_1.u256GreaterThanOrEqual(u256("0x8000000000000000000000000000000000000000000000000000000000000000")) };
+permit (principal, action, resource);
+"#;
+
+fn c_string(s: &str) -> CString {
+    CString::new(s).unwrap_or_default()
+}
+
+/// Take the string `s` returned by the library
+fn take(s: *mut c_char) -> String {
+    assert!(!s.is_null());
+    // SAFETY: `s` is a string returned by the library, which is freed once
+    let taken = unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned();
+    // SAFETY: as above
+    unsafe { cedar_string_free(s) };
+    taken
+}
+
+fn approval(amount: &str) -> CString {
+    c_string(
+        &json!({
+            "principal": { "type": "Address", "id": "0x7c3250001bc0abeeef91f52e9054a9f951190132" },
+            "action": { "type": "Action", "id": "0x095ea7b3" },
+            "resource": { "type": "Address", "id": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48" },
+            "context": { "args": { "arg_1": { "__extn": { "fn": "u256", "arg": amount } } } }
+        })
+        .to_string(),
+    )
+}
+
+#[test]
+fn authorize() {
+    let mut error = ptr::null_mut();
+    // SAFETY: the arguments are nul-terminated strings and a valid pointer to
+    // write an error to, and the handles are freed once, after their last use
+    unsafe {
+        let policies = cedar_policy_set_new(c_string(POLICIES).as_ptr(), &mut error);
+        let entities = cedar_entities_new(c_string("[]").as_ptr(), ptr::null(), &mut error);
+        assert!(error.is_null());
+
+        let decision = |amount: &str| {
+            let response = take(cedar_authorize(
+                policies,
+                entities,
+                approval(amount).as_ptr(),
+                ptr::null_mut(),
+            ));
+            serde_json::from_str::<Value>(&response).unwrap_or_default()
+        };
+        assert_eq!(
+            decision("100"),
+            json!({ "decision": "Allow", "diagnostics": { "reason": ["policy1"], "errors": [] } })
+        );
+        assert_eq!(
+            decision("0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"),
+            json!({ "decision": "Deny", "diagnostics": { "reason": ["policy0"], "errors": [] } })
+        );
+
+        cedar_policy_set_free(policies);
+        cedar_entities_free(entities);
+    }
+}
+
+#[test]
+fn errors() {
+    let mut error = ptr::null_mut();
+    // SAFETY: the arguments are nul-terminated strings, null, and a valid
+    // pointer to write an error to
+    unsafe {
+        let policies = cedar_policy_set_new(c_string("permit(").as_ptr(), &mut error);
+        assert!(policies.is_null());
+        assert!(take(error).starts_with("failed to parse policies"));
+
+        let entities = cedar_entities_new(ptr::null(), ptr::null(), &mut error);
+        assert!(entities.is_null());
+        assert_eq!(take(error), "`entities` is NULL");
+
+        let response = cedar_authorize(
+            ptr::null(),
+            ptr::null(),
+            approval("100").as_ptr(),
+            &mut error,
+        );
+        assert!(response.is_null());
+        assert_eq!(take(error), "`policies` is NULL");
+    }
+}
@@ -0,0 +1,166 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The `bench` command, which measures how long loading a policy set and
+//! entities takes, and the latency and allocations of authorizing requests
+//! against them.
+//!
+//! Allocations are counted by the allocator of the `cedar` executable, which
+//! is instrumented with `stats_alloc`. When this library is used with another
+//! allocator, they are reported as unavailable.
+
+use std::{
+    io::BufRead,
+    time::{Duration, Instant},
+};
+
+use cedar_policy::*;
+use miette::{IntoDiagnostic, Result, WrapErr};
+use stats_alloc::{Region, Stats, INSTRUMENTED_SYSTEM};
+
+use super::{
+    add_template_links_to_set, load_actions_from_schema, load_entities, read_policy_set,
+    read_schema_file, BenchArgs, CedarExitCode, RequestJSON,
+};
+
+/// Run `f`, returning its result, how long it took, and the allocations it
+/// made
+fn measure<T>(f: impl FnOnce() -> T) -> (T, Duration, Stats) {
+    let region = Region::new(&INSTRUMENTED_SYSTEM);
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+    (result, elapsed, region.change())
+}
+
+/// Describe the allocations of `stats`, spread over `runs` runs
+fn allocations(stats: Stats, runs: usize) -> String {
+    if stats.allocations == 0 {
+        return "allocations not counted".to_string();
+    }
+    let runs = runs.max(1);
+    format!(
+        "{} allocations ({} bytes)",
+        stats.allocations / runs,
+        stats.bytes_allocated / runs
+    )
+}
+
+/// The `p`th percentile of the sorted `samples`
+fn percentile(samples: &[Duration], p: usize) -> Duration {
+    let index = samples.len().saturating_sub(1) * p / 100;
+    samples.get(index).copied().unwrap_or_default()
+}
+
+/// Read the requests of the `authorize-batch` file `filename`
+fn read_requests(filename: &str, schema: Option<&Schema>) -> Result<Vec<Request>> {
+    let file = std::fs::File::open(filename)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to open requests file {filename}"))?;
+    let mut requests = Vec::new();
+    for (index, line) in std::io::BufReader::new(file).lines().enumerate() {
+        let line = line
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to read requests file {filename}"))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let source = format!("{filename} line {}", index + 1);
+        let request = serde_json::from_str::<RequestJSON>(&line)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to parse request in {source}"))?
+            .into_request(schema, &source)?;
+        requests.push(request);
+    }
+    Ok(requests)
+}
+
+fn bench_inner(args: &BenchArgs) -> Result<()> {
+    let schema = args
+        .schema_file
+        .as_ref()
+        .map(read_schema_file)
+        .transpose()?;
+
+    let (policies, parse_time, parse_allocs) =
+        measure(|| read_policy_set(Some(args.policies_file.as_str())));
+    let mut policies = policies?;
+    println!(
+        "parse:      {} policies and {} templates in {parse_time:?}, {}",
+        policies.policies().count(),
+        policies.templates().count(),
+        allocations(parse_allocs, 1)
+    );
+
+    if let Some(links_file) = &args.template_linked_file {
+        let before = policies.policies().count();
+        let (linked, link_time, link_allocs) =
+            measure(|| add_template_links_to_set(links_file, &mut policies));
+        linked?;
+        println!(
+            "link:       {} links in {link_time:?}, {}",
+            policies.policies().count() - before,
+            allocations(link_allocs, 1)
+        );
+    }
+
+    let (entities, load_time, load_allocs) =
+        measure(|| load_entities(&args.entities_file, schema.as_ref()));
+    let entities = load_actions_from_schema(entities?, &schema)?;
+    println!(
+        "entities:   {} entities in {load_time:?}, {}",
+        entities.iter().count(),
+        allocations(load_allocs, 1)
+    );
+
+    let requests = read_requests(&args.requests_file, schema.as_ref())?;
+    let authorizer = Authorizer::new();
+    let iterations = args.iterations.get();
+    let mut samples = Vec::with_capacity(requests.len() * iterations);
+    let region = Region::new(&INSTRUMENTED_SYSTEM);
+    for request in &requests {
+        for _ in 0..iterations {
+            let start = Instant::now();
+            let response = authorizer.is_authorized(request, &policies, &entities);
+            samples.push(start.elapsed());
+            drop(response);
+        }
+    }
+    // the samples vector was allocated up front, so only authorization
+    // allocates here
+    let eval_allocs = region.change();
+    samples.sort();
+    println!(
+        "evaluate:   {} requests x {iterations}: p50 {:?}, p99 {:?}, max {:?}, {} per request",
+        requests.len(),
+        percentile(&samples, 50),
+        percentile(&samples, 99),
+        samples.last().copied().unwrap_or_default(),
+        allocations(eval_allocs, samples.len())
+    );
+    Ok(())
+}
+
+/// Run the `bench` command
+pub fn bench(args: &BenchArgs) -> CedarExitCode {
+    match bench_inner(args) {
+        Ok(()) => CedarExitCode::Success,
+        Err(err) => {
+            println!("Error: {err:?}");
+            CedarExitCode::Failure
+        }
+    }
+}
@@ -0,0 +1,191 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The `differential` command, which authorizes the requests of test files
+//! both with this fork and with an upstream `cedar` executable, and reports
+//! the requests for which the decisions differ.
+//!
+//! Upstream is run as a child process, with `cedar authorize` and the files
+//! named by the test file, so that it can be any build of the upstream CLI.
+//! Its decision is read from its exit code: 0 for `Allow` and 2 for `Deny`.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use cedar_policy::{
+    conformance::{TestCase, TestRequest},
+    *,
+};
+use miette::{miette, IntoDiagnostic, Result, WrapErr};
+
+use super::{
+    test_runner::{discover, load, load_files, Files},
+    watch::Colors,
+    CedarExitCode, DifferentialArgs,
+};
+
+/// How the decisions of this fork and of upstream compare for a request
+enum Comparison {
+    Same(Decision),
+    Differ { fork: Decision, upstream: Decision },
+}
+
+/// Authorize `request` of `test`, parsed as `parsed`, with the `upstream`
+/// executable, writing its context to `context_file`
+fn upstream_decision(
+    upstream: &str,
+    test: &TestCase,
+    root: &Path,
+    (request, parsed): (&TestRequest, &Request),
+    context_file: &Path,
+) -> Result<Decision> {
+    let mut cmd = Command::new(upstream);
+    cmd.arg("authorize")
+        .arg("--policies")
+        .arg(root.join(&test.policies))
+        .arg("--entities")
+        .arg(root.join(&test.entities));
+    if let Some(schema) = &test.schema {
+        cmd.arg("--schema").arg(root.join(schema));
+    }
+    for (flag, uid) in [
+        ("--principal", parsed.principal()),
+        ("--action", parsed.action()),
+        ("--resource", parsed.resource()),
+    ] {
+        if let Some(uid) = uid {
+            cmd.arg(flag).arg(uid.to_string());
+        }
+    }
+    if let Some(context) = &request.context {
+        std::fs::write(context_file, context.to_string())
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to write {}", context_file.display()))?;
+        cmd.arg("--context").arg(context_file);
+    }
+    let output = cmd
+        .output()
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to run {upstream}"))?;
+    match output.status.code() {
+        Some(0) => Ok(Decision::Allow),
+        Some(2) => Ok(Decision::Deny),
+        _ => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Err(miette!(
+                "upstream failed ({}): {}",
+                output.status,
+                format!("{stdout}{stderr}").trim()
+            ))
+        }
+    }
+}
+
+/// Authorize `request` with this fork and with upstream
+fn compare(
+    args: &DifferentialArgs,
+    test: &TestCase,
+    files: &Files,
+    root: &Path,
+    request: &TestRequest,
+    context_file: &Path,
+) -> Result<Comparison> {
+    let parsed = request.request(files.schema.as_ref()).into_diagnostic()?;
+    let fork = Authorizer::new()
+        .is_authorized(&parsed, &files.policies, &files.entities)
+        .decision();
+    let upstream = upstream_decision(&args.upstream, test, root, (request, &parsed), context_file)?;
+    Ok(if fork == upstream {
+        Comparison::Same(fork)
+    } else {
+        Comparison::Differ { fork, upstream }
+    })
+}
+
+pub(super) fn differential(args: &DifferentialArgs) -> CedarExitCode {
+    let paths = match discover(&args.paths) {
+        Ok(paths) => paths,
+        Err(e) => {
+            println!("Error: {e:?}");
+            return CedarExitCode::Failure;
+        }
+    };
+    // removed when dropped, even if a run fails
+    let context_file = match tempfile::Builder::new()
+        .prefix("cedar-differential-")
+        .suffix(".json")
+        .tempfile()
+    {
+        Ok(file) => file,
+        Err(e) => {
+            println!("Error: failed to create a temporary file: {e}");
+            return CedarExitCode::Failure;
+        }
+    };
+    let colors = Colors::detect();
+    let (mut same, mut differ, mut errors) = (0, 0, 0);
+    for path in &paths {
+        let root = match &args.root {
+            Some(root) => PathBuf::from(root),
+            None => path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        };
+        let loaded = load(path).and_then(|test| {
+            let files = load_files(&test, &root)
+                .wrap_err_with(|| format!("failed to load the files of {}", path.display()))?;
+            Ok((test, files))
+        });
+        let (test, files) = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                println!("{} {}", colors.red("ERROR"), path.display());
+                println!("  Error: {e:?}");
+                errors += 1;
+                continue;
+            }
+        };
+        for request in &test.requests {
+            let name = format!("{}: {}", path.display(), request.desc);
+            match compare(args, &test, &files, &root, request, context_file.path()) {
+                Ok(Comparison::Same(decision)) => {
+                    println!("{} {name} ({decision:?})", colors.green("SAME"));
+                    same += 1;
+                }
+                Ok(Comparison::Differ { fork, upstream }) => {
+                    println!("{} {name}", colors.red("DIFFER"));
+                    println!("  this fork: {fork:?}, upstream: {upstream:?}");
+                    differ += 1;
+                }
+                Err(e) => {
+                    println!("{} {name}", colors.red("ERROR"));
+                    println!("  Error: {e:?}");
+                    errors += 1;
+                }
+            }
+        }
+    }
+    if paths.is_empty() {
+        println!("No test files found");
+    }
+    println!("{same} same, {differ} differ, {errors} errors");
+    if differ == 0 && errors == 0 {
+        CedarExitCode::Success
+    } else {
+        CedarExitCode::Failure
+    }
+}
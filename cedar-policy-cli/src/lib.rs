@@ -19,7 +19,15 @@
 // omitted.
 #![allow(clippy::needless_return)]
 
+mod bench;
+mod differential;
 mod err;
+#[cfg(feature = "proxy")]
+mod proxy;
+mod repl;
+mod server;
+mod test_runner;
+mod watch;
 
 use clap::{Args, Parser, Subcommand, ValueEnum};
 use miette::{miette, IntoDiagnostic, NamedSource, Report, Result, WrapErr};
@@ -28,12 +36,17 @@ use std::{
     collections::HashMap,
     fmt::{self, Display},
     fs::OpenOptions,
-    path::Path,
+    io::{BufRead, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
     process::{ExitCode, Termination},
     str::FromStr,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
+use cedar_policy::bundle::{BundleLink, PolicyBundle};
+use cedar_policy::export::{export_policies, export_schema, ExportIssue};
+use cedar_policy::rego::{self, ImportIssue};
 use cedar_policy::*;
 use cedar_policy_formatter::{policies_str_to_pretty, Config};
 
@@ -82,10 +95,25 @@ impl Display for ErrorFormat {
     }
 }
 
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text.
+    #[default]
+    Human,
+    /// A JSON object, whose shape is described in the README.
+    Json,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     /// Evaluate an authorization request
     Authorize(AuthorizeArgs),
+    /// Evaluate each authorization request in a file of newline-delimited
+    /// JSON requests, writing one JSON decision per line
+    AuthorizeBatch(AuthorizeBatchArgs),
+    /// Explain the decision for an authorization request, showing how each
+    /// policy evaluated
+    Explain(ExplainArgs),
     /// Evaluate a Cedar expression
     Evaluate(EvaluateArgs),
     /// Validate a policy set against a schema
@@ -95,9 +123,64 @@ pub enum Commands {
     /// Link a template
     Link(LinkArgs),
     /// Format a policy set
+    #[command(alias = "fmt")]
     Format(FormatArgs),
     /// Create a Cedar project
     New(NewArgs),
+    /// Link a template, giving the entities to fill its slots with as
+    /// `--param SLOT=ENTITY`
+    NewLink(NewLinkArgs),
+    /// Write a new policy, starting from one of the builtin policies
+    NewPolicy(NewPolicyArgs),
+    /// Generate a template entities file from a schema
+    Skeleton(SkeletonArgs),
+    /// Write a binary snapshot of an entities file, which other commands load
+    /// in place of the JSON file without parsing it
+    SnapshotEntities(SnapshotEntitiesArgs),
+    /// Write a policy set, its template links and its schema to a single
+    /// bundle file, with a manifest of their hashes
+    Bundle(BundleArgs),
+    /// Check that the files of a bundle match the hashes of its manifest, and
+    /// that its policies, links and schema load
+    VerifyBundle(VerifyBundleArgs),
+    /// Translate a schema between the JSON and human-readable formats
+    TranslateSchema(TranslateSchemaArgs),
+    /// Translate a policy set between the JSON and Cedar formats
+    TranslatePolicy(TranslatePolicyArgs),
+    /// List the changes between two versions of a schema, and whether they
+    /// are breaking for existing policies and entity files
+    DiffSchema(DiffSchemaArgs),
+    /// List the entity types, actions and attributes of a schema which no
+    /// policy uses, and the attributes used by policies but not declared
+    CheckSchemaUsage(CheckSchemaUsageArgs),
+    /// Check policies for style problems and common mistakes
+    Lint(LintArgs),
+    /// Convert a policy set, and its schema, for upstream Cedar and Amazon
+    /// Verified Permissions, reporting what could not be converted
+    Export(ExportArgs),
+    /// Convert the `allow` and `deny` rules of a Rego module to policies,
+    /// reporting the rules which could not be converted
+    ImportRego(ImportRegoArgs),
+    /// Start an interactive session for trying requests and expressions
+    /// against a policy set
+    Repl(ReplArgs),
+    /// Run an HTTP server which answers authorization requests against a
+    /// policy set
+    Server(ServerArgs),
+    /// Run a JSON-RPC proxy in front of an Ethereum node, which authorizes
+    /// every call against a policy set, with API keys as principals, and
+    /// forwards the allowed ones
+    #[cfg(feature = "proxy")]
+    Proxy(ProxyArgs),
+    /// Run the policy tests in test files, or in the test files found in
+    /// directories
+    Test(TestArgs),
+    /// Authorize the requests of test files with this fork and with an
+    /// upstream `cedar` executable, and report where their decisions differ
+    Differential(DifferentialArgs),
+    /// Measure the time and allocations taken to parse and link a policy set,
+    /// load entities, and authorize requests against them
+    Bench(BenchArgs),
 }
 
 #[derive(Args, Debug)]
@@ -108,6 +191,22 @@ pub struct ValidateArgs {
     /// File containing the policy set
     #[arg(short, long = "policies", value_name = "FILE")]
     pub policies_file: String,
+    /// Report the warning with this code as an error, e.g., `impossible-policy`.
+    /// May be given more than once.
+    #[arg(long = "deny", value_name = "CODE")]
+    pub denied_warnings: Vec<WarningCode>,
+    /// Validate again whenever the schema file, or one of the policy files
+    /// (including those it imports) changes
+    #[arg(long, overrides_with = "no_watch")]
+    pub watch: bool,
+    /// Run the command once, even if `--watch` is given before this. Each
+    /// run of `--watch` is the same command with this added.
+    #[arg(long, hide = true)]
+    pub no_watch: bool,
+    /// Format of the output. The shape of the JSON output is described in
+    /// the README.
+    #[arg(long, value_enum, default_value_t, env = "CEDAR_OUTPUT")]
+    pub output: OutputFormat,
 }
 
 #[derive(Args, Debug)]
@@ -115,6 +214,10 @@ pub struct CheckParseArgs {
     /// File containing the policy set
     #[clap(short, long = "policies", value_name = "FILE")]
     pub policies_file: Option<String>,
+    /// Format of the output. The shape of the JSON output is described in
+    /// the README.
+    #[arg(long, value_enum, default_value_t, env = "CEDAR_OUTPUT")]
+    pub output: OutputFormat,
 }
 
 /// This struct contains the arguments that together specify a request.
@@ -152,37 +255,7 @@ impl RequestArgs {
                 let qjson: RequestJSON = serde_json::from_str(&jsonstring)
                     .into_diagnostic()
                     .wrap_err_with(|| format!("failed to parse request-json file {jsonfile}"))?;
-                let principal = qjson
-                    .principal
-                    .map(|s| {
-                        s.parse().wrap_err_with(|| {
-                            format!("failed to parse principal in {jsonfile} as entity Uid")
-                        })
-                    })
-                    .transpose()?;
-                let action = qjson
-                    .action
-                    .map(|s| {
-                        s.parse().wrap_err_with(|| {
-                            format!("failed to parse action in {jsonfile} as entity Uid")
-                        })
-                    })
-                    .transpose()?;
-                let resource = qjson
-                    .resource
-                    .map(|s| {
-                        s.parse().wrap_err_with(|| {
-                            format!("failed to parse resource in {jsonfile} as entity Uid")
-                        })
-                    })
-                    .transpose()?;
-                let context = Context::from_json_value(
-                    qjson.context,
-                    schema.and_then(|s| Some((s, action.as_ref()?))),
-                )
-                .into_diagnostic()
-                .wrap_err_with(|| format!("failed to create a context from {jsonfile}"))?;
-                Ok(Request::new(principal, action, resource, context))
+                qjson.into_request(schema, jsonfile)
             }
             None => {
                 let principal = self
@@ -255,6 +328,69 @@ pub struct AuthorizeArgs {
     /// Time authorization and report timing information
     #[arg(short, long)]
     pub timing: bool,
+    /// Authorize again whenever one of the policy (including those the policy
+    /// file imports), schema, entity or request files changes
+    #[arg(long, overrides_with = "no_watch")]
+    pub watch: bool,
+    /// Run the command once, even if `--watch` is given before this. Each
+    /// run of `--watch` is the same command with this added.
+    #[arg(long, hide = true)]
+    pub no_watch: bool,
+    /// Format of the output. The shape of the JSON output is described in
+    /// the README.
+    #[arg(long, value_enum, default_value_t, env = "CEDAR_OUTPUT")]
+    pub output: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct ExplainArgs {
+    /// Request args (incorporated by reference)
+    #[command(flatten)]
+    pub request: RequestArgs,
+    /// File containing the static Cedar policies and templates to evaluate against
+    #[arg(long = "policies", value_name = "FILE")]
+    pub policies_file: String,
+    /// File containing template linked policies
+    #[arg(long = "template-linked", value_name = "FILE")]
+    pub template_linked_file: Option<String>,
+    /// File containing schema information
+    /// Used to populate the store with action entities and for schema-based
+    /// parsing of entity hierarchy, if present
+    #[arg(long = "schema", value_name = "FILE")]
+    pub schema_file: Option<String>,
+    /// File containing JSON representation of the Cedar entity hierarchy
+    #[arg(long = "entities", value_name = "FILE")]
+    pub entities_file: String,
+}
+
+#[derive(Args, Debug)]
+pub struct AuthorizeBatchArgs {
+    /// File containing one request per line, each a JSON object with the
+    /// fields of a --request-json file
+    #[arg(long = "requests", value_name = "FILE")]
+    pub requests_file: String,
+    /// File containing the static Cedar policies and templates to evaluate against
+    #[arg(long = "policies", value_name = "FILE")]
+    pub policies_file: String,
+    /// File containing template linked policies
+    #[arg(long = "template-linked", value_name = "FILE")]
+    pub template_linked_file: Option<String>,
+    /// File containing schema information
+    /// Used to populate the store with action entities and for schema-based
+    /// parsing of entity hierarchy, if present
+    #[arg(long = "schema", value_name = "FILE")]
+    pub schema_file: Option<String>,
+    /// File containing JSON representation of the Cedar entity hierarchy
+    #[arg(long = "entities", value_name = "FILE")]
+    pub entities_file: String,
+    /// Number of threads to evaluate the requests on
+    #[arg(short, long, value_name = "N", default_value = "1")]
+    pub jobs: NonZeroUsize,
+    /// After the last request, print to stderr how many requests were
+    /// allowed, denied and invalid, and how often each policy determined the
+    /// decision
+    #[arg(long)]
+    pub summary: bool,
 }
 
 #[derive(Args, Debug)]
@@ -274,6 +410,18 @@ pub struct LinkArgs {
     /// Arguments to fill slots
     #[arg(short, long)]
     pub arguments: Arguments,
+    /// Link again whenever one of the policy files (including those the
+    /// policy file imports) changes, replacing the link made before
+    #[arg(long, overrides_with = "no_watch")]
+    pub watch: bool,
+    /// Run the command once, even if `--watch` is given before this. Each
+    /// run of `--watch` is the same command with this added.
+    #[arg(long, hide = true)]
+    pub no_watch: bool,
+    /// Format of the output. The shape of the JSON output is described in
+    /// the README.
+    #[arg(long, value_enum, default_value_t, env = "CEDAR_OUTPUT")]
+    pub output: OutputFormat,
 }
 
 #[derive(Args, Debug)]
@@ -289,6 +437,11 @@ pub struct FormatArgs {
     /// Custom indentation width (default: 2).
     #[arg(short, long, value_name = "INT", default_value_t = 2)]
     pub indent_width: isize,
+
+    /// Check that the policy set is already formatted instead of printing it,
+    /// and fail if it is not.
+    #[arg(long)]
+    pub check: bool,
 }
 
 #[derive(Args, Debug)]
@@ -298,6 +451,448 @@ pub struct NewArgs {
     pub name: String,
 }
 
+#[derive(Args, Debug)]
+pub struct NewLinkArgs {
+    /// File containing the template
+    #[arg(short, long = "policies", value_name = "FILE")]
+    pub policies_file: String,
+    /// Id of the template to link
+    #[arg(long = "template", value_name = "ID")]
+    pub template_id: String,
+    /// Entity to fill a slot of the template with, e.g.,
+    /// `principal=Address::"0x..."`. May be given once for each slot.
+    #[arg(long = "param", value_name = "SLOT=ENTITY", value_parser = parse_param)]
+    pub params: Vec<(SlotId, String)>,
+    /// Id for the new template-linked policy. If none is provided, the id is
+    /// the template id followed by the first number which is not already
+    /// taken, e.g., `block-address-1`.
+    #[arg(long = "id", value_name = "ID")]
+    pub new_id: Option<String>,
+    /// File containing template-linked policies to add the new one to, which
+    /// is created if it does not exist. If none is provided, print the
+    /// template-linked policies file with just the new one.
+    #[arg(short, long = "template-linked", value_name = "FILE")]
+    pub template_linked_file: Option<String>,
+}
+
+/// Parse a `--param` of `new-link`, e.g., `principal=Address::"0x..."`
+fn parse_param(s: &str) -> Result<(SlotId, String), String> {
+    let (slot, entity) = s.split_once('=').ok_or_else(|| {
+        format!("expected SLOT=ENTITY, e.g., principal=Address::\"0x...\", got: {s}")
+    })?;
+    let slot = slot.trim();
+    let slot = parse_slot_id(format!("?{}", slot.strip_prefix('?').unwrap_or(slot)))?;
+    let entity = entity.trim();
+    EntityUid::from_str(entity)
+        .map_err(|e| format!("failed to parse {entity} as entity Uid: {e}"))?;
+    Ok((slot, entity.to_string()))
+}
+
+#[derive(Args, Debug)]
+pub struct NewPolicyArgs {
+    /// Builtin policy to start from
+    #[arg(long, value_enum, value_name = "NAME")]
+    pub from_builtin: BuiltinPolicy,
+    /// Id for the new policy, given as its `@id` annotation. If none is
+    /// provided, the id is the name of the builtin policy.
+    #[arg(long = "id", value_name = "ID")]
+    pub new_id: Option<String>,
+    /// File to add the new policy to, at its end, which is created if it does
+    /// not exist. If none is provided, print the new policy.
+    #[arg(short, long = "policies", value_name = "FILE")]
+    pub policies_file: Option<String>,
+}
+
+/// The policies and templates `new-policy` can start from
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum BuiltinPolicy {
+    /// Forbid ERC20 approvals of an unlimited amount
+    ForbidInfiniteApproval,
+    /// Forbid ERC20 transfers from or to an address in `Group::"block_list"`
+    ForbidBlockListTransfer,
+    /// Template forbidding all transactions sent by the address in the
+    /// `?principal` slot
+    BlockAddress,
+    /// Template forbidding all transactions to the contract in the
+    /// `?resource` slot
+    BlockContract,
+    /// Permit all transactions which no other policy forbids
+    PermitAll,
+}
+
+impl BuiltinPolicy {
+    /// The text of the policy, without an `@id` annotation
+    fn text(self) -> &'static str {
+        match self {
+            Self::ForbidInfiniteApproval => {
+                include_str!("../builtins/forbid-infinite-approval.cedar")
+            }
+            Self::ForbidBlockListTransfer => {
+                include_str!("../builtins/forbid-block-list-transfer.cedar")
+            }
+            Self::BlockAddress => include_str!("../builtins/block-address.cedar"),
+            Self::BlockContract => include_str!("../builtins/block-contract.cedar"),
+            Self::PermitAll => include_str!("../builtins/permit-all.cedar"),
+        }
+    }
+
+    /// The name of the policy on the command line, e.g.,
+    /// `forbid-infinite-approval`
+    fn name(self) -> String {
+        self.to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct SkeletonArgs {
+    /// File containing the schema
+    #[arg(short, long = "schema", value_name = "FILE")]
+    pub schema_file: String,
+    /// File to write the template entities to. If none is provided, write to stdout.
+    #[arg(short, long = "output", value_name = "FILE")]
+    pub output_file: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct SnapshotEntitiesArgs {
+    /// File containing JSON representation of the Cedar entity hierarchy
+    #[arg(long = "entities", value_name = "FILE")]
+    pub entities_file: String,
+    /// File containing the schema the entities are parsed with
+    #[arg(short, long = "schema", value_name = "FILE")]
+    pub schema_file: Option<String>,
+    /// File to write the snapshot to
+    #[arg(short, long = "output", value_name = "FILE")]
+    pub output_file: String,
+}
+
+#[derive(Args, Debug)]
+pub struct BundleArgs {
+    /// File containing the policy set
+    #[arg(short, long = "policies", value_name = "FILE")]
+    pub policies_file: String,
+    /// File containing the template-linked policies
+    #[arg(short = 'k', long = "template-linked", value_name = "FILE")]
+    pub template_linked_file: Option<String>,
+    /// File containing the schema
+    #[arg(short, long = "schema", value_name = "FILE")]
+    pub schema_file: Option<String>,
+    /// Name of the bundle
+    #[arg(long)]
+    pub name: String,
+    /// Version of the bundle, e.g., a semantic version or a commit
+    #[arg(long)]
+    pub version: String,
+    /// Metadata to record in the manifest, e.g., `environment=production`.
+    /// May be given more than once.
+    #[arg(long = "metadata", value_name = "KEY=VALUE", value_parser = parse_metadata)]
+    pub metadata: Vec<(String, String)>,
+    /// File to write the bundle to, conventionally ending in `.bundle.json`
+    #[arg(short, long = "output", value_name = "FILE")]
+    pub output_file: String,
+}
+
+/// Parse a `--metadata` of `bundle`, e.g., `environment=production`
+fn parse_metadata(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got `{s}`"))
+}
+
+#[derive(Args, Debug)]
+pub struct VerifyBundleArgs {
+    /// The bundle file
+    #[arg(value_name = "FILE")]
+    pub bundle_file: String,
+}
+
+#[derive(Args, Debug)]
+pub struct TranslateSchemaArgs {
+    /// Direction of the translation
+    #[arg(long, value_enum, default_value_t = TranslationDirection::JsonToHuman)]
+    pub direction: TranslationDirection,
+    /// File containing the schema. If none is provided, read input from stdin.
+    #[arg(short = 's', long = "schema", value_name = "FILE")]
+    pub input_file: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct TranslatePolicyArgs {
+    /// Direction of the translation
+    #[arg(long, value_enum, default_value_t = PolicyTranslationDirection::CedarToJson)]
+    pub direction: PolicyTranslationDirection,
+    /// File containing the policy set. If none is provided, read input from stdin.
+    #[arg(short = 'p', long = "policies", value_name = "FILE")]
+    pub input_file: Option<String>,
+    /// File containing template linked policies. It is read when translating
+    /// to JSON, and written with the links of the JSON policy set when
+    /// translating to Cedar.
+    #[arg(long = "template-linked", value_name = "FILE")]
+    pub template_linked_file: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct DiffSchemaArgs {
+    /// File containing the old version of the schema
+    #[arg(long = "old", value_name = "FILE")]
+    pub old_schema_file: String,
+    /// File containing the new version of the schema
+    #[arg(long = "new", value_name = "FILE")]
+    pub new_schema_file: String,
+}
+
+#[derive(Args, Debug)]
+pub struct CheckSchemaUsageArgs {
+    /// File containing the schema
+    #[arg(short, long = "schema", value_name = "FILE")]
+    pub schema_file: String,
+    /// File containing the policy set
+    #[arg(short, long = "policies", value_name = "FILE")]
+    pub policies_file: String,
+    /// Typecheck the policies in permissive mode rather than strict mode
+    #[arg(long)]
+    pub permissive: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct LintArgs {
+    /// File containing the policy set
+    #[arg(short, long = "policies", value_name = "FILE")]
+    pub policies_file: String,
+    /// Report the lints of this rule as errors, e.g., `unconditional-permit`.
+    /// May be given more than once.
+    #[arg(long = "deny", value_name = "RULE")]
+    pub denied_rules: Vec<String>,
+    /// Do not run this rule. May be given more than once.
+    #[arg(long = "allow", value_name = "RULE")]
+    pub allowed_rules: Vec<String>,
+    /// Annotation which `require-annotations` requires every policy to have,
+    /// e.g., `id` for `@id`, in place of the default `id` and `owner`. May be
+    /// given more than once.
+    #[arg(long = "require-annotation", value_name = "KEY")]
+    pub required_annotations: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// File containing the policy set
+    #[arg(short, long = "policies", value_name = "FILE")]
+    pub policies_file: String,
+    /// File containing template linked policies
+    #[arg(long = "template-linked", value_name = "FILE")]
+    pub template_linked_file: Option<String>,
+    /// File containing the schema, in either format
+    #[arg(
+        short,
+        long = "schema",
+        value_name = "FILE",
+        requires = "schema_output_file"
+    )]
+    pub schema_file: Option<String>,
+    /// File to write the exported policies to. If none is provided, write to
+    /// stdout.
+    #[arg(short, long = "output", value_name = "FILE")]
+    pub output_file: Option<String>,
+    /// File to write the template links of the exported policies to
+    #[arg(long = "output-template-linked", value_name = "FILE")]
+    pub output_template_linked_file: Option<String>,
+    /// File to write the exported schema to, in the JSON format
+    #[arg(long = "schema-output", value_name = "FILE", requires = "schema_file")]
+    pub schema_output_file: Option<String>,
+    /// Succeed even if some policies or parts of the schema were left out
+    #[arg(long)]
+    pub allow_dropped: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ImportRegoArgs {
+    /// File containing the Rego module
+    #[arg(value_name = "FILE")]
+    pub rego_file: String,
+    /// File to write the policies to. If none is provided, write to stdout.
+    #[arg(short, long = "output", value_name = "FILE")]
+    pub output_file: Option<String>,
+    /// Succeed even if some rules were left out
+    #[arg(long)]
+    pub allow_dropped: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ReplArgs {
+    /// File containing the static Cedar policies and templates to authorize
+    /// requests against
+    #[arg(long = "policies", value_name = "FILE")]
+    pub policies_file: Option<String>,
+    /// File containing template linked policies
+    #[arg(
+        long = "template-linked",
+        value_name = "FILE",
+        requires = "policies_file"
+    )]
+    pub template_linked_file: Option<String>,
+    /// File containing schema information
+    /// Used to populate the store with action entities and for schema-based
+    /// parsing of entity hierarchy, if present
+    #[arg(long = "schema", value_name = "FILE")]
+    pub schema_file: Option<String>,
+    /// File containing JSON representation of the Cedar entity hierarchy.
+    /// This is optional; if not present, we'll just use an empty hierarchy.
+    #[arg(long = "entities", value_name = "FILE")]
+    pub entities_file: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ServerArgs {
+    /// File containing the static Cedar policies and templates to evaluate against
+    #[arg(long = "policies", value_name = "FILE")]
+    pub policies_file: String,
+    /// File containing template linked policies
+    #[arg(long = "template-linked", value_name = "FILE")]
+    pub template_linked_file: Option<String>,
+    /// File containing schema information
+    /// Used to populate the store with action entities and for schema-based
+    /// parsing of entity hierarchy, if present
+    #[arg(long = "schema", value_name = "FILE")]
+    pub schema_file: Option<String>,
+    /// File containing JSON representation of the Cedar entity hierarchy.
+    /// This is optional; if not present, only requests which give their own
+    /// entities can use any.
+    #[arg(long = "entities", value_name = "FILE")]
+    pub entities_file: Option<String>,
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1")]
+    pub host: String,
+    /// Port to listen on, or 0 for any free port
+    #[arg(long, default_value_t = 8080)]
+    pub port: u16,
+    /// Number of threads answering requests
+    #[arg(long, default_value_t = 8)]
+    pub workers: usize,
+    /// Most connections open at once, including those being answered; others
+    /// are refused with 503 Service Unavailable
+    #[arg(long, default_value_t = 64)]
+    pub max_connections: usize,
+    /// Token which `POST /reload` must give as `Authorization: Bearer <TOKEN>`.
+    /// Without one, only clients on the same host may reload.
+    #[arg(long, value_name = "TOKEN", env = "CEDAR_RELOAD_TOKEN")]
+    pub reload_token: Option<String>,
+}
+
+#[cfg(feature = "proxy")]
+#[derive(Args, Debug)]
+pub struct ProxyArgs {
+    /// File containing the static Cedar policies and templates to evaluate against
+    #[arg(long = "policies", value_name = "FILE")]
+    pub policies_file: String,
+    /// File containing template linked policies
+    #[arg(long = "template-linked", value_name = "FILE")]
+    pub template_linked_file: Option<String>,
+    /// File containing schema information
+    /// Used to populate the store with action entities and for schema-based
+    /// parsing of entity hierarchy, if present
+    #[arg(long = "schema", value_name = "FILE")]
+    pub schema_file: Option<String>,
+    /// File containing JSON representation of the Cedar entity hierarchy,
+    /// such as the groups of API keys.
+    /// This is optional; if not present, we'll just use an empty hierarchy.
+    #[arg(long = "entities", value_name = "FILE")]
+    pub entities_file: Option<String>,
+    /// URL of the JSON-RPC endpoint of the node to forward allowed calls to
+    #[arg(long, value_name = "URL")]
+    pub upstream: String,
+    /// Id of the chain of the node, for the UIDs of the contracts called
+    #[arg(long, default_value_t = 1)]
+    pub chain_id: u64,
+    /// Header holding the API key of a client. Without it, the key is the
+    /// path of the request, like `/<key>`.
+    #[arg(long, default_value = "x-api-key")]
+    pub api_key_header: String,
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1")]
+    pub host: String,
+    /// Port to listen on, or 0 for any free port
+    #[arg(long, default_value_t = 8545)]
+    pub port: u16,
+    /// Number of threads answering requests
+    #[arg(long, default_value_t = 8)]
+    pub workers: usize,
+    /// Most connections open at once, including those being answered; others
+    /// are refused with 503 Service Unavailable
+    #[arg(long, default_value_t = 64)]
+    pub max_connections: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct TestArgs {
+    /// Test files, or directories to search for test files in. In
+    /// directories, test files are the JSON files with a `requests` (or
+    /// `queries`) field.
+    #[arg(value_name = "PATH", default_value = ".")]
+    pub paths: Vec<String>,
+    /// Directory which the files named in test files are relative to,
+    /// instead of the directory of each test file
+    #[arg(long, value_name = "DIR")]
+    pub root: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct DifferentialArgs {
+    /// Test files, or directories to search for test files in, as for the
+    /// `test` command. The expected results in them are not checked.
+    #[arg(value_name = "PATH", default_value = ".")]
+    pub paths: Vec<String>,
+    /// Directory which the files named in test files are relative to,
+    /// instead of the directory of each test file
+    #[arg(long, value_name = "DIR")]
+    pub root: Option<String>,
+    /// Upstream `cedar` executable to compare with, e.g., one built from
+    /// <https://github.com/cedar-policy/cedar>
+    #[arg(long, value_name = "FILE", env = "CEDAR_UPSTREAM")]
+    pub upstream: String,
+}
+
+#[derive(Args, Debug)]
+pub struct BenchArgs {
+    /// File containing the static Cedar policies and templates
+    #[arg(long = "policies", value_name = "FILE")]
+    pub policies_file: String,
+    /// File containing template linked policies
+    #[arg(long = "template-linked", value_name = "FILE")]
+    pub template_linked_file: Option<String>,
+    /// File containing schema information, used as by `authorize-batch`
+    #[arg(long = "schema", value_name = "FILE")]
+    pub schema_file: Option<String>,
+    /// File containing JSON representation of the Cedar entity hierarchy
+    #[arg(long = "entities", value_name = "FILE")]
+    pub entities_file: String,
+    /// File containing one request per line, as for `authorize-batch`
+    #[arg(long = "requests", value_name = "FILE")]
+    pub requests_file: String,
+    /// Number of times to authorize each request
+    #[arg(long, value_name = "N", default_value = "100")]
+    pub iterations: NonZeroUsize,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum TranslationDirection {
+    /// JSON schema to human-readable schema
+    #[default]
+    JsonToHuman,
+    /// Human-readable schema to JSON schema
+    HumanToJson,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum PolicyTranslationDirection {
+    /// Cedar policies to JSON policies
+    #[default]
+    CedarToJson,
+    /// JSON policies to Cedar policies
+    JsonToCedar,
+}
+
 /// Wrapper struct
 #[derive(Clone, Debug, Deserialize)]
 #[serde(try_from = "HashMap<String,String>")]
@@ -342,6 +937,42 @@ struct RequestJSON {
     context: serde_json::Value,
 }
 
+impl RequestJSON {
+    /// Turn this `RequestJSON` into a `Request`, naming `source` as where it
+    /// came from in errors
+    fn into_request(self, schema: Option<&Schema>, source: &str) -> Result<Request> {
+        let principal = self
+            .principal
+            .map(|s| {
+                s.parse().wrap_err_with(|| {
+                    format!("failed to parse principal in {source} as entity Uid")
+                })
+            })
+            .transpose()?;
+        let action = self
+            .action
+            .map(|s| {
+                s.parse()
+                    .wrap_err_with(|| format!("failed to parse action in {source} as entity Uid"))
+            })
+            .transpose()?;
+        let resource = self
+            .resource
+            .map(|s| {
+                s.parse()
+                    .wrap_err_with(|| format!("failed to parse resource in {source} as entity Uid"))
+            })
+            .transpose()?;
+        let context = Context::from_json_value(
+            self.context,
+            schema.and_then(|s| Some((s, action.as_ref()?))),
+        )
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to create a context from {source}"))?;
+        Ok(Request::new(principal, action, resource, context))
+    }
+}
+
 #[derive(Args, Debug)]
 pub struct EvaluateArgs {
     /// Request args (incorporated by reference)
@@ -374,6 +1005,9 @@ pub enum CedarExitCode {
     // The command completed successfully, but it detected a validation failure
     // in the given schema and policies.
     ValidationFailure,
+    // The command completed successfully, but it detected a breaking change
+    // between the given schemas.
+    BreakingSchemaChange,
 }
 
 impl Termination for CedarExitCode {
@@ -383,49 +1017,142 @@ impl Termination for CedarExitCode {
             CedarExitCode::Failure => ExitCode::FAILURE,
             CedarExitCode::AuthorizeDeny => ExitCode::from(2),
             CedarExitCode::ValidationFailure => ExitCode::from(3),
+            CedarExitCode::BreakingSchemaChange => ExitCode::from(4),
         }
     }
 }
 
-pub fn check_parse(args: &CheckParseArgs) -> CedarExitCode {
-    match read_policy_set(args.policies_file.as_ref()) {
-        Ok(_) => CedarExitCode::Success,
-        Err(e) => {
-            println!("Error: {e:?}");
-            CedarExitCode::Failure
+/// The JSON form of an error, as written by `--error-format json`
+fn error_json(error: &Report) -> serde_json::Value {
+    let mut json = String::new();
+    match miette::JSONReportHandler::new().render_report(&mut json, error.as_ref()) {
+        Ok(()) => serde_json::from_str(&json).unwrap_or(serde_json::Value::String(json)),
+        Err(_) => serde_json::json!({ "message": error.to_string() }),
+    }
+}
+
+/// Print the `--output json` of a command: whether it succeeded, the errors
+/// which made it fail, and the other `fields` of its output
+fn print_json_output(
+    success: bool,
+    errors: &[Report],
+    fields: serde_json::Map<String, serde_json::Value>,
+) {
+    let mut json = serde_json::Map::new();
+    json.insert("success".to_string(), success.into());
+    json.insert(
+        "errors".to_string(),
+        errors.iter().map(error_json).collect(),
+    );
+    json.extend(fields);
+    println!("{:#}", serde_json::Value::Object(json));
+}
+
+/// Print the errors which made a command fail
+fn print_errors(output: OutputFormat, errors: Vec<Report>) -> CedarExitCode {
+    match output {
+        OutputFormat::Human => {
+            for e in errors {
+                println!("Error: {e:?}");
+            }
         }
+        OutputFormat::Json => print_json_output(false, &errors, serde_json::Map::new()),
     }
+    CedarExitCode::Failure
+}
+
+pub fn check_parse(args: &CheckParseArgs) -> CedarExitCode {
+    match read_policy_set(args.policies_file.as_ref()) {
+        Ok(_) => {
+            if args.output == OutputFormat::Json {
+                print_json_output(true, &[], serde_json::Map::new());
+            }
+            CedarExitCode::Success
+        }
+        Err(e) => print_errors(args.output, vec![e]),
+    }
+}
+
+/// The JSON form of a validation error or warning
+fn validation_json(
+    location: &SourceLocation<'_>,
+    code: &str,
+    message: impl Display,
+) -> serde_json::Value {
+    let span = match (location.range_start(), location.range_end()) {
+        (Some(start), Some(end)) => serde_json::json!({
+            "offset": start,
+            "length": end.saturating_sub(start),
+        }),
+        _ => serde_json::Value::Null,
+    };
+    serde_json::json!({
+        "policyId": location.policy_id().as_ref(),
+        "code": code,
+        "message": message.to_string(),
+        "span": span,
+    })
 }
 
 pub fn validate(args: &ValidateArgs) -> CedarExitCode {
+    if args.watch {
+        return watch::watch(|| {
+            let mut files = PolicySet::source_files(&args.policies_file);
+            files.push(PathBuf::from(&args.schema_file));
+            files
+        });
+    }
     let pset = match read_policy_set(Some(&args.policies_file)) {
         Ok(pset) => pset,
-        Err(e) => {
-            println!("Error: {e:?}");
-            return CedarExitCode::Failure;
-        }
+        Err(e) => return print_errors(args.output, vec![e]),
     };
 
     let schema = match read_schema_file(&args.schema_file) {
         Ok(schema) => schema,
-        Err(e) => {
-            println!("Error: {e:?}");
-            return CedarExitCode::Failure;
-        }
+        Err(e) => return print_errors(args.output, vec![e]),
     };
 
-    let validator = Validator::new(schema);
+    let validator =
+        Validator::new(schema).with_denied_warnings(args.denied_warnings.iter().copied());
     let result = validator.validate(&pset, ValidationMode::default());
-    if result.validation_passed() {
-        println!("Validation Passed");
-        return CedarExitCode::Success;
+    let exit_code = if result.validation_passed() {
+        CedarExitCode::Success
     } else {
-        println!("Validation Results:");
-        for note in result.validation_errors() {
-            println!("{}", note);
+        CedarExitCode::ValidationFailure
+    };
+    match args.output {
+        OutputFormat::Human => {
+            if result.validation_passed() {
+                println!("Validation Passed");
+            } else {
+                println!("Validation Results:");
+                for note in result.validation_errors() {
+                    println!("{}", note);
+                }
+            }
+            for warning in result.validation_warnings() {
+                println!("{}", warning);
+            }
+        }
+        OutputFormat::Json => {
+            let errors = result
+                .validation_errors()
+                .map(|e| validation_json(e.location(), e.error_kind().code(), e.error_kind()))
+                .collect();
+            let warnings = result
+                .validation_warnings()
+                .map(|w| {
+                    let kind = w.warning_kind();
+                    validation_json(w.location(), kind.code().as_str(), kind)
+                })
+                .collect();
+            let mut fields = serde_json::Map::new();
+            fields.insert("validationErrors".to_string(), errors);
+            fields.insert("validationWarnings".to_string(), warnings);
+            print_json_output(result.validation_passed(), &[], fields);
         }
-        return CedarExitCode::ValidationFailure;
     }
+    exit_code
 }
 
 pub fn evaluate(args: &EvaluateArgs) -> (CedarExitCode, EvalResult) {
@@ -485,130 +1212,795 @@ pub fn evaluate(args: &EvaluateArgs) -> (CedarExitCode, EvalResult) {
     }
 }
 
-pub fn link(args: &LinkArgs) -> CedarExitCode {
-    if let Err(err) = link_inner(args) {
-        println!("Error: {err:?}");
-        CedarExitCode::Failure
-    } else {
-        CedarExitCode::Success
+pub fn link(args: &LinkArgs) -> CedarExitCode {
+    if args.watch {
+        // not the template-linked file, which each run writes
+        return watch::watch(|| PolicySet::source_files(&args.policies_file));
+    }
+    match link_inner(args) {
+        Ok((linked, entry)) => {
+            match args.output {
+                OutputFormat::Human => println!("Template Linked Policy Added: {linked}"),
+                OutputFormat::Json => {
+                    let mut fields = serde_json::Map::new();
+                    fields.insert("link".to_string(), serde_json::json!(entry));
+                    print_json_output(true, &[], fields);
+                }
+            }
+            CedarExitCode::Success
+        }
+        Err(err) => print_errors(args.output, vec![err]),
+    }
+}
+
+fn format_policies_inner(args: &FormatArgs) -> Result<()> {
+    let policies_str = read_from_file_or_stdin(args.file_name.as_ref(), "policy set")?;
+    let config = Config {
+        line_width: args.line_width,
+        indent_width: args.indent_width,
+    };
+    let formatted = policies_str_to_pretty(&policies_str, &config)?;
+    if args.check {
+        // A formatted file is the formatted policies followed by a newline,
+        // as printed without `--check`
+        if policies_str != format!("{formatted}\n") {
+            return Err(miette!(
+                "{} is not formatted",
+                args.file_name.as_deref().unwrap_or("policy set")
+            ));
+        }
+    } else {
+        println!("{formatted}");
+    }
+    Ok(())
+}
+
+pub fn format_policies(args: &FormatArgs) -> CedarExitCode {
+    if let Err(err) = format_policies_inner(args) {
+        println!("Error: {err:?}");
+        CedarExitCode::Failure
+    } else {
+        CedarExitCode::Success
+    }
+}
+
+fn generate_schema(path: &Path) -> Result<()> {
+    std::fs::write(
+        path,
+        serde_json::to_string_pretty(&serde_json::json!(
+        {
+            "": {
+                "entityTypes": {
+                    "A": {
+                        "memberOfTypes": [
+                            "B"
+                        ]
+                    },
+                    "B": {
+                        "memberOfTypes": []
+                    },
+                    "C": {
+                        "memberOfTypes": []
+                    }
+                },
+                "actions": {
+                    "action": {
+                        "appliesTo": {
+                            "resourceTypes": [
+                                "C"
+                            ],
+                            "principalTypes": [
+                                "A",
+                                "B"
+                            ]
+                        }
+                    }
+                }
+            }
+        }))
+        .into_diagnostic()?,
+    )
+    .into_diagnostic()
+}
+
+fn generate_policy(path: &Path) -> Result<()> {
+    std::fs::write(
+        path,
+        r#"permit (
+  principal in A::"a",
+  action == Action::"action",
+  resource == C::"c"
+) when { true };
+"#,
+    )
+    .into_diagnostic()
+}
+
+fn generate_entities(path: &Path) -> Result<()> {
+    std::fs::write(
+        path,
+        serde_json::to_string_pretty(&serde_json::json!(
+        [
+            {
+                "uid": { "type": "A", "id": "a"} ,
+                "attrs": {},
+                "parents": [{"type": "B", "id": "b"}]
+            },
+            {
+                "uid": { "type": "B", "id": "b"} ,
+                "attrs": {},
+                "parents": []
+            },
+            {
+                "uid": { "type": "C", "id": "c"} ,
+                "attrs": {},
+                "parents": []
+            }
+        ]))
+        .into_diagnostic()?,
+    )
+    .into_diagnostic()
+}
+
+fn new_inner(args: &NewArgs) -> Result<()> {
+    let dir = &std::env::current_dir().into_diagnostic()?.join(&args.name);
+    std::fs::create_dir(dir).into_diagnostic()?;
+    let schema_path = dir.join("schema.cedarschema.json");
+    let policy_path = dir.join("policy.cedar");
+    let entities_path = dir.join("entities.jon");
+    generate_schema(&schema_path)?;
+    generate_policy(&policy_path)?;
+    generate_entities(&entities_path)
+}
+
+pub fn new(args: &NewArgs) -> CedarExitCode {
+    if let Err(err) = new_inner(args) {
+        println!("Error: {err:?}");
+        CedarExitCode::Failure
+    } else {
+        CedarExitCode::Success
+    }
+}
+
+/// Whether a policy or template in `policies` has the id `id`
+fn id_taken(policies: &PolicySet, id: &str) -> bool {
+    policies.policies().any(|p| p.id().as_ref() == id)
+        || policies.templates().any(|t| t.id().as_ref() == id)
+}
+
+/// Returns the new template-linked policy, and its entry in the template-linked
+/// file
+fn new_link_inner(args: &NewLinkArgs) -> Result<(Policy, TemplateLinked)> {
+    let mut policies =
+        read_policy_and_links(&args.policies_file, args.template_linked_file.as_ref())?;
+    let mut slots = HashMap::new();
+    for (slot, entity) in &args.params {
+        if slots.insert(slot.clone(), entity.clone()).is_some() {
+            return Err(miette!("`--param` is given more than once for {slot}"));
+        }
+    }
+    let new_id = match &args.new_id {
+        Some(id) => id.clone(),
+        None => (1..)
+            .map(|n| format!("{}-{n}", args.template_id))
+            .find(|id| !id_taken(&policies, id))
+            .ok_or_else(|| miette!("failed to find an unused id for the link"))?,
+    };
+    policies
+        .link(
+            PolicyId::from_str(&args.template_id)?,
+            PolicyId::from_str(&new_id)?,
+            create_slot_env(&slots)?,
+        )
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to link template `{}`", args.template_id))?;
+    let linked = policies
+        .policy(&PolicyId::from_str(&new_id)?)
+        .ok_or_else(|| miette!("Failed to add template-linked policy"))?
+        .clone();
+    let entry = TemplateLinked {
+        template_id: args.template_id.clone(),
+        link_id: new_id,
+        args: slots,
+    };
+    if let Some(path) = &args.template_linked_file {
+        update_template_linked_file(path, entry.clone())?;
+    }
+    Ok((linked, entry))
+}
+
+pub fn new_link(args: &NewLinkArgs) -> CedarExitCode {
+    match new_link_inner(args) {
+        Ok((linked, entry)) => {
+            if args.template_linked_file.is_some() {
+                println!("Template Linked Policy Added: {linked}");
+            } else {
+                match serde_json::to_string_pretty(&[entry]).into_diagnostic() {
+                    Ok(json) => println!("{json}"),
+                    Err(err) => {
+                        println!("Error: {err:?}");
+                        return CedarExitCode::Failure;
+                    }
+                }
+            }
+            CedarExitCode::Success
+        }
+        Err(err) => {
+            println!("Error: {err:?}");
+            CedarExitCode::Failure
+        }
+    }
+}
+
+/// The text of `builtin` with the `@id` annotation `id`, which goes before its
+/// other annotations, after the comment explaining it
+fn builtin_policy_text(builtin: BuiltinPolicy, id: &str) -> String {
+    let text = builtin.text();
+    let at = if text.starts_with('@') {
+        0
+    } else {
+        text.find("\n@").map_or(0, |at| at + 1)
+    };
+    let (comment, policy) = text.split_at(at);
+    format!("{comment}@id(\"{}\")\n{policy}", id.escape_default())
+}
+
+fn new_policy_inner(args: &NewPolicyArgs) -> Result<()> {
+    let name = args.from_builtin.name();
+    let id = args.new_id.clone().unwrap_or_else(|| name.clone());
+    let text = builtin_policy_text(args.from_builtin, &id);
+    PolicySet::from_str(&text)
+        .map_err(|err| Report::new(err).with_source_code(NamedSource::new(&name, text.clone())))
+        .wrap_err_with(|| format!("failed to parse the builtin policy {name}"))?;
+    let Some(path) = &args.policies_file else {
+        print!("{text}");
+        return Ok(());
+    };
+    let mut contents = String::new();
+    if Path::new(path).exists() {
+        if id_taken(&read_policy_set(Some(path))?, &id) {
+            return Err(miette!(
+                "{path} already has a policy with id `{id}`; give another one with `--id`"
+            ));
+        }
+        contents = read_from_file(path, "policy set")?;
+        if !contents.is_empty() {
+            if !contents.ends_with('\n') {
+                contents.push('\n');
+            }
+            contents.push('\n');
+        }
+    }
+    contents.push_str(&text);
+    std::fs::write(path, contents)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to write policies to file {path}"))?;
+    println!("Policy `{id}` added to {path}");
+    Ok(())
+}
+
+pub fn new_policy(args: &NewPolicyArgs) -> CedarExitCode {
+    if let Err(err) = new_policy_inner(args) {
+        println!("Error: {err:?}");
+        CedarExitCode::Failure
+    } else {
+        CedarExitCode::Success
+    }
+}
+
+fn skeleton_inner(args: &SkeletonArgs) -> Result<()> {
+    let schema = read_schema_file(&args.schema_file)?;
+    let skeleton = serde_json::to_string_pretty(&schema.entities_skeleton()).into_diagnostic()?;
+    match &args.output_file {
+        Some(path) => std::fs::write(path, skeleton)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to write entities to file {path}")),
+        None => {
+            println!("{skeleton}");
+            Ok(())
+        }
+    }
+}
+
+pub fn skeleton(args: &SkeletonArgs) -> CedarExitCode {
+    if let Err(err) = skeleton_inner(args) {
+        println!("Error: {err:?}");
+        CedarExitCode::Failure
+    } else {
+        CedarExitCode::Success
+    }
+}
+
+fn snapshot_entities_inner(args: &SnapshotEntitiesArgs) -> Result<()> {
+    let schema = args
+        .schema_file
+        .as_ref()
+        .map(|path| read_schema_file(path.as_str()))
+        .transpose()?;
+    let entities = load_entities(&args.entities_file, schema.as_ref())?;
+    let file = std::fs::File::create(&args.output_file)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to create snapshot file {}", args.output_file))?;
+    entities
+        .write_snapshot(std::io::BufWriter::new(file))
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to write snapshot to file {}", args.output_file))
+}
+
+pub fn snapshot_entities(args: &SnapshotEntitiesArgs) -> CedarExitCode {
+    if let Err(err) = snapshot_entities_inner(args) {
+        println!("Error: {err:?}");
+        CedarExitCode::Failure
+    } else {
+        CedarExitCode::Success
+    }
+}
+
+fn bundle_inner(args: &BundleArgs) -> Result<PolicyBundle> {
+    let links = args
+        .template_linked_file
+        .as_ref()
+        .map(|path| {
+            let links = read_from_file(path, "template-linked")?;
+            serde_json::from_str::<Vec<BundleLink>>(&links)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("failed to parse template-linked file {path}"))
+        })
+        .transpose()?
+        .unwrap_or_default();
+    let mut bundle = PolicyBundle::new(
+        &args.name,
+        &args.version,
+        read_from_file(&args.policies_file, "policy set")?,
+    )
+    .with_links(links);
+    if let Some(schema_file) = &args.schema_file {
+        bundle = bundle.with_schema(read_from_file(schema_file, "schema")?);
+    }
+    for (key, value) in &args.metadata {
+        bundle = bundle.with_metadata(key, value);
+    }
+    bundle
+        .save(&args.output_file)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to write bundle {}", args.output_file))?;
+    Ok(bundle)
+}
+
+pub fn bundle(args: &BundleArgs) -> CedarExitCode {
+    match bundle_inner(args) {
+        Ok(bundle) => {
+            println!(
+                "wrote {} {} to {} (content hash {})",
+                bundle.manifest().name,
+                bundle.manifest().version,
+                args.output_file,
+                bundle.content_hash()
+            );
+            CedarExitCode::Success
+        }
+        Err(err) => {
+            println!("Error: {err:?}");
+            CedarExitCode::Failure
+        }
+    }
+}
+
+pub fn verify_bundle(args: &VerifyBundleArgs) -> CedarExitCode {
+    match read_bundle(&args.bundle_file) {
+        Ok(bundle) => {
+            let manifest = bundle.manifest();
+            println!("{} {}", manifest.name, manifest.version);
+            for (key, value) in &manifest.metadata {
+                println!("  {key}: {value}");
+            }
+            for (file, hash) in &manifest.hashes {
+                println!("  {file}: sha256 {hash}");
+            }
+            println!("content hash {}", bundle.content_hash());
+            CedarExitCode::Success
+        }
+        Err(err) => {
+            println!("Error: {err:?}");
+            CedarExitCode::Failure
+        }
+    }
+}
+
+fn translate_schema_inner(args: &TranslateSchemaArgs) -> Result<String> {
+    let src = read_from_file_or_stdin(args.input_file.as_ref(), "schema")?;
+    match args.direction {
+        TranslationDirection::JsonToHuman => {
+            let fragment = SchemaFragment::from_str(&src)
+                .into_diagnostic()
+                .wrap_err("failed to parse JSON schema")?;
+            fragment
+                .as_natural()
+                .into_diagnostic()
+                .wrap_err("failed to translate schema")
+        }
+        TranslationDirection::HumanToJson => {
+            let fragment = SchemaFragment::from_str_natural(&src)
+                .into_diagnostic()
+                .wrap_err("failed to parse human-readable schema")?;
+            let json = fragment.to_json_value().into_diagnostic()?;
+            serde_json::to_string_pretty(&json)
+                .map(|json| json + "\n")
+                .into_diagnostic()
+        }
+    }
+}
+
+pub fn translate_schema(args: &TranslateSchemaArgs) -> CedarExitCode {
+    match translate_schema_inner(args) {
+        Ok(schema) => {
+            print!("{schema}");
+            CedarExitCode::Success
+        }
+        Err(err) => {
+            println!("Error: {err:?}");
+            CedarExitCode::Failure
+        }
+    }
+}
+
+/// The Cedar text of a policy or template, with an `@id` annotation so that
+/// reading it back gives it the same id
+fn policy_text_with_id(
+    id: &PolicyId,
+    id_annotation: Option<&str>,
+    text: impl Display,
+) -> Result<String> {
+    match id_annotation {
+        None => Ok(format!("@id(\"{}\")\n{text}", id.as_ref().escape_debug())),
+        Some(annotation) if annotation == id.as_ref() => Ok(text.to_string()),
+        Some(annotation) => Err(miette!(
+            "policy `{}` has a conflicting id annotation `{annotation}`",
+            id.as_ref()
+        )),
+    }
+}
+
+/// The Cedar text of the static policies and templates of `policies`, each
+/// with an `@id` annotation, and its template links, ordered by id
+fn policy_set_to_cedar(policies: &PolicySet) -> Result<(String, Vec<TemplateLinked>)> {
+    let mut texts = Vec::new();
+    let mut links = Vec::new();
+    for policy in policies.policies() {
+        match (policy.template_id(), policy.template_links()) {
+            (Some(template_id), Some(values)) => links.push(TemplateLinked {
+                template_id: template_id.as_ref().to_string(),
+                link_id: policy.id().as_ref().to_string(),
+                args: values
+                    .into_iter()
+                    .map(|(slot, value)| (slot, value.to_string()))
+                    .collect(),
+            }),
+            _ => texts.push((
+                policy.id(),
+                policy_text_with_id(policy.id(), policy.annotation("id"), policy)?,
+            )),
+        }
+    }
+    for template in policies.templates() {
+        texts.push((
+            template.id(),
+            policy_text_with_id(template.id(), template.annotation("id"), template)?,
+        ));
+    }
+    texts.sort_by(|(a, _), (b, _)| a.as_ref().cmp(b.as_ref()));
+    links.sort_by(|a, b| a.link_id.cmp(&b.link_id));
+    let text = texts
+        .into_iter()
+        .map(|(_, text)| text)
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let config = Config {
+        line_width: 80,
+        indent_width: 2,
+    };
+    let text = policies_str_to_pretty(&text, &config)?;
+    Ok((text + "\n", links))
+}
+
+/// Write `links` to `links_filename`, which is given with `flag`, or fail if
+/// there are links and no file to write them to
+fn write_template_links(
+    links: &[TemplateLinked],
+    links_filename: Option<&str>,
+    flag: &str,
+) -> Result<()> {
+    match links_filename {
+        Some(links_filename) => write_template_linked_file(links, links_filename),
+        None if !links.is_empty() => Err(miette!(
+            "the policy set has template links; use {flag} to give a file to write them to"
+        )),
+        None => Ok(()),
+    }
+}
+
+fn translate_policy_inner(args: &TranslatePolicyArgs) -> Result<String> {
+    match args.direction {
+        PolicyTranslationDirection::CedarToJson => {
+            let mut policies = read_policy_set(args.input_file.as_deref())?;
+            if let Some(links_filename) = &args.template_linked_file {
+                add_template_links_to_set(links_filename, &mut policies)?;
+            }
+            let json = policies
+                .to_json()
+                .into_diagnostic()
+                .wrap_err("failed to translate policy set")?;
+            serde_json::to_string_pretty(&json)
+                .map(|json| json + "\n")
+                .into_diagnostic()
+        }
+        PolicyTranslationDirection::JsonToCedar => {
+            let src = read_from_file_or_stdin(args.input_file.as_ref(), "policy set")?;
+            let policies = PolicySet::from_json_str(&src)
+                .into_diagnostic()
+                .wrap_err("failed to parse JSON policy set")?;
+            let (text, links) = policy_set_to_cedar(&policies)?;
+            write_template_links(
+                &links,
+                args.template_linked_file.as_deref(),
+                "--template-linked",
+            )?;
+            Ok(text)
+        }
+    }
+}
+
+pub fn translate_policy(args: &TranslatePolicyArgs) -> CedarExitCode {
+    match translate_policy_inner(args) {
+        Ok(policies) => {
+            print!("{policies}");
+            CedarExitCode::Success
+        }
+        Err(err) => {
+            println!("Error: {err:?}");
+            CedarExitCode::Failure
+        }
+    }
+}
+
+pub fn diff_schema(args: &DiffSchemaArgs) -> CedarExitCode {
+    let (old, new) = match (
+        read_schema_file(&args.old_schema_file),
+        read_schema_file(&args.new_schema_file),
+    ) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(e), _) | (_, Err(e)) => {
+            println!("Error: {e:?}");
+            return CedarExitCode::Failure;
+        }
+    };
+    let changes = old.diff(&new);
+    if changes.is_empty() {
+        println!("No changes");
+    }
+    for change in &changes {
+        let kind = if change.is_breaking() {
+            "breaking"
+        } else {
+            "compatible"
+        };
+        println!("{kind}: {change}");
+    }
+    if changes.iter().any(SchemaChange::is_breaking) {
+        CedarExitCode::BreakingSchemaChange
+    } else {
+        CedarExitCode::Success
+    }
+}
+
+pub fn check_schema_usage(args: &CheckSchemaUsageArgs) -> CedarExitCode {
+    let (schema, pset) = match (
+        read_schema_file(&args.schema_file),
+        read_policy_set(Some(&args.policies_file)),
+    ) {
+        (Ok(schema), Ok(pset)) => (schema, pset),
+        (Err(e), _) | (_, Err(e)) => {
+            println!("Error: {e:?}");
+            return CedarExitCode::Failure;
+        }
+    };
+    let mode = if args.permissive {
+        ValidationMode::Permissive
+    } else {
+        ValidationMode::Strict
+    };
+    let issues = schema.check_usage(&pset, mode);
+    if issues.is_empty() {
+        println!("All schema elements are used");
+    }
+    for issue in &issues {
+        println!("{issue}");
+    }
+    CedarExitCode::Success
+}
+
+pub fn lint(args: &LintArgs) -> CedarExitCode {
+    use cedar_policy::lint::{Linter, RequireAnnotations, Severity};
+
+    let pset = match read_policy_set(Some(&args.policies_file)) {
+        Ok(pset) => pset,
+        Err(e) => {
+            println!("Error: {e:?}");
+            return CedarExitCode::Failure;
+        }
+    };
+    let mut linter = Linter::new();
+    if !args.required_annotations.is_empty() {
+        linter = linter.with_rule(RequireAnnotations::new(&args.required_annotations));
+    }
+    let severities = args
+        .denied_rules
+        .iter()
+        .map(|rule| (rule, Severity::Error))
+        .chain(
+            args.allowed_rules
+                .iter()
+                .map(|rule| (rule, Severity::Allow)),
+        );
+    for (rule, severity) in severities {
+        linter = match linter.with_severity(rule, severity) {
+            Ok(linter) => linter,
+            Err(e) => {
+                println!("Error: {e}");
+                return CedarExitCode::Failure;
+            }
+        };
+    }
+    let lints = linter.lint(&pset);
+    if lints.is_empty() {
+        println!("No lints found");
+    }
+    for lint in &lints {
+        println!("{lint}");
+    }
+    if lints.iter().any(|lint| lint.severity() == Severity::Error) {
+        CedarExitCode::Failure
+    } else {
+        CedarExitCode::Success
+    }
+}
+
+fn export_inner(args: &ExportArgs) -> Result<Vec<ExportIssue>> {
+    let mut policies = read_policy_set(Some(&args.policies_file))?;
+    if let Some(links_filename) = &args.template_linked_file {
+        add_template_links_to_set(links_filename, &mut policies)?;
+    }
+    let exported = export_policies(&policies);
+    let mut issues = exported.issues;
+    let (text, links) = policy_set_to_cedar(&exported.value)?;
+    write_template_links(
+        &links,
+        args.output_template_linked_file.as_deref(),
+        "--output-template-linked",
+    )?;
+    if let (Some(schema_file), Some(schema_output_file)) =
+        (&args.schema_file, &args.schema_output_file)
+    {
+        let src = read_from_file(schema_file, "schema")?;
+        // JSON schemas are objects; anything else is in the human-readable format
+        let fragment = if src.trim_start().starts_with('{') {
+            SchemaFragment::from_str(&src)
+        } else {
+            SchemaFragment::from_str_natural(&src)
+        }
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to parse schema from file {schema_file}"))?;
+        let schema = export_schema(&fragment).into_diagnostic()?;
+        issues.extend(schema.issues);
+        let json = serde_json::to_string_pretty(&schema.value).into_diagnostic()?;
+        std::fs::write(schema_output_file, json + "\n")
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to write {schema_output_file}"))?;
+    }
+    match &args.output_file {
+        Some(output_file) => std::fs::write(output_file, text)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to write {output_file}"))?,
+        None => print!("{text}"),
+    }
+    Ok(issues)
+}
+
+/// Export the policies, and the schema, of `args`, printing what was left out
+/// or changed to stderr, so that the policies can be written to stdout
+pub fn export(args: &ExportArgs) -> CedarExitCode {
+    match export_inner(args) {
+        Ok(issues) => {
+            for issue in &issues {
+                eprintln!("{issue}");
+            }
+            if issues.iter().any(|issue| issue.dropped) && !args.allow_dropped {
+                CedarExitCode::Failure
+            } else {
+                CedarExitCode::Success
+            }
+        }
+        Err(err) => {
+            println!("Error: {err:?}");
+            CedarExitCode::Failure
+        }
     }
 }
 
-fn format_policies_inner(args: &FormatArgs) -> Result<()> {
-    let policies_str = read_from_file_or_stdin(args.file_name.as_ref(), "policy set")?;
+fn import_rego_inner(args: &ImportRegoArgs) -> Result<Vec<ImportIssue>> {
+    let src = read_from_file(&args.rego_file, "Rego module")?;
+    let imported = rego::import_rego(&src);
     let config = Config {
-        line_width: args.line_width,
-        indent_width: args.indent_width,
+        line_width: 80,
+        indent_width: 2,
     };
-    println!("{}", policies_str_to_pretty(&policies_str, &config)?);
-    Ok(())
-}
-
-pub fn format_policies(args: &FormatArgs) -> CedarExitCode {
-    if let Err(err) = format_policies_inner(args) {
-        println!("Error: {err:?}");
-        CedarExitCode::Failure
+    let text = if imported.text.is_empty() {
+        String::new()
     } else {
-        CedarExitCode::Success
+        policies_str_to_pretty(&imported.text, &config)? + "\n"
+    };
+    match &args.output_file {
+        Some(output_file) => std::fs::write(output_file, text)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to write {output_file}"))?,
+        None => print!("{text}"),
     }
+    Ok(imported.issues)
 }
 
-fn generate_schema(path: &Path) -> Result<()> {
-    std::fs::write(
-        path,
-        serde_json::to_string_pretty(&serde_json::json!(
-        {
-            "": {
-                "entityTypes": {
-                    "A": {
-                        "memberOfTypes": [
-                            "B"
-                        ]
-                    },
-                    "B": {
-                        "memberOfTypes": []
-                    },
-                    "C": {
-                        "memberOfTypes": []
-                    }
-                },
-                "actions": {
-                    "action": {
-                        "appliesTo": {
-                            "resourceTypes": [
-                                "C"
-                            ],
-                            "principalTypes": [
-                                "A",
-                                "B"
-                            ]
-                        }
-                    }
-                }
+/// Convert the rules of the Rego module of `args`, printing what was left out
+/// or changed to stderr, so that the policies can be written to stdout
+pub fn import_rego(args: &ImportRegoArgs) -> CedarExitCode {
+    match import_rego_inner(args) {
+        Ok(issues) => {
+            for issue in &issues {
+                eprintln!("{issue}");
             }
-        }))
-        .into_diagnostic()?,
-    )
-    .into_diagnostic()
+            if issues.iter().any(|issue| issue.dropped) && !args.allow_dropped {
+                CedarExitCode::Failure
+            } else {
+                CedarExitCode::Success
+            }
+        }
+        Err(err) => {
+            println!("Error: {err:?}");
+            CedarExitCode::Failure
+        }
+    }
 }
 
-fn generate_policy(path: &Path) -> Result<()> {
-    std::fs::write(
-        path,
-        r#"permit (
-  principal in A::"a",
-  action == Action::"action",
-  resource == C::"c"
-) when { true };
-"#,
-    )
-    .into_diagnostic()
+/// Read commands and expressions from stdin until it ends or `:quit` is
+/// entered. The files are reloaded on `:reload`.
+pub fn repl(args: &ReplArgs) -> CedarExitCode {
+    repl::repl(args)
 }
 
-fn generate_entities(path: &Path) -> Result<()> {
-    std::fs::write(
-        path,
-        serde_json::to_string_pretty(&serde_json::json!(
-        [
-            {
-                "uid": { "type": "A", "id": "a"} ,
-                "attrs": {},
-                "parents": [{"type": "B", "id": "b"}]
-            },
-            {
-                "uid": { "type": "B", "id": "b"} ,
-                "attrs": {},
-                "parents": []
-            },
-            {
-                "uid": { "type": "C", "id": "c"} ,
-                "attrs": {},
-                "parents": []
-            }
-        ]))
-        .into_diagnostic()?,
-    )
-    .into_diagnostic()
+pub fn server(args: &ServerArgs) -> CedarExitCode {
+    server::server(args)
 }
 
-fn new_inner(args: &NewArgs) -> Result<()> {
-    let dir = &std::env::current_dir().into_diagnostic()?.join(&args.name);
-    std::fs::create_dir(dir).into_diagnostic()?;
-    let schema_path = dir.join("schema.cedarschema.json");
-    let policy_path = dir.join("policy.cedar");
-    let entities_path = dir.join("entities.jon");
-    generate_schema(&schema_path)?;
-    generate_policy(&policy_path)?;
-    generate_entities(&entities_path)
+/// Proxy the JSON-RPC calls allowed by the policies of `args` to its node
+#[cfg(feature = "proxy")]
+pub fn proxy(args: &ProxyArgs) -> CedarExitCode {
+    proxy::proxy(args)
 }
 
-pub fn new(args: &NewArgs) -> CedarExitCode {
-    if let Err(err) = new_inner(args) {
-        println!("Error: {err:?}");
-        CedarExitCode::Failure
-    } else {
-        CedarExitCode::Success
-    }
+/// Run the tests of the test files in `args`, printing whether each passed
+/// and how the failed ones differ from their expected results
+pub fn test(args: &TestArgs) -> CedarExitCode {
+    test_runner::test(args)
+}
+
+/// Authorize the requests of the test files in `args` with this fork and with
+/// upstream Cedar, printing whether their decisions are the same
+pub fn differential(args: &DifferentialArgs) -> CedarExitCode {
+    differential::differential(args)
+}
+
+/// Measure loading the files of `args` and authorizing their requests,
+/// printing the times, latency percentiles and allocations
+pub fn bench(args: &BenchArgs) -> CedarExitCode {
+    bench::bench(args)
 }
 
 fn create_slot_env(data: &HashMap<SlotId, String>) -> Result<HashMap<SlotId, EntityUid>> {
@@ -617,7 +2009,9 @@ fn create_slot_env(data: &HashMap<SlotId, String>) -> Result<HashMap<SlotId, Ent
         .collect::<Result<HashMap<SlotId, EntityUid>>>()
 }
 
-fn link_inner(args: &LinkArgs) -> Result<()> {
+/// Returns the new template-linked policy, and its entry in the template-linked
+/// file
+fn link_inner(args: &LinkArgs) -> Result<(Policy, TemplateLinked)> {
     let mut policies = read_policy_set(Some(&args.policies_file))?;
     let slotenv = create_slot_env(&args.arguments.data)?;
     policies
@@ -629,15 +2023,16 @@ fn link_inner(args: &LinkArgs) -> Result<()> {
         .into_diagnostic()?;
     let linked = policies
         .policy(&PolicyId::from_str(&args.new_id)?)
-        .ok_or_else(|| miette!("Failed to add template-linked policy"))?;
-    println!("Template Linked Policy Added: {linked}");
-    let linked = TemplateLinked {
+        .ok_or_else(|| miette!("Failed to add template-linked policy"))?
+        .clone();
+    let entry = TemplateLinked {
         template_id: args.template_id.clone(),
         link_id: args.new_id.clone(),
         args: args.arguments.data.clone(),
     };
 
-    update_template_linked_file(&args.template_linked_file, linked)
+    update_template_linked_file(&args.template_linked_file, entry.clone())?;
+    Ok((linked, entry))
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -697,17 +2092,21 @@ impl From<TemplateLinked> for LiteralTemplateLinked {
     }
 }
 
-/// Iterate over links in the template-linked file and add them to the set
+/// Iterate over links in the template-linked file and add them to the set,
+/// recording the file as their provenance
 fn add_template_links_to_set(path: impl AsRef<Path>, policy_set: &mut PolicySet) -> Result<()> {
+    let provenance = Provenance::new(Origin::File(path.as_ref().to_path_buf()));
     for template_linked in load_liked_file(path)? {
         let slot_env = create_slot_env(&template_linked.args)?;
+        let link_id = PolicyId::from_str(&template_linked.link_id)?;
         policy_set
             .link(
                 PolicyId::from_str(&template_linked.template_id)?,
-                PolicyId::from_str(&template_linked.link_id)?,
+                link_id.clone(),
                 slot_env,
             )
             .into_diagnostic()?;
+        policy_set.set_provenance(link_id, provenance.clone());
     }
     Ok(())
 }
@@ -738,8 +2137,11 @@ fn load_liked_file(path: impl AsRef<Path>) -> Result<Vec<TemplateLinked>> {
 }
 
 /// Add a single template-linked policy to the linked file
+/// Add `new_linked` to the template-linked file, in place of any link with the
+/// same id, so linking again (e.g., with `--watch`) replaces the old link
 fn update_template_linked_file(path: impl AsRef<Path>, new_linked: TemplateLinked) -> Result<()> {
     let mut template_linked = load_liked_file(path.as_ref())?;
+    template_linked.retain(|linked| linked.link_id != new_linked.link_id);
     template_linked.push(new_linked);
     write_template_linked_file(&template_linked, path.as_ref())
 }
@@ -756,17 +2158,58 @@ fn write_template_linked_file(linked: &[TemplateLinked], path: impl AsRef<Path>)
 }
 
 pub fn authorize(args: &AuthorizeArgs) -> CedarExitCode {
-    println!();
+    if args.watch {
+        return watch::watch(|| {
+            let mut files = PolicySet::source_files(&args.policies_file);
+            files.extend(
+                [
+                    args.template_linked_file.as_ref(),
+                    args.schema_file.as_ref(),
+                    Some(&args.entities_file),
+                    args.request.context_json_file.as_ref(),
+                    args.request.request_json_file.as_ref(),
+                ]
+                .into_iter()
+                .flatten()
+                .map(PathBuf::from),
+            );
+            files
+        });
+    }
     let ans = execute_request(
         &args.request,
         &args.policies_file,
         args.template_linked_file.as_ref(),
         &args.entities_file,
         args.schema_file.as_ref(),
-        args.timing,
     );
+    if args.output == OutputFormat::Json {
+        return match ans {
+            Ok((ans, duration, _)) => {
+                let exit_code = match ans.decision() {
+                    Decision::Allow => CedarExitCode::Success,
+                    Decision::Deny => CedarExitCode::AuthorizeDeny,
+                };
+                let mut fields = decision_record(&Ok(ans));
+                if args.timing {
+                    let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+                    fields.insert("timeMicros".to_string(), micros.into());
+                }
+                print_json_output(true, &[], fields);
+                exit_code
+            }
+            Err(errs) => print_errors(args.output, errs),
+        };
+    }
+    println!();
     match ans {
-        Ok(ans) => {
+        Ok((ans, duration, policies)) => {
+            if args.timing {
+                println!(
+                    "Authorization Time (micro seconds) : {}",
+                    duration.as_micros()
+                );
+            }
             let status = match ans.decision() {
                 Decision::Allow => {
                     println!("ALLOW");
@@ -789,8 +2232,11 @@ pub fn authorize(args: &AuthorizeArgs) -> CedarExitCode {
                     println!("note: no policies applied to this request");
                 } else {
                     println!("note: this decision was due to the following policies:");
-                    for reason in ans.diagnostics().reason() {
-                        println!("  {}", reason);
+                    for (reason, provenance) in ans.diagnostics().reason_provenance(&policies) {
+                        match provenance {
+                            Some(provenance) => println!("  {reason} (from {provenance})"),
+                            None => println!("  {reason}"),
+                        }
                     }
                     println!();
                 }
@@ -806,12 +2252,259 @@ pub fn authorize(args: &AuthorizeArgs) -> CedarExitCode {
     }
 }
 
+/// Number of requests which `authorize-batch` reads at a time for each thread
+const BATCH_SIZE: usize = 1024;
+
+/// Counts of the outcomes of `authorize-batch`
+#[derive(Default)]
+struct BatchSummary {
+    allowed: usize,
+    denied: usize,
+    invalid: usize,
+    /// How often each policy was one of those which determined the decision
+    reasons: HashMap<String, usize>,
+}
+
+impl BatchSummary {
+    fn add(&mut self, outcome: &Result<Response>) {
+        match outcome {
+            Ok(ans) => {
+                match ans.decision() {
+                    Decision::Allow => self.allowed += 1,
+                    Decision::Deny => self.denied += 1,
+                }
+                for reason in ans.diagnostics().reason() {
+                    *self.reasons.entry(reason.to_string()).or_default() += 1;
+                }
+            }
+            Err(_) => self.invalid += 1,
+        }
+    }
+}
+
+impl Display for BatchSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} requests: {} allowed, {} denied, {} invalid",
+            self.allowed + self.denied + self.invalid,
+            self.allowed,
+            self.denied,
+            self.invalid
+        )?;
+        let mut reasons = self.reasons.iter().collect::<Vec<_>>();
+        // most often first, then by policy id
+        reasons.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        if !reasons.is_empty() {
+            writeln!(f, "decisions due to each policy:")?;
+        }
+        for (policy, count) in reasons {
+            writeln!(f, "  {policy}: {count}")?;
+        }
+        Ok(())
+    }
+}
+
+fn explain_inner(args: &ExplainArgs) -> Result<cedar_policy::explain::Explanation> {
+    let policies = read_policy_and_links(&args.policies_file, args.template_linked_file.as_ref())?;
+    let schema = args
+        .schema_file
+        .as_ref()
+        .map(read_schema_file)
+        .transpose()?;
+    let entities = load_entities(&args.entities_file, schema.as_ref())?;
+    let entities = load_actions_from_schema(entities, &schema)?;
+    let request = args
+        .request
+        .get_request(schema.as_ref())
+        .wrap_err("failed to parse request")?;
+    cedar_policy::explain::explain(&request, &policies, &entities)
+        .into_diagnostic()
+        .wrap_err("failed to evaluate the context of the request")
+}
+
+/// Print how each policy evaluated for the request, and how they combined into
+/// the decision. Like `authorize`, this exits with `AuthorizeDeny` if the
+/// request is denied.
+pub fn explain(args: &ExplainArgs) -> CedarExitCode {
+    match explain_inner(args) {
+        Ok(explanation) => {
+            print!("{explanation}");
+            match explanation.decision() {
+                Decision::Allow => CedarExitCode::Success,
+                Decision::Deny => CedarExitCode::AuthorizeDeny,
+            }
+        }
+        Err(e) => {
+            println!("Error: {e:?}");
+            CedarExitCode::Failure
+        }
+    }
+}
+
+/// The JSON decision for an authorization request, in the form of the
+/// `InterfaceResponse`s of the JSON interface, or the error which made the
+/// request invalid
+fn decision_record(outcome: &Result<Response>) -> serde_json::Map<String, serde_json::Value> {
+    let mut record = serde_json::Map::new();
+    match outcome {
+        Ok(ans) => {
+            let mut reason = ans.diagnostics().reason().collect::<Vec<_>>();
+            reason.sort_by_key(|id| id.to_string());
+            let errors = ans
+                .diagnostics()
+                .errors()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            record.insert("decision".to_string(), serde_json::json!(ans.decision()));
+            record.insert(
+                "diagnostics".to_string(),
+                serde_json::json!({ "reason": reason, "errors": errors }),
+            );
+        }
+        Err(e) => {
+            let error = e
+                .chain()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(": ");
+            record.insert("error".to_string(), error.into());
+        }
+    }
+    record
+}
+
+/// The JSON decision which `authorize-batch` writes for the request on line
+/// `line`
+fn batch_record(line: usize, outcome: &Result<Response>) -> serde_json::Value {
+    let mut record = serde_json::Map::new();
+    record.insert("line".to_string(), line.into());
+    record.extend(decision_record(outcome));
+    record.into()
+}
+
+/// Apply `f` to `items` on up to `jobs` threads, keeping their order
+fn parallel_map<T: Send, U: Send>(
+    items: Vec<T>,
+    jobs: usize,
+    f: &(impl Fn(T) -> U + Sync),
+) -> Vec<U> {
+    if jobs <= 1 {
+        return items.into_iter().map(f).collect();
+    }
+    let chunk_size = items.len().div_ceil(jobs).max(1);
+    let mut items = items.into_iter();
+    let chunks = std::iter::from_fn(|| {
+        let chunk = items.by_ref().take(chunk_size).collect::<Vec<_>>();
+        (!chunk.is_empty()).then_some(chunk)
+    });
+    std::thread::scope(|scope| {
+        let handles = chunks
+            .map(|chunk| scope.spawn(move || chunk.into_iter().map(f).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    })
+}
+
+/// Returns whether every request was valid
+fn authorize_batch_inner(args: &AuthorizeBatchArgs) -> Result<bool> {
+    let policies = read_policy_and_links(&args.policies_file, args.template_linked_file.as_ref())?;
+    let schema = args
+        .schema_file
+        .as_ref()
+        .map(read_schema_file)
+        .transpose()?;
+    let entities = load_entities(&args.entities_file, schema.as_ref())?;
+    let entities = load_actions_from_schema(entities, &schema)?;
+    let requests = std::fs::File::open(&args.requests_file)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to open requests file {}", args.requests_file))?;
+    let mut lines = std::io::BufReader::new(requests).lines().enumerate();
+
+    let authorizer = Authorizer::new();
+    let authorize_line = |(index, line): (usize, String)| {
+        let source = format!("{} line {}", args.requests_file, index + 1);
+        let ans = serde_json::from_str::<RequestJSON>(&line)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to parse request in {source}"))
+            .and_then(|qjson| qjson.into_request(schema.as_ref(), &source))
+            .map(|request| authorizer.is_authorized(&request, &policies, &entities));
+        (index + 1, ans)
+    };
+    let mut summary = BatchSummary::default();
+    let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+    loop {
+        let batch = lines
+            .by_ref()
+            .take(BATCH_SIZE * args.jobs.get())
+            .map(|(index, line)| line.map(|line| (index, line)))
+            .collect::<std::io::Result<Vec<_>>>()
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to read requests file {}", args.requests_file))?;
+        if batch.is_empty() {
+            break;
+        }
+        let batch = batch
+            .into_iter()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .collect();
+        for (line, outcome) in parallel_map(batch, args.jobs.get(), &authorize_line) {
+            summary.add(&outcome);
+            writeln!(stdout, "{}", batch_record(line, &outcome)).into_diagnostic()?;
+        }
+    }
+    stdout.flush().into_diagnostic()?;
+    if args.summary {
+        eprint!("{summary}");
+    }
+    Ok(summary.invalid == 0)
+}
+
+pub fn authorize_batch(args: &AuthorizeBatchArgs) -> CedarExitCode {
+    match authorize_batch_inner(args) {
+        Ok(true) => CedarExitCode::Success,
+        Ok(false) => CedarExitCode::Failure,
+        Err(err) => {
+            println!("Error: {err:?}");
+            CedarExitCode::Failure
+        }
+    }
+}
+
 /// Load an `Entities` object from the given JSON filename and optional schema.
+/// Load entities from a JSON file, or from a snapshot written by the
+/// `snapshot-entities` command, which was already checked against its schema
 fn load_entities(entities_filename: impl AsRef<Path>, schema: Option<&Schema>) -> Result<Entities> {
+    if remote_uri(entities_filename.as_ref()).is_some() {
+        let text = read_from_file(entities_filename.as_ref(), "entities")?;
+        return Entities::from_json_str(&text, schema)
+            .into_diagnostic()
+            .wrap_err_with(|| {
+                format!(
+                    "failed to parse entities from {}",
+                    entities_filename.as_ref().display()
+                )
+            });
+    }
     match std::fs::OpenOptions::new()
         .read(true)
         .open(entities_filename.as_ref())
     {
+        Ok(f) if is_snapshot(&f) => Entities::from_snapshot_file(entities_filename.as_ref())
+            .into_diagnostic()
+            .wrap_err_with(|| {
+                format!(
+                    "failed to load entities snapshot {}",
+                    entities_filename.as_ref().display()
+                )
+            }),
         Ok(f) => Entities::from_json_file(f, schema)
             .into_diagnostic()
             .wrap_err_with(|| {
@@ -829,6 +2522,14 @@ fn load_entities(entities_filename: impl AsRef<Path>, schema: Option<&Schema>) -
     }
 }
 
+/// Does the file start like an entities snapshot? Rewinds it either way.
+fn is_snapshot(mut file: &std::fs::File) -> bool {
+    use std::io::{Read, Seek};
+    let mut start = Vec::new();
+    let read = file.take(8).read_to_end(&mut start);
+    file.rewind().is_ok() && read.is_ok() && Entities::is_snapshot(&start)
+}
+
 /// Renames policies and templates based on (@id("new_id") annotation.
 /// If no such annotation exists, it keeps the current id.
 ///
@@ -841,10 +2542,13 @@ fn rename_from_id_annotation(ps: PolicySet) -> Result<PolicySet> {
         None => Ok(t.clone()),
         Some(anno) => anno.parse().map(|a| t.new_id(a)),
     });
-    for t in t_iter {
+    for (old_id, t) in ps.templates().map(Template::id).zip(t_iter) {
         let template = t
             .into_diagnostic()
             .wrap_err("failed to parse policy id annotation")?;
+        if let Some(provenance) = ps.provenance(old_id) {
+            new_ps.set_provenance(template.id().clone(), provenance.clone());
+        }
         new_ps
             .add_template(template)
             .into_diagnostic()
@@ -854,10 +2558,13 @@ fn rename_from_id_annotation(ps: PolicySet) -> Result<PolicySet> {
         None => Ok(p.clone()),
         Some(anno) => anno.parse().map(|a| p.new_id(a)),
     });
-    for p in p_iter {
+    for (old_id, p) in ps.policies().map(Policy::id).zip(p_iter) {
         let policy = p
             .into_diagnostic()
             .wrap_err("failed to parse policy id annotation")?;
+        if let Some(provenance) = ps.provenance(old_id) {
+            new_ps.set_provenance(policy.id().clone(), provenance.clone());
+        }
         new_ps
             .add(policy)
             .into_diagnostic()
@@ -881,6 +2588,9 @@ fn read_policy_and_links(
 fn read_from_file_or_stdin(filename: Option<impl AsRef<Path>>, context: &str) -> Result<String> {
     let mut src_str = String::new();
     match filename.as_ref() {
+        Some(path) if remote_uri(path.as_ref()).is_some() => {
+            src_str = fetch_remote(path.as_ref(), context)?;
+        }
         Some(path) => {
             src_str = std::fs::read_to_string(path)
                 .into_diagnostic()
@@ -901,6 +2611,69 @@ fn read_from_file_or_stdin(filename: Option<impl AsRef<Path>>, context: &str) ->
     Ok(src_str)
 }
 
+/// The URI a file was given as, if it is an `s3://`, `gs://`, `http://` or
+/// `https://` URI, to be fetched rather than read
+fn remote_uri(filename: &Path) -> Option<&str> {
+    let uri = filename.to_str()?;
+    ["s3://", "gs://", "http://", "https://"]
+        .iter()
+        .any(|scheme| uri.starts_with(scheme))
+        .then_some(uri)
+}
+
+/// Fetch the `context` file at a remote URI, caching it in
+/// `$CEDAR_CACHE_DIR` if set, and checking that it is signed by
+/// `$CEDAR_SIGNER`, with the signature at the URI followed by `.sig`, if set,
+/// with a sequence number of at least `$CEDAR_MIN_SEQUENCE`, if set
+#[cfg(feature = "remote")]
+fn fetch_remote(filename: &Path, context: &str) -> Result<String> {
+    use cedar_policy::sources::{SignedSource, Source, UrlSource};
+
+    let uri = remote_uri(filename).unwrap_or_default();
+    let url_source = |uri: &str| -> Result<UrlSource> {
+        let source = UrlSource::from_uri(uri).into_diagnostic()?;
+        Ok(match std::env::var_os("CEDAR_CACHE_DIR") {
+            Some(dir) => source.with_cache_dir(dir),
+            None => source,
+        })
+    };
+    let source: Box<dyn Source> = match std::env::var("CEDAR_SIGNER") {
+        Ok(signer) => {
+            let min_sequence = match std::env::var("CEDAR_MIN_SEQUENCE") {
+                Ok(sequence) => sequence.parse().map_err(|_| {
+                    miette!("CEDAR_MIN_SEQUENCE `{sequence}` is not a sequence number")
+                })?,
+                Err(_) => 0,
+            };
+            Box::new(
+                SignedSource::new(
+                    url_source(uri)?,
+                    url_source(&format!("{uri}.sig"))?,
+                    signer
+                        .parse()
+                        .map_err(|_| miette!("CEDAR_SIGNER `{signer}` is not an address"))?,
+                )
+                .with_min_sequence(min_sequence),
+            )
+        }
+        Err(_) => Box::new(url_source(uri)?),
+    };
+    source
+        .fetch(None)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to fetch {context} file {uri}"))?
+        .map(|fetched| fetched.text)
+        .ok_or_else(|| miette!("failed to fetch {context} file {uri}"))
+}
+
+#[cfg(not(feature = "remote"))]
+fn fetch_remote(filename: &Path, context: &str) -> Result<String> {
+    Err(miette!(
+        "cannot fetch {context} file {}: the CLI was built without the `remote` feature",
+        filename.display()
+    ))
+}
+
 // Convenient wrapper around `read_from_file_or_stdin` to just read from a file
 fn read_from_file(filename: impl AsRef<Path>, context: &str) -> Result<String> {
     read_from_file_or_stdin(Some(filename), context)
@@ -910,39 +2683,92 @@ fn read_policy_set(
     filename: Option<impl AsRef<Path> + std::marker::Copy>,
 ) -> miette::Result<PolicySet> {
     let context = "policy set";
-    let ps_str = read_from_file_or_stdin(filename, context)?;
-    let ps = PolicySet::from_str(&ps_str)
-        .map_err(|err| {
-            let name = filename.map_or_else(
-                || "<stdin>".to_owned(),
-                |n| n.as_ref().display().to_string(),
+    let ps = match filename {
+        Some(filename) if is_bundle(filename.as_ref()) => {
+            return rename_from_id_annotation(
+                read_bundle(filename)?
+                    .policy_set()
+                    .into_diagnostic()
+                    .wrap_err_with(|| format!("failed to load {context}"))?,
             );
-            Report::new(err).with_source_code(NamedSource::new(name, ps_str))
-        })
-        .wrap_err_with(|| format!("failed to parse {context}"))?;
+        }
+        Some(filename) if remote_uri(filename.as_ref()).is_some() => {
+            let ps_str = read_from_file(filename, context)?;
+            PolicySet::from_str(&ps_str).map_err(|err| {
+                Report::new(err).with_source_code(NamedSource::new(
+                    filename.as_ref().display().to_string(),
+                    ps_str,
+                ))
+            })
+        }
+        // a policy file may import other files, which are relative to it
+        Some(filename) => PolicySet::from_file(filename).map_err(|err| {
+            let dir = filename.as_ref().parent().unwrap_or_else(|| Path::new(""));
+            match err {
+                ImportError::Parse { file, text, errs } => Report::new(errs)
+                    .with_source_code(NamedSource::new(dir.join(file).display().to_string(), text)),
+                err => Report::new(err),
+            }
+        }),
+        None => {
+            let ps_str = read_from_file_or_stdin(filename, context)?;
+            PolicySet::from_str(&ps_str).map_err(|err| {
+                Report::new(err).with_source_code(NamedSource::new("<stdin>", ps_str))
+            })
+        }
+    }
+    .wrap_err_with(|| format!("failed to parse {context}"))?;
     rename_from_id_annotation(ps)
 }
 
+/// Is the file a policy bundle, whose policies and schema commands read in
+/// place of a policy or schema file?
+fn is_bundle(filename: &Path) -> bool {
+    filename.to_string_lossy().ends_with(".bundle.json")
+}
+
+/// Load and verify the bundle in a file
+fn read_bundle(filename: impl AsRef<Path>) -> Result<PolicyBundle> {
+    PolicyBundle::from_json_str(&read_from_file(filename.as_ref(), "bundle")?)
+        .and_then(|bundle| bundle.verify().map(|()| bundle))
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to verify bundle {}", filename.as_ref().display()))
+}
+
 fn read_schema_file(filename: impl AsRef<Path> + std::marker::Copy) -> Result<Schema> {
+    if is_bundle(filename.as_ref()) {
+        return read_bundle(filename)?
+            .schema()
+            .into_diagnostic()?
+            .ok_or_else(|| miette!("bundle {} has no schema", filename.as_ref().display()));
+    }
     let schema_src = read_from_file(filename, "schema")?;
-    Schema::from_str(&schema_src)
-        .into_diagnostic()
-        .wrap_err_with(|| {
-            format!(
-                "failed to parse schema from file {}",
-                filename.as_ref().display()
-            )
-        })
+    // JSON schemas are objects; anything else is in the human-readable format
+    if schema_src.trim_start().starts_with('{') {
+        Schema::from_str(&schema_src)
+    } else {
+        Schema::from_str_natural(&schema_src)
+    }
+    .into_diagnostic()
+    .wrap_err_with(|| {
+        format!(
+            "failed to parse schema from file {}",
+            filename.as_ref().display()
+        )
+    })
 }
 
 fn load_actions_from_schema(entities: Entities, schema: &Option<Schema>) -> Result<Entities> {
     match schema {
-        Some(schema) => match schema.action_entities() {
-            Ok(action_entities) => Entities::from_entities(
-                entities
+        Some(schema) => match Entities::actions_from_schema(schema) {
+            // The schema's definition of an action takes precedence over any
+            // definition of it in the entity file.
+            Ok(action_entities) => Entities::from_entities_with_options(
+                action_entities
                     .iter()
                     .cloned()
-                    .chain(action_entities.iter().cloned()),
+                    .chain(entities.iter().cloned()),
+                EntitiesOptions::new().with_duplicates(DuplicateUidStrategy::FirstWins),
             )
             .into_diagnostic()
             .wrap_err("failed to merge action entities with entity file"),
@@ -961,8 +2787,7 @@ fn execute_request(
     links_filename: Option<impl AsRef<Path>>,
     entities_filename: impl AsRef<Path>,
     schema_filename: Option<impl AsRef<Path> + std::marker::Copy>,
-    compute_duration: bool,
-) -> Result<Response, Vec<Report>> {
+) -> Result<(Response, Duration, PolicySet), Vec<Report>> {
     let mut errs = vec![];
     let policies = match read_policy_and_links(policies_filename.as_ref(), links_filename) {
         Ok(pset) => pset,
@@ -998,14 +2823,7 @@ fn execute_request(
             let authorizer = Authorizer::new();
             let auth_start = Instant::now();
             let ans = authorizer.is_authorized(&request, &policies, &entities);
-            let auth_dur = auth_start.elapsed();
-            if compute_duration {
-                println!(
-                    "Authorization Time (micro seconds) : {}",
-                    auth_dur.as_micros()
-                );
-            }
-            Ok(ans)
+            Ok((ans, auth_start.elapsed(), policies))
         }
         Ok(_) => Err(errs),
         Err(e) => {
@@ -18,10 +18,17 @@
 
 use clap::Parser;
 use miette::ErrorHook;
+use stats_alloc::{StatsAlloc, INSTRUMENTED_SYSTEM};
+
+// counts allocations for the `bench` command
+#[global_allocator]
+static GLOBAL: &StatsAlloc<std::alloc::System> = &INSTRUMENTED_SYSTEM;
 
 use cedar_policy_cli::{
-    authorize, check_parse, evaluate, format_policies, link, new, validate, CedarExitCode, Cli,
-    Commands, ErrorFormat,
+    authorize, authorize_batch, bench, bundle, check_parse, check_schema_usage, diff_schema,
+    differential, evaluate, explain, export, format_policies, import_rego, link, lint, new,
+    new_link, new_policy, repl, server, skeleton, snapshot_entities, test, translate_policy,
+    translate_schema, validate, verify_bundle, CedarExitCode, Cli, Commands, ErrorFormat,
 };
 
 fn main() -> CedarExitCode {
@@ -42,11 +49,33 @@ fn main() -> CedarExitCode {
 
     match cli.command {
         Commands::Authorize(args) => authorize(&args),
+        Commands::AuthorizeBatch(args) => authorize_batch(&args),
         Commands::Evaluate(args) => evaluate(&args).0,
+        Commands::Explain(args) => explain(&args),
         Commands::CheckParse(args) => check_parse(&args),
         Commands::Validate(args) => validate(&args),
         Commands::Format(args) => format_policies(&args),
         Commands::Link(args) => link(&args),
         Commands::New(args) => new(&args),
+        Commands::NewLink(args) => new_link(&args),
+        Commands::NewPolicy(args) => new_policy(&args),
+        Commands::Skeleton(args) => skeleton(&args),
+        Commands::SnapshotEntities(args) => snapshot_entities(&args),
+        Commands::Bundle(args) => bundle(&args),
+        Commands::VerifyBundle(args) => verify_bundle(&args),
+        Commands::TranslateSchema(args) => translate_schema(&args),
+        Commands::TranslatePolicy(args) => translate_policy(&args),
+        Commands::DiffSchema(args) => diff_schema(&args),
+        Commands::CheckSchemaUsage(args) => check_schema_usage(&args),
+        Commands::Lint(args) => lint(&args),
+        Commands::Export(args) => export(&args),
+        Commands::ImportRego(args) => import_rego(&args),
+        Commands::Repl(args) => repl(&args),
+        Commands::Server(args) => server(&args),
+        #[cfg(feature = "proxy")]
+        Commands::Proxy(args) => cedar_policy_cli::proxy(&args),
+        Commands::Test(args) => test(&args),
+        Commands::Differential(args) => differential(&args),
+        Commands::Bench(args) => bench(&args),
     }
 }
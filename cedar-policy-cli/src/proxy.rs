@@ -0,0 +1,245 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The `proxy` command: a JSON-RPC proxy in front of an Ethereum node, which
+//! authorizes every call against a policy set and forwards the allowed ones.
+//!
+//! The principal of a call is `ApiKey::"<key>"`, with the key of the client
+//! from the `--api-key-header` header, or else the path, like `/<key>`, and is
+//! unspecified if there is neither. Calls of `eth_call`, `eth_getLogs` and
+//! `eth_getStorageAt` are mapped into requests as by
+//! `cedar_policy::rpc::RpcMapper`, and calls of any other method into a
+//! request for the action `Action::"<method>"` with an unspecified resource
+//! and an empty context.
+//!
+//! Calls which are denied are answered with a JSON-RPC error, without
+//! reaching the node. In a batch, the allowed calls are forwarded as a batch,
+//! and the errors of the others added to its response. `GET /health` tells
+//! whether the proxy is up.
+//!
+//! Connections are answered by a fixed number of worker threads, and closed
+//! after one request, as by the `server` command.
+
+use std::{net::TcpListener, net::TcpStream};
+
+use cedar_policy::{
+    rpc::{RpcError, RpcMapper},
+    *,
+};
+use miette::{IntoDiagnostic, Result, WrapErr};
+use serde_json::{json, Value};
+
+use super::{server, CedarExitCode, ProxyArgs};
+
+/// The JSON-RPC error code of calls which are not authorized
+const UNAUTHORIZED: i64 = -32001;
+
+/// The JSON-RPC error code of requests which are not JSON
+const PARSE_ERROR: i64 = -32700;
+
+/// The JSON-RPC error code of calls with invalid parameters
+const INVALID_PARAMS: i64 = -32602;
+
+/// The JSON-RPC error code of calls which could not be forwarded
+const INTERNAL_ERROR: i64 = -32603;
+
+/// The JSON-RPC error response to `call` with `code` and `message`
+fn error_response(call: &Value, code: i64, message: impl Into<String>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": call.get("id").cloned().unwrap_or(Value::Null),
+        "error": { "code": code, "message": message.into() },
+    })
+}
+
+/// The state shared by the threads answering requests
+struct Proxy<'a> {
+    args: &'a ProxyArgs,
+    files: server::Files,
+    mapper: RpcMapper,
+    client: reqwest::blocking::Client,
+}
+
+impl Proxy<'_> {
+    /// The principal of a request, from its API key
+    fn principal(&self, request: &server::HttpRequest) -> Option<EntityUid> {
+        request
+            .header(&self.args.api_key_header)
+            .or_else(|| request.path.strip_prefix('/').filter(|key| !key.is_empty()))
+            .map(|key| EntityUid::from_known_type("ApiKey", key))
+    }
+
+    /// `None` if `principal` may make `call`, or else the error response to
+    /// it
+    fn authorize(&self, principal: Option<&EntityUid>, call: &Value) -> Option<Value> {
+        let requests = match self.mapper.requests(principal, call) {
+            Ok(requests) => requests,
+            Err(RpcError::UnsupportedMethod(method)) => vec![Request::new(
+                principal.cloned(),
+                Some(EntityUid::from_known_type("Action", &method)),
+                None,
+                Context::empty(),
+            )],
+            Err(e) => return Some(error_response(call, INVALID_PARAMS, e.to_string())),
+        };
+        let authorizer = Authorizer::new();
+        let denied = requests.iter().any(|request| {
+            authorizer
+                .is_authorized(request, &self.files.policies, &self.files.entities)
+                .decision()
+                == Decision::Deny
+        });
+        let method = call.get("method").and_then(Value::as_str).unwrap_or("");
+        denied.then(|| error_response(call, UNAUTHORIZED, format!("`{method}` is not authorized")))
+    }
+
+    /// Forward `calls` to the node, giving the status code and body of its
+    /// response
+    fn forward(&self, calls: &Value) -> Result<(u16, Value)> {
+        let response = self
+            .client
+            .post(&self.args.upstream)
+            .json(calls)
+            .send()
+            .into_diagnostic()
+            .wrap_err("failed to forward the request to the node")?;
+        let status = response.status().as_u16();
+        let body = response
+            .json()
+            .into_diagnostic()
+            .wrap_err("failed to parse the response of the node")?;
+        Ok((status, body))
+    }
+
+    /// The status code and body of the response to a JSON-RPC request
+    fn respond(&self, request: &server::HttpRequest) -> (u16, Value) {
+        if request.method == "GET" && request.path == "/health" {
+            return (200, json!({ "status": "ok" }));
+        }
+        if request.method != "POST" {
+            return (
+                405,
+                error_response(
+                    &Value::Null,
+                    PARSE_ERROR,
+                    "JSON-RPC requests must be POSTed",
+                ),
+            );
+        }
+        let body = match serde_json::from_slice::<Value>(&request.body) {
+            Ok(body) => body,
+            Err(e) => {
+                return (
+                    400,
+                    error_response(&Value::Null, PARSE_ERROR, e.to_string()),
+                )
+            }
+        };
+        let principal = self.principal(request);
+        let (calls, batch) = match body {
+            Value::Array(calls) => (calls, true),
+            call => (vec![call], false),
+        };
+        let (mut allowed, mut denied) = (Vec::new(), Vec::new());
+        for call in calls {
+            match self.authorize(principal.as_ref(), &call) {
+                None => allowed.push(call),
+                Some(error) => denied.push(error),
+            }
+        }
+        let forwarded = if allowed.is_empty() {
+            None
+        } else if batch {
+            Some(Value::Array(allowed))
+        } else {
+            allowed.pop()
+        };
+        let (status, responses) = match forwarded.map(|calls| self.forward(&calls)) {
+            None => (200, Vec::new()),
+            Some(Ok((status, Value::Array(responses)))) => (status, responses),
+            Some(Ok((status, response))) => (status, vec![response]),
+            Some(Err(e)) => {
+                return (
+                    502,
+                    error_response(&Value::Null, INTERNAL_ERROR, format!("{e:?}")),
+                )
+            }
+        };
+        let mut responses = responses.into_iter().chain(denied);
+        if batch {
+            (status, Value::Array(responses.collect()))
+        } else {
+            (status, responses.next().unwrap_or(Value::Null))
+        }
+    }
+
+    /// Read a request from `stream` and write the response to it
+    fn handle(&self, mut stream: TcpStream) {
+        let (status, body) = match server::read_request(&mut stream) {
+            Ok(request) => {
+                let (status, body) = self.respond(&request);
+                eprintln!("{} {} {status}", request.method, request.path);
+                (status, body)
+            }
+            Err((status, e)) => (
+                status,
+                error_response(&Value::Null, PARSE_ERROR, e.to_string()),
+            ),
+        };
+        server::write_response(&mut stream, status, "application/json", &body.to_string());
+    }
+}
+
+fn serve(args: &ProxyArgs) -> Result<()> {
+    let files = server::Files::load_from(
+        &args.policies_file,
+        args.template_linked_file.as_ref(),
+        args.schema_file.as_ref(),
+        args.entities_file.as_ref(),
+    )?;
+    let proxy = Proxy {
+        args,
+        files,
+        mapper: RpcMapper::new(args.chain_id),
+        client: reqwest::blocking::Client::new(),
+    };
+    let listener = TcpListener::bind((args.host.as_str(), args.port))
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to listen on {}:{}", args.host, args.port))?;
+    let addr = listener.local_addr().into_diagnostic()?;
+    println!("Listening on http://{addr}");
+    server::serve_connections(
+        &listener,
+        args.workers,
+        args.max_connections,
+        |stream| proxy.handle(stream),
+        |mut stream| {
+            let body = error_response(&Value::Null, INTERNAL_ERROR, "the proxy is too busy");
+            server::write_response(&mut stream, 503, "application/json", &body.to_string());
+        },
+    );
+    Ok(())
+}
+
+pub(super) fn proxy(args: &ProxyArgs) -> CedarExitCode {
+    match serve(args) {
+        Ok(()) => CedarExitCode::Success,
+        Err(e) => {
+            println!("Error: {e:?}");
+            CedarExitCode::Failure
+        }
+    }
+}
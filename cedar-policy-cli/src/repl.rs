@@ -0,0 +1,285 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The `repl` command: an interactive session which keeps a policy set,
+//! schema and entities loaded, so that requests and expressions can be tried
+//! against them one after another.
+
+use std::{
+    io::{self, BufRead, IsTerminal, Write},
+    str::FromStr,
+};
+
+use cedar_policy::*;
+use miette::{miette, IntoDiagnostic, Result, WrapErr};
+
+use super::{
+    load_actions_from_schema, load_entities, read_policy_and_links, read_schema_file,
+    CedarExitCode, ReplArgs,
+};
+
+const HELP: &str = "\
+Enter a Cedar expression to evaluate it for the current request, or one of:
+  :principal [UID]   set the principal of the request, or unset it
+  :action [UID]      set the action of the request, or unset it
+  :resource [UID]    set the resource of the request, or unset it
+  :context [JSON]    set the context of the request to a JSON object, or empty it
+  :request           show the current request
+  :authorize         authorize the current request against the policies
+  :policies          list the policies and templates
+  :reload            read the policy, schema and entity files again
+  :help              show this message
+  :quit              leave the REPL";
+
+/// What the REPL has read from the files it was given
+struct Files {
+    policies: PolicySet,
+    schema: Option<Schema>,
+    entities: Entities,
+}
+
+impl Files {
+    fn load(args: &ReplArgs) -> Result<Self> {
+        let policies = match &args.policies_file {
+            Some(file) => read_policy_and_links(file, args.template_linked_file.as_ref())?,
+            None => PolicySet::new(),
+        };
+        let schema = args
+            .schema_file
+            .as_ref()
+            .map(read_schema_file)
+            .transpose()?;
+        let entities = match &args.entities_file {
+            Some(file) => load_entities(file, schema.as_ref())?,
+            None => Entities::empty(),
+        };
+        let entities = load_actions_from_schema(entities, &schema)?;
+        Ok(Self {
+            policies,
+            schema,
+            entities,
+        })
+    }
+}
+
+/// The state of a REPL session: the loaded files and the request built up so
+/// far
+struct Session<'a> {
+    args: &'a ReplArgs,
+    files: Files,
+    principal: Option<EntityUid>,
+    action: Option<EntityUid>,
+    resource: Option<EntityUid>,
+    context: Option<serde_json::Value>,
+}
+
+impl<'a> Session<'a> {
+    fn new(args: &'a ReplArgs) -> Result<Self> {
+        Ok(Self {
+            args,
+            files: Files::load(args)?,
+            principal: None,
+            action: None,
+            resource: None,
+            context: None,
+        })
+    }
+
+    /// Run one line of input
+    fn run(&mut self, line: &str) -> Result<()> {
+        let Some(command) = line.strip_prefix(':') else {
+            return self.evaluate(line);
+        };
+        let (command, arg) = command
+            .split_once(char::is_whitespace)
+            .map_or((command, ""), |(command, arg)| (command, arg.trim()));
+        match command {
+            "principal" => self.principal = parse_uid(arg, "principal")?,
+            "action" => self.action = parse_uid(arg, "action")?,
+            "resource" => self.resource = parse_uid(arg, "resource")?,
+            "context" => {
+                self.context = if arg.is_empty() {
+                    None
+                } else {
+                    Some(
+                        serde_json::from_str(arg)
+                            .into_diagnostic()
+                            .wrap_err("failed to parse the context as JSON")?,
+                    )
+                }
+            }
+            "request" => self.show_request(),
+            "authorize" => self.authorize()?,
+            "policies" => self.list_policies(),
+            "reload" => {
+                // keep the files loaded before if any of them is now broken
+                self.files = Files::load(self.args)?;
+                println!("Reloaded the files");
+            }
+            "help" => println!("{HELP}"),
+            _ => {
+                return Err(miette!(
+                    "unknown command `:{command}`; enter `:help` for the list of commands"
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// The request made of the principal, action, resource and context set so
+    /// far
+    fn request(&self) -> Result<Request> {
+        let context = match &self.context {
+            None => Context::empty(),
+            Some(json) => Context::from_json_value(
+                json.clone(),
+                self.files
+                    .schema
+                    .as_ref()
+                    .and_then(|schema| Some((schema, self.action.as_ref()?))),
+            )
+            .into_diagnostic()
+            .wrap_err("failed to create the context")?,
+        };
+        Ok(Request::new(
+            self.principal.clone(),
+            self.action.clone(),
+            self.resource.clone(),
+            context,
+        ))
+    }
+
+    fn show_request(&self) {
+        let show = |uid: &Option<EntityUid>| {
+            uid.as_ref()
+                .map_or_else(|| "unset".to_string(), ToString::to_string)
+        };
+        println!("principal: {}", show(&self.principal));
+        println!("action: {}", show(&self.action));
+        println!("resource: {}", show(&self.resource));
+        match &self.context {
+            None => println!("context: {{}}"),
+            Some(json) => println!("context: {json}"),
+        }
+    }
+
+    fn authorize(&self) -> Result<()> {
+        let request = self.request()?;
+        let ans =
+            Authorizer::new().is_authorized(&request, &self.files.policies, &self.files.entities);
+        match ans.decision() {
+            Decision::Allow => println!("ALLOW"),
+            Decision::Deny => println!("DENY"),
+        }
+        for err in ans.diagnostics().errors() {
+            println!("{err}");
+        }
+        let mut reasons = ans.diagnostics().reason().collect::<Vec<_>>();
+        if reasons.is_empty() {
+            println!("note: no policies applied to this request");
+        } else {
+            reasons.sort_by_key(|id| id.to_string());
+            println!("note: this decision was due to the following policies:");
+            for reason in reasons {
+                println!("  {reason}");
+            }
+        }
+        Ok(())
+    }
+
+    fn evaluate(&self, expression: &str) -> Result<()> {
+        let expr = Expression::from_str(expression).wrap_err("failed to parse the expression")?;
+        let result = eval_expression(&self.request()?, &self.files.entities, &expr)
+            .into_diagnostic()
+            .wrap_err("failed to evaluate the expression")?;
+        println!("{result}");
+        Ok(())
+    }
+
+    fn list_policies(&self) {
+        let mut lines =
+            self.files
+                .policies
+                .policies()
+                .map(|policy| format!("{} {}", policy.effect(), policy.id()))
+                .chain(
+                    self.files.policies.templates().map(|template| {
+                        format!("{} {} (template)", template.effect(), template.id())
+                    }),
+                )
+                .collect::<Vec<_>>();
+        if lines.is_empty() {
+            println!("no policies are loaded");
+        }
+        lines.sort();
+        for line in lines {
+            println!("{line}");
+        }
+    }
+}
+
+/// Parse the argument of `:principal` and the like, where no argument unsets
+/// the entity
+fn parse_uid(arg: &str, what: &str) -> Result<Option<EntityUid>> {
+    if arg.is_empty() {
+        return Ok(None);
+    }
+    arg.parse()
+        .map(Some)
+        .wrap_err_with(|| format!("failed to parse {what} {arg} as entity Uid"))
+}
+
+pub(super) fn repl(args: &ReplArgs) -> CedarExitCode {
+    let mut session = match Session::new(args) {
+        Ok(session) => session,
+        Err(e) => {
+            println!("Error: {e:?}");
+            return CedarExitCode::Failure;
+        }
+    };
+    let stdin = io::stdin();
+    // only prompt a person, not a script piping its input in
+    let interactive = stdin.is_terminal();
+    if interactive {
+        println!("Enter `:help` for the list of commands");
+    }
+    let mut lines = stdin.lock().lines();
+    loop {
+        if interactive {
+            print!("cedar> ");
+            // a prompt which fails to show is no reason to stop
+            let _ = io::stdout().flush();
+        }
+        let line = match lines.next() {
+            None => break,
+            Some(Ok(line)) => line,
+            Some(Err(e)) => {
+                println!("Error: {e}");
+                return CedarExitCode::Failure;
+            }
+        };
+        match line.trim() {
+            "" => continue,
+            ":quit" => break,
+            line => {
+                if let Err(e) = session.run(line) {
+                    println!("Error: {e:?}");
+                }
+            }
+        }
+    }
+    CedarExitCode::Success
+}
@@ -0,0 +1,554 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The `server` command: an HTTP server which answers authorization requests
+//! against a policy set, schema and entities loaded from files.
+//!
+//! It has the following endpoints, which respond with JSON:
+//! * `GET /health`: whether the server is up
+//! * `POST /authorize`: the decision for one request, given as a JSON object
+//!   with the fields of a `--request-json` file, and optionally the entities
+//!   to use instead of those of the entities file
+//! * `POST /authorize-batch`: the decisions for a JSON array of such requests
+//! * `POST /reload`: read the policy, schema and entity files again. With
+//!   `--reload-token`, the client must give the token as a bearer token;
+//!   without, it must be on the same host.
+//! * `GET /metrics`: the metrics of the requests authorized so far, in the
+//!   Prometheus text format rather than JSON
+//!
+//! Connections are answered by a fixed number of worker threads, and closed
+//! after one request. Connections beyond `--max-connections` are refused, and
+//! a client gets 30 seconds to send its whole request.
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{IpAddr, TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, TrySendError},
+        Arc, Mutex, PoisonError, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use cedar_policy::{metrics::PrometheusMetrics, *};
+use miette::{miette, IntoDiagnostic, Result, WrapErr};
+use serde::Deserialize;
+
+use super::{
+    decision_record, load_actions_from_schema, load_entities, read_policy_and_links,
+    read_schema_file, CedarExitCode, RequestJSON, ServerArgs,
+};
+
+/// The largest request head we read, in bytes
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+/// The largest request body we read, in bytes
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+/// How long a client may take to send its whole request, or to receive the
+/// response
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What the server has read from the files it was given
+pub(super) struct Files {
+    pub(super) policies: PolicySet,
+    pub(super) schema: Option<Schema>,
+    pub(super) entities: Entities,
+}
+
+impl Files {
+    fn load(args: &ServerArgs) -> Result<Self> {
+        Self::load_from(
+            &args.policies_file,
+            args.template_linked_file.as_ref(),
+            args.schema_file.as_ref(),
+            args.entities_file.as_ref(),
+        )
+    }
+
+    /// Read the policy, template-linked, schema and entity files
+    pub(super) fn load_from(
+        policies_file: &str,
+        template_linked_file: Option<&String>,
+        schema_file: Option<&String>,
+        entities_file: Option<&String>,
+    ) -> Result<Self> {
+        let policies = read_policy_and_links(policies_file, template_linked_file)?;
+        let schema = schema_file.map(read_schema_file).transpose()?;
+        let entities = match entities_file {
+            Some(file) => load_entities(file, schema.as_ref())?,
+            None => Entities::empty(),
+        };
+        let entities = load_actions_from_schema(entities, &schema)?;
+        Ok(Self {
+            policies,
+            schema,
+            entities,
+        })
+    }
+
+    /// Authorize a request given as the JSON body of `/authorize`, naming
+    /// `source` as where it came from in errors, and report it to `metrics`
+    fn authorize(
+        &self,
+        json: serde_json::Value,
+        source: &str,
+        metrics: &PrometheusMetrics,
+    ) -> Result<Response> {
+        let request: AuthorizeRequest = serde_json::from_value(json)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to parse {source}"))?;
+        let entities = match request.entities {
+            None => None,
+            Some(json) => {
+                let entities = Entities::from_json_value(json, self.schema.as_ref())
+                    .into_diagnostic()
+                    .wrap_err_with(|| format!("failed to parse the entities of {source}"))?;
+                Some(load_actions_from_schema(entities, &self.schema)?)
+            }
+        };
+        let request = request.request.into_request(self.schema.as_ref(), source)?;
+        Ok(Authorizer::new().is_authorized_with_metrics(
+            &request,
+            &self.policies,
+            entities.as_ref().unwrap_or(&self.entities),
+            metrics,
+        ))
+    }
+}
+
+/// The body of `/authorize`, and each element of the body of
+/// `/authorize-batch`
+#[derive(Deserialize)]
+struct AuthorizeRequest {
+    #[serde(flatten)]
+    request: RequestJSON,
+    /// Entities to use for this request instead of those of the entities file,
+    /// in the JSON format of an entities file
+    #[serde(default)]
+    entities: Option<serde_json::Value>,
+}
+
+/// The state shared by the threads answering requests
+struct Server<'a> {
+    args: &'a ServerArgs,
+    /// Replaced as a whole by `/reload`, so that requests being answered keep
+    /// the files they started with
+    files: RwLock<Arc<Files>>,
+    /// Kept across `/reload`s
+    metrics: PrometheusMetrics,
+}
+
+/// The body of a response
+enum Body {
+    Json(serde_json::Value),
+    /// Metrics in the Prometheus text format
+    Metrics(String),
+}
+
+impl From<serde_json::Value> for Body {
+    fn from(json: serde_json::Value) -> Self {
+        Self::Json(json)
+    }
+}
+
+impl Server<'_> {
+    fn files(&self) -> Arc<Files> {
+        self.files
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Whether `request`, from `peer`, may reload the files
+    fn may_reload(&self, request: &HttpRequest, peer: Option<IpAddr>) -> Result<(), (u16, Body)> {
+        match &self.args.reload_token {
+            Some(token) => {
+                let given = request
+                    .header("authorization")
+                    .and_then(|value| value.strip_prefix("Bearer "))
+                    .unwrap_or_default();
+                if constant_time_eq(given.as_bytes(), token.as_bytes()) {
+                    Ok(())
+                } else {
+                    Err(error_response(
+                        401,
+                        miette!("`/reload` requires the reload token as a bearer token"),
+                    ))
+                }
+            }
+            None if peer.is_some_and(|peer| peer.is_loopback()) => Ok(()),
+            None => Err(error_response(
+                403,
+                miette!("`/reload` is only accepted from the same host without a reload token"),
+            )),
+        }
+    }
+
+    /// The status code and body of the response to `request`, from `peer`
+    fn respond(&self, request: &HttpRequest, peer: Option<IpAddr>) -> (u16, Body) {
+        let (method, path, body) = (
+            request.method.as_str(),
+            request.path.as_str(),
+            request.body.as_slice(),
+        );
+        let allowed = match path {
+            "/health" | "/metrics" => "GET",
+            "/authorize" | "/authorize-batch" | "/reload" => "POST",
+            _ => return error_response(404, miette!("no such endpoint `{path}`")),
+        };
+        if method != allowed {
+            return error_response(
+                405,
+                miette!("`{path}` only accepts {allowed}, not {method}"),
+            );
+        }
+        match path {
+            "/health" => (200, serde_json::json!({ "status": "ok" }).into()),
+            "/metrics" => (200, Body::Metrics(self.metrics.render())),
+            "/reload" => match self
+                .may_reload(request, peer)
+                .and_then(|()| Files::load(self.args).map_err(|e| error_response(500, e)))
+            {
+                Ok(files) => {
+                    // keep the files loaded before if any of them is now broken
+                    *self.files.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(files);
+                    (200, serde_json::json!({ "status": "reloaded" }).into())
+                }
+                Err(response) => response,
+            },
+            _ => {
+                let json = match serde_json::from_slice::<serde_json::Value>(body)
+                    .into_diagnostic()
+                    .wrap_err("failed to parse the request body as JSON")
+                {
+                    Ok(json) => json,
+                    Err(e) => return error_response(400, e),
+                };
+                let files = self.files();
+                if path == "/authorize" {
+                    let outcome = files.authorize(json, "the request", &self.metrics);
+                    let status = if outcome.is_ok() { 200 } else { 400 };
+                    (
+                        status,
+                        serde_json::Value::from(decision_record(&outcome)).into(),
+                    )
+                } else {
+                    let serde_json::Value::Array(requests) = json else {
+                        return error_response(
+                            400,
+                            miette!("the body of `/authorize-batch` must be an array of requests"),
+                        );
+                    };
+                    let records = requests
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, json)| {
+                            let outcome =
+                                files.authorize(json, &format!("request {index}"), &self.metrics);
+                            serde_json::Value::from(decision_record(&outcome))
+                        })
+                        .collect();
+                    (200, serde_json::Value::Array(records).into())
+                }
+            }
+        }
+    }
+
+    /// Read a request from `stream` and write the response to it
+    fn handle(&self, mut stream: TcpStream) {
+        let peer = stream.peer_addr().ok().map(|addr| addr.ip());
+        let (status, body) = match read_request(&mut stream) {
+            Ok(request) => {
+                let (status, response) = self.respond(&request, peer);
+                eprintln!("{} {} {status}", request.method, request.path);
+                (status, response)
+            }
+            Err((status, e)) => error_response(status, e),
+        };
+        write_body(&mut stream, status, body);
+    }
+}
+
+/// Write a response with `status` and `body` to `stream`
+fn write_body(stream: &mut TcpStream, status: u16, body: Body) {
+    let (content_type, body) = match body {
+        Body::Json(json) => ("application/json", json.to_string()),
+        Body::Metrics(metrics) => ("text/plain; version=0.0.4", metrics),
+    };
+    write_response(stream, status, content_type, &body);
+}
+
+/// Write a response with `status`, and `body` of `content_type`, to `stream`
+pub(super) fn write_response(stream: &mut TcpStream, status: u16, content_type: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {status} {}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        reason_phrase(status),
+        body.len()
+    );
+    // a client which went away before its answer, or does not read it, is no
+    // reason to stop
+    let _ = stream.set_write_timeout(Some(READ_TIMEOUT));
+    let _ = stream.write_all(response.as_bytes());
+}
+
+/// Whether `a` and `b` are equal, taking as long whichever byte they differ
+/// at, so that comparing a secret leaks nothing of it
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn error_response(status: u16, error: miette::Report) -> (u16, Body) {
+    (
+        status,
+        serde_json::Value::from(decision_record(&Err(error))).into(),
+    )
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+/// An HTTP request read by [`read_request`]
+pub(super) struct HttpRequest {
+    pub(super) method: String,
+    /// The path, without any query
+    pub(super) path: String,
+    pub(super) headers: Vec<(String, String)>,
+    pub(super) body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// The value of the header `name`, which is case-insensitive
+    pub(super) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Read a request, or the status code to respond with if it cannot be read.
+/// The body is read as it arrives, and the whole request must arrive within
+/// [`READ_TIMEOUT`].
+pub(super) fn read_request(stream: &mut TcpStream) -> Result<HttpRequest, (u16, miette::Report)> {
+    let bad_request = |e: miette::Report| (400, e);
+    let deadline = Instant::now() + READ_TIMEOUT;
+    let mut buf = Vec::new();
+    let mut chunk = [0; 8192];
+    loop {
+        let mut headers = [httparse::EMPTY_HEADER; 64];
+        let mut request = httparse::Request::new(&mut headers);
+        match request
+            .parse(&buf)
+            .into_diagnostic()
+            .wrap_err("failed to parse the HTTP request")
+            .map_err(bad_request)?
+        {
+            httparse::Status::Complete(head_len) => {
+                let method = request.method.unwrap_or_default().to_string();
+                let path = request.path.unwrap_or_default();
+                let path = path
+                    .split_once('?')
+                    .map_or(path, |(path, _)| path)
+                    .to_string();
+                let headers = request
+                    .headers
+                    .iter()
+                    .map(|header| {
+                        (
+                            header.name.to_string(),
+                            String::from_utf8_lossy(header.value).into_owned(),
+                        )
+                    })
+                    .collect();
+                let mut request = HttpRequest {
+                    method,
+                    path,
+                    headers,
+                    body: Vec::new(),
+                };
+                if request.header("transfer-encoding").is_some() {
+                    return Err((501, miette!("chunked request bodies are not supported")));
+                }
+                let body_len = match request.header("content-length") {
+                    None => 0,
+                    Some(len) => len
+                        .trim()
+                        .parse::<usize>()
+                        .into_diagnostic()
+                        .wrap_err("failed to parse the Content-Length header")
+                        .map_err(bad_request)?,
+                };
+                if body_len > MAX_BODY_SIZE {
+                    return Err((
+                        413,
+                        miette!("request bodies may be at most {MAX_BODY_SIZE} bytes"),
+                    ));
+                }
+                let mut body = buf.split_off(head_len.min(buf.len()));
+                while body.len() < body_len {
+                    let read = read_before(stream, &mut chunk, deadline)?;
+                    if read == 0 {
+                        return Err(bad_request(miette!("the request body ended early")));
+                    }
+                    body.extend(chunk.iter().take(read));
+                }
+                body.truncate(body_len);
+                request.body = body;
+                return Ok(request);
+            }
+            httparse::Status::Partial if buf.len() > MAX_HEAD_SIZE => {
+                return Err((
+                    413,
+                    miette!("request heads may be at most {MAX_HEAD_SIZE} bytes"),
+                ));
+            }
+            httparse::Status::Partial => {
+                let read = read_before(stream, &mut chunk, deadline)?;
+                if read == 0 {
+                    return Err(bad_request(miette!("the request ended early")));
+                }
+                buf.extend(chunk.iter().take(read));
+            }
+        }
+    }
+}
+
+/// Read what `stream` has into `chunk`, waiting no later than `deadline`
+fn read_before(
+    stream: &mut TcpStream,
+    chunk: &mut [u8],
+    deadline: Instant,
+) -> Result<usize, (u16, miette::Report)> {
+    let timed_out = || {
+        (
+            408,
+            miette!(
+                "the request took longer than {} seconds to send",
+                READ_TIMEOUT.as_secs()
+            ),
+        )
+    };
+    let remaining = deadline
+        .checked_duration_since(Instant::now())
+        .filter(|remaining| !remaining.is_zero())
+        .ok_or_else(timed_out)?;
+    stream
+        .set_read_timeout(Some(remaining))
+        .into_diagnostic()
+        .map_err(|e| (400, e))?;
+    match stream.read(chunk) {
+        Ok(read) => Ok(read),
+        Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+            Err(timed_out())
+        }
+        Err(e) => Err((
+            400,
+            miette::Report::msg(e).wrap_err("failed to read the request"),
+        )),
+    }
+}
+
+/// Answer the connections of `listener` with `handle` on `workers` threads,
+/// keeping at most `max_connections` open at once. Those beyond are answered
+/// with `refuse`, on the thread accepting connections.
+pub(super) fn serve_connections(
+    listener: &TcpListener,
+    workers: usize,
+    max_connections: usize,
+    handle: impl Fn(TcpStream) + Sync,
+    refuse: impl Fn(TcpStream),
+) {
+    let workers = workers.max(1);
+    let (queue, queued) = mpsc::sync_channel(max_connections.saturating_sub(workers));
+    let queued = Mutex::new(queued);
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let (handle, queued) = (&handle, &queued);
+            scope.spawn(move || loop {
+                let stream = queued.lock().unwrap_or_else(PoisonError::into_inner).recv();
+                match stream {
+                    Ok(stream) => handle(stream),
+                    // the listener stopped
+                    Err(_) => break,
+                }
+            });
+        }
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => match queue.try_send(stream) {
+                    Ok(()) => (),
+                    Err(TrySendError::Full(stream) | TrySendError::Disconnected(stream)) => {
+                        refuse(stream)
+                    }
+                },
+                // e.g., the client reset the connection before it was accepted
+                Err(e) => eprintln!("failed to accept a connection: {e}"),
+            }
+        }
+        drop(queue);
+    });
+}
+
+fn serve(args: &ServerArgs) -> Result<()> {
+    let server = Server {
+        args,
+        files: RwLock::new(Arc::new(Files::load(args)?)),
+        metrics: PrometheusMetrics::new(),
+    };
+    let listener = TcpListener::bind((args.host.as_str(), args.port))
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to listen on {}:{}", args.host, args.port))?;
+    let addr = listener.local_addr().into_diagnostic()?;
+    println!("Listening on http://{addr}");
+    serve_connections(
+        &listener,
+        args.workers,
+        args.max_connections,
+        |stream| server.handle(stream),
+        |mut stream| {
+            let (status, body) = error_response(503, miette!("the server is too busy"));
+            write_body(&mut stream, status, body);
+        },
+    );
+    Ok(())
+}
+
+pub(super) fn server(args: &ServerArgs) -> CedarExitCode {
+    match serve(args) {
+        Ok(()) => CedarExitCode::Success,
+        Err(e) => {
+            println!("Error: {e:?}");
+            CedarExitCode::Failure
+        }
+    }
+}
@@ -0,0 +1,259 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The `test` command, which runs policy tests written in the format of the
+//! Cedar integration tests: a JSON file naming the policies, entities and
+//! schema to use, and listing requests with the decision, determining
+//! policies and errors expected for each.
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use cedar_policy::{
+    conformance::{TestCase, TestInputs, TestRequest},
+    *,
+};
+use miette::{miette, IntoDiagnostic, Result, WrapErr};
+
+use super::{
+    load_actions_from_schema, load_entities, read_from_file, read_policy_set, read_schema_file,
+    watch::Colors, CedarExitCode, TestArgs,
+};
+
+/// The policies, schema and entities of a test file
+pub(super) type Files = TestInputs;
+
+/// Whether `path` is a test file, found by looking for the requests list since
+/// test files sit next to entity and schema files in JSON
+fn is_test_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+        && std::fs::read_to_string(path)
+            .ok()
+            .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+            .and_then(|json| {
+                json.as_object()
+                    .map(|json| json.contains_key("requests") || json.contains_key("queries"))
+            })
+            .unwrap_or(false)
+}
+
+/// The test files among `paths`, which are test files or directories to
+/// search for test files in, in order
+pub(super) fn discover(paths: &[String]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    for path in paths {
+        let path = PathBuf::from(path);
+        if path.is_dir() {
+            dirs.push(path);
+        } else {
+            files.push(path);
+        }
+    }
+    while let Some(dir) = dirs.pop() {
+        let mut entries = std::fs::read_dir(&dir)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to read directory {}", dir.display()))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to read directory {}", dir.display()))?;
+        entries.sort();
+        for path in entries {
+            if path.is_dir() {
+                dirs.push(path);
+            } else if is_test_file(&path) {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Read the test file at `path`
+pub(super) fn load(path: &Path) -> Result<TestCase> {
+    let text = read_from_file(path, "test")?;
+    text.parse()
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to parse test file {}", path.display()))
+}
+
+/// Load the files named by `test`, with relative paths relative to `root`,
+/// and check that the policies validate if that is expected. Unlike
+/// [`TestCase::load_inputs`], this renames policies by their `@id`
+/// annotations and adds the actions of the schema to the entities, as the
+/// other commands do.
+pub(super) fn load_files(test: &TestCase, root: &Path) -> Result<Files> {
+    let policies = read_policy_set(Some(&root.join(&test.policies)))?;
+    let schema = test
+        .schema
+        .as_ref()
+        .map(|schema| read_schema_file(&root.join(schema)))
+        .transpose()?;
+    if let Some(should_validate) = test.should_validate {
+        let schema = schema
+            .as_ref()
+            .ok_or_else(|| miette!("`should_validate` is given, but no `schema`"))?;
+        let validator = Validator::new(schema.clone());
+        let result = validator.validate(&policies, ValidationMode::default());
+        match (should_validate, result.validation_passed()) {
+            (true, false) => {
+                let errors = result
+                    .validation_errors()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("; ");
+                return Err(miette!(
+                    "the policies were expected to validate, but did not: {errors}"
+                ));
+            }
+            (false, true) => {
+                return Err(miette!(
+                    "the policies were expected to fail validation, but validated"
+                ))
+            }
+            _ => (),
+        }
+    }
+    let entities = load_entities(root.join(&test.entities), schema.as_ref())?;
+    let entities = load_actions_from_schema(entities, &schema)?;
+    Ok(Files {
+        policies,
+        schema,
+        entities,
+    })
+}
+
+/// Run `request`, returning how its result differs from the expected one,
+/// which is empty if the test passed
+fn run(request: &TestRequest, files: &Files, colors: Colors) -> Result<Vec<String>> {
+    let ans = Authorizer::new().is_authorized(
+        &request.request(files.schema.as_ref()).into_diagnostic()?,
+        &files.policies,
+        &files.entities,
+    );
+    let mut diff = Vec::new();
+    if ans.decision() != request.decision {
+        diff.push(format!(
+            "decision: expected {:?}, got {:?}",
+            request.decision,
+            ans.decision()
+        ));
+    }
+    let reasons = ans
+        .diagnostics()
+        .reason()
+        .map(|id| id.as_ref().to_string())
+        .collect::<BTreeSet<_>>();
+    diff.extend(set_diff("reasons", &request.reasons, &reasons, colors));
+    let errors = ans
+        .diagnostics()
+        .errors()
+        .map(ToString::to_string)
+        .collect::<BTreeSet<_>>();
+    diff.extend(set_diff("errors", &request.errors, &errors, colors));
+    Ok(diff)
+}
+
+/// The lines showing how `actual` differs from `expected`, with `-` for the
+/// expected items which are missing and `+` for the unexpected ones
+fn set_diff(
+    what: &str,
+    expected: &[String],
+    actual: &BTreeSet<String>,
+    colors: Colors,
+) -> Vec<String> {
+    let expected = expected.iter().cloned().collect::<BTreeSet<_>>();
+    if &expected == actual {
+        return Vec::new();
+    }
+    let mut lines = vec![format!("{what}:")];
+    for item in expected.union(actual) {
+        let line = if !actual.contains(item) {
+            colors.red(&format!("  - {item}"))
+        } else if !expected.contains(item) {
+            colors.green(&format!("  + {item}"))
+        } else {
+            format!("    {item}")
+        };
+        lines.push(line);
+    }
+    lines
+}
+
+pub(super) fn test(args: &TestArgs) -> CedarExitCode {
+    let paths = match discover(&args.paths) {
+        Ok(paths) => paths,
+        Err(e) => {
+            println!("Error: {e:?}");
+            return CedarExitCode::Failure;
+        }
+    };
+    let colors = Colors::detect();
+    let (mut passed, mut failed) = (0, 0);
+    for path in &paths {
+        let root = match &args.root {
+            Some(root) => PathBuf::from(root),
+            None => path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        };
+        let loaded = load(path).and_then(|test| {
+            let files = load_files(&test, &root)
+                .wrap_err_with(|| format!("failed to load the files of {}", path.display()))?;
+            Ok((test, files))
+        });
+        let (test, files) = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                println!("{} {}", colors.red("FAIL"), path.display());
+                println!("  Error: {e:?}");
+                failed += 1;
+                continue;
+            }
+        };
+        for request in &test.requests {
+            let name = format!("{}: {}", path.display(), request.desc);
+            match run(request, &files, colors) {
+                Ok(diff) if diff.is_empty() => {
+                    println!("{} {name}", colors.green("PASS"));
+                    passed += 1;
+                }
+                Ok(diff) => {
+                    println!("{} {name}", colors.red("FAIL"));
+                    for line in diff {
+                        println!("  {line}");
+                    }
+                    failed += 1;
+                }
+                Err(e) => {
+                    println!("{} {name}", colors.red("FAIL"));
+                    println!("  Error: {e:?}");
+                    failed += 1;
+                }
+            }
+        }
+    }
+    if paths.is_empty() {
+        println!("No test files found");
+    }
+    println!("{passed} passed, {failed} failed");
+    if failed == 0 {
+        CedarExitCode::Success
+    } else {
+        CedarExitCode::Failure
+    }
+}
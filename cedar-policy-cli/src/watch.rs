@@ -0,0 +1,227 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The `--watch` mode of the `validate`, `authorize` and `link` commands,
+//! which runs the command again whenever one of its files changes and shows
+//! how its output changed.
+//!
+//! Each run is the same `cedar` command with `--no-watch` added, which
+//! overrides `--watch`, run as a child process so that its output can be
+//! compared with that of the run before.
+
+use std::{
+    ffi::OsString,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use super::CedarExitCode;
+
+/// How often the files are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Whether to color the output with ANSI escape codes. We only do so for a
+/// terminal, and not if the `NO_COLOR` environment variable is set.
+#[derive(Clone, Copy)]
+pub(super) struct Colors(bool);
+
+impl Colors {
+    pub(super) fn detect() -> Self {
+        Self(std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none())
+    }
+
+    fn paint(self, code: &str, text: &str) -> String {
+        if self.0 {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text.to_string()
+        }
+    }
+
+    pub(super) fn red(self, text: &str) -> String {
+        self.paint("31", text)
+    }
+
+    pub(super) fn green(self, text: &str) -> String {
+        self.paint("32", text)
+    }
+
+    pub(super) fn dim(self, text: &str) -> String {
+        self.paint("2", text)
+    }
+}
+
+/// The time each file was last modified, or `None` for a file which cannot be
+/// read, e.g., because it does not exist (yet)
+fn modified_times(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|file| std::fs::metadata(file).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// Block until one of `files` changes from the time in `times`, returning it
+fn wait_for_change<'a>(files: &'a [PathBuf], times: &[Option<SystemTime>]) -> &'a Path {
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let new_times = modified_times(files);
+        if let Some(file) = files
+            .iter()
+            .zip(times.iter().zip(&new_times))
+            .find_map(|(file, (old, new))| (old != new).then_some(file))
+        {
+            return file;
+        }
+    }
+}
+
+/// A line of the difference between two outputs
+enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// The difference between the lines of `old` and `new`, from their longest
+/// common subsequence of lines
+fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    // `common[i][j]` is the length of the longest common subsequence of
+    // `old[i..]` and `new[j..]`
+    let mut common = vec![vec![0_usize; new.len() + 1]; old.len() + 1];
+    let at = |common: &[Vec<usize>], i: usize, j: usize| {
+        common
+            .get(i)
+            .and_then(|row| row.get(j))
+            .copied()
+            .unwrap_or(0)
+    };
+    for (i, old_line) in old.iter().enumerate().rev() {
+        for (j, new_line) in new.iter().enumerate().rev() {
+            let length = if old_line == new_line {
+                at(&common, i + 1, j + 1) + 1
+            } else {
+                at(&common, i + 1, j).max(at(&common, i, j + 1))
+            };
+            if let Some(cell) = common.get_mut(i).and_then(|row| row.get_mut(j)) {
+                *cell = length;
+            }
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut diff = Vec::new();
+    loop {
+        match (old.get(i), new.get(j)) {
+            (Some(old_line), Some(new_line)) if old_line == new_line => {
+                diff.push(DiffLine::Same(old_line));
+                i += 1;
+                j += 1;
+            }
+            (Some(old_line), Some(_)) if at(&common, i + 1, j) >= at(&common, i, j + 1) => {
+                diff.push(DiffLine::Removed(old_line));
+                i += 1;
+            }
+            (_, Some(new_line)) => {
+                diff.push(DiffLine::Added(new_line));
+                j += 1;
+            }
+            (Some(old_line), None) => {
+                diff.push(DiffLine::Removed(old_line));
+                i += 1;
+            }
+            (None, None) => break,
+        }
+    }
+    diff
+}
+
+/// Run the current `cedar` command without watching, then again each time
+/// one of the files returned by `files` changes. These are found again after
+/// each run, as they may depend on the contents of the files, e.g., the
+/// imports of a policy file. This only returns if the command cannot be run.
+pub(super) fn watch(files: impl Fn() -> Vec<PathBuf>) -> CedarExitCode {
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            println!("Error: failed to find the cedar executable: {e}");
+            return CedarExitCode::Failure;
+        }
+    };
+    let args = std::env::args_os()
+        .skip(1)
+        .chain([OsString::from("--no-watch")])
+        .collect::<Vec<OsString>>();
+    let colors = Colors::detect();
+    let mut previous: Option<String> = None;
+    loop {
+        // the times are taken before the run, so that changes during it are
+        // not missed
+        let watched = files();
+        let times = modified_times(&watched);
+        let output = match Command::new(&exe)
+            .args(&args)
+            .stderr(Stdio::inherit())
+            .output()
+        {
+            Ok(output) => output,
+            Err(e) => {
+                println!("Error: failed to run {}: {e}", exe.display());
+                return CedarExitCode::Failure;
+            }
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        match &previous {
+            None => print!("{stdout}"),
+            Some(previous) => {
+                let diff = diff_lines(previous, &stdout);
+                if diff.iter().all(|line| matches!(line, DiffLine::Same(_))) {
+                    println!("{}", colors.dim("(the output did not change)"));
+                }
+                for line in diff {
+                    match line {
+                        DiffLine::Same(line) => println!(" {line}"),
+                        DiffLine::Removed(line) => println!("{}", colors.red(&format!("-{line}"))),
+                        DiffLine::Added(line) => println!("{}", colors.green(&format!("+{line}"))),
+                    }
+                }
+            }
+        }
+        let status = match output.status.code() {
+            Some(code) => format!("exited with code {code}"),
+            None => "was stopped by a signal".to_string(),
+        };
+        let status = if output.status.success() {
+            colors.green(&status)
+        } else {
+            colors.red(&status)
+        };
+        println!(
+            "{} {status}{}",
+            colors.dim("[watch] the command"),
+            colors.dim("; waiting for changes, or Ctrl-C to stop")
+        );
+        previous = Some(stdout);
+        let changed = wait_for_change(&watched, &times);
+        println!(
+            "{}",
+            colors.dim(&format!("[watch] {} changed", changed.display()))
+        );
+    }
+}
@@ -18,15 +18,17 @@ use std::collections::HashMap;
 
 use cedar_policy::EvalResult;
 use cedar_policy::SlotId;
+use cedar_policy::WarningCode;
 use cedar_policy_cli::check_parse;
 use cedar_policy_cli::{
     authorize, evaluate, link, validate, Arguments, AuthorizeArgs, CedarExitCode, CheckParseArgs,
-    EvaluateArgs, LinkArgs, RequestArgs, ValidateArgs,
+    EvaluateArgs, LinkArgs, OutputFormat, RequestArgs, ValidateArgs,
 };
 
 fn run_check_parse_test(policies_file: impl Into<String>, expected_exit_code: CedarExitCode) {
     let cmd = CheckParseArgs {
         policies_file: Some(policies_file.into()),
+        output: OutputFormat::Human,
     };
     let output = check_parse(&cmd);
     assert_eq!(output, expected_exit_code, "{:#?}", cmd);
@@ -74,6 +76,9 @@ fn run_authorize_test_with_linked_policies(
         entities_file: entities_file.into(),
         verbose: true,
         timing: false,
+        watch: false,
+        no_watch: false,
+        output: OutputFormat::Human,
     };
     let output = authorize(&cmd);
     assert_eq!(exit_code, output, "{:#?}", cmd,);
@@ -93,6 +98,9 @@ fn run_link_test(
         template_id: template_id.into(),
         new_id: linked_id.into(),
         arguments: Arguments { data: env },
+        watch: false,
+        no_watch: false,
+        output: OutputFormat::Human,
     };
     let output = link(&cmd);
     assert_eq!(output, expected);
@@ -108,6 +116,13 @@ fn run_format_test(policies_file: &str) {
         std::str::from_utf8(&format_cmd.get_output().stdout).expect("output should be decodable"),
         std::fs::read_to_string(policies_file).unwrap()
     );
+    assert_cmd::Command::cargo_bin("cedar")
+        .expect("bin exists")
+        .arg("fmt")
+        .arg("--check")
+        .arg(policies_file)
+        .assert()
+        .code(0);
 }
 
 fn run_authorize_test_context(
@@ -133,6 +148,9 @@ fn run_authorize_test_context(
         entities_file: entities_file.into(),
         verbose: true,
         timing: false,
+        watch: false,
+        no_watch: false,
+        output: OutputFormat::Human,
     };
     let output = authorize(&cmd);
     assert_eq!(exit_code, output, "{:#?}", cmd,);
@@ -158,6 +176,9 @@ fn run_authorize_test_json(
         entities_file: entities_file.into(),
         verbose: true,
         timing: false,
+        watch: false,
+        no_watch: false,
+        output: OutputFormat::Human,
     };
     let output = authorize(&cmd);
     assert_eq!(exit_code, output, "{:#?}", cmd,);
@@ -417,10 +438,47 @@ fn test_authorize_samples() {
     );
 }
 
+#[test]
+fn test_authorize_imports_samples() {
+    run_check_parse_test(
+        "sample-data/sandbox_a/imports.cedar",
+        CedarExitCode::Success,
+    );
+    run_authorize_test(
+        "sample-data/sandbox_a/imports.cedar",
+        "sample-data/sandbox_a/entities.json",
+        "User::\"alice\"",
+        "Action::\"view\"",
+        "Photo::\"VacationPhoto94.jpg\"",
+        CedarExitCode::Success,
+    );
+    run_authorize_test(
+        "sample-data/sandbox_a/imports.cedar",
+        "sample-data/sandbox_a/entities.json",
+        "User::\"tim\"",
+        "Action::\"view\"",
+        "Photo::\"VacationPhoto94.jpg\"",
+        CedarExitCode::AuthorizeDeny,
+    );
+}
+
 fn run_validate_test(policies_file: &str, schema_file: &str, exit_code: CedarExitCode) {
+    run_validate_test_denying(policies_file, schema_file, &[], exit_code)
+}
+
+fn run_validate_test_denying(
+    policies_file: &str,
+    schema_file: &str,
+    denied_warnings: &[WarningCode],
+    exit_code: CedarExitCode,
+) {
     let cmd = ValidateArgs {
         schema_file: schema_file.into(),
         policies_file: policies_file.into(),
+        denied_warnings: denied_warnings.to_vec(),
+        watch: false,
+        no_watch: false,
+        output: OutputFormat::Human,
     };
     let output = validate(&cmd);
     assert_eq!(exit_code, output, "{:#?}", cmd);
@@ -459,6 +517,19 @@ fn test_validate_samples() {
         "sample-data/sandbox_a/schema.cedarschema.json",
         CedarExitCode::Success,
     );
+    // Contains a policy which can never apply, which is only a warning unless
+    // it is denied.
+    run_validate_test(
+        "sample-data/sandbox_a/policies_impossible.cedar",
+        "sample-data/sandbox_a/schema.cedarschema.json",
+        CedarExitCode::Success,
+    );
+    run_validate_test_denying(
+        "sample-data/sandbox_a/policies_impossible.cedar",
+        "sample-data/sandbox_a/schema.cedarschema.json",
+        &[WarningCode::ImpossiblePolicy],
+        CedarExitCode::ValidationFailure,
+    );
     run_validate_test(
         "sample-data/sandbox_b/policies_4.cedar",
         "sample-data/sandbox_b/schema.cedarschema.json",
@@ -821,6 +892,28 @@ fn test_link_samples() {
         "Photo::\"VacationPhoto94.jpg\"",
         CedarExitCode::Success,
     );
+
+    // linking again with the same id replaces the link
+    run_link_test(
+        "sample-data/sandbox_c/policies.cedar",
+        &linked_file_name,
+        "AccessVacation",
+        "AliceAccess",
+        [(SlotId::principal(), "User::\"bob\"".to_string())]
+            .into_iter()
+            .collect(),
+        CedarExitCode::Success,
+    );
+
+    run_authorize_test_with_linked_policies(
+        "sample-data/sandbox_c/policies.cedar",
+        "sample-data/sandbox_c/entities.json",
+        Some(&linked_file_name),
+        "User::\"alice\"",
+        "Action::\"view\"",
+        "Photo::\"VacationPhoto94.jpg\"",
+        CedarExitCode::AuthorizeDeny,
+    );
 }
 
 #[test]
@@ -829,3 +922,1402 @@ fn test_format_samples() {
     let ps_files = glob("sample-data/**/polic*.cedar").unwrap();
     ps_files.for_each(|ps_file| run_format_test(ps_file.unwrap().to_str().unwrap()));
 }
+
+#[test]
+fn test_format_check_unformatted() {
+    assert_cmd::Command::cargo_bin("cedar")
+        .expect("bin exists")
+        .arg("fmt")
+        .arg("--check")
+        .write_stdin("permit(principal,action,resource);\n")
+        .assert()
+        .code(1);
+}
+
+#[test]
+fn test_skeleton_samples() {
+    use cedar_policy::{Entities, Schema};
+    use glob::glob;
+    for schema_file in glob("sample-data/**/schema.cedarschema.json").unwrap() {
+        let schema_file = schema_file.unwrap();
+        let skeleton_cmd = assert_cmd::Command::cargo_bin("cedar")
+            .expect("bin exists")
+            .arg("skeleton")
+            .arg("--schema")
+            .arg(&schema_file)
+            .assert()
+            .success();
+        let schema = Schema::from_file(std::fs::File::open(&schema_file).unwrap()).unwrap();
+        let skeleton = std::str::from_utf8(&skeleton_cmd.get_output().stdout)
+            .expect("output should be decodable");
+        Entities::from_json_str(skeleton, Some(&schema))
+            .unwrap_or_else(|e| panic!("skeleton for {} should load: {e}", schema_file.display()));
+    }
+}
+
+#[test]
+fn test_snapshot_entities() {
+    let snapshot = std::env::temp_dir().join(format!("sandbox_b-{}.snapshot", std::process::id()));
+    assert_cmd::Command::cargo_bin("cedar")
+        .expect("bin exists")
+        .arg("snapshot-entities")
+        .arg("--entities")
+        .arg("sample-data/sandbox_b/entities.json")
+        .arg("--output")
+        .arg(&snapshot)
+        .assert()
+        .success();
+    let snapshot_file = snapshot.to_str().expect("path is valid UTF-8");
+    run_authorize_test(
+        "sample-data/sandbox_b/policies_4.cedar",
+        snapshot_file,
+        "User::\"alice\"",
+        "Action::\"view\"",
+        "Photo::\"prototype_v0.jpg\"",
+        CedarExitCode::Success,
+    );
+    run_authorize_test(
+        "sample-data/sandbox_b/policies_4.cedar",
+        snapshot_file,
+        "User::\"stacey\"",
+        "Action::\"view\"",
+        "Photo::\"prototype_v0.jpg\"",
+        CedarExitCode::AuthorizeDeny,
+    );
+    std::fs::remove_file(&snapshot).expect("snapshot is removed");
+}
+
+#[test]
+fn test_bundle() {
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let bundle = dir.path().join("photos.bundle.json");
+    let bundle_file = bundle.to_str().expect("path is valid UTF-8");
+    let cedar = || assert_cmd::Command::cargo_bin("cedar").expect("bin exists");
+    cedar()
+        .arg("bundle")
+        .arg("--policies")
+        .arg("sample-data/sandbox_a/policies_1.cedar")
+        .arg("--schema")
+        .arg("sample-data/sandbox_a/schema.cedarschema.json")
+        .arg("--name")
+        .arg("photos")
+        .arg("--version")
+        .arg("1.0.0")
+        .arg("--metadata")
+        .arg("environment=production")
+        .arg("--output")
+        .arg(&bundle)
+        .assert()
+        .success();
+    let verified = cedar().arg("verify-bundle").arg(&bundle).assert().success();
+    let output = String::from_utf8_lossy(&verified.get_output().stdout).to_string();
+    assert!(
+        output.starts_with("photos 1.0.0\n  environment: production\n"),
+        "{output}"
+    );
+
+    // a bundle stands in for the policy and schema files
+    run_authorize_test(
+        bundle_file,
+        "sample-data/sandbox_a/entities.json",
+        "User::\"alice\"",
+        "Action::\"view\"",
+        "Photo::\"VacationPhoto94.jpg\"",
+        CedarExitCode::Success,
+    );
+    run_validate_test(bundle_file, bundle_file, CedarExitCode::Success);
+
+    let json = std::fs::read_to_string(&bundle).expect("bundle is readable");
+    std::fs::write(
+        &bundle,
+        json.replace("User::\\\"tim\\\"", "User::\\\"bob\\\""),
+    )
+    .expect("bundle is writable");
+    cedar().arg("verify-bundle").arg(&bundle).assert().code(1);
+    run_validate_test(bundle_file, bundle_file, CedarExitCode::Failure);
+}
+
+#[test]
+fn test_translate_schema_samples() {
+    use cedar_policy::SchemaFragment;
+    use glob::glob;
+    use std::str::FromStr;
+    for schema_file in glob("sample-data/**/schema.cedarschema.json").unwrap() {
+        let schema_file = schema_file.unwrap();
+        let to_human_cmd = assert_cmd::Command::cargo_bin("cedar")
+            .expect("bin exists")
+            .arg("translate-schema")
+            .arg("--direction")
+            .arg("json-to-human")
+            .arg("--schema")
+            .arg(&schema_file)
+            .assert()
+            .success();
+        let human = to_human_cmd.get_output().stdout.clone();
+        let to_json_cmd = assert_cmd::Command::cargo_bin("cedar")
+            .expect("bin exists")
+            .arg("translate-schema")
+            .arg("--direction")
+            .arg("human-to-json")
+            .write_stdin(human)
+            .assert()
+            .success();
+        let json = std::str::from_utf8(&to_json_cmd.get_output().stdout)
+            .expect("output should be decodable");
+        let original =
+            SchemaFragment::from_file(std::fs::File::open(&schema_file).unwrap()).unwrap();
+        let translated = SchemaFragment::from_str(json).unwrap_or_else(|e| {
+            panic!("translation of {} should parse: {e}", schema_file.display())
+        });
+        assert_eq!(
+            original.to_json_value().unwrap(),
+            translated.to_json_value().unwrap(),
+            "translation of {} should round-trip",
+            schema_file.display()
+        );
+    }
+}
+
+#[test]
+fn test_translate_policy_samples() {
+    use glob::glob;
+    let translate = |direction: &str, input: Vec<u8>, links: &std::path::Path| {
+        let cmd = assert_cmd::Command::cargo_bin("cedar")
+            .expect("bin exists")
+            .arg("translate-policy")
+            .arg("--direction")
+            .arg(direction)
+            .arg("--template-linked")
+            .arg(links)
+            .write_stdin(input)
+            .assert()
+            .success();
+        cmd.get_output().stdout.clone()
+    };
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let links = dir.path().join("links.json");
+    for policies_file in glob("sample-data/**/*.cedar").unwrap() {
+        let policies_file = policies_file.unwrap();
+        // the first translation to Cedar is only the same as the second up to
+        // formatting of the original policies
+        // from the file rather than stdin, for its imports
+        let json = assert_cmd::Command::cargo_bin("cedar")
+            .expect("bin exists")
+            .arg("translate-policy")
+            .arg("--policies")
+            .arg(&policies_file)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let cedar = translate("json-to-cedar", json.clone(), &links);
+        let json_again = translate("cedar-to-json", cedar.clone(), &links);
+        let cedar_again = translate("json-to-cedar", json_again.clone(), &links);
+        assert_eq!(
+            String::from_utf8(cedar).unwrap(),
+            String::from_utf8(cedar_again).unwrap(),
+            "translation of {} should round-trip",
+            policies_file.display()
+        );
+        let ids = |json: &[u8]| {
+            let json: serde_json::Value = serde_json::from_slice(json).unwrap();
+            ["staticPolicies", "templates"].map(|key| {
+                json.get(key)
+                    .and_then(serde_json::Value::as_object)
+                    .map(|policies| policies.keys().cloned().collect::<Vec<_>>())
+                    .unwrap()
+            })
+        };
+        assert_eq!(ids(&json), ids(&json_again));
+    }
+
+    // links are read from and written to the template-linked file
+    let linked_file = dir.path().join("linked.json");
+    std::fs::write(
+        &linked_file,
+        r#"[{"template_id":"AccessVacation","link_id":"bob's link","args":{"?principal":"User::\"bob\""}}]"#,
+    )
+    .unwrap();
+    let json = translate(
+        "cedar-to-json",
+        std::fs::read("sample-data/sandbox_c/policies.cedar").unwrap(),
+        &linked_file,
+    );
+    let json_value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(
+        json_value.get("templateLinks"),
+        Some(&serde_json::json!([{
+            "templateId": "AccessVacation",
+            "newId": "bob's link",
+            "values": { "?principal": { "__entity": { "type": "User", "id": "bob" } } },
+        }]))
+    );
+    translate("json-to-cedar", json.clone(), &links);
+    let written: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&links).unwrap()).unwrap();
+    let original: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&linked_file).unwrap()).unwrap();
+    assert_eq!(written, original);
+
+    // links need a file to be written to
+    assert_cmd::Command::cargo_bin("cedar")
+        .expect("bin exists")
+        .arg("translate-policy")
+        .arg("--direction")
+        .arg("json-to-cedar")
+        .write_stdin(json)
+        .assert()
+        .code(1);
+}
+
+#[test]
+fn test_diff_schema_samples() {
+    assert_cmd::Command::cargo_bin("cedar")
+        .expect("bin exists")
+        .arg("diff-schema")
+        .arg("--old")
+        .arg("sample-data/sandbox_b/schema.cedarschema.json")
+        .arg("--new")
+        .arg("sample-data/sandbox_b/schema.cedarschema.json")
+        .assert()
+        .success()
+        .stdout("No changes\n");
+    let diff_cmd = assert_cmd::Command::cargo_bin("cedar")
+        .expect("bin exists")
+        .arg("diff-schema")
+        .arg("--old")
+        .arg("sample-data/sandbox_a/schema.cedarschema.json")
+        .arg("--new")
+        .arg("sample-data/sandbox_b/schema.cedarschema.json")
+        .assert()
+        .code(4);
+    let diff =
+        std::str::from_utf8(&diff_cmd.get_output().stdout).expect("output should be decodable");
+    assert!(diff.contains("compatible: added entity type `AccountGroup`"));
+    assert!(diff.contains("breaking: removed entity type `Video`"));
+}
+
+#[test]
+fn test_check_schema_usage_samples() {
+    let usage_cmd = assert_cmd::Command::cargo_bin("cedar")
+        .expect("bin exists")
+        .arg("check-schema-usage")
+        .arg("--schema")
+        .arg("sample-data/sandbox_a/schema.cedarschema.json")
+        .arg("--policies")
+        .arg("sample-data/sandbox_a/policies_1.cedar")
+        .assert()
+        .success();
+    let usage =
+        std::str::from_utf8(&usage_cmd.get_output().stdout).expect("output should be decodable");
+    assert!(usage.contains("entity type `Video` is never used"));
+}
+
+#[test]
+fn test_lint_samples() {
+    let lint_cmd = assert_cmd::Command::cargo_bin("cedar")
+        .expect("bin exists")
+        .arg("lint")
+        .arg("--policies")
+        .arg("sample-data/sandbox_a/policies_1.cedar")
+        .assert()
+        .success();
+    let lints =
+        std::str::from_utf8(&lint_cmd.get_output().stdout).expect("output should be decodable");
+    assert!(lints.contains(
+        "warning `require-annotations` on policy `disallow tim policy`: missing the `@owner` annotation"
+    ));
+
+    assert_cmd::Command::cargo_bin("cedar")
+        .expect("bin exists")
+        .arg("lint")
+        .arg("--policies")
+        .arg("sample-data/sandbox_a/policies_1.cedar")
+        .arg("--deny")
+        .arg("require-annotations")
+        .assert()
+        .failure();
+    let lint_cmd = assert_cmd::Command::cargo_bin("cedar")
+        .expect("bin exists")
+        .arg("lint")
+        .arg("--policies")
+        .arg("sample-data/sandbox_a/policies_1.cedar")
+        .arg("--deny")
+        .arg("require-annotations")
+        .arg("--require-annotation")
+        .arg("id")
+        .arg("--allow")
+        .arg("unconditional-permit")
+        .assert()
+        .success();
+    let lints =
+        std::str::from_utf8(&lint_cmd.get_output().stdout).expect("output should be decodable");
+    assert_eq!(lints.trim(), "No lints found");
+
+    assert_cmd::Command::cargo_bin("cedar")
+        .expect("bin exists")
+        .arg("lint")
+        .arg("--policies")
+        .arg("sample-data/sandbox_a/policies_1.cedar")
+        .arg("--allow")
+        .arg("no-such-rule")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_export() {
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let policies_file = dir.path().join("policies.cedar");
+    let schema_file = dir.path().join("schema.cedarschema.json");
+    let schema_output_file = dir.path().join("exported.cedarschema.json");
+    std::fs::write(
+        &policies_file,
+        r#"@id("limit")
+permit(principal, action == Action::"withdraw", resource)
+when { context.amount <= u256("1000") && resource.owner == address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed") };
+
+@id("fee")
+permit(principal, action == Action::"withdraw", resource)
+when { context.amount.u256Add(u256("10")) <= u256("1000") };
+"#,
+    )
+    .unwrap();
+    std::fs::write(
+        &schema_file,
+        serde_json::json!({ "": {
+            "entityTypes": {
+                "User": {},
+                "Vault": { "shape": { "type": "Record", "attributes": {
+                    "owner": { "type": "Extension", "name": "address" }
+                } } }
+            },
+            "actions": { "withdraw": { "appliesTo": {
+                "principalTypes": ["User"],
+                "resourceTypes": ["Vault"],
+                "context": { "type": "Record", "attributes": {
+                    "amount": { "type": "Extension", "name": "u256" }
+                } }
+            } } }
+        } })
+        .to_string(),
+    )
+    .unwrap();
+    let export = || {
+        let mut cmd = assert_cmd::Command::cargo_bin("cedar").expect("bin exists");
+        cmd.arg("export")
+            .arg("--policies")
+            .arg(&policies_file)
+            .arg("--schema")
+            .arg(&schema_file)
+            .arg("--schema-output")
+            .arg(&schema_output_file);
+        cmd
+    };
+    // a policy was left out
+    let output = export().assert().code(1).get_output().clone();
+    let policies = std::str::from_utf8(&output.stdout).expect("output should be decodable");
+    assert!(policies.contains("@id(\"limit\")"), "{policies}");
+    assert!(policies.contains("decimal(\"1000.0\")"), "{policies}");
+    assert!(
+        policies.contains("\"0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed\""),
+        "{policies}"
+    );
+    assert!(!policies.contains("fee"), "{policies}");
+    let issues = std::str::from_utf8(&output.stderr).expect("output should be decodable");
+    assert!(
+        issues.contains("policy `fee` left out: `u256` arithmetic (`u256Add`)"),
+        "{issues}"
+    );
+    assert!(
+        issues.contains("`Vault.owner` changed: `address` became `String`"),
+        "{issues}"
+    );
+    let schema: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&schema_output_file).unwrap()).unwrap();
+    assert_eq!(
+        schema.get("").and_then(|namespace| namespace
+            .pointer("/actions/withdraw/appliesTo/context/attributes/amount/name")),
+        Some(&serde_json::json!("decimal"))
+    );
+
+    export().arg("--allow-dropped").assert().success();
+}
+
+#[test]
+fn test_import_rego() {
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let rego_file = dir.path().join("transfers.rego");
+    std::fs::write(
+        &rego_file,
+        r#"package transfers
+
+default allow := false
+
+allow if {
+    input.action == "transfer"
+    input.context.amount <= 1000
+}
+
+allow if is_treasurer
+"#,
+    )
+    .unwrap();
+    let import = || {
+        let mut cmd = assert_cmd::Command::cargo_bin("cedar").expect("bin exists");
+        cmd.arg("import-rego").arg(&rego_file);
+        cmd
+    };
+    // a rule was left out
+    let output = import().assert().code(1).get_output().clone();
+    let policies = std::str::from_utf8(&output.stdout).expect("output should be decodable");
+    assert!(policies.contains("@id(\"allow0\")"), "{policies}");
+    assert!(
+        policies.contains("action == Action::\"transfer\""),
+        "{policies}"
+    );
+    assert!(policies.contains("context.amount <= 1000"), "{policies}");
+    let issues = std::str::from_utf8(&output.stderr).expect("output should be decodable");
+    assert!(
+        issues.contains("line 10 left out: the variable or rule `is_treasurer`"),
+        "{issues}"
+    );
+
+    import().arg("--allow-dropped").assert().success();
+}
+
+#[test]
+fn test_repl_samples() {
+    let repl_cmd = assert_cmd::Command::cargo_bin("cedar")
+        .expect("bin exists")
+        .arg("repl")
+        .arg("--policies")
+        .arg("sample-data/sandbox_a/policies_1.cedar")
+        .arg("--entities")
+        .arg("sample-data/sandbox_a/entities.json")
+        .write_stdin(
+            r#":principal User::"alice"
+:action Action::"view"
+:resource Photo::"VacationPhoto94.jpg"
+:authorize
+principal in UserGroup::"jane_friends"
+:principal User::"tim"
+:authorize
+:context {"count": 1}
+context.count + 1
+:no-such-command
+:reload
+"#,
+        )
+        .assert()
+        .success();
+    let output =
+        std::str::from_utf8(&repl_cmd.get_output().stdout).expect("output should be decodable");
+    let lines = output.lines().map(str::trim).collect::<Vec<_>>();
+    let allow = lines
+        .iter()
+        .position(|line| *line == "ALLOW")
+        .expect("alice should be allowed");
+    assert_eq!(
+        lines[allow + 2],
+        r#"jane\'s friends view-permission policy"#
+    );
+    assert_eq!(lines[allow + 3], "true");
+    let deny = lines
+        .iter()
+        .position(|line| *line == "DENY")
+        .expect("tim should be denied");
+    assert_eq!(lines[deny + 2], "disallow tim policy");
+    assert_eq!(lines[deny + 3], "2");
+    assert!(output.contains("unknown command `:no-such-command`"));
+    assert!(output.contains("Reloaded the files"));
+
+    assert_cmd::Command::cargo_bin("cedar")
+        .expect("bin exists")
+        .arg("repl")
+        .arg("--policies")
+        .arg("sample-data/sandbox_a/no_such_file.cedar")
+        .write_stdin("")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_validate_watch() {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let policies_file = dir.path().join("policies.cedar");
+    let imported_file = dir.path().join("imported.cedar");
+    let schema_file = dir.path().join("schema.cedarschema.json");
+    let policies = std::fs::read_to_string("sample-data/sandbox_a/policies_1.cedar").unwrap();
+    std::fs::write(
+        &policies_file,
+        format!("import \"imported.cedar\";\n{policies}"),
+    )
+    .unwrap();
+    std::fs::write(&imported_file, "").unwrap();
+    std::fs::copy(
+        "sample-data/sandbox_a/schema.cedarschema.json",
+        &schema_file,
+    )
+    .unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cedar"))
+        .arg("validate")
+        .arg("--watch")
+        .arg("--schema")
+        .arg(&schema_file)
+        .arg("--policies")
+        .arg(&policies_file)
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run cedar");
+    let stdout = child.stdout.take().unwrap();
+    let (sender, lines) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            if sender.send(line.unwrap()).is_err() {
+                break;
+            }
+        }
+    });
+    let wait_for = |expected: &str| loop {
+        let line = lines
+            .recv_timeout(Duration::from_secs(10))
+            .unwrap_or_else(|_| panic!("no line `{expected}` in the output"));
+        if line == expected {
+            break;
+        }
+    };
+
+    wait_for("Validation Passed");
+    wait_for("[watch] the command exited with code 0; waiting for changes, or Ctrl-C to stop");
+    // the imported file is watched too
+    std::fs::write(
+        &imported_file,
+        "permit(principal, action == Action::\"nope\", resource);\n",
+    )
+    .unwrap();
+    wait_for("-Validation Passed");
+    wait_for("+Validation Results:");
+    wait_for("[watch] the command exited with code 3; waiting for changes, or Ctrl-C to stop");
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn test_authorize_batch_samples() {
+    let run = |requests_file: &str, jobs: &str| {
+        assert_cmd::Command::cargo_bin("cedar")
+            .expect("bin exists")
+            .arg("authorize-batch")
+            .arg("--requests")
+            .arg(requests_file)
+            .arg("--policies")
+            .arg("sample-data/sandbox_a/policies_1.cedar")
+            .arg("--entities")
+            .arg("sample-data/sandbox_a/entities.json")
+            .arg("--jobs")
+            .arg(jobs)
+            .arg("--summary")
+            .assert()
+    };
+    let decisions = |output: &[u8]| {
+        std::str::from_utf8(output)
+            .expect("output should be decodable")
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("each line should be JSON"))
+            .collect::<Vec<serde_json::Value>>()
+    };
+
+    let batch_cmd = run("sample-data/sandbox_a/requests.ndjson", "1").success();
+    let output = batch_cmd.get_output();
+    assert_eq!(
+        decisions(&output.stdout),
+        vec![
+            serde_json::json!({
+                "line": 1,
+                "decision": "Allow",
+                "diagnostics": { "reason": ["jane's friends view-permission policy"], "errors": [] },
+            }),
+            serde_json::json!({
+                "line": 2,
+                "decision": "Deny",
+                "diagnostics": { "reason": ["disallow tim policy"], "errors": [] },
+            }),
+            serde_json::json!({
+                "line": 3,
+                "decision": "Deny",
+                "diagnostics": { "reason": [], "errors": [] },
+            }),
+        ]
+    );
+    let summary = std::str::from_utf8(&output.stderr).expect("output should be decodable");
+    assert!(summary.starts_with("3 requests: 1 allowed, 2 denied, 0 invalid"));
+    assert!(summary.contains("  disallow tim policy: 1"));
+
+    // the decisions are in the order of the requests on any number of threads
+    let parallel_cmd = run("sample-data/sandbox_a/requests.ndjson", "2").success();
+    assert_eq!(parallel_cmd.get_output().stdout, output.stdout);
+
+    let mut requests = tempfile::NamedTempFile::new().expect("failed to create requests file");
+    std::io::Write::write_all(
+        &mut requests,
+        br#"{"principal": "User::\"alice\"", "action": "Action::\"view\"", "resource": "Photo::\"VacationPhoto94.jpg\"", "context": {}}
+
+{"principal": "alice", "context": {}}
+"#,
+    )
+    .unwrap();
+    let batch_cmd = run(&requests.path().to_string_lossy(), "1").failure();
+    let decisions = decisions(&batch_cmd.get_output().stdout);
+    let [allowed, invalid] = decisions.as_slice() else {
+        panic!("expected two decisions, got {decisions:?}");
+    };
+    assert_eq!(allowed.get("decision"), Some(&serde_json::json!("Allow")));
+    assert_eq!(invalid.get("line"), Some(&serde_json::json!(3)));
+    assert!(invalid
+        .get("error")
+        .and_then(serde_json::Value::as_str)
+        .unwrap()
+        .contains("failed to parse principal"));
+}
+
+#[test]
+fn test_server() {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpStream;
+    use std::process::{Command, Stdio};
+
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let policies_file = dir.path().join("policies.cedar");
+    std::fs::copy("sample-data/sandbox_a/policies_1.cedar", &policies_file).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cedar"))
+        .arg("server")
+        .arg("--policies")
+        .arg(&policies_file)
+        .arg("--entities")
+        .arg("sample-data/sandbox_a/entities.json")
+        .arg("--port")
+        .arg("0")
+        .arg("--workers")
+        .arg("2")
+        .arg("--reload-token")
+        .arg("secret")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("failed to run cedar");
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    let addr = line
+        .trim()
+        .strip_prefix("Listening on http://")
+        .unwrap_or_else(|| panic!("unexpected first line `{line}`"))
+        .to_string();
+    let send_with_headers = |method: &str, path: &str, headers: &str, body: &str| {
+        let mut stream = TcpStream::connect(&addr).unwrap();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\n{headers}Content-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse::<u16>().unwrap();
+        (status, body.to_string())
+    };
+    let send_text =
+        |method: &str, path: &str, body: &str| send_with_headers(method, path, "", body);
+    let send = |method: &str, path: &str, body: &str| {
+        let (status, body) = send_text(method, path, body);
+        (
+            status,
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+        )
+    };
+    let alice = r#"{"principal": "User::\"alice\"", "action": "Action::\"view\"", "resource": "Photo::\"VacationPhoto94.jpg\"", "context": {}}"#;
+    let allowed = serde_json::json!({
+        "decision": "Allow",
+        "diagnostics": { "reason": ["jane's friends view-permission policy"], "errors": [] },
+    });
+    let denied = serde_json::json!({
+        "decision": "Deny",
+        "diagnostics": { "reason": [], "errors": [] },
+    });
+
+    assert_eq!(
+        send("GET", "/health", ""),
+        (200, serde_json::json!({ "status": "ok" }))
+    );
+    assert_eq!(send("POST", "/authorize", alice), (200, allowed.clone()));
+    // the entities of the request replace those of the entities file
+    let without_entities = alice.replace(r#""context": {}"#, r#""context": {}, "entities": []"#);
+    assert_eq!(
+        send("POST", "/authorize", &without_entities),
+        (200, denied.clone())
+    );
+    let (status, batch) = send(
+        "POST",
+        "/authorize-batch",
+        &format!(r#"[{alice}, {{"principal": "nope", "context": {{}}}}]"#),
+    );
+    assert_eq!(status, 200);
+    match batch.as_array().map(Vec::as_slice) {
+        Some([first, second]) => {
+            assert_eq!(first, &allowed);
+            assert!(second.get("error").is_some(), "{second}");
+        }
+        _ => panic!("unexpected batch response {batch}"),
+    }
+    assert_eq!(send("POST", "/authorize", "nope").0, 400);
+    assert_eq!(send("GET", "/authorize", "").0, 405);
+    assert_eq!(send("GET", "/nope", "").0, 404);
+    let (status, metrics) = send_text("GET", "/metrics", "");
+    assert_eq!(status, 200);
+    for line in [
+        r#"cedar_authorization_decisions_total{decision="allow"} 2"#,
+        r#"cedar_authorization_decisions_total{decision="deny"} 1"#,
+        "cedar_authorization_duration_seconds_count 3",
+    ] {
+        assert!(
+            metrics.lines().any(|l| l == line),
+            "missing `{line}` in:\n{metrics}"
+        );
+    }
+
+    std::fs::write(&policies_file, "").unwrap();
+    // reloading requires the token
+    assert_eq!(send("POST", "/reload", "").0, 401);
+    assert_eq!(
+        send_with_headers("POST", "/reload", "Authorization: Bearer wrong\r\n", "").0,
+        401
+    );
+    assert_eq!(send("POST", "/authorize", alice), (200, allowed.clone()));
+    let reload = || send_with_headers("POST", "/reload", "Authorization: Bearer secret\r\n", "");
+    assert_eq!(
+        reload(),
+        (200, serde_json::json!({ "status": "reloaded" }).to_string())
+    );
+    assert_eq!(send("POST", "/authorize", alice), (200, denied));
+    // a broken file keeps the policies loaded before
+    std::fs::write(&policies_file, "permit(").unwrap();
+    assert_eq!(reload().0, 500);
+    assert_eq!(send("GET", "/health", "").0, 200);
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn test_json_output() {
+    let run = |args: &[&str], code: i32| {
+        let cmd = assert_cmd::Command::cargo_bin("cedar")
+            .expect("bin exists")
+            .args(args)
+            .arg("--output")
+            .arg("json")
+            .assert()
+            .code(code);
+        serde_json::from_slice::<serde_json::Value>(&cmd.get_output().stdout)
+            .expect("output should be JSON")
+    };
+
+    let validated = run(
+        &[
+            "validate",
+            "--schema",
+            "sample-data/sandbox_a/schema.cedarschema.json",
+            "--policies",
+            "sample-data/sandbox_a/policies_1_bad.cedar",
+        ],
+        3,
+    );
+    assert_eq!(validated.get("success"), Some(&serde_json::json!(false)));
+    assert_eq!(validated.get("errors"), Some(&serde_json::json!([])));
+    assert_eq!(
+        validated
+            .get("validationErrors")
+            .and_then(|errors| errors.get(0))
+            .and_then(|error| error.get("code")),
+        Some(&serde_json::json!("unrecognized-entity-type"))
+    );
+
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let bad_policies = dir.path().join("bad.cedar");
+    std::fs::write(
+        &bad_policies,
+        "permit(principal, action, resource) when { 1 + };",
+    )
+    .unwrap();
+    let parsed = run(
+        &["check-parse", "--policies", bad_policies.to_str().unwrap()],
+        1,
+    );
+    assert_eq!(parsed.get("success"), Some(&serde_json::json!(false)));
+    let labels = parsed
+        .get("errors")
+        .and_then(|errors| errors.get(0))
+        .and_then(|error| error.get("labels"))
+        .and_then(|labels| labels.get(0));
+    assert_eq!(
+        labels.and_then(|label| label.get("span")),
+        Some(&serde_json::json!({ "offset": 47, "length": 1 }))
+    );
+
+    let authorized = run(
+        &[
+            "authorize",
+            "--policies",
+            "sample-data/sandbox_a/policies_1.cedar",
+            "--entities",
+            "sample-data/sandbox_a/entities.json",
+            "--principal",
+            "User::\"tim\"",
+            "--action",
+            "Action::\"view\"",
+            "--resource",
+            "Photo::\"VacationPhoto94.jpg\"",
+        ],
+        2,
+    );
+    assert_eq!(
+        authorized,
+        serde_json::json!({
+            "success": true,
+            "errors": [],
+            "decision": "Deny",
+            "diagnostics": { "reason": ["disallow tim policy"], "errors": [] },
+        })
+    );
+
+    let linked_file = dir.path().join("linked.json");
+    let linked = run(
+        &[
+            "link",
+            "--policies-file",
+            "sample-data/sandbox_c/policies.cedar",
+            "--template-linked-file",
+            linked_file.to_str().unwrap(),
+            "--template-id",
+            "AccessVacation",
+            "--new-id",
+            "BobAccess",
+            "--arguments",
+            r#"{"?principal": "User::\"bob\""}"#,
+        ],
+        0,
+    );
+    assert_eq!(
+        linked,
+        serde_json::json!({
+            "success": true,
+            "errors": [],
+            "link": {
+                "template_id": "AccessVacation",
+                "link_id": "BobAccess",
+                "args": { "?principal": "User::\"bob\"" },
+            },
+        })
+    );
+}
+
+#[test]
+fn test_test_samples() {
+    let test_cmd = assert_cmd::Command::cargo_bin("cedar")
+        .expect("bin exists")
+        .arg("test")
+        .arg("sample-data")
+        .assert()
+        .success();
+    let output = String::from_utf8(test_cmd.get_output().stdout.clone()).unwrap();
+    assert!(output.contains("PASS sample-data/sandbox_a/policies_1.test.json"));
+    assert!(output.ends_with("3 passed, 0 failed\n"), "{output}");
+
+    // the integration tests name their files relative to the folder they are in
+    assert_cmd::Command::cargo_bin("cedar")
+        .expect("bin exists")
+        .arg("test")
+        .arg("--root")
+        .arg("../cedar-integration-tests")
+        .arg("../cedar-integration-tests/tests/multi")
+        .arg("../cedar-integration-tests/tests/ip/1.json")
+        .assert()
+        .success();
+
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let test_file = dir.path().join("policies_1.test.json");
+    let test = std::fs::read_to_string("sample-data/sandbox_a/policies_1.test.json")
+        .unwrap()
+        .replace("policies_1.cedar", "policies_2.cedar");
+    std::fs::write(&test_file, test).unwrap();
+    let test_cmd = assert_cmd::Command::cargo_bin("cedar")
+        .expect("bin exists")
+        .arg("test")
+        .arg("--root")
+        .arg("sample-data/sandbox_a")
+        .arg(dir.path())
+        .assert()
+        .code(1);
+    let output = String::from_utf8(test_cmd.get_output().stdout.clone()).unwrap();
+    assert!(
+        output.contains(
+            "  reasons:\n    + alice's access policy\n    - jane's friends view-permission policy\n"
+        ),
+        "{output}"
+    );
+    assert!(
+        output.contains("  decision: expected Deny, got Allow"),
+        "{output}"
+    );
+    assert!(output.ends_with("0 passed, 3 failed\n"), "{output}");
+}
+
+#[test]
+fn test_explain_samples() {
+    let explain = |principal: &str, policies: &str| {
+        assert_cmd::Command::cargo_bin("cedar")
+            .expect("bin exists")
+            .arg("explain")
+            .arg("--principal")
+            .arg(principal)
+            .arg("--action")
+            .arg("Action::\"view\"")
+            .arg("--resource")
+            .arg("Photo::\"alice_w2.jpg\"")
+            .arg("--context")
+            .arg("sample-data/sandbox_b/context.json")
+            .arg("--policies")
+            .arg(policies)
+            .arg("--entities")
+            .arg("sample-data/sandbox_b/entities.json")
+            .assert()
+    };
+    let output = |cmd: assert_cmd::assert::Assert| {
+        String::from_utf8(cmd.get_output().stdout.clone()).unwrap()
+    };
+
+    let cmd = explain("User::\"stacey\"", "sample-data/sandbox_b/policies_5.cedar").code(2);
+    let stacey = output(cmd);
+    assert!(
+        stacey.contains("forbid `privacy rule`: satisfied\n"),
+        "{stacey}"
+    );
+    assert!(
+        stacey.ends_with(
+            "decision: Deny, because forbid `privacy rule` overrides any permit policy\n"
+        ),
+        "{stacey}"
+    );
+
+    let cmd = explain("User::\"alice\"", "sample-data/sandbox_b/policies_5.cedar").success();
+    let alice = output(cmd);
+    assert!(
+        alice.contains("forbid `privacy rule`: not satisfied\n"),
+        "{alice}"
+    );
+    assert!(
+        alice.contains("└── conditions: false\n    └── false: !("),
+        "{alice}"
+    );
+
+    let cmd = explain("User::\"alice\"", "sample-data/sandbox_b/policies_6.cedar").success();
+    let ip = output(cmd);
+    assert!(
+        ip.contains("forbid `ip_denylist`: skipped because of an error\n"),
+        "{ip}"
+    );
+    assert!(
+        ip.contains("    └── failed: (context[\"source_ip\"]).isInRange(ip(\"222.222.222.0/24\")) (at bytes 312..363)\n"),
+        "{ip}"
+    );
+    assert!(
+        ip.ends_with("; `ip_denylist` skipped because of errors\n"),
+        "{ip}"
+    );
+}
+
+#[test]
+fn test_new_policy_and_link() {
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let policies_file = dir.path().join("policies.cedar");
+    let linked_file = dir.path().join("linked.json");
+    let entities_file = dir.path().join("entities.json");
+    let context_file = dir.path().join("context.json");
+    std::fs::write(&entities_file, "[]").unwrap();
+    let cedar = || assert_cmd::Command::cargo_bin("cedar").expect("bin exists");
+    let new_policy = |builtin: &str| {
+        cedar()
+            .arg("new-policy")
+            .arg("--from-builtin")
+            .arg(builtin)
+            .arg("--policies")
+            .arg(&policies_file)
+            .assert()
+    };
+    let authorize = |amount: &str, linked: bool| {
+        std::fs::write(
+            &context_file,
+            format!(r#"{{ "args": {{ "arg_1": {{ "__extn": {{ "fn": "u256", "arg": "{amount}" }} }} }} }}"#),
+        )
+        .unwrap();
+        let mut cmd = cedar();
+        cmd.arg("authorize")
+            .arg("--principal")
+            .arg("Address::\"0xab\"")
+            .arg("--action")
+            .arg("Action::\"0x095ea7b3\"")
+            .arg("--resource")
+            .arg("Address::\"0xcd\"")
+            .arg("--context")
+            .arg(&context_file)
+            .arg("--policies")
+            .arg(&policies_file)
+            .arg("--entities")
+            .arg(&entities_file);
+        if linked {
+            cmd.arg("--template-linked").arg(&linked_file);
+        }
+        cmd.assert()
+    };
+
+    for builtin in ["forbid-infinite-approval", "block-address", "permit-all"] {
+        new_policy(builtin).success();
+    }
+    new_policy("permit-all").code(1);
+    new_policy("no-such-policy").code(2);
+    run_check_parse_test(policies_file.to_string_lossy(), CedarExitCode::Success);
+    authorize("100", false).success();
+    authorize(
+        "115792089237316195423570985008687907853269984665640564039457584007913129639935",
+        false,
+    )
+    .code(2);
+
+    let new_link = |param: &str, linked: bool| {
+        let mut cmd = cedar();
+        cmd.arg("new-link")
+            .arg("--policies")
+            .arg(&policies_file)
+            .arg("--template")
+            .arg("block-address")
+            .arg("--param")
+            .arg(param);
+        if linked {
+            cmd.arg("--template-linked").arg(&linked_file);
+        }
+        cmd.assert()
+    };
+    let printed = new_link("principal=Address::\"0xab\"", false).success();
+    let printed = String::from_utf8(printed.get_output().stdout.clone()).unwrap();
+    assert!(
+        printed.contains(r#""link_id": "block-address-1""#),
+        "{printed}"
+    );
+    new_link("resource=Address::\"0xab\"", true).code(1);
+    new_link("principal=Address::\"0xab\"", true).success();
+    authorize("100", true).code(2);
+}
+
+#[test]
+fn test_differential_samples() {
+    let differential = |upstream: &std::path::Path| {
+        assert_cmd::Command::cargo_bin("cedar")
+            .expect("bin exists")
+            .arg("differential")
+            .arg("--upstream")
+            .arg(upstream)
+            .arg("sample-data/sandbox_a/policies_1.test.json")
+            .assert()
+    };
+    let output = |cmd: assert_cmd::assert::Assert| {
+        String::from_utf8(cmd.get_output().stdout.clone()).unwrap()
+    };
+
+    // this fork agrees with itself
+    let same = output(differential(std::path::Path::new(env!("CARGO_BIN_EXE_cedar"))).success());
+    assert!(same.ends_with("3 same, 0 differ, 0 errors\n"), "{same}");
+
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let missing = dir.path().join("missing");
+    let errors = output(differential(&missing).code(1));
+    assert!(errors.ends_with("0 same, 0 differ, 3 errors\n"), "{errors}");
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        // an upstream which denies every request
+        let deny = dir.path().join("deny");
+        std::fs::write(&deny, "#!/bin/sh\nexit 2\n").unwrap();
+        std::fs::set_permissions(&deny, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let differ = output(differential(&deny).code(1));
+        assert!(
+            differ.contains("  this fork: Allow, upstream: Deny\n"),
+            "{differ}"
+        );
+        assert!(differ.ends_with("2 same, 1 differ, 0 errors\n"), "{differ}");
+    }
+}
+
+#[test]
+fn test_bench_samples() {
+    let cmd = assert_cmd::Command::cargo_bin("cedar")
+        .expect("bin exists")
+        .arg("bench")
+        .arg("--policies")
+        .arg("sample-data/sandbox_a/policies_1.cedar")
+        .arg("--entities")
+        .arg("sample-data/sandbox_a/entities.json")
+        .arg("--requests")
+        .arg("sample-data/sandbox_a/requests.ndjson")
+        .arg("--iterations")
+        .arg("5")
+        .assert()
+        .success();
+    let output = std::str::from_utf8(&cmd.get_output().stdout)
+        .expect("output should be decodable")
+        .to_string();
+    let lines = output.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3, "{output}");
+    assert!(
+        lines[0].starts_with("parse:      2 policies and 0 templates in "),
+        "{output}"
+    );
+    assert!(
+        lines[1].starts_with("entities:   18 entities in "),
+        "{output}"
+    );
+    assert!(
+        lines[2].starts_with("evaluate:   3 requests x 5: p50 "),
+        "{output}"
+    );
+    // the allocator of the executable counts allocations
+    assert!(lines[2].ends_with("bytes) per request"), "{output}");
+
+    assert_cmd::Command::cargo_bin("cedar")
+        .expect("bin exists")
+        .arg("bench")
+        .arg("--policies")
+        .arg("sample-data/sandbox_a/policies_1.cedar")
+        .arg("--entities")
+        .arg("sample-data/sandbox_a/entities.json")
+        .arg("--requests")
+        .arg("sample-data/sandbox_a/policies_1.cedar")
+        .assert()
+        .failure();
+}
+
+#[cfg(feature = "proxy")]
+#[test]
+fn test_proxy() {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::process::{Command, Stdio};
+
+    // a node which answers every call with its method
+    let node = TcpListener::bind("127.0.0.1:0").unwrap();
+    let node_addr = node.local_addr().unwrap();
+    std::thread::spawn(move || {
+        for stream in node.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut len = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        len = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; len];
+            reader.read_exact(&mut body).unwrap();
+            let answer = |call: &serde_json::Value| serde_json::json!({ "jsonrpc": "2.0", "id": call["id"], "result": call["method"] });
+            let body = match serde_json::from_slice::<serde_json::Value>(&body).unwrap() {
+                serde_json::Value::Array(calls) => {
+                    serde_json::Value::Array(calls.iter().map(answer).collect())
+                }
+                call => answer(&call),
+            }
+            .to_string();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+        }
+    });
+
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let policies_file = dir.path().join("policies.cedar");
+    std::fs::write(
+        &policies_file,
+        r#"permit(principal == ApiKey::"key-1", action == Action::"eth_blockNumber", resource);
+        permit(principal == ApiKey::"key-1", action == Action::"eth_call", resource)
+        when { context.selector == "0x70a08231" };"#,
+    )
+    .unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cedar"))
+        .arg("proxy")
+        .arg("--policies")
+        .arg(&policies_file)
+        .arg("--upstream")
+        .arg(format!("http://{node_addr}"))
+        .arg("--port")
+        .arg("0")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("failed to run cedar");
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    let addr = line
+        .trim()
+        .strip_prefix("Listening on http://")
+        .unwrap_or_else(|| panic!("unexpected first line `{line}`"))
+        .to_string();
+    let send = |path: &str, key: Option<&str>, body: &str| {
+        let mut stream = TcpStream::connect(&addr).unwrap();
+        let key = key.map_or(String::new(), |key| format!("X-Api-Key: {key}\r\n"));
+        write!(
+            stream,
+            "POST {path} HTTP/1.1\r\nHost: localhost\r\n{key}Content-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse::<u16>().unwrap();
+        (
+            status,
+            serde_json::from_str::<serde_json::Value>(body).unwrap(),
+        )
+    };
+    let block_number = r#"{"jsonrpc": "2.0", "id": 1, "method": "eth_blockNumber", "params": []}"#;
+    let balance_of = r#"{"jsonrpc": "2.0", "id": 2, "method": "eth_call", "params": [{"to": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", "data": "0x70a08231"}, "latest"]}"#;
+    let total_supply = r#"{"jsonrpc": "2.0", "id": 3, "method": "eth_call", "params": [{"to": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", "data": "0x18160ddd"}, "latest"]}"#;
+
+    let (status, response) = send("/", Some("key-1"), block_number);
+    assert_eq!(status, 200);
+    assert_eq!(response["result"], "eth_blockNumber");
+    // the key in the path
+    assert_eq!(
+        send("/key-1", None, balance_of).1["result"],
+        serde_json::json!("eth_call")
+    );
+    // denied calls do not reach the node
+    let (status, response) = send("/", Some("key-2"), block_number);
+    assert_eq!(status, 200);
+    assert_eq!(response["error"]["code"], -32001);
+    assert_eq!(response["id"], 1);
+    let (_, response) = send(
+        "/",
+        Some("key-1"),
+        &format!("[{block_number}, {total_supply}, {balance_of}]"),
+    );
+    let mut ids = response
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|response| {
+            (
+                response["id"].as_i64().unwrap(),
+                response.get("error").is_some(),
+            )
+        })
+        .collect::<Vec<_>>();
+    ids.sort_unstable();
+    assert_eq!(ids, [(1, false), (2, false), (3, true)]);
+    assert_eq!(send("/", Some("key-1"), "nope").0, 400);
+
+    child.kill().unwrap();
+}
+
+#[cfg(feature = "remote")]
+#[test]
+fn test_remote_files() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    // a bucket with a policy set and entities, which it serves with ETags
+    let bucket = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", bucket.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in bucket.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            let body = match request.split(' ').nth(1).unwrap() {
+                "/policies.cedar" => {
+                    r#"permit(principal == User::"alice", action == Action::"view", resource);"#
+                }
+                "/entities.json" => "[]",
+                _ => {
+                    write!(
+                        stream,
+                        "HTTP/1.1 404 Not Found\r\nConnection: close\r\n\r\n"
+                    )
+                    .unwrap();
+                    continue;
+                }
+            };
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+        }
+    });
+
+    let cache = tempfile::tempdir().expect("failed to create temp dir");
+    let authorize = |principal: &str, signer: Option<&str>| {
+        let mut cmd = assert_cmd::Command::cargo_bin("cedar").expect("bin exists");
+        cmd.arg("authorize")
+            .arg("--principal")
+            .arg(format!("User::\"{principal}\""))
+            .arg("--action")
+            .arg("Action::\"view\"")
+            .arg("--resource")
+            .arg("Photo::\"1\"")
+            .arg("--policies")
+            .arg(format!("{url}/policies.cedar"))
+            .arg("--entities")
+            .arg(format!("{url}/entities.json"))
+            .env("CEDAR_CACHE_DIR", cache.path())
+            .env_remove("CEDAR_SIGNER");
+        if let Some(signer) = signer {
+            cmd.env("CEDAR_SIGNER", signer);
+        }
+        cmd.assert()
+    };
+
+    authorize("alice", None).success();
+    authorize("bob", None).code(2);
+    assert!(std::fs::read_dir(cache.path()).unwrap().next().is_some());
+    // the policies are not signed
+    authorize("alice", Some("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed")).code(1);
+    authorize("alice", Some("alice")).code(1);
+}
@@ -0,0 +1,74 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Compares evaluating the condition of a signing policy from its expression
+//! tree and from its bytecode program.
+
+use cedar_policy_core::ast::{Context, EntityUID, Expr, Request, RestrictedExpr, SlotEnv};
+use cedar_policy_core::entities::Entities;
+use cedar_policy_core::evaluator::{Evaluator, Program};
+use cedar_policy_core::extensions::Extensions;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+/// The condition of a policy guarding transaction signing
+const CONDITION: &str = r#"
+    context.chain_id == 1
+    && context.value <= 1000000
+    && context.gas_limit < 300000
+    && ["0xa0b86991", "0xdac17f95", "0x6b175474"].contains(context.to)
+    && !(context.method like "approve*")
+    && (context has memo || context.value < 100)
+    && (if context.value > 500000 then context.signers >= 2 else context.signers >= 1)
+    && [1, 5, 10, 137].contains(context.chain_id)
+"#;
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let request = Request::new(
+        EntityUID::with_eid_and_type("Signer", "alice").expect("valid uid"),
+        EntityUID::with_eid_and_type("Action", "sign").expect("valid uid"),
+        EntityUID::with_eid_and_type("Wallet", "treasury").expect("valid uid"),
+        Context::from_pairs([
+            ("chain_id".into(), RestrictedExpr::val(1)),
+            ("value".into(), RestrictedExpr::val(600000)),
+            ("gas_limit".into(), RestrictedExpr::val(21000)),
+            ("to".into(), RestrictedExpr::val("0xdac17f95")),
+            ("method".into(), RestrictedExpr::val("transfer")),
+            ("signers".into(), RestrictedExpr::val(2)),
+        ]),
+    );
+    let entities = Entities::new();
+    let extensions = Extensions::all_available();
+    let eval = Evaluator::new(&request, &entities, &extensions).expect("evaluator is built");
+    let expr: Expr = CONDITION.parse().expect("condition parses");
+    let program = Program::compile(&expr);
+    let slots = SlotEnv::new();
+    assert_eq!(
+        eval.run_program(&program, &slots)
+            .expect("condition evaluates"),
+        Some(eval.interpret(&expr, &slots).expect("condition evaluates"))
+    );
+
+    c.bench_function("condition_tree", |b| {
+        b.iter(|| eval.interpret(black_box(&expr), &slots))
+    });
+    c.bench_function("condition_bytecode", |b| {
+        b.iter(|| eval.run_program(black_box(&program), &slots))
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,114 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Measures parsing an allowlist of entities with address ids, and checking
+//! their membership in groups named by policies, with and without an
+//! `Interner` in scope.
+
+use cedar_policy_core::ast::intern::Interner;
+use cedar_policy_core::ast::EntityUID;
+use cedar_policy_core::entities::{
+    Dereference, Entities, EntityJsonParser, NoEntitiesSchema, TCComputation,
+};
+use cedar_policy_core::extensions::Extensions;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+/// The number of entities in the allowlist
+const USERS: usize = 20_000;
+
+/// The number of groups they are in
+const GROUPS: usize = 50;
+
+fn address(n: usize) -> String {
+    format!("0x{n:040x}")
+}
+
+fn group(n: usize) -> String {
+    format!(r#"Group::"{}""#, address(USERS + n % GROUPS))
+}
+
+fn allowlist() -> String {
+    let users = (0..USERS).map(|n| {
+        serde_json::json!({
+            "uid": {"type": "User", "id": address(n)},
+            "attrs": {"allowlisted_since": n},
+            "parents": [{"type": "Group", "id": address(USERS + n % GROUPS)}]
+        })
+    });
+    serde_json::Value::Array(users.collect()).to_string()
+}
+
+fn parse(json: &str) -> Entities {
+    EntityJsonParser::<NoEntitiesSchema>::new(
+        None,
+        Extensions::all_available(),
+        TCComputation::ComputeNow,
+    )
+    .from_json_str(json)
+    .expect("entities parse")
+}
+
+/// Whether each user is in the group its policy names
+fn in_checks(entities: &Entities, users: &[EntityUID], groups: &[EntityUID]) -> usize {
+    users
+        .iter()
+        .zip(groups)
+        .filter(|(user, group)| match entities.entity(user) {
+            Dereference::Data(user) => entities.is_descendant_of(user, group),
+            _ => false,
+        })
+        .count()
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let json = allowlist();
+    let uids = || {
+        let users: Vec<EntityUID> = (0..USERS)
+            .map(|n| {
+                format!(r#"User::"{}""#, address(n))
+                    .parse()
+                    .expect("valid uid")
+            })
+            .collect();
+        let groups: Vec<EntityUID> = (0..USERS)
+            .map(|n| group(n).parse().expect("valid uid"))
+            .collect();
+        (users, groups)
+    };
+
+    c.bench_function("entities_parse", |b| b.iter(|| parse(black_box(&json))));
+    c.bench_function("entities_parse_interned", |b| {
+        b.iter(|| Interner::new().scope(|| parse(black_box(&json))))
+    });
+
+    let entities = parse(&json);
+    let (users, groups) = uids();
+    assert_eq!(in_checks(&entities, &users, &groups), USERS);
+    c.bench_function("in_checks", |b| {
+        b.iter(|| in_checks(&entities, black_box(&users), &groups))
+    });
+
+    let interner = Interner::new();
+    let entities = interner.scope(|| parse(&json));
+    let (users, groups) = interner.scope(uids);
+    c.bench_function("in_checks_interned", |b| {
+        b.iter(|| in_checks(&entities, black_box(&users), &groups))
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,76 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Measures `u256` parsing and arithmetic in the condition of a value-limit
+//! policy. Run it with and without the `u256-ruint` feature to compare the
+//! backends.
+
+use cedar_policy_core::ast::{Context, EntityUID, Expr, Request, RestrictedExpr, SlotEnv, Value};
+use cedar_policy_core::entities::Entities;
+use cedar_policy_core::evaluator::Evaluator;
+use cedar_policy_core::extensions::Extensions;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+/// The condition of a policy limiting the value of transfers
+const CONDITION: &str = r#"
+    context.value.u256Add(context.fee).u256LessThanOrEqual(context.balance)
+    && context.balance.u256Sub(context.value).u256Sub(context.fee)
+        .u256GreaterThanOrEqual(u256("1000000000000000000"))
+    && context.value.u256Mul(u256("3")).u256LessThanOrEqual(context.daily_limit)
+    && context.spent_today.u256Add(context.value).u256LessThan(context.daily_limit)
+"#;
+
+fn u256(value: &str) -> RestrictedExpr {
+    format!(r#"u256("{value}")"#)
+        .parse()
+        .expect("u256 call parses")
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let request = Request::new(
+        EntityUID::with_eid_and_type("Signer", "alice").expect("valid uid"),
+        EntityUID::with_eid_and_type("Action", "transfer").expect("valid uid"),
+        EntityUID::with_eid_and_type("Wallet", "treasury").expect("valid uid"),
+        Context::from_pairs([
+            ("value".into(), u256("2500000000000000000")),
+            ("fee".into(), u256("0x5af3107a4000")),
+            ("balance".into(), u256("0x56bc75e2d63100000")),
+            ("daily_limit".into(), u256("10000000000000000000")),
+            ("spent_today".into(), u256("1200000000000000000")),
+        ]),
+    );
+    let entities = Entities::new();
+    let extensions = Extensions::all_available();
+    let eval = Evaluator::new(&request, &entities, &extensions).expect("evaluator is built");
+    let expr: Expr = CONDITION.parse().expect("condition parses");
+    let slots = SlotEnv::new();
+    assert_eq!(
+        eval.interpret(&expr, &slots).expect("condition evaluates"),
+        Value::from(true)
+    );
+
+    let literal: Expr = r#"u256("0xde0b6b3a7640000")"#.parse().expect("literal parses");
+    c.bench_function("u256_parse", |b| {
+        b.iter(|| eval.interpret(black_box(&literal), &slots))
+    });
+    c.bench_function("u256_value_limit", |b| {
+        b.iter(|| eval.interpret(black_box(&expr), &slots))
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -15,10 +15,12 @@
  */
 
 fn main() {
+    #[cfg(feature = "parser")]
     generate_parsers();
 }
 
 /// Reads parser grammar files (.lalrpop) and generates Rust modules
+#[cfg(feature = "parser")]
 fn generate_parsers() {
     // PANIC SAFETY: panicking inside our build script on a build dependency error is acceptable
     #[allow(clippy::expect_used)]
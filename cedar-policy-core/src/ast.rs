@@ -22,6 +22,7 @@ mod entity;
 pub use entity::*;
 mod extension;
 pub use extension::*;
+pub mod intern;
 mod literal;
 pub use literal::*;
 mod name;
@@ -44,3 +45,5 @@ mod value;
 pub use value::*;
 mod expr_iterator;
 pub use expr_iterator::*;
+mod source_info;
+pub use source_info::*;
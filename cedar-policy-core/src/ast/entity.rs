@@ -15,8 +15,11 @@
  */
 
 use crate::ast::*;
+use crate::collections;
+#[cfg(feature = "parser")]
 use crate::parser::err::ParseErrors;
 use crate::transitive_closure::TCNode;
+#[cfg(feature = "parser")]
 use crate::FromNormalizedStr;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
@@ -104,11 +107,12 @@ impl EntityUID {
     // GRCOV_BEGIN_COVERAGE
 
     /// Create an `EntityUID` with the given (unqualified) typename, and the given string as its EID.
+    #[cfg(feature = "parser")]
     pub fn with_eid_and_type(typename: &str, eid: &str) -> Result<Self, ParseErrors> {
-        Ok(Self {
-            ty: EntityType::Concrete(Name::parse_unqualified_name(typename)?),
-            eid: Eid(eid.into()),
-        })
+        Ok(Self::from_components(
+            Name::parse_unqualified_name(typename)?,
+            Eid::new(eid),
+        ))
     }
 
     /// Split into the `EntityType` representing the entity type, and the `Eid`
@@ -120,8 +124,8 @@ impl EntityUID {
     /// Create a nominally-typed `EntityUID` with the given typename and EID
     pub fn from_components(name: Name, eid: Eid) -> Self {
         Self {
-            ty: EntityType::Concrete(name),
-            eid,
+            ty: EntityType::Concrete(intern::intern_name(name)),
+            eid: Eid(intern::intern_str(eid.0)),
         }
     }
 
@@ -155,6 +159,7 @@ impl std::fmt::Display for EntityUID {
     }
 }
 
+#[cfg(feature = "parser")]
 // allow `.parse()` on a string to make an `EntityUID`
 impl std::str::FromStr for EntityUID {
     type Err = ParseErrors;
@@ -164,6 +169,7 @@ impl std::str::FromStr for EntityUID {
     }
 }
 
+#[cfg(feature = "parser")]
 impl FromNormalizedStr for EntityUID {
     fn describe_self() -> &'static str {
         "Entity UID"
@@ -171,13 +177,26 @@ impl FromNormalizedStr for EntityUID {
 }
 
 /// EID type is just a SmolStr for now
-#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Hash, PartialOrd, Ord)]
+#[derive(Serialize, Deserialize, Eq, Debug, Clone, PartialOrd, Ord)]
 pub struct Eid(SmolStr);
 
 impl Eid {
     /// Construct an Eid
     pub fn new(eid: impl Into<SmolStr>) -> Self {
-        Eid(eid.into())
+        Eid(intern::intern_str(eid.into()))
+    }
+}
+
+impl PartialEq for Eid {
+    fn eq(&self, other: &Self) -> bool {
+        // interned ids are equal if they are the same string
+        std::ptr::eq(self.0.as_str(), other.0.as_str()) || self.0 == other.0
+    }
+}
+
+impl std::hash::Hash for Eid {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
     }
 }
 
@@ -217,11 +236,17 @@ pub struct Entity {
     ///
     /// In the serialized form of `Entity`, attribute values appear as
     /// `RestrictedExpr`s.
-    attrs: HashMap<SmolStr, RestrictedExpr>,
+    attrs: collections::HashMap<SmolStr, RestrictedExpr>,
 
     /// Set of ancestors of this `Entity` (i.e., all direct and transitive
     /// parents), as UIDs
-    ancestors: HashSet<EntityUID>,
+    ancestors: collections::HashSet<EntityUID>,
+
+    /// Internal HashMap of tags. Tags are key-value pairs like attributes,
+    /// but are declared separately in the schema and are accessed with the
+    /// `hasTag` and `getTag` operators.
+    #[serde(default)]
+    tags: collections::HashMap<SmolStr, RestrictedExpr>,
 }
 
 impl Entity {
@@ -231,10 +256,47 @@ impl Entity {
         attrs: HashMap<SmolStr, RestrictedExpr>,
         ancestors: HashSet<EntityUID>,
     ) -> Self {
+        Self::new_with_tags(uid, attrs, ancestors, HashMap::new())
+    }
+
+    /// Create a new `Entity` with this UID, attributes, ancestors, and tags
+    pub fn new_with_tags(
+        uid: EntityUID,
+        attrs: HashMap<SmolStr, RestrictedExpr>,
+        ancestors: HashSet<EntityUID>,
+        tags: HashMap<SmolStr, RestrictedExpr>,
+    ) -> Self {
+        Self::from_collections(
+            uid,
+            collections::from_std_map(attrs),
+            collections::from_std_set(ancestors),
+            collections::from_std_map(tags),
+        )
+    }
+
+    /// Create a new `Entity` from the maps and sets it is kept in, which saves
+    /// converting those of `std` with the `fast-hash` feature
+    pub(crate) fn from_collections(
+        uid: EntityUID,
+        attrs: collections::HashMap<SmolStr, RestrictedExpr>,
+        ancestors: collections::HashSet<EntityUID>,
+        tags: collections::HashMap<SmolStr, RestrictedExpr>,
+    ) -> Self {
+        let intern_keys = |values: collections::HashMap<SmolStr, RestrictedExpr>| {
+            if cfg!(feature = "interning") {
+                values
+                    .into_iter()
+                    .map(|(key, value)| (intern::intern_str(key), value))
+                    .collect()
+            } else {
+                values
+            }
+        };
         Entity {
             uid,
-            attrs,
+            attrs: intern_keys(attrs),
             ancestors,
+            tags: intern_keys(tags),
         }
     }
 
@@ -248,6 +310,11 @@ impl Entity {
         self.attrs.get(attr)
     }
 
+    /// Get the value for the given tag, or `None` if not present
+    pub fn get_tag(&self, tag: &str) -> Option<&RestrictedExpr> {
+        self.tags.get(tag)
+    }
+
     /// Is this `Entity` a descendant of `e` in the entity hierarchy?
     pub fn is_descendant_of(&self, e: &EntityUID) -> bool {
         self.ancestors.contains(e)
@@ -265,34 +332,57 @@ impl Entity {
             .map(|(k, v)| (k.as_str(), v.as_borrowed()))
     }
 
-    /// Create an `Entity` with the given UID, no attributes, and no parents.
+    /// Iterate over this entity's tags
+    pub fn tags(&self) -> impl Iterator<Item = (&str, BorrowedRestrictedExpr<'_>)> {
+        self.tags.iter().map(|(k, v)| (k.as_str(), v.as_borrowed()))
+    }
+
+    /// Create an `Entity` with the given UID, no attributes, no parents, and
+    /// no tags.
     pub fn with_uid(uid: EntityUID) -> Self {
         Self {
             uid,
-            attrs: HashMap::new(),
-            ancestors: HashSet::new(),
+            attrs: collections::HashMap::new(),
+            ancestors: collections::HashSet::new(),
+            tags: collections::HashMap::new(),
         }
     }
 
     /// Read-only access the internal `attrs` map of String to RestrictedExpr.
     /// This function is available only inside Core.
-    pub(crate) fn attrs_map(&self) -> &HashMap<SmolStr, RestrictedExpr> {
+    pub(crate) fn attrs_map(&self) -> &collections::HashMap<SmolStr, RestrictedExpr> {
         &self.attrs
     }
 
+    /// Read-only access the internal `tags` map of String to RestrictedExpr.
+    /// This function is available only inside Core.
+    pub(crate) fn tags_map(&self) -> &collections::HashMap<SmolStr, RestrictedExpr> {
+        &self.tags
+    }
+
     /// Read-only access the internal `ancestors` hashset.
     /// This function is available only inside Core.
-    pub(crate) fn ancestors_set(&self) -> &HashSet<EntityUID> {
+    pub(crate) fn ancestors_set(&self) -> &collections::HashSet<EntityUID> {
         &self.ancestors
     }
 
     /// Set the given attribute to the given value.
-    // Only used for convenience in some tests and when fuzzing
-    #[cfg(any(test, fuzzing))]
+    // When fuzzing, `set_attr()` is fully `pub`.
+    #[cfg(not(fuzzing))]
+    pub(crate) fn set_attr(&mut self, attr: SmolStr, val: RestrictedExpr) {
+        self.attrs.insert(attr, val);
+    }
+    /// Set the given attribute to the given value.
+    #[cfg(fuzzing)]
     pub fn set_attr(&mut self, attr: SmolStr, val: RestrictedExpr) {
         self.attrs.insert(attr, val);
     }
 
+    /// Set the given tag to the given value.
+    pub(crate) fn set_tag(&mut self, tag: SmolStr, val: RestrictedExpr) {
+        self.tags.insert(tag, val);
+    }
+
     /// Mark the given `UID` as an ancestor of this `Entity`.
     // When fuzzing, `add_ancestor()` is fully `pub`.
     #[cfg(not(fuzzing))]
@@ -349,7 +439,18 @@ impl std::fmt::Display for Entity {
                 .map(|(k, v)| format!("{}: {}", k, v))
                 .join("; "),
             self.ancestors.iter().join(", ")
-        )
+        )?;
+        if !self.tags.is_empty() {
+            write!(
+                f,
+                "\n  tags:{}",
+                self.tags
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", k, v))
+                    .join("; ")
+            )?;
+        }
+        Ok(())
     }
 }
 
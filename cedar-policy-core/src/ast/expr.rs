@@ -14,11 +14,9 @@
  * limitations under the License.
  */
 
-use crate::{
-    ast::*,
-    extensions::Extensions,
-    parser::{err::ParseErrors, SourceInfo},
-};
+#[cfg(feature = "parser")]
+use crate::parser::err::ParseErrors;
+use crate::{ast::*, extensions::Extensions};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use smol_str::SmolStr;
@@ -415,6 +413,18 @@ impl Expr {
         ExprBuilder::new().contains_any(e1, e2)
     }
 
+    /// Create a 'has_tag' expression. `e1` must evaluate to an entity and
+    /// `e2` to a string
+    pub fn has_tag(e1: Expr, e2: Expr) -> Self {
+        ExprBuilder::new().has_tag(e1, e2)
+    }
+
+    /// Create a 'get_tag' expression. `e1` must evaluate to an entity and
+    /// `e2` to a string
+    pub fn get_tag(e1: Expr, e2: Expr) -> Self {
+        ExprBuilder::new().get_tag(e1, e2)
+    }
+
     /// Create an `Expr` which evaluates to a Set of the given `Expr`s
     pub fn set(exprs: impl IntoIterator<Item = Expr>) -> Self {
         ExprBuilder::new().set(exprs)
@@ -667,6 +677,12 @@ impl std::fmt::Display for Expr {
                 BinaryOp::ContainsAny => {
                     write!(f, "{}.containsAny({})", maybe_with_parens(arg1), &arg2)
                 }
+                BinaryOp::HasTag => {
+                    write!(f, "{}.hasTag({})", maybe_with_parens(arg1), &arg2)
+                }
+                BinaryOp::GetTag => {
+                    write!(f, "{}.getTag({})", maybe_with_parens(arg1), &arg2)
+                }
             },
             ExprKind::MulByConst { arg, constant } => {
                 write!(f, "{} * {}", maybe_with_parens(arg), constant)
@@ -766,6 +782,7 @@ fn maybe_with_parens(expr: &Expr) -> String {
     }
 }
 
+#[cfg(feature = "parser")]
 impl std::str::FromStr for Expr {
     type Err = ParseErrors;
 
@@ -1049,6 +1066,26 @@ impl<T> ExprBuilder<T> {
         })
     }
 
+    /// Create a 'has_tag' expression. `e1` must evaluate to an entity and
+    /// `e2` to a string
+    pub fn has_tag(self, e1: Expr<T>, e2: Expr<T>) -> Expr<T> {
+        self.with_expr_kind(ExprKind::BinaryApp {
+            op: BinaryOp::HasTag,
+            arg1: Arc::new(e1),
+            arg2: Arc::new(e2),
+        })
+    }
+
+    /// Create a 'get_tag' expression. `e1` must evaluate to an entity and
+    /// `e2` to a string
+    pub fn get_tag(self, e1: Expr<T>, e2: Expr<T>) -> Expr<T> {
+        self.with_expr_kind(ExprKind::BinaryApp {
+            op: BinaryOp::GetTag,
+            arg1: Arc::new(e1),
+            arg2: Arc::new(e2),
+        })
+    }
+
     /// Create an `Expr` which evaluates to a Set of the given `Expr`s
     pub fn set(self, exprs: impl IntoIterator<Item = Expr<T>>) -> Expr<T> {
         self.with_expr_kind(ExprKind::Set(Arc::new(exprs.into_iter().collect())))
@@ -1096,7 +1133,7 @@ impl<T> ExprBuilder<T> {
     pub fn get_attr(self, expr: Expr<T>, attr: SmolStr) -> Expr<T> {
         self.with_expr_kind(ExprKind::GetAttr {
             expr: Arc::new(expr),
-            attr,
+            attr: intern::intern_str(attr),
         })
     }
 
@@ -1107,7 +1144,7 @@ impl<T> ExprBuilder<T> {
     pub fn has_attr(self, expr: Expr<T>, attr: SmolStr) -> Expr<T> {
         self.with_expr_kind(ExprKind::HasAttr {
             expr: Arc::new(expr),
-            attr,
+            attr: intern::intern_str(attr),
         })
     }
 
@@ -1418,13 +1455,10 @@ impl From<PrincipalOrResource> for Var {
     }
 }
 
-// PANIC SAFETY Tested by `test::all_vars_are_ids`. Never panics.
-#[allow(clippy::fallible_impl_from)]
+// All vars are formatted as valid `Id`s. Tested by `test::all_vars_are_ids`
 impl From<Var> for Id {
     fn from(var: Var) -> Self {
-        // PANIC SAFETY: `Var` is a simple enum and all vars are formatted as valid `Id`. Tested by `test::all_vars_are_ids`
-        #[allow(clippy::unwrap_used)]
-        format!("{var}").parse().unwrap()
+        Id::new_unchecked(format!("{var}"))
     }
 }
 
@@ -1452,7 +1486,8 @@ mod test {
     #[test]
     fn all_vars_are_ids() {
         for var in all_vars() {
-            let _id: Id = var.into();
+            let id: Id = var.into();
+            assert_eq!(id.as_ref().parse::<Id>().expect("should be a valid Id"), id);
         }
     }
 
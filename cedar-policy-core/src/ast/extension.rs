@@ -36,6 +36,9 @@ pub struct Extension {
     name: Name,
     /// Extension functions. These are legal to call in Cedar expressions.
     functions: HashMap<Name, ExtensionFunction>,
+    /// Operators overloaded by this extension, and the names of the
+    /// extension functions implementing them
+    operators: HashMap<OverloadedOperator, Name>,
 }
 
 impl Extension {
@@ -44,9 +47,27 @@ impl Extension {
         Self {
             name,
             functions: functions.into_iter().map(|f| (f.name.clone(), f)).collect(),
+            operators: HashMap::new(),
         }
     }
 
+    /// Overload operators for the type of the first argument of the given
+    /// extension functions, so that applying an operator to a value of that
+    /// type calls the function instead
+    pub fn with_operators(
+        mut self,
+        operators: impl IntoIterator<Item = (OverloadedOperator, Name)>,
+    ) -> Self {
+        self.operators.extend(operators);
+        self
+    }
+
+    /// Look up the function implementing the operator `op`, or return `None`
+    /// if the extension doesn't overload `op`
+    pub fn operator_func(&self, op: OverloadedOperator) -> Option<&ExtensionFunction> {
+        self.operators.get(&op).and_then(|name| self.get_func(name))
+    }
+
     /// Get the name of the extension
     pub fn name(&self) -> &Name {
         &self.name
@@ -70,6 +91,25 @@ impl std::fmt::Debug for Extension {
     }
 }
 
+/// Binary operators which an extension may overload for its types
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum OverloadedOperator {
+    /// `<`
+    Less,
+    /// `<=`
+    LessEq,
+    /// `>`
+    Greater,
+    /// `>=`
+    GreaterEq,
+    /// `+`
+    Add,
+    /// `-`
+    Sub,
+    /// `*`
+    Mul,
+}
+
 /// The output of an extension call, either a value or an unknown
 #[derive(Debug, Clone)]
 pub enum ExtensionOutputValue {
@@ -272,6 +312,18 @@ impl ExtensionFunction {
         )
     }
 
+    /// Create an `ExtensionFunction` with the name, call style and types of
+    /// this one, which calls `func` instead
+    pub fn with_func(&self, func: ExtensionFunctionObject) -> Self {
+        Self::new(
+            self.name.clone(),
+            self.style,
+            func,
+            self.return_type.clone(),
+            self.arg_types.clone(),
+        )
+    }
+
     /// Get the `Name` of the `ExtensionFunction`
     pub fn name(&self) -> &Name {
         &self.name
@@ -0,0 +1,216 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Interning of entity type names, entity ids and attribute names, shared by
+//! policies and entities.
+//!
+//! With the `interning` feature, every `EntityUID` and `Eid` made, by the
+//! parser, the entity JSON parser or the constructors, and every attribute
+//! name of an `Entity` or an attribute access, is interned while an
+//! [`Interner`] is in scope, so that the same name or id is kept in memory once
+//! however many entities and policies use it. Equal interned values are then
+//! compared by pointer, rather than by their contents.
+//!
+//! Only strings which `SmolStr` allocates are interned: shorter ones are kept
+//! inline, and take no memory of their own to share. An interner keeps its
+//! values until it is dropped, and belongs to the thread it was made on, so
+//! interning takes no lock. Outside [`Interner::scope`], or without the
+//! feature, nothing is interned.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use smol_str::SmolStr;
+
+use super::Name;
+
+/// The values interned so far
+#[derive(Debug, Default)]
+struct Values {
+    strings: HashSet<SmolStr>,
+    names: HashSet<Name>,
+}
+
+/// Interns the values made in its [`Self::scope`]. Clones share their values.
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    values: Rc<RefCell<Values>>,
+}
+
+thread_local! {
+    /// The interner of the innermost scope of this thread
+    static CURRENT: RefCell<Option<Interner>> = const { RefCell::new(None) };
+}
+
+/// Restores the interner of the enclosing scope, even if the scope panics
+struct Restore(Option<Interner>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        CURRENT.with(|current| *current.borrow_mut() = self.0.take());
+    }
+}
+
+impl Interner {
+    /// Create an interner with no values
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `f`, interning the values made by it in this interner, such as
+    /// those of the policies and entities it parses
+    pub fn scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        let _restore = Restore(CURRENT.with(|current| current.replace(Some(self.clone()))));
+        f()
+    }
+
+    /// How many strings and names are interned
+    pub fn stats(&self) -> InternerStats {
+        let values = self.values.borrow();
+        InternerStats {
+            strings: values.strings.len(),
+            names: values.names.len(),
+        }
+    }
+}
+
+/// Get the interned copy of `value` in the interner in scope, interning it if
+/// it is not yet
+fn intern<T: Clone + Eq + std::hash::Hash>(
+    value: T,
+    set_of: impl FnOnce(&mut Values) -> &mut HashSet<T>,
+) -> T {
+    CURRENT.with(|current| match current.borrow().as_ref() {
+        Some(interner) => {
+            let mut values = interner.values.borrow_mut();
+            let set = set_of(&mut values);
+            match set.get(&value) {
+                Some(interned) => interned.clone(),
+                None => {
+                    set.insert(value.clone());
+                    value
+                }
+            }
+        }
+        None => value,
+    })
+}
+
+/// Get the interned copy of `s`, interning it if it is not yet
+pub fn intern_str(s: SmolStr) -> SmolStr {
+    if !cfg!(feature = "interning") || !s.is_heap_allocated() {
+        return s;
+    }
+    intern(s, |values| &mut values.strings)
+}
+
+/// Get the interned copy of `name`, whose namespaces are shared with every
+/// other copy, interning it if it is not yet
+pub fn intern_name(name: Name) -> Name {
+    if !cfg!(feature = "interning") {
+        return name;
+    }
+    intern(name, |values| &mut values.names)
+}
+
+/// How many strings and names are interned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InternerStats {
+    /// The number of interned strings: entity ids and attribute names
+    pub strings: usize,
+    /// The number of interned entity type names
+    pub names: usize,
+}
+
+#[cfg(all(test, feature = "interning"))]
+mod test {
+    use super::*;
+    use crate::ast::{Eid, EntityUID};
+    use std::sync::Arc;
+
+    const ID: &str = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed";
+
+    #[test]
+    fn shares_long_strings() {
+        let interner = Interner::new();
+        let (first, second, short) = interner.scope(|| {
+            (
+                intern_str(SmolStr::new(ID)),
+                intern_str(SmolStr::new(ID)),
+                // short strings are kept inline
+                intern_str(SmolStr::new("balance")),
+            )
+        });
+        assert!(std::ptr::eq(first.as_str(), second.as_str()));
+        assert!(!short.is_heap_allocated());
+        assert_eq!(
+            interner.stats(),
+            InternerStats {
+                strings: 1,
+                names: 0
+            }
+        );
+    }
+
+    #[test]
+    fn interns_only_in_scope() {
+        let outside = intern_str(SmolStr::new(ID));
+        assert!(!std::ptr::eq(
+            outside.as_str(),
+            intern_str(SmolStr::new(ID)).as_str()
+        ));
+
+        let outer = Interner::new();
+        let inner = Interner::new();
+        outer.scope(|| {
+            let first = intern_str(SmolStr::new(ID));
+            let second = inner.scope(|| intern_str(SmolStr::new(ID)));
+            assert!(!std::ptr::eq(first.as_str(), second.as_str()));
+            // the outer interner is in scope again
+            assert!(std::ptr::eq(
+                first.as_str(),
+                intern_str(SmolStr::new(ID)).as_str()
+            ));
+        });
+        assert_eq!(outer.stats().strings, 1);
+        assert_eq!(inner.stats().strings, 1);
+    }
+
+    #[test]
+    fn shares_uids_of_policies_and_entities() {
+        let (parsed, made) = Interner::new().scope(|| {
+            let parsed: EntityUID = format!(r#"Ns::Address::"{ID}""#)
+                .parse()
+                .expect("valid uid");
+            let made = EntityUID::from_components(
+                "Ns::Address".parse().expect("valid name"),
+                Eid::new(ID),
+            );
+            (parsed, made)
+        });
+        assert_eq!(parsed, made);
+        let (parsed_type, parsed_eid) = parsed.components();
+        let (made_type, made_eid) = made.components();
+        assert!(std::ptr::eq::<str>(parsed_eid.as_ref(), made_eid.as_ref()));
+        match (parsed_type, made_type) {
+            (crate::ast::EntityType::Concrete(a), crate::ast::EntityType::Concrete(b)) => {
+                assert!(Arc::ptr_eq(&a.path, &b.path));
+            }
+            _ => panic!("expected concrete types"),
+        }
+    }
+}
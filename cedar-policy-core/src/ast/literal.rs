@@ -15,6 +15,7 @@
  */
 
 use crate::ast::{EntityUID, StaticallyTyped, Type};
+#[cfg(feature = "parser")]
 use crate::parser;
 use serde::{Deserialize, Serialize};
 use smol_str::SmolStr;
@@ -70,6 +71,7 @@ impl std::fmt::Display for Literal {
     }
 }
 
+#[cfg(feature = "parser")]
 impl std::str::FromStr for Literal {
     type Err = parser::err::ParseErrors;
 
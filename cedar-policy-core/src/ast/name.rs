@@ -20,7 +20,9 @@ use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use smol_str::SmolStr;
 
+#[cfg(feature = "parser")]
 use crate::parser::err::ParseErrors;
+#[cfg(feature = "parser")]
 use crate::FromNormalizedStr;
 
 use super::PrincipalOrResource;
@@ -35,7 +37,7 @@ pub fn unwrap_or_clone<T: Clone>(arc: Arc<T>) -> T {
 /// This is the `Name` type used to name types, functions, etc.
 /// The name can include namespaces.
 /// Clone is O(1).
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Hash, PartialOrd, Ord)]
+#[derive(Serialize, Deserialize, Debug, Eq, Clone, PartialOrd, Ord)]
 #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Name {
     /// Basename
@@ -63,6 +65,7 @@ impl Name {
 
     /// Create a `Name` with no path (no namespaces).
     /// Returns an error if `s` is not a valid identifier.
+    #[cfg(feature = "parser")]
     pub fn parse_unqualified_name(s: &str) -> Result<Self, ParseErrors> {
         Ok(Self {
             id: s.parse()?,
@@ -70,6 +73,13 @@ impl Name {
         })
     }
 
+    /// Create a `Name` with no path for a built-in, such as an extension
+    /// function, whose name `id` is a valid identifier. This does not go
+    /// through the parser, so that built-ins are available without it.
+    pub(crate) fn builtin(id: &'static str) -> Self {
+        Self::unqualified_name(Id::new_unchecked(id))
+    }
+
     /// Given a type basename and a namespace (as a `Name` itself),
     /// return a `Name` representing the type's fully qualified name
     pub fn type_in_namespace(basename: Id, namespace: Name) -> Name {
@@ -99,6 +109,20 @@ impl Name {
     }
 }
 
+impl PartialEq for Name {
+    fn eq(&self, other: &Self) -> bool {
+        // interned names share their namespaces
+        self.id == other.id && (Arc::ptr_eq(&self.path, &other.path) || self.path == other.path)
+    }
+}
+
+impl std::hash::Hash for Name {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+        self.path.hash(state);
+    }
+}
+
 impl std::fmt::Display for Name {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for elem in self.path.as_ref() {
@@ -109,6 +133,7 @@ impl std::fmt::Display for Name {
     }
 }
 
+#[cfg(feature = "parser")]
 // allow `.parse()` on a string to make a `Name`
 impl std::str::FromStr for Name {
     type Err = ParseErrors;
@@ -118,6 +143,7 @@ impl std::str::FromStr for Name {
     }
 }
 
+#[cfg(feature = "parser")]
 impl FromNormalizedStr for Name {
     fn describe_self() -> &'static str {
         "Name"
@@ -247,6 +273,7 @@ impl std::fmt::Display for Id {
     }
 }
 
+#[cfg(feature = "parser")]
 // allow `.parse()` on a string to make an `Id`
 impl std::str::FromStr for Id {
     type Err = ParseErrors;
@@ -256,6 +283,7 @@ impl std::str::FromStr for Id {
     }
 }
 
+#[cfg(feature = "parser")]
 impl FromNormalizedStr for Id {
     fn describe_self() -> &'static str {
         "Id"
@@ -84,6 +84,19 @@ pub enum BinaryOp {
     ///
     /// Arguments must have Set type
     ContainsAny,
+
+    /// Does the first arg have the tag named by the second arg
+    ///
+    /// First argument must have Entity type, second argument must have
+    /// String type
+    HasTag,
+
+    /// Get the value of the tag named by the second arg on the first arg.
+    /// It is an error if the entity does not have that tag.
+    ///
+    /// First argument must have Entity type, second argument must have
+    /// String type
+    GetTag,
 }
 
 impl std::fmt::Display for UnaryOp {
@@ -107,6 +120,8 @@ impl std::fmt::Display for BinaryOp {
             BinaryOp::Contains => write!(f, "contains"),
             BinaryOp::ContainsAll => write!(f, "containsAll"),
             BinaryOp::ContainsAny => write!(f, "containsAny"),
+            BinaryOp::HasTag => write!(f, "hasTag"),
+            BinaryOp::GetTag => write!(f, "getTag"),
         }
     }
 }
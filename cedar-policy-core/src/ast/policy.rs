@@ -15,6 +15,9 @@
  */
 
 use crate::ast::*;
+use crate::collections;
+#[cfg(feature = "bytecode")]
+use crate::evaluator::Program;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use smol_str::SmolStr;
@@ -32,6 +35,9 @@ pub struct Template {
     /// INVARIANT (slot cache correctness): This Vec must contain _all_ of the open slots in `body`
     /// This is maintained by the only two public constructors, `new` and `instantiate_inline_policy`
     slots: Vec<SlotId>,
+    /// The condition of `body`, compiled to bytecode for evaluation
+    #[cfg(feature = "bytecode")]
+    program: Arc<Program>,
 }
 
 impl From<Template> for TemplateBody {
@@ -108,11 +114,27 @@ impl Template {
         self.body.id()
     }
 
+    /// Get the locations of this template and its scope constraints in the
+    /// policy source text
+    pub fn source_info(&self) -> &PolicySourceInfo {
+        self.body.source_info()
+    }
+
+    /// Set the locations of this template and its scope constraints in the
+    /// policy source text
+    #[cfg(feature = "parser")]
+    pub(crate) fn with_source_info(mut self, source_info: PolicySourceInfo) -> Self {
+        self.body.source_info = source_info;
+        self
+    }
+
     /// Clone this Policy with a new ID
     pub fn new_id(&self, id: PolicyID) -> Self {
         Template {
             body: self.body.new_id(id),
             slots: self.slots.clone(),
+            #[cfg(feature = "bytecode")]
+            program: self.program.clone(),
         }
     }
 
@@ -140,6 +162,12 @@ impl Template {
         self.body.condition()
     }
 
+    /// Get the condition expression of this template, compiled to bytecode
+    #[cfg(feature = "bytecode")]
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
     /// List of open slots in this template
     pub fn slots(&self) -> impl Iterator<Item = &SlotId> {
         self.slots.iter()
@@ -156,15 +184,12 @@ impl Template {
     /// Ensure that every slot in the template is bound by values,
     /// and that no extra values are bound in values
     /// This upholds invariant (values total map)
-    pub fn check_binding(
-        template: &Template,
-        values: &HashMap<SlotId, EntityUID>,
-    ) -> Result<(), LinkingError> {
+    pub fn check_binding(template: &Template, values: &SlotEnv) -> Result<(), LinkingError> {
         // Verify all slots bound
         let unbound = template
             .slots
             .iter()
-            .filter(|slot| !values.contains_key(slot))
+            .filter(|slot| !values.contains_key(*slot))
             .collect::<Vec<_>>();
 
         let extra = values
@@ -209,6 +234,8 @@ impl Template {
         // StaticPolicy by invariant (inline policy correctness)
         // can have no slots, so it is safe to make `slots` the empty vec
         let t = Arc::new(Self {
+            #[cfg(feature = "bytecode")]
+            program: Arc::new(Program::compile(&body.condition())),
             body,
             slots: vec![],
         });
@@ -221,17 +248,23 @@ impl Template {
         // we use the following sentinel to "turn back on" coverage tracking for
         // remaining lines of this file, until the next #[cfg(test)]
         // GRCOV_BEGIN_COVERAGE
-        let p = Policy::new(Arc::clone(&t), None, HashMap::new());
+        let p = Policy::new(Arc::clone(&t), None, SlotEnv::new());
         (t, p)
     }
 }
 
 impl From<TemplateBody> for Template {
     fn from(body: TemplateBody) -> Self {
+        let condition = body.condition();
         // INVARIANT: (slot cache correctness)
         // Pull all the slots out of the template body's condition.
-        let slots = body.condition().slots().copied().collect::<Vec<_>>();
-        Self { body, slots }
+        let slots = condition.slots().copied().collect::<Vec<_>>();
+        Self {
+            body,
+            slots,
+            #[cfg(feature = "bytecode")]
+            program: Arc::new(Program::compile(&condition)),
+        }
     }
 }
 
@@ -311,7 +344,7 @@ pub struct Policy {
     /// values the slots are bound to.
     /// The constructor `new` is only visible in this module,
     /// so it is the responsibility of callers to maintain
-    values: HashMap<SlotId, EntityUID>,
+    values: SlotEnv,
 }
 
 impl Policy {
@@ -410,6 +443,12 @@ impl Policy {
         self.template.non_head_constraints()
     }
 
+    /// Get the locations of this policy's template and its scope constraints
+    /// in the policy source text
+    pub fn source_info(&self) -> &PolicySourceInfo {
+        self.template.source_info()
+    }
+
     /// Get the expression that represents this policy.
     pub fn condition(&self) -> Expr {
         self.template.condition()
@@ -543,7 +582,7 @@ mod hashing_tests {
     }
 
     fn build_template_linked_policy() -> LiteralPolicy {
-        let mut map = HashMap::new();
+        let mut map = SlotEnv::new();
         map.insert(SlotId::principal(), EntityUID::with_eid("eid"));
         LiteralPolicy {
             template_id: PolicyID::from_string("template"),
@@ -584,7 +623,7 @@ impl LiteralPolicy {
     /// Consumes the policy.
     pub fn reify(
         self,
-        templates: &HashMap<PolicyID, Arc<Template>>,
+        templates: &collections::HashMap<PolicyID, Arc<Template>>,
     ) -> Result<Policy, ReificationError> {
         let template = templates
             .get(&self.template_id)
@@ -723,6 +762,12 @@ impl StaticPolicy {
         self.0.non_head_constraints()
     }
 
+    /// Get the locations of this policy and its scope constraints in the
+    /// policy source text
+    pub fn source_info(&self) -> &PolicySourceInfo {
+        self.0.source_info()
+    }
+
     /// Get the condition expression of this policy.
     ///
     /// This will be a conjunction of the policy's head constraints (on
@@ -816,6 +861,9 @@ pub struct TemplateBody {
     /// This will be a conjunction of the policy's `when` conditions and the
     /// negation of each of the policy's `unless` conditions.
     non_head_constraints: Expr,
+    /// Locations of the policy and its scope constraints in the source text
+    #[serde(default)]
+    source_info: PolicySourceInfo,
 }
 
 impl TemplateBody {
@@ -890,6 +938,12 @@ impl TemplateBody {
         &self.non_head_constraints
     }
 
+    /// Get the locations of this policy and its scope constraints in the
+    /// policy source text
+    pub fn source_info(&self) -> &PolicySourceInfo {
+        &self.source_info
+    }
+
     /// Get the condition expression of this policy.
     ///
     /// This will be a conjunction of the policy's head constraints (on
@@ -926,8 +980,58 @@ impl TemplateBody {
             action_constraint,
             resource_constraint,
             non_head_constraints,
+            source_info: PolicySourceInfo::default(),
+        }
+    }
+}
+
+/// The locations in the policy source text of a policy and of its scope
+/// constraints. These are all `None` for a policy which was not parsed from
+/// text, such as one constructed directly or converted from JSON.
+#[derive(Serialize, Deserialize, Clone, Hash, Eq, PartialEq, Debug, Default)]
+pub struct PolicySourceInfo {
+    policy: Option<SourceInfo>,
+    principal: Option<SourceInfo>,
+    action: Option<SourceInfo>,
+    resource: Option<SourceInfo>,
+}
+
+impl PolicySourceInfo {
+    /// Construct a `PolicySourceInfo` from the locations of a policy and its
+    /// principal, action and resource constraints
+    pub fn new(
+        policy: Option<SourceInfo>,
+        principal: Option<SourceInfo>,
+        action: Option<SourceInfo>,
+        resource: Option<SourceInfo>,
+    ) -> Self {
+        Self {
+            policy,
+            principal,
+            action,
+            resource,
         }
     }
+
+    /// Get the location of the whole policy, including its annotations
+    pub fn policy(&self) -> Option<&SourceInfo> {
+        self.policy.as_ref()
+    }
+
+    /// Get the location of the principal constraint, e.g., `principal in ?principal`
+    pub fn principal(&self) -> Option<&SourceInfo> {
+        self.principal.as_ref()
+    }
+
+    /// Get the location of the action constraint
+    pub fn action(&self) -> Option<&SourceInfo> {
+        self.action.as_ref()
+    }
+
+    /// Get the location of the resource constraint
+    pub fn resource(&self) -> Option<&SourceInfo> {
+        self.resource.as_ref()
+    }
 }
 
 impl From<StaticPolicy> for TemplateBody {
@@ -1383,7 +1487,7 @@ impl std::fmt::Display for StaticPolicy {
 }
 
 /// A unique identifier for a policy statement
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Hash)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
 pub struct PolicyID(SmolStr);
 
 impl PolicyID {
@@ -1404,6 +1508,12 @@ impl std::fmt::Display for PolicyID {
     }
 }
 
+impl AsRef<str> for PolicyID {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
 #[cfg(feature = "arbitrary")]
 impl<'u> arbitrary::Arbitrary<'u> for PolicyID {
     fn arbitrary(u: &mut arbitrary::Unstructured<'_>) -> arbitrary::Result<PolicyID> {
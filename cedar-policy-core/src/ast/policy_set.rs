@@ -18,10 +18,11 @@ use super::{
     EntityUID, LinkingError, LiteralPolicy, Policy, PolicyID, ReificationError, SlotId,
     StaticPolicy, Template,
 };
+use crate::collections::{hash_map::Entry, HashMap};
+use crate::memory::{table_size, HeapSize, PolicySetMemoryUsage};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use std::collections::{hash_map::Entry, HashMap};
-use std::{borrow::Borrow, sync::Arc};
+use std::{borrow::Borrow, mem::size_of, sync::Arc};
 use thiserror::Error;
 
 /// Represents a set of `Policy`s
@@ -198,7 +199,7 @@ impl PolicySet {
         &mut self,
         template_id: PolicyID,
         new_id: PolicyID,
-        values: HashMap<SlotId, EntityUID>,
+        values: std::collections::HashMap<SlotId, EntityUID>,
     ) -> Result<&Policy, LinkingError> {
         let t = self
             .get_template(&template_id)
@@ -222,6 +223,16 @@ impl PolicySet {
         }
     }
 
+    /// Remove a template-linked policy, leaving its template in place.
+    /// Returns the removed policy, or `None` if there is no template-linked
+    /// policy with this id; static policies are not removed.
+    pub fn unlink(&mut self, id: &PolicyID) -> Option<Policy> {
+        match self.links.get(id) {
+            Some(policy) if !policy.is_static() => self.links.remove(id),
+            _ => None,
+        }
+    }
+
     /// Iterate over all policies
     pub fn policies(&self) -> impl Iterator<Item = &Policy> {
         self.links.values()
@@ -248,6 +259,21 @@ impl PolicySet {
         self.templates.is_empty() && self.links.is_empty()
     }
 
+    /// Estimate the memory retained by this `PolicySet`, by category. See
+    /// [`crate::memory`] for what the estimate counts.
+    pub fn approx_memory_usage(&self) -> PolicySetMemoryUsage {
+        let templates = self
+            .all_templates()
+            .map(|t| size_of::<Template>() + t.heap_size())
+            .sum::<usize>();
+        let links = self.policies().map(HeapSize::heap_size).sum::<usize>();
+        PolicySetMemoryUsage {
+            templates: table_size::<(PolicyID, Arc<Template>)>(self.templates.capacity())
+                + templates,
+            links: table_size::<(PolicyID, Policy)>(self.links.capacity()) + links,
+        }
+    }
+
     /// Lookup a template by policy id
     pub fn get_template(&self, id: &PolicyID) -> Option<Arc<Template>> {
         self.templates.get(id).map(Arc::clone)
@@ -328,6 +354,84 @@ mod test {
         };
     }
 
+    #[test]
+    fn unlink_removes_only_links() {
+        let mut pset = PolicySet::new();
+        let p1 = parser::parse_policy(Some("id".into()), "permit(principal,action,resource);")
+            .expect("Failed to parse");
+        pset.add_static(p1).expect("Failed to add!");
+        let template = parser::parse_policy_template(
+            Some("t".into()),
+            "permit(principal == ?principal, action, resource);",
+        )
+        .expect("Failed to parse");
+        pset.add_template(template).expect("Add failed");
+        let env: HashMap<SlotId, EntityUID> = [(
+            SlotId::principal(),
+            r#"Test::"test""#.parse().expect("Failed to parse"),
+        )]
+        .into_iter()
+        .collect();
+        pset.link(
+            PolicyID::from_string("t"),
+            PolicyID::from_string("link"),
+            env.clone(),
+        )
+        .expect("Failed to link");
+
+        assert!(pset.unlink(&PolicyID::from_string("id")).is_none());
+        assert!(pset.unlink(&PolicyID::from_string("t")).is_none());
+        let removed = pset
+            .unlink(&PolicyID::from_string("link"))
+            .expect("should remove the link");
+        assert_eq!(removed.id(), &PolicyID::from_string("link"));
+        assert!(pset.get(&PolicyID::from_string("link")).is_none());
+        assert_eq!(pset.policies().count(), 1);
+        // the id is free again
+        pset.link(
+            PolicyID::from_string("t"),
+            PolicyID::from_string("link"),
+            env,
+        )
+        .expect("Failed to link again");
+    }
+
+    #[test]
+    fn memory_usage() {
+        let mut pset = PolicySet::new();
+        assert_eq!(pset.approx_memory_usage().total(), 0);
+        let template = parser::parse_policy_template(
+            Some("t".into()),
+            r#"permit(principal == ?principal, action, resource) when { context.amount < 100 };"#,
+        )
+        .expect("Failed to parse");
+        pset.add_template(template).expect("Add failed");
+        let before = pset.approx_memory_usage();
+        assert!(before.templates > 0);
+
+        for i in 0..8 {
+            let env: HashMap<SlotId, EntityUID> = [(
+                SlotId::principal(),
+                format!(r#"Wallet::"0x{i:040}""#)
+                    .parse()
+                    .expect("Failed to parse"),
+            )]
+            .into_iter()
+            .collect();
+            pset.link(
+                PolicyID::from_string("t"),
+                PolicyID::from_string(format!("link{i}")),
+                env,
+            )
+            .expect("Failed to link");
+        }
+        let after = pset.approx_memory_usage();
+        // links share their template
+        assert_eq!(after.templates, before.templates);
+        assert!(after.links > before.links);
+        assert_eq!(after.total(), after.templates + after.links);
+    }
+
     /// This test focuses on `PolicySet::add()`, while other tests mostly use
     /// `PolicySet::add_static()` and `PolicySet::link()`.
     #[test]
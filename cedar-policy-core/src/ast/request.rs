@@ -15,11 +15,14 @@
  */
 
 use crate::ast::{BorrowedRestrictedExpr, EntityUID, ExprKind, RestrictedExpr};
+#[cfg(feature = "json")]
 use crate::entities::{ContextJsonParser, JsonDeserializationError, NullContextSchema};
+#[cfg(feature = "json")]
 use crate::extensions::Extensions;
 use serde::{Deserialize, Serialize};
 use smol_str::SmolStr;
 use std::sync::Arc;
+use thiserror::Error;
 
 use super::{Expr, Literal, PartialValue, Value, Var};
 
@@ -129,18 +132,23 @@ impl Request {
     }
 }
 
+impl std::fmt::Display for EntityUIDEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EntityUIDEntry::Concrete(euid) => write!(f, "{euid}"),
+            EntityUIDEntry::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
 impl std::fmt::Display for Request {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let display_euid = |maybe_euid: &EntityUIDEntry| match maybe_euid {
-            EntityUIDEntry::Concrete(euid) => format!("{euid}"),
-            EntityUIDEntry::Unknown => "unknown".to_string(),
-        };
         write!(
             f,
             "request with principal {}, action {}, resource {}, and context {}",
-            display_euid(&self.principal),
-            display_euid(&self.action),
-            display_euid(&self.resource),
+            self.principal,
+            self.action,
+            self.resource,
             match &self.context {
                 Some(x) => format!("{x}"),
                 None => "unknown".to_string(),
@@ -183,6 +191,7 @@ impl Context {
     /// references, extension values, etc.
     ///
     /// For schema-based parsing, use `ContextJsonParser`.
+    #[cfg(feature = "json")]
     pub fn from_json_str(json: &str) -> Result<Self, JsonDeserializationError> {
         ContextJsonParser::new(None::<&NullContextSchema>, Extensions::all_available())
             .from_json_str(json)
@@ -194,6 +203,7 @@ impl Context {
     /// references, extension values, etc.
     ///
     /// For schema-based parsing, use `ContextJsonParser`.
+    #[cfg(feature = "json")]
     pub fn from_json_value(json: serde_json::Value) -> Result<Self, JsonDeserializationError> {
         ContextJsonParser::new(None::<&NullContextSchema>, Extensions::all_available())
             .from_json_value(json)
@@ -205,6 +215,7 @@ impl Context {
     /// references, extension values, etc.
     ///
     /// For schema-based parsing, use `ContextJsonParser`.
+    #[cfg(feature = "json")]
     pub fn from_json_file(json: impl std::io::Read) -> Result<Self, JsonDeserializationError> {
         ContextJsonParser::new(None::<&NullContextSchema>, Extensions::all_available())
             .from_json_file(json)
@@ -219,6 +230,89 @@ impl Context {
             e => panic!("internal invariant violation: expected Expr::Record, got {e:?}"),
         }
     }
+
+    /// Merge the attributes of `overlay` into those of `base`, deciding with
+    /// `conflict` what happens to attributes set by both. Attributes keep the
+    /// order of `base`, followed by those only set by `overlay`.
+    pub fn merge(
+        base: &Context,
+        overlay: &Context,
+        conflict: ContextConflict,
+    ) -> Result<Context, ContextConflictError> {
+        let pairs = merge_pairs(
+            record_pairs(base.context.as_ref()),
+            record_pairs(overlay.context.as_ref()),
+            conflict,
+        )?;
+        // merging records of restricted expressions gives a record of
+        // restricted expressions
+        Ok(Self::from_expr(RestrictedExpr::new_unchecked(
+            Expr::record(pairs),
+        )))
+    }
+}
+
+/// What [`Context::merge`] does with an attribute set by both contexts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContextConflict {
+    /// Fail with a [`ContextConflictError`]
+    #[default]
+    Error,
+    /// Keep the value of the base context
+    KeepBase,
+    /// Take the value of the overlay context
+    Overlay,
+    /// Merge the values recursively if both are records, and otherwise take
+    /// the value of the overlay context
+    MergeRecords,
+}
+
+/// Error thrown by [`Context::merge`] when both contexts set an attribute and
+/// the conflict policy is [`ContextConflict::Error`]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("context attribute `{attr}` is set by both contexts")]
+pub struct ContextConflictError {
+    /// Attribute set by both contexts
+    pub attr: SmolStr,
+}
+
+/// The attributes of `expr`, if it is a record
+fn record_pairs(expr: &Expr) -> &[(SmolStr, Expr)] {
+    match expr.expr_kind() {
+        ExprKind::Record { pairs } => pairs,
+        _ => &[],
+    }
+}
+
+/// Merge the attributes `overlay` into `base`, following `conflict`
+fn merge_pairs(
+    base: &[(SmolStr, Expr)],
+    overlay: &[(SmolStr, Expr)],
+    conflict: ContextConflict,
+) -> Result<Vec<(SmolStr, Expr)>, ContextConflictError> {
+    let mut merged = base.to_vec();
+    for (attr, value) in overlay {
+        let Some((_, existing)) = merged.iter_mut().find(|(k, _)| k == attr) else {
+            merged.push((attr.clone(), value.clone()));
+            continue;
+        };
+        match conflict {
+            ContextConflict::Error => {
+                return Err(ContextConflictError { attr: attr.clone() });
+            }
+            ContextConflict::KeepBase => {}
+            ContextConflict::Overlay => *existing = value.clone(),
+            ContextConflict::MergeRecords => {
+                *existing = match (existing.expr_kind(), value.expr_kind()) {
+                    (ExprKind::Record { pairs: ours }, ExprKind::Record { pairs: theirs }) => {
+                        Expr::record(merge_pairs(ours, theirs, conflict)?)
+                    }
+                    _ => value.clone(),
+                };
+            }
+        }
+    }
+    Ok(merged)
 }
 
 impl AsRef<RestrictedExpr> for Context {
@@ -238,3 +332,41 @@ impl std::fmt::Display for Context {
         write!(f, "{}", self.context)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn context(record: &str) -> Context {
+        Context::from_expr(record.parse().expect("valid record"))
+    }
+
+    #[test]
+    fn merges_disjoint_contexts() {
+        let tx = context(r#"{to: "0xabc", value: 5}"#);
+        let score = context("{risk: 2}");
+        let merged = Context::merge(&tx, &score, ContextConflict::Error).expect("no conflict");
+        let attrs: Vec<&str> = merged.iter().map(|(k, _)| k).collect();
+        assert_eq!(attrs, ["to", "value", "risk"]);
+    }
+
+    #[test]
+    fn applies_conflict_policies() {
+        let base = context("{value: 5, sim: {gas: 21000, ok: true}}");
+        let overlay = context("{value: 7, sim: {gas: 30000, logs: 2}}");
+        assert_eq!(
+            Context::merge(&base, &overlay, ContextConflict::Error).map(|_| ()),
+            Err(ContextConflictError {
+                attr: "value".into()
+            })
+        );
+        let kept = Context::merge(&base, &overlay, ContextConflict::KeepBase).expect("merges");
+        assert_eq!(kept.to_string(), base.to_string());
+        let replaced = Context::merge(&base, &overlay, ContextConflict::Overlay).expect("merges");
+        assert_eq!(replaced.to_string(), overlay.to_string());
+        let merged =
+            Context::merge(&base, &overlay, ContextConflict::MergeRecords).expect("merges");
+        let expected = context("{value: 7, sim: {gas: 30000, ok: true, logs: 2}}");
+        assert_eq!(merged.to_string(), expected.to_string());
+    }
+}
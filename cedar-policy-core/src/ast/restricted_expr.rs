@@ -15,8 +15,11 @@
  */
 
 use super::{Expr, ExprKind, Literal, Name};
+#[cfg(feature = "json")]
 use crate::entities::JsonSerializationError;
+#[cfg(feature = "parser")]
 use crate::parser;
+#[cfg(feature = "parser")]
 use crate::parser::err::ParseErrors;
 use serde::{Deserialize, Serialize};
 use smol_str::SmolStr;
@@ -118,6 +121,7 @@ impl RestrictedExpr {
     }
 }
 
+#[cfg(feature = "parser")]
 impl std::str::FromStr for RestrictedExpr {
     type Err = RestrictedExprError;
 
@@ -167,6 +171,7 @@ impl<'a> BorrowedRestrictedExpr<'a> {
     /// Write a BorrowedRestrictedExpr in "natural JSON" format.
     ///
     /// Used to output the context as a map from Strings to JSON Values
+    #[cfg(feature = "json")]
     pub fn to_natural_json(self) -> Result<serde_json::Value, JsonSerializationError> {
         Ok(serde_json::to_value(
             crate::entities::JSONValue::from_expr(self)?,
@@ -338,6 +343,7 @@ pub enum RestrictedExprError {
     },
 
     /// Failed to parse the expression that the restricted expression wraps.
+    #[cfg(feature = "parser")]
     #[error("failed to parse restricted expression: {0}")]
     Parse(#[from] ParseErrors),
 }
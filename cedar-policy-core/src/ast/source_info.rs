@@ -0,0 +1,107 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+/// Describes where in policy source code a node in the CST or expression AST
+/// occurs.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct SourceInfo(pub Range<usize>);
+
+impl SourceInfo {
+    /// Construct a new [`SourceInfo`] from a start offset and a length, in
+    /// bytes.
+    pub const fn new(start: usize, len: usize) -> Self {
+        SourceInfo(start..(start + len))
+    }
+
+    /// Construct a new zero-length [`SourceInfo`] pointing to a specific
+    /// offset.
+    pub const fn from_offset(offset: usize) -> Self {
+        SourceInfo(offset..offset)
+    }
+
+    /// Get the start of range, in bytes.
+    pub const fn range_start(&self) -> usize {
+        self.0.start
+    }
+
+    /// Get the end of range, in bytes.
+    pub const fn range_end(&self) -> usize {
+        self.0.end
+    }
+
+    /// Get the length of the source range, in bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the end of the range is before the start.
+    pub const fn len(&self) -> usize {
+        assert!(self.range_start() <= self.range_end());
+        self.range_end() - self.range_start()
+    }
+
+    /// Tests whether this [`SourceInfo`] range is a zero-length offset.
+    pub const fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Display for SourceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            write!(f, "{}", self.range_start())
+        } else {
+            write!(f, "[{}, {})", self.range_start(), self.range_end())
+        }
+    }
+}
+
+impl Ord for SourceInfo {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.range_start()
+            .cmp(&other.range_start())
+            .then_with(|| self.len().cmp(&other.len()))
+    }
+}
+
+impl PartialOrd for SourceInfo {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl From<usize> for SourceInfo {
+    fn from(offset: usize) -> Self {
+        SourceInfo::from_offset(offset)
+    }
+}
+
+impl From<Range<usize>> for SourceInfo {
+    fn from(range: Range<usize>) -> Self {
+        SourceInfo(range)
+    }
+}
+
+impl From<SourceInfo> for Range<usize> {
+    fn from(info: SourceInfo) -> Self {
+        info.0
+    }
+}
@@ -22,7 +22,10 @@
 
 use crate::ast::*;
 use crate::entities::Entities;
-use crate::evaluator::{EvaluationError, Evaluator};
+use crate::evaluator::{
+    EntityOverlay, EvaluationError, EvaluationErrorKind, EvaluationRecord, Evaluator,
+    MissingAttributeMode,
+};
 use crate::extensions::Extensions;
 use itertools::Either;
 use serde::{Deserialize, Serialize};
@@ -32,12 +35,37 @@ use std::iter::once;
 mod err;
 pub use err::AuthorizationError;
 
+/// With the `rayon` feature, the number of policies from which a policy set is
+/// evaluated in parallel
+#[cfg(feature = "rayon")]
+pub const PARALLEL_THRESHOLD: usize = 64;
+
 /// Authorizer
 pub struct Authorizer {
     /// Cedar `Extension`s which will be used during requests to this `Authorizer`
     extensions: Extensions<'static>,
     /// Error-handling behavior of this `Authorizer`
     error_handling: ErrorHandling,
+    /// How accesses of missing attributes are evaluated
+    missing_attributes: MissingAttributeMode,
+    /// What is done with policies whose extension functions fail
+    extension_failures: ExtensionFailureMode,
+}
+
+/// What an `Authorizer` does with a policy whose evaluation fails in an
+/// extension function, such as one backed by an RPC provider which timed out,
+/// so that one flaky provider need not poison every decision
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExtensionFailureMode {
+    /// The policy errors, like on any other evaluation error
+    #[default]
+    PolicyError,
+    /// The whole request is denied, with the policy as the reason
+    FailClosed,
+    /// The policy is skipped, and the failure is recorded as a warning rather
+    /// than an error
+    SkipWithWarning,
 }
 
 /// Describes the possible Cedar error-handling modes. Note that modes other than
@@ -72,6 +100,15 @@ impl ResponseKind {
             ResponseKind::Partial(_) => None,
         }
     }
+
+    /// This response, with `warnings` in its diagnostics
+    fn with_warnings(mut self, warnings: Vec<AuthorizationError>) -> Self {
+        match &mut self {
+            ResponseKind::FullyEvaluated(a) => a.diagnostics.warnings = warnings,
+            ResponseKind::Partial(p) => p.diagnostics.warnings = warnings,
+        }
+        self
+    }
 }
 
 impl Default for ErrorHandling {
@@ -86,20 +123,107 @@ impl Authorizer {
         Self {
             extensions: Extensions::all_available(), // set at compile time
             error_handling: Default::default(),
+            missing_attributes: MissingAttributeMode::default(),
+            extension_failures: ExtensionFailureMode::default(),
+        }
+    }
+
+    /// Create a new `Authorizer` which evaluates policies with `extensions`,
+    /// such as the available extensions with some of their functions
+    /// overridden (see [`Extensions::with_overrides`])
+    pub fn with_extensions(extensions: Extensions<'static>) -> Self {
+        Self {
+            extensions,
+            error_handling: Default::default(),
+            missing_attributes: MissingAttributeMode::default(),
+            extension_failures: ExtensionFailureMode::default(),
         }
     }
 
+    /// Evaluate accesses of missing attributes as `mode` says, rather than as
+    /// errors
+    #[must_use]
+    pub fn with_missing_attributes(mut self, mode: MissingAttributeMode) -> Self {
+        self.missing_attributes = mode;
+        self
+    }
+
+    /// Handle the policies whose extension functions fail as `mode` says,
+    /// rather than as any other policy which errors
+    #[must_use]
+    pub fn with_extension_failures(mut self, mode: ExtensionFailureMode) -> Self {
+        self.extension_failures = mode;
+        self
+    }
+
+    /// How accesses of missing attributes are evaluated
+    pub fn missing_attributes(&self) -> MissingAttributeMode {
+        self.missing_attributes
+    }
+
+    /// What is done with policies whose extension functions fail
+    pub fn extension_failures(&self) -> ExtensionFailureMode {
+        self.extension_failures
+    }
+
     /// Returns an authorization response for `q` with respect to the given `Slice`.
     ///
     /// The language spec and Dafny model give a precise definition of how this is
     /// computed.
     pub fn is_authorized(&self, q: &Request, pset: &PolicySet, entities: &Entities) -> Response {
-        match self.is_authorized_core(q, pset, entities) {
+        self.is_authorized_with(q, pset, entities, None, None)
+    }
+
+    /// Like [`Authorizer::is_authorized`], but records the entities and
+    /// extension function calls consulted in `record`
+    pub fn is_authorized_recording(
+        &self,
+        q: &Request,
+        pset: &PolicySet,
+        entities: &Entities,
+        record: &EvaluationRecord,
+    ) -> Response {
+        self.is_authorized_with(q, pset, entities, Some(record), None)
+    }
+
+    /// Like [`Authorizer::is_authorized`], but reads the attributes and tags
+    /// set in `overlay` in preference to those of `entities`
+    pub fn is_authorized_overlaid(
+        &self,
+        q: &Request,
+        pset: &PolicySet,
+        entities: &Entities,
+        overlay: &EntityOverlay,
+    ) -> Response {
+        self.is_authorized_with(q, pset, entities, None, Some(overlay))
+    }
+
+    fn is_authorized_with(
+        &self,
+        q: &Request,
+        pset: &PolicySet,
+        entities: &Entities,
+        record: Option<&EvaluationRecord>,
+        overlay: Option<&EntityOverlay>,
+    ) -> Response {
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "is_authorized",
+            principal = %q.principal(),
+            action = %q.action(),
+            resource = %q.resource(),
+            decision = tracing::field::Empty,
+            reasons = tracing::field::Empty,
+            errors = tracing::field::Empty,
+        )
+        .entered();
+        let response = match self.is_authorized_core_with(q, pset, entities, record, overlay) {
             ResponseKind::FullyEvaluated(response) => response,
             ResponseKind::Partial(partial) => {
                 // If we get a residual, we have to treat every residual policy as an error, and obey the error semantics.
                 // This can result in an Accept in one case:
                 // `error_handling` is `SkipOnerror`, no forbids evaluated to a concrete response, and some permits evaluated to `true`
+                let warnings = partial.diagnostics.warnings;
                 let mut errors = partial.diagnostics.errors;
                 errors.extend(partial.residuals.policies().map(|p| {
                     AuthorizationError::PolicyEvaluationError {
@@ -110,7 +234,7 @@ impl Authorizer {
 
                 let idset = partial.residuals.policies().map(|p| p.id().clone());
 
-                match self.error_handling {
+                let mut response = match self.error_handling {
                     ErrorHandling::Deny => Response::new(
                         Decision::Deny,
                         idset.chain(partial.diagnostics.reason).collect(),
@@ -149,9 +273,25 @@ impl Authorizer {
                             )
                         }
                     }
-                }
+                };
+                response.diagnostics.warnings = warnings;
+                response
             }
+        };
+        #[cfg(feature = "tracing")]
+        {
+            let mut reasons: Vec<String> = response
+                .diagnostics
+                .reason
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            reasons.sort();
+            span.record("decision", tracing::field::debug(response.decision));
+            span.record("reasons", reasons.join(", "));
+            span.record("errors", response.diagnostics.errors.len());
         }
+        response
     }
 
     /// Returns an authorization response for `q` with respect to the given `Slice`.
@@ -164,9 +304,30 @@ impl Authorizer {
         q: &Request,
         pset: &PolicySet,
         entities: &Entities,
+    ) -> ResponseKind {
+        self.is_authorized_core_with(q, pset, entities, None, None)
+    }
+
+    fn is_authorized_core_with(
+        &self,
+        q: &Request,
+        pset: &PolicySet,
+        entities: &Entities,
+        record: Option<&EvaluationRecord>,
+        overlay: Option<&EntityOverlay>,
     ) -> ResponseKind {
         let eval = match Evaluator::new(q, entities, &self.extensions) {
-            Ok(eval) => eval,
+            Ok(eval) => {
+                let eval = eval.with_missing_attributes(self.missing_attributes);
+                let eval = match record {
+                    Some(record) => eval.recording(record),
+                    None => eval,
+                };
+                match overlay {
+                    Some(overlay) => eval.with_overlay(overlay),
+                    None => eval,
+                }
+            }
             Err(e) => {
                 return ResponseKind::FullyEvaluated(Response::new(
                     Decision::Deny,
@@ -178,13 +339,19 @@ impl Authorizer {
 
         let results = self.evaluate_policies(pset, eval);
 
+        let to_authorization_error = |(pid, err)| AuthorizationError::PolicyEvaluationError {
+            id: pid,
+            error: err,
+        };
         let errors = results
             .errors
             .into_iter()
-            .map(|(pid, err)| AuthorizationError::PolicyEvaluationError {
-                id: pid,
-                error: err,
-            })
+            .map(to_authorization_error)
+            .collect();
+        let warnings = results
+            .warnings
+            .into_iter()
+            .map(to_authorization_error)
             .collect();
 
         if !results.global_deny_policies.is_empty() {
@@ -192,7 +359,8 @@ impl Authorizer {
                 Decision::Deny,
                 results.global_deny_policies,
                 errors,
-            ));
+            ))
+            .with_warnings(warnings);
         }
         // Semantics ask for the set C_I^+ of all satisfied Permit policies
         // which override all satisfied Forbid policies. We call this set
@@ -211,7 +379,7 @@ impl Authorizer {
             })
             .peekable();
 
-        match (
+        let response = match (
             satisfied_permits.peek().is_some(),
             !results.permit_residuals.is_empty(),
             !results.forbid_residuals.is_empty(),
@@ -279,7 +447,8 @@ impl Authorizer {
                     ))
                 }
             }
-        }
+        };
+        response.with_warnings(warnings)
     }
 
     fn evaluate_policies<'a>(
@@ -290,8 +459,32 @@ impl Authorizer {
         let mut results = EvaluationResults::default();
         let mut satisfied_policies = vec![];
 
-        for p in pset.policies() {
-            match eval.partial_evaluate(p) {
+        // policies are evaluated in the order of their ids, rather than that
+        // of the map they are stored in, so that the errors and residuals are
+        // reported in an order which is the same from one run to the next. In
+        // parallel, the results are still collected in that order
+        let mut policies = pset.policies().collect::<Vec<_>>();
+        policies.sort_unstable_by(|p1, p2| p1.id().cmp(p2.id()));
+        #[cfg(feature = "rayon")]
+        let evaluated = if policies.len() >= PARALLEL_THRESHOLD {
+            use rayon::prelude::*;
+            policies
+                .into_par_iter()
+                .map(|p| (p, Self::evaluate_policy(&eval, p)))
+                .collect::<Vec<_>>()
+        } else {
+            policies
+                .into_iter()
+                .map(|p| (p, Self::evaluate_policy(&eval, p)))
+                .collect()
+        };
+        #[cfg(not(feature = "rayon"))]
+        let evaluated = policies
+            .into_iter()
+            .map(|p| (p, Self::evaluate_policy(&eval, p)));
+
+        for (p, result) in evaluated {
+            match result {
                 Ok(Either::Left(response)) => {
                     if response {
                         satisfied_policies.push(p)
@@ -309,6 +502,19 @@ impl Authorizer {
                         p.id().clone(),
                     )),
                 },
+                Err(e)
+                    if matches!(
+                        e.error_kind(),
+                        EvaluationErrorKind::FailedExtensionFunctionApplication { .. }
+                    ) && self.extension_failures != ExtensionFailureMode::PolicyError =>
+                {
+                    if self.extension_failures == ExtensionFailureMode::FailClosed {
+                        results.global_deny_policies.insert(p.id().clone());
+                        results.errors.push((p.id().clone(), e));
+                    } else {
+                        results.warnings.push((p.id().clone(), e));
+                    }
+                }
                 Err(e) => {
                     results.errors.push((p.id().clone(), e));
                     let satisfied = match self.error_handling {
@@ -339,6 +545,37 @@ impl Authorizer {
         results
     }
 
+    /// Partially evaluate `p`
+    fn evaluate_policy(
+        eval: &Evaluator<'_>,
+        p: &Policy,
+    ) -> Result<Either<bool, Expr>, EvaluationError> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            "evaluate_policy",
+            policy_id = %p.id(),
+            effect = %p.effect(),
+            result = tracing::field::Empty,
+            error = tracing::field::Empty,
+        )
+        .entered();
+        let result = eval.partial_evaluate(p);
+        #[cfg(feature = "tracing")]
+        match &result {
+            Ok(Either::Left(response)) => {
+                span.record("result", response);
+            }
+            Ok(Either::Right(_)) => {
+                span.record("result", "residual");
+            }
+            Err(e) => {
+                span.record("result", "error");
+                span.record("error", tracing::field::display(e));
+            }
+        }
+        result
+    }
+
     /// Private helper function which determines if policy `p1` overrides policy
     /// `p2`.
     ///
@@ -375,6 +612,7 @@ struct EvaluationResults<'a> {
     satisfied_forbids: Vec<&'a Policy>,
     global_deny_policies: HashSet<PolicyID>,
     errors: Vec<(PolicyID, EvaluationError)>,
+    warnings: Vec<(PolicyID, EvaluationError)>,
     permit_residuals: Vec<Policy>,
     forbid_residuals: Vec<Policy>,
 }
@@ -793,6 +1031,134 @@ mod test {
         let r = a.is_authorized_core(&q, &pset, &es);
         assert_eq!(r.decision(), Some(Decision::Deny));
     }
+    #[test]
+    fn reports_errors_in_the_order_of_policy_ids() {
+        let a = Authorizer::new();
+        let q = Request::new(
+            EntityUID::with_eid("p"),
+            EntityUID::with_eid("a"),
+            EntityUID::with_eid("r"),
+            Context::empty(),
+        );
+        let mut pset = PolicySet::new();
+        for id in ["delta", "alpha", "charlie", "bravo"] {
+            let src = "permit(principal, action, resource) when { context.bad };";
+            pset.add_static(parser::parse_policy(Some(id.into()), src).unwrap())
+                .unwrap();
+        }
+        let ans = a.is_authorized(&q, &pset, &Entities::new());
+        let errors = ans
+            .diagnostics
+            .errors
+            .iter()
+            .filter_map(|error| match error {
+                AuthorizationError::PolicyEvaluationError { id, .. } => Some(id.to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(errors, vec!["alpha", "bravo", "charlie", "delta"]);
+    }
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn evaluates_large_sets_in_parallel() {
+        let a = Authorizer::new();
+        let q = Request::new(
+            EntityUID::with_eid("p"),
+            EntityUID::with_eid("a"),
+            EntityUID::with_eid("r"),
+            Context::empty(),
+        );
+        let mut pset = PolicySet::new();
+        for i in 0..PARALLEL_THRESHOLD * 2 {
+            let src = match i % 10 {
+                3 => format!("permit(principal, action, resource) when {{ context.bad == {i} }};"),
+                7 => "forbid(principal, action, resource) unless { 1 == 1 };".to_string(),
+                _ => "permit(principal, action, resource);".to_string(),
+            };
+            pset.add_static(parser::parse_policy(Some(i.to_string()), &src).unwrap())
+                .unwrap();
+        }
+        let ans = a.is_authorized(&q, &pset, &Entities::new());
+        assert_eq!(ans.decision, Decision::Allow);
+        assert_eq!(
+            ans.diagnostics.reason.len(),
+            PARALLEL_THRESHOLD * 2 * 8 / 10
+        );
+        // the errors are in the order of the policy ids
+        let mut expected = (0..PARALLEL_THRESHOLD * 2)
+            .filter(|i| i % 10 == 3)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>();
+        expected.sort();
+        let expected = expected
+            .into_iter()
+            .map(PolicyID::from_string)
+            .collect::<Vec<_>>();
+        let errors = ans
+            .diagnostics
+            .errors
+            .iter()
+            .filter_map(|error| match error {
+                AuthorizationError::PolicyEvaluationError { id, .. } => Some(id.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(errors, expected);
+    }
+    #[test]
+    fn extension_failure_modes() {
+        let q = Request::new(
+            EntityUID::with_eid("p"),
+            EntityUID::with_eid("a"),
+            EntityUID::with_eid("r"),
+            Context::empty(),
+        );
+        let mut pset = PolicySet::new();
+        let srcs = [
+            r#"permit(principal, action, resource);"#,
+            r#"forbid(principal, action, resource) when { decimal("oops") == decimal("1.0") };"#,
+            r#"permit(principal, action, resource) when { context.bad == 2 };"#,
+        ];
+        for (i, src) in srcs.into_iter().enumerate() {
+            pset.add_static(parser::parse_policy(Some(i.to_string()), src).unwrap())
+                .unwrap();
+        }
+        let entities = Entities::new();
+        let ids = |errors: &[AuthorizationError]| {
+            let mut ids = errors
+                .iter()
+                .filter_map(|error| match error {
+                    AuthorizationError::PolicyEvaluationError { id, .. } => Some(id.to_string()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+
+        let ans = Authorizer::new().is_authorized(&q, &pset, &entities);
+        assert_eq!(ans.decision, Decision::Allow);
+        assert_eq!(ids(&ans.diagnostics.errors), vec!["1", "2"]);
+        assert!(ans.diagnostics.warnings.is_empty());
+
+        let ans = Authorizer::new()
+            .with_extension_failures(ExtensionFailureMode::FailClosed)
+            .is_authorized(&q, &pset, &entities);
+        assert_eq!(ans.decision, Decision::Deny);
+        assert_eq!(
+            ans.diagnostics.reason,
+            HashSet::from([PolicyID::from_string("1")])
+        );
+        assert_eq!(ids(&ans.diagnostics.errors), vec!["1", "2"]);
+
+        // only the failure of the extension function is a warning
+        let ans = Authorizer::new()
+            .with_extension_failures(ExtensionFailureMode::SkipWithWarning)
+            .is_authorized(&q, &pset, &entities);
+        assert_eq!(ans.decision, Decision::Allow);
+        assert_eq!(ids(&ans.diagnostics.errors), vec!["2"]);
+        assert_eq!(ids(&ans.diagnostics.warnings), vec!["1"]);
+    }
 }
 // by default, Coverlay does not track coverage for lines after a line
 // containing #[cfg(test)].
@@ -827,7 +1193,11 @@ impl PartialResponse {
     ) -> Self {
         PartialResponse {
             residuals: pset,
-            diagnostics: Diagnostics { reason, errors },
+            diagnostics: Diagnostics {
+                reason,
+                errors,
+                warnings: Vec::new(),
+            },
         }
     }
 }
@@ -840,6 +1210,9 @@ pub struct Diagnostics {
     pub reason: HashSet<PolicyID>,
     /// List of errors that occurred
     pub errors: Vec<AuthorizationError>,
+    /// List of failures of extension functions in policies which were skipped
+    /// (see [`ExtensionFailureMode::SkipWithWarning`])
+    pub warnings: Vec<AuthorizationError>,
 }
 
 impl Response {
@@ -851,7 +1224,11 @@ impl Response {
     ) -> Self {
         Response {
             decision,
-            diagnostics: Diagnostics { reason, errors },
+            diagnostics: Diagnostics {
+                reason,
+                errors,
+                warnings: Vec::new(),
+            },
         }
     }
 }
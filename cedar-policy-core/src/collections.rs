@@ -0,0 +1,73 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! This module contains the hash maps and sets which hold policy sets, and
+//! the attributes, tags and ancestors of entities.
+//!
+//! They are the maps and sets of `std`, unless the `fast-hash` feature is
+//! enabled, in which case they are those of `hashbrown`, hashed with `ahash`.
+//! Those are faster to build and look up, but whether `ahash` seeds its hasher
+//! randomly, or with fixed keys, depends on the target and on the features
+//! other crates enable for it, so the feature is opt-in. Iteration order is
+//! unspecified in either case.
+//!
+//! Public constructors still take the maps and sets of `std`, and convert them
+//! with [`from_std_map`] and [`from_std_set`]. Slot environments
+//! ([`crate::ast::SlotEnv`]) are always maps of `std`: they bind at most two
+//! slots, so they gain nothing from a faster hasher.
+
+#[cfg(feature = "fast-hash")]
+pub use hashbrown::{hash_map, HashMap, HashSet};
+#[cfg(not(feature = "fast-hash"))]
+pub use std::collections::{hash_map, HashMap, HashSet};
+
+/// Convert a map of `std` into a [`HashMap`]. Without the `fast-hash` feature,
+/// the map is returned as is.
+pub fn from_std_map<K: Eq + std::hash::Hash, V>(
+    map: std::collections::HashMap<K, V>,
+) -> HashMap<K, V> {
+    #[cfg(feature = "fast-hash")]
+    return map.into_iter().collect();
+    #[cfg(not(feature = "fast-hash"))]
+    return map;
+}
+
+/// Convert a set of `std` into a [`HashSet`]. Without the `fast-hash` feature,
+/// the set is returned as is.
+pub fn from_std_set<T: Eq + std::hash::Hash>(set: std::collections::HashSet<T>) -> HashSet<T> {
+    #[cfg(feature = "fast-hash")]
+    return set.into_iter().collect();
+    #[cfg(not(feature = "fast-hash"))]
+    return set;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn converts_std_collections() {
+        let map = std::collections::HashMap::from([("a", 1), ("b", 2)]);
+        let converted = from_std_map(map);
+        assert_eq!(converted.len(), 2);
+        assert_eq!(converted.get("b"), Some(&2));
+
+        let set = std::collections::HashSet::from(["a", "b", "c"]);
+        let converted = from_std_set(set);
+        assert_eq!(converted.len(), 3);
+        assert!(converted.contains("c"));
+    }
+}
@@ -19,7 +19,10 @@
 use crate::ast::*;
 use crate::evaluator::{EvaluationError, RestrictedEvaluator};
 use crate::extensions::Extensions;
-use crate::transitive_closure::{compute_tc, enforce_tc_and_dag};
+use crate::memory::{table_size, EntitiesMemoryUsage, HeapSize};
+use crate::transitive_closure::{
+    compute_tc, enforce_dag, enforce_tc_and_dag, has_path_to, reachable_from,
+};
 use std::borrow::Cow;
 use std::collections::{hash_map, HashMap};
 use std::fmt::Write;
@@ -31,7 +34,15 @@ mod err;
 pub use err::*;
 mod json;
 pub use json::*;
+#[cfg(feature = "csv")]
+mod csv;
+#[cfg(feature = "csv")]
+pub use self::csv::*;
+#[cfg(feature = "snapshot")]
+mod snapshot;
 use smol_str::SmolStr;
+#[cfg(feature = "snapshot")]
+pub use snapshot::*;
 
 /// Represents an entity hierarchy, and allows looking up `Entity` objects by
 /// UID.
@@ -48,13 +59,23 @@ pub struct Entities {
     /// lists instead.
     ///
     /// Important internal invariant: for any `Entities` object that exists, the
-    /// the `ancestor` relation is transitively closed.
+    /// the `ancestor` relation is transitively closed, unless `lazy_tc` is set.
     #[serde_as(as = "Vec<(_, _)>")]
     entities: HashMap<EntityUID, Entity>,
 
+    /// If set, the `ancestor` relation of the entities may contain only
+    /// direct parent edges, and ancestors are computed on demand by following
+    /// those edges. See [`TCComputation::ComputeLazily`].
+    #[serde(default)]
+    lazy_tc: bool,
+
     #[serde(skip)]
     evaluated_entities: Option<EvaluatedEntities>,
 
+    /// Which chain state the entities were read at, if known
+    #[serde(default)]
+    snapshot_info: Option<SnapshotInfo>,
+
     /// The mode flag determines whether this store functions as a partial store or
     /// as a fully concrete store.
     /// Mode::Concrete means that the store is fully concrete, and failed dereferences are an error.
@@ -65,13 +86,46 @@ pub struct Entities {
     mode: Mode,
 }
 
+/// Which chain state a set of entities was read at, from the optional header
+/// of the entities JSON. Instead of a list of entities, the JSON can be an
+/// object of the list, as `entities`, and of this header, as `snapshot`:
+/// ```json
+/// {
+///     "snapshot": {
+///         "chainId": 1,
+///         "blockNumber": 19000000,
+///         "blockHash": "0x…",
+///         "fetchedAtMs": 1700000000000
+///     },
+///     "entities": []
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotInfo {
+    /// The id of the chain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+    /// The number of the block the entities were read at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    /// The hash of the block the entities were read at, in hex
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_hash: Option<String>,
+    /// When the entities were fetched, in milliseconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fetched_at_ms: Option<u64>,
+}
+
 impl Entities {
     /// Create a fresh `Entities` with no entities
     pub fn new() -> Self {
         Self {
             entities: HashMap::new(),
+            lazy_tc: false,
             mode: Mode::default(),
             evaluated_entities: None,
+            snapshot_info: None,
         }
     }
 
@@ -82,11 +136,25 @@ impl Entities {
     pub fn partial(self) -> Self {
         Self {
             entities: self.entities,
+            lazy_tc: self.lazy_tc,
             mode: Mode::Partial,
             evaluated_entities: self.evaluated_entities,
+            snapshot_info: self.snapshot_info,
         }
     }
 
+    /// Which chain state the entities were read at, from the header of the
+    /// entities JSON they were parsed from, if it had one
+    pub fn snapshot_info(&self) -> Option<&SnapshotInfo> {
+        self.snapshot_info.as_ref()
+    }
+
+    /// Set which chain state the entities were read at
+    pub fn with_snapshot_info(mut self, snapshot_info: Option<SnapshotInfo>) -> Self {
+        self.snapshot_info = snapshot_info;
+        self
+    }
+
     /// Get the `Entity` with the given UID, if any
     pub fn entity(&self, uid: &EntityUID) -> Dereference<'_, Entity> {
         match self.entities.get(uid) {
@@ -104,6 +172,69 @@ impl Entities {
         self.entities.values()
     }
 
+    /// Estimate the memory retained by this `Entities`, by category. See
+    /// [`crate::memory`] for what the estimate counts.
+    pub fn approx_memory_usage(&self) -> EntitiesMemoryUsage {
+        let mut usage = EntitiesMemoryUsage {
+            entities: table_size::<(EntityUID, Entity)>(self.entities.capacity()),
+            ..Default::default()
+        };
+        for (uid, entity) in &self.entities {
+            let (attrs, tags) = (entity.attrs_map(), entity.tags_map());
+            // the UID is kept both as the key and in the entity
+            usage.entities += 2 * uid.heap_size()
+                + table_size::<(SmolStr, RestrictedExpr)>(attrs.capacity())
+                + table_size::<(SmolStr, RestrictedExpr)>(tags.capacity());
+            for (k, v) in attrs.iter().chain(tags.iter()) {
+                usage.entities += k.heap_size();
+                usage.attribute_values += v.heap_size();
+            }
+            let ancestors = entity.ancestors_set();
+            usage.ancestors += table_size::<EntityUID>(ancestors.capacity())
+                + ancestors.iter().map(HeapSize::heap_size).sum::<usize>();
+        }
+        if let Some(evaluated) = &self.evaluated_entities {
+            usage.attribute_values +=
+                table_size::<(EntityUID, HashMap<SmolStr, PartialValue>)>(evaluated.capacity());
+            for (uid, attrs) in evaluated {
+                usage.attribute_values += uid.heap_size()
+                    + table_size::<(SmolStr, PartialValue)>(attrs.capacity())
+                    + attrs
+                        .iter()
+                        .map(|(k, v)| k.heap_size() + v.heap_size())
+                        .sum::<usize>();
+            }
+        }
+        usage
+    }
+
+    /// Is `entity` a descendant of the entity with UID `ancestor` in this
+    /// hierarchy?
+    ///
+    /// Always use this rather than [`Entity::is_descendant_of`] when `entity`
+    /// came from an `Entities`, since with
+    /// [`TCComputation::ComputeLazily`] the entity itself only records its
+    /// direct parents.
+    pub fn is_descendant_of(&self, entity: &Entity, ancestor: &EntityUID) -> bool {
+        if self.lazy_tc {
+            has_path_to(&self.entities, entity, ancestor)
+        } else {
+            entity.is_descendant_of(ancestor)
+        }
+    }
+
+    /// Iterate over all the ancestors of `entity` in this hierarchy.
+    ///
+    /// As with [`Entities::is_descendant_of`], prefer this to
+    /// [`Entity::ancestors`] when `entity` came from an `Entities`.
+    pub fn ancestors_of<'a>(&'a self, entity: &'a Entity) -> impl Iterator<Item = &'a EntityUID> {
+        if self.lazy_tc {
+            either::Either::Left(reachable_from(&self.entities, entity).into_iter())
+        } else {
+            either::Either::Right(entity.ancestors())
+        }
+    }
+
     /// Adds the [`crate::ast::Entity`]s in the iterator to this [`Entities`].
     /// Fails if the passed iterator contains any duplicate entities with this structure,
     /// or if any error is encountered in the transitive closure computation.
@@ -128,13 +259,21 @@ impl Entities {
             TCComputation::EnforceAlreadyComputed => {
                 enforce_tc_and_dag(&self.entities).map_err(Box::new)?
             }
-            TCComputation::ComputeNow => compute_tc(&mut self.entities, true).map_err(Box::new)?,
+            TCComputation::ComputeNow => {
+                compute_tc(&mut self.entities, true).map_err(Box::new)?;
+                self.lazy_tc = false;
+            }
+            TCComputation::ComputeLazily => {
+                enforce_dag(&self.entities).map_err(Box::new)?;
+                self.lazy_tc = true;
+            }
         };
         self.evaluated_entities = None;
         Ok(self)
     }
 
     /// Create an `Entities` object with the given entities.
+    /// Fails if the passed iterator contains the same UID more than once.
     ///
     /// If you pass `TCComputation::AssumeAlreadyComputed`, then the caller is
     /// responsible for ensuring that TC and DAG hold before calling this method.
@@ -142,7 +281,45 @@ impl Entities {
         entities: impl IntoIterator<Item = Entity>,
         tc_computation: TCComputation,
     ) -> Result<Self> {
-        let mut entity_map = entities.into_iter().map(|e| (e.uid(), e)).collect();
+        Self::from_entities_with_duplicates(entities, tc_computation, DuplicateUidStrategy::Error)
+    }
+
+    /// Create an `Entities` object with the given entities, resolving any UID
+    /// that appears more than once as specified by `duplicates`.
+    ///
+    /// If you pass `TCComputation::AssumeAlreadyComputed`, then the caller is
+    /// responsible for ensuring that TC and DAG hold before calling this method.
+    pub fn from_entities_with_duplicates(
+        entities: impl IntoIterator<Item = Entity>,
+        tc_computation: TCComputation,
+        duplicates: DuplicateUidStrategy,
+    ) -> Result<Self> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            "build_entities",
+            tc_computation = ?tc_computation,
+            entities = tracing::field::Empty,
+        )
+        .entered();
+        let mut entity_map: HashMap<EntityUID, Entity> = HashMap::new();
+        for entity in entities {
+            match entity_map.entry(entity.uid()) {
+                hash_map::Entry::Vacant(vacant_entry) => {
+                    vacant_entry.insert(entity);
+                }
+                hash_map::Entry::Occupied(mut occupied_entry) => match duplicates {
+                    DuplicateUidStrategy::Error => {
+                        return Err(EntitiesError::Duplicate(entity.uid()))
+                    }
+                    DuplicateUidStrategy::FirstWins => (),
+                    DuplicateUidStrategy::MergeAttributes => {
+                        merge_entity(occupied_entry.get_mut(), entity)?
+                    }
+                },
+            }
+        }
+        #[cfg(feature = "tracing")]
+        span.record("entities", entity_map.len());
         match tc_computation {
             TCComputation::AssumeAlreadyComputed => {}
             TCComputation::EnforceAlreadyComputed => {
@@ -151,11 +328,16 @@ impl Entities {
             TCComputation::ComputeNow => {
                 compute_tc(&mut entity_map, true).map_err(Box::new)?;
             }
+            TCComputation::ComputeLazily => {
+                enforce_dag(&entity_map).map_err(Box::new)?;
+            }
         }
         Ok(Self {
             entities: entity_map,
+            lazy_tc: tc_computation == TCComputation::ComputeLazily,
             mode: Mode::default(),
             evaluated_entities: None,
+            snapshot_info: None,
         })
     }
 
@@ -165,11 +347,18 @@ impl Entities {
     /// The returned JSON value will be parse-able even with no `Schema`.
     ///
     /// To parse an `Entities` object from a JSON value, use `EntityJsonParser`.
+    #[cfg(feature = "json")]
     pub fn to_json_value(&self) -> Result<serde_json::Value> {
         let ejsons: Vec<EntityJSON> = self.to_ejsons()?;
-        serde_json::to_value(ejsons)
-            .map_err(JsonSerializationError::from)
-            .map_err(Into::into)
+        match &self.snapshot_info {
+            Some(snapshot) => serde_json::to_value(WithHeader {
+                snapshot,
+                entities: &ejsons,
+            }),
+            None => serde_json::to_value(ejsons),
+        }
+        .map_err(JsonSerializationError::from)
+        .map_err(Into::into)
     }
 
     /// Dump an `Entities` object into an entities JSON file.
@@ -179,13 +368,25 @@ impl Entities {
     ///
     /// To read an `Entities` object from an entities JSON file, use
     /// `EntityJsonParser`.
+    #[cfg(feature = "json")]
     pub fn write_to_json(&self, f: impl std::io::Write) -> Result<()> {
         let ejsons: Vec<EntityJSON> = self.to_ejsons()?;
-        serde_json::to_writer_pretty(f, &ejsons).map_err(JsonSerializationError::from)?;
+        match &self.snapshot_info {
+            Some(snapshot) => serde_json::to_writer_pretty(
+                f,
+                &WithHeader {
+                    snapshot,
+                    entities: &ejsons,
+                },
+            ),
+            None => serde_json::to_writer_pretty(f, &ejsons),
+        }
+        .map_err(JsonSerializationError::from)?;
         Ok(())
     }
 
     /// Internal helper function to convert this `Entities` into a `Vec<EntityJSON>`
+    #[cfg(feature = "json")]
     fn to_ejsons(&self) -> Result<Vec<EntityJSON>> {
         self.entities
             .values()
@@ -267,12 +468,19 @@ impl Entities {
             let r = self.compute_entities_values()?;
             Ok(Self {
                 entities: self.entities,
+                lazy_tc: self.lazy_tc,
                 evaluated_entities: Some(r),
                 mode: self.mode,
+                snapshot_info: self.snapshot_info,
             })
         }
     }
 
+    /// Whether the values of attributes have already been computed via [`Self::evaluate`]
+    pub fn is_evaluated(&self) -> bool {
+        self.evaluated_entities.is_some()
+    }
+
     fn compute_entities_values(&self) -> std::result::Result<EvaluatedEntities, EvaluationError> {
         build_evaluated_entities(self, &Extensions::all_available())
     }
@@ -281,6 +489,13 @@ impl Entities {
     /// If the entity values have already been computed via [`Self::evaluate`], then that will be re-used.
     /// Otherwise, the attributes will be evaluated.
     pub fn get_attr_values(&self) -> std::result::Result<EntityAttrValues<'_>, EvaluationError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "evaluate_entity_attrs",
+            entities = self.entities.len(),
+            cached = self.evaluated_entities.is_some(),
+        )
+        .entered();
         let map = match &self.evaluated_entities {
             Some(cached) => Cow::Borrowed(cached),
             None => Cow::Owned(self.compute_entities_values()?),
@@ -289,6 +504,46 @@ impl Entities {
     }
 }
 
+/// Merge `other` into `entity`, which must have the same UID. Attributes and
+/// tags defined by only one of them are kept, and the parents are unioned.
+/// Fails if both define the same attribute or tag with different values.
+fn merge_entity(entity: &mut Entity, other: Entity) -> Result<()> {
+    for (attr, val) in other.attrs_map() {
+        match entity.attrs_map().get(attr) {
+            Some(existing)
+                if RestrictedExprShapeOnly::new(existing.as_borrowed())
+                    != RestrictedExprShapeOnly::new(val.as_borrowed()) =>
+            {
+                return Err(EntitiesError::ConflictingDuplicate {
+                    uid: entity.uid(),
+                    attr: attr.clone(),
+                })
+            }
+            Some(_) => (),
+            None => entity.set_attr(attr.clone(), val.clone()),
+        }
+    }
+    for (tag, val) in other.tags_map() {
+        match entity.tags_map().get(tag) {
+            Some(existing)
+                if RestrictedExprShapeOnly::new(existing.as_borrowed())
+                    != RestrictedExprShapeOnly::new(val.as_borrowed()) =>
+            {
+                return Err(EntitiesError::ConflictingDuplicateTag {
+                    uid: entity.uid(),
+                    tag: tag.clone(),
+                })
+            }
+            Some(_) => (),
+            None => entity.set_tag(tag.clone(), val.clone()),
+        }
+    }
+    for parent in other.ancestors() {
+        entity.add_ancestor(parent.clone());
+    }
+    Ok(())
+}
+
 type EvaluatedEntities = HashMap<EntityUID, HashMap<SmolStr, PartialValue>>;
 
 /// Structure of borrowed entity information that is used in the evaluator
@@ -442,7 +697,6 @@ impl Default for Mode {
 
 /// Describes the option for how the TC (transitive closure) of the entity
 /// hierarchy is computed
-#[allow(dead_code)] // only `ComputeNow` is used currently, that's intentional
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum TCComputation {
     /// Assume that the TC has already been computed and that the input is a DAG before the call of
@@ -456,6 +710,28 @@ pub enum TCComputation {
     /// This doesn't make any assumptions about the input, which can in fact
     /// contain just parent edges and not transitive ancestor edges. Also checks for cycles and returns an error if found.
     ComputeNow,
+    /// Don't compute the TC; instead keep only the edges given in the input
+    /// and follow them on demand whenever the hierarchy is queried. This makes
+    /// constructing the `Entities` cheap for large hierarchies, at the cost of
+    /// slower `in` checks. Also checks for cycles and returns an error if found.
+    ComputeLazily,
+}
+
+/// Describes what to do when the same entity UID is given more than once
+/// while constructing an `Entities`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DuplicateUidStrategy {
+    /// Return an [`EntitiesError::Duplicate`] error. This is the default.
+    #[default]
+    Error,
+    /// Keep the first definition of the entity and ignore any later ones
+    FirstWins,
+    /// Merge all the definitions of the entity into one: the attributes and
+    /// parents of each definition are unioned. If two definitions give the
+    /// same attribute or tag different values, return an
+    /// [`EntitiesError::ConflictingDuplicate`] or
+    /// [`EntitiesError::ConflictingDuplicateTag`] error.
+    MergeAttributes,
 }
 
 #[cfg(test)]
@@ -686,6 +962,82 @@ mod json_parsing_tests {
         }
     }
 
+    fn duplicated_jeff() -> serde_json::Value {
+        serde_json::json!([
+            {"uid":{"__expr":"Test::\"jeff\""}, "attrs" : {"foo" : 1}, "parents" : ["Test::\"susan\""]},
+            {"uid":{"__expr":"Test::\"susan\""}, "attrs" : {}, "parents" : []},
+            {"uid":{"__expr":"Test::\"jeff\""}, "attrs" : {"foo" : 1, "bar" : 2}, "parents" : ["Test::\"george\""]}])
+    }
+
+    #[test]
+    fn parse_duplicates_fail() {
+        let parser: EntityJsonParser<'_> =
+            EntityJsonParser::new(None, Extensions::all_available(), TCComputation::ComputeNow);
+        let err = parser.from_json_value(duplicated_jeff()).err().unwrap();
+        let expected = r#"Test::"jeff""#.parse().unwrap();
+        match err {
+            EntitiesError::Duplicate(e) => assert_eq!(e, expected),
+            e => panic!("Wrong error: {e}"),
+        }
+    }
+
+    #[test]
+    fn parse_duplicates_first_wins() {
+        let parser: EntityJsonParser<'_> =
+            EntityJsonParser::new(None, Extensions::all_available(), TCComputation::ComputeNow)
+                .with_duplicate_strategy(DuplicateUidStrategy::FirstWins);
+        let es = parser.from_json_value(duplicated_jeff()).unwrap();
+        let jeff = es.entity(&r#"Test::"jeff""#.parse().unwrap()).unwrap();
+        assert!(jeff.get("foo").is_some());
+        assert!(jeff.get("bar").is_none());
+        assert!(jeff.is_descendant_of(&r#"Test::"susan""#.parse().unwrap()));
+        assert!(!jeff.is_descendant_of(&r#"Test::"george""#.parse().unwrap()));
+    }
+
+    #[test]
+    fn parse_duplicates_merge() {
+        let parser: EntityJsonParser<'_> =
+            EntityJsonParser::new(None, Extensions::all_available(), TCComputation::ComputeNow)
+                .with_duplicate_strategy(DuplicateUidStrategy::MergeAttributes);
+        let es = parser.from_json_value(duplicated_jeff()).unwrap();
+        let jeff = es.entity(&r#"Test::"jeff""#.parse().unwrap()).unwrap();
+        assert!(jeff.get("foo").is_some());
+        assert!(jeff.get("bar").is_some());
+        assert!(jeff.is_descendant_of(&r#"Test::"susan""#.parse().unwrap()));
+        assert!(jeff.is_descendant_of(&r#"Test::"george""#.parse().unwrap()));
+    }
+
+    #[test]
+    fn parse_duplicates_merge_conflict() {
+        let parser: EntityJsonParser<'_> =
+            EntityJsonParser::new(None, Extensions::all_available(), TCComputation::ComputeNow)
+                .with_duplicate_strategy(DuplicateUidStrategy::MergeAttributes);
+        let new = serde_json::json!([
+            {"uid":{"__expr":"Test::\"jeff\""}, "attrs" : {"foo" : 1}, "parents" : []},
+            {"uid":{"__expr":"Test::\"jeff\""}, "attrs" : {"foo" : 2}, "parents" : []}]);
+        let err = parser.from_json_value(new).err().unwrap();
+        match err {
+            EntitiesError::ConflictingDuplicate { uid, attr } => {
+                assert_eq!(uid, r#"Test::"jeff""#.parse().unwrap());
+                assert_eq!(attr, "foo");
+            }
+            e => panic!("Wrong error: {e}"),
+        }
+    }
+
+    #[test]
+    fn parse_tags() {
+        let parser: EntityJsonParser<'_> =
+            EntityJsonParser::new(None, Extensions::all_available(), TCComputation::ComputeNow);
+        let new = serde_json::json!([
+            {"uid":{"__expr":"Test::\"jeff\""}, "attrs" : {"risk" : "low"}, "parents" : [], "tags" : {"risk" : 87}}]);
+        let es = parser.from_json_value(new).unwrap();
+        let jeff = es.entity(&r#"Test::"jeff""#.parse().unwrap()).unwrap();
+        assert_eq!(jeff.get_tag("risk"), Some(&RestrictedExpr::val(87)));
+        assert_eq!(jeff.get("risk"), Some(&RestrictedExpr::val("low")));
+        assert_eq!(es, roundtrip(&es).expect("should roundtrip without errors"));
+    }
+
     fn simple_entities(parser: &EntityJsonParser<'_>) -> Entities {
         let json = serde_json::json!(
             [
@@ -1194,6 +1546,59 @@ mod json_parsing_tests {
         ));
     }
 
+    /// Test that the header of the entities JSON is parsed, and written back
+    #[test]
+    fn json_snapshot_header() {
+        let eparser: EntityJsonParser<'_> =
+            EntityJsonParser::new(None, Extensions::all_available(), TCComputation::ComputeNow);
+        let entities = eparser
+            .from_json_value(serde_json::json!({
+                "snapshot": {
+                    "chainId": 1,
+                    "blockNumber": 19000000,
+                    "blockHash": "0xabc",
+                    "fetchedAtMs": 1700000000000_u64
+                },
+                "entities": [{"uid": {"type": "Test", "id": "alice"}, "attrs": {}, "parents": []}]
+            }))
+            .expect("should parse with a header");
+        let expected = SnapshotInfo {
+            chain_id: Some(1),
+            block_number: Some(19000000),
+            block_hash: Some("0xabc".into()),
+            fetched_at_ms: Some(1700000000000),
+        };
+        assert_eq!(entities.snapshot_info(), Some(&expected));
+        assert_eq!(entities.iter().count(), 1);
+        assert_eq!(
+            entities,
+            roundtrip(&entities).expect("should roundtrip without errors")
+        );
+        assert_eq!(
+            eparser
+                .iter_from_json_value(entities.to_json_value().expect("should serialize"))
+                .expect("should parse with a header")
+                .count(),
+            1
+        );
+
+        let partial = eparser
+            .from_json_str(r#"{ "snapshot": { "blockNumber": 7 }, "entities": [] }"#)
+            .expect("should parse with a partial header");
+        assert_eq!(
+            partial.snapshot_info().and_then(|info| info.block_number),
+            Some(7)
+        );
+        assert_eq!(partial.snapshot_info().and_then(|info| info.chain_id), None);
+        assert_eq!(
+            eparser
+                .from_json_str("[]")
+                .expect("should parse without a header")
+                .snapshot_info(),
+            None
+        );
+    }
+
     /// test that an Action having a non-Action parent is an error
     #[test]
     fn bad_action_parent() {
@@ -1226,6 +1631,7 @@ mod json_parsing_tests {
 #[cfg(test)]
 mod entities_tests {
     use super::*;
+    use std::collections::HashSet;
 
     #[test]
     fn empty_entities() {
@@ -1234,6 +1640,46 @@ mod entities_tests {
         assert!(es.is_empty(), "This vec should be empty");
     }
 
+    #[test]
+    fn memory_usage() {
+        let mut wallet = Entity::with_uid(EntityUID::with_eid("wallet"));
+        let before = Entities::from_entities([wallet.clone()], TCComputation::ComputeNow)
+            .expect("Failed to construct entities")
+            .approx_memory_usage();
+        assert_eq!(before.attribute_values, 0);
+        assert_eq!(before.ancestors, 0);
+
+        wallet.set_attr(
+            "owners".into(),
+            RestrictedExpr::set([
+                RestrictedExpr::val("0x52908400098527886E0F7030069857D2E4169EE7"),
+                RestrictedExpr::val("0x8617E340B3D01FA5F11F306F4090FD50E238070D"),
+            ]),
+        );
+        wallet.add_ancestor(EntityUID::with_eid("dao"));
+        let es = Entities::from_entities(
+            [wallet, Entity::with_uid(EntityUID::with_eid("dao"))],
+            TCComputation::ComputeNow,
+        )
+        .expect("Failed to construct entities");
+        let after = es.approx_memory_usage();
+        assert!(after.attribute_values > 0);
+        assert!(after.ancestors > 0);
+        assert!(after.entities > before.entities);
+        assert_eq!(
+            after.total(),
+            after.entities + after.attribute_values + after.ancestors
+        );
+
+        // evaluated attributes are kept as well
+        let evaluated = es
+            .evaluate()
+            .expect("Failed to evaluate")
+            .approx_memory_usage();
+        assert!(evaluated.attribute_values > after.attribute_values);
+        assert_eq!(evaluated.entities, after.entities);
+    }
+
     /// helper function
     fn test_entities() -> (Entity, Entity, Entity, Entity) {
         (
@@ -1293,6 +1739,55 @@ mod entities_tests {
         Entities::from_entities(vec![e1, e2, e3], TCComputation::EnforceAlreadyComputed)
             .expect("Should have succeeded");
     }
+
+    #[test]
+    fn test_compute_lazily() {
+        // Hierarchy
+        // a -> b -> c
+        // d
+        // This isn't transitively closed, but ancestors are followed on demand
+        let mut e1 = Entity::with_uid(EntityUID::with_eid("a"));
+        let mut e2 = Entity::with_uid(EntityUID::with_eid("b"));
+        let e3 = Entity::with_uid(EntityUID::with_eid("c"));
+        let e4 = Entity::with_uid(EntityUID::with_eid("d"));
+        e1.add_ancestor(EntityUID::with_eid("b"));
+        e2.add_ancestor(EntityUID::with_eid("c"));
+
+        let es = Entities::from_entities(vec![e1, e2, e3, e4], TCComputation::ComputeLazily)
+            .expect("Should have succeeded");
+        let a = es.entity(&EntityUID::with_eid("a")).unwrap();
+        // the entity itself still only records its parent
+        assert!(!a.is_descendant_of(&EntityUID::with_eid("c")));
+        assert!(es.is_descendant_of(a, &EntityUID::with_eid("b")));
+        assert!(es.is_descendant_of(a, &EntityUID::with_eid("c")));
+        assert!(!es.is_descendant_of(a, &EntityUID::with_eid("d")));
+        assert_eq!(
+            es.ancestors_of(a).collect::<HashSet<_>>(),
+            HashSet::from([&EntityUID::with_eid("b"), &EntityUID::with_eid("c")])
+        );
+    }
+
+    #[test]
+    fn test_compute_lazily_cycle() {
+        // Hierarchy
+        // a -> b -> a
+        let mut e1 = Entity::with_uid(EntityUID::with_eid("a"));
+        let mut e2 = Entity::with_uid(EntityUID::with_eid("b"));
+        e1.add_ancestor(EntityUID::with_eid("b"));
+        e2.add_ancestor(EntityUID::with_eid("a"));
+
+        let es = Entities::from_entities(vec![e1, e2], TCComputation::ComputeLazily);
+        match es {
+            Ok(_) => panic!("Hierarchy has a cycle!"),
+            Err(EntitiesError::TransitiveClosureError(e)) => {
+                assert!(matches!(
+                    *e,
+                    crate::transitive_closure::TcError::HasCycle { .. }
+                ))
+            }
+            Err(_) => panic!("Wrong Error!"),
+        };
+    }
 }
 
 #[cfg(test)]
@@ -2626,4 +3121,92 @@ mod schema_based_parsing_tests {
             err
         );
     }
+
+    /// Test that attributes missing from the JSON are given their defaults
+    #[test]
+    fn attribute_defaults() {
+        struct MockSchema;
+        impl Schema for MockSchema {
+            type EntityTypeDescription = MockVaultDescription;
+            fn entity_type(&self, entity_type: &EntityType) -> Option<MockVaultDescription> {
+                match entity_type.to_string().as_str() {
+                    "Vault" => Some(MockVaultDescription),
+                    _ => None,
+                }
+            }
+            fn action(&self, _action: &EntityUID) -> Option<Arc<Entity>> {
+                None
+            }
+            fn entity_types_with_basename<'a>(
+                &'a self,
+                _basename: &'a Id,
+            ) -> Box<dyn Iterator<Item = EntityType> + 'a> {
+                Box::new(std::iter::empty())
+            }
+        }
+
+        struct MockVaultDescription;
+        impl EntityTypeDescription for MockVaultDescription {
+            fn entity_type(&self) -> EntityType {
+                EntityType::Concrete(Name::parse_unqualified_name("Vault").expect("valid"))
+            }
+
+            fn attr_type(&self, attr: &str) -> Option<SchemaType> {
+                match attr {
+                    "paused" => Some(SchemaType::Bool),
+                    "fee" => Some(SchemaType::Long),
+                    _ => None,
+                }
+            }
+
+            fn required_attrs(&self) -> Box<dyn Iterator<Item = SmolStr>> {
+                Box::new(["paused", "fee"].map(SmolStr::new).into_iter())
+            }
+
+            fn default_attrs(&self) -> Box<dyn Iterator<Item = (SmolStr, serde_json::Value)>> {
+                Box::new(std::iter::once(("paused".into(), json!(false))))
+            }
+
+            fn allowed_parent_types(&self) -> Arc<HashSet<EntityType>> {
+                Arc::new(HashSet::new())
+            }
+        }
+
+        let eparser = EntityJsonParser::new(
+            Some(MockSchema),
+            Extensions::all_available(),
+            TCComputation::ComputeNow,
+        );
+        let parsed = eparser
+            .from_json_value(json!(
+                [
+                    { "uid": { "type": "Vault", "id": "a" }, "attrs": { "fee": 3 }, "parents": [] },
+                    { "uid": { "type": "Vault", "id": "b" }, "attrs": { "paused": true, "fee": 3 }, "parents": [] }
+                ]
+            ))
+            .expect("should parse without error");
+        for (id, paused) in [("a", false), ("b", true)] {
+            let uid = EntityUID::with_eid_and_type("Vault", id).expect("valid uid");
+            let attr = parsed
+                .entity(&uid)
+                .expect("entity should exist")
+                .get("paused")
+                .expect("paused attr should exist");
+            assert_eq!(
+                RestrictedExprShapeOnly::new(attr.as_borrowed()),
+                RestrictedExprShapeOnly::new(RestrictedExpr::val(paused).as_borrowed())
+            );
+        }
+
+        let err = eparser
+            .from_json_value(json!(
+                [{ "uid": { "type": "Vault", "id": "a" }, "attrs": { "paused": true }, "parents": [] }]
+            ))
+            .expect_err("should fail due to missing attribute without a default");
+        assert!(
+            err.to_string().contains("fee"),
+            "actual error message was {}",
+            err
+        );
+    }
 }
@@ -0,0 +1,470 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! This module contains functionality for importing entities from CSV files.
+//!
+//! Each CSV row describes one entity. A [`CsvMapping`] declares which column
+//! holds the entity id, which columns become attributes (and with which type),
+//! and which columns list parents. Rows are converted into the same structure
+//! that the JSON entity parser consumes, so schema-based parsing and
+//! validation work exactly as they do for entities JSON.
+
+use super::{EntityJSON, EntityJsonParser, EntityUidJSON, Schema};
+use crate::ast::Name;
+use crate::entities::{Entities, EntitiesError};
+use smol_str::SmolStr;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Declarative description of how the columns of a CSV file map onto
+/// entities.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvMapping {
+    /// Column containing the entity id
+    uid_column: String,
+    /// Entity type of every entity produced by this mapping
+    entity_type: Name,
+    /// Columns that become entity attributes
+    attrs: Vec<CsvAttrMapping>,
+    /// Columns that contain entity parents
+    parents: Vec<CsvParentsMapping>,
+}
+
+/// Mapping of a single CSV column onto an entity attribute
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CsvAttrMapping {
+    /// Name of the CSV column
+    column: String,
+    /// Name of the attribute the column is stored in
+    attr: SmolStr,
+    /// How the contents of the column are interpreted
+    ty: CsvValueType,
+}
+
+/// Mapping of a single CSV column onto entity parents
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CsvParentsMapping {
+    /// Name of the CSV column
+    column: String,
+    /// Entity type of the parents listed in this column
+    entity_type: Name,
+    /// Separator between multiple parent ids in a single cell
+    separator: char,
+}
+
+/// How the contents of a CSV cell are interpreted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CsvValueType {
+    /// The cell is taken verbatim as a string
+    String,
+    /// The cell is parsed as a (64-bit signed) integer
+    Long,
+    /// The cell is parsed as a boolean (`true` or `false`, case-insensitive)
+    Bool,
+    /// The cell is passed as the string argument to the named extension
+    /// constructor, e.g. `u256`, `ip`, or `decimal`
+    Extension {
+        /// Name of the extension constructor function
+        constructor: SmolStr,
+    },
+    /// The cell is the id of an entity of the given type
+    Entity {
+        /// Type of the referenced entity
+        entity_type: Name,
+    },
+    /// The cell contains several values separated by `separator`, each of
+    /// which is interpreted according to `element`
+    Set {
+        /// Type of each element of the set
+        element: Box<CsvValueType>,
+        /// Separator between elements in a single cell
+        separator: char,
+    },
+}
+
+/// Errors thrown while importing entities from CSV
+#[derive(Debug, Error)]
+pub enum CsvDeserializationError {
+    /// Error thrown by the `csv` crate, e.g. for malformed input or I/O
+    /// failures
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    /// A column named in the mapping does not exist in the CSV header
+    #[error("column `{column}` is referenced by the mapping but missing from the CSV header")]
+    MissingColumn {
+        /// Name of the missing column
+        column: String,
+    },
+    /// The id column of a row was empty
+    #[error("row {row}: entity id column `{column}` is empty")]
+    EmptyUid {
+        /// Row number (1-based, not counting the header)
+        row: usize,
+        /// Name of the id column
+        column: String,
+    },
+    /// A cell could not be interpreted as the type declared in the mapping
+    #[error("row {row}, column `{column}`: expected {expected}, but got `{value}`")]
+    InvalidCell {
+        /// Row number (1-based, not counting the header)
+        row: usize,
+        /// Name of the column
+        column: String,
+        /// Contents of the offending cell
+        value: String,
+        /// Description of the expected type
+        expected: &'static str,
+    },
+}
+
+impl CsvMapping {
+    /// Create a new `CsvMapping` which produces entities of type
+    /// `entity_type`, taking the entity id from `uid_column`
+    pub fn new(uid_column: impl Into<String>, entity_type: Name) -> Self {
+        Self {
+            uid_column: uid_column.into(),
+            entity_type,
+            attrs: Vec::new(),
+            parents: Vec::new(),
+        }
+    }
+
+    /// Store the contents of `column` in the attribute `attr`, interpreting
+    /// each cell as `ty`.
+    ///
+    /// Empty cells are treated as the attribute being absent, which is how
+    /// optional attributes are expressed.
+    #[must_use]
+    pub fn attr(
+        mut self,
+        column: impl Into<String>,
+        attr: impl Into<SmolStr>,
+        ty: CsvValueType,
+    ) -> Self {
+        self.attrs.push(CsvAttrMapping {
+            column: column.into(),
+            attr: attr.into(),
+            ty,
+        });
+        self
+    }
+
+    /// Interpret the contents of `column` as a `separator`-separated list of
+    /// ids of parents of type `entity_type`.
+    ///
+    /// Empty cells and empty list items are ignored.
+    #[must_use]
+    pub fn parents(
+        mut self,
+        column: impl Into<String>,
+        entity_type: Name,
+        separator: char,
+    ) -> Self {
+        self.parents.push(CsvParentsMapping {
+            column: column.into(),
+            entity_type,
+            separator,
+        });
+        self
+    }
+
+    /// Convert every row of the CSV data in `reader` into an `EntityJSON`
+    fn to_ejsons(
+        &self,
+        reader: impl std::io::Read,
+    ) -> Result<Vec<EntityJSON>, CsvDeserializationError> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let headers = rdr.headers()?.clone();
+        let column_index = |column: &str| {
+            headers.iter().position(|h| h == column).ok_or_else(|| {
+                CsvDeserializationError::MissingColumn {
+                    column: column.to_string(),
+                }
+            })
+        };
+        let uid_idx = column_index(&self.uid_column)?;
+        let attr_idxs = self
+            .attrs
+            .iter()
+            .map(|m| column_index(&m.column))
+            .collect::<Result<Vec<_>, _>>()?;
+        let parent_idxs = self
+            .parents
+            .iter()
+            .map(|m| column_index(&m.column))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let entity_type: SmolStr = self.entity_type.to_string().into();
+        rdr.records()
+            .enumerate()
+            .map(|(i, record)| {
+                let record = record?;
+                let row = i + 1;
+                let cell = |idx: usize| record.get(idx).unwrap_or_default();
+
+                let id = cell(uid_idx);
+                if id.is_empty() {
+                    return Err(CsvDeserializationError::EmptyUid {
+                        row,
+                        column: self.uid_column.clone(),
+                    });
+                }
+
+                let mut attrs = HashMap::new();
+                for (mapping, idx) in self.attrs.iter().zip(attr_idxs.iter()) {
+                    let value = cell(*idx);
+                    if value.is_empty() {
+                        continue;
+                    }
+                    let jvalue = mapping.ty.cell_to_json(value).ok_or_else(|| {
+                        CsvDeserializationError::InvalidCell {
+                            row,
+                            column: mapping.column.clone(),
+                            value: value.to_string(),
+                            expected: mapping.ty.description(),
+                        }
+                    })?;
+                    attrs.insert(mapping.attr.clone(), jvalue);
+                }
+
+                let parents = self
+                    .parents
+                    .iter()
+                    .zip(parent_idxs.iter())
+                    .flat_map(|(mapping, idx)| {
+                        let parent_type: SmolStr = mapping.entity_type.to_string().into();
+                        cell(*idx)
+                            .split(mapping.separator)
+                            .map(str::trim)
+                            .filter(|id| !id.is_empty())
+                            .map(move |id| EntityUidJSON::new(parent_type.clone(), id))
+                    })
+                    .collect();
+
+                Ok(EntityJSON::new(
+                    EntityUidJSON::new(entity_type.clone(), id),
+                    attrs,
+                    parents,
+                ))
+            })
+            .collect()
+    }
+}
+
+impl CsvValueType {
+    /// Convert the contents of a single (non-empty) cell into the JSON value
+    /// that the entity parser expects for this type.
+    /// Returns `None` if the cell is not a valid value of this type.
+    fn cell_to_json(&self, value: &str) -> Option<serde_json::Value> {
+        match self {
+            Self::String => Some(serde_json::Value::String(value.to_string())),
+            Self::Long => value.parse::<i64>().ok().map(Into::into),
+            Self::Bool => match value.to_ascii_lowercase().as_str() {
+                "true" => Some(true.into()),
+                "false" => Some(false.into()),
+                _ => None,
+            },
+            Self::Extension { constructor } => Some(serde_json::json!({
+                "__extn": { "fn": constructor.as_str(), "arg": value }
+            })),
+            Self::Entity { entity_type } => Some(serde_json::json!({
+                "__entity": { "type": entity_type.to_string(), "id": value }
+            })),
+            Self::Set { element, separator } => value
+                .split(*separator)
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(|v| element.cell_to_json(v))
+                .collect::<Option<Vec<_>>>()
+                .map(serde_json::Value::Array),
+        }
+    }
+
+    /// Short description of this type, for error messages
+    fn description(&self) -> &'static str {
+        match self {
+            Self::String => "a string",
+            Self::Long => "an integer",
+            Self::Bool => "a boolean",
+            Self::Extension { .. } => "an extension value",
+            Self::Entity { .. } => "an entity id",
+            Self::Set { .. } => "a set",
+        }
+    }
+}
+
+impl<'e, S: Schema> EntityJsonParser<'e, S> {
+    /// Parse a CSV file (in [`std::io::Read`] form) into an [`Entities`]
+    /// object, using `mapping` to interpret its columns.
+    ///
+    /// The first row of the file must be a header naming the columns.
+    pub fn from_csv(
+        &self,
+        reader: impl std::io::Read,
+        mapping: &CsvMapping,
+    ) -> Result<Entities, EntitiesError> {
+        let ejsons = mapping.to_ejsons(reader)?;
+        self.parse_ejsons(ejsons)
+    }
+
+    /// Parse a CSV file (in [`std::io::Read`] form) into an iterator over
+    /// [`crate::ast::Entity`]s, using `mapping` to interpret its columns.
+    pub fn iter_from_csv(
+        &self,
+        reader: impl std::io::Read,
+        mapping: &CsvMapping,
+    ) -> Result<impl Iterator<Item = Result<crate::ast::Entity, EntitiesError>> + '_, EntitiesError>
+    {
+        let ejsons = mapping.to_ejsons(reader)?;
+        Ok(ejsons
+            .into_iter()
+            .map(|ejson| self.parse_ejson(ejson).map_err(EntitiesError::from)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{EntityUID, RestrictedExpr};
+    use crate::entities::{NoEntitiesSchema, TCComputation};
+    use crate::extensions::Extensions;
+    use cool_asserts::assert_matches;
+
+    fn parser() -> EntityJsonParser<'static, NoEntitiesSchema> {
+        EntityJsonParser::new(None, Extensions::all_available(), TCComputation::ComputeNow)
+    }
+
+    fn name(s: &str) -> Name {
+        s.parse().expect("should be a valid name")
+    }
+
+    fn euid(s: &str) -> EntityUID {
+        s.parse().expect("should be a valid entity uid")
+    }
+
+    #[test]
+    fn basic_import() {
+        let data = "\
+address,label,limit,active,groups,approvers\n\
+0xabc,treasury,100,true,admins;signers,\"alice, bob\"\n\
+0xdef,,5,FALSE,,\n";
+        let mapping = CsvMapping::new("address", name("Wallet"))
+            .attr("label", "label", CsvValueType::String)
+            .attr("limit", "limit", CsvValueType::Long)
+            .attr("active", "active", CsvValueType::Bool)
+            .attr(
+                "approvers",
+                "approvers",
+                CsvValueType::Set {
+                    element: Box::new(CsvValueType::Entity {
+                        entity_type: name("User"),
+                    }),
+                    separator: ',',
+                },
+            )
+            .parents("groups", name("Group"), ';');
+        let entities = parser()
+            .from_csv(data.as_bytes(), &mapping)
+            .expect("should parse");
+
+        let treasury = entities
+            .entity(&euid(r#"Wallet::"0xabc""#))
+            .expect("entity should exist");
+        assert_eq!(
+            treasury.get("label"),
+            Some(&RestrictedExpr::val("treasury"))
+        );
+        assert_eq!(treasury.get("limit"), Some(&RestrictedExpr::val(100)));
+        assert_eq!(treasury.get("active"), Some(&RestrictedExpr::val(true)));
+        assert_eq!(
+            treasury.get("approvers"),
+            Some(&RestrictedExpr::set([
+                RestrictedExpr::val(euid(r#"User::"alice""#)),
+                RestrictedExpr::val(euid(r#"User::"bob""#)),
+            ]))
+        );
+        assert!(treasury.is_descendant_of(&euid(r#"Group::"admins""#)));
+        assert!(treasury.is_descendant_of(&euid(r#"Group::"signers""#)));
+
+        let other = entities
+            .entity(&euid(r#"Wallet::"0xdef""#))
+            .expect("entity should exist");
+        assert_eq!(other.get("label"), None);
+        assert_eq!(other.get("approvers"), None);
+        assert_eq!(other.get("active"), Some(&RestrictedExpr::val(false)));
+        assert_eq!(other.ancestors().count(), 0);
+    }
+
+    #[test]
+    fn extension_values() {
+        let data = "id,addr\nbob,10.0.0.1\n";
+        let mapping = CsvMapping::new("id", name("User")).attr(
+            "addr",
+            "addr",
+            CsvValueType::Extension {
+                constructor: "ip".into(),
+            },
+        );
+        let entities = parser()
+            .from_csv(data.as_bytes(), &mapping)
+            .expect("should parse");
+        let bob = entities
+            .entity(&euid(r#"User::"bob""#))
+            .expect("entity should exist");
+        assert_eq!(
+            bob.get("addr"),
+            Some(&RestrictedExpr::call_extension_fn(
+                name("ip"),
+                vec![RestrictedExpr::val("10.0.0.1")]
+            ))
+        );
+    }
+
+    #[test]
+    fn missing_column() {
+        let data = "id,name\nbob,Bob\n";
+        let mapping = CsvMapping::new("id", name("User")).attr("age", "age", CsvValueType::Long);
+        assert_matches!(
+            parser().from_csv(data.as_bytes(), &mapping),
+            Err(EntitiesError::Csv(CsvDeserializationError::MissingColumn { column })) if column == "age"
+        );
+    }
+
+    #[test]
+    fn invalid_cell() {
+        let data = "id,age\nbob,7\nalice,seven\n";
+        let mapping = CsvMapping::new("id", name("User")).attr("age", "age", CsvValueType::Long);
+        assert_matches!(
+            parser().from_csv(data.as_bytes(), &mapping),
+            Err(EntitiesError::Csv(CsvDeserializationError::InvalidCell { row: 2, column, .. })) if column == "age"
+        );
+    }
+
+    #[test]
+    fn empty_uid() {
+        let data = "id\nbob\n\"\"\n";
+        let mapping = CsvMapping::new("id", name("User"));
+        assert_matches!(
+            parser().from_csv(data.as_bytes(), &mapping),
+            Err(EntitiesError::Csv(CsvDeserializationError::EmptyUid {
+                row: 2,
+                ..
+            }))
+        );
+    }
+}
@@ -16,20 +16,49 @@
 
 use super::EntityUID;
 use crate::transitive_closure;
+use smol_str::SmolStr;
 use thiserror::Error;
 
 /// Error type for errors raised in entities.rs.
 #[derive(Debug, Error)]
 pub enum EntitiesError {
     /// Error occurring in serialization of entities
+    #[cfg(feature = "json")]
     #[error("error during entity serialization: {0}")]
     Serialization(#[from] crate::entities::JsonSerializationError),
     /// Error occurring in deserialization of entities
+    #[cfg(feature = "json")]
     #[error("error during entity deserialization: {0}")]
     Deserialization(#[from] crate::entities::JsonDeserializationError),
+    /// Error occurring in deserialization of entities from CSV
+    #[cfg(feature = "csv")]
+    #[error("error during entity deserialization from CSV: {0}")]
+    Csv(#[from] crate::entities::CsvDeserializationError),
+    /// Error saving or loading an entities snapshot
+    #[cfg(feature = "snapshot")]
+    #[error("error in entities snapshot: {0}")]
+    Snapshot(#[from] crate::entities::SnapshotError),
     /// Error constructing the `[crate::entities::Entities]` as there is a duplicate Entity UID
     #[error("duplicate entity entry `{0}`")]
     Duplicate(EntityUID),
+    /// Error constructing the `[crate::entities::Entities]` as two definitions
+    /// of the same Entity UID give different values to the same attribute
+    #[error("conflicting definitions of attribute `{attr}` for duplicate entity entry `{uid}`")]
+    ConflictingDuplicate {
+        /// UID of the duplicated entity
+        uid: EntityUID,
+        /// Attribute which was given different values
+        attr: SmolStr,
+    },
+    /// Error constructing the `[crate::entities::Entities]` as two definitions
+    /// of the same Entity UID give different values to the same tag
+    #[error("conflicting definitions of tag `{tag}` for duplicate entity entry `{uid}`")]
+    ConflictingDuplicateTag {
+        /// UID of the duplicated entity
+        uid: EntityUID,
+        /// Tag which was given different values
+        tag: SmolStr,
+    },
     /// Errors occurring while computing or enforcing transitive closure on the
     /// entity hierarchy.
     #[error("transitive closure computation/enforcement error: {0}")]
@@ -19,15 +19,21 @@
 
 /// Representation of a Cedar value in JSON, and functionality for parsing it.
 /// Shared by both entity-attribute and context parsers.
+#[cfg(feature = "json")]
 mod jsonvalue;
+#[cfg(feature = "json")]
 pub use jsonvalue::*;
 
 /// Parser for `Entities`, with related functionality.
+#[cfg(feature = "json")]
 mod entities;
+#[cfg(feature = "json")]
 pub use entities::*;
 
 /// Parser for `Context`, with related functionality.
+#[cfg(feature = "json")]
 mod context;
+#[cfg(feature = "json")]
 pub use context::*;
 
 /// the `Schema` trait and related types/traits, used for schema-based parsing.
@@ -39,5 +45,7 @@ mod schema_types;
 pub use schema_types::*;
 
 /// Error types for JSON serialization and deserialization
+#[cfg(feature = "json")]
 mod err;
+#[cfg(feature = "json")]
 pub use err::*;
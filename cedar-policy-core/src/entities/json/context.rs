@@ -15,14 +15,22 @@
  */
 
 use super::{JsonDeserializationError, JsonDeserializationErrorContext, SchemaType, ValueParser};
-use crate::ast::{Context, ExprKind};
+use crate::ast::{Context, ContextConflict, ExprKind};
 use crate::extensions::Extensions;
+use smol_str::SmolStr;
 use std::collections::HashMap;
 
 /// Trait for schemas that can inform the parsing of Context data
 pub trait ContextSchema {
     /// `SchemaType` (expected to be a `Record`) for the context.
     fn context_type(&self) -> SchemaType;
+
+    /// Get the default values, as context JSON, of the optional context
+    /// attributes which have one. A context which does not have such an
+    /// attribute in its JSON is given the default value.
+    fn default_attrs<'s>(&'s self) -> Box<dyn Iterator<Item = (SmolStr, serde_json::Value)> + 's> {
+        Box::new(std::iter::empty())
+    }
 }
 
 /// Simple type that implements `ContextSchema` by expecting an empty context
@@ -73,8 +81,13 @@ impl<'e, 's, S: ContextSchema> ContextJsonParser<'e, 's, S> {
     /// Parse context JSON (in `serde_json::Value` form) into a `Context` object
     pub fn from_json_value(
         &self,
-        json: serde_json::Value,
+        mut json: serde_json::Value,
     ) -> Result<Context, JsonDeserializationError> {
+        if let (Some(schema), serde_json::Value::Object(attrs)) = (self.schema, &mut json) {
+            for (attr, default) in schema.default_attrs() {
+                attrs.entry(attr.to_string()).or_insert(default);
+            }
+        }
         let vparser = ValueParser::new(self.extensions.clone());
         let expected_ty = self.schema.map(|s| s.context_type());
         let rexpr = vparser.val_into_rexpr(json, expected_ty.as_ref(), || {
@@ -96,4 +109,68 @@ impl<'e, 's, S: ContextSchema> ContextJsonParser<'e, 's, S> {
         let val = serde_json::from_reader(json).map_err(JsonDeserializationError::from)?;
         self.from_json_value(val)
     }
+
+    /// Make a `Context` built by other means than this parser, such as by
+    /// merging contexts, conform to the `schema`: optional attributes it lacks
+    /// are given their default values, and it must then have every required
+    /// attribute, no attribute the schema does not declare, and attributes of
+    /// the declared types. Without a `schema`, any `Context` conforms.
+    pub fn conform(&self, context: Context) -> Result<Context, JsonDeserializationError> {
+        let Some(schema) = self.schema else {
+            return Ok(context);
+        };
+        let SchemaType::Record { attrs: expected } = schema.context_type() else {
+            return Ok(context);
+        };
+        let vparser = ValueParser::new(self.extensions.clone());
+        let defaults = schema
+            .default_attrs()
+            .map(|(attr, default)| {
+                let expected_ty = expected.get(&attr).map(|ty| ty.schema_type());
+                let value = vparser.val_into_rexpr(default, expected_ty, || {
+                    JsonDeserializationErrorContext::Context
+                })?;
+                Ok((attr, value))
+            })
+            .collect::<Result<Vec<_>, JsonDeserializationError>>()?;
+        let context = match Context::merge(
+            &context,
+            &Context::from_pairs(defaults),
+            ContextConflict::KeepBase,
+        ) {
+            Ok(context) => context,
+            // keeping the base value never conflicts
+            Err(_) => context,
+        };
+        for (attr, value) in context.iter() {
+            let expected_ty = match expected.get(attr) {
+                Some(expected_ty) => expected_ty.schema_type(),
+                None => {
+                    return Err(JsonDeserializationError::UnexpectedRecordAttr {
+                        ctx: Box::new(JsonDeserializationErrorContext::Context),
+                        record_attr: attr.into(),
+                    })
+                }
+            };
+            let actual_ty =
+                vparser.type_of_rexpr(value, || JsonDeserializationErrorContext::Context)?;
+            if !actual_ty.is_consistent_with(expected_ty) {
+                return Err(JsonDeserializationError::TypeMismatch {
+                    ctx: Box::new(JsonDeserializationErrorContext::Context),
+                    expected: Box::new(expected_ty.clone()),
+                    actual: Box::new(actual_ty),
+                });
+            }
+        }
+        match expected
+            .iter()
+            .find(|(attr, ty)| ty.is_required() && !context.iter().any(|(k, _)| k == attr.as_str()))
+        {
+            Some((attr, _)) => Err(JsonDeserializationError::MissingRequiredRecordAttr {
+                ctx: Box::new(JsonDeserializationErrorContext::Context),
+                record_attr: attr.clone(),
+            }),
+            None => Ok(context),
+        }
+    }
 }
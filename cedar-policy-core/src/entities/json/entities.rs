@@ -20,9 +20,11 @@ use super::{
     ValueParser,
 };
 use crate::ast::{Entity, EntityType, EntityUID, RestrictedExpr};
-use crate::entities::{Entities, EntitiesError, TCComputation};
+use crate::collections;
+use crate::entities::{DuplicateUidStrategy, Entities, EntitiesError, SnapshotInfo, TCComputation};
 use crate::extensions::Extensions;
 use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
 use smol_str::SmolStr;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -37,8 +39,46 @@ pub struct EntityJSON {
     /// instance be an `EntityUidJSON` if we're expecting an entity reference,
     /// so for now we leave it in its raw `serde_json::Value` form.)
     attrs: HashMap<SmolStr, serde_json::Value>,
-    /// Parents of the entity, specified in any form accepted by `EntityUidJSON`
-    parents: Vec<EntityUidJSON>,
+    /// Parents of the entity, specified in any form accepted by `EntityUidJSON`.
+    /// Most entities have only a few, which are kept inline.
+    parents: SmallVec<[EntityUidJSON; 4]>,
+    /// tags, whose values can be any JSON value (as with `attrs`).
+    /// May be omitted if the entity has no tags.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    tags: HashMap<SmolStr, serde_json::Value>,
+}
+
+/// The entities JSON `json`, split into its header, if it has one, and the
+/// list of entities
+fn split_header(
+    json: serde_json::Value,
+) -> Result<(Option<SnapshotInfo>, Vec<EntityJSON>), JsonDeserializationError> {
+    match json {
+        serde_json::Value::Object(mut document) => {
+            let snapshot = document
+                .remove("snapshot")
+                .map(serde_json::from_value)
+                .transpose()?;
+            let entities = document.remove("entities").unwrap_or_default();
+            Ok((snapshot, serde_json::from_value(entities)?))
+        }
+        json => Ok((None, serde_json::from_value(json)?)),
+    }
+}
+
+/// Entities JSON with a header, as written when the entities have
+/// [`SnapshotInfo`]
+#[derive(Serialize)]
+pub(crate) struct WithHeader<'a> {
+    pub(crate) snapshot: &'a SnapshotInfo,
+    pub(crate) entities: &'a [EntityJSON],
+}
+
+/// Whether the entities JSON `json` is an object, with a header, rather than
+/// a list
+fn has_header(json: &str) -> bool {
+    json.trim_start().starts_with('{')
 }
 
 /// Struct used to parse entities from JSON.
@@ -58,6 +98,10 @@ pub struct EntityJsonParser<'e, S: Schema = NoEntitiesSchema> {
     /// Whether to compute, enforce, or assume TC for entities parsed using this
     /// parser.
     tc_computation: TCComputation,
+
+    /// What to do when the same entity UID appears more than once in the
+    /// input.
+    duplicates: DuplicateUidStrategy,
 }
 
 /// Schema information about a single entity can take one of these forms:
@@ -93,11 +137,23 @@ impl<'e, S: Schema> EntityJsonParser<'e, S> {
             schema,
             extensions,
             tc_computation,
+            duplicates: DuplicateUidStrategy::default(),
         }
     }
 
+    /// Set how entity UIDs which appear more than once in the input are
+    /// handled by this parser. By default, they are an error.
+    pub fn with_duplicate_strategy(self, duplicates: DuplicateUidStrategy) -> Self {
+        Self { duplicates, ..self }
+    }
+
     /// Parse an entities JSON file (in [`&str`] form) into an [`Entities`] object
     pub fn from_json_str(&self, json: &str) -> Result<Entities, EntitiesError> {
+        if has_header(json) {
+            return self.from_json_value(
+                serde_json::from_str(json).map_err(JsonDeserializationError::from)?,
+            );
+        }
         let ejsons: Vec<EntityJSON> =
             serde_json::from_str(json).map_err(JsonDeserializationError::from)?;
         self.parse_ejsons(ejsons)
@@ -105,16 +161,13 @@ impl<'e, S: Schema> EntityJsonParser<'e, S> {
 
     /// Parse an entities JSON file (in [`serde_json::Value`] form) into an [`Entities`] object
     pub fn from_json_value(&self, json: serde_json::Value) -> Result<Entities, EntitiesError> {
-        let ejsons: Vec<EntityJSON> =
-            serde_json::from_value(json).map_err(JsonDeserializationError::from)?;
-        self.parse_ejsons(ejsons)
+        let (snapshot, ejsons) = split_header(json)?;
+        Ok(self.parse_ejsons(ejsons)?.with_snapshot_info(snapshot))
     }
 
     /// Parse an entities JSON file (in [`std::io::Read`] form) into an [`Entities`] object
     pub fn from_json_file(&self, json: impl std::io::Read) -> Result<Entities, EntitiesError> {
-        let ejsons: Vec<EntityJSON> =
-            serde_json::from_reader(json).map_err(JsonDeserializationError::from)?;
-        self.parse_ejsons(ejsons)
+        self.from_json_value(serde_json::from_reader(json).map_err(JsonDeserializationError::from)?)
     }
 
     /// Parse an entities JSON file (in [`&str`] form) into an iterator over [`Entity`]s
@@ -122,8 +175,11 @@ impl<'e, S: Schema> EntityJsonParser<'e, S> {
         &self,
         json: &str,
     ) -> Result<impl Iterator<Item = Result<Entity, EntitiesError>> + '_, EntitiesError> {
-        let ejsons: Vec<EntityJSON> =
-            serde_json::from_str(json).map_err(JsonDeserializationError::from)?;
+        let ejsons: Vec<EntityJSON> = if has_header(json) {
+            split_header(serde_json::from_str(json).map_err(JsonDeserializationError::from)?)?.1
+        } else {
+            serde_json::from_str(json).map_err(JsonDeserializationError::from)?
+        };
         Ok(ejsons
             .into_iter()
             .map(|ejson| self.parse_ejson(ejson).map_err(EntitiesError::from)))
@@ -134,8 +190,7 @@ impl<'e, S: Schema> EntityJsonParser<'e, S> {
         &self,
         json: serde_json::Value,
     ) -> Result<impl Iterator<Item = Result<Entity, EntitiesError>> + '_, EntitiesError> {
-        let ejsons: Vec<EntityJSON> =
-            serde_json::from_value(json).map_err(JsonDeserializationError::from)?;
+        let (_, ejsons) = split_header(json)?;
         Ok(ejsons
             .into_iter()
             .map(|ejson| self.parse_ejson(ejson).map_err(EntitiesError::from)))
@@ -146,27 +201,32 @@ impl<'e, S: Schema> EntityJsonParser<'e, S> {
         &self,
         json: impl std::io::Read,
     ) -> Result<impl Iterator<Item = Result<Entity, EntitiesError>> + '_, EntitiesError> {
-        let ejsons: Vec<EntityJSON> =
-            serde_json::from_reader(json).map_err(JsonDeserializationError::from)?;
+        let (_, ejsons) =
+            split_header(serde_json::from_reader(json).map_err(JsonDeserializationError::from)?)?;
         Ok(ejsons
             .into_iter()
             .map(|ejson| self.parse_ejson(ejson).map_err(EntitiesError::from)))
     }
 
     /// internal function that creates an [`Entities`] from a stream of [`EntityJSON`]
-    fn parse_ejsons(
+    pub(crate) fn parse_ejsons(
         &self,
         ejsons: impl IntoIterator<Item = EntityJSON>,
     ) -> Result<Entities, EntitiesError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("parse_entities").entered();
         let entities = ejsons
             .into_iter()
             .map(|ejson| self.parse_ejson(ejson))
             .collect::<Result<Vec<Entity>, _>>()?;
-        Entities::from_entities(entities, self.tc_computation)
+        Entities::from_entities_with_duplicates(entities, self.tc_computation, self.duplicates)
     }
 
     /// internal function that parses an `EntityJSON` into an `Entity`
-    fn parse_ejson(&self, ejson: EntityJSON) -> Result<Entity, JsonDeserializationError> {
+    pub(crate) fn parse_ejson(
+        &self,
+        mut ejson: EntityJSON,
+    ) -> Result<Entity, JsonDeserializationError> {
         let uid = ejson
             .uid
             .into_euid(|| JsonDeserializationErrorContext::EntityUid)?;
@@ -212,6 +272,11 @@ impl<'e, S: Schema> EntityJsonParser<'e, S> {
                 }
             }
             EntitySchemaInfo::NonAction(etype_desc) => {
+                // attributes which are not included in `ejson.attrs` take their
+                // default values, if they have one, before any other checks.
+                for (attr, default) in etype_desc.default_attrs() {
+                    ejson.attrs.entry(attr).or_insert(default);
+                }
                 // here, we ensure that all required attributes for `etype` are actually
                 // included in `ejson.attrs`. Later when consuming `ejson.attrs` to build
                 // `attrs`, we'll check for unexpected attributes.
@@ -228,7 +293,7 @@ impl<'e, S: Schema> EntityJsonParser<'e, S> {
             }
         }
         let vparser = ValueParser::new(self.extensions.clone());
-        let attrs: HashMap<SmolStr, RestrictedExpr> = ejson
+        let attrs: collections::HashMap<SmolStr, RestrictedExpr> = ejson
             .attrs
             .into_iter()
             .map(|(k, v)| match &entity_schema_info {
@@ -394,11 +459,71 @@ impl<'e, S: Schema> EntityJsonParser<'e, S> {
                 }
             }
         }
-        Ok(Entity::new(uid, attrs, parents))
+        let tags = ejson
+            .tags
+            .into_iter()
+            .map(|(k, v)| {
+                let ctx = || JsonDeserializationErrorContext::EntityTag {
+                    uid: uid.clone(),
+                    tag: k.clone(),
+                };
+                match &entity_schema_info {
+                    EntitySchemaInfo::NoSchema => {
+                        Ok((k.clone(), vparser.val_into_rexpr(v, None, ctx)?))
+                    }
+                    EntitySchemaInfo::NonAction(desc) => {
+                        let expected_ty = match desc.tag_type() {
+                            Some(expected_ty) => expected_ty,
+                            // `None` indicates this entity type shouldn't have tags
+                            None => {
+                                return Err(JsonDeserializationError::UnexpectedEntityTag {
+                                    uid: uid.clone(),
+                                    tag: k,
+                                })
+                            }
+                        };
+                        let rexpr = vparser.val_into_rexpr(v, Some(&expected_ty), ctx)?;
+                        // typecheck, as for attributes above
+                        let actual_ty = vparser.type_of_rexpr(rexpr.as_borrowed(), ctx)?;
+                        if actual_ty.is_consistent_with(&expected_ty) {
+                            Ok((k, rexpr))
+                        } else {
+                            Err(JsonDeserializationError::TypeMismatch {
+                                ctx: Box::new(ctx()),
+                                expected: Box::new(expected_ty),
+                                actual: Box::new(actual_ty),
+                            })
+                        }
+                    }
+                    // actions never have tags
+                    EntitySchemaInfo::Action(_) => {
+                        Err(JsonDeserializationError::ActionDeclarationMismatch {
+                            uid: uid.clone(),
+                        })
+                    }
+                }
+            })
+            .collect::<Result<_, JsonDeserializationError>>()?;
+        Ok(Entity::from_collections(uid, attrs, parents, tags))
     }
 }
 
 impl EntityJSON {
+    /// Construct an `EntityJSON` directly from its components
+    #[cfg(feature = "csv")]
+    pub(crate) fn new(
+        uid: EntityUidJSON,
+        attrs: HashMap<SmolStr, serde_json::Value>,
+        parents: Vec<EntityUidJSON>,
+    ) -> Self {
+        Self {
+            uid,
+            attrs,
+            parents: parents.into(),
+            tags: HashMap::new(),
+        }
+    }
+
     /// Convert an `Entity` into an EntityJSON
     ///
     /// (for the reverse transformation, use `EntityJsonParser`)
@@ -414,6 +539,10 @@ impl EntityJSON {
                 .ancestors()
                 .map(|euid| EntityUidJSON::ImplicitEntityEscape(TypeAndId::from(euid.clone())))
                 .collect(),
+            tags: entity
+                .tags()
+                .map(|(k, expr)| Ok((k.into(), serde_json::to_value(JSONValue::from_expr(expr)?)?)))
+                .collect::<Result<_, JsonSerializationError>>()?,
         })
     }
 }
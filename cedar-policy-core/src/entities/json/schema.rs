@@ -88,6 +88,23 @@ pub trait EntityTypeDescription {
     /// Get the names of all the required attributes for this entity type.
     fn required_attrs<'s>(&'s self) -> Box<dyn Iterator<Item = SmolStr> + 's>;
 
+    /// Get the default values, as entity JSON, of the optional attributes of
+    /// this entity type which have one. An entity which does not have such an
+    /// attribute in its JSON is given the default value.
+    #[cfg(feature = "json")]
+    fn default_attrs<'s>(&'s self) -> Box<dyn Iterator<Item = (SmolStr, serde_json::Value)> + 's> {
+        Box::new(std::iter::empty())
+    }
+
+    /// Do entities of this type have tags, and if so, what type are the tag
+    /// values?
+    ///
+    /// Returning `None` indicates that entities of this type should not have
+    /// any tags.
+    fn tag_type(&self) -> Option<SchemaType> {
+        None
+    }
+
     /// Get the entity types which are allowed to be parents of this entity type.
     fn allowed_parent_types(&self) -> Arc<HashSet<EntityType>>;
 }
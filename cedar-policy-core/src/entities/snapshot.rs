@@ -0,0 +1,126 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! This module contains functionality for saving entities to, and loading
+//! them from, binary snapshot files.
+//!
+//! A snapshot holds entities as they are after parsing: attribute values are
+//! already expressions, and the ancestors of each entity are already closed
+//! (or kept as parent edges, for entities built with
+//! [`super::TCComputation::ComputeLazily`]). Loading a snapshot therefore
+//! skips JSON parsing, schema-based conversion and the transitive closure
+//! computation.
+//!
+//! The snapshot format, and the errors loading snapshots may throw, are
+//! described in [`crate::snapshot`].
+
+use crate::entities::{Entities, EntitiesError};
+pub use crate::snapshot::SnapshotError;
+use crate::snapshot::{read, read_file, write};
+use std::io::Write;
+use std::path::Path;
+
+/// The bytes every entities snapshot starts with
+const MAGIC: &[u8; 8] = b"CEDARENT";
+
+impl Entities {
+    /// Write a snapshot of these entities to `writer`
+    pub fn write_snapshot(&self, writer: impl Write) -> Result<(), EntitiesError> {
+        Ok(write(MAGIC, self, writer)?)
+    }
+
+    /// Do `bytes`, such as the start of a file, look like a snapshot?
+    pub fn is_snapshot(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+
+    /// Load entities from a snapshot written by [`Entities::write_snapshot`]
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, EntitiesError> {
+        Ok(read(MAGIC, bytes)?)
+    }
+
+    /// Load entities from a snapshot file written by
+    /// [`Entities::write_snapshot`]
+    pub fn from_snapshot_file(path: impl AsRef<Path>) -> Result<Self, EntitiesError> {
+        Ok(read_file(MAGIC, path)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::{Entity, EntityUID, RestrictedExpr};
+    use crate::entities::{Dereference, TCComputation};
+
+    fn entities() -> Entities {
+        let mut wallet = Entity::with_uid(EntityUID::with_eid("wallet"));
+        wallet.set_attr("balance".into(), RestrictedExpr::val(100));
+        wallet.set_attr(
+            "owners".into(),
+            RestrictedExpr::set([RestrictedExpr::val("0xabc"), RestrictedExpr::val("0xdef")]),
+        );
+        wallet.add_ancestor(EntityUID::with_eid("dao"));
+        let mut dao = Entity::with_uid(EntityUID::with_eid("dao"));
+        dao.add_ancestor(EntityUID::with_eid("root"));
+        Entities::from_entities(
+            [wallet, dao, Entity::with_uid(EntityUID::with_eid("root"))],
+            TCComputation::ComputeNow,
+        )
+        .expect("entities are valid")
+    }
+
+    #[test]
+    fn round_trips() {
+        let entities = entities();
+        let mut bytes = Vec::new();
+        entities.write_snapshot(&mut bytes).expect("writes");
+        assert!(Entities::is_snapshot(&bytes));
+        let loaded = Entities::from_snapshot(&bytes).expect("loads");
+        assert_eq!(loaded, entities);
+        // the closed hierarchy is kept
+        match loaded.entity(&EntityUID::with_eid("wallet")) {
+            Dereference::Data(wallet) => {
+                assert!(wallet.is_descendant_of(&EntityUID::with_eid("root")));
+            }
+            _ => panic!("wallet is not loaded"),
+        }
+    }
+
+    #[test]
+    fn loads_files() {
+        let path = std::env::temp_dir().join(format!("entities-{}.snapshot", std::process::id()));
+        let file = std::fs::File::create(&path).expect("creates");
+        entities().write_snapshot(file).expect("writes");
+        let loaded = Entities::from_snapshot_file(&path);
+        std::fs::remove_file(&path).expect("removes");
+        assert_eq!(loaded.expect("loads"), entities());
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(!Entities::is_snapshot(b"[]"));
+        assert!(matches!(
+            Entities::from_snapshot(b"[]"),
+            Err(EntitiesError::Snapshot(SnapshotError::NotASnapshot))
+        ));
+        let mut bytes = postcard::to_stdvec(&(MAGIC, "0.0.1")).expect("encodes");
+        bytes.extend([0, 0]);
+        assert!(matches!(
+            Entities::from_snapshot(&bytes),
+            Err(EntitiesError::Snapshot(SnapshotError::Version { found })) if found == "0.0.1"
+        ));
+    }
+}
@@ -55,6 +55,15 @@ pub enum FromJsonError {
         /// Second non-constant argument
         arg2: ast::Expr,
     },
+    /// An operator has an operand of an extension type overloading it, and
+    /// another operand known to be of another type
+    #[error("an operand of `{func}` is a `{ty}`, but another is known not to be; convert it to a `{ty}`")]
+    MixedOperandTypes {
+        /// The extension type of an operand
+        ty: ast::Name,
+        /// The extension function implementing the operator for that type
+        func: ast::Name,
+    },
     /// Error thrown while processing string escapes
     #[error("invalid escape patterns: {:?}", .0.iter().map(|e| e.to_string()).collect::<Vec<String>>())]
     UnescapeError(Vec<unescape::UnescapeError>),
@@ -70,3 +79,12 @@ pub enum InstantiationError {
         slot: ast::SlotId,
     },
 }
+
+impl From<crate::parser::overload::MixedOperandTypes> for FromJsonError {
+    fn from(err: crate::parser::overload::MixedOperandTypes) -> Self {
+        Self::MixedOperandTypes {
+            ty: err.ty,
+            func: err.func,
+        }
+    }
+}
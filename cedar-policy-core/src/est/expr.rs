@@ -17,15 +17,18 @@
 use super::utils::unwrap_or_clone;
 use super::FromJsonError;
 use crate::ast;
+use crate::ast::OverloadedOperator;
 use crate::entities::{EscapeKind, JSONValue, JsonDeserializationError, TypeAndId};
 use crate::parser::cst::{self, Ident};
 use crate::parser::err::{ParseError, ParseErrors, ToASTError};
+use crate::parser::hex;
+use crate::parser::overload::{overloaded_operator_func, MixedOperandTypes};
 use crate::parser::unescape;
 use crate::parser::ASTNode;
 use either::Either;
 use serde::{Deserialize, Serialize};
 use smol_str::SmolStr;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
 /// Serde JSON structure for a Cedar expression in the EST format
@@ -190,6 +193,22 @@ pub enum ExprNoExt {
         /// Right-hand argument (inside the `()`)
         right: Arc<Expr>,
     },
+    /// `hasTag()`
+    #[serde(rename = "hasTag")]
+    HasTag {
+        /// Left-hand argument (receiver)
+        left: Arc<Expr>,
+        /// Right-hand argument (inside the `()`)
+        right: Arc<Expr>,
+    },
+    /// `getTag()`
+    #[serde(rename = "getTag")]
+    GetTag {
+        /// Left-hand argument (receiver)
+        left: Arc<Expr>,
+        /// Right-hand argument (inside the `()`)
+        right: Arc<Expr>,
+    },
     /// Get-attribute
     #[serde(rename = ".")]
     GetAttr {
@@ -403,6 +422,22 @@ impl Expr {
         })
     }
 
+    /// `left.hasTag(right)`
+    pub fn has_tag(left: Arc<Expr>, right: Expr) -> Self {
+        Expr::ExprNoExt(ExprNoExt::HasTag {
+            left,
+            right: Arc::new(right),
+        })
+    }
+
+    /// `left.getTag(right)`
+    pub fn get_tag(left: Arc<Expr>, right: Expr) -> Self {
+        Expr::ExprNoExt(ExprNoExt::GetTag {
+            left,
+            right: Arc::new(right),
+        })
+    }
+
     /// `left.attr`
     pub fn get_attr(left: Expr, attr: SmolStr) -> Self {
         Expr::ExprNoExt(ExprNoExt::GetAttr {
@@ -490,22 +525,30 @@ impl TryFrom<Expr> for ast::Expr {
                 (*left).clone().try_into()?,
                 (*right).clone().try_into()?,
             )),
-            Expr::ExprNoExt(ExprNoExt::Less { left, right }) => Ok(ast::Expr::less(
+            Expr::ExprNoExt(ExprNoExt::Less { left, right }) => Ok(overloadable(
+                OverloadedOperator::Less,
                 (*left).clone().try_into()?,
                 (*right).clone().try_into()?,
-            )),
-            Expr::ExprNoExt(ExprNoExt::LessEq { left, right }) => Ok(ast::Expr::lesseq(
+                ast::Expr::less,
+            )?),
+            Expr::ExprNoExt(ExprNoExt::LessEq { left, right }) => Ok(overloadable(
+                OverloadedOperator::LessEq,
                 (*left).clone().try_into()?,
                 (*right).clone().try_into()?,
-            )),
-            Expr::ExprNoExt(ExprNoExt::Greater { left, right }) => Ok(ast::Expr::greater(
+                ast::Expr::lesseq,
+            )?),
+            Expr::ExprNoExt(ExprNoExt::Greater { left, right }) => Ok(overloadable(
+                OverloadedOperator::Greater,
                 (*left).clone().try_into()?,
                 (*right).clone().try_into()?,
-            )),
-            Expr::ExprNoExt(ExprNoExt::GreaterEq { left, right }) => Ok(ast::Expr::greatereq(
+                ast::Expr::greater,
+            )?),
+            Expr::ExprNoExt(ExprNoExt::GreaterEq { left, right }) => Ok(overloadable(
+                OverloadedOperator::GreaterEq,
                 (*left).clone().try_into()?,
                 (*right).clone().try_into()?,
-            )),
+                ast::Expr::greatereq,
+            )?),
             Expr::ExprNoExt(ExprNoExt::And { left, right }) => Ok(ast::Expr::and(
                 (*left).clone().try_into()?,
                 (*right).clone().try_into()?,
@@ -514,17 +557,26 @@ impl TryFrom<Expr> for ast::Expr {
                 (*left).clone().try_into()?,
                 (*right).clone().try_into()?,
             )),
-            Expr::ExprNoExt(ExprNoExt::Add { left, right }) => Ok(ast::Expr::add(
+            Expr::ExprNoExt(ExprNoExt::Add { left, right }) => Ok(overloadable(
+                OverloadedOperator::Add,
                 (*left).clone().try_into()?,
                 (*right).clone().try_into()?,
-            )),
-            Expr::ExprNoExt(ExprNoExt::Sub { left, right }) => Ok(ast::Expr::sub(
+                ast::Expr::add,
+            )?),
+            Expr::ExprNoExt(ExprNoExt::Sub { left, right }) => Ok(overloadable(
+                OverloadedOperator::Sub,
                 (*left).clone().try_into()?,
                 (*right).clone().try_into()?,
-            )),
+                ast::Expr::sub,
+            )?),
             Expr::ExprNoExt(ExprNoExt::Mul { left, right }) => {
                 let left: ast::Expr = (*left).clone().try_into()?;
                 let right: ast::Expr = (*right).clone().try_into()?;
+                if let Some(func) =
+                    overloaded_operator_func(OverloadedOperator::Mul, [&left, &right])?
+                {
+                    return Ok(ast::Expr::call_extension_fn(func, vec![left, right]));
+                }
                 let left_c = match left.expr_kind() {
                     ast::ExprKind::Lit(ast::Literal::Long(c)) => Some(c),
                     _ => None,
@@ -554,6 +606,14 @@ impl TryFrom<Expr> for ast::Expr {
                 (*left).clone().try_into()?,
                 (*right).clone().try_into()?,
             )),
+            Expr::ExprNoExt(ExprNoExt::HasTag { left, right }) => Ok(ast::Expr::has_tag(
+                (*left).clone().try_into()?,
+                (*right).clone().try_into()?,
+            )),
+            Expr::ExprNoExt(ExprNoExt::GetTag { left, right }) => Ok(ast::Expr::get_tag(
+                (*left).clone().try_into()?,
+                (*right).clone().try_into()?,
+            )),
             Expr::ExprNoExt(ExprNoExt::GetAttr { left, attr }) => {
                 Ok(ast::Expr::get_attr((*left).clone().try_into()?, attr))
             }
@@ -581,10 +641,11 @@ impl TryFrom<Expr> for ast::Expr {
                     .map(|el| el.try_into())
                     .collect::<Result<Vec<_>, Self::Error>>()?,
             )),
+            // sorted, so that the AST does not depend on the order of the map
             Expr::ExprNoExt(ExprNoExt::Record(map)) => Ok(ast::Expr::record(
                 map.into_iter()
                     .map(|(k, v)| Ok((k, v.try_into()?)))
-                    .collect::<Result<HashMap<SmolStr, _>, Self::Error>>()?,
+                    .collect::<Result<BTreeMap<SmolStr, _>, Self::Error>>()?,
             )),
             Expr::ExtFuncCall(ExtFuncCall { call }) => {
                 match call.len() {
@@ -661,6 +722,8 @@ impl From<ast::Expr> for Expr {
                     ast::BinaryOp::Contains => Expr::contains(Arc::new(arg1), arg2),
                     ast::BinaryOp::ContainsAll => Expr::contains_all(Arc::new(arg1), arg2),
                     ast::BinaryOp::ContainsAny => Expr::contains_any(Arc::new(arg1), arg2),
+                    ast::BinaryOp::HasTag => Expr::has_tag(Arc::new(arg1), arg2),
+                    ast::BinaryOp::GetTag => Expr::get_tag(Arc::new(arg1), arg2),
                 }
             }
             ast::ExprKind::MulByConst { arg, constant } => Expr::mul(
@@ -781,14 +844,31 @@ impl TryFrom<cst::Relation> for Expr {
     fn try_from(r: cst::Relation) -> Result<Expr, ParseErrors> {
         match r {
             cst::Relation::Common { initial, extended } => {
-                let mut expr = match initial.node {
-                    Some(a) => a.try_into(),
-                    None => Err(ParseError::ToAST(ToASTError::MissingNodeData).into()),
-                }?;
-                for (op, ASTNode { node, .. }) in extended {
-                    let rhs = match node {
+                let is_numeric = |op: &cst::RelOp| {
+                    matches!(
+                        op,
+                        cst::RelOp::Less
+                            | cst::RelOp::LessEq
+                            | cst::RelOp::Greater
+                            | cst::RelOp::GreaterEq
+                    )
+                };
+                let mut expr = if extended.first().is_some_and(|(op, _)| is_numeric(op)) {
+                    interpret_operand(initial.node, hex::as_hex_literal_add)
+                } else {
+                    match initial.node {
                         Some(a) => a.try_into(),
                         None => Err(ParseError::ToAST(ToASTError::MissingNodeData).into()),
+                    }
+                }?;
+                for (op, ASTNode { node, .. }) in extended {
+                    let rhs = if is_numeric(&op) {
+                        interpret_operand(node, hex::as_hex_literal_add)
+                    } else {
+                        match node {
+                            Some(a) => a.try_into(),
+                            None => Err(ParseError::ToAST(ToASTError::MissingNodeData).into()),
+                        }
                     }?;
                     match op {
                         cst::RelOp::Eq => {
@@ -870,15 +950,16 @@ impl TryFrom<cst::Relation> for Expr {
 impl TryFrom<cst::Add> for Expr {
     type Error = ParseErrors;
     fn try_from(a: cst::Add) -> Result<Expr, ParseErrors> {
-        let mut expr = match a.initial.node {
-            Some(m) => m.try_into(),
-            None => Err(ParseError::ToAST(ToASTError::MissingNodeData).into()),
-        }?;
-        for (op, node) in a.extended {
-            let rhs = match node.node {
+        let mut expr = if a.extended.is_empty() {
+            match a.initial.node {
                 Some(m) => m.try_into(),
                 None => Err(ParseError::ToAST(ToASTError::MissingNodeData).into()),
-            }?;
+            }
+        } else {
+            interpret_operand(a.initial.node, hex::as_hex_literal_mult)
+        }?;
+        for (op, node) in a.extended {
+            let rhs = interpret_operand(node.node, hex::as_hex_literal_mult)?;
             match op {
                 cst::AddOp::Plus => {
                     expr = Expr::add(expr, rhs);
@@ -956,15 +1037,16 @@ fn is_primary_name(primary: cst::Primary) -> Option<cst::Name> {
 impl TryFrom<cst::Mult> for Expr {
     type Error = ParseErrors;
     fn try_from(m: cst::Mult) -> Result<Expr, ParseErrors> {
-        let mut expr = match m.initial.node {
-            Some(u) => u.try_into(),
-            None => Err(ParseError::ToAST(ToASTError::MissingNodeData).into()),
-        }?;
-        for (op, node) in m.extended {
-            let rhs = match node.node {
+        let mut expr = if m.extended.is_empty() {
+            match m.initial.node {
                 Some(u) => u.try_into(),
                 None => Err(ParseError::ToAST(ToASTError::MissingNodeData).into()),
-            }?;
+            }
+        } else {
+            interpret_operand(m.initial.node, hex::as_hex_literal_unary)
+        }?;
+        for (op, node) in m.extended {
+            let rhs = interpret_operand(node.node, hex::as_hex_literal_unary)?;
             match op {
                 cst::MultOp::Times => {
                     expr = Expr::mul(expr, rhs);
@@ -1167,11 +1249,18 @@ fn interpret_primary(p: cst::Primary) -> Result<Either<ast::Name, Expr>, ParseEr
 impl TryFrom<cst::Member> for Expr {
     type Error = ParseErrors;
     fn try_from(m: cst::Member) -> Result<Expr, ParseErrors> {
+        // a hex literal receiver of a method call takes the type the method
+        // expects, so keep it to convert with the call
+        let hex_receiver = m
+            .item
+            .as_inner()
+            .and_then(hex::as_hex_literal_primary)
+            .cloned();
         let mut item: Either<ast::Name, Expr> = match m.item.node {
             Some(p) => interpret_primary(p),
             None => Err(ParseError::ToAST(ToASTError::MissingNodeData).into()),
         }?;
-        for access in m.access {
+        for (access_idx, access) in m.access.into_iter().enumerate() {
             match access.node {
                 Some(cst::MemAccess::Field(ASTNode { node, .. })) => match node {
                     Some(cst::Ident::Ident(i)) => {
@@ -1201,27 +1290,19 @@ impl TryFrom<cst::Member> for Expr {
                     //      method call instead.
                     //   - any other expression: it's an illegal call as the target is a higher order expression
                     item = match item {
-                        Either::Left(name) => Either::Right(Expr::ext_call(
-                            name.to_string().into(),
-                            args.into_iter()
-                                .map(|ASTNode { node, .. }| match node {
-                                    Some(expr) => expr.try_into(),
-                                    None => {
-                                        Err(ParseError::ToAST(ToASTError::MissingNodeData).into())
-                                    }
-                                })
-                                .collect::<Result<Vec<_>, _>>()?,
-                        )),
+                        Either::Left(name) => {
+                            let args = interpret_call_args(args, Some(&name), 0)?;
+                            Either::Right(Expr::ext_call(name.to_string().into(), args))
+                        }
                         Either::Right(Expr::ExprNoExt(ExprNoExt::GetAttr { left, attr })) => {
-                            let args = args
-                                .into_iter()
-                                .map(|node| match node.node {
-                                    Some(arg) => arg.try_into(),
-                                    None => {
-                                        Err(ParseError::ToAST(ToASTError::MissingNodeData).into())
-                                    }
-                                })
-                                .collect::<Result<Vec<Expr>, ParseErrors>>()?;
+                            let method = ast::Name::parse_unqualified_name(&attr).ok();
+                            let left = match &hex_receiver {
+                                Some(h) if access_idx == 1 => {
+                                    Arc::new(hex_literal(h, method.as_ref(), 0)?)
+                                }
+                                _ => left,
+                            };
+                            let args = interpret_call_args(args, method.as_ref(), 1)?;
                             let args = args.into_iter();
                             match attr.as_str() {
                                 "contains" => Either::Right(Expr::contains(
@@ -1271,6 +1352,71 @@ impl TryFrom<cst::Member> for Expr {
     }
 }
 
+/// Convert the arguments of a call to the function `func`, starting at
+/// argument `first`, giving each hex literal the type `func` expects
+fn interpret_call_args(
+    args: Vec<ASTNode<Option<cst::Expr>>>,
+    func: Option<&ast::Name>,
+    first: usize,
+) -> Result<Vec<Expr>, ParseErrors> {
+    args.into_iter()
+        .enumerate()
+        .map(|(i, ASTNode { node, .. })| match node {
+            Some(arg) => match hex::as_hex_literal(&arg) {
+                Some(h) => hex_literal(h, func, first + i),
+                None => arg.try_into(),
+            },
+            None => Err(ParseError::ToAST(ToASTError::MissingNodeData).into()),
+        })
+        .collect()
+}
+
+/// Convert an operand of an arithmetic or comparison operator, which may be a
+/// hex literal, as found by `as_hex`
+fn interpret_operand<T: TryInto<Expr, Error = ParseErrors>>(
+    node: Option<T>,
+    as_hex: impl Fn(&T) -> Option<&SmolStr>,
+) -> Result<Expr, ParseErrors> {
+    match node {
+        Some(node) => match as_hex(&node) {
+            Some(h) => hex_literal_of_type(h, hex::HEX_OPERAND_CONSTRUCTOR),
+            None => node.try_into(),
+        },
+        None => Err(ParseError::ToAST(ToASTError::MissingNodeData).into()),
+    }
+}
+
+/// Construct the value of the hex literal `h`, which is argument `index` of
+/// the function `func`, if any
+fn hex_literal(h: &SmolStr, func: Option<&ast::Name>, index: usize) -> Result<Expr, ParseErrors> {
+    hex_literal_of_type(h, hex::hex_literal_constructor(func, index))
+}
+
+/// Construct the value of the hex literal `h` with the extension function
+/// `constructor`, if it has a whole number of bytes
+fn hex_literal_of_type(h: &SmolStr, constructor: &str) -> Result<Expr, ParseErrors> {
+    hex::check_hex_digits(h).map_err(ParseError::ToAST)?;
+    Ok(Expr::ext_call(
+        constructor.into(),
+        vec![Expr::lit(JSONValue::String(h.clone()))],
+    ))
+}
+
+/// Apply the operator `op` to `left` and `right`: with an operand of an
+/// extension type which overloads `op`, this calls the extension function
+/// implementing it, and otherwise it's the built-in operator `builtin`
+fn overloadable(
+    op: OverloadedOperator,
+    left: ast::Expr,
+    right: ast::Expr,
+    builtin: impl FnOnce(ast::Expr, ast::Expr) -> ast::Expr,
+) -> Result<ast::Expr, MixedOperandTypes> {
+    Ok(match overloaded_operator_func(op, [&left, &right])? {
+        Some(func) => ast::Expr::call_extension_fn(func, vec![left, right]),
+        None => builtin(left, right),
+    })
+}
+
 fn extract_single_argument(
     es: impl ExactSizeIterator<Item = Expr>,
     fn_name: &'static str,
@@ -1298,6 +1444,7 @@ impl TryFrom<cst::Literal> for Expr {
                     ParseError::ToAST(ToASTError::IntegerLiteralTooLarge(n))
                 })?)))
             }
+            cst::Literal::Hex(h) => hex_literal(&h, None, 0),
             cst::Literal::Str(ASTNode { node, .. }) => match node {
                 Some(cst::Str::String(s)) => Ok(Expr::lit(JSONValue::String(s))),
                 Some(cst::Str::Invalid(invalid_str)) => Err(ParseError::ToAST(
@@ -19,27 +19,52 @@
 use crate::ast::*;
 use crate::entities::{Dereference, Entities, EntityAttrValues};
 use crate::extensions::Extensions;
-#[cfg(test)]
-use std::collections::HashMap;
 use std::sync::Arc;
 
 mod err;
 pub(crate) use err::*;
+#[cfg(feature = "bytecode")]
+mod bytecode;
+#[cfg(feature = "bytecode")]
+pub use bytecode::Program;
 pub use err::{EvaluationError, EvaluationErrorKind};
+mod overlay;
+mod record;
 use itertools::Either;
+pub use overlay::EntityOverlay;
+pub use record::{Consulted, EntityAccess, EvaluationRecord, ExtensionCall};
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
 use smol_str::SmolStr;
 
 const REQUIRED_STACK_SPACE: usize = 1024 * 100;
 
-// PANIC SAFETY `Name`s in here are valid `Name`s
-#[allow(clippy::expect_used)]
 mod names {
     use super::Name;
     lazy_static::lazy_static! {
-        pub static ref ANY_ENTITY_TYPE : Name = Name::parse_unqualified_name("any_entity_type").expect("valid identifier");
+        pub static ref ANY_ENTITY_TYPE : Name = Name::builtin("any_entity_type");
     }
 }
 
+/// How an [`Evaluator`] treats an access of an attribute which the entity or
+/// record does not have, such as an optional attribute accessed without a
+/// `has` guard
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MissingAttributeMode {
+    /// The access is an error, so the policy is not satisfied, and the error
+    /// is reported
+    #[default]
+    Error,
+    /// The condition of the policy evaluates to `false`, so the policy is not
+    /// satisfied, without an error
+    False,
+    /// The access is an unknown, named by the entity and attribute, like
+    /// `User::"alice".age`, or by the attribute of a record, so the policy has
+    /// a residual, as in partial evaluation
+    Unknown,
+}
+
 /// Evaluator object.
 ///
 /// Conceptually keeps the evaluation environment as part of its internal state,
@@ -66,6 +91,13 @@ pub struct Evaluator<'e> {
     ///
     /// We evaluate entity attribute expressions upon the creation of an evaluator.
     entity_attr_values: EntityAttrValues<'e>,
+    /// How accesses of missing attributes are treated
+    missing_attributes: MissingAttributeMode,
+    /// Where the entities and extension function calls consulted are
+    /// recorded, if anywhere
+    record: Option<&'e EvaluationRecord>,
+    /// Attributes and tags read in preference to those of `entities`, if any
+    overlay: Option<&'e EntityOverlay>,
 }
 
 /// Evaluator for "restricted" expressions. See notes on `RestrictedExpr`.
@@ -125,10 +157,10 @@ impl<'e> RestrictedEvaluator<'e> {
                 let args = args
                     .iter()
                     .map(|arg| self.partial_interpret(BorrowedRestrictedExpr::new_unchecked(arg))) // assuming the invariant holds for `e`, it will hold here
-                    .collect::<Result<Vec<_>>>()?;
+                    .collect::<Result<SmallVec<[_; 4]>>>()?;
                 match split(args) {
                     Either::Left(values) => {
-                        let values : Vec<_> = values.collect();
+                        let values : SmallVec<[_; 4]> = values.collect();
                         let efunc = self.extensions.func(fn_name)?;
                         efunc.call(&values)
                     },
@@ -178,9 +210,85 @@ impl<'q, 'e> Evaluator<'e> {
             entities,
             extensions,
             entity_attr_values,
+            missing_attributes: MissingAttributeMode::default(),
+            record: None,
+            overlay: None,
         })
     }
 
+    /// Treat accesses of missing attributes as `mode` says, rather than as
+    /// errors
+    #[must_use]
+    pub fn with_missing_attributes(mut self, mode: MissingAttributeMode) -> Self {
+        self.missing_attributes = mode;
+        self
+    }
+
+    /// Record the entities and extension function calls consulted while
+    /// evaluating in `record`
+    #[must_use]
+    pub fn recording(mut self, record: &'e EvaluationRecord) -> Self {
+        self.record = Some(record);
+        self
+    }
+
+    /// Read the attributes and tags set in `overlay` in preference to those
+    /// of the entities
+    #[must_use]
+    pub fn with_overlay(mut self, overlay: &'e EntityOverlay) -> Self {
+        self.overlay = Some(overlay);
+        self
+    }
+
+    /// Evaluate `value`, if the overlay sets one
+    fn overlaid(&self, value: Option<&RestrictedExpr>) -> Option<Result<PartialValue>> {
+        value.map(|value| {
+            RestrictedEvaluator::new(self.extensions).partial_interpret(value.as_borrowed())
+        })
+    }
+
+    /// Record that the entity `uid` was looked up, and what `read` of it
+    fn record_entity(&self, uid: &EntityUID, read: impl FnOnce(&mut EntityAccess)) {
+        if let Some(record) = self.record {
+            record.entity(uid, read);
+        }
+    }
+
+    /// Call the extension function `fn_name` with `args`, recording the call
+    fn call_extension_fn(&self, fn_name: &Name, args: &[Value]) -> Result<PartialValue> {
+        let result = self.extensions.func(fn_name)?.call(args);
+        if let Some(record) = self.record {
+            record.extension_call(ExtensionCall {
+                function: fn_name.clone(),
+                args: args.to_vec(),
+                result: match &result {
+                    Ok(PartialValue::Value(value)) => Some(Ok(value.clone())),
+                    Ok(PartialValue::Residual(_)) => None,
+                    Err(e) => Some(Err(e.to_string())),
+                },
+            });
+        }
+        result
+    }
+
+    /// `result`, or `unsatisfied` if it is an error accessing a missing
+    /// attribute and such accesses make the condition `false`
+    fn unless_missing_attribute<T>(&self, result: Result<T>, unsatisfied: T) -> Result<T> {
+        match result {
+            Err(e)
+                if self.missing_attributes == MissingAttributeMode::False
+                    && matches!(
+                        e.error_kind(),
+                        EvaluationErrorKind::EntityAttrDoesNotExist { .. }
+                            | EvaluationErrorKind::RecordAttrDoesNotExist(..)
+                    ) =>
+            {
+                Ok(unsatisfied)
+            }
+            result => result,
+        }
+    }
+
     /// Evaluate the given `Policy`, returning either a bool or an error.
     /// The bool indicates whether the policy applies, ie, "is satisfied" for the
     /// current `request`.
@@ -188,7 +296,12 @@ impl<'q, 'e> Evaluator<'e> {
     /// it doesn't consider whether we're processing a `Permit` policy or a
     /// `Forbid` policy.
     pub fn evaluate(&self, p: &Policy) -> Result<bool> {
-        self.interpret(&p.condition(), p.env())?.get_as_bool()
+        let result = match self.evaluate_condition(p) {
+            Ok(PartialValue::Value(v)) => v.get_as_bool(),
+            Ok(PartialValue::Residual(r)) => Err(EvaluationError::non_value(r)),
+            Err(e) => Err(e),
+        };
+        self.unless_missing_attribute(result, false)
     }
 
     /// Partially evaluate the given `Policy`, returning one of:
@@ -201,10 +314,23 @@ impl<'q, 'e> Evaluator<'e> {
     /// it doesn't consider whether we're processing a `Permit` policy or a
     /// `Forbid` policy.
     pub fn partial_evaluate(&self, p: &Policy) -> Result<Either<bool, Expr>> {
-        match self.partial_interpret(&p.condition(), p.env())? {
-            PartialValue::Value(v) => v.get_as_bool().map(Either::Left),
-            PartialValue::Residual(e) => Ok(Either::Right(e)),
+        let result = match self.evaluate_condition(p) {
+            Ok(PartialValue::Value(v)) => v.get_as_bool().map(Either::Left),
+            Ok(PartialValue::Residual(e)) => Ok(Either::Right(e)),
+            Err(e) => Err(e),
+        };
+        self.unless_missing_attribute(result, Either::Left(false))
+    }
+
+    /// Partially evaluate the condition of `p`. With the `bytecode` feature,
+    /// this runs the program its template was compiled to, and only walks the
+    /// condition if the program meets a residual.
+    fn evaluate_condition(&self, p: &Policy) -> Result<PartialValue> {
+        #[cfg(feature = "bytecode")]
+        if let Some(v) = self.run_program(p.template().program(), p.env())? {
+            return Ok(PartialValue::Value(v));
         }
+        self.partial_interpret(&p.condition(), p.env())
     }
 
     /// Run an expression as far as possible.
@@ -219,9 +345,7 @@ impl<'q, 'e> Evaluator<'e> {
             Ok(e) => (e, None),
             Err(err) => {
                 let arg = Expr::val(format!("{err}"));
-                // PANIC SAFETY: Input to `parse` is fully static and a valid extension function name
-                #[allow(clippy::unwrap_used)]
-                let fn_name = "error".parse().unwrap();
+                let fn_name = Name::builtin("error");
                 (
                     PartialValue::Residual(Expr::call_extension_fn(fn_name, vec![arg])),
                     Some(err),
@@ -323,19 +447,7 @@ impl<'q, 'e> Evaluator<'e> {
                 }
             }
             ExprKind::UnaryApp { op, arg } => match self.partial_interpret(arg, slots)? {
-                PartialValue::Value(arg) => match op {
-                    UnaryOp::Not => match arg.get_as_bool()? {
-                        true => Ok(false.into()),
-                        false => Ok(true.into()),
-                    },
-                    UnaryOp::Neg => {
-                        let i = arg.get_as_long()?;
-                        match i.checked_neg() {
-                            Some(v) => Ok(v.into()),
-                            None => Err(IntegerOverflowError::UnaryOp { op: *op, arg }.into()),
-                        }
-                    }
-                },
+                PartialValue::Value(arg) => unary_app(*op, arg),
                 // NOTE, there was a bug here found during manual review. (I forgot to wrap in unary_app call)
                 // Could be a nice target for fault injection
                 PartialValue::Residual(r) => Ok(PartialValue::Residual(Expr::unary_app(*op, r))),
@@ -359,150 +471,21 @@ impl<'q, 'e> Evaluator<'e> {
                         return Ok(PartialValue::Residual(Expr::binary_app(*op, e1, e2)))
                     }
                 };
-                match op {
-                    BinaryOp::Eq => Ok((arg1 == arg2).into()),
-                    // comparison and arithmetic operators, which only work on Longs
-                    BinaryOp::Less | BinaryOp::LessEq | BinaryOp::Add | BinaryOp::Sub => {
-                        let i1 = arg1.get_as_long()?;
-                        let i2 = arg2.get_as_long()?;
-                        match op {
-                            BinaryOp::Less => Ok((i1 < i2).into()),
-                            BinaryOp::LessEq => Ok((i1 <= i2).into()),
-                            BinaryOp::Add => match i1.checked_add(i2) {
-                                Some(sum) => Ok(sum.into()),
-                                None => Err(IntegerOverflowError::BinaryOp {
-                                    op: *op,
-                                    arg1,
-                                    arg2,
-                                }
-                                .into()),
-                            },
-                            BinaryOp::Sub => match i1.checked_sub(i2) {
-                                Some(diff) => Ok(diff.into()),
-                                None => Err(IntegerOverflowError::BinaryOp {
-                                    op: *op,
-                                    arg1,
-                                    arg2,
-                                }
-                                .into()),
-                            },
-                            // PANIC SAFETY `op` is checked to be one of the above
-                            #[allow(clippy::unreachable)]
-                            _ => {
-                                unreachable!("Should have already checked that op was one of these")
-                            }
-                        }
-                    }
-                    // hierarchy membership operator; see note on `BinaryOp::In`
-                    BinaryOp::In => {
-                        let uid1 = arg1.get_as_entity().map_err(|mut e|
-                            {
-                                // If arg1 is not an entity and arg2 is a set, then possibly
-                                // the user intended `arg2.contains(arg1)` rather than `arg1 in arg2`.
-                                // If arg2 is a record, then possibly they intended `arg2 has arg1`.
-                                if matches!(e.error_kind(), EvaluationErrorKind::TypeError { .. }) {
-                                    match arg2 {
-                                        Value::Set(_) => e.set_advice("`in` is for checking the entity hierarchy, use `.contains()` to test set membership".into()),
-                                        Value::Record(_) =>  e.set_advice("`in` is for checking the entity hierarchy, use `has` to test if a record has a key".into()),
-                                        _ => {}
-                                    }
-                                };
-                                e
-                            })?;
-                        match self.entities.entity(uid1) {
-                            Dereference::Residual(r) => Ok(PartialValue::Residual(
-                                Expr::binary_app(BinaryOp::In, r, arg2.into()),
-                            )),
-                            Dereference::NoSuchEntity => self.eval_in(uid1, None, arg2),
-                            Dereference::Data(e) => self.eval_in(uid1, Some(e), arg2),
-                        }
-                    }
-                    // contains, which works on Sets
-                    BinaryOp::Contains => match arg1 {
-                        Value::Set(Set { fast: Some(h), .. }) => match arg2.try_as_lit() {
-                            Some(lit) => Ok((h.contains(lit)).into()),
-                            None => Ok(false.into()), // we know it doesn't contain a non-literal
-                        },
-                        Value::Set(Set { authoritative, .. }) => {
-                            Ok((authoritative.contains(&arg2)).into())
-                        }
-                        _ => Err(EvaluationError::type_error(vec![Type::Set], arg1.type_of())),
-                    },
-                    // ContainsAll and ContainsAny, which work on Sets
-                    BinaryOp::ContainsAll | BinaryOp::ContainsAny => {
-                        let arg1_set = arg1.get_as_set()?;
-                        let arg2_set = arg2.get_as_set()?;
-                        match (&arg1_set.fast, &arg2_set.fast) {
-                            (Some(arg1_set), Some(arg2_set)) => {
-                                // both sets are in fast form, ie, they only contain literals.
-                                // Fast hashset-based implementation.
-                                match op {
-                                    BinaryOp::ContainsAll => {
-                                        Ok((arg2_set.is_subset(arg1_set)).into())
-                                    }
-                                    BinaryOp::ContainsAny => {
-                                        Ok((!arg1_set.is_disjoint(arg2_set)).into())
-                                    }
-                                    // PANIC SAFETY `op` is checked to be one of these two above
-                                    #[allow(clippy::unreachable)]
-                                    _ => unreachable!(
-                                        "Should have already checked that op was one of these"
-                                    ),
-                                }
-                            }
-                            (_, _) => {
-                                // one or both sets are in slow form, ie, contain a non-literal.
-                                // Fallback to slow implementation.
-                                match op {
-                                    BinaryOp::ContainsAll => {
-                                        let is_subset = arg2_set
-                                            .authoritative
-                                            .iter()
-                                            .all(|item| arg1_set.authoritative.contains(item));
-                                        Ok(is_subset.into())
-                                    }
-                                    BinaryOp::ContainsAny => {
-                                        let not_disjoint = arg1_set
-                                            .authoritative
-                                            .iter()
-                                            .any(|item| arg2_set.authoritative.contains(item));
-                                        Ok(not_disjoint.into())
-                                    }
-                                    // PANIC SAFETY `op` is checked to be one of these two above
-                                    #[allow(clippy::unreachable)]
-                                    _ => unreachable!(
-                                        "Should have already checked that op was one of these"
-                                    ),
-                                }
-                            }
-                        }
-                    }
-                }
+                self.binary_app(*op, arg1, arg2)
             }
             ExprKind::MulByConst { arg, constant } => match self.partial_interpret(arg, slots)? {
-                PartialValue::Value(arg) => {
-                    let i1 = arg.get_as_long()?;
-                    match i1.checked_mul(*constant) {
-                        Some(prod) => Ok(prod.into()),
-                        None => Err(IntegerOverflowError::Multiplication {
-                            arg,
-                            constant: *constant,
-                        }
-                        .into()),
-                    }
-                }
+                PartialValue::Value(arg) => mul_by_const(arg, *constant),
                 PartialValue::Residual(r) => Ok(PartialValue::Residual(Expr::mul(r, *constant))),
             },
             ExprKind::ExtensionFunctionApp { fn_name, args } => {
                 let args = args
                     .iter()
                     .map(|arg| self.partial_interpret(arg, slots))
-                    .collect::<Result<Vec<_>>>()?;
+                    .collect::<Result<SmallVec<[_; 4]>>>()?;
                 match split(args) {
                     Either::Left(vals) => {
-                        let vals: Vec<_> = vals.collect();
-                        let efunc = self.extensions.func(fn_name)?;
-                        efunc.call(&vals)
+                        let vals: SmallVec<[_; 4]> = vals.collect();
+                        self.call_extension_fn(fn_name, &vals)
                     }
                     Either::Right(residuals) => Ok(PartialValue::Residual(
                         Expr::call_extension_fn(fn_name.clone(), residuals.collect()),
@@ -511,23 +494,7 @@ impl<'q, 'e> Evaluator<'e> {
             }
             ExprKind::GetAttr { expr, attr } => self.get_attr(expr.as_ref(), attr, slots),
             ExprKind::HasAttr { expr, attr } => match self.partial_interpret(expr, slots)? {
-                PartialValue::Value(Value::Record(record)) => Ok(record.get(attr).is_some().into()),
-                PartialValue::Value(Value::Lit(Literal::EntityUID(uid))) => {
-                    match self.entities.entity(&uid) {
-                        Dereference::NoSuchEntity => Ok(false.into()),
-                        Dereference::Residual(r) => {
-                            Ok(PartialValue::Residual(Expr::has_attr(r, attr.clone())))
-                        }
-                        Dereference::Data(e) => Ok(e.get(attr).is_some().into()),
-                    }
-                }
-                PartialValue::Value(val) => Err(err::EvaluationError::type_error(
-                    vec![
-                        Type::Record,
-                        Type::entity_type(names::ANY_ENTITY_TYPE.clone()),
-                    ],
-                    val.type_of(),
-                )),
+                PartialValue::Value(val) => self.has_attr(val, attr),
                 PartialValue::Residual(r) => Ok(Expr::has_attr(r, attr.clone()).into()),
             },
             ExprKind::Like { expr, pattern } => {
@@ -591,7 +558,7 @@ impl<'q, 'e> Evaluator<'e> {
         for uid2 in rhs {
             if uid1 == &uid2
                 || entity1
-                    .map(|e1| e1.is_descendant_of(&uid2))
+                    .map(|e1| self.entities.is_descendant_of(e1, &uid2))
                     .unwrap_or(false)
             {
                 return Ok(true.into());
@@ -670,17 +637,203 @@ impl<'q, 'e> Evaluator<'e> {
                     _ => Ok(PartialValue::Residual(Expr::get_attr(e, attr.clone()))),
                 }
             }
-            PartialValue::Value(Value::Record(attrs)) => attrs
-                .as_ref()
-                .get(attr)
-                .ok_or_else(|| {
-                    EvaluationError::record_attr_does_not_exist(
-                        attr.clone(),
-                        attrs.iter().map(|(f, _)| f.clone()).collect(),
-                    )
-                })
-                .map(|v| PartialValue::Value(v.clone())),
-            PartialValue::Value(Value::Lit(Literal::EntityUID(uid))) => {
+            PartialValue::Value(v) => self.get_attr_of(&v, attr),
+        }
+    }
+
+    /// Apply `op` to `arg1` and `arg2`
+    fn binary_app(&self, op: BinaryOp, arg1: Value, arg2: Value) -> Result<PartialValue> {
+        match op {
+            BinaryOp::Eq => Ok((arg1 == arg2).into()),
+            // comparison and arithmetic operators, which only work on Longs
+            BinaryOp::Less | BinaryOp::LessEq | BinaryOp::Add | BinaryOp::Sub => {
+                let i1 = arg1.get_as_long()?;
+                let i2 = arg2.get_as_long()?;
+                match op {
+                    BinaryOp::Less => Ok((i1 < i2).into()),
+                    BinaryOp::LessEq => Ok((i1 <= i2).into()),
+                    BinaryOp::Add => match i1.checked_add(i2) {
+                        Some(sum) => Ok(sum.into()),
+                        None => Err(IntegerOverflowError::BinaryOp { op, arg1, arg2 }.into()),
+                    },
+                    BinaryOp::Sub => match i1.checked_sub(i2) {
+                        Some(diff) => Ok(diff.into()),
+                        None => Err(IntegerOverflowError::BinaryOp { op, arg1, arg2 }.into()),
+                    },
+                    // PANIC SAFETY `op` is checked to be one of the above
+                    #[allow(clippy::unreachable)]
+                    _ => {
+                        unreachable!("Should have already checked that op was one of these")
+                    }
+                }
+            }
+            // hierarchy membership operator; see note on `BinaryOp::In`
+            BinaryOp::In => {
+                let uid1 = arg1.get_as_entity().map_err(|mut e|
+                    {
+                        // If arg1 is not an entity and arg2 is a set, then possibly
+                        // the user intended `arg2.contains(arg1)` rather than `arg1 in arg2`.
+                        // If arg2 is a record, then possibly they intended `arg2 has arg1`.
+                        if matches!(e.error_kind(), EvaluationErrorKind::TypeError { .. }) {
+                            match arg2 {
+                                Value::Set(_) => e.set_advice("`in` is for checking the entity hierarchy, use `.contains()` to test set membership".into()),
+                                Value::Record(_) =>  e.set_advice("`in` is for checking the entity hierarchy, use `has` to test if a record has a key".into()),
+                                _ => {}
+                            }
+                        };
+                        e
+                    })?;
+                self.record_entity(uid1, |read| read.ancestors = true);
+                match self.entities.entity(uid1) {
+                    Dereference::Residual(r) => Ok(PartialValue::Residual(Expr::binary_app(
+                        BinaryOp::In,
+                        r,
+                        arg2.into(),
+                    ))),
+                    Dereference::NoSuchEntity => self.eval_in(uid1, None, arg2),
+                    Dereference::Data(e) => self.eval_in(uid1, Some(e), arg2),
+                }
+            }
+            // hasTag and getTag, which work on entities
+            BinaryOp::HasTag | BinaryOp::GetTag => {
+                let uid = arg1.get_as_entity()?;
+                let tag = arg2.get_as_string()?;
+                self.record_entity(uid, |read| {
+                    read.tags.insert(tag.clone());
+                });
+                if let Some(tag_val) = self.overlaid(self.overlay.and_then(|o| o.tag(uid, tag))) {
+                    return match op {
+                        BinaryOp::HasTag => Ok(true.into()),
+                        _ => tag_val,
+                    };
+                }
+                match self.entities.entity(uid) {
+                    Dereference::Residual(r) => {
+                        Ok(PartialValue::Residual(Expr::binary_app(op, r, arg2.into())))
+                    }
+                    Dereference::NoSuchEntity => match op {
+                        BinaryOp::HasTag => Ok(false.into()),
+                        _ => Err(EvaluationError::entity_does_not_exist(Arc::new(
+                            uid.clone(),
+                        ))),
+                    },
+                    Dereference::Data(entity) => match (op, entity.get_tag(tag)) {
+                        (BinaryOp::HasTag, tag_val) => Ok(tag_val.is_some().into()),
+                        (_, Some(tag_val)) => RestrictedEvaluator::new(self.extensions)
+                            .partial_interpret(tag_val.as_borrowed()),
+                        (_, None) => Err(EvaluationError::entity_tag_does_not_exist(
+                            Arc::new(uid.clone()),
+                            tag.clone(),
+                        )),
+                    },
+                }
+            }
+            // contains, which works on Sets
+            BinaryOp::Contains => match arg1 {
+                Value::Set(Set { fast: Some(h), .. }) => match arg2.try_as_lit() {
+                    Some(lit) => Ok((h.contains(lit)).into()),
+                    None => Ok(false.into()), // we know it doesn't contain a non-literal
+                },
+                Value::Set(Set { authoritative, .. }) => Ok((authoritative.contains(&arg2)).into()),
+                _ => Err(EvaluationError::type_error(vec![Type::Set], arg1.type_of())),
+            },
+            // ContainsAll and ContainsAny, which work on Sets
+            BinaryOp::ContainsAll | BinaryOp::ContainsAny => {
+                let arg1_set = arg1.get_as_set()?;
+                let arg2_set = arg2.get_as_set()?;
+                match (&arg1_set.fast, &arg2_set.fast) {
+                    (Some(arg1_set), Some(arg2_set)) => {
+                        // both sets are in fast form, ie, they only contain literals.
+                        // Fast hashset-based implementation.
+                        match op {
+                            BinaryOp::ContainsAll => Ok((arg2_set.is_subset(arg1_set)).into()),
+                            BinaryOp::ContainsAny => Ok((!arg1_set.is_disjoint(arg2_set)).into()),
+                            // PANIC SAFETY `op` is checked to be one of these two above
+                            #[allow(clippy::unreachable)]
+                            _ => {
+                                unreachable!("Should have already checked that op was one of these")
+                            }
+                        }
+                    }
+                    (_, _) => {
+                        // one or both sets are in slow form, ie, contain a non-literal.
+                        // Fallback to slow implementation.
+                        match op {
+                            BinaryOp::ContainsAll => {
+                                let is_subset = arg2_set
+                                    .authoritative
+                                    .iter()
+                                    .all(|item| arg1_set.authoritative.contains(item));
+                                Ok(is_subset.into())
+                            }
+                            BinaryOp::ContainsAny => {
+                                let not_disjoint = arg1_set
+                                    .authoritative
+                                    .iter()
+                                    .any(|item| arg2_set.authoritative.contains(item));
+                                Ok(not_disjoint.into())
+                            }
+                            // PANIC SAFETY `op` is checked to be one of these two above
+                            #[allow(clippy::unreachable)]
+                            _ => {
+                                unreachable!("Should have already checked that op was one of these")
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Does `value`, an entity or record, have the given `attr`?
+    fn has_attr(&self, value: Value, attr: &SmolStr) -> Result<PartialValue> {
+        match value {
+            Value::Record(record) => Ok(record.get(attr).is_some().into()),
+            Value::Lit(Literal::EntityUID(uid)) => {
+                self.record_entity(&uid, |read| {
+                    read.attributes.insert(attr.clone());
+                });
+                if self.overlay.is_some_and(|o| o.attr(&uid, attr).is_some()) {
+                    return Ok(true.into());
+                }
+                match self.entities.entity(&uid) {
+                    Dereference::NoSuchEntity => Ok(false.into()),
+                    Dereference::Residual(r) => {
+                        Ok(PartialValue::Residual(Expr::has_attr(r, attr.clone())))
+                    }
+                    Dereference::Data(e) => Ok(e.get(attr).is_some().into()),
+                }
+            }
+            val => Err(err::EvaluationError::type_error(
+                vec![
+                    Type::Record,
+                    Type::entity_type(names::ANY_ENTITY_TYPE.clone()),
+                ],
+                val.type_of(),
+            )),
+        }
+    }
+
+    /// Get the given `attr` of `value`, an entity or record
+    fn get_attr_of(&self, value: &Value, attr: &SmolStr) -> Result<PartialValue> {
+        match value {
+            Value::Record(attrs) => match attrs.as_ref().get(attr) {
+                Some(v) => Ok(PartialValue::Value(v.clone())),
+                None if self.missing_attributes == MissingAttributeMode::Unknown => {
+                    Ok(PartialValue::Residual(Expr::unknown(attr.clone())))
+                }
+                None => Err(EvaluationError::record_attr_does_not_exist(
+                    attr.clone(),
+                    attrs.iter().map(|(f, _)| f.clone()).collect(),
+                )),
+            },
+            Value::Lit(Literal::EntityUID(uid)) => {
+                self.record_entity(uid, |read| {
+                    read.attributes.insert(attr.clone());
+                });
+                if let Some(v) = self.overlaid(self.overlay.and_then(|o| o.attr(uid, attr))) {
+                    return v;
+                }
                 match self.entity_attr_values.get(uid.as_ref()) {
                     Dereference::NoSuchEntity => Err(match *uid.entity_type() {
                         EntityType::Unspecified => {
@@ -693,31 +846,31 @@ impl<'q, 'e> Evaluator<'e> {
                     Dereference::Residual(r) => {
                         Ok(PartialValue::Residual(Expr::get_attr(r, attr.clone())))
                     }
-                    Dereference::Data(attrs) => attrs
-                        .get(attr)
-                        .ok_or_else(|| {
-                            EvaluationError::entity_attr_does_not_exist(uid, attr.clone())
-                        })
-                        .cloned(),
+                    Dereference::Data(attrs) => match attrs.get(attr) {
+                        Some(v) => Ok(v.clone()),
+                        None if self.missing_attributes == MissingAttributeMode::Unknown => Ok(
+                            PartialValue::Residual(Expr::unknown(format!("{uid}.{attr}"))),
+                        ),
+                        None => Err(EvaluationError::entity_attr_does_not_exist(
+                            uid.clone(),
+                            attr.clone(),
+                        )),
+                    },
                 }
             }
-            PartialValue::Value(v) => {
-                // PANIC SAFETY Entity type name is fully static and a valid unqualified `Name`
-                #[allow(clippy::unwrap_used)]
-                Err(EvaluationError::type_error(
-                    vec![
-                        Type::Record,
-                        Type::entity_type(Name::parse_unqualified_name("any_entity_type").unwrap()),
-                    ],
-                    v.type_of(),
-                ))
-            }
+            v => Err(EvaluationError::type_error(
+                vec![
+                    Type::Record,
+                    Type::entity_type(names::ANY_ENTITY_TYPE.clone()),
+                ],
+                v.type_of(),
+            )),
         }
     }
 
     #[cfg(test)]
     pub fn interpret_inline_policy(&self, e: &Expr) -> Result<Value> {
-        match self.partial_interpret(e, &HashMap::new())? {
+        match self.partial_interpret(e, &SlotEnv::new())? {
             PartialValue::Value(v) => Ok(v),
             PartialValue::Residual(r) => Err(err::EvaluationError::non_value(r)),
         }
@@ -807,6 +960,32 @@ impl Value {
     }
 }
 
+/// Apply `op` to `arg`
+fn unary_app(op: UnaryOp, arg: Value) -> Result<PartialValue> {
+    match op {
+        UnaryOp::Not => match arg.get_as_bool()? {
+            true => Ok(false.into()),
+            false => Ok(true.into()),
+        },
+        UnaryOp::Neg => {
+            let i = arg.get_as_long()?;
+            match i.checked_neg() {
+                Some(v) => Ok(v.into()),
+                None => Err(IntegerOverflowError::UnaryOp { op, arg }.into()),
+            }
+        }
+    }
+}
+
+/// Multiply `arg` by `constant`
+fn mul_by_const(arg: Value, constant: i64) -> Result<PartialValue> {
+    let i1 = arg.get_as_long()?;
+    match i1.checked_mul(constant) {
+        Some(prod) => Ok(prod.into()),
+        None => Err(IntegerOverflowError::Multiplication { arg, constant }.into()),
+    }
+}
+
 #[inline(always)]
 fn stack_size_check() -> Result<()> {
     #[cfg(not(target_arch = "wasm32"))]
@@ -820,7 +999,10 @@ fn stack_size_check() -> Result<()> {
 
 #[cfg(test)]
 pub mod test {
-    use std::{collections::HashMap, str::FromStr};
+    use std::{
+        collections::{HashMap, HashSet},
+        str::FromStr,
+    };
 
     use super::*;
 
@@ -3004,6 +3186,89 @@ pub mod test {
         );
     }
 
+    #[test]
+    fn interpret_hierarchy_membership_lazy_tc() {
+        // Alice -> Friends -> Everyone, with no transitive edge stored
+        let request = basic_request();
+        let mut alice = Entity::with_uid(EntityUID::with_eid("Alice"));
+        alice.add_ancestor(EntityUID::with_eid("Friends"));
+        let mut friends = Entity::with_uid(EntityUID::with_eid("Friends"));
+        friends.add_ancestor(EntityUID::with_eid("Everyone"));
+        let entities = Entities::from_entities(vec![alice, friends], TCComputation::ComputeLazily)
+            .expect("failed to create entities");
+        let exts = Extensions::none();
+        let eval = Evaluator::new(&request, &entities, &exts).expect("failed to create evaluator");
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::is_in(
+                Expr::val(EntityUID::with_eid("Alice")),
+                Expr::val(EntityUID::with_eid("Everyone"))
+            )),
+            Ok(Value::Lit(Literal::Bool(true)))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::is_in(
+                Expr::val(EntityUID::with_eid("Everyone")),
+                Expr::val(EntityUID::with_eid("Alice"))
+            )),
+            Ok(Value::Lit(Literal::Bool(false)))
+        );
+    }
+
+    #[test]
+    fn interpret_tags() {
+        let request = basic_request();
+        let alice = Entity::new_with_tags(
+            EntityUID::with_eid("Alice"),
+            HashMap::from([("risk".into(), RestrictedExpr::val(3))]),
+            HashSet::new(),
+            HashMap::from([("risk".into(), RestrictedExpr::val(87))]),
+        );
+        let entities = Entities::from_entities(vec![alice], TCComputation::ComputeNow)
+            .expect("failed to create entities");
+        let exts = Extensions::none();
+        let eval = Evaluator::new(&request, &entities, &exts).expect("failed to create evaluator");
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"test_entity_type::"Alice".hasTag("risk")"#).expect("parsing error")
+            ),
+            Ok(Value::Lit(Literal::Bool(true)))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"test_entity_type::"Alice".hasTag("sanctioned")"#)
+                    .expect("parsing error")
+            ),
+            Ok(Value::Lit(Literal::Bool(false)))
+        );
+        // tags are separate from attributes
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"test_entity_type::"Alice".getTag("risk")"#).expect("parsing error")
+            ),
+            Ok(Value::Lit(Literal::Long(87)))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"test_entity_type::"Alice".risk"#).expect("parsing error")
+            ),
+            Ok(Value::Lit(Literal::Long(3)))
+        );
+        assert_matches!(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"test_entity_type::"Alice".getTag("sanctioned")"#)
+                    .expect("parsing error")
+            ),
+            Err(e) => assert_matches!(e.error_kind(), EvaluationErrorKind::EntityTagDoesNotExist { .. })
+        );
+        // `hasTag` on an entity which doesn't exist is false, like `has`
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"test_entity_type::"Bob".hasTag("risk")"#).expect("parsing error")
+            ),
+            Ok(Value::Lit(Literal::Bool(false)))
+        );
+    }
+
     #[test]
     fn interpret_string_like() {
         let request = basic_request();
@@ -3584,7 +3849,7 @@ pub mod test {
         let evaluator = Evaluator::new(&request, &entities, &exts).expect("empty slice");
         let e = Expr::slot(SlotId::principal());
 
-        let slots = HashMap::new();
+        let slots = SlotEnv::new();
         let r = evaluator.partial_interpret(&e, &slots);
         match r {
             Err(e) => match e.error_kind() {
@@ -3596,7 +3861,7 @@ pub mod test {
             Ok(v) => panic!("Got wrong response: {v}"),
         };
 
-        let mut slots = HashMap::new();
+        let mut slots = SlotEnv::new();
         slots.insert(SlotId::principal(), EntityUID::with_eid("eid"));
         let r = evaluator.partial_interpret(&e, &slots);
         match r {
@@ -3852,7 +4117,7 @@ pub mod test {
                     .collect();
                 let new_expr = expr.substitute(&m).unwrap();
                 assert_eq!(
-                    e.partial_interpret(&new_expr, &HashMap::new())
+                    e.partial_interpret(&new_expr, &SlotEnv::new())
                         .expect("Failed to eval"),
                     PartialValue::Value(true.into())
                 );
@@ -4062,7 +4327,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
 
         assert_eq!(
             r,
@@ -4103,7 +4368,7 @@ pub mod test {
         );
         let eval = Evaluator::new(&q, &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
 
         assert_eq!(
             r,
@@ -4132,7 +4397,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        assert!(eval.partial_interpret(&e, &HashMap::new()).is_err());
+        assert!(eval.partial_interpret(&e, &SlotEnv::new()).is_err());
     }
 
     #[test]
@@ -4147,7 +4412,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
 
         assert_eq!(r, PartialValue::Value(Value::Lit(Literal::Bool(false))));
     }
@@ -4164,7 +4429,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
 
         assert_eq!(
             r,
@@ -4187,7 +4452,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        assert!(eval.partial_interpret(&e, &HashMap::new()).is_err());
+        assert!(eval.partial_interpret(&e, &SlotEnv::new()).is_err());
     }
 
     #[test]
@@ -4202,7 +4467,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        assert!(eval.partial_interpret(&e, &HashMap::new()).is_ok());
+        assert!(eval.partial_interpret(&e, &SlotEnv::new()).is_ok());
     }
 
     #[test]
@@ -4218,7 +4483,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
 
         assert_eq!(r, PartialValue::Value(Value::Lit(Literal::Bool(true))));
     }
@@ -4235,7 +4500,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
 
         assert_eq!(
             r,
@@ -4258,7 +4523,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        assert!(eval.partial_interpret(&e, &HashMap::new()).is_err());
+        assert!(eval.partial_interpret(&e, &SlotEnv::new()).is_err());
     }
 
     #[test]
@@ -4273,7 +4538,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        assert!(eval.partial_interpret(&e, &HashMap::new()).is_ok());
+        assert!(eval.partial_interpret(&e, &SlotEnv::new()).is_ok());
     }
 
     #[test]
@@ -4287,7 +4552,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        assert!(eval.partial_interpret(&a, &HashMap::new()).is_err());
+        assert!(eval.partial_interpret(&a, &SlotEnv::new()).is_err());
     }
 
     #[test]
@@ -4301,7 +4566,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&a, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&a, &SlotEnv::new()).unwrap();
 
         let expected = PartialValue::Residual(Expr::unknown("test"));
 
@@ -4322,7 +4587,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        assert!(eval.partial_interpret(&a, &HashMap::new()).is_err());
+        assert!(eval.partial_interpret(&a, &SlotEnv::new()).is_err());
     }
 
     #[test]
@@ -4339,7 +4604,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&a, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&a, &SlotEnv::new()).unwrap();
 
         let expected = PartialValue::Residual(Expr::unknown("b"));
 
@@ -4357,7 +4622,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
 
         let expected = Expr::ite(guard, Expr::val(1), Expr::val(2));
 
@@ -4375,7 +4640,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
 
         let expected = Expr::ite(
             guard,
@@ -4400,7 +4665,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
 
         let expected = Expr::ite(
             guard,
@@ -4424,7 +4689,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        assert!(eval.partial_interpret(&e, &HashMap::new()).is_err());
+        assert!(eval.partial_interpret(&e, &SlotEnv::new()).is_err());
     }
 
     // err && res -> err
@@ -4437,7 +4702,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        assert!(eval.partial_interpret(&e, &HashMap::new()).is_err());
+        assert!(eval.partial_interpret(&e, &SlotEnv::new()).is_err());
     }
 
     // err || res -> err
@@ -4450,7 +4715,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        assert!(eval.partial_interpret(&e, &HashMap::new()).is_err());
+        assert!(eval.partial_interpret(&e, &SlotEnv::new()).is_err());
     }
 
     // true && res -> true && res
@@ -4463,7 +4728,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
 
         let expected = Expr::and(
             Expr::val(true),
@@ -4482,7 +4747,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
         assert_eq!(r, PartialValue::Value(Value::Lit(false.into())));
     }
 
@@ -4496,7 +4761,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
         let expected = Expr::and(lhs, Expr::val(true));
         assert_eq!(r, PartialValue::Residual(expected));
     }
@@ -4510,7 +4775,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
         let expected = Expr::and(lhs, Expr::val(false));
         assert_eq!(r, PartialValue::Residual(expected));
     }
@@ -4525,7 +4790,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
 
         let expected = Expr::and(
             Expr::unknown("b"),
@@ -4544,7 +4809,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
 
         let expected = Expr::and(
             Expr::get_attr(Expr::unknown("test"), "field".into()),
@@ -4566,7 +4831,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
         assert_eq!(r, PartialValue::Value(Value::Lit(true.into())));
     }
 
@@ -4580,7 +4845,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
         let expected = Expr::or(
             Expr::val(false),
             Expr::get_attr(Expr::unknown("test"), "field".into()),
@@ -4598,7 +4863,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
         let expected = Expr::or(lhs, Expr::val(true));
         assert_eq!(r, PartialValue::Residual(expected));
     }
@@ -4612,7 +4877,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
         let expected = Expr::or(lhs, Expr::val(false));
         assert_eq!(r, PartialValue::Residual(expected));
     }
@@ -4627,7 +4892,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
 
         let expected = Expr::or(
             Expr::unknown("b"),
@@ -4646,7 +4911,7 @@ pub mod test {
         let exts = Extensions::none();
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
 
         let expected = Expr::or(
             Expr::get_attr(Expr::unknown("test"), "field".into()),
@@ -4665,11 +4930,11 @@ pub mod test {
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
         let e = Expr::unary_app(UnaryOp::Neg, Expr::unknown("a"));
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
         assert_eq!(r, PartialValue::Residual(e));
 
         let e = Expr::unary_app(UnaryOp::Not, Expr::unknown("a"));
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
         assert_eq!(r, PartialValue::Residual(e));
     }
 
@@ -4698,7 +4963,7 @@ pub mod test {
                 Expr::binary_app(BinaryOp::Add, Expr::val(1), Expr::val(2)),
                 Expr::unknown("a"),
             );
-            let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+            let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
             let expected = Expr::binary_app(binop, Expr::val(3), Expr::unknown("a"));
             assert_eq!(r, PartialValue::Residual(expected));
             // ensure PE propagates left side errors
@@ -4707,14 +4972,14 @@ pub mod test {
                 Expr::binary_app(BinaryOp::Add, Expr::val("hello"), Expr::val(2)),
                 Expr::unknown("a"),
             );
-            assert!(eval.partial_interpret(&e, &HashMap::new()).is_err());
+            assert!(eval.partial_interpret(&e, &SlotEnv::new()).is_err());
             // ensure PE evaluates right side
             let e = Expr::binary_app(
                 binop,
                 Expr::unknown("a"),
                 Expr::binary_app(BinaryOp::Add, Expr::val(1), Expr::val(2)),
             );
-            let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+            let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
             let expected = Expr::binary_app(binop, Expr::unknown("a"), Expr::val(3));
             assert_eq!(r, PartialValue::Residual(expected));
             // ensure PE propagates right side errors
@@ -4723,10 +4988,10 @@ pub mod test {
                 Expr::unknown("a"),
                 Expr::binary_app(BinaryOp::Add, Expr::val("hello"), Expr::val(2)),
             );
-            assert!(eval.partial_interpret(&e, &HashMap::new()).is_err());
+            assert!(eval.partial_interpret(&e, &SlotEnv::new()).is_err());
             // Both left and right residuals
             let e = Expr::binary_app(binop, Expr::unknown("a"), Expr::unknown("b"));
-            let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+            let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
             let expected = Expr::binary_app(binop, Expr::unknown("a"), Expr::unknown("b"));
             assert_eq!(r, PartialValue::Residual(expected));
         }
@@ -4739,7 +5004,7 @@ pub mod test {
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
         let e = Expr::mul(Expr::unknown("a"), 32);
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
         assert_eq!(r, PartialValue::Residual(e));
     }
 
@@ -4751,7 +5016,7 @@ pub mod test {
 
         let e = Expr::call_extension_fn("ip".parse().unwrap(), vec![Expr::unknown("a")]);
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
 
         assert_eq!(r, PartialValue::Residual(e));
     }
@@ -4767,7 +5032,7 @@ pub mod test {
         let b = Expr::unknown("a");
         let e = Expr::call_extension_fn("isInRange".parse().unwrap(), vec![a, b]);
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
 
         assert_eq!(r, PartialValue::Residual(e));
 
@@ -4775,7 +5040,7 @@ pub mod test {
         let a = Expr::unknown("a");
         let e = Expr::call_extension_fn("isInRange".parse().unwrap(), vec![a, b]);
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
 
         assert_eq!(r, PartialValue::Residual(e));
 
@@ -4783,7 +5048,7 @@ pub mod test {
         let a = Expr::unknown("a");
         let e = Expr::call_extension_fn("isInRange".parse().unwrap(), vec![a, b]);
 
-        assert!(eval.partial_interpret(&e, &HashMap::new()).is_err());
+        assert!(eval.partial_interpret(&e, &SlotEnv::new()).is_err());
     }
 
     #[test]
@@ -4794,7 +5059,7 @@ pub mod test {
 
         let e = Expr::like(Expr::unknown("a"), []);
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
 
         assert_eq!(r, PartialValue::Residual(e));
     }
@@ -4807,7 +5072,7 @@ pub mod test {
 
         let e = Expr::has_attr(Expr::unknown("a"), "test".into());
 
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
 
         assert_eq!(r, PartialValue::Residual(e));
     }
@@ -4819,7 +5084,7 @@ pub mod test {
         let eval = Evaluator::new(&empty_request(), &es, &exts).unwrap();
 
         let e = Expr::set([Expr::val(1), Expr::unknown("a"), Expr::val(2)]);
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
         assert_eq!(r, PartialValue::Residual(e));
 
         let e = Expr::set([
@@ -4827,7 +5092,7 @@ pub mod test {
             Expr::unknown("a"),
             Expr::binary_app(BinaryOp::Add, Expr::val(1), Expr::val(2)),
         ]);
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
         assert_eq!(
             r,
             PartialValue::Residual(Expr::set([Expr::val(1), Expr::unknown("a"), Expr::val(3)]))
@@ -4838,7 +5103,7 @@ pub mod test {
             Expr::unknown("a"),
             Expr::binary_app(BinaryOp::Add, Expr::val(1), Expr::val("a")),
         ]);
-        assert!(eval.partial_interpret(&e, &HashMap::new()).is_err());
+        assert!(eval.partial_interpret(&e, &SlotEnv::new()).is_err());
     }
 
     #[test]
@@ -4852,11 +5117,11 @@ pub mod test {
             ("b".into(), Expr::unknown("a")),
             ("c".into(), Expr::val(2)),
         ]);
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
         assert_eq!(r, PartialValue::Residual(e));
 
         let e = Expr::record([("a".into(), Expr::val(1)), ("a".into(), Expr::unknown("a"))]);
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
         assert_eq!(
             r,
             PartialValue::Residual(Expr::record([
@@ -4866,7 +5131,7 @@ pub mod test {
         );
 
         let e = Expr::record([("a".into(), Expr::unknown("a")), ("a".into(), Expr::val(1))]);
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
         assert_eq!(
             r,
             PartialValue::Residual(Expr::record([
@@ -4883,7 +5148,7 @@ pub mod test {
                 Expr::binary_app(BinaryOp::Add, Expr::val(1), Expr::val(2)),
             ),
         ]);
-        let r = eval.partial_interpret(&e, &HashMap::new()).unwrap();
+        let r = eval.partial_interpret(&e, &SlotEnv::new()).unwrap();
         assert_eq!(
             r,
             PartialValue::Residual(Expr::record([
@@ -4901,7 +5166,7 @@ pub mod test {
                 Expr::binary_app(BinaryOp::Add, Expr::val(1), Expr::val("hello")),
             ),
         ]);
-        assert!(eval.partial_interpret(&e, &HashMap::new()).is_err());
+        assert!(eval.partial_interpret(&e, &SlotEnv::new()).is_err());
     }
 
     #[test]
@@ -4947,4 +5212,115 @@ pub mod test {
         );
         assert!(eval.partial_eval_expr(&e).is_err());
     }
+
+    #[test]
+    fn missing_attribute_modes() {
+        let q = basic_request();
+        let entities = rich_entities();
+        let exts = Extensions::none();
+        let policy = |src: &str| {
+            Policy::from(parser::parse_policy(Some("policy0".into()), src).expect("policy parses"))
+        };
+        let entity_attr = policy(
+            r#"permit(principal, action, resource) when { test_entity_type::"entity_with_attrs".missing == 1 };"#,
+        );
+        let record_attr =
+            policy(r#"permit(principal, action, resource) when { context.missing == 1 };"#);
+        let guarded = policy(
+            r#"permit(principal, action, resource) when { !(context has missing) || context.missing == 1 };"#,
+        );
+        let eval = |mode| {
+            Evaluator::new(&q, &entities, &exts)
+                .expect("evaluator")
+                .with_missing_attributes(mode)
+        };
+
+        let error = eval(MissingAttributeMode::Error);
+        assert_matches!(
+            error
+                .evaluate(&entity_attr)
+                .map_err(|e| e.error_kind().clone()),
+            Err(EvaluationErrorKind::EntityAttrDoesNotExist { .. })
+        );
+        assert_matches!(
+            error
+                .partial_evaluate(&record_attr)
+                .map_err(|e| e.error_kind().clone()),
+            Err(EvaluationErrorKind::RecordAttrDoesNotExist(..))
+        );
+
+        let to_false = eval(MissingAttributeMode::False);
+        assert_matches!(to_false.evaluate(&entity_attr), Ok(false));
+        assert_matches!(
+            to_false.partial_evaluate(&record_attr),
+            Ok(Either::Left(false))
+        );
+        // a `!` around the access does not make a missing attribute satisfy it
+        let negated =
+            policy(r#"permit(principal, action, resource) when { !(context.missing == 1) };"#);
+        assert_matches!(to_false.evaluate(&negated), Ok(false));
+
+        let unknown = eval(MissingAttributeMode::Unknown);
+        assert_matches!(
+            unknown.partial_evaluate(&entity_attr),
+            Ok(Either::Right(residual)) if residual.unknowns().any(|name| name == r#"test_entity_type::"entity_with_attrs".missing"#)
+        );
+        assert_matches!(
+            unknown.partial_evaluate(&record_attr),
+            Ok(Either::Right(residual)) if residual.unknowns().any(|name| name == "missing")
+        );
+
+        // accesses guarded by `has` are unaffected
+        for mode in [
+            MissingAttributeMode::Error,
+            MissingAttributeMode::False,
+            MissingAttributeMode::Unknown,
+        ] {
+            assert_matches!(eval(mode).evaluate(&guarded), Ok(true));
+        }
+    }
+
+    #[test]
+    fn reads_overlay() {
+        let q = basic_request();
+        let entities = rich_entities();
+        let exts = Extensions::none();
+        let with_attrs = EntityUID::with_eid("entity_with_attrs");
+        let missing = EntityUID::with_eid("missing");
+        let mut overlay = EntityOverlay::new();
+        overlay.set_attr(with_attrs.clone(), "spoon".into(), RestrictedExpr::val(1));
+        overlay.set_attr(missing.clone(), "score".into(), RestrictedExpr::val(2));
+        overlay.set_tag(missing, "flag".into(), RestrictedExpr::val(true));
+        let eval = Evaluator::new(&q, &entities, &exts)
+            .expect("evaluator")
+            .with_overlay(&overlay);
+        for (src, expected) in [
+            // set attributes replace those of the entity, and others are kept
+            (r#"test_entity_type::"entity_with_attrs".spoon == 1"#, true),
+            (r#"test_entity_type::"entity_with_attrs" has tags"#, true),
+            // entities which do not exist get the values set for them
+            (r#"test_entity_type::"missing".score == 2"#, true),
+            (r#"test_entity_type::"missing" has score"#, true),
+            (r#"test_entity_type::"missing" has other"#, false),
+            (r#"test_entity_type::"missing".getTag("flag")"#, true),
+            (r#"test_entity_type::"missing".hasTag("other")"#, false),
+        ] {
+            let expr = parser::parse_expr(src).expect("expression parses");
+            assert_eq!(
+                eval.interpret_inline_policy(&expr).expect("evaluates"),
+                Value::from(expected),
+                "{src}"
+            );
+        }
+        // the entities themselves are unchanged
+        let expr = parser::parse_expr(r#"test_entity_type::"entity_with_attrs".spoon"#)
+            .expect("expression parses");
+        assert_eq!(
+            Evaluator::new(&q, &entities, &exts)
+                .expect("evaluator")
+                .interpret_inline_policy(&expr)
+                .expect("evaluates"),
+            Value::from(787)
+        );
+    }
 }
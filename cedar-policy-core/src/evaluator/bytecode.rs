@@ -0,0 +1,635 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! This module contains the compilation of expressions to bytecode, and the
+//! interpreter running it.
+//!
+//! A [`Program`] is a flat list of instructions over numbered registers. Each
+//! instruction reads its operands from registers and writes its result to one;
+//! `&&`, `||` and `if` become jumps, so short-circuiting needs no recursion.
+//! Every register is written once and read once, so the interpreter moves
+//! values out of registers rather than cloning them.
+//!
+//! Compiling also does work once which evaluating an expression tree repeats
+//! on every request: literal sets and records (such as allowlists of
+//! addresses) are built into a constant pool, constant operands of binary
+//! operators are read from the pool directly, and attributes of variables
+//! (such as `context.value`) are read without copying the variable first.
+//!
+//! A program stops as soon as it meets a residual, and evaluates everything up
+//! to then in the same order as [`Evaluator::partial_interpret`], so fails
+//! with the same errors.
+
+use super::{mul_by_const, unary_app, Evaluator, Result};
+use crate::ast::*;
+use crate::memory::{vec_size, HeapSize};
+use smallvec::SmallVec;
+use smol_str::SmolStr;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// The index of a register
+type Reg = usize;
+
+/// An operand of a binary operator: a register, or a constant of the pool,
+/// which saves writing the constant to a register first
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+enum Operand {
+    Reg(Reg),
+    Const(usize),
+}
+
+/// An instruction of a [`Program`]
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+enum Instr {
+    /// Write the constant at `index` of the constant pool
+    Const { dst: Reg, index: usize },
+    /// Write the value of a variable
+    Var { dst: Reg, var: Var },
+    /// Write an attribute of a variable, without writing the variable first
+    VarAttr { dst: Reg, var: Var, attr: SmolStr },
+    /// Write the entity a slot is linked to
+    Slot { dst: Reg, slot: SlotId },
+    /// Stop, since the expression has an unknown
+    Unknown,
+    /// Check that `src` is a bool, and write it
+    Bool { dst: Reg, src: Reg },
+    /// Check that `cond` is a bool, and jump to `target` if it is `value`
+    JumpIf {
+        cond: Reg,
+        value: bool,
+        target: usize,
+    },
+    /// Jump to `target`
+    Jump { target: usize },
+    /// Apply a unary operator
+    Unary { dst: Reg, op: UnaryOp, arg: Reg },
+    /// Apply a binary operator
+    Binary {
+        dst: Reg,
+        op: BinaryOp,
+        arg1: Operand,
+        arg2: Operand,
+    },
+    /// Multiply by a constant
+    Mul { dst: Reg, arg: Reg, constant: i64 },
+    /// Call an extension function
+    Call {
+        dst: Reg,
+        fn_name: Name,
+        args: SmallVec<[Reg; 4]>,
+    },
+    /// Get an attribute of an entity or record
+    GetAttr { dst: Reg, src: Reg, attr: SmolStr },
+    /// Check whether an entity or record has an attribute
+    HasAttr { dst: Reg, src: Reg, attr: SmolStr },
+    /// Match a string against a pattern
+    Like {
+        dst: Reg,
+        src: Reg,
+        pattern: Pattern,
+    },
+    /// Build a set
+    Set { dst: Reg, elements: Vec<Reg> },
+    /// Build a record
+    Record {
+        dst: Reg,
+        fields: Vec<(SmolStr, Reg)>,
+    },
+}
+
+/// An expression compiled to bytecode, which [`Evaluator::run_program`] runs.
+/// See the [module documentation](self).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    instrs: Vec<Instr>,
+    /// Literals, and sets and records of them, which are built once when
+    /// compiling rather than on every run
+    constants: Vec<Value>,
+    /// The number of registers the program uses
+    registers: usize,
+}
+
+// `Value` does not implement `Hash`, so the constant pool is left out. Equal
+// programs still hash equally.
+impl std::hash::Hash for Program {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.instrs.hash(state);
+        self.registers.hash(state);
+    }
+}
+
+impl Program {
+    /// Compile `expr`
+    pub fn compile(expr: &Expr) -> Self {
+        let mut program = Self {
+            instrs: Vec::new(),
+            constants: Vec::new(),
+            registers: 1,
+        };
+        // the result is in register 0
+        program.compile_expr(expr, 0);
+        program
+    }
+
+    /// The number of instructions of the program
+    pub fn len(&self) -> usize {
+        self.instrs.len()
+    }
+
+    /// Does the program have no instructions? It never does, since every
+    /// expression compiles to at least one.
+    pub fn is_empty(&self) -> bool {
+        self.instrs.is_empty()
+    }
+
+    fn register(&mut self) -> Reg {
+        self.registers += 1;
+        self.registers - 1
+    }
+
+    /// Emit `instr`, returning its index
+    fn emit(&mut self, instr: Instr) -> usize {
+        self.instrs.push(instr);
+        self.instrs.len() - 1
+    }
+
+    /// Add `value` to the constant pool, returning its index
+    fn constant_index(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    /// Emit an instruction writing `value` to `dst`
+    fn emit_const(&mut self, dst: Reg, value: Value) {
+        let index = self.constant_index(value);
+        self.emit(Instr::Const { dst, index });
+    }
+
+    /// The value of `expr`, if it is a literal, or a set or record of them
+    fn constant(expr: &Expr) -> Option<Value> {
+        match expr.expr_kind() {
+            ExprKind::Lit(lit) => Some(lit.clone().into()),
+            ExprKind::Set(elements) => {
+                let vals = elements
+                    .iter()
+                    .map(Self::constant)
+                    .collect::<Option<Vec<_>>>()?;
+                Some(Value::set(vals))
+            }
+            ExprKind::Record { pairs } => {
+                let mut record = BTreeMap::new();
+                for (attr, value) in pairs.iter() {
+                    record.insert(attr.clone(), Self::constant(value)?);
+                }
+                Some(Value::Record(Arc::new(record)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Point the jump at `index` to the next instruction
+    fn patch(&mut self, index: usize) {
+        let next = self.instrs.len();
+        if let Some(Instr::Jump { target } | Instr::JumpIf { target, .. }) =
+            self.instrs.get_mut(index)
+        {
+            *target = next;
+        }
+    }
+
+    /// Compile `expr`, writing its value to `dst`
+    fn compile_expr(&mut self, expr: &Expr, dst: Reg) {
+        if let Some(value) = Self::constant(expr) {
+            self.emit_const(dst, value);
+            return;
+        }
+        match expr.expr_kind() {
+            ExprKind::Lit(lit) => self.emit_const(dst, lit.clone().into()),
+            ExprKind::Var(var) => {
+                self.emit(Instr::Var { dst, var: *var });
+            }
+            ExprKind::Slot(slot) => {
+                self.emit(Instr::Slot { dst, slot: *slot });
+            }
+            ExprKind::Unknown { .. } => {
+                self.emit(Instr::Unknown);
+            }
+            ExprKind::If {
+                test_expr,
+                then_expr,
+                else_expr,
+            } => {
+                let cond = self.operand(test_expr);
+                let to_else = self.emit(Instr::JumpIf {
+                    cond,
+                    value: false,
+                    target: 0,
+                });
+                self.compile_expr(then_expr, dst);
+                let to_end = self.emit(Instr::Jump { target: 0 });
+                self.patch(to_else);
+                self.compile_expr(else_expr, dst);
+                self.patch(to_end);
+            }
+            ExprKind::And { left, right } => self.short_circuit(left, right, false, dst),
+            ExprKind::Or { left, right } => self.short_circuit(left, right, true, dst),
+            ExprKind::UnaryApp { op, arg } => {
+                let arg = self.operand(arg);
+                self.emit(Instr::Unary { dst, op: *op, arg });
+            }
+            ExprKind::BinaryApp { op, arg1, arg2 } => {
+                let arg1 = self.binary_operand(arg1);
+                let arg2 = self.binary_operand(arg2);
+                self.emit(Instr::Binary {
+                    dst,
+                    op: *op,
+                    arg1,
+                    arg2,
+                });
+            }
+            ExprKind::MulByConst { arg, constant } => {
+                let arg = self.operand(arg);
+                self.emit(Instr::Mul {
+                    dst,
+                    arg,
+                    constant: *constant,
+                });
+            }
+            ExprKind::ExtensionFunctionApp { fn_name, args } => {
+                let args = args.iter().map(|arg| self.operand(arg)).collect();
+                self.emit(Instr::Call {
+                    dst,
+                    fn_name: fn_name.clone(),
+                    args,
+                });
+            }
+            ExprKind::GetAttr { expr, attr } => {
+                if let ExprKind::Var(var) = expr.expr_kind() {
+                    self.emit(Instr::VarAttr {
+                        dst,
+                        var: *var,
+                        attr: attr.clone(),
+                    });
+                    return;
+                }
+                let src = self.operand(expr);
+                self.emit(Instr::GetAttr {
+                    dst,
+                    src,
+                    attr: attr.clone(),
+                });
+            }
+            ExprKind::HasAttr { expr, attr } => {
+                let src = self.operand(expr);
+                self.emit(Instr::HasAttr {
+                    dst,
+                    src,
+                    attr: attr.clone(),
+                });
+            }
+            ExprKind::Like { expr, pattern } => {
+                let src = self.operand(expr);
+                self.emit(Instr::Like {
+                    dst,
+                    src,
+                    pattern: pattern.clone(),
+                });
+            }
+            ExprKind::Set(elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.operand(element))
+                    .collect();
+                self.emit(Instr::Set { dst, elements });
+            }
+            ExprKind::Record { pairs } => {
+                let fields = pairs
+                    .iter()
+                    .map(|(attr, value)| (attr.clone(), self.operand(value)))
+                    .collect();
+                self.emit(Instr::Record { dst, fields });
+            }
+        }
+    }
+
+    /// Compile `expr` to a new register, and return it
+    fn operand(&mut self, expr: &Expr) -> Reg {
+        let reg = self.register();
+        self.compile_expr(expr, reg);
+        reg
+    }
+
+    /// Compile `expr` as an operand of a binary operator
+    fn binary_operand(&mut self, expr: &Expr) -> Operand {
+        match Self::constant(expr) {
+            Some(value) => Operand::Const(self.constant_index(value)),
+            None => Operand::Reg(self.operand(expr)),
+        }
+    }
+
+    /// Compile `left && right` (if `value` is false) or `left || right` (if it
+    /// is true): `right` is only evaluated if `left` is not `value`
+    fn short_circuit(&mut self, left: &Expr, right: &Expr, value: bool, dst: Reg) {
+        let cond = self.operand(left);
+        let to_short = self.emit(Instr::JumpIf {
+            cond,
+            value,
+            target: 0,
+        });
+        let src = self.operand(right);
+        self.emit(Instr::Bool { dst, src });
+        let to_end = self.emit(Instr::Jump { target: 0 });
+        self.patch(to_short);
+        self.emit_const(dst, value.into());
+        self.patch(to_end);
+    }
+}
+
+impl<'e> Evaluator<'e> {
+    /// Run `program` in this evaluation environment.
+    ///
+    /// Returns `None` as soon as a residual is met, in which case the
+    /// expression should be partially evaluated with
+    /// [`Evaluator::partial_interpret`] instead.
+    pub fn run_program(&self, program: &Program, slots: &SlotEnv) -> Result<Option<Value>> {
+        let mut regs: Vec<Option<Value>> = vec![None; program.registers];
+        // every register is read once, so its value is moved out
+        macro_rules! read {
+            ($reg:expr) => {
+                match regs.get_mut($reg).and_then(Option::take) {
+                    Some(v) => v,
+                    None => return Ok(None),
+                }
+            };
+        }
+        macro_rules! operand {
+            ($operand:expr) => {
+                match $operand {
+                    Operand::Reg(reg) => read!(reg),
+                    Operand::Const(index) => match program.constants.get(index) {
+                        Some(v) => v.clone(),
+                        None => return Ok(None),
+                    },
+                }
+            };
+        }
+        macro_rules! write {
+            ($reg:expr, $value:expr) => {{
+                let value = $value;
+                match (regs.get_mut($reg), value) {
+                    (Some(reg), Some(v)) => *reg = Some(v),
+                    _ => return Ok(None),
+                }
+            }};
+        }
+        let mut pc = 0;
+        while let Some(instr) = program.instrs.get(pc) {
+            pc += 1;
+            match instr {
+                Instr::Const { dst, index } => write!(*dst, program.constants.get(*index).cloned()),
+                Instr::Var { dst, var } => {
+                    let v = match var {
+                        Var::Principal => self.principal.evaluate(*var),
+                        Var::Action => self.action.evaluate(*var),
+                        Var::Resource => self.resource.evaluate(*var),
+                        Var::Context => self.context.clone(),
+                    };
+                    write!(*dst, v.try_into().ok())
+                }
+                Instr::VarAttr { dst, var, attr } => {
+                    let v = match var {
+                        Var::Principal => self.principal.evaluate(*var),
+                        Var::Action => self.action.evaluate(*var),
+                        Var::Resource => self.resource.evaluate(*var),
+                        // the context is borrowed rather than cloned
+                        Var::Context => match &self.context {
+                            PartialValue::Value(context) => {
+                                write!(*dst, self.get_attr_of(context, attr)?.try_into().ok());
+                                continue;
+                            }
+                            PartialValue::Residual(_) => return Ok(None),
+                        },
+                    };
+                    match v {
+                        PartialValue::Value(v) => {
+                            write!(*dst, self.get_attr_of(&v, attr)?.try_into().ok())
+                        }
+                        PartialValue::Residual(_) => return Ok(None),
+                    }
+                }
+                Instr::Slot { dst, slot } => {
+                    let euid = slots
+                        .get(slot)
+                        .ok_or_else(|| super::EvaluationError::unlinked_slot(*slot))?;
+                    write!(*dst, Some(euid.clone().into()))
+                }
+                Instr::Unknown => return Ok(None),
+                Instr::Bool { dst, src } => {
+                    let b = read!(*src).get_as_bool()?;
+                    write!(*dst, Some(b.into()))
+                }
+                Instr::JumpIf {
+                    cond,
+                    value,
+                    target,
+                } => {
+                    if read!(*cond).get_as_bool()? == *value {
+                        pc = *target;
+                    }
+                }
+                Instr::Jump { target } => pc = *target,
+                Instr::Unary { dst, op, arg } => {
+                    write!(*dst, unary_app(*op, read!(*arg))?.try_into().ok())
+                }
+                Instr::Binary {
+                    dst,
+                    op,
+                    arg1,
+                    arg2,
+                } => {
+                    let arg1 = operand!(*arg1);
+                    let arg2 = operand!(*arg2);
+                    write!(*dst, self.binary_app(*op, arg1, arg2)?.try_into().ok())
+                }
+                Instr::Mul { dst, arg, constant } => {
+                    write!(*dst, mul_by_const(read!(*arg), *constant)?.try_into().ok())
+                }
+                Instr::Call { dst, fn_name, args } => {
+                    let mut vals = SmallVec::<[_; 4]>::with_capacity(args.len());
+                    for arg in args {
+                        vals.push(read!(*arg));
+                    }
+                    write!(
+                        *dst,
+                        self.call_extension_fn(fn_name, &vals)?.try_into().ok()
+                    )
+                }
+                Instr::GetAttr { dst, src, attr } => {
+                    write!(*dst, self.get_attr_of(&read!(*src), attr)?.try_into().ok())
+                }
+                Instr::HasAttr { dst, src, attr } => {
+                    write!(*dst, self.has_attr(read!(*src), attr)?.try_into().ok())
+                }
+                Instr::Like { dst, src, pattern } => {
+                    let matched = pattern.wildcard_match(read!(*src).get_as_string()?);
+                    write!(*dst, Some(matched.into()))
+                }
+                Instr::Set { dst, elements } => {
+                    let mut vals = Vec::with_capacity(elements.len());
+                    for element in elements {
+                        vals.push(read!(*element));
+                    }
+                    write!(*dst, Some(Value::set(vals)))
+                }
+                Instr::Record { dst, fields } => {
+                    let mut record = BTreeMap::new();
+                    for (attr, value) in fields {
+                        record.insert(attr.clone(), read!(*value));
+                    }
+                    write!(*dst, Some(Value::Record(Arc::new(record))))
+                }
+            }
+        }
+        Ok(Some(read!(0)))
+    }
+}
+
+impl HeapSize for Program {
+    fn heap_size(&self) -> usize {
+        let payloads = self
+            .instrs
+            .iter()
+            .map(|instr| match instr {
+                Instr::VarAttr { attr, .. }
+                | Instr::GetAttr { attr, .. }
+                | Instr::HasAttr { attr, .. } => attr.heap_size(),
+                Instr::Call { fn_name, args, .. } => {
+                    let spilled = if args.spilled() {
+                        vec_size::<Reg>(args.capacity())
+                    } else {
+                        0
+                    };
+                    fn_name.heap_size() + spilled
+                }
+                Instr::Like { pattern, .. } => pattern.heap_size(),
+                Instr::Set { elements, .. } => vec_size::<Reg>(elements.capacity()),
+                Instr::Record { fields, .. } => {
+                    vec_size::<(SmolStr, Reg)>(fields.capacity())
+                        + fields.iter().map(|(k, _)| k.heap_size()).sum::<usize>()
+                }
+                _ => 0,
+            })
+            .sum::<usize>();
+        vec_size::<Instr>(self.instrs.capacity())
+            + vec_size::<Value>(self.constants.capacity())
+            + self
+                .constants
+                .iter()
+                .map(HeapSize::heap_size)
+                .sum::<usize>()
+            + payloads
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entities::Entities;
+    use crate::evaluator::test::{basic_request, rich_entities};
+    use crate::evaluator::EvaluationRecord;
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    #[test]
+    fn runs_programs_as_expressions() {
+        let q = basic_request();
+        let entities = rich_entities();
+        let exts = Extensions::all_available();
+        let eval = Evaluator::new(&q, &entities, &exts).expect("evaluator is built");
+        for src in [
+            "true && (false || 1 + 2 * 3 == 7)",
+            "false && 1",
+            "true || 1",
+            "1 && true",
+            "true && 1",
+            "if context.cur_time like \"03:*\" then -3 < 2 else false",
+            "if false then 1 else if true then 2 else 3",
+            r#"test_entity_type::"child" in [test_entity_type::"grandparent"]"#,
+            r#"test_entity_type::"entity_with_attrs".tags.containsAll(["fun", "good"])"#,
+            r#"{a: 1, b: [2, 3]}.b.contains(3) && context has device_properties"#,
+            r#"{a: 1, a: 2}"#,
+            r#"decimal("1.23").lessThan(decimal("1.24"))"#,
+            r#"test_entity_type::"entity_with_attrs".missing"#,
+            "!(9223372036854775807 + 1 > 0)",
+            "context.cur_time < 3",
+        ] {
+            let expr = parse_expr(src).expect("expression parses");
+            let program = Program::compile(&expr);
+            let from_program = eval.run_program(&program, &SlotEnv::new());
+            let from_expr = eval.interpret(&expr, &SlotEnv::new());
+            match (from_program, from_expr) {
+                (Ok(Some(p)), Ok(e)) => assert_eq!(p, e, "{src}"),
+                (Err(p), Err(e)) => assert_eq!(p.to_string(), e.to_string(), "{src}"),
+                (p, e) => panic!("{src}: {p:?} from the program but {e:?} from the expression"),
+            }
+        }
+    }
+
+    #[test]
+    fn records_what_expressions_record() {
+        let q = basic_request();
+        let entities = rich_entities();
+        let exts = Extensions::all_available();
+        for src in [
+            r#"decimal("1.23").lessThan(decimal("1.24"))"#,
+            r#"test_entity_type::"entity_with_attrs".tags.contains("fun")"#,
+            r#"test_entity_type::"child" in test_entity_type::"grandparent""#,
+        ] {
+            let expr = parse_expr(src).expect("expression parses");
+            let from_program = EvaluationRecord::new();
+            Evaluator::new(&q, &entities, &exts)
+                .expect("evaluator is built")
+                .recording(&from_program)
+                .run_program(&Program::compile(&expr), &SlotEnv::new())
+                .expect("no error");
+            let from_expr = EvaluationRecord::new();
+            Evaluator::new(&q, &entities, &exts)
+                .expect("evaluator is built")
+                .recording(&from_expr)
+                .interpret(&expr, &SlotEnv::new())
+                .expect("no error");
+            assert_eq!(from_program.consulted(), from_expr.consulted(), "{src}");
+        }
+    }
+
+    #[test]
+    fn stops_at_residuals() {
+        let q = basic_request();
+        let entities = Entities::new();
+        let exts = Extensions::none();
+        let eval = Evaluator::new(&q, &entities, &exts).expect("evaluator is built");
+        let run = |expr: &Expr| {
+            eval.run_program(&Program::compile(expr), &SlotEnv::new())
+                .expect("no error")
+        };
+        assert_eq!(run(&Expr::and(Expr::val(true), Expr::unknown("a"))), None);
+        assert_eq!(
+            run(&Expr::and(Expr::val(false), Expr::unknown("a"))),
+            Some(false.into())
+        );
+    }
+}
@@ -65,6 +65,14 @@ impl EvaluationError {
         }
     }
 
+    /// Construct a [`EntityTagDoesNotExist`] error
+    pub(crate) fn entity_tag_does_not_exist(entity: Arc<EntityUID>, tag: SmolStr) -> Self {
+        Self {
+            error_kind: EvaluationErrorKind::EntityTagDoesNotExist { entity, tag },
+            advice: None,
+        }
+    }
+
     /// Construct a [`UnspecifiedEntityAccess`] error
     pub(crate) fn unspecified_entity_access(attr: SmolStr) -> Self {
         Self {
@@ -122,7 +130,7 @@ impl EvaluationError {
     }
 
     /// Construct a [`FailedExtensionFunctionApplication`] error
-    pub(crate) fn failed_extension_function_application(extension_name: Name, msg: String) -> Self {
+    pub fn failed_extension_function_application(extension_name: Name, msg: String) -> Self {
         Self {
             error_kind: EvaluationErrorKind::FailedExtensionFunctionApplication {
                 extension_name,
@@ -194,6 +202,15 @@ pub enum EvaluationErrorKind {
         attr: SmolStr,
     },
 
+    /// Tried to get this tag, but the specified entity didn't have that tag
+    #[error("`{}` does not have the tag `{}`", &.entity, &.tag)]
+    EntityTagDoesNotExist {
+        /// Entity that didn't have the tag
+        entity: Arc<EntityUID>,
+        /// Name of the tag it didn't have
+        tag: SmolStr,
+    },
+
     /// Tried to access an attribute of an unspecified entity
     #[error("cannot access attribute `{0}` of unspecified entity")]
     UnspecifiedEntityAccess(SmolStr),
@@ -0,0 +1,64 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Setting attributes and tags on entities for one evaluation, without
+//! changing the entities.
+
+use crate::ast::{EntityUID, RestrictedExpr};
+use smol_str::SmolStr;
+use std::collections::HashMap;
+
+/// Attributes and tags an [`Evaluator`](super::Evaluator) made with
+/// [`Evaluator::with_overlay`](super::Evaluator::with_overlay) reads in
+/// preference to those the entities have. They are read even for entities
+/// which do not exist.
+#[derive(Debug, Clone, Default)]
+pub struct EntityOverlay {
+    attrs: HashMap<EntityUID, HashMap<SmolStr, RestrictedExpr>>,
+    tags: HashMap<EntityUID, HashMap<SmolStr, RestrictedExpr>>,
+}
+
+impl EntityOverlay {
+    /// Create an empty `EntityOverlay`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the attribute `attr` of the entity `uid` to `value`
+    pub fn set_attr(&mut self, uid: EntityUID, attr: SmolStr, value: RestrictedExpr) {
+        self.attrs.entry(uid).or_default().insert(attr, value);
+    }
+
+    /// Set the tag `tag` of the entity `uid` to `value`
+    pub fn set_tag(&mut self, uid: EntityUID, tag: SmolStr, value: RestrictedExpr) {
+        self.tags.entry(uid).or_default().insert(tag, value);
+    }
+
+    /// Whether no attributes or tags are set
+    pub fn is_empty(&self) -> bool {
+        self.attrs.is_empty() && self.tags.is_empty()
+    }
+
+    /// The value set for the attribute `attr` of the entity `uid`, if any
+    pub fn attr(&self, uid: &EntityUID, attr: &str) -> Option<&RestrictedExpr> {
+        self.attrs.get(uid)?.get(attr)
+    }
+
+    /// The value set for the tag `tag` of the entity `uid`, if any
+    pub fn tag(&self, uid: &EntityUID, tag: &str) -> Option<&RestrictedExpr> {
+        self.tags.get(uid)?.get(tag)
+    }
+}
@@ -0,0 +1,93 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Recording what evaluations consult.
+
+use crate::ast::{EntityUID, Name, Value};
+use smol_str::SmolStr;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Mutex, PoisonError};
+
+/// What an [`Evaluator`](super::Evaluator) made with
+/// [`Evaluator::recording`](super::Evaluator::recording) consulted. One record
+/// may be shared by several evaluators, including ones evaluating in
+/// parallel.
+#[derive(Debug, Default)]
+pub struct EvaluationRecord(Mutex<Consulted>);
+
+impl EvaluationRecord {
+    /// Create an empty `EvaluationRecord`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What the evaluations consulted so far
+    pub fn consulted(&self) -> Consulted {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Record that the entity `uid` was looked up, and what `read` of it
+    pub(super) fn entity(&self, uid: &EntityUID, read: impl FnOnce(&mut EntityAccess)) {
+        let mut consulted = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        read(consulted.entities.entry(uid.clone()).or_default());
+    }
+
+    /// Record a call of an extension function
+    pub(super) fn extension_call(&self, call: ExtensionCall) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extension_calls
+            .push(call);
+    }
+}
+
+/// The entities and extension function calls evaluations consulted
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Consulted {
+    /// The entities looked up, whether or not they exist, with what was read
+    /// of each
+    pub entities: BTreeMap<EntityUID, EntityAccess>,
+    /// The calls of extension functions with values as arguments, in the
+    /// order they were made
+    pub extension_calls: Vec<ExtensionCall>,
+}
+
+/// What was read of one entity
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntityAccess {
+    /// The attributes read or tested with `has`
+    pub attributes: BTreeSet<SmolStr>,
+    /// The tags read or tested with `hasTag`
+    pub tags: BTreeSet<SmolStr>,
+    /// Whether its ancestors were read, by `in`
+    pub ancestors: bool,
+}
+
+/// A call of an extension function, and what it returned
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtensionCall {
+    /// The function called
+    pub function: Name,
+    /// The arguments it was called with
+    pub args: Vec<Value>,
+    /// The value it returned, or the message of the error it failed with.
+    /// `None` if it returned a residual.
+    pub result: Option<Result<Value, String>>,
+}
@@ -26,8 +26,24 @@ pub mod partial_evaluation;
 #[cfg(feature = "u256")]
 pub mod u256;
 
-use crate::ast::{Extension, ExtensionFunction, Name};
+#[cfg(feature = "bytes")]
+pub mod bytes;
+
+#[cfg(feature = "address")]
+pub mod address;
+
+#[cfg(feature = "regex")]
+pub mod regex;
+
+#[cfg(feature = "quota")]
+pub mod quota;
+
+#[cfg(feature = "simulation")]
+pub mod simulation;
+
+use crate::ast::{Extension, ExtensionFunction, Name, OverloadedOperator};
 use crate::entities::SchemaType;
+use std::sync::Arc;
 use thiserror::Error;
 
 lazy_static::lazy_static! {
@@ -39,6 +55,16 @@ lazy_static::lazy_static! {
         partial_evaluation::extension(),
         #[cfg(feature = "u256")]
         u256::extension(),
+        #[cfg(feature = "bytes")]
+        bytes::extension(),
+        #[cfg(feature = "address")]
+        address::extension(),
+        #[cfg(feature = "regex")]
+        regex::extension(),
+        #[cfg(feature = "quota")]
+        quota::extension(),
+        #[cfg(feature = "simulation")]
+        simulation::extension(),
     ];
 }
 
@@ -49,6 +75,9 @@ lazy_static::lazy_static! {
 pub struct Extensions<'a> {
     /// the actual extensions
     extensions: &'a [Extension],
+    /// extensions whose functions are used instead of those of the same name
+    /// in `extensions`
+    overrides: Option<Arc<[Extension]>>,
 }
 
 impl Extensions<'static> {
@@ -56,19 +85,37 @@ impl Extensions<'static> {
     pub fn all_available() -> Extensions<'static> {
         Extensions {
             extensions: &ALL_AVAILABLE_EXTENSIONS,
+            overrides: None,
         }
     }
 
     /// Get a new `Extensions` with no extensions enabled.
     pub fn none() -> Extensions<'static> {
-        Extensions { extensions: &[] }
+        Extensions {
+            extensions: &[],
+            overrides: None,
+        }
     }
 }
 
 impl<'a> Extensions<'a> {
     /// Get a new `Extensions` with these specific extensions enabled.
     pub fn specific_extensions(extensions: &'a [Extension]) -> Extensions<'a> {
-        Extensions { extensions }
+        Extensions {
+            extensions,
+            overrides: None,
+        }
+    }
+
+    /// Use the functions of `overrides` instead of those of the same name in
+    /// these extensions, such as to bind the functions of an extension to
+    /// state the evaluator does not hold. Other functions of the extensions
+    /// are unaffected.
+    pub fn with_overrides(self, overrides: Arc<[Extension]>) -> Extensions<'a> {
+        Extensions {
+            overrides: Some(overrides),
+            ..self
+        }
     }
 
     /// Get the names of all active extensions.
@@ -76,6 +123,17 @@ impl<'a> Extensions<'a> {
         self.extensions.iter().map(|ext| ext.name())
     }
 
+    /// Get the names of all extension types defined by these extensions, i.e.,
+    /// the types returned by their constructors.
+    pub fn ext_types(&self) -> impl Iterator<Item = &Name> {
+        self.all_funcs()
+            .filter(|f| f.is_constructor())
+            .filter_map(|f| match f.return_type() {
+                Some(SchemaType::Extension { name }) => Some(name),
+                _ => None,
+            })
+    }
+
     /// Get the extension function with the given name, from these extensions.
     ///
     /// Returns an error if the function is not defined by any extension, or if
@@ -84,6 +142,14 @@ impl<'a> Extensions<'a> {
         // NOTE: in the future, we could build a single HashMap of function
         // name to ExtensionFunction, combining all extension functions
         // into one map, to make this lookup faster.
+        if let Some(func) = self
+            .overrides
+            .iter()
+            .flat_map(|overrides| overrides.iter())
+            .find_map(|ext| ext.get_func(name))
+        {
+            return Ok(func);
+        }
         let extension_funcs: Vec<&ExtensionFunction> = self
             .extensions
             .iter()
@@ -106,11 +172,23 @@ impl<'a> Extensions<'a> {
         self.extensions.iter().flat_map(|ext| ext.funcs())
     }
 
+    /// Look up the function implementing the operator `op` for values of the
+    /// extension type `ty`, or return `None` if no extension overloads `op`
+    /// for that type
+    pub fn operator_func(&self, op: OverloadedOperator, ty: &Name) -> Option<&ExtensionFunction> {
+        let ty = SchemaType::Extension { name: ty.clone() };
+        self.extensions
+            .iter()
+            .filter_map(|ext| ext.operator_func(op))
+            .find(|f| f.arg_types().first().map(Option::as_ref) == Some(Some(&ty)))
+    }
+
     /// Lookup a single-argument constructor by its return type and argument type.
     /// This will ignore polymorphic functions (that accept multiple argument types).
     ///
     /// `Ok(None)` means no constructor has that signature.
     /// `Err` is returned in the case that multiple constructors have that signature.
+    #[cfg(feature = "json")]
     pub(crate) fn lookup_single_arg_constructor(
         &self,
         return_type: &SchemaType,
@@ -0,0 +1,595 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! This module contains the Cedar 'address' extension, and the canonical
+//! entity UIDs of accounts and contracts.
+//!
+//! An account or contract is identified by the entity type `Account` or
+//! `Contract`, and an EID holding its chain and EIP-55 checksummed address in
+//! the CAIP-10 format, such as `eip155:1:0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed`.
+
+use crate::ast::{
+    CallStyle, Eid, EntityType, EntityUID, Extension, ExtensionFunction, ExtensionOutputValue,
+    ExtensionValue, ExtensionValueWithArgs, Name, RestrictedExpr, StaticallyTyped, Type, Value,
+};
+use crate::entities::SchemaType;
+use crate::evaluator;
+use std::str::FromStr;
+use std::sync::Arc;
+use thiserror::Error;
+
+pub use ethers::types::Address;
+use ethers::utils::to_checksum;
+
+/// Ethereum account address, represented internally as its 20 bytes.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+struct EthAddress {
+    value: Address,
+}
+
+mod names {
+    use super::{Name, EXTENSION_NAME};
+    lazy_static::lazy_static! {
+        pub static ref ADDRESS_FROM_STR_NAME : Name = Name::builtin(EXTENSION_NAME);
+        pub static ref ACCOUNT_TYPE : Name = Name::builtin("Account");
+        pub static ref CONTRACT_TYPE : Name = Name::builtin("Contract");
+    }
+}
+
+/// Potential errors when working with address values. Note that these are
+/// converted to evaluator::Err::ExtensionErr (which takes a string argument)
+/// before being reported to users.
+#[derive(Debug, Error)]
+enum Error {
+    /// Error parsing the input string as an address
+    #[error("input string is not a well-formed address: `{0}`")]
+    FailedParse(String),
+
+    /// The input string is mixed-case, but not a valid EIP-55 checksum
+    #[error("input string `{0}` has an invalid checksum, expected `{1}`")]
+    InvalidChecksum(String, String),
+}
+
+impl EthAddress {
+    /// The Cedar typename of address values
+    fn typename() -> Name {
+        names::ADDRESS_FROM_STR_NAME.clone()
+    }
+
+    /// Convert a string into an `EthAddress` value.
+    ///
+    /// The string must be `0x` followed by exactly 40 hex digits. All-lowercase
+    /// and all-uppercase digits are accepted as is; mixed-case digits must be
+    /// a valid EIP-55 checksum.
+    fn from_str(str: impl AsRef<str>) -> Result<Self, Error> {
+        let str = str.as_ref();
+        let digits = match str.strip_prefix("0x") {
+            Some(digits) if digits.len() == 40 && digits.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                digits
+            }
+            _ => return Err(Error::FailedParse(str.to_owned())),
+        };
+        let value = Address::from_str(digits).map_err(|_| Error::FailedParse(str.to_owned()))?;
+        let is_mixed_case = digits.bytes().any(|b| b.is_ascii_lowercase())
+            && digits.bytes().any(|b| b.is_ascii_uppercase());
+        if is_mixed_case {
+            let checksummed = to_checksum(&value, None);
+            if checksummed != str {
+                return Err(Error::InvalidChecksum(str.to_owned(), checksummed));
+            }
+        }
+        Ok(Self { value })
+    }
+}
+
+impl std::fmt::Display for EthAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", to_checksum(&self.value, None))
+    }
+}
+
+impl ExtensionValue for EthAddress {
+    fn typename(&self) -> Name {
+        Self::typename()
+    }
+}
+
+const EXTENSION_NAME: &str = "address";
+
+fn extension_err(msg: impl Into<String>) -> evaluator::EvaluationError {
+    evaluator::EvaluationError::failed_extension_function_application(
+        names::ADDRESS_FROM_STR_NAME.clone(),
+        msg.into(),
+    )
+}
+
+/// Construct the restricted expression `address("...")` holding `value`, with
+/// its EIP-55 checksum, for attribute and context values built
+/// programmatically
+pub fn address_expr(value: Address) -> RestrictedExpr {
+    RestrictedExpr::call_extension_fn(
+        names::ADDRESS_FROM_STR_NAME.clone(),
+        vec![RestrictedExpr::val(to_checksum(&value, None))],
+    )
+}
+
+/// Parse the string form of an `address` value: `0x` followed by 40 hex
+/// digits, in one case or with a valid EIP-55 checksum
+pub fn parse_address(str: &str) -> Option<Address> {
+    EthAddress::from_str(str).ok().map(|a| a.value)
+}
+
+impl From<Address> for RestrictedExpr {
+    fn from(value: Address) -> Self {
+        address_expr(value)
+    }
+}
+
+impl TryFrom<&Value> for Address {
+    type Error = evaluator::EvaluationError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        as_address(value).map(|a| a.value)
+    }
+}
+
+/// The namespace of EVM chains in CAIP-2 chain ids
+const CHAIN_NAMESPACE: &str = "eip155";
+
+/// The chain and address of an account or contract, as held in the EID of its
+/// canonical entity UID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChainAddress {
+    /// EIP-155 id of the chain
+    pub chain_id: u64,
+    /// Address on the chain
+    pub address: Address,
+}
+
+impl std::fmt::Display for ChainAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{CHAIN_NAMESPACE}:{}:{}",
+            self.chain_id,
+            to_checksum(&self.address, None)
+        )
+    }
+}
+
+impl FromStr for ChainAddress {
+    type Err = ChainAddressError;
+
+    /// Parse an EID in the canonical form. Any other spelling of the same
+    /// chain and address, such as a lowercase address, is rejected, as it
+    /// would name a different entity.
+    fn from_str(eid: &str) -> Result<Self, Self::Err> {
+        let malformed = || ChainAddressError::Malformed(eid.to_owned());
+        let mut parts = eid.split(':');
+        let (Some(CHAIN_NAMESPACE), Some(chain_id), Some(address), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(malformed());
+        };
+        let chain_address = Self {
+            chain_id: chain_id.parse().map_err(|_| malformed())?,
+            address: parse_address(address).ok_or_else(malformed)?,
+        };
+        let canonical = chain_address.to_string();
+        if canonical == eid {
+            Ok(chain_address)
+        } else {
+            Err(ChainAddressError::NotCanonical {
+                eid: eid.to_owned(),
+                canonical,
+            })
+        }
+    }
+}
+
+/// Errors reading the chain and address of an entity UID
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ChainAddressError {
+    /// The entity UID is not of the expected type
+    #[error("expected an entity of type `{expected}`, got `{uid}`")]
+    WrongType {
+        /// Entity type which was expected
+        expected: Name,
+        /// Entity UID which was found instead
+        uid: EntityUID,
+    },
+    /// The EID is not of the form `eip155:<chain id>:<address>`
+    #[error("`{0}` is not of the form `eip155:<chain id>:<address>`")]
+    Malformed(String),
+    /// The EID names a chain and address, but not in the canonical form
+    #[error("`{eid}` is not in the canonical form `{canonical}`")]
+    NotCanonical {
+        /// EID which was found
+        eid: String,
+        /// Canonical form of the EID
+        canonical: String,
+    },
+}
+
+impl EntityUID {
+    /// The canonical UID of the externally owned account at `address` on the
+    /// chain `chain_id`
+    pub fn account(chain_id: u64, address: Address) -> Self {
+        Self::chain_scoped(names::ACCOUNT_TYPE.clone(), chain_id, address)
+    }
+
+    /// The canonical UID of the contract at `address` on the chain `chain_id`
+    pub fn contract(chain_id: u64, address: Address) -> Self {
+        Self::chain_scoped(names::CONTRACT_TYPE.clone(), chain_id, address)
+    }
+
+    /// Get the chain and address of the canonical UID of an account
+    pub fn account_address(&self) -> Result<ChainAddress, ChainAddressError> {
+        self.chain_address(&names::ACCOUNT_TYPE)
+    }
+
+    /// Get the chain and address of the canonical UID of a contract
+    pub fn contract_address(&self) -> Result<ChainAddress, ChainAddressError> {
+        self.chain_address(&names::CONTRACT_TYPE)
+    }
+
+    /// The UID of type `ty` of the account or contract at `address` on the
+    /// chain `chain_id`
+    fn chain_scoped(ty: Name, chain_id: u64, address: Address) -> Self {
+        let eid = ChainAddress { chain_id, address }.to_string();
+        Self::from_components(ty, Eid::new(eid))
+    }
+
+    /// Get the chain and address of this UID, which must be of type `expected`
+    fn chain_address(&self, expected: &Name) -> Result<ChainAddress, ChainAddressError> {
+        match self.entity_type() {
+            EntityType::Concrete(ty) if ty == expected => {
+                ChainAddress::from_str(self.eid().as_ref())
+            }
+            _ => Err(ChainAddressError::WrongType {
+                expected: expected.clone(),
+                uid: self.clone(),
+            }),
+        }
+    }
+}
+
+/// Check that `v` is an `address` value and get it
+fn as_address(v: &Value) -> Result<&EthAddress, evaluator::EvaluationError> {
+    match v {
+        Value::ExtensionValue(ev) if ev.typename() == EthAddress::typename() => {
+            // PANIC SAFETY Conditional above performs a typecheck
+            #[allow(clippy::expect_used)]
+            let a = ev
+                .value()
+                .as_any()
+                .downcast_ref::<EthAddress>()
+                .expect("already typechecked, so this downcast should succeed");
+            Ok(a)
+        }
+        _ => Err(evaluator::EvaluationError::type_error(
+            vec![Type::Extension {
+                name: EthAddress::typename(),
+            }],
+            v.type_of(),
+        )),
+    }
+}
+
+/// Cedar function that constructs an `address` Cedar type from a
+/// Cedar string
+fn address_from_str(arg: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let str = arg.get_as_string()?;
+    let address = EthAddress::from_str(str.as_str()).map_err(|e| extension_err(e.to_string()))?;
+    let function_name = names::ADDRESS_FROM_STR_NAME.clone();
+    let e = ExtensionValueWithArgs::new(Arc::new(address), vec![arg.into()], function_name);
+    Ok(Value::ExtensionValue(Arc::new(e)).into())
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    let address_type = SchemaType::Extension {
+        name: EthAddress::typename(),
+    };
+    Extension::new(
+        names::ADDRESS_FROM_STR_NAME.clone(),
+        vec![ExtensionFunction::unary(
+            names::ADDRESS_FROM_STR_NAME.clone(),
+            CallStyle::FunctionStyle,
+            Box::new(address_from_str),
+            address_type,
+            Some(SchemaType::String),
+        )],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Expr, Value};
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::Evaluator;
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    /// Asserts that a `Result` is an `Err::ExtensionErr` with our extension name
+    fn assert_address_err<T>(res: evaluator::Result<T>) {
+        match res {
+            Err(e) => match e.error_kind() {
+                evaluator::EvaluationErrorKind::FailedExtensionFunctionApplication {
+                    extension_name,
+                    msg,
+                } => {
+                    println!("{msg}");
+                    assert_eq!(
+                        *extension_name,
+                        Name::parse_unqualified_name("address")
+                            .expect("should be a valid identifier")
+                    )
+                }
+                _ => panic!("Expected an address ExtensionErr, got {:?}", e),
+            },
+            Ok(_) => panic!("Expected an address ExtensionErr, got Ok"),
+        }
+    }
+
+    /// Asserts that a `Result` is an address value
+    fn assert_address_valid(res: evaluator::Result<Value>) {
+        match res {
+            Ok(Value::ExtensionValue(ev)) => {
+                assert_eq!(ev.typename(), EthAddress::typename())
+            }
+            Ok(v) => panic!("Expected address ExtensionValue, got {:?}", v),
+            Err(e) => panic!("Expected Ok, got Err: {:?}", e),
+        }
+    }
+
+    /// this test just ensures that the right functions are marked constructors
+    #[test]
+    fn constructors() {
+        let ext = extension();
+        assert!(ext
+            .get_func(
+                &Name::parse_unqualified_name("address").expect("should be a valid identifier")
+            )
+            .expect("function should exist")
+            .is_constructor());
+    }
+
+    #[test]
+    fn address_creation() {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        // valid address strings
+        assert_address_valid(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"address("0x0000000000000000000000000000000000000000")"#)
+                    .expect("parsing error"),
+            ),
+        );
+        assert_address_valid(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed")"#)
+                    .expect("parsing error"),
+            ),
+        );
+        assert_address_valid(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"address("0x5AAEB6053F3E94C9B9A09F33669435E7EF1BEAED")"#)
+                    .expect("parsing error"),
+            ),
+        );
+        assert_address_valid(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed")"#)
+                    .expect("parsing error"),
+            ),
+        );
+
+        // invalid address strings
+        assert_address_err(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"address("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed")"#)
+                    .expect("parsing error"),
+            ),
+        );
+        assert_address_err(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1bea")"#)
+                    .expect("parsing error"),
+            ),
+        );
+        assert_address_err(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaedaa")"#)
+                    .expect("parsing error"),
+            ),
+        );
+        assert_address_err(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beazz")"#)
+                    .expect("parsing error"),
+            ),
+        );
+        assert_address_err(
+            eval.interpret_inline_policy(&parse_expr(r#"address("")"#).expect("parsing error")),
+        );
+        // mixed case with a bad checksum
+        assert_address_err(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"address("0x5AAeb6053F3E94C9b9A09f33669435E7Ef1BeAed")"#)
+                    .expect("parsing error"),
+            ),
+        );
+
+        // bad use of `address` as method
+        parse_expr(r#" "0x0000000000000000000000000000000000000000".address() "#)
+            .expect_err("should fail");
+    }
+
+    #[test]
+    fn address_equality() {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        let a = parse_expr(r#"address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed")"#)
+            .expect("parsing error");
+        let b = parse_expr(r#"address("0x5AAEB6053F3E94C9B9A09F33669435E7EF1BEAED")"#)
+            .expect("parsing error");
+        let c = parse_expr(r#"address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed")"#)
+            .expect("parsing error");
+        let d = parse_expr(r#"address("0x0000000000000000000000000000000000000000")"#)
+            .expect("parsing error");
+
+        // a, b, c are all the same address, in different cases
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::is_eq(a.clone(), b.clone())),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::is_eq(b, c)),
+            Ok(Value::from(true))
+        );
+
+        // d is distinct
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::is_eq(a.clone(), d)),
+            Ok(Value::from(false))
+        );
+
+        // other types are not equal
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::is_eq(
+                a,
+                Expr::val("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed")
+            )),
+            Ok(Value::from(false))
+        );
+    }
+
+    #[test]
+    fn address_display() {
+        let address = EthAddress::from_str("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed")
+            .expect("should be a valid address");
+        assert_eq!(
+            address.to_string(),
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"
+        );
+    }
+
+    #[test]
+    fn address_exprs() {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).expect("evaluator is built");
+
+        let address =
+            Address::from_str("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").expect("valid address");
+        let expr = address_expr(address);
+        assert_eq!(
+            expr.to_string(),
+            r#"address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed")"#
+        );
+        assert_address_valid(eval.interpret_inline_policy(&Expr::from(expr)));
+    }
+
+    #[test]
+    fn address_conversions() {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).expect("evaluator is built");
+
+        let address =
+            parse_address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").expect("valid address");
+        let evaluated = eval
+            .interpret_inline_policy(&Expr::from(RestrictedExpr::from(address)))
+            .expect("address evaluates");
+        assert_eq!(
+            Address::try_from(&evaluated).expect("is an address"),
+            address
+        );
+        assert!(
+            Address::try_from(&Value::from("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed")).is_err()
+        );
+        assert_eq!(
+            parse_address("0x5AAeb6053f3e94c9b9a09f33669435e7ef1beaed"),
+            None
+        );
+    }
+
+    #[test]
+    fn chain_scoped_uids() {
+        let address =
+            parse_address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").expect("valid address");
+        let account = EntityUID::account(1, address);
+        assert_eq!(
+            account.to_string(),
+            r#"Account::"eip155:1:0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed""#
+        );
+        assert_eq!(
+            account.account_address(),
+            Ok(ChainAddress {
+                chain_id: 1,
+                address
+            })
+        );
+        assert!(matches!(
+            account.contract_address(),
+            Err(ChainAddressError::WrongType { .. })
+        ));
+
+        let contract = EntityUID::contract(10, address);
+        assert_eq!(contract.contract_address().map(|c| c.chain_id), Ok(10));
+    }
+
+    #[test]
+    fn chain_scoped_uids_are_canonical() {
+        let lowercase = EntityUID::with_eid_and_type(
+            "Account",
+            "eip155:1:0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed",
+        )
+        .expect("valid uid");
+        assert_eq!(
+            lowercase.account_address(),
+            Err(ChainAddressError::NotCanonical {
+                eid: "eip155:1:0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed".into(),
+                canonical: "eip155:1:0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed".into(),
+            })
+        );
+        for eid in [
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "eip155:mainnet:0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "solana:1:0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "eip155:1:0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed:extra",
+        ] {
+            assert_eq!(
+                ChainAddress::from_str(eid),
+                Err(ChainAddressError::Malformed(eid.into()))
+            );
+        }
+    }
+}
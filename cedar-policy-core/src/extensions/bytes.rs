@@ -0,0 +1,499 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! This module contains the Cedar 'bytes' extension.
+
+use base64::Engine;
+
+use crate::ast::{
+    CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, ExtensionValue,
+    ExtensionValueWithArgs, Literal, Name, RestrictedExpr, StaticallyTyped, Type, Value,
+};
+use crate::entities::SchemaType;
+use crate::evaluator;
+use std::sync::Arc;
+use thiserror::Error;
+
+pub use ethers::types::{Bytes as EthBytes, H256};
+
+/// Prefix for hex-encoded byte strings
+const HEX_PREFIX: &str = "0x";
+
+/// Prefix for base64-encoded byte strings
+const BASE64_PREFIX: &str = "base64:";
+
+/// Byte string value, such as calldata or a hash.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+struct Bytes {
+    value: Vec<u8>,
+}
+
+mod names {
+    use super::{Name, EXTENSION_NAME};
+    lazy_static::lazy_static! {
+        pub static ref BYTES_FROM_STR_NAME : Name = Name::builtin(EXTENSION_NAME);
+        pub static ref LENGTH : Name = Name::builtin("bytesLength");
+        pub static ref STARTS_WITH : Name = Name::builtin("bytesStartsWith");
+    }
+}
+
+/// Help message to display when a String was provided where a bytes value was expected.
+/// This error is likely due to confusion between "0x00" and bytes("0x00").
+const ADVICE_MSG: &str = "Maybe you forgot to apply the `bytes` constructor?";
+
+/// Potential errors when working with bytes values. Note that these are
+/// converted to evaluator::Err::ExtensionErr (which takes a string argument)
+/// before being reported to users.
+#[derive(Debug, Error)]
+enum Error {
+    /// The input string had neither the `0x` nor the `base64:` prefix
+    #[error("input string `{0}` must start with `{HEX_PREFIX}` or `{BASE64_PREFIX}`")]
+    MissingPrefix(String),
+
+    /// Error decoding the input string as hex
+    #[error("input string `{0}` is not well-formed hex: {1}")]
+    FailedHexParse(String, hex::FromHexError),
+
+    /// Error decoding the input string as base64
+    #[error("input string `{0}` is not well-formed base64: {1}")]
+    FailedBase64Parse(String, base64::DecodeError),
+}
+
+impl Bytes {
+    /// The Cedar typename of bytes values
+    fn typename() -> Name {
+        names::BYTES_FROM_STR_NAME.clone()
+    }
+
+    /// Convert a string into a `Bytes` value.
+    ///
+    /// The string is either `0x` followed by an even number of hex digits (in
+    /// either case), or `base64:` followed by standard, padded base64.
+    fn from_str(str: impl AsRef<str>) -> Result<Self, Error> {
+        let str = str.as_ref();
+        let value = if let Some(digits) = str.strip_prefix(HEX_PREFIX) {
+            hex::decode(digits).map_err(|e| Error::FailedHexParse(str.to_owned(), e))?
+        } else if let Some(encoded) = str.strip_prefix(BASE64_PREFIX) {
+            base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .map_err(|e| Error::FailedBase64Parse(str.to_owned(), e))?
+        } else {
+            return Err(Error::MissingPrefix(str.to_owned()));
+        };
+        Ok(Self { value })
+    }
+}
+
+impl std::fmt::Display for Bytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{HEX_PREFIX}{}", hex::encode(&self.value))
+    }
+}
+
+impl ExtensionValue for Bytes {
+    fn typename(&self) -> Name {
+        Self::typename()
+    }
+}
+
+const EXTENSION_NAME: &str = "bytes";
+
+fn extension_err(msg: impl Into<String>) -> evaluator::EvaluationError {
+    evaluator::EvaluationError::failed_extension_function_application(
+        names::BYTES_FROM_STR_NAME.clone(),
+        msg.into(),
+    )
+}
+
+/// Construct the restricted expression `bytes("0x...")` holding `value`, for
+/// attribute and context values built programmatically
+pub fn bytes_expr(value: impl AsRef<[u8]>) -> RestrictedExpr {
+    RestrictedExpr::call_extension_fn(
+        names::BYTES_FROM_STR_NAME.clone(),
+        vec![RestrictedExpr::val(format!(
+            "{HEX_PREFIX}{}",
+            hex::encode(value)
+        ))],
+    )
+}
+
+/// Cedar function that constructs a `bytes` Cedar type from a
+/// Cedar string
+fn bytes_from_str(arg: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let str = arg.get_as_string()?;
+    let bytes = Bytes::from_str(str.as_str()).map_err(|e| extension_err(e.to_string()))?;
+    let function_name = names::BYTES_FROM_STR_NAME.clone();
+    let e = ExtensionValueWithArgs::new(Arc::new(bytes), vec![arg.into()], function_name);
+    Ok(Value::ExtensionValue(Arc::new(e)).into())
+}
+
+/// Parse the string form of a `bytes` value: `0x` followed by an even number
+/// of hex digits, or `base64:` followed by standard, padded base64
+pub fn parse_bytes(str: &str) -> Option<Vec<u8>> {
+    Bytes::from_str(str).ok().map(|b| b.value)
+}
+
+impl From<EthBytes> for RestrictedExpr {
+    fn from(value: EthBytes) -> Self {
+        bytes_expr(value)
+    }
+}
+
+impl From<H256> for RestrictedExpr {
+    fn from(value: H256) -> Self {
+        bytes_expr(value)
+    }
+}
+
+impl TryFrom<&Value> for EthBytes {
+    type Error = evaluator::EvaluationError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        as_bytes(value).map(|b| b.value.clone().into())
+    }
+}
+
+impl TryFrom<&Value> for H256 {
+    type Error = evaluator::EvaluationError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        let bytes = as_bytes(value)?;
+        if bytes.value.len() == H256::len_bytes() {
+            Ok(H256::from_slice(&bytes.value))
+        } else {
+            Err(extension_err(format!(
+                "bytes value is {} bytes long, but a 32-byte hash was expected",
+                bytes.value.len()
+            )))
+        }
+    }
+}
+
+/// Check that `v` is a bytes type and, if it is, return the wrapped value
+fn as_bytes(v: &Value) -> Result<&Bytes, evaluator::EvaluationError> {
+    match v {
+        Value::ExtensionValue(ev) if ev.typename() == Bytes::typename() => {
+            // PANIC SAFETY Conditional above performs a typecheck
+            #[allow(clippy::expect_used)]
+            let b = ev
+                .value()
+                .as_any()
+                .downcast_ref::<Bytes>()
+                .expect("already typechecked, so this downcast should succeed");
+            Ok(b)
+        }
+        Value::Lit(Literal::String(_)) => Err(evaluator::EvaluationError::type_error_with_advice(
+            vec![Type::Extension {
+                name: Bytes::typename(),
+            }],
+            v.type_of(),
+            ADVICE_MSG.into(),
+        )),
+        _ => Err(evaluator::EvaluationError::type_error(
+            vec![Type::Extension {
+                name: Bytes::typename(),
+            }],
+            v.type_of(),
+        )),
+    }
+}
+
+/// Cedar function that returns the number of bytes in a `bytes` Cedar type,
+/// as a Cedar long
+fn bytes_length(arg: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let bytes = as_bytes(&arg)?;
+    let len = i64::try_from(bytes.value.len())
+        .map_err(|_| extension_err("length of bytes value does not fit in a long"))?;
+    Ok(Value::Lit(len.into()).into())
+}
+
+/// Cedar function that tests whether the first `bytes` Cedar type starts
+/// with the second `bytes` Cedar type, returning a Cedar bool. This is useful
+/// for matching the function selector of calldata.
+fn bytes_starts_with(left: Value, right: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let left = as_bytes(&left)?;
+    let right = as_bytes(&right)?;
+    Ok(Value::Lit(left.value.starts_with(&right.value).into()).into())
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    let bytes_type = SchemaType::Extension {
+        name: Bytes::typename(),
+    };
+    Extension::new(
+        names::BYTES_FROM_STR_NAME.clone(),
+        vec![
+            ExtensionFunction::unary(
+                names::BYTES_FROM_STR_NAME.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(bytes_from_str),
+                bytes_type.clone(),
+                Some(SchemaType::String),
+            ),
+            ExtensionFunction::unary(
+                names::LENGTH.clone(),
+                CallStyle::MethodStyle,
+                Box::new(bytes_length),
+                SchemaType::Long,
+                Some(bytes_type.clone()),
+            ),
+            ExtensionFunction::binary(
+                names::STARTS_WITH.clone(),
+                CallStyle::MethodStyle,
+                Box::new(bytes_starts_with),
+                SchemaType::Bool,
+                (Some(bytes_type.clone()), Some(bytes_type)),
+            ),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Expr, Value};
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::Evaluator;
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    /// Asserts that a `Result` is an `Err::ExtensionErr` with our extension name
+    fn assert_bytes_err<T>(res: evaluator::Result<T>) {
+        match res {
+            Err(e) => match e.error_kind() {
+                evaluator::EvaluationErrorKind::FailedExtensionFunctionApplication {
+                    extension_name,
+                    msg,
+                } => {
+                    println!("{msg}");
+                    assert_eq!(
+                        *extension_name,
+                        Name::parse_unqualified_name("bytes")
+                            .expect("should be a valid identifier")
+                    )
+                }
+                _ => panic!("Expected a bytes ExtensionErr, got {:?}", e),
+            },
+            Ok(_) => panic!("Expected a bytes ExtensionErr, got Ok"),
+        }
+    }
+
+    /// Asserts that a `Result` is a bytes value
+    fn assert_bytes_valid(res: evaluator::Result<Value>) {
+        match res {
+            Ok(Value::ExtensionValue(ev)) => {
+                assert_eq!(ev.typename(), Bytes::typename())
+            }
+            Ok(v) => panic!("Expected bytes ExtensionValue, got {:?}", v),
+            Err(e) => panic!("Expected Ok, got Err: {:?}", e),
+        }
+    }
+
+    /// this test just ensures that the right functions are marked constructors
+    #[test]
+    fn constructors() {
+        let ext = extension();
+        assert!(ext
+            .get_func(&Name::parse_unqualified_name("bytes").expect("should be a valid identifier"))
+            .expect("function should exist")
+            .is_constructor());
+        assert!(!ext
+            .get_func(
+                &Name::parse_unqualified_name("bytesLength").expect("should be a valid identifier")
+            )
+            .expect("function should exist")
+            .is_constructor());
+        assert!(!ext
+            .get_func(
+                &Name::parse_unqualified_name("bytesStartsWith")
+                    .expect("should be a valid identifier")
+            )
+            .expect("function should exist")
+            .is_constructor());
+    }
+
+    #[test]
+    fn bytes_creation() {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        // valid bytes strings
+        assert_bytes_valid(
+            eval.interpret_inline_policy(&parse_expr(r#"bytes("0x")"#).expect("parsing error")),
+        );
+        assert_bytes_valid(eval.interpret_inline_policy(
+            &parse_expr(r#"bytes("0xa9059cbb")"#).expect("parsing error"),
+        ));
+        assert_bytes_valid(eval.interpret_inline_policy(
+            &parse_expr(r#"bytes("0xA9059CBB")"#).expect("parsing error"),
+        ));
+        assert_bytes_valid(eval.interpret_inline_policy(
+            &parse_expr(r#"bytes("base64:qQWcuw==")"#).expect("parsing error"),
+        ));
+
+        // invalid bytes strings
+        assert_bytes_err(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"bytes("a9059cbb")"#).expect("parsing error"),
+            ),
+        );
+        assert_bytes_err(
+            eval.interpret_inline_policy(&parse_expr(r#"bytes("0xabc")"#).expect("parsing error")),
+        );
+        assert_bytes_err(
+            eval.interpret_inline_policy(&parse_expr(r#"bytes("0xzz")"#).expect("parsing error")),
+        );
+        assert_bytes_err(eval.interpret_inline_policy(
+            &parse_expr(r#"bytes("base64:qQWcuw")"#).expect("parsing error"),
+        ));
+
+        // bad use of `bytes` as method
+        parse_expr(r#" "0x00".bytes() "#).expect_err("should fail");
+    }
+
+    #[test]
+    fn bytes_equality() {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        let a = parse_expr(r#"bytes("0xa9059cbb")"#).expect("parsing error");
+        let b = parse_expr(r#"bytes("0xA9059CBB")"#).expect("parsing error");
+        let c = parse_expr(r#"bytes("base64:qQWcuw==")"#).expect("parsing error");
+        let d = parse_expr(r#"bytes("0xa9059cbc")"#).expect("parsing error");
+
+        // a, b, c are the same bytes, regardless of encoding
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::is_eq(a.clone(), b)),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::is_eq(a.clone(), c)),
+            Ok(Value::from(true))
+        );
+
+        // a, d are distinct
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::is_eq(a.clone(), d)),
+            Ok(Value::from(false))
+        );
+
+        // other types are not equal
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::is_eq(a, Expr::val("0xa9059cbb"))),
+            Ok(Value::from(false))
+        );
+    }
+
+    #[test]
+    fn bytes_ops() {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"bytes("0xa9059cbb0000").bytesLength()"#).expect("parsing error")
+            ),
+            Ok(Value::from(6))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"bytes("0xa9059cbb0000").bytesStartsWith(bytes("0xa9059cbb"))"#)
+                    .expect("parsing error")
+            ),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"bytes("0xa905").bytesStartsWith(bytes("0xa9059cbb"))"#)
+                    .expect("parsing error")
+            ),
+            Ok(Value::from(false))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"bytes("0xa9059cbb").bytesStartsWith("0xa9")"#)
+                    .expect("parsing error")
+            ),
+            Err(evaluator::EvaluationError::type_error_with_advice(
+                vec![Type::Extension {
+                    name: Bytes::typename()
+                }],
+                Type::String,
+                ADVICE_MSG.into(),
+            ))
+        );
+    }
+
+    #[test]
+    fn bytes_display() {
+        let b = Bytes::from_str("base64:qQWcuw==").expect("should be valid bytes");
+        assert_eq!(b.to_string(), "0xa9059cbb");
+        let b = Bytes::from_str("0xA9059CBB").expect("should be valid bytes");
+        assert_eq!(b.to_string(), "0xa9059cbb");
+    }
+
+    #[test]
+    fn bytes_exprs() {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).expect("evaluator is built");
+
+        let expr = bytes_expr([0xa9, 0x05, 0x9c, 0xbb]);
+        assert_eq!(expr.to_string(), r#"bytes("0xa9059cbb")"#);
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::call_extension_fn(
+                names::LENGTH.clone(),
+                vec![Expr::from(expr)]
+            )),
+            Ok(Value::from(4))
+        );
+    }
+
+    #[test]
+    fn bytes_conversions() {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).expect("evaluator is built");
+
+        let hash = H256::repeat_byte(0xab);
+        let evaluated = eval
+            .interpret_inline_policy(&Expr::from(RestrictedExpr::from(hash)))
+            .expect("bytes evaluates");
+        assert_eq!(H256::try_from(&evaluated).expect("is a hash"), hash);
+
+        let calldata = EthBytes::from(vec![0xa9, 0x05, 0x9c, 0xbb]);
+        let evaluated = eval
+            .interpret_inline_policy(&Expr::from(RestrictedExpr::from(calldata.clone())))
+            .expect("bytes evaluates");
+        assert_eq!(EthBytes::try_from(&evaluated).expect("is bytes"), calldata);
+        assert_bytes_err(H256::try_from(&evaluated));
+        assert_eq!(parse_bytes("base64:qQWcuw=="), Some(calldata.to_vec()));
+    }
+}
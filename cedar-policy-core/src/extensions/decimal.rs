@@ -20,7 +20,7 @@ use regex::Regex;
 
 use crate::ast::{
     CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, ExtensionValue,
-    ExtensionValueWithArgs, Literal, Name, StaticallyTyped, Type, Value,
+    ExtensionValueWithArgs, Literal, Name, OverloadedOperator, StaticallyTyped, Type, Value,
 };
 use crate::entities::SchemaType;
 use crate::evaluator;
@@ -38,17 +38,14 @@ struct Decimal {
     value: i64,
 }
 
-// PANIC SAFETY All `Name`s in here are valid `Name`s
-#[allow(clippy::expect_used)]
 mod names {
     use super::{Name, EXTENSION_NAME};
-    // PANIC SAFETY all of the names here are valid names
     lazy_static::lazy_static! {
-        pub static ref DECIMAL_FROM_STR_NAME : Name = Name::parse_unqualified_name(EXTENSION_NAME).expect("should be a valid identifier");
-        pub static ref LESS_THAN : Name = Name::parse_unqualified_name("lessThan").expect("should be a valid identifier");
-        pub static ref LESS_THAN_OR_EQUAL : Name = Name::parse_unqualified_name("lessThanOrEqual").expect("should be a valid identifier");
-        pub static ref GREATER_THAN : Name = Name::parse_unqualified_name("greaterThan").expect("should be a valid identifier");
-        pub static ref GREATER_THAN_OR_EQUAL : Name = Name::parse_unqualified_name("greaterThanOrEqual").expect("should be a valid identifier");
+        pub static ref DECIMAL_FROM_STR_NAME : Name = Name::builtin(EXTENSION_NAME);
+        pub static ref LESS_THAN : Name = Name::builtin("lessThan");
+        pub static ref LESS_THAN_OR_EQUAL : Name = Name::builtin("lessThanOrEqual");
+        pub static ref GREATER_THAN : Name = Name::builtin("greaterThan");
+        pub static ref GREATER_THAN_OR_EQUAL : Name = Name::builtin("greaterThanOrEqual");
     }
 }
 
@@ -287,6 +284,18 @@ pub fn extension() -> Extension {
             ),
         ],
     )
+    .with_operators([
+        (OverloadedOperator::Less, names::LESS_THAN.clone()),
+        (
+            OverloadedOperator::LessEq,
+            names::LESS_THAN_OR_EQUAL.clone(),
+        ),
+        (OverloadedOperator::Greater, names::GREATER_THAN.clone()),
+        (
+            OverloadedOperator::GreaterEq,
+            names::GREATER_THAN_OR_EQUAL.clone(),
+        ),
+    ])
 }
 
 #[cfg(test)]
@@ -603,10 +612,13 @@ mod tests {
         let entities = basic_entities();
         let eval = Evaluator::new(&request, &entities, &exts).unwrap();
 
+        // the built-in `<`, as opposed to the `<` of policy text, which calls
+        // `lessThan` on decimal values
         assert_eq!(
-            eval.interpret_inline_policy(
-                &parse_expr(r#"decimal("1.23") < decimal("1.24")"#).expect("parsing error")
-            ),
+            eval.interpret_inline_policy(&Expr::less(
+                parse_expr(r#"decimal("1.23")"#).expect("parsing error"),
+                parse_expr(r#"decimal("1.24")"#).expect("parsing error")
+            )),
             Err(evaluator::EvaluationError::type_error(
                 vec![Type::Long],
                 Type::Extension {
@@ -628,6 +640,12 @@ mod tests {
                 ADVICE_MSG.into(),
             ))
         );
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"decimal("1.23") < decimal("1.24")"#).expect("parsing error")
+            ),
+            Ok(Value::from(true))
+        );
         // bad use of `lessThan` as function
         parse_expr(r#"lessThan(decimal("-1.23"), decimal("1.23"))"#).expect_err("should fail");
     }
@@ -25,18 +25,16 @@ use crate::evaluator;
 use std::str::FromStr;
 use std::sync::Arc;
 
-// PANIC SAFETY All the names are valid names
-#[allow(clippy::expect_used)]
 mod names {
     use super::Name;
     lazy_static::lazy_static! {
-        pub static ref EXTENSION_NAME : Name = Name::parse_unqualified_name("ipaddr").expect("should be a valid identifier");
-        pub static ref IP_FROM_STR_NAME : Name = Name::parse_unqualified_name("ip").expect("should be a valid identifier");
-        pub static ref IS_IPV4 : Name = Name::parse_unqualified_name("isIpv4").expect("should be a valid identifier");
-        pub static ref IS_IPV6 : Name = Name::parse_unqualified_name("isIpv6").expect("should be a valid identifier");
-        pub static ref IS_LOOPBACK : Name = Name::parse_unqualified_name("isLoopback").expect("should be a valid identifier");
-        pub static ref IS_MULTICAST : Name = Name::parse_unqualified_name("isMulticast").expect("should be a valid identifier");
-        pub static ref IS_IN_RANGE : Name = Name::parse_unqualified_name("isInRange").expect("should be a valid identifier");
+        pub static ref EXTENSION_NAME : Name = Name::builtin("ipaddr");
+        pub static ref IP_FROM_STR_NAME : Name = Name::builtin("ip");
+        pub static ref IS_IPV4 : Name = Name::builtin("isIpv4");
+        pub static ref IS_IPV6 : Name = Name::builtin("isIpv6");
+        pub static ref IS_LOOPBACK : Name = Name::builtin("isLoopback");
+        pub static ref IS_MULTICAST : Name = Name::builtin("isMulticast");
+        pub static ref IS_IN_RANGE : Name = Name::builtin("isInRange");
     }
 }
 
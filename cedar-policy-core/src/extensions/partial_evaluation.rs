@@ -16,7 +16,7 @@
 
 //! This module contains the extension for including unknown values
 use crate::{
-    ast::{CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, Value},
+    ast::{CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, Name, Value},
     entities::SchemaType,
     evaluator::{self, EvaluationError},
 };
@@ -27,30 +27,26 @@ fn create_new_unknown(v: Value) -> evaluator::Result<ExtensionOutputValue> {
 
 fn throw_error(v: Value) -> evaluator::Result<ExtensionOutputValue> {
     let msg = v.get_as_string()?;
-    // PANIC SAFETY: This name is fully static, and is a valid extension name
-    #[allow(clippy::unwrap_used)]
     let err = EvaluationError::failed_extension_function_application(
-        "partial_evaluation".parse().unwrap(),
+        Name::builtin("partial_evaluation"),
         msg.to_string(),
     );
     Err(err)
 }
 
 /// Construct the extension
-// PANIC SAFETY: all uses of `unwrap` here on parsing extension names are correct names
-#[allow(clippy::unwrap_used)]
 pub fn extension() -> Extension {
     Extension::new(
-        "partial_evaluation".parse().unwrap(),
+        Name::builtin("partial_evaluation"),
         vec![
             ExtensionFunction::unary_never(
-                "unknown".parse().unwrap(),
+                Name::builtin("unknown"),
                 CallStyle::FunctionStyle,
                 Box::new(create_new_unknown),
                 Some(SchemaType::String),
             ),
             ExtensionFunction::unary_never(
-                "error".parse().unwrap(),
+                Name::builtin("error"),
                 CallStyle::FunctionStyle,
                 Box::new(throw_error),
                 Some(SchemaType::String),
@@ -0,0 +1,213 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! This module contains the Cedar 'quota' extension, whose functions look up
+//! how much an entity spent in a window of time, such as `spentToday(principal)`,
+//! as a `u256`.
+//!
+//! The evaluator holds no state, so the functions of [`extension`] fail: they
+//! must be overridden by those of [`extension_with`], which read the amounts
+//! from a store (see [`super::Extensions::with_overrides`]).
+
+use super::u256::{self, U256};
+use crate::ast::Value;
+use crate::ast::{CallStyle, EntityUID, Extension, ExtensionFunction, ExtensionOutputValue, Name};
+use crate::entities::SchemaType;
+use crate::evaluator::{self, EvaluationError};
+use std::sync::Arc;
+
+/// A window of time an amount is spent in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QuotaWindow {
+    /// The current UTC day
+    Day,
+    /// The current week, starting on Monday at midnight UTC
+    Week,
+}
+
+impl QuotaWindow {
+    /// All the windows
+    pub const ALL: [QuotaWindow; 2] = [QuotaWindow::Day, QuotaWindow::Week];
+
+    /// The name of the extension function giving the amount spent in this
+    /// window
+    pub fn function_name(self) -> &'static str {
+        match self {
+            Self::Day => "spentToday",
+            Self::Week => "spentThisWeek",
+        }
+    }
+
+    /// The length of this window, in milliseconds
+    pub fn duration_ms(self) -> u64 {
+        const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+        match self {
+            Self::Day => DAY_MS,
+            Self::Week => 7 * DAY_MS,
+        }
+    }
+
+    /// The start of the window containing `timestamp_ms`, in milliseconds
+    /// since the Unix epoch
+    pub fn start_ms(self, timestamp_ms: u64) -> u64 {
+        // the Unix epoch was on a Thursday, three days after a Monday
+        const EPOCH_OFFSET_MS: u64 = 3 * 24 * 60 * 60 * 1000;
+        match self {
+            Self::Day => timestamp_ms - timestamp_ms % self.duration_ms(),
+            Self::Week => {
+                let since_monday = (timestamp_ms + EPOCH_OFFSET_MS) % self.duration_ms();
+                timestamp_ms.saturating_sub(since_monday)
+            }
+        }
+    }
+}
+
+/// A function giving the amount an entity spent in a window, or why it could
+/// not be looked up
+pub type SpentFn = Arc<dyn Fn(&EntityUID, QuotaWindow) -> Result<U256, String> + Sync + Send>;
+
+fn extension_name() -> Name {
+    Name::builtin("quota")
+}
+
+fn spent(
+    spent: &SpentFn,
+    window: QuotaWindow,
+    arg: Value,
+) -> evaluator::Result<ExtensionOutputValue> {
+    let entity = arg.get_as_entity()?;
+    spent(entity, window)
+        .map(|amount| u256::u256_value(amount).into())
+        .map_err(|msg| {
+            EvaluationError::failed_extension_function_application(extension_name(), msg)
+        })
+}
+
+fn functions(spent_fn: Option<SpentFn>) -> Extension {
+    Extension::new(
+        extension_name(),
+        QuotaWindow::ALL.map(|window| {
+            let spent_fn = spent_fn.clone();
+            let func = move |arg: Value| match &spent_fn {
+                Some(spent_fn) => spent(spent_fn, window, arg),
+                None => Err(EvaluationError::failed_extension_function_application(
+                    extension_name(),
+                    format!(
+                        "`{}` requires a quota store to read the amounts spent from",
+                        window.function_name()
+                    ),
+                )),
+            };
+            ExtensionFunction::unary(
+                Name::builtin(window.function_name()),
+                CallStyle::FunctionStyle,
+                Box::new(func),
+                SchemaType::Extension {
+                    name: Name::builtin("u256"),
+                },
+                None,
+            )
+        }),
+    )
+}
+
+/// Construct the extension, whose functions fail for lack of a store
+pub fn extension() -> Extension {
+    functions(None)
+}
+
+/// Construct the extension with functions which read the amounts spent from
+/// `spent`
+pub fn extension_with(spent: SpentFn) -> Extension {
+    functions(Some(spent))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Expr, Request};
+    use crate::entities::Entities;
+    use crate::evaluator::Evaluator;
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    fn eval(exts: &Extensions<'_>, expr: &Expr) -> evaluator::Result<Value> {
+        let request = Request::new(
+            EntityUID::with_eid("alice"),
+            EntityUID::with_eid("spend"),
+            EntityUID::with_eid("vault"),
+            crate::ast::Context::empty(),
+        );
+        let entities = Entities::new();
+        let evaluator = Evaluator::new(&request, &entities, exts).expect("evaluator");
+        evaluator.interpret(expr, &std::collections::HashMap::new())
+    }
+
+    #[test]
+    fn spent_without_store() {
+        let exts = Extensions::all_available();
+        let expr = parse_expr(r#"spentToday(principal) < u256("100")"#).expect("valid expression");
+        assert!(matches!(
+            eval(&exts, &expr),
+            Err(e) if e.to_string().contains("requires a quota store")
+        ));
+    }
+
+    #[test]
+    fn spent_with_store() {
+        let overrides: Arc<[Extension]> =
+            Arc::new([extension_with(Arc::new(|uid, window| {
+                match (uid.eid().to_string().as_str(), window) {
+                    ("alice", QuotaWindow::Day) => Ok(U256::from(40u64)),
+                    ("alice", QuotaWindow::Week) => Ok(U256::from(250u64)),
+                    _ => Err("unknown entity".to_string()),
+                }
+            }))]);
+        let exts = Extensions::all_available().with_overrides(overrides);
+        for (expr, expected) in [
+            (r#"spentToday(principal) + u256("60") <= u256("100")"#, true),
+            (
+                r#"spentThisWeek(principal) + u256("60") <= u256("300")"#,
+                false,
+            ),
+        ] {
+            let expr = parse_expr(expr).expect("valid expression");
+            assert_eq!(
+                eval(&exts, &expr).expect("evaluates"),
+                Value::from(expected)
+            );
+        }
+        let expr = parse_expr("spentToday(resource)").expect("valid expression");
+        assert!(matches!(
+            eval(&exts, &expr),
+            Err(e) if e.to_string().contains("unknown entity")
+        ));
+    }
+
+    #[test]
+    fn window_starts() {
+        // Thursday, 1 January 1970, 10:00 UTC
+        let ts = 10 * 60 * 60 * 1000;
+        assert_eq!(QuotaWindow::Day.start_ms(ts), 0);
+        // Monday, 5 January 1970, 10:00 UTC
+        let monday = 7 * 24 * 60 * 60 * 1000 - 3 * 24 * 60 * 60 * 1000;
+        assert_eq!(QuotaWindow::Week.start_ms(monday + ts), monday);
+        assert_eq!(
+            QuotaWindow::Week.start_ms(monday + 6 * 24 * 60 * 60 * 1000),
+            monday
+        );
+    }
+}
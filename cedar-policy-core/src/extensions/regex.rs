@@ -0,0 +1,402 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! This module contains the Cedar 'regex' extension.
+
+use crate::ast::{
+    CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, ExtensionValue,
+    ExtensionValueWithArgs, Literal, Name, StaticallyTyped, Type, Value,
+};
+use crate::entities::SchemaType;
+use crate::evaluator;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Maximum size, in bytes, of a compiled pattern. This bounds the memory and
+/// time taken to match, which is linear in the length of the input string.
+const SIZE_LIMIT: usize = 1 << 16;
+
+/// Maximum nesting depth of groups and repetitions in a pattern
+const NEST_LIMIT: u32 = 16;
+
+/// Regular expression value, which matches whole strings.
+///
+/// Two regex values are equal if their patterns are the same text.
+#[derive(Debug, Clone)]
+struct Regex {
+    pattern: String,
+    regex: ::regex::Regex,
+}
+
+impl PartialEq for Regex {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern
+    }
+}
+
+impl Eq for Regex {}
+
+impl PartialOrd for Regex {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Regex {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.pattern.cmp(&other.pattern)
+    }
+}
+
+mod names {
+    use super::{Name, EXTENSION_NAME};
+    lazy_static::lazy_static! {
+        pub static ref REGEX_FROM_STR_NAME : Name = Name::builtin(EXTENSION_NAME);
+        pub static ref MATCHES_REGEX : Name = Name::builtin("matchesRegex");
+    }
+}
+
+/// Help message to display when a String was provided where a regex value was expected.
+/// This error is likely due to confusion between "[0-9]+" and regex("[0-9]+").
+const ADVICE_MSG: &str = "Maybe you forgot to apply the `regex` constructor?";
+
+/// Potential errors when working with regex values. Note that these are
+/// converted to evaluator::Err::ExtensionErr (which takes a string argument)
+/// before being reported to users.
+#[derive(Debug, Error)]
+enum Error {
+    /// Error parsing the input string as a pattern, or the pattern is over
+    /// the size or nesting limits
+    #[error("input string `{0}` is not a valid pattern: {1}")]
+    FailedParse(String, ::regex::Error),
+}
+
+impl Regex {
+    /// The Cedar typename of regex values
+    fn typename() -> Name {
+        names::REGEX_FROM_STR_NAME.clone()
+    }
+
+    /// Convert a string into a `Regex` value.
+    ///
+    /// The string uses the syntax of the `regex` crate, which has no
+    /// backreferences or lookaround, so matching takes time linear in the
+    /// length of the input. The pattern must match the whole input, as if it
+    /// were surrounded by `^(?:` and `)$`.
+    fn from_str(str: impl AsRef<str>) -> Result<Self, Error> {
+        let str = str.as_ref();
+        let build = |pattern: &str, nest_limit| {
+            ::regex::RegexBuilder::new(pattern)
+                .size_limit(SIZE_LIMIT)
+                .nest_limit(nest_limit)
+                .build()
+                .map_err(|e| Error::FailedParse(str.to_owned(), e))
+        };
+        // errors in the pattern are reported against the pattern as written,
+        // before it is anchored with an extra group
+        build(str, NEST_LIMIT)?;
+        let regex = build(&format!("^(?:{str})$"), NEST_LIMIT + 1)?;
+        Ok(Self {
+            pattern: str.to_owned(),
+            regex,
+        })
+    }
+}
+
+impl std::fmt::Display for Regex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.pattern)
+    }
+}
+
+impl ExtensionValue for Regex {
+    fn typename(&self) -> Name {
+        Self::typename()
+    }
+}
+
+const EXTENSION_NAME: &str = "regex";
+
+fn extension_err(msg: impl Into<String>) -> evaluator::EvaluationError {
+    evaluator::EvaluationError::failed_extension_function_application(
+        names::REGEX_FROM_STR_NAME.clone(),
+        msg.into(),
+    )
+}
+
+/// Cedar function that constructs a `regex` Cedar type from a
+/// Cedar string
+fn regex_from_str(arg: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let str = arg.get_as_string()?;
+    let regex = Regex::from_str(str.as_str()).map_err(|e| extension_err(e.to_string()))?;
+    let function_name = names::REGEX_FROM_STR_NAME.clone();
+    let e = ExtensionValueWithArgs::new(Arc::new(regex), vec![arg.into()], function_name);
+    Ok(Value::ExtensionValue(Arc::new(e)).into())
+}
+
+/// Check that `v` is a regex type and, if it is, return the wrapped value
+fn as_regex(v: &Value) -> Result<&Regex, evaluator::EvaluationError> {
+    match v {
+        Value::ExtensionValue(ev) if ev.typename() == Regex::typename() => {
+            // PANIC SAFETY Conditional above performs a typecheck
+            #[allow(clippy::expect_used)]
+            let r = ev
+                .value()
+                .as_any()
+                .downcast_ref::<Regex>()
+                .expect("already typechecked, so this downcast should succeed");
+            Ok(r)
+        }
+        Value::Lit(Literal::String(_)) => Err(evaluator::EvaluationError::type_error_with_advice(
+            vec![Type::Extension {
+                name: Regex::typename(),
+            }],
+            v.type_of(),
+            ADVICE_MSG.into(),
+        )),
+        _ => Err(evaluator::EvaluationError::type_error(
+            vec![Type::Extension {
+                name: Regex::typename(),
+            }],
+            v.type_of(),
+        )),
+    }
+}
+
+/// Cedar function that tests whether the whole of a Cedar string matches a
+/// `regex` Cedar type, returning a Cedar bool
+fn matches_regex(left: Value, right: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let str = left.get_as_string()?;
+    let regex = as_regex(&right)?;
+    Ok(Value::Lit(regex.regex.is_match(str).into()).into())
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    let regex_type = SchemaType::Extension {
+        name: Regex::typename(),
+    };
+    Extension::new(
+        names::REGEX_FROM_STR_NAME.clone(),
+        vec![
+            ExtensionFunction::unary(
+                names::REGEX_FROM_STR_NAME.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(regex_from_str),
+                regex_type.clone(),
+                Some(SchemaType::String),
+            ),
+            ExtensionFunction::binary(
+                names::MATCHES_REGEX.clone(),
+                CallStyle::MethodStyle,
+                Box::new(matches_regex),
+                SchemaType::Bool,
+                (Some(SchemaType::String), Some(regex_type)),
+            ),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Expr, Value};
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::Evaluator;
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    /// Asserts that a `Result` is an `Err::ExtensionErr` with our extension name
+    fn assert_regex_err<T>(res: evaluator::Result<T>) {
+        match res {
+            Err(e) => match e.error_kind() {
+                evaluator::EvaluationErrorKind::FailedExtensionFunctionApplication {
+                    extension_name,
+                    msg,
+                } => {
+                    println!("{msg}");
+                    assert_eq!(
+                        *extension_name,
+                        Name::parse_unqualified_name("regex")
+                            .expect("should be a valid identifier")
+                    )
+                }
+                _ => panic!("Expected a regex ExtensionErr, got {:?}", e),
+            },
+            Ok(_) => panic!("Expected a regex ExtensionErr, got Ok"),
+        }
+    }
+
+    /// Asserts that a `Result` is a regex value
+    fn assert_regex_valid(res: evaluator::Result<Value>) {
+        match res {
+            Ok(Value::ExtensionValue(ev)) => {
+                assert_eq!(ev.typename(), Regex::typename())
+            }
+            Ok(v) => panic!("Expected regex ExtensionValue, got {:?}", v),
+            Err(e) => panic!("Expected Ok, got Err: {:?}", e),
+        }
+    }
+
+    /// this test just ensures that the right functions are marked constructors
+    #[test]
+    fn constructors() {
+        let ext = extension();
+        assert!(ext
+            .get_func(&Name::parse_unqualified_name("regex").expect("should be a valid identifier"))
+            .expect("function should exist")
+            .is_constructor());
+        assert!(!ext
+            .get_func(
+                &Name::parse_unqualified_name("matchesRegex")
+                    .expect("should be a valid identifier")
+            )
+            .expect("function should exist")
+            .is_constructor());
+    }
+
+    #[test]
+    fn regex_creation() {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        // valid patterns
+        assert_regex_valid(
+            eval.interpret_inline_policy(&parse_expr(r#"regex("")"#).expect("parsing error")),
+        );
+        assert_regex_valid(eval.interpret_inline_policy(
+            &parse_expr(r#"regex("eip155:[0-9]+:0x[0-9a-fA-F]{40}")"#).expect("parsing error"),
+        ));
+        assert_regex_valid(eval.interpret_inline_policy(
+            &parse_expr(r#"regex("(mainnet|sepolia)\\.vault")"#).expect("parsing error"),
+        ));
+
+        // invalid patterns
+        assert_regex_err(
+            eval.interpret_inline_policy(&parse_expr(r#"regex("[0-9")"#).expect("parsing error")),
+        );
+        assert_regex_err(
+            eval.interpret_inline_policy(&parse_expr(r#"regex("(a")"#).expect("parsing error")),
+        );
+        // backreferences are not supported
+        assert_regex_err(
+            eval.interpret_inline_policy(&parse_expr(r#"regex("(a)\\1")"#).expect("parsing error")),
+        );
+        // over the size limit
+        assert_regex_err(eval.interpret_inline_policy(
+            &parse_expr(r#"regex("(\\w{100}){100}")"#).expect("parsing error"),
+        ));
+        // over the nesting limit
+        assert_regex_err(
+            eval.interpret_inline_policy(
+                &parse_expr(&format!(
+                    r#"regex("{}a{}")"#,
+                    "(".repeat(20),
+                    ")".repeat(20)
+                ))
+                .expect("parsing error"),
+            ),
+        );
+
+        // bad use of `regex` as method
+        parse_expr(r#" "[0-9]+".regex() "#).expect_err("should fail");
+    }
+
+    #[test]
+    fn regex_equality() {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        let a = parse_expr(r#"regex("[0-9]+")"#).expect("parsing error");
+        let b = parse_expr(r#"regex("[0-9]+")"#).expect("parsing error");
+        let c = parse_expr(r#"regex("\\d+")"#).expect("parsing error");
+
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::is_eq(a.clone(), b)),
+            Ok(Value::from(true))
+        );
+        // patterns which are different text are different, even if they match
+        // the same strings
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::is_eq(a.clone(), c)),
+            Ok(Value::from(false))
+        );
+        // other types are not equal
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::is_eq(a, Expr::val("[0-9]+"))),
+            Ok(Value::from(false))
+        );
+    }
+
+    #[test]
+    fn regex_matching() {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        let matches = |str: &str, pattern: &str| {
+            eval.interpret_inline_policy(
+                &parse_expr(&format!(r#""{str}".matchesRegex(regex("{pattern}"))"#))
+                    .expect("parsing error"),
+            )
+        };
+        let account = "eip155:1:0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+        let pattern = "eip155:[0-9]+:0x[0-9a-fA-F]{40}";
+        assert_eq!(matches(account, pattern), Ok(Value::from(true)));
+        assert_eq!(
+            matches("eip155:1:0xA0b86991", pattern),
+            Ok(Value::from(false))
+        );
+        // the pattern must match the whole string
+        assert_eq!(
+            matches(&format!("{account}/extra"), pattern),
+            Ok(Value::from(false))
+        );
+        assert_eq!(
+            matches("xeip155:1", "eip155:[0-9]+"),
+            Ok(Value::from(false))
+        );
+        assert_eq!(matches("a", "a|b"), Ok(Value::from(true)));
+        assert_eq!(matches("ab", "a|b"), Ok(Value::from(false)));
+
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(r#""123".matchesRegex("[0-9]+")"#).expect("parsing error")
+            ),
+            Err(evaluator::EvaluationError::type_error_with_advice(
+                vec![Type::Extension {
+                    name: Regex::typename()
+                }],
+                Type::String,
+                ADVICE_MSG.into(),
+            ))
+        );
+    }
+
+    #[test]
+    fn regex_display() {
+        let r = Regex::from_str("eip155:[0-9]+").expect("should be a valid pattern");
+        assert_eq!(r.to_string(), "eip155:[0-9]+");
+    }
+}
@@ -0,0 +1,333 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! This module contains the Cedar 'simulation' extension, whose methods assert
+//! on the result of simulating a transaction, such as
+//! `context.simulation.receivedAtLeast(usdc, u256("1000000"))`.
+//!
+//! The methods are called on a simulation record of the form
+//! ```text
+//! {
+//!   "balanceChanges": [{ "token": address, "received": u256, "sent": u256 }],
+//!   "events": [{ "contract": address, "topic0": bytes }]
+//! }
+//! ```
+//! where a token may appear in several balance changes, whose amounts add up.
+
+use crate::ast::{
+    CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, Name, StaticallyTyped, Type,
+    Value,
+};
+use crate::entities::{AttributeType, SchemaType};
+use crate::evaluator;
+use crate::extensions::u256::{u256_value, U256};
+use ethers::types::{Address, H256};
+use smol_str::SmolStr;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+/// The attribute of the simulation record listing balance changes
+pub const BALANCE_CHANGES: &str = "balanceChanges";
+/// The attribute of the simulation record listing emitted events
+pub const EVENTS: &str = "events";
+/// The attribute of `netBalanceChange` results holding how much the balance
+/// increased by
+pub const GAIN: &str = "gain";
+/// The attribute of `netBalanceChange` results holding how much the balance
+/// decreased by
+pub const LOSS: &str = "loss";
+
+mod names {
+    use super::{Name, EXTENSION_NAME};
+    lazy_static::lazy_static! {
+        pub static ref EXTENSION : Name = Name::builtin(EXTENSION_NAME);
+        pub static ref NET_BALANCE_CHANGE : Name = Name::builtin("netBalanceChange");
+        pub static ref RECEIVED_AT_LEAST : Name = Name::builtin("receivedAtLeast");
+        pub static ref EMITTED_EVENT : Name = Name::builtin("emittedEvent");
+    }
+}
+
+const EXTENSION_NAME: &str = "simulation";
+
+fn extension_err(msg: impl Into<String>) -> evaluator::EvaluationError {
+    evaluator::EvaluationError::failed_extension_function_application(
+        names::EXTENSION.clone(),
+        msg.into(),
+    )
+}
+
+/// Get the attribute `attr` of the record `v`
+fn get_attr<'a>(v: &'a Value, attr: &str) -> evaluator::Result<&'a Value> {
+    match v {
+        Value::Record(record) => record
+            .get(attr)
+            .ok_or_else(|| extension_err(format!("simulation record has no `{attr}` attribute"))),
+        _ => Err(evaluator::EvaluationError::type_error(
+            vec![Type::Record],
+            v.type_of(),
+        )),
+    }
+}
+
+/// Get the elements of the set in the attribute `attr` of the record `v`
+fn get_list<'a>(
+    v: &'a Value,
+    attr: &str,
+) -> evaluator::Result<impl Iterator<Item = &'a Value> + 'a> {
+    match get_attr(v, attr)? {
+        Value::Set(set) => Ok(set.authoritative.iter()),
+        other => Err(evaluator::EvaluationError::type_error(
+            vec![Type::Set],
+            other.type_of(),
+        )),
+    }
+}
+
+/// The total amounts of `token` received and sent in the simulation `sim`
+fn totals(sim: &Value, token: Address) -> evaluator::Result<(U256, U256)> {
+    let overflow = || {
+        extension_err(format!(
+            "overflow adding up the balance changes of {token:?}"
+        ))
+    };
+    let mut received = U256::default();
+    let mut sent = U256::default();
+    for change in get_list(sim, BALANCE_CHANGES)? {
+        if Address::try_from(get_attr(change, "token")?)? == token {
+            received = received
+                .checked_add(U256::try_from(get_attr(change, "received")?)?)
+                .ok_or_else(overflow)?;
+            sent = sent
+                .checked_add(U256::try_from(get_attr(change, "sent")?)?)
+                .ok_or_else(overflow)?;
+        }
+    }
+    Ok((received, sent))
+}
+
+/// Cedar function giving the net change of the balance of a token in a
+/// simulation, as a record with the amount it increased by, and the amount it
+/// decreased by, one of which is zero
+fn net_balance_change(sim: Value, token: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let (received, sent) = totals(&sim, Address::try_from(&token)?)?;
+    let (gain, loss) = if received >= sent {
+        (received - sent, U256::default())
+    } else {
+        (U256::default(), sent - received)
+    };
+    Ok(Value::from(BTreeMap::from([
+        (SmolStr::new(GAIN), u256_value(gain)),
+        (SmolStr::new(LOSS), u256_value(loss)),
+    ]))
+    .into())
+}
+
+/// Cedar function that tests whether the balance of a token increased by at
+/// least an amount in a simulation
+fn received_at_least(
+    sim: Value,
+    token: Value,
+    amount: Value,
+) -> evaluator::Result<ExtensionOutputValue> {
+    let (received, sent) = totals(&sim, Address::try_from(&token)?)?;
+    let amount = U256::try_from(&amount)?;
+    let at_least = received >= sent && received - sent >= amount;
+    Ok(Value::from(at_least).into())
+}
+
+/// Cedar function that tests whether a contract emitted an event with a
+/// topic0 (the hash of the event signature) in a simulation
+fn emitted_event(
+    sim: Value,
+    contract: Value,
+    topic0: Value,
+) -> evaluator::Result<ExtensionOutputValue> {
+    let contract = Address::try_from(&contract)?;
+    let topic0 = H256::try_from(&topic0)?;
+    for event in get_list(&sim, EVENTS)? {
+        if Address::try_from(get_attr(event, "contract")?)? == contract
+            && H256::try_from(get_attr(event, "topic0")?)? == topic0
+        {
+            return Ok(Value::from(true).into());
+        }
+    }
+    Ok(Value::from(false).into())
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    let [address_type, bytes_type, u256_type] =
+        ["address", "bytes", "u256"].map(|name| SchemaType::Extension {
+            name: Name::builtin(name),
+        });
+    let change_type = SchemaType::Record {
+        attrs: HashMap::from([
+            (
+                SmolStr::new(GAIN),
+                AttributeType::required(u256_type.clone()),
+            ),
+            (
+                SmolStr::new(LOSS),
+                AttributeType::required(u256_type.clone()),
+            ),
+        ]),
+    };
+    Extension::new(
+        names::EXTENSION.clone(),
+        vec![
+            ExtensionFunction::binary(
+                names::NET_BALANCE_CHANGE.clone(),
+                CallStyle::MethodStyle,
+                Box::new(net_balance_change),
+                change_type,
+                (None, Some(address_type.clone())),
+            ),
+            ExtensionFunction::ternary(
+                names::RECEIVED_AT_LEAST.clone(),
+                CallStyle::MethodStyle,
+                Box::new(received_at_least),
+                SchemaType::Bool,
+                (None, Some(address_type.clone()), Some(u256_type)),
+            ),
+            ExtensionFunction::ternary(
+                names::EMITTED_EVENT.clone(),
+                CallStyle::MethodStyle,
+                Box::new(emitted_event),
+                SchemaType::Bool,
+                (None, Some(address_type), Some(bytes_type)),
+            ),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Expr, Request};
+    use crate::entities::Entities;
+    use crate::evaluator::Evaluator;
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    const USDC: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+    const WETH: &str = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+    const TRANSFER: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+
+    /// Evaluate `expr`, with `sim` bound to a simulation in which 1500 USDC
+    /// was received in two transfers and 500 sent, and 1 WETH sent
+    fn eval(expr: &str) -> evaluator::Result<Value> {
+        let sim = format!(
+            r#"{{
+                balanceChanges: [
+                    {{ token: address("{USDC}"), received: u256("1000"), sent: u256("500") }},
+                    {{ token: address("{USDC}"), received: u256("500"), sent: u256("0") }},
+                    {{ token: address("{WETH}"), received: u256("0"), sent: u256("1") }}
+                ],
+                events: [{{ contract: address("{USDC}"), topic0: bytes("{TRANSFER}") }}]
+            }}"#
+        );
+        let expr = expr.replace("sim", &sim);
+        let expr: Expr = parse_expr(&expr).expect("valid expression");
+        let request = Request::new(
+            crate::ast::EntityUID::with_eid("alice"),
+            crate::ast::EntityUID::with_eid("swap"),
+            crate::ast::EntityUID::with_eid("router"),
+            crate::ast::Context::empty(),
+        );
+        let entities = Entities::new();
+        let exts = Extensions::all_available();
+        let evaluator = Evaluator::new(&request, &entities, &exts).expect("evaluator");
+        evaluator.interpret(&expr, &std::collections::HashMap::new())
+    }
+
+    #[test]
+    fn net_balance_change() {
+        for (expr, expected) in [
+            (
+                format!(r#"sim.netBalanceChange(address("{USDC}")).gain == u256("1000")"#),
+                true,
+            ),
+            (
+                format!(r#"sim.netBalanceChange(address("{USDC}")).loss == u256("0")"#),
+                true,
+            ),
+            (
+                format!(r#"sim.netBalanceChange(address("{WETH}")).loss == u256("1")"#),
+                true,
+            ),
+            (
+                format!(r#"sim.netBalanceChange(address("{WETH}")).gain == u256("0")"#),
+                true,
+            ),
+        ] {
+            assert_eq!(
+                eval(&expr).expect("evaluates"),
+                Value::from(expected),
+                "{expr}"
+            );
+        }
+    }
+
+    #[test]
+    fn received_at_least() {
+        for (expr, expected) in [
+            (format!(r#"sim.receivedAtLeast(address("{USDC}"), u256("1000"))"#), true),
+            (format!(r#"sim.receivedAtLeast(address("{USDC}"), u256("1001"))"#), false),
+            (format!(r#"sim.receivedAtLeast(address("{WETH}"), u256("0"))"#), false),
+            (
+                r#"sim.receivedAtLeast(address("0x0000000000000000000000000000000000000000"), u256("0"))"#
+                    .to_string(),
+                true,
+            ),
+        ] {
+            assert_eq!(eval(&expr).expect("evaluates"), Value::from(expected), "{expr}");
+        }
+    }
+
+    #[test]
+    fn emitted_event() {
+        for (expr, expected) in [
+            (
+                format!(r#"sim.emittedEvent(address("{USDC}"), bytes("{TRANSFER}"))"#),
+                true,
+            ),
+            (
+                format!(r#"sim.emittedEvent(address("{WETH}"), bytes("{TRANSFER}"))"#),
+                false,
+            ),
+        ] {
+            assert_eq!(
+                eval(&expr).expect("evaluates"),
+                Value::from(expected),
+                "{expr}"
+            );
+        }
+        // topics are 32-byte hashes
+        assert!(eval(&format!(
+            r#"sim.emittedEvent(address("{USDC}"), bytes("0xddf252ad"))"#
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn malformed_simulation() {
+        assert!(matches!(
+            eval(&format!(r#"{{}}.receivedAtLeast(address("{USDC}"), u256("1"))"#)),
+            Err(e) if e.to_string().contains("no `balanceChanges` attribute")
+        ));
+        assert!(eval(&format!(r#""text".netBalanceChange(address("{USDC}"))"#)).is_err());
+    }
+}
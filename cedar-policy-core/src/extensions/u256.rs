@@ -15,19 +15,25 @@
  */
 
 //! This module contains the Cedar 'u256' extension.
-
-use regex::Regex;
+//!
+//! Values are held in the `U256` of `ethers`, or with the `u256-ruint` feature,
+//! in that of `ruint`, whose limb arithmetic is faster for the additions,
+//! multiplications and comparisons of value-limit policies. Both backends
+//! parse, print and overflow alike.
 
 use crate::ast::{
     CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, ExtensionValue,
-    ExtensionValueWithArgs, Name, Value,
+    ExtensionValueWithArgs, Name, OverloadedOperator, RestrictedExpr, StaticallyTyped, Type, Value,
 };
 use crate::entities::SchemaType;
 use crate::evaluator;
 use std::sync::Arc;
 use thiserror::Error;
 
-use ethers::prelude::U256;
+#[cfg(not(feature = "u256-ruint"))]
+pub use ethers::prelude::U256;
+#[cfg(feature = "u256-ruint")]
+pub use ruint::aliases::U256;
 
 /// UINT256 value, represented internally as an integer.
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
@@ -35,18 +41,18 @@ struct UINT256 {
     value: U256,
 }
 
-// PANIC SAFETY All `Name`s in here are valid `Name`s
-#[allow(clippy::expect_used)]
 mod names {
     use super::{Name, EXTENSION_NAME};
-    // PANIC SAFETY all of the names here are valid names
     lazy_static::lazy_static! {
         // pub static ref DECIMAL_FROM_STR_NAME : Name = Name::parse_unqualified_name(EXTENSION_NAME).expect("should be a valid identifier");
-        pub static ref UINT256_FROM_STR_NAME : Name = Name::parse_unqualified_name(EXTENSION_NAME).expect("should be a valid identifier");
-        pub static ref LESS_THAN : Name = Name::parse_unqualified_name("u256LessThan").expect("should be a valid identifier");
-        pub static ref LESS_THAN_OR_EQUAL : Name = Name::parse_unqualified_name("u256LessThanOrEqual").expect("should be a valid identifier");
-        pub static ref GREATER_THAN : Name = Name::parse_unqualified_name("u256GreaterThan").expect("should be a valid identifier");
-        pub static ref GREATER_THAN_OR_EQUAL : Name = Name::parse_unqualified_name("u256GreaterThanOrEqual").expect("should be a valid identifier");
+        pub static ref UINT256_FROM_STR_NAME : Name = Name::builtin(EXTENSION_NAME);
+        pub static ref LESS_THAN : Name = Name::builtin("u256LessThan");
+        pub static ref LESS_THAN_OR_EQUAL : Name = Name::builtin("u256LessThanOrEqual");
+        pub static ref GREATER_THAN : Name = Name::builtin("u256GreaterThan");
+        pub static ref GREATER_THAN_OR_EQUAL : Name = Name::builtin("u256GreaterThanOrEqual");
+        pub static ref ADD : Name = Name::builtin("u256Add");
+        pub static ref SUB : Name = Name::builtin("u256Sub");
+        pub static ref MUL : Name = Name::builtin("u256Mul");
     }
 }
 
@@ -62,6 +68,10 @@ enum Error {
     /// Overflow occurred when converting to a u256 value
     #[error("overflow when converting to u256")]
     Overflow,
+
+    /// Overflow or underflow occurred in u256 arithmetic
+    #[error("overflow in u256 {0}")]
+    ArithmeticOverflow(&'static str),
 }
 
 impl UINT256 {
@@ -72,23 +82,21 @@ impl UINT256 {
 
     /// Convert a string into a `UINT256` value.
     ///
-    /// Matches against the regular expression `-?[0-9]+.[0-9]+`, which requires
-    /// only int digits
-    ///
+    /// The string is either decimal digits, or `0x` followed by hex digits (in
+    /// either case), as used for balances and other quantities in Ethereum
+    /// JSON-RPC responses.
     fn from_str(str: impl AsRef<str>) -> Result<Self, Error> {
-        // check that the string matches the regex
-        // PANIC SAFETY: This regex does parse
-        #[allow(clippy::unwrap_used)]
-        let re = Regex::new(r#"^[0-9]\d*$"#).unwrap();
-        if !re.is_match(str.as_ref()) {
-            return Err(Error::FailedParse(str.as_ref().to_owned()));
+        let str = str.as_ref();
+        let (digits, radix) = match str.strip_prefix("0x") {
+            Some(digits) => (digits, 16),
+            None => (str, 10),
+        };
+        if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+            return Err(Error::FailedParse(str.to_owned()));
         }
 
-        let l = U256::from_dec_str(str.as_ref()).map_err(|_| Error::Overflow)?;
-
-        Ok(Self { value: l })
-        // l.map(|value| Self { value })
-        // .ok_or(Error::Overflow)
+        let value = U256::from_str_radix(digits, radix.into()).map_err(|_| Error::Overflow)?;
+        Ok(Self { value })
     }
 }
 
@@ -120,6 +128,35 @@ fn extension_err(msg: impl Into<String>) -> evaluator::EvaluationError {
 //     }
 // }
 
+/// Construct the restricted expression `u256("...")` holding `value`, for
+/// attribute and context values built programmatically
+pub fn u256_expr(value: U256) -> RestrictedExpr {
+    RestrictedExpr::call_extension_fn(
+        names::UINT256_FROM_STR_NAME.clone(),
+        vec![RestrictedExpr::val(value.to_string())],
+    )
+}
+
+/// Parse the string form of a `u256` value: decimal digits, or `0x` followed
+/// by hex digits
+pub fn parse_u256(str: &str) -> Option<U256> {
+    UINT256::from_str(str).ok().map(|u| u.value)
+}
+
+impl From<U256> for RestrictedExpr {
+    fn from(value: U256) -> Self {
+        u256_expr(value)
+    }
+}
+
+impl TryFrom<&Value> for U256 {
+    type Error = evaluator::EvaluationError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        as_u256(value).map(|u| u.value)
+    }
+}
+
 /// Cedar function that constructs a `u256` Cedar type from a
 /// Cedar string
 fn uint256_from_str(arg: Value) -> evaluator::Result<ExtensionOutputValue> {
@@ -130,6 +167,75 @@ fn uint256_from_str(arg: Value) -> evaluator::Result<ExtensionOutputValue> {
     Ok(Value::ExtensionValue(Arc::new(e)).into())
 }
 
+/// Check that `v` is a `u256` value and get it
+fn as_u256(v: &Value) -> Result<&UINT256, evaluator::EvaluationError> {
+    match v {
+        Value::ExtensionValue(ev) if ev.typename() == UINT256::typename() => {
+            // PANIC SAFETY Conditional above performs a typecheck
+            #[allow(clippy::expect_used)]
+            let u = ev
+                .value()
+                .as_any()
+                .downcast_ref::<UINT256>()
+                .expect("already typechecked, so this downcast should succeed");
+            Ok(u)
+        }
+        _ => Err(evaluator::EvaluationError::type_error(
+            vec![Type::Extension {
+                name: UINT256::typename(),
+            }],
+            v.type_of(),
+        )),
+    }
+}
+
+/// Construct the Cedar value of the result of u256 arithmetic
+fn uint256_value(value: U256) -> ExtensionOutputValue {
+    u256_value(value).into()
+}
+
+/// Construct the Cedar `u256` value holding `value`, for the results of
+/// functions of other extensions
+pub(crate) fn u256_value(value: U256) -> Value {
+    let u256 = UINT256 { value };
+    let arg = Value::from(u256.to_string());
+    let e = ExtensionValueWithArgs::new(
+        Arc::new(u256),
+        vec![arg.into()],
+        names::UINT256_FROM_STR_NAME.clone(),
+    );
+    Value::ExtensionValue(Arc::new(e))
+}
+
+/// Cedar function that adds two `u256` Cedar types, erroring on overflow
+fn uint256_add(left: Value, right: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let sum = as_u256(&left)?
+        .value
+        .checked_add(as_u256(&right)?.value)
+        .ok_or_else(|| extension_err(Error::ArithmeticOverflow("addition").to_string()))?;
+    Ok(uint256_value(sum))
+}
+
+/// Cedar function that subtracts the second `u256` Cedar type from the first,
+/// erroring if the result would be negative
+fn uint256_sub(left: Value, right: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let difference = as_u256(&left)?
+        .value
+        .checked_sub(as_u256(&right)?.value)
+        .ok_or_else(|| extension_err(Error::ArithmeticOverflow("subtraction").to_string()))?;
+    Ok(uint256_value(difference))
+}
+
+/// Cedar function that multiplies two `u256` Cedar types, erroring on
+/// overflow
+fn uint256_mul(left: Value, right: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let product = as_u256(&left)?
+        .value
+        .checked_mul(as_u256(&right)?.value)
+        .ok_or_else(|| extension_err(Error::ArithmeticOverflow("multiplication").to_string()))?;
+    Ok(uint256_value(product))
+}
+
 /// Cedar function that tests whether the first `u256` Cedar type is
 /// less than the second `u256` Cedar type, returning a Cedar bool
 fn uint256_lt(left: Value, right: Value) -> evaluator::Result<ExtensionOutputValue> {
@@ -195,10 +301,46 @@ pub fn extension() -> Extension {
                 CallStyle::MethodStyle,
                 Box::new(uint256_ge),
                 SchemaType::Bool,
+                (Some(uint256_type.clone()), Some(uint256_type.clone())),
+            ),
+            ExtensionFunction::binary(
+                names::ADD.clone(),
+                CallStyle::MethodStyle,
+                Box::new(uint256_add),
+                uint256_type.clone(),
+                (Some(uint256_type.clone()), Some(uint256_type.clone())),
+            ),
+            ExtensionFunction::binary(
+                names::SUB.clone(),
+                CallStyle::MethodStyle,
+                Box::new(uint256_sub),
+                uint256_type.clone(),
+                (Some(uint256_type.clone()), Some(uint256_type.clone())),
+            ),
+            ExtensionFunction::binary(
+                names::MUL.clone(),
+                CallStyle::MethodStyle,
+                Box::new(uint256_mul),
+                uint256_type.clone(),
                 (Some(uint256_type.clone()), Some(uint256_type)),
             ),
         ],
     )
+    .with_operators([
+        (OverloadedOperator::Less, names::LESS_THAN.clone()),
+        (
+            OverloadedOperator::LessEq,
+            names::LESS_THAN_OR_EQUAL.clone(),
+        ),
+        (OverloadedOperator::Greater, names::GREATER_THAN.clone()),
+        (
+            OverloadedOperator::GreaterEq,
+            names::GREATER_THAN_OR_EQUAL.clone(),
+        ),
+        (OverloadedOperator::Add, names::ADD.clone()),
+        (OverloadedOperator::Sub, names::SUB.clone()),
+        (OverloadedOperator::Mul, names::MUL.clone()),
+    ])
 }
 
 #[cfg(test)]
@@ -300,6 +442,17 @@ mod tests {
         assert_uint256_valid(
             eval.interpret_inline_policy(&parse_expr(r#"u256("1234")"#).expect("parsing error")),
         );
+        assert_uint256_valid(eval.interpret_inline_policy(
+            &parse_expr(r#"u256("0x1bc16d674ec80000")"#).expect("parsing error"),
+        ));
+        assert_uint256_valid(
+            eval.interpret_inline_policy(
+                &parse_expr(
+                    r#"u256("0xFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF")"#,
+                )
+                .expect("parsing error"),
+            ),
+        );
 
         // invalid u256 strings
         assert_uint256_err(
@@ -320,6 +473,21 @@ mod tests {
         assert_uint256_err(
             eval.interpret_inline_policy(&parse_expr(r#"u256("-.")"#).expect("parsing error")),
         );
+        assert_uint256_err(
+            eval.interpret_inline_policy(&parse_expr(r#"u256("")"#).expect("parsing error")),
+        );
+        assert_uint256_err(
+            eval.interpret_inline_policy(&parse_expr(r#"u256("0x")"#).expect("parsing error")),
+        );
+        assert_uint256_err(
+            eval.interpret_inline_policy(&parse_expr(r#"u256("0x1g")"#).expect("parsing error")),
+        );
+        assert_uint256_err(
+            eval.interpret_inline_policy(&parse_expr(r#"u256("115792089237316195423570985008687907853269984665640564039457584007913129639936")"#).expect("parsing error")),
+        );
+        assert_uint256_err(
+            eval.interpret_inline_policy(&parse_expr(r#"u256("0x10000000000000000000000000000000000000000000000000000000000000000")"#).expect("parsing error")),
+        );
 
         // bad use of `u256` as method
         parse_expr(r#" "1.0".u256() "#).expect_err("should fail");
@@ -335,7 +503,7 @@ mod tests {
 
         let a = parse_expr(r#"u256("123")"#).expect("parsing error");
         let b = parse_expr(r#"u256("123")"#).expect("parsing error");
-        let c = parse_expr(r#"u256("123")"#).expect("parsing error");
+        let c = parse_expr(r#"u256("0x7b")"#).expect("parsing error");
         let d = parse_expr(r#"u256("124")"#).expect("parsing error");
         let e = parse_expr(r#"u256("1")"#).expect("parsing error");
         let f = parse_expr(r#"u256("0")"#).expect("parsing error");
@@ -449,10 +617,13 @@ mod tests {
         let entities = basic_entities();
         let eval = Evaluator::new(&request, &entities, &exts).unwrap();
 
+        // the built-in `<`, as opposed to the `<` of policy text, which calls
+        // `u256LessThan` on u256 values
         assert_eq!(
-            eval.interpret_inline_policy(
-                &parse_expr(r#"u256("123") < u256("124")"#).expect("parsing error")
-            ),
+            eval.interpret_inline_policy(&Expr::less(
+                parse_expr(r#"u256("123")"#).expect("parsing error"),
+                parse_expr(r#"u256("124")"#).expect("parsing error")
+            )),
             Err(evaluator::EvaluationError::type_error(
                 vec![Type::Long],
                 Type::Extension {
@@ -463,6 +634,42 @@ mod tests {
         );
     }
 
+    #[test]
+    fn uint256_arithmetic() {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        for (src, expected) in [
+            (r#"u256("123").u256Add(u256("1"))"#, r#"u256("124")"#),
+            (r#"u256("123").u256Sub(u256("123"))"#, r#"u256("0")"#),
+            (r#"u256("0x10").u256Mul(u256("16"))"#, r#"u256("256")"#),
+        ] {
+            assert_eq!(
+                eval.interpret_inline_policy(&parse_expr(src).expect("parsing error")),
+                eval.interpret_inline_policy(&parse_expr(expected).expect("parsing error")),
+                "{src}"
+            );
+        }
+
+        // overflow and underflow
+        assert_uint256_err(eval.interpret_inline_policy(
+            &parse_expr(r#"u256("0").u256Sub(u256("1"))"#).expect("parsing error"),
+        ));
+        assert_uint256_err(eval.interpret_inline_policy(
+            &parse_expr(r#"u256("115792089237316195423570985008687907853269984665640564039457584007913129639935").u256Add(u256("1"))"#).expect("parsing error"),
+        ));
+        assert_uint256_err(eval.interpret_inline_policy(
+            &parse_expr(r#"u256("0x100000000000000000000000000000000").u256Mul(u256("0x100000000000000000000000000000000"))"#).expect("parsing error"),
+        ));
+        // type errors
+        assert!(eval
+            .interpret_inline_policy(&parse_expr(r#"u256("1").u256Add(1)"#).expect("parsing error"))
+            .is_err());
+    }
+
     fn check_round_trip(s: &str) {
         let d = UINT256::from_str(s).expect("should be a valid u256");
         assert_eq!(s, d.to_string());
@@ -475,4 +682,43 @@ mod tests {
         check_round_trip("12300");
         check_round_trip("1234560");
     }
+
+    #[test]
+    fn u256_exprs() {
+        let exts = Extensions::all_available();
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).expect("evaluator is built");
+
+        let expr = u256_expr(U256::from(1_000_000_000_000_000_000u64));
+        assert_eq!(expr.to_string(), r#"u256("1000000000000000000")"#);
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::from(expr)),
+            eval.interpret_inline_policy(
+                &parse_expr(r#"u256("0xde0b6b3a7640000")"#).expect("parsing error")
+            )
+        );
+        assert_eq!(
+            u256_expr(U256::MAX).to_string(),
+            format!(r#"u256("{}")"#, U256::MAX)
+        );
+    }
+
+    #[test]
+    fn u256_conversions() {
+        let exts = Extensions::all_available();
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).expect("evaluator is built");
+
+        let value = U256::MAX;
+        let expr = RestrictedExpr::from(value);
+        let evaluated = eval
+            .interpret_inline_policy(&Expr::from(expr))
+            .expect("u256 evaluates");
+        assert_eq!(U256::try_from(&evaluated).expect("is a u256"), value);
+        assert!(U256::try_from(&Value::from(1)).is_err());
+        assert_eq!(parse_u256("0x10"), Some(U256::from(16u64)));
+        assert_eq!(parse_u256("ten"), None);
+    }
 }
@@ -18,16 +18,25 @@
 #![forbid(unsafe_code)]
 #![warn(missing_docs, missing_debug_implementations, rust_2018_idioms)]
 
+#[cfg(feature = "parser")]
 #[macro_use]
 extern crate lalrpop_util;
 
 pub mod ast;
 pub mod authorizer;
+pub mod collections;
+#[cfg(feature = "parser")]
 mod from_normalized_str;
+#[cfg(feature = "parser")]
 pub use from_normalized_str::*;
 pub mod entities;
+#[cfg(feature = "json")]
 pub mod est;
 pub mod evaluator;
 pub mod extensions;
+pub mod memory;
+#[cfg(feature = "parser")]
 pub mod parser;
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
 pub mod transitive_closure;
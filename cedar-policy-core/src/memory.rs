@@ -0,0 +1,257 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! This module contains estimates of the memory retained by policy sets and
+//! entities.
+//!
+//! Estimates add up the inline size of each value and the heap allocations it
+//! owns, taking the capacity of collections into account. Allocator overhead
+//! is ignored, and data shared through an `Arc` is counted once for every value
+//! holding it, except for templates, which are counted once however many
+//! policies link them. The numbers are meant for capacity planning, not for
+//! exact accounting.
+
+use crate::ast::*;
+use smol_str::SmolStr;
+use std::mem::size_of;
+
+/// Memory retained by a [`PolicySet`], in bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PolicySetMemoryUsage {
+    /// Templates, including those of static policies: their conditions, in
+    /// every form kept for evaluation, and their annotations
+    pub templates: usize,
+    /// Links and static policies: their ids and the values bound to their
+    /// slots
+    pub links: usize,
+}
+
+impl PolicySetMemoryUsage {
+    /// Memory retained in all categories
+    pub fn total(&self) -> usize {
+        self.templates + self.links
+    }
+}
+
+/// Memory retained by an [`crate::entities::Entities`], in bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EntitiesMemoryUsage {
+    /// Entities themselves: their UIDs, and the tables holding them and their
+    /// attributes
+    pub entities: usize,
+    /// Attribute and tag values, including those evaluated in advance
+    pub attribute_values: usize,
+    /// Ancestors of entities
+    pub ancestors: usize,
+}
+
+impl EntitiesMemoryUsage {
+    /// Memory retained in all categories
+    pub fn total(&self) -> usize {
+        self.entities + self.attribute_values + self.ancestors
+    }
+}
+
+/// Values owning heap allocations
+pub(crate) trait HeapSize {
+    /// The number of bytes this value owns on the heap, not counting its inline
+    /// size
+    fn heap_size(&self) -> usize;
+}
+
+/// Bytes a `SmolStr` holding `s` owns on the heap. Short strings are inlined.
+pub(crate) fn str_heap_size(s: &str) -> usize {
+    const INLINE_CAP: usize = 23;
+    if s.len() > INLINE_CAP {
+        s.len()
+    } else {
+        0
+    }
+}
+
+/// Bytes the table of a hash map or set with `capacity` entries of type `T`
+/// owns on the heap, including one control byte per entry
+pub(crate) fn table_size<T>(capacity: usize) -> usize {
+    capacity * (size_of::<T>() + 1)
+}
+
+/// Bytes the buffer of a vector with `capacity` elements of type `T` owns on
+/// the heap
+pub(crate) fn vec_size<T>(capacity: usize) -> usize {
+    capacity * size_of::<T>()
+}
+
+impl HeapSize for SmolStr {
+    fn heap_size(&self) -> usize {
+        str_heap_size(self)
+    }
+}
+
+impl HeapSize for Id {
+    fn heap_size(&self) -> usize {
+        str_heap_size(self.as_ref())
+    }
+}
+
+impl HeapSize for Name {
+    fn heap_size(&self) -> usize {
+        self.id.heap_size()
+            + size_of::<Vec<Id>>()
+            + vec_size::<Id>(self.path.capacity())
+            + self.path.iter().map(HeapSize::heap_size).sum::<usize>()
+    }
+}
+
+impl HeapSize for EntityUID {
+    fn heap_size(&self) -> usize {
+        let ty = match self.entity_type() {
+            EntityType::Concrete(name) => name.heap_size(),
+            EntityType::Unspecified => 0,
+        };
+        ty + str_heap_size(self.eid().as_ref())
+    }
+}
+
+impl HeapSize for Literal {
+    fn heap_size(&self) -> usize {
+        match self {
+            Literal::Bool(_) | Literal::Long(_) => 0,
+            Literal::String(s) => s.heap_size(),
+            Literal::EntityUID(uid) => size_of::<EntityUID>() + uid.heap_size(),
+        }
+    }
+}
+
+impl HeapSize for Pattern {
+    fn heap_size(&self) -> usize {
+        size_of::<Vec<PatternElem>>() + vec_size::<PatternElem>(self.get_elems().len())
+    }
+}
+
+impl HeapSize for Expr {
+    fn heap_size(&self) -> usize {
+        // every node but the root is allocated on its own, or in the vector of
+        // a set or record
+        let nodes = self.subexpressions().count().saturating_sub(1);
+        nodes * size_of::<Expr>()
+            + self
+                .subexpressions()
+                .map(|e| match e.expr_kind() {
+                    ExprKind::Lit(lit) => lit.heap_size(),
+                    ExprKind::Unknown { name, .. } => name.heap_size(),
+                    ExprKind::ExtensionFunctionApp { fn_name, .. } => fn_name.heap_size(),
+                    ExprKind::GetAttr { attr, .. } | ExprKind::HasAttr { attr, .. } => {
+                        attr.heap_size()
+                    }
+                    ExprKind::Like { pattern, .. } => pattern.heap_size(),
+                    ExprKind::Record { pairs } => pairs
+                        .iter()
+                        .map(|(k, _)| size_of::<SmolStr>() + k.heap_size())
+                        .sum(),
+                    _ => 0,
+                })
+                .sum::<usize>()
+    }
+}
+
+impl HeapSize for RestrictedExpr {
+    fn heap_size(&self) -> usize {
+        self.as_ref().heap_size()
+    }
+}
+
+impl HeapSize for Value {
+    fn heap_size(&self) -> usize {
+        match self {
+            Value::Lit(lit) => lit.heap_size(),
+            Value::Set(set) => {
+                let fast = set.fast.as_ref().map_or(0, |fast| {
+                    table_size::<Literal>(fast.capacity())
+                        + fast.iter().map(HeapSize::heap_size).sum::<usize>()
+                });
+                set.authoritative
+                    .iter()
+                    .map(|v| size_of::<Value>() + v.heap_size())
+                    .sum::<usize>()
+                    + fast
+            }
+            Value::Record(fields) => fields
+                .iter()
+                .map(|(k, v)| size_of::<(SmolStr, Value)>() + k.heap_size() + v.heap_size())
+                .sum(),
+            // the internals of extension values are opaque
+            Value::ExtensionValue(_) => size_of::<ExtensionValueWithArgs>(),
+        }
+    }
+}
+
+impl HeapSize for PartialValue {
+    fn heap_size(&self) -> usize {
+        match self {
+            PartialValue::Value(v) => v.heap_size(),
+            PartialValue::Residual(e) => e.heap_size(),
+        }
+    }
+}
+
+impl HeapSize for Template {
+    fn heap_size(&self) -> usize {
+        let annotations = self
+            .annotations()
+            .map(|(k, v)| size_of::<(Id, SmolStr)>() + k.heap_size() + v.heap_size())
+            .sum::<usize>();
+        let slots = vec_size::<SlotId>(self.slots().count());
+        #[cfg(feature = "bytecode")]
+        let program = size_of::<crate::evaluator::Program>() + self.program().heap_size();
+        #[cfg(not(feature = "bytecode"))]
+        let program = 0;
+        str_heap_size(self.id().as_ref())
+            + annotations
+            + slots
+            + self.non_head_constraints().heap_size()
+            + program
+    }
+}
+
+impl HeapSize for Policy {
+    fn heap_size(&self) -> usize {
+        str_heap_size(self.id().as_ref())
+            + table_size::<(SlotId, EntityUID)>(self.env().capacity())
+            + self.env().values().map(HeapSize::heap_size).sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts_long_strings() {
+        assert_eq!(SmolStr::new("0xabc").heap_size(), 0);
+        let address = SmolStr::new("0x52908400098527886E0F7030069857D2E4169EE7");
+        assert_eq!(address.heap_size(), address.len());
+    }
+
+    #[test]
+    fn counts_nested_values() {
+        let short = Value::from("0xabc");
+        assert_eq!(short.heap_size(), 0);
+        let set = Value::set([Value::from(1), Value::from(2), Value::from(3)]);
+        assert!(set.heap_size() >= 3 * size_of::<Value>());
+        let record = Value::from(vec![(SmolStr::new("owners"), set.clone())]);
+        assert!(record.heap_size() > set.heap_size());
+    }
+}
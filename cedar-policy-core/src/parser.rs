@@ -26,94 +26,130 @@ pub mod err;
 mod fmt;
 /// Metadata wrapper for CST Nodes
 mod node;
-pub use node::{ASTNode, SourceInfo};
+pub use crate::ast::SourceInfo;
+pub use node::ASTNode;
+/// Expansion of the names defined in a policy set
+pub(crate) mod definitions;
+/// Utility functions to find the type of hex literals
+pub(crate) mod hex;
+/// Loading policy sets split across files with `import`
+mod imports;
+pub use imports::{
+    parse_policyset_files, parse_policyset_files_with_origins, ImportError, PolicyFiles,
+};
+/// Utility functions to resolve operators overloaded by extension types
+pub(crate) mod overload;
 /// Step one: Convert text to CST
 pub mod text_to_cst;
 /// Utility functions to unescape string literals
 pub(crate) mod unescape;
 
 use smol_str::SmolStr;
+use std::borrow::Cow;
 use std::collections::HashMap;
 
 use crate::ast;
 use crate::ast::RestrictedExprError;
+#[cfg(feature = "json")]
 use crate::est;
 
 /// simple main function for parsing policies
 /// generates numbered ids
 pub fn parse_policyset(text: &str) -> Result<ast::PolicySet, err::ParseErrors> {
-    let mut errs = err::ParseErrors::new();
-    let cst = text_to_cst::parse_policies(text)?;
-    let Some(ast) = cst.to_policyset(&mut errs) else {
-        return Err(errs);
+    parse_policies_to_cst_and_pset(text).map(|(_, pset)| pset)
+}
+
+/// Parse `text` into both its CST and a policy set. After a syntax error, the
+/// parser continues with the next policy, and the policies which did parse
+/// are still converted to AST, so the returned errors include every syntax
+/// error in `text` as well as the errors found in the well-formed policies.
+fn parse_policies_to_cst_and_pset(
+    text: &str,
+) -> Result<(ASTNode<Option<cst::Policies>>, ast::PolicySet), err::ParseErrors> {
+    #[cfg(feature = "tracing")]
+    let span = tracing::debug_span!(
+        "parse_policies",
+        bytes = text.len(),
+        policies = tracing::field::Empty,
+        errors = tracing::field::Empty,
+    )
+    .entered();
+    let (cst, mut errs) = text_to_cst::parse_policies_with_recovery(text);
+    let result = match cst {
+        Some(cst) => match cst.to_policyset(&mut errs) {
+            Some(pset) if errs.is_empty() => Ok((cst, pset)),
+            _ => Err(errs),
+        },
+        None => Err(errs),
     };
-    if errs.is_empty() {
-        Ok(ast)
-    } else {
-        Err(errs)
+    #[cfg(feature = "tracing")]
+    match &result {
+        Ok((_, pset)) => {
+            span.record("policies", pset.all_templates().count());
+        }
+        Err(errs) => {
+            span.record("errors", errs.len());
+        }
     }
+    result
 }
 
 /// Like `parse_policyset()`, but also returns the (lossless) original text of
-/// each individual policy.
+/// each individual policy. For a policy which uses names defined in the policy
+/// set, the text is instead that of the policy with the names replaced by
+/// their expressions, so that it can be parsed on its own.
 /// INVARIANT: The `PolicyId` of every `Policy` and `Template` returned by the
 /// `policies()` and `templates()` methods on the returned `Policy` _must_
 /// appear as a key in the returned map.
 pub fn parse_policyset_and_also_return_policy_text(
     text: &str,
-) -> Result<(HashMap<ast::PolicyID, &str>, ast::PolicySet), err::ParseErrors> {
+) -> Result<(HashMap<ast::PolicyID, Cow<'_, str>>, ast::PolicySet), err::ParseErrors> {
+    let (cst, pset) = parse_policies_to_cst_and_pset(text)?;
     let mut errs = err::ParseErrors::new();
-    let cst = text_to_cst::parse_policies(text)?;
-    let Some(pset) = cst.to_policyset(&mut errs) else {
-        return Err(errs);
-    };
-    if errs.is_empty() {
-        // PANIC SAFETY Shouldn't be `none` since `parse_policies()` and `to_policyset()` didn't return `Err`
-        #[allow(clippy::expect_used)]
-        // PANIC SAFETY Indexing is safe because of how `SourceInfo` is constructed
-        #[allow(clippy::indexing_slicing)]
-        // The `PolicyID` keys for `texts` are generated by
-        // `cst.with_generated_policyids()`. This is the same method used to
-        // generate the ids for policies and templates in `cst.to_policyset()`,
-        // so every static policy and template in the policy set will have its
-        // `PolicyId` present as a key in this map.
-        let texts = cst
-            .with_generated_policyids()
-            .expect("shouldn't be None since parse_policies() and to_policyset() didn't return Err")
-            .map(|(id, policy)| (id, &text[policy.info.0.clone()]))
-            .collect::<HashMap<ast::PolicyID, &str>>();
-        Ok((texts, pset))
-    } else {
-        Err(errs)
-    }
+    // PANIC SAFETY Shouldn't be `none` since `parse_policies()` and `to_policyset()` didn't return `Err`
+    #[allow(clippy::expect_used)]
+    // PANIC SAFETY Indexing is safe because of how `SourceInfo` is constructed
+    #[allow(clippy::indexing_slicing)]
+    // The `PolicyID` keys for `texts` are generated by
+    // `cst.with_expanded_definitions()`, which uses the same method to
+    // generate ids as `cst.to_policyset()`, so every static policy and
+    // template in the policy set will have its `PolicyId` present as a key in
+    // this map.
+    let texts = cst
+        .with_expanded_definitions(&mut errs)
+        .expect("shouldn't be None since parse_policies() and to_policyset() didn't return Err")
+        .map(|(id, policy)| match policy {
+            Cow::Borrowed(policy) => (id, Cow::Borrowed(&text[policy.info.0.clone()])),
+            Cow::Owned(policy) => (
+                id,
+                Cow::Owned(policy.as_inner().expect("missing policy node").to_string()),
+            ),
+        })
+        .collect::<HashMap<ast::PolicyID, Cow<'_, str>>>();
+    Ok((texts, pset))
 }
 
 /// Like `parse_policyset()`, but also returns the (lossless) ESTs -- that is,
 /// the ESTs of the original policies without any of the lossy transforms
-/// involved in converting to AST.
+/// involved in converting to AST. Names defined in the policy set are
+/// replaced by their expressions in the ESTs.
+#[cfg(feature = "json")]
 pub fn parse_policyset_to_ests_and_pset(
     text: &str,
 ) -> Result<(HashMap<ast::PolicyID, est::Policy>, ast::PolicySet), err::ParseErrors> {
+    let (cst, pset) = parse_policies_to_cst_and_pset(text)?;
     let mut errs = err::ParseErrors::new();
-    let cst = text_to_cst::parse_policies(text)?;
-    let Some(pset) = cst.to_policyset(&mut errs) else {
-        return Err(errs);
-    };
-    if errs.is_empty() {
-        // PANIC SAFETY Shouldn't be `None` since `parse_policies()` and `to_policyset()` didn't return `Err`
-        #[allow(clippy::expect_used)]
-        let ests = cst
-            .with_generated_policyids()
-            .expect("missing policy set node")
-            .map(|(id, policy)| {
-                let p = policy.node.as_ref().expect("missing policy node").clone();
-                Ok((id, p.try_into()?))
-            })
-            .collect::<Result<HashMap<ast::PolicyID, est::Policy>, err::ParseErrors>>()?;
-        Ok((ests, pset))
-    } else {
-        Err(errs)
-    }
+    // PANIC SAFETY Shouldn't be `None` since `parse_policies()` and `to_policyset()` didn't return `Err`
+    #[allow(clippy::expect_used)]
+    let ests = cst
+        .with_expanded_definitions(&mut errs)
+        .expect("missing policy set node")
+        .map(|(id, policy)| {
+            let p = policy.node.as_ref().expect("missing policy node").clone();
+            Ok((id, p.try_into()?))
+        })
+        .collect::<Result<HashMap<ast::PolicyID, est::Policy>, err::ParseErrors>>()?;
+    Ok((ests, pset))
 }
 
 /// Simple main function for parsing a policy template.
@@ -128,6 +164,8 @@ pub fn parse_policy_template(
         Some(id) => ast::PolicyID::from_string(id),
         None => ast::PolicyID::from_string("policy0"),
     };
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("parse_template", policy_id = %id).entered();
     let cst = text_to_cst::parse_policy(text)?;
     let Some(ast) = cst.to_policy_template(id, &mut errs) else {
         return Err(errs);
@@ -142,6 +180,7 @@ pub fn parse_policy_template(
 /// Like `parse_policy_template()`, but also returns the (lossless) EST -- that
 /// is, the EST of the original template without any of the lossy transforms
 /// involved in converting to AST.
+#[cfg(feature = "json")]
 pub fn parse_policy_template_to_est_and_ast(
     id: Option<String>,
     text: &str,
@@ -172,6 +211,8 @@ pub fn parse_policy(id: Option<String>, text: &str) -> Result<ast::StaticPolicy,
         Some(id) => ast::PolicyID::from_string(id),
         None => ast::PolicyID::from_string("policy0"),
     };
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("parse_policy", policy_id = %id).entered();
     let cst = text_to_cst::parse_policy(text)?;
     let Some(ast) = cst.to_policy(id, &mut errs) else {
         return Err(errs);
@@ -186,6 +227,7 @@ pub fn parse_policy(id: Option<String>, text: &str) -> Result<ast::StaticPolicy,
 /// Like `parse_policy()`, but also returns the (lossless) EST -- that is, the
 /// EST of the original policy without any of the lossy transforms involved in
 /// converting to AST.
+#[cfg(feature = "json")]
 pub fn parse_policy_to_est_and_ast(
     id: Option<String>,
     text: &str,
@@ -208,6 +250,7 @@ pub fn parse_policy_to_est_and_ast(
 }
 
 /// Parse a policy or template (either one works) to its EST representation
+#[cfg(feature = "json")]
 pub fn parse_policy_or_template_to_est(text: &str) -> Result<est::Policy, err::ParseErrors> {
     let cst = text_to_cst::parse_policy(text)?;
     // PANIC SAFETY Shouldn't be `none` since `parse_policy()` didn't return `Err`
@@ -629,21 +672,95 @@ mod parse_tests {
         assert_eq!(pset.static_policies().count(), 2);
         assert_eq!(texts.len(), 2);
         assert_eq!(
-            texts.get(&PolicyID::from_string("policy0")),
+            texts
+                .get(&PolicyID::from_string("policy0"))
+                .map(AsRef::as_ref),
             Some(
-                &r#"permit(principal, action, resource)
+                r#"permit(principal, action, resource)
             when { principal == resource.owner };"#
             )
         );
         assert_eq!(
-            texts.get(&PolicyID::from_string("policy1")),
+            texts
+                .get(&PolicyID::from_string("policy1"))
+                .map(AsRef::as_ref),
             Some(
-                &r#"forbid(principal, action == Action::"modify", resource) // a comment
+                r#"forbid(principal, action == Action::"modify", resource) // a comment
             when { resource . highSecurity };"#
             )
         );
     }
 
+    #[test]
+    fn test_parse_policyset_recovery() {
+        let src = r#"
+            permit(principal, action, resource) when { principal.level > };
+            permit(principal, action, resuorce);
+            forbid(principal, action resource);
+            permit(principal, action, resource) when { context.x == 1 +* 2 };
+            permit(principal, action, resource);
+        "#;
+        let errs = parse_policyset(src).expect_err("should not parse");
+        // one error for each bad policy, with the syntax errors first
+        assert_eq!(errs.len(), 4, "{errs:?}");
+        let spans = errs
+            .iter()
+            .map(|err| err.primary_source_span().map(|span| &src[span.offset()..]))
+            .collect::<Vec<_>>();
+        assert!(spans[0].is_some_and(|s| s.starts_with("};")), "{spans:?}");
+        assert!(spans[1].is_some_and(|s| s.starts_with("resource);")));
+        assert!(spans[2].is_some_and(|s| s.starts_with("* 2")));
+        assert!(matches!(errs[3], err::ParseError::ToAST(_)));
+
+        let errs = parse_policyset_and_also_return_policy_text(src).expect_err("should not parse");
+        assert_eq!(errs.len(), 4);
+        let errs = parse_policyset_to_ests_and_pset(src).expect_err("should not parse");
+        assert_eq!(errs.len(), 4);
+    }
+
+    #[test]
+    fn test_source_info() {
+        let src = r#"
+            @id("vault")
+            permit(principal in ?principal, action == Action::"withdraw", resource)
+            when { context.amount < 100 };
+        "#;
+        let template = parse_policy_template(None, src).expect("should parse");
+        let text = |info: Option<&SourceInfo>| info.map(|info| &src[info.0.clone()]);
+        let source_info = template.source_info();
+        assert_eq!(
+            text(source_info.policy()),
+            Some(src.trim()),
+            "policy span should include the annotations"
+        );
+        assert_eq!(
+            text(source_info.principal()),
+            Some("principal in ?principal")
+        );
+        assert_eq!(
+            text(source_info.action()),
+            Some(r#"action == Action::"withdraw""#)
+        );
+        assert_eq!(text(source_info.resource()), Some("resource"));
+        assert_eq!(
+            text(template.non_head_constraints().source_info().as_ref()),
+            Some("context.amount < 100")
+        );
+
+        let policy =
+            parse_policy(None, r#"permit(principal, action, resource);"#).expect("should parse");
+        assert_eq!(
+            policy.source_info().principal(),
+            Some(&SourceInfo::new(7, 9))
+        );
+        let est =
+            parse_policy_to_est_and_ast(None, src.replace("?principal", "User::\"a\"").as_str())
+                .expect("should parse")
+                .0;
+        let from_json = est.try_into_ast_template(None).expect("should convert");
+        assert_eq!(from_json.source_info(), &ast::PolicySourceInfo::default());
+    }
+
     #[test]
     fn test_parse_string() {
         // test idempotence
@@ -23,7 +23,36 @@ type Node<N> = ASTNode<Option<N>>;
 
 /// The set of policy statements that forms an authorization policy
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Policies(pub Vec<Node<Policy>>);
+pub struct Policies {
+    /// Imports of other files, which must come before the definitions and
+    /// policies
+    pub imports: Vec<Node<Import>>,
+    /// Definitions, which must come before the policies
+    pub definitions: Vec<Node<Definition>>,
+    /// Policies
+    pub policies: Vec<Node<Policy>>,
+}
+
+/// Import of the policies and definitions of another file:
+/// `import "path.cedar";`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    /// Annotations, which are not allowed on imports
+    pub annotations: Vec<Node<Annotation>>,
+    /// the path of the imported file
+    pub path: Node<Str>,
+}
+
+/// Definition of a name for an expression: `def NAME = expr;`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    /// Annotations, which are not allowed on definitions
+    pub annotations: Vec<Node<Annotation>>,
+    /// the name defined
+    pub name: Node<Ident>,
+    /// the expression the name stands for
+    pub expr: Node<Expr>,
+}
 
 /// Annotations: application-defined data, as a key-value pair
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -344,6 +373,8 @@ pub enum Literal {
     False,
     /// some integer
     Num(u64),
+    /// some `0x`-prefixed hex literal
+    Hex(SmolStr),
     /// some String
     Str(Node<Str>),
 }
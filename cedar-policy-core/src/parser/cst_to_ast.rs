@@ -36,12 +36,18 @@
 // cloning.
 
 use super::err::{ParseError, ParseErrors, Ref, RefCreationError, ToASTError};
-use super::node::{ASTNode, SourceInfo};
+use super::hex::{
+    check_hex_comparison, check_hex_digits, hex_literal_constructor, HEX_OPERAND_CONSTRUCTOR,
+};
+use super::node::ASTNode;
+use super::overload::overloaded_operator_func;
 use super::unescape::{to_pattern, to_unescaped_string};
+use super::SourceInfo;
 use super::{cst, err};
 use crate::ast::{
-    self, ActionConstraint, CallStyle, EntityReference, EntityType, EntityUID, PatternElem,
-    PolicySetError, PrincipalConstraint, PrincipalOrResourceConstraint, ResourceConstraint,
+    self, ActionConstraint, CallStyle, EntityReference, EntityType, EntityUID, OverloadedOperator,
+    PatternElem, PolicySetError, PrincipalConstraint, PrincipalOrResourceConstraint,
+    ResourceConstraint,
 };
 use itertools::Either;
 use smol_str::SmolStr;
@@ -84,7 +90,7 @@ impl ASTNode<Option<cst::Policies>> {
 
         Some(
             policies
-                .0
+                .policies
                 .iter()
                 .enumerate()
                 .map(|(count, node)| (ast::PolicyID::from_string(format!("policy{count}")), node)),
@@ -94,11 +100,19 @@ impl ASTNode<Option<cst::Policies>> {
     /// convert `cst::Policies` to `ast::PolicySet`
     pub fn to_policyset(&self, errs: &mut ParseErrors) -> Option<ast::PolicySet> {
         let mut pset = ast::PolicySet::new();
-        let mut complete_set = true;
+        let num_errs = errs.len();
+        // imports can only be resolved when loading policy files, with
+        // `parser::parse_policyset_files()`
+        for path in self.import_paths(errs)? {
+            errs.push(ParseError::ToAST(ToASTError::UnresolvedImport(path)));
+        }
         // Caution: `parser::parse_policyset_and_also_return_policy_text()`
         // depends on this function returning a policy set with `PolicyID`s as
-        // generated by `with_generated_policyids()` to maintain an invariant.
-        for (policy_id, policy) in self.with_generated_policyids()? {
+        // generated by `with_expanded_definitions()` to maintain an invariant.
+        let policies = self.with_expanded_definitions(errs)?;
+        // errors in the imports or definitions
+        let mut complete_set = errs.len() == num_errs;
+        for (policy_id, policy) in policies {
             // policy may have convert error
             match policy.to_policy_or_template(policy_id, errs) {
                 Some(Either::Right(template)) => {
@@ -241,16 +255,26 @@ impl ASTNode<Option<cst::Policy>> {
         let action = maybe_action?;
         let resource = maybe_resource?;
 
-        Some(construct_template_policy(
-            id,
-            annotations,
-            effect,
-            principal,
-            action,
-            resource,
-            conds,
-            src.clone(),
-        ))
+        let scope_info = |i: usize| policy.variables.get(i).map(|var| var.info.clone());
+        let source_info = ast::PolicySourceInfo::new(
+            Some(src.clone()),
+            scope_info(0),
+            scope_info(1),
+            scope_info(2),
+        );
+        Some(
+            construct_template_policy(
+                id,
+                annotations,
+                effect,
+                principal,
+                action,
+                resource,
+                conds,
+                src.clone(),
+            )
+            .with_source_info(source_info),
+        )
     }
 }
 
@@ -412,11 +436,15 @@ impl ASTNode<Option<cst::Ident>> {
 impl ast::Id {
     fn to_meth(
         &self,
-        e: ast::Expr,
-        mut args: Vec<ast::Expr>,
+        e: ExprOrSpecial<'_>,
+        args: Vec<ExprOrSpecial<'_>>,
         errs: &mut ParseErrors,
         l: SourceInfo,
     ) -> Option<ast::Expr> {
+        // the receiver is the first argument of a method-style function
+        let name = ast::Name::unqualified_name(self.clone());
+        let e = convert_call_args(&name, 0, vec![e], errs)?.pop()?;
+        let mut args = convert_call_args(&name, 1, args, errs)?;
         let mut adj_args = args.iter_mut().peekable();
         match (self.as_ref(), adj_args.next(), adj_args.peek()) {
             ("contains", Some(a), None) => {
@@ -433,6 +461,14 @@ impl ast::Id {
                 let arg = mem::replace(a, ast::Expr::val(false));
                 Some(construct_method_contains_any(e, arg, l))
             }
+            ("hasTag", Some(a), None) => {
+                let arg = mem::replace(a, ast::Expr::val(false));
+                Some(construct_method_has_tag(e, arg, l))
+            }
+            ("getTag", Some(a), None) => {
+                let arg = mem::replace(a, ast::Expr::val(false));
+                Some(construct_method_get_tag(e, arg, l))
+            }
             (name, _, _) => {
                 if EXTENSION_STYLES.methods.contains(&name) {
                     args.insert(0, e);
@@ -702,6 +738,8 @@ pub(crate) enum ExprOrSpecial<'a> {
     /// String literal, not yet unescaped
     /// Must be processed with to_unescaped_string or to_pattern before inclusion in the AST
     StrLit(&'a SmolStr, SourceInfo),
+    /// Hex literal, not yet converted, as its type depends on where it's used
+    HexLit(&'a SmolStr, SourceInfo),
 }
 
 impl ExprOrSpecial<'_> {
@@ -724,7 +762,41 @@ impl ExprOrSpecial<'_> {
                     None
                 }
             },
+            Self::HexLit(h, l) => Some(construct_expr_hex(h, hex_literal_constructor(None, 0), l)),
+        }
+    }
+
+    /// Convert an operand of an arithmetic or comparison operator, which may
+    /// be a hex literal
+    fn into_operand(self, errs: &mut ParseErrors) -> Option<ast::Expr> {
+        match self {
+            Self::HexLit(h, l) => Some(construct_expr_hex(h, HEX_OPERAND_CONSTRUCTOR, l)),
+            e => e.into_expr(errs),
+        }
+    }
+
+    /// Convert the operands `self` and `other` of `==` or `!=`, rejecting a
+    /// hex literal compared with a `u256`
+    fn into_compared(
+        self,
+        other: Self,
+        errs: &mut ParseErrors,
+    ) -> (Option<ast::Expr>, Option<ast::Expr>) {
+        let hex = |e: &Self| match e {
+            Self::HexLit(h, _) => Some(SmolStr::clone(h)),
+            _ => None,
+        };
+        let (hex_first, hex_second) = (hex(&self), hex(&other));
+        let (first, second) = (self.into_expr(errs), other.into_expr(errs));
+        for (h, other) in [(hex_first, &second), (hex_second, &first)] {
+            if let (Some(h), Some(other)) = (h, other) {
+                if let Err(err) = check_hex_comparison(&h, other) {
+                    errs.push(err.into());
+                    return (None, None);
+                }
+            }
         }
+        (first, second)
     }
 
     /// Variables, names (with no prefixes), and string literals can all be used as record attributes
@@ -747,6 +819,10 @@ impl ExprOrSpecial<'_> {
                 errs.push(ToASTError::InvalidAttribute(e.to_string().into()).into());
                 None
             }
+            Self::HexLit(h, _) => {
+                errs.push(ToASTError::InvalidAttribute(h.clone()).into());
+                None
+            }
         }
     }
 
@@ -775,6 +851,10 @@ impl ExprOrSpecial<'_> {
                 errs.push(ToASTError::InvalidPattern(e.to_string()).into());
                 None
             }
+            Self::HexLit(h, _) => {
+                errs.push(ToASTError::InvalidPattern(h.to_string()).into());
+                None
+            }
         }
     }
     /// to string literal
@@ -803,6 +883,10 @@ impl ExprOrSpecial<'_> {
                 errs.push(ToASTError::InvalidString(e.to_string()).into());
                 None
             }
+            Self::HexLit(h, _) => {
+                errs.push(ToASTError::InvalidString(h.to_string()).into());
+                None
+            }
         }
     }
 }
@@ -1045,7 +1129,7 @@ impl ASTNode<Option<cst::Relation>> {
                 let maybe_first = initial.to_expr_or_special(errs);
                 let mut more = extended
                     .iter()
-                    .filter_map(|(op, i)| i.to_expr(errs).map(|e| (op, e)));
+                    .filter_map(|(op, i)| i.to_expr_or_special(errs).map(|e| (op, e)));
                 // getting the second here avoids the possibility of a singleton construction
                 let maybe_second = more.next();
                 // collect() preforms all the conversions, generating any errors
@@ -1059,9 +1143,17 @@ impl ASTNode<Option<cst::Relation>> {
                     // error reported and result filtered out
                     (_, None, 1) => None,
                     (f, None, 0) => f,
-                    (Some(f), Some((op, s)), _) => f
-                        .into_expr(errs)
-                        .map(|e| ExprOrSpecial::Expr(construct_expr_rel(e, *op, s, src.clone()))),
+                    (Some(f), Some((op, s)), _) => {
+                        let (f, s) = match op {
+                            cst::RelOp::Less
+                            | cst::RelOp::LessEq
+                            | cst::RelOp::Greater
+                            | cst::RelOp::GreaterEq => (f.into_operand(errs), s.into_operand(errs)),
+                            cst::RelOp::Eq | cst::RelOp::NotEq => f.into_compared(s, errs),
+                            cst::RelOp::In => (f.into_expr(errs), s.into_expr(errs)),
+                        };
+                        construct_expr_rel(f?, *op, s?, errs, src.clone()).map(ExprOrSpecial::Expr)
+                    }
                     _ => None,
                 }
             }
@@ -1117,14 +1209,15 @@ impl ASTNode<Option<cst::Add>> {
         let more: Vec<(cst::AddOp, _)> = add
             .extended
             .iter()
-            .filter_map(|&(op, ref i)| i.to_expr(errs).map(|e| (op, e)))
+            .filter_map(|&(op, ref i)| {
+                i.to_expr_or_special(errs)
+                    .and_then(|e| e.into_operand(errs))
+                    .map(|e| (op, e))
+            })
             .collect();
         if !more.is_empty() {
-            Some(ExprOrSpecial::Expr(construct_expr_add(
-                maybe_first?.into_expr(errs)?,
-                more,
-                src.clone(),
-            )))
+            construct_expr_add(maybe_first?.into_operand(errs)?, more, errs, src.clone())
+                .map(ExprOrSpecial::Expr)
         } else {
             maybe_first
         }
@@ -1144,9 +1237,6 @@ impl ASTNode<Option<cst::Mult>> {
         }
     }
 
-    fn to_expr(&self, errs: &mut ParseErrors) -> Option<ast::Expr> {
-        self.to_expr_or_special(errs)?.into_expr(errs)
-    }
     fn to_expr_or_special(&self, errs: &mut ParseErrors) -> Option<ExprOrSpecial<'_>> {
         let (src, maybe_mult) = self.as_inner_pair();
         // return right away if there's no data, parse provided error
@@ -1157,11 +1247,15 @@ impl ASTNode<Option<cst::Mult>> {
         let more: Vec<(cst::MultOp, _)> = mult
             .extended
             .iter()
-            .filter_map(|&(op, ref i)| i.to_expr(errs).map(|e| (op, e)))
+            .filter_map(|&(op, ref i)| {
+                i.to_expr_or_special(errs)
+                    .and_then(|e| e.into_operand(errs))
+                    .map(|e| (op, e))
+            })
             .collect();
 
         if !more.is_empty() {
-            let first = maybe_first?.into_expr(errs)?;
+            let first = maybe_first?.into_operand(errs)?;
             // enforce that division and remainder/modulo are not supported
             for (op, _) in &more {
                 match op {
@@ -1176,15 +1270,23 @@ impl ASTNode<Option<cst::Mult>> {
                     }
                 }
             }
-            // split all the operands into constantints and nonconstantints.
-            // also, remove the opcodes -- from here on we assume they're all
+            // remove the opcodes -- from here on we assume they're all
             // `Times`, having checked above that this is the case
-            let (constantints, nonconstantints): (Vec<ast::Expr>, Vec<ast::Expr>) =
-                std::iter::once(first)
-                    .chain(more.into_iter().map(|(_, e)| e))
-                    .partition(|e| {
-                        matches!(e.expr_kind(), ast::ExprKind::Lit(ast::Literal::Long(_)))
-                    });
+            let operands: Vec<ast::Expr> = std::iter::once(first)
+                .chain(more.into_iter().map(|(_, e)| e))
+                .collect();
+            let overloaded = operands.iter().any(|operand| {
+                overloaded_operator_func(OverloadedOperator::Mul, [operand])
+                    .is_ok_and(|func| func.is_some())
+            });
+            if overloaded {
+                return construct_expr_overloaded_mul(operands, errs, src.clone())
+                    .map(ExprOrSpecial::Expr);
+            }
+            // split all the operands into constantints and nonconstantints.
+            let (constantints, nonconstantints): (Vec<ast::Expr>, Vec<ast::Expr>) = operands
+                .into_iter()
+                .partition(|e| matches!(e.expr_kind(), ast::ExprKind::Lit(ast::Literal::Long(_))));
             let constantints = constantints
                 .into_iter()
                 .map(|e| match e.expr_kind() {
@@ -1240,9 +1342,6 @@ impl ASTNode<Option<cst::Unary>> {
         }
     }
 
-    fn to_expr(&self, errs: &mut ParseErrors) -> Option<ast::Expr> {
-        self.to_expr_or_special(errs)?.into_expr(errs)
-    }
     fn to_expr_or_special(&self, errs: &mut ParseErrors) -> Option<ExprOrSpecial<'_>> {
         let (src, maybe_unary) = self.as_inner_pair();
         // return right away if there's no data, parse provided error
@@ -1313,9 +1412,10 @@ impl ASTNode<Option<cst::Unary>> {
 }
 
 /// Temporary converted data, mirroring `cst::MemAccess`
-enum AstAccessor {
+enum AstAccessor<'a> {
     Field(ast::Id),
-    Call(Vec<ast::Expr>),
+    /// Call arguments, which are expressions or hex literals
+    Call(Vec<ExprOrSpecial<'a>>),
     Index(SmolStr),
 }
 
@@ -1429,7 +1529,12 @@ impl ASTNode<Option<cst::Member>> {
                     // move the id out of the slice as well, to avoid cloning the internal string
                     let id = mem::replace(i, ast::Id::new_unchecked(""));
                     head = id
-                        .to_meth(construct_expr_var(var, vl.clone()), args, errs, src.clone())
+                        .to_meth(
+                            Expr(construct_expr_var(var, vl.clone())),
+                            args,
+                            errs,
+                            src.clone(),
+                        )
                         .map(Expr);
                     tail = rest;
                 }
@@ -1440,7 +1545,7 @@ impl ASTNode<Option<cst::Member>> {
                     let expr = mem::replace(e, ast::Expr::val(false));
                     // move the id out of the slice as well, to avoid cloning the internal string
                     let id = mem::replace(i, ast::Id::new_unchecked(""));
-                    head = id.to_meth(expr, args, errs, src.clone()).map(Expr);
+                    head = id.to_meth(Expr(expr), args, errs, src.clone()).map(Expr);
                     tail = rest;
                 }
                 // method call on string literal (same as Expr case)
@@ -1458,8 +1563,27 @@ impl ASTNode<Option<cst::Member>> {
                             None
                         }
                     };
-                    head =
-                        maybe_expr.and_then(|e| id.to_meth(e, args, errs, src.clone()).map(Expr));
+                    head = maybe_expr
+                        .and_then(|e| id.to_meth(Expr(e), args, errs, src.clone()).map(Expr));
+                    tail = rest;
+                }
+                // method call on hex literal, which takes the type the method
+                // expects of its receiver
+                (Some(HexLit(h, hl)), [Some(Field(i)), Some(Call(a)), rest @ ..]) => {
+                    let args = std::mem::take(a);
+                    let id = mem::replace(i, ast::Id::new_unchecked(""));
+                    head = id
+                        .to_meth(HexLit(h, hl.clone()), args, errs, src.clone())
+                        .map(Expr);
+                    tail = rest;
+                }
+                // access of hex literal (same as Expr case)
+                (Some(HexLit(h, hl)), rest @ [Some(Field(_)) | Some(Index(_)), ..]) => {
+                    head = Some(Expr(construct_expr_hex(
+                        h,
+                        hex_literal_constructor(None, 0),
+                        hl.clone(),
+                    )));
                     tail = rest;
                 }
                 // access of failure - ignore
@@ -1560,7 +1684,7 @@ impl ASTNode<Option<cst::Member>> {
 }
 
 impl ASTNode<Option<cst::MemAccess>> {
-    fn to_access(&self, errs: &mut ParseErrors) -> Option<AstAccessor> {
+    fn to_access(&self, errs: &mut ParseErrors) -> Option<AstAccessor<'_>> {
         let maybe_acc = self.as_inner();
         // return right away if there's no data, parse provided error
         let acc = maybe_acc?;
@@ -1571,7 +1695,15 @@ impl ASTNode<Option<cst::MemAccess>> {
                 ident.map(AstAccessor::Field)
             }
             cst::MemAccess::Call(args) => {
-                let conv_args: Vec<_> = args.iter().filter_map(|e| e.to_expr(errs)).collect();
+                let conv_args: Vec<_> = args
+                    .iter()
+                    .filter_map(|e| match e.to_expr_or_special(errs)? {
+                        // the type of a hex literal depends on the function
+                        // it's an argument of, so it's converted with the call
+                        h @ ExprOrSpecial::HexLit(..) => Some(h),
+                        e => e.into_expr(errs).map(ExprOrSpecial::Expr),
+                    })
+                    .collect();
                 if conv_args.len() == args.len() {
                     Some(AstAccessor::Call(conv_args))
                 } else {
@@ -1767,7 +1899,7 @@ impl ast::Name {
 
     fn into_func(
         self,
-        args: Vec<ast::Expr>,
+        args: Vec<ExprOrSpecial<'_>>,
         errs: &mut ParseErrors,
         l: SourceInfo,
     ) -> Option<ast::Expr> {
@@ -1775,7 +1907,7 @@ impl ast::Name {
         if self.path.is_empty() {
             let id = self.id.as_ref();
             match id {
-                "contains" | "containsAll" | "containsAny" => {
+                "contains" | "containsAll" | "containsAny" | "hasTag" | "getTag" => {
                     errs.push(ToASTError::FunctionCallOnMethod(self.id).into());
                     return None;
                 }
@@ -1783,6 +1915,7 @@ impl ast::Name {
             }
         }
         if EXTENSION_STYLES.functions.contains(&self) {
+            let args = convert_call_args(&self, 0, args, errs)?;
             Some(construct_ext_func(self, args, l))
         } else {
             errs.push(ToASTError::NotAFunction(self).into());
@@ -1852,6 +1985,13 @@ impl ASTNode<Option<cst::Literal>> {
                     None
                 }
             },
+            cst::Literal::Hex(h) => match check_hex_digits(h) {
+                Ok(()) => Some(ExprOrSpecial::HexLit(h, src.clone())),
+                Err(err) => {
+                    errs.push(err.into());
+                    None
+                }
+            },
             cst::Literal::Str(s) => {
                 let maybe_str = s.as_valid_string(errs);
                 maybe_str.map(|s| ExprOrSpecial::StrLit(s, src.clone()))
@@ -1984,9 +2124,32 @@ fn construct_expr_and(
             .and(a, n)
     })
 }
-fn construct_expr_rel(f: ast::Expr, rel: cst::RelOp, s: ast::Expr, l: SourceInfo) -> ast::Expr {
+fn construct_expr_rel(
+    f: ast::Expr,
+    rel: cst::RelOp,
+    s: ast::Expr,
+    errs: &mut ParseErrors,
+    l: SourceInfo,
+) -> Option<ast::Expr> {
+    let overloaded = match rel {
+        cst::RelOp::Less => Some(OverloadedOperator::Less),
+        cst::RelOp::LessEq => Some(OverloadedOperator::LessEq),
+        cst::RelOp::GreaterEq => Some(OverloadedOperator::GreaterEq),
+        cst::RelOp::Greater => Some(OverloadedOperator::Greater),
+        cst::RelOp::NotEq | cst::RelOp::Eq | cst::RelOp::In => None,
+    };
+    if let Some(op) = overloaded {
+        match overloaded_operator_func(op, [&f, &s]) {
+            Ok(Some(func)) => return Some(construct_ext_meth_call(func, vec![f, s], l)),
+            Ok(None) => {}
+            Err(err) => {
+                errs.push(ToASTError::from(err).into());
+                return None;
+            }
+        }
+    }
     let builder = ast::ExprBuilder::new().with_source_info(l);
-    match rel {
+    Some(match rel {
         cst::RelOp::Less => builder.less(f, s),
         cst::RelOp::LessEq => builder.lesseq(f, s),
         cst::RelOp::GreaterEq => builder.greatereq(f, s),
@@ -1994,23 +2157,72 @@ fn construct_expr_rel(f: ast::Expr, rel: cst::RelOp, s: ast::Expr, l: SourceInfo
         cst::RelOp::NotEq => builder.noteq(f, s),
         cst::RelOp::Eq => builder.is_eq(f, s),
         cst::RelOp::In => builder.is_in(f, s),
-    }
+    })
 }
 /// used for a chain of addition and/or subtraction
 fn construct_expr_add(
     f: ast::Expr,
     chained: impl IntoIterator<Item = (cst::AddOp, ast::Expr)>,
+    errs: &mut ParseErrors,
     l: SourceInfo,
-) -> ast::Expr {
+) -> Option<ast::Expr> {
     let mut expr = f;
     for (op, next_expr) in chained {
+        let overloaded = match op {
+            cst::AddOp::Plus => OverloadedOperator::Add,
+            cst::AddOp::Minus => OverloadedOperator::Sub,
+        };
+        match overloaded_operator_func(overloaded, [&expr, &next_expr]) {
+            Ok(Some(func)) => {
+                expr = construct_ext_meth_call(func, vec![expr, next_expr], l.clone());
+                continue;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                errs.push(ToASTError::from(err).into());
+                return None;
+            }
+        }
         let builder = ast::ExprBuilder::new().with_source_info(l.clone());
         expr = match op {
             cst::AddOp::Plus => builder.add(expr, next_expr),
             cst::AddOp::Minus => builder.sub(expr, next_expr),
         };
     }
-    expr
+    Some(expr)
+}
+/// used for a chain of multiplication in which some operand is of an
+/// extension type overloading `*`. Steps of the chain without such an operand
+/// must multiply by a constant, as usual, and steps with one must not mix it
+/// with an operand known to be of another type.
+fn construct_expr_overloaded_mul(
+    operands: Vec<ast::Expr>,
+    errs: &mut ParseErrors,
+    l: SourceInfo,
+) -> Option<ast::Expr> {
+    let mut operands = operands.into_iter();
+    let mut expr = operands.next()?;
+    for next_expr in operands {
+        let overloaded =
+            match overloaded_operator_func(OverloadedOperator::Mul, [&expr, &next_expr]) {
+                Ok(overloaded) => overloaded,
+                Err(err) => {
+                    errs.push(ToASTError::from(err).into());
+                    return None;
+                }
+            };
+        if let Some(func) = overloaded {
+            expr = construct_ext_meth_call(func, vec![expr, next_expr], l.clone());
+        } else if let ast::ExprKind::Lit(ast::Literal::Long(c)) = next_expr.expr_kind() {
+            expr = construct_expr_mul(expr, [*c], l.clone());
+        } else if let ast::ExprKind::Lit(ast::Literal::Long(c)) = expr.expr_kind() {
+            expr = construct_expr_mul(next_expr, [*c], l.clone());
+        } else {
+            errs.push(ToASTError::NonConstantMultiplication.into());
+            return None;
+        }
+    }
+    Some(expr)
 }
 /// used for a chain of multiplication only (no division or mod)
 fn construct_expr_mul(
@@ -2035,6 +2247,34 @@ fn construct_expr_attr(e: ast::Expr, s: SmolStr, l: SourceInfo) -> ast::Expr {
 fn construct_expr_like(e: ast::Expr, s: Vec<PatternElem>, l: SourceInfo) -> ast::Expr {
     ast::ExprBuilder::new().with_source_info(l).like(e, s)
 }
+/// Convert the arguments of a call to the function `func`, starting at
+/// argument `first`, giving each hex literal the type `func` expects
+fn convert_call_args(
+    func: &ast::Name,
+    first: usize,
+    args: Vec<ExprOrSpecial<'_>>,
+    errs: &mut ParseErrors,
+) -> Option<Vec<ast::Expr>> {
+    let num_args = args.len();
+    let conv_args: Vec<_> = args
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, arg)| match arg {
+            ExprOrSpecial::HexLit(h, l) => Some(construct_expr_hex(
+                h,
+                hex_literal_constructor(Some(func), first + i),
+                l,
+            )),
+            arg => arg.into_expr(errs),
+        })
+        .collect();
+    (conv_args.len() == num_args).then_some(conv_args)
+}
+fn construct_expr_hex(h: &SmolStr, constructor: &str, l: SourceInfo) -> ast::Expr {
+    let constructor = ast::Name::unqualified_name(ast::Id::new_unchecked(constructor));
+    let arg = construct_expr_string(h.clone(), l.clone());
+    construct_ext_func(constructor, vec![arg], l)
+}
 fn construct_ext_func(name: ast::Name, args: Vec<ast::Expr>, l: SourceInfo) -> ast::Expr {
     // INVARIANT (MethodStyleArgs): CallStyle is not MethodStyle, so any args vector is fine
     ast::ExprBuilder::new()
@@ -2055,6 +2295,12 @@ fn construct_method_contains_any(e0: ast::Expr, e1: ast::Expr, l: SourceInfo) ->
         .with_source_info(l)
         .contains_any(e0, e1)
 }
+fn construct_method_has_tag(e0: ast::Expr, e1: ast::Expr, l: SourceInfo) -> ast::Expr {
+    ast::ExprBuilder::new().with_source_info(l).has_tag(e0, e1)
+}
+fn construct_method_get_tag(e0: ast::Expr, e1: ast::Expr, l: SourceInfo) -> ast::Expr {
+    ast::ExprBuilder::new().with_source_info(l).get_tag(e0, e1)
+}
 
 // INVARIANT (MethodStyleArgs), args must be non-empty
 fn construct_ext_meth(n: String, args: Vec<ast::Expr>, l: SourceInfo) -> ast::Expr {
@@ -2065,6 +2311,14 @@ fn construct_ext_meth(n: String, args: Vec<ast::Expr>, l: SourceInfo) -> ast::Ex
         .with_source_info(l)
         .call_extension_fn(name, args)
 }
+/// used for an operator overloaded by an extension type, which is a call of
+/// the method-style extension function `func`
+fn construct_ext_meth_call(func: ast::Name, args: Vec<ast::Expr>, l: SourceInfo) -> ast::Expr {
+    // INVARIANT (MethodStyleArgs), args are the two operands, so non-empty
+    ast::ExprBuilder::new()
+        .with_source_info(l)
+        .call_extension_fn(func, args)
+}
 fn construct_expr_set(s: Vec<ast::Expr>, l: SourceInfo) -> ast::Expr {
     ast::ExprBuilder::new().with_source_info(l).set(s)
 }
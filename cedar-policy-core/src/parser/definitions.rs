@@ -0,0 +1,479 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use smol_str::SmolStr;
+
+use super::cst;
+use super::err::{ParseError, ParseErrors, ToASTError};
+use super::node::ASTNode;
+use crate::ast;
+
+type Node<N> = ASTNode<Option<N>>;
+
+/// The expressions named by the definitions of a policy set. Names used in
+/// the expressions are already expanded.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Definitions(HashMap<SmolStr, Node<cst::Expr>>);
+
+impl Node<cst::Policies> {
+    /// Iterate over the `Policy` nodes in this `cst::Policies`, with
+    /// corresponding generated `PolicyID`s, and with every use of a name
+    /// defined by a definition replaced by its expression. Policies which use
+    /// no defined names are returned unchanged, as `Cow::Borrowed`.
+    ///
+    /// Errors in the definitions are added to `errs`; the definitions without
+    /// errors are still expanded.
+    pub fn with_expanded_definitions(
+        &self,
+        errs: &mut ParseErrors,
+    ) -> Option<impl Iterator<Item = (ast::PolicyID, Cow<'_, Node<cst::Policy>>)>> {
+        let definitions = self.definitions(&Definitions::default(), errs)?;
+        Some(
+            self.with_generated_policyids()?
+                .map(move |(id, policy)| (id, definitions.expand_policy(policy))),
+        )
+    }
+
+    /// Collect the definitions, each with the names defined before it expanded,
+    /// along with the `imported` definitions
+    pub(crate) fn definitions(
+        &self,
+        imported: &Definitions,
+        errs: &mut ParseErrors,
+    ) -> Option<Definitions> {
+        let policies = self.as_inner()?;
+        let first_policy = policies.policies.first().map(|p| p.info.range_start());
+        let mut definitions = imported.clone();
+        for definition in &policies.definitions {
+            let Some(def) = definition.as_inner() else {
+                continue;
+            };
+            let Some(cst::Ident::Ident(name)) = def.name.as_inner() else {
+                continue;
+            };
+            if !def.annotations.is_empty() {
+                errs.push(ParseError::ToAST(ToASTError::AnnotatedDefinition(
+                    name.clone(),
+                )));
+            }
+            if first_policy.is_some_and(|start| definition.info.range_start() > start) {
+                errs.push(ParseError::ToAST(ToASTError::DefinitionAfterPolicy(
+                    name.clone(),
+                )));
+            }
+            if definitions.0.contains_key(name) {
+                errs.push(ParseError::ToAST(ToASTError::DuplicateDefinition(
+                    name.clone(),
+                )));
+                continue;
+            }
+            let mut expr = def.expr.clone();
+            Expander::new(&definitions).expr(&mut expr);
+            definitions.0.insert(name.clone(), expr);
+        }
+        Some(definitions)
+    }
+}
+
+impl Definitions {
+    /// Add the definitions of `other`. A name defined in both is an error.
+    pub(crate) fn import(&mut self, other: &Definitions, errs: &mut ParseErrors) {
+        for (name, expr) in &other.0 {
+            if self.0.contains_key(name) {
+                errs.push(ParseError::ToAST(ToASTError::DuplicateDefinition(
+                    name.clone(),
+                )));
+            } else {
+                self.0.insert(name.clone(), expr.clone());
+            }
+        }
+    }
+
+    /// Remove the definitions of the names defined in `imported`
+    pub(crate) fn without(mut self, imported: &Definitions) -> Self {
+        self.0.retain(|name, _| !imported.0.contains_key(name));
+        self
+    }
+
+    /// Expand the defined names used in `policy`
+    pub(crate) fn expand_policy<'a>(
+        &self,
+        policy: &'a Node<cst::Policy>,
+    ) -> Cow<'a, Node<cst::Policy>> {
+        if self.0.is_empty() {
+            return Cow::Borrowed(policy);
+        }
+        let mut expanded = policy.clone();
+        let mut expander = Expander::new(self);
+        if let Some(p) = expanded.node.as_mut() {
+            for var in &mut p.variables {
+                if let Some((_, expr)) = var.node.as_mut().and_then(|v| v.ineq.as_mut()) {
+                    expander.expr(expr);
+                }
+            }
+            for cond in &mut p.conds {
+                if let Some(expr) = cond.node.as_mut().and_then(|c| c.expr.as_mut()) {
+                    expander.expr(expr);
+                }
+            }
+        }
+        if expander.expanded {
+            Cow::Owned(expanded)
+        } else {
+            Cow::Borrowed(policy)
+        }
+    }
+
+    /// The expression defined for `primary`, if it is a defined name
+    fn lookup(&self, primary: &cst::Primary) -> Option<&Node<cst::Expr>> {
+        let cst::Primary::Name(name) = primary else {
+            return None;
+        };
+        let name = name.as_inner().filter(|name| name.path.is_empty())?;
+        match name.name.as_inner()? {
+            cst::Ident::Ident(name) => self.0.get(name),
+            _ => None,
+        }
+    }
+}
+
+/// Replaces defined names in CST expressions
+struct Expander<'a> {
+    definitions: &'a Definitions,
+    /// whether any name has been replaced
+    expanded: bool,
+}
+
+impl<'a> Expander<'a> {
+    fn new(definitions: &'a Definitions) -> Self {
+        Self {
+            definitions,
+            expanded: false,
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Node<cst::Expr>) {
+        let Some(expr) = expr.node.as_mut() else {
+            return;
+        };
+        match expr.expr.as_mut() {
+            cst::ExprData::Or(or) => self.or(or),
+            cst::ExprData::If(c, t, e) => {
+                self.expr(c);
+                self.expr(t);
+                self.expr(e);
+            }
+        }
+    }
+
+    fn or(&mut self, or: &mut Node<cst::Or>) {
+        let Some(or) = or.node.as_mut() else {
+            return;
+        };
+        for and in std::iter::once(&mut or.initial).chain(&mut or.extended) {
+            let Some(and) = and.node.as_mut() else {
+                continue;
+            };
+            for rel in std::iter::once(&mut and.initial).chain(&mut and.extended) {
+                self.relation(rel);
+            }
+        }
+    }
+
+    fn relation(&mut self, rel: &mut Node<cst::Relation>) {
+        match rel.node.as_mut() {
+            Some(cst::Relation::Common { initial, extended }) => {
+                self.add(initial);
+                for (_, add) in extended {
+                    self.add(add);
+                }
+            }
+            // The right hand side of `has` is an attribute name, even if it
+            // looks like a defined name
+            Some(cst::Relation::Has { target, .. }) => self.add(target),
+            Some(cst::Relation::Like { target, pattern }) => {
+                self.add(target);
+                self.add(pattern);
+            }
+            None => (),
+        }
+    }
+
+    fn add(&mut self, add: &mut Node<cst::Add>) {
+        let Some(add) = add.node.as_mut() else {
+            return;
+        };
+        for mult in std::iter::once(&mut add.initial).chain(add.extended.iter_mut().map(|(_, m)| m))
+        {
+            let Some(mult) = mult.node.as_mut() else {
+                continue;
+            };
+            for unary in
+                std::iter::once(&mut mult.initial).chain(mult.extended.iter_mut().map(|(_, u)| u))
+            {
+                if let Some(unary) = unary.node.as_mut() {
+                    self.member(&mut unary.item);
+                }
+            }
+        }
+    }
+
+    fn member(&mut self, member: &mut Node<cst::Member>) {
+        let Some(member) = member.node.as_mut() else {
+            return;
+        };
+        // A name which is called is a function, not a defined name
+        let called = matches!(
+            member.access.first().and_then(|a| a.as_inner()),
+            Some(cst::MemAccess::Call(_))
+        );
+        match member
+            .item
+            .as_inner()
+            .and_then(|p| self.definitions.lookup(p))
+        {
+            Some(expr) if !called => {
+                member.item = substitute(expr);
+                self.expanded = true;
+            }
+            _ => self.primary(&mut member.item),
+        }
+        for access in &mut member.access {
+            match access.node.as_mut() {
+                Some(cst::MemAccess::Call(args)) => {
+                    for arg in args {
+                        self.expr(arg);
+                    }
+                }
+                Some(cst::MemAccess::Index(index)) => self.expr(index),
+                Some(cst::MemAccess::Field(_)) | None => (),
+            }
+        }
+    }
+
+    fn primary(&mut self, primary: &mut Node<cst::Primary>) {
+        match primary.node.as_mut() {
+            Some(cst::Primary::Expr(expr)) => self.expr(expr),
+            Some(cst::Primary::EList(exprs)) => {
+                for expr in exprs {
+                    self.expr(expr);
+                }
+            }
+            Some(cst::Primary::RInits(inits)) => {
+                // The keys of a record literal are attribute names, even if
+                // they look like defined names
+                for init in inits {
+                    if let Some(cst::RecInit(_, value)) = init.node.as_mut() {
+                        self.expr(value);
+                    }
+                }
+            }
+            Some(
+                cst::Primary::Literal(_)
+                | cst::Primary::Ref(_)
+                | cst::Primary::Name(_)
+                | cst::Primary::Slot(_),
+            )
+            | None => (),
+        }
+    }
+}
+
+/// The primary to use in place of a defined name: the expression itself if it
+/// is a single primary, such as a literal or an entity reference, and the
+/// expression in parentheses otherwise
+fn substitute(expr: &Node<cst::Expr>) -> Node<cst::Primary> {
+    match expr.as_inner().and_then(as_primary) {
+        Some(primary) => primary.clone(),
+        None => ASTNode::new(
+            Some(cst::Primary::Expr(expr.clone())),
+            expr.info.range_start(),
+            expr.info.range_end(),
+        ),
+    }
+}
+
+/// If `expr` is a single primary with no operators or member accesses applied
+/// to it, get the primary
+fn as_primary(expr: &cst::Expr) -> Option<&Node<cst::Primary>> {
+    let cst::ExprData::Or(or) = expr.expr.as_ref() else {
+        return None;
+    };
+    let or = or.as_inner().filter(|or| or.extended.is_empty())?;
+    let and = or
+        .initial
+        .as_inner()
+        .filter(|and| and.extended.is_empty())?;
+    let cst::Relation::Common { initial, extended } = and.initial.as_inner()? else {
+        return None;
+    };
+    if !extended.is_empty() {
+        return None;
+    }
+    let add = initial.as_inner().filter(|add| add.extended.is_empty())?;
+    let mult = add
+        .initial
+        .as_inner()
+        .filter(|mult| mult.extended.is_empty())?;
+    let unary = mult.initial.as_inner().filter(|unary| unary.op.is_none())?;
+    let member = unary
+        .item
+        .as_inner()
+        .filter(|member| member.access.is_empty())?;
+    Some(&member.item)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::{
+        parse_policyset, parse_policyset_and_also_return_policy_text,
+        parse_policyset_to_ests_and_pset,
+    };
+
+    fn policy_texts(src: &str) -> Vec<String> {
+        let pset = parse_policyset(src).expect("should parse");
+        let mut texts = pset.policies().map(ToString::to_string).collect::<Vec<_>>();
+        texts.sort();
+        texts
+    }
+
+    #[test]
+    fn definitions_are_expanded() {
+        let src = r#"
+            def USDC = Token::"0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+            def STABLES = [USDC, Token::"0xdAC17F958D2ee523a2206206994597C13D831ec7"];
+            def LIMIT = 1000 * 2;
+            def ADMIN = User::"admin";
+
+            permit(principal == ADMIN, action, resource == USDC)
+            when { context.amount < LIMIT && context.tokens.containsAny(STABLES) };
+            forbid(principal, action, resource) unless { resource in STABLES };
+        "#;
+        let expanded = r#"
+            permit(principal == User::"admin", action, resource == Token::"0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48")
+            when { context.amount < (1000 * 2) && context.tokens.containsAny([Token::"0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48", Token::"0xdAC17F958D2ee523a2206206994597C13D831ec7"]) };
+            forbid(principal, action, resource) unless { resource in [Token::"0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48", Token::"0xdAC17F958D2ee523a2206206994597C13D831ec7"] };
+        "#;
+        assert_eq!(policy_texts(src), policy_texts(expanded));
+    }
+
+    #[test]
+    fn names_which_are_not_definitions() {
+        // A called name is a function, and a record key or the right hand side
+        // of `has` is an attribute name
+        let src = r#"
+            def ip = "not a function";
+            def owner = User::"alice";
+            permit(principal, action, resource)
+            when { ip("10.0.0.1").isLoopback() && {owner: owner} has owner && resource has owner };
+        "#;
+        let expanded = r#"
+            permit(principal, action, resource)
+            when { ip("10.0.0.1").isLoopback() && {owner: User::"alice"} has owner && resource has owner };
+        "#;
+        assert_eq!(policy_texts(src), policy_texts(expanded));
+        // `def` is still an identifier
+        assert!(parse_policyset(
+            "permit(principal, action, resource) when { context.def == def::\"x\" };"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn hex_literal_definitions() {
+        let src = r#"
+            def MAX = 0x0de0b6b3a7640000;
+            permit(principal, action, resource) when { context.amount.u256LessThan(MAX) };
+        "#;
+        let expanded = r#"
+            permit(principal, action, resource)
+            when { context.amount.u256LessThan(0x0de0b6b3a7640000) };
+        "#;
+        assert_eq!(policy_texts(src), policy_texts(expanded));
+    }
+
+    #[test]
+    fn invalid_definitions() {
+        let errs = |src: &str| parse_policyset(src).expect_err("should not parse");
+        assert!(errs(
+            r#"def A = 1; def A = 2; permit(principal, action, resource) when { A == 1 };"#
+        )
+        .contains(&ParseError::ToAST(ToASTError::DuplicateDefinition(
+            "A".into()
+        ))));
+        assert!(
+            errs(r#"permit(principal, action, resource); def A = 1;"#).contains(
+                &ParseError::ToAST(ToASTError::DefinitionAfterPolicy("A".into()))
+            )
+        );
+        assert!(errs(r#"@id("a") def A = 1;"#).contains(&ParseError::ToAST(
+            ToASTError::AnnotatedDefinition("A".into())
+        )));
+        // a definition can only use the names defined before it
+        assert!(errs(
+            r#"def A = B; def B = 1; permit(principal, action, resource) when { A == 1 };"#
+        )
+        .contains(&ParseError::ToAST(ToASTError::ArbitraryVariable(
+            "B".into()
+        ))));
+        assert!(
+            errs(r#"permit(principal, action, resource) when { A == 1 };"#).contains(
+                &ParseError::ToAST(ToASTError::ArbitraryVariable("A".into()))
+            )
+        );
+    }
+
+    #[test]
+    fn policy_text_and_ests() {
+        let src = r#"
+            def ADMIN = User::"admin";
+            permit(principal == ADMIN, action, resource);
+            forbid(principal, action, resource);
+        "#;
+        let (texts, _) = parse_policyset_and_also_return_policy_text(src).expect("should parse");
+        let admin: ast::PolicyID = ast::PolicyID::from_string("policy0");
+        let other: ast::PolicyID = ast::PolicyID::from_string("policy1");
+        let admin_text = texts.get(&admin).expect("should have policy0");
+        assert!(matches!(admin_text, Cow::Owned(_)));
+        assert_eq!(
+            parse_policyset(admin_text)
+                .expect("expanded text should parse")
+                .policies()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                parse_policyset(r#"permit(principal == User::"admin", action, resource);"#)
+                    .unwrap()
+                    .policies()
+                    .next()
+                    .unwrap()
+                    .to_string()
+            ]
+        );
+        assert!(matches!(
+            texts.get(&other),
+            Some(Cow::Borrowed("forbid(principal, action, resource);"))
+        ));
+
+        let (ests, _) = parse_policyset_to_ests_and_pset(src).expect("should parse");
+        let json = serde_json::to_value(ests.get(&admin).unwrap()).unwrap();
+        assert_eq!(json["principal"]["entity"]["id"], "admin");
+    }
+}
@@ -209,6 +209,28 @@ pub enum ToASTError {
     /// Returned when a policy attempts to multiply by a non-constant integer
     #[error("multiplication must be by an integer literal")]
     NonConstantMultiplication,
+    /// Returned when an operator has an operand of an extension type
+    /// overloading it, such as `u256`, and another operand known to be of
+    /// another type, such as an integer literal, so that neither the built-in
+    /// operator nor the extension function applies to both
+    #[error("an operand of `{func}` is a `{ty}`, but another is known not to be; convert it to a `{ty}`")]
+    MixedOperandTypes {
+        /// The extension type of an operand
+        ty: ast::Name,
+        /// The extension function implementing the operator for that type
+        func: ast::Name,
+    },
+    /// Returned when a policy contains a hex literal with an odd number of
+    /// digits, like `0x123`, which is not a whole number of bytes
+    #[error("hex literal `{0}` has an odd number of digits; pad it with a leading zero")]
+    OddLengthHexLiteral(SmolStr),
+    /// Returned when a hex literal is compared with `==` or `!=` to an
+    /// expression known to be a `u256`. The literal is a `bytes` there, so the
+    /// two are never equal.
+    #[error(
+        "hex literal `{0}` is compared with a `u256`, but is a `bytes` here; write `u256(\"{0}\")`"
+    )]
+    HexLiteralComparedWithU256(SmolStr),
     /// Returned when a policy contains an integer literal that is out of range
     #[error(
         "integer literal `{0}` is too large. Maximum allowed integer literal is `{}`",
@@ -287,6 +309,34 @@ pub enum ToASTError {
     /// Returns when a policy scope has incorrect EntityUIDs/Template Slots
     #[error(transparent)]
     RefCreation(#[from] RefCreationError),
+    /// Returned when a name is defined more than once in a policy set
+    #[error("`{0}` is defined more than once")]
+    DuplicateDefinition(SmolStr),
+    /// Returned when a definition comes after a policy
+    #[error("the definition of `{0}` must come before the first policy")]
+    DefinitionAfterPolicy(SmolStr),
+    /// Returned when a definition has annotations
+    #[error("the definition of `{0}` cannot have annotations")]
+    AnnotatedDefinition(SmolStr),
+    /// Returned when a policy set with imports is parsed from text alone,
+    /// rather than loaded from files
+    #[error("cannot import `{0}`: imports are only supported when loading policy files")]
+    UnresolvedImport(SmolStr),
+    /// Returned when an import comes after a definition or policy
+    #[error("the import of `{0}` must come before the definitions and policies")]
+    ImportAfterPolicy(SmolStr),
+    /// Returned when an import has annotations
+    #[error("the import of `{0}` cannot have annotations")]
+    AnnotatedImport(SmolStr),
+}
+
+impl From<super::overload::MixedOperandTypes> for ToASTError {
+    fn from(err: super::overload::MixedOperandTypes) -> Self {
+        Self::MixedOperandTypes {
+            ty: err.ty,
+            func: err.func,
+        }
+    }
 }
 
 impl ToASTError {
@@ -460,6 +510,8 @@ lazy_static! {
         ("LIKE", "`like`"),
         ("THEN", "`then`"),
         ("ELSE", "`else`"),
+        ("DEF", "`def`"),
+        ("IMPORT", "`import`"),
         ("PRINCIPAL", "`principal`"),
         ("ACTION", "`action`"),
         ("RESOURCE", "`resource`"),
@@ -468,6 +520,7 @@ lazy_static! {
         ("RESOURCE_SLOT", "`?resource`"),
         ("IDENTIFIER", "identifier"),
         ("NUMBER", "number"),
+        ("HEXLIT", "hex literal"),
         ("STRINGLIT", "string literal"),
     ]);
 }
@@ -37,26 +37,46 @@ impl<'a, T: fmt::Display> fmt::Display for View<'a, T> {
 
 impl fmt::Display for Policies {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if f.alternate() {
-            let mut ps = self.0.iter();
-            if let Some(p) = ps.next() {
-                write!(f, "{:#}", View(p))?;
-            }
-            for p in ps {
-                write!(f, "\n\n{:#}", View(p))?;
-            }
-        } else {
-            let mut ps = self.0.iter();
-            if let Some(p) = ps.next() {
-                write!(f, "{}", View(p))?;
-            }
-            for p in ps {
-                write!(f, " {}", View(p))?;
+        let alternate = f.alternate();
+        let show = |item: &dyn fmt::Display| {
+            if alternate {
+                format!("{item:#}")
+            } else {
+                item.to_string()
             }
+        };
+        let mut items = self
+            .imports
+            .iter()
+            .map(|i| show(&View(i)))
+            .chain(self.definitions.iter().map(|d| show(&View(d))))
+            .chain(self.policies.iter().map(|p| show(&View(p))));
+        if let Some(item) = items.next() {
+            write!(f, "{item}")?;
+        }
+        let separator = if alternate { "\n\n" } else { " " };
+        for item in items {
+            write!(f, "{separator}{item}")?;
         }
         Ok(())
     }
 }
+impl fmt::Display for Import {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for anno in self.annotations.iter() {
+            write!(f, "{} ", View(anno))?;
+        }
+        write!(f, "import {};", View(&self.path))
+    }
+}
+impl fmt::Display for Definition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for anno in self.annotations.iter() {
+            write!(f, "{} ", View(anno))?;
+        }
+        write!(f, "def {} = {};", View(&self.name), View(&self.expr))
+    }
+}
 impl fmt::Display for Policy {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // start with annotations
@@ -404,6 +424,7 @@ impl fmt::Display for Literal {
             Literal::True => write!(f, "true"),
             Literal::False => write!(f, "false"),
             Literal::Num(n) => write!(f, "{}", n),
+            Literal::Hex(h) => write!(f, "{}", h),
             Literal::Str(s) => write!(f, "{}", View(s)),
         }
     }
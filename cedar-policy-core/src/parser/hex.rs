@@ -0,0 +1,242 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::cst;
+use super::err::ToASTError;
+use super::overload::known_extension_type;
+use crate::ast::{Expr, Name};
+use crate::entities::SchemaType;
+use crate::extensions::Extensions;
+use smol_str::SmolStr;
+
+/// The type of a hex literal is given by where it is used: it is a `u256` as
+/// argument `index` of an extension function which expects a `u256` there, or
+/// as an operand of an arithmetic or comparison operator (see
+/// [`HEX_OPERAND_CONSTRUCTOR`]), and `bytes` everywhere else, including as an
+/// argument of a function which is not an extension function.
+///
+/// Returns the name of the extension function which constructs the value of
+/// the hex literal, given the name of the function it is an argument of, if
+/// any.
+pub(crate) fn hex_literal_constructor(func: Option<&Name>, index: usize) -> &'static str {
+    let extensions = Extensions::all_available();
+    let expects_u256 = func
+        .and_then(|func| extensions.func(func).ok())
+        .and_then(|func| func.arg_types().get(index).cloned().flatten())
+        .is_some_and(
+            |ty| matches!(ty, SchemaType::Extension { name } if name.to_string() == "u256"),
+        );
+    if expects_u256 {
+        "u256"
+    } else {
+        "bytes"
+    }
+}
+
+/// Check that the hex literal `h` has a whole number of bytes, so that it is
+/// a valid `bytes` whatever type it is given
+pub(crate) fn check_hex_digits(h: &SmolStr) -> Result<(), ToASTError> {
+    let digits = h.strip_prefix("0x").unwrap_or(h);
+    if digits.len().is_multiple_of(2) {
+        Ok(())
+    } else {
+        Err(ToASTError::OddLengthHexLiteral(h.clone()))
+    }
+}
+
+/// Check that the hex literal `h`, compared with `==` or `!=` to `other`, is
+/// not compared with a `u256`. The literal is a `bytes` there, which is never
+/// equal to a `u256`. Operands whose type is only known from a schema, like
+/// attributes, are checked by the validator instead.
+pub(crate) fn check_hex_comparison(h: &SmolStr, other: &Expr) -> Result<(), ToASTError> {
+    match known_extension_type(other) {
+        Some(ty) if ty.to_string() == "u256" => {
+            Err(ToASTError::HexLiteralComparedWithU256(h.clone()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Name of the extension function which constructs the value of a hex literal
+/// which is an operand of `<`, `<=`, `>`, `>=`, `+`, `-` or `*`. These only
+/// apply to numbers, so the literal is a `u256`.
+pub(crate) const HEX_OPERAND_CONSTRUCTOR: &str = "u256";
+
+/// If `expr` is just a hex literal, with no operators applied to it, get the
+/// text of the literal
+#[cfg(feature = "json")]
+pub(crate) fn as_hex_literal(expr: &cst::Expr) -> Option<&SmolStr> {
+    let cst::ExprData::Or(or) = expr.expr.as_ref() else {
+        return None;
+    };
+    let or = or.as_inner().filter(|or| or.extended.is_empty())?;
+    let and = or
+        .initial
+        .as_inner()
+        .filter(|and| and.extended.is_empty())?;
+    match and.initial.as_inner()? {
+        cst::Relation::Common { initial, extended } if extended.is_empty() => {
+            as_hex_literal_add(initial.as_inner()?)
+        }
+        _ => None,
+    }
+}
+
+/// If `add` is just a hex literal, get the text of the literal
+#[cfg(feature = "json")]
+pub(crate) fn as_hex_literal_add(add: &cst::Add) -> Option<&SmolStr> {
+    if !add.extended.is_empty() {
+        return None;
+    }
+    as_hex_literal_mult(add.initial.as_inner()?)
+}
+
+/// If `mult` is just a hex literal, get the text of the literal
+#[cfg(feature = "json")]
+pub(crate) fn as_hex_literal_mult(mult: &cst::Mult) -> Option<&SmolStr> {
+    if !mult.extended.is_empty() {
+        return None;
+    }
+    as_hex_literal_unary(mult.initial.as_inner()?)
+}
+
+/// If `unary` is just a hex literal, get the text of the literal
+#[cfg(feature = "json")]
+pub(crate) fn as_hex_literal_unary(unary: &cst::Unary) -> Option<&SmolStr> {
+    if unary.op.is_some() {
+        return None;
+    }
+    let member = unary
+        .item
+        .as_inner()
+        .filter(|member| member.access.is_empty())?;
+    as_hex_literal_primary(member.item.as_inner()?)
+}
+
+/// If `primary` is a hex literal, get the text of the literal
+#[cfg(feature = "json")]
+pub(crate) fn as_hex_literal_primary(primary: &cst::Primary) -> Option<&SmolStr> {
+    match primary {
+        cst::Primary::Literal(lit) => match lit.as_inner()? {
+            cst::Literal::Hex(h) => Some(h),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::{PolicyID, Value};
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::Evaluator;
+    use crate::extensions::Extensions;
+    use crate::parser::{parse_expr, parse_policy_to_est_and_ast};
+
+    #[test]
+    fn hex_literal_types() {
+        for (src, expected) in [
+            ("0x1F", r#"bytes("0x1F")"#),
+            ("[0x12, 0x34]", r#"[bytes("0x12"), bytes("0x34")]"#),
+            (
+                r#"u256("1").u256LessThan(0x10)"#,
+                r#"u256("1").u256LessThan(u256("0x10"))"#,
+            ),
+            (
+                r#"0x10.u256GreaterThan(u256("1"))"#,
+                r#"u256("0x10").u256GreaterThan(u256("1"))"#,
+            ),
+            (
+                "0x1234.bytesStartsWith(0x12)",
+                r#"bytes("0x1234").bytesStartsWith(bytes("0x12"))"#,
+            ),
+            (
+                "[0x12].contains(0x12)",
+                r#"[bytes("0x12")].contains(bytes("0x12"))"#,
+            ),
+        ] {
+            let expr = parse_expr(src).expect("should parse");
+            let expected = parse_expr(expected).expect("should parse");
+            assert!(expr.eq_shape(&expected), "{src} parsed to {expr}");
+        }
+    }
+
+    #[test]
+    fn hex_literal_evaluation() {
+        let request = basic_request();
+        let entities = basic_entities();
+        let exts = Extensions::all_available();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+        for (src, expected) in [
+            (r#"u256("100").u256LessThan(0x10)"#, false),
+            (r#"0x7b.u256GreaterThanOrEqual(u256("123"))"#, true),
+            ("0xa9059cbb00ff.bytesStartsWith(0xa9059cbb)", true),
+            (r#"0xAB == bytes("0xab")"#, true),
+        ] {
+            let expr = parse_expr(src).expect("should parse");
+            assert_eq!(
+                eval.interpret_inline_policy(&expr),
+                Ok(Value::from(expected)),
+                "{src}"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_hex_literals() {
+        for (src, expected) in [
+            ("0x123", "hex literal `0x123` has an odd number of digits"),
+            (
+                r#"u256("1").u256LessThan(0xabc)"#,
+                "hex literal `0xabc` has an odd number of digits",
+            ),
+            (
+                r#"u256("16") == 0x10"#,
+                "hex literal `0x10` is compared with a `u256`",
+            ),
+            (
+                r#"0x10 != u256("1").u256Add(u256("15"))"#,
+                "hex literal `0x10` is compared with a `u256`",
+            ),
+        ] {
+            let errs = parse_expr(src).expect_err("should not parse");
+            assert!(errs.to_string().contains(expected), "{src}: {errs}");
+        }
+        // attributes may be `bytes`, which the validator checks
+        assert!(parse_expr("principal.selector == 0xa9059cbb").is_ok());
+        assert!(parse_policy_to_est_and_ast(
+            None,
+            "permit(principal, action, resource) when { context.amount < 0x123 };"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn hex_literal_est() {
+        let src = r#"permit(principal, action, resource) when {
+            context.amount.u256LessThan(0x0de0b6b3a7640000) &&
+            0x10.u256GreaterThan(context.fee) &&
+            context.selector == 0xa9059cbb
+        };"#;
+        let (est, ast) = parse_policy_to_est_and_ast(None, src).expect("should parse");
+        let from_est = est
+            .try_into_ast_policy(Some(PolicyID::from_string("policy0")))
+            .expect("should convert");
+        assert!(from_est
+            .non_head_constraints()
+            .eq_shape(ast.non_head_constraints()));
+    }
+}
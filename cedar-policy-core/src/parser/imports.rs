@@ -0,0 +1,460 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use itertools::Either;
+use miette::Diagnostic;
+use smol_str::SmolStr;
+use thiserror::Error;
+
+use super::cst;
+use super::definitions::Definitions;
+use super::err::{ParseError, ParseErrors, ToASTError};
+use super::node::ASTNode;
+use super::text_to_cst;
+use super::unescape::to_unescaped_string;
+use crate::ast::{self, PolicySetError};
+
+/// Access to the files of a policy set which is split across files with
+/// `import` statements
+pub trait PolicyFiles {
+    /// The name of the file imported by `import "<path>";` in the file named
+    /// `importer`. Files with the same name are the same file.
+    fn resolve(&self, importer: &str, path: &str) -> String;
+
+    /// Read the text of the file named `name`
+    fn read(&mut self, name: &str) -> std::io::Result<String>;
+}
+
+/// Errors loading a policy set from files
+#[derive(Debug, Diagnostic, Error)]
+pub enum ImportError {
+    /// A file could not be read
+    #[error("failed to read `{file}`")]
+    Read {
+        /// The name of the file
+        file: String,
+        /// The error reading it
+        #[source]
+        error: std::io::Error,
+    },
+    /// A file does not parse, or has errors in its imports or definitions
+    #[error("failed to parse `{file}`")]
+    Parse {
+        /// The name of the file
+        file: String,
+        /// The text of the file, which the errors refer to
+        text: String,
+        /// The errors
+        #[source]
+        errs: ParseErrors,
+    },
+    /// Files import each other in a cycle
+    #[error("files import each other in a cycle: {}", .0.join(" -> "))]
+    ImportCycle(Vec<String>),
+}
+
+/// Parse the policy set in the file named `root`, together with the files it
+/// imports, and the files they import, and so on. Also returns the text of
+/// each policy, as `parse_policyset_and_also_return_policy_text()` does.
+///
+/// Each file is loaded once, even if several files import it. A file can use
+/// the names defined by the files it imports, but not those defined by the
+/// files they import, and it is an error for two of the files it imports to
+/// define the same name. The policies of `root` have the ids `policy0`,
+/// `policy1`, ..., as if it were parsed on its own, while those of an
+/// imported file have its name as a prefix, as in `tokens.cedar:policy0`.
+pub fn parse_policyset_files(
+    root: &str,
+    files: &mut impl PolicyFiles,
+) -> Result<(HashMap<ast::PolicyID, String>, ast::PolicySet), ImportError> {
+    parse_policyset_files_with_origins(root, files).map(|(texts, _, pset)| (texts, pset))
+}
+
+/// Values, such as policy texts, by policy id
+type ById<T> = HashMap<ast::PolicyID, T>;
+
+/// Like `parse_policyset_files()`, but also returns the name of the file each
+/// policy is in
+pub fn parse_policyset_files_with_origins(
+    root: &str,
+    files: &mut impl PolicyFiles,
+) -> Result<(ById<String>, ById<String>, ast::PolicySet), ImportError> {
+    let mut loader = Loader {
+        files,
+        loaded: HashMap::new(),
+        stack: Vec::new(),
+        texts: HashMap::new(),
+        origins: HashMap::new(),
+        pset: ast::PolicySet::new(),
+    };
+    loader.load(root)?;
+    Ok((loader.texts, loader.origins, loader.pset))
+}
+
+struct Loader<'a, F> {
+    files: &'a mut F,
+    /// The files loaded so far, with the definitions which the files
+    /// importing them can use
+    loaded: HashMap<String, Definitions>,
+    /// The files being loaded, each imported by the one before it
+    stack: Vec<String>,
+    texts: HashMap<ast::PolicyID, String>,
+    /// The name of the file each policy is in
+    origins: HashMap<ast::PolicyID, String>,
+    pset: ast::PolicySet,
+}
+
+impl<F: PolicyFiles> Loader<'_, F> {
+    fn load(&mut self, name: &str) -> Result<(), ImportError> {
+        if self.stack.iter().any(|file| file == name) {
+            let mut cycle = self
+                .stack
+                .iter()
+                .skip_while(|file| *file != name)
+                .cloned()
+                .collect::<Vec<_>>();
+            cycle.push(name.to_string());
+            return Err(ImportError::ImportCycle(cycle));
+        }
+        if self.loaded.contains_key(name) {
+            return Ok(());
+        }
+        let text = self.files.read(name).map_err(|error| ImportError::Read {
+            file: name.to_string(),
+            error,
+        })?;
+        let parse_error = |errs| ImportError::Parse {
+            file: name.to_string(),
+            text: text.clone(),
+            errs,
+        };
+        let (cst, mut errs) = text_to_cst::parse_policies_with_recovery(&text);
+        let Some(paths) = cst.as_ref().and_then(|cst| cst.import_paths(&mut errs)) else {
+            return Err(parse_error(errs));
+        };
+
+        self.stack.push(name.to_string());
+        let mut imported = Definitions::default();
+        for path in paths {
+            let file = self.files.resolve(name, &path);
+            self.load(&file)?;
+            if let Some(definitions) = self.loaded.get(&file) {
+                imported.import(definitions, &mut errs);
+            }
+        }
+        self.stack.pop();
+        let is_root = self.stack.is_empty();
+
+        // PANIC SAFETY: `import_paths()` returned `Some`, so there is a CST
+        #[allow(clippy::expect_used)]
+        let cst = cst.expect("the policy set CST should exist");
+        let definitions = cst.definitions(&imported, &mut errs);
+        for (id, policy) in cst.with_generated_policyids().into_iter().flatten() {
+            let Some(definitions) = definitions.as_ref() else {
+                break;
+            };
+            let id = if is_root {
+                id
+            } else {
+                ast::PolicyID::from_string(format!("{name}:{id}"))
+            };
+            let policy = definitions.expand_policy(policy);
+            let added = match policy.to_policy_or_template(id.clone(), &mut errs) {
+                Some(Either::Left(policy)) => self.pset.add_static(policy),
+                Some(Either::Right(template)) => self.pset.add_template(template),
+                None => continue,
+            };
+            if let Err(PolicySetError::Occupied { id }) = added {
+                errs.push(ParseError::ToAST(ToASTError::DuplicatePolicyId(id)));
+                continue;
+            }
+            // PANIC SAFETY: indexing is safe because of how `SourceInfo` is constructed
+            #[allow(clippy::indexing_slicing)]
+            let policy_text = match &policy {
+                Cow::Borrowed(policy) => text[policy.info.0.clone()].to_string(),
+                Cow::Owned(policy) => policy
+                    .as_inner()
+                    .map(ToString::to_string)
+                    .unwrap_or_default(),
+            };
+            self.origins.insert(id.clone(), name.to_string());
+            self.texts.insert(id, policy_text);
+        }
+        match definitions {
+            Some(definitions) if errs.is_empty() => {
+                self.loaded
+                    .insert(name.to_string(), definitions.without(&imported));
+                Ok(())
+            }
+            _ => Err(parse_error(errs)),
+        }
+    }
+}
+
+impl ASTNode<Option<cst::Policies>> {
+    /// The paths of the files imported by this policy set
+    pub(crate) fn import_paths(&self, errs: &mut ParseErrors) -> Option<Vec<SmolStr>> {
+        let policies = self.as_inner()?;
+        let first_item = policies
+            .definitions
+            .first()
+            .map(|d| d.info.range_start())
+            .into_iter()
+            .chain(policies.policies.first().map(|p| p.info.range_start()))
+            .min();
+        let mut paths = Vec::new();
+        for node in &policies.imports {
+            let Some(import) = node.as_inner() else {
+                continue;
+            };
+            let Some(path) = import.path.as_valid_string(errs) else {
+                continue;
+            };
+            let path = match to_unescaped_string(path) {
+                Ok(path) => path,
+                Err(unescape_errs) => {
+                    errs.extend(
+                        unescape_errs
+                            .into_iter()
+                            .map(|e| ParseError::ToAST(e.into())),
+                    );
+                    continue;
+                }
+            };
+            if !import.annotations.is_empty() {
+                errs.push(ParseError::ToAST(ToASTError::AnnotatedImport(path.clone())));
+            }
+            if first_item.is_some_and(|start| node.info.range_start() > start) {
+                errs.push(ParseError::ToAST(ToASTError::ImportAfterPolicy(
+                    path.clone(),
+                )));
+            }
+            paths.push(path);
+        }
+        Some(paths)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse_policyset;
+
+    /// Policy files held in memory, with import paths relative to the root
+    struct Files(HashMap<&'static str, &'static str>);
+
+    impl PolicyFiles for Files {
+        fn resolve(&self, _importer: &str, path: &str) -> String {
+            path.to_string()
+        }
+
+        fn read(&mut self, name: &str) -> std::io::Result<String> {
+            self.0
+                .get(name)
+                .map(ToString::to_string)
+                .ok_or_else(|| std::io::ErrorKind::NotFound.into())
+        }
+    }
+
+    fn load(files: &[(&'static str, &'static str)]) -> Result<ast::PolicySet, ImportError> {
+        let mut files = Files(files.iter().copied().collect());
+        parse_policyset_files("main.cedar", &mut files).map(|(_, pset)| pset)
+    }
+
+    fn parse_errors(err: ImportError) -> (String, ParseErrors) {
+        match err {
+            ImportError::Parse { file, errs, .. } => (file, errs),
+            err => panic!("expected a parse error, got {err:?}"),
+        }
+    }
+
+    #[test]
+    fn imported_policies_and_definitions() {
+        let tokens = r#"
+            def USDC = Token::"0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+            forbid(principal, action, resource == Token::"0xdead");
+        "#;
+        let vaults = r#"
+            import "tokens.cedar";
+            def VAULT = Vault::"main";
+            permit(principal, action == Action::"deposit", resource == VAULT)
+            when { context.token == USDC };
+        "#;
+        let main = r#"
+            import "vaults.cedar";
+            import "tokens.cedar";
+            permit(principal, action, resource == VAULT) when { context.token == USDC };
+        "#;
+        let (texts, pset) = parse_policyset_files(
+            "main.cedar",
+            &mut Files(HashMap::from([
+                ("main.cedar", main),
+                ("vaults.cedar", vaults),
+                ("tokens.cedar", tokens),
+            ])),
+        )
+        .expect("should load");
+        let mut ids = pset
+            .policies()
+            .map(|p| p.id().to_string())
+            .collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(
+            ids,
+            vec!["policy0", "tokens.cedar:policy0", "vaults.cedar:policy0"]
+        );
+        // the policy texts parse on their own
+        for (id, text) in texts {
+            let policy = parse_policyset(&text).expect("policy text should parse");
+            assert!(policy
+                .policies()
+                .next()
+                .unwrap()
+                .non_head_constraints()
+                .eq_shape(pset.get(&id).unwrap().non_head_constraints()));
+        }
+    }
+
+    #[test]
+    fn records_origins() {
+        let (_, origins, _) = parse_policyset_files_with_origins(
+            "main.cedar",
+            &mut Files(HashMap::from([
+                (
+                    "main.cedar",
+                    r#"import "tokens.cedar"; permit(principal, action, resource);"#,
+                ),
+                ("tokens.cedar", "forbid(principal, action, resource);"),
+            ])),
+        )
+        .expect("should load");
+        assert_eq!(
+            origins,
+            HashMap::from([
+                (ast::PolicyID::from_string("policy0"), "main.cedar".into()),
+                (
+                    ast::PolicyID::from_string("tokens.cedar:policy0"),
+                    "tokens.cedar".into()
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn definitions_are_not_reexported() {
+        let err = load(&[
+            (
+                "main.cedar",
+                r#"import "b.cedar"; permit(principal, action, resource) when { A };"#,
+            ),
+            ("b.cedar", r#"import "a.cedar";"#),
+            ("a.cedar", r#"def A = Vault::"a";"#),
+        ])
+        .expect_err("should not load");
+        let (file, errs) = parse_errors(err);
+        assert_eq!(file, "main.cedar");
+        assert!(
+            errs.contains(&ParseError::ToAST(ToASTError::ArbitraryVariable(
+                "A".into()
+            )))
+        );
+    }
+
+    #[test]
+    fn colliding_definitions() {
+        let err = load(&[
+            ("main.cedar", r#"import "a.cedar"; import "b.cedar";"#),
+            ("a.cedar", r#"def LIMIT = 1;"#),
+            ("b.cedar", r#"def LIMIT = 2;"#),
+        ])
+        .expect_err("should not load");
+        let (file, errs) = parse_errors(err);
+        assert_eq!(file, "main.cedar");
+        assert!(
+            errs.contains(&ParseError::ToAST(ToASTError::DuplicateDefinition(
+                "LIMIT".into()
+            )))
+        );
+
+        let err = load(&[
+            ("main.cedar", r#"import "a.cedar"; def LIMIT = 2;"#),
+            ("a.cedar", r#"def LIMIT = 1;"#),
+        ])
+        .expect_err("should not load");
+        assert!(parse_errors(err)
+            .1
+            .contains(&ParseError::ToAST(ToASTError::DuplicateDefinition(
+                "LIMIT".into()
+            ))));
+    }
+
+    #[test]
+    fn import_errors() {
+        assert!(matches!(
+            load(&[
+                ("main.cedar", r#"import "a.cedar";"#),
+                ("a.cedar", r#"import "b.cedar";"#),
+                ("b.cedar", r#"import "a.cedar";"#),
+            ]),
+            Err(ImportError::ImportCycle(cycle)) if cycle == vec!["a.cedar", "b.cedar", "a.cedar"]
+        ));
+        assert!(matches!(
+            load(&[("main.cedar", r#"import "missing.cedar";"#)]),
+            Err(ImportError::Read { file, .. }) if file == "missing.cedar"
+        ));
+        let err = load(&[
+            ("main.cedar", r#"import "a.cedar";"#),
+            (
+                "a.cedar",
+                r#"permit(principal, action, resource) when { 1 + };"#,
+            ),
+        ])
+        .expect_err("should not load");
+        assert_eq!(parse_errors(err).0, "a.cedar");
+        let err = load(&[
+            (
+                "main.cedar",
+                r#"permit(principal, action, resource); import "a.cedar";"#,
+            ),
+            ("a.cedar", ""),
+        ])
+        .expect_err("should not load");
+        assert!(parse_errors(err)
+            .1
+            .contains(&ParseError::ToAST(ToASTError::ImportAfterPolicy(
+                "a.cedar".into()
+            ))));
+    }
+
+    #[test]
+    fn imports_need_files() {
+        let errs = parse_policyset(r#"import "a.cedar"; permit(principal, action, resource);"#)
+            .expect_err("should not parse");
+        assert!(
+            errs.contains(&ParseError::ToAST(ToASTError::UnresolvedImport(
+                "a.cedar".into()
+            )))
+        );
+        // `import` is still an identifier
+        assert!(parse_policyset(
+            "permit(principal, action, resource) when { context.import == import::\"x\" };"
+        )
+        .is_ok());
+    }
+}
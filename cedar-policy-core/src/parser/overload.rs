@@ -0,0 +1,248 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::ast::{Expr, ExprKind, Name, OverloadedOperator};
+use crate::entities::SchemaType;
+use crate::extensions::Extensions;
+
+/// An operator has an operand of the extension type `ty`, which overloads it
+/// with the extension function `func`, and another operand known to be of
+/// another type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MixedOperandTypes {
+    pub(crate) ty: Name,
+    pub(crate) func: Name,
+}
+
+/// What is known of the type of the value of an expression without
+/// evaluating it
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum OperandType {
+    /// A call of an extension function returning values of that type
+    Extension(Name),
+    /// A literal, or the result of a built-in operator, which is not of an
+    /// extension type
+    Builtin,
+    /// Anything else, such as a variable or an attribute, which may be of any
+    /// type
+    Unknown,
+}
+
+fn operand_type(e: &Expr) -> OperandType {
+    match e.expr_kind() {
+        ExprKind::ExtensionFunctionApp { fn_name, .. } => {
+            match Extensions::all_available()
+                .func(fn_name)
+                .ok()
+                .and_then(|func| func.return_type())
+            {
+                Some(SchemaType::Extension { name }) => OperandType::Extension(name.clone()),
+                _ => OperandType::Unknown,
+            }
+        }
+        ExprKind::Lit(_)
+        | ExprKind::And { .. }
+        | ExprKind::Or { .. }
+        | ExprKind::UnaryApp { .. }
+        | ExprKind::BinaryApp { .. }
+        | ExprKind::MulByConst { .. }
+        | ExprKind::HasAttr { .. }
+        | ExprKind::Like { .. }
+        | ExprKind::Set(_)
+        | ExprKind::Record { .. } => OperandType::Builtin,
+        _ => OperandType::Unknown,
+    }
+}
+
+/// The extension type of the value of `e`, if it is known without evaluating
+/// it
+pub(crate) fn known_extension_type(e: &Expr) -> Option<Name> {
+    match operand_type(e) {
+        OperandType::Extension(name) => Some(name),
+        OperandType::Builtin | OperandType::Unknown => None,
+    }
+}
+
+/// Operators are resolved by the types of their operands: an operator applied
+/// to operands of which any is known to be of an extension type overloading
+/// the operator is a call of the extension function implementing it for that
+/// type, and otherwise is the built-in operator.
+///
+/// The other operands must then be known to be of that type too, or be of
+/// unknown type, such as attributes, which are then expected to be of that
+/// type. Operands of unknown type only, like `context.amount <=
+/// resource.limit`, use the built-in operator, which the validator rejects
+/// for operands of an extension type.
+///
+/// Returns the name of the extension function implementing `op` for the
+/// first of `operands` whose type overloads it, if any, or an error if
+/// another operand is known to be of another type.
+pub(crate) fn overloaded_operator_func<'a>(
+    op: OverloadedOperator,
+    operands: impl IntoIterator<Item = &'a Expr>,
+) -> Result<Option<Name>, MixedOperandTypes> {
+    let extensions = Extensions::all_available();
+    let types = operands.into_iter().map(operand_type).collect::<Vec<_>>();
+    let Some((ty, func)) = types.iter().find_map(|ty| match ty {
+        OperandType::Extension(name) => extensions
+            .operator_func(op, name)
+            .map(|func| (name, func.name())),
+        _ => None,
+    }) else {
+        return Ok(None);
+    };
+    let mixed = types.iter().any(|other| match other {
+        OperandType::Extension(name) => name != ty,
+        OperandType::Builtin => true,
+        OperandType::Unknown => false,
+    });
+    if mixed {
+        return Err(MixedOperandTypes {
+            ty: ty.clone(),
+            func: func.clone(),
+        });
+    }
+    Ok(Some(func.clone()))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::{PolicyID, Value};
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::Evaluator;
+    use crate::extensions::Extensions;
+    use crate::parser::err::{ParseError, ToASTError};
+    use crate::parser::{parse_expr, parse_policy_to_est_and_ast};
+
+    #[test]
+    fn overloaded_operators() {
+        for (src, expected) in [
+            (
+                r#"u256("1") < u256("2")"#,
+                r#"u256("1").u256LessThan(u256("2"))"#,
+            ),
+            (
+                r#"principal.limit >= u256("2")"#,
+                r#"principal.limit.u256GreaterThanOrEqual(u256("2"))"#,
+            ),
+            (
+                "principal.balance <= 0x0de0b6b3a7640000",
+                r#"principal.balance.u256LessThanOrEqual(u256("0x0de0b6b3a7640000"))"#,
+            ),
+            (
+                r#"u256("1") + principal.a - principal.b > principal.c"#,
+                r#"u256("1").u256Add(principal.a).u256Sub(principal.b).u256GreaterThan(principal.c)"#,
+            ),
+            (
+                r#"principal.a * u256("3") * principal.b"#,
+                r#"principal.a.u256Mul(u256("3")).u256Mul(principal.b)"#,
+            ),
+            (
+                r#"decimal("1.5") > principal.fee"#,
+                r#"decimal("1.5").greaterThan(principal.fee)"#,
+            ),
+            // operands of unknown type use the built-in operators
+            (
+                "principal.a < principal.b + 1",
+                "principal.a < principal.b + 1",
+            ),
+            ("principal.a * 2", "principal.a * 2"),
+        ] {
+            let expr = parse_expr(src).expect("should parse");
+            let expected = parse_expr(expected).expect("should parse");
+            assert!(expr.eq_shape(&expected), "{src} parsed to {expr}");
+        }
+        // `ipaddr` does not overload `<`
+        let expr = parse_expr(r#"ip("10.0.0.1") < principal.a"#).expect("should parse");
+        assert!(expr.to_string().contains('<'), "{expr}");
+    }
+
+    #[test]
+    fn mixed_operands() {
+        for src in [
+            // the integer literal is not a `u256`
+            r#"principal.a * u256("3") * 2"#,
+            r#"u256("1") + 1"#,
+            r#"2 * u256("3")"#,
+            // `principal.a + principal.b` is the built-in `+`, so its value
+            // is not a `u256`
+            r#"principal.a + principal.b - u256("1")"#,
+            r#"u256("1") < decimal("1.0")"#,
+            r#"decimal("1.5") > 1"#,
+        ] {
+            let errs = parse_expr(src).expect_err("should not parse");
+            assert!(
+                errs.iter()
+                    .any(|e| matches!(e, ParseError::ToAST(ToASTError::MixedOperandTypes { .. }))),
+                "{src}: {errs}"
+            );
+        }
+    }
+
+    #[test]
+    fn overloaded_operator_evaluation() {
+        let request = basic_request();
+        let entities = basic_entities();
+        let exts = Extensions::all_available();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+        for (src, expected) in [
+            (r#"u256("100") + 0x10 == u256("116")"#, true),
+            (r#"u256("100") - u256("1") * 0x03 < u256("98")"#, true),
+            (r#"u256("100") * u256("100") >= u256("10000")"#, true),
+            (r#"decimal("1.5") > decimal("1.25")"#, true),
+        ] {
+            let expr = parse_expr(src).expect("should parse");
+            assert_eq!(
+                eval.interpret_inline_policy(&expr),
+                Ok(Value::from(expected)),
+                "{src}"
+            );
+        }
+        let expr = parse_expr(r#"u256("1") - u256("2")"#).expect("should parse");
+        assert!(eval.interpret_inline_policy(&expr).is_err());
+    }
+
+    #[test]
+    fn overloaded_operators_est() {
+        let src = r#"permit(principal, action, resource) when {
+            context.amount.u256Add(context.fee) <= u256("1000000") &&
+            context.amount * u256("2") > 0x10 &&
+            context.count * 2 < 10
+        };"#;
+        let (est, ast) = parse_policy_to_est_and_ast(None, src).expect("should parse");
+        let from_est = est
+            .try_into_ast_policy(Some(PolicyID::from_string("policy0")))
+            .expect("should convert");
+        assert!(
+            from_est
+                .non_head_constraints()
+                .eq_shape(ast.non_head_constraints()),
+            "{} is not {}",
+            from_est.non_head_constraints(),
+            ast.non_head_constraints()
+        );
+
+        // the EST does not mix operand types either
+        let mixed: crate::est::Expr = serde_json::from_value(serde_json::json!(
+            { "*": { "left": { "u256": [{ "Value": "3" }] }, "right": { "Value": 2 } } }
+        ))
+        .expect("should deserialize");
+        assert!(matches!(
+            crate::ast::Expr::try_from(mixed),
+            Err(crate::est::FromJsonError::MixedOperandTypes { .. })
+        ));
+    }
+}
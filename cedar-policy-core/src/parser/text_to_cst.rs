@@ -45,6 +45,24 @@ fn parse_collect_errors<'a, P, T>(
     ) -> Result<T, err::RawParseError<'a>>,
     text: &'a str,
 ) -> Result<T, err::ParseErrors> {
+    match parse_with_recovery(parser, parse, text) {
+        (Some(parsed), errors) if errors.is_empty() => Ok(parsed),
+        (_, errors) => Err(errors),
+    }
+}
+
+/// Like `parse_collect_errors()`, but also returns the CST when the parser
+/// recovered from every syntax error it found. Nodes which could not be
+/// parsed have no data.
+fn parse_with_recovery<'a, P, T>(
+    parser: &P,
+    parse: impl FnOnce(
+        &P,
+        &mut Vec<err::RawErrorRecovery<'a>>,
+        &'a str,
+    ) -> Result<T, err::RawParseError<'a>>,
+    text: &'a str,
+) -> (Option<T>, err::ParseErrors) {
     let mut errs = Vec::new();
     let result = parse(parser, &mut errs, text);
 
@@ -52,17 +70,12 @@ fn parse_collect_errors<'a, P, T>(
         .into_iter()
         .map(|recovery| err::ToCSTError::from_raw_err_recovery(recovery).into())
         .collect();
-    let parsed = match result {
-        Ok(parsed) => parsed,
+    match result {
+        Ok(parsed) => (Some(parsed), errors),
         Err(e) => {
             errors.push(err::ToCSTError::from_raw_parse_err(e).into());
-            return Err(errors);
+            (None, errors)
         }
-    };
-    if errors.is_empty() {
-        Ok(parsed)
-    } else {
-        Err(errors)
     }
 }
 
@@ -84,6 +97,18 @@ pub fn parse_policies(
     parse_collect_errors(&*POLICIES_PARSER, grammar::PoliciesParser::parse, text)
 }
 
+/// Like `parse_policies()`, but on a syntax error, skip to the end of the
+/// policy and continue with the next one. Returns the CST, if the parser
+/// could recover from every error, along with all of the syntax errors.
+pub fn parse_policies_with_recovery(
+    text: &str,
+) -> (
+    Option<node::ASTNode<Option<cst::Policies>>>,
+    err::ParseErrors,
+) {
+    parse_with_recovery(&*POLICIES_PARSER, grammar::PoliciesParser::parse, text)
+}
+
 /// Create CST for one policy statement from text
 pub fn parse_policy(text: &str) -> Result<node::ASTNode<Option<cst::Policy>>, err::ParseErrors> {
     parse_collect_errors(&*POLICY_PARSER, grammar::PolicyParser::parse, text)
@@ -821,7 +846,7 @@ mod tests {
         .expect("parse fail")
         .node
         .expect("no data")
-        .0
+        .policies
         .iter()
         .all(|p| p.node.is_some()));
     }
@@ -841,7 +866,7 @@ mod tests {
         .expect("parse fail")
         .node
         .expect("no data")
-        .0
+        .policies
         .iter()
         .all(|p| p.node.is_some()));
     }
@@ -864,7 +889,7 @@ mod tests {
         .expect("parse fail")
         .node
         .expect("no data");
-        assert!(result.0.iter().all(|p| p.node.is_some()));
+        assert!(result.policies.iter().all(|p| p.node.is_some()));
     }
 
     #[test]
@@ -884,7 +909,7 @@ mod tests {
         .expect("parse fail")
         .node
         .expect("no data")
-        .0
+        .policies
         .into_iter()
         .all(|p| p.node.is_some()));
     }
@@ -906,7 +931,7 @@ mod tests {
             .node
             .expect("no data");
         let success = policies
-            .0
+            .policies
             .into_iter()
             .filter_map(|p| p.node)
             .collect::<Vec<_>>();
@@ -927,7 +952,7 @@ mod tests {
         .node
         .expect("no data");
         let success = policies
-            .0
+            .policies
             .into_iter()
             .filter_map(|p| p.node)
             .collect::<Vec<_>>();
@@ -0,0 +1,191 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! This module contains the binary snapshot format shared by entities and
+//! policy sets, and functionality for saving policy sets to, and loading them
+//! from, snapshot files.
+//!
+//! A snapshot starts with 8 bytes identifying what it holds, followed by the
+//! version of this crate which wrote it, and then the AST, encoded with its
+//! `serde` implementation. Loading a policy set snapshot therefore does not
+//! run the parser: the policies are precompiled, and only their bytecode
+//! programs (with the `bytecode` feature) are rebuilt.
+//! See [`crate::entities::Entities::write_snapshot`] for entity snapshots.
+//!
+//! Snapshots are only meant to be read by the version of this crate which
+//! wrote them; loading one written by another version fails with
+//! [`SnapshotError::Version`].
+//!
+//! A snapshot file is read into memory and decoded in full, allocating every
+//! value it holds. It is not memory-mapped, and values are not read in place:
+//! the AST types own their data behind `Arc`s and hash maps, which an
+//! archived, zero-copy layout could only provide by duplicating them.
+
+use crate::ast::PolicySet;
+use serde::{de::DeserializeOwned, Serialize};
+use std::io::Write;
+use std::path::Path;
+use thiserror::Error;
+
+/// The bytes every policy set snapshot starts with
+const POLICY_SET_MAGIC: &[u8; 8] = b"CEDARPOL";
+
+/// The version of the snapshot format. Snapshots are encoded with the `serde`
+/// implementations of the AST, so this changes with the crate version.
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Errors thrown while saving or loading snapshots
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    /// Error reading or writing the snapshot file
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// The input does not start like a snapshot of the expected kind
+    #[error("not a snapshot of the expected kind")]
+    NotASnapshot,
+    /// The snapshot was written by another version of Cedar
+    #[error("snapshot was written by version {found} of Cedar, but this is version {VERSION}")]
+    Version {
+        /// Version which wrote the snapshot
+        found: String,
+    },
+    /// The snapshot is corrupt
+    #[error("invalid snapshot: {0}")]
+    Encoding(#[from] postcard::Error),
+}
+
+/// Write `value` to `writer` as a snapshot starting with `magic`
+pub(crate) fn write<T: Serialize>(
+    magic: &[u8; 8],
+    value: &T,
+    mut writer: impl Write,
+) -> Result<(), SnapshotError> {
+    let mut bytes = postcard::to_stdvec(&(magic, VERSION))?;
+    bytes.extend(postcard::to_stdvec(value)?);
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+/// Decode the snapshot in `bytes`, which must start with `magic`
+pub(crate) fn read<T: DeserializeOwned>(magic: &[u8; 8], bytes: &[u8]) -> Result<T, SnapshotError> {
+    let ((found_magic, version), rest) = postcard::take_from_bytes::<([u8; 8], &str)>(bytes)
+        .map_err(|_| SnapshotError::NotASnapshot)?;
+    if &found_magic != magic {
+        return Err(SnapshotError::NotASnapshot);
+    }
+    if version != VERSION {
+        return Err(SnapshotError::Version {
+            found: version.to_string(),
+        });
+    }
+    Ok(postcard::from_bytes(rest)?)
+}
+
+/// Decode the snapshot file at `path`, which must start with `magic`, after
+/// reading all of it
+pub(crate) fn read_file<T: DeserializeOwned>(
+    magic: &[u8; 8],
+    path: impl AsRef<Path>,
+) -> Result<T, SnapshotError> {
+    read(magic, &std::fs::read(path)?)
+}
+
+impl PolicySet {
+    /// Write a snapshot of this policy set to `writer`
+    pub fn write_snapshot(&self, writer: impl Write) -> Result<(), SnapshotError> {
+        write(POLICY_SET_MAGIC, self, writer)
+    }
+
+    /// Do `bytes`, such as the start of a file, look like a policy set
+    /// snapshot?
+    pub fn is_snapshot(bytes: &[u8]) -> bool {
+        bytes.starts_with(POLICY_SET_MAGIC)
+    }
+
+    /// Load a policy set from a snapshot written by
+    /// [`PolicySet::write_snapshot`], without parsing any policy text
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        read(POLICY_SET_MAGIC, bytes)
+    }
+
+    /// Load a policy set from a snapshot file written by
+    /// [`PolicySet::write_snapshot`]
+    pub fn from_snapshot_file(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        read_file(POLICY_SET_MAGIC, path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::{EntityUID, PolicyID, SlotId};
+    use crate::parser::parse_policyset;
+
+    fn policy_set() -> PolicySet {
+        let mut pset = parse_policyset(
+            r#"
+            permit(principal, action, resource) when { context.amount < 100 };
+            @id("owners")
+            permit(principal in ?principal, action, resource)
+            when { resource.owners.contains(principal) };
+            "#,
+        )
+        .expect("policies are valid");
+        let values = [(SlotId::principal(), EntityUID::with_eid("dao"))].into();
+        pset.link(
+            PolicyID::from_string("policy1"),
+            PolicyID::from_string("link"),
+            values,
+        )
+        .expect("links");
+        pset
+    }
+
+    #[test]
+    fn round_trips() {
+        let pset = policy_set();
+        let mut bytes = Vec::new();
+        pset.write_snapshot(&mut bytes).expect("writes");
+        assert!(PolicySet::is_snapshot(&bytes));
+        let loaded = PolicySet::from_snapshot(&bytes).expect("loads");
+        // with the `bytecode` feature, the program of each template is rebuilt,
+        // and compared too
+        assert_eq!(loaded, pset);
+    }
+
+    #[test]
+    fn loads_files() {
+        let path = std::env::temp_dir().join(format!("policies-{}.snapshot", std::process::id()));
+        let file = std::fs::File::create(&path).expect("creates");
+        policy_set().write_snapshot(file).expect("writes");
+        let loaded = PolicySet::from_snapshot_file(&path);
+        std::fs::remove_file(&path).expect("removes");
+        assert_eq!(loaded.expect("loads"), policy_set());
+    }
+
+    #[test]
+    fn rejects_other_snapshots() {
+        let mut bytes = Vec::new();
+        crate::entities::Entities::new()
+            .write_snapshot(&mut bytes)
+            .expect("writes");
+        assert!(!PolicySet::is_snapshot(&bytes));
+        assert!(matches!(
+            PolicySet::from_snapshot(&bytes),
+            Err(SnapshotError::NotASnapshot)
+        ));
+    }
+}
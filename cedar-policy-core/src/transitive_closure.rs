@@ -183,6 +183,101 @@ where
     Ok(())
 }
 
+/// Given a graph (as a map from keys to `TCNode`) which need not be
+/// transitively closed, enforce that it is a DAG. If it is not, return a
+/// `HasCycle` error naming some vertex on a cycle.
+///
+/// Unlike `compute_tc` this does not add any edges, and runs in time linear in
+/// the size of the graph.
+pub fn enforce_dag<K, V>(nodes: &HashMap<K, V>) -> Result<(), K>
+where
+    K: Clone + Eq + Hash + Debug + Display,
+    V: TCNode<K>,
+{
+    let out_edges = |k: &K| -> Box<dyn Iterator<Item = &K> + '_> {
+        match nodes.get(k) {
+            Some(node) => node.out_edges(),
+            None => Box::new(std::iter::empty()),
+        }
+    };
+    // nodes all of whose descendants are known not to be on a cycle
+    let mut done: HashSet<&K> = HashSet::new();
+    for start in nodes.keys() {
+        if done.contains(start) {
+            continue;
+        }
+        // iterative depth-first search, so that deep hierarchies can't
+        // overflow the stack. `on_path` holds the keys currently on `stack`.
+        let mut on_path: HashSet<&K> = HashSet::from([start]);
+        let mut stack = vec![(start, out_edges(start))];
+        while let Some((_, edges)) = stack.last_mut() {
+            match edges.next() {
+                Some(next) => {
+                    if on_path.contains(next) {
+                        return Err(TcError::HasCycle {
+                            vertex_with_loop: next.clone(),
+                        });
+                    }
+                    if !done.contains(next) {
+                        on_path.insert(next);
+                        stack.push((next, out_edges(next)));
+                    }
+                }
+                None => {
+                    if let Some((key, _)) = stack.pop() {
+                        on_path.remove(key);
+                        done.insert(key);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Given a graph (as a map from keys to `TCNode`) which need not be
+/// transitively closed, return whether there is a path of one or more edges
+/// from `node` to the node with key `target`.
+pub fn has_path_to<K, V>(nodes: &HashMap<K, V>, node: &V, target: &K) -> bool
+where
+    K: Clone + Eq + Hash + Debug + Display,
+    V: TCNode<K>,
+{
+    let mut visited: HashSet<&K> = HashSet::new();
+    let mut worklist: Vec<&K> = node.out_edges().collect();
+    while let Some(k) = worklist.pop() {
+        if k == target {
+            return true;
+        }
+        if visited.insert(k) {
+            if let Some(n) = nodes.get(k) {
+                worklist.extend(n.out_edges());
+            }
+        }
+    }
+    false
+}
+
+/// Given a graph (as a map from keys to `TCNode`) which need not be
+/// transitively closed, return the keys of all nodes reachable from `node` by
+/// a path of one or more edges.
+pub fn reachable_from<'a, K, V>(nodes: &'a HashMap<K, V>, node: &'a V) -> HashSet<&'a K>
+where
+    K: Clone + Eq + Hash + Debug + Display,
+    V: TCNode<K>,
+{
+    let mut reachable: HashSet<&K> = HashSet::new();
+    let mut worklist: Vec<&K> = node.out_edges().collect();
+    while let Some(k) = worklist.pop() {
+        if reachable.insert(k) {
+            if let Some(n) = nodes.get(k) {
+                worklist.extend(n.out_edges());
+            }
+        }
+    }
+    reachable
+}
+
 // PANIC SAFETY test cases
 #[allow(clippy::indexing_slicing)]
 #[cfg(test)]
@@ -749,4 +844,47 @@ mod tests {
             Err(_) => panic!("Unexpected error in enforce_dag_from_tc"),
         }
     }
+
+    #[test]
+    fn lazy_dag_and_reachability() {
+        // A -> B -> C, D -> B, with no transitive edges
+        let mut a = Entity::with_uid(EntityUID::with_eid("A"));
+        a.add_ancestor(EntityUID::with_eid("B"));
+        let mut b = Entity::with_uid(EntityUID::with_eid("B"));
+        b.add_ancestor(EntityUID::with_eid("C"));
+        let c = Entity::with_uid(EntityUID::with_eid("C"));
+        let mut d = Entity::with_uid(EntityUID::with_eid("D"));
+        d.add_ancestor(EntityUID::with_eid("B"));
+        let entities = HashMap::from([(a.uid(), a), (b.uid(), b), (c.uid(), c), (d.uid(), d)]);
+        assert!(enforce_dag(&entities).is_ok());
+        let a = &entities[&EntityUID::with_eid("A")];
+        assert!(has_path_to(&entities, a, &EntityUID::with_eid("C")));
+        assert!(!has_path_to(&entities, a, &EntityUID::with_eid("D")));
+        assert!(!has_path_to(&entities, a, &EntityUID::with_eid("A")));
+        assert_eq!(
+            reachable_from(&entities, a),
+            HashSet::from([&EntityUID::with_eid("B"), &EntityUID::with_eid("C")])
+        );
+    }
+
+    #[test]
+    fn lazy_dag_detects_cycles() {
+        // A -> B -> C -> A, without transitive edges
+        let mut a = Entity::with_uid(EntityUID::with_eid("A"));
+        a.add_ancestor(EntityUID::with_eid("B"));
+        let mut b = Entity::with_uid(EntityUID::with_eid("B"));
+        b.add_ancestor(EntityUID::with_eid("C"));
+        let mut c = Entity::with_uid(EntityUID::with_eid("C"));
+        c.add_ancestor(EntityUID::with_eid("A"));
+        let entities = HashMap::from([(a.uid(), a), (b.uid(), b), (c.uid(), c)]);
+        match enforce_dag(&entities) {
+            Err(TcError::HasCycle { .. }) => (),
+            res => panic!("expected a cycle error, got {res:?}"),
+        }
+        // a self-loop is also a cycle
+        let mut a = Entity::with_uid(EntityUID::with_eid("A"));
+        a.add_ancestor(EntityUID::with_eid("A"));
+        let entities = HashMap::from([(a.uid(), a)]);
+        assert!(enforce_dag(&entities).is_err());
+    }
 }
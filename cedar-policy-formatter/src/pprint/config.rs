@@ -23,6 +23,16 @@ pub struct Config {
     pub indent_width: isize,
 }
 
+impl Default for Config {
+    /// A line width of 80 and an indentation width of 2
+    fn default() -> Self {
+        Self {
+            line_width: 80,
+            indent_width: 2,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Context<'a> {
     pub config: &'a Config,
@@ -697,6 +697,42 @@ impl Doc for ASTNode<Option<Ident>> {
     }
 }
 
+impl Doc for ASTNode<Option<Definition>> {
+    fn to_doc(&self, context: &mut Context<'_>) -> Option<RcDoc<'_>> {
+        let definition = self.as_inner()?;
+        let def_doc = add_comment(
+            RcDoc::text("def"),
+            get_comment_at_start(self.info.0.start, &mut context.tokens)?,
+            RcDoc::nil(),
+        );
+        let name_doc = definition.name.to_doc(context)?;
+        let eq_doc = add_comment(
+            RcDoc::text("="),
+            get_comment_after_end(definition.name.info.0.end, &mut context.tokens)?,
+            RcDoc::nil(),
+        );
+        let expr_doc = definition.expr.to_doc(context)?;
+        Some(
+            def_doc
+                .append(RcDoc::space())
+                .append(name_doc)
+                .append(RcDoc::space())
+                .append(eq_doc)
+                .append(
+                    RcDoc::line()
+                        .append(expr_doc)
+                        .nest(context.config.indent_width),
+                )
+                .group()
+                .append(add_comment(
+                    RcDoc::text(";"),
+                    get_comment_at_end(self.info.0.end, &mut context.tokens)?,
+                    RcDoc::nil(),
+                )),
+        )
+    }
+}
+
 impl Doc for ASTNode<Option<Policy>> {
     fn to_doc(&self, context: &mut Context<'_>) -> Option<RcDoc<'_>> {
         let policy = self.as_inner()?;
@@ -93,12 +93,25 @@ pub fn policies_str_to_pretty(ps: &str, config: &Config) -> Result<String> {
         )
         .ok_or(miette!("cannot get ending comment string"))?;
     let mut context = config::Context { config, tokens };
-    let mut formatted_policies = cst
+    let policies = cst
         .as_inner()
-        .ok_or(miette!("fail to get input policy CST"))?
-        .0
+        .ok_or(miette!("fail to get input policy CST"))?;
+    // definitions are kept together, one per line, before the policies
+    let definitions = policies
+        .definitions
         .iter()
-        .map(|p| Ok(remove_empty_lines(tree_to_pretty(p, &mut context)?.trim())))
+        .map(|d| Ok(remove_empty_lines(tree_to_pretty(d, &mut context)?.trim())))
+        .collect::<Result<Vec<String>>>()?
+        .join("\n");
+    let mut formatted_policies = std::iter::once(definitions)
+        .filter(|definitions| !definitions.is_empty())
+        .map(Ok)
+        .chain(
+            policies
+                .policies
+                .iter()
+                .map(|p| Ok(remove_empty_lines(tree_to_pretty(p, &mut context)?.trim()))),
+        )
         .collect::<Result<Vec<String>>>()?
         .join("\n\n");
     // handle comment at the end of a policyset
@@ -127,6 +140,13 @@ pub fn policies_str_to_pretty(ps: &str, config: &Config) -> Result<String> {
     Ok(formatted_policies)
 }
 
+/// Format a policy set with the default [`Config`]. Formatting is canonical:
+/// any two texts of the same policies, differing only in whitespace, are
+/// formatted the same, and formatted text is left unchanged.
+pub fn format_policies(ps: &str) -> Result<String> {
+    policies_str_to_pretty(ps, &Config::default())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -193,4 +213,72 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn format_is_canonical() {
+        let policy = r#"@id("a")   @advice("long")
+permit(principal == User::"alice", action, resource) when { principal.level > 3 && context.amount < 100000000 && resource.owner == principal };"#;
+        let formatted = format_policies(policy).unwrap();
+        assert_eq!(
+            formatted,
+            r#"@id("a")
+@advice("long")
+permit (
+  principal == User::"alice",
+  action,
+  resource
+)
+when
+{
+  principal.level > 3 &&
+  context.amount < 100000000 &&
+  resource.owner == principal
+};"#
+        );
+        assert_eq!(format_policies(&formatted).unwrap(), formatted);
+        assert_eq!(
+            format_policies(&policy.replace(' ', "\n  ")).unwrap(),
+            formatted
+        );
+    }
+    #[test]
+    fn hex_literals() {
+        let policy = r#"permit (principal, action, resource)
+when { context.selector == 0xa9059cbb // transfer
+};"#;
+        assert_eq!(
+            format_policies(policy).unwrap(),
+            r#"permit (principal, action, resource)
+when
+{
+  context.selector == 0xa9059cbb // transfer
+};"#
+        );
+    }
+
+    #[test]
+    fn definitions() {
+        let policy = r#"// tokens
+def USDC   =   Token::"0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+def  LIMIT = 1000; // per day
+def STABLES = [USDC, Token::"0xdAC17F958D2ee523a2206206994597C13D831ec7"];
+permit (principal, action, resource == USDC) when { context.amount < LIMIT };"#;
+        assert_eq!(
+            policies_str_to_pretty(policy, TEST_CONFIG).unwrap(),
+            r#"// tokens
+def USDC =
+  Token::"0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+def LIMIT = 1000; // per day
+def STABLES =
+  [USDC,
+   Token::"0xdAC17F958D2ee523a2206206994597C13D831ec7"];
+
+permit (
+  principal,
+  action,
+  resource == USDC
+)
+when { context.amount < LIMIT };"#
+        );
+    }
 }
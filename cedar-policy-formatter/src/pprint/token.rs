@@ -134,12 +134,18 @@ pub enum Token {
     #[regex("[0-9]+", |lex| SmolStr::new(lex.slice()))]
     Number(SmolStr),
 
+    #[regex("0x[0-9a-fA-F]+", |lex| SmolStr::new(lex.slice()))]
+    HexNumber(SmolStr),
+
     #[regex(r#""(\\.|[^"\\])*""#, |lex| SmolStr::new(lex.slice()))]
     Str(SmolStr),
 
     #[token("@")]
     At,
 
+    #[token("=")]
+    Assign,
+
     #[token(".")]
     Dot,
 
@@ -222,6 +228,7 @@ impl fmt::Display for Token {
             Self::Action => write!(f, "action"),
             Self::Add => write!(f, "+"),
             Self::And => write!(f, "&&"),
+            Self::Assign => write!(f, "="),
             Self::At => write!(f, "@"),
             Self::Colon => write!(f, ":"),
             Self::Comma => write!(f, ","),
@@ -254,6 +261,7 @@ impl fmt::Display for Token {
             Self::Neg => write!(f, "!"),
             Self::NotEqual => write!(f, "!="),
             Self::Number(n) => write!(f, "{}", n),
+            Self::HexNumber(n) => write!(f, "{}", n),
             Self::Or => write!(f, "||"),
             Self::Permit => write!(f, "permit"),
             Self::Principal => write!(f, "principal"),
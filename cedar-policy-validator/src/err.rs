@@ -22,13 +22,19 @@ use cedar_policy_core::{
     transitive_closure,
 };
 use itertools::Itertools;
+use smol_str::SmolStr;
 use thiserror::Error;
 
+use crate::HumanSchemaParseError;
+
 #[derive(Debug, Error)]
 pub enum SchemaError {
     /// Error thrown by the `serde_json` crate during deserialization
     #[error("failed to parse schema: {0}")]
     Serde(#[from] serde_json::Error),
+    /// Error parsing a schema in the human-readable schema format
+    #[error("failed to parse schema: {0}")]
+    HumanSchemaParse(#[from] HumanSchemaParseError),
     /// Errors occurring while computing or enforcing transitive closure on
     /// action hierarchy.
     #[error("transitive closure computation/enforcement error on action hierarchy: {0}")]
@@ -63,6 +69,18 @@ pub enum SchemaError {
     /// Duplicate specification for a reusable type declaration.
     #[error("duplicate common type `{0}`")]
     DuplicateCommonType(String),
+    /// A common type is declared differently in two schemas being merged.
+    /// Argument is the name of the common type.
+    #[error("common type `{0}` is declared differently in the schemas being merged")]
+    ConflictingCommonType(String),
+    /// An entity type is declared differently in two schemas being merged.
+    /// Argument is the name of the entity type.
+    #[error("entity type `{0}` is declared differently in the schemas being merged")]
+    ConflictingEntityType(String),
+    /// An action is declared differently in two schemas being merged.
+    /// Argument is the name of the action.
+    #[error("action `{0}` is declared differently in the schemas being merged")]
+    ConflictingAction(String),
     /// Cycle in the schema's action hierarchy.
     #[error("cycle in action hierarchy")]
     CycleInActionHierarchy,
@@ -75,6 +93,10 @@ pub enum SchemaError {
     /// Parse errors occurring while parsing an extension type.
     #[error("parse error in extension type: {}", Self::format_parse_errs(.0))]
     ParseExtensionType(ParseErrors),
+    /// An extension type used in the schema is not defined by any of the
+    /// available extensions.
+    #[error("unknown extension type `{0}`")]
+    UnknownExtensionType(Name),
     /// Parse errors occurring while parsing the name of one of reusable
     /// declared types.
     #[error("parse error in common type identifier: {}", Self::format_parse_errs(.0))]
@@ -97,6 +119,11 @@ pub enum SchemaError {
     /// This error variant should only be used when `PermitAttributes` is enabled.
     #[error("action `{0}` has an attribute with unsupported JSON representation: {1}")]
     UnsupportedActionAttribute(EntityUID, String),
+    /// The default value declared for an attribute of an entity type shape or
+    /// action context is invalid: it is declared for a required attribute, or
+    /// it does not have the type of the attribute.
+    #[error("invalid default value for attribute `{1}` in {0}: {2}")]
+    InvalidAttributeDefault(ContextOrShape, SmolStr, String),
 }
 
 impl From<transitive_closure::TcError<EntityUID>> for SchemaError {
@@ -143,6 +170,9 @@ pub enum UnsupportedFeature {
     OpenRecordsAndEntities,
     // Action attributes are allowed if `ActionBehavior` is `PermitAttributes`
     ActionAttributes(Vec<String>),
+    // Only the attributes declared directly in an entity type shape or action
+    // context may have default values
+    NestedAttributeDefault(SmolStr),
 }
 
 impl std::fmt::Display for UnsupportedFeature {
@@ -157,6 +187,10 @@ impl std::fmt::Display for UnsupportedFeature {
                 "action declared with attributes: [{}]",
                 attrs.iter().join(", ")
             ),
+            Self::NestedAttributeDefault(attr) => write!(
+                f,
+                "default value for attribute `{attr}`, which is not declared directly in an entity type shape or action context"
+            ),
         }
     }
 }
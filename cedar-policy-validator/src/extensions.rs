@@ -30,6 +30,21 @@ pub mod partial_evaluation;
 #[cfg(feature = "u256")]
 pub mod u256;
 
+#[cfg(feature = "bytes")]
+pub mod bytes;
+
+#[cfg(feature = "address")]
+pub mod address;
+
+#[cfg(feature = "regex")]
+pub mod regex;
+
+#[cfg(feature = "quota")]
+pub mod quota;
+
+#[cfg(feature = "simulation")]
+pub mod simulation;
+
 /// Get schemas for all the available extensions.
 pub fn all_available_extension_schemas() -> Vec<ExtensionSchema> {
     vec![
@@ -40,5 +55,15 @@ pub fn all_available_extension_schemas() -> Vec<ExtensionSchema> {
         partial_evaluation::extension_schema(),
         #[cfg(feature = "u256")]
         u256::extension_schema(),
+        #[cfg(feature = "bytes")]
+        bytes::extension_schema(),
+        #[cfg(feature = "address")]
+        address::extension_schema(),
+        #[cfg(feature = "regex")]
+        regex::extension_schema(),
+        #[cfg(feature = "quota")]
+        quota::extension_schema(),
+        #[cfg(feature = "simulation")]
+        simulation::extension_schema(),
     ]
 }
@@ -0,0 +1,83 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::extension_schema::{ExtensionFunctionType, ExtensionSchema};
+use crate::types::{self, OpenTag, Type};
+use cedar_policy_core::ast::Name;
+use cedar_policy_core::extensions::simulation;
+
+// If any of the panics in this file are triggered, that means that this file has become
+// out-of-date with the simulation extension definition in CedarCore.
+
+/// The type of the extension values named `name`
+// PANIC SAFETY: the names of the `address`, `bytes` and `u256` types are valid
+#[allow(clippy::expect_used)]
+fn extension_type(name: &str) -> Type {
+    Type::extension(Name::parse_unqualified_name(name).expect("should be a valid identifier"))
+}
+
+fn get_argument_types(fname: &str) -> Vec<types::Type> {
+    match fname {
+        "netBalanceChange" => vec![Type::any_record(), extension_type("address")],
+        "receivedAtLeast" => vec![
+            Type::any_record(),
+            extension_type("address"),
+            extension_type("u256"),
+        ],
+        "emittedEvent" => vec![
+            Type::any_record(),
+            extension_type("address"),
+            extension_type("bytes"),
+        ],
+        _ => panic!("unexpected simulation extension function name: {fname}"),
+    }
+}
+
+fn get_return_type(fname: &str) -> Type {
+    match fname {
+        "netBalanceChange" => Type::record_with_required_attributes(
+            [simulation::GAIN, simulation::LOSS].map(|attr| (attr.into(), extension_type("u256"))),
+            OpenTag::ClosedAttributes,
+        ),
+        "receivedAtLeast" | "emittedEvent" => Type::primitive_boolean(),
+        _ => panic!("unexpected simulation extension function name: {fname}"),
+    }
+}
+
+/// Construct the extension schema
+pub fn extension_schema() -> ExtensionSchema {
+    let simulation_ext = simulation::extension();
+
+    let fun_tys: Vec<ExtensionFunctionType> = simulation_ext
+        .funcs()
+        .map(|f| {
+            let fname = f.name();
+            let fstring = fname.to_string();
+            let return_type = get_return_type(&fstring);
+            debug_assert!(f
+                .return_type()
+                .map(|ty| return_type.is_consistent_with(ty))
+                .unwrap_or_else(|| return_type == Type::Never));
+            ExtensionFunctionType::new(
+                fname.clone(),
+                get_argument_types(&fstring),
+                return_type,
+                None,
+            )
+        })
+        .collect();
+    ExtensionSchema::new(simulation_ext.name().clone(), fun_tys)
+}
@@ -27,16 +27,20 @@ use std::str::FromStr;
 fn get_argument_types(fname: &str, u256_ty: &Type) -> Vec<types::Type> {
     match fname {
         "u256" => vec![Type::primitive_string()],
-        "u256LessThan" | "u256LessThanOrEqual" | "u256GreaterThan" | "u256GreaterThanOrEqual" => {
-            vec![u256_ty.clone(), u256_ty.clone()]
-        }
+        "u256LessThan"
+        | "u256LessThanOrEqual"
+        | "u256GreaterThan"
+        | "u256GreaterThanOrEqual"
+        | "u256Add"
+        | "u256Sub"
+        | "u256Mul" => vec![u256_ty.clone(), u256_ty.clone()],
         _ => panic!("unexpected u256 extension function name: {fname}"),
     }
 }
 
 fn get_return_type(fname: &str, u256_ty: &Type) -> Type {
     match fname {
-        "u256" => u256_ty.clone(),
+        "u256" | "u256Add" | "u256Sub" | "u256Mul" => u256_ty.clone(),
         "u256LessThan" | "u256LessThanOrEqual" | "u256GreaterThan" | "u256GreaterThanOrEqual" => {
             Type::primitive_boolean()
         }
@@ -47,7 +51,13 @@ fn get_return_type(fname: &str, u256_ty: &Type) -> Type {
 fn get_argument_check(fname: &str) -> Option<ArgumentCheckFn> {
     match fname {
         "u256" => Some(Box::new(validate_u256_string)),
-        "u256LessThan" | "u256LessThanOrEqual" | "u256GreaterThan" | "u256GreaterThanOrEqual" => None,
+        "u256LessThan"
+        | "u256LessThanOrEqual"
+        | "u256GreaterThan"
+        | "u256GreaterThanOrEqual"
+        | "u256Add"
+        | "u256Sub"
+        | "u256Mul" => None,
         _ => panic!("unexpected u256 extension function name: {fname}"),
     }
 }
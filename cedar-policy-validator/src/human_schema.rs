@@ -0,0 +1,545 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The human-readable schema format, an alternative to JSON for writing
+//! schema fragments. For example,
+//!
+//! ```text
+//! namespace Vault {
+//!   type Owner = { wallet: address };
+//!   entity Account in [Group] = { balance: u256, owner?: Owner } tags String;
+//!   entity Group;
+//!   action "withdraw" in ["write"] appliesTo {
+//!     principal: [Account],
+//!     resource: Account,
+//!     context: { amount: u256 }
+//!   };
+//!   action "write";
+//! }
+//! ```
+//!
+//! Type names are resolved as follows: `Long`, `String` and `Bool` are the
+//! primitive types, the names of extension types (e.g., `ipaddr` or `u256`)
+//! are extension types, names declared with `type` are common types, and any
+//! other name is an entity type.
+
+use cedar_policy_core::extensions::Extensions;
+use smol_str::SmolStr;
+use std::collections::HashSet;
+use thiserror::Error;
+
+use crate::{Result, SchemaFragment, SchemaType, SchemaTypeVariant, ValidatorSchema};
+
+mod fmt;
+mod lexer;
+mod parser;
+
+/// Error parsing a schema in the human-readable schema format
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{message} at line {line}, column {column}")]
+pub struct HumanSchemaParseError {
+    message: String,
+    line: usize,
+    column: usize,
+}
+
+impl HumanSchemaParseError {
+    /// Construct an error for the given byte offset into `src`
+    fn new(src: &str, offset: usize, message: impl Into<String>) -> Self {
+        let before = src.get(..offset).unwrap_or(src);
+        let line = before.matches('\n').count() + 1;
+        let column = before
+            .rsplit('\n')
+            .next()
+            .map_or(0, |line| line.chars().count())
+            + 1;
+        Self {
+            message: message.into(),
+            line,
+            column,
+        }
+    }
+
+    /// Description of the error, without its location
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Line (starting at 1) at which the error occurred
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Column (starting at 1) at which the error occurred
+    pub fn column(&self) -> usize {
+        self.column
+    }
+}
+
+/// Error writing a schema fragment in the human-readable schema format. Some
+/// features of the JSON format have no counterpart in the human-readable
+/// format.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ToHumanSchemaStrError {
+    /// A record or entity type declares `additionalAttributes`
+    #[error(
+        "records with `additionalAttributes` cannot be written in the human-readable schema format"
+    )]
+    OpenRecord,
+    /// An action declares attributes
+    #[error(
+        "action `{0}` has attributes, which cannot be written in the human-readable schema format"
+    )]
+    ActionAttributes(SmolStr),
+    /// A type name would be read back as a different kind of type, e.g., an
+    /// entity type with the same name as a common type or an extension type
+    #[error("type name `{0}` is ambiguous in the human-readable schema format")]
+    AmbiguousTypeName(SmolStr),
+    /// A namespace, entity type, or common type name is not a valid
+    /// identifier, or `::`-separated path of identifiers
+    #[error("`{0}` is not a valid name")]
+    InvalidName(SmolStr),
+    /// An attribute declares a default value
+    #[error(
+        "attribute `{0}` has a default value, which cannot be written in the human-readable schema format"
+    )]
+    AttributeDefault(SmolStr),
+}
+
+impl SchemaFragment {
+    /// Parse a `SchemaFragment` from a string in the human-readable schema
+    /// format. Like [`SchemaFragment::from_json_value`], this does not check
+    /// that the types it references are declared.
+    pub fn from_str_natural(src: &str) -> Result<Self> {
+        Ok(parser::parse_schema_fragment(src)?)
+    }
+
+    /// Write this `SchemaFragment` in the human-readable schema format.
+    /// Namespaces, and the declarations in each namespace, are written in
+    /// alphabetical order.
+    pub fn as_natural_schema(&self) -> std::result::Result<String, ToHumanSchemaStrError> {
+        fmt::natural_schema(self)
+    }
+}
+
+impl ValidatorSchema {
+    /// Construct a `ValidatorSchema` from a string in the human-readable
+    /// schema format.
+    pub fn from_str_natural(src: &str) -> Result<Self> {
+        SchemaFragment::from_str_natural(src)?.try_into()
+    }
+}
+
+/// Names of the primitive types. Note that `Bool` is spelled `Boolean` in the
+/// JSON format.
+const LONG: &str = "Long";
+const STRING: &str = "String";
+const BOOL: &str = "Bool";
+
+/// Is `name` reserved for a primitive or extension type, so that it cannot be
+/// used as the name of a common type?
+fn is_builtin_type_name(name: &str) -> bool {
+    [LONG, STRING, BOOL].contains(&name)
+        || Extensions::all_available()
+            .ext_types()
+            .any(|ty| ty.to_string() == name)
+}
+
+/// The fully qualified name of a declaration named `name` in `namespace`, as
+/// used to look up common types
+fn qualify(namespace: &str, name: &str) -> SmolStr {
+    if namespace.is_empty() || name.contains("::") {
+        name.into()
+    } else {
+        format!("{namespace}::{name}").into()
+    }
+}
+
+/// The type denoted by the type name `name` in `namespace`, where
+/// `common_types` contains the fully qualified names of all common types
+/// declared in the schema fragment. The parser uses this to resolve type
+/// names, and the printer uses it to check that names it writes will be read
+/// back as the same type.
+fn resolve_type_name(name: &str, namespace: &str, common_types: &HashSet<SmolStr>) -> SchemaType {
+    match name {
+        LONG => SchemaTypeVariant::Long.into(),
+        STRING => SchemaTypeVariant::String.into(),
+        BOOL => SchemaTypeVariant::Boolean.into(),
+        _ if is_builtin_type_name(name) => {
+            SchemaTypeVariant::Extension { name: name.into() }.into()
+        }
+        _ if common_types.contains(&qualify(namespace, name)) => SchemaType::TypeDef {
+            type_name: name.into(),
+        },
+        _ => SchemaTypeVariant::Entity { name: name.into() }.into(),
+    }
+}
+
+/// Is `s` a valid identifier in the human-readable schema format?
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ActionEntityUID, NamespaceDefinition};
+    use serde_json::json;
+
+    #[test]
+    #[cfg(all(feature = "u256", feature = "address"))]
+    fn parse() {
+        let src = r#"
+            // types for the vault contract
+            namespace Vault {
+                type Owner = { wallet: address, "display name"?: String };
+                entity Account in [Group] = {
+                    balance: u256,
+                    owner?: Owner,
+                    flags: Set<Bool>,
+                    admin: Account,
+                } tags String;
+                entity Group;
+                entity Team in Group;
+                action "withdraw", deposit in ["write", Other::Action::"root"] appliesTo {
+                    principal: [Account, Team],
+                    resource: Account,
+                    context: { amount: u256 }
+                };
+                action "write";
+            }
+            entity Global;
+        "#;
+        let fragment = SchemaFragment::from_str_natural(src).expect("should parse");
+        let expected = SchemaFragment::from_json_value(json!({
+            "Vault": {
+                "commonTypes": {
+                    "Owner": {
+                        "type": "Record",
+                        "attributes": {
+                            "wallet": { "type": "Extension", "name": "address" },
+                            "display name": { "type": "String", "required": false }
+                        }
+                    }
+                },
+                "entityTypes": {
+                    "Account": {
+                        "memberOfTypes": ["Group"],
+                        "shape": {
+                            "type": "Record",
+                            "attributes": {
+                                "balance": { "type": "Extension", "name": "u256" },
+                                "owner": { "type": "Owner", "required": false },
+                                "flags": { "type": "Set", "element": { "type": "Boolean" } },
+                                "admin": { "type": "Entity", "name": "Account" }
+                            }
+                        },
+                        "tags": { "type": "String" }
+                    },
+                    "Group": {},
+                    "Team": { "memberOfTypes": ["Group"] }
+                },
+                "actions": {
+                    "withdraw": {
+                        "memberOf": [{ "id": "write" }, { "id": "root", "type": "Other::Action" }],
+                        "appliesTo": {
+                            "principalTypes": ["Account", "Team"],
+                            "resourceTypes": ["Account"],
+                            "context": {
+                                "type": "Record",
+                                "attributes": {
+                                    "amount": { "type": "Extension", "name": "u256" }
+                                }
+                            }
+                        }
+                    },
+                    "deposit": {
+                        "memberOf": [{ "id": "write" }, { "id": "root", "type": "Other::Action" }],
+                        "appliesTo": {
+                            "principalTypes": ["Account", "Team"],
+                            "resourceTypes": ["Account"],
+                            "context": {
+                                "type": "Record",
+                                "attributes": {
+                                    "amount": { "type": "Extension", "name": "u256" }
+                                }
+                            }
+                        }
+                    },
+                    "write": {}
+                }
+            },
+            "": {
+                "entityTypes": { "Global": {} },
+                "actions": {}
+            }
+        }))
+        .expect("should be a valid schema fragment");
+        assert_eq!(fragment.0, expected.0);
+        assert_eq!(
+            fragment.0["Vault"].actions["withdraw"].member_of,
+            Some(vec![
+                ActionEntityUID::default_type("write".into()),
+                ActionEntityUID {
+                    id: "root".into(),
+                    ty: Some("Other::Action".into())
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |src: &str| match SchemaFragment::from_str_natural(src) {
+            Err(crate::SchemaError::HumanSchemaParse(e)) => e,
+            r => panic!("expected a parse error, got {r:?}"),
+        };
+
+        let e = err("entity User\nentity Group;");
+        assert_eq!(e.message(), "expected `;`, found identifier `entity`");
+        assert_eq!((e.line(), e.column()), (2, 1));
+
+        let e = err("entity User = { name: String, name: Long };");
+        assert_eq!(e.message(), "duplicate attribute `name`");
+        assert_eq!((e.line(), e.column()), (1, 31));
+
+        let e = err("entity User; entity User;");
+        assert_eq!(e.message(), "duplicate entity type `User`");
+
+        let e = err(r#"action "view"; action view;"#);
+        assert_eq!(e.message(), "duplicate action `view`");
+
+        let e = err("namespace A { } namespace A { }");
+        assert_eq!(e.message(), "duplicate namespace `A`");
+
+        let e = err("type Long = String;");
+        assert_eq!(
+            e.message(),
+            "common type `Long` has the name of a builtin type"
+        );
+
+        let e = err(r#"entity User = { name: "String" };"#);
+        assert_eq!(e.message(), "expected a type, found string `\"String\"`");
+
+        let e = err("entity User = { name: String ");
+        assert_eq!(e.message(), "unexpected end of input");
+
+        let e = err(r#"action view in [A::B];"#);
+        assert_eq!(e.message(), "expected an action name string, found `]`");
+
+        let e = err("entity User # comment");
+        assert_eq!(e.message(), "unexpected character `#`");
+
+        let e = err("action view appliesTo { principal: [User], principal: [User] };");
+        assert_eq!(e.message(), "duplicate `principal` in `appliesTo`");
+    }
+
+    #[test]
+    fn validator_schema() {
+        let schema = ValidatorSchema::from_str_natural(
+            r#"
+            entity User in [Group];
+            entity Group;
+            entity Photo = { owner: User };
+            action "view" appliesTo { principal: User, resource: Photo };
+            "#,
+        )
+        .expect("should be a valid schema");
+        assert_eq!(schema.entity_types().count(), 3);
+
+        assert!(matches!(
+            ValidatorSchema::from_str_natural("entity User in [Group];"),
+            Err(crate::SchemaError::UndeclaredEntityTypes(_))
+        ));
+    }
+
+    #[test]
+    fn print() {
+        let fragment = SchemaFragment::from_json_value(json!({
+            "": {
+                "commonTypes": {
+                    "Name": { "type": "String" }
+                },
+                "entityTypes": {
+                    "User": {
+                        "memberOfTypes": ["Group"],
+                        "shape": {
+                            "type": "Record",
+                            "attributes": {
+                                "name": { "type": "Name" },
+                                "age": { "type": "Long", "required": false },
+                                "home address": {
+                                    "type": "Record",
+                                    "attributes": {
+                                        "street": { "type": "String" }
+                                    }
+                                },
+                                "groups": { "type": "Set", "element": { "type": "Entity", "name": "Group" } }
+                            }
+                        }
+                    },
+                    "Group": {
+                        "tags": { "type": "Boolean" }
+                    }
+                },
+                "actions": {
+                    "view": {
+                        "memberOf": [{ "id": "read" }],
+                        "appliesTo": {
+                            "principalTypes": ["User"],
+                            "resourceTypes": ["Group"],
+                            "context": {
+                                "type": "Record",
+                                "attributes": {
+                                    "authenticated": { "type": "Boolean" }
+                                }
+                            }
+                        }
+                    },
+                    "read": {
+                        "appliesTo": {
+                            "principalTypes": [],
+                            "resourceTypes": ["Group"]
+                        }
+                    }
+                }
+            },
+            "Other": {
+                "entityTypes": {
+                    "Thing": {}
+                },
+                "actions": {
+                    "touch": { "memberOf": [{ "id": "view", "type": "Action" }] }
+                }
+            }
+        }))
+        .expect("should be a valid schema fragment");
+        let printed = fragment.as_natural_schema().expect("should be expressible");
+        assert_eq!(
+            printed,
+            r#"type Name = String;
+
+entity Group tags Bool;
+entity User in [Group] = {
+  age?: Long,
+  groups: Set<Group>,
+  "home address": {
+    street: String
+  },
+  name: Name
+};
+
+action "read" appliesTo {
+  principal: [],
+  resource: [Group]
+};
+action "view" in ["read"] appliesTo {
+  principal: [User],
+  resource: [Group],
+  context: {
+    authenticated: Bool
+  }
+};
+
+namespace Other {
+  entity Thing;
+
+  action "touch" in [Action::"view"];
+}
+"#
+        );
+
+        // printing is the inverse of parsing
+        let reparsed = SchemaFragment::from_str_natural(&printed).expect("should parse");
+        assert_eq!(reparsed.0, fragment.0);
+    }
+
+    #[test]
+    fn print_errors() {
+        let print = |json: serde_json::Value| {
+            SchemaFragment::from_json_value(json)
+                .expect("should be a valid schema fragment")
+                .as_natural_schema()
+        };
+        assert_eq!(
+            print(json!({"": {
+                "entityTypes": {
+                    "User": { "shape": { "type": "Record", "attributes": {}, "additionalAttributes": true } }
+                },
+                "actions": {}
+            }})),
+            Err(ToHumanSchemaStrError::OpenRecord)
+        );
+        assert_eq!(
+            print(json!({"": {
+                "entityTypes": {},
+                "actions": { "view": { "attributes": { "level": 1 } } }
+            }})),
+            Err(ToHumanSchemaStrError::ActionAttributes("view".into()))
+        );
+        assert_eq!(
+            print(json!({"": {
+                "commonTypes": { "User": { "type": "String" } },
+                "entityTypes": {
+                    "User": {},
+                    "Group": { "shape": { "type": "Record", "attributes": {
+                        "owner": { "type": "Entity", "name": "User" }
+                    } } }
+                },
+                "actions": {}
+            }})),
+            Err(ToHumanSchemaStrError::AmbiguousTypeName("User".into()))
+        );
+        assert_eq!(
+            print(json!({"": {
+                "entityTypes": { "my user": {} },
+                "actions": {}
+            }})),
+            Err(ToHumanSchemaStrError::InvalidName("my user".into()))
+        );
+        assert_eq!(
+            print(json!({"": {
+                "entityTypes": {
+                    "User": { "shape": { "type": "Record", "attributes": {
+                        "level": { "type": "Long", "required": false, "default": 0 }
+                    } } }
+                },
+                "actions": {}
+            }})),
+            Err(ToHumanSchemaStrError::AttributeDefault("level".into()))
+        );
+    }
+
+    #[test]
+    fn empty() {
+        let fragment =
+            SchemaFragment::from_str_natural("  // nothing here\n").expect("should parse");
+        assert!(fragment.0.is_empty());
+        let fragment = SchemaFragment::from_str_natural("namespace A {}").expect("should parse");
+        assert_eq!(
+            fragment.0.get("A"),
+            Some(&NamespaceDefinition::new(Vec::new(), Vec::new()))
+        );
+        assert_eq!(
+            fragment.as_natural_schema().expect("should be expressible"),
+            "namespace A {\n}\n"
+        );
+    }
+}
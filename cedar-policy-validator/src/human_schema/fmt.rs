@@ -0,0 +1,279 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Printer for the human-readable schema format.
+
+use itertools::Itertools;
+use smol_str::SmolStr;
+use std::collections::HashSet;
+
+use super::{is_ident, qualify, resolve_type_name, ToHumanSchemaStrError, BOOL, LONG, STRING};
+use crate::{ActionEntityUID, NamespaceDefinition, SchemaFragment, SchemaType, SchemaTypeVariant};
+
+type Result<T> = std::result::Result<T, ToHumanSchemaStrError>;
+
+/// Indentation for each level of nesting
+const INDENT: &str = "  ";
+
+pub(super) fn natural_schema(fragment: &SchemaFragment) -> Result<String> {
+    let common_types = fragment
+        .0
+        .iter()
+        .flat_map(|(namespace, definition)| {
+            definition
+                .common_types
+                .keys()
+                .map(|name| qualify(namespace, name))
+        })
+        .collect::<HashSet<_>>();
+
+    let mut out = String::new();
+    for (namespace, definition) in fragment.0.iter().sorted_by_key(|(namespace, _)| *namespace) {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        let printer = Printer {
+            namespace,
+            common_types: &common_types,
+        };
+        if namespace.is_empty() {
+            printer.namespace_body(definition, "", &mut out)?;
+        } else {
+            out.push_str(&format!("namespace {} {{\n", printer.path(namespace)?));
+            printer.namespace_body(definition, INDENT, &mut out)?;
+            out.push_str("}\n");
+        }
+    }
+    Ok(out)
+}
+
+struct Printer<'a> {
+    namespace: &'a str,
+    common_types: &'a HashSet<SmolStr>,
+}
+
+impl<'a> Printer<'a> {
+    /// Write the declarations of a namespace, with each line starting with
+    /// `indent`. Common types, entity types and actions are separated by a
+    /// blank line.
+    fn namespace_body(
+        &self,
+        definition: &NamespaceDefinition,
+        indent: &str,
+        out: &mut String,
+    ) -> Result<()> {
+        let mut sections = Vec::new();
+
+        let mut section = String::new();
+        for (name, ty) in definition
+            .common_types
+            .iter()
+            .sorted_by_key(|(name, _)| *name)
+        {
+            section.push_str(&format!(
+                "{indent}type {} = {};\n",
+                self.path(name)?,
+                self.ty(ty, indent)?
+            ));
+        }
+        sections.push(section);
+
+        let mut section = String::new();
+        for (name, entity_type) in definition
+            .entity_types
+            .iter()
+            .sorted_by_key(|(name, _)| *name)
+        {
+            section.push_str(&format!("{indent}entity {}", self.path(name)?));
+            if !entity_type.member_of_types.is_empty() {
+                section.push_str(&format!(
+                    " in {}",
+                    self.entity_types(&entity_type.member_of_types)?
+                ));
+            }
+            if !is_empty_record(&entity_type.shape.0) {
+                section.push_str(&format!(" = {}", self.ty(&entity_type.shape.0, indent)?));
+            }
+            if let Some(tags) = &entity_type.tags {
+                section.push_str(&format!(" tags {}", self.ty(tags, indent)?));
+            }
+            section.push_str(";\n");
+        }
+        sections.push(section);
+
+        let mut section = String::new();
+        for (name, action) in definition.actions.iter().sorted_by_key(|(name, _)| *name) {
+            if action.attributes.is_some() {
+                return Err(ToHumanSchemaStrError::ActionAttributes(name.clone()));
+            }
+            section.push_str(&format!("{indent}action {}", quote(name)));
+            if let Some(member_of) = &action.member_of {
+                section.push_str(&format!(
+                    " in [{}]",
+                    member_of.iter().map(action_ref).join(", ")
+                ));
+            }
+            if let Some(applies_to) = &action.applies_to {
+                let inner = format!("{indent}{INDENT}");
+                let mut fields = Vec::new();
+                if let Some(principals) = &applies_to.principal_types {
+                    fields.push(format!(
+                        "{inner}principal: {}",
+                        self.entity_types(principals)?
+                    ));
+                }
+                if let Some(resources) = &applies_to.resource_types {
+                    fields.push(format!(
+                        "{inner}resource: {}",
+                        self.entity_types(resources)?
+                    ));
+                }
+                if !is_empty_record(&applies_to.context.0) {
+                    fields.push(format!(
+                        "{inner}context: {}",
+                        self.ty(&applies_to.context.0, &inner)?
+                    ));
+                }
+                if fields.is_empty() {
+                    section.push_str(" appliesTo {}");
+                } else {
+                    section.push_str(&format!(
+                        " appliesTo {{\n{}\n{indent}}}",
+                        fields.join(",\n")
+                    ));
+                }
+            }
+            section.push_str(";\n");
+        }
+        sections.push(section);
+
+        out.push_str(&sections.into_iter().filter(|s| !s.is_empty()).join("\n"));
+        Ok(())
+    }
+
+    /// A list of entity types, in brackets
+    fn entity_types(&self, names: &[SmolStr]) -> Result<String> {
+        Ok(format!(
+            "[{}]",
+            names
+                .iter()
+                .map(|name| self.path(name))
+                .collect::<Result<Vec<_>>>()?
+                .join(", ")
+        ))
+    }
+
+    /// A `::`-separated path of identifiers
+    fn path<'b>(&self, name: &'b str) -> Result<&'b str> {
+        if name.split("::").all(is_ident) {
+            Ok(name)
+        } else {
+            Err(ToHumanSchemaStrError::InvalidName(name.into()))
+        }
+    }
+
+    /// A type name, which must be read back as the type `ty`
+    fn type_name(&self, name: &str, ty: &SchemaType) -> Result<String> {
+        let name = self.path(name)?;
+        if &resolve_type_name(name, self.namespace, self.common_types) == ty {
+            Ok(name.to_string())
+        } else {
+            Err(ToHumanSchemaStrError::AmbiguousTypeName(name.into()))
+        }
+    }
+
+    /// A type, where `indent` is the indentation of the line on which it
+    /// starts
+    fn ty(&self, ty: &SchemaType, indent: &str) -> Result<String> {
+        match ty {
+            SchemaType::Type(SchemaTypeVariant::Long) => Ok(LONG.into()),
+            SchemaType::Type(SchemaTypeVariant::String) => Ok(STRING.into()),
+            SchemaType::Type(SchemaTypeVariant::Boolean) => Ok(BOOL.into()),
+            SchemaType::Type(SchemaTypeVariant::Set { element }) => {
+                Ok(format!("Set<{}>", self.ty(element, indent)?))
+            }
+            SchemaType::Type(SchemaTypeVariant::Record {
+                attributes,
+                additional_attributes,
+            }) => {
+                if *additional_attributes {
+                    return Err(ToHumanSchemaStrError::OpenRecord);
+                }
+                if attributes.is_empty() {
+                    return Ok("{}".into());
+                }
+                let inner = format!("{indent}{INDENT}");
+                let attrs = attributes
+                    .iter()
+                    .map(|(name, attr)| {
+                        if attr.default.is_some() {
+                            return Err(ToHumanSchemaStrError::AttributeDefault(name.clone()));
+                        }
+                        Ok(format!(
+                            "{inner}{}{}: {}",
+                            if is_ident(name) {
+                                name.to_string()
+                            } else {
+                                quote(name)
+                            },
+                            if attr.required { "" } else { "?" },
+                            self.ty(&attr.ty, &inner)?
+                        ))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(format!("{{\n{}\n{indent}}}", attrs.join(",\n")))
+            }
+            SchemaType::Type(SchemaTypeVariant::Entity { name })
+            | SchemaType::Type(SchemaTypeVariant::Extension { name })
+            | SchemaType::TypeDef { type_name: name } => self.type_name(name, ty),
+        }
+    }
+}
+
+fn is_empty_record(ty: &SchemaType) -> bool {
+    matches!(
+        ty,
+        SchemaType::Type(SchemaTypeVariant::Record {
+            attributes,
+            additional_attributes: false,
+        }) if attributes.is_empty()
+    )
+}
+
+fn action_ref(uid: &ActionEntityUID) -> String {
+    match &uid.ty {
+        Some(ty) => format!("{ty}::{}", quote(&uid.id)),
+        None => quote(&uid.id),
+    }
+}
+
+/// A string literal for `s`, using the escapes understood by the lexer
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '\0' => quoted.push_str("\\0"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
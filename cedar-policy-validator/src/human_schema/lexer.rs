@@ -0,0 +1,201 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Lexer for the human-readable schema format.
+
+use smol_str::SmolStr;
+
+use super::HumanSchemaParseError;
+
+/// A token of the human-readable schema format. Keywords such as `entity`
+/// are lexed as identifiers, since they may also be used as attribute names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Token {
+    Ident(SmolStr),
+    Str(SmolStr),
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    LAngle,
+    RAngle,
+    Comma,
+    Semi,
+    Colon,
+    ColonColon,
+    Question,
+    Eq,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Ident(id) => write!(f, "identifier `{id}`"),
+            Token::Str(s) => write!(f, "string `{s:?}`"),
+            Token::LBrace => write!(f, "`{{`"),
+            Token::RBrace => write!(f, "`}}`"),
+            Token::LBracket => write!(f, "`[`"),
+            Token::RBracket => write!(f, "`]`"),
+            Token::LAngle => write!(f, "`<`"),
+            Token::RAngle => write!(f, "`>`"),
+            Token::Comma => write!(f, "`,`"),
+            Token::Semi => write!(f, "`;`"),
+            Token::Colon => write!(f, "`:`"),
+            Token::ColonColon => write!(f, "`::`"),
+            Token::Question => write!(f, "`?`"),
+            Token::Eq => write!(f, "`=`"),
+        }
+    }
+}
+
+/// Split `src` into tokens, each paired with its byte offset in `src`.
+/// Whitespace and `//` comments are skipped.
+pub(super) fn tokenize(src: &str) -> Result<Vec<(Token, usize)>, HumanSchemaParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = src.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            _ if c.is_whitespace() => continue,
+            '/' if matches!(chars.peek(), Some((_, '/'))) => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+                continue;
+            }
+            '{' => Token::LBrace,
+            '}' => Token::RBrace,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            '<' => Token::LAngle,
+            '>' => Token::RAngle,
+            ',' => Token::Comma,
+            ';' => Token::Semi,
+            '?' => Token::Question,
+            '=' => Token::Eq,
+            ':' => {
+                if matches!(chars.peek(), Some((_, ':'))) {
+                    chars.next();
+                    Token::ColonColon
+                } else {
+                    Token::Colon
+                }
+            }
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((offset, '\\')) => match chars.next() {
+                            Some((_, '"')) => s.push('"'),
+                            Some((_, '\'')) => s.push('\''),
+                            Some((_, '\\')) => s.push('\\'),
+                            Some((_, 'n')) => s.push('\n'),
+                            Some((_, 'r')) => s.push('\r'),
+                            Some((_, 't')) => s.push('\t'),
+                            Some((_, '0')) => s.push('\0'),
+                            _ => {
+                                return Err(HumanSchemaParseError::new(
+                                    src,
+                                    offset,
+                                    "invalid escape sequence",
+                                ))
+                            }
+                        },
+                        Some((_, c)) => s.push(c),
+                        None => {
+                            return Err(HumanSchemaParseError::new(
+                                src,
+                                start,
+                                "unterminated string",
+                            ))
+                        }
+                    }
+                }
+                Token::Str(s.into())
+            }
+            _ if c.is_ascii_alphabetic() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some((offset, c)) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || *c == '_' {
+                        end = offset + c.len_utf8();
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                Token::Ident(src.get(start..end).unwrap_or_default().into())
+            }
+            _ => {
+                return Err(HumanSchemaParseError::new(
+                    src,
+                    start,
+                    format!("unexpected character `{c}`"),
+                ))
+            }
+        };
+        tokens.push((token, start));
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tokens() {
+        let tokens = tokenize("entity A::B // comment\n{ \"a\\\"b\": Set<Long>?,;= }[]")
+            .expect("should lex")
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Ident("entity".into()),
+                Token::Ident("A".into()),
+                Token::ColonColon,
+                Token::Ident("B".into()),
+                Token::LBrace,
+                Token::Str("a\"b".into()),
+                Token::Colon,
+                Token::Ident("Set".into()),
+                Token::LAngle,
+                Token::Ident("Long".into()),
+                Token::RAngle,
+                Token::Question,
+                Token::Comma,
+                Token::Semi,
+                Token::Eq,
+                Token::RBrace,
+                Token::LBracket,
+                Token::RBracket,
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let e = tokenize("entity \"abc").expect_err("should fail");
+        assert_eq!(e.message(), "unterminated string");
+        assert_eq!((e.line(), e.column()), (1, 8));
+        let e = tokenize("\n  \"a\\qb\"").expect_err("should fail");
+        assert_eq!(e.message(), "invalid escape sequence");
+        assert_eq!((e.line(), e.column()), (2, 5));
+    }
+}
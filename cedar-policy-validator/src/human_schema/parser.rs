@@ -0,0 +1,572 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Parser for the human-readable schema format.
+//!
+//! Parsing happens in two passes: the first builds a syntax tree in which
+//! type names are unresolved, and the second resolves type names, once all
+//! common type declarations are known, to build the `SchemaFragment`.
+
+use smol_str::SmolStr;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::lexer::{tokenize, Token};
+use super::{is_builtin_type_name, qualify, resolve_type_name, HumanSchemaParseError};
+use crate::{
+    ActionEntityUID, ActionType, ApplySpec, AttributesOrContext, EntityType, NamespaceDefinition,
+    SchemaFragment, SchemaType, SchemaTypeVariant, TypeOfAttribute,
+};
+
+type Result<T> = std::result::Result<T, HumanSchemaParseError>;
+
+/// A name paired with the byte offset at which it occurs
+type Located<T> = (T, usize);
+
+/// A type, with type names not yet resolved
+enum TypeExpr {
+    Set(Box<TypeExpr>),
+    Record(Vec<AttrDecl>),
+    Name(SmolStr),
+}
+
+struct AttrDecl {
+    name: SmolStr,
+    required: bool,
+    ty: TypeExpr,
+}
+
+enum Decl {
+    Entity {
+        names: Vec<Located<SmolStr>>,
+        member_of: Vec<SmolStr>,
+        shape: Option<TypeExpr>,
+        tags: Option<TypeExpr>,
+    },
+    Action {
+        names: Vec<Located<SmolStr>>,
+        member_of: Option<Vec<ActionEntityUID>>,
+        applies_to: Option<AppliesTo>,
+    },
+    CommonType {
+        name: Located<SmolStr>,
+        ty: TypeExpr,
+    },
+}
+
+struct AppliesTo {
+    principal: Option<Vec<SmolStr>>,
+    resource: Option<Vec<SmolStr>>,
+    context: Option<TypeExpr>,
+}
+
+/// Parse `src` into a `SchemaFragment`
+pub(super) fn parse_schema_fragment(src: &str) -> Result<SchemaFragment> {
+    let tokens = tokenize(src)?;
+    let mut parser = Parser {
+        src,
+        tokens,
+        pos: 0,
+    };
+    let namespaces = parser.parse_namespaces()?;
+    resolve(src, namespaces)
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    tokens: Vec<Located<Token>>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    /// Byte offset of the next token, or the end of the input if there are
+    /// no more tokens
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.src.len(), |(_, offset)| *offset)
+    }
+
+    fn error(&self, message: impl Into<String>) -> HumanSchemaParseError {
+        HumanSchemaParseError::new(self.src, self.offset(), message)
+    }
+
+    /// Error for an unexpected next token, where `expected` describes what
+    /// was expected instead
+    fn unexpected(&self, expected: &str) -> HumanSchemaParseError {
+        match self.peek() {
+            Some(token) => self.error(format!("expected {expected}, found {token}")),
+            None => self.error("unexpected end of input"),
+        }
+    }
+
+    /// Consume the next token if it is `token`
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consume the next token if it is the keyword `keyword`
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Ident(id)) if id == keyword => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&token.to_string()))
+        }
+    }
+
+    fn expect_ident(&mut self) -> Result<Located<SmolStr>> {
+        let offset = self.offset();
+        match self.peek() {
+            Some(Token::Ident(id)) => {
+                let id = id.clone();
+                self.pos += 1;
+                Ok((id, offset))
+            }
+            _ => Err(self.unexpected("an identifier")),
+        }
+    }
+
+    /// Parse an identifier or a string, as used for attribute and action names
+    fn expect_name(&mut self, expected: &str) -> Result<Located<SmolStr>> {
+        let offset = self.offset();
+        match self.peek() {
+            Some(Token::Ident(name) | Token::Str(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok((name, offset))
+            }
+            _ => Err(self.unexpected(expected)),
+        }
+    }
+
+    /// Parse a `::`-separated path of identifiers
+    fn parse_path(&mut self) -> Result<Located<SmolStr>> {
+        let (mut path, offset) = self.expect_ident()?;
+        while self.eat(&Token::ColonColon) {
+            let (id, _) = self.expect_ident()?;
+            path = format!("{path}::{id}").into();
+        }
+        Ok((path, offset))
+    }
+
+    /// Parse a comma-separated list of items, ending with `end`. A trailing
+    /// comma is allowed.
+    fn parse_list<T>(
+        &mut self,
+        end: &Token,
+        mut item: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        while !self.eat(end) {
+            items.push(item(self)?);
+            if !self.eat(&Token::Comma) {
+                self.expect(end)?;
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    /// Parse either a single entity type, or a list of them in brackets
+    fn parse_entity_types(&mut self) -> Result<Vec<SmolStr>> {
+        if self.eat(&Token::LBracket) {
+            self.parse_list(&Token::RBracket, |p| Ok(p.parse_path()?.0))
+        } else {
+            Ok(vec![self.parse_path()?.0])
+        }
+    }
+
+    fn parse_namespaces(&mut self) -> Result<Vec<(Located<SmolStr>, Vec<Decl>)>> {
+        let mut namespaces = Vec::new();
+        let mut top_level = Vec::new();
+        while self.peek().is_some() {
+            if self.eat_keyword("namespace") {
+                let name = self.parse_path()?;
+                self.expect(&Token::LBrace)?;
+                let mut decls = Vec::new();
+                while !self.eat(&Token::RBrace) {
+                    decls.push(self.parse_decl()?);
+                }
+                namespaces.push((name, decls));
+            } else {
+                top_level.push(self.parse_decl()?);
+            }
+        }
+        if !top_level.is_empty() {
+            namespaces.push(((SmolStr::default(), 0), top_level));
+        }
+        Ok(namespaces)
+    }
+
+    fn parse_decl(&mut self) -> Result<Decl> {
+        if self.eat_keyword("entity") {
+            self.parse_entity_decl()
+        } else if self.eat_keyword("action") {
+            self.parse_action_decl()
+        } else if self.eat_keyword("type") {
+            let name = self.expect_ident()?;
+            self.expect(&Token::Eq)?;
+            let ty = self.parse_type()?;
+            self.expect(&Token::Semi)?;
+            Ok(Decl::CommonType { name, ty })
+        } else {
+            Err(self.unexpected("`entity`, `action`, or `type`"))
+        }
+    }
+
+    fn parse_entity_decl(&mut self) -> Result<Decl> {
+        let mut names = vec![self.expect_ident()?];
+        while self.eat(&Token::Comma) {
+            names.push(self.expect_ident()?);
+        }
+        let member_of = if self.eat_keyword("in") {
+            self.parse_entity_types()?
+        } else {
+            Vec::new()
+        };
+        let shape = if self.eat(&Token::Eq) || self.peek() == Some(&Token::LBrace) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        let tags = if self.eat_keyword("tags") {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        self.expect(&Token::Semi)?;
+        Ok(Decl::Entity {
+            names,
+            member_of,
+            shape,
+            tags,
+        })
+    }
+
+    fn parse_action_decl(&mut self) -> Result<Decl> {
+        let mut names = vec![self.expect_name("an action name")?];
+        while self.eat(&Token::Comma) {
+            names.push(self.expect_name("an action name")?);
+        }
+        let member_of = if self.eat_keyword("in") {
+            if self.eat(&Token::LBracket) {
+                Some(self.parse_list(&Token::RBracket, Self::parse_action_ref)?)
+            } else {
+                Some(vec![self.parse_action_ref()?])
+            }
+        } else {
+            None
+        };
+        let applies_to = if self.eat_keyword("appliesTo") {
+            Some(self.parse_applies_to()?)
+        } else {
+            None
+        };
+        self.expect(&Token::Semi)?;
+        Ok(Decl::Action {
+            names,
+            member_of,
+            applies_to,
+        })
+    }
+
+    /// Parse a reference to an action: either the name of an action in the
+    /// same namespace, or `Path::"name"`
+    fn parse_action_ref(&mut self) -> Result<ActionEntityUID> {
+        if let Some(Token::Str(id)) = self.peek() {
+            let id = id.clone();
+            self.pos += 1;
+            return Ok(ActionEntityUID::default_type(id));
+        }
+        let (first, _) = self.expect_ident()?;
+        let mut path = vec![first];
+        while self.eat(&Token::ColonColon) {
+            match self.peek() {
+                Some(Token::Str(id)) => {
+                    let id = id.clone();
+                    self.pos += 1;
+                    return Ok(ActionEntityUID {
+                        id,
+                        ty: Some(path.join("::").into()),
+                    });
+                }
+                Some(Token::Ident(id)) => {
+                    path.push(id.clone());
+                    self.pos += 1;
+                }
+                _ => return Err(self.unexpected("an action name string")),
+            }
+        }
+        match <[SmolStr; 1]>::try_from(path) {
+            Ok([id]) => Ok(ActionEntityUID::default_type(id)),
+            Err(_) => Err(self.unexpected("an action name string")),
+        }
+    }
+
+    fn parse_applies_to(&mut self) -> Result<AppliesTo> {
+        self.expect(&Token::LBrace)?;
+        let mut applies_to = AppliesTo {
+            principal: None,
+            resource: None,
+            context: None,
+        };
+        self.parse_list(&Token::RBrace, |p| {
+            let offset = p.offset();
+            let (field, _) = p.expect_ident()?;
+            p.expect(&Token::Colon)?;
+            let duplicate = match field.as_str() {
+                "principal" => applies_to
+                    .principal
+                    .replace(p.parse_entity_types()?)
+                    .is_some(),
+                "resource" => applies_to
+                    .resource
+                    .replace(p.parse_entity_types()?)
+                    .is_some(),
+                "context" => applies_to.context.replace(p.parse_type()?).is_some(),
+                _ => {
+                    return Err(HumanSchemaParseError::new(
+                        p.src,
+                        offset,
+                        format!(
+                            "expected `principal`, `resource`, or `context`, found identifier `{field}`"
+                        ),
+                    ))
+                }
+            };
+            if duplicate {
+                Err(HumanSchemaParseError::new(
+                    p.src,
+                    offset,
+                    format!("duplicate `{field}` in `appliesTo`"),
+                ))
+            } else {
+                Ok(())
+            }
+        })?;
+        Ok(applies_to)
+    }
+
+    fn parse_type(&mut self) -> Result<TypeExpr> {
+        match self.peek() {
+            Some(Token::LBrace) => {
+                self.pos += 1;
+                let mut seen = HashSet::new();
+                let attrs = self.parse_list(&Token::RBrace, |p| {
+                    let (name, offset) = p.expect_name("an attribute name")?;
+                    if !seen.insert(name.clone()) {
+                        return Err(HumanSchemaParseError::new(
+                            p.src,
+                            offset,
+                            format!("duplicate attribute `{name}`"),
+                        ));
+                    }
+                    let required = !p.eat(&Token::Question);
+                    p.expect(&Token::Colon)?;
+                    let ty = p.parse_type()?;
+                    Ok(AttrDecl { name, required, ty })
+                })?;
+                Ok(TypeExpr::Record(attrs))
+            }
+            Some(Token::Ident(id))
+                if id == "Set"
+                    && self.tokens.get(self.pos + 1).map(|(t, _)| t) == Some(&Token::LAngle) =>
+            {
+                self.pos += 2;
+                let element = self.parse_type()?;
+                self.expect(&Token::RAngle)?;
+                Ok(TypeExpr::Set(Box::new(element)))
+            }
+            Some(Token::Ident(_)) => Ok(TypeExpr::Name(self.parse_path()?.0)),
+            _ => Err(self.unexpected("a type")),
+        }
+    }
+}
+
+/// Resolve the type names in the parsed namespaces, and check for duplicate
+/// declarations
+fn resolve(src: &str, namespaces: Vec<(Located<SmolStr>, Vec<Decl>)>) -> Result<SchemaFragment> {
+    let mut common_types = HashSet::new();
+    for ((namespace, _), decls) in &namespaces {
+        for decl in decls {
+            if let Decl::CommonType {
+                name: (name, offset),
+                ..
+            } = decl
+            {
+                if is_builtin_type_name(name) {
+                    return Err(HumanSchemaParseError::new(
+                        src,
+                        *offset,
+                        format!("common type `{name}` has the name of a builtin type"),
+                    ));
+                }
+                common_types.insert(qualify(namespace, name));
+            }
+        }
+    }
+
+    let mut fragment = HashMap::new();
+    for ((namespace, offset), decls) in namespaces {
+        let resolver = Resolver {
+            namespace: &namespace,
+            common_types: &common_types,
+        };
+        let mut definition = NamespaceDefinition {
+            common_types: HashMap::new(),
+            entity_types: HashMap::new(),
+            actions: HashMap::new(),
+        };
+        for decl in decls {
+            match decl {
+                Decl::Entity {
+                    names,
+                    member_of,
+                    shape,
+                    tags,
+                } => {
+                    let entity_type = EntityType {
+                        member_of_types: member_of,
+                        shape: shape
+                            .map(|shape| AttributesOrContext(resolver.resolve(shape)))
+                            .unwrap_or_default(),
+                        tags: tags.map(|tags| resolver.resolve(tags)),
+                    };
+                    for (name, offset) in names {
+                        if definition
+                            .entity_types
+                            .insert(name.clone(), entity_type.clone())
+                            .is_some()
+                        {
+                            return Err(HumanSchemaParseError::new(
+                                src,
+                                offset,
+                                format!("duplicate entity type `{name}`"),
+                            ));
+                        }
+                    }
+                }
+                Decl::Action {
+                    names,
+                    member_of,
+                    applies_to,
+                } => {
+                    let action = ActionType {
+                        attributes: None,
+                        applies_to: applies_to.map(|applies_to| ApplySpec {
+                            resource_types: applies_to.resource,
+                            principal_types: applies_to.principal,
+                            context: applies_to
+                                .context
+                                .map(|context| AttributesOrContext(resolver.resolve(context)))
+                                .unwrap_or_default(),
+                        }),
+                        member_of,
+                    };
+                    for (name, offset) in names {
+                        if definition
+                            .actions
+                            .insert(name.clone(), action.clone())
+                            .is_some()
+                        {
+                            return Err(HumanSchemaParseError::new(
+                                src,
+                                offset,
+                                format!("duplicate action `{name}`"),
+                            ));
+                        }
+                    }
+                }
+                Decl::CommonType {
+                    name: (name, offset),
+                    ty,
+                } => {
+                    if definition
+                        .common_types
+                        .insert(name.clone(), resolver.resolve(ty))
+                        .is_some()
+                    {
+                        return Err(HumanSchemaParseError::new(
+                            src,
+                            offset,
+                            format!("duplicate common type `{name}`"),
+                        ));
+                    }
+                }
+            }
+        }
+        if fragment.insert(namespace.clone(), definition).is_some() {
+            return Err(HumanSchemaParseError::new(
+                src,
+                offset,
+                format!("duplicate namespace `{namespace}`"),
+            ));
+        }
+    }
+    Ok(SchemaFragment(fragment))
+}
+
+struct Resolver<'a> {
+    namespace: &'a str,
+    common_types: &'a HashSet<SmolStr>,
+}
+
+impl<'a> Resolver<'a> {
+    fn resolve(&self, ty: TypeExpr) -> SchemaType {
+        match ty {
+            TypeExpr::Set(element) => SchemaTypeVariant::Set {
+                element: Box::new(self.resolve(*element)),
+            }
+            .into(),
+            TypeExpr::Record(attrs) => SchemaTypeVariant::Record {
+                attributes: attrs
+                    .into_iter()
+                    .map(|attr| {
+                        (
+                            attr.name,
+                            TypeOfAttribute {
+                                ty: self.resolve(attr.ty),
+                                required: attr.required,
+                                default: None,
+                            },
+                        )
+                    })
+                    .collect::<BTreeMap<_, _>>(),
+                additional_attributes: false,
+            }
+            .into(),
+            TypeExpr::Name(name) => resolve_type_name(&name, self.namespace, self.common_types),
+        }
+    }
+}
@@ -28,20 +28,28 @@ mod expr_iterator;
 mod extension_schema;
 mod extensions;
 mod fuzzy_match;
+mod human_schema;
+pub use human_schema::*;
 mod validation_result;
 use serde::Serialize;
 pub use validation_result::*;
 mod rbac;
 mod schema;
 pub use schema::*;
+mod schema_diff;
+pub use schema_diff::*;
 mod schema_file_format;
+mod schema_usage;
+pub use schema_usage::*;
+mod skeleton;
+mod slot_types;
 pub use schema_file_format::*;
 mod type_error;
 pub use type_error::*;
 pub mod typecheck;
 pub mod types;
 
-pub use str_checks::{confusable_string_checks, ValidationWarning, ValidationWarningKind};
+pub use str_checks::confusable_string_checks;
 
 use self::typecheck::Typechecker;
 
@@ -64,16 +72,28 @@ impl ValidationMode {
 }
 
 /// Structure containing the context needed for policy validation. This is
-/// currently only the `EntityType`s and `ActionType`s from a single schema.
+/// currently only the `EntityType`s and `ActionType`s from a single schema,
+/// and the warnings which should be reported as errors.
 #[derive(Debug)]
 pub struct Validator {
     schema: ValidatorSchema,
+    denied_warnings: HashSet<WarningCode>,
 }
 
 impl Validator {
     /// Construct a new Validator from a schema file.
     pub fn new(schema: ValidatorSchema) -> Validator {
-        Self { schema }
+        Self {
+            schema,
+            denied_warnings: HashSet::new(),
+        }
+    }
+
+    /// Report the warnings with the given codes as errors, so that they cause
+    /// validation to fail.
+    pub fn with_denied_warnings(mut self, codes: impl IntoIterator<Item = WarningCode>) -> Self {
+        self.denied_warnings.extend(codes);
+        self
     }
 
     /// Validate all templates in a policy set (which includes static policies) and
@@ -83,14 +103,70 @@ impl Validator {
         policies: &'a PolicySet,
         mode: ValidationMode,
     ) -> ValidationResult<'a> {
-        let template_errs = policies
+        // A policy which is impossible is valid, but is probably a mistake, so
+        // the typechecker error is reported as a warning.
+        let (impossible, template_errs): (Vec<_>, Vec<_>) = policies
             .all_templates()
-            .flat_map(|p| self.validate_policy(p, mode));
+            .flat_map(|p| self.validate_policy(p, mode))
+            .partition(|err| {
+                matches!(
+                    err.error_kind(),
+                    ValidationErrorKind::TypeError(TypeErrorKind::ImpossiblePolicy)
+                )
+            });
         let instantiation_errs = policies.policies().flat_map(|p| {
             self.validate_slots(p.env())
                 .map(move |note| ValidationError::with_policy_id(p.id(), None, note))
         });
-        ValidationResult::new(template_errs.chain(instantiation_errs))
+        let warnings = confusable_string_checks(policies.all_templates()).chain(
+            impossible.into_iter().map(|err| {
+                let (location, _) = err.into_location_and_error_kind();
+                ValidationWarning::new(
+                    location.policy_id(),
+                    ValidationWarningKind::ImpossiblePolicy,
+                )
+            }),
+        );
+        self.validation_result(
+            template_errs.into_iter().chain(instantiation_errs),
+            warnings,
+        )
+    }
+
+    /// Validate the slot values of every template-linked policy in a policy
+    /// set. Their entity types must be declared in the schema and allowed in
+    /// the scope positions of their slots (see [`ValidatorSchema::slot_types`]),
+    /// since otherwise the linked policy can never apply to a request allowed
+    /// by the schema. The templates themselves are not validated; use
+    /// `validate` for those.
+    pub fn validate_linked_policies<'a>(&'a self, policies: &'a PolicySet) -> ValidationResult<'a> {
+        ValidationResult::new(
+            policies
+                .policies()
+                .filter(|p| !p.is_static())
+                .flat_map(|p| {
+                    self.validate_slots(p.env())
+                        .chain(self.validate_slot_types(p))
+                        .map(move |note| ValidationError::with_policy_id(p.id(), None, note))
+                }),
+            std::iter::empty(),
+        )
+    }
+
+    /// Collect the errors and warnings found by validation, reporting the
+    /// denied warnings as errors.
+    fn validation_result<'a>(
+        &self,
+        errors: impl Iterator<Item = ValidationError<'a>>,
+        warnings: impl Iterator<Item = ValidationWarning<'a>>,
+    ) -> ValidationResult<'a> {
+        let (denied, warnings): (Vec<_>, Vec<_>) =
+            warnings.partition(|warning| self.denied_warnings.contains(&warning.kind().code()));
+        let denied = denied.into_iter().map(|warning| {
+            let (id, kind) = warning.to_kind_and_location();
+            ValidationError::with_policy_id(id, None, ValidationErrorKind::denied_warning(kind))
+        });
+        ValidationResult::new(errors.chain(denied), warnings.into_iter())
     }
 
     /// Run all validations against a single policy, gathering all validation
@@ -144,6 +220,7 @@ mod test {
                     foo_type.into(),
                     EntityType {
                         member_of_types: vec![],
+                        tags: None,
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -151,6 +228,7 @@ mod test {
                     bar_type.into(),
                     EntityType {
                         member_of_types: vec![],
+                        tags: None,
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -329,4 +407,200 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn validate_linked_policies() {
+        let schema: ValidatorSchema = serde_json::from_value::<SchemaFragment>(serde_json::json!(
+        {
+            "": {
+                "entityTypes": {
+                    "User": { "memberOfTypes": ["Team"] },
+                    "Team": {},
+                    "Vault": {}
+                },
+                "actions": {
+                    "withdraw": {
+                        "appliesTo": { "principalTypes": ["User"], "resourceTypes": ["Vault"] }
+                    }
+                }
+            }
+        }))
+        .expect("Schema parse error.")
+        .try_into()
+        .expect("Expected valid schema.");
+        let validator = Validator::new(schema);
+
+        let mut set = PolicySet::new();
+        let t = parser::parse_policy_template(
+            Some("template".to_string()),
+            r#"permit(principal in ?principal, action == Action::"withdraw", resource == ?resource);"#,
+        )
+        .expect("Parse Error");
+        set.add_template(t)
+            .expect("Template already present in PolicySet");
+        let mut link = |id: &str, principal: &str, resource: &str| {
+            set.link(
+                ast::PolicyID::from_string("template"),
+                ast::PolicyID::from_string(id),
+                HashMap::from([
+                    (ast::SlotId::principal(), principal.parse().unwrap()),
+                    (ast::SlotId::resource(), resource.parse().unwrap()),
+                ]),
+            )
+            .expect("Linking failed!");
+        };
+        link("good", r#"Team::"t""#, r#"Vault::"v""#);
+        link("bad", r#"Vault::"v""#, r#"Team::"t""#);
+        link("unknown", r#"User::"u""#, r#"Vaults::"v""#);
+
+        let result = validator.validate_linked_policies(&set);
+        let errors = result.validation_errors().collect::<Vec<_>>();
+        assert_eq!(errors.len(), 3, "{errors:?}");
+        let bad = ast::PolicyID::from_string("bad");
+        assert!(errors.contains(&&ValidationError::with_policy_id(
+            &bad,
+            None,
+            ValidationErrorKind::unexpected_slot_type(
+                ast::SlotId::principal(),
+                "Vault".to_string(),
+                vec!["Team".to_string(), "User".to_string()],
+            ),
+        )));
+        assert!(errors.contains(&&ValidationError::with_policy_id(
+            &bad,
+            None,
+            ValidationErrorKind::unexpected_slot_type(
+                ast::SlotId::resource(),
+                "Team".to_string(),
+                vec!["Vault".to_string()],
+            ),
+        )));
+        assert!(errors.contains(&&ValidationError::with_policy_id(
+            &ast::PolicyID::from_string("unknown"),
+            None,
+            ValidationErrorKind::unrecognized_entity_type(
+                "Vaults".to_string(),
+                Some("Vault".to_string()),
+            ),
+        )));
+    }
+
+    #[test]
+    fn denied_warnings() {
+        let schema: ValidatorSchema = serde_json::from_value::<SchemaFragment>(serde_json::json!(
+        {
+            "": {
+                "entityTypes": { "User": {} },
+                "actions": {
+                    "view": {
+                        "appliesTo": { "principalTypes": ["User"], "resourceTypes": ["User"] }
+                    }
+                }
+            }
+        }))
+        .expect("Schema parse error.")
+        .try_into()
+        .expect("Expected valid schema.");
+        let mut set = PolicySet::new();
+        let p = parser::parse_policy(
+            Some("impossible".to_string()),
+            r#"permit(principal, action, resource) when { false };"#,
+        )
+        .expect("Parse Error");
+        set.add_static(p)
+            .expect("Policy already present in PolicySet");
+        let id = ast::PolicyID::from_string("impossible");
+
+        let validator = Validator::new(schema);
+        let result = validator.validate(&set, ValidationMode::default());
+        assert!(result.validation_passed());
+        assert_eq!(
+            result
+                .validation_warnings()
+                .map(|w| (w.location(), w.kind()))
+                .collect::<Vec<_>>(),
+            vec![(&id, &ValidationWarningKind::ImpossiblePolicy)]
+        );
+
+        let validator = validator.with_denied_warnings([WarningCode::ImpossiblePolicy]);
+        let result = validator.validate(&set, ValidationMode::default());
+        assert_eq!(result.validation_warnings().count(), 0);
+        assert_eq!(
+            result.validation_errors().collect::<Vec<_>>(),
+            vec![&ValidationError::with_policy_id(
+                &id,
+                None,
+                ValidationErrorKind::denied_warning(ValidationWarningKind::ImpossiblePolicy)
+            )]
+        );
+        assert_eq!(
+            result.validation_errors().next().unwrap().error_kind().to_string(),
+            "policy is impossible. The policy expression evaluates to false for all valid requests (warning `impossible-policy` is denied)"
+        );
+    }
+
+    #[test]
+    fn action_groups() {
+        let schema = ValidatorSchema::from_json_value(serde_json::json!(
+        {
+            "": {
+                "entityTypes": {
+                    "User": {},
+                    "Token": {
+                        "shape": {
+                            "type": "Record",
+                            "attributes": { "paused": { "type": "Boolean" } }
+                        }
+                    }
+                },
+                "actions": {
+                    "erc20Write": {},
+                    "transfer": {
+                        "memberOf": [{ "id": "erc20Write" }],
+                        "appliesTo": { "principalTypes": ["User"], "resourceTypes": ["Token"] }
+                    },
+                    "approve": {
+                        "memberOf": [{ "id": "erc20Write" }],
+                        "appliesTo": { "principalTypes": ["User"], "resourceTypes": ["Token"] }
+                    }
+                }
+            }
+        }))
+        .expect("Expected valid schema.");
+        let validator = Validator::new(schema);
+        let validate = |src: &str| {
+            let mut set = PolicySet::new();
+            let p = parser::parse_policy(Some("p".to_string()), src).expect("Parse Error");
+            set.add_static(p)
+                .expect("Policy already present in PolicySet");
+            let errors = validator
+                .validate(&set, ValidationMode::default())
+                .into_errors_and_warnings()
+                .0
+                .map(|e| e.into_location_and_error_kind().1)
+                .collect::<Vec<_>>();
+            errors
+        };
+
+        // The group is not the action of any request, so that `resource` is
+        // known to be a `Token` in the condition.
+        assert_eq!(
+            validate(
+                r#"permit(principal, action in Action::"erc20Write", resource) when { resource.paused };"#
+            ),
+            vec![]
+        );
+        assert_eq!(
+            validate(
+                r#"permit(principal, action, resource) when { action in Action::"erc20Write" && resource.paused };"#
+            ),
+            vec![]
+        );
+        assert_eq!(
+            validate(r#"permit(principal, action == Action::"erc20Write", resource);"#),
+            vec![ValidationErrorKind::invalid_action_application(
+                false, false
+            )]
+        );
+    }
 }
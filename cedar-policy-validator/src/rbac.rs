@@ -17,8 +17,8 @@
 //! Contains the validation logic specific to RBAC policy validation.
 
 use cedar_policy_core::ast::{
-    self, ActionConstraint, EntityReference, EntityUID, Name, PrincipalOrResourceConstraint,
-    SlotEnv, Template,
+    self, ActionConstraint, EntityReference, EntityUID, Name, Policy,
+    PrincipalOrResourceConstraint, SlotEnv, Template,
 };
 
 use std::{collections::HashSet, sync::Arc};
@@ -166,6 +166,33 @@ impl Validator {
         })
     }
 
+    /// Generate UnexpectedSlotType notes for every slot of a template-linked
+    /// policy which is filled with an entity of a type that the schema does
+    /// not allow in the scope position of the slot. Types which are not in
+    /// the schema are reported by `validate_slots` instead.
+    pub(crate) fn validate_slot_types<'a>(
+        &'a self,
+        policy: &'a Policy,
+    ) -> impl Iterator<Item = ValidationErrorKind> + 'a {
+        let slot_types = self.schema.slot_types(policy.template());
+        policy.env().iter().filter_map(move |(slot, euid)| {
+            let ast::EntityType::Concrete(name) = euid.entity_type() else {
+                return None;
+            };
+            let expected = slot_types.get(slot)?;
+            if expected.contains(name) || !self.schema.is_known_entity_type(name) {
+                return None;
+            }
+            let mut expected = expected.iter().map(ToString::to_string).collect::<Vec<_>>();
+            expected.sort();
+            Some(ValidationErrorKind::unexpected_slot_type(
+                *slot,
+                name.to_string(),
+                expected,
+            ))
+        })
+    }
+
     fn check_if_in_fixes_principal(&self, template: &Template) -> bool {
         self.check_if_in_fixes(
             template.principal_constraint().as_inner(),
@@ -424,7 +451,7 @@ impl Validator {
 
 #[cfg(test)]
 mod test {
-    use std::collections::{BTreeMap, HashMap, HashSet};
+    use std::collections::{BTreeMap, HashSet};
 
     use cedar_policy_core::{
         ast::{Effect, Eid, EntityUID, Expr, PolicyID, PrincipalConstraint, ResourceConstraint},
@@ -486,6 +513,7 @@ mod test {
                     user_type.into(),
                     EntityType {
                         member_of_types: vec![group_type.into()],
+                        tags: None,
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -493,6 +521,7 @@ mod test {
                     group_type.into(),
                     EntityType {
                         member_of_types: vec![],
+                        tags: None,
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -500,6 +529,7 @@ mod test {
                     widget_type.into(),
                     EntityType {
                         member_of_types: vec![bin_type.into()],
+                        tags: None,
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -507,6 +537,7 @@ mod test {
                     bin_type.into(),
                     EntityType {
                         member_of_types: vec![],
+                        tags: None,
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -563,6 +594,7 @@ mod test {
                 foo_type.into(),
                 EntityType {
                     member_of_types: vec![],
+                    tags: None,
                     shape: AttributesOrContext::default(),
                 },
             )],
@@ -598,6 +630,7 @@ mod test {
                 "foo_type".into(),
                 EntityType {
                     member_of_types: vec![],
+                    tags: None,
                     shape: AttributesOrContext::default(),
                 },
             )],
@@ -719,6 +752,7 @@ mod test {
                 p_name.into(),
                 EntityType {
                     member_of_types: vec![],
+                    tags: None,
                     shape: AttributesOrContext::default(),
                 },
             )],
@@ -751,6 +785,7 @@ mod test {
                 p_name.into(),
                 EntityType {
                     member_of_types: vec![],
+                    tags: None,
                     shape: AttributesOrContext::default(),
                 },
             )],
@@ -783,6 +818,7 @@ mod test {
                 p_name.into(),
                 EntityType {
                     member_of_types: vec![],
+                    tags: None,
                     shape: AttributesOrContext::default(),
                 },
             )],
@@ -793,7 +829,9 @@ mod test {
         let undefined_euid: EntityUID = "Undefined::\"foo\""
             .parse()
             .expect("Expected entity UID to parse.");
-        let env = HashMap::from([(ast::SlotId::principal(), undefined_euid)]);
+        let env: ast::SlotEnv = [(ast::SlotId::principal(), undefined_euid)]
+            .into_iter()
+            .collect();
 
         let validator = Validator::new(schema);
         let notes: Vec<ValidationErrorKind> = validator.validate_slots(&env).collect();
@@ -1134,6 +1172,7 @@ mod test {
                 foo_type.into(),
                 EntityType {
                     member_of_types: vec![],
+                    tags: None,
                     shape: AttributesOrContext::default(),
                 },
             )],
@@ -1169,6 +1208,7 @@ mod test {
                     principal_type.into(),
                     EntityType {
                         member_of_types: vec![],
+                        tags: None,
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -1176,6 +1216,7 @@ mod test {
                     resource_type.into(),
                     EntityType {
                         member_of_types: vec![],
+                        tags: None,
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -1377,6 +1418,7 @@ mod test {
                     principal_type.into(),
                     EntityType {
                         member_of_types: vec![],
+                        tags: None,
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -1384,6 +1426,7 @@ mod test {
                     resource_type.into(),
                     EntityType {
                         member_of_types: vec![resource_parent_type.into()],
+                        tags: None,
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -1391,6 +1434,7 @@ mod test {
                     resource_parent_type.into(),
                     EntityType {
                         member_of_types: vec![resource_grandparent_type.into()],
+                        tags: None,
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -1398,6 +1442,7 @@ mod test {
                     resource_grandparent_type.into(),
                     EntityType {
                         member_of_types: vec![],
+                        tags: None,
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -25,7 +25,8 @@ use std::sync::Arc;
 
 use cedar_policy_core::{
     ast::{Eid, Entity, EntityType, EntityUID, Id, Name, RestrictedExpr},
-    entities::{Entities, JSONValue, TCComputation},
+    entities::{Entities, JSONValue, JsonDeserializationErrorContext, TCComputation, ValueParser},
+    extensions::Extensions,
     parser::err::ParseErrors,
     transitive_closure::{compute_tc, TCNode},
     FromNormalizedStr,
@@ -122,6 +123,12 @@ pub struct EntityTypeFragment {
     /// defined in this schema fragment. All entity type `Name` keys in this map
     /// are declared in this schema fragment.
     attributes: WithUnresolvedTypeDefs<Type>,
+    /// The type of tag values for this entity type, if it has tags. As with
+    /// `attributes`, this may contain typedefs which are not yet resolved.
+    tags: Option<WithUnresolvedTypeDefs<Type>>,
+    /// The default values for optional attributes, which are checked against
+    /// the attribute types once typedefs are resolved.
+    attribute_defaults: HashMap<SmolStr, serde_json::Value>,
     /// The direct parent entity types for this entity type come from the
     /// `memberOfTypes` list. These types might be declared in a different
     /// namespace, so we will check if they are declared in any fragment when
@@ -142,8 +149,14 @@ pub struct ActionFragment {
     /// a `WithUnresolvedTypeDefs` because it may refer to common types which
     /// are not defined in this fragment.
     context: WithUnresolvedTypeDefs<Type>,
+    /// The default values for optional context attributes.
+    context_defaults: HashMap<SmolStr, serde_json::Value>,
     /// The principals and resources that an action can be applied to.
     applies_to: ValidatorApplySpec,
+    /// Whether the `appliesTo` field was omitted for this action. Such an
+    /// action with members is an action group, which does not apply to any
+    /// principals and resources itself.
+    applies_to_omitted: bool,
     /// The direct parent action entities for this action.
     parents: HashSet<EntityUID>,
     /// The types for the attributes defined for this actions entity.
@@ -320,15 +333,26 @@ impl ValidatorNamespaceDef {
                         })
                         .collect::<Result<HashSet<_>>>()?;
 
-                    let attributes = Self::try_schema_type_into_validator_type(
-                        schema_namespace,
-                        entity_type.shape.into_inner(),
-                    )?;
+                    let mut shape = entity_type.shape.into_inner();
+                    let attribute_defaults = Self::take_attribute_defaults(&mut shape, || {
+                        ContextOrShape::EntityTypeShape(name.clone())
+                    })?;
+                    let attributes =
+                        Self::try_schema_type_into_validator_type(schema_namespace, shape)?;
+
+                    let tags = entity_type
+                        .tags
+                        .map(|tags| {
+                            Self::try_schema_type_into_validator_type(schema_namespace, tags)
+                        })
+                        .transpose()?;
 
                     Ok((
                         name,
                         EntityTypeFragment {
                             attributes,
+                            tags,
+                            attribute_defaults,
                             parents,
                         },
                     ))
@@ -443,6 +467,7 @@ impl ValidatorNamespaceDef {
                         schema_namespace,
                     )?;
 
+                    let applies_to_omitted = action_type.applies_to.is_none();
                     let (principal_types, resource_types, context) = action_type
                         .applies_to
                         .map(|applies_to| {
@@ -462,10 +487,12 @@ impl ValidatorNamespaceDef {
                         Self::parse_apply_spec_type_list(resource_types, schema_namespace)?,
                     );
 
-                    let context = Self::try_schema_type_into_validator_type(
-                        schema_namespace,
-                        context.into_inner(),
-                    )?;
+                    let mut context = context.into_inner();
+                    let context_defaults = Self::take_attribute_defaults(&mut context, || {
+                        ContextOrShape::ActionContext(action_id.clone())
+                    })?;
+                    let context =
+                        Self::try_schema_type_into_validator_type(schema_namespace, context)?;
 
                     let parents = action_type
                         .member_of
@@ -486,7 +513,9 @@ impl ValidatorNamespaceDef {
                         action_id,
                         ActionFragment {
                             context,
+                            context_defaults,
                             applies_to,
+                            applies_to_omitted,
                             parents,
                             attribute_types,
                             attributes,
@@ -497,6 +526,33 @@ impl ValidatorNamespaceDef {
         })
     }
 
+    /// Remove the default values from the attributes of an entity type shape
+    /// or action context as written in a schema file, returning them by
+    /// attribute name. Only optional attributes may have a default value.
+    fn take_attribute_defaults(
+        ty: &mut SchemaType,
+        location: impl Fn() -> ContextOrShape,
+    ) -> Result<HashMap<SmolStr, serde_json::Value>> {
+        let SchemaType::Type(SchemaTypeVariant::Record { attributes, .. }) = ty else {
+            return Ok(HashMap::new());
+        };
+        attributes
+            .iter_mut()
+            .filter_map(|(attr, ty)| {
+                let default = ty.default.take()?;
+                Some(if ty.required {
+                    Err(SchemaError::InvalidAttributeDefault(
+                        location(),
+                        attr.clone(),
+                        "only optional attributes may have a default value".into(),
+                    ))
+                } else {
+                    Ok((attr.clone(), default.0))
+                })
+            })
+            .collect()
+    }
+
     // Check that `schema_file` uses actions in a way consistent with the
     // specified `action_behavior`. When the behavior specifies that actions
     // should not be used in groups and should not have attributes, then this
@@ -544,6 +600,13 @@ impl ValidatorNamespaceDef {
         let attrs_with_type_defs = attrs
             .into_iter()
             .map(|(attr, ty)| -> Result<_> {
+                // Defaults on the attributes of an entity type shape or action
+                // context were already taken by `take_attribute_defaults`.
+                if ty.default.is_some() {
+                    return Err(SchemaError::UnsupportedFeature(
+                        UnsupportedFeature::NestedAttributeDefault(attr),
+                    ));
+                }
                 Ok((
                     attr,
                     (
@@ -709,7 +772,14 @@ impl ValidatorNamespaceDef {
             SchemaType::Type(SchemaTypeVariant::Extension { name }) => {
                 let extension_type_name =
                     Name::from_normalized_str(&name).map_err(SchemaError::ParseExtensionType)?;
-                Ok(Type::extension(extension_type_name).into())
+                if Extensions::all_available()
+                    .ext_types()
+                    .any(|ty| ty == &extension_type_name)
+                {
+                    Ok(Type::extension(extension_type_name).into())
+                } else {
+                    Err(SchemaError::UnknownExtensionType(extension_type_name))
+                }
             }
             SchemaType::TypeDef { type_name } => {
                 let defined_type_name = Self::parse_possibly_qualified_name_with_default_namespace(
@@ -913,17 +983,30 @@ impl ValidatorSchema {
                 // error for any other undeclared entity types by
                 // `check_for_undeclared`.
                 let descendants = entity_children.remove(&name).unwrap_or_default();
+                let attributes = Self::record_attributes_or_none(
+                    entity_type.attributes.resolve_type_defs(&type_defs)?,
+                )
+                .ok_or_else(|| {
+                    SchemaError::ContextOrShapeNotRecord(ContextOrShape::EntityTypeShape(
+                        name.clone(),
+                    ))
+                })?;
+                Self::check_attribute_defaults(
+                    &attributes,
+                    &entity_type.attribute_defaults,
+                    || ContextOrShape::EntityTypeShape(name.clone()),
+                )?;
                 Ok((
                     name.clone(),
                     ValidatorEntityType {
                         name: name.clone(),
                         descendants,
-                        attributes: Self::record_attributes_or_none(
-                            entity_type.attributes.resolve_type_defs(&type_defs)?,
-                        )
-                        .ok_or(SchemaError::ContextOrShapeNotRecord(
-                            ContextOrShape::EntityTypeShape(name),
-                        ))?,
+                        attributes,
+                        tags: entity_type
+                            .tags
+                            .map(|tags| tags.resolve_type_defs(&type_defs))
+                            .transpose()?,
+                        attribute_defaults: entity_type.attribute_defaults,
                     },
                 ))
             })
@@ -942,19 +1025,32 @@ impl ValidatorSchema {
             .into_iter()
             .map(|(name, action)| -> Result<_> {
                 let descendants = action_children.remove(&name).unwrap_or_default();
+                // An action group is only used in `action in` constraints, so
+                // it should not be the action of any request.
+                let applies_to = if action.applies_to_omitted && !descendants.is_empty() {
+                    ValidatorApplySpec::new(HashSet::new(), HashSet::new())
+                } else {
+                    action.applies_to
+                };
+                let context =
+                    Self::record_attributes_or_none(action.context.resolve_type_defs(&type_defs)?)
+                        .ok_or_else(|| {
+                            SchemaError::ContextOrShapeNotRecord(ContextOrShape::ActionContext(
+                                name.clone(),
+                            ))
+                        })?;
+                Self::check_attribute_defaults(&context, &action.context_defaults, || {
+                    ContextOrShape::ActionContext(name.clone())
+                })?;
 
                 Ok((
                     name.clone(),
                     ValidatorActionId {
                         name: name.clone(),
-                        applies_to: action.applies_to,
+                        applies_to,
                         descendants,
-                        context: Self::record_attributes_or_none(
-                            action.context.resolve_type_defs(&type_defs)?,
-                        )
-                        .ok_or(SchemaError::ContextOrShapeNotRecord(
-                            ContextOrShape::ActionContext(name),
-                        ))?,
+                        context,
+                        context_defaults: action.context_defaults,
                         attribute_types: action.attribute_types,
                         attributes: action.attributes,
                     },
@@ -1019,6 +1115,9 @@ impl ValidatorSchema {
                     &mut undeclared_e,
                 );
             }
+            if let Some(tag_typ) = entity_type.tag_type() {
+                Self::check_undeclared_in_type(tag_typ, entity_types, &mut undeclared_e);
+            }
         }
 
         // Undeclared actions in a `memberOf` list.
@@ -1070,6 +1169,41 @@ impl ValidatorSchema {
         Ok(())
     }
 
+    /// Check that the default value of each attribute in `defaults` is a
+    /// value of the attribute's type. Attributes with a default stay optional
+    /// for validation: only data parsed with the schema is given their
+    /// default, and entities and contexts built any other way, such as with
+    /// `Entity::new` or `Context::from_pairs`, may still lack them.
+    fn check_attribute_defaults(
+        attrs: &Attributes,
+        defaults: &HashMap<SmolStr, serde_json::Value>,
+        location: impl Fn() -> ContextOrShape,
+    ) -> Result<()> {
+        let parser = ValueParser::new(Extensions::all_available());
+        for (attr, default) in defaults {
+            let invalid =
+                |msg: String| SchemaError::InvalidAttributeDefault(location(), attr.clone(), msg);
+            let Some(attr_type) = attrs.attrs.get(attr) else {
+                continue;
+            };
+            let expected: cedar_policy_core::entities::SchemaType =
+                attr_type.attr_type.clone().try_into().map_err(invalid)?;
+            let ctx = || JsonDeserializationErrorContext::AttributeDefault { attr: attr.clone() };
+            let value = parser
+                .val_into_rexpr(default.clone(), Some(&expected), ctx)
+                .map_err(|e| invalid(e.to_string()))?;
+            let actual = parser
+                .type_of_rexpr(value.as_borrowed(), ctx)
+                .map_err(|e| invalid(e.to_string()))?;
+            if !actual.is_consistent_with(&expected) {
+                return Err(invalid(format!(
+                    "expected a value of type {expected}, but got a value of type {actual}"
+                )));
+            }
+        }
+        Ok(())
+    }
+
     fn record_attributes_or_none(ty: Type) -> Option<Attributes> {
         match ty {
             Type::EntityOrRecord(EntityRecordKind::Record { attrs, .. }) => Some(attrs),
@@ -1206,13 +1340,16 @@ impl ValidatorSchema {
             // representable. The values are representable because they are
             // taken from the context of a `ValidatorActionId` which was
             // constructed directly from a schema.
-            ContextSchema(crate::types::Type::record_with_attributes(
-                action_id
-                    .context
-                    .iter()
-                    .map(|(k, v)| (k.clone(), v.clone())),
-                OpenTag::ClosedAttributes,
-            ))
+            ContextSchema(
+                crate::types::Type::record_with_attributes(
+                    action_id
+                        .context
+                        .iter()
+                        .map(|(k, v)| (k.clone(), v.clone())),
+                    OpenTag::ClosedAttributes,
+                ),
+                action_id.context_defaults.clone(),
+            )
         })
     }
 
@@ -1371,6 +1508,29 @@ impl cedar_policy_core::entities::EntityTypeDescription for EntityTypeDescriptio
         )
     }
 
+    fn default_attrs<'s>(&'s self) -> Box<dyn Iterator<Item = (SmolStr, serde_json::Value)> + 's> {
+        Box::new(
+            self.validator_type
+                .attribute_defaults
+                .iter()
+                .map(|(attr, value)| (attr.clone(), value.clone())),
+        )
+    }
+
+    fn tag_type(&self) -> Option<cedar_policy_core::entities::SchemaType> {
+        let tag_type: &crate::types::Type = self.validator_type.tag_type()?;
+        // As in `attr_type()`, `tag_type` is taken from a `ValidatorEntityType`
+        // which was constructed from a schema.
+        // PANIC SAFETY: see above
+        #[allow(clippy::expect_used)]
+        let core_schema_type: cedar_policy_core::entities::SchemaType = tag_type
+            .clone()
+            .try_into()
+            .expect("failed to convert validator type into Core SchemaType");
+        debug_assert!(tag_type.is_consistent_with(&core_schema_type));
+        Some(core_schema_type)
+    }
+
     fn allowed_parent_types(&self) -> Arc<HashSet<cedar_policy_core::ast::EntityType>> {
         Arc::clone(&self.allowed_parent_types)
     }
@@ -1379,7 +1539,8 @@ impl cedar_policy_core::entities::EntityTypeDescription for EntityTypeDescriptio
 /// Struct which carries enough information that it can impl Core's
 /// `ContextSchema` INVARIANT: The `Type` stored in this struct must be
 /// representable as a `SchemaType` to avoid panicking in `context_type`.
-struct ContextSchema(crate::types::Type);
+/// The map holds the default values of optional context attributes.
+struct ContextSchema(crate::types::Type, HashMap<SmolStr, serde_json::Value>);
 
 /// A `Type` contains all the information we need for a Core `ContextSchema`.
 impl cedar_policy_core::entities::ContextSchema for ContextSchema {
@@ -1391,6 +1552,14 @@ impl cedar_policy_core::entities::ContextSchema for ContextSchema {
             .try_into()
             .expect("failed to convert validator type into Core SchemaType")
     }
+
+    fn default_attrs<'s>(&'s self) -> Box<dyn Iterator<Item = (SmolStr, serde_json::Value)> + 's> {
+        Box::new(
+            self.1
+                .iter()
+                .map(|(attr, value)| (attr.clone(), value.clone())),
+        )
+    }
 }
 
 /// Contains entity type information for use by the validator. The contents of
@@ -1410,9 +1579,23 @@ pub struct ValidatorEntityType {
     /// The attributes associated with this entity. Keys are the attribute
     /// identifiers while the values are the type of the attribute.
     pub(crate) attributes: Attributes,
+
+    /// The type of the values of tags on this entity, or `None` if entities
+    /// of this type may not have tags.
+    pub(crate) tags: Option<Type>,
+
+    /// The default values for attributes that may be omitted from entity
+    /// data, which are filled in when entities are parsed with the schema.
+    #[serde(skip)]
+    pub(crate) attribute_defaults: HashMap<SmolStr, serde_json::Value>,
 }
 
 impl ValidatorEntityType {
+    /// Get the type of the values of tags on this entity, if it has tags
+    pub fn tag_type(&self) -> Option<&Type> {
+        self.tags.as_ref()
+    }
+
     /// Get the type of the attribute with the given name, if it exists
     pub fn attr(&self, attr: &str) -> Option<&AttributeType> {
         self.attributes.get_attr(attr)
@@ -1476,6 +1659,12 @@ pub struct ValidatorActionId {
     /// attribute identifiers while the values are the type of the attribute.
     pub(crate) context: Attributes,
 
+    /// The default values for context attributes that may be omitted from a
+    /// request context, which are filled in when the context is parsed with
+    /// the schema.
+    #[serde(skip)]
+    pub(crate) context_defaults: HashMap<SmolStr, serde_json::Value>,
+
     /// The attribute types for this action, used for typechecking.
     pub(crate) attribute_types: Attributes,
 
@@ -1511,7 +1700,7 @@ impl TCNode<EntityUID> for ValidatorActionId {
 }
 
 /// The principals and resources that an action can be applied to.
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub(crate) struct ValidatorApplySpec {
     /// The principal entity types the action can be applied to. This set may
     /// be a singleton set containing the unspecified entity type when the
@@ -1935,6 +2124,234 @@ mod test {
         }
     }
 
+    // Misspelled extension type "adress"
+    #[test]
+    fn test_from_schema_file_unknown_extension_type() {
+        let src = json!(
+        {
+            "entityTypes": {
+                "Wallet": {
+                    "shape": {
+                        "type": "Record",
+                        "attributes": {
+                            "owner": { "type": "Extension", "name": "adress" }
+                        }
+                    }
+                }
+            },
+            "actions": {}
+        });
+        let schema_file: NamespaceDefinition = serde_json::from_value(src).expect("Parse Error");
+        let schema: Result<ValidatorSchema> = schema_file.try_into();
+        match schema {
+            Ok(_) => panic!("from_schema_file should have failed"),
+            Err(SchemaError::UnknownExtensionType(name)) => assert_eq!(name.to_string(), "adress"),
+            e => panic!("Unexpected error from from_schema_file: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn attribute_defaults() {
+        let schema = ValidatorSchema::from_json_value(json!(
+        {
+            "": {
+                "entityTypes": {
+                    "Vault": {
+                        "shape": {
+                            "type": "Record",
+                            "attributes": {
+                                "paused": { "type": "Boolean", "required": false, "default": false },
+                                "fee": { "type": "Long", "required": false }
+                            }
+                        }
+                    }
+                },
+                "actions": {
+                    "withdraw": {
+                        "appliesTo": {
+                            "context": {
+                                "type": "Record",
+                                "attributes": {
+                                    "memo": { "type": "String", "required": false, "default": "" }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }))
+        .expect("should be a valid schema");
+        let vault = schema
+            .get_entity_type(&Name::from_str("Vault").expect("should be a valid name"))
+            .expect("should be declared");
+        // attributes with a default stay optional
+        assert!(
+            !vault
+                .attr("paused")
+                .expect("should be declared")
+                .is_required
+        );
+        assert!(!vault.attr("fee").expect("should be declared").is_required);
+        let withdraw = schema
+            .get_action_id(&EntityUID::from_str(r#"Action::"withdraw""#).expect("should parse"))
+            .expect("should be declared");
+        assert!(!withdraw.context.attrs["memo"].is_required);
+    }
+
+    #[test]
+    fn invalid_attribute_defaults() {
+        let schema_with_attribute = |attr: serde_json::Value| {
+            ValidatorSchema::from_json_value(json!(
+            {
+                "": {
+                    "entityTypes": {
+                        "Vault": {
+                            "shape": {
+                                "type": "Record",
+                                "attributes": { "paused": attr }
+                            }
+                        }
+                    },
+                    "actions": {}
+                }
+            }))
+        };
+        assert!(matches!(
+            schema_with_attribute(json!({ "type": "Boolean", "default": false })),
+            Err(SchemaError::InvalidAttributeDefault(ContextOrShape::EntityTypeShape(_), attr, _)) if attr == "paused"
+        ));
+        assert!(matches!(
+            schema_with_attribute(json!({ "type": "Boolean", "required": false, "default": 0 })),
+            Err(SchemaError::InvalidAttributeDefault(ContextOrShape::EntityTypeShape(_), attr, _)) if attr == "paused"
+        ));
+        assert!(matches!(
+            schema_with_attribute(json!(
+            {
+                "type": "Record",
+                "attributes": {
+                    "since": { "type": "Long", "required": false, "default": 0 }
+                }
+            })),
+            Err(SchemaError::UnsupportedFeature(UnsupportedFeature::NestedAttributeDefault(attr))) if attr == "since"
+        ));
+    }
+
+    #[test]
+    fn merge_schema_fragments() {
+        let vault = SchemaFragment::from_json_value(json!(
+        {
+            "Protocol": {
+                "entityTypes": {
+                    "Vault": {
+                        "memberOfTypes": ["DAO"],
+                        "shape": {
+                            "type": "Record",
+                            "attributes": { "owner": { "type": "String" } }
+                        }
+                    }
+                },
+                "actions": { "deposit": { "appliesTo": { "resourceTypes": ["Vault"] } } }
+            }
+        }))
+        .expect("should be a valid fragment");
+        // Declares `Protocol::DAO`, which `vault` refers to, and refers to
+        // `Protocol::deposit`, which `vault` declares.
+        let dao = SchemaFragment::from_json_value(json!(
+        {
+            "Protocol": {
+                "entityTypes": {
+                    "DAO": { "memberOfTypes": ["Org"] },
+                    "Org": {}
+                },
+                "actions": {}
+            },
+            "Token": {
+                "entityTypes": {},
+                "actions": {
+                    "transfer": {
+                        "memberOf": [{ "id": "deposit", "type": "Protocol::Action" }]
+                    }
+                }
+            }
+        }))
+        .expect("should be a valid fragment");
+        let schema = ValidatorSchema::try_from(vault.merge(dao).expect("should merge"))
+            .expect("should be a valid schema");
+
+        let name = |n: &str| Name::from_str(n).expect("should be a valid name");
+        let org = schema
+            .get_entity_type(&name("Protocol::Org"))
+            .expect("should be declared");
+        assert!(org.descendants.contains(&name("Protocol::DAO")));
+        assert!(org.descendants.contains(&name("Protocol::Vault")));
+        let deposit = schema
+            .get_action_id(
+                &EntityUID::from_str(r#"Protocol::Action::"deposit""#).expect("should parse"),
+            )
+            .expect("should be declared");
+        assert!(deposit
+            .descendants
+            .contains(&EntityUID::from_str(r#"Token::Action::"transfer""#).expect("should parse")));
+    }
+
+    #[test]
+    fn merge_conflicting_entity_type() {
+        let fragment = |vault: serde_json::Value| {
+            SchemaFragment::from_json_value(json!(
+            {
+                "": {
+                    "entityTypes": { "Vault": vault, "DAO": {} },
+                    "actions": {}
+                }
+            }))
+            .expect("should be a valid fragment")
+        };
+        let owner = |ty: &str| {
+            json!({
+                "shape": {
+                    "type": "Record",
+                    "attributes": { "owner": { "type": ty } }
+                }
+            })
+        };
+        fragment(owner("String"))
+            .merge(fragment(owner("String")))
+            .expect("identical declarations should merge");
+        match fragment(owner("String")).merge(fragment(owner("Long"))) {
+            Err(SchemaError::ConflictingEntityType(name)) => assert_eq!(name, "Vault"),
+            r => panic!("Expected ConflictingEntityType, got {:?}", r),
+        }
+        match fragment(json!({})).merge(fragment(json!({ "memberOfTypes": ["DAO"] }))) {
+            Err(SchemaError::ConflictingEntityType(name)) => assert_eq!(name, "Vault"),
+            r => panic!("Expected ConflictingEntityType, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn merge_conflicting_action() {
+        let fragment = |resource_types: serde_json::Value| {
+            SchemaFragment::from_json_value(json!(
+            {
+                "Protocol": {
+                    "entityTypes": { "Vault": {}, "Pool": {} },
+                    "actions": {
+                        "deposit": { "appliesTo": { "resourceTypes": resource_types } }
+                    }
+                }
+            }))
+            .expect("should be a valid fragment")
+        };
+        fragment(json!(["Vault", "Pool"]))
+            .merge(fragment(json!(["Pool", "Vault"])))
+            .expect("declarations differing in order should merge");
+        match fragment(json!(["Vault"])).merge(fragment(json!(["Pool"]))) {
+            Err(SchemaError::ConflictingAction(name)) => {
+                assert_eq!(name, r#"Protocol::Action::"deposit""#)
+            }
+            r => panic!("Expected ConflictingAction, got {:?}", r),
+        }
+    }
+
     // Trivial cycle in action hierarchy
     // view_photo -> view_photo
     #[test]
@@ -0,0 +1,703 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Comparison of two versions of a schema.
+
+use cedar_policy_core::ast::{EntityType, EntityUID, Name};
+use itertools::Itertools;
+use smol_str::SmolStr;
+use std::collections::HashSet;
+
+use crate::types::{Attributes, Type};
+use crate::ValidatorSchema;
+
+/// An entity type or action context, as a record of attributes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordOf {
+    /// The attributes of an entity type
+    EntityType(Name),
+    /// The context of an action
+    Context(EntityUID),
+}
+
+impl std::fmt::Display for RecordOf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EntityType(name) => write!(f, "entity type `{name}`"),
+            Self::Context(action) => write!(f, "the context of action `{action}`"),
+        }
+    }
+}
+
+/// Whether an action may apply to a principal or a resource
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppliesToPosition {
+    /// The principal of the action
+    Principal,
+    /// The resource of the action
+    Resource,
+}
+
+impl std::fmt::Display for AppliesToPosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Principal => write!(f, "principal"),
+            Self::Resource => write!(f, "resource"),
+        }
+    }
+}
+
+/// A difference between two versions of a schema, as found by
+/// [`ValidatorSchema::diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaChange {
+    /// An entity type was added
+    EntityTypeAdded(Name),
+    /// An entity type was removed
+    EntityTypeRemoved(Name),
+    /// An entity type may now be a member of `parent`
+    ParentAdded {
+        /// The entity type
+        entity_type: Name,
+        /// Its new parent type
+        parent: Name,
+    },
+    /// An entity type may no longer be a member of `parent`
+    ParentRemoved {
+        /// The entity type
+        entity_type: Name,
+        /// Its former parent type
+        parent: Name,
+    },
+    /// The type of the tags of an entity type changed. `None` means that
+    /// entities of the type may not have tags.
+    TagsChanged {
+        /// The entity type
+        entity_type: Name,
+        /// The old type of the tags
+        old: Option<Type>,
+        /// The new type of the tags
+        new: Option<Type>,
+    },
+    /// An attribute was added to an entity type or context
+    AttributeAdded {
+        /// The entity type or context
+        record: RecordOf,
+        /// The name of the attribute
+        attr: SmolStr,
+        /// Whether the attribute is required
+        required: bool,
+    },
+    /// An attribute was removed from an entity type or context
+    AttributeRemoved {
+        /// The entity type or context
+        record: RecordOf,
+        /// The name of the attribute
+        attr: SmolStr,
+    },
+    /// The type of an attribute of an entity type or context changed
+    AttributeTypeChanged {
+        /// The entity type or context
+        record: RecordOf,
+        /// The name of the attribute
+        attr: SmolStr,
+        /// The old type of the attribute
+        old: Type,
+        /// The new type of the attribute
+        new: Type,
+    },
+    /// An attribute of an entity type or context became required or optional
+    AttributeRequiredChanged {
+        /// The entity type or context
+        record: RecordOf,
+        /// The name of the attribute
+        attr: SmolStr,
+        /// Whether the attribute is now required
+        required: bool,
+    },
+    /// An action was added
+    ActionAdded(EntityUID),
+    /// An action was removed
+    ActionRemoved(EntityUID),
+    /// An action may now be a member of `parent`
+    ActionParentAdded {
+        /// The action
+        action: EntityUID,
+        /// Its new parent action
+        parent: EntityUID,
+    },
+    /// An action may no longer be a member of `parent`
+    ActionParentRemoved {
+        /// The action
+        action: EntityUID,
+        /// Its former parent action
+        parent: EntityUID,
+    },
+    /// An action may now apply to a principal or resource of `entity_type`
+    AppliesToAdded {
+        /// The action
+        action: EntityUID,
+        /// Whether `entity_type` is a principal or resource type
+        position: AppliesToPosition,
+        /// The entity type
+        entity_type: EntityType,
+    },
+    /// An action may no longer apply to a principal or resource of
+    /// `entity_type`
+    AppliesToRemoved {
+        /// The action
+        action: EntityUID,
+        /// Whether `entity_type` is a principal or resource type
+        position: AppliesToPosition,
+        /// The entity type
+        entity_type: EntityType,
+    },
+}
+
+impl SchemaChange {
+    /// Whether this change may break policies, entity files or requests that
+    /// were valid against the old version of the schema.
+    ///
+    /// Removing entity types, actions, attributes, parents, or the entity
+    /// types an action applies to is breaking, as are adding a required
+    /// attribute and changing the type of an attribute or of tags. Other
+    /// additions are compatible, as is allowing tags on an entity type which
+    /// previously had none.
+    pub fn is_breaking(&self) -> bool {
+        match self {
+            Self::EntityTypeAdded(_)
+            | Self::ParentAdded { .. }
+            | Self::ActionAdded(_)
+            | Self::ActionParentAdded { .. }
+            | Self::AppliesToAdded { .. } => false,
+            Self::AttributeAdded { required, .. } => *required,
+            Self::TagsChanged { old, .. } => old.is_some(),
+            Self::EntityTypeRemoved(_)
+            | Self::ParentRemoved { .. }
+            | Self::AttributeRemoved { .. }
+            | Self::AttributeTypeChanged { .. }
+            // An attribute which becomes optional must be guarded with `has`
+            // in policies, and one which becomes required must be present in
+            // entity files
+            | Self::AttributeRequiredChanged { .. }
+            | Self::ActionRemoved(_)
+            | Self::ActionParentRemoved { .. }
+            | Self::AppliesToRemoved { .. } => true,
+        }
+    }
+}
+
+impl std::fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EntityTypeAdded(name) => write!(f, "added entity type `{name}`"),
+            Self::EntityTypeRemoved(name) => write!(f, "removed entity type `{name}`"),
+            Self::ParentAdded {
+                entity_type,
+                parent,
+            } => write!(
+                f,
+                "entity type `{entity_type}` may now be a member of `{parent}`"
+            ),
+            Self::ParentRemoved {
+                entity_type,
+                parent,
+            } => write!(
+                f,
+                "entity type `{entity_type}` may no longer be a member of `{parent}`"
+            ),
+            Self::TagsChanged {
+                entity_type,
+                old,
+                new,
+            } => match (old, new) {
+                (None, Some(new)) => write!(f, "entity type `{entity_type}` may now have tags of type {new}"),
+                (Some(_), None) => write!(f, "entity type `{entity_type}` may no longer have tags"),
+                (Some(old), Some(new)) => write!(
+                    f,
+                    "changed the type of the tags of entity type `{entity_type}` from {old} to {new}"
+                ),
+                (None, None) => write!(f, "entity type `{entity_type}` has no tags"),
+            },
+            Self::AttributeAdded {
+                record,
+                attr,
+                required,
+            } => write!(
+                f,
+                "added {} attribute `{attr}` to {record}",
+                if *required { "required" } else { "optional" }
+            ),
+            Self::AttributeRemoved { record, attr } => {
+                write!(f, "removed attribute `{attr}` from {record}")
+            }
+            Self::AttributeTypeChanged {
+                record,
+                attr,
+                old,
+                new,
+            } => write!(
+                f,
+                "changed the type of attribute `{attr}` of {record} from {old} to {new}"
+            ),
+            Self::AttributeRequiredChanged {
+                record,
+                attr,
+                required,
+            } => write!(
+                f,
+                "attribute `{attr}` of {record} is now {}",
+                if *required { "required" } else { "optional" }
+            ),
+            Self::ActionAdded(action) => write!(f, "added action `{action}`"),
+            Self::ActionRemoved(action) => write!(f, "removed action `{action}`"),
+            Self::ActionParentAdded { action, parent } => {
+                write!(f, "action `{action}` is now a member of `{parent}`")
+            }
+            Self::ActionParentRemoved { action, parent } => {
+                write!(f, "action `{action}` is no longer a member of `{parent}`")
+            }
+            Self::AppliesToAdded {
+                action,
+                position,
+                entity_type,
+            } => write!(
+                f,
+                "action `{action}` now applies to {position} type `{entity_type}`"
+            ),
+            Self::AppliesToRemoved {
+                action,
+                position,
+                entity_type,
+            } => write!(
+                f,
+                "action `{action}` no longer applies to {position} type `{entity_type}`"
+            ),
+        }
+    }
+}
+
+impl ValidatorSchema {
+    /// Find the changes between this schema and a `new` version of it. The
+    /// changes are ordered by the entity type or action they affect, with
+    /// entity types first.
+    pub fn diff(&self, new: &ValidatorSchema) -> Vec<SchemaChange> {
+        let mut changes = Vec::new();
+
+        let entity_types = self
+            .known_entity_types()
+            .chain(new.known_entity_types())
+            .unique()
+            .sorted_by_key(|name| name.to_string());
+        for name in entity_types {
+            let (old_ety, new_ety) = match (self.get_entity_type(name), new.get_entity_type(name)) {
+                (Some(old_ety), Some(new_ety)) => (old_ety, new_ety),
+                (None, _) => {
+                    changes.push(SchemaChange::EntityTypeAdded(name.clone()));
+                    continue;
+                }
+                (_, None) => {
+                    changes.push(SchemaChange::EntityTypeRemoved(name.clone()));
+                    continue;
+                }
+            };
+            // Parents are stored as the (transitive) descendants of each
+            // parent, so look for `name` among them.
+            let parents = |schema: &ValidatorSchema| {
+                schema
+                    .entity_types()
+                    .filter(|(_, ety)| ety.descendants.contains(name))
+                    .map(|(parent, _)| parent.clone())
+                    .collect::<HashSet<_>>()
+            };
+            let (old_parents, new_parents) = (parents(self), parents(new));
+            changes.extend(
+                new_parents
+                    .difference(&old_parents)
+                    .sorted_by_key(|parent| parent.to_string())
+                    .map(|parent| SchemaChange::ParentAdded {
+                        entity_type: name.clone(),
+                        parent: parent.clone(),
+                    }),
+            );
+            // A parent which is removed because it was itself removed from
+            // the schema is covered by `EntityTypeRemoved`.
+            changes.extend(
+                old_parents
+                    .difference(&new_parents)
+                    .filter(|parent| new.is_known_entity_type(parent))
+                    .sorted_by_key(|parent| parent.to_string())
+                    .map(|parent| SchemaChange::ParentRemoved {
+                        entity_type: name.clone(),
+                        parent: parent.clone(),
+                    }),
+            );
+            if old_ety.tags != new_ety.tags {
+                changes.push(SchemaChange::TagsChanged {
+                    entity_type: name.clone(),
+                    old: old_ety.tags.clone(),
+                    new: new_ety.tags.clone(),
+                });
+            }
+            diff_attributes(
+                RecordOf::EntityType(name.clone()),
+                &old_ety.attributes,
+                &new_ety.attributes,
+                &mut changes,
+            );
+        }
+
+        let actions = self
+            .known_action_ids()
+            .chain(new.known_action_ids())
+            .unique()
+            .sorted_by_key(|euid| euid.to_string());
+        for euid in actions {
+            let (old_action, new_action) = match (self.get_action_id(euid), new.get_action_id(euid))
+            {
+                (Some(old_action), Some(new_action)) => (old_action, new_action),
+                (None, _) => {
+                    changes.push(SchemaChange::ActionAdded(euid.clone()));
+                    continue;
+                }
+                (_, None) => {
+                    changes.push(SchemaChange::ActionRemoved(euid.clone()));
+                    continue;
+                }
+            };
+            let parents = |schema: &ValidatorSchema| {
+                schema
+                    .known_action_ids()
+                    .filter(|parent| {
+                        schema
+                            .get_action_id(parent)
+                            .is_some_and(|action| action.descendants.contains(euid))
+                    })
+                    .cloned()
+                    .collect::<HashSet<_>>()
+            };
+            let (old_parents, new_parents) = (parents(self), parents(new));
+            changes.extend(
+                new_parents
+                    .difference(&old_parents)
+                    .sorted_by_key(|parent| parent.to_string())
+                    .map(|parent| SchemaChange::ActionParentAdded {
+                        action: euid.clone(),
+                        parent: parent.clone(),
+                    }),
+            );
+            changes.extend(
+                old_parents
+                    .difference(&new_parents)
+                    .filter(|parent| new.is_known_action_id(parent))
+                    .sorted_by_key(|parent| parent.to_string())
+                    .map(|parent| SchemaChange::ActionParentRemoved {
+                        action: euid.clone(),
+                        parent: parent.clone(),
+                    }),
+            );
+            for (position, old_types, new_types) in [
+                (
+                    AppliesToPosition::Principal,
+                    old_action
+                        .applies_to
+                        .applicable_principal_types()
+                        .collect::<HashSet<_>>(),
+                    new_action
+                        .applies_to
+                        .applicable_principal_types()
+                        .collect::<HashSet<_>>(),
+                ),
+                (
+                    AppliesToPosition::Resource,
+                    old_action
+                        .applies_to
+                        .applicable_resource_types()
+                        .collect::<HashSet<_>>(),
+                    new_action
+                        .applies_to
+                        .applicable_resource_types()
+                        .collect::<HashSet<_>>(),
+                ),
+            ] {
+                changes.extend(
+                    new_types
+                        .difference(&old_types)
+                        .sorted_by_key(|ety| ety.to_string())
+                        .map(|ety| SchemaChange::AppliesToAdded {
+                            action: euid.clone(),
+                            position,
+                            entity_type: (*ety).clone(),
+                        }),
+                );
+                // As for parents, entity types which were removed from the
+                // schema are covered by `EntityTypeRemoved`.
+                changes.extend(
+                    old_types
+                        .difference(&new_types)
+                        .filter(|ety| match ety {
+                            EntityType::Concrete(name) => new.is_known_entity_type(name),
+                            EntityType::Unspecified => true,
+                        })
+                        .sorted_by_key(|ety| ety.to_string())
+                        .map(|ety| SchemaChange::AppliesToRemoved {
+                            action: euid.clone(),
+                            position,
+                            entity_type: (*ety).clone(),
+                        }),
+                );
+            }
+            diff_attributes(
+                RecordOf::Context(euid.clone()),
+                &old_action.context,
+                &new_action.context,
+                &mut changes,
+            );
+        }
+
+        changes
+    }
+}
+
+/// Push the changes between the `old` and `new` attributes of `record` onto
+/// `changes`, ordered by attribute name.
+fn diff_attributes(
+    record: RecordOf,
+    old: &Attributes,
+    new: &Attributes,
+    changes: &mut Vec<SchemaChange>,
+) {
+    let attrs = old
+        .iter()
+        .chain(new.iter())
+        .map(|(attr, _)| attr)
+        .unique()
+        .sorted();
+    for attr in attrs {
+        match (old.get_attr(attr), new.get_attr(attr)) {
+            (None, Some(new_attr)) => changes.push(SchemaChange::AttributeAdded {
+                record: record.clone(),
+                attr: attr.clone(),
+                required: new_attr.is_required,
+            }),
+            (Some(_), None) => changes.push(SchemaChange::AttributeRemoved {
+                record: record.clone(),
+                attr: attr.clone(),
+            }),
+            (Some(old_attr), Some(new_attr)) => {
+                if old_attr.attr_type != new_attr.attr_type {
+                    changes.push(SchemaChange::AttributeTypeChanged {
+                        record: record.clone(),
+                        attr: attr.clone(),
+                        old: old_attr.attr_type.clone(),
+                        new: new_attr.attr_type.clone(),
+                    });
+                }
+                if old_attr.is_required != new_attr.is_required {
+                    changes.push(SchemaChange::AttributeRequiredChanged {
+                        record: record.clone(),
+                        attr: attr.clone(),
+                        required: new_attr.is_required,
+                    });
+                }
+            }
+            (None, None) => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn schema(json: serde_json::Value) -> ValidatorSchema {
+        ValidatorSchema::from_json_value(json).expect("should be a valid schema")
+    }
+
+    fn diff(old: serde_json::Value, new: serde_json::Value) -> Vec<String> {
+        let changes = schema(old).diff(&schema(new));
+        changes
+            .iter()
+            .map(|change| {
+                format!(
+                    "{}: {change}",
+                    if change.is_breaking() {
+                        "breaking"
+                    } else {
+                        "compatible"
+                    }
+                )
+            })
+            .collect()
+    }
+
+    fn vault_schema(
+        attrs: serde_json::Value,
+        resource_types: serde_json::Value,
+    ) -> serde_json::Value {
+        json!(
+        {
+            "": {
+                "entityTypes": {
+                    "User": {},
+                    "DAO": {},
+                    "Vault": {
+                        "memberOfTypes": ["DAO"],
+                        "shape": { "type": "Record", "attributes": attrs }
+                    }
+                },
+                "actions": {
+                    "withdraw": {
+                        "appliesTo": {
+                            "principalTypes": ["User"],
+                            "resourceTypes": resource_types,
+                            "context": {
+                                "type": "Record",
+                                "attributes": { "amount": { "type": "Long" } }
+                            }
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn no_changes() {
+        let src = vault_schema(json!({ "owner": { "type": "String" } }), json!(["Vault"]));
+        assert_eq!(diff(src.clone(), src), Vec::<String>::new());
+    }
+
+    #[test]
+    fn compatible_changes() {
+        let old = vault_schema(json!({ "owner": { "type": "String" } }), json!(["Vault"]));
+        let mut new = vault_schema(
+            json!({
+                "owner": { "type": "String" },
+                "limit": { "type": "Long", "required": false }
+            }),
+            json!(["Vault", "DAO"]),
+        );
+        new[""]["entityTypes"]["Pool"] = json!({});
+        new[""]["actions"]["deposit"] = json!({});
+        assert_eq!(
+            diff(old, new),
+            vec![
+                "compatible: added entity type `Pool`",
+                "compatible: added optional attribute `limit` to entity type `Vault`",
+                "compatible: added action `Action::\"deposit\"`",
+                "compatible: action `Action::\"withdraw\"` now applies to resource type `DAO`",
+            ]
+        );
+    }
+
+    #[test]
+    fn breaking_changes() {
+        let old = vault_schema(
+            json!({
+                "owner": { "type": "String" },
+                "balance": { "type": "Long" },
+                "limit": { "type": "Long", "required": false }
+            }),
+            json!(["Vault", "DAO"]),
+        );
+        let mut new = vault_schema(
+            json!({
+                "balance": { "type": "String" },
+                "limit": { "type": "Long" },
+                "admin": { "type": "Entity", "name": "User" }
+            }),
+            json!(["Vault"]),
+        );
+        new[""]["entityTypes"]["Vault"]["memberOfTypes"] = json!([]);
+        new[""]["actions"]["withdraw"]["appliesTo"]["context"]["attributes"] = json!({});
+        assert_eq!(
+            diff(old, new),
+            vec![
+                "breaking: entity type `Vault` may no longer be a member of `DAO`",
+                "breaking: added required attribute `admin` to entity type `Vault`",
+                "breaking: changed the type of attribute `balance` of entity type `Vault` from {\"type\":\"Long\"} to {\"type\":\"String\"}",
+                "breaking: attribute `limit` of entity type `Vault` is now required",
+                "breaking: removed attribute `owner` from entity type `Vault`",
+                "breaking: action `Action::\"withdraw\"` no longer applies to resource type `DAO`",
+                "breaking: removed attribute `amount` from the context of action `Action::\"withdraw\"`",
+            ]
+        );
+    }
+
+    #[test]
+    fn removed_declarations() {
+        let old = vault_schema(json!({}), json!(["Vault"]));
+        let new = json!(
+        {
+            "": {
+                "entityTypes": { "User": {}, "Vault": {} },
+                "actions": {}
+            }
+        });
+        assert_eq!(
+            diff(old, new),
+            vec![
+                "breaking: removed entity type `DAO`",
+                "breaking: removed action `Action::\"withdraw\"`",
+            ]
+        );
+    }
+
+    #[test]
+    fn tags_and_action_parents() {
+        let old = json!(
+        {
+            "": {
+                "entityTypes": { "Vault": {} },
+                "actions": {
+                    "all": {},
+                    "withdraw": { "memberOf": [{ "id": "all" }] }
+                }
+            }
+        });
+        let new = json!(
+        {
+            "": {
+                "entityTypes": { "Vault": { "tags": { "type": "String" } } },
+                "actions": {
+                    "all": {},
+                    "withdraw": {}
+                }
+            }
+        });
+        assert_eq!(
+            diff(old.clone(), new.clone()),
+            vec![
+                "compatible: entity type `Vault` may now have tags of type {\"type\":\"String\"}",
+                // Without members, `all` is no longer an action group
+                "compatible: action `Action::\"all\"` now applies to principal type `<Unspecified>`",
+                "compatible: action `Action::\"all\"` now applies to resource type `<Unspecified>`",
+                "breaking: action `Action::\"withdraw\"` is no longer a member of `Action::\"all\"`",
+            ]
+        );
+        assert_eq!(
+            diff(new, old),
+            vec![
+                "breaking: entity type `Vault` may no longer have tags",
+                "breaking: action `Action::\"all\"` no longer applies to principal type `<Unspecified>`",
+                "breaking: action `Action::\"all\"` no longer applies to resource type `<Unspecified>`",
+                "compatible: action `Action::\"withdraw\"` is now a member of `Action::\"all\"`",
+            ]
+        );
+    }
+}
@@ -23,7 +23,7 @@ use serde_with::serde_as;
 use smol_str::SmolStr;
 use std::collections::{BTreeMap, HashMap, HashSet};
 
-use crate::Result;
+use crate::{Result, SchemaError};
 
 /// A SchemaFragment describe the types for a given instance of Cedar.
 /// SchemaFragments are composed of Entity Types and Action Types. The
@@ -48,6 +48,101 @@ impl SchemaFragment {
     pub fn from_file(file: impl std::io::Read) -> Result<Self> {
         serde_json::from_reader(file).map_err(Into::into)
     }
+
+    /// Convert this `SchemaFragment` to a JSON value. Unlike serializing it
+    /// directly, namespaces and the declarations in each namespace are sorted
+    /// by name, so the output is deterministic.
+    pub fn to_json_value(&self) -> Result<serde_json::Value> {
+        #[derive(Serialize)]
+        struct SortedNamespaceDefinition<'a> {
+            #[serde(rename = "commonTypes")]
+            #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+            common_types: BTreeMap<&'a SmolStr, &'a SchemaType>,
+            #[serde(rename = "entityTypes")]
+            entity_types: BTreeMap<&'a SmolStr, &'a EntityType>,
+            actions: BTreeMap<&'a SmolStr, &'a ActionType>,
+        }
+        let sorted = self
+            .0
+            .iter()
+            .map(|(namespace, definition)| {
+                (
+                    namespace,
+                    SortedNamespaceDefinition {
+                        common_types: definition.common_types.iter().collect(),
+                        entity_types: definition.entity_types.iter().collect(),
+                        actions: definition.actions.iter().collect(),
+                    },
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+        serde_json::to_value(sorted).map_err(Into::into)
+    }
+
+    /// Combine this fragment with `other`, e.g., to compose fragments written
+    /// separately for several contracts into one, before building a schema
+    /// from it. Since the fragments are combined before they are checked,
+    /// either may refer to the declarations of the other. A common type,
+    /// entity type or action may be declared in both fragments so long as
+    /// both declarations are the same, including the types or actions it is
+    /// a member of; otherwise this returns
+    /// `SchemaError::ConflictingCommonType`,
+    /// `SchemaError::ConflictingEntityType` or `SchemaError::ConflictingAction`.
+    pub fn merge(mut self, other: SchemaFragment) -> Result<SchemaFragment> {
+        for (namespace, definition) in other.0 {
+            let qualified = |name: &str| {
+                if namespace.is_empty() {
+                    name.to_string()
+                } else {
+                    format!("{namespace}::{name}")
+                }
+            };
+            let existing = self.0.entry(namespace.clone()).or_insert_with(|| {
+                NamespaceDefinition::new(std::iter::empty(), std::iter::empty())
+            });
+            merge_declarations(
+                &mut existing.common_types,
+                definition.common_types,
+                |a, b| a == b,
+                |name| SchemaError::ConflictingCommonType(qualified(name)),
+            )?;
+            merge_declarations(
+                &mut existing.entity_types,
+                definition.entity_types,
+                EntityType::same_declaration,
+                |name| SchemaError::ConflictingEntityType(qualified(name)),
+            )?;
+            merge_declarations(
+                &mut existing.actions,
+                definition.actions,
+                ActionType::same_declaration,
+                |name| {
+                    SchemaError::ConflictingAction(format!("{}::\"{name}\"", qualified("Action")))
+                },
+            )?;
+        }
+        Ok(self)
+    }
+}
+
+/// Add the `declarations` to `existing`, failing with `conflict` for a name
+/// declared in both unless `same` holds for the two declarations
+fn merge_declarations<T>(
+    existing: &mut HashMap<SmolStr, T>,
+    declarations: HashMap<SmolStr, T>,
+    same: impl Fn(&T, &T) -> bool,
+    conflict: impl Fn(&str) -> SchemaError,
+) -> Result<()> {
+    for (name, declaration) in declarations {
+        match existing.get(&name) {
+            Some(other) if !same(other, &declaration) => return Err(conflict(&name)),
+            Some(_) => (),
+            None => {
+                existing.insert(name, declaration);
+            }
+        }
+    }
+    Ok(())
 }
 
 /// A single namespace definition from a SchemaFragment.
@@ -91,6 +186,22 @@ pub struct EntityType {
     pub member_of_types: Vec<SmolStr>,
     #[serde(default)]
     pub shape: AttributesOrContext,
+    /// The type of the values of tags on entities of this type. Entities of
+    /// this type may not have tags if this is `None`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<SchemaType>,
+}
+
+impl EntityType {
+    /// Whether `self` and `other` declare the same entity type, whatever the
+    /// order of their `memberOfTypes`
+    fn same_declaration(&self, other: &Self) -> bool {
+        self.member_of_types.iter().collect::<HashSet<_>>()
+            == other.member_of_types.iter().collect::<HashSet<_>>()
+            && self.shape == other.shape
+            && self.tags == other.tags
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -125,15 +236,46 @@ pub struct ActionType {
     /// `cedar_policy_core::entities::json::jsonvalue::JSONValue` which is the
     /// canonical representation of a cedar value as JSON.
     #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub attributes: Option<HashMap<SmolStr, JSONValue>>,
     #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "appliesTo")]
     pub applies_to: Option<ApplySpec>,
     #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "memberOf")]
     pub member_of: Option<Vec<ActionEntityUID>>,
 }
 
+impl ActionType {
+    /// Whether `self` and `other` declare the same action, whatever the order
+    /// of the actions it is a member of and of the types it applies to
+    fn same_declaration(&self, other: &Self) -> bool {
+        fn set<T: Eq + std::hash::Hash>(list: Option<&Vec<T>>) -> Option<HashSet<&T>> {
+            list.map(|list| list.iter().collect())
+        }
+        /// The types and context of the apply spec of `action`
+        type Spec<'a> = (
+            Option<HashSet<&'a SmolStr>>,
+            Option<HashSet<&'a SmolStr>>,
+            &'a AttributesOrContext,
+        );
+        fn applies_to(action: &ActionType) -> Option<Spec<'_>> {
+            action.applies_to.as_ref().map(|spec| {
+                (
+                    set(spec.principal_types.as_ref()),
+                    set(spec.resource_types.as_ref()),
+                    &spec.context,
+                )
+            })
+        }
+        self.attributes == other.attributes
+            && set(self.member_of.as_ref()) == set(other.member_of.as_ref())
+            && applies_to(self) == applies_to(other)
+    }
+}
+
 /// The apply spec specifies what principals and resources an action can be used
 /// with.  This specification can either be done through containing to entity
 /// types. The fields of this record are optional so that they can be omitted to
@@ -146,16 +288,18 @@ pub struct ActionType {
 #[serde(deny_unknown_fields)]
 pub struct ApplySpec {
     #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "resourceTypes")]
     pub resource_types: Option<Vec<SmolStr>>,
     #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "principalTypes")]
     pub principal_types: Option<Vec<SmolStr>>,
     #[serde(default)]
     pub context: AttributesOrContext,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct ActionEntityUID {
     pub id: SmolStr,
@@ -592,6 +736,36 @@ pub struct TypeOfAttribute {
     pub ty: SchemaType,
     #[serde(default = "record_attribute_required_default")]
     pub required: bool,
+    /// The value of an optional attribute of an entity type or action context
+    /// when it is not given in the entity or context JSON.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<AttributeDefault>,
+}
+
+/// The default value of an attribute, written as in entity and context JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AttributeDefault(pub serde_json::Value);
+
+// `serde_json::Value` is not `Ord`, so default values are ordered by their JSON
+// text. This is only needed so that `SchemaType` can be `Ord`.
+impl PartialOrd for AttributeDefault {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for AttributeDefault {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.to_string().cmp(&other.0.to_string())
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for AttributeDefault {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self(serde_json::Value::from(i64::arbitrary(u)?)))
+    }
 }
 
 /// Defines the default value for `additionalAttributes` on records and
@@ -753,6 +927,63 @@ mod test {
         assert_eq!(namespace, "foo::foo::bar::baz".to_string());
     }
 
+    #[test]
+    fn test_to_json_value() {
+        let src = serde_json::json!(
+        {
+            "foo": {
+                "entityTypes": {
+                    "User": { "memberOfTypes": ["Group"] },
+                    "Group": {}
+                },
+                "actions": {
+                    "view": { "appliesTo": { "principalTypes": ["User"] } },
+                    "edit": { "memberOf": [{"id": "view"}] }
+                }
+            },
+            "": {
+                "commonTypes": { "Name": { "type": "String" } },
+                "entityTypes": {},
+                "actions": {}
+            }
+        });
+        let schema = SchemaFragment::from_json_value(src).expect("Parse Error");
+        let json = schema.to_json_value().expect("should serialize");
+        assert_eq!(
+            serde_json::to_string(&json).expect("should serialize"),
+            serde_json::to_string(&serde_json::json!(
+            {
+                "": {
+                    "commonTypes": { "Name": { "type": "String" } },
+                    "entityTypes": {},
+                    "actions": {}
+                },
+                "foo": {
+                    "entityTypes": {
+                        "Group": {
+                            "memberOfTypes": [],
+                            "shape": { "type": "Record", "attributes": {}, "additionalAttributes": false }
+                        },
+                        "User": {
+                            "memberOfTypes": ["Group"],
+                            "shape": { "type": "Record", "attributes": {}, "additionalAttributes": false }
+                        }
+                    },
+                    "actions": {
+                        "edit": { "memberOf": [{"id": "view", "type": null}] },
+                        "view": {
+                            "appliesTo": {
+                                "principalTypes": ["User"],
+                                "context": { "type": "Record", "attributes": {}, "additionalAttributes": false }
+                            }
+                        }
+                    }
+                }
+            }))
+            .expect("should serialize")
+        );
+    }
+
     #[test]
     #[should_panic(expected = "unknown field `requiredddddd`")]
     fn test_schema_file_with_misspelled_required() {
@@ -0,0 +1,331 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Cross-referencing of the elements declared in a schema with their uses in
+//! a policy set.
+
+use cedar_policy_core::ast::{
+    EntityType, EntityUID, Expr, ExprKind, Literal, Name, PolicySet, Var,
+};
+use itertools::Itertools;
+use smol_str::SmolStr;
+use std::collections::HashSet;
+
+use crate::typecheck::{PolicyCheck, Typechecker};
+use crate::types::{EntityRecordKind, RequestEnv, Type};
+use crate::{RecordOf, ValidationMode, ValidatorSchema};
+
+/// A schema element which is declared but not used by a policy set, or an
+/// attribute which is used but not declared, as found by
+/// [`ValidatorSchema::check_usage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaUsageIssue {
+    /// No policy refers to an entity of this type or may apply to a request
+    /// with a principal or resource of this type
+    UnusedEntityType(Name),
+    /// No policy refers to this action or may apply to a request for it
+    UnusedAction(EntityUID),
+    /// No policy accesses this attribute of an entity type or context
+    UnusedAttribute {
+        /// The entity type or context
+        record: RecordOf,
+        /// The name of the attribute
+        attr: SmolStr,
+    },
+    /// A policy checks for this attribute on an entity which may be of this
+    /// type, but the attribute is not declared for the type. This only
+    /// happens for policies validated in permissive mode, where an entity
+    /// may be one of several types.
+    UndeclaredAttribute {
+        /// The entity type
+        entity_type: Name,
+        /// The name of the attribute
+        attr: SmolStr,
+    },
+}
+
+impl std::fmt::Display for SchemaUsageIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnusedEntityType(name) => write!(f, "entity type `{name}` is never used"),
+            Self::UnusedAction(action) => write!(f, "action `{action}` is never used"),
+            Self::UnusedAttribute { record, attr } => {
+                write!(f, "attribute `{attr}` of {record} is never used")
+            }
+            Self::UndeclaredAttribute { entity_type, attr } => write!(
+                f,
+                "attribute `{attr}` is used but not declared for entity type `{entity_type}`"
+            ),
+        }
+    }
+}
+
+/// The schema elements used by a policy set
+#[derive(Debug, Default)]
+struct Usage {
+    entity_types: HashSet<Name>,
+    actions: HashSet<EntityUID>,
+    attributes: HashSet<(Name, SmolStr)>,
+    context_attributes: HashSet<(EntityUID, SmolStr)>,
+    undeclared_attributes: HashSet<(Name, SmolStr)>,
+}
+
+impl ValidatorSchema {
+    /// Find the entity types, actions and attributes declared in this schema
+    /// which are not used by `policies`, and the attributes used by
+    /// `policies` which are not declared.
+    ///
+    /// Uses are found by typechecking each policy in `mode` under every
+    /// request environment allowed by the schema, so a policy which is not
+    /// valid in an environment does not use anything in it. Attributes of
+    /// unused entity types and the context of unused actions are not reported
+    /// separately. The issues are ordered by the entity type or action they
+    /// affect, with entity types first.
+    pub fn check_usage(&self, policies: &PolicySet, mode: ValidationMode) -> Vec<SchemaUsageIssue> {
+        let typechecker = Typechecker::new(self, mode);
+        let mut usage = Usage::default();
+        for template in policies.all_templates() {
+            for (env, check) in typechecker.typecheck_by_request_env(template) {
+                if let PolicyCheck::Success(expr) = check {
+                    self.record_usage(&env, &expr, &mut usage);
+                }
+            }
+        }
+
+        let mut issues = Vec::new();
+        for (name, entity_type) in self
+            .entity_types()
+            .sorted_by_key(|(name, _)| name.to_string())
+        {
+            if !usage.entity_types.contains(name) {
+                issues.push(SchemaUsageIssue::UnusedEntityType(name.clone()));
+                continue;
+            }
+            issues.extend(
+                entity_type
+                    .attributes()
+                    .filter(|(attr, _)| {
+                        !usage
+                            .attributes
+                            .contains(&(name.clone(), SmolStr::clone(attr)))
+                    })
+                    .map(|(attr, _)| SchemaUsageIssue::UnusedAttribute {
+                        record: RecordOf::EntityType(name.clone()),
+                        attr: attr.clone(),
+                    }),
+            );
+            issues.extend(
+                usage
+                    .undeclared_attributes
+                    .iter()
+                    .filter(|(entity_type, _)| entity_type == name)
+                    .sorted()
+                    .map(|(_, attr)| SchemaUsageIssue::UndeclaredAttribute {
+                        entity_type: name.clone(),
+                        attr: attr.clone(),
+                    }),
+            );
+        }
+        for euid in self
+            .known_action_ids()
+            .sorted_by_key(|euid| euid.to_string())
+        {
+            if !usage.actions.contains(euid) {
+                issues.push(SchemaUsageIssue::UnusedAction(euid.clone()));
+                continue;
+            }
+            if let Some(action) = self.get_action_id(euid) {
+                issues.extend(
+                    action
+                        .context()
+                        .filter(|(attr, _)| {
+                            !usage
+                                .context_attributes
+                                .contains(&(euid.clone(), SmolStr::clone(attr)))
+                        })
+                        .map(|(attr, _)| SchemaUsageIssue::UnusedAttribute {
+                            record: RecordOf::Context(euid.clone()),
+                            attr: attr.clone(),
+                        }),
+                );
+            }
+        }
+        issues
+    }
+
+    /// Record the schema elements used by `expr`, a policy condition which
+    /// typechecked in the request environment `env`
+    fn record_usage(&self, env: &RequestEnv<'_>, expr: &Expr<Option<Type>>, usage: &mut Usage) {
+        usage.actions.insert(env.action.clone());
+        for ety in [env.principal, env.resource] {
+            if let EntityType::Concrete(name) = ety {
+                usage.entity_types.insert(name.clone());
+            }
+        }
+        for e in expr.subexpressions() {
+            if let Some(ty) = e.data() {
+                entity_types_of(ty, &mut usage.entity_types);
+            }
+            match e.expr_kind() {
+                ExprKind::Lit(Literal::EntityUID(euid)) if self.is_known_action_id(euid) => {
+                    usage.actions.insert(euid.as_ref().clone());
+                }
+                ExprKind::GetAttr { expr, attr } | ExprKind::HasAttr { expr, attr } => {
+                    match (expr.expr_kind(), expr.data()) {
+                        (ExprKind::Var(Var::Context), _) => {
+                            usage
+                                .context_attributes
+                                .insert((env.action.clone(), attr.clone()));
+                        }
+                        (_, Some(Type::EntityOrRecord(EntityRecordKind::Entity(lub)))) => {
+                            for name in lub.iter() {
+                                let declared = self
+                                    .get_entity_type(name)
+                                    .is_some_and(|ety| ety.attr(attr).is_some());
+                                if declared {
+                                    usage.attributes.insert((name.clone(), attr.clone()));
+                                } else {
+                                    usage
+                                        .undeclared_attributes
+                                        .insert((name.clone(), attr.clone()));
+                                }
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Add the entity types which a value of type `ty` may be, or contain as an
+/// element, to `entity_types`
+fn entity_types_of(ty: &Type, entity_types: &mut HashSet<Name>) {
+    match ty {
+        Type::EntityOrRecord(EntityRecordKind::Entity(lub)) => {
+            entity_types.extend(lub.iter().cloned());
+        }
+        Type::Set {
+            element_type: Some(element_type),
+        } => entity_types_of(element_type, entity_types),
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cedar_policy_core::parser::parse_policyset;
+    use serde_json::json;
+
+    fn schema() -> ValidatorSchema {
+        ValidatorSchema::from_json_value(json!(
+        {
+            "": {
+                "entityTypes": {
+                    "User": {
+                        "memberOfTypes": ["Team"],
+                        "shape": {
+                            "type": "Record",
+                            "attributes": {
+                                "level": { "type": "Long" },
+                                "email": { "type": "String" }
+                            }
+                        }
+                    },
+                    "Admin": {},
+                    "Team": {},
+                    "Vault": {
+                        "shape": {
+                            "type": "Record",
+                            "attributes": { "owner": { "type": "Entity", "name": "User" } }
+                        }
+                    },
+                    "Pool": {}
+                },
+                "actions": {
+                    "withdraw": {
+                        "appliesTo": {
+                            "principalTypes": ["User", "Admin"],
+                            "resourceTypes": ["Vault"],
+                            "context": {
+                                "type": "Record",
+                                "attributes": {
+                                    "amount": { "type": "Long" },
+                                    "memo": { "type": "String" }
+                                }
+                            }
+                        }
+                    },
+                    "swap": {
+                        "appliesTo": { "principalTypes": ["User"], "resourceTypes": ["Pool"] }
+                    }
+                }
+            }
+        }))
+        .expect("should be a valid schema")
+    }
+
+    fn issues(src: &str, mode: ValidationMode) -> Vec<String> {
+        let policies = parse_policyset(src).expect("should parse");
+        schema()
+            .check_usage(&policies, mode)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn unused_elements() {
+        assert_eq!(
+            issues(
+                r#"permit(principal == User::"alice", action == Action::"withdraw", resource)
+                when { resource.owner.level > 2 && context.amount < 100 };"#,
+                ValidationMode::Strict
+            ),
+            vec![
+                "entity type `Admin` is never used",
+                "entity type `Pool` is never used",
+                "entity type `Team` is never used",
+                "attribute `email` of entity type `User` is never used",
+                r#"action `Action::"swap"` is never used"#,
+                r#"attribute `memo` of the context of action `Action::"withdraw"` is never used"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn undeclared_attributes() {
+        let src = r#"permit(principal == User::"alice", action == Action::"withdraw", resource)
+            when { (if context.amount > 100 then principal else Admin::"root") has email };"#;
+        assert_eq!(
+            issues(src, ValidationMode::Permissive),
+            vec![
+                "attribute `email` is used but not declared for entity type `Admin`",
+                "entity type `Pool` is never used",
+                "entity type `Team` is never used",
+                "attribute `level` of entity type `User` is never used",
+                "attribute `owner` of entity type `Vault` is never used",
+                r#"action `Action::"swap"` is never used"#,
+                r#"attribute `memo` of the context of action `Action::"withdraw"` is never used"#,
+            ]
+        );
+        // The policy is not valid in strict mode, so it uses nothing
+        assert_eq!(issues(src, ValidationMode::Strict).len(), 7);
+    }
+}
@@ -0,0 +1,201 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Generation of template entities JSON from a schema.
+
+use cedar_policy_core::ast::Name;
+use serde_json::{json, Map, Value};
+
+use crate::types::{Attributes, EntityRecordKind, Primitive, Type};
+use crate::ValidatorSchema;
+
+/// Entity id used for every entity in a skeleton. Entity references in
+/// attributes and parents all point at the example entity of their type, so
+/// the skeleton is a self-contained, loadable entities file.
+const EXAMPLE_ID: &str = "example";
+
+impl ValidatorSchema {
+    /// Generate a template entities JSON array containing one example entity
+    /// for each entity type in the schema, ordered by type name.
+    ///
+    /// Each entity has every required attribute stubbed with a placeholder of
+    /// the declared type (`false`, `0`, `""`, empty sets, and the smallest
+    /// valid extension value), one parent of each type it may be a member of
+    /// (except where that would make the hierarchy cyclic), and, if the type
+    /// declares tags, one example tag. Optional attributes are omitted.
+    pub fn entities_skeleton(&self) -> Value {
+        let mut entity_types = self.entity_types().collect::<Vec<_>>();
+        entity_types.sort_by_key(|(name, _)| name.to_string());
+        let entities = entity_types
+            .iter()
+            .map(|(name, ety)| {
+                let parents = entity_types
+                    .iter()
+                    // skip parents which would make the hierarchy cyclic,
+                    // e.g. for types which may be members of themselves
+                    .filter(|(parent_name, parent)| {
+                        parent.descendants.contains(name) && !ety.descendants.contains(parent_name)
+                    })
+                    .map(|(parent, _)| uid_json(parent))
+                    .collect::<Vec<_>>();
+                let mut entity = Map::new();
+                entity.insert("uid".into(), uid_json(name));
+                entity.insert("attrs".into(), required_attrs_placeholder(&ety.attributes));
+                entity.insert("parents".into(), Value::Array(parents));
+                if let Some(tag_type) = ety.tag_type() {
+                    entity.insert("tags".into(), json!({ "example": placeholder(tag_type) }));
+                }
+                Value::Object(entity)
+            })
+            .collect();
+        Value::Array(entities)
+    }
+}
+
+fn uid_json(name: &Name) -> Value {
+    json!({ "type": name.to_string(), "id": EXAMPLE_ID })
+}
+
+fn required_attrs_placeholder(attrs: &Attributes) -> Value {
+    Value::Object(
+        attrs
+            .iter()
+            .filter(|(_, ty)| ty.is_required)
+            .map(|(attr, ty)| (attr.to_string(), placeholder(&ty.attr_type)))
+            .collect::<Map<_, _>>(),
+    )
+}
+
+/// A placeholder JSON value of the given schema type
+fn placeholder(ty: &Type) -> Value {
+    match ty {
+        Type::Never => Value::Null,
+        Type::True => Value::Bool(true),
+        Type::False => Value::Bool(false),
+        Type::Primitive { primitive_type } => match primitive_type {
+            Primitive::Bool => Value::Bool(false),
+            Primitive::Long => json!(0),
+            Primitive::String => json!(""),
+        },
+        Type::Set { .. } => json!([]),
+        Type::EntityOrRecord(EntityRecordKind::Record { attrs, .. }) => {
+            required_attrs_placeholder(attrs)
+        }
+        Type::EntityOrRecord(EntityRecordKind::Entity(lub)) => match lub.iter().next() {
+            Some(name) => json!({ "__entity": uid_json(name) }),
+            None => Value::Null,
+        },
+        Type::EntityOrRecord(EntityRecordKind::ActionEntity { name, .. }) => {
+            json!({ "__entity": uid_json(name) })
+        }
+        Type::EntityOrRecord(EntityRecordKind::AnyEntity) => Value::Null,
+        Type::ExtensionType { name } => match extension_placeholder(name) {
+            Some((func, arg)) => json!({ "__extn": { "fn": func, "arg": arg } }),
+            None => Value::Null,
+        },
+    }
+}
+
+/// Constructor and argument for a valid value of each standard extension type
+fn extension_placeholder(name: &Name) -> Option<(&'static str, &'static str)> {
+    match name.to_string().as_str() {
+        "ipaddr" => Some(("ip", "0.0.0.0")),
+        "decimal" => Some(("decimal", "0.0")),
+        "u256" => Some(("u256", "0")),
+        "bytes" => Some(("bytes", "0x")),
+        "address" => Some(("address", "0x0000000000000000000000000000000000000000")),
+        "regex" => Some(("regex", "")),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::CoreSchema;
+    use cedar_policy_core::entities::{Entities, EntityJsonParser, TCComputation};
+    use cedar_policy_core::extensions::Extensions;
+
+    #[test]
+    #[cfg(feature = "ipaddr")]
+    fn skeleton() {
+        let schema = ValidatorSchema::from_json_value(json!(
+        {"": {
+            "entityTypes": {
+                "User": {
+                    "memberOfTypes": ["Group"],
+                    "shape": {
+                        "type": "Record",
+                        "attributes": {
+                            "name": { "type": "String" },
+                            "age": { "type": "Long", "required": false },
+                            "admin": { "type": "Boolean" },
+                            "manager": { "type": "Entity", "name": "User" },
+                            "roles": { "type": "Set", "element": { "type": "String" } },
+                            "address": { "type": "Record", "attributes": {
+                                "street": { "type": "String" },
+                                "zip": { "type": "String", "required": false }
+                            }},
+                            "ip": { "type": "Extension", "name": "ipaddr" }
+                        }
+                    },
+                    "tags": { "type": "Long" }
+                },
+                "Group": {}
+            },
+            "actions": {
+                "view": {}
+            }
+        }}))
+        .expect("should be a valid schema");
+        let skeleton = schema.entities_skeleton();
+        assert_eq!(
+            skeleton,
+            json!([
+                {
+                    "uid": { "type": "Group", "id": "example" },
+                    "attrs": {},
+                    "parents": []
+                },
+                {
+                    "uid": { "type": "User", "id": "example" },
+                    "attrs": {
+                        "address": { "street": "" },
+                        "admin": false,
+                        "ip": { "__extn": { "fn": "ip", "arg": "0.0.0.0" } },
+                        "manager": { "__entity": { "type": "User", "id": "example" } },
+                        "name": "",
+                        "roles": []
+                    },
+                    "parents": [{ "type": "Group", "id": "example" }],
+                    "tags": { "example": 0 }
+                }
+            ])
+        );
+
+        // the skeleton is a valid entities file for the schema
+        let core_schema = CoreSchema::new(&schema);
+        let parser = EntityJsonParser::new(
+            Some(core_schema),
+            Extensions::all_available(),
+            TCComputation::ComputeNow,
+        );
+        let entities: Entities = parser
+            .from_json_value(skeleton)
+            .expect("skeleton should parse against the schema");
+        assert_eq!(entities.iter().count(), 2);
+    }
+}
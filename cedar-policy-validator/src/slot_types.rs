@@ -0,0 +1,185 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The entity types that the slots of a template may be linked to.
+
+use cedar_policy_core::ast::{
+    ActionConstraint, EntityReference, EntityType, EntityUID, Name, PrincipalOrResourceConstraint,
+    SlotId, Template,
+};
+use std::collections::{HashMap, HashSet};
+
+use crate::{ValidatorActionId, ValidatorSchema};
+
+impl ValidatorSchema {
+    /// For each slot of `template`, the entity types it may be linked to such
+    /// that the linked policy can apply to some request allowed by the schema.
+    ///
+    /// These are the principal (resource) types of the actions satisfying the
+    /// action constraint of the template. For a slot in a `principal in
+    /// ?principal` (`resource in ?resource`) constraint, they also include
+    /// the entity types which may have an entity of one of those types as a
+    /// descendant.
+    pub fn slot_types(&self, template: &Template) -> HashMap<SlotId, HashSet<Name>> {
+        let actions = self.actions_satisfying(template.action_constraint());
+        [
+            (
+                SlotId::principal(),
+                template.principal_constraint().as_inner(),
+                actions
+                    .iter()
+                    .flat_map(|action| action.applies_to.applicable_principal_types())
+                    .collect::<HashSet<_>>(),
+            ),
+            (
+                SlotId::resource(),
+                template.resource_constraint().as_inner(),
+                actions
+                    .iter()
+                    .flat_map(|action| action.applies_to.applicable_resource_types())
+                    .collect::<HashSet<_>>(),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(slot, constraint, applicable)| {
+            let applicable = applicable
+                .into_iter()
+                .filter_map(|ety| match ety {
+                    EntityType::Concrete(name) => Some(name.clone()),
+                    EntityType::Unspecified => None,
+                })
+                .collect::<HashSet<_>>();
+            match constraint {
+                PrincipalOrResourceConstraint::Eq(EntityReference::Slot) => {
+                    Some((slot, applicable))
+                }
+                PrincipalOrResourceConstraint::In(EntityReference::Slot) => {
+                    let ancestors = self
+                        .entity_types()
+                        .filter(|(_, ety)| !ety.descendants.is_disjoint(&applicable))
+                        .map(|(name, _)| name.clone())
+                        .collect::<Vec<_>>();
+                    Some((slot, applicable.into_iter().chain(ancestors).collect()))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+    }
+
+    /// The actions in the schema satisfying the action constraint of a policy
+    fn actions_satisfying(&self, constraint: &ActionConstraint) -> Vec<&ValidatorActionId> {
+        let is_in = |action: &ValidatorActionId, parent: &EntityUID| {
+            &action.name == parent
+                || self
+                    .get_action_id(parent)
+                    .is_some_and(|parent| parent.descendants.contains(&action.name))
+        };
+        self.known_action_ids()
+            .filter_map(|euid| self.get_action_id(euid))
+            .filter(|action| match constraint {
+                ActionConstraint::Any => true,
+                ActionConstraint::Eq(euid) => &action.name == euid.as_ref(),
+                ActionConstraint::In(parents) => parents.iter().any(|parent| is_in(action, parent)),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cedar_policy_core::parser::parse_policy_template;
+    use serde_json::json;
+
+    fn schema() -> ValidatorSchema {
+        ValidatorSchema::from_json_value(json!(
+        {
+            "": {
+                "entityTypes": {
+                    "User": { "memberOfTypes": ["Team"] },
+                    "Team": { "memberOfTypes": ["Org"] },
+                    "Org": {},
+                    "Vault": {},
+                    "Pool": {}
+                },
+                "actions": {
+                    "manage": {},
+                    "withdraw": {
+                        "memberOf": [{ "id": "manage" }],
+                        "appliesTo": { "principalTypes": ["User"], "resourceTypes": ["Vault"] }
+                    },
+                    "swap": {
+                        "appliesTo": { "principalTypes": ["User"], "resourceTypes": ["Pool"] }
+                    }
+                }
+            }
+        }))
+        .expect("should be a valid schema")
+    }
+
+    fn slot_types(src: &str) -> HashMap<SlotId, HashSet<String>> {
+        let template = parse_policy_template(None, src).expect("should parse");
+        schema()
+            .slot_types(&template)
+            .into_iter()
+            .map(|(slot, types)| (slot, types.iter().map(ToString::to_string).collect()))
+            .collect()
+    }
+
+    fn names(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn eq_slots() {
+        assert_eq!(
+            slot_types(
+                r#"permit(principal == ?principal, action == Action::"withdraw", resource == ?resource);"#
+            ),
+            HashMap::from([
+                (SlotId::principal(), names(&["User"])),
+                (SlotId::resource(), names(&["Vault"]))
+            ])
+        );
+    }
+
+    #[test]
+    fn in_slots() {
+        assert_eq!(
+            slot_types(r#"permit(principal in ?principal, action, resource == Pool::"p");"#),
+            HashMap::from([(SlotId::principal(), names(&["User", "Team", "Org"]))])
+        );
+    }
+
+    #[test]
+    fn action_groups() {
+        assert_eq!(
+            slot_types(
+                r#"permit(principal, action in [Action::"manage"], resource in ?resource);"#
+            ),
+            HashMap::from([(SlotId::resource(), names(&["Vault"]))])
+        );
+        assert_eq!(
+            slot_types(r#"permit(principal, action, resource == ?resource);"#),
+            HashMap::from([(SlotId::resource(), names(&["Vault", "Pool"]))])
+        );
+        assert_eq!(
+            slot_types(r#"permit(principal, action == Action::"manage", resource == ?resource);"#),
+            HashMap::from([(SlotId::resource(), names(&[]))])
+        );
+    }
+}
@@ -126,6 +126,22 @@ impl TypeError {
         }
     }
 
+    pub(crate) fn unsafe_tag_access(on_expr: Expr, tag: Option<SmolStr>) -> Self {
+        Self {
+            on_expr: Some(on_expr),
+            source_location: None,
+            kind: TypeErrorKind::UnsafeTagAccess(UnsafeTagAccess { tag }),
+        }
+    }
+
+    pub(crate) fn no_tags_allowed(on_expr: Expr, entity_type: Type) -> Self {
+        Self {
+            on_expr: Some(on_expr),
+            source_location: None,
+            kind: TypeErrorKind::NoTagsAllowed(NoTagsAllowed { entity_type }),
+        }
+    }
+
     pub(crate) fn impossible_policy(on_expr: Expr) -> Self {
         Self {
             on_expr: Some(on_expr),
@@ -244,6 +260,18 @@ pub enum TypeErrorKind {
     /// attribute was safe.
     #[error("unable to guarantee safety of access to optional attribute {}", .0.attribute_access)]
     UnsafeOptionalAttributeAccess(UnsafeOptionalAttributeAccess),
+    /// The typechecker could not conclude that an access to a tag was safe,
+    /// because it was not guarded by a `hasTag` check on the same literal tag.
+    #[error("unable to guarantee safety of access to tag {}",
+        match &.0.tag {
+            Some(tag) => format!("`{tag}`"),
+            None => "with a non-literal name".to_string(),
+        })]
+    UnsafeTagAccess(UnsafeTagAccess),
+    /// The typechecker found a tag access on an entity type which does not
+    /// declare any tags.
+    #[error("tags are not declared for type {}", .0.entity_type)]
+    NoTagsAllowed(NoTagsAllowed),
     /// The typechecker found that a policy condition will always evaluate to false.
     #[error(
         "policy is impossible. The policy expression evaluates to false for all valid requests"
@@ -308,6 +336,20 @@ pub struct UnsafeOptionalAttributeAccess {
     attribute_access: AttributeAccess,
 }
 
+/// Structure containing details about an unsafe tag access error.
+#[derive(Debug, Hash, Eq, PartialEq)]
+pub struct UnsafeTagAccess {
+    /// The tag accessed, if it was a literal
+    tag: Option<SmolStr>,
+}
+
+/// Structure containing details about a tag access on an entity type without
+/// tags.
+#[derive(Debug, Hash, Eq, PartialEq)]
+pub struct NoTagsAllowed {
+    entity_type: Type,
+}
+
 /// Structure containing details about an undefined function error.
 #[derive(Debug, Hash, Eq, PartialEq)]
 pub struct UndefinedFunction {
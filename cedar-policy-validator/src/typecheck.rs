@@ -24,6 +24,7 @@ mod test_namespace;
 mod test_optional_attributes;
 mod test_policy;
 mod test_strict;
+mod test_tags;
 mod test_type_annotation;
 mod test_unspecified_entity;
 mod test_utils;
@@ -1351,6 +1352,96 @@ impl<'a> Typechecker<'a> {
                     })
             }
 
+            BinaryOp::HasTag => {
+                // The first argument must be an entity and the second a string.
+                self.expect_type(
+                    request_env,
+                    prior_eff,
+                    arg1,
+                    Type::any_entity_reference(),
+                    type_errors,
+                )
+                .then_typecheck(|expr_ty_arg1, _| {
+                    self.expect_type(
+                        request_env,
+                        prior_eff,
+                        arg2,
+                        Type::primitive_string(),
+                        type_errors,
+                    )
+                    .then_typecheck(|expr_ty_arg2, _| {
+                        let annotated_expr =
+                            ExprBuilder::with_data(Some(Type::primitive_boolean()))
+                                .with_same_source_info(bin_expr)
+                                .binary_app(*op, expr_ty_arg1, expr_ty_arg2);
+                        // As for `has`, if `hasTag` evaluates to `true` for a
+                        // literal tag name, then it is safe to access that tag,
+                        // so we add an entry to the effect set.
+                        match Self::literal_tag(arg2) {
+                            Some(tag) => TypecheckAnswer::success_with_effect(
+                                annotated_expr,
+                                EffectSet::singleton(Effect::new_tag(arg1, tag)),
+                            ),
+                            None => TypecheckAnswer::success(annotated_expr),
+                        }
+                    })
+                })
+            }
+
+            BinaryOp::GetTag => {
+                // The first argument must be an entity and the second a string.
+                self.expect_type(
+                    request_env,
+                    prior_eff,
+                    arg1,
+                    Type::any_entity_reference(),
+                    type_errors,
+                )
+                .then_typecheck(|expr_ty_arg1, _| {
+                    self.expect_type(
+                        request_env,
+                        prior_eff,
+                        arg2,
+                        Type::primitive_string(),
+                        type_errors,
+                    )
+                    .then_typecheck(|expr_ty_arg2, _| {
+                        let entity_ty = expr_ty_arg1.data().clone();
+                        let tag_ty = entity_ty
+                            .as_ref()
+                            .and_then(|ty| Type::lookup_tag_type(self.schema, ty, self.mode));
+                        let annotated_expr = ExprBuilder::with_data(tag_ty.clone())
+                            .with_same_source_info(bin_expr)
+                            .binary_app(*op, expr_ty_arg1, expr_ty_arg2);
+                        let tag = Self::literal_tag(arg2);
+                        match (entity_ty, tag_ty) {
+                            (Some(entity_ty), None) => {
+                                type_errors
+                                    .push(TypeError::no_tags_allowed(bin_expr.clone(), entity_ty));
+                                TypecheckAnswer::fail(annotated_expr)
+                            }
+                            // Failed to compute a type for the entity, so we
+                            // already failed typechecking for it.
+                            (None, _) => TypecheckAnswer::fail(annotated_expr),
+                            // A safe access to a tag requires that the access
+                            // is guarded by a `hasTag` on the same literal tag.
+                            (Some(_), Some(_)) => match tag {
+                                Some(tag) if prior_eff.contains(&Effect::new_tag(arg1, tag)) => {
+                                    TypecheckAnswer::success(annotated_expr)
+                                }
+                                _ => {
+                                    type_errors.push(TypeError::unsafe_tag_access(
+                                        bin_expr.clone(),
+                                        tag.map(Into::into),
+                                    ));
+                                    TypecheckAnswer::fail(annotated_expr)
+                                }
+                            },
+                        }
+                    })
+                })
+            }
+
             BinaryOp::ContainsAll | BinaryOp::ContainsAny => {
                 // Both arguments to a `containsAll` or `containsAny` must be sets.
                 self.expect_type(request_env, prior_eff, arg1, Type::any_set(), type_errors)
@@ -1386,6 +1477,14 @@ impl<'a> Typechecker<'a> {
         }
     }
 
+    /// If `e` is a string literal, get the tag name it denotes.
+    fn literal_tag(e: &Expr) -> Option<&str> {
+        match e.expr_kind() {
+            ExprKind::Lit(Literal::String(tag)) => Some(tag.as_str()),
+            _ => None,
+        }
+    }
+
     fn enforce_strict_equality<'b>(
         &self,
         unannotated_expr: &'b Expr,
@@ -57,6 +57,7 @@ fn slot_typechecks() {
 fn slot_in_typechecks() {
     let etype = EntityType {
         member_of_types: vec![],
+        tags: None,
         shape: AttributesOrContext::default(),
     };
     let schema = NamespaceDefinition::new([("typename".into(), etype)], []);
@@ -86,6 +87,7 @@ fn slot_in_typechecks() {
 fn slot_equals_typechecks() {
     let etype = EntityType {
         member_of_types: vec![],
+        tags: None,
         shape: AttributesOrContext::default(),
     };
     // These don't typecheck in strict mode because the test_util expression
@@ -140,3 +140,153 @@ fn decimal_extension_typecheck_fails() {
         )],
     );
 }
+
+#[test]
+#[cfg(feature = "bytes")]
+fn bytes_extension_typechecks() {
+    let bytes_name = Name::parse_unqualified_name("bytes").expect("should be a valid identifier");
+    let expr = Expr::from_str("bytes(\"0xa9059cbb\")").expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::extension(bytes_name));
+    let expr =
+        Expr::from_str("bytes(\"base64:qQWcuw==\").bytesLength()").expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_long());
+    let expr = Expr::from_str("bytes(\"0xa9059cbb00\").bytesStartsWith(bytes(\"0xa9059cbb\"))")
+        .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+}
+
+#[test]
+#[cfg(feature = "bytes")]
+fn bytes_extension_typecheck_fails() {
+    let bytes_name = Name::parse_unqualified_name("bytes").expect("should be a valid identifier");
+    let expr = Expr::from_str("bytes(\"a9059cbb\")").expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr.clone(),
+        Type::extension(bytes_name.clone()),
+        vec![TypeError::arg_validation_error(
+            expr,
+            "Failed to parse as a bytes value: `\"a9059cbb\"`".into(),
+        )],
+    );
+    let expr = Expr::from_str("bytes(\"0xa9059cbb\").bytesStartsWith(\"0xa9\")")
+        .expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr,
+        Type::primitive_boolean(),
+        vec![TypeError::expected_type(
+            Expr::val("0xa9"),
+            Type::extension(bytes_name),
+            Type::primitive_string(),
+        )],
+    );
+}
+
+#[test]
+#[cfg(feature = "address")]
+fn address_extension_typechecks() {
+    let address_name =
+        Name::parse_unqualified_name("address").expect("should be a valid identifier");
+    let expr = Expr::from_str("address(\"0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed\")")
+        .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::extension(address_name));
+    let expr = Expr::from_str(
+        "address(\"0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed\") == address(\"0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed\")",
+    )
+    .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+}
+
+#[test]
+#[cfg(feature = "address")]
+fn address_extension_typecheck_fails() {
+    let address_name =
+        Name::parse_unqualified_name("address").expect("should be a valid identifier");
+    let expr = Expr::from_str("address(\"0x5aaeb6\")").expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr.clone(),
+        Type::extension(address_name),
+        vec![TypeError::arg_validation_error(
+            expr,
+            "Failed to parse as an address value: `\"0x5aaeb6\"`".into(),
+        )],
+    );
+}
+
+#[test]
+#[cfg(feature = "regex")]
+fn regex_extension_typechecks() {
+    let expr = Expr::from_str(
+        "\"eip155:1:0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48\".matchesRegex(regex(\"eip155:[0-9]+:0x[0-9a-fA-F]{40}\"))",
+    )
+    .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+}
+
+#[test]
+#[cfg(feature = "regex")]
+fn regex_extension_typecheck_fails() {
+    let regex_name = Name::parse_unqualified_name("regex").expect("should be a valid identifier");
+    let expr = Expr::from_str("regex(\"eip155:[0-9\")").expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr.clone(),
+        Type::extension(regex_name.clone()),
+        vec![TypeError::arg_validation_error(
+            expr,
+            "Failed to parse as a regex value: input string `eip155:[0-9` is not a valid pattern: \
+            regex parse error:\n    eip155:[0-9\n           ^\nerror: unclosed character class"
+                .into(),
+        )],
+    );
+    let expr = Expr::from_str("\"eip155:1\".matchesRegex(\"eip155:[0-9]+\")")
+        .expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr,
+        Type::primitive_boolean(),
+        vec![TypeError::expected_type(
+            Expr::val("eip155:[0-9]+"),
+            Type::extension(regex_name),
+            Type::primitive_string(),
+        )],
+    );
+}
+
+#[test]
+#[cfg(feature = "simulation")]
+fn simulation_extension_typechecks() {
+    let sim = r#"{ balanceChanges: [{ token: address("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"), received: u256("1000"), sent: u256("0") }], events: [{ contract: address("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"), topic0: bytes("0x00") }] }"#;
+    let expr = Expr::from_str(&format!(
+        r#"{sim}.receivedAtLeast(address("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"), u256("1000"))"#
+    ))
+    .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+    let expr = Expr::from_str(&format!(
+        r#"{sim}.emittedEvent(address("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"), bytes("0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"))"#
+    ))
+    .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+    let expr = Expr::from_str(&format!(
+        r#"{sim}.netBalanceChange(address("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")).loss <= u256("5")"#
+    ))
+    .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+}
+
+#[test]
+#[cfg(feature = "simulation")]
+fn simulation_extension_typecheck_fails() {
+    let address_name =
+        Name::parse_unqualified_name("address").expect("should be a valid identifier");
+    let expr = Expr::from_str(
+        r#"{}.receivedAtLeast("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", u256("1"))"#,
+    )
+    .expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr,
+        Type::primitive_boolean(),
+        vec![TypeError::expected_type(
+            Expr::val("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"),
+            Type::extension(address_name),
+            Type::primitive_string(),
+        )],
+    );
+}
@@ -0,0 +1,115 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Contains tests for declaring entity tags and typechecking `hasTag` and
+//! `getTag`.
+#![cfg(test)]
+// GRCOV_STOP_COVERAGE
+
+use cedar_policy_core::{
+    ast::{Expr, StaticPolicy, Var},
+    parser::parse_policy,
+};
+
+use crate::{type_error::TypeError, types::Type, NamespaceDefinition};
+
+use super::test_utils::{assert_policy_typecheck_fails, assert_policy_typechecks};
+
+fn schema_with_tags() -> NamespaceDefinition {
+    serde_json::from_str::<NamespaceDefinition>(
+        r#"
+{
+    "entityTypes": {
+        "Address": {
+            "tags": { "type": "Long" }
+        },
+        "Contract": {}
+    },
+    "actions": {
+        "transfer": {
+            "appliesTo": {
+                "principalTypes": ["Address"],
+                "resourceTypes": ["Contract"]
+            }
+        }
+    }
+}
+    "#,
+    )
+    .expect("Expected valid schema.")
+}
+
+fn parse(policy: &str) -> StaticPolicy {
+    parse_policy(Some("0".to_string()), policy).expect("Policy should parse.")
+}
+
+#[test]
+fn guarded_get_tag() {
+    assert_policy_typechecks(
+        schema_with_tags(),
+        parse(
+            r#"permit(principal, action, resource) when { principal.hasTag("risk") && principal.getTag("risk") < 50 };"#,
+        ),
+    );
+}
+
+#[test]
+fn has_tag_on_entity_without_tags() {
+    assert_policy_typechecks(
+        schema_with_tags(),
+        parse(r#"permit(principal, action, resource) when { !resource.hasTag("risk") };"#),
+    );
+}
+
+#[test]
+fn unguarded_get_tag_fails() {
+    assert_policy_typecheck_fails(
+        schema_with_tags(),
+        parse(r#"permit(principal, action, resource) when { principal.getTag("risk") < 50 };"#),
+        vec![TypeError::unsafe_tag_access(
+            Expr::get_tag(Expr::var(Var::Principal), Expr::val("risk")),
+            Some("risk".into()),
+        )],
+    );
+}
+
+#[test]
+fn get_tag_guarded_by_other_tag_fails() {
+    assert_policy_typecheck_fails(
+        schema_with_tags(),
+        parse(
+            r#"permit(principal, action, resource) when { principal.hasTag("sanctioned") && principal.getTag("risk") < 50 };"#,
+        ),
+        vec![TypeError::unsafe_tag_access(
+            Expr::get_tag(Expr::var(Var::Principal), Expr::val("risk")),
+            Some("risk".into()),
+        )],
+    );
+}
+
+#[test]
+fn get_tag_on_entity_without_tags_fails() {
+    assert_policy_typecheck_fails(
+        schema_with_tags(),
+        parse(
+            r#"permit(principal, action, resource) when { resource.hasTag("risk") && resource.getTag("risk") < 50 };"#,
+        ),
+        vec![TypeError::no_tags_allowed(
+            Expr::get_tag(Expr::var(Var::Resource), Expr::val("risk")),
+            Type::named_entity_reference_from_str("Contract"),
+        )],
+    );
+}
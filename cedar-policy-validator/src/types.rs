@@ -339,6 +339,30 @@ impl Type {
         })
     }
 
+    /// Get the type of the tag values of an entity type. If the type is not
+    /// an entity type, or any of the entity types it may be does not have
+    /// tags, then `None` is returned.
+    pub(crate) fn lookup_tag_type(
+        schema: &ValidatorSchema,
+        ty: &Type,
+        mode: ValidationMode,
+    ) -> Option<Type> {
+        match ty {
+            Type::EntityOrRecord(EntityRecordKind::Entity(entity_lub)) => {
+                let mut tag_types = entity_lub.lub_elements.iter().map(|entity| {
+                    schema
+                        .get_entity_type(entity)
+                        .and_then(|entity_type| entity_type.tag_type())
+                });
+                let first = tag_types.next()??.clone();
+                tag_types.try_fold(first, |lub, tag_ty| {
+                    Type::least_upper_bound(schema, &lub, tag_ty?, mode)
+                })
+            }
+            _ => None,
+        }
+    }
+
     /// Get the type of the specified attribute of an entity or record type.
     /// If the type is not an entity or record type, or does not have the
     /// required attribute, then `None` is returned.
@@ -1355,12 +1379,14 @@ impl<'a> EffectSet<'a> {
     }
 }
 
-/// Represent a single effect, which is an expression and some attribute that is
-/// known to exist for that expression.
+/// Represent a single effect, which is an expression and some attribute (or
+/// tag) that is known to exist for that expression.
 #[derive(Hash, Eq, PartialEq, Debug, Clone)]
 pub struct Effect<'a> {
     on_expr: ExprShapeOnly<'a>,
     attribute: &'a str,
+    /// Whether `attribute` names a tag rather than an attribute
+    is_tag: bool,
 }
 
 impl<'a> Effect<'a> {
@@ -1368,6 +1394,15 @@ impl<'a> Effect<'a> {
         Self {
             on_expr: ExprShapeOnly::new(on_expr),
             attribute,
+            is_tag: false,
+        }
+    }
+
+    pub fn new_tag(on_expr: &'a Expr, tag: &'a str) -> Self {
+        Self {
+            on_expr: ExprShapeOnly::new(on_expr),
+            attribute: tag,
+            is_tag: true,
         }
     }
 }
@@ -14,24 +14,35 @@
  * limitations under the License.
  */
 
-use cedar_policy_core::{ast::PolicyID, parser::SourceInfo};
+use cedar_policy_core::{
+    ast::{PolicyID, SlotId},
+    parser::SourceInfo,
+};
 use thiserror::Error;
 
 use crate::TypeErrorKind;
 
-/// Contains the result of policy validation. The result includes the list of of
-/// issues found by the validation and whether validation succeeds or fails.
-/// Validation succeeds if there are no fatal errors.  There are currently no
-/// non-fatal warnings, so any issues found will cause validation to fail.
+/// Contains the result of policy validation. The result includes the list of
+/// errors and the list of warnings found by the validation. Validation
+/// succeeds if there are no errors. Warnings point out policies which are
+/// probably mistaken but are otherwise valid, so they do not cause validation
+/// to fail unless the validator is configured to deny them (see
+/// [`crate::Validator::with_denied_warnings`]), in which case they are
+/// reported as errors instead.
 #[derive(Debug)]
 pub struct ValidationResult<'a> {
     validation_errors: Vec<ValidationError<'a>>,
+    validation_warnings: Vec<ValidationWarning<'a>>,
 }
 
 impl<'a> ValidationResult<'a> {
-    pub(crate) fn new(validation_errors: impl Iterator<Item = ValidationError<'a>>) -> Self {
+    pub(crate) fn new(
+        validation_errors: impl Iterator<Item = ValidationError<'a>>,
+        validation_warnings: impl Iterator<Item = ValidationWarning<'a>>,
+    ) -> Self {
         Self {
             validation_errors: validation_errors.collect::<Vec<_>>(),
+            validation_warnings: validation_warnings.collect::<Vec<_>>(),
         }
     }
 
@@ -45,10 +56,28 @@ impl<'a> ValidationResult<'a> {
         self.validation_errors.iter()
     }
 
+    /// Get the list of warnings found by the validator.
+    pub fn validation_warnings(&self) -> impl Iterator<Item = &ValidationWarning<'a>> {
+        self.validation_warnings.iter()
+    }
+
     /// Get the list of errors found by the validator.
     pub fn into_validation_errors(self) -> impl Iterator<Item = ValidationError<'a>> {
         self.validation_errors.into_iter()
     }
+
+    /// Get the lists of errors and warnings found by the validator.
+    pub fn into_errors_and_warnings(
+        self,
+    ) -> (
+        impl Iterator<Item = ValidationError<'a>>,
+        impl Iterator<Item = ValidationWarning<'a>>,
+    ) {
+        (
+            self.validation_errors.into_iter(),
+            self.validation_warnings.into_iter(),
+        )
+    }
 }
 
 /// An error generated by the validator when it finds a potential problem in a
@@ -164,6 +193,23 @@ pub enum ValidationErrorKind {
         .0.entity_id,
     )]
     UnspecifiedEntity(UnspecifiedEntity),
+    /// A template-linked policy fills a slot with an entity whose type is not
+    /// allowed in the scope position of the slot, so the policy can never
+    /// apply to a request allowed by the schema.
+    #[error(
+        "slot `{}` is linked to an entity of type `{}`, which {}",
+        .0.slot,
+        .0.actual_entity_type,
+        if .0.expected_entity_types.is_empty() {
+            "no action in the template applies to".to_string()
+        } else {
+            format!("is not one of the expected types {}", .0.expected_entity_types.iter().map(|ety| format!("`{ety}`")).collect::<Vec<_>>().join(", "))
+        }
+    )]
+    UnexpectedSlotType(UnexpectedSlotType),
+    /// The validator found a warning which it is configured to deny.
+    #[error("{0} (warning `{code}` is denied)", code = .0.code())]
+    DeniedWarning(ValidationWarningKind),
 }
 
 impl ValidationErrorKind {
@@ -204,6 +250,37 @@ impl ValidationErrorKind {
     pub(crate) fn unspecified_entity(entity_id: String) -> ValidationErrorKind {
         Self::UnspecifiedEntity(UnspecifiedEntity { entity_id })
     }
+
+    pub(crate) fn unexpected_slot_type(
+        slot: SlotId,
+        actual_entity_type: String,
+        expected_entity_types: Vec<String>,
+    ) -> ValidationErrorKind {
+        Self::UnexpectedSlotType(UnexpectedSlotType {
+            slot,
+            actual_entity_type,
+            expected_entity_types,
+        })
+    }
+
+    pub(crate) fn denied_warning(warning: ValidationWarningKind) -> ValidationErrorKind {
+        Self::DeniedWarning(warning)
+    }
+
+    /// A stable code identifying this kind of error, written in kebab-case,
+    /// e.g., `unrecognized-entity-type`. A denied warning has the code of the
+    /// warning, e.g., `impossible-policy`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnrecognizedEntityType(_) => "unrecognized-entity-type",
+            Self::UnrecognizedActionId(_) => "unrecognized-action-id",
+            Self::InvalidActionApplication(_) => "invalid-action-application",
+            Self::TypeError(_) => "type-error",
+            Self::UnspecifiedEntity(_) => "unspecified-entity",
+            Self::UnexpectedSlotType(_) => "unexpected-slot-type",
+            Self::DeniedWarning(warning) => warning.code().as_str(),
+        }
+    }
 }
 
 /// Structure containing details about an unrecognized entity type error.
@@ -243,3 +320,189 @@ pub struct UnspecifiedEntity {
     /// EID of the unspecified entity.
     pub(crate) entity_id: String,
 }
+
+/// Structure containing details about an unexpected slot type error.
+#[derive(Debug)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+pub struct UnexpectedSlotType {
+    /// The slot which was linked.
+    pub(crate) slot: SlotId,
+    /// The entity type of the value linked to the slot.
+    pub(crate) actual_entity_type: String,
+    /// The entity types allowed in the slot, in sorted order.
+    pub(crate) expected_entity_types: Vec<String>,
+}
+
+/// A warning generated by the validator when it finds a policy which is
+/// probably mistaken, but which is not an error.
+#[derive(Debug, Clone)]
+pub struct ValidationWarning<'a> {
+    location: &'a PolicyID,
+    kind: ValidationWarningKind,
+}
+
+impl<'a> ValidationWarning<'a> {
+    pub(crate) fn new(location: &'a PolicyID, kind: ValidationWarningKind) -> Self {
+        Self { location, kind }
+    }
+
+    pub fn location(&self) -> &'a PolicyID {
+        self.location
+    }
+
+    pub fn kind(&self) -> &ValidationWarningKind {
+        &self.kind
+    }
+
+    pub fn to_kind_and_location(self) -> (&'a PolicyID, ValidationWarningKind) {
+        (self.location, self.kind)
+    }
+}
+
+impl std::fmt::Display for ValidationWarning<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "validation warning on policy `{}`: {}",
+            self.location, self.kind
+        )
+    }
+}
+
+/// Enumeration of the possible warnings that could be found by the
+/// verification steps. Each kind of warning has a stable [`WarningCode`].
+#[derive(Debug, Clone, PartialEq, Error, Eq)]
+pub enum ValidationWarningKind {
+    /// A string contains mixed scripts. Different scripts can contain visually similar characters which may be confused for each other.
+    #[error("string `\"{0}\"` contains mixed scripts")]
+    MixedScriptString(String),
+    /// A string contains BIDI control characters. These can be used to create crafted pieces of code that obfuscate true control flow.
+    #[error("string `\"{0}\"` contains BIDI control characters")]
+    BidiCharsInString(String),
+    /// An id contains BIDI control characters. These can be used to create crafted pieces of code that obfuscate true control flow.
+    #[error("identifier `{0}` contains BIDI control characters")]
+    BidiCharsInIdentifier(String),
+    /// An id contains mixed scripts. This can cause characters to be confused for each other.
+    #[error("identifier `{0}` contains mixed scripts")]
+    MixedScriptIdentifier(String),
+    /// An id contains characters that fall outside of the General Security Profile for Identifiers. We recommend adhering to this if possible. See Unicode® Technical Standard #39 for more info.
+    #[error("identifier `{0}` contains characters that fall outside of the General Security Profile for Identifiers")]
+    ConfusableIdentifier(String),
+    /// The policy evaluates to false for every request allowed by the
+    /// schema, so it can never apply.
+    #[error(
+        "policy is impossible. The policy expression evaluates to false for all valid requests"
+    )]
+    ImpossiblePolicy,
+}
+
+impl ValidationWarningKind {
+    /// The code identifying this kind of warning.
+    pub fn code(&self) -> WarningCode {
+        match self {
+            Self::MixedScriptString(_) => WarningCode::MixedScriptString,
+            Self::BidiCharsInString(_) => WarningCode::BidiCharsInString,
+            Self::BidiCharsInIdentifier(_) => WarningCode::BidiCharsInIdentifier,
+            Self::MixedScriptIdentifier(_) => WarningCode::MixedScriptIdentifier,
+            Self::ConfusableIdentifier(_) => WarningCode::ConfusableIdentifier,
+            Self::ImpossiblePolicy => WarningCode::ImpossiblePolicy,
+        }
+    }
+}
+
+/// Stable codes for the kinds of validation warnings, used to select the
+/// warnings which a validator should deny. Each code is written in
+/// kebab-case, e.g., `impossible-policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WarningCode {
+    /// `mixed-script-string`
+    MixedScriptString,
+    /// `bidi-chars-in-string`
+    BidiCharsInString,
+    /// `bidi-chars-in-identifier`
+    BidiCharsInIdentifier,
+    /// `mixed-script-identifier`
+    MixedScriptIdentifier,
+    /// `confusable-identifier`
+    ConfusableIdentifier,
+    /// `impossible-policy`
+    ImpossiblePolicy,
+}
+
+impl WarningCode {
+    /// All warning codes
+    pub const ALL: [WarningCode; 6] = [
+        Self::MixedScriptString,
+        Self::BidiCharsInString,
+        Self::BidiCharsInIdentifier,
+        Self::MixedScriptIdentifier,
+        Self::ConfusableIdentifier,
+        Self::ImpossiblePolicy,
+    ];
+
+    /// The code as written, e.g., `impossible-policy`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::MixedScriptString => "mixed-script-string",
+            Self::BidiCharsInString => "bidi-chars-in-string",
+            Self::BidiCharsInIdentifier => "bidi-chars-in-identifier",
+            Self::MixedScriptIdentifier => "mixed-script-identifier",
+            Self::ConfusableIdentifier => "confusable-identifier",
+            Self::ImpossiblePolicy => "impossible-policy",
+        }
+    }
+}
+
+impl std::fmt::Display for WarningCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for WarningCode {
+    type Err = UnknownWarningCode;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|code| code.as_str() == s)
+            .ok_or_else(|| UnknownWarningCode(s.to_string()))
+    }
+}
+
+/// Error when parsing a [`WarningCode`] which does not exist.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("unknown warning code `{0}`")]
+pub struct UnknownWarningCode(String);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn warning_codes() {
+        for code in WarningCode::ALL {
+            assert_eq!(code.to_string().parse(), Ok(code));
+        }
+        assert_eq!(
+            ValidationWarningKind::ImpossiblePolicy.code().to_string(),
+            "impossible-policy"
+        );
+        assert_eq!(
+            "impossible".parse::<WarningCode>(),
+            Err(UnknownWarningCode("impossible".to_string()))
+        );
+    }
+
+    #[test]
+    fn error_codes() {
+        assert_eq!(
+            ValidationErrorKind::unrecognized_entity_type("Usr".to_string(), None).code(),
+            "unrecognized-entity-type"
+        );
+        assert_eq!(
+            ValidationErrorKind::denied_warning(ValidationWarningKind::ImpossiblePolicy).code(),
+            "impossible-policy"
+        );
+    }
+}
@@ -0,0 +1,60 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The Cedar values of ABI-encoded values, such as the arguments of a
+//! constructor or the parameters of an event.
+
+use cedar_policy_core::extensions::u256::parse_u256;
+use ethers::abi::Token;
+use ethers::types::I256;
+
+use crate::RestrictedExpression;
+
+/// The Cedar value of an ABI-decoded `token`, or why it has none.
+///
+/// Addresses are held as `address`es, unsigned integers as `u256`s, signed
+/// integers as `Long`s, byte strings as `bytes`, arrays as sets, and tuples as
+/// records of their elements by position.
+pub fn token_value(token: Token) -> Result<RestrictedExpression, String> {
+    Ok(match token {
+        Token::Address(address) => address.into(),
+        Token::Bool(b) => b.into(),
+        Token::String(s) => s.into(),
+        Token::Bytes(bytes) | Token::FixedBytes(bytes) => RestrictedExpression::new_bytes(bytes),
+        Token::Uint(value) => parse_u256(&value.to_string())
+            .map(RestrictedExpression::new_u256)
+            .ok_or_else(|| format!("`{value}` is not a u256"))?,
+        Token::Int(value) => {
+            let value = I256::from_raw(value);
+            i64::try_from(value)
+                .map_err(|_| format!("`{value}` does not fit in a Long"))?
+                .into()
+        }
+        Token::Array(tokens) | Token::FixedArray(tokens) => RestrictedExpression::new_set(
+            tokens
+                .into_iter()
+                .map(token_value)
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        Token::Tuple(tokens) => RestrictedExpression::new_record(
+            tokens
+                .into_iter()
+                .enumerate()
+                .map(|(index, token)| Ok((index.to_string(), token_value(token)?)))
+                .collect::<Result<Vec<_>, String>>()?,
+        ),
+    })
+}
@@ -0,0 +1,687 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Analyzing the impact of a change to a policy set, such as for the change
+//! report of a governance vote.
+//!
+//! [`impact`] compares two policy sets in two ways:
+//! * symbolically, by the heads of the policies which were added, removed or
+//!   modified: which principals, actions and resources of an entity store
+//!   their scope constraints reach, and whether the change may grant or
+//!   revoke access to them. This covers every request, but over-approximates,
+//!   since it ignores the conditions of the policies.
+//! * concretely, by replaying sample requests against both policy sets, and
+//!   recording those whose decision changed. This is exact, but covers only
+//!   the samples.
+//!
+//! The [`Display`] of an [`ImpactReport`] shows both as a human-readable
+//! report.
+//!
+//! ```
+//! # use cedar_policy::analysis::impact;
+//! # use cedar_policy::{Context, Entities, PolicySet, Request};
+//! let before: PolicySet = r#"permit(principal == User::"alice", action, resource);"#
+//!     .parse()
+//!     .unwrap();
+//! let after: PolicySet = r#"permit(principal, action, resource);"#.parse().unwrap();
+//! let request = Request::new(
+//!     Some(r#"User::"bob""#.parse().unwrap()),
+//!     Some(r#"Action::"withdraw""#.parse().unwrap()),
+//!     Some(r#"Vault::"main""#.parse().unwrap()),
+//!     Context::empty(),
+//! );
+//! let report = impact(&before, &after, &Entities::empty(), &[request]);
+//! assert_eq!(report.gained().count(), 1);
+//! println!("{report}");
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display};
+
+use crate::{
+    ActionConstraint, Authorizer, Decision, Effect, Entities, Entity, EntityUid, Policy, PolicyId,
+    PolicySet, PrincipalConstraint, Request, ResourceConstraint,
+};
+
+/// How a policy changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ChangeKind {
+    /// The policy is only in the policy set after the change
+    Added,
+    /// The policy is only in the policy set before the change
+    Removed,
+    /// The policy is in both policy sets, with different text
+    Modified,
+}
+
+impl Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added => write!(f, "added"),
+            Self::Removed => write!(f, "removed"),
+            Self::Modified => write!(f, "modified"),
+        }
+    }
+}
+
+/// Whether a change to a policy may grant or revoke access
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Direction {
+    /// The change may only grant access, as adding a `permit` or removing a
+    /// `forbid` does
+    Grants,
+    /// The change may only revoke access, as removing a `permit` or adding a
+    /// `forbid` does
+    Revokes,
+    /// The change may grant access to some requests and revoke it from
+    /// others, as modifying a policy without changing its effect does
+    Either,
+}
+
+impl Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Grants => write!(f, "may grant access"),
+            Self::Revokes => write!(f, "may revoke access"),
+            Self::Either => write!(f, "may grant or revoke access"),
+        }
+    }
+}
+
+/// The entities a scope constraint reaches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reach {
+    /// Every entity, as an unconstrained scope does
+    All,
+    /// The entities named by the constraint and, for `in`, their descendants
+    /// in the entity store
+    Entities(BTreeSet<EntityUid>),
+}
+
+impl Reach {
+    /// Whether the constraint reaches `uid`
+    pub fn contains(&self, uid: &EntityUid) -> bool {
+        match self {
+            Self::All => true,
+            Self::Entities(uids) => uids.contains(uid),
+        }
+    }
+
+    /// The entities reached by either `self` or `other`
+    fn union(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Entities(mut uids), Self::Entities(other)) => {
+                uids.extend(other);
+                Self::Entities(uids)
+            }
+            _ => Self::All,
+        }
+    }
+
+    /// The reach of `in` any of `ancestors` in `entities`
+    fn descendants<'a>(
+        entities: &Entities,
+        ancestors: impl IntoIterator<Item = &'a EntityUid>,
+    ) -> Self {
+        let mut uids = BTreeSet::new();
+        for ancestor in ancestors {
+            uids.insert(ancestor.clone());
+            uids.extend(
+                entities
+                    .iter()
+                    .map(Entity::uid)
+                    .filter(|uid| entities.is_ancestor_of(ancestor, uid)),
+            );
+        }
+        Self::Entities(uids)
+    }
+
+    fn principal(constraint: PrincipalConstraint, entities: &Entities) -> Self {
+        match constraint {
+            PrincipalConstraint::Any => Self::All,
+            PrincipalConstraint::Eq(uid) => Self::Entities(BTreeSet::from([uid])),
+            PrincipalConstraint::In(uid) => Self::descendants(entities, [&uid]),
+        }
+    }
+
+    fn action(constraint: ActionConstraint, entities: &Entities) -> Self {
+        match constraint {
+            ActionConstraint::Any => Self::All,
+            ActionConstraint::Eq(uid) => Self::Entities(BTreeSet::from([uid])),
+            ActionConstraint::In(uids) => Self::descendants(entities, &uids),
+        }
+    }
+
+    fn resource(constraint: ResourceConstraint, entities: &Entities) -> Self {
+        match constraint {
+            ResourceConstraint::Any => Self::All,
+            ResourceConstraint::Eq(uid) => Self::Entities(BTreeSet::from([uid])),
+            ResourceConstraint::In(uid) => Self::descendants(entities, [&uid]),
+        }
+    }
+}
+
+impl Display for Reach {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::All => write!(f, "any"),
+            Self::Entities(uids) => {
+                let uids: Vec<String> = uids.iter().map(ToString::to_string).collect();
+                write!(f, "{}", uids.join(", "))
+            }
+        }
+    }
+}
+
+/// The symbolic impact of a change to a policy, from its head
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyImpact {
+    id: PolicyId,
+    kind: ChangeKind,
+    effect: Effect,
+    direction: Direction,
+    principals: Reach,
+    actions: Reach,
+    resources: Reach,
+}
+
+impl PolicyImpact {
+    /// The id of the policy
+    pub fn id(&self) -> &PolicyId {
+        &self.id
+    }
+
+    /// How the policy changed
+    pub fn kind(&self) -> ChangeKind {
+        self.kind
+    }
+
+    /// The effect of the policy, after the change unless it was removed
+    pub fn effect(&self) -> Effect {
+        self.effect
+    }
+
+    /// Whether the change may grant or revoke access
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// The principals whose access may change, reached by the principal
+    /// constraint of the policy before or after the change
+    pub fn principals(&self) -> &Reach {
+        &self.principals
+    }
+
+    /// The actions whose access may change
+    pub fn actions(&self) -> &Reach {
+        &self.actions
+    }
+
+    /// The resources whose access may change
+    pub fn resources(&self) -> &Reach {
+        &self.resources
+    }
+
+    /// The impact of a change of `kind` from `before` to `after`, at least
+    /// one of which is given
+    fn new(
+        id: PolicyId,
+        kind: ChangeKind,
+        before: Option<&Policy>,
+        after: Option<&Policy>,
+        entities: &Entities,
+    ) -> Self {
+        let effects = (before.map(Policy::effect), after.map(Policy::effect));
+        let direction =
+            match effects {
+                (None | Some(Effect::Forbid), Some(Effect::Permit))
+                | (Some(Effect::Forbid), None) => Direction::Grants,
+                (None | Some(Effect::Permit), Some(Effect::Forbid))
+                | (Some(Effect::Permit), None) => Direction::Revokes,
+                _ => Direction::Either,
+            };
+        let reach = |scope: fn(&Policy, &Entities) -> Reach| match (
+            before.map(|p| scope(p, entities)),
+            after.map(|p| scope(p, entities)),
+        ) {
+            (Some(before), Some(after)) => before.union(after),
+            (Some(reach), None) | (None, Some(reach)) => reach,
+            (None, None) => Reach::Entities(BTreeSet::new()),
+        };
+        Self {
+            id,
+            kind,
+            effect: effects.1.or(effects.0).unwrap_or(Effect::Permit),
+            direction,
+            principals: reach(|p, entities| Reach::principal(p.principal_constraint(), entities)),
+            actions: reach(|p, entities| Reach::action(p.action_constraint(), entities)),
+            resources: reach(|p, entities| Reach::resource(p.resource_constraint(), entities)),
+        }
+    }
+}
+
+impl Display for PolicyImpact {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} policy {}: {}\n    principals: {}\n    actions: {}\n    resources: {}",
+            self.kind,
+            self.effect,
+            self.id,
+            self.direction,
+            self.principals,
+            self.actions,
+            self.resources
+        )
+    }
+}
+
+/// A sample request whose decision changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessChange {
+    index: usize,
+    principal: Option<EntityUid>,
+    action: Option<EntityUid>,
+    resource: Option<EntityUid>,
+    before: Decision,
+    after: Decision,
+}
+
+impl AccessChange {
+    /// The index of the request in the samples
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The principal of the request
+    pub fn principal(&self) -> Option<&EntityUid> {
+        self.principal.as_ref()
+    }
+
+    /// The action of the request
+    pub fn action(&self) -> Option<&EntityUid> {
+        self.action.as_ref()
+    }
+
+    /// The resource of the request
+    pub fn resource(&self) -> Option<&EntityUid> {
+        self.resource.as_ref()
+    }
+
+    /// The decision before the change
+    pub fn before(&self) -> Decision {
+        self.before
+    }
+
+    /// The decision after the change
+    pub fn after(&self) -> Decision {
+        self.after
+    }
+
+    /// Whether the request is allowed after the change, but was not before
+    pub fn is_gain(&self) -> bool {
+        self.after == Decision::Allow
+    }
+}
+
+impl Display for AccessChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let uid = |uid: &Option<EntityUid>| {
+            uid.as_ref()
+                .map_or_else(|| "unspecified".to_string(), ToString::to_string)
+        };
+        write!(
+            f,
+            "{} {} {} {} (request {})",
+            if self.is_gain() { "+" } else { "-" },
+            uid(&self.principal),
+            uid(&self.action),
+            uid(&self.resource),
+            self.index
+        )
+    }
+}
+
+/// The impact of a change to a policy set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImpactReport {
+    policies: Vec<PolicyImpact>,
+    changes: Vec<AccessChange>,
+    samples: usize,
+}
+
+impl ImpactReport {
+    /// The symbolic impacts of the policies which changed, sorted by kind and
+    /// then by id
+    pub fn policies(&self) -> &[PolicyImpact] {
+        &self.policies
+    }
+
+    /// The sample requests whose decision changed, in order
+    pub fn changes(&self) -> &[AccessChange] {
+        &self.changes
+    }
+
+    /// The sample requests which are allowed only after the change
+    pub fn gained(&self) -> impl Iterator<Item = &AccessChange> {
+        self.changes.iter().filter(|change| change.is_gain())
+    }
+
+    /// The sample requests which are allowed only before the change
+    pub fn lost(&self) -> impl Iterator<Item = &AccessChange> {
+        self.changes.iter().filter(|change| !change.is_gain())
+    }
+
+    /// The principals of the sample requests which gained access
+    pub fn principals_gaining(&self) -> BTreeSet<&EntityUid> {
+        self.gained().filter_map(AccessChange::principal).collect()
+    }
+
+    /// The principals of the sample requests which lost access
+    pub fn principals_losing(&self) -> BTreeSet<&EntityUid> {
+        self.lost().filter_map(AccessChange::principal).collect()
+    }
+
+    /// The resources of the sample requests which gained access
+    pub fn resources_gaining(&self) -> BTreeSet<&EntityUid> {
+        self.gained().filter_map(AccessChange::resource).collect()
+    }
+
+    /// The resources of the sample requests which lost access
+    pub fn resources_losing(&self) -> BTreeSet<&EntityUid> {
+        self.lost().filter_map(AccessChange::resource).collect()
+    }
+
+    /// Whether no policy changed, and so no decision can have changed
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty()
+    }
+}
+
+impl Display for ImpactReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.policies.is_empty() {
+            return writeln!(f, "No policies changed");
+        }
+        writeln!(f, "{} policies changed:", self.policies.len())?;
+        for policy in &self.policies {
+            writeln!(f, "  {policy}")?;
+        }
+        let gained = self.gained().count();
+        writeln!(
+            f,
+            "Replayed {} requests: {gained} gained access, {} lost access",
+            self.samples,
+            self.changes.len() - gained
+        )?;
+        for change in &self.changes {
+            writeln!(f, "  {change}")?;
+        }
+        let mut list = |title: &str, uids: BTreeSet<&EntityUid>| {
+            if uids.is_empty() {
+                return Ok(());
+            }
+            let uids: Vec<String> = uids.iter().map(ToString::to_string).collect();
+            writeln!(f, "{title}: {}", uids.join(", "))
+        };
+        list("Principals gaining access", self.principals_gaining())?;
+        list("Principals losing access", self.principals_losing())?;
+        list("Resources gaining access", self.resources_gaining())?;
+        list("Resources losing access", self.resources_losing())
+    }
+}
+
+/// The policies of `policies`, static and template-linked, by id
+fn policies_by_id(policies: &PolicySet) -> BTreeMap<&str, (&Policy, String)> {
+    policies
+        .policies()
+        .map(|policy| (policy.id().as_ref(), (policy, policy.to_string())))
+        .collect()
+}
+
+/// The impact of changing the policy set `before` to `after`, with the entity
+/// store `entities`: the heads of the policies which changed, and the
+/// `sample_requests` whose decision changed
+pub fn impact(
+    before: &PolicySet,
+    after: &PolicySet,
+    entities: &Entities,
+    sample_requests: &[Request],
+) -> ImpactReport {
+    let old = policies_by_id(before);
+    let new = policies_by_id(after);
+    let ids: BTreeSet<&str> = old.keys().chain(new.keys()).copied().collect();
+    let mut policies = Vec::new();
+    for id in ids {
+        let (kind, old, new) = match (old.get(id), new.get(id)) {
+            (None, Some((new, _))) => (ChangeKind::Added, None, Some(*new)),
+            (Some((old, _)), None) => (ChangeKind::Removed, Some(*old), None),
+            (Some((old, old_text)), Some((new, new_text))) if old_text != new_text => {
+                (ChangeKind::Modified, Some(*old), Some(*new))
+            }
+            _ => continue,
+        };
+        let id = old.or(new).map(|policy| policy.id().clone());
+        if let Some(id) = id {
+            policies.push(PolicyImpact::new(id, kind, old, new, entities));
+        }
+    }
+    // stable, so that policies of the same kind stay sorted by id
+    policies.sort_by_key(PolicyImpact::kind);
+
+    let authorizer = Authorizer::new();
+    let changes = if policies.is_empty() {
+        Vec::new()
+    } else {
+        sample_requests
+            .iter()
+            .enumerate()
+            .filter_map(|(index, request)| {
+                let was = authorizer
+                    .is_authorized(request, before, entities)
+                    .decision();
+                let is = authorizer
+                    .is_authorized(request, after, entities)
+                    .decision();
+                (was != is).then(|| AccessChange {
+                    index,
+                    principal: request.principal().cloned(),
+                    action: request.action().cloned(),
+                    resource: request.resource().cloned(),
+                    before: was,
+                    after: is,
+                })
+            })
+            .collect()
+    };
+    ImpactReport {
+        policies,
+        changes,
+        samples: sample_requests.len(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Context;
+
+    fn uid(s: &str) -> EntityUid {
+        s.parse().expect("valid uid")
+    }
+
+    fn request(principal: &str, action: &str, resource: &str) -> Request {
+        Request::new(
+            Some(uid(principal)),
+            Some(uid(action)),
+            Some(uid(resource)),
+            Context::empty(),
+        )
+    }
+
+    fn entities() -> Entities {
+        Entities::from_json_str(
+            r#"[
+                { "uid": { "type": "User", "id": "alice" }, "attrs": {}, "parents": [{ "type": "Group", "id": "signers" }] },
+                { "uid": { "type": "User", "id": "bob" }, "attrs": {}, "parents": [{ "type": "Group", "id": "signers" }] },
+                { "uid": { "type": "User", "id": "eve" }, "attrs": {}, "parents": [] },
+                { "uid": { "type": "Group", "id": "signers" }, "attrs": {}, "parents": [] }
+            ]"#,
+            None,
+        )
+        .expect("valid entities")
+    }
+
+    fn policies(policies: &[(&str, &str)]) -> PolicySet {
+        PolicySet::from_policies(
+            policies.iter().map(|(id, text)| {
+                Policy::parse(Some((*id).to_string()), *text).expect("valid policy")
+            }),
+        )
+        .expect("unique ids")
+    }
+
+    #[test]
+    fn head_analysis() {
+        let before = policies(&[
+            (
+                "signers",
+                r#"permit(principal in Group::"signers", action == Action::"sign", resource);"#,
+            ),
+            (
+                "freeze",
+                r#"forbid(principal, action, resource == Vault::"frozen");"#,
+            ),
+            (
+                "eve",
+                r#"permit(principal == User::"eve", action == Action::"sign", resource);"#,
+            ),
+        ]);
+        let after = policies(&[
+            (
+                "signers",
+                r#"permit(principal in Group::"signers", action == Action::"sign", resource)
+                when { context.amount < 100 };"#,
+            ),
+            (
+                "eve",
+                r#"permit(principal == User::"eve", action == Action::"sign", resource);"#,
+            ),
+            (
+                "withdraw",
+                r#"permit(principal == User::"eve", action == Action::"withdraw", resource == Vault::"main");"#,
+            ),
+        ]);
+        let report = impact(&before, &after, &entities(), &[]);
+        let summary: Vec<(&str, ChangeKind, Direction)> = report
+            .policies()
+            .iter()
+            .map(|policy| (policy.id().as_ref(), policy.kind(), policy.direction()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("withdraw", ChangeKind::Added, Direction::Grants),
+                ("freeze", ChangeKind::Removed, Direction::Grants),
+                ("signers", ChangeKind::Modified, Direction::Either),
+            ]
+        );
+        let signers = report.policies().last().expect("a policy");
+        assert_eq!(
+            signers.principals(),
+            &Reach::Entities(BTreeSet::from([
+                uid(r#"Group::"signers""#),
+                uid(r#"User::"alice""#),
+                uid(r#"User::"bob""#),
+            ]))
+        );
+        assert!(!signers.principals().contains(&uid(r#"User::"eve""#)));
+        let freeze = report.policies().get(1).expect("a policy");
+        assert_eq!(freeze.principals(), &Reach::All);
+        assert!(freeze.resources().contains(&uid(r#"Vault::"frozen""#)));
+    }
+
+    #[test]
+    fn replay() {
+        let before: PolicySet = r#"
+            permit(principal in Group::"signers", action == Action::"sign", resource);"#
+            .parse()
+            .expect("valid policies");
+        let after: PolicySet = r#"
+            permit(principal in Group::"signers", action == Action::"sign", resource);
+            forbid(principal == User::"bob", action, resource);
+            permit(principal == User::"eve", action == Action::"sign", resource == Vault::"main");"#
+            .parse()
+            .expect("valid policies");
+        let samples = [
+            request(r#"User::"alice""#, r#"Action::"sign""#, r#"Vault::"main""#),
+            request(r#"User::"bob""#, r#"Action::"sign""#, r#"Vault::"main""#),
+            request(r#"User::"eve""#, r#"Action::"sign""#, r#"Vault::"main""#),
+            request(r#"User::"eve""#, r#"Action::"sign""#, r#"Vault::"cold""#),
+        ];
+        let report = impact(&before, &after, &entities(), &samples);
+        let changes: Vec<(usize, Decision, Decision)> = report
+            .changes()
+            .iter()
+            .map(|change| (change.index(), change.before(), change.after()))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (1, Decision::Allow, Decision::Deny),
+                (2, Decision::Deny, Decision::Allow),
+            ]
+        );
+        assert_eq!(
+            report.principals_gaining(),
+            BTreeSet::from([&uid(r#"User::"eve""#)])
+        );
+        assert_eq!(
+            report.principals_losing(),
+            BTreeSet::from([&uid(r#"User::"bob""#)])
+        );
+        let text = report.to_string();
+        assert!(text.contains("2 policies changed"), "{text}");
+        assert!(
+            text.contains("Replayed 4 requests: 1 gained access, 1 lost access"),
+            "{text}"
+        );
+        assert!(
+            text.contains(r#"+ User::"eve" Action::"sign" Vault::"main" (request 2)"#),
+            "{text}"
+        );
+        assert!(
+            text.contains(r#"Principals losing access: User::"bob""#),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn unchanged() {
+        let policies: PolicySet = r#"permit(principal, action, resource);"#
+            .parse()
+            .expect("valid policies");
+        let samples = [request(
+            r#"User::"alice""#,
+            r#"Action::"sign""#,
+            r#"Vault::"main""#,
+        )];
+        let report = impact(&policies, &policies, &entities(), &samples);
+        assert!(report.is_empty());
+        assert!(report.changes().is_empty());
+        assert_eq!(report.to_string(), "No policies changed\n");
+    }
+}
@@ -20,26 +20,32 @@
     clippy::missing_errors_doc,
     clippy::similar_names
 )]
+use crate::Provenance;
 pub use ast::Effect;
 pub use authorizer::Decision;
 use cedar_policy_core::ast;
 use cedar_policy_core::ast::RestrictedExprError;
+pub use cedar_policy_core::ast::{ContextConflict, ContextConflictError};
 use cedar_policy_core::authorizer;
-pub use cedar_policy_core::authorizer::AuthorizationError;
+pub use cedar_policy_core::authorizer::{AuthorizationError, ExtensionFailureMode};
 use cedar_policy_core::entities;
 use cedar_policy_core::entities::JsonDeserializationErrorContext;
 use cedar_policy_core::entities::{ContextSchema, Dereference, JsonDeserializationError};
 use cedar_policy_core::est;
-pub use cedar_policy_core::evaluator::{EvaluationError, EvaluationErrorKind};
+pub use cedar_policy_core::evaluator::{
+    EvaluationError, EvaluationErrorKind, MissingAttributeMode,
+};
 use cedar_policy_core::evaluator::{Evaluator, RestrictedEvaluator};
 pub use cedar_policy_core::extensions;
 use cedar_policy_core::extensions::Extensions;
+pub use cedar_policy_core::memory::{EntitiesMemoryUsage, PolicySetMemoryUsage};
 use cedar_policy_core::parser;
 pub use cedar_policy_core::parser::err::ParseErrors;
 use cedar_policy_core::parser::SourceInfo;
 use cedar_policy_core::FromNormalizedStr;
 pub use cedar_policy_validator::{
-    TypeErrorKind, UnsupportedFeature, ValidationErrorKind, ValidationWarningKind,
+    HumanSchemaParseError, ToHumanSchemaStrError, TypeErrorKind, UnknownWarningCode,
+    UnsupportedFeature, ValidationErrorKind, ValidationWarningKind, WarningCode,
 };
 use ref_cast::RefCast;
 use serde::{Deserialize, Serialize};
@@ -86,7 +92,7 @@ impl From<SlotId> for ast::SlotId {
 /// Entity datatype
 #[repr(transparent)]
 #[derive(Debug, Clone, PartialEq, Eq, RefCast)]
-pub struct Entity(ast::Entity);
+pub struct Entity(pub(crate) ast::Entity);
 
 impl Entity {
     /// Create a new `Entity` with this Uid, attributes, and parents.
@@ -127,6 +133,43 @@ impl Entity {
         ))
     }
 
+    /// Create a new `Entity` with this Uid, attributes, parents, and tags.
+    ///
+    /// Tags are key-value pairs like attributes, but are declared separately
+    /// in the schema and are accessed in policies with `hasTag` and `getTag`.
+    /// Tag values are specified as "restricted expressions", like attribute
+    /// values.
+    /// ```
+    /// # use cedar_policy::{Entity, EntityUid, EvalResult, RestrictedExpression};
+    /// # use std::collections::{HashMap, HashSet};
+    /// # use std::str::FromStr;
+    /// let euid = EntityUid::from_str(r#"Address::"0xabc""#).unwrap();
+    /// let tags = HashMap::from([
+    ///     ("risk_score".to_string(), RestrictedExpression::from_str("87").unwrap()),
+    /// ]);
+    /// let entity = Entity::new_with_tags(euid, HashMap::new(), HashSet::new(), tags);
+    /// assert_eq!(entity.tag("risk_score").unwrap(), Ok(EvalResult::Long(87)));
+    /// assert_eq!(entity.attr("risk_score"), None);
+    /// ```
+    pub fn new_with_tags(
+        uid: EntityUid,
+        attrs: HashMap<String, RestrictedExpression>,
+        parents: HashSet<EntityUid>,
+        tags: HashMap<String, RestrictedExpression>,
+    ) -> Self {
+        Self(ast::Entity::new_with_tags(
+            uid.0,
+            attrs
+                .into_iter()
+                .map(|(k, v)| (SmolStr::from(k), v.0))
+                .collect(),
+            parents.into_iter().map(|uid| uid.0).collect(),
+            tags.into_iter()
+                .map(|(k, v)| (SmolStr::from(k), v.0))
+                .collect(),
+        ))
+    }
+
     /// Create a new `Entity` with this Uid, no attributes, and no parents.
     /// ```
     /// use cedar_policy::{Entity, EntityId, EntityTypeName, EntityUid};
@@ -184,6 +227,20 @@ impl Entity {
                 .map(EvalResult::from),
         )
     }
+
+    /// Get the value for the given tag, or `None` if not present.
+    ///
+    /// This can also return Some(Err) if the tag had an illegal value.
+    pub fn tag(&self, tag: &str) -> Option<Result<EvalResult, EvaluationError>> {
+        let expr = self.0.get_tag(tag)?;
+        let all_ext = Extensions::all_available();
+        let evaluator = RestrictedEvaluator::new(&all_ext);
+        Some(
+            evaluator
+                .interpret(expr.as_borrowed())
+                .map(EvalResult::from),
+        )
+    }
 }
 
 impl std::fmt::Display for Entity {
@@ -198,7 +255,9 @@ impl std::fmt::Display for Entity {
 #[derive(Debug, Clone, Default, PartialEq, Eq, RefCast)]
 pub struct Entities(pub(crate) entities::Entities);
 
-pub use entities::EntitiesError;
+#[cfg(feature = "snapshot")]
+pub use cedar_policy_core::snapshot::SnapshotError;
+pub use entities::{EntitiesError, SnapshotInfo};
 
 impl Entities {
     /// Create a fresh `Entities` with no entities
@@ -218,6 +277,33 @@ impl Entities {
         }
     }
 
+    /// Which chain state the entities were read at, from the `snapshot`
+    /// header of the entities JSON they were parsed from, if it had one.
+    /// Record it alongside decisions to know exactly which chain state they
+    /// were based on.
+    /// ```
+    /// use cedar_policy::Entities;
+    /// let entities = Entities::from_json_str(
+    ///     r#"{ "snapshot": { "chainId": 1, "blockNumber": 19000000 }, "entities": [] }"#,
+    ///     None,
+    /// )
+    /// .unwrap();
+    /// let info = entities.snapshot_info().unwrap();
+    /// assert_eq!(info.chain_id, Some(1));
+    /// assert_eq!(info.block_number, Some(19000000));
+    /// assert_eq!(info.block_hash, None);
+    /// ```
+    pub fn snapshot_info(&self) -> Option<&SnapshotInfo> {
+        self.0.snapshot_info()
+    }
+
+    /// Set which chain state the entities were read at, to be written as the
+    /// header of their entities JSON
+    #[must_use]
+    pub fn with_snapshot_info(self, snapshot_info: Option<SnapshotInfo>) -> Self {
+        Self(self.0.with_snapshot_info(snapshot_info))
+    }
+
     /// Transform the store into a partial store, where
     /// attempting to dereference a non-existent `EntityUID` results in
     /// a residual instead of an error.
@@ -234,23 +320,105 @@ impl Entities {
         Ok(Self(self.0.evaluate()?))
     }
 
+    /// Whether the values of attributes have already been computed via
+    /// [`Entities::evaluate`], so that they are re-used across calls to
+    /// `is_authorized`
+    pub fn is_evaluated(&self) -> bool {
+        self.0.is_evaluated()
+    }
+
     /// Iterate over the `Entity`'s in the `Entities`
     pub fn iter(&self) -> impl Iterator<Item = &Entity> {
         self.0.iter().map(Entity::ref_cast)
     }
 
+    /// Estimate the memory retained by these entities, in bytes, split into
+    /// the entities themselves, their attribute values (including those
+    /// computed by [`Entities::evaluate`]) and their ancestors
+    pub fn approx_memory_usage(&self) -> EntitiesMemoryUsage {
+        self.0.approx_memory_usage()
+    }
+
     /// Create an `Entities` object with the given entities.
-    /// It will error if the entities cannot be read or if the entities hierarchy is cyclic
+    /// It will error if the entities cannot be read, if the same entity UID
+    /// is given more than once, or if the entities hierarchy is cyclic
     pub fn from_entities(
         entities: impl IntoIterator<Item = Entity>,
     ) -> Result<Self, entities::EntitiesError> {
-        entities::Entities::from_entities(
+        Self::from_entities_with_options(entities, EntitiesOptions::default())
+    }
+
+    /// Create an `Entities` object with the given entities, handling the
+    /// transitive closure of the entity hierarchy and any duplicated entity
+    /// UIDs as specified by `options`.
+    /// It will error if the entities hierarchy is cyclic, or (for
+    /// [`TcComputation::EnforceAlreadyComputed`]) not already transitively
+    /// closed.
+    /// ```
+    /// # use cedar_policy::{DuplicateUidStrategy, Entities, EntitiesOptions, Entity, EntityUid, TcComputation};
+    /// # use std::collections::{HashMap, HashSet};
+    /// # use std::str::FromStr;
+    /// let alice = EntityUid::from_str(r#"User::"alice""#).unwrap();
+    /// let signers = EntityUid::from_str(r#"Group::"signers""#).unwrap();
+    /// let dao = EntityUid::from_str(r#"Group::"dao""#).unwrap();
+    /// let entities = Entities::from_entities_with_options(
+    ///     [
+    ///         Entity::new(alice.clone(), HashMap::new(), HashSet::from([signers.clone()])),
+    ///         Entity::new(signers.clone(), HashMap::new(), HashSet::from([dao.clone()])),
+    ///         Entity::new(signers, HashMap::new(), HashSet::new()),
+    ///     ],
+    ///     EntitiesOptions::new()
+    ///         .with_tc_computation(TcComputation::ComputeLazily)
+    ///         .with_duplicates(DuplicateUidStrategy::MergeAttributes),
+    /// )
+    /// .unwrap();
+    /// assert!(entities.is_ancestor_of(&dao, &alice));
+    /// ```
+    pub fn from_entities_with_options(
+        entities: impl IntoIterator<Item = Entity>,
+        options: EntitiesOptions,
+    ) -> Result<Self, entities::EntitiesError> {
+        entities::Entities::from_entities_with_duplicates(
             entities.into_iter().map(|e| e.0),
-            entities::TCComputation::ComputeNow,
+            options.tc_computation.into(),
+            options.duplicates.into(),
         )
         .map(Entities)
     }
 
+    /// Create an `Entities` object containing all of the action entities
+    /// declared in `schema`, with their hierarchy (transitively closed) and
+    /// any declared attributes. This is the same as
+    /// [`Schema::action_entities`].
+    ///
+    /// Use this instead of maintaining the action entities in a separate
+    /// entities file, which can drift from the schema:
+    /// ```
+    /// # use cedar_policy::{Entities, EntityUid, Schema};
+    /// # use std::str::FromStr;
+    /// let schema = Schema::from_str(r#"{"": {
+    ///     "entityTypes": { "User": {} },
+    ///     "actions": {
+    ///         "read": {},
+    ///         "view": { "memberOf": [{ "id": "read" }] }
+    ///     }
+    /// }}"#).unwrap();
+    /// let entities = Entities::from_json_str(
+    ///     r#"[{ "uid": { "type": "User", "id": "alice" }, "attrs": {}, "parents": [] }]"#,
+    ///     Some(&schema),
+    /// )
+    /// .unwrap();
+    /// let actions = Entities::actions_from_schema(&schema).unwrap();
+    /// let entities = entities.add_entities(actions.iter().cloned()).unwrap();
+    /// assert!(entities.is_ancestor_of(
+    ///     &EntityUid::from_str(r#"Action::"read""#).unwrap(),
+    ///     &EntityUid::from_str(r#"Action::"view""#).unwrap(),
+    /// ));
+    /// ```
+    pub fn actions_from_schema(schema: &Schema) -> Result<Self, EntitiesError> {
+        schema.action_entities()
+    }
+
     /// Add all of the [`Entity`]s in the collection to this [`Entities`] structure, re-computing the transitive closure
     /// Re-computing the transitive closure can be expensive, so it is advised to not call this method in a loop
     pub fn add_entities(
@@ -379,12 +547,7 @@ impl Entities {
         json: &str,
         schema: Option<&Schema>,
     ) -> Result<Self, entities::EntitiesError> {
-        let eparser = entities::EntityJsonParser::new(
-            schema.map(|s| cedar_policy_validator::CoreSchema::new(&s.0)),
-            Extensions::all_available(),
-            entities::TCComputation::ComputeNow,
-        );
-        eparser.from_json_str(json).map(Entities)
+        Self::from_json_str_with_options(json, schema, EntitiesOptions::default())
     }
 
     /// Parse an entities JSON file (in `serde_json::Value` form) into an
@@ -420,12 +583,7 @@ impl Entities {
         json: serde_json::Value,
         schema: Option<&Schema>,
     ) -> Result<Self, entities::EntitiesError> {
-        let eparser = entities::EntityJsonParser::new(
-            schema.map(|s| cedar_policy_validator::CoreSchema::new(&s.0)),
-            Extensions::all_available(),
-            entities::TCComputation::ComputeNow,
-        );
-        eparser.from_json_value(json).map(Entities)
+        Self::from_json_value_with_options(json, schema, EntitiesOptions::default())
     }
 
     /// Parse an entities JSON file (in `std::io::Read` form) into an `Entities`
@@ -437,12 +595,64 @@ impl Entities {
     pub fn from_json_file(
         json: impl std::io::Read,
         schema: Option<&Schema>,
+    ) -> Result<Self, entities::EntitiesError> {
+        Self::from_json_file_with_options(json, schema, EntitiesOptions::default())
+    }
+
+    /// Parse an entities JSON file (in `&str` form) into an `Entities`
+    /// object, handling the transitive closure of the entity hierarchy and
+    /// any duplicated entity UIDs as specified by `options`.
+    ///
+    /// See [`Entities::from_json_str`] for the role of `schema`.
+    pub fn from_json_str_with_options(
+        json: &str,
+        schema: Option<&Schema>,
+        options: EntitiesOptions,
     ) -> Result<Self, entities::EntitiesError> {
         let eparser = entities::EntityJsonParser::new(
             schema.map(|s| cedar_policy_validator::CoreSchema::new(&s.0)),
             Extensions::all_available(),
-            entities::TCComputation::ComputeNow,
-        );
+            options.tc_computation.into(),
+        )
+        .with_duplicate_strategy(options.duplicates.into());
+        eparser.from_json_str(json).map(Entities)
+    }
+
+    /// Parse an entities JSON file (in `serde_json::Value` form) into an
+    /// `Entities` object, handling the transitive closure of the entity
+    /// hierarchy and any duplicated entity UIDs as specified by `options`.
+    ///
+    /// See [`Entities::from_json_value`] for the role of `schema`.
+    pub fn from_json_value_with_options(
+        json: serde_json::Value,
+        schema: Option<&Schema>,
+        options: EntitiesOptions,
+    ) -> Result<Self, entities::EntitiesError> {
+        let eparser = entities::EntityJsonParser::new(
+            schema.map(|s| cedar_policy_validator::CoreSchema::new(&s.0)),
+            Extensions::all_available(),
+            options.tc_computation.into(),
+        )
+        .with_duplicate_strategy(options.duplicates.into());
+        eparser.from_json_value(json).map(Entities)
+    }
+
+    /// Parse an entities JSON file (in `std::io::Read` form) into an
+    /// `Entities` object, handling the transitive closure of the entity
+    /// hierarchy and any duplicated entity UIDs as specified by `options`.
+    ///
+    /// See [`Entities::from_json_file`] for the role of `schema`.
+    pub fn from_json_file_with_options(
+        json: impl std::io::Read,
+        schema: Option<&Schema>,
+        options: EntitiesOptions,
+    ) -> Result<Self, entities::EntitiesError> {
+        let eparser = entities::EntityJsonParser::new(
+            schema.map(|s| cedar_policy_validator::CoreSchema::new(&s.0)),
+            Extensions::all_available(),
+            options.tc_computation.into(),
+        )
+        .with_duplicate_strategy(options.duplicates.into());
         eparser.from_json_file(json).map(Entities)
     }
 
@@ -450,7 +660,7 @@ impl Entities {
     /// Same semantics as `b in a` in the Cedar language
     pub fn is_ancestor_of(&self, a: &EntityUid, b: &EntityUid) -> bool {
         match self.0.entity(&b.0) {
-            Dereference::Data(b) => b.is_descendant_of(&a.0),
+            Dereference::Data(b) => self.0.is_descendant_of(b, &a.0),
             _ => a == b, // if b doesn't exist, `b in a` is only true if `b == a`
         }
     }
@@ -465,7 +675,180 @@ impl Entities {
             Dereference::Residual(_) | Dereference::NoSuchEntity => None,
             Dereference::Data(e) => Some(e),
         }?;
-        Some(entity.ancestors().map(EntityUid::ref_cast))
+        Some(self.0.ancestors_of(entity).map(EntityUid::ref_cast))
+    }
+
+    /// The ancestors of `entity`, an entity of these entities, whether or
+    /// not the transitive closure of the hierarchy has been computed.
+    /// [`Entity`] itself only records the parents it was made with when the
+    /// closure is computed lazily.
+    pub(crate) fn transitive_ancestors<'a>(
+        &'a self,
+        entity: &'a Entity,
+    ) -> impl Iterator<Item = &'a EntityUid> {
+        self.0.ancestors_of(&entity.0).map(EntityUid::ref_cast)
+    }
+
+    /// The parent DAG of the entities, by UID: each entity, and each
+    /// ancestor which is not itself an entity, with its direct parents. Since
+    /// only the transitive closure of the hierarchy is stored, the direct
+    /// parents of an entity are its ancestors which are not ancestors of
+    /// another of its ancestors.
+    pub(crate) fn hierarchy(&self) -> BTreeMap<EntityUid, BTreeSet<EntityUid>> {
+        let mut graph: BTreeMap<EntityUid, BTreeSet<EntityUid>> = BTreeMap::new();
+        for entity in self.iter() {
+            let uid = entity.uid();
+            let ancestors: BTreeSet<&EntityUid> =
+                self.ancestors(&uid).into_iter().flatten().collect();
+            let parents: BTreeSet<EntityUid> = ancestors
+                .iter()
+                .filter(|parent| {
+                    !ancestors
+                        .iter()
+                        .any(|other| other != *parent && self.is_ancestor_of(parent, other))
+                })
+                .map(|parent| (*parent).clone())
+                .collect();
+            for parent in &parents {
+                graph.entry(parent.clone()).or_default();
+            }
+            graph.entry(uid).or_default().extend(parents);
+        }
+        graph
+    }
+
+    /// The ids of the policies of `policies` which name each entity, in their
+    /// scope or as a literal in their conditions
+    fn policy_references(policies: &PolicySet) -> HashMap<EntityUid, BTreeSet<String>> {
+        let mut references: HashMap<EntityUid, BTreeSet<String>> = HashMap::new();
+        for policy in policies.policies() {
+            let scope = match policy.principal_constraint() {
+                PrincipalConstraint::Any => None,
+                PrincipalConstraint::In(uid) | PrincipalConstraint::Eq(uid) => Some(uid),
+            }
+            .into_iter()
+            .chain(match policy.action_constraint() {
+                ActionConstraint::Any => Vec::new(),
+                ActionConstraint::In(uids) => uids,
+                ActionConstraint::Eq(uid) => vec![uid],
+            })
+            .chain(match policy.resource_constraint() {
+                ResourceConstraint::Any => None,
+                ResourceConstraint::In(uid) | ResourceConstraint::Eq(uid) => Some(uid),
+            });
+            let literals = policy
+                .ast
+                .non_head_constraints()
+                .subexpressions()
+                .filter_map(|expr| match expr.expr_kind() {
+                    ast::ExprKind::Lit(ast::Literal::EntityUID(uid)) => {
+                        Some(EntityUid(uid.as_ref().clone()))
+                    }
+                    _ => None,
+                });
+            for uid in scope.chain(literals) {
+                references
+                    .entry(uid)
+                    .or_default()
+                    .insert(policy.id().to_string());
+            }
+        }
+        references
+    }
+
+    /// Export the parent DAG of the entities in the Graphviz DOT language,
+    /// with an edge from each entity to each of its direct parents, for
+    /// visualizing group and role structures.
+    ///
+    /// If `policies` are given, each node is also labeled with the ids of the
+    /// policies which name it.
+    /// ```
+    /// # use cedar_policy::Entities;
+    /// let entities = Entities::from_json_str(
+    ///     r#"[
+    ///         {"uid": {"type": "Wallet", "id": "0xabc"}, "attrs": {}, "parents": [{"type": "Group", "id": "signers"}]},
+    ///         {"uid": {"type": "Group", "id": "signers"}, "attrs": {}, "parents": []}
+    ///     ]"#,
+    ///     None,
+    /// )
+    /// .unwrap();
+    /// let dot = entities.to_dot(None);
+    /// assert!(dot.contains(r#""Wallet::\"0xabc\"" -> "Group::\"signers\"";"#));
+    /// ```
+    pub fn to_dot(&self, policies: Option<&PolicySet>) -> String {
+        fn quote(s: &str) -> String {
+            format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+        let references = policies.map(Self::policy_references);
+        let graph = self.hierarchy();
+        let nodes = graph.keys().map(|uid| {
+            let name = uid.to_string();
+            let label = references
+                .as_ref()
+                .and_then(|references| references.get(uid))
+                .map_or_else(
+                    || name.clone(),
+                    |ids| {
+                        let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+                        format!("{name}\npolicies: {}", ids.join(", "))
+                    },
+                );
+            format!(
+                "  {} [label={}];\n",
+                quote(&name),
+                quote(&label).replace('\n', "\\n")
+            )
+        });
+        let edges = graph.iter().flat_map(|(uid, parents)| {
+            parents.iter().map(move |parent| {
+                format!(
+                    "  {} -> {};\n",
+                    quote(&uid.to_string()),
+                    quote(&parent.to_string())
+                )
+            })
+        });
+        std::iter::once("digraph entities {\n".to_string())
+            .chain(nodes)
+            .chain(edges)
+            .chain(std::iter::once("}\n".to_string()))
+            .collect()
+    }
+
+    /// Export the parent DAG of the entities as JSON, for visualizing group
+    /// and role structures in a web UI: an object with the `nodes`, each with
+    /// its `id` (the UID, as in a policy), entity `type` and `entityId`, and
+    /// the `edges`, each `from` an entity `to` one of its direct parents.
+    ///
+    /// If `policies` are given, each node also has the ids of the `policies`
+    /// which name it.
+    pub fn to_json_graph(&self, policies: Option<&PolicySet>) -> serde_json::Value {
+        let references = policies.map(Self::policy_references);
+        let graph = self.hierarchy();
+        let nodes: Vec<serde_json::Value> = graph
+            .keys()
+            .map(|uid| {
+                let mut node = serde_json::json!({
+                    "id": uid.to_string(),
+                    "type": uid.type_name().to_string(),
+                    "entityId": uid.id().as_ref(),
+                });
+                if let (Some(references), Some(node)) = (&references, node.as_object_mut()) {
+                    let ids = references.get(uid).cloned().unwrap_or_default();
+                    node.insert("policies".to_string(), serde_json::json!(ids));
+                }
+                node
+            })
+            .collect();
+        let edges: Vec<serde_json::Value> = graph
+            .iter()
+            .flat_map(|(uid, parents)| {
+                parents.iter().map(move |parent| {
+                    serde_json::json!({ "from": uid.to_string(), "to": parent.to_string() })
+                })
+            })
+            .collect();
+        serde_json::json!({ "nodes": nodes, "edges": edges })
     }
 
     /// Dump an `Entities` object into an entities JSON file.
@@ -481,6 +864,262 @@ impl Entities {
     ) -> std::result::Result<(), entities::EntitiesError> {
         self.0.write_to_json(f)
     }
+
+    /// Parse a CSV file (in [`std::io::Read`] form) into an `Entities` object,
+    /// using `mapping` to interpret its columns.
+    ///
+    /// The first row of the file must be a header naming the columns. Each
+    /// subsequent row describes one entity.
+    ///
+    /// If a `schema` is provided, the resulting entities are checked against
+    /// it exactly as they would be by [`Entities::from_json_file`].
+    /// ```
+    /// # use cedar_policy::{CsvMapping, CsvValueType, Entities, EntityUid, EvalResult};
+    /// # use std::str::FromStr;
+    /// let data = "address,limit,groups\n0xabc,100,admins;signers\n";
+    /// let mapping = CsvMapping::new("address", "Wallet".parse().unwrap())
+    ///     .attr("limit", "limit", CsvValueType::Long)
+    ///     .parents("groups", "Group".parse().unwrap(), ';');
+    /// let entities = Entities::from_csv(data.as_bytes(), &mapping, None).unwrap();
+    /// let wallet = entities.get(&EntityUid::from_str(r#"Wallet::"0xabc""#).unwrap()).unwrap();
+    /// assert_eq!(wallet.attr("limit").unwrap(), Ok(EvalResult::Long(100)));
+    /// ```
+    #[cfg(feature = "csv")]
+    pub fn from_csv(
+        reader: impl std::io::Read,
+        mapping: &CsvMapping,
+        schema: Option<&Schema>,
+    ) -> Result<Self, entities::EntitiesError> {
+        let eparser = entities::EntityJsonParser::new(
+            schema.map(|s| cedar_policy_validator::CoreSchema::new(&s.0)),
+            Extensions::all_available(),
+            entities::TCComputation::ComputeNow,
+        );
+        eparser.from_csv(reader, &mapping.0).map(Entities)
+    }
+
+    /// Write a binary snapshot of these entities to `writer`, which
+    /// [`Entities::from_snapshot_file`] loads without parsing JSON or
+    /// recomputing the entity hierarchy.
+    ///
+    /// Snapshots can only be loaded by the version of Cedar which wrote them.
+    /// ```
+    /// # use cedar_policy::Entities;
+    /// let entities = Entities::from_json_str(
+    ///     r#"[{"uid": {"type": "Wallet", "id": "0xabc"}, "attrs": {}, "parents": []}]"#,
+    ///     None,
+    /// )
+    /// .unwrap();
+    /// let mut snapshot = Vec::new();
+    /// entities.write_snapshot(&mut snapshot).unwrap();
+    /// assert_eq!(Entities::from_snapshot(&snapshot).unwrap(), entities);
+    /// ```
+    #[cfg(feature = "snapshot")]
+    pub fn write_snapshot(
+        &self,
+        writer: impl std::io::Write,
+    ) -> Result<(), entities::EntitiesError> {
+        self.0.write_snapshot(writer)
+    }
+
+    /// Do `bytes`, such as the first bytes of a file, look like a snapshot
+    /// written by [`Entities::write_snapshot`]?
+    #[cfg(feature = "snapshot")]
+    pub fn is_snapshot(bytes: &[u8]) -> bool {
+        entities::Entities::is_snapshot(bytes)
+    }
+
+    /// Load entities from a snapshot written by [`Entities::write_snapshot`]
+    #[cfg(feature = "snapshot")]
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, entities::EntitiesError> {
+        entities::Entities::from_snapshot(bytes).map(Entities)
+    }
+
+    /// Load entities from a snapshot file written by
+    /// [`Entities::write_snapshot`]. The whole file is read and decoded, so
+    /// loading still allocates every entity, but does no JSON parsing.
+    #[cfg(feature = "snapshot")]
+    pub fn from_snapshot_file(
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<Self, entities::EntitiesError> {
+        entities::Entities::from_snapshot_file(path).map(Entities)
+    }
+}
+
+/// Declarative description of how the columns of a CSV file map onto
+/// entities, for use with [`Entities::from_csv`]
+#[cfg(feature = "csv")]
+#[repr(transparent)]
+#[derive(Debug, Clone, PartialEq, Eq, RefCast)]
+pub struct CsvMapping(entities::CsvMapping);
+
+#[cfg(feature = "csv")]
+impl CsvMapping {
+    /// Create a new `CsvMapping` which produces entities of type
+    /// `entity_type`, taking the entity id from `uid_column`
+    pub fn new(uid_column: impl Into<String>, entity_type: EntityTypeName) -> Self {
+        Self(entities::CsvMapping::new(uid_column, entity_type.0))
+    }
+
+    /// Store the contents of `column` in the attribute `attr`, interpreting
+    /// each cell as `ty`.
+    ///
+    /// Empty cells are treated as the attribute being absent, which is how
+    /// optional attributes are expressed.
+    #[must_use]
+    pub fn attr(self, column: impl Into<String>, attr: &str, ty: CsvValueType) -> Self {
+        Self(self.0.attr(column, attr, ty.into()))
+    }
+
+    /// Interpret the contents of `column` as a `separator`-separated list of
+    /// ids of parents of type `entity_type`.
+    ///
+    /// Empty cells and empty list items are ignored.
+    #[must_use]
+    pub fn parents(
+        self,
+        column: impl Into<String>,
+        entity_type: EntityTypeName,
+        separator: char,
+    ) -> Self {
+        Self(self.0.parents(column, entity_type.0, separator))
+    }
+}
+
+/// How the contents of a CSV cell are interpreted by a [`CsvMapping`]
+#[cfg(feature = "csv")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CsvValueType {
+    /// The cell is taken verbatim as a string
+    String,
+    /// The cell is parsed as a (64-bit signed) integer
+    Long,
+    /// The cell is parsed as a boolean (`true` or `false`, case-insensitive)
+    Bool,
+    /// The cell is passed as the string argument to the named extension
+    /// constructor, e.g. `u256`, `ip`, or `decimal`
+    Extension {
+        /// Name of the extension constructor function
+        constructor: String,
+    },
+    /// The cell is the id of an entity of the given type
+    Entity {
+        /// Type of the referenced entity
+        entity_type: EntityTypeName,
+    },
+    /// The cell contains several values separated by `separator`, each of
+    /// which is interpreted according to `element`
+    Set {
+        /// Type of each element of the set
+        element: Box<Self>,
+        /// Separator between elements in a single cell
+        separator: char,
+    },
+}
+
+#[cfg(feature = "csv")]
+impl From<CsvValueType> for entities::CsvValueType {
+    fn from(ty: CsvValueType) -> Self {
+        match ty {
+            CsvValueType::String => Self::String,
+            CsvValueType::Long => Self::Long,
+            CsvValueType::Bool => Self::Bool,
+            CsvValueType::Extension { constructor } => Self::Extension {
+                constructor: constructor.into(),
+            },
+            CsvValueType::Entity { entity_type } => Self::Entity {
+                entity_type: entity_type.0,
+            },
+            CsvValueType::Set { element, separator } => Self::Set {
+                element: Box::new((*element).into()),
+                separator,
+            },
+        }
+    }
+}
+
+/// How the transitive closure (TC) of the entity hierarchy is handled when
+/// constructing an [`Entities`]
+#[derive(Default, Eq, PartialEq, Copy, Clone, Debug)]
+#[non_exhaustive]
+pub enum TcComputation {
+    /// Compute the TC eagerly. The input may contain just parent edges.
+    /// This is the default.
+    #[default]
+    ComputeNow,
+    /// Require the input to already contain all transitive ancestor edges,
+    /// and return an error if it does not
+    EnforceAlreadyComputed,
+    /// Keep only the parent edges given in the input, and follow them on
+    /// demand whenever the hierarchy is queried. Construction is cheap even
+    /// for very large hierarchies, at the cost of slower `in` checks.
+    ComputeLazily,
+}
+
+impl From<TcComputation> for entities::TCComputation {
+    fn from(tc: TcComputation) -> Self {
+        match tc {
+            TcComputation::ComputeNow => Self::ComputeNow,
+            TcComputation::EnforceAlreadyComputed => Self::EnforceAlreadyComputed,
+            TcComputation::ComputeLazily => Self::ComputeLazily,
+        }
+    }
+}
+
+/// What to do when the same entity UID is given more than once while
+/// constructing an [`Entities`]
+#[derive(Default, Eq, PartialEq, Copy, Clone, Debug)]
+#[non_exhaustive]
+pub enum DuplicateUidStrategy {
+    /// Return an error. This is the default.
+    #[default]
+    Error,
+    /// Keep the first definition of the entity and ignore any later ones
+    FirstWins,
+    /// Merge all the definitions of the entity into one, taking the union of
+    /// their attributes and parents. Two definitions giving the same
+    /// attribute different values is an error.
+    MergeAttributes,
+}
+
+impl From<DuplicateUidStrategy> for entities::DuplicateUidStrategy {
+    fn from(duplicates: DuplicateUidStrategy) -> Self {
+        match duplicates {
+            DuplicateUidStrategy::Error => Self::Error,
+            DuplicateUidStrategy::FirstWins => Self::FirstWins,
+            DuplicateUidStrategy::MergeAttributes => Self::MergeAttributes,
+        }
+    }
+}
+
+/// Options for constructing an [`Entities`]
+#[derive(Default, Eq, PartialEq, Copy, Clone, Debug)]
+pub struct EntitiesOptions {
+    tc_computation: TcComputation,
+    duplicates: DuplicateUidStrategy,
+}
+
+impl EntitiesOptions {
+    /// The default options: compute the transitive closure eagerly, and
+    /// error on duplicated entity UIDs
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how the transitive closure of the entity hierarchy is handled
+    #[must_use]
+    pub fn with_tc_computation(self, tc_computation: TcComputation) -> Self {
+        Self {
+            tc_computation,
+            ..self
+        }
+    }
+
+    /// Set how entity UIDs given more than once are handled
+    #[must_use]
+    pub fn with_duplicates(self, duplicates: DuplicateUidStrategy) -> Self {
+        Self { duplicates, ..self }
+    }
 }
 
 /// Authorizer object, which provides responses to authorization queries
@@ -550,26 +1189,140 @@ impl Authorizer {
         Self(authorizer::Authorizer::new())
     }
 
-    /// Returns an authorization response for `r` with respect to the given
-    /// `PolicySet` and `Entities`.
-    ///
-    /// The language spec and Dafny model give a precise definition of how this
-    /// is computed.
+    /// Evaluate accesses of attributes which the entity or record does not
+    /// have, and which are not guarded by `has`, as `mode` says, rather than
+    /// as errors: as a `false` condition, or as an unknown, which
+    /// [`Authorizer::is_authorized`] treats as an error and
+    /// `is_authorized_partial` returns as a residual
     /// ```
-    /// use cedar_policy::{Authorizer,Context,Entities,EntityId,EntityTypeName,
-    /// EntityUid, Request,PolicySet};
-    /// use std::str::FromStr;
-    ///
-    /// // create a request
-    /// let p_eid = EntityId::from_str("alice").unwrap();
-    /// let p_name: EntityTypeName = EntityTypeName::from_str("User").unwrap();
-    /// let p = EntityUid::from_type_name_and_id(p_name, p_eid);
+    /// # use cedar_policy::{Authorizer, Context, Decision, Entities, MissingAttributeMode, PolicySet, Request};
+    /// let policies: PolicySet = r#"
+    ///     forbid(principal, action, resource) when { principal.frozen };
+    ///     permit(principal, action, resource);"#
+    ///     .parse()
+    ///     .unwrap();
+    /// let request = Request::new(
+    ///     Some(r#"Wallet::"0xabc""#.parse().unwrap()),
+    ///     Some(r#"Action::"transfer""#.parse().unwrap()),
+    ///     Some(r#"Token::"usdc""#.parse().unwrap()),
+    ///     Context::empty(),
+    /// );
+    /// let entities = Entities::from_json_str(
+    ///     r#"[{"uid": {"type": "Wallet", "id": "0xabc"}, "attrs": {}, "parents": []}]"#,
+    ///     None,
+    /// )
+    /// .unwrap();
+    /// let authorizer = Authorizer::new().with_missing_attributes(MissingAttributeMode::False);
+    /// let response = authorizer.is_authorized(&request, &policies, &entities);
+    /// // the forbid policy does not apply, and no error is reported
+    /// assert_eq!(response.decision(), Decision::Allow);
+    /// assert_eq!(response.diagnostics().errors().count(), 0);
+    /// ```
+    #[must_use]
+    pub fn with_missing_attributes(self, mode: MissingAttributeMode) -> Self {
+        Self(self.0.with_missing_attributes(mode))
+    }
+
+    /// Handle the policies whose extension functions fail, such as an
+    /// RPC-backed function which timed out, as `mode` says: as errors of the
+    /// single policies (the default), by denying the whole request, or by
+    /// skipping the policies and reporting the failures as
+    /// [`Diagnostics::warnings`]
+    /// ```
+    /// # use cedar_policy::{Authorizer, Context, Decision, Entities, ExtensionFailureMode, PolicySet, Request};
+    /// let policies: PolicySet = r#"
+    ///     forbid(principal, action, resource) when { decimal("oops") == decimal("1.0") };
+    ///     permit(principal, action, resource);"#
+    ///     .parse()
+    ///     .unwrap();
+    /// let request = Request::new(None, None, None, Context::empty());
+    /// let entities = Entities::empty();
     ///
-    /// let a_eid = EntityId::from_str("view").unwrap();
-    /// let a_name: EntityTypeName = EntityTypeName::from_str("Action").unwrap();
-    /// let a = EntityUid::from_type_name_and_id(a_name, a_eid);
+    /// let authorizer = Authorizer::new().with_extension_failures(ExtensionFailureMode::FailClosed);
+    /// let response = authorizer.is_authorized(&request, &policies, &entities);
+    /// assert_eq!(response.decision(), Decision::Deny);
     ///
-    /// let r_eid = EntityId::from_str("trip").unwrap();
+    /// let authorizer =
+    ///     Authorizer::new().with_extension_failures(ExtensionFailureMode::SkipWithWarning);
+    /// let response = authorizer.is_authorized(&request, &policies, &entities);
+    /// assert_eq!(response.decision(), Decision::Allow);
+    /// assert_eq!(response.diagnostics().errors().count(), 0);
+    /// assert_eq!(response.diagnostics().warnings().count(), 1);
+    /// ```
+    #[must_use]
+    pub fn with_extension_failures(self, mode: ExtensionFailureMode) -> Self {
+        Self(self.0.with_extension_failures(mode))
+    }
+
+    /// How accesses of missing attributes are evaluated, as set with
+    /// [`Authorizer::with_missing_attributes`]
+    pub fn missing_attributes(&self) -> MissingAttributeMode {
+        self.0.missing_attributes()
+    }
+
+    /// What is done with policies whose extension functions fail, as set with
+    /// [`Authorizer::with_extension_failures`]
+    pub fn extension_failures(&self) -> ExtensionFailureMode {
+        self.0.extension_failures()
+    }
+
+    /// Create an `Authorizer` which evaluates policies with the functions of
+    /// `overrides` instead of the extension functions of the same name
+    pub(crate) fn with_extension_overrides(overrides: std::sync::Arc<[ast::Extension]>) -> Self {
+        Self(authorizer::Authorizer::with_extensions(
+            Extensions::all_available().with_overrides(overrides),
+        ))
+    }
+
+    /// Like [`Authorizer::is_authorized`], but records the entities and
+    /// extension function calls consulted in `record`
+    pub(crate) fn is_authorized_recording(
+        &self,
+        r: &Request,
+        p: &PolicySet,
+        e: &Entities,
+        record: &cedar_policy_core::evaluator::EvaluationRecord,
+    ) -> Response {
+        self.0
+            .is_authorized_recording(&r.0, &p.ast, &e.0, record)
+            .into()
+    }
+
+    /// Like [`Authorizer::is_authorized`], but reads the attributes and tags
+    /// set in `overlay` in preference to those of the entities
+    #[cfg(feature = "screening")]
+    pub(crate) fn is_authorized_overlaid(
+        &self,
+        r: &Request,
+        p: &PolicySet,
+        e: &Entities,
+        overlay: &cedar_policy_core::evaluator::EntityOverlay,
+    ) -> Response {
+        self.0
+            .is_authorized_overlaid(&r.0, &p.ast, &e.0, overlay)
+            .into()
+    }
+
+    /// Returns an authorization response for `r` with respect to the given
+    /// `PolicySet` and `Entities`.
+    ///
+    /// The language spec and Dafny model give a precise definition of how this
+    /// is computed.
+    /// ```
+    /// use cedar_policy::{Authorizer,Context,Entities,EntityId,EntityTypeName,
+    /// EntityUid, Request,PolicySet};
+    /// use std::str::FromStr;
+    ///
+    /// // create a request
+    /// let p_eid = EntityId::from_str("alice").unwrap();
+    /// let p_name: EntityTypeName = EntityTypeName::from_str("User").unwrap();
+    /// let p = EntityUid::from_type_name_and_id(p_name, p_eid);
+    ///
+    /// let a_eid = EntityId::from_str("view").unwrap();
+    /// let a_name: EntityTypeName = EntityTypeName::from_str("Action").unwrap();
+    /// let a = EntityUid::from_type_name_and_id(a_name, a_eid);
+    ///
+    /// let r_eid = EntityId::from_str("trip").unwrap();
     /// let r_name: EntityTypeName = EntityTypeName::from_str("Album").unwrap();
     /// let r = EntityUid::from_type_name_and_id(r_name, r_eid);
     ///
@@ -609,6 +1362,31 @@ impl Authorizer {
         self.0.is_authorized(&r.0, &p.ast, &e.0).into()
     }
 
+    /// Like [`Authorizer::is_authorized`], but also reports the decision, the
+    /// policies which failed to evaluate, how long it took and whether the
+    /// entity attributes were already computed to `metrics`. See the
+    /// [`metrics`](crate::metrics) module.
+    #[cfg(feature = "metrics")]
+    pub fn is_authorized_with_metrics(
+        &self,
+        r: &Request,
+        p: &PolicySet,
+        e: &Entities,
+        metrics: &dyn crate::metrics::Metrics,
+    ) -> Response {
+        metrics.record_cache_lookup(e.is_evaluated());
+        let start = std::time::Instant::now();
+        let response = self.is_authorized(r, p, e);
+        metrics.record_latency(start.elapsed());
+        metrics.record_decision(response.decision());
+        for error in response.diagnostics().errors() {
+            if let AuthorizationError::PolicyEvaluationError { id, .. } = error {
+                metrics.record_evaluation_error(PolicyId::ref_cast(id));
+            }
+        }
+        response
+    }
+
     /// A partially evaluated authorization request.
     /// The Authorizer will attempt to make as much progress as possible in the presence of unknowns.
     /// If the Authorizer can reach a response, it will return that response.
@@ -669,6 +1447,9 @@ pub struct Diagnostics {
     /// Errors that occurred during authorization. The errors should be
     /// treated as unordered, since policies may be evaluated in any order.
     errors: Vec<AuthorizationError>,
+    /// Failures of extension functions in policies which were skipped, with
+    /// [`ExtensionFailureMode::SkipWithWarning`]
+    warnings: Vec<AuthorizationError>,
 }
 
 impl From<authorizer::Diagnostics> for Diagnostics {
@@ -676,6 +1457,7 @@ impl From<authorizer::Diagnostics> for Diagnostics {
         Self {
             reason: diagnostics.reason.into_iter().map(PolicyId).collect(),
             errors: diagnostics.errors,
+            warnings: diagnostics.warnings,
         }
     }
 }
@@ -797,6 +1579,23 @@ impl Diagnostics {
     pub fn errors(&self) -> impl Iterator<Item = &AuthorizationError> + '_ {
         self.errors.iter()
     }
+
+    /// Get the failures of extension functions in the policies which were
+    /// skipped rather than reported as errors (see
+    /// [`Authorizer::with_extension_failures`])
+    pub fn warnings(&self) -> impl Iterator<Item = &AuthorizationError> + '_ {
+        self.warnings.iter()
+    }
+
+    /// Get the policies that contributed to the decision, each with where it
+    /// came from, if `policies`, the policy set the request was authorized
+    /// against, recorded it (see [`PolicySet::provenance`])
+    pub fn reason_provenance<'a>(
+        &'a self,
+        policies: &'a PolicySet,
+    ) -> impl Iterator<Item = (&'a PolicyId, Option<&'a Provenance>)> + 'a {
+        self.reason.iter().map(|id| (id, policies.provenance(id)))
+    }
 }
 
 impl Response {
@@ -808,7 +1607,11 @@ impl Response {
     ) -> Self {
         Self {
             decision,
-            diagnostics: Diagnostics { reason, errors },
+            diagnostics: Diagnostics {
+                reason,
+                errors,
+                warnings: Vec::new(),
+            },
         }
     }
 
@@ -842,7 +1645,11 @@ impl ResidualResponse {
     ) -> Self {
         Self {
             residuals,
-            diagnostics: Diagnostics { reason, errors },
+            diagnostics: Diagnostics {
+                reason,
+                errors,
+                warnings: Vec::new(),
+            },
         }
     }
 
@@ -900,6 +1707,13 @@ impl Validator {
         Self(cedar_policy_validator::Validator::new(schema.0))
     }
 
+    /// Report the warnings with the given codes as errors, so that they cause
+    /// validation to fail.
+    #[must_use]
+    pub fn with_denied_warnings(self, codes: impl IntoIterator<Item = WarningCode>) -> Self {
+        Self(self.0.with_denied_warnings(codes))
+    }
+
     /// Validate all policies in a policy set, collecting all validation errors
     /// found into the returned `ValidationResult`. Each error is returned together with the
     /// policy id of the policy where the error was found. If a policy id
@@ -912,21 +1726,35 @@ impl Validator {
         pset: &'a PolicySet,
         mode: ValidationMode,
     ) -> ValidationResult<'a> {
-        ValidationResult::from(self.0.validate(&pset.ast, mode.into()))
+        ValidationResult::from(self.0.validate(&pset.ast, mode.into())).with_provenance(pset)
+    }
+
+    /// Validate the slot values of every template-linked policy in a policy
+    /// set. Each value must be an entity of a type declared in the schema,
+    /// and of a type allowed in the scope position of its slot by the actions
+    /// the template applies to (see [`Template::slot_types`]). Templates and
+    /// static policies are not validated; use `validate` for those.
+    pub fn validate_linked_policies<'a>(&'a self, pset: &'a PolicySet) -> ValidationResult<'a> {
+        ValidationResult::from(self.0.validate_linked_policies(&pset.ast)).with_provenance(pset)
     }
 }
 
 /// Contains all the type information used to construct a `Schema` that can be
 /// used to validate a policy.
 #[derive(Debug)]
-pub struct SchemaFragment(cedar_policy_validator::ValidatorSchemaFragment);
+pub struct SchemaFragment {
+    value: cedar_policy_validator::ValidatorSchemaFragment,
+    /// The fragment as written, which is needed to write it back out in
+    /// either schema format
+    lossless: cedar_policy_validator::SchemaFragment,
+}
 
 impl SchemaFragment {
     /// Extract namespaces defined in this `SchemaFragment`. Each namespace
     /// entry defines the name of the namespace and the entity types and actions
     /// that exist in the namespace.
     pub fn namespaces(&self) -> impl Iterator<Item = Option<EntityNamespace>> + '_ {
-        self.0
+        self.value
             .namespaces()
             .map(|ns| ns.as_ref().map(|ns| EntityNamespace(ns.clone())))
     }
@@ -934,16 +1762,61 @@ impl SchemaFragment {
     /// Create an `SchemaFragment` from a JSON value (which should be an
     /// object of the shape required for Cedar schemas).
     pub fn from_json_value(json: serde_json::Value) -> Result<Self, SchemaError> {
-        Ok(Self(
-            cedar_policy_validator::SchemaFragment::from_json_value(json)?.try_into()?,
-        ))
+        cedar_policy_validator::SchemaFragment::from_json_value(json)?.try_into()
     }
 
     /// Create a `SchemaFragment` directly from a file.
     pub fn from_file(file: impl std::io::Read) -> Result<Self, SchemaError> {
-        Ok(Self(
-            cedar_policy_validator::SchemaFragment::from_file(file)?.try_into()?,
-        ))
+        cedar_policy_validator::SchemaFragment::from_file(file)?.try_into()
+    }
+
+    /// Create a `SchemaFragment` from a string containing a schema in the
+    /// human-readable schema format.
+    /// ```
+    /// # use cedar_policy::SchemaFragment;
+    /// let fragment = SchemaFragment::from_str_natural(r#"
+    ///     entity User in [Group];
+    ///     entity Group;
+    ///     action "view" appliesTo { principal: User, resource: Group };
+    /// "#).unwrap();
+    /// assert_eq!(fragment.namespaces().collect::<Vec<_>>(), vec![None]);
+    /// ```
+    pub fn from_str_natural(src: &str) -> Result<Self, SchemaError> {
+        cedar_policy_validator::SchemaFragment::from_str_natural(src)?.try_into()
+    }
+
+    /// Write this `SchemaFragment` in the human-readable schema format. This
+    /// fails if it uses features of the JSON format which have no counterpart
+    /// in the human-readable format, such as action attributes.
+    pub fn as_natural(&self) -> Result<String, ToHumanSchemaStrError> {
+        self.lossless.as_natural_schema()
+    }
+
+    /// Write this `SchemaFragment` in the JSON schema format, with namespaces
+    /// and the declarations in each namespace sorted by name.
+    pub fn to_json_value(&self) -> Result<serde_json::Value, SchemaError> {
+        Ok(self.lossless.to_json_value()?)
+    }
+
+    /// Combine this fragment with `other`, e.g., to compose the fragments
+    /// generated separately for several contracts into one, from which a
+    /// [`Schema`] for validation is then built. Either fragment may refer to
+    /// the declarations of the other. A common type, entity type or action may
+    /// be declared in both so long as both declarations are the same,
+    /// including the types or actions it is a member of.
+    pub fn merge(self, other: Self) -> Result<Self, SchemaError> {
+        self.lossless.merge(other.lossless)?.try_into()
+    }
+}
+
+impl TryFrom<cedar_policy_validator::SchemaFragment> for SchemaFragment {
+    type Error = SchemaError;
+
+    fn try_from(lossless: cedar_policy_validator::SchemaFragment) -> Result<Self, Self::Error> {
+        Ok(Self {
+            value: lossless.clone().try_into()?,
+            lossless,
+        })
     }
 }
 
@@ -955,7 +1828,7 @@ impl TryInto<Schema> for SchemaFragment {
     /// any undeclared entity types are referenced in the schema fragment.
     fn try_into(self) -> Result<Schema, Self::Error> {
         Ok(Schema(
-            cedar_policy_validator::ValidatorSchema::from_schema_fragments([self.0])?,
+            cedar_policy_validator::ValidatorSchema::from_schema_fragments([self.value])?,
         ))
     }
 }
@@ -969,11 +1842,9 @@ impl FromStr for SchemaFragment {
     /// to undefined entities) because this is not required until a `Schema` is
     /// constructed.
     fn from_str(src: &str) -> Result<Self, Self::Err> {
-        Ok(Self(
-            serde_json::from_str::<cedar_policy_validator::SchemaFragment>(src)
-                .map_err(cedar_policy_validator::SchemaError::from)?
-                .try_into()?,
-        ))
+        serde_json::from_str::<cedar_policy_validator::SchemaFragment>(src)
+            .map_err(cedar_policy_validator::SchemaError::from)?
+            .try_into()
     }
 }
 
@@ -1006,7 +1877,7 @@ impl Schema {
     ) -> Result<Self, SchemaError> {
         Ok(Self(
             cedar_policy_validator::ValidatorSchema::from_schema_fragments(
-                fragments.into_iter().map(|f| f.0),
+                fragments.into_iter().map(|f| f.value),
             )?,
         ))
     }
@@ -1026,11 +1897,85 @@ impl Schema {
         )?))
     }
 
+    /// Create a `Schema` from a string containing a schema in the
+    /// human-readable schema format.
+    pub fn from_str_natural(src: &str) -> Result<Self, SchemaError> {
+        Ok(Self(
+            cedar_policy_validator::ValidatorSchema::from_str_natural(src)?,
+        ))
+    }
+
     /// Extract from the schema an `Entities` containing the action entities
     /// declared in the schema.
     pub fn action_entities(&self) -> Result<Entities, entities::EntitiesError> {
         Ok(Entities(self.0.action_entities()?))
     }
+
+    /// Generate a template entities JSON array, with one example entity for
+    /// each entity type in the schema. Every required attribute is stubbed with
+    /// a placeholder of its declared type, and entity references point at the
+    /// example entities, so the template loads with [`Entities::from_json_value`]
+    /// against this schema as is.
+    pub fn entities_skeleton(&self) -> serde_json::Value {
+        self.0.entities_skeleton()
+    }
+
+    /// Find the changes from this schema to a `new` version of it, e.g., to
+    /// check that a schema rollout does not break existing policies or entity
+    /// files. The changes are ordered by the entity type or action they
+    /// affect, with entity types first.
+    pub fn diff(&self, new: &Self) -> Vec<SchemaChange> {
+        self.0.diff(&new.0).into_iter().map(SchemaChange).collect()
+    }
+
+    /// Find the entity types, actions and attributes declared in this schema
+    /// which are not used by `policies`, and the attributes used by `policies`
+    /// which are not declared, e.g., to keep a generated schema tidy. Uses are
+    /// found by typechecking the policies in `mode`, and the issues are
+    /// ordered by the entity type or action they affect.
+    pub fn check_usage(&self, policies: &PolicySet, mode: ValidationMode) -> Vec<SchemaUsageIssue> {
+        self.0
+            .check_usage(&policies.ast, mode.into())
+            .into_iter()
+            .map(SchemaUsageIssue)
+            .collect()
+    }
+}
+
+/// A difference between two versions of a schema, as found by
+/// [`Schema::diff`]
+#[repr(transparent)]
+#[derive(Debug, Clone, PartialEq, Eq, RefCast)]
+pub struct SchemaChange(cedar_policy_validator::SchemaChange);
+
+impl SchemaChange {
+    /// Whether this change may break policies, entity files or requests that
+    /// were valid against the old version of the schema. Removing entity
+    /// types, actions, attributes, parents, or the entity types an action
+    /// applies to is breaking, as are adding a required attribute and changing
+    /// the type of an attribute or of tags. Other additions are compatible.
+    pub fn is_breaking(&self) -> bool {
+        self.0.is_breaking()
+    }
+}
+
+impl std::fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A schema element which is declared but not used by a policy set, or an
+/// attribute which is used but not declared, as found by
+/// [`Schema::check_usage`]
+#[repr(transparent)]
+#[derive(Debug, Clone, PartialEq, Eq, RefCast)]
+pub struct SchemaUsageIssue(cedar_policy_validator::SchemaUsageIssue);
+
+impl std::fmt::Display for SchemaUsageIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
 }
 
 /// Errors encountered during construction of a Validation Schema
@@ -1039,6 +1984,9 @@ pub enum SchemaError {
     /// Error thrown by the `serde_json` crate during deserialization
     #[error("failed to parse schema: {0}")]
     Serde(#[from] serde_json::Error),
+    /// Error parsing a schema in the human-readable schema format
+    #[error("failed to parse schema: {0}")]
+    HumanSchemaParse(HumanSchemaParseError),
     /// Errors occurring while computing or enforcing transitive closure on
     /// action hierarchy.
     #[error("transitive closure computation/enforcement error on action hierarchy: {0}")]
@@ -1073,6 +2021,18 @@ pub enum SchemaError {
     /// Duplicate specification for a reusable type declaration.
     #[error("duplicate common type `{0}`")]
     DuplicateCommonType(String),
+    /// A common type is declared differently in two schemas being merged.
+    /// Argument is the name of the common type.
+    #[error("common type `{0}` is declared differently in the schemas being merged")]
+    ConflictingCommonType(String),
+    /// An entity type is declared differently in two schemas being merged.
+    /// Argument is the name of the entity type.
+    #[error("entity type `{0}` is declared differently in the schemas being merged")]
+    ConflictingEntityType(String),
+    /// An action is declared differently in two schemas being merged.
+    /// Argument is the name of the action.
+    #[error("action `{0}` is declared differently in the schemas being merged")]
+    ConflictingAction(String),
     /// Cycle in the schema's action hierarchy.
     #[error("cycle in action hierarchy")]
     CycleInActionHierarchy,
@@ -1085,6 +2045,10 @@ pub enum SchemaError {
     /// Parse errors occurring while parsing an extension type.
     #[error("parse error in extension type: {0}")]
     ParseExtensionType(ParseErrors),
+    /// An extension type used in the schema is not defined by any of the
+    /// available extensions.
+    #[error("unknown extension type `{0}`")]
+    UnknownExtensionType(String),
     /// Parse errors occurring while parsing the name of a reusable
     /// declared type.
     #[error("parse error in common type identifier: {0}")]
@@ -1107,6 +2071,11 @@ pub enum SchemaError {
     /// This error variant should only be used when `PermitAttributes` is enabled.
     #[error("action `{0}` has an attribute with unsupported JSON representation: {1}")]
     UnsupportedActionAttribute(EntityUid, String),
+    /// The default value declared for an attribute of an entity type shape or
+    /// action context is invalid: it is declared for a required attribute, or
+    /// it does not have the type of the attribute.
+    #[error("invalid default value for attribute `{1}` in {0}: {2}")]
+    InvalidAttributeDefault(ContextOrShape, String, String),
 }
 
 /// Describes in what action context or entity type shape a schema parsing error
@@ -1150,6 +2119,7 @@ impl From<cedar_policy_validator::SchemaError> for SchemaError {
     fn from(value: cedar_policy_validator::SchemaError) -> Self {
         match value {
             cedar_policy_validator::SchemaError::Serde(e) => Self::Serde(e),
+            cedar_policy_validator::SchemaError::HumanSchemaParse(e) => Self::HumanSchemaParse(e),
             cedar_policy_validator::SchemaError::ActionTransitiveClosure(e) => {
                 Self::ActionTransitiveClosure(e.to_string())
             }
@@ -1173,6 +2143,13 @@ impl From<cedar_policy_validator::SchemaError> for SchemaError {
             cedar_policy_validator::SchemaError::DuplicateCommonType(c) => {
                 Self::DuplicateCommonType(c)
             }
+            cedar_policy_validator::SchemaError::ConflictingCommonType(c) => {
+                Self::ConflictingCommonType(c)
+            }
+            cedar_policy_validator::SchemaError::ConflictingEntityType(e) => {
+                Self::ConflictingEntityType(e)
+            }
+            cedar_policy_validator::SchemaError::ConflictingAction(a) => Self::ConflictingAction(a),
             cedar_policy_validator::SchemaError::CycleInActionHierarchy => {
                 Self::CycleInActionHierarchy
             }
@@ -1182,6 +2159,9 @@ impl From<cedar_policy_validator::SchemaError> for SchemaError {
             cedar_policy_validator::SchemaError::ParseExtensionType(e) => {
                 Self::ParseExtensionType(e)
             }
+            cedar_policy_validator::SchemaError::UnknownExtensionType(name) => {
+                Self::UnknownExtensionType(name.to_string())
+            }
             cedar_policy_validator::SchemaError::ActionEntityTypeDeclared => {
                 Self::ActionEntityTypeDeclared
             }
@@ -1194,17 +2174,26 @@ impl From<cedar_policy_validator::SchemaError> for SchemaError {
             cedar_policy_validator::SchemaError::UnsupportedActionAttribute(uid, escape_type) => {
                 Self::UnsupportedActionAttribute(EntityUid(uid), escape_type)
             }
+            cedar_policy_validator::SchemaError::InvalidAttributeDefault(
+                context_or_shape,
+                attr,
+                msg,
+            ) => Self::InvalidAttributeDefault(context_or_shape.into(), attr.to_string(), msg),
         }
     }
 }
 
-/// Contains the result of policy validation. The result includes the list of of
-/// issues found by the validation and whether validation succeeds or fails.
-/// Validation succeeds if there are no fatal errors.  There are currently no
-/// non-fatal warnings, so any issues found will cause validation to fail.
+/// Contains the result of policy validation.
+///
+/// The result includes the list of errors and the list of warnings found by
+/// the validation. Validation succeeds if there are no errors. Warnings do not
+/// cause validation to fail unless the validator is configured to deny them (see
+/// [`Validator::with_denied_warnings`]), in which case they are reported as
+/// errors instead.
 #[derive(Debug)]
 pub struct ValidationResult<'a> {
     validation_errors: Vec<ValidationError<'a>>,
+    validation_warnings: Vec<ValidationWarning<'a>>,
 }
 
 impl<'a> ValidationResult<'a> {
@@ -1217,15 +2206,31 @@ impl<'a> ValidationResult<'a> {
     pub fn validation_errors(&self) -> impl Iterator<Item = &ValidationError<'a>> {
         self.validation_errors.iter()
     }
+
+    /// Get the list of warnings found by the validator.
+    pub fn validation_warnings(&self) -> impl Iterator<Item = &ValidationWarning<'a>> {
+        self.validation_warnings.iter()
+    }
+
+    /// Attach the provenance recorded in `pset` for the policy of each error
+    /// and warning
+    fn with_provenance(mut self, pset: &'a PolicySet) -> Self {
+        for error in &mut self.validation_errors {
+            error.provenance = pset.provenance(error.location.policy_id);
+        }
+        for warning in &mut self.validation_warnings {
+            warning.provenance = pset.provenance(warning.location.policy_id);
+        }
+        self
+    }
 }
 
 impl<'a> From<cedar_policy_validator::ValidationResult<'a>> for ValidationResult<'a> {
     fn from(r: cedar_policy_validator::ValidationResult<'a>) -> Self {
+        let (errors, warnings) = r.into_errors_and_warnings();
         Self {
-            validation_errors: r
-                .into_validation_errors()
-                .map(ValidationError::from)
-                .collect(),
+            validation_errors: errors.map(ValidationError::from).collect(),
+            validation_warnings: warnings.map(ValidationWarning::from).collect(),
         }
     }
 }
@@ -1238,6 +2243,7 @@ impl<'a> From<cedar_policy_validator::ValidationResult<'a>> for ValidationResult
 pub struct ValidationError<'a> {
     location: SourceLocation<'a>,
     error_kind: ValidationErrorKind,
+    provenance: Option<&'a Provenance>,
 }
 
 impl<'a> ValidationError<'a> {
@@ -1250,6 +2256,12 @@ impl<'a> ValidationError<'a> {
     pub fn location(&self) -> &SourceLocation<'a> {
         &self.location
     }
+
+    /// Get where the policy with the issue came from, if the policy set
+    /// recorded it (see [`PolicySet::provenance`]).
+    pub fn provenance(&self) -> Option<&'a Provenance> {
+        self.provenance
+    }
 }
 
 impl<'a> From<cedar_policy_validator::ValidationError<'a>> for ValidationError<'a> {
@@ -1258,6 +2270,7 @@ impl<'a> From<cedar_policy_validator::ValidationError<'a>> for ValidationError<'
         Self {
             location: SourceLocation::from(location),
             error_kind,
+            provenance: None,
         }
     }
 }
@@ -1274,6 +2287,9 @@ impl<'a> std::fmt::Display for ValidationError<'a> {
         {
             write!(f, " at offset {range_start}-{range_end}")?;
         }
+        if let Some(provenance) = self.provenance {
+            write!(f, " (from {provenance})")?;
+        }
         write!(f, ": {}", self.error_kind())
     }
 }
@@ -1329,6 +2345,7 @@ pub fn confusable_string_checker<'a>(
 pub struct ValidationWarning<'a> {
     location: SourceLocation<'a>,
     kind: ValidationWarningKind,
+    provenance: Option<&'a Provenance>,
 }
 
 impl<'a> ValidationWarning<'a> {
@@ -1341,6 +2358,12 @@ impl<'a> ValidationWarning<'a> {
     pub fn location(&self) -> &SourceLocation<'a> {
         &self.location
     }
+
+    /// Get where the policy with the issue came from, if the policy set
+    /// recorded it (see [`PolicySet::provenance`]).
+    pub fn provenance(&self) -> Option<&'a Provenance> {
+        self.provenance
+    }
 }
 
 #[doc(hidden)]
@@ -1353,6 +2376,7 @@ impl<'a> From<cedar_policy_validator::ValidationWarning<'a>> for ValidationWarni
                 source_range: None,
             },
             kind,
+            provenance: None,
         }
     }
 }
@@ -1467,7 +2491,7 @@ impl std::fmt::Display for EntityNamespace {
 /// Unique Id for an entity, such as `User::"alice"`
 #[repr(transparent)]
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, RefCast)]
-pub struct EntityUid(ast::EntityUID);
+pub struct EntityUid(pub(crate) ast::EntityUID);
 
 impl EntityUid {
     /// Returns the portion of the Euid that represents namespace and entity type
@@ -1512,6 +2536,26 @@ impl EntityUid {
         Self(ast::EntityUID::from_components(name.0, id.0))
     }
 
+    /// Creates `EntityUid` from the name of an entity type known to be valid,
+    /// such as one written in the source, and an id
+    /// ```
+    /// # use cedar_policy::EntityUid;
+    /// let action = EntityUid::from_known_type("Action", "transfer");
+    /// assert_eq!(action.to_string(), r#"Action::"transfer""#);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If `type_name` is not a valid entity type name.
+    // PANIC SAFETY: documented above; every string is a valid entity id
+    #[allow(clippy::expect_used)]
+    pub fn from_known_type(type_name: &str, id: &str) -> Self {
+        Self::from_type_name_and_id(
+            EntityTypeName::from_str(type_name).expect("type name should be valid"),
+            EntityId::from_str(id).expect("entity ids should always parse"),
+        )
+    }
+
     /// Creates `EntityUid` from a JSON value, which should have
     /// either the implicit or explicit `__entity` form.
     /// ```
@@ -1528,6 +2572,41 @@ impl EntityUid {
         ))
     }
 
+    /// The canonical UID of the externally owned account at `address` on the
+    /// chain `chain_id`: an `Account` whose id holds the chain and the
+    /// checksummed address, such as
+    /// `Account::"eip155:1:0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"`
+    #[cfg(feature = "address")]
+    pub fn account(chain_id: u64, address: extensions::address::Address) -> Self {
+        Self(ast::EntityUID::account(chain_id, address))
+    }
+
+    /// The canonical UID of the contract at `address` on the chain
+    /// `chain_id`: a `Contract` whose id holds the chain and the checksummed
+    /// address
+    #[cfg(feature = "address")]
+    pub fn contract(chain_id: u64, address: extensions::address::Address) -> Self {
+        Self(ast::EntityUID::contract(chain_id, address))
+    }
+
+    /// Get the chain and address of the canonical UID of an account, failing
+    /// if this is not one
+    #[cfg(feature = "address")]
+    pub fn account_address(
+        &self,
+    ) -> Result<extensions::address::ChainAddress, extensions::address::ChainAddressError> {
+        self.0.account_address()
+    }
+
+    /// Get the chain and address of the canonical UID of a contract, failing
+    /// if this is not one
+    #[cfg(feature = "address")]
+    pub fn contract_address(
+        &self,
+    ) -> Result<extensions::address::ChainAddress, extensions::address::ChainAddressError> {
+        self.0.contract_address()
+    }
+
     /// Testing utility for creating `EntityUids` a bit easier
     #[cfg(test)]
     pub(crate) fn from_strs(typename: &str, id: &str) -> Self {
@@ -1595,6 +2674,79 @@ pub enum PolicySetError {
     /// Expected a template, but a static policy was provided.
     #[error("expected a template, but a static policy was provided")]
     ExpectedTemplate,
+    /// Expected a template-linked policy, but the id is of a static policy
+    /// or a template, or is not in the policy set
+    #[error("`{id}` is not a template-linked policy of the policy set")]
+    ExpectedLink {
+        /// [`PolicyId`] which is not of a template-linked policy
+        id: PolicyId,
+    },
+    /// A slot of a template was linked to an entity of a type which is not
+    /// allowed in that slot, as declared by [`PolicySet::declare_slot_types`]
+    #[error("cannot link slot `{slot}` of template `{template_id}` to `{value}`: expected an entity of type {}", describe_expected_types(.expected))]
+    UnexpectedSlotType {
+        /// [`PolicyId`] of the template
+        template_id: PolicyId,
+        /// The slot
+        slot: SlotId,
+        /// The value provided for the slot
+        value: EntityUid,
+        /// The entity types allowed in the slot
+        expected: Vec<EntityTypeName>,
+    },
+}
+
+/// Errors that can happen when creating a `PolicySet` from its JSON
+/// representation
+#[derive(Debug, Error)]
+pub enum PolicySetFromJsonError {
+    /// The JSON is not of the form of a policy set
+    #[error("invalid JSON policy set: {0}")]
+    Serde(#[from] serde_json::Error),
+    /// The JSON representation of a static policy or template is invalid
+    #[error("invalid JSON for policy `{id}`: {error}")]
+    Policy {
+        /// [`PolicyId`] of the policy or template
+        id: PolicyId,
+        /// The error in its JSON representation
+        error: est::FromJsonError,
+    },
+    /// A template link has a value for something other than `?principal` or
+    /// `?resource`
+    #[error("template link `{id}` has a value for `{slot}`, which is not a slot")]
+    UnknownSlot {
+        /// [`PolicyId`] of the template-linked policy
+        id: PolicyId,
+        /// The key of the value
+        slot: String,
+    },
+    /// A value of a template link is not the JSON representation of an
+    /// entity uid
+    #[error("invalid value for `{slot}` in template link `{id}`: {error}")]
+    SlotValue {
+        /// [`PolicyId`] of the template-linked policy
+        id: PolicyId,
+        /// The slot of the value
+        slot: SlotId,
+        /// The error in the value
+        error: JsonDeserializationError,
+    },
+    /// The policies cannot be added to one policy set, e.g., because two have
+    /// the same id
+    #[error(transparent)]
+    PolicySet(#[from] PolicySetError),
+}
+
+fn describe_expected_types(expected: &[EntityTypeName]) -> String {
+    if expected.is_empty() {
+        "none (no action in the template applies to this slot)".into()
+    } else {
+        expected
+            .iter()
+            .map(|ety| format!("`{ety}`"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
 }
 
 impl From<ast::PolicySetError> for PolicySetError {
@@ -1621,6 +2773,14 @@ pub struct PolicySet {
     policies: HashMap<PolicyId, Policy>,
     /// Templates in the set
     templates: HashMap<PolicyId, Template>,
+    /// The schema declared with `declare_slot_types`, which gives the entity
+    /// types allowed in the slots of every template. Links are not checked
+    /// without one.
+    slot_schema: Option<std::sync::Arc<Schema>>,
+    /// Where templates and policies came from, as recorded by
+    /// `set_provenance` and by the functions loading policy sets from files
+    /// and sources
+    pub(crate) provenance: HashMap<PolicyId, Provenance>,
 }
 
 impl PartialEq for PolicySet {
@@ -1642,30 +2802,76 @@ impl FromStr for PolicySet {
     /// See [`Policy`] for more.
     fn from_str(policies: &str) -> Result<Self, Self::Err> {
         let (texts, pset) = parser::parse_policyset_and_also_return_policy_text(policies)?;
-        // PANIC SAFETY: By the invariant on `parse_policyset_and_also_return_policy_text(policies)`, every `PolicyId` in `pset.policies()` occurs as a key in `text`.
+        Ok(Self::from_texts_and_ast(&texts, pset))
+    }
+}
+
+impl PolicySet {
+    /// Create a `PolicySet` from its AST and the text of each of its static
+    /// policies and templates.
+    /// INVARIANT: `texts` must have the id of every policy and template in
+    /// `pset` as a key, as the parser functions returning policy texts ensure.
+    pub(crate) fn from_texts_and_ast(
+        texts: &HashMap<ast::PolicyID, impl AsRef<str>>,
+        pset: ast::PolicySet,
+    ) -> Self {
+        // PANIC SAFETY: By the invariant on `texts`, every `PolicyId` in `pset.policies()` occurs as a key in `texts`.
         #[allow(clippy::expect_used)]
         let policies = pset.policies().map(|p|
             (
                 PolicyId(p.id().clone()),
-                Policy { lossless: LosslessPolicy::policy_or_template_text(*texts.get(p.id()).expect("internal invariant violation: policy id exists in asts but not texts")), ast: p.clone() }
+                Policy { lossless: LosslessPolicy::policy_or_template_text(texts.get(p.id()).expect("internal invariant violation: policy id exists in asts but not texts").as_ref()), ast: p.clone() }
             )
         ).collect();
-        // PANIC SAFETY: By the same invariant, every `PolicyId` in `pset.templates()` also occurs as a key in `text`.
+        // PANIC SAFETY: By the same invariant, every `PolicyId` in `pset.templates()` also occurs as a key in `texts`.
         #[allow(clippy::expect_used)]
         let templates = pset.templates().map(|t|
             (
                 PolicyId(t.id().clone()),
-                Template { lossless: LosslessPolicy::policy_or_template_text(*texts.get(t.id()).expect("internal invariant violation: template id exists in asts but not ests")), ast: t.clone() }
+                Template { lossless: LosslessPolicy::policy_or_template_text(texts.get(t.id()).expect("internal invariant violation: template id exists in asts but not ests").as_ref()), ast: t.clone() }
             )
         ).collect();
-        Ok(Self {
+        Self {
             ast: pset,
             policies,
             templates,
-        })
+            slot_schema: None,
+            provenance: HashMap::new(),
+        }
     }
 }
 
+/// The JSON representation of a `PolicySet`, as read by
+/// [`PolicySet::from_json_value`] and written by [`PolicySet::to_json`]
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+struct PolicySetJson {
+    /// JSON representations of the static policies, by id
+    #[serde(default)]
+    static_policies: BTreeMap<String, serde_json::Value>,
+    /// JSON representations of the templates, by id
+    #[serde(default)]
+    templates: BTreeMap<String, serde_json::Value>,
+    /// The template-linked policies
+    #[serde(default)]
+    template_links: Vec<TemplateLinkJson>,
+}
+
+/// The JSON representation of a template-linked policy
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+struct TemplateLinkJson {
+    /// Id of the template
+    template_id: String,
+    /// Id of the template-linked policy
+    new_id: String,
+    /// The entity uid each slot is linked to, keyed by `?principal` or
+    /// `?resource`
+    values: BTreeMap<String, entities::EntityUidJSON>,
+}
+
 impl PolicySet {
     /// Create a fresh empty `PolicySet`
     pub fn new() -> Self {
@@ -1673,6 +2879,8 @@ impl PolicySet {
             ast: ast::PolicySet::new(),
             policies: HashMap::new(),
             templates: HashMap::new(),
+            slot_schema: None,
+            provenance: HashMap::new(),
         }
     }
 
@@ -1756,60 +2964,327 @@ impl PolicySet {
         self.ast.is_empty()
     }
 
+    /// Estimate the memory retained by the policies of this set for
+    /// evaluation, in bytes, split into templates (including those of static
+    /// policies) and links. Templates are counted once, however many policies
+    /// link them.
+    pub fn approx_memory_usage(&self) -> PolicySetMemoryUsage {
+        self.ast.approx_memory_usage()
+    }
+
+    /// Write a binary snapshot of this policy set to `writer`, which
+    /// [`PolicySet::from_snapshot_file`] loads without parsing any policy
+    /// text. This suits deployments evaluating policies compiled elsewhere.
+    ///
+    /// Snapshots can only be loaded by the version of Cedar which wrote them.
+    /// Slot types declared with `declare_slot_types` and provenance are not
+    /// kept.
+    /// ```
+    /// # use cedar_policy::PolicySet;
+    /// # use std::str::FromStr;
+    /// let pset = PolicySet::from_str("permit(principal, action, resource);").unwrap();
+    /// let mut snapshot = Vec::new();
+    /// pset.write_snapshot(&mut snapshot).unwrap();
+    /// let loaded = PolicySet::from_snapshot(&snapshot).unwrap();
+    /// assert_eq!(loaded.policies().count(), 1);
+    /// ```
+    #[cfg(feature = "snapshot")]
+    pub fn write_snapshot(&self, writer: impl std::io::Write) -> Result<(), SnapshotError> {
+        self.ast.write_snapshot(writer)
+    }
+
+    /// Do `bytes`, such as the first bytes of a file, look like a snapshot
+    /// written by [`PolicySet::write_snapshot`]?
+    #[cfg(feature = "snapshot")]
+    pub fn is_snapshot(bytes: &[u8]) -> bool {
+        ast::PolicySet::is_snapshot(bytes)
+    }
+
+    /// Load a policy set from a snapshot written by
+    /// [`PolicySet::write_snapshot`]
+    #[cfg(feature = "snapshot")]
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        ast::PolicySet::from_snapshot(bytes).map(Self::from_ast)
+    }
+
+    /// Load a policy set from a snapshot file written by
+    /// [`PolicySet::write_snapshot`]
+    #[cfg(feature = "snapshot")]
+    pub fn from_snapshot_file(path: impl AsRef<std::path::Path>) -> Result<Self, SnapshotError> {
+        ast::PolicySet::from_snapshot_file(path).map(Self::from_ast)
+    }
+
+    /// Declare that the slots of templates may only be linked to the entity
+    /// types which `schema` allows in their scope positions (see
+    /// [`Template::slot_types`]). `link` then rejects slot values of other
+    /// entity types, since the resulting policies could never apply to a
+    /// request allowed by the schema. This holds for every template, including
+    /// those added afterwards, until it is declared again with another schema.
+    pub fn declare_slot_types(&mut self, schema: &Schema) {
+        self.slot_schema = Some(std::sync::Arc::new(schema.clone()));
+    }
+
     /// Attempt to link a template and add the new template-linked policy to the policy set.
     /// If link fails, the `PolicySet` is not modified.
-    /// Failure can happen for three reasons
+    /// Failure can happen for four reasons
     ///   1) The map passed in `vals` may not match the slots in the template
     ///   2) The `new_id` may conflict w/ a policy that already exists in the set
     ///   3) `template_id` does not correspond to a template. Either the id is
     ///   not in the policy set, or it is in the policy set but is either a
     ///   linked or static policy rather than a template
-    #[allow(clippy::needless_pass_by_value)]
+    ///   4) A value in `vals` has an entity type not declared for its slot
     pub fn link(
         &mut self,
         template_id: PolicyId,
         new_id: PolicyId,
         vals: HashMap<SlotId, EntityUid>,
     ) -> Result<(), PolicySetError> {
+        let linked = self.preview_link(template_id, new_id.clone(), vals)?;
+        self.ast.add(linked.ast.clone())?;
+        self.policies.insert(new_id, linked);
+        Ok(())
+    }
+
+    /// Remove the template-linked policy `policy_id` from the policy set, and
+    /// return it. Its template stays in the policy set, so it can be linked
+    /// again, even with the same id.
+    /// Fails if `policy_id` is not the id of a template-linked policy of the
+    /// set; static policies and templates are never removed.
+    /// ```
+    /// # use cedar_policy::{PolicyId, PolicySet, SlotId, Template};
+    /// # use std::collections::HashMap;
+    /// # use std::str::FromStr;
+    /// let mut policies = PolicySet::new();
+    /// let template = Template::parse(
+    ///     Some("t".to_string()),
+    ///     "permit(principal == ?principal, action, resource);",
+    /// )
+    /// .unwrap();
+    /// policies.add_template(template).unwrap();
+    /// let link = PolicyId::from_str("link").unwrap();
+    /// let vals = HashMap::from([(SlotId::principal(), r#"User::"alice""#.parse().unwrap())]);
+    /// policies.link(PolicyId::from_str("t").unwrap(), link.clone(), vals).unwrap();
+    /// assert_eq!(policies.unlink(link.clone()).unwrap().id(), &link);
+    /// assert!(policies.policy(&link).is_none());
+    /// assert!(policies.unlink(PolicyId::from_str("t").unwrap()).is_err());
+    /// ```
+    pub fn unlink(&mut self, policy_id: PolicyId) -> Result<Policy, PolicySetError> {
+        match self.policies.get(&policy_id) {
+            Some(policy) if policy.template_id().is_some() => {}
+            _ => return Err(PolicySetError::ExpectedLink { id: policy_id }),
+        }
+        self.ast.unlink(&policy_id.0);
+        self.provenance.remove(&policy_id);
+        self.policies
+            .remove(&policy_id)
+            .ok_or(PolicySetError::ExpectedLink { id: policy_id })
+    }
+
+    /// The template-linked policy [`PolicySet::link`] would add to the policy
+    /// set, without adding it, so that what a link means can be shown, as
+    /// text (see [`Policy::to_cedar`]), before it is made.
+    /// Fails for the same reasons as [`PolicySet::link`].
+    /// ```
+    /// # use cedar_policy::{PolicyId, PolicySet, SlotId, Template};
+    /// # use std::collections::HashMap;
+    /// # use std::str::FromStr;
+    /// let mut policies = PolicySet::new();
+    /// let template = Template::parse(
+    ///     Some("t".to_string()),
+    ///     "permit(principal == ?principal, action, resource);",
+    /// )
+    /// .unwrap();
+    /// policies.add_template(template).unwrap();
+    /// let preview = policies
+    ///     .preview_link(
+    ///         PolicyId::from_str("t").unwrap(),
+    ///         PolicyId::from_str("link").unwrap(),
+    ///         HashMap::from([(SlotId::principal(), r#"User::"alice""#.parse().unwrap())]),
+    ///     )
+    ///     .unwrap();
+    /// assert!(preview
+    ///     .to_cedar()
+    ///     .unwrap()
+    ///     .contains(r#"principal == User::"alice""#));
+    /// assert!(policies.policy(&PolicyId::from_str("link").unwrap()).is_none());
+    /// ```
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn preview_link(
+        &self,
+        template_id: PolicyId,
+        new_id: PolicyId,
+        vals: HashMap<SlotId, EntityUid>,
+    ) -> Result<Policy, PolicySetError> {
+        let slot_types = self.slot_schema.as_ref().and_then(|schema| {
+            let template = self.templates.get(&template_id)?;
+            Some(schema.0.slot_types(&template.ast))
+        });
+        if let Some(slot_types) = slot_types {
+            for (slot, value) in &vals {
+                let Some(expected) = slot_types.get(&slot.0) else {
+                    continue;
+                };
+                let allowed = match value.0.entity_type() {
+                    ast::EntityType::Concrete(name) => expected.contains(name),
+                    ast::EntityType::Unspecified => false,
+                };
+                if !allowed {
+                    let mut expected = expected
+                        .iter()
+                        .cloned()
+                        .map(EntityTypeName)
+                        .collect::<Vec<_>>();
+                    expected.sort();
+                    return Err(PolicySetError::UnexpectedSlotType {
+                        template_id,
+                        slot: slot.clone(),
+                        value: value.clone(),
+                        expected,
+                    });
+                }
+            }
+        }
         let unwrapped_vals: HashMap<ast::SlotId, ast::EntityUID> = vals
             .into_iter()
             .map(|(key, value)| (key.into(), value.0))
             .collect();
-        let linked_ast = self
-            .ast
-            .link(
-                template_id.0.clone(),
-                new_id.0.clone(),
-                unwrapped_vals.clone(),
-            )
-            .map_err(PolicySetError::LinkingError)?;
-        // PANIC SAFETY: `lossless.link()` will not fail after `ast.link()` succeeds
+        let template = self.ast.get_template(&template_id.0).ok_or_else(|| {
+            ast::LinkingError::NoSuchTemplate {
+                id: template_id.0.clone(),
+            }
+        })?;
+        let linked_ast = ast::Template::link(template, new_id.0.clone(), unwrapped_vals.clone())?;
+        if self.ast.get(&new_id.0).is_some() || self.ast.get_template(&new_id.0).is_some() {
+            return Err(ast::LinkingError::PolicyIdConflict { id: new_id.0 }.into());
+        }
+        // PANIC SAFETY: `lossless.link()` will not fail after `Template::link()` succeeds
         #[allow(clippy::expect_used)]
         let linked_lossless = self
             .templates
             .get(&template_id)
             // We know `template_id` exists in the policy set as either a
-            // template or a static policy because otherwise `ast.link()` would
-            // have errored. If `ast.link()` did not error, then it could still
-            // be that the id corresponds to a static policy. This function
-            // should only be used to link templates, so this is an error.
+            // template or a static policy because otherwise we would have
+            // errored above. It could still be that the id corresponds to a
+            // static policy. This function should only be used to link
+            // templates, so this is an error.
             .ok_or(PolicySetError::ExpectedTemplate)?
             .lossless
             .clone()
             .link(unwrapped_vals.iter().map(|(k, v)| (*k, v)))
             // The only error case for `lossless.link()` is a template with
-            // slots which are not filled by the provided values. `ast.link()`
-            // will have already errored if there are any unfilled slots in the
-            // template.
-            .expect("ast.link() didn't fail above, so this shouldn't fail");
-        self.policies.insert(
-            new_id,
-            Policy {
-                ast: linked_ast.clone(),
-                lossless: linked_lossless,
-            },
-        );
-        Ok(())
+            // slots which are not filled by the provided values.
+            // `Template::link()` will have already errored if there are any
+            // unfilled slots in the template.
+            .expect("Template::link() didn't fail above, so this shouldn't fail");
+        Ok(Policy {
+            ast: linked_ast,
+            lossless: linked_lossless,
+        })
+    }
+
+    /// Create a `PolicySet` from its JSON representation: an object with the
+    /// JSON representations (see [`Policy::to_json`]) of the static policies
+    /// and templates, keyed by their ids, and a list of template links.
+    /// ```
+    /// # use cedar_policy::{PolicyId, PolicySet};
+    /// # use std::str::FromStr;
+    /// let json = serde_json::json!({
+    ///     "staticPolicies": {},
+    ///     "templates": {
+    ///         "viewers": {
+    ///             "effect": "permit",
+    ///             "principal": { "op": "==", "slot": "?principal" },
+    ///             "action": { "op": "All" },
+    ///             "resource": { "op": "All" },
+    ///             "conditions": []
+    ///         }
+    ///     },
+    ///     "templateLinks": [{
+    ///         "templateId": "viewers",
+    ///         "newId": "alice viewer",
+    ///         "values": { "?principal": { "__entity": { "type": "User", "id": "alice" } } }
+    ///     }]
+    /// });
+    /// let policies = PolicySet::from_json_value(json).unwrap();
+    /// let link = policies.policy(&PolicyId::from_str("alice viewer").unwrap()).unwrap();
+    /// assert_eq!(link.template_id(), Some(&PolicyId::from_str("viewers").unwrap()));
+    /// ```
+    pub fn from_json_value(json: serde_json::Value) -> Result<Self, PolicySetFromJsonError> {
+        let json: PolicySetJson = serde_json::from_value(json)?;
+        let mut policies = Self::new();
+        for (id, policy) in json.static_policies {
+            let id = PolicyId(ast::PolicyID::from_string(id));
+            let policy = Policy::from_json(Some(id.clone()), policy)
+                .map_err(|error| PolicySetFromJsonError::Policy { id, error })?;
+            policies.add(policy)?;
+        }
+        for (id, template) in json.templates {
+            let id = PolicyId(ast::PolicyID::from_string(id));
+            let template = Template::from_json(Some(id.clone()), template)
+                .map_err(|error| PolicySetFromJsonError::Policy { id, error })?;
+            policies.add_template(template)?;
+        }
+        for link in json.template_links {
+            let id = PolicyId(ast::PolicyID::from_string(link.new_id));
+            let mut values = HashMap::new();
+            for (slot, value) in link.values {
+                let slot = match slot.as_str() {
+                    "?principal" => SlotId::principal(),
+                    "?resource" => SlotId::resource(),
+                    _ => return Err(PolicySetFromJsonError::UnknownSlot { id, slot }),
+                };
+                let value = value
+                    .into_euid(|| JsonDeserializationErrorContext::EntityUid)
+                    .map_err(|error| PolicySetFromJsonError::SlotValue {
+                        id: id.clone(),
+                        slot: slot.clone(),
+                        error,
+                    })?;
+                values.insert(slot, EntityUid(value));
+            }
+            policies.link(
+                PolicyId(ast::PolicyID::from_string(link.template_id)),
+                id,
+                values,
+            )?;
+        }
+        Ok(policies)
+    }
+
+    /// Create a `PolicySet` from the text of its JSON representation; see
+    /// [`PolicySet::from_json_value`]
+    pub fn from_json_str(json: &str) -> Result<Self, PolicySetFromJsonError> {
+        Self::from_json_value(serde_json::from_str(json)?)
+    }
+
+    /// Get the JSON representation of this `PolicySet`, as read by
+    /// [`PolicySet::from_json_value`]
+    pub fn to_json(&self) -> Result<serde_json::Value, PolicyToJsonError> {
+        let mut json = PolicySetJson::default();
+        for (id, policy) in &self.policies {
+            match policy.template_id() {
+                None => {
+                    json.static_policies
+                        .insert(id.as_ref().to_string(), policy.to_json()?);
+                }
+                Some(template_id) => json.template_links.push(TemplateLinkJson {
+                    template_id: template_id.as_ref().to_string(),
+                    new_id: id.as_ref().to_string(),
+                    values: policy
+                        .ast
+                        .env()
+                        .iter()
+                        .map(|(slot, value)| (slot.to_string(), value.into()))
+                        .collect(),
+                }),
+            }
+        }
+        for (id, template) in &self.templates {
+            json.templates
+                .insert(id.as_ref().to_string(), template.to_json()?);
+        }
+        json.template_links.sort_by(|a, b| a.new_id.cmp(&b.new_id));
+        Ok(serde_json::to_value(json)?)
     }
 
     /// Create a `PolicySet` from its AST representation only. The EST will
@@ -1817,8 +3292,7 @@ impl PolicySet {
     /// create the ESTs from the policy text or CST instead, as the conversion
     /// to AST is lossy. ESTs generated by this method will reflect the AST and
     /// not the original policy syntax.
-    #[cfg_attr(not(feature = "partial-eval"), allow(unused))]
-    fn from_ast(ast: ast::PolicySet) -> Self {
+    pub(crate) fn from_ast(ast: ast::PolicySet) -> Self {
         let policies = ast
             .policies()
             .map(|p| (PolicyId(p.id().clone()), Policy::from_ast(p.clone())))
@@ -1831,6 +3305,8 @@ impl PolicySet {
             ast,
             policies,
             templates,
+            slot_schema: None,
+            provenance: HashMap::new(),
         }
     }
 }
@@ -1887,6 +3363,11 @@ impl Template {
         PolicyId::ref_cast(self.ast.id())
     }
 
+    /// Get the AST of this `Template`
+    pub(crate) fn ast(&self) -> &ast::Template {
+        &self.ast
+    }
+
     /// Clone this `Template` with a new `PolicyId`
     #[must_use]
     pub fn new_id(&self, id: PolicyId) -> Self {
@@ -1896,6 +3377,25 @@ impl Template {
         }
     }
 
+    /// For each slot of this `Template`, the entity types it may be linked to
+    /// such that the linked policy can apply to some request allowed by
+    /// `schema`. These are the principal (resource) types of the actions the
+    /// template applies to, plus, for a slot in an `in` constraint, the
+    /// entity types which may be ancestors of those.
+    pub fn slot_types(&self, schema: &Schema) -> HashMap<SlotId, HashSet<EntityTypeName>> {
+        schema
+            .0
+            .slot_types(&self.ast)
+            .into_iter()
+            .map(|(slot, types)| {
+                (
+                    SlotId(slot),
+                    types.into_iter().map(EntityTypeName).collect(),
+                )
+            })
+            .collect()
+    }
+
     /// Get the `Effect` (`Forbid` or `Permit`) of this `Template`
     pub fn effect(&self) -> Effect {
         self.ast.effect()
@@ -1972,12 +3472,44 @@ impl Template {
         }
     }
 
-    /// Create a `Template` from its JSON representation.
-    /// If `id` is Some, the policy will be given that Policy Id.
+    /// Get the location of this template in the source text it was parsed from,
+    /// including its annotations. For a template parsed as part of a policy set,
+    /// the offsets are into the text of the whole policy set. The location has
+    /// no range if the template was not parsed from text.
+    pub fn source_location(&self) -> SourceLocation<'_> {
+        self.scope_location(self.ast.source_info().policy())
+    }
+
+    /// Get the location of the principal constraint of this template in the
+    /// source text, as for [`Self::source_location`]
+    pub fn principal_constraint_location(&self) -> SourceLocation<'_> {
+        self.scope_location(self.ast.source_info().principal())
+    }
+
+    /// Get the location of the action constraint of this template in the source
+    /// text, as for [`Self::source_location`]
+    pub fn action_constraint_location(&self) -> SourceLocation<'_> {
+        self.scope_location(self.ast.source_info().action())
+    }
+
+    /// Get the location of the resource constraint of this template in the
+    /// source text, as for [`Self::source_location`]
+    pub fn resource_constraint_location(&self) -> SourceLocation<'_> {
+        self.scope_location(self.ast.source_info().resource())
+    }
+
+    fn scope_location(&self, source_range: Option<&SourceInfo>) -> SourceLocation<'_> {
+        SourceLocation {
+            policy_id: self.id(),
+            source_range: source_range.cloned(),
+        }
+    }
+
+    /// Create a `Template` from its JSON representation.
+    /// If `id` is Some, the policy will be given that Policy Id.
     /// If `id` is None, then "JSON policy" will be used.
     /// The behavior around None may change in the future.
-    #[allow(dead_code)] // planned to be a public method in the future
-    fn from_json(
+    pub fn from_json(
         id: Option<PolicyId>,
         json: serde_json::Value,
     ) -> Result<Self, cedar_policy_core::est::FromJsonError> {
@@ -1990,11 +3522,10 @@ impl Template {
     }
 
     /// Get the JSON representation of this `Template`.
-    #[allow(dead_code)] // planned to be a public method in the future
-    fn to_json(&self) -> Result<serde_json::Value, impl std::error::Error> {
+    pub fn to_json(&self) -> Result<serde_json::Value, PolicyToJsonError> {
         let est = self.lossless.est()?;
         let json = serde_json::to_value(est)?;
-        Ok::<_, PolicyToJsonError>(json)
+        Ok(json)
     }
 
     /// Create a `Template` from its AST representation only. The EST will
@@ -2002,7 +3533,6 @@ impl Template {
     /// create the EST from the policy text or CST instead, as the conversion
     /// to AST is lossy. ESTs generated by this method will reflect the AST and
     /// not the original policy syntax.
-    #[cfg_attr(not(feature = "partial-eval"), allow(unused))]
     fn from_ast(ast: ast::Template) -> Self {
         let text = ast.to_string(); // assume that pretty-printing is faster than `est::Policy::from(ast.clone())`; is that true?
         Self {
@@ -2012,6 +3542,12 @@ impl Template {
     }
 }
 
+impl std::fmt::Display for Template {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.ast.fmt(f)
+    }
+}
+
 impl FromStr for Template {
     type Err = ParseErrors;
 
@@ -2113,6 +3649,13 @@ impl FromStr for PolicyId {
     }
 }
 
+impl AsRef<str> for PolicyId {
+    /// The id as it was given, unlike `to_string()`, which escapes it
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
 impl std::fmt::Display for PolicyId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
@@ -2154,11 +3697,41 @@ impl Policy {
         }
     }
 
+    /// Get the values which the slots of this template-linked policy are
+    /// linked to. If this is a static policy, this will return `None`.
+    pub fn template_links(&self) -> Option<HashMap<SlotId, EntityUid>> {
+        if self.is_static() {
+            None
+        } else {
+            Some(
+                self.ast
+                    .env()
+                    .iter()
+                    .map(|(slot, value)| (SlotId::from(*slot), EntityUid(value.clone())))
+                    .collect(),
+            )
+        }
+    }
+
+    /// Get this static policy as a `Template` with the same `PolicyId`, for
+    /// code which handles static policies and templates alike
+    pub(crate) fn static_template(&self) -> Template {
+        Template {
+            ast: self.ast.template().clone(),
+            lossless: self.lossless.clone(),
+        }
+    }
+
     /// Get the `Effect` (`Permit` or `Forbid`) for this instance
     pub fn effect(&self) -> Effect {
         self.ast.effect()
     }
 
+    /// Get the AST of this `Policy`
+    pub(crate) fn ast(&self) -> &ast::Policy {
+        &self.ast
+    }
+
     /// Get an annotation value of this template-linked or static policy
     pub fn annotation(&self, key: impl AsRef<str>) -> Option<&str> {
         self.ast
@@ -2235,6 +3808,40 @@ impl Policy {
         }
     }
 
+    /// Get the location of this policy in the source text it was parsed from,
+    /// including its annotations. For a policy parsed as part of a policy set,
+    /// the offsets are into the text of the whole policy set. The location has
+    /// no range if the policy was not parsed from text. For a template-linked
+    /// policy, this is the location of its template.
+    pub fn source_location(&self) -> SourceLocation<'_> {
+        self.scope_location(self.ast.source_info().policy())
+    }
+
+    /// Get the location of the principal constraint of this policy in the
+    /// source text, as for [`Self::source_location`]
+    pub fn principal_constraint_location(&self) -> SourceLocation<'_> {
+        self.scope_location(self.ast.source_info().principal())
+    }
+
+    /// Get the location of the action constraint of this policy in the source
+    /// text, as for [`Self::source_location`]
+    pub fn action_constraint_location(&self) -> SourceLocation<'_> {
+        self.scope_location(self.ast.source_info().action())
+    }
+
+    /// Get the location of the resource constraint of this policy in the
+    /// source text, as for [`Self::source_location`]
+    pub fn resource_constraint_location(&self) -> SourceLocation<'_> {
+        self.scope_location(self.ast.source_info().resource())
+    }
+
+    fn scope_location(&self, source_range: Option<&SourceInfo>) -> SourceLocation<'_> {
+        SourceLocation {
+            policy_id: self.id(),
+            source_range: source_range.cloned(),
+        }
+    }
+
     /// To avoid panicking, this function may only be called when `slot` is the
     /// `SlotId` corresponding to the scope constraint from which the entity
     /// reference `r` was extracted. I.e., If `r` is taken from the principal
@@ -2367,10 +3974,25 @@ impl Policy {
     /// println!("{}", json);
     /// assert_eq!(policy.to_string(), Policy::from_json(None, json).unwrap().to_string());
     /// ```
-    pub fn to_json(&self) -> Result<serde_json::Value, impl std::error::Error> {
+    pub fn to_json(&self) -> Result<serde_json::Value, PolicyToJsonError> {
         let est = self.lossless.est()?;
         let json = serde_json::to_value(est)?;
-        Ok::<_, PolicyToJsonError>(json)
+        Ok(json)
+    }
+
+    /// The text of this policy. For a template-linked policy, this is the text
+    /// of its template with the values of its slots substituted in, rather
+    /// than the summary its `Display` gives. `None` if the policy was created
+    /// from JSON which does not convert back into a policy.
+    pub fn to_cedar(&self) -> Option<String> {
+        match &self.lossless {
+            LosslessPolicy::Text { text, slots } if slots.is_empty() => Some(text.clone()),
+            _ => {
+                let est = self.lossless.est().ok()?;
+                let ast = est.try_into_ast_policy(Some(self.ast.id().clone())).ok()?;
+                Some(ast.to_string())
+            }
+        }
     }
 
     /// Create a `Policy` from its AST representation only. The `LosslessPolicy`
@@ -2378,7 +4000,6 @@ impl Policy {
     /// create the `Policy` from the policy text, CST, or EST instead, as the
     /// conversion to AST is lossy. ESTs for policies generated by this method
     /// will reflect the AST and not the original policy syntax.
-    #[cfg_attr(not(feature = "partial-eval"), allow(unused))]
     fn from_ast(ast: ast::Policy) -> Self {
         let text = ast.to_string(); // assume that pretty-printing is faster than `est::Policy::from(ast.clone())`; is that true?
         Self {
@@ -2547,7 +4168,7 @@ impl FromStr for Expression {
 ///   - if-then-else expressions
 #[repr(transparent)]
 #[derive(Debug, Clone, RefCast)]
-pub struct RestrictedExpression(ast::RestrictedExpr);
+pub struct RestrictedExpression(pub(crate) ast::RestrictedExpr);
 
 impl RestrictedExpression {
     /// Create an expression representing a literal string.
@@ -2576,6 +4197,156 @@ impl RestrictedExpression {
     pub fn new_set(values: impl IntoIterator<Item = Self>) -> Self {
         Self(ast::RestrictedExpr::set(values.into_iter().map(|v| v.0)))
     }
+
+    /// Create an expression representing a literal entity reference.
+    pub fn new_entity_uid(value: EntityUid) -> Self {
+        Self(ast::RestrictedExpr::val(value.0))
+    }
+
+    /// Create an expression representing a `u256` value.
+    #[cfg(feature = "u256")]
+    pub fn new_u256(value: extensions::u256::U256) -> Self {
+        Self(extensions::u256::u256_expr(value))
+    }
+
+    /// Create an expression representing an `address` value.
+    #[cfg(feature = "address")]
+    pub fn new_address(value: extensions::address::Address) -> Self {
+        Self(extensions::address::address_expr(value))
+    }
+
+    /// Create an expression representing a `bytes` value.
+    #[cfg(feature = "bytes")]
+    pub fn new_bytes(value: impl AsRef<[u8]>) -> Self {
+        Self(extensions::bytes::bytes_expr(value))
+    }
+}
+
+impl From<bool> for RestrictedExpression {
+    fn from(value: bool) -> Self {
+        Self::new_bool(value)
+    }
+}
+
+impl From<i64> for RestrictedExpression {
+    fn from(value: i64) -> Self {
+        Self::new_long(value)
+    }
+}
+
+impl From<String> for RestrictedExpression {
+    fn from(value: String) -> Self {
+        Self::new_string(value)
+    }
+}
+
+impl From<&str> for RestrictedExpression {
+    fn from(value: &str) -> Self {
+        Self::new_string(value.to_string())
+    }
+}
+
+impl From<EntityUid> for RestrictedExpression {
+    fn from(value: EntityUid) -> Self {
+        Self::new_entity_uid(value)
+    }
+}
+
+#[cfg(feature = "u256")]
+impl From<extensions::u256::U256> for RestrictedExpression {
+    fn from(value: extensions::u256::U256) -> Self {
+        Self::new_u256(value)
+    }
+}
+
+#[cfg(feature = "address")]
+impl From<extensions::address::Address> for RestrictedExpression {
+    fn from(value: extensions::address::Address) -> Self {
+        Self::new_address(value)
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl From<extensions::bytes::H256> for RestrictedExpression {
+    fn from(value: extensions::bytes::H256) -> Self {
+        Self::new_bytes(value)
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl From<extensions::bytes::EthBytes> for RestrictedExpression {
+    fn from(value: extensions::bytes::EthBytes) -> Self {
+        Self::new_bytes(value)
+    }
+}
+
+impl From<RecordBuilder> for RestrictedExpression {
+    fn from(value: RecordBuilder) -> Self {
+        value.build()
+    }
+}
+
+/// Builder for records, such as the attributes of an [`Entity`] or a
+/// [`Context`], from values held by the caller rather than JSON.
+///
+/// Attribute values are anything convertible into a [`RestrictedExpression`]:
+/// bools, longs, strings, [`EntityUid`]s, other `RecordBuilder`s, and with the
+/// corresponding features, `U256`, `Address`, `H256` and `Bytes` values of
+/// `ethers`.
+/// ```
+/// use cedar_policy::{EntityUid, RecordBuilder, RestrictedExpression};
+/// use std::str::FromStr;
+/// let treasury = EntityUid::from_str(r#"Wallet::"treasury""#).unwrap();
+/// let context = RecordBuilder::new()
+///     .attr("to", treasury)
+///     .attr("gas", 21000)
+///     .attr(
+///         "simulation",
+///         RecordBuilder::new().attr("reverted", false).attr("logs", 2),
+///     )
+///     .attr(
+///         "tags",
+///         RestrictedExpression::new_set(["defi".into(), "swap".into()]),
+///     )
+///     .build_context();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RecordBuilder {
+    fields: Vec<(String, RestrictedExpression)>,
+}
+
+impl RecordBuilder {
+    /// Create a builder for an empty record
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the attribute `name` to `value`, replacing any value it was set to
+    #[must_use]
+    pub fn attr(mut self, name: impl Into<String>, value: impl Into<RestrictedExpression>) -> Self {
+        let name = name.into();
+        let value = value.into();
+        match self.fields.iter_mut().find(|(k, _)| *k == name) {
+            Some((_, existing)) => *existing = value,
+            None => self.fields.push((name, value)),
+        }
+        self
+    }
+
+    /// Create the record
+    pub fn build(self) -> RestrictedExpression {
+        RestrictedExpression::new_record(self.fields)
+    }
+
+    /// Create a [`Context`] with the attributes of the record
+    pub fn build_context(self) -> Context {
+        Context::from_pairs(self.fields)
+    }
+
+    /// Create the attributes of an [`Entity`], for [`Entity::new`]
+    pub fn build_attrs(self) -> HashMap<String, RestrictedExpression> {
+        self.fields.into_iter().collect()
+    }
 }
 
 impl FromStr for RestrictedExpression {
@@ -2973,6 +4744,37 @@ impl Context {
         Ok(Self(context))
     }
 
+    /// Merge the attributes of `overlay` into those of `base`, deciding with
+    /// `conflict` what happens to attributes set by both. This assembles a
+    /// context from several sources, such as transaction fields, simulation
+    /// results and risk scores, each parsed on its own.
+    ///
+    /// If a `schema` is provided, the merged context is checked against the
+    /// context type of the action, as [`Context::from_json_value`] would check
+    /// it, and attributes it lacks are given their default values.
+    /// ```
+    /// use cedar_policy::{Context, ContextConflict};
+    /// let tx = Context::from_json_value(serde_json::json!({ "value": 5 }), None).unwrap();
+    /// let risk = Context::from_json_value(serde_json::json!({ "score": 2 }), None).unwrap();
+    /// let context = Context::merge(&tx, &risk, ContextConflict::Error, None).unwrap();
+    /// ```
+    pub fn merge(
+        base: &Self,
+        overlay: &Self,
+        conflict: ContextConflict,
+        schema: Option<(&Schema, &EntityUid)>,
+    ) -> Result<Self, ContextMergeError> {
+        let context = ast::Context::merge(&base.0, &overlay.0, conflict)?;
+        let schema = schema
+            .map(|(s, uid)| Self::get_context_schema(s, uid))
+            .transpose()?;
+        let context =
+            entities::ContextJsonParser::new(schema.as_ref(), Extensions::all_available())
+                .conform(context)
+                .map_err(ContextJsonError::from)?;
+        Ok(Self(context))
+    }
+
     /// Internal helper function to convert `(&Schema, &EntityUid)` to `impl ContextSchema`
     fn get_context_schema(
         schema: &Schema,
@@ -3001,6 +4803,17 @@ pub enum ContextJsonError {
     },
 }
 
+/// Error type for merging `Context`s
+#[derive(Debug, Error)]
+pub enum ContextMergeError {
+    /// Both contexts set an attribute, which the conflict policy forbids
+    #[error(transparent)]
+    Conflict(#[from] ContextConflictError),
+    /// The merged context does not conform to the supplied schema
+    #[error(transparent)]
+    Schema(#[from] ContextJsonError),
+}
+
 impl std::fmt::Display for Request {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
@@ -3108,6 +4921,78 @@ impl From<ast::Value> for EvalResult {
         }
     }
 }
+
+/// Error converting an [`EvalResult`] into a value of `ethers`
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("expected {expected} value, got `{got}`")]
+pub struct EvalResultConversionError {
+    /// Extension type which was expected
+    expected: &'static str,
+    /// Value which was found instead
+    got: String,
+}
+
+impl EvalResult {
+    /// The string form of this extension value, if it is one, parsed with
+    /// `parse`
+    #[cfg(any(feature = "u256", feature = "address", feature = "bytes"))]
+    fn parse_extension_value<T>(
+        &self,
+        expected: &'static str,
+        parse: impl FnOnce(&str) -> Option<T>,
+    ) -> Result<T, EvalResultConversionError> {
+        match self {
+            Self::ExtensionValue(s) => parse(s),
+            _ => None,
+        }
+        .ok_or_else(|| EvalResultConversionError {
+            expected,
+            got: self.to_string(),
+        })
+    }
+}
+
+#[cfg(feature = "u256")]
+impl TryFrom<EvalResult> for extensions::u256::U256 {
+    type Error = EvalResultConversionError;
+
+    fn try_from(value: EvalResult) -> Result<Self, Self::Error> {
+        value.parse_extension_value("u256", extensions::u256::parse_u256)
+    }
+}
+
+#[cfg(feature = "address")]
+impl TryFrom<EvalResult> for extensions::address::Address {
+    type Error = EvalResultConversionError;
+
+    fn try_from(value: EvalResult) -> Result<Self, Self::Error> {
+        value.parse_extension_value("address", extensions::address::parse_address)
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl TryFrom<EvalResult> for extensions::bytes::EthBytes {
+    type Error = EvalResultConversionError;
+
+    fn try_from(value: EvalResult) -> Result<Self, Self::Error> {
+        value
+            .parse_extension_value("bytes", extensions::bytes::parse_bytes)
+            .map(Self::from)
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl TryFrom<EvalResult> for extensions::bytes::H256 {
+    type Error = EvalResultConversionError;
+
+    fn try_from(value: EvalResult) -> Result<Self, Self::Error> {
+        value.parse_extension_value("32-byte bytes", |s| {
+            extensions::bytes::parse_bytes(s)
+                .filter(|bytes| bytes.len() == Self::len_bytes())
+                .map(|bytes| Self::from_slice(&bytes))
+        })
+    }
+}
 impl std::fmt::Display for EvalResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -3401,12 +5286,81 @@ permit(principal ==  A :: B
         assert!(a.is_none());
         assert!(r.is_none());
     }
+
+    /// accounts and contracts have canonical UIDs which match policies
+    #[test]
+    #[cfg(feature = "address")]
+    fn chain_scoped_uids() {
+        let address =
+            extensions::address::Address::from_str("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed")
+                .expect("valid address");
+        let account = EntityUid::account(1, address);
+        assert_eq!(
+            account,
+            EntityUid::from_str(
+                r#"Account::"eip155:1:0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed""#
+            )
+            .expect("valid uid")
+        );
+        assert_eq!(
+            account.account_address().map(|a| (a.chain_id, a.address)),
+            Ok((1, address))
+        );
+        assert!(account.contract_address().is_err());
+        let contract = EntityUid::contract(137, address);
+        assert_eq!(contract.type_name().to_string(), "Contract");
+        assert_eq!(contract.contract_address().map(|c| c.chain_id), Ok(137));
+    }
 }
 
 #[cfg(test)]
 mod head_constraints_tests {
     use super::*;
 
+    #[test]
+    fn constraint_locations() {
+        let src = r#"permit(principal, action, resource);
+@id("t")
+permit(principal == ?principal, action in [Action::"a"], resource);"#;
+        let pset = PolicySet::from_str(src).expect("should parse");
+        let template = pset
+            .template(&PolicyId::from_str("policy1").unwrap())
+            .expect("should be a template");
+        let text = |loc: SourceLocation<'_>| {
+            loc.range_start()
+                .zip(loc.range_end())
+                .map(|(start, end)| &src[start..end])
+        };
+        assert_eq!(
+            text(template.source_location()),
+            Some(src.lines().skip(1).collect::<Vec<_>>().join("\n").as_str())
+        );
+        assert_eq!(
+            text(template.principal_constraint_location()),
+            Some("principal == ?principal")
+        );
+        assert_eq!(
+            text(template.action_constraint_location()),
+            Some(r#"action in [Action::"a"]"#)
+        );
+        assert_eq!(
+            text(template.resource_constraint_location()),
+            Some("resource")
+        );
+
+        let policy = pset
+            .policy(&PolicyId::from_str("policy0").unwrap())
+            .expect("should be a policy");
+        assert_eq!(
+            policy.principal_constraint_location().range_start(),
+            Some(7)
+        );
+        assert_eq!(policy.principal_constraint_location().range_end(), Some(16));
+
+        let policy = Policy::from_json(None, policy.to_json().unwrap()).unwrap();
+        assert_eq!(policy.source_location().range_start(), None);
+    }
+
     #[test]
     fn principal_constraint_inline() {
         let p = Policy::from_str("permit(principal,action,resource);").unwrap();
@@ -3573,6 +5527,7 @@ mod head_constraints_tests {
 #[cfg(test)]
 mod policy_set_tests {
     use super::*;
+    use crate::Origin;
     use ast::LinkingError;
     use cool_asserts::assert_matches;
 
@@ -3607,6 +5562,283 @@ mod policy_set_tests {
         };
     }
 
+    #[test]
+    fn memory_usage() {
+        let mut pset = PolicySet::new();
+        let template = Template::parse(
+            Some("t".into()),
+            "permit(principal == ?principal, action, resource);",
+        )
+        .expect("Failed to parse");
+        pset.add_template(template).expect("Add failed");
+        let before = pset.approx_memory_usage();
+        let env: HashMap<SlotId, EntityUid> =
+            std::iter::once((SlotId::principal(), EntityUid::from_strs("Test", "test"))).collect();
+        pset.link(
+            PolicyId::from_str("t").unwrap(),
+            PolicyId::from_str("link").unwrap(),
+            env,
+        )
+        .expect("Failed to link");
+        let after = pset.approx_memory_usage();
+        assert_eq!(after.templates, before.templates);
+        assert!(after.links > before.links);
+    }
+
+    #[test]
+    fn link_typed_slots() {
+        let schema = Schema::from_str_natural(
+            r#"
+            entity Team;
+            entity User in [Team];
+            entity Vault;
+            action "withdraw" appliesTo { principal: [User], resource: [Vault] };
+            "#,
+        )
+        .expect("schema should be valid");
+        let mut pset = PolicySet::new();
+        let template = Template::parse(
+            Some("t".into()),
+            r#"permit(principal in ?principal, action == Action::"withdraw", resource == ?resource);"#,
+        )
+        .expect("Failed to parse");
+        assert_eq!(
+            template.slot_types(&schema),
+            HashMap::from([
+                (
+                    SlotId::principal(),
+                    HashSet::from([
+                        EntityTypeName::from_str("User").unwrap(),
+                        EntityTypeName::from_str("Team").unwrap()
+                    ])
+                ),
+                (
+                    SlotId::resource(),
+                    HashSet::from([EntityTypeName::from_str("Vault").unwrap()])
+                ),
+            ])
+        );
+        pset.add_template(template).expect("Failed to add");
+        pset.declare_slot_types(&schema);
+
+        let env = |principal: EntityUid, resource: EntityUid| {
+            HashMap::from([
+                (SlotId::principal(), principal),
+                (SlotId::resource(), resource),
+            ])
+        };
+        pset.link(
+            PolicyId::from_str("t").unwrap(),
+            PolicyId::from_str("team").unwrap(),
+            env(
+                EntityUid::from_strs("Team", "devs"),
+                EntityUid::from_strs("Vault", "v"),
+            ),
+        )
+        .expect("Failed to link");
+        assert_matches!(
+            pset.link(
+                PolicyId::from_str("t").unwrap(),
+                PolicyId::from_str("wrong").unwrap(),
+                env(
+                    EntityUid::from_strs("User", "alice"),
+                    EntityUid::from_strs("User", "bob"),
+                ),
+            ),
+            Err(e @ PolicySetError::UnexpectedSlotType { .. }) => {
+                assert_eq!(
+                    e.to_string(),
+                    r#"cannot link slot `?resource` of template `t` to `User::"bob"`: expected an entity of type `Vault`"#
+                );
+            }
+        );
+        assert!(pset.policy(&PolicyId::from_str("wrong").unwrap()).is_none());
+
+        // templates added after the declaration are checked too
+        let later = Template::parse(
+            Some("later".into()),
+            r#"permit(principal, action == Action::"withdraw", resource == ?resource);"#,
+        )
+        .expect("Failed to parse");
+        pset.add_template(later).expect("Failed to add");
+        assert_matches!(
+            pset.link(
+                PolicyId::from_str("later").unwrap(),
+                PolicyId::from_str("later_wrong").unwrap(),
+                HashMap::from([(SlotId::resource(), EntityUid::from_strs("Team", "devs"))]),
+            ),
+            Err(PolicySetError::UnexpectedSlotType { .. })
+        );
+        pset.link(
+            PolicyId::from_str("later").unwrap(),
+            PolicyId::from_str("later_vault").unwrap(),
+            HashMap::from([(SlotId::resource(), EntityUid::from_strs("Vault", "v"))]),
+        )
+        .expect("Failed to link");
+    }
+
+    #[test]
+    fn preview_link() {
+        let mut pset = PolicySet::new();
+        let template = Template::parse(
+            Some("t".into()),
+            r#"permit(principal == ?principal, action, resource in ?resource) when { context.amount < 10 };"#,
+        )
+        .expect("Failed to parse");
+        pset.add_template(template).expect("Add failed");
+        let env = HashMap::from([
+            (SlotId::principal(), EntityUid::from_strs("User", "alice")),
+            (SlotId::resource(), EntityUid::from_strs("Vault", "v")),
+        ]);
+
+        let preview = pset
+            .preview_link(
+                PolicyId::from_str("t").unwrap(),
+                PolicyId::from_str("link").unwrap(),
+                env.clone(),
+            )
+            .expect("Failed to preview");
+        assert_eq!(
+            preview.to_cedar().unwrap(),
+            "permit(\n  principal == User::\"alice\",\n  action,\n  resource in Vault::\"v\"\n) when {\n  (context[\"amount\"]) < 10\n};"
+        );
+        assert_eq!(
+            preview.template_id(),
+            Some(&PolicyId::from_str("t").unwrap())
+        );
+        assert!(pset.policy(&PolicyId::from_str("link").unwrap()).is_none());
+
+        pset.link(
+            PolicyId::from_str("t").unwrap(),
+            PolicyId::from_str("link").unwrap(),
+            env.clone(),
+        )
+        .expect("Failed to link");
+        assert_eq!(
+            pset.policy(&PolicyId::from_str("link").unwrap())
+                .and_then(Policy::to_cedar),
+            preview.to_cedar()
+        );
+        assert_matches!(
+            pset.preview_link(
+                PolicyId::from_str("t").unwrap(),
+                PolicyId::from_str("link").unwrap(),
+                env.clone(),
+            ),
+            Err(PolicySetError::LinkingError(
+                LinkingError::PolicyIdConflict { .. }
+            ))
+        );
+        assert_matches!(
+            pset.preview_link(
+                PolicyId::from_str("missing").unwrap(),
+                PolicyId::from_str("other").unwrap(),
+                env,
+            ),
+            Err(PolicySetError::LinkingError(
+                LinkingError::NoSuchTemplate { .. }
+            ))
+        );
+        assert_matches!(
+            pset.preview_link(
+                PolicyId::from_str("t").unwrap(),
+                PolicyId::from_str("other").unwrap(),
+                HashMap::new(),
+            ),
+            Err(PolicySetError::LinkingError(
+                LinkingError::ArityError { .. }
+            ))
+        );
+    }
+
+    #[test]
+    fn validate_linked_policies() {
+        let schema = Schema::from_str_natural(
+            r#"
+            entity Team;
+            entity User in [Team];
+            entity Vault;
+            action "withdraw" appliesTo { principal: [User], resource: [Vault] };
+            "#,
+        )
+        .expect("schema should be valid");
+        let mut pset = PolicySet::new();
+        let template = Template::parse(
+            Some("t".into()),
+            r#"permit(principal in ?principal, action == Action::"withdraw", resource == ?resource);"#,
+        )
+        .expect("Failed to parse");
+        pset.add_template(template).expect("Failed to add");
+        for (id, principal) in [("team", "Team"), ("vault", "Vault")] {
+            pset.link(
+                PolicyId::from_str("t").unwrap(),
+                PolicyId::from_str(id).unwrap(),
+                HashMap::from([
+                    (SlotId::principal(), EntityUid::from_strs(principal, "p")),
+                    (SlotId::resource(), EntityUid::from_strs("Vault", "v")),
+                ]),
+            )
+            .expect("Failed to link");
+        }
+
+        let validator = Validator::new(schema);
+        let result = validator.validate_linked_policies(&pset);
+        let errors = result.validation_errors().collect::<Vec<_>>();
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert_eq!(
+            errors[0].location().policy_id(),
+            &PolicyId::from_str("vault").unwrap()
+        );
+        assert_eq!(
+            errors[0].error_kind().to_string(),
+            "slot `?principal` is linked to an entity of type `Vault`, which is not one of the expected types `Team`, `User`"
+        );
+    }
+
+    #[test]
+    fn validation_errors_have_provenance() {
+        let schema = Schema::from_str_natural(
+            r#"
+            entity User;
+            action "withdraw" appliesTo { principal: [User], resource: [User] };
+            "#,
+        )
+        .expect("schema should be valid");
+        let src = r#"permit(principal == Vault::"v", action, resource);
+permit(principal, action, resource);"#;
+        let mut pset = PolicySet::from_str(src).expect("Failed to parse");
+        pset.record_origin(&Origin::File("policies/vaults.cedar".into()));
+
+        let validator = Validator::new(schema);
+        let result = validator.validate(&pset, ValidationMode::default());
+        let errors = result.validation_errors().collect::<Vec<_>>();
+        assert_eq!(errors.len(), 2, "{errors:?}");
+        assert_eq!(
+            errors[0].provenance(),
+            Some(&Provenance {
+                origin: Origin::File("policies/vaults.cedar".into()),
+                span: Some(0..50),
+            })
+        );
+        assert!(errors[0]
+            .to_string()
+            .contains("(from `policies/vaults.cedar` at offset 0-50)"));
+
+        let request = Request::new(None, None, None, Context::empty());
+        let response = Authorizer::new().is_authorized(&request, &pset, &Entities::empty());
+        assert_eq!(
+            response
+                .diagnostics()
+                .reason_provenance(&pset)
+                .map(|(id, provenance)| (id.to_string(), provenance.map(ToString::to_string)))
+                .collect::<Vec<_>>(),
+            vec![(
+                "policy1".to_string(),
+                Some("`policies/vaults.cedar` at offset 51-87".to_string())
+            )]
+        );
+    }
+
     #[test]
     fn policyset_add() {
         let mut pset = PolicySet::new();
@@ -3779,6 +6011,122 @@ mod policy_set_tests {
             ))
         );
     }
+
+    #[test]
+    fn json_round_trip() {
+        let mut pset = PolicySet::from_str(
+            r#"
+            @id("ignored")
+            permit(principal, action == Action::"view", resource) when { context.level > 2 };
+            forbid(principal == ?principal, action, resource in ?resource);
+            "#,
+        )
+        .unwrap();
+        pset.link(
+            PolicyId::from_str("policy1").unwrap(),
+            PolicyId::from_str("bob's link").unwrap(),
+            [
+                (SlotId::principal(), EntityUid::from_strs("User", "bob")),
+                (SlotId::resource(), EntityUid::from_strs("Folder", "docs")),
+            ]
+            .into_iter()
+            .collect(),
+        )
+        .unwrap();
+
+        let json = pset.to_json().unwrap();
+        assert_eq!(
+            json.get("templateLinks"),
+            Some(&serde_json::json!([{
+                "templateId": "policy1",
+                "newId": "bob's link",
+                "values": {
+                    "?principal": { "__entity": { "type": "User", "id": "bob" } },
+                    "?resource": { "__entity": { "type": "Folder", "id": "docs" } },
+                },
+            }]))
+        );
+        assert_eq!(
+            json.get("staticPolicies")
+                .and_then(|policies| policies.get("policy0")),
+            Some(
+                &pset
+                    .policy(&PolicyId::from_str("policy0").unwrap())
+                    .unwrap()
+                    .to_json()
+                    .unwrap()
+            )
+        );
+
+        let parsed = PolicySet::from_json_value(json.clone()).unwrap();
+        assert_eq!(parsed.to_json().unwrap(), json);
+        let link = parsed
+            .policy(&PolicyId::from_str("bob's link").unwrap())
+            .unwrap();
+        assert_eq!(
+            link.template_links(),
+            Some(
+                [
+                    (SlotId::principal(), EntityUid::from_strs("User", "bob")),
+                    (SlotId::resource(), EntityUid::from_strs("Folder", "docs")),
+                ]
+                .into_iter()
+                .collect()
+            )
+        );
+        assert_eq!(
+            parsed
+                .policy(&PolicyId::from_str("policy0").unwrap())
+                .unwrap()
+                .template_links(),
+            None
+        );
+    }
+
+    #[test]
+    fn json_errors() {
+        let template = serde_json::json!({
+            "effect": "permit",
+            "principal": { "op": "==", "slot": "?principal" },
+            "action": { "op": "All" },
+            "resource": { "op": "All" },
+            "conditions": [],
+        });
+        assert_matches!(
+            PolicySet::from_json_value(serde_json::json!({ "policies": {} })),
+            Err(PolicySetFromJsonError::Serde(_))
+        );
+        assert_matches!(
+            PolicySet::from_json_value(serde_json::json!({
+                "staticPolicies": { "p": template },
+            })),
+            Err(PolicySetFromJsonError::Policy { id, error: est::FromJsonError::TemplateToPolicy(_) }) => {
+                assert_eq!(id.as_ref(), "p");
+            }
+        );
+        assert_matches!(
+            PolicySet::from_json_value(serde_json::json!({
+                "templates": { "t": template },
+                "templateLinks": [{
+                    "templateId": "t",
+                    "newId": "link",
+                    "values": { "?action": { "__entity": { "type": "Action", "id": "view" } } },
+                }],
+            })),
+            Err(PolicySetFromJsonError::UnknownSlot { slot, .. }) => {
+                assert_eq!(slot, "?action");
+            }
+        );
+        assert_matches!(
+            PolicySet::from_json_value(serde_json::json!({
+                "templates": { "t": template },
+                "templateLinks": [
+                    { "templateId": "t", "newId": "t", "values": {} },
+                ],
+            })),
+            Err(PolicySetFromJsonError::PolicySet(_))
+        );
+    }
 }
 
 #[cfg(test)]
@@ -3787,6 +6135,35 @@ mod schema_tests {
     use cool_asserts::assert_matches;
     use serde_json::json;
 
+    #[test]
+    fn validation_warnings() {
+        let schema = Schema::from_str_natural(
+            r#"
+            entity User;
+            action "view" appliesTo { principal: [User], resource: [User] };
+            "#,
+        )
+        .expect("schema should be valid");
+        let policies =
+            PolicySet::from_str(r#"permit(principal, action, resource) unless { true };"#)
+                .expect("policies should parse");
+
+        let validator = Validator::new(schema);
+        let result = validator.validate(&policies, ValidationMode::default());
+        assert!(result.validation_passed());
+        let warnings = result.validation_warnings().collect::<Vec<_>>();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].warning_kind().code(),
+            WarningCode::ImpossiblePolicy
+        );
+
+        let validator = validator.with_denied_warnings([WarningCode::ImpossiblePolicy]);
+        let result = validator.validate(&policies, ValidationMode::default());
+        assert!(!result.validation_passed());
+        assert_eq!(result.validation_warnings().count(), 0);
+    }
+
     /// A minimal test that a valid Schema parses
     #[test]
     fn valid_schema() {
@@ -3889,11 +6266,191 @@ mod schema_tests {
             Err(SchemaError::Serde(_))
         );
     }
+
+    /// Test that schema diffs classify changes as breaking or compatible
+    #[test]
+    fn diff_schemas() {
+        let old = Schema::from_str_natural(
+            r#"
+            entity User;
+            entity Vault = { owner: User, limit?: Long };
+            action "withdraw" appliesTo { principal: [User], resource: [Vault] };
+            "#,
+        )
+        .expect("schema should be valid");
+        let new = Schema::from_str_natural(
+            r#"
+            entity User;
+            entity Vault = { owner: User, limit?: Long, paused?: Bool };
+            action "withdraw" appliesTo { principal: [User], resource: [Vault] };
+            action "deposit" appliesTo { principal: [User], resource: [Vault] };
+            "#,
+        )
+        .expect("schema should be valid");
+        assert!(old.diff(&old).is_empty());
+        let changes = old.diff(&new);
+        assert_eq!(
+            changes.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                "added optional attribute `paused` to entity type `Vault`",
+                r#"added action `Action::"deposit"`"#,
+            ]
+        );
+        assert!(!changes.iter().any(SchemaChange::is_breaking));
+        let changes = new.diff(&old);
+        assert_eq!(
+            changes.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                "removed attribute `paused` from entity type `Vault`",
+                r#"removed action `Action::"deposit"`"#,
+            ]
+        );
+        assert!(changes.iter().all(SchemaChange::is_breaking));
+    }
+
+    /// Test that an action group applies to requests for its members, but
+    /// not to requests for itself
+    #[test]
+    fn action_groups() {
+        let schema = Schema::from_str_natural(
+            r#"
+            entity User;
+            entity Token = { paused: Bool };
+            action "erc20Write";
+            action "transfer", "approve" in ["erc20Write"]
+                appliesTo { principal: [User], resource: [Token] };
+            "#,
+        )
+        .expect("schema should be valid");
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action in Action::"erc20Write", resource)
+            when { !resource.paused };"#,
+        )
+        .expect("should be a valid policy");
+        let validator = Validator::new(schema.clone());
+        assert!(validator
+            .validate(&policies, ValidationMode::default())
+            .validation_passed());
+        let group_policy =
+            PolicySet::from_str(r#"permit(principal, action == Action::"erc20Write", resource);"#)
+                .expect("should be a valid policy");
+        assert!(!validator
+            .validate(&group_policy, ValidationMode::default())
+            .validation_passed());
+
+        let entities = Entities::from_json_value(
+            json!([{ "uid": { "type": "Token", "id": "t" }, "attrs": { "paused": false }, "parents": [] }]),
+            Some(&schema),
+        )
+        .expect("Should parse without error")
+        .add_entities(
+            Entities::actions_from_schema(&schema)
+                .expect("should construct action entities")
+                .iter()
+                .cloned(),
+        )
+        .expect("should add action entities");
+        let request = Request::new(
+            Some(EntityUid::from_strs("User", "alice")),
+            Some(EntityUid::from_strs("Action", "approve")),
+            Some(EntityUid::from_strs("Token", "t")),
+            Context::empty(),
+        );
+        let response = Authorizer::new().is_authorized(&request, &policies, &entities);
+        assert_eq!(response.decision(), Decision::Allow);
+    }
+
+    /// Test that schema elements which no policy uses are reported
+    #[test]
+    fn check_usage() {
+        let schema = Schema::from_str_natural(
+            r#"
+            entity User;
+            entity Pool;
+            entity Vault = { owner: User, limit?: Long };
+            action "withdraw" appliesTo { principal: [User], resource: [Vault] };
+            action "swap" appliesTo { principal: [User], resource: [Pool] };
+            "#,
+        )
+        .expect("schema should be valid");
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action == Action::"withdraw", resource)
+            when { resource.owner == principal };"#,
+        )
+        .expect("should be a valid policy");
+        assert_eq!(
+            schema
+                .check_usage(&policies, ValidationMode::default())
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "entity type `Pool` is never used",
+                "attribute `limit` of entity type `Vault` is never used",
+                r#"action `Action::"swap"` is never used"#,
+            ]
+        );
+    }
+
+    /// Test that merged schema fragments validate policies using types from
+    /// each
+    #[test]
+    fn merge_schema_fragments() {
+        let token = SchemaFragment::from_str_natural(
+            r#"
+            namespace Token {
+                entity Holder;
+                action "transfer" appliesTo { principal: [Holder], resource: [Holder] };
+            }
+            "#,
+        )
+        .expect("schema should be valid");
+        // refers to `Token::Holder` without declaring it
+        let vault = SchemaFragment::from_str_natural(
+            r#"
+            namespace Vault {
+                entity Vault = { owner: Token::Holder };
+                action "withdraw" appliesTo { principal: [Token::Holder], resource: [Vault] };
+            }
+            "#,
+        )
+        .expect("schema should be valid");
+        let schema: Schema = token
+            .merge(vault)
+            .expect("schemas should merge")
+            .try_into()
+            .expect("merged schema should be valid");
+        let policies = PolicySet::from_str(
+            r#"
+            permit(principal, action == Vault::Action::"withdraw", resource)
+            when { resource.owner == principal };
+            permit(principal, action == Token::Action::"transfer", resource);
+            "#,
+        )
+        .expect("policies should parse");
+        let validator = Validator::new(schema);
+        let result = validator.validate(&policies, ValidationMode::default());
+        assert!(result.validation_passed(), "{result:?}");
+
+        let fragment =
+            |src: &str| SchemaFragment::from_str_natural(src).expect("schema should be valid");
+        assert_matches!(
+            fragment("namespace Token { entity Holder; }")
+                .merge(fragment("namespace Token { entity Holder = { id: Long }; }")),
+            Err(SchemaError::ConflictingEntityType(name)) => assert_eq!(name, "Token::Holder")
+        );
+        assert_matches!(
+            fragment("namespace Token { entity Holder; entity Group; }")
+                .merge(fragment("namespace Token { entity Holder in [Group]; entity Group; }")),
+            Err(SchemaError::ConflictingEntityType(name)) => assert_eq!(name, "Token::Holder")
+        );
+    }
 }
 
 #[cfg(test)]
 mod ancestors_tests {
     use super::*;
+    use serde_json::json;
 
     #[test]
     fn test_ancestors() {
@@ -3911,11 +6468,79 @@ mod ancestors_tests {
             HashMap::new(),
             std::iter::once(b_euid.clone()).collect(),
         );
-        let es = Entities::from_entities([a, b, c]).unwrap();
-        let ans = es.ancestors(&c_euid).unwrap().collect::<HashSet<_>>();
-        assert_eq!(ans.len(), 2);
-        assert!(ans.contains(&b_euid));
-        assert!(ans.contains(&a_euid));
+        let es = Entities::from_entities([a, b, c]).unwrap();
+        let ans = es.ancestors(&c_euid).unwrap().collect::<HashSet<_>>();
+        assert_eq!(ans.len(), 2);
+        assert!(ans.contains(&b_euid));
+        assert!(ans.contains(&a_euid));
+    }
+
+    fn hierarchy() -> Entities {
+        Entities::from_json_str(
+            r#"[
+                { "uid": { "type": "Wallet", "id": "0xabc" }, "attrs": {}, "parents": [{ "type": "Group", "id": "signers" }] },
+                { "uid": { "type": "Group", "id": "signers" }, "attrs": {}, "parents": [{ "type": "Group", "id": "dao" }] },
+                { "uid": { "type": "Group", "id": "dao" }, "attrs": {}, "parents": [] },
+                { "uid": { "type": "Wallet", "id": "0xdef" }, "attrs": {}, "parents": [{ "type": "Role", "id": "admin" }] }
+            ]"#,
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn to_dot() {
+        let dot = hierarchy().to_dot(None);
+        let edges: Vec<&str> = dot.lines().filter(|line| line.contains("->")).collect();
+        // the edge from `0xabc` to `dao` is implied by the hierarchy
+        assert_eq!(
+            edges,
+            [
+                r#"  "Group::\"signers\"" -> "Group::\"dao\"";"#,
+                r#"  "Wallet::\"0xabc\"" -> "Group::\"signers\"";"#,
+                r#"  "Wallet::\"0xdef\"" -> "Role::\"admin\"";"#,
+            ]
+        );
+        // `Role::"admin"` is an ancestor, but not an entity
+        assert!(dot.contains(r#"  "Role::\"admin\"" [label="Role::\"admin\""];"#));
+        assert!(dot.starts_with("digraph entities {\n") && dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn to_json_graph() {
+        let policies: PolicySet = r#"
+            permit(principal in Group::"signers", action, resource);
+            permit(principal, action, resource) when { principal in Group::"dao" || principal == Wallet::"0xdef" };"#
+            .parse()
+            .unwrap();
+        let entities = hierarchy();
+        let graph = entities.to_json_graph(Some(&policies));
+        let nodes = graph["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), 5);
+        let node = |id: &str| {
+            nodes
+                .iter()
+                .find(|node| node["id"] == id)
+                .unwrap_or_else(|| panic!("no node {id}"))
+        };
+        assert_eq!(node(r#"Group::"signers""#)["policies"], json!(["policy0"]));
+        assert_eq!(node(r#"Group::"dao""#)["policies"], json!(["policy1"]));
+        assert_eq!(node(r#"Wallet::"0xabc""#)["policies"], json!([]));
+        assert_eq!(node(r#"Wallet::"0xdef""#)["type"], "Wallet");
+        assert_eq!(node(r#"Wallet::"0xdef""#)["entityId"], "0xdef");
+        assert_eq!(graph["edges"].as_array().unwrap().len(), 3);
+        assert!(graph["edges"]
+            .as_array()
+            .unwrap()
+            .contains(&json!({ "from": r#"Wallet::"0xabc""#, "to": r#"Group::"signers""# })));
+        // without policies, nodes are not annotated
+        let graph = entities.to_json_graph(None);
+        assert!(graph["nodes"][0].get("policies").is_none());
+        let dot = entities.to_dot(Some(&policies));
+        assert!(
+            dot.contains(r#"  "Group::\"dao\"" [label="Group::\"dao\"\npolicies: policy1"];"#),
+            "{dot}"
+        );
     }
 }
 
@@ -4862,4 +7487,557 @@ mod schema_based_parsing_tests {
             .unwrap()
         );
     }
+
+    #[test]
+    fn tags() {
+        let schema = Schema::from_json_value(json!(
+        {"": {
+            "entityTypes": {
+                "Address": {
+                    "tags": { "type": "Entity", "name": "Label" }
+                },
+                "Label": {}
+            },
+            "actions": {
+                "transfer": { }
+            }
+        }}
+        ))
+        .expect("should be a valid schema");
+
+        let entitiesjson = json!(
+            [
+                {
+                    "uid": { "type": "Address", "id": "0xabc" },
+                    "attrs": {},
+                    "parents": [],
+                    "tags": { "category": { "type": "Label", "id": "exchange" } }
+                }
+            ]
+        );
+        // without schema-based parsing, the tag is a Record
+        let parsed = Entities::from_json_value(entitiesjson.clone(), None)
+            .expect("Should parse without error");
+        let parsed = parsed
+            .get(&EntityUid::from_strs("Address", "0xabc"))
+            .expect("that should be the address");
+        assert!(matches!(
+            parsed.tag("category"),
+            Some(Ok(EvalResult::Record(_)))
+        ));
+        // but with schema-based parsing, it is an entity reference
+        let parsed = Entities::from_json_value(entitiesjson, Some(&schema))
+            .expect("Should parse without error");
+        let parsed = parsed
+            .get(&EntityUid::from_strs("Address", "0xabc"))
+            .expect("that should be the address");
+        assert_eq!(
+            parsed.tag("category"),
+            Some(Ok(EvalResult::EntityUid(EntityUid::from_strs(
+                "Label", "exchange"
+            ))))
+        );
+        assert_eq!(parsed.attr("category"), None);
+
+        // tags on an entity type which doesn't declare them are an error
+        let entitiesjson = json!(
+            [
+                {
+                    "uid": { "type": "Label", "id": "exchange" },
+                    "attrs": {},
+                    "parents": [],
+                    "tags": { "category": "cex" }
+                }
+            ]
+        );
+        let err = Entities::from_json_value(entitiesjson, Some(&schema))
+            .expect_err("should fail due to unexpected tag");
+        assert!(
+            err.to_string().contains(
+                r#"tag `category` on `Label::"exchange"` should not exist according to the schema"#
+            ),
+            "actual error message was {err}"
+        );
+
+        // tag values must have the declared type
+        let entitiesjson = json!(
+            [
+                {
+                    "uid": { "type": "Address", "id": "0xabc" },
+                    "attrs": {},
+                    "parents": [],
+                    "tags": { "category": "cex" }
+                }
+            ]
+        );
+        Entities::from_json_value(entitiesjson, Some(&schema))
+            .expect_err("should fail due to tag type mismatch");
+    }
+
+    #[test]
+    #[cfg(feature = "bytes")]
+    fn bytes() {
+        let schema = Schema::from_json_value(json!(
+        {"": {
+            "entityTypes": {
+                "Transaction": {
+                    "shape": {
+                        "type": "Record",
+                        "attributes": {
+                            "calldata": { "type": "Extension", "name": "bytes" },
+                            "hash": { "type": "Extension", "name": "bytes" },
+                            "signature": { "type": "Extension", "name": "bytes" }
+                        }
+                    }
+                }
+            },
+            "actions": {
+                "submit": { }
+            }
+        }}
+        ))
+        .expect("should be a valid schema");
+
+        let entitiesjson = json!(
+            [
+                {
+                    "uid": { "type": "Transaction", "id": "tx1" },
+                    "attrs": {
+                        "calldata": "0xA9059CBB",
+                        "hash": { "__extn": { "fn": "bytes", "arg": "0x00ff" } },
+                        "signature": "base64:qQWcuw=="
+                    },
+                    "parents": []
+                }
+            ]
+        );
+        // without schema-based parsing, the shorthand forms are strings
+        let parsed = Entities::from_json_value(entitiesjson.clone(), None)
+            .expect("Should parse without error");
+        let parsed = parsed
+            .get(&EntityUid::from_strs("Transaction", "tx1"))
+            .expect("that should be the transaction");
+        assert_eq!(
+            parsed.attr("calldata"),
+            Some(Ok(EvalResult::String("0xA9059CBB".into())))
+        );
+        assert_eq!(
+            parsed.attr("hash"),
+            Some(Ok(EvalResult::ExtensionValue("0x00ff".into())))
+        );
+        // but with schema-based parsing, they are all bytes values
+        let parsed = Entities::from_json_value(entitiesjson, Some(&schema))
+            .expect("Should parse without error");
+        let parsed = parsed
+            .get(&EntityUid::from_strs("Transaction", "tx1"))
+            .expect("that should be the transaction");
+        assert_eq!(
+            parsed.attr("calldata"),
+            Some(Ok(EvalResult::ExtensionValue("0xa9059cbb".into())))
+        );
+        assert_eq!(
+            parsed.attr("hash"),
+            Some(Ok(EvalResult::ExtensionValue("0x00ff".into())))
+        );
+        assert_eq!(
+            parsed.attr("signature"),
+            Some(Ok(EvalResult::ExtensionValue("0xa9059cbb".into())))
+        );
+
+        // strings which aren't valid bytes are an error
+        let entitiesjson = json!(
+            [
+                {
+                    "uid": { "type": "Transaction", "id": "tx1" },
+                    "attrs": {
+                        "calldata": "a9059cbb",
+                        "hash": "0x00ff",
+                        "signature": "0x"
+                    },
+                    "parents": []
+                }
+            ]
+        );
+        let parsed = Entities::from_json_value(entitiesjson, Some(&schema))
+            .expect("errors in extension values are only reported on evaluation");
+        let parsed = parsed
+            .get(&EntityUid::from_strs("Transaction", "tx1"))
+            .expect("that should be the transaction");
+        assert_matches!(parsed.attr("calldata"), Some(Err(_)));
+    }
+
+    #[test]
+    #[cfg(all(feature = "u256", feature = "address"))]
+    fn ethers_types() {
+        let schema = Schema::from_json_value(json!(
+        {"": {
+            "entityTypes": {
+                "User": {},
+                "Vault": {
+                    "shape": {
+                        "type": "Record",
+                        "attributes": {
+                            "balance": { "type": "Extension", "name": "u256" },
+                            "owner": { "type": "Extension", "name": "address" }
+                        }
+                    }
+                }
+            },
+            "actions": {
+                "withdraw": {
+                    "appliesTo": {
+                        "principalTypes": ["User"],
+                        "resourceTypes": ["Vault"],
+                        "context": {
+                            "type": "Record",
+                            "attributes": {
+                                "amount": { "type": "Extension", "name": "u256" },
+                                "sender": { "type": "Extension", "name": "address" }
+                            }
+                        }
+                    }
+                }
+            }
+        }}
+        ))
+        .expect("should be a valid schema");
+
+        let entitiesjson = json!(
+            [
+                {
+                    "uid": { "type": "Vault", "id": "v1" },
+                    "attrs": {
+                        "balance": "0x1bc16d674ec80000",
+                        "owner": "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"
+                    },
+                    "parents": []
+                }
+            ]
+        );
+        let entities = Entities::from_json_value(entitiesjson, Some(&schema))
+            .expect("Should parse without error");
+        let vault = entities
+            .get(&EntityUid::from_strs("Vault", "v1"))
+            .expect("that should be the vault");
+        assert_eq!(
+            vault.attr("balance"),
+            Some(Ok(EvalResult::ExtensionValue("2000000000000000000".into())))
+        );
+        assert_eq!(
+            vault.attr("owner"),
+            Some(Ok(EvalResult::ExtensionValue(
+                "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed".into()
+            )))
+        );
+
+        let action = EntityUid::from_strs("Action", "withdraw");
+        let context = Context::from_json_value(
+            json!({
+                "amount": "1000000000000000000",
+                "sender": "0x5AAEB6053F3E94C9B9A09F33669435E7EF1BEAED"
+            }),
+            Some((&schema, &action)),
+        )
+        .expect("Should parse without error");
+
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action == Action::"withdraw", resource)
+            when {
+                context.sender == resource.owner &&
+                context.amount.u256LessThanOrEqual(resource.balance)
+            };"#,
+        )
+        .expect("should be a valid policy");
+        let validator = Validator::new(schema);
+        assert!(validator
+            .validate(&policies, ValidationMode::default())
+            .validation_passed());
+
+        let request = Request::new(
+            Some(EntityUid::from_strs("User", "alice")),
+            Some(action),
+            Some(EntityUid::from_strs("Vault", "v1")),
+            context,
+        );
+        let response = Authorizer::new().is_authorized(&request, &policies, &entities);
+        assert_eq!(response.decision(), Decision::Allow);
+    }
+
+    /// Attributes with a default value are given their default when missing
+    /// from entity or context data parsed with the schema, but still need a
+    /// `has` check, since entities and contexts built without it lack them
+    #[test]
+    fn attribute_defaults() {
+        let schema = Schema::from_json_value(json!(
+        {"": {
+            "entityTypes": {
+                "User": {},
+                "Vault": {
+                    "shape": {
+                        "type": "Record",
+                        "attributes": {
+                            "paused": { "type": "Boolean", "required": false, "default": false }
+                        }
+                    }
+                }
+            },
+            "actions": {
+                "withdraw": {
+                    "appliesTo": {
+                        "principalTypes": ["User"],
+                        "resourceTypes": ["Vault"],
+                        "context": {
+                            "type": "Record",
+                            "attributes": {
+                                "memo": { "type": "String", "required": false, "default": "" }
+                            }
+                        }
+                    }
+                }
+            }
+        }}
+        ))
+        .expect("should be a valid schema");
+
+        let entities = Entities::from_json_value(
+            json!([{ "uid": { "type": "Vault", "id": "v1" }, "attrs": {}, "parents": [] }]),
+            Some(&schema),
+        )
+        .expect("Should parse without error");
+        assert_eq!(
+            entities
+                .get(&EntityUid::from_strs("Vault", "v1"))
+                .expect("that should be the vault")
+                .attr("paused"),
+            Some(Ok(EvalResult::Bool(false)))
+        );
+
+        let action = EntityUid::from_strs("Action", "withdraw");
+        let context = Context::from_json_value(json!({}), Some((&schema, &action)))
+            .expect("Should parse without error");
+
+        let unguarded = PolicySet::from_str(
+            r#"permit(principal, action == Action::"withdraw", resource)
+            when { !resource.paused && context.memo == "" };"#,
+        )
+        .expect("should be a valid policy");
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action == Action::"withdraw", resource)
+            when {
+                resource has paused && !resource.paused &&
+                context has memo && context.memo == ""
+            };"#,
+        )
+        .expect("should be a valid policy");
+        let validator = Validator::new(schema);
+        assert!(!validator
+            .validate(&unguarded, ValidationMode::default())
+            .validation_passed());
+        assert!(validator
+            .validate(&policies, ValidationMode::default())
+            .validation_passed());
+
+        let request = Request::new(
+            Some(EntityUid::from_strs("User", "alice")),
+            Some(action),
+            Some(EntityUid::from_strs("Vault", "v1")),
+            context,
+        );
+        let response = Authorizer::new().is_authorized(&request, &policies, &entities);
+        assert_eq!(response.decision(), Decision::Allow);
+
+        // an entity built without the schema has no default, and the guarded
+        // policy does not allow the request rather than erroring
+        let entities = Entities::from_entities([Entity::new(
+            EntityUid::from_strs("Vault", "v1"),
+            HashMap::new(),
+            HashSet::new(),
+        )])
+        .expect("Should build without error");
+        let response = Authorizer::new().is_authorized(&request, &policies, &entities);
+        assert_eq!(response.decision(), Decision::Deny);
+        assert_eq!(response.diagnostics().errors().count(), 0);
+    }
+
+    /// Contexts assembled from several sources are checked against the schema
+    /// once merged
+    #[test]
+    fn merged_contexts() {
+        let schema = Schema::from_json_value(json!(
+        {"": {
+            "entityTypes": {},
+            "actions": {
+                "transfer": {
+                    "appliesTo": {
+                        "principalTypes": [],
+                        "resourceTypes": [],
+                        "context": {
+                            "type": "Record",
+                            "attributes": {
+                                "value": { "type": "Long" },
+                                "risk": { "type": "Long" },
+                                "memo": { "type": "String", "required": false, "default": "" }
+                            }
+                        }
+                    }
+                }
+            }
+        }}
+        ))
+        .expect("should be a valid schema");
+        let action = EntityUid::from_strs("Action", "transfer");
+        let tx = Context::from_json_value(json!({ "value": 5 }), None).expect("valid context");
+        let risk = Context::from_json_value(json!({ "risk": 2 }), None).expect("valid context");
+
+        let merged = Context::merge(&tx, &risk, ContextConflict::Error, Some((&schema, &action)))
+            .expect("should conform to the schema");
+        assert_eq!(
+            merged.0.to_string(),
+            r#"{"value": 5, "risk": 2, "memo": ""}"#
+        );
+
+        assert_matches!(
+            Context::merge(&tx, &tx, ContextConflict::Error, None),
+            Err(ContextMergeError::Conflict(ContextConflictError { attr })) if attr == "value"
+        );
+        assert_matches!(
+            Context::merge(&tx, &tx, ContextConflict::Overlay, Some((&schema, &action))),
+            Err(ContextMergeError::Schema(ContextJsonError::JsonDeserialization(
+                JsonDeserializationError::MissingRequiredRecordAttr { record_attr, .. }
+            ))) if record_attr == "risk"
+        );
+        let wrong =
+            Context::from_json_value(json!({ "risk": "high" }), None).expect("valid context");
+        assert_matches!(
+            Context::merge(
+                &tx,
+                &wrong,
+                ContextConflict::Error,
+                Some((&schema, &action))
+            ),
+            Err(ContextMergeError::Schema(
+                ContextJsonError::JsonDeserialization(
+                    JsonDeserializationError::TypeMismatch { .. }
+                )
+            ))
+        );
+        let extra = Context::from_json_value(json!({ "risk": 2, "gas": 21000 }), None)
+            .expect("valid context");
+        assert_matches!(
+            Context::merge(&tx, &extra, ContextConflict::Error, Some((&schema, &action))),
+            Err(ContextMergeError::Schema(ContextJsonError::JsonDeserialization(
+                JsonDeserializationError::UnexpectedRecordAttr { record_attr, .. }
+            ))) if record_attr == "gas"
+        );
+    }
+}
+
+#[cfg(test)]
+mod record_builder_tests {
+    use super::*;
+
+    #[test]
+    fn builds_contexts_and_attributes() {
+        let treasury = EntityUid::from_strs("Wallet", "treasury");
+        let attrs = RecordBuilder::new()
+            .attr("owner", EntityUid::from_strs("Signer", "alice"))
+            .attr("paused", false)
+            .attr("paused", true)
+            .build_attrs();
+        assert_eq!(attrs.len(), 2);
+        let entities =
+            Entities::from_entities([Entity::new(treasury.clone(), attrs, HashSet::new())])
+                .expect("valid entities");
+        let context = RecordBuilder::new()
+            .attr("gas", 21000)
+            .attr("sim", RecordBuilder::new().attr("reverted", false))
+            .build_context();
+
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action, resource)
+            when { resource.paused && context.gas < 30000 && !context.sim.reverted };"#,
+        )
+        .expect("should be a valid policy");
+        let request = Request::new(
+            Some(EntityUid::from_strs("Signer", "alice")),
+            Some(EntityUid::from_strs("Action", "transfer")),
+            Some(treasury),
+            context,
+        );
+        let response = Authorizer::new().is_authorized(&request, &policies, &entities);
+        assert_eq!(response.decision(), Decision::Allow);
+    }
+
+    #[test]
+    #[cfg(all(feature = "u256", feature = "address", feature = "bytes"))]
+    fn builds_extension_values() {
+        use extensions::address::Address;
+        use extensions::u256::U256;
+
+        let to =
+            Address::from_str("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").expect("valid address");
+        let context = RecordBuilder::new()
+            .attr("to", to)
+            .attr("value", U256::from(2_500_000_000_000_000_000u64))
+            .attr(
+                "data",
+                RestrictedExpression::new_bytes([0xa9, 0x05, 0x9c, 0xbb]),
+            )
+            .build_context();
+
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action, resource)
+            when {
+                context.to == address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed")
+                && context.value.u256LessThan(u256("3000000000000000000"))
+                && context.data.bytesStartsWith(bytes("0xa9059cbb"))
+            };"#,
+        )
+        .expect("should be a valid policy");
+        let request = Request::new(
+            Some(EntityUid::from_strs("Signer", "alice")),
+            Some(EntityUid::from_strs("Action", "transfer")),
+            Some(EntityUid::from_strs("Wallet", "treasury")),
+            context,
+        );
+        let response = Authorizer::new().is_authorized(&request, &policies, &Entities::empty());
+        assert_eq!(response.decision(), Decision::Allow);
+    }
+
+    #[test]
+    #[cfg(all(feature = "u256", feature = "address", feature = "bytes"))]
+    fn converts_extension_values_back() {
+        use extensions::address::Address;
+        use extensions::bytes::{EthBytes, H256};
+        use extensions::u256::U256;
+
+        let owner =
+            Address::from_str("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").expect("valid address");
+        let balance = U256::from(2_500_000_000_000_000_000u64);
+        let code_hash = H256::repeat_byte(0xab);
+        let attrs = RecordBuilder::new()
+            .attr("owner", owner)
+            .attr("balance", balance)
+            .attr("code_hash", code_hash)
+            .attr("code", EthBytes::from(vec![0x60, 0x80]))
+            .build_attrs();
+        let uid = EntityUid::from_strs("Wallet", "treasury");
+        let entity = Entity::new(uid, attrs, HashSet::new());
+
+        let attr = |name| {
+            entity
+                .attr(name)
+                .expect("attribute exists")
+                .expect("evaluates")
+        };
+        assert_eq!(Address::try_from(attr("owner")), Ok(owner));
+        assert_eq!(U256::try_from(attr("balance")), Ok(balance));
+        assert_eq!(H256::try_from(attr("code_hash")), Ok(code_hash));
+        assert_eq!(
+            EthBytes::try_from(attr("code")),
+            Ok(EthBytes::from(vec![0x60, 0x80]))
+        );
+        assert!(H256::try_from(attr("code")).is_err());
+        assert!(U256::try_from(EvalResult::Long(1)).is_err());
+    }
 }
@@ -0,0 +1,677 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Decisions which must be approved by a threshold of approvers, like the
+//! owners of a multisig, before they take effect, such as large transfers out
+//! of a treasury.
+//!
+//! A `permit` policy requires approval when it has an `@approvers` annotation:
+//! a comma-separated list of the UIDs of the approvers, each the canonical UID
+//! of an account (see [`EntityUid::account`]) or an entity whose id is an
+//! address. An optional `@threshold` annotation gives how many of them must
+//! approve; by default, all of them must.
+//!
+//! [`ApprovalAuthorizer::is_authorized`] returns
+//! [`ApprovalOutcome::PendingApproval`] instead of allowing a request when
+//! every policy which allowed it requires approval. Approvers sign, with
+//! EIP-712 in the [`SigningDomain`] of the authorizer, the struct
+//! ```text
+//! Approval(bytes32 request,bytes32 nonce)
+//! ```
+//! of the Keccak-256 hash of the canonical JSON of the request (see
+//! [`crate::canonical::request_json`]) and a random nonce of the pending
+//! approval, so that an approval is not a signature of the request itself and
+//! only counts for that one pending approval. Their signatures are submitted
+//! with [`PendingApproval::submit`], and [`PendingApproval::finalize`] allows
+//! the request once the approvals meet the threshold of any of those
+//! policies. Approvers are counted by address, so that two UIDs of the same
+//! address, such as accounts on two chains, count once.
+//!
+//! ```
+//! # use cedar_policy::{approvals::*, Context, Decision, Entities, EntityUid, PolicySet, Request};
+//! # use ethers::signers::{LocalWallet, Signer};
+//! let alice: LocalWallet = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+//!     .parse()
+//!     .unwrap();
+//! let bob: LocalWallet = "0000000000000000000000000000000000000000000000000000000000000001"
+//!     .parse()
+//!     .unwrap();
+//! let policies: PolicySet = r#"
+//!     @approvers("Account::\"eip155:1:0x2c7536E3605D9C16a7a3D7b1898e529396a65c23\",
+//!                 Account::\"eip155:1:0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf\"")
+//!     @threshold("2")
+//!     permit(principal, action == Action::"withdraw", resource);"#
+//!     .parse()
+//!     .unwrap();
+//! let authorizer = ApprovalAuthorizer::new(policies, Entities::empty()).unwrap();
+//! let request = Request::new(None, Some(r#"Action::"withdraw""#.parse().unwrap()), None, Context::empty());
+//! let ApprovalOutcome::PendingApproval(mut pending) = authorizer.is_authorized(&request).unwrap() else {
+//!     panic!("withdrawals require approval");
+//! };
+//! for wallet in [alice, bob] {
+//!     let signature = pending.sign(&wallet).unwrap();
+//!     pending.submit(&EntityUid::account(1, wallet.address()), &signature).unwrap();
+//! }
+//! assert_eq!(pending.finalize().unwrap(), Decision::Allow);
+//! ```
+
+use std::collections::{BTreeSet, HashMap};
+use std::str::FromStr;
+
+use ethers::abi::{self, Token};
+use ethers::core::rand;
+use ethers::signers::LocalWallet;
+use ethers::types::{Address, Signature, H256};
+use ethers::utils::keccak256;
+use thiserror::Error;
+
+use crate::canonical::{self, CanonicalJsonError};
+use crate::signed_request::{entity_address, typed_data_hash, SigningDomain};
+use crate::{Authorizer, Decision, Entities, EntityUid, Policy, PolicyId, PolicySet};
+use crate::{Request, Response};
+
+/// The annotation listing the approvers of a policy
+pub const APPROVERS_ANNOTATION: &str = "approvers";
+
+/// The annotation giving how many approvers of a policy must approve
+pub const THRESHOLD_ANNOTATION: &str = "threshold";
+
+/// The EIP-712 type of the signed struct
+pub const APPROVAL_TYPE: &str = "Approval(bytes32 request,bytes32 nonce)";
+
+/// Errors of the approval workflow
+#[derive(Debug, Error)]
+pub enum ApprovalError {
+    /// The approval annotations of a policy are malformed
+    #[error("invalid approval annotations on policy `{policy}`: {message}")]
+    Annotation {
+        /// The policy with the annotations
+        policy: PolicyId,
+        /// What is wrong with them
+        message: String,
+    },
+    /// The request could not be converted to canonical JSON to be hashed
+    #[error(transparent)]
+    Canonical(#[from] CanonicalJsonError),
+    /// The signature is malformed, or no signer can be recovered from it
+    #[error("invalid signature: {0}")]
+    Signature(String),
+    /// The entity is not an approver of any policy the approval is pending on
+    #[error("`{0}` is not an approver of this request")]
+    NotAnApprover(EntityUid),
+    /// The approval was signed by another address than the approver
+    #[error("the approval of `{approver}` was not signed by its address")]
+    SignerMismatch {
+        /// The approver the approval was submitted for
+        approver: EntityUid,
+    },
+    /// The approvals do not meet the threshold of any policy yet
+    #[error("the request has {approvals} approvals, but requires {threshold}")]
+    NotApproved {
+        /// The number of approvals, for the policy closest to its threshold
+        approvals: usize,
+        /// The threshold of that policy
+        threshold: usize,
+    },
+}
+
+/// The approvers of a policy, and how many of them must approve
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApprovalRequirement {
+    approvers: Vec<EntityUid>,
+    threshold: usize,
+}
+
+impl ApprovalRequirement {
+    /// The requirement given by the annotations of `policy`, if it has an
+    /// `@approvers` annotation
+    ///
+    /// # Errors
+    ///
+    /// If an approver is not a valid UID or has no address, or the threshold
+    /// is not between one and the number of approvers.
+    pub fn of(policy: &Policy) -> Result<Option<Self>, ApprovalError> {
+        let invalid = |message: String| ApprovalError::Annotation {
+            policy: policy.id().clone(),
+            message,
+        };
+        let Some(approvers) = policy.annotation(APPROVERS_ANNOTATION) else {
+            return match policy.annotation(THRESHOLD_ANNOTATION) {
+                Some(_) => Err(invalid(format!(
+                    "`@{THRESHOLD_ANNOTATION}` without `@{APPROVERS_ANNOTATION}`"
+                ))),
+                None => Ok(None),
+            };
+        };
+        let mut addresses = BTreeSet::new();
+        let mut uids = Vec::new();
+        for approver in approvers.split(',').map(str::trim) {
+            let uid = EntityUid::from_str(approver)
+                .map_err(|e| invalid(format!("`{approver}` is not an entity UID: {e}")))?;
+            let address =
+                entity_address(&uid).ok_or_else(|| invalid(format!("`{uid}` has no address")))?;
+            // UIDs of the same address are the same approver
+            if addresses.insert(address) {
+                uids.push(uid);
+            }
+        }
+        let threshold = match policy.annotation(THRESHOLD_ANNOTATION) {
+            Some(threshold) => threshold
+                .trim()
+                .parse()
+                .map_err(|_| invalid(format!("`{threshold}` is not a threshold")))?,
+            None => uids.len(),
+        };
+        if threshold == 0 || threshold > uids.len() {
+            return Err(invalid(format!(
+                "the threshold must be between 1 and {}, the number of approvers",
+                uids.len()
+            )));
+        }
+        Ok(Some(Self {
+            approvers: uids,
+            threshold,
+        }))
+    }
+
+    /// The approvers, in the order they are listed in, without the later
+    /// UIDs of the address of an earlier one
+    pub fn approvers(&self) -> &[EntityUid] {
+        &self.approvers
+    }
+
+    /// How many approvers must approve
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+}
+
+/// The outcome of authorizing a request with an [`ApprovalAuthorizer`]
+#[derive(Debug)]
+pub enum ApprovalOutcome {
+    /// The request was allowed by a policy which does not require approval,
+    /// or denied
+    Decided(Response),
+    /// The request was only allowed by policies which require approval
+    PendingApproval(PendingApproval),
+}
+
+/// A request which will be allowed once enough of its approvers approve it
+#[derive(Debug)]
+pub struct PendingApproval {
+    request: Request,
+    nonce: H256,
+    hash: H256,
+    requirements: Vec<(PolicyId, ApprovalRequirement)>,
+    approved_by: Vec<EntityUid>,
+    approved: BTreeSet<Address>,
+}
+
+impl PendingApproval {
+    /// The request awaiting approval
+    pub fn request(&self) -> &Request {
+        &self.request
+    }
+
+    /// The random nonce of this pending approval
+    pub fn nonce(&self) -> H256 {
+        self.nonce
+    }
+
+    /// The hash approvers sign: the EIP-712 hash of the [`APPROVAL_TYPE`]
+    /// struct of the request and the nonce
+    pub fn hash(&self) -> H256 {
+        self.hash
+    }
+
+    /// The policies which allowed the request, each with its requirement.
+    /// Meeting any one of them is enough.
+    pub fn requirements(&self) -> impl Iterator<Item = (&PolicyId, &ApprovalRequirement)> {
+        self.requirements.iter().map(|(id, req)| (id, req))
+    }
+
+    /// The approvers which have approved the request so far, in the order they
+    /// did
+    pub fn approved_by(&self) -> &[EntityUid] {
+        &self.approved_by
+    }
+
+    /// Sign an approval of the request with `wallet`, giving the signature to
+    /// submit for the approver with its address
+    ///
+    /// # Errors
+    ///
+    /// If signing fails.
+    pub fn sign(&self, wallet: &LocalWallet) -> Result<String, ApprovalError> {
+        let signature = wallet
+            .sign_hash(self.hash)
+            .map_err(|e| ApprovalError::Signature(e.to_string()))?;
+        Ok(format!("0x{signature}"))
+    }
+
+    /// Submit the approval of `approver`: an EIP-712 signature of
+    /// [`Self::hash`] by its address. Submitting it again, or for another
+    /// UID of the same address, has no effect.
+    ///
+    /// # Errors
+    ///
+    /// If the address of `approver` is not that of an approver of any of the
+    /// policies, the signature is invalid, or it was not signed by the
+    /// approver.
+    pub fn submit(&mut self, approver: &EntityUid, signature: &str) -> Result<(), ApprovalError> {
+        let address = entity_address(approver);
+        if address.is_none()
+            || !self.requirements.iter().any(|(_, req)| {
+                req.approvers
+                    .iter()
+                    .any(|uid| entity_address(uid) == address)
+            })
+        {
+            return Err(ApprovalError::NotAnApprover(approver.clone()));
+        }
+        let signer = Signature::from_str(signature)
+            .map_err(|e| ApprovalError::Signature(e.to_string()))?
+            .recover(self.hash)
+            .map_err(|e| ApprovalError::Signature(e.to_string()))?;
+        if address != Some(signer) {
+            return Err(ApprovalError::SignerMismatch {
+                approver: approver.clone(),
+            });
+        }
+        if self.approved.insert(signer) {
+            self.approved_by.push(approver.clone());
+        }
+        Ok(())
+    }
+
+    /// Whether the approvals meet the threshold of any of the policies
+    pub fn is_approved(&self) -> bool {
+        self.finalize().is_ok()
+    }
+
+    /// The decision on the request, [`Decision::Allow`], once the approvals
+    /// meet the threshold of any of the policies
+    ///
+    /// # Errors
+    ///
+    /// If they do not yet.
+    pub fn finalize(&self) -> Result<Decision, ApprovalError> {
+        match self.closest() {
+            Some((approvals, threshold)) if approvals < threshold => {
+                Err(ApprovalError::NotApproved {
+                    approvals,
+                    threshold,
+                })
+            }
+            _ => Ok(Decision::Allow),
+        }
+    }
+
+    /// The number of approvals and the threshold of the policy with the
+    /// fewest approvals left to meet its threshold
+    fn closest(&self) -> Option<(usize, usize)> {
+        self.requirements
+            .iter()
+            .map(|(_, req)| {
+                let approvals = req
+                    .approvers
+                    .iter()
+                    .filter_map(entity_address)
+                    .filter(|address| self.approved.contains(address))
+                    .count();
+                (approvals, req.threshold)
+            })
+            .min_by_key(|(approvals, threshold)| threshold.saturating_sub(*approvals))
+    }
+}
+
+/// Authorizes requests against a policy set and entities, holding back the
+/// requests allowed only by policies which require approval
+#[derive(Debug)]
+pub struct ApprovalAuthorizer {
+    authorizer: Authorizer,
+    domain: SigningDomain,
+    policies: PolicySet,
+    entities: Entities,
+    requirements: HashMap<PolicyId, ApprovalRequirement>,
+}
+
+impl ApprovalAuthorizer {
+    /// Authorize requests against `policies` and `entities`, with approvals
+    /// signed in the domain `cedar-approvals`, version `1`, on chain `1`
+    /// until [`Self::with_domain`] gives another
+    ///
+    /// # Errors
+    ///
+    /// If the approval annotations of a policy are malformed.
+    pub fn new(policies: PolicySet, entities: Entities) -> Result<Self, ApprovalError> {
+        let mut requirements = HashMap::new();
+        for policy in policies.policies() {
+            if let Some(requirement) = ApprovalRequirement::of(policy)? {
+                requirements.insert(policy.id().clone(), requirement);
+            }
+        }
+        Ok(Self {
+            authorizer: Authorizer::new(),
+            domain: SigningDomain::new("cedar-approvals", "1", 1),
+            policies,
+            entities,
+            requirements,
+        })
+    }
+
+    /// The same authorizer, with approvals signed in `domain`
+    #[must_use]
+    pub fn with_domain(mut self, domain: SigningDomain) -> Self {
+        self.domain = domain;
+        self
+    }
+
+    /// The domain approvals are signed in
+    pub fn domain(&self) -> &SigningDomain {
+        &self.domain
+    }
+
+    /// The policies, including those whose approvers must approve the
+    /// requests they allow
+    pub fn policies(&self) -> &PolicySet {
+        &self.policies
+    }
+
+    /// The entities requests are authorized with
+    pub fn entities(&self) -> &Entities {
+        &self.entities
+    }
+
+    /// Authorize `request`. If it is allowed, but only by policies which
+    /// require approval, it is pending their approval.
+    ///
+    /// # Errors
+    ///
+    /// If the request cannot be hashed for approvers to sign.
+    pub fn is_authorized(&self, request: &Request) -> Result<ApprovalOutcome, ApprovalError> {
+        let response = self
+            .authorizer
+            .is_authorized(request, &self.policies, &self.entities);
+        if response.decision() == Decision::Deny {
+            return Ok(ApprovalOutcome::Decided(response));
+        }
+        let requirements = response
+            .diagnostics()
+            .reason()
+            .map(|id| Some((id.clone(), self.requirements.get(id)?.clone())))
+            .collect::<Option<Vec<_>>>();
+        let Some(mut requirements) = requirements else {
+            return Ok(ApprovalOutcome::Decided(response));
+        };
+        requirements.sort_by(|(a, _), (b, _)| a.as_ref().cmp(b.as_ref()));
+        let nonce = H256(rand::random());
+        Ok(ApprovalOutcome::PendingApproval(PendingApproval {
+            request: Request(request.0.clone()),
+            nonce,
+            hash: approval_hash(request, nonce, &self.domain)?,
+            requirements,
+            approved_by: Vec::new(),
+            approved: BTreeSet::new(),
+        }))
+    }
+}
+
+/// The hash approvers of `request` sign, with `nonce`, in `domain`: the
+/// EIP-712 hash of the [`APPROVAL_TYPE`] struct
+///
+/// # Errors
+///
+/// If a value of the context cannot be converted to JSON.
+pub fn approval_hash(
+    request: &Request,
+    nonce: H256,
+    domain: &SigningDomain,
+) -> Result<H256, CanonicalJsonError> {
+    let struct_hash = keccak256(abi::encode(&[
+        Token::FixedBytes(keccak256(APPROVAL_TYPE).to_vec()),
+        Token::FixedBytes(keccak256(canonical::request_json(request)?).to_vec()),
+        Token::FixedBytes(nonce.as_bytes().to_vec()),
+    ]));
+    Ok(typed_data_hash(domain, struct_hash))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::signed_request::{signing_hash, RequestClaims};
+    use crate::{Context, RestrictedExpression};
+    use ethers::signers::Signer;
+
+    const KEYS: [&str; 3] = [
+        "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318",
+        "0000000000000000000000000000000000000000000000000000000000000001",
+        "0000000000000000000000000000000000000000000000000000000000000002",
+    ];
+
+    fn wallets() -> Vec<(EntityUid, LocalWallet)> {
+        KEYS.iter()
+            .map(|key| {
+                let wallet: LocalWallet = key.parse().expect("valid key");
+                (EntityUid::account(1, wallet.address()), wallet)
+            })
+            .collect()
+    }
+
+    fn authorizer(owners: &[(EntityUid, LocalWallet)]) -> ApprovalAuthorizer {
+        let approvers = owners
+            .iter()
+            .map(|(uid, _)| uid.to_string().replace('"', "\\\""))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let policies = format!(
+            r#"@id("small")
+            permit(principal, action == Action::"transfer", resource)
+            when {{ context.amount <= 1000 }};
+            @id("large")
+            @approvers("{approvers}")
+            @threshold("2")
+            permit(principal, action == Action::"transfer", resource)
+            when {{ context.amount > 1000 }};"#
+        );
+        ApprovalAuthorizer::new(policies.parse().expect("valid policies"), Entities::empty())
+            .expect("valid annotations")
+    }
+
+    fn transfer(amount: i64) -> Request {
+        Request::new(
+            Some(r#"User::"treasurer""#.parse().expect("valid uid")),
+            Some(r#"Action::"transfer""#.parse().expect("valid uid")),
+            Some(r#"Vault::"treasury""#.parse().expect("valid uid")),
+            Context::from_pairs([("amount".to_string(), RestrictedExpression::new_long(amount))]),
+        )
+    }
+
+    #[test]
+    fn large_transfers_require_approval() {
+        let owners = wallets();
+        let authorizer = authorizer(&owners);
+        assert!(matches!(
+            authorizer.is_authorized(&transfer(10)).expect("authorizes"),
+            ApprovalOutcome::Decided(response) if response.decision() == Decision::Allow
+        ));
+
+        let ApprovalOutcome::PendingApproval(mut pending) = authorizer
+            .is_authorized(&transfer(1_000_000))
+            .expect("authorizes")
+        else {
+            panic!("a large transfer should be pending approval");
+        };
+        let (_, requirement) = pending.requirements().next().expect("one requirement");
+        assert_eq!(requirement.threshold(), 2);
+        assert_eq!(requirement.approvers().len(), 3);
+
+        let (first, first_wallet) = &owners[0];
+        let signature = pending.sign(first_wallet).expect("signs");
+        pending.submit(first, &signature).expect("valid approval");
+        // approving twice counts once
+        pending.submit(first, &signature).expect("valid approval");
+        assert!(!pending.is_approved());
+        assert!(matches!(
+            pending.finalize(),
+            Err(ApprovalError::NotApproved {
+                approvals: 1,
+                threshold: 2
+            })
+        ));
+
+        let (second, second_wallet) = &owners[2];
+        let signature = pending.sign(second_wallet).expect("signs");
+        pending.submit(second, &signature).expect("valid approval");
+        assert_eq!(pending.approved_by(), [first.clone(), second.clone()]);
+        assert_eq!(pending.finalize().expect("approved"), Decision::Allow);
+    }
+
+    #[test]
+    fn rejects_invalid_approvals() {
+        let owners = wallets();
+        let authorizer = authorizer(&owners[..2]);
+        let ApprovalOutcome::PendingApproval(mut pending) = authorizer
+            .is_authorized(&transfer(5000))
+            .expect("authorizes")
+        else {
+            panic!("a large transfer should be pending approval");
+        };
+        let (outsider, outsider_wallet) = &owners[2];
+        let signature = pending.sign(outsider_wallet).expect("signs");
+        assert!(matches!(
+            pending.submit(outsider, &signature),
+            Err(ApprovalError::NotAnApprover(_))
+        ));
+        // an approver cannot submit the signature of another address
+        assert!(matches!(
+            pending.submit(&owners[0].0, &signature),
+            Err(ApprovalError::SignerMismatch { .. })
+        ));
+        assert!(matches!(
+            pending.submit(&owners[0].0, "0x1234"),
+            Err(ApprovalError::Signature(_))
+        ));
+        assert!(pending.approved_by().is_empty());
+    }
+
+    #[test]
+    fn approvals_are_not_request_signatures_and_do_not_replay() {
+        let owners = wallets();
+        let authorizer = authorizer(&owners[..2]);
+        let pending = |authorizer: &ApprovalAuthorizer| {
+            let ApprovalOutcome::PendingApproval(pending) = authorizer
+                .is_authorized(&transfer(5000))
+                .expect("authorizes")
+            else {
+                panic!("a large transfer should be pending approval");
+            };
+            pending
+        };
+        let (approver, wallet) = &owners[0];
+
+        // a signature of the request itself is not an approval
+        let domain = SigningDomain::new("cedar-approvals", "1", 1);
+        let claims = RequestClaims::new("approvals", std::time::Duration::from_secs(60));
+        let request_hash = signing_hash(&transfer(5000), &claims, &domain).expect("hashes");
+        let request_signature = format!("0x{}", wallet.sign_hash(request_hash).expect("signs"));
+        let mut first = pending(&authorizer);
+        assert!(matches!(
+            first.submit(approver, &request_signature),
+            Err(ApprovalError::SignerMismatch { .. })
+        ));
+
+        // an approval of one pending approval does not count for a later
+        // identical request
+        let signature = first.sign(wallet).expect("signs");
+        first.submit(approver, &signature).expect("valid approval");
+        let mut second = pending(&authorizer);
+        assert_ne!(first.hash(), second.hash());
+        assert!(matches!(
+            second.submit(approver, &signature),
+            Err(ApprovalError::SignerMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn approvers_count_once_per_address() {
+        let owners = wallets();
+        let (_, wallet) = &owners[0];
+        let address = wallet.address();
+        let other = EntityUid::account(10, address);
+        let approvers = [
+            EntityUid::account(1, address),
+            other.clone(),
+            owners[1].0.clone(),
+        ]
+        .iter()
+        .map(|uid| uid.to_string().replace('"', "\\\""))
+        .collect::<Vec<_>>()
+        .join(", ");
+        // the same address on two chains is one approver, so two of them
+        // cannot meet a threshold of two
+        let policies: PolicySet = format!(
+            r#"@approvers("{approvers}") @threshold("2")
+            permit(principal, action == Action::"transfer", resource);"#
+        )
+        .parse()
+        .expect("valid policies");
+        let authorizer =
+            ApprovalAuthorizer::new(policies, Entities::empty()).expect("valid annotations");
+        let ApprovalOutcome::PendingApproval(mut pending) = authorizer
+            .is_authorized(&transfer(5000))
+            .expect("authorizes")
+        else {
+            panic!("a transfer should be pending approval");
+        };
+        let (_, requirement) = pending.requirements().next().expect("one requirement");
+        assert_eq!(requirement.approvers().len(), 2);
+        let signature = pending.sign(wallet).expect("signs");
+        pending
+            .submit(&EntityUid::account(1, address), &signature)
+            .expect("valid approval");
+        pending.submit(&other, &signature).expect("valid approval");
+        assert!(matches!(
+            pending.finalize(),
+            Err(ApprovalError::NotApproved {
+                approvals: 1,
+                threshold: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn malformed_annotations() {
+        for annotations in [
+            r#"@approvers("User::\"alice\"")"#,
+            r#"@approvers("not a uid")"#,
+            r#"@threshold("1")"#,
+            r#"@approvers("Account::\"eip155:1:0x0000000000000000000000000000000000000001\"") @threshold("2")"#,
+            r#"@approvers("Account::\"eip155:1:0x0000000000000000000000000000000000000001\"") @threshold("0")"#,
+        ] {
+            let policies: PolicySet = format!("{annotations} permit(principal, action, resource);")
+                .parse()
+                .expect("valid policies");
+            assert!(
+                matches!(
+                    ApprovalAuthorizer::new(policies, Entities::empty()),
+                    Err(ApprovalError::Annotation { .. })
+                ),
+                "{annotations}"
+            );
+        }
+    }
+}
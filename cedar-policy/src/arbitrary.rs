@@ -0,0 +1,769 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Generators of random schemas, and of entities, policies and requests which
+//! are consistent with them, for property testing integrations with Cedar and
+//! fuzzing the evaluator. This module is only available with the `arbitrary`
+//! feature.
+//!
+//! The generators implement [`arbitrary::Arbitrary`], so they are driven by
+//! the bytes of an [`Unstructured`], as given by a fuzzer or a property
+//! testing library. For a schema generated by [`ArbitrarySchema`],
+//! * the entities of [`ArbitrarySchema::arbitrary_entities`] conform to it,
+//! * the policies of [`ArbitrarySchema::arbitrary_policy_set`] validate
+//!   against it, and
+//! * the requests of [`ArbitrarySchema::arbitrary_request`] conform to it
+//!   and are mostly about the generated entities.
+//!
+//! Attributes and context fields may be `u256`s and `address`es as well as
+//! booleans, integers, strings and entities, so the policies use the
+//! Ethereum extension functions. As for transactions, actions are function
+//! selectors like `Action::"0x095ea7b3"`, entity ids are addresses and the
+//! context holds the arguments of the call in `context.args`.
+//!
+//! ```
+//! use arbitrary::Unstructured;
+//! use cedar_policy::{arbitrary::Scenario, Authorizer};
+//!
+//! let bytes = [7; 1024];
+//! let scenario: Scenario = Unstructured::new(&bytes).arbitrary().unwrap();
+//! for request in &scenario.requests {
+//!     Authorizer::new().is_authorized(
+//!         request,
+//!         scenario.policies.policies(),
+//!         scenario.entities.entities(),
+//!     );
+//! }
+//! ```
+
+use std::fmt::Write;
+
+use arbitrary::{Arbitrary, Error, Result, Unstructured};
+use serde_json::{json, Map, Value};
+
+use crate::{Context, Entities, EntityUid, PolicySet, Request, Schema};
+
+/// The names generated entity types are given, in order
+const ENTITY_TYPE_NAMES: [&str; 5] = ["Address", "Contract", "Group", "Token", "Network"];
+
+/// The selectors generated actions are given, in order: those of ERC20
+/// `transfer`, `approve` and `transferFrom`, and of `setApprovalForAll`
+const ACTION_NAMES: [&str; 4] = ["0xa9059cbb", "0x095ea7b3", "0x23b872dd", "0xa22cb465"];
+
+/// How many entity ids each entity type has, which entities and requests
+/// draw from so that requests are about the generated entities
+const IDS_PER_TYPE: usize = 3;
+
+/// The largest number of attributes of an entity type, and of arguments in
+/// the context of an action
+const MAX_ATTRS: usize = 4;
+
+/// The largest number of policies in a policy set
+const MAX_POLICIES: usize = 6;
+
+/// How deeply the conditions of policies nest
+const MAX_DEPTH: usize = 3;
+
+/// The strings string attributes are drawn from
+const STRINGS: [&str; 4] = ["", "a", "alice", "0x01"];
+
+/// The type of an attribute or context field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AttrType {
+    Bool,
+    Long,
+    String,
+    U256,
+    Address,
+    /// An entity of the entity type with this index
+    Entity(usize),
+}
+
+impl AttrType {
+    fn arbitrary(u: &mut Unstructured<'_>, entity_types: usize) -> Result<Self> {
+        Ok(match u.int_in_range(0..=5)? {
+            0 => Self::Bool,
+            1 => Self::Long,
+            2 => Self::String,
+            3 => Self::U256,
+            4 => Self::Address,
+            _ => Self::Entity(u.choose_index(entity_types)?),
+        })
+    }
+
+    fn schema_json(self) -> Value {
+        match self {
+            Self::Bool => json!({ "type": "Boolean" }),
+            Self::Long => json!({ "type": "Long" }),
+            Self::String => json!({ "type": "String" }),
+            Self::U256 => json!({ "type": "Extension", "name": "u256" }),
+            Self::Address => json!({ "type": "Extension", "name": "address" }),
+            Self::Entity(ty) => json!({ "type": "Entity", "name": entity_type_name(ty) }),
+        }
+    }
+}
+
+/// An entity type of a generated schema
+#[derive(Debug, Clone)]
+struct EntityType {
+    attrs: Vec<AttrType>,
+    /// The indices of the entity types which entities of this type may be
+    /// members of, which are all greater than its own, so that the hierarchy
+    /// has no cycles
+    parents: Vec<usize>,
+}
+
+/// An action of a generated schema
+#[derive(Debug, Clone)]
+struct Action {
+    principal: usize,
+    resource: usize,
+    args: Vec<AttrType>,
+}
+
+fn entity_type_name(ty: usize) -> &'static str {
+    ENTITY_TYPE_NAMES.get(ty).copied().unwrap_or("Address")
+}
+
+fn attr_name(index: usize) -> String {
+    format!("attr_{index}")
+}
+
+fn arg_name(index: usize) -> String {
+    format!("arg_{index}")
+}
+
+/// The id of the entity of type `ty` with index `index`, which is an Ethereum
+/// address
+fn entity_id(ty: usize, index: usize) -> String {
+    format!("0x{:040x}", (ty + 1) * 0x100 + index)
+}
+
+/// A random value of a generated schema, in the JSON format of entity and
+/// context files
+fn value_json(u: &mut Unstructured<'_>, ty: AttrType) -> Result<Value> {
+    Ok(match ty {
+        AttrType::Bool => Value::Bool(u.arbitrary()?),
+        AttrType::Long => json!(arbitrary_long(u)?),
+        AttrType::String => json!(u.choose(&STRINGS)?),
+        AttrType::U256 => json!({ "__extn": { "fn": "u256", "arg": arbitrary_u256(u)? } }),
+        AttrType::Address => json!({ "__extn": { "fn": "address", "arg": arbitrary_address(u)? } }),
+        AttrType::Entity(ty) => json!({
+            "__entity": {
+                "type": entity_type_name(ty),
+                "id": entity_id(ty, u.choose_index(IDS_PER_TYPE)?),
+            }
+        }),
+    })
+}
+
+/// A random integer, which is small more often than not, so that integers
+/// are often equal
+fn arbitrary_long(u: &mut Unstructured<'_>) -> Result<i64> {
+    if u.ratio(3, 4)? {
+        u.int_in_range(-3..=3)
+    } else {
+        u.arbitrary()
+    }
+}
+
+/// The text of a random `u256`, which is small, one ether in wei, or at least
+/// 2^255 as for unlimited ERC20 approvals more often than it is anything else
+fn arbitrary_u256(u: &mut Unstructured<'_>) -> Result<String> {
+    Ok(match u.int_in_range(0..=4)? {
+        0 => u.int_in_range(0_u8..=3)?.to_string(),
+        1 => "1000000000000000000".to_string(),
+        2 => format!("0x8{}", "0".repeat(63)),
+        3 => format!("0x{}", "f".repeat(64)),
+        _ => {
+            let bytes: [u8; 32] = u.arbitrary()?;
+            bytes
+                .iter()
+                .fold("0x".to_string(), |hex, byte| hex + &format!("{byte:02x}"))
+        }
+    })
+}
+
+/// A random address, which is the id of a generated entity
+fn arbitrary_address(u: &mut Unstructured<'_>) -> Result<String> {
+    Ok(entity_id(
+        u.choose_index(ENTITY_TYPE_NAMES.len())?,
+        u.choose_index(IDS_PER_TYPE)?,
+    ))
+}
+
+/// A schema with randomly chosen entity types and actions. Each action
+/// applies to one principal type and one resource type, and its context has
+/// the arguments of the call in the `args` record.
+#[derive(Debug, Clone)]
+pub struct ArbitrarySchema {
+    entity_types: Vec<EntityType>,
+    actions: Vec<Action>,
+    json: Value,
+    schema: Schema,
+}
+
+impl<'a> Arbitrary<'a> for ArbitrarySchema {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let type_count = u.int_in_range(1..=ENTITY_TYPE_NAMES.len())?;
+        let mut entity_types = Vec::new();
+        for ty in 0..type_count {
+            let attrs = (0..u.int_in_range(0..=MAX_ATTRS)?)
+                .map(|_| AttrType::arbitrary(u, type_count))
+                .collect::<Result<Vec<_>>>()?;
+            let mut parents = Vec::new();
+            for parent in ty + 1..type_count {
+                if u.ratio(1, 3)? {
+                    parents.push(parent);
+                }
+            }
+            entity_types.push(EntityType { attrs, parents });
+        }
+        let mut actions = Vec::new();
+        for _ in 0..u.int_in_range(1..=ACTION_NAMES.len())? {
+            actions.push(Action {
+                principal: u.choose_index(type_count)?,
+                resource: u.choose_index(type_count)?,
+                args: (0..u.int_in_range(0..=MAX_ATTRS)?)
+                    .map(|_| AttrType::arbitrary(u, type_count))
+                    .collect::<Result<Vec<_>>>()?,
+            });
+        }
+        Self::new(entity_types, actions)
+    }
+}
+
+/// The JSON schema of the record type with `attrs`, named by `name`
+fn record_json(attrs: &[AttrType], name: fn(usize) -> String) -> Value {
+    let attributes = attrs
+        .iter()
+        .enumerate()
+        .map(|(index, ty)| (name(index), ty.schema_json()))
+        .collect::<Map<_, _>>();
+    json!({ "type": "Record", "attributes": attributes })
+}
+
+impl ArbitrarySchema {
+    fn new(entity_types: Vec<EntityType>, actions: Vec<Action>) -> Result<Self> {
+        let entity_types_json = entity_types
+            .iter()
+            .enumerate()
+            .map(|(ty, entity_type)| {
+                let member_of = entity_type
+                    .parents
+                    .iter()
+                    .map(|parent| entity_type_name(*parent))
+                    .collect::<Vec<_>>();
+                (
+                    entity_type_name(ty).to_string(),
+                    json!({
+                        "memberOfTypes": member_of,
+                        "shape": record_json(&entity_type.attrs, attr_name),
+                    }),
+                )
+            })
+            .collect::<Map<_, _>>();
+        let actions_json = actions
+            .iter()
+            .zip(ACTION_NAMES)
+            .map(|(action, name)| {
+                (
+                    name.to_string(),
+                    json!({
+                        "appliesTo": {
+                            "principalTypes": [entity_type_name(action.principal)],
+                            "resourceTypes": [entity_type_name(action.resource)],
+                            "context": {
+                                "type": "Record",
+                                "attributes": { "args": record_json(&action.args, arg_name) },
+                            },
+                        }
+                    }),
+                )
+            })
+            .collect::<Map<_, _>>();
+        let json = json!({ "": { "entityTypes": entity_types_json, "actions": actions_json } });
+        // the schema is well-formed by construction, so this only fails if
+        // this module has a bug
+        let schema = Schema::from_json_value(json.clone()).map_err(|_| Error::IncorrectFormat)?;
+        Ok(Self {
+            entity_types,
+            actions,
+            json,
+            schema,
+        })
+    }
+
+    /// The schema
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// The schema, in the JSON schema format
+    pub fn json(&self) -> &Value {
+        &self.json
+    }
+
+    /// Random entities of the entity types of this schema. There is at most
+    /// one entity for each entity id which requests and policies use.
+    ///
+    /// # Errors
+    ///
+    /// As for [`Arbitrary::arbitrary`], if no value can be made of the data
+    /// in `u`.
+    pub fn arbitrary_entities(&self, u: &mut Unstructured<'_>) -> Result<ArbitraryEntities> {
+        let mut json = Vec::new();
+        for (ty, entity_type) in self.entity_types.iter().enumerate() {
+            for index in 0..IDS_PER_TYPE {
+                if !u.ratio(2, 3)? {
+                    continue;
+                }
+                let attrs = entity_type
+                    .attrs
+                    .iter()
+                    .enumerate()
+                    .map(|(attr, attr_type)| Ok((attr_name(attr), value_json(u, *attr_type)?)))
+                    .collect::<Result<Map<_, _>>>()?;
+                let mut parents = Vec::new();
+                for parent in &entity_type.parents {
+                    for parent_index in 0..IDS_PER_TYPE {
+                        if u.ratio(1, 3)? {
+                            parents.push(json!({
+                                "type": entity_type_name(*parent),
+                                "id": entity_id(*parent, parent_index),
+                            }));
+                        }
+                    }
+                }
+                json.push(json!({
+                    "uid": { "type": entity_type_name(ty), "id": entity_id(ty, index) },
+                    "attrs": attrs,
+                    "parents": parents,
+                }));
+            }
+        }
+        let json = Value::Array(json);
+        // as for the schema, the entities conform to it by construction
+        let entities = Entities::from_json_value(json.clone(), Some(&self.schema))
+            .map_err(|_| Error::IncorrectFormat)?;
+        Ok(ArbitraryEntities { entities, json })
+    }
+
+    /// A random request for one of the actions of this schema, whose
+    /// principal and resource are of the types the action applies to
+    ///
+    /// # Errors
+    ///
+    /// As for [`Arbitrary::arbitrary`], if no value can be made of the data
+    /// in `u`.
+    pub fn arbitrary_request(&self, u: &mut Unstructured<'_>) -> Result<Request> {
+        let index = u.choose_index(self.actions.len())?;
+        let (action, name) = self
+            .actions
+            .get(index)
+            .zip(ACTION_NAMES.get(index))
+            .ok_or(Error::IncorrectFormat)?;
+        let uid = |ty: &str, id: &str| -> Result<EntityUid> {
+            format!("{ty}::\"{id}\"")
+                .parse()
+                .map_err(|_| Error::IncorrectFormat)
+        };
+        let principal = uid(
+            entity_type_name(action.principal),
+            &entity_id(action.principal, u.choose_index(IDS_PER_TYPE)?),
+        )?;
+        let resource = uid(
+            entity_type_name(action.resource),
+            &entity_id(action.resource, u.choose_index(IDS_PER_TYPE)?),
+        )?;
+        let action_uid = uid("Action", name)?;
+        let args = action
+            .args
+            .iter()
+            .enumerate()
+            .map(|(arg, ty)| Ok((arg_name(arg), value_json(u, *ty)?)))
+            .collect::<Result<Map<_, _>>>()?;
+        let context =
+            Context::from_json_value(json!({ "args": args }), Some((&self.schema, &action_uid)))
+                .map_err(|_| Error::IncorrectFormat)?;
+        Ok(Request::new(
+            Some(principal),
+            Some(action_uid),
+            Some(resource),
+            context,
+        ))
+    }
+
+    /// A random policy set, whose policies validate against this schema
+    ///
+    /// # Errors
+    ///
+    /// As for [`Arbitrary::arbitrary`], if no value can be made of the data
+    /// in `u`.
+    pub fn arbitrary_policy_set(&self, u: &mut Unstructured<'_>) -> Result<ArbitraryPolicySet> {
+        let mut text = String::new();
+        for _ in 0..u.int_in_range(1..=MAX_POLICIES)? {
+            self.write_policy(u, &mut text)?;
+        }
+        // as for the schema, the policies parse by construction
+        let policies = text.parse().map_err(|_| Error::IncorrectFormat)?;
+        Ok(ArbitraryPolicySet { policies, text })
+    }
+
+    fn write_policy(&self, u: &mut Unstructured<'_>, text: &mut String) -> Result<()> {
+        let index = u.choose_index(self.actions.len())?;
+        let (action, name) = self
+            .actions
+            .get(index)
+            .zip(ACTION_NAMES.get(index))
+            .ok_or(Error::IncorrectFormat)?;
+        let effect = if u.ratio(2, 3)? { "permit" } else { "forbid" };
+        let scope = |u: &mut Unstructured<'_>, var: &str, ty: usize| -> Result<String> {
+            Ok(if u.arbitrary()? {
+                var.to_string()
+            } else {
+                format!(
+                    "{var} == {}::\"{}\"",
+                    entity_type_name(ty),
+                    entity_id(ty, u.choose_index(IDS_PER_TYPE)?)
+                )
+            })
+        };
+        let principal = scope(u, "principal", action.principal)?;
+        let resource = scope(u, "resource", action.resource)?;
+        let _ = write!(
+            text,
+            "{effect} (\n  {principal},\n  action == Action::\"{name}\",\n  {resource}\n)"
+        );
+        let env = Env {
+            schema: self,
+            action,
+        };
+        for clause in ["when", "unless"] {
+            if u.arbitrary()? {
+                let condition = env.bool_expr(u, MAX_DEPTH)?;
+                let _ = write!(text, "\n{clause} {{ {condition} }}");
+            }
+        }
+        text.push_str(";\n");
+        Ok(())
+    }
+}
+
+/// The variables a condition of a policy for `action` may use
+struct Env<'a> {
+    schema: &'a ArbitrarySchema,
+    action: &'a Action,
+}
+
+impl Env<'_> {
+    /// The expressions of type `ty` made of the variables and their
+    /// attributes, including the attributes of entity attributes
+    fn paths(&self, ty: AttrType) -> Vec<String> {
+        let mut paths = Vec::new();
+        let mut add = |path: String, path_ty: AttrType, depth: usize| {
+            let mut stack = vec![(path, path_ty, depth)];
+            while let Some((path, path_ty, depth)) = stack.pop() {
+                if path_ty == ty {
+                    paths.push(path.clone());
+                }
+                if let (AttrType::Entity(entity_ty), true) = (path_ty, depth > 0) {
+                    if let Some(entity_type) = self.schema.entity_types.get(entity_ty) {
+                        for (attr, attr_ty) in entity_type.attrs.iter().enumerate() {
+                            stack.push((
+                                format!("{path}.{}", attr_name(attr)),
+                                *attr_ty,
+                                depth - 1,
+                            ));
+                        }
+                    }
+                }
+            }
+        };
+        add(
+            "principal".to_string(),
+            AttrType::Entity(self.action.principal),
+            2,
+        );
+        add(
+            "resource".to_string(),
+            AttrType::Entity(self.action.resource),
+            2,
+        );
+        for (arg, arg_ty) in self.action.args.iter().enumerate() {
+            add(format!("context.args.{}", arg_name(arg)), *arg_ty, 1);
+        }
+        paths
+    }
+
+    /// A random expression of type `ty`, as the text of a policy
+    fn expr(&self, u: &mut Unstructured<'_>, ty: AttrType, depth: usize) -> Result<String> {
+        if ty == AttrType::Bool {
+            return self.bool_expr(u, depth);
+        }
+        let paths = self.paths(ty);
+        if !paths.is_empty() && u.ratio(2, 3)? {
+            return Ok(u.choose(&paths)?.clone());
+        }
+        Ok(match ty {
+            AttrType::Long if depth > 0 && u.ratio(1, 4)? => {
+                let left = self.expr(u, ty, depth - 1)?;
+                // integers can only be multiplied by literals
+                match *u.choose(&["+", "-", "*"])? {
+                    "*" => format!("({left} * {})", arbitrary_long(u)?),
+                    op => format!("({left} {op} {})", self.expr(u, ty, depth - 1)?),
+                }
+            }
+            AttrType::Long => arbitrary_long(u)?.to_string(),
+            AttrType::String => format!("{:?}", u.choose(&STRINGS)?),
+            AttrType::U256 if depth > 0 && u.ratio(1, 4)? => {
+                let function = u.choose(&["u256Add", "u256Sub", "u256Mul"])?;
+                format!(
+                    "{}.{function}({})",
+                    self.expr(u, ty, depth - 1)?,
+                    self.expr(u, ty, depth - 1)?
+                )
+            }
+            AttrType::U256 => format!("u256(\"{}\")", arbitrary_u256(u)?),
+            AttrType::Address => format!("address(\"{}\")", arbitrary_address(u)?),
+            AttrType::Entity(ty) => format!(
+                "{}::\"{}\"",
+                entity_type_name(ty),
+                entity_id(ty, u.choose_index(IDS_PER_TYPE)?)
+            ),
+            AttrType::Bool => self.bool_expr(u, depth)?,
+        })
+    }
+
+    /// A random boolean expression, as the text of a policy
+    fn bool_expr(&self, u: &mut Unstructured<'_>, depth: usize) -> Result<String> {
+        if depth == 0 {
+            let paths = self.paths(AttrType::Bool);
+            return Ok(if !paths.is_empty() && u.arbitrary()? {
+                u.choose(&paths)?.clone()
+            } else {
+                u.choose(&["true", "false"])?.to_string()
+            });
+        }
+        let next = depth - 1;
+        Ok(match u.int_in_range(0..=7)? {
+            0 => format!("!({})", self.bool_expr(u, next)?),
+            1 => format!(
+                "({} && {})",
+                self.bool_expr(u, next)?,
+                self.bool_expr(u, next)?
+            ),
+            2 => format!(
+                "({} || {})",
+                self.bool_expr(u, next)?,
+                self.bool_expr(u, next)?
+            ),
+            3 => format!(
+                "(if {} then {} else {})",
+                self.bool_expr(u, next)?,
+                self.bool_expr(u, next)?,
+                self.bool_expr(u, next)?
+            ),
+            4 => {
+                let op = u.choose(&["<", "<=", ">", ">=", "==", "!="])?;
+                format!(
+                    "({} {op} {})",
+                    self.expr(u, AttrType::Long, next)?,
+                    self.expr(u, AttrType::Long, next)?
+                )
+            }
+            5 => {
+                let function = u.choose(&[
+                    "u256LessThan",
+                    "u256LessThanOrEqual",
+                    "u256GreaterThan",
+                    "u256GreaterThanOrEqual",
+                ])?;
+                format!(
+                    "{}.{function}({})",
+                    self.expr(u, AttrType::U256, next)?,
+                    self.expr(u, AttrType::U256, next)?
+                )
+            }
+            6 => {
+                let ty = u.choose(&[AttrType::String, AttrType::Address])?;
+                format!(
+                    "({} == {})",
+                    self.expr(u, *ty, next)?,
+                    self.expr(u, *ty, next)?
+                )
+            }
+            _ => {
+                let ty = u.choose_index(self.schema.entity_types.len())?;
+                let var = if self.action.principal == ty {
+                    "principal"
+                } else if self.action.resource == ty {
+                    "resource"
+                } else {
+                    return self.bool_expr(u, next);
+                };
+                let ancestors = self
+                    .schema
+                    .entity_types
+                    .get(ty)
+                    .map(|entity_type| entity_type.parents.as_slice())
+                    .unwrap_or_default();
+                let ancestor = if ancestors.is_empty() {
+                    ty
+                } else {
+                    *u.choose(ancestors)?
+                };
+                format!(
+                    "({var} in {}::\"{}\")",
+                    entity_type_name(ancestor),
+                    entity_id(ancestor, u.choose_index(IDS_PER_TYPE)?)
+                )
+            }
+        })
+    }
+}
+
+/// Entities generated by [`ArbitrarySchema::arbitrary_entities`]
+#[derive(Debug, Clone)]
+pub struct ArbitraryEntities {
+    entities: Entities,
+    json: Value,
+}
+
+impl ArbitraryEntities {
+    /// The entities
+    pub fn entities(&self) -> &Entities {
+        &self.entities
+    }
+
+    /// The entities, in the JSON format of entity files
+    pub fn json(&self) -> &Value {
+        &self.json
+    }
+}
+
+/// A policy set generated by [`ArbitrarySchema::arbitrary_policy_set`]
+#[derive(Debug, Clone)]
+pub struct ArbitraryPolicySet {
+    policies: PolicySet,
+    text: String,
+}
+
+impl ArbitraryPolicySet {
+    /// The policy set
+    pub fn policies(&self) -> &PolicySet {
+        &self.policies
+    }
+
+    /// The policy set, as Cedar text
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// A random schema, with entities, a policy set and requests for it
+#[derive(Debug)]
+pub struct Scenario {
+    /// The schema
+    pub schema: ArbitrarySchema,
+    /// Entities conforming to the schema
+    pub entities: ArbitraryEntities,
+    /// Policies which validate against the schema
+    pub policies: ArbitraryPolicySet,
+    /// Requests conforming to the schema
+    pub requests: Vec<Request>,
+}
+
+impl<'a> Arbitrary<'a> for Scenario {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let schema = ArbitrarySchema::arbitrary(u)?;
+        let entities = schema.arbitrary_entities(u)?;
+        let policies = schema.arbitrary_policy_set(u)?;
+        let requests = (0..u.int_in_range(1..=4)?)
+            .map(|_| schema.arbitrary_request(u))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            schema,
+            entities,
+            policies,
+            requests,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, ValidationMode, Validator};
+
+    /// Scenarios generated from a fixed sequence of pseudo-random bytes
+    fn scenarios() -> impl Iterator<Item = Scenario> {
+        (0_u64..200).map(|seed| {
+            // xorshift, so the test needs no random number generator
+            let mut state = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+            let bytes = (0..4096)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state.to_le_bytes()[0]
+                })
+                .collect::<Vec<_>>();
+            Unstructured::new(&bytes)
+                .arbitrary()
+                .unwrap_or_else(|e| panic!("failed to generate scenario {seed}: {e}"))
+        })
+    }
+
+    #[test]
+    fn policies_validate() {
+        for scenario in scenarios() {
+            let validator = Validator::new(scenario.schema.schema().clone());
+            let result =
+                validator.validate(scenario.policies.policies(), ValidationMode::default());
+            assert!(
+                result.validation_passed(),
+                "{}\n{}\n{:?}",
+                scenario.schema.json(),
+                scenario.policies.text(),
+                result.validation_errors().collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn requests_are_authorized() {
+        let (mut allowed, mut denied) = (0, 0);
+        for scenario in scenarios() {
+            for request in &scenario.requests {
+                let response = Authorizer::new().is_authorized(
+                    request,
+                    scenario.policies.policies(),
+                    scenario.entities.entities(),
+                );
+                match response.decision() {
+                    crate::Decision::Allow => allowed += 1,
+                    crate::Decision::Deny => denied += 1,
+                }
+            }
+        }
+        // the generators make both decisions, rather than always the same one
+        assert!(
+            allowed > 0 && denied > 0,
+            "{allowed} allowed, {denied} denied"
+        );
+    }
+}
@@ -0,0 +1,1345 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! An audit log of authorization decisions, for custody operations which must
+//! keep a record of why each transaction was allowed or denied.
+//!
+//! [`AuditedAuthorizer`] authorizes requests against a policy set and
+//! entities, and gives an [`AuditRecord`] of every decision to an
+//! [`AuditSink`] before returning it. If the sink fails, so does the
+//! authorization, so that no decision goes unrecorded. A record holds:
+//! * the SHA-256 hash of the request, along with its principal, action and
+//!   resource
+//! * the decision, the policies which determined it, and any errors
+//! * the SHA-256 hash of the policy set
+//! * a snapshot of the entities: their SHA-256 hash and the block they were
+//!   read at, if they came from a chain
+//!
+//! Records serialize to a canonical JSON form, whose [`AuditRecord::digest`]
+//! can be signed. The hashes do not depend on the order in which the
+//! policies, entities or parents of entities were given: entities and
+//! requests are hashed in the canonical JSON of [`crate::canonical`].
+//!
+//! [`FileSink`] appends records to a file, one JSON object per line, and
+//! `WebhookSink`, behind the `audit-webhook` feature, posts them to a URL.
+//! For higher volumes of decisions, `KafkaSink`, behind the `audit-kafka`
+//! feature, sends them to a Kafka topic, and `NatsSink`, behind the
+//! `audit-nats` feature, publishes them to a NATS subject.
+//! `DecisionNotifier`, behind the same feature, posts them, or only denies,
+//! to webhooks in the background, in signed batches, for alerting rather than
+//! record keeping.
+//!
+//! ```
+//! # use cedar_policy::{audit::*, Context, Entities, PolicySet, Request};
+//! # let dir = tempfile::tempdir().unwrap();
+//! let policies: PolicySet = r#"permit(principal, action == Action::"approve", resource);"#
+//!     .parse()
+//!     .unwrap();
+//! let sink = FileSink::open(dir.path().join("audit.jsonl")).unwrap();
+//! let authorizer = AuditedAuthorizer::new(policies, Entities::empty(), Some(19_000_000), sink).unwrap();
+//! let request = Request::new(None, Some(r#"Action::"approve""#.parse().unwrap()), None, Context::empty());
+//! let response = authorizer.is_authorized(&request).unwrap();
+//! # assert_eq!(response.decision(), cedar_policy::Decision::Allow);
+//! ```
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::{Mutex, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::canonical::{self, canonical_json, CanonicalJsonError};
+use crate::{Authorizer, Decision, Entities, PolicySet, Request, Response};
+
+/// Errors of the audit log
+#[derive(Debug, Error)]
+pub enum AuditError {
+    /// The entities could not be serialized to take a snapshot of them
+    #[error("failed to serialize the entities for the audit log: {0}")]
+    Entities(#[from] CanonicalJsonError),
+    /// A record could not be serialized
+    #[error("failed to serialize the audit record: {0}")]
+    Serialize(#[from] serde_json::Error),
+    /// A record could not be written to the file of a [`FileSink`]
+    #[error("failed to write the audit log: {0}")]
+    Io(#[from] std::io::Error),
+    /// A record could not be posted to the URL of a `WebhookSink`
+    #[error("failed to post the audit record to `{url}`: {message}")]
+    Webhook {
+        /// The URL of the webhook
+        url: String,
+        /// What went wrong
+        message: String,
+    },
+    /// A record could not be sent to the topic of a `KafkaSink`
+    #[error("failed to send the audit record to the Kafka topic `{topic}`: {message}")]
+    Kafka {
+        /// The topic
+        topic: String,
+        /// What went wrong
+        message: String,
+    },
+    /// A record could not be published to the subject of a `NatsSink`
+    #[error("failed to publish the audit record to the NATS subject `{subject}`: {message}")]
+    Nats {
+        /// The subject
+        subject: String,
+        /// What went wrong
+        message: String,
+    },
+}
+
+/// Receives the record of every decision of an [`AuditedAuthorizer`]
+pub trait AuditSink: Send + Sync {
+    /// Record `record`. If this fails, the authorization fails with the error.
+    ///
+    /// # Errors
+    ///
+    /// If the record could not be stored.
+    fn record(&self, record: &AuditRecord) -> Result<(), AuditError>;
+}
+
+/// The entities a decision was made with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntitiesSnapshot {
+    /// The block the entities were read at, if they came from a chain
+    pub block: Option<u64>,
+    /// The SHA-256 hash of the entities, in hex
+    pub hash: String,
+}
+
+impl EntitiesSnapshot {
+    /// Take a snapshot of `entities`, read at `block`
+    ///
+    /// # Errors
+    ///
+    /// If the entities cannot be serialized to JSON.
+    pub fn new(entities: &Entities, block: Option<u64>) -> Result<Self, AuditError> {
+        Ok(Self {
+            block,
+            hash: sha256_hex(canonical::entities_json(entities)?.as_bytes()),
+        })
+    }
+}
+
+/// The record of one decision
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditRecord {
+    /// The version of the format of the record, currently 1
+    pub version: u32,
+    /// When the decision was made, in milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    /// The SHA-256 hash of the request, in hex
+    pub request_hash: String,
+    /// The principal of the request, if it was specified
+    pub principal: Option<String>,
+    /// The action of the request, if it was specified
+    pub action: Option<String>,
+    /// The resource of the request, if it was specified
+    pub resource: Option<String>,
+    /// The decision
+    pub decision: Decision,
+    /// The ids of the policies which determined the decision, sorted
+    pub determining_policies: Vec<String>,
+    /// The errors encountered while making the decision
+    pub errors: Vec<String>,
+    /// The SHA-256 hash of the policy set, in hex
+    pub policy_set_hash: String,
+    /// The entities the decision was made with
+    pub entities: EntitiesSnapshot,
+}
+
+impl AuditRecord {
+    /// The record of `response` to `request`, made with the policy set hashed
+    /// by [`policy_set_hash`] as `policy_set_hash` and with `entities`
+    pub fn new(
+        request: &Request,
+        response: &Response,
+        policy_set_hash: String,
+        entities: EntitiesSnapshot,
+    ) -> Self {
+        let mut determining_policies: Vec<String> = response
+            .diagnostics()
+            .reason()
+            .map(|id| id.as_ref().to_string())
+            .collect();
+        determining_policies.sort();
+        Self {
+            version: 1,
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| {
+                    u64::try_from(since.as_millis()).unwrap_or(u64::MAX)
+                }),
+            request_hash: request_hash(request),
+            principal: request.principal().map(ToString::to_string),
+            action: request.action().map(ToString::to_string),
+            resource: request.resource().map(ToString::to_string),
+            decision: response.decision(),
+            determining_policies,
+            errors: response
+                .diagnostics()
+                .errors()
+                .map(ToString::to_string)
+                .collect(),
+            policy_set_hash,
+            entities,
+        }
+    }
+
+    /// The canonical JSON form of the record, which [`Self::digest`] hashes
+    pub fn canonical_json(&self) -> String {
+        // the record serializes to an object, so there is no error to return
+        serde_json::to_value(self)
+            .map(|json| canonical_json(&json))
+            .unwrap_or_default()
+    }
+
+    /// The SHA-256 hash of [`Self::canonical_json`], in hex, to sign
+    pub fn digest(&self) -> String {
+        sha256_hex(self.canonical_json().as_bytes())
+    }
+}
+
+/// The SHA-256 hash of `policies`, in hex, which does not depend on the order
+/// of the policies in the policy set
+pub fn policy_set_hash(policies: &PolicySet) -> String {
+    let mut texts: Vec<String> = policies
+        .templates()
+        .map(|template| format!("template {}\n{template}", template.id()))
+        .chain(
+            policies
+                .policies()
+                .map(|policy| format!("policy {}\n{policy}", policy.id())),
+        )
+        .collect();
+    texts.sort();
+    sha256_hex(texts.join("\n").as_bytes())
+}
+
+/// The SHA-256 hash of `request`, in hex
+fn request_hash(request: &Request) -> String {
+    // a context which cannot be converted, with an invalid extension value,
+    // is hashed as it is displayed instead
+    let json = canonical::request_json(request).unwrap_or_else(|_| request.0.to_string());
+    sha256_hex(json.as_bytes())
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Authorizes requests against a policy set and entities, recording every
+/// decision in an [`AuditSink`]
+#[derive(Debug)]
+pub struct AuditedAuthorizer<S> {
+    authorizer: Authorizer,
+    policies: PolicySet,
+    policy_set_hash: String,
+    entities: Entities,
+    entities_snapshot: EntitiesSnapshot,
+    sink: S,
+}
+
+impl<S: AuditSink> AuditedAuthorizer<S> {
+    /// Authorize requests against `policies` and `entities`, which were read
+    /// at `block` if they came from a chain, recording the decisions in
+    /// `sink`
+    ///
+    /// # Errors
+    ///
+    /// If the entities cannot be serialized to take a snapshot of them.
+    pub fn new(
+        policies: PolicySet,
+        entities: Entities,
+        block: Option<u64>,
+        sink: S,
+    ) -> Result<Self, AuditError> {
+        Ok(Self {
+            authorizer: Authorizer::new(),
+            policy_set_hash: policy_set_hash(&policies),
+            policies,
+            entities_snapshot: EntitiesSnapshot::new(&entities, block)?,
+            entities,
+            sink,
+        })
+    }
+
+    /// The policies whose decisions are audited
+    pub fn policies(&self) -> &PolicySet {
+        &self.policies
+    }
+
+    /// The entities requests are authorized with
+    pub fn entities(&self) -> &Entities {
+        &self.entities
+    }
+
+    /// The sink the decisions are recorded in
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// Authorize `request`, recording the decision in the sink
+    ///
+    /// # Errors
+    ///
+    /// If the sink fails to record the decision, in which case the decision
+    /// must not be acted on.
+    pub fn is_authorized(&self, request: &Request) -> Result<Response, AuditError> {
+        let response = self
+            .authorizer
+            .is_authorized(request, &self.policies, &self.entities);
+        self.sink.record(&AuditRecord::new(
+            request,
+            &response,
+            self.policy_set_hash.clone(),
+            self.entities_snapshot.clone(),
+        ))?;
+        Ok(response)
+    }
+}
+
+/// An [`AuditSink`] which appends the canonical JSON of each record to a
+/// file, on its own line, and syncs the file before the decision is returned
+#[derive(Debug)]
+pub struct FileSink {
+    file: Mutex<File>,
+}
+
+impl FileSink {
+    /// Append records to the file at `path`, creating it if it does not exist
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be opened.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AuditError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl AuditSink for FileSink {
+    fn record(&self, record: &AuditRecord) -> Result<(), AuditError> {
+        let line = format!("{}\n", record.canonical_json());
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+        drop(file);
+        Ok(())
+    }
+}
+
+/// An [`AuditSink`] which posts the canonical JSON of each record to a URL,
+/// with its [`AuditRecord::digest`] in the `X-Audit-Digest` header. Any
+/// response other than a success fails the authorization.
+///
+/// This uses a blocking HTTP client, which must not be used from within an
+/// async runtime, such as in a tokio task; use `spawn_blocking` there.
+#[cfg(feature = "audit-webhook")]
+#[derive(Debug)]
+pub struct WebhookSink {
+    url: String,
+    client: reqwest::blocking::Client,
+}
+
+#[cfg(feature = "audit-webhook")]
+impl WebhookSink {
+    /// Post records to `url`
+    ///
+    /// # Errors
+    ///
+    /// If the HTTP client cannot be created.
+    pub fn new(url: impl Into<String>) -> Result<Self, AuditError> {
+        let url = url.into();
+        let client = reqwest::blocking::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .map_err(|e| AuditError::Webhook {
+                url: url.clone(),
+                message: e.to_string(),
+            })?;
+        Ok(Self { url, client })
+    }
+}
+
+#[cfg(feature = "audit-webhook")]
+impl AuditSink for WebhookSink {
+    fn record(&self, record: &AuditRecord) -> Result<(), AuditError> {
+        let error = |message: String| AuditError::Webhook {
+            url: self.url.clone(),
+            message,
+        };
+        let response = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Audit-Digest", record.digest())
+            .body(record.canonical_json())
+            .send()
+            .map_err(|e| error(e.to_string()))?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(error(format!(
+                "the webhook responded {}",
+                response.status()
+            )))
+        }
+    }
+}
+
+/// An [`AuditSink`] which sends the canonical JSON of each record to a Kafka
+/// topic, keyed by the [`AuditRecord::request_hash`].
+///
+/// The brokers acknowledge each record before the decision is returned.
+/// This uses a blocking client, which must not be used from within an async
+/// runtime, such as in a tokio task; use `spawn_blocking` there.
+#[cfg(feature = "audit-kafka")]
+pub struct KafkaSink {
+    topic: String,
+    producer: Mutex<kafka::producer::Producer>,
+}
+
+#[cfg(feature = "audit-kafka")]
+impl KafkaSink {
+    /// Send records to `topic` of the cluster bootstrapped from `hosts`, such
+    /// as `localhost:9092`, each acknowledged by all the in-sync replicas
+    ///
+    /// # Errors
+    ///
+    /// If the metadata of the cluster cannot be loaded from any of the hosts.
+    pub fn new(hosts: Vec<String>, topic: impl Into<String>) -> Result<Self, AuditError> {
+        let topic = topic.into();
+        let producer = kafka::producer::Producer::from_hosts(hosts)
+            .with_ack_timeout(std::time::Duration::from_secs(10))
+            .with_required_acks(kafka::producer::RequiredAcks::All)
+            .create()
+            .map_err(|e| AuditError::Kafka {
+                topic: topic.clone(),
+                message: e.to_string(),
+            })?;
+        Ok(Self::from_producer(producer, topic))
+    }
+
+    /// Send records to `topic` with `producer`, for producers configured
+    /// otherwise, such as with TLS
+    pub fn from_producer(producer: kafka::producer::Producer, topic: impl Into<String>) -> Self {
+        Self {
+            topic: topic.into(),
+            producer: Mutex::new(producer),
+        }
+    }
+}
+
+#[cfg(feature = "audit-kafka")]
+impl AuditSink for KafkaSink {
+    fn record(&self, record: &AuditRecord) -> Result<(), AuditError> {
+        let message = kafka::producer::Record::from_key_value(
+            &self.topic,
+            record.request_hash.as_str(),
+            record.canonical_json(),
+        );
+        self.producer
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .send(&message)
+            .map_err(|e| AuditError::Kafka {
+                topic: self.topic.clone(),
+                message: e.to_string(),
+            })
+    }
+}
+
+#[cfg(feature = "audit-kafka")]
+impl std::fmt::Debug for KafkaSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KafkaSink")
+            .field("topic", &self.topic)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An [`AuditSink`] which publishes the canonical JSON of each record to a
+/// NATS subject, with its [`AuditRecord::digest`] in the `X-Audit-Digest`
+/// header.
+///
+/// The connection is flushed before the decision is returned, so that the
+/// server has received the record. The server does not store it: capture the
+/// subject in a `JetStream` stream to keep the records.
+///
+/// The connection is driven by the tokio runtime it was made in, and recording
+/// blocks until the record is flushed, so it must not be done from within
+/// that runtime, such as in a tokio task; use `spawn_blocking` there.
+#[cfg(feature = "audit-nats")]
+#[derive(Debug)]
+pub struct NatsSink {
+    subject: String,
+    client: async_nats::Client,
+}
+
+#[cfg(feature = "audit-nats")]
+impl NatsSink {
+    /// Publish records to `subject` of the server at `url`, such as
+    /// `nats://localhost:4222`
+    ///
+    /// # Errors
+    ///
+    /// If the server cannot be connected to.
+    pub async fn connect(url: &str, subject: impl Into<String>) -> Result<Self, AuditError> {
+        let subject = subject.into();
+        let client = async_nats::connect(url)
+            .await
+            .map_err(|e| AuditError::Nats {
+                subject: subject.clone(),
+                message: e.to_string(),
+            })?;
+        Ok(Self::from_client(client, subject))
+    }
+
+    /// Publish records to `subject` with `client`, for clients connected
+    /// otherwise, such as with credentials
+    pub fn from_client(client: async_nats::Client, subject: impl Into<String>) -> Self {
+        Self {
+            subject: subject.into(),
+            client,
+        }
+    }
+}
+
+#[cfg(feature = "audit-nats")]
+impl AuditSink for NatsSink {
+    fn record(&self, record: &AuditRecord) -> Result<(), AuditError> {
+        let error = |message: String| AuditError::Nats {
+            subject: self.subject.clone(),
+            message,
+        };
+        let mut headers = async_nats::HeaderMap::new();
+        headers.insert("X-Audit-Digest", record.digest().as_str());
+        futures_executor::block_on(async {
+            self.client
+                .publish_with_headers(
+                    self.subject.clone(),
+                    headers,
+                    record.canonical_json().into(),
+                )
+                .await
+                .map_err(|e| error(e.to_string()))?;
+            self.client.flush().await.map_err(|e| error(e.to_string()))
+        })
+    }
+}
+
+/// Which decisions a [`DecisionNotifier`] posts
+#[cfg(feature = "audit-webhook")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NotifyFilter {
+    /// Every decision
+    #[default]
+    All,
+    /// Only the decisions which denied the request
+    Denies,
+}
+
+#[cfg(feature = "audit-webhook")]
+impl NotifyFilter {
+    /// Whether `record` is posted
+    pub fn accepts(self, record: &AuditRecord) -> bool {
+        match self {
+            Self::All => true,
+            Self::Denies => record.decision == Decision::Deny,
+        }
+    }
+}
+
+/// A webhook a [`DecisionNotifier`] posts to
+#[cfg(feature = "audit-webhook")]
+#[derive(Clone)]
+struct Endpoint {
+    url: String,
+    secret: Option<Vec<u8>>,
+}
+
+#[cfg(feature = "audit-webhook")]
+impl std::fmt::Debug for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the secret is not printed
+        f.debug_struct("Endpoint")
+            .field("url", &self.url)
+            .field("signed", &self.secret.is_some())
+            .finish()
+    }
+}
+
+/// The configuration of a [`DecisionNotifier`]: the webhooks it posts to,
+/// which decisions it posts, how it batches them, and how it retries
+#[cfg(feature = "audit-webhook")]
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct NotifierConfig {
+    endpoints: Vec<Endpoint>,
+    filter: NotifyFilter,
+    batch_size: usize,
+    batch_interval: std::time::Duration,
+    retries: u32,
+    backoff: std::time::Duration,
+    timeout: std::time::Duration,
+}
+
+#[cfg(feature = "audit-webhook")]
+impl Default for NotifierConfig {
+    fn default() -> Self {
+        Self {
+            endpoints: Vec::new(),
+            filter: NotifyFilter::All,
+            batch_size: 100,
+            batch_interval: std::time::Duration::from_secs(1),
+            retries: 5,
+            backoff: std::time::Duration::from_millis(500),
+            timeout: std::time::Duration::from_secs(10),
+        }
+    }
+}
+
+#[cfg(feature = "audit-webhook")]
+impl NotifierConfig {
+    /// Post every decision, to no webhooks yet, in batches of up to 100
+    /// records sent at least every second, retrying a failed post 5 times
+    /// after backoffs doubling from half a second
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Post to `url`, without a signature
+    pub fn endpoint(mut self, url: impl Into<String>) -> Self {
+        self.endpoints.push(Endpoint {
+            url: url.into(),
+            secret: None,
+        });
+        self
+    }
+
+    /// Post to `url`, signing each body with HMAC-SHA256 keyed by `secret`,
+    /// in the `X-Audit-Signature` header as `sha256=` followed by the hex of
+    /// the signature
+    pub fn signed_endpoint(mut self, url: impl Into<String>, secret: impl AsRef<[u8]>) -> Self {
+        self.endpoints.push(Endpoint {
+            url: url.into(),
+            secret: Some(secret.as_ref().to_vec()),
+        });
+        self
+    }
+
+    /// Post only the decisions `filter` accepts
+    pub fn filter(mut self, filter: NotifyFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Post at most `size` records at once, and a batch which is not full
+    /// `interval` after its first record was received. A size of 0 is taken
+    /// as 1.
+    pub fn batching(mut self, size: usize, interval: std::time::Duration) -> Self {
+        self.batch_size = size.max(1);
+        self.batch_interval = interval;
+        self
+    }
+
+    /// Retry a failed post up to `retries` times, waiting `backoff` before
+    /// the first retry and twice as long before each retry after it
+    pub fn retries(mut self, retries: u32, backoff: std::time::Duration) -> Self {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Give up on a post which has not been answered after `timeout`
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// An [`AuditSink`] which posts decisions to webhooks in the background, for
+/// alerting on blocked transactions as they happen.
+///
+/// Unlike [`WebhookSink`], recording never fails or waits on the network: the
+/// records [`NotifyFilter`] accepts are queued for a background thread, which
+/// posts them in batches, as a JSON array of the canonical JSON of each
+/// record, to every endpoint. A post which fails, with an error, or a
+/// response of 429 or 5xx, is retried with exponential backoff; a post which
+/// still fails, or gets another response other than a success, is dropped and
+/// its error passed to the listeners added with [`Self::on_error`].
+///
+/// Dropping the notifier posts the records still queued, and waits for that.
+#[cfg(feature = "audit-webhook")]
+pub struct DecisionNotifier {
+    filter: NotifyFilter,
+    queue: Option<std::sync::mpsc::Sender<AuditRecord>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+    error_listeners: std::sync::Arc<std::sync::RwLock<Vec<ErrorListener>>>,
+}
+
+#[cfg(feature = "audit-webhook")]
+type ErrorListener = Box<dyn Fn(&AuditError) + Send + Sync>;
+
+#[cfg(feature = "audit-webhook")]
+impl DecisionNotifier {
+    /// Start posting decisions as configured by `config`
+    ///
+    /// # Errors
+    ///
+    /// If the HTTP client cannot be created.
+    pub fn new(config: NotifierConfig) -> Result<Self, AuditError> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .map_err(|e| AuditError::Webhook {
+                url: config
+                    .endpoints
+                    .first()
+                    .map(|endpoint| endpoint.url.clone())
+                    .unwrap_or_default(),
+                message: e.to_string(),
+            })?;
+        let filter = config.filter;
+        let (queue, records) = std::sync::mpsc::channel();
+        let error_listeners = std::sync::Arc::default();
+        let delivery = Delivery {
+            client,
+            config,
+            error_listeners: std::sync::Arc::clone(&error_listeners),
+        };
+        let thread = std::thread::spawn(move || delivery.run(&records));
+        Ok(Self {
+            filter,
+            queue: Some(queue),
+            thread: Some(thread),
+            error_listeners,
+        })
+    }
+
+    /// Call `listener` with the error of every batch which could not be
+    /// posted to an endpoint
+    pub fn on_error(&self, listener: impl Fn(&AuditError) + Send + Sync + 'static) {
+        self.error_listeners
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Box::new(listener));
+    }
+}
+
+#[cfg(feature = "audit-webhook")]
+impl AuditSink for DecisionNotifier {
+    fn record(&self, record: &AuditRecord) -> Result<(), AuditError> {
+        if self.filter.accepts(record) {
+            if let Some(queue) = &self.queue {
+                // the thread only stops once the queue is dropped
+                let _ = queue.send(record.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "audit-webhook")]
+impl std::fmt::Debug for DecisionNotifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DecisionNotifier")
+            .field("filter", &self.filter)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "audit-webhook")]
+impl Drop for DecisionNotifier {
+    fn drop(&mut self) {
+        // disconnecting the queue stops the thread once it has posted the
+        // records still queued
+        drop(self.queue.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The background thread of a [`DecisionNotifier`]
+#[cfg(feature = "audit-webhook")]
+struct Delivery {
+    client: reqwest::blocking::Client,
+    config: NotifierConfig,
+    error_listeners: std::sync::Arc<std::sync::RwLock<Vec<ErrorListener>>>,
+}
+
+#[cfg(feature = "audit-webhook")]
+impl Delivery {
+    /// Post the records received from `records` in batches, until it is
+    /// disconnected
+    fn run(&self, records: &std::sync::mpsc::Receiver<AuditRecord>) {
+        use std::sync::mpsc::RecvTimeoutError;
+        use std::time::Instant;
+
+        let mut batch = Vec::new();
+        // when the current batch is posted, if it is not filled first
+        let mut deadline: Option<Instant> = None;
+        loop {
+            let received = deadline.map_or_else(
+                || records.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                |deadline| records.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            );
+            match received {
+                Ok(record) => {
+                    batch.push(record);
+                    deadline.get_or_insert_with(|| Instant::now() + self.config.batch_interval);
+                    if batch.len() < self.config.batch_size {
+                        continue;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.post(&batch);
+                    return;
+                }
+            }
+            self.post(&std::mem::take(&mut batch));
+            deadline = None;
+        }
+    }
+
+    /// Post `batch` to every endpoint
+    fn post(&self, batch: &[AuditRecord]) {
+        if batch.is_empty() {
+            return;
+        }
+        let body = format!(
+            "[{}]",
+            batch
+                .iter()
+                .map(AuditRecord::canonical_json)
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        for endpoint in &self.config.endpoints {
+            if let Err(error) = self.post_with_retries(endpoint, &body) {
+                for listener in self
+                    .error_listeners
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .iter()
+                {
+                    listener(&error);
+                }
+            }
+        }
+    }
+
+    /// Post `body` to `endpoint`, retrying as configured
+    fn post_with_retries(&self, endpoint: &Endpoint, body: &str) -> Result<(), AuditError> {
+        let error = |message: String| AuditError::Webhook {
+            url: endpoint.url.clone(),
+            message,
+        };
+        let signature = endpoint
+            .secret
+            .as_deref()
+            .map(|secret| format!("sha256={}", hmac_sha256_hex(secret, body.as_bytes())));
+        let mut backoff = self.config.backoff;
+        let mut attempt = 0;
+        loop {
+            let mut request = self
+                .client
+                .post(&endpoint.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.to_string());
+            if let Some(signature) = &signature {
+                request = request.header("X-Audit-Signature", signature);
+            }
+            let (message, retryable) = match request.send() {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => {
+                    let status = response.status();
+                    (
+                        format!("the webhook responded {status}"),
+                        status.is_server_error()
+                            || status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+                    )
+                }
+                Err(e) => (e.to_string(), true),
+            };
+            if !retryable || attempt >= self.config.retries {
+                return Err(error(message));
+            }
+            std::thread::sleep(backoff);
+            backoff = backoff.saturating_mul(2);
+            attempt += 1;
+        }
+    }
+}
+
+/// The HMAC-SHA256 of `message` keyed by `secret`, in hex
+#[cfg(feature = "audit-webhook")]
+fn hmac_sha256_hex(secret: &[u8], message: &[u8]) -> String {
+    use hmac::{Hmac, Mac};
+
+    // HMAC accepts keys of any length, so this never falls back
+    Hmac::<Sha256>::new_from_slice(secret).map_or_else(
+        |_| String::new(),
+        |mut mac| {
+            mac.update(message);
+            hex::encode(mac.finalize().into_bytes())
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Context, EntityUid};
+    use std::str::FromStr;
+
+    /// An [`AuditSink`] which keeps the records in memory, or fails
+    #[derive(Default)]
+    struct MemorySink {
+        records: Mutex<Vec<AuditRecord>>,
+        fail: bool,
+    }
+
+    impl AuditSink for MemorySink {
+        fn record(&self, record: &AuditRecord) -> Result<(), AuditError> {
+            if self.fail {
+                return Err(AuditError::Io(std::io::Error::other("disk full")));
+            }
+            self.records
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(record.clone());
+            Ok(())
+        }
+    }
+
+    fn request() -> Request {
+        Request::new(
+            EntityUid::from_str(r#"Address::"0xabc""#).ok(),
+            EntityUid::from_str(r#"Action::"approve""#).ok(),
+            EntityUid::from_str(r#"Vault::"cold""#).ok(),
+            Context::empty(),
+        )
+    }
+
+    fn entities(json: &str) -> Entities {
+        Entities::from_json_str(json, None).expect("entities should parse")
+    }
+
+    const ENTITIES: &str = r#"[
+        { "uid": { "type": "Address", "id": "0xabc" }, "attrs": { "limit": 10 },
+          "parents": [{ "type": "Group", "id": "signers" }, { "type": "Group", "id": "admins" }] },
+        { "uid": { "type": "Group", "id": "signers" }, "attrs": {}, "parents": [] },
+        { "uid": { "type": "Group", "id": "admins" }, "attrs": {}, "parents": [] }
+    ]"#;
+
+    #[test]
+    fn records_every_decision() {
+        let policies = PolicySet::from_str(
+            r#"permit(principal in Group::"signers", action == Action::"approve", resource);"#,
+        )
+        .expect("policies should parse");
+        let hash = policy_set_hash(&policies);
+        let authorizer = AuditedAuthorizer::new(
+            policies,
+            entities(ENTITIES),
+            Some(42),
+            MemorySink::default(),
+        )
+        .expect("entities should serialize");
+        let response = authorizer.is_authorized(&request()).expect("sink works");
+        assert_eq!(response.decision(), Decision::Allow);
+        let denied = Request::new(None, None, None, Context::empty());
+        let response = authorizer.is_authorized(&denied).expect("sink works");
+        assert_eq!(response.decision(), Decision::Deny);
+
+        let records = authorizer
+            .sink()
+            .records
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let [allowed, denied] = records.as_slice() else {
+            panic!("expected two records, got {records:?}");
+        };
+        assert_eq!(allowed.decision, Decision::Allow);
+        assert_eq!(allowed.determining_policies, vec!["policy0".to_string()]);
+        assert_eq!(allowed.principal.as_deref(), Some(r#"Address::"0xabc""#));
+        assert_eq!(allowed.policy_set_hash, hash);
+        assert_eq!(allowed.entities.block, Some(42));
+        assert_eq!(denied.decision, Decision::Deny);
+        assert_eq!(denied.principal, None);
+        assert_ne!(allowed.request_hash, denied.request_hash);
+        assert_eq!(allowed.request_hash, request_hash(&request()));
+    }
+
+    #[test]
+    fn fails_closed() {
+        let sink = MemorySink {
+            fail: true,
+            ..MemorySink::default()
+        };
+        let authorizer = AuditedAuthorizer::new(PolicySet::new(), Entities::empty(), None, sink)
+            .expect("entities should serialize");
+        assert!(matches!(
+            authorizer.is_authorized(&request()),
+            Err(AuditError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn hashes_do_not_depend_on_order() {
+        let reordered = r#"[
+            { "uid": { "type": "Group", "id": "admins" }, "attrs": {}, "parents": [] },
+            { "uid": { "type": "Address", "id": "0xabc" }, "attrs": { "limit": 10 },
+              "parents": [{ "type": "Group", "id": "admins" }, { "type": "Group", "id": "signers" }] },
+            { "uid": { "type": "Group", "id": "signers" }, "attrs": {}, "parents": [] }
+        ]"#;
+        let snapshot = |json| EntitiesSnapshot::new(&entities(json), None).expect("serializes");
+        assert_eq!(snapshot(ENTITIES), snapshot(reordered));
+        assert_ne!(
+            snapshot(ENTITIES),
+            snapshot(&ENTITIES.replace("\"limit\": 10", "\"limit\": 11"))
+        );
+
+        let mut first = PolicySet::new();
+        let mut second = PolicySet::new();
+        for (id, text) in [
+            ("a", "permit(principal, action, resource);"),
+            ("b", "forbid(principal, action, resource) when { false };"),
+        ] {
+            let policy = crate::Policy::parse(Some(id.to_string()), text).expect("parses");
+            first.add(policy).expect("unique ids");
+        }
+        for (id, text) in [
+            ("b", "forbid(principal, action, resource) when { false };"),
+            ("a", "permit(principal, action, resource);"),
+        ] {
+            let policy = crate::Policy::parse(Some(id.to_string()), text).expect("parses");
+            second.add(policy).expect("unique ids");
+        }
+        assert_eq!(policy_set_hash(&first), policy_set_hash(&second));
+        assert_ne!(policy_set_hash(&first), policy_set_hash(&PolicySet::new()));
+    }
+
+    #[test]
+    fn canonical_json_is_stable() {
+        let record = AuditRecord::new(
+            &request(),
+            &Authorizer::new().is_authorized(&request(), &PolicySet::new(), &Entities::empty()),
+            "00".to_string(),
+            EntitiesSnapshot {
+                block: None,
+                hash: "11".to_string(),
+            },
+        );
+        let json = record.canonical_json();
+        assert!(json.starts_with(r#"{"action":"Action::\"approve\"","decision":"Deny","#));
+        assert_eq!(
+            serde_json::from_str::<AuditRecord>(&json).expect("round-trips"),
+            record
+        );
+        assert_eq!(record.digest(), sha256_hex(json.as_bytes()));
+    }
+
+    #[test]
+    fn file_sink_appends_lines() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("audit.jsonl");
+        let authorizer = AuditedAuthorizer::new(
+            PolicySet::new(),
+            Entities::empty(),
+            None,
+            FileSink::open(&path).expect("opens"),
+        )
+        .expect("entities should serialize");
+        authorizer.is_authorized(&request()).expect("writes");
+        authorizer.is_authorized(&request()).expect("writes");
+        let log = std::fs::read_to_string(&path).expect("reads");
+        let records: Vec<AuditRecord> = log
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("each line is a record"))
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert!(records
+            .iter()
+            .all(|record| record.decision == Decision::Deny));
+    }
+
+    /// Serve one request per status of `statuses`, and return the URL of the
+    /// server and its thread, which returns the headers, with lowercase
+    /// names, and the body of each request
+    #[cfg(feature = "audit-webhook")]
+    fn serve(
+        statuses: Vec<&'static str>,
+    ) -> (
+        String,
+        std::thread::JoinHandle<Vec<(std::collections::HashMap<String, String>, String)>>,
+    ) {
+        use std::io::{BufRead, BufReader, Read};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").expect("binds");
+        let url = format!(
+            "http://{}/audit",
+            listener.local_addr().expect("has address")
+        );
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for status in statuses {
+                let (stream, _) = listener.accept().expect("accepts");
+                let mut reader = BufReader::new(stream);
+                let mut headers = std::collections::HashMap::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).expect("reads");
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(": ") {
+                        headers.insert(name.to_ascii_lowercase(), value.to_string());
+                    }
+                }
+                let length = headers
+                    .get("content-length")
+                    .and_then(|length| length.parse().ok())
+                    .unwrap_or(0);
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).expect("reads body");
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .expect("writes");
+                requests.push((headers, String::from_utf8_lossy(&body).into_owned()));
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    #[cfg(feature = "audit-webhook")]
+    #[test]
+    fn webhook_sink_posts_records() {
+        let (url, server) = serve(vec!["200 OK", "500 Internal Server Error"]);
+        let authorizer = AuditedAuthorizer::new(
+            PolicySet::new(),
+            Entities::empty(),
+            None,
+            WebhookSink::new(url).expect("client builds"),
+        )
+        .expect("entities should serialize");
+        authorizer.is_authorized(&request()).expect("posts");
+        assert!(matches!(
+            authorizer.is_authorized(&request()),
+            Err(AuditError::Webhook { .. })
+        ));
+        let requests = server.join().expect("server runs");
+        let (headers, body) = requests.first().expect("got a request");
+        let record: AuditRecord = serde_json::from_str(body).expect("body is a record");
+        assert_eq!(headers.get("x-audit-digest"), Some(&record.digest()));
+    }
+
+    #[cfg(feature = "audit-webhook")]
+    #[test]
+    fn hmac_matches_test_vector() {
+        assert_eq!(
+            hmac_sha256_hex(b"key", b"The quick brown fox jumps over the lazy dog"),
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[cfg(feature = "audit-webhook")]
+    #[test]
+    fn notifier_batches_signs_and_retries() {
+        use std::time::Duration;
+
+        // the first batch is retried after a 503, and the second is dropped
+        // after a 400
+        let (url, server) = serve(vec!["503 Service Unavailable", "200 OK", "400 Bad Request"]);
+        let notifier = DecisionNotifier::new(
+            NotifierConfig::new()
+                .signed_endpoint(url, "secret")
+                .filter(NotifyFilter::Denies)
+                .batching(2, Duration::from_secs(60))
+                .retries(3, Duration::from_millis(10)),
+        )
+        .expect("client builds");
+        let errors = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let listened = std::sync::Arc::clone(&errors);
+        notifier.on_error(move |error| {
+            listened
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(error.to_string());
+        });
+        let policies =
+            PolicySet::from_str(r#"permit(principal == Address::"0xabc", action, resource);"#)
+                .expect("policies should parse");
+        let authorizer = AuditedAuthorizer::new(policies, Entities::empty(), None, notifier)
+            .expect("entities should serialize");
+        let denied = Request::new(None, None, None, Context::empty());
+        assert_eq!(
+            authorizer
+                .is_authorized(&request())
+                .expect("never fails")
+                .decision(),
+            Decision::Allow
+        );
+        for _ in 0..3 {
+            assert_eq!(
+                authorizer
+                    .is_authorized(&denied)
+                    .expect("never fails")
+                    .decision(),
+                Decision::Deny
+            );
+        }
+        // posts the last, partial, batch
+        drop(authorizer);
+
+        let requests = server.join().expect("server runs");
+        let [(failed, first), (headers, retried), (_, last)] = requests.as_slice() else {
+            panic!("expected three posts, got {requests:?}");
+        };
+        assert_eq!(first, retried);
+        assert_eq!(
+            failed.get("x-audit-signature"),
+            headers.get("x-audit-signature")
+        );
+        assert_eq!(
+            headers.get("x-audit-signature"),
+            Some(&format!(
+                "sha256={}",
+                hmac_sha256_hex(b"secret", retried.as_bytes())
+            ))
+        );
+        let batch: Vec<AuditRecord> = serde_json::from_str(retried).expect("body is records");
+        assert_eq!(batch.len(), 2);
+        assert!(batch.iter().all(|record| record.decision == Decision::Deny));
+        let batch: Vec<AuditRecord> = serde_json::from_str(last).expect("body is records");
+        assert_eq!(batch.len(), 1);
+        let errors = errors.lock().unwrap_or_else(PoisonError::into_inner);
+        let [error] = errors.as_slice() else {
+            panic!("expected one error, got {errors:?}");
+        };
+        assert!(error.contains("400"), "{error}");
+    }
+
+    #[cfg(feature = "audit-kafka")]
+    #[test]
+    fn kafka_sink_needs_a_cluster() {
+        // a port nothing listens on
+        let address = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("binds");
+        assert!(matches!(
+            KafkaSink::new(vec![address.to_string()], "audit"),
+            Err(AuditError::Kafka { topic, .. }) if topic == "audit"
+        ));
+    }
+
+    #[cfg(feature = "audit-nats")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn nats_sink_publishes_records() {
+        use std::io::{BufRead, BufReader, Read};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").expect("binds");
+        let url = format!("nats://{}", listener.local_addr().expect("has address"));
+        // speaks enough of the NATS protocol to take one published message,
+        // returning its subject, headers and payload
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().expect("accepts");
+            let mut reader = BufReader::new(stream);
+            write!(
+                reader.get_mut(),
+                "INFO {{\"server_id\":\"test\",\"version\":\"2.10.0\",\"proto\":1,\"headers\":true,\"max_payload\":1048576}}\r\n"
+            )
+            .expect("writes");
+            let mut published = None;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).expect("reads") == 0 {
+                    return published;
+                }
+                let words: Vec<&str> = line.split_whitespace().collect();
+                match words.as_slice() {
+                    ["PING"] => {
+                        write!(reader.get_mut(), "PONG\r\n").expect("writes");
+                        if published.is_some() {
+                            return published;
+                        }
+                    }
+                    ["HPUB", subject, header_length, total_length] => {
+                        let header_length: usize = header_length.parse().expect("a length");
+                        let total_length: usize = total_length.parse().expect("a length");
+                        let mut message = vec![0; total_length + 2];
+                        reader.read_exact(&mut message).expect("reads message");
+                        let (headers, payload) = message.split_at(header_length);
+                        published = Some((
+                            (*subject).to_string(),
+                            String::from_utf8_lossy(headers).into_owned(),
+                            String::from_utf8_lossy(payload).trim_end().to_string(),
+                        ));
+                    }
+                    _ => (),
+                }
+            }
+        });
+
+        let sink = NatsSink::connect(&url, "audit.decisions")
+            .await
+            .expect("connects");
+        let record = AuditRecord::new(
+            &request(),
+            &Authorizer::new().is_authorized(&request(), &PolicySet::new(), &Entities::empty()),
+            "00".to_string(),
+            EntitiesSnapshot {
+                block: None,
+                hash: "11".to_string(),
+            },
+        );
+        let published = record.clone();
+        tokio::task::spawn_blocking(move || sink.record(&published))
+            .await
+            .expect("joins")
+            .expect("publishes");
+        let (subject, headers, payload) =
+            server.join().expect("server runs").expect("got a message");
+        assert_eq!(subject, "audit.decisions");
+        assert!(
+            headers.contains(&format!("X-Audit-Digest: {}", record.digest())),
+            "{headers}"
+        );
+        assert_eq!(payload, record.canonical_json());
+    }
+}
@@ -0,0 +1,560 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Policy bundles: a policy set, its template links and its schema in a
+//! single file, so that every environment loads exactly the same policies.
+//!
+//! A [`PolicyBundle`] is a JSON object with a [`BundleManifest`] and the
+//! files of the bundle:
+//! * [`POLICIES_FILE`], the policies and templates, in the Cedar syntax,
+//! * [`LINKS_FILE`], the template links, as a JSON array of [`BundleLink`]s
+//!   in the format of the template-linked files of the CLI, and
+//! * [`SCHEMA_FILE`], if the bundle has a schema, in the JSON or the
+//!   human-readable schema format.
+//!
+//! The manifest names and versions the bundle, records when it was made and
+//! any other metadata, and holds the SHA-256 hash of each file.
+//! [`PolicyBundle::verify`] checks that the files match their hashes and that
+//! the policies, links and schema all load, and [`PolicyBundle::load`] only
+//! returns bundles which pass. [`PolicyBundle::content_hash`] identifies the
+//! contents of a bundle, whenever and wherever it was made, so that two
+//! environments can check they run the same policies.
+//!
+//! ```
+//! # use cedar_policy::bundle::{BundleLink, PolicyBundle};
+//! # let dir = tempfile::tempdir().unwrap();
+//! # let path = dir.path().join("guards.bundle.json");
+//! let bundle = PolicyBundle::new(
+//!     "guards",
+//!     "1.4.0",
+//!     "permit(principal == ?principal, action, resource) when { context.amount < 100 };",
+//! )
+//! .with_link(BundleLink::new("policy0", "alice-limit").with_arg("?principal", r#"User::"alice""#))
+//! .with_metadata("environment", "production");
+//! bundle.save(&path).unwrap();
+//!
+//! let loaded = PolicyBundle::load(&path).unwrap();
+//! assert_eq!(loaded.content_hash(), bundle.content_hash());
+//! assert_eq!(loaded.policy_set().unwrap().policies().count(), 1);
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::{
+    EntityUid, ParseErrors, PolicyId, PolicySet, PolicySetError, Schema, SchemaError, SlotId,
+};
+
+/// The version of the bundle format written by [`PolicyBundle::save`]
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The name of the file of the policies and templates of a bundle
+pub const POLICIES_FILE: &str = "policies.cedar";
+
+/// The name of the file of the template links of a bundle
+pub const LINKS_FILE: &str = "links.json";
+
+/// The name of the file of the schema of a bundle
+pub const SCHEMA_FILE: &str = "schema";
+
+/// Errors loading, verifying or saving a [`PolicyBundle`]
+#[derive(Debug, Error)]
+pub enum BundleError {
+    /// A bundle file could not be read or written
+    #[error("failed to access `{}`: {error}", .path.display())]
+    Io {
+        /// The path of the bundle
+        path: PathBuf,
+        /// The underlying error
+        error: std::io::Error,
+    },
+    /// A bundle is not valid JSON, or not in the expected format
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// A bundle was written in a later version of the format
+    #[error("bundle format version {0} is not supported, only up to {FORMAT_VERSION}")]
+    UnsupportedFormat(u32),
+    /// A file of a bundle does not match the hash in its manifest
+    #[error("`{file}` does not match its hash `{expected}` in the manifest")]
+    HashMismatch {
+        /// The file
+        file: String,
+        /// The hash in the manifest
+        expected: String,
+    },
+    /// A file of a bundle is not listed in its manifest, or the reverse
+    #[error("`{file}` is not both in the bundle and in its manifest")]
+    Unlisted {
+        /// The file
+        file: String,
+    },
+    /// The policies of a bundle do not parse
+    #[error("failed to parse the policies of the bundle: {0}")]
+    Policies(Box<ParseErrors>),
+    /// The links of a bundle are malformed, or a template cannot be linked
+    #[error("failed to link `{link_id}`: {message}")]
+    Link {
+        /// The id of the link
+        link_id: String,
+        /// What went wrong
+        message: String,
+    },
+    /// The schema of a bundle does not parse
+    #[error("failed to parse the schema of the bundle: {0}")]
+    Schema(Box<SchemaError>),
+}
+
+/// The name, version and other metadata of a bundle, and the hashes of its
+/// files
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleManifest {
+    /// The version of the bundle format
+    pub format_version: u32,
+    /// The name of the bundle
+    pub name: String,
+    /// The version of the bundle, such as a semantic version or a commit
+    pub version: String,
+    /// When the bundle was made, in milliseconds since the Unix epoch
+    pub created_at_ms: u64,
+    /// Any other metadata, such as the environment the bundle is for
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+    /// The hex SHA-256 hash of each file of the bundle, by name
+    pub hashes: BTreeMap<String, String>,
+}
+
+/// A template link of a bundle, in the format of the template-linked files of
+/// the CLI
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleLink {
+    /// The id of the template
+    pub template_id: String,
+    /// The id of the linked policy
+    pub link_id: String,
+    /// The entity each slot is linked to, like `?principal` to
+    /// `User::"alice"`
+    pub args: BTreeMap<String, String>,
+}
+
+impl BundleLink {
+    /// A link of the template `template_id` as `link_id`, with no slots
+    /// filled yet
+    pub fn new(template_id: impl Into<String>, link_id: impl Into<String>) -> Self {
+        Self {
+            template_id: template_id.into(),
+            link_id: link_id.into(),
+            args: BTreeMap::new(),
+        }
+    }
+
+    /// The same link, with the slot `slot` filled with the entity `entity`
+    #[must_use]
+    pub fn with_arg(mut self, slot: impl Into<String>, entity: impl Into<String>) -> Self {
+        self.args.insert(slot.into(), entity.into());
+        self
+    }
+
+    /// Link the template of the link in `policies`
+    fn link(&self, policies: &mut PolicySet) -> Result<(), BundleError> {
+        let error = |message: String| BundleError::Link {
+            link_id: self.link_id.clone(),
+            message,
+        };
+        let vals = self
+            .args
+            .iter()
+            .map(|(slot, entity)| {
+                let slot = match slot.as_str() {
+                    "?principal" => SlotId::principal(),
+                    "?resource" => SlotId::resource(),
+                    _ => return Err(error(format!("`{slot}` is not a slot"))),
+                };
+                let entity = EntityUid::from_str(entity).map_err(|e| error(e.to_string()))?;
+                Ok((slot, entity))
+            })
+            .collect::<Result<_, _>>()?;
+        let template_id =
+            PolicyId::from_str(&self.template_id).map_err(|e| error(e.to_string()))?;
+        let link_id = PolicyId::from_str(&self.link_id).map_err(|e| error(e.to_string()))?;
+        policies
+            .link(template_id, link_id, vals)
+            .map_err(|e: PolicySetError| error(e.to_string()))
+    }
+}
+
+/// A policy set, its template links and its schema, with a manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyBundle {
+    manifest: BundleManifest,
+    files: BTreeMap<String, String>,
+}
+
+impl PolicyBundle {
+    /// A bundle named `name` at `version`, made now, with the policies and
+    /// templates `policies`, in the Cedar syntax, and no links or schema
+    pub fn new(
+        name: impl Into<String>,
+        version: impl Into<String>,
+        policies: impl Into<String>,
+    ) -> Self {
+        let bundle = Self {
+            manifest: BundleManifest {
+                format_version: FORMAT_VERSION,
+                name: name.into(),
+                version: version.into(),
+                created_at_ms: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |since| {
+                        u64::try_from(since.as_millis()).unwrap_or(u64::MAX)
+                    }),
+                metadata: BTreeMap::new(),
+                hashes: BTreeMap::new(),
+            },
+            files: BTreeMap::new(),
+        };
+        bundle
+            .with_file(POLICIES_FILE, policies.into())
+            .with_links(Vec::new())
+    }
+
+    /// The same bundle, with the template link `link` added
+    #[must_use]
+    pub fn with_link(self, link: BundleLink) -> Self {
+        let mut links = self.links().unwrap_or_default();
+        links.push(link);
+        self.with_links(links)
+    }
+
+    /// The same bundle, with the template links `links` in place of its own
+    #[must_use]
+    pub fn with_links(self, links: impl IntoIterator<Item = BundleLink>) -> Self {
+        let links: Vec<_> = links.into_iter().collect();
+        // a `Vec` of plain structs always serializes
+        let links = serde_json::to_string_pretty(&links).unwrap_or_default();
+        self.with_file(LINKS_FILE, links)
+    }
+
+    /// The same bundle, with the schema `schema`, in the JSON or the
+    /// human-readable schema format
+    #[must_use]
+    pub fn with_schema(self, schema: impl Into<String>) -> Self {
+        self.with_file(SCHEMA_FILE, schema.into())
+    }
+
+    /// The same bundle, with the metadata `key` set to `value`
+    #[must_use]
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.manifest.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// The same bundle, made at `created_at_ms` milliseconds since the Unix
+    /// epoch instead
+    #[must_use]
+    pub fn at(mut self, created_at_ms: u64) -> Self {
+        self.manifest.created_at_ms = created_at_ms;
+        self
+    }
+
+    fn with_file(mut self, name: &str, text: String) -> Self {
+        self.manifest
+            .hashes
+            .insert(name.to_string(), hash(text.as_bytes()));
+        self.files.insert(name.to_string(), text);
+        self
+    }
+
+    /// The manifest of the bundle
+    pub fn manifest(&self) -> &BundleManifest {
+        &self.manifest
+    }
+
+    /// The text of the policies and templates of the bundle
+    pub fn policies_text(&self) -> &str {
+        self.files.get(POLICIES_FILE).map_or("", String::as_str)
+    }
+
+    /// The text of the schema of the bundle, if it has one
+    pub fn schema_text(&self) -> Option<&str> {
+        self.files.get(SCHEMA_FILE).map(String::as_str)
+    }
+
+    /// The template links of the bundle
+    ///
+    /// # Errors
+    ///
+    /// If the links file is malformed.
+    pub fn links(&self) -> Result<Vec<BundleLink>, BundleError> {
+        self.files
+            .get(LINKS_FILE)
+            .map_or(Ok(Vec::new()), |links| Ok(serde_json::from_str(links)?))
+    }
+
+    /// The policies and templates of the bundle, with its links
+    ///
+    /// # Errors
+    ///
+    /// If the policies do not parse, or a template cannot be linked.
+    pub fn policy_set(&self) -> Result<PolicySet, BundleError> {
+        let mut policies = PolicySet::from_str(self.policies_text())
+            .map_err(|e| BundleError::Policies(Box::new(e)))?;
+        for link in self.links()? {
+            link.link(&mut policies)?;
+        }
+        Ok(policies)
+    }
+
+    /// The schema of the bundle, if it has one
+    ///
+    /// # Errors
+    ///
+    /// If the schema does not parse.
+    pub fn schema(&self) -> Result<Option<Schema>, BundleError> {
+        self.schema_text()
+            .map(|schema| {
+                // JSON schemas are objects; anything else is in the
+                // human-readable format
+                if schema.trim_start().starts_with('{') {
+                    Schema::from_str(schema)
+                } else {
+                    Schema::from_str_natural(schema)
+                }
+                .map_err(|e| BundleError::Schema(Box::new(e)))
+            })
+            .transpose()
+    }
+
+    /// The hex SHA-256 hash of the files of the bundle, which is the same for
+    /// any two bundles with the same files, whatever their manifests say
+    pub fn content_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for (name, file_hash) in &self.manifest.hashes {
+            hasher.update(name.as_bytes());
+            hasher.update([0]);
+            hasher.update(file_hash.as_bytes());
+            hasher.update([0]);
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    /// Check that the format of the bundle is supported, that its files are
+    /// those listed in its manifest and match their hashes, and that its
+    /// policies, links and schema load
+    ///
+    /// # Errors
+    ///
+    /// On the first check which fails.
+    pub fn verify(&self) -> Result<(), BundleError> {
+        if self.manifest.format_version > FORMAT_VERSION {
+            return Err(BundleError::UnsupportedFormat(self.manifest.format_version));
+        }
+        if let Some(file) = self
+            .files
+            .keys()
+            .find(|file| !self.manifest.hashes.contains_key(*file))
+            .or_else(|| {
+                self.manifest
+                    .hashes
+                    .keys()
+                    .find(|file| !self.files.contains_key(*file))
+            })
+        {
+            return Err(BundleError::Unlisted { file: file.clone() });
+        }
+        for (file, expected) in &self.manifest.hashes {
+            if self
+                .files
+                .get(file)
+                .is_some_and(|text| hash(text.as_bytes()) != *expected)
+            {
+                return Err(BundleError::HashMismatch {
+                    file: file.clone(),
+                    expected: expected.clone(),
+                });
+            }
+        }
+        self.policy_set()?;
+        self.schema()?;
+        Ok(())
+    }
+
+    /// Parse a bundle from JSON, without verifying it
+    ///
+    /// # Errors
+    ///
+    /// If `json` is not a bundle.
+    pub fn from_json_str(json: &str) -> Result<Self, BundleError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// The bundle as JSON
+    ///
+    /// # Errors
+    ///
+    /// If the bundle cannot be serialized.
+    pub fn to_json_string(&self) -> Result<String, BundleError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Load the bundle in the file at `path`, and verify it
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read, is not a bundle, or does not verify.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BundleError> {
+        let json = std::fs::read_to_string(path.as_ref()).map_err(|error| BundleError::Io {
+            path: path.as_ref().to_path_buf(),
+            error,
+        })?;
+        let bundle = Self::from_json_str(&json)?;
+        bundle.verify()?;
+        Ok(bundle)
+    }
+
+    /// Verify the bundle, and save it to the file at `path`
+    ///
+    /// # Errors
+    ///
+    /// If the bundle does not verify, or the file cannot be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), BundleError> {
+        self.verify()?;
+        std::fs::write(path.as_ref(), self.to_json_string()?).map_err(|error| BundleError::Io {
+            path: path.as_ref().to_path_buf(),
+            error,
+        })
+    }
+}
+
+/// The hex SHA-256 hash of `bytes`
+fn hash(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const POLICIES: &str = r#"
+        permit(principal == ?principal, action == Action::"transfer", resource)
+        when { context.amount < 100 };
+        forbid(principal, action, resource) when { context.amount > 1000000 };"#;
+
+    fn bundle() -> PolicyBundle {
+        PolicyBundle::new("guards", "1.4.0", POLICIES)
+            .with_link(BundleLink::new("policy0", "alice-limit").with_arg("?principal", r#"User::"alice""#))
+            .with_schema(
+                r#"entity User;
+                entity Vault;
+                action transfer appliesTo { principal: User, resource: Vault, context: { amount: Long } };"#,
+            )
+            .with_metadata("environment", "production")
+            .at(1_700_000_000_000)
+    }
+
+    #[test]
+    fn bundles_round_trip() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("guards.bundle.json");
+        let bundle = bundle();
+        bundle.save(&path).expect("saves");
+        let loaded = PolicyBundle::load(&path).expect("loads");
+        assert_eq!(loaded, bundle);
+        assert_eq!(loaded.manifest().format_version, FORMAT_VERSION);
+        assert_eq!(
+            loaded.manifest().hashes.keys().collect::<Vec<_>>(),
+            [LINKS_FILE, POLICIES_FILE, SCHEMA_FILE]
+        );
+        let policies = loaded.policy_set().expect("links");
+        assert_eq!(policies.policies().count(), 2);
+        assert!(policies
+            .policy(&PolicyId::from_str("alice-limit").expect("valid id"))
+            .is_some());
+        assert!(loaded.schema().expect("parses").is_some());
+
+        // the content hash ignores the manifest, but not the files
+        assert_eq!(
+            bundle
+                .clone()
+                .at(0)
+                .with_metadata("environment", "staging")
+                .content_hash(),
+            bundle.content_hash()
+        );
+        assert_ne!(
+            bundle.clone().with_links(Vec::new()).content_hash(),
+            bundle.content_hash()
+        );
+    }
+
+    #[test]
+    fn tampered_bundles_fail_to_verify() {
+        let json = bundle().to_json_string().expect("serializes");
+        let tampered = json.replace("< 100", "< 100000");
+        assert!(matches!(
+            PolicyBundle::from_json_str(&tampered).expect("parses").verify(),
+            Err(BundleError::HashMismatch { file, .. }) if file == POLICIES_FILE
+        ));
+
+        let mut value: serde_json::Value = serde_json::from_str(&json).expect("JSON");
+        value["manifest"]["hashes"]
+            .as_object_mut()
+            .expect("an object")
+            .remove(SCHEMA_FILE);
+        assert!(matches!(
+            serde_json::from_value::<PolicyBundle>(value.clone()).expect("parses").verify(),
+            Err(BundleError::Unlisted { file }) if file == SCHEMA_FILE
+        ));
+        value["manifest"]["formatVersion"] = serde_json::json!(FORMAT_VERSION + 1);
+        assert!(matches!(
+            serde_json::from_value::<PolicyBundle>(value)
+                .expect("parses")
+                .verify(),
+            Err(BundleError::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn broken_bundles_fail_to_verify() {
+        assert!(matches!(
+            PolicyBundle::new("guards", "1", "permit(").verify(),
+            Err(BundleError::Policies(_))
+        ));
+        assert!(matches!(
+            PolicyBundle::new("guards", "1", POLICIES)
+                .with_link(BundleLink::new("policy0", "bob-limit").with_arg("?resource", r#"User::"bob""#))
+                .verify(),
+            Err(BundleError::Link { link_id, .. }) if link_id == "bob-limit"
+        ));
+        assert!(matches!(
+            PolicyBundle::new("guards", "1", POLICIES)
+                .with_schema("entity User in [Nope];")
+                .verify(),
+            Err(BundleError::Schema(_))
+        ));
+        let dir = tempfile::tempdir().expect("temp dir");
+        assert!(matches!(
+            PolicyBundle::load(dir.path().join("missing.bundle.json")),
+            Err(BundleError::Io { .. })
+        ));
+    }
+}
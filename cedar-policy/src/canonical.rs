@@ -0,0 +1,444 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Canonical JSON for policy sets, entities and requests, which is the same
+//! on every machine for the same content, so that hashes of it can be
+//! compared, anchored on chain, or signed.
+//!
+//! Canonical JSON has:
+//! * no whitespace, and the keys of every object sorted
+//! * numbers as serde_json writes integers, which is the only kind of number
+//!   Cedar has
+//! * the entities of an `Entities`, and the parents of each, sorted by uid,
+//!   and the elements of sets sorted, without duplicates
+//! * extension values by the value they construct, rather than the string
+//!   they were constructed from, so that `u256("0x10")` and `u256("16")`, or
+//!   `decimal("1.50")` and `decimal("1.5")`, are the same
+//!
+//! Policies are kept in the order of their conditions, and so are the sets
+//! they write, since that is how they were written; only the order of the
+//! policies, templates and links of a policy set does not matter.
+//!
+//! ```
+//! # use cedar_policy::{canonical, Entities};
+//! let json = r#"[{"uid": {"type": "Address", "id": "0xabc"}, "parents": [],
+//!     "attrs": {"balance": {"__extn": {"fn": "u256", "arg": "0x10"}}}}]"#;
+//! let entities = Entities::from_json_str(json, None).unwrap();
+//! assert_eq!(
+//!     canonical::entities_json(&entities).unwrap(),
+//!     r#"[{"attrs":{"balance":{"__extn":{"arg":"16","fn":"u256"}}},"parents":[],"uid":{"id":"0xabc","type":"Address"}}]"#,
+//! );
+//! ```
+
+use cedar_policy_core::ast::{self, BorrowedRestrictedExpr, Name, RestrictedExpr};
+use cedar_policy_core::entities::{JSONValue, TypeAndId};
+use cedar_policy_core::evaluator::RestrictedEvaluator;
+use cedar_policy_core::extensions::Extensions;
+use cedar_policy_core::FromNormalizedStr;
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::{Entities, PolicySet, PolicyToJsonError, Request};
+
+/// Errors converting to canonical JSON
+#[derive(Debug, Error)]
+pub enum CanonicalJsonError {
+    /// A policy or template could not be converted to JSON
+    #[error(transparent)]
+    Policy(#[from] PolicyToJsonError),
+    /// A value could not be converted to JSON, such as an extension value
+    /// constructed from an invalid string
+    #[error("failed to convert a value to JSON: {0}")]
+    Value(String),
+    /// JSON could not be serialized
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+/// `json` with the keys of its objects sorted and no whitespace. The order of
+/// arrays is kept.
+pub fn canonical_json(json: &Value) -> String {
+    match json {
+        Value::Array(elements) => format!(
+            "[{}]",
+            elements
+                .iter()
+                .map(canonical_json)
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            format!(
+                "{{{}}}",
+                entries
+                    .into_iter()
+                    .map(|(key, value)| format!(
+                        "{}:{}",
+                        Value::from(key.as_str()),
+                        canonical_json(value)
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            )
+        }
+        other => other.to_string(),
+    }
+}
+
+/// The canonical JSON of `policies`, in the format of
+/// [`PolicySet::to_json`], with its template links sorted by id
+///
+/// # Errors
+///
+/// If a policy or template cannot be converted to JSON.
+pub fn policy_set_json(policies: &PolicySet) -> Result<String, CanonicalJsonError> {
+    Ok(canonical_json(&policies.to_json()?))
+}
+
+/// The canonical JSON of `entities`, in the format of
+/// [`Entities::write_to_json`]
+///
+/// # Errors
+///
+/// If an attribute or tag cannot be converted to JSON.
+pub fn entities_json(entities: &Entities) -> Result<String, CanonicalJsonError> {
+    let mut entities = entities
+        .iter()
+        .map(|entity| {
+            let ancestors = entities.transitive_ancestors(entity);
+            let entity = &entity.0;
+            let uid = uid_json(entity.uid())?;
+            let key = canonical_json(&uid);
+            let mut json = Map::new();
+            json.insert("uid".into(), uid);
+            json.insert("attrs".into(), record_json(entity.attrs())?);
+            json.insert(
+                "parents".into(),
+                sorted(
+                    ancestors
+                        .map(|parent| uid_json(parent.0.clone()))
+                        .collect::<Result<_, CanonicalJsonError>>()?,
+                ),
+            );
+            if entity.tags().next().is_some() {
+                json.insert("tags".into(), record_json(entity.tags())?);
+            }
+            Ok((key, canonical_json(&Value::Object(json))))
+        })
+        .collect::<Result<Vec<_>, CanonicalJsonError>>()?;
+    entities.sort();
+    Ok(format!(
+        "[{}]",
+        entities
+            .into_iter()
+            .map(|(_, entity)| entity)
+            .collect::<Vec<_>>()
+            .join(",")
+    ))
+}
+
+/// The canonical JSON of `request`: an object of its `principal`, `action`
+/// and `resource`, each `null` if it is unspecified, and its `context`,
+/// `null` if it is unknown
+///
+/// # Errors
+///
+/// If a value of the context cannot be converted to JSON.
+pub fn request_json(request: &Request) -> Result<String, CanonicalJsonError> {
+    let uid =
+        |uid: Option<&crate::EntityUid>| uid.map_or(Ok(Value::Null), |uid| uid_json(uid.0.clone()));
+    let mut json = Map::new();
+    json.insert("principal".into(), uid(request.principal())?);
+    json.insert("action".into(), uid(request.action())?);
+    json.insert("resource".into(), uid(request.resource())?);
+    json.insert(
+        "context".into(),
+        match request.0.context() {
+            Some(context) => record_json(context.iter())?,
+            None => Value::Null,
+        },
+    );
+    Ok(canonical_json(&Value::Object(json)))
+}
+
+/// The `{"type": ..., "id": ...}` object of `uid`
+fn uid_json(uid: ast::EntityUID) -> Result<Value, CanonicalJsonError> {
+    Ok(serde_json::to_value(TypeAndId::from(uid))?)
+}
+
+/// The JSON object of the values of a record, entity or context
+fn record_json<'a>(
+    values: impl Iterator<Item = (&'a str, BorrowedRestrictedExpr<'a>)>,
+) -> Result<Value, CanonicalJsonError> {
+    Ok(Value::Object(
+        values
+            .map(|(key, value)| Ok((key.to_string(), value_json(value)?)))
+            .collect::<Result<_, CanonicalJsonError>>()?,
+    ))
+}
+
+/// The canonical JSON of the value `expr`
+fn value_json(expr: BorrowedRestrictedExpr<'_>) -> Result<Value, CanonicalJsonError> {
+    let json =
+        JSONValue::from_expr(expr).map_err(|error| CanonicalJsonError::Value(error.to_string()))?;
+    normalize(serde_json::to_value(json)?)
+}
+
+/// `json`, a value in the JSON format of entities, with its sets sorted and
+/// its extension values normalized
+fn normalize(json: Value) -> Result<Value, CanonicalJsonError> {
+    match json {
+        // every array of a value is a set
+        Value::Array(elements) => Ok(sorted(
+            elements
+                .into_iter()
+                .map(normalize)
+                .collect::<Result<_, _>>()?,
+        )),
+        Value::Object(mut map) => match map.remove("__extn") {
+            Some(Value::Object(call)) if map.is_empty() => normalize_extension(call),
+            Some(other) => {
+                map.insert("__extn".into(), other);
+                normalize_object(map)
+            }
+            None => normalize_object(map),
+        },
+        other => Ok(other),
+    }
+}
+
+fn normalize_object(map: Map<String, Value>) -> Result<Value, CanonicalJsonError> {
+    Ok(Value::Object(
+        map.into_iter()
+            .map(|(key, value)| Ok((key, normalize(value)?)))
+            .collect::<Result<_, CanonicalJsonError>>()?,
+    ))
+}
+
+/// The `__extn` escape of the value the call `{"fn": ..., "arg": ...}`
+/// constructs, with the argument it is displayed as
+fn normalize_extension(mut call: Map<String, Value>) -> Result<Value, CanonicalJsonError> {
+    let (Some(Value::String(fn_name)), Some(Value::String(arg))) =
+        (call.remove("fn"), call.remove("arg"))
+    else {
+        return Err(CanonicalJsonError::Value(
+            "extension values must be constructed from a string".into(),
+        ));
+    };
+    let name = Name::from_normalized_str(&fn_name)
+        .map_err(|_| CanonicalJsonError::Value(format!("`{fn_name}` is not a function name")))?;
+    let extensions = Extensions::all_available();
+    let value = RestrictedEvaluator::new(&extensions)
+        .interpret(
+            RestrictedExpr::call_extension_fn(name, vec![RestrictedExpr::val(arg.as_str())])
+                .as_borrowed(),
+        )
+        .map_err(|error| CanonicalJsonError::Value(error.to_string()))?;
+    // decimals are displayed with the digits after their point as a number,
+    // so `1.05` as `1.5`, and are normalized from their string instead
+    let arg = if fn_name == "decimal" {
+        normalize_decimal(&arg)
+    } else {
+        value.to_string()
+    };
+    Ok(serde_json::json!({ "__extn": { "fn": fn_name, "arg": arg } }))
+}
+
+/// The valid decimal `arg` without leading or trailing zeros, or a negative
+/// zero
+fn normalize_decimal(arg: &str) -> String {
+    let (negative, digits) = arg
+        .strip_prefix('-')
+        .map_or((false, arg), |digits| (true, digits));
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let integer = match integer.trim_start_matches('0') {
+        "" => "0",
+        integer => integer,
+    };
+    let fraction = match fraction.trim_end_matches('0') {
+        "" => "0",
+        fraction => fraction,
+    };
+    let sign = if negative && (integer, fraction) != ("0", "0") {
+        "-"
+    } else {
+        ""
+    };
+    format!("{sign}{integer}.{fraction}")
+}
+
+/// `elements` sorted by their canonical JSON, without duplicates
+fn sorted(elements: Vec<Value>) -> Value {
+    let mut elements: Vec<(String, Value)> = elements
+        .into_iter()
+        .map(|element| (canonical_json(&element), element))
+        .collect();
+    elements.sort_by(|(a, _), (b, _)| a.cmp(b));
+    elements.dedup_by(|(a, _), (b, _)| a == b);
+    Value::Array(elements.into_iter().map(|(_, element)| element).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Context, EntitiesOptions, EntityUid, RestrictedExpression, TcComputation};
+    use std::str::FromStr;
+
+    fn entities(json: &str) -> Entities {
+        Entities::from_json_str(json, None).expect("entities parse")
+    }
+
+    #[test]
+    fn sorts_keys_and_keeps_arrays() {
+        let json = serde_json::json!({"b": [2, 1], "a": {"d": null, "c": "x"}});
+        assert_eq!(
+            canonical_json(&json),
+            r#"{"a":{"c":"x","d":null},"b":[2,1]}"#
+        );
+    }
+
+    #[test]
+    fn entities_do_not_depend_on_order() {
+        let first = entities(
+            r#"[
+                {"uid": {"type": "User", "id": "b"}, "parents": [{"type": "Group", "id": "y"}, {"type": "Group", "id": "x"}], "attrs": {"tags": [3, 1, 2, 1]}},
+                {"uid": {"type": "User", "id": "a"}, "parents": [], "attrs": {}}
+            ]"#,
+        );
+        let second = entities(
+            r#"[
+                {"uid": {"type": "User", "id": "a"}, "parents": [], "attrs": {}},
+                {"uid": {"type": "User", "id": "b"}, "parents": [{"type": "Group", "id": "x"}, {"type": "Group", "id": "y"}], "attrs": {"tags": [1, 2, 3]}}
+            ]"#,
+        );
+        let json = entities_json(&first).expect("converts");
+        assert_eq!(json, entities_json(&second).expect("converts"));
+        assert_eq!(
+            json,
+            r#"[{"attrs":{},"parents":[],"uid":{"id":"a","type":"User"}},{"attrs":{"tags":[1,2,3]},"parents":[{"id":"x","type":"Group"},{"id":"y","type":"Group"}],"uid":{"id":"b","type":"User"}}]"#
+        );
+    }
+
+    #[test]
+    fn entities_do_not_depend_on_the_tc_computation() {
+        let json = r#"[
+            {"uid": {"type": "User", "id": "a"}, "parents": [{"type": "Group", "id": "x"}], "attrs": {}},
+            {"uid": {"type": "Group", "id": "x"}, "parents": [{"type": "Group", "id": "y"}], "attrs": {}}
+        ]"#;
+        let lazy = Entities::from_json_str_with_options(
+            json,
+            None,
+            EntitiesOptions::new().with_tc_computation(TcComputation::ComputeLazily),
+        )
+        .expect("entities parse");
+        assert_eq!(
+            entities_json(&lazy).expect("converts"),
+            entities_json(&entities(json)).expect("converts")
+        );
+    }
+
+    #[test]
+    fn normalizes_extension_values() {
+        let value = |ext: &str| {
+            let json = format!(
+                r#"[{{"uid": {{"type": "A", "id": "a"}}, "parents": [], "attrs": {{"v": {ext}}}}}]"#
+            );
+            entities_json(&entities(&json)).expect("converts")
+        };
+        let extn = |f: &str, arg: &str| format!(r#"{{"__extn": {{"fn": "{f}", "arg": "{arg}"}}}}"#);
+        assert_eq!(value(&extn("u256", "0x10")), value(&extn("u256", "16")));
+        assert_eq!(
+            value(&extn("decimal", "01.50")),
+            value(&extn("decimal", "1.5"))
+        );
+        assert_ne!(
+            value(&extn("decimal", "1.05")),
+            value(&extn("decimal", "1.5"))
+        );
+        assert_eq!(
+            value(&extn(
+                "address",
+                "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"
+            )),
+            value(&extn(
+                "address",
+                "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"
+            ))
+        );
+        assert_eq!(
+            value(&extn("bytes", "0xABCD")),
+            value(&extn("bytes", "0xabcd"))
+        );
+        assert!(value(&extn("u256", "0x10")).contains(r#""arg":"16""#));
+    }
+
+    #[test]
+    fn normalizes_decimals() {
+        assert_eq!(normalize_decimal("001.2300"), "1.23");
+        assert_eq!(normalize_decimal("-0.0"), "0.0");
+        assert_eq!(normalize_decimal("-12.0100"), "-12.01");
+        assert_eq!(normalize_decimal("0.0001"), "0.0001");
+    }
+
+    #[test]
+    fn requests_do_not_depend_on_the_order_of_their_context() {
+        let request = |pairs: Vec<(&str, &str)>| {
+            let context = Context::from_pairs(pairs.into_iter().map(|(key, value)| {
+                (
+                    key.to_string(),
+                    RestrictedExpression::from_str(value).expect("valid value"),
+                )
+            }));
+            Request::new(
+                Some(EntityUid::from_str(r#"User::"alice""#).expect("valid uid")),
+                None,
+                None,
+                context,
+            )
+        };
+        let first = request_json(&request(vec![
+            ("amount", r#"u256("0x10")"#),
+            ("chain", "1"),
+        ]))
+        .expect("converts");
+        let second = request_json(&request(vec![("chain", "1"), ("amount", r#"u256("16")"#)]))
+            .expect("converts");
+        assert_eq!(first, second);
+        assert_eq!(
+            first,
+            r#"{"action":null,"context":{"amount":{"__extn":{"arg":"16","fn":"u256"}},"chain":1},"principal":{"id":"alice","type":"User"},"resource":null}"#
+        );
+    }
+
+    #[test]
+    fn policy_sets_do_not_depend_on_order() {
+        let first = PolicySet::from_str(
+            r#"permit(principal, action, resource);
+            forbid(principal, action, resource) when { context.risk > 5 };"#,
+        )
+        .expect("policies parse");
+        let mut second = PolicySet::new();
+        for policy in first.policies().collect::<Vec<_>>().into_iter().rev() {
+            second.add(policy.clone()).expect("adds");
+        }
+        assert_eq!(
+            policy_set_json(&first).expect("converts"),
+            policy_set_json(&second).expect("converts")
+        );
+    }
+}
@@ -0,0 +1,531 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Evaluation capsules, for reproducing decisions.
+//!
+//! [`Capsule::capture`] authorizes a request while recording everything the
+//! evaluation consulted: the entities it looked up, with the attributes, tags
+//! and ancestors it read of each, and the extension functions it called, with
+//! their arguments and results. A [`Capsule`] holds these along with the
+//! request, the policy set, the decision and when it was made, and serializes
+//! to self-contained JSON.
+//!
+//! [`Capsule::replay`] authorizes the request again against the entities and
+//! policies of the capsule, answering the calls of extension functions which
+//! are not constructors, such as RPC-backed ones, with their recorded results.
+//! The decision can so be reproduced later, e.g., to resolve a dispute,
+//! without the entity store or the providers it was made with. Replays use an
+//! `Authorizer` with the settings recorded in the capsule, such as
+//! [`Authorizer::with_missing_attributes`] and
+//! [`Authorizer::with_extension_failures`].
+//!
+//! ```
+//! # use cedar_policy::{capsule::Capsule, Authorizer, Context, Entities, PolicySet, Request};
+//! let policies: PolicySet = r#"
+//!     permit(principal, action == Action::"withdraw", resource)
+//!     when { principal.verified && decimal("1.5").lessThan(context.limit) };"#
+//!     .parse()
+//!     .unwrap();
+//! let entities = Entities::from_json_str(
+//!     r#"[{"uid": {"type": "User", "id": "alice"},
+//!          "attrs": {"verified": true, "email": "alice@example.com"},
+//!          "parents": []}]"#,
+//!     None,
+//! )
+//! .unwrap();
+//! let request = Request::new(
+//!     Some(r#"User::"alice""#.parse().unwrap()),
+//!     Some(r#"Action::"withdraw""#.parse().unwrap()),
+//!     Some(r#"Vault::"main""#.parse().unwrap()),
+//!     Context::from_json_str(r#"{"limit": {"__extn": {"fn": "decimal", "arg": "2.0"}}}"#, None)
+//!         .unwrap(),
+//! );
+//! let (response, capsule) =
+//!     Capsule::capture(&Authorizer::new(), &request, &policies, &entities).unwrap();
+//! assert_eq!(capsule.decision, response.decision());
+//!
+//! let capsule = Capsule::from_json_str(&capsule.to_json_string().unwrap()).unwrap();
+//! let replay = capsule.replay().unwrap();
+//! assert!(replay.reproduced);
+//! ```
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use cedar_policy_core::ast::{self, ExtensionOutputValue};
+use cedar_policy_core::entities::{self as core_entities, Dereference, TCComputation};
+use cedar_policy_core::evaluator::{Consulted, EvaluationRecord, RestrictedEvaluator};
+use cedar_policy_core::extensions::Extensions;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    canonical::{self, CanonicalJsonError},
+    Authorizer, Context, ContextJsonError, Decision, Entities, EntitiesError, EntityUid,
+    EvaluationError, ExtensionFailureMode, MissingAttributeMode, PolicySet, PolicySetFromJsonError,
+    PolicyToJsonError, Request, Response,
+};
+
+/// Errors capturing, reading and replaying capsules
+#[derive(Debug, Error)]
+pub enum CapsuleError {
+    /// The request could not be converted to JSON
+    #[error("failed to convert the request to JSON: {0}")]
+    Request(#[from] CanonicalJsonError),
+    /// The policy set could not be converted to JSON
+    #[error(transparent)]
+    Policies(#[from] PolicyToJsonError),
+    /// The entities consulted could not be converted to or from JSON
+    #[error(transparent)]
+    Entities(#[from] EntitiesError),
+    /// The capsule is not valid JSON
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// The request of the capsule is invalid
+    #[error("invalid request in the capsule: {0}")]
+    InvalidRequest(String),
+    /// The context of the request of the capsule is invalid
+    #[error(transparent)]
+    InvalidContext(#[from] ContextJsonError),
+    /// The policy set of the capsule is invalid
+    #[error(transparent)]
+    InvalidPolicies(#[from] PolicySetFromJsonError),
+}
+
+/// A call of an extension function recorded in a capsule
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedCall {
+    /// The name of the function
+    pub function: String,
+    /// The arguments of the call, as Cedar expressions
+    pub args: Vec<String>,
+    /// The value returned, as a Cedar expression, if the call succeeded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+    /// The message of the error, if the call failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Everything consulted to authorize one request, and the decision reached
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Capsule {
+    /// The version of the format of the capsule, currently 1
+    pub version: u32,
+    /// When the decision was made, in milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    /// The request, in the canonical JSON of [`canonical::request_json`]
+    pub request: serde_json::Value,
+    /// The policy set, in the JSON of [`PolicySet::to_json`]
+    pub policies: serde_json::Value,
+    /// The entities consulted, in the entities JSON format, with only the
+    /// attributes and tags read, and with their ancestors only if they were
+    /// read
+    pub entities: serde_json::Value,
+    /// The calls of extension functions, in the order they were made
+    pub extension_calls: Vec<RecordedCall>,
+    /// How the authorizer evaluated accesses of missing attributes
+    #[serde(default)]
+    pub missing_attributes: MissingAttributeMode,
+    /// What the authorizer did with policies whose extension functions
+    /// failed
+    #[serde(default)]
+    pub extension_failures: ExtensionFailureMode,
+    /// The decision
+    pub decision: Decision,
+    /// The ids of the policies which determined the decision, sorted
+    pub reason: Vec<String>,
+    /// The errors encountered while making the decision
+    pub errors: Vec<String>,
+}
+
+/// The result of replaying a capsule
+#[derive(Debug, Clone)]
+pub struct Replay {
+    /// The response to the request of the capsule
+    pub response: Response,
+    /// Whether the decision and the policies which determined it are those
+    /// of the capsule
+    pub reproduced: bool,
+}
+
+/// The ids of the policies which determined `response`, sorted
+fn reason(response: &Response) -> Vec<String> {
+    let mut reason: Vec<String> = response
+        .diagnostics()
+        .reason()
+        .map(ToString::to_string)
+        .collect();
+    reason.sort();
+    reason
+}
+
+impl Capsule {
+    /// Authorize `request` against `policies` and `entities` with
+    /// `authorizer`, capturing what was consulted
+    ///
+    /// # Errors
+    ///
+    /// If the request, policies or entities consulted cannot be converted to
+    /// JSON.
+    pub fn capture(
+        authorizer: &Authorizer,
+        request: &Request,
+        policies: &PolicySet,
+        entities: &Entities,
+    ) -> Result<(Response, Self), CapsuleError> {
+        let record = EvaluationRecord::new();
+        let response = authorizer.is_authorized_recording(request, policies, entities, &record);
+        let consulted = record.consulted();
+        let capsule = Self {
+            version: 1,
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| {
+                    u64::try_from(since.as_millis()).unwrap_or(u64::MAX)
+                }),
+            request: serde_json::from_str(&canonical::request_json(request)?)?,
+            policies: policies.to_json()?,
+            entities: consulted_entities(entities, &consulted)?.to_json_value()?,
+            extension_calls: consulted
+                .extension_calls
+                .into_iter()
+                .filter_map(|call| {
+                    let (result, error) = match call.result? {
+                        Ok(value) => (Some(ast::Expr::from(value).to_string()), None),
+                        Err(error) => (None, Some(error)),
+                    };
+                    Some(RecordedCall {
+                        function: call.function.to_string(),
+                        args: call
+                            .args
+                            .into_iter()
+                            .map(|arg| ast::Expr::from(arg).to_string())
+                            .collect(),
+                        result,
+                        error,
+                    })
+                })
+                .collect(),
+            missing_attributes: authorizer.missing_attributes(),
+            extension_failures: authorizer.extension_failures(),
+            decision: response.decision(),
+            reason: reason(&response),
+            errors: response
+                .diagnostics()
+                .errors()
+                .map(ToString::to_string)
+                .collect(),
+        };
+        Ok((response, capsule))
+    }
+
+    /// Read a capsule from its JSON
+    ///
+    /// # Errors
+    ///
+    /// If `json` is not the JSON of a capsule.
+    pub fn from_json_str(json: &str) -> Result<Self, CapsuleError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// The JSON of this capsule
+    ///
+    /// # Errors
+    ///
+    /// If the capsule cannot be serialized.
+    pub fn to_json_string(&self) -> Result<String, CapsuleError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Authorize the request of this capsule again, against its policies and
+    /// entities, with the settings of the authorizer it was captured with,
+    /// answering calls of extension functions which are not constructors with
+    /// their recorded results
+    ///
+    /// # Errors
+    ///
+    /// If the request, policies or entities of the capsule are invalid.
+    pub fn replay(&self) -> Result<Replay, CapsuleError> {
+        let request = self.request()?;
+        let policies = PolicySet::from_json_value(self.policies.clone())?;
+        let entities = Entities::from_json_value(self.entities.clone(), None)?;
+        let authorizer = Authorizer::with_extension_overrides(self.overrides())
+            .with_missing_attributes(self.missing_attributes)
+            .with_extension_failures(self.extension_failures);
+        let response = authorizer.is_authorized(&request, &policies, &entities);
+        Ok(Replay {
+            reproduced: response.decision() == self.decision && reason(&response) == self.reason,
+            response,
+        })
+    }
+
+    /// The request of this capsule
+    fn request(&self) -> Result<Request, CapsuleError> {
+        let uid = |component: &str| match self.request.get(component) {
+            None | Some(serde_json::Value::Null) => Ok(None),
+            Some(uid) => EntityUid::from_json(uid.clone())
+                .map(Some)
+                .map_err(|e| CapsuleError::InvalidRequest(format!("invalid {component}: {e}"))),
+        };
+        let context = match self.request.get("context") {
+            None | Some(serde_json::Value::Null) => Context::empty(),
+            Some(context) => Context::from_json_value(context.clone(), None)?,
+        };
+        Ok(Request::new(
+            uid("principal")?,
+            uid("action")?,
+            uid("resource")?,
+            context,
+        ))
+    }
+
+    /// The extension functions which answer the recorded calls of functions
+    /// which are not constructors
+    fn overrides(&self) -> Arc<[ast::Extension]> {
+        let mut calls: BTreeMap<&str, HashMap<Vec<String>, Result<String, String>>> =
+            BTreeMap::new();
+        for call in &self.extension_calls {
+            let result = match (&call.result, &call.error) {
+                (Some(result), _) => Ok(result.clone()),
+                (None, error) => Err(error.clone().unwrap_or_default()),
+            };
+            calls
+                .entry(&call.function)
+                .or_default()
+                .insert(call.args.clone(), result);
+        }
+        let extensions = Extensions::all_available();
+        let functions = calls.into_iter().filter_map(|(function, calls)| {
+            let name = ast::Name::from_str(function).ok()?;
+            let original = extensions.func(&name).ok()?;
+            if original.is_constructor() {
+                return None;
+            }
+            Some(original.with_func(Box::new(move |args: &[ast::Value]| {
+                let args: Vec<String> = args
+                    .iter()
+                    .map(|arg| ast::Expr::from(arg.clone()).to_string())
+                    .collect();
+                let failed = |msg: String| {
+                    EvaluationError::failed_extension_function_application(name.clone(), msg)
+                };
+                match calls.get(&args) {
+                    Some(Ok(result)) => {
+                        let expr = ast::RestrictedExpr::from_str(result)
+                            .map_err(|e| failed(format!("invalid recorded result: {e}")))?;
+                        let value = RestrictedEvaluator::new(&Extensions::all_available())
+                            .interpret(expr.as_borrowed())?;
+                        Ok(ExtensionOutputValue::Concrete(value))
+                    }
+                    Some(Err(error)) => Err(failed(error.clone())),
+                    None => Err(failed("the call is not recorded in the capsule".into())),
+                }
+            })))
+        });
+        // PANIC SAFETY: `capsule` is a valid name
+        #[allow(clippy::expect_used)]
+        let name = ast::Name::from_str("capsule").expect("valid name");
+        Arc::new([ast::Extension::new(name, functions)])
+    }
+}
+
+/// The entities of `entities` which were looked up, with only what was read
+/// of them
+fn consulted_entities(
+    entities: &Entities,
+    consulted: &Consulted,
+) -> Result<core_entities::Entities, EntitiesError> {
+    let slice = consulted.entities.iter().filter_map(|(uid, access)| {
+        let Dereference::Data(entity) = entities.0.entity(uid) else {
+            return None;
+        };
+        let ancestors = if access.ancestors {
+            entities.0.ancestors_of(entity).cloned().collect()
+        } else {
+            HashSet::new()
+        };
+        Some(ast::Entity::new_with_tags(
+            uid.clone(),
+            access
+                .attributes
+                .iter()
+                .filter_map(|attr| Some((attr.clone(), entity.get(attr)?.clone())))
+                .collect(),
+            ancestors,
+            access
+                .tags
+                .iter()
+                .filter_map(|tag| Some((tag.clone(), entity.get_tag(tag)?.clone())))
+                .collect(),
+        ))
+    });
+    core_entities::Entities::from_entities(slice, TCComputation::AssumeAlreadyComputed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn captures_only_what_was_read() {
+        let policies: PolicySet = r#"
+            permit(principal in Group::"admins", action, resource)
+            when { resource.owner == principal && resource has label };"#
+            .parse()
+            .unwrap();
+        let entities = Entities::from_json_str(
+            r#"[
+                {"uid": {"type": "User", "id": "alice"}, "attrs": {"email": "a@example.com"},
+                 "parents": [{"type": "Group", "id": "admins"}]},
+                {"uid": {"type": "Group", "id": "admins"}, "attrs": {}, "parents": []},
+                {"uid": {"type": "Doc", "id": "d"},
+                 "attrs": {"owner": {"__entity": {"type": "User", "id": "alice"}}, "size": 3},
+                 "parents": []},
+                {"uid": {"type": "Doc", "id": "unrelated"}, "attrs": {}, "parents": []}
+            ]"#,
+            None,
+        )
+        .unwrap();
+        let request = Request::new(
+            Some(r#"User::"alice""#.parse().unwrap()),
+            Some(r#"Action::"read""#.parse().unwrap()),
+            Some(r#"Doc::"d""#.parse().unwrap()),
+            Context::empty(),
+        );
+        let (response, capsule) =
+            Capsule::capture(&Authorizer::new(), &request, &policies, &entities).unwrap();
+        assert_eq!(response.decision(), Decision::Deny);
+        assert_eq!(capsule.decision, Decision::Deny);
+
+        let captured = Entities::from_json_value(capsule.entities.clone(), None).unwrap();
+        let uids: Vec<String> = {
+            let mut uids: Vec<String> = captured.iter().map(|e| e.uid().to_string()).collect();
+            uids.sort();
+            uids
+        };
+        assert_eq!(uids, vec![r#"Doc::"d""#, r#"User::"alice""#]);
+        let doc = captured.get(&r#"Doc::"d""#.parse().unwrap()).unwrap();
+        assert!(doc.attr("owner").is_some());
+        assert!(doc.attr("size").is_none());
+        let alice = captured.get(&r#"User::"alice""#.parse().unwrap()).unwrap();
+        assert!(alice.attr("email").is_none());
+        let ancestors: Vec<String> = captured
+            .ancestors(&r#"User::"alice""#.parse().unwrap())
+            .unwrap()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(ancestors, vec![r#"Group::"admins""#]);
+
+        let replay = capsule.replay().unwrap();
+        assert!(replay.reproduced);
+        assert_eq!(replay.response.decision(), Decision::Deny);
+    }
+
+    #[test]
+    fn replays_with_the_settings_of_the_authorizer() {
+        let policies: PolicySet = r#"
+            forbid(principal, action, resource) when { principal.frozen };
+            permit(principal, action, resource);"#
+            .parse()
+            .unwrap();
+        let entities = Entities::from_json_str(
+            r#"[{"uid": {"type": "Wallet", "id": "0xabc"}, "attrs": {}, "parents": []}]"#,
+            None,
+        )
+        .unwrap();
+        let request = Request::new(
+            Some(r#"Wallet::"0xabc""#.parse().unwrap()),
+            Some(r#"Action::"transfer""#.parse().unwrap()),
+            Some(r#"Token::"usdc""#.parse().unwrap()),
+            Context::empty(),
+        );
+        let authorizer = Authorizer::new()
+            .with_missing_attributes(MissingAttributeMode::False)
+            .with_extension_failures(ExtensionFailureMode::FailClosed);
+        let (response, capsule) =
+            Capsule::capture(&authorizer, &request, &policies, &entities).unwrap();
+        assert_eq!(response.decision(), Decision::Allow);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&capsule.to_json_string().unwrap()).unwrap();
+        assert_eq!(json.get("missingAttributes"), Some(&"false".into()));
+        assert_eq!(json.get("extensionFailures"), Some(&"failClosed".into()));
+        let capsule = Capsule::from_json_str(&json.to_string()).unwrap();
+        assert_eq!(capsule.missing_attributes, MissingAttributeMode::False);
+        assert_eq!(capsule.extension_failures, ExtensionFailureMode::FailClosed);
+        let replay = capsule.replay().unwrap();
+        assert!(replay.reproduced);
+        assert_eq!(replay.response.diagnostics().errors().count(), 0);
+
+        // capsules without the settings were captured with the defaults, with
+        // which the access of the missing attribute is an error
+        let mut json = json;
+        if let Some(json) = json.as_object_mut() {
+            json.remove("missingAttributes");
+            json.remove("extensionFailures");
+        }
+        let capsule = Capsule::from_json_str(&json.to_string()).unwrap();
+        assert_eq!(capsule.missing_attributes, MissingAttributeMode::Error);
+        let replay = capsule.replay().unwrap();
+        assert_eq!(replay.response.diagnostics().errors().count(), 1);
+    }
+
+    #[test]
+    fn replays_recorded_extension_calls() {
+        let policies: PolicySet = r#"
+            permit(principal, action, resource)
+            when { context.amount.lessThan(decimal("10.0")) };"#
+            .parse()
+            .unwrap();
+        let request = Request::new(
+            None,
+            None,
+            None,
+            Context::from_json_str(
+                r#"{"amount": {"__extn": {"fn": "decimal", "arg": "2.5"}}}"#,
+                None,
+            )
+            .unwrap(),
+        );
+        let (_, mut capsule) =
+            Capsule::capture(&Authorizer::new(), &request, &policies, &Entities::empty()).unwrap();
+        assert_eq!(capsule.decision, Decision::Allow);
+        let call = capsule
+            .extension_calls
+            .iter()
+            .find(|call| call.function == "lessThan")
+            .unwrap();
+        assert_eq!(call.args, vec![r#"decimal("2.5")"#, r#"decimal("10.0")"#]);
+        assert_eq!(call.result.as_deref(), Some("true"));
+        assert!(capsule.replay().unwrap().reproduced);
+
+        // the replay answers with the recorded result, not by calling the
+        // function
+        for call in &mut capsule.extension_calls {
+            if call.function == "lessThan" {
+                call.result = Some("false".into());
+            }
+        }
+        let replay = capsule.replay().unwrap();
+        assert!(!replay.reproduced);
+        assert_eq!(replay.response.decision(), Decision::Deny);
+    }
+}
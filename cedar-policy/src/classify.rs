@@ -0,0 +1,986 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Categorizing transactions, so that coarse policies such as "forbid all
+//! bridging from this account" need not enumerate the contracts and functions
+//! of every protocol.
+//!
+//! A [`Classifier`] maps a transaction, from its target, calldata and the
+//! balance changes of its simulation, into a [`Category`], which requests
+//! carry as `context.category`:
+//! ```cedar
+//! forbid(principal == Account::"eip155:1:0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed", action, resource)
+//! when { context.category == "bridge" };
+//! ```
+//!
+//! A transaction is categorized by the first of these which applies:
+//! 1. without a target, it is a [`Category::ContractDeployment`]
+//! 2. a target registered with [`Classifier::with_contract`], such as the
+//!    router of a DEX, gives its category
+//! 3. the selector of the function called, which is registered with
+//!    [`Classifier::with_selector`] or one of the well-known functions of
+//!    tokens, DEX routers and bridges, gives its category
+//! 4. without calldata, it is a [`Category::NativeTransfer`]
+//! 5. a simulation in which the sender's balance of one token decreased and
+//!    that of another increased is a [`Category::Swap`]
+//! 6. otherwise, it is [`Category::Unknown`]
+//!
+//! Calls of the well-known functions of the canonical OP Stack and Arbitrum
+//! bridges, LayerZero OFTs and CCTP are also decoded by
+//! [`Classifier::bridge_call`], and [`Classifier::context`] adds where they
+//! bridge to into the context, so that treasury policies can restrict which
+//! chains funds may leave to:
+//! ```cedar
+//! forbid(principal, action, resource)
+//! when { context.category == "bridge" }
+//! unless { context has destinationChain && [10, 8453].contains(context.destinationChain) };
+//! ```
+//!
+//! ```
+//! # use cedar_policy::classify::*;
+//! # use cedar_policy::RecordBuilder;
+//! let usdc = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48".parse().unwrap();
+//! // transfer(address,uint256)
+//! let calldata = ethers::utils::hex::decode(
+//!     "a9059cbb000000000000000000000000ab5801a7d398351b8be11c439e05c5b3259aec9b\
+//!      00000000000000000000000000000000000000000000000000000000000f4240",
+//! )
+//! .unwrap();
+//! let category = Classifier::new().classify(Some(usdc), &calldata, &[]);
+//! assert_eq!(category, Category::TokenTransfer);
+//! let context = RecordBuilder::new()
+//!     .attr(CATEGORY_ATTRIBUTE, category)
+//!     .build_context();
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use ethers::types::{Address, U256};
+use ethers::utils::id;
+use thiserror::Error;
+
+use crate::{RecordBuilder, RestrictedExpression};
+
+/// The attribute of the context holding the category of a transaction
+pub const CATEGORY_ATTRIBUTE: &str = "category";
+/// The attribute of the context holding the [`BridgeProtocol`] of a bridge
+/// call
+pub const BRIDGE_PROTOCOL_ATTRIBUTE: &str = "bridgeProtocol";
+/// The attribute of the context holding the id of the chain a bridge call
+/// bridges to
+pub const DESTINATION_CHAIN_ATTRIBUTE: &str = "destinationChain";
+/// The attribute of the context holding the address receiving the tokens of a
+/// bridge call on the destination chain
+pub const BRIDGE_RECIPIENT_ATTRIBUTE: &str = "bridgeRecipient";
+
+/// The category of a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Category {
+    /// A transfer of the native token of the chain, such as ether
+    NativeTransfer,
+    /// A transfer of an ERC-20, ERC-721 or ERC-1155 token
+    TokenTransfer,
+    /// An approval of a spender of tokens, including `permit` and
+    /// `setApprovalForAll`
+    Approval,
+    /// A swap of one token for another
+    Swap,
+    /// A transfer of tokens to another chain
+    Bridge,
+    /// The deployment of a contract
+    ContractDeployment,
+    /// Anything else
+    Unknown,
+}
+
+impl Category {
+    /// All the categories
+    pub const ALL: [Self; 7] = [
+        Self::NativeTransfer,
+        Self::TokenTransfer,
+        Self::Approval,
+        Self::Swap,
+        Self::Bridge,
+        Self::ContractDeployment,
+        Self::Unknown,
+    ];
+
+    /// The name of this category, as held in `context.category`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::NativeTransfer => "native_transfer",
+            Self::TokenTransfer => "token_transfer",
+            Self::Approval => "approval",
+            Self::Swap => "swap",
+            Self::Bridge => "bridge",
+            Self::ContractDeployment => "contract_deployment",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+impl Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The name is not that of a [`Category`]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("`{0}` is not a transaction category")]
+pub struct UnknownCategory(String);
+
+impl FromStr for Category {
+    type Err = UnknownCategory;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|category| category.as_str() == s)
+            .ok_or_else(|| UnknownCategory(s.to_string()))
+    }
+}
+
+impl From<Category> for RestrictedExpression {
+    fn from(category: Category) -> Self {
+        Self::new_string(category.as_str().to_string())
+    }
+}
+
+/// How much of a token the sender of a transaction received and sent in its
+/// simulation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BalanceChange {
+    /// The address of the token, or any placeholder for the native token
+    pub token: Address,
+    /// The amount received
+    pub received: U256,
+    /// The amount sent
+    pub sent: U256,
+}
+
+/// The signatures of the well-known functions of each category
+const KNOWN_FUNCTIONS: &[(Category, &[&str])] = &[
+    (
+        Category::TokenTransfer,
+        &[
+            "transfer(address,uint256)",
+            "transferFrom(address,address,uint256)",
+            "safeTransferFrom(address,address,uint256)",
+            "safeTransferFrom(address,address,uint256,bytes)",
+            "safeTransferFrom(address,address,uint256,uint256,bytes)",
+            "safeBatchTransferFrom(address,address,uint256[],uint256[],bytes)",
+        ],
+    ),
+    (
+        Category::Approval,
+        &[
+            "approve(address,uint256)",
+            "increaseAllowance(address,uint256)",
+            "setApprovalForAll(address,bool)",
+            "permit(address,address,uint256,uint256,uint8,bytes32,bytes32)",
+        ],
+    ),
+    (
+        Category::Swap,
+        &[
+            // Uniswap V2 and its forks
+            "swapExactTokensForTokens(uint256,uint256,address[],address,uint256)",
+            "swapTokensForExactTokens(uint256,uint256,address[],address,uint256)",
+            "swapExactETHForTokens(uint256,address[],address,uint256)",
+            "swapETHForExactTokens(uint256,address[],address,uint256)",
+            "swapExactTokensForETH(uint256,uint256,address[],address,uint256)",
+            "swapTokensForExactETH(uint256,uint256,address[],address,uint256)",
+            // Uniswap V3
+            "exactInputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+            "exactInput((bytes,address,uint256,uint256,uint256))",
+            "exactOutputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+            "exactOutput((bytes,address,uint256,uint256,uint256))",
+        ],
+    ),
+];
+
+/// The selector of the function with `signature`
+fn selector(signature: &str) -> [u8; 4] {
+    id(signature)
+}
+
+/// The bridging protocol of a [`BridgeCall`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BridgeProtocol {
+    /// The standard bridge and portal of an OP Stack chain, such as Optimism
+    /// or Base
+    OpStack,
+    /// The inbox and gateway router of an Arbitrum chain
+    Arbitrum,
+    /// An omnichain fungible token (OFT) of the Layer Zero protocol
+    LayerZero,
+    /// Circle's Cross-Chain Transfer Protocol (CCTP)
+    Cctp,
+}
+
+impl BridgeProtocol {
+    /// The name of this protocol, as held in `context.bridgeProtocol`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::OpStack => "op_stack",
+            Self::Arbitrum => "arbitrum",
+            Self::LayerZero => "layer_zero",
+            Self::Cctp => "cctp",
+        }
+    }
+}
+
+impl Display for BridgeProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A call bridging tokens to another chain, recognized by
+/// [`Classifier::bridge_call`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BridgeCall {
+    /// The protocol of the bridge
+    pub protocol: BridgeProtocol,
+    /// The id of the chain the tokens are bridged to, if it is known
+    pub destination_chain: Option<u64>,
+    /// The address receiving the tokens on the destination chain, or `None`
+    /// if it is the sender of the transaction or is not an address
+    pub recipient: Option<Address>,
+}
+
+impl BridgeCall {
+    /// The attributes of the context describing this call: the protocol, and
+    /// the destination chain and recipient when they are known
+    pub fn attrs(&self) -> impl Iterator<Item = (&'static str, RestrictedExpression)> {
+        [
+            Some((BRIDGE_PROTOCOL_ATTRIBUTE, self.protocol.as_str().into())),
+            self.destination_chain
+                .and_then(|chain| i64::try_from(chain).ok())
+                .map(|chain| (DESTINATION_CHAIN_ATTRIBUTE, chain.into())),
+            self.recipient
+                .map(|recipient| (BRIDGE_RECIPIENT_ATTRIBUTE, recipient.into())),
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
+/// Where the arguments of a bridging function give the destination chain and
+/// recipient
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BridgeArgs {
+    /// The bridge contract decides the destination chain, and the recipient
+    /// is the sender
+    ToSender,
+    /// The bridge contract decides the destination chain, and the recipient
+    /// is the address argument at this index
+    ToRecipient(usize),
+    /// `send(SendParam,MessagingFee,address)` of a V2 OFT, whose
+    /// first argument begins with the destination endpoint and recipient
+    OftSend,
+    /// `sendFrom(address,uint16,bytes,...)` of a V1 OFT
+    OftSendFrom,
+    /// `depositForBurn(uint256,uint32,bytes32,address,...)` of the CCTP token
+    /// messenger
+    DepositForBurn,
+}
+
+/// The signatures of the well-known bridging functions
+const BRIDGE_FUNCTIONS: &[(&str, BridgeProtocol, BridgeArgs)] = &[
+    (
+        "depositETH(uint32,bytes)",
+        BridgeProtocol::OpStack,
+        BridgeArgs::ToSender,
+    ),
+    (
+        "depositETHTo(address,uint32,bytes)",
+        BridgeProtocol::OpStack,
+        BridgeArgs::ToRecipient(0),
+    ),
+    (
+        "depositERC20(address,address,uint256,uint32,bytes)",
+        BridgeProtocol::OpStack,
+        BridgeArgs::ToSender,
+    ),
+    (
+        "depositERC20To(address,address,address,uint256,uint32,bytes)",
+        BridgeProtocol::OpStack,
+        BridgeArgs::ToRecipient(2),
+    ),
+    (
+        "depositTransaction(address,uint256,uint64,bool,bytes)",
+        BridgeProtocol::OpStack,
+        BridgeArgs::ToRecipient(0),
+    ),
+    (
+        "depositEth()",
+        BridgeProtocol::Arbitrum,
+        BridgeArgs::ToSender,
+    ),
+    (
+        "outboundTransfer(address,address,uint256,uint256,uint256,bytes)",
+        BridgeProtocol::Arbitrum,
+        BridgeArgs::ToRecipient(1),
+    ),
+    (
+        "send((uint32,bytes32,uint256,uint256,bytes,bytes,bytes),(uint256,uint256),address)",
+        BridgeProtocol::LayerZero,
+        BridgeArgs::OftSend,
+    ),
+    (
+        "sendFrom(address,uint16,bytes,uint256,address,address,bytes)",
+        BridgeProtocol::LayerZero,
+        BridgeArgs::OftSendFrom,
+    ),
+    (
+        "depositForBurn(uint256,uint32,bytes32,address)",
+        BridgeProtocol::Cctp,
+        BridgeArgs::DepositForBurn,
+    ),
+    (
+        "depositForBurnWithCaller(uint256,uint32,bytes32,address,bytes32)",
+        BridgeProtocol::Cctp,
+        BridgeArgs::DepositForBurn,
+    ),
+];
+
+/// The canonical bridges on Ethereum mainnet, and the chains they bridge to
+const CANONICAL_BRIDGES: &[(&str, u64)] = &[
+    // Optimism: the L1 standard bridge and the portal
+    ("0x99c9fc46f92e8a1c0dec1b1747d010903e884be1", 10),
+    ("0xbeb5fc579115071764c7423a4f12edde41f8ed0a", 10),
+    // Base: the L1 standard bridge and the portal
+    ("0x3154cf16ccdb4c6d922629664174b904d80f2c35", 8453),
+    ("0x49048044d57e1c92a77f79988d21fa8faf74e97e", 8453),
+    // Arbitrum One: the delayed inbox and the L1 gateway router
+    ("0x4dbd4fc535ac27206064b68ffcf827b0a60bab3f", 42161),
+    ("0x72ce9c846789fdb6fc1f34ac4ad25dd9ef7031ef", 42161),
+];
+
+/// The chains of the V1 OFT chain ids, which are also the V2 endpoint
+/// ids less 30000
+const LAYER_ZERO_CHAINS: &[(u64, u64)] = &[
+    (101, 1),
+    (102, 56),
+    (106, 43114),
+    (109, 137),
+    (110, 42161),
+    (111, 10),
+    (184, 8453),
+];
+
+/// The chains of the CCTP domains
+const CCTP_CHAINS: &[(u64, u64)] = &[(0, 1), (1, 43114), (2, 10), (3, 42161), (6, 8453), (7, 137)];
+
+/// The chain with `id` in `chains`
+fn chain_of(chains: &[(u64, u64)], id: u64) -> Option<u64> {
+    chains
+        .iter()
+        .find(|(other, _)| *other == id)
+        .map(|(_, chain)| *chain)
+}
+
+/// The ABI-encoded word at `offset` bytes into `args`
+fn word_at(args: &[u8], offset: usize) -> Option<&[u8]> {
+    args.get(offset..offset.checked_add(32)?)
+}
+
+/// The `uint` at `offset` bytes into `args`, if it fits in a `u64`
+fn uint_at(args: &[u8], offset: usize) -> Option<u64> {
+    let word = word_at(args, offset)?;
+    let (high, low) = word.split_at(24);
+    if high.iter().any(|byte| *byte != 0) {
+        return None;
+    }
+    Some(u64::from_be_bytes(low.try_into().ok()?))
+}
+
+/// The address at `offset` bytes into `args`, which is the last 20 bytes of
+/// the word for both `address` and `bytes32` arguments
+fn address_at(args: &[u8], offset: usize) -> Option<Address> {
+    word_at(args, offset)?.get(12..).map(Address::from_slice)
+}
+
+impl BridgeArgs {
+    /// The destination chain and recipient in `args`, where `bridge` is the
+    /// destination chain of the contract called
+    fn decode(self, args: &[u8], bridge: Option<u64>) -> (Option<u64>, Option<Address>) {
+        match self {
+            Self::ToSender => (bridge, None),
+            Self::ToRecipient(index) => (
+                bridge,
+                index
+                    .checked_mul(32)
+                    .and_then(|offset| address_at(args, offset)),
+            ),
+            Self::OftSend => {
+                let params = uint_at(args, 0).and_then(|offset| usize::try_from(offset).ok());
+                let endpoint = params.and_then(|offset| uint_at(args, offset));
+                let recipient = params
+                    .and_then(|offset| offset.checked_add(32))
+                    .and_then(|offset| address_at(args, offset));
+                (
+                    endpoint.and_then(|endpoint| {
+                        chain_of(LAYER_ZERO_CHAINS, endpoint.saturating_sub(30000))
+                    }),
+                    recipient,
+                )
+            }
+            Self::OftSendFrom => {
+                let chain = uint_at(args, 32).and_then(|id| chain_of(LAYER_ZERO_CHAINS, id));
+                // `_toAddress` is dynamic `bytes`, holding an address when
+                // bridging to an EVM chain
+                let recipient = uint_at(args, 64)
+                    .and_then(|offset| usize::try_from(offset).ok())
+                    .and_then(|offset| {
+                        let start = offset.checked_add(32)?;
+                        match uint_at(args, offset)? {
+                            20 => args.get(start..start.checked_add(20)?),
+                            _ => None,
+                        }
+                    })
+                    .map(Address::from_slice);
+                (chain, recipient)
+            }
+            Self::DepositForBurn => (
+                uint_at(args, 32).and_then(|domain| chain_of(CCTP_CHAINS, domain)),
+                address_at(args, 64),
+            ),
+        }
+    }
+}
+
+/// Maps transactions into [`Category`]s
+#[derive(Debug, Clone)]
+pub struct Classifier {
+    contracts: HashMap<Address, Category>,
+    selectors: HashMap<[u8; 4], Category>,
+    bridges: HashMap<Address, u64>,
+    bridge_functions: HashMap<[u8; 4], (BridgeProtocol, BridgeArgs)>,
+}
+
+impl Default for Classifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Classifier {
+    /// A classifier which knows the well-known functions of tokens, DEX
+    /// routers and bridges, and the canonical bridges on Ethereum mainnet
+    pub fn new() -> Self {
+        let bridge_functions: HashMap<_, _> = BRIDGE_FUNCTIONS
+            .iter()
+            .map(|(signature, protocol, args)| (selector(signature), (*protocol, *args)))
+            .collect();
+        let selectors = KNOWN_FUNCTIONS
+            .iter()
+            .flat_map(|(category, signatures)| {
+                signatures
+                    .iter()
+                    .map(|signature| (selector(signature), *category))
+            })
+            .chain(
+                bridge_functions
+                    .keys()
+                    .map(|selector| (*selector, Category::Bridge)),
+            )
+            .collect();
+        let bridges = CANONICAL_BRIDGES
+            .iter()
+            .filter_map(|(contract, chain)| Some((contract.parse().ok()?, *chain)))
+            .collect();
+        Self {
+            contracts: HashMap::new(),
+            selectors,
+            bridges,
+            bridge_functions,
+        }
+    }
+
+    /// Categorize every transaction to `contract` as `category`, such as
+    /// those to the router of a DEX as swaps
+    #[must_use]
+    pub fn with_contract(mut self, contract: Address, category: Category) -> Self {
+        self.contracts.insert(contract, category);
+        self
+    }
+
+    /// Categorize the transactions calling the function with the selector
+    /// `selector` as `category`, replacing its well-known category if it has
+    /// one
+    #[must_use]
+    pub fn with_selector(mut self, selector: [u8; 4], category: Category) -> Self {
+        self.selectors.insert(selector, category);
+        self
+    }
+
+    /// Record that the bridge `contract` bridges to the chain with id
+    /// `destination_chain`, such as the canonical bridge of a rollup, whose
+    /// calls do not name the chain
+    #[must_use]
+    pub fn with_bridge(mut self, contract: Address, destination_chain: u64) -> Self {
+        self.bridges.insert(contract, destination_chain);
+        self
+    }
+
+    /// Recognize a call to `to` with `calldata` of a well-known bridging
+    /// function, giving where it bridges to
+    pub fn bridge_call(&self, to: Address, calldata: &[u8]) -> Option<BridgeCall> {
+        let (selector, args) = calldata.split_first_chunk::<4>()?;
+        let (protocol, bridge_args) = self.bridge_functions.get(selector)?;
+        let (destination_chain, recipient) =
+            bridge_args.decode(args, self.bridges.get(&to).copied());
+        Some(BridgeCall {
+            protocol: *protocol,
+            destination_chain,
+            recipient,
+        })
+    }
+
+    /// A builder of the context of the transaction to `to`, or deploying a
+    /// contract if it is `None`, with `calldata`, holding its
+    /// `context.category` and, for bridge calls, the attributes of
+    /// [`BridgeCall::attrs`]
+    pub fn context(
+        &self,
+        to: Option<Address>,
+        calldata: &[u8],
+        balance_changes: &[BalanceChange],
+    ) -> RecordBuilder {
+        let record = RecordBuilder::new().attr(
+            CATEGORY_ATTRIBUTE,
+            self.classify(to, calldata, balance_changes),
+        );
+        to.and_then(|to| self.bridge_call(to, calldata))
+            .into_iter()
+            .flat_map(|call| call.attrs())
+            .fold(record, |record, (attr, value)| record.attr(attr, value))
+    }
+
+    /// Categorize the transaction to `to`, or deploying a contract if it is
+    /// `None`, with `calldata`, in whose simulation the sender's balances
+    /// changed by `balance_changes`
+    pub fn classify(
+        &self,
+        to: Option<Address>,
+        calldata: &[u8],
+        balance_changes: &[BalanceChange],
+    ) -> Category {
+        let Some(to) = to else {
+            return Category::ContractDeployment;
+        };
+        if let Some(category) = self.contracts.get(&to) {
+            return *category;
+        }
+        let selector = calldata
+            .get(..4)
+            .and_then(|selector| <[u8; 4]>::try_from(selector).ok());
+        if let Some(category) = selector.and_then(|selector| self.selectors.get(&selector)) {
+            return *category;
+        }
+        if calldata.is_empty() {
+            return Category::NativeTransfer;
+        }
+        if is_swap(balance_changes) {
+            return Category::Swap;
+        }
+        Category::Unknown
+    }
+}
+
+/// Whether the balance of one token decreased and that of another increased
+fn is_swap(balance_changes: &[BalanceChange]) -> bool {
+    let mut net: HashMap<Address, (U256, U256)> = HashMap::new();
+    for change in balance_changes {
+        let (received, sent) = net.entry(change.token).or_default();
+        *received = received.saturating_add(change.received);
+        *sent = sent.saturating_add(change.sent);
+    }
+    let decreased = net.values().any(|(received, sent)| sent > received);
+    let increased = net.values().any(|(received, sent)| received > sent);
+    decreased && increased
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Decision, Entities, PolicySet, Request};
+    use ethers::abi::{encode, Token};
+
+    fn address(s: &str) -> Address {
+        s.parse().expect("valid address")
+    }
+
+    fn call(signature: &str) -> Vec<u8> {
+        let mut calldata = selector(signature).to_vec();
+        calldata.extend([0; 64]);
+        calldata
+    }
+
+    #[test]
+    fn known_functions() {
+        let classifier = Classifier::new();
+        let token = Some(address("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"));
+        // the well-known selectors
+        assert_eq!(
+            call("transfer(address,uint256)").get(..4),
+            Some([0xa9, 0x05, 0x9c, 0xbb].as_slice())
+        );
+        assert_eq!(
+            call("approve(address,uint256)").get(..4),
+            Some([0x09, 0x5e, 0xa7, 0xb3].as_slice())
+        );
+        for (signature, category) in [
+            ("transfer(address,uint256)", Category::TokenTransfer),
+            ("approve(address,uint256)", Category::Approval),
+            ("setApprovalForAll(address,bool)", Category::Approval),
+            (
+                "swapExactETHForTokens(uint256,address[],address,uint256)",
+                Category::Swap,
+            ),
+            ("depositETH(uint32,bytes)", Category::Bridge),
+            ("mint(uint256)", Category::Unknown),
+        ] {
+            assert_eq!(
+                classifier.classify(token, &call(signature), &[]),
+                category,
+                "{signature}"
+            );
+        }
+    }
+
+    #[test]
+    fn deployments_and_native_transfers() {
+        let classifier = Classifier::new();
+        assert_eq!(
+            classifier.classify(None, &call("constructor()"), &[]),
+            Category::ContractDeployment
+        );
+        assert_eq!(
+            classifier.classify(
+                Some(address("0xab5801a7d398351b8be11c439e05c5b3259aec9b")),
+                &[],
+                &[]
+            ),
+            Category::NativeTransfer
+        );
+    }
+
+    #[test]
+    fn registered_contracts_and_selectors() {
+        let router = address("0x3fc91a3afd70395cd496c647d5a6cc9d4b2b7fad");
+        let vault = address("0xba12222222228d8ba445958a75a0704d566bf2c8");
+        let classifier = Classifier::new()
+            .with_contract(router, Category::Swap)
+            .with_selector(selector("bridgeOut(uint256)"), Category::Bridge);
+        // the contract decides before the selector
+        assert_eq!(
+            classifier.classify(Some(router), &call("approve(address,uint256)"), &[]),
+            Category::Swap
+        );
+        assert_eq!(
+            classifier.classify(Some(vault), &call("bridgeOut(uint256)"), &[]),
+            Category::Bridge
+        );
+    }
+
+    #[test]
+    fn swaps_from_simulation() {
+        let classifier = Classifier::new();
+        let vault = Some(address("0xba12222222228d8ba445958a75a0704d566bf2c8"));
+        let usdc = address("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48");
+        let weth = address("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2");
+        let calldata = call("swap((bytes32,uint8,address,address,uint256,bytes),(address,bool,address,bool),uint256,uint256)");
+        let changes = [
+            BalanceChange {
+                token: usdc,
+                received: U256::zero(),
+                sent: U256::from(1000),
+            },
+            BalanceChange {
+                token: weth,
+                received: U256::from(1),
+                sent: U256::zero(),
+            },
+        ];
+        assert_eq!(
+            classifier.classify(vault, &calldata, &changes),
+            Category::Swap
+        );
+        assert_eq!(
+            classifier.classify(vault, &calldata, &changes[..1]),
+            Category::Unknown
+        );
+    }
+
+    /// `signature` called with `args`
+    fn call_with(signature: &str, args: &[Token]) -> Vec<u8> {
+        let mut calldata = selector(signature).to_vec();
+        calldata.extend(encode(args));
+        calldata
+    }
+
+    #[test]
+    fn canonical_bridges() {
+        let classifier = Classifier::new();
+        let recipient = address("0xab5801a7d398351b8be11c439e05c5b3259aec9b");
+        let usdc = address("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48");
+        let op_bridge = address("0x99c9fc46f92e8a1c0dec1b1747d010903e884be1");
+        let calldata = call_with(
+            "depositETHTo(address,uint32,bytes)",
+            &[
+                Token::Address(recipient),
+                Token::Uint(200_000.into()),
+                Token::Bytes(vec![]),
+            ],
+        );
+        assert_eq!(
+            classifier.bridge_call(op_bridge, &calldata),
+            Some(BridgeCall {
+                protocol: BridgeProtocol::OpStack,
+                destination_chain: Some(10),
+                recipient: Some(recipient),
+            })
+        );
+        let calldata = call_with(
+            "outboundTransfer(address,address,uint256,uint256,uint256,bytes)",
+            &[
+                Token::Address(usdc),
+                Token::Address(recipient),
+                Token::Uint(1000.into()),
+                Token::Uint(300_000.into()),
+                Token::Uint(1.into()),
+                Token::Bytes(vec![]),
+            ],
+        );
+        assert_eq!(
+            classifier.bridge_call(
+                address("0x72ce9c846789fdb6fc1f34ac4ad25dd9ef7031ef"),
+                &calldata
+            ),
+            Some(BridgeCall {
+                protocol: BridgeProtocol::Arbitrum,
+                destination_chain: Some(42161),
+                recipient: Some(recipient),
+            })
+        );
+        // the bridge of another OP Stack chain, which bridges to the sender
+        let zora_bridge = address("0x3e2ea9b92b7e48a52296fd261dc26fd995284631");
+        let calldata = call_with(
+            "depositETH(uint32,bytes)",
+            &[Token::Uint(200_000.into()), Token::Bytes(vec![])],
+        );
+        assert_eq!(
+            classifier.bridge_call(zora_bridge, &calldata),
+            Some(BridgeCall {
+                protocol: BridgeProtocol::OpStack,
+                destination_chain: None,
+                recipient: None,
+            })
+        );
+        assert_eq!(
+            classifier
+                .clone()
+                .with_bridge(zora_bridge, 7_777_777)
+                .bridge_call(zora_bridge, &calldata)
+                .and_then(|call| call.destination_chain),
+            Some(7_777_777)
+        );
+        assert_eq!(
+            classifier.bridge_call(op_bridge, &call("transfer(address,uint256)")),
+            None
+        );
+    }
+
+    #[test]
+    fn layer_zero_and_cctp() {
+        let classifier = Classifier::new();
+        let oft = address("0x6c96de32cea08842dcc4058c14d3aaad7fa41dee");
+        let recipient = address("0xab5801a7d398351b8be11c439e05c5b3259aec9b");
+        let recipient_word = {
+            let mut word = [0; 32];
+            word[12..].copy_from_slice(recipient.as_bytes());
+            word.to_vec()
+        };
+        // a LayerZero V2 OFT sending to Base
+        let calldata = call_with(
+            "send((uint32,bytes32,uint256,uint256,bytes,bytes,bytes),(uint256,uint256),address)",
+            &[
+                Token::Tuple(vec![
+                    Token::Uint(30184.into()),
+                    Token::FixedBytes(recipient_word.clone()),
+                    Token::Uint(1000.into()),
+                    Token::Uint(990.into()),
+                    Token::Bytes(vec![]),
+                    Token::Bytes(vec![]),
+                    Token::Bytes(vec![]),
+                ]),
+                Token::Tuple(vec![Token::Uint(1.into()), Token::Uint(0.into())]),
+                Token::Address(recipient),
+            ],
+        );
+        assert_eq!(
+            classifier.classify(Some(oft), &calldata, &[]),
+            Category::Bridge
+        );
+        assert_eq!(
+            classifier.bridge_call(oft, &calldata),
+            Some(BridgeCall {
+                protocol: BridgeProtocol::LayerZero,
+                destination_chain: Some(8453),
+                recipient: Some(recipient),
+            })
+        );
+        // a LayerZero V1 OFT sending to Arbitrum
+        let calldata = call_with(
+            "sendFrom(address,uint16,bytes,uint256,address,address,bytes)",
+            &[
+                Token::Address(recipient),
+                Token::Uint(110.into()),
+                Token::Bytes(recipient.as_bytes().to_vec()),
+                Token::Uint(1000.into()),
+                Token::Address(recipient),
+                Token::Address(Address::zero()),
+                Token::Bytes(vec![]),
+            ],
+        );
+        assert_eq!(
+            classifier.bridge_call(oft, &calldata),
+            Some(BridgeCall {
+                protocol: BridgeProtocol::LayerZero,
+                destination_chain: Some(42161),
+                recipient: Some(recipient),
+            })
+        );
+        // CCTP burning USDC to mint on Base, and on an unknown domain
+        let messenger = address("0xbd3fa81b58ba92a82136038b25adec7066af3155");
+        let usdc = address("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48");
+        let burn = |domain: u32| {
+            call_with(
+                "depositForBurn(uint256,uint32,bytes32,address)",
+                &[
+                    Token::Uint(1000.into()),
+                    Token::Uint(domain.into()),
+                    Token::FixedBytes(recipient_word.clone()),
+                    Token::Address(usdc),
+                ],
+            )
+        };
+        assert_eq!(
+            classifier.bridge_call(messenger, &burn(6)),
+            Some(BridgeCall {
+                protocol: BridgeProtocol::Cctp,
+                destination_chain: Some(8453),
+                recipient: Some(recipient),
+            })
+        );
+        assert_eq!(
+            classifier
+                .bridge_call(messenger, &burn(99))
+                .map(|call| call.destination_chain),
+            Some(None)
+        );
+        // truncated calldata is still a bridge call, to an unknown chain
+        assert_eq!(
+            classifier.bridge_call(messenger, &burn(6)[..40]),
+            Some(BridgeCall {
+                protocol: BridgeProtocol::Cctp,
+                destination_chain: None,
+                recipient: None,
+            })
+        );
+    }
+
+    #[test]
+    fn restricting_destination_chains() {
+        let classifier = Classifier::new();
+        let recipient = address("0xab5801a7d398351b8be11c439e05c5b3259aec9b");
+        let policies: PolicySet = r#"
+            permit(principal, action, resource);
+            forbid(principal, action, resource)
+            when { context.category == "bridge" }
+            unless { context has destinationChain && [10, 8453].contains(context.destinationChain) };
+        "#
+        .parse()
+        .expect("valid policies");
+        let decision = |to: &str, calldata: &[u8]| {
+            let context = classifier
+                .context(Some(address(to)), calldata, &[])
+                .build_context();
+            let request = Request::new(
+                Some(r#"Account::"treasury""#.parse().expect("valid uid")),
+                Some(r#"Action::"send""#.parse().expect("valid uid")),
+                Some(r#"Contract::"bridge""#.parse().expect("valid uid")),
+                context,
+            );
+            Authorizer::new()
+                .is_authorized(&request, &policies, &Entities::empty())
+                .decision()
+        };
+        let deposit = call_with(
+            "depositETHTo(address,uint32,bytes)",
+            &[
+                Token::Address(recipient),
+                Token::Uint(200_000.into()),
+                Token::Bytes(vec![]),
+            ],
+        );
+        // to Optimism and Base, but not to Arbitrum or unknown chains
+        for (bridge, decision_expected) in [
+            (
+                "0x99c9fc46f92e8a1c0dec1b1747d010903e884be1",
+                Decision::Allow,
+            ),
+            (
+                "0x3154cf16ccdb4c6d922629664174b904d80f2c35",
+                Decision::Allow,
+            ),
+            ("0x4dbd4fc535ac27206064b68ffcf827b0a60bab3f", Decision::Deny),
+            ("0x3e2ea9b92b7e48a52296fd261dc26fd995284631", Decision::Deny),
+        ] {
+            assert_eq!(decision(bridge, &deposit), decision_expected, "{bridge}");
+        }
+        assert_eq!(
+            decision(
+                "0x99c9fc46f92e8a1c0dec1b1747d010903e884be1",
+                &call("transfer(address,uint256)")
+            ),
+            Decision::Allow
+        );
+        let attrs = classifier
+            .context(
+                Some(address("0x99c9fc46f92e8a1c0dec1b1747d010903e884be1")),
+                &deposit,
+                &[],
+            )
+            .build_attrs();
+        assert!(attrs.contains_key(BRIDGE_RECIPIENT_ATTRIBUTE));
+        assert!(attrs.contains_key(BRIDGE_PROTOCOL_ATTRIBUTE));
+    }
+
+    #[test]
+    fn category_names() {
+        for category in Category::ALL {
+            assert_eq!(category.as_str().parse(), Ok(category));
+        }
+        assert!("bridging".parse::<Category>().is_err());
+    }
+}
@@ -0,0 +1,854 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Running conformance tests written in the format of the Cedar integration
+//! tests, so that suites of tests for a protocol can be run against the
+//! engine.
+//!
+//! A test file is a JSON object with the fields of [`TestCase`]:
+//! * `policies` and `entities`: the files containing the policy set, which
+//!   may import other files, and the entities, in JSON. Extension values in
+//!   entity attributes are written as
+//!   `{ "__extn": { "fn": "u256", "arg": "1000000" } }`.
+//! * `schema` (optional): the file containing the schema, in the JSON or the
+//!   human-readable format, which the entities and contexts are parsed with
+//! * `should_validate` (optional): whether the policies are expected to pass
+//!   validation against the schema. Validation is not checked if it is not
+//!   given.
+//! * `requests` (or `queries`): the requests, with their expected results, in
+//!   the format of [`TestRequest`]
+//!
+//! ```json
+//! {
+//!   "policies": "policies.cedar",
+//!   "entities": "entities.json",
+//!   "schema": "schema.cedarschema.json",
+//!   "should_validate": true,
+//!   "requests": [
+//!     {
+//!       "desc": "an unlimited approval is forbidden",
+//!       "principal": "Address::\"0x7c3250001bc0abeeef91f52e9054a9f951190132\"",
+//!       "action": { "type": "Action", "id": "0x095ea7b3" },
+//!       "resource": "Address::\"0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48\"",
+//!       "context": {
+//!         "args": {
+//!           "arg_1": { "__extn": { "fn": "u256", "arg": "0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff" } }
+//!         }
+//!       },
+//!       "decision": "Deny",
+//!       "reasons": ["policy0"],
+//!       "errors": []
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! The files named by a test file are relative to a root directory, which
+//! [`run_test_file`] takes to be the directory of the test file.
+//!
+//! Tests are run against this crate, or against another implementation of
+//! Cedar given as a [`CustomCedarImpl`] to [`TestCase::run_inputs`].
+#![allow(clippy::result_large_err)]
+
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    frontend::is_authorized::InterfaceResponse, Authorizer, Context, Decision, Entities,
+    EntitiesError, EntityUid, ImportError, PolicySet, Request, Response, Schema, SchemaError,
+    ValidationMode, Validator,
+};
+
+/// A test file: the policies, entities and schema to use, and requests with
+/// their expected results
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestCase {
+    /// File containing the policy set
+    pub policies: String,
+    /// File containing the entities, in JSON
+    pub entities: String,
+    /// File containing the schema, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    /// Whether the policies are expected to pass validation against the
+    /// schema, if that is to be checked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub should_validate: Option<bool>,
+    /// The requests, with their expected results
+    #[serde(alias = "queries")]
+    pub requests: Vec<TestRequest>,
+}
+
+/// A request of a [`TestCase`], with its expected result
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestRequest {
+    /// Description of the request
+    pub desc: String,
+    /// Principal of the request, if it is known. This is an entity uid as
+    /// Cedar text, like `"User::\"alice\""`, or in the JSON form
+    /// `{ "type": "User", "id": "alice" }`, optionally wrapped in
+    /// `{ "__entity": ... }`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub principal: Option<serde_json::Value>,
+    /// Action of the request, if it is known, in the same forms as the
+    /// principal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<serde_json::Value>,
+    /// Resource of the request, if it is known, in the same forms as the
+    /// principal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource: Option<serde_json::Value>,
+    /// Context of the request, as a JSON object, which is empty if it is not
+    /// given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<serde_json::Value>,
+    /// Expected decision
+    pub decision: Decision,
+    /// Expected ids of the policies which determined the decision
+    #[serde(default)]
+    pub reasons: Vec<String>,
+    /// Expected error messages
+    #[serde(default)]
+    pub errors: Vec<String>,
+}
+
+/// Errors loading a test file, or the files or requests it names
+#[derive(Debug, Error)]
+pub enum ConformanceError {
+    /// A file could not be read
+    #[error("failed to read {}: {source}", .path.display())]
+    Io {
+        /// The file
+        path: PathBuf,
+        /// The error reading it
+        source: std::io::Error,
+    },
+    /// A test file is not in the format of a [`TestCase`]
+    #[error("failed to parse test file {}: {source}", .path.display())]
+    TestFile {
+        /// The test file
+        path: PathBuf,
+        /// The error parsing it
+        source: serde_json::Error,
+    },
+    /// The policy set, or a file it imports, could not be loaded
+    #[error("failed to load policies from {}: {source}", .path.display())]
+    Policies {
+        /// The policy file
+        path: PathBuf,
+        /// The error loading it
+        source: ImportError,
+    },
+    /// The schema could not be parsed
+    #[error("failed to parse schema {}: {source}", .path.display())]
+    Schema {
+        /// The schema file
+        path: PathBuf,
+        /// The error parsing it
+        source: SchemaError,
+    },
+    /// The entities could not be parsed
+    #[error("failed to parse entities {}: {source}", .path.display())]
+    Entities {
+        /// The entities file
+        path: PathBuf,
+        /// The error parsing it
+        source: EntitiesError,
+    },
+    /// The principal, action, resource or context of a request could not be
+    /// parsed
+    #[error("failed to parse the {part} of request `{desc}`: {message}")]
+    Request {
+        /// Description of the request
+        desc: String,
+        /// Which part of the request could not be parsed, e.g., `principal`
+        part: &'static str,
+        /// Why it could not be parsed
+        message: String,
+    },
+}
+
+/// The policies, schema and entities named by a [`TestCase`]
+#[derive(Debug, Clone)]
+pub struct TestInputs {
+    /// The policy set
+    pub policies: PolicySet,
+    /// The schema, if the test case names one
+    pub schema: Option<Schema>,
+    /// The entities
+    pub entities: Entities,
+}
+
+/// Data structure for the validation result of a [`CustomCedarImpl`].
+///
+/// Unlike a definitional authorization response, a definitional validation
+/// result isn't feasible to convert to its production analogue, so instead, we
+/// define a simple data structure to which both can be converted that is
+/// sufficient for the checks we want to perform.
+#[derive(Debug)]
+pub struct IntegrationTestValidationResult {
+    /// Whether the test inputs passed validation.
+    pub validation_passed: bool,
+    /// Information about validation errors that may be shown to the user for
+    /// diagnostic purposes. As the name suggests, it's customary to use the
+    /// `Debug` representation of the original data structure.
+    pub validation_errors_debug: String,
+}
+
+/// A custom Cedar implementation (authorizer + validator) on which to run
+/// tests instead of the `Cedar` API.
+pub trait CustomCedarImpl {
+    /// Custom authorizer entry point.
+    fn is_authorized(
+        &self,
+        q: &cedar_policy_core::ast::Request,
+        p: &cedar_policy_core::ast::PolicySet,
+        e: &cedar_policy_core::entities::Entities,
+    ) -> InterfaceResponse;
+
+    /// Custom validator entry point.
+    ///
+    /// The fact that this API takes ownership of `schema` is a quirk that
+    /// parallels the equivalent production code pattern, in which construction
+    /// of the `Validator` would take ownership of the schema. Indeed, the only
+    /// current implementation of this method is based on
+    /// `DefinitionalValidator::validate`, which seems to have replicated this
+    /// quirk even though it has no apparent implementation need to take
+    /// ownership of the schema.
+    fn validate(
+        &self,
+        schema: cedar_policy_validator::ValidatorSchema,
+        policies: &cedar_policy_core::ast::PolicySet,
+    ) -> IntegrationTestValidationResult;
+}
+
+/// A way in which the result of a test differs from the expected one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    /// The policies were expected to pass validation and did not, or the
+    /// other way around
+    Validation {
+        /// Whether they were expected to pass
+        expected: bool,
+        /// The validation errors, if there were any
+        errors: Vec<String>,
+    },
+    /// The decision is not the expected one
+    Decision {
+        /// The expected decision
+        expected: Decision,
+        /// The decision
+        actual: Decision,
+    },
+    /// The policies which determined the decision are not the expected ones
+    Reasons {
+        /// Ids of the expected policies which did not determine the decision
+        missing: Vec<String>,
+        /// Ids of the policies which determined the decision but were not
+        /// expected to
+        unexpected: Vec<String>,
+    },
+    /// The errors are not the expected ones
+    Errors {
+        /// The expected errors which did not occur
+        missing: Vec<String>,
+        /// The errors which occurred but were not expected
+        unexpected: Vec<String>,
+    },
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let differences =
+            |f: &mut fmt::Formatter<'_>, missing: &[String], unexpected: &[String]| {
+                let items = missing
+                    .iter()
+                    .map(|item| format!("missing `{item}`"))
+                    .chain(unexpected.iter().map(|item| format!("unexpected `{item}`")))
+                    .collect::<Vec<_>>();
+                write!(f, "{}", items.join(", "))
+            };
+        match self {
+            Self::Validation {
+                expected: true,
+                errors,
+            } => {
+                write!(
+                    f,
+                    "expected the policies to validate, but they did not: {}",
+                    errors.join("; ")
+                )
+            }
+            Self::Validation {
+                expected: false, ..
+            } => {
+                write!(
+                    f,
+                    "expected the policies to fail validation, but they validated"
+                )
+            }
+            Self::Decision { expected, actual } => {
+                write!(f, "decision: expected {expected:?}, got {actual:?}")
+            }
+            Self::Reasons {
+                missing,
+                unexpected,
+            } => {
+                write!(f, "reasons: ")?;
+                differences(f, missing, unexpected)
+            }
+            Self::Errors {
+                missing,
+                unexpected,
+            } => {
+                write!(f, "errors: ")?;
+                differences(f, missing, unexpected)
+            }
+        }
+    }
+}
+
+/// The result of running one request of a test
+#[derive(Debug)]
+pub struct RequestReport {
+    /// Description of the request
+    pub desc: String,
+    /// How the result differs from the expected one, which is empty if the
+    /// request passed, or why the request could not be made
+    pub outcome: Result<Vec<Mismatch>, ConformanceError>,
+}
+
+impl RequestReport {
+    /// Whether the result of the request is the expected one
+    pub fn passed(&self) -> bool {
+        self.outcome.as_ref().is_ok_and(Vec::is_empty)
+    }
+}
+
+/// The result of running a test
+#[derive(Debug)]
+pub struct TestReport {
+    /// How the result of validation differs from the expected one, if it
+    /// does
+    pub validation: Option<Mismatch>,
+    /// The results of the requests, in order
+    pub requests: Vec<RequestReport>,
+}
+
+impl TestReport {
+    /// Whether validation and every request had the expected results
+    pub fn passed(&self) -> bool {
+        self.validation.is_none() && self.requests.iter().all(RequestReport::passed)
+    }
+}
+
+impl Display for TestReport {
+    /// One line for validation and for each request which did not pass
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(mismatch) = &self.validation {
+            writeln!(f, "{mismatch}")?;
+        }
+        for request in &self.requests {
+            match &request.outcome {
+                Ok(mismatches) if mismatches.is_empty() => {}
+                Ok(mismatches) => {
+                    let mismatches = mismatches.iter().map(ToString::to_string);
+                    writeln!(
+                        f,
+                        "request `{}`: {}",
+                        request.desc,
+                        mismatches.collect::<Vec<_>>().join("; ")
+                    )?;
+                }
+                Err(err) => writeln!(f, "{err}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read(path: &Path) -> Result<String, ConformanceError> {
+    std::fs::read_to_string(path).map_err(|source| ConformanceError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// The items of `expected` missing from `actual`, and those of `actual` not in
+/// `expected`
+fn set_difference(
+    expected: &[String],
+    actual: &BTreeSet<String>,
+) -> Option<(Vec<String>, Vec<String>)> {
+    let expected = expected.iter().cloned().collect::<BTreeSet<_>>();
+    if &expected == actual {
+        None
+    } else {
+        Some((
+            expected.difference(actual).cloned().collect(),
+            actual.difference(&expected).cloned().collect(),
+        ))
+    }
+}
+
+impl TestCase {
+    /// Read a test file
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read or is not a test file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConformanceError> {
+        let path = path.as_ref();
+        Self::from_str(&read(path)?).map_err(|source| ConformanceError::TestFile {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// Load the policies, schema and entities named by this test case, which
+    /// are relative to `root` unless they are absolute paths
+    ///
+    /// # Errors
+    ///
+    /// If any of them cannot be read or parsed.
+    pub fn load_inputs(&self, root: impl AsRef<Path>) -> Result<TestInputs, ConformanceError> {
+        let root = root.as_ref();
+        let path = root.join(&self.policies);
+        let policies = PolicySet::from_file(&path)
+            .map_err(|source| ConformanceError::Policies { path, source })?;
+        let schema = match &self.schema {
+            None => None,
+            Some(schema) => {
+                let path = root.join(schema);
+                let text = read(&path)?;
+                // JSON schemas are objects; anything else is in the
+                // human-readable format
+                let schema = if text.trim_start().starts_with('{') {
+                    Schema::from_str(&text)
+                } else {
+                    Schema::from_str_natural(&text)
+                };
+                Some(schema.map_err(|source| ConformanceError::Schema { path, source })?)
+            }
+        };
+        let path = root.join(&self.entities);
+        let entities = Entities::from_json_str(&read(&path)?, schema.as_ref())
+            .map_err(|source| ConformanceError::Entities { path, source })?;
+        Ok(TestInputs {
+            policies,
+            schema,
+            entities,
+        })
+    }
+
+    /// Run this test case with the files it names relative to `root`,
+    /// checking whether the policies validate, if that is expected, and
+    /// authorizing each request with [`Authorizer`]
+    ///
+    /// # Errors
+    ///
+    /// If the policies, schema or entities cannot be loaded. Requests which
+    /// cannot be parsed are reported in the [`TestReport`].
+    pub fn run(&self, root: impl AsRef<Path>) -> Result<TestReport, ConformanceError> {
+        Ok(self.run_inputs(&self.load_inputs(root)?, None))
+    }
+
+    /// Run this test case with `inputs`, loaded with
+    /// [`TestCase::load_inputs`], validating and authorizing with `custom`
+    /// if it is given, and else with [`Validator`] and [`Authorizer`].
+    /// Requests which cannot be parsed are reported in the [`TestReport`].
+    pub fn run_inputs(
+        &self,
+        inputs: &TestInputs,
+        custom: Option<&dyn CustomCedarImpl>,
+    ) -> TestReport {
+        let authorizer = Authorizer::new();
+        let requests = self
+            .requests
+            .iter()
+            .map(|request| RequestReport {
+                desc: request.desc.clone(),
+                outcome: request.request(inputs.schema.as_ref()).map(|parsed| {
+                    let response = match custom {
+                        Some(custom) => custom.is_authorized(
+                            &parsed.0,
+                            &inputs.policies.ast,
+                            &inputs.entities.0,
+                        ),
+                        None => authorizer
+                            .is_authorized(&parsed, &inputs.policies, &inputs.entities)
+                            .into(),
+                    };
+                    request.check_interface(&response)
+                }),
+            })
+            .collect();
+        TestReport {
+            validation: inputs.check_validation_with(self.should_validate, custom),
+            requests,
+        }
+    }
+}
+
+impl FromStr for TestCase {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+impl TestInputs {
+    /// How the result of validating the policies against the schema differs
+    /// from `should_validate`, if it does. Nothing is checked if
+    /// `should_validate` is `None`, and the policies are expected to fail
+    /// validation if there is no schema.
+    pub fn check_validation(&self, should_validate: Option<bool>) -> Option<Mismatch> {
+        self.check_validation_with(should_validate, None)
+    }
+
+    /// Like [`TestInputs::check_validation`], validating with `custom` if it
+    /// is given
+    pub fn check_validation_with(
+        &self,
+        should_validate: Option<bool>,
+        custom: Option<&dyn CustomCedarImpl>,
+    ) -> Option<Mismatch> {
+        let expected = should_validate?;
+        let errors = match (&self.schema, custom) {
+            (None, _) => vec!["there is no schema to validate against".to_string()],
+            (Some(schema), Some(custom)) => {
+                let result = custom.validate(schema.0.clone(), &self.policies.ast);
+                if result.validation_passed {
+                    Vec::new()
+                } else {
+                    vec![result.validation_errors_debug]
+                }
+            }
+            (Some(schema), None) => Validator::new(schema.clone())
+                .validate(&self.policies, ValidationMode::default())
+                .validation_errors()
+                .map(ToString::to_string)
+                .collect(),
+        };
+        (expected != errors.is_empty()).then_some(Mismatch::Validation { expected, errors })
+    }
+}
+
+/// Parse the principal, action or resource of a test request
+fn parse_uid(
+    json: &serde_json::Value,
+    desc: &str,
+    part: &'static str,
+) -> Result<EntityUid, ConformanceError> {
+    let parsed = match json {
+        serde_json::Value::String(s) => EntityUid::from_str(s).map_err(|e| e.to_string()),
+        json => EntityUid::from_json(json.clone()).map_err(|e| e.to_string()),
+    };
+    parsed.map_err(|message| ConformanceError::Request {
+        desc: desc.to_string(),
+        part,
+        message,
+    })
+}
+
+impl TestRequest {
+    /// The request, with its context parsed with `schema` if one is given
+    ///
+    /// # Errors
+    ///
+    /// If the principal, action, resource or context cannot be parsed.
+    pub fn request(&self, schema: Option<&Schema>) -> Result<Request, ConformanceError> {
+        let uid = |json: &Option<serde_json::Value>, part| {
+            json.as_ref()
+                .map(|json| parse_uid(json, &self.desc, part))
+                .transpose()
+        };
+        let principal = uid(&self.principal, "principal")?;
+        let action = uid(&self.action, "action")?;
+        let resource = uid(&self.resource, "resource")?;
+        let context = match &self.context {
+            None => Context::empty(),
+            Some(json) => Context::from_json_value(
+                json.clone(),
+                schema.and_then(|schema| Some((schema, action.as_ref()?))),
+            )
+            .map_err(|e| ConformanceError::Request {
+                desc: self.desc.clone(),
+                part: "context",
+                message: e.to_string(),
+            })?,
+        };
+        Ok(Request::new(principal, action, resource, context))
+    }
+
+    /// How `response` differs from the expected result of this request,
+    /// which is empty if it is the expected one
+    pub fn check(&self, response: &Response) -> Vec<Mismatch> {
+        self.check_interface(&InterfaceResponse::from(response.clone()))
+    }
+
+    /// Like [`TestRequest::check`], for the response of a
+    /// [`CustomCedarImpl`]
+    pub fn check_interface(&self, response: &InterfaceResponse) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        if response.decision() != self.decision {
+            mismatches.push(Mismatch::Decision {
+                expected: self.decision,
+                actual: response.decision(),
+            });
+        }
+        let reasons = response
+            .diagnostics()
+            .reason()
+            .map(|id| id.as_ref().to_string())
+            .collect();
+        if let Some((missing, unexpected)) = set_difference(&self.reasons, &reasons) {
+            mismatches.push(Mismatch::Reasons {
+                missing,
+                unexpected,
+            });
+        }
+        let errors = response
+            .diagnostics()
+            .errors()
+            .map(str::to_string)
+            .collect();
+        if let Some((missing, unexpected)) = set_difference(&self.errors, &errors) {
+            mismatches.push(Mismatch::Errors {
+                missing,
+                unexpected,
+            });
+        }
+        mismatches
+    }
+}
+
+/// Run the test file at `path`, with the files it names relative to its
+/// directory
+///
+/// # Errors
+///
+/// If the test file, or the policies, schema or entities it names, cannot be
+/// loaded.
+pub fn run_test_file(path: impl AsRef<Path>) -> Result<TestReport, ConformanceError> {
+    let path = path.as_ref();
+    TestCase::from_file(path)?.run(path.parent().unwrap_or_else(|| Path::new("")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn write(dir: &Path, name: &str, text: &str) {
+        std::fs::write(dir.join(name), text).unwrap();
+    }
+
+    /// A test of ERC20 approvals, whose requests are given by `requests`
+    fn approvals(dir: &Path, requests: &str) -> PathBuf {
+        write(
+            dir,
+            "policies.cedar",
+            r#"
+            forbid (principal, action == Action::"0x095ea7b3", resource)
+            when { context.args.arg_1.u256GreaterThanOrEqual(u256("0x8000000000000000000000000000000000000000000000000000000000000000")) };
+            permit (principal, action, resource);
+            "#,
+        );
+        write(dir, "entities.json", "[]");
+        write(
+            dir,
+            "schema.cedarschema",
+            r#"
+            entity Address;
+            action "0x095ea7b3" appliesTo {
+              principal: [Address],
+              resource: [Address],
+              context: { args: { arg_0: Address, arg_1: u256 } }
+            };
+            "#,
+        );
+        let test = format!(
+            r#"{{
+                "policies": "policies.cedar",
+                "entities": "entities.json",
+                "schema": "schema.cedarschema",
+                "should_validate": true,
+                "requests": {requests}
+            }}"#
+        );
+        let path = dir.join("approvals.json");
+        write(dir, "approvals.json", &test);
+        path
+    }
+
+    fn request(desc: &str, amount: &str, decision: &str, reasons: &str) -> String {
+        format!(
+            r#"{{
+                "desc": "{desc}",
+                "principal": "Address::\"0x7c3250001bc0abeeef91f52e9054a9f951190132\"",
+                "action": {{ "type": "Action", "id": "0x095ea7b3" }},
+                "resource": {{ "__entity": {{ "type": "Address", "id": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48" }} }},
+                "context": {{
+                    "args": {{
+                        "arg_0": {{ "type": "Address", "id": "0x01" }},
+                        "arg_1": {{ "__extn": {{ "fn": "u256", "arg": "{amount}" }} }}
+                    }}
+                }},
+                "decision": "{decision}",
+                "reasons": {reasons}
+            }}"#
+        )
+    }
+
+    #[test]
+    fn passing_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let requests = format!(
+            "[{}, {}]",
+            request("small approval", "100", "Allow", r#"["policy1"]"#),
+            request(
+                "unlimited approval",
+                "0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+                "Deny",
+                r#"["policy0"]"#
+            )
+        );
+        let report = run_test_file(approvals(dir.path(), &requests)).unwrap();
+        assert!(report.passed(), "{report:?}");
+        assert_eq!(report.requests.len(), 2);
+    }
+
+    #[test]
+    fn failing_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let requests = format!(
+            "[{}]",
+            request("small approval", "100", "Deny", r#"["policy0"]"#)
+        );
+        let report = run_test_file(approvals(dir.path(), &requests)).unwrap();
+        assert!(!report.passed());
+        let mismatches = report.requests.first().unwrap().outcome.as_ref().unwrap();
+        assert_eq!(
+            mismatches,
+            &vec![
+                Mismatch::Decision {
+                    expected: Decision::Deny,
+                    actual: Decision::Allow,
+                },
+                Mismatch::Reasons {
+                    missing: vec!["policy0".to_string()],
+                    unexpected: vec!["policy1".to_string()],
+                },
+            ]
+        );
+        assert_eq!(
+            mismatches.get(1).unwrap().to_string(),
+            "reasons: missing `policy0`, unexpected `policy1`"
+        );
+    }
+
+    /// An implementation which denies every request and fails validation
+    struct DenyAll;
+
+    impl CustomCedarImpl for DenyAll {
+        fn is_authorized(
+            &self,
+            _: &cedar_policy_core::ast::Request,
+            _: &cedar_policy_core::ast::PolicySet,
+            _: &cedar_policy_core::entities::Entities,
+        ) -> InterfaceResponse {
+            InterfaceResponse::new(Decision::Deny, HashSet::new(), HashSet::new())
+        }
+
+        fn validate(
+            &self,
+            _: cedar_policy_validator::ValidatorSchema,
+            _: &cedar_policy_core::ast::PolicySet,
+        ) -> IntegrationTestValidationResult {
+            IntegrationTestValidationResult {
+                validation_passed: false,
+                validation_errors_debug: "denied".to_string(),
+            }
+        }
+    }
+
+    #[test]
+    fn custom_implementation() {
+        let dir = tempfile::tempdir().unwrap();
+        let requests = format!(
+            "[{}]",
+            request("small approval", "100", "Allow", r#"["policy1"]"#)
+        );
+        let path = approvals(dir.path(), &requests);
+        let test = TestCase::from_file(&path).unwrap();
+        let inputs = test.load_inputs(dir.path()).unwrap();
+        assert!(test.run_inputs(&inputs, None).passed());
+        let report = test.run_inputs(&inputs, Some(&DenyAll));
+        assert_eq!(
+            report.validation,
+            Some(Mismatch::Validation {
+                expected: true,
+                errors: vec!["denied".to_string()],
+            })
+        );
+        assert_eq!(
+            report.to_string(),
+            "expected the policies to validate, but they did not: denied\n\
+             request `small approval`: decision: expected Allow, got Deny; \
+             reasons: missing `policy1`\n"
+        );
+    }
+
+    #[test]
+    fn bad_request() {
+        let dir = tempfile::tempdir().unwrap();
+        let requests = format!(
+            "[{}]",
+            request("not a u256", "100", "Allow", r#"["policy1"]"#)
+                .replace(r#"{ "__extn": { "fn": "u256", "arg": "100" } }"#, "true")
+        );
+        let report = run_test_file(approvals(dir.path(), &requests)).unwrap();
+        assert!(matches!(
+            &report.requests.first().unwrap().outcome,
+            Err(ConformanceError::Request {
+                part: "context",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = approvals(dir.path(), "[]");
+        std::fs::remove_file(dir.path().join("entities.json")).unwrap();
+        assert!(matches!(
+            run_test_file(path),
+            Err(ConformanceError::Io { .. })
+        ));
+    }
+}
@@ -0,0 +1,279 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Schema fragments declaring the standard contexts of requests, so that
+//! every integrator validates policies against the same shapes.
+//!
+//! The fragment, [`SCHEMA`], declares a common type in the namespace
+//! [`NAMESPACE`] for each [`ContextSchema`]:
+//! * `TransactionContext`, for a transaction: its `chainId`, `from`, `to`
+//!   (absent for a deployment), `value`, `data`, `nonce` and fees, the
+//!   `function` called and the `category` of the transaction, if known, and
+//!   the `timestamp` of the block, in seconds since the Unix epoch,
+//! * `UserOperationContext`, for an ERC-4337 user operation: its `chainId`,
+//!   `entryPoint`, and the fields of the operation but its signature,
+//! * `SimulationContext`, for the result of simulating a transaction: the
+//!   `simulation` record the methods of the simulation extension are called
+//!   on, and
+//! * `SiweContext`, for a Sign-In with Ethereum (EIP-4361) message: its
+//!   fields, with its times in seconds since the Unix epoch.
+//!
+//! Addresses are `address`es, amounts `u256`s and byte strings `bytes`.
+//! The namespace holds the [`VERSION`] of the shapes, so a schema keeps
+//! validating against the shapes it was written for when they change.
+//!
+//! A schema in the human-readable format imports the fragment by being parsed
+//! together with [`SCHEMA`], and a schema in the JSON format by being combined
+//! with [`fragment`] with [`Schema::from_schema_fragments`], referring to a
+//! context by its [`ContextSchema::type_name`]:
+//! ```
+//! # use cedar_policy::context_schemas::{self, ContextSchema};
+//! # use cedar_policy::{Schema, SchemaFragment};
+//! let schema = Schema::from_str_natural(&format!(
+//!     "{}
+//!     entity Account;
+//!     action transfer appliesTo {{
+//!         principal: Account,
+//!         resource: Account,
+//!         context: {}
+//!     }};",
+//!     context_schemas::SCHEMA,
+//!     ContextSchema::Transaction.type_name(),
+//! ))
+//! .unwrap();
+//!
+//! let fragment = SchemaFragment::from_json_value(serde_json::json!({
+//!     "": {
+//!         "entityTypes": { "Account": {} },
+//!         "actions": {
+//!             "login": {
+//!                 "appliesTo": {
+//!                     "principalTypes": ["Account"],
+//!                     "resourceTypes": ["Account"],
+//!                     "context": { "type": ContextSchema::Siwe.type_name() }
+//!                 }
+//!             }
+//!         }
+//!     }
+//! }))
+//! .unwrap();
+//! let schema = Schema::from_schema_fragments([fragment, context_schemas::fragment()]).unwrap();
+//! ```
+
+use std::fmt::{self, Display};
+
+#[cfg(doc)]
+use crate::Schema;
+use crate::SchemaFragment;
+
+/// The version of the shapes declared by [`SCHEMA`]
+pub const VERSION: u32 = 1;
+
+/// The namespace of the common types declared by [`SCHEMA`]
+pub const NAMESPACE: &str = "Ethereum::V1";
+
+/// The schema fragment declaring the standard contexts, in the human-readable
+/// schema format
+pub const SCHEMA: &str = r"namespace Ethereum::V1 {
+    type TransactionContext = {
+        chainId: Long,
+        from: address,
+        to?: address,
+        value: u256,
+        data: bytes,
+        nonce: Long,
+        gasLimit: u256,
+        maxFeePerGas?: u256,
+        maxPriorityFeePerGas?: u256,
+        function?: String,
+        category?: String,
+        timestamp: Long,
+    };
+    type UserOperationContext = {
+        chainId: Long,
+        entryPoint: address,
+        sender: address,
+        nonce: u256,
+        initCode: bytes,
+        callData: bytes,
+        callGasLimit: u256,
+        verificationGasLimit: u256,
+        preVerificationGas: u256,
+        maxFeePerGas: u256,
+        maxPriorityFeePerGas: u256,
+        paymasterAndData: bytes,
+    };
+    type SimulationContext = {
+        simulation: {
+            balanceChanges: Set<{ token: address, received: u256, sent: u256 }>,
+            events: Set<{ contract: address, topic0: bytes }>,
+        },
+    };
+    type SiweContext = {
+        domain: String,
+        address: address,
+        statement?: String,
+        uri: String,
+        version: String,
+        chainId: Long,
+        nonce: String,
+        issuedAt: Long,
+        expirationTime?: Long,
+        notBefore?: Long,
+        requestId?: String,
+        resources: Set<String>,
+    };
+}
+";
+
+/// The standard contexts declared by [`SCHEMA`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ContextSchema {
+    /// The context of a transaction
+    Transaction,
+    /// The context of an ERC-4337 user operation
+    UserOperation,
+    /// The context holding the result of simulating a transaction
+    Simulation,
+    /// The context of a Sign-In with Ethereum message
+    Siwe,
+}
+
+impl ContextSchema {
+    /// All the standard contexts
+    pub const ALL: [Self; 4] = [
+        Self::Transaction,
+        Self::UserOperation,
+        Self::Simulation,
+        Self::Siwe,
+    ];
+
+    /// The name of the common type of the context, without its namespace
+    pub fn basename(self) -> &'static str {
+        match self {
+            Self::Transaction => "TransactionContext",
+            Self::UserOperation => "UserOperationContext",
+            Self::Simulation => "SimulationContext",
+            Self::Siwe => "SiweContext",
+        }
+    }
+
+    /// The fully qualified name of the common type of the context, like
+    /// `Ethereum::V1::TransactionContext`
+    pub fn type_name(self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for ContextSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{NAMESPACE}::{}", self.basename())
+    }
+}
+
+/// The schema fragment declaring the standard contexts, [`SCHEMA`] parsed
+///
+/// # Panics
+///
+/// Never, since [`SCHEMA`] parses.
+// PANIC SAFETY: `SCHEMA` is a valid schema fragment, as tested below
+#[allow(clippy::expect_used)]
+pub fn fragment() -> SchemaFragment {
+    SchemaFragment::from_str_natural(SCHEMA).expect("the standard contexts should parse")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Context, EntityUid, Schema};
+    use std::str::FromStr;
+
+    /// A schema with an action `act` whose context is `context`
+    fn schema_with_context(context: ContextSchema) -> Schema {
+        Schema::from_str_natural(&format!(
+            "{SCHEMA}
+            entity Account;
+            action act appliesTo {{ principal: Account, resource: Account, context: {context} }};"
+        ))
+        .expect("should be a valid schema")
+    }
+
+    /// `context` parsed as the context of `act`
+    fn context(context: serde_json::Value, schema: &Schema) -> Result<Context, String> {
+        let action = EntityUid::from_str(r#"Action::"act""#).expect("valid UID");
+        Context::from_json_value(context, Some((schema, &action))).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn fragment_declares_every_context() {
+        let fragment = fragment();
+        let json = fragment.to_json_value().expect("should serialize");
+        for context in ContextSchema::ALL {
+            assert!(
+                json.pointer(&format!("/{NAMESPACE}/commonTypes/{}", context.basename()))
+                    .is_some(),
+                "missing {context}"
+            );
+        }
+        assert!(Schema::from_schema_fragments([fragment]).is_ok());
+        assert_eq!(NAMESPACE, format!("Ethereum::V{VERSION}"));
+    }
+
+    #[test]
+    fn contexts_validate() {
+        let schema = schema_with_context(ContextSchema::Transaction);
+        let tx = serde_json::json!({
+            "chainId": 1,
+            "from": { "__extn": { "fn": "address", "arg": "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed" } },
+            "value": { "__extn": { "fn": "u256", "arg": "1000" } },
+            "data": { "__extn": { "fn": "bytes", "arg": "0x" } },
+            "nonce": 0,
+            "gasLimit": { "__extn": { "fn": "u256", "arg": "21000" } },
+            "timestamp": 1700000000
+        });
+        assert!(context(tx.clone(), &schema).is_ok());
+        let mut wrong = tx;
+        wrong["value"] = serde_json::json!(1000);
+        assert!(context(wrong, &schema).is_err());
+
+        let schema = schema_with_context(ContextSchema::Simulation);
+        let simulation = serde_json::json!({
+            "simulation": {
+                "balanceChanges": [{
+                    "token": { "__extn": { "fn": "address", "arg": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48" } },
+                    "received": { "__extn": { "fn": "u256", "arg": "5" } },
+                    "sent": { "__extn": { "fn": "u256", "arg": "0" } }
+                }],
+                "events": []
+            }
+        });
+        assert!(context(simulation, &schema).is_ok());
+
+        let schema = schema_with_context(ContextSchema::Siwe);
+        let siwe = serde_json::json!({
+            "domain": "example.com",
+            "address": { "__extn": { "fn": "address", "arg": "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed" } },
+            "uri": "https://example.com/login",
+            "version": "1",
+            "chainId": 1,
+            "nonce": "32891756",
+            "issuedAt": 1700000000,
+            "resources": []
+        });
+        assert!(context(siwe, &schema).is_ok());
+        assert!(context(serde_json::json!({ "domain": "example.com" }), &schema).is_err());
+    }
+}
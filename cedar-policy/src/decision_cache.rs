@@ -0,0 +1,429 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Caching authorization decisions, so that a request which is repeated, such
+//! as a wallet polling the same contract, is only evaluated once for a given
+//! state of the policies and entities.
+//!
+//! The caller names that state with a version, such as the hash of the policy
+//! set and the block number the entities were read at; a decision is only
+//! reused for the same request at the same version. Decisions are kept in a
+//! [`CacheStore`], in memory by default, or shared by a fleet of instances
+//! with a store such as Redis.
+//!
+//! Only responses without errors are cached, since errors cannot be rebuilt
+//! from the store.
+//!
+//! Anyone who can write to a shared store could otherwise decide the requests
+//! of every instance, so a store which is not trusted as much as the policies
+//! should be used with a MAC key (see [`DecisionCache::with_mac_key`]), which
+//! the cached decisions are authenticated with.
+//!
+//! ```
+//! # use cedar_policy::{decision_cache::DecisionCache, Context, Decision, Entities, PolicySet, Request};
+//! # use std::time::Duration;
+//! let cache = DecisionCache::new(Duration::from_secs(12));
+//! let policies: PolicySet = "permit(principal, action, resource);".parse().unwrap();
+//! let request = Request::new(None, None, None, Context::empty());
+//! let response = cache.is_authorized(&request, &policies, &Entities::empty(), "block-1");
+//! // the decision is reused at the same version, even if the policies changed,
+//! // and made again at a new one
+//! let forbid: PolicySet = "forbid(principal, action, resource);".parse().unwrap();
+//! let cached = cache.is_authorized(&request, &forbid, &Entities::empty(), "block-1");
+//! assert_eq!(cached.decision(), response.decision());
+//! let fresh = cache.is_authorized(&request, &forbid, &Entities::empty(), "block-2");
+//! assert_eq!(fresh.decision(), Decision::Deny);
+//! ```
+
+use std::{
+    collections::HashSet,
+    fmt::{self, Debug},
+    sync::{PoisonError, RwLock},
+    time::Duration,
+};
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::canonical;
+use crate::entity_cache::{CacheStore, MemoryStore, StoreError};
+use crate::{Authorizer, Decision, Entities, PolicyId, PolicySet, Request, Response};
+
+/// The prefix of the keys of cached decisions in the store
+const KEY_PREFIX: &str = "decision:";
+
+/// A cached response
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedDecision {
+    allow: bool,
+    reason: HashSet<PolicyId>,
+}
+
+/// A cached decision which is not authenticated by the MAC key of a
+/// [`DecisionCache`], as reported to its error listeners. The request is then
+/// authorized again.
+#[derive(Debug, Error)]
+#[error("the cached decision `{0}` is not authenticated by the MAC key")]
+pub struct InvalidMac(String);
+
+type Listener<T> = Box<dyn Fn(&T) + Send + Sync>;
+
+/// Caches the decisions of an [`Authorizer`]. See the
+/// [module documentation](self) for when decisions are reused.
+pub struct DecisionCache {
+    authorizer: Authorizer,
+    ttl: Duration,
+    store: Box<dyn CacheStore>,
+    mac_key: Option<Vec<u8>>,
+    error_listeners: RwLock<Vec<Listener<StoreError>>>,
+}
+
+impl DecisionCache {
+    /// Cache decisions for `ttl`, in a [`MemoryStore`]
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            authorizer: Authorizer::new(),
+            ttl,
+            store: Box::new(MemoryStore::new()),
+            mac_key: None,
+            error_listeners: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Keep the cached decisions in `store` instead of in memory
+    #[must_use]
+    pub fn with_store(mut self, store: impl CacheStore + 'static) -> Self {
+        self.store = Box::new(store);
+        self
+    }
+
+    /// Authenticate the cached decisions with an HMAC-SHA256 keyed by `key`,
+    /// shared by the instances using the store. A decision in the store which
+    /// was not cached with the key, e.g., one written by an attacker with
+    /// access to the store, is reported as an [`InvalidMac`] and not reused.
+    /// Without a key, the store must be trusted.
+    #[must_use]
+    pub fn with_mac_key(mut self, key: impl Into<Vec<u8>>) -> Self {
+        self.mac_key = Some(key.into());
+        self
+    }
+
+    /// Call `listener` with the errors of the store. Requests are still
+    /// authorized when the store fails, without the cache.
+    pub fn on_error(&self, listener: impl Fn(&StoreError) + Send + Sync + 'static) {
+        self.error_listeners
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Box::new(listener));
+    }
+
+    /// Authorize `request` against `policies` and `entities`, reusing the
+    /// decision for the same request at the same `version` of them if it is
+    /// cached
+    pub fn is_authorized(
+        &self,
+        request: &Request,
+        policies: &PolicySet,
+        entities: &Entities,
+        version: &str,
+    ) -> Response {
+        let key = key(request, version);
+        match self.store.get(&key) {
+            Ok(Some(value)) => match self.open(&key, &value) {
+                Ok(cached) => return response(cached),
+                Err(error) => self.report(&error),
+            },
+            Ok(None) => (),
+            Err(error) => self.report(&error),
+        }
+        let response = self.authorizer.is_authorized(request, policies, entities);
+        if response.diagnostics().errors().next().is_none() {
+            let cached = CachedDecision {
+                allow: response.decision() == Decision::Allow,
+                reason: response.diagnostics().reason().cloned().collect(),
+            };
+            if let Err(error) = serde_json::to_string(&cached)
+                .map_err(StoreError::from)
+                .and_then(|json| self.store.set(&key, &self.seal(&key, &json), self.ttl))
+            {
+                self.report(&error);
+            }
+        }
+        response
+    }
+
+    /// Forget every cached decision
+    ///
+    /// # Errors
+    ///
+    /// If the store fails.
+    pub fn clear(&self) -> Result<(), StoreError> {
+        for key in self.store.keys(KEY_PREFIX)? {
+            self.store.delete(&key)?;
+        }
+        Ok(())
+    }
+
+    /// The value stored for the decision `json` at `key`: with a MAC key, the
+    /// hex MAC of both, then `:`, then `json`
+    fn seal(&self, key: &str, json: &str) -> String {
+        self.mac_key.as_ref().map_or_else(
+            || json.to_string(),
+            |mac_key| {
+                let tag = mac(mac_key, key, json).finalize().into_bytes();
+                format!("{}:{json}", hex::encode(tag))
+            },
+        )
+    }
+
+    /// The decision in `value`, stored at `key`, checking its MAC
+    fn open(&self, key: &str, value: &str) -> Result<CachedDecision, StoreError> {
+        let json = match &self.mac_key {
+            Some(mac_key) => {
+                let invalid = || InvalidMac(key.to_string());
+                let (tag, json) = value.split_once(':').ok_or_else(invalid)?;
+                let tag = hex::decode(tag).map_err(|_| invalid())?;
+                mac(mac_key, key, json)
+                    .verify_slice(&tag)
+                    .map_err(|_| invalid())?;
+                json
+            }
+            None => value,
+        };
+        Ok(serde_json::from_str(json)?)
+    }
+
+    fn report(&self, error: &StoreError) {
+        for listener in self
+            .error_listeners
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+        {
+            listener(error);
+        }
+    }
+}
+
+impl Debug for DecisionCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DecisionCache")
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The key of the decision for `request` at `version` in the store
+fn key(request: &Request, version: &str) -> String {
+    // a context which cannot be converted, with an invalid extension value,
+    // is keyed as it is displayed instead
+    let request = canonical::request_json(request).unwrap_or_else(|_| request.0.to_string());
+    let json = serde_json::json!([version, request]);
+    format!(
+        "{KEY_PREFIX}{}",
+        hex::encode(Sha256::digest(json.to_string().as_bytes()))
+    )
+}
+
+/// The HMAC-SHA256 keyed by `mac_key` of the decision `json` at `key`, which
+/// binds the decision to its request and version
+fn mac(mac_key: &[u8], key: &str, json: &str) -> Hmac<Sha256> {
+    // PANIC SAFETY: HMAC accepts keys of any length
+    #[allow(clippy::expect_used)]
+    let mut mac = Hmac::<Sha256>::new_from_slice(mac_key).expect("HMAC accepts any key");
+    // the key is hex after its prefix, so it cannot contain the newline
+    mac.update(key.as_bytes());
+    mac.update(b"\n");
+    mac.update(json.as_bytes());
+    mac
+}
+
+fn response(cached: CachedDecision) -> Response {
+    let decision = if cached.allow {
+        Decision::Allow
+    } else {
+        Decision::Deny
+    };
+    Response::new(decision, cached.reason, Vec::new())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Context, EntityUid};
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// A [`MemoryStore`] counting the decisions set in it
+    #[derive(Default)]
+    struct Counted {
+        store: MemoryStore,
+        sets: AtomicUsize,
+    }
+
+    impl CacheStore for Counted {
+        fn get(&self, key: &str) -> Result<Option<String>, StoreError> {
+            self.store.get(key)
+        }
+
+        fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), StoreError> {
+            self.sets.fetch_add(1, Ordering::SeqCst);
+            self.store.set(key, value, ttl)
+        }
+
+        fn delete(&self, key: &str) -> Result<(), StoreError> {
+            self.store.delete(key)
+        }
+
+        fn keys(&self, prefix: &str) -> Result<Vec<String>, StoreError> {
+            self.store.keys(prefix)
+        }
+    }
+
+    /// A store which is down
+    struct Down;
+
+    impl CacheStore for Down {
+        fn get(&self, _key: &str) -> Result<Option<String>, StoreError> {
+            Err("connection refused".into())
+        }
+
+        fn set(&self, _key: &str, _value: &str, _ttl: Duration) -> Result<(), StoreError> {
+            Err("connection refused".into())
+        }
+
+        fn delete(&self, _key: &str) -> Result<(), StoreError> {
+            Err("connection refused".into())
+        }
+
+        fn keys(&self, _prefix: &str) -> Result<Vec<String>, StoreError> {
+            Err("connection refused".into())
+        }
+    }
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn request(principal: &str) -> Request {
+        Request::new(
+            Some(EntityUid::from_str(principal).expect("valid uid")),
+            None,
+            None,
+            Context::empty(),
+        )
+    }
+
+    fn policies() -> PolicySet {
+        PolicySet::from_str(r#"permit(principal == Address::"0xabc", action, resource);"#)
+            .expect("policies parse")
+    }
+
+    #[test]
+    fn reuses_decisions_at_the_same_version() {
+        let store = Arc::new(Counted::default());
+        let cache = DecisionCache::new(MINUTE).with_store(Arc::clone(&store));
+        let allowed = request(r#"Address::"0xabc""#);
+        let denied = request(r#"Address::"0xdef""#);
+        let entities = Entities::empty();
+
+        let first = cache.is_authorized(&allowed, &policies(), &entities, "1");
+        let second = cache.is_authorized(&allowed, &policies(), &entities, "1");
+        assert_eq!(first.decision(), Decision::Allow);
+        assert_eq!(second.decision(), Decision::Allow);
+        assert_eq!(
+            second.diagnostics().reason().collect::<Vec<_>>(),
+            first.diagnostics().reason().collect::<Vec<_>>()
+        );
+        assert_eq!(store.sets.load(Ordering::SeqCst), 1);
+
+        let denial = cache.is_authorized(&denied, &policies(), &entities, "1");
+        assert_eq!(denial.decision(), Decision::Deny);
+        cache.is_authorized(&allowed, &policies(), &entities, "2");
+        assert_eq!(store.sets.load(Ordering::SeqCst), 3);
+
+        cache.clear().expect("clears");
+        cache.is_authorized(&allowed, &policies(), &entities, "2");
+        assert_eq!(store.sets.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn does_not_cache_errors() {
+        let store = Arc::new(Counted::default());
+        let cache = DecisionCache::new(MINUTE).with_store(Arc::clone(&store));
+        let policies =
+            PolicySet::from_str("permit(principal, action, resource) when { principal.missing };")
+                .expect("policies parse");
+        let request = request(r#"Address::"0xabc""#);
+        let response = cache.is_authorized(&request, &policies, &Entities::empty(), "1");
+        assert_eq!(response.diagnostics().errors().count(), 1);
+        assert_eq!(store.sets.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn rejects_decisions_without_a_valid_mac() {
+        let store = Arc::new(Counted::default());
+        let cache = DecisionCache::new(MINUTE)
+            .with_store(Arc::clone(&store))
+            .with_mac_key("secret");
+        let errors = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&errors);
+        cache.on_error(move |error| {
+            assert!(error.is::<InvalidMac>());
+            counted.fetch_add(1, Ordering::SeqCst);
+        });
+        let denied = request(r#"Address::"0xdef""#);
+        let entities = Entities::empty();
+
+        cache.is_authorized(&denied, &policies(), &entities, "1");
+        let response = cache.is_authorized(&denied, &policies(), &entities, "1");
+        assert_eq!(response.decision(), Decision::Deny);
+        assert_eq!(store.sets.load(Ordering::SeqCst), 1);
+        assert_eq!(errors.load(Ordering::SeqCst), 0);
+
+        // an Allow written without the key, or moved from another request,
+        // is not reused
+        let denied_key = key(&denied, "1");
+        let allow = r#"{"allow":true,"reason":[]}"#;
+        let other = cache.seal(&key(&request(r#"Address::"0xabc""#), "1"), allow);
+        for forged in [allow.to_string(), format!("00:{allow}"), other] {
+            store.set(&denied_key, &forged, MINUTE).expect("sets");
+            let response = cache.is_authorized(&denied, &policies(), &entities, "1");
+            assert_eq!(response.decision(), Decision::Deny);
+        }
+        assert_eq!(errors.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn authorizes_when_the_store_is_down() {
+        let cache = DecisionCache::new(MINUTE).with_store(Down);
+        let errors = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&errors);
+        cache.on_error(move |_| {
+            counted.fetch_add(1, Ordering::SeqCst);
+        });
+        let response = cache.is_authorized(
+            &request(r#"Address::"0xabc""#),
+            &policies(),
+            &Entities::empty(),
+            "1",
+        );
+        assert_eq!(response.decision(), Decision::Allow);
+        // both the get and the set failed
+        assert_eq!(errors.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,443 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Authorizing the deployment of contracts, so that organizations can
+//! restrict who may deploy, and which bytecode.
+//!
+//! A [`Deployment`] is a contract-creation transaction, with `CREATE` or
+//! with `CREATE2` through a factory such as the deterministic deployment
+//! proxy. [`Deployment::context`] gives a builder of its context, holding
+//! * `deploymentMethod`, `"create"` or `"create2"`,
+//! * `initCodeHash`, the keccak256 hash of the init code, as `bytes`,
+//! * `deployedAddress`, the address the contract will have, as an `address`,
+//!   and
+//! * `salt`, for `CREATE2`, as `bytes`
+//!
+//! to which [`Deployment::constructor_args`] can add the arguments of the
+//! constructor, as `constructorArgs`, given the ABI of the contract:
+//! ```cedar
+//! permit(principal in Group::"deployers", action == Action::"deploy", resource)
+//! when {
+//!   [bytes("0x9b4e0b4b9c1b5b6cf4d3a8f5e2f8cbb1d1f0a6e5f4b7f1c0e3d2a1b0c9d8e7f6")]
+//!     .contains(context.initCodeHash)
+//! };
+//! ```
+//!
+//! ```
+//! # use cedar_policy::deployment::*;
+//! let deployer = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed".parse().unwrap();
+//! // the init code of a contract which returns no code
+//! let init_code = ethers::utils::hex::decode("600080600a8239f3").unwrap();
+//! let deployment = Deployment::from_transaction(deployer, 0, None, &init_code).unwrap();
+//! assert_eq!(deployment.method(), CreateMethod::Create { nonce: 0 });
+//! let context = deployment.context().build_context();
+//! ```
+
+use std::fmt::{self, Display};
+
+use ethers::abi::{self, Constructor, ParamType};
+use ethers::types::{Address, H256};
+use ethers::utils::{get_contract_address, get_create2_address_from_hash, keccak256};
+use thiserror::Error;
+
+use crate::abi_value::token_value;
+use crate::{RecordBuilder, RestrictedExpression};
+
+/// The attribute of the context holding how a contract is deployed
+pub const DEPLOYMENT_METHOD_ATTRIBUTE: &str = "deploymentMethod";
+/// The attribute of the context holding the hash of the init code
+pub const INIT_CODE_HASH_ATTRIBUTE: &str = "initCodeHash";
+/// The attribute of the context holding the address of the deployed contract
+pub const DEPLOYED_ADDRESS_ATTRIBUTE: &str = "deployedAddress";
+/// The attribute of the context holding the salt of a `CREATE2` deployment
+pub const SALT_ATTRIBUTE: &str = "salt";
+/// The attribute of the context holding the arguments of the constructor
+pub const CONSTRUCTOR_ARGS_ATTRIBUTE: &str = "constructorArgs";
+
+/// The deterministic deployment proxy, which deploys the init code following
+/// a 32-byte salt in its calldata with `CREATE2`
+pub const DETERMINISTIC_DEPLOYMENT_PROXY: &str = "0x4e59b44847b379578588920ca78fbf26c0b4956c";
+
+/// Errors decoding the arguments of a constructor
+#[derive(Debug, Error)]
+pub enum DeploymentError {
+    /// The init code does not begin with the bytecode of the contract
+    #[error("the init code is not of the contract")]
+    NotBytecode,
+    /// The arguments do not match the constructor
+    #[error("the constructor arguments do not match the ABI: {0}")]
+    Decode(#[from] abi::Error),
+    /// An argument has no Cedar value
+    #[error("the constructor argument `{param}` is unsupported: {reason}")]
+    Unsupported {
+        /// The name of the argument
+        param: String,
+        /// Why it is unsupported
+        reason: String,
+    },
+}
+
+/// How a contract is deployed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CreateMethod {
+    /// By a contract-creation transaction, or `CREATE`, whose address
+    /// depends on the nonce of the deployer
+    Create {
+        /// The nonce of the deployer
+        nonce: u64,
+    },
+    /// By `CREATE2`, whose address depends on a salt and the init code
+    Create2 {
+        /// The salt
+        salt: H256,
+    },
+}
+
+impl CreateMethod {
+    /// The name of this method, as held in `context.deploymentMethod`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Create { .. } => "create",
+            Self::Create2 { .. } => "create2",
+        }
+    }
+}
+
+impl Display for CreateMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The deployment of a contract
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deployment {
+    deployer: Address,
+    init_code: Vec<u8>,
+    method: CreateMethod,
+}
+
+impl Deployment {
+    /// The deployment of `init_code` by `deployer` with `CREATE`, when its
+    /// nonce is `nonce`
+    pub fn create(deployer: Address, nonce: u64, init_code: impl Into<Vec<u8>>) -> Self {
+        Self {
+            deployer,
+            init_code: init_code.into(),
+            method: CreateMethod::Create { nonce },
+        }
+    }
+
+    /// The deployment of `init_code` by the contract `factory` with
+    /// `CREATE2` and `salt`
+    pub fn create2(factory: Address, salt: H256, init_code: impl Into<Vec<u8>>) -> Self {
+        Self {
+            deployer: factory,
+            init_code: init_code.into(),
+            method: CreateMethod::Create2 { salt },
+        }
+    }
+
+    /// The deployment made by the transaction from `from` with `nonce` to
+    /// `to` with `calldata`, if it is a contract-creation transaction, or a
+    /// call of the [`DETERMINISTIC_DEPLOYMENT_PROXY`]
+    pub fn from_transaction(
+        from: Address,
+        nonce: u64,
+        to: Option<Address>,
+        calldata: &[u8],
+    ) -> Option<Self> {
+        match to {
+            None => Some(Self::create(from, nonce, calldata)),
+            Some(to) if Some(to) == DETERMINISTIC_DEPLOYMENT_PROXY.parse().ok() => {
+                let (salt, init_code) = calldata.split_first_chunk::<32>()?;
+                Some(Self::create2(to, H256(*salt), init_code))
+            }
+            Some(_) => None,
+        }
+    }
+
+    /// The account or factory deploying the contract
+    pub fn deployer(&self) -> Address {
+        self.deployer
+    }
+
+    /// The init code, which is the bytecode of the contract followed by the
+    /// ABI-encoded arguments of its constructor
+    pub fn init_code(&self) -> &[u8] {
+        &self.init_code
+    }
+
+    /// How the contract is deployed
+    pub fn method(&self) -> CreateMethod {
+        self.method
+    }
+
+    /// The keccak256 hash of the init code
+    pub fn init_code_hash(&self) -> H256 {
+        H256(keccak256(&self.init_code))
+    }
+
+    /// The address the contract will have
+    pub fn address(&self) -> Address {
+        match self.method {
+            CreateMethod::Create { nonce } => get_contract_address(self.deployer, nonce),
+            CreateMethod::Create2 { salt } => {
+                get_create2_address_from_hash(self.deployer, salt, self.init_code_hash())
+            }
+        }
+    }
+
+    /// A builder of the context of the deployment, holding its
+    /// `deploymentMethod`, `initCodeHash`, `deployedAddress` and, for
+    /// `CREATE2`, `salt`
+    pub fn context(&self) -> RecordBuilder {
+        let record = RecordBuilder::new()
+            .attr(DEPLOYMENT_METHOD_ATTRIBUTE, self.method.as_str())
+            .attr(INIT_CODE_HASH_ATTRIBUTE, self.init_code_hash())
+            .attr(DEPLOYED_ADDRESS_ATTRIBUTE, self.address());
+        match self.method {
+            CreateMethod::Create { .. } => record,
+            CreateMethod::Create2 { salt } => record.attr(SALT_ATTRIBUTE, salt),
+        }
+    }
+
+    /// The arguments of the constructor of the contract with `bytecode`, which
+    /// the init code must begin with, as a record of the arguments by name
+    /// (or by position, for unnamed arguments), for `context.constructorArgs`.
+    ///
+    /// Addresses are held as `address`es, unsigned integers as `u256`s,
+    /// signed integers as `Long`s, byte strings as `bytes`, arrays as sets,
+    /// and tuples as records of their elements by position.
+    ///
+    /// # Errors
+    ///
+    /// If the init code does not begin with `bytecode`, the rest of it does
+    /// not decode as the arguments of `constructor`, or a signed integer does
+    /// not fit in a `Long`.
+    pub fn constructor_args(
+        &self,
+        bytecode: &[u8],
+        constructor: &Constructor,
+    ) -> Result<RestrictedExpression, DeploymentError> {
+        let args = self
+            .init_code
+            .strip_prefix(bytecode)
+            .ok_or(DeploymentError::NotBytecode)?;
+        let kinds: Vec<ParamType> = constructor
+            .inputs
+            .iter()
+            .map(|param| param.kind.clone())
+            .collect();
+        let tokens = abi::decode(&kinds, args)?;
+        let fields = constructor
+            .inputs
+            .iter()
+            .zip(tokens)
+            .enumerate()
+            .map(|(index, (param, token))| {
+                let name = if param.name.is_empty() {
+                    index.to_string()
+                } else {
+                    param.name.clone()
+                };
+                let value = token_value(token).map_err(|reason| DeploymentError::Unsupported {
+                    param: name.clone(),
+                    reason,
+                })?;
+                Ok((name, value))
+            })
+            .collect::<Result<Vec<_>, DeploymentError>>()?;
+        Ok(RestrictedExpression::new_record(fields))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        eval_expression, Authorizer, Context, Decision, Entities, EvalResult, Expression,
+        PolicySet, Request,
+    };
+    use ethers::abi::{Param, Token};
+    use ethers::types::{I256, U256};
+    use std::str::FromStr;
+
+    fn address(s: &str) -> Address {
+        s.parse().expect("valid address")
+    }
+
+    /// The bytecode of a contract, which is not run
+    const BYTECODE: &[u8] = &[0x60, 0x00, 0x80, 0x60, 0x0a, 0x82, 0x39, 0xf3];
+
+    fn constructor() -> Constructor {
+        Constructor {
+            inputs: vec![
+                Param {
+                    name: "owner".to_string(),
+                    kind: ParamType::Address,
+                    internal_type: None,
+                },
+                Param {
+                    name: "supply".to_string(),
+                    kind: ParamType::Uint(256),
+                    internal_type: None,
+                },
+                Param {
+                    name: String::new(),
+                    kind: ParamType::Array(Box::new(ParamType::Int(8))),
+                    internal_type: None,
+                },
+            ],
+        }
+    }
+
+    fn init_code(owner: Address) -> Vec<u8> {
+        constructor()
+            .encode_input(
+                BYTECODE.to_vec(),
+                &[
+                    Token::Address(owner),
+                    Token::Uint(U256::from(1000)),
+                    Token::Array(vec![Token::Int(I256::from(-1).into_raw())]),
+                ],
+            )
+            .expect("encodes")
+    }
+
+    #[test]
+    fn predicted_addresses() {
+        // the first contract deployed by an account
+        let deployer = address("0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0");
+        let deployment =
+            Deployment::from_transaction(deployer, 0, None, BYTECODE).expect("deploys a contract");
+        assert_eq!(
+            deployment.address(),
+            address("0xcd234a471b72ba2f1ccf0a70fcaba648a5eecd8d")
+        );
+        assert_eq!(deployment.deployer(), deployer);
+        // the example of EIP-1014
+        let deployment = Deployment::create2(Address::zero(), H256::zero(), vec![0]);
+        assert_eq!(
+            deployment.address(),
+            address("0x4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38")
+        );
+    }
+
+    #[test]
+    fn deterministic_deployment_proxy() {
+        let proxy = address(DETERMINISTIC_DEPLOYMENT_PROXY);
+        let salt = H256::repeat_byte(1);
+        let mut calldata = salt.as_bytes().to_vec();
+        calldata.extend(BYTECODE);
+        let deployment = Deployment::from_transaction(Address::zero(), 7, Some(proxy), &calldata)
+            .expect("deploys a contract");
+        assert_eq!(deployment, Deployment::create2(proxy, salt, BYTECODE));
+        assert_eq!(deployment.method().to_string(), "create2");
+        assert_eq!(deployment.init_code_hash(), H256(keccak256(BYTECODE)));
+        assert!(deployment
+            .context()
+            .build_attrs()
+            .contains_key(SALT_ATTRIBUTE));
+        // calls of other contracts, or without a salt, deploy nothing
+        assert_eq!(
+            Deployment::from_transaction(Address::zero(), 7, Some(proxy), &[0; 31]),
+            None
+        );
+        assert_eq!(
+            Deployment::from_transaction(Address::zero(), 7, Some(Address::zero()), &calldata),
+            None
+        );
+    }
+
+    #[test]
+    fn constructor_args() {
+        let owner = address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed");
+        let deployment = Deployment::create(owner, 3, init_code(owner));
+        let args = deployment
+            .constructor_args(BYTECODE, &constructor())
+            .expect("decodes");
+        let request = Request::new(
+            None,
+            None,
+            None,
+            Context::from_pairs([(CONSTRUCTOR_ARGS_ATTRIBUTE.to_string(), args)]),
+        );
+        let expected = format!(
+            r#"context.constructorArgs == {{ owner: address("{owner:?}"), supply: u256("1000"), "2": [-1] }}"#
+        );
+        assert_eq!(
+            eval_expression(
+                &request,
+                &Entities::empty(),
+                &Expression::from_str(&expected).expect("valid expression")
+            ),
+            Ok(EvalResult::Bool(true))
+        );
+        assert!(matches!(
+            deployment.constructor_args(&[0x60, 0x01], &constructor()),
+            Err(DeploymentError::NotBytecode)
+        ));
+        assert!(matches!(
+            Deployment::create(owner, 3, BYTECODE).constructor_args(BYTECODE, &constructor()),
+            Err(DeploymentError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn restricting_deployments() {
+        let owner = address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed");
+        let permitted = Deployment::create(owner, 0, init_code(owner));
+        let policies: PolicySet = format!(
+            r#"
+            permit(principal, action == Action::"deploy", resource)
+            when {{
+                [bytes("{:?}")].contains(context.initCodeHash) &&
+                context.constructorArgs.owner == address("{owner:?}")
+            }};"#,
+            permitted.init_code_hash()
+        )
+        .parse()
+        .expect("valid policies");
+        let decision = |deployment: &Deployment| {
+            let context = deployment
+                .context()
+                .attr(
+                    CONSTRUCTOR_ARGS_ATTRIBUTE,
+                    deployment
+                        .constructor_args(BYTECODE, &constructor())
+                        .expect("decodes"),
+                )
+                .build_context();
+            let request = Request::new(
+                Some(r#"User::"alice""#.parse().expect("valid uid")),
+                Some(r#"Action::"deploy""#.parse().expect("valid uid")),
+                Some(r#"Chain::"1""#.parse().expect("valid uid")),
+                context,
+            );
+            Authorizer::new()
+                .is_authorized(&request, &policies, &Entities::empty())
+                .decision()
+        };
+        assert_eq!(decision(&permitted), Decision::Allow);
+        // the same contract, owned by someone else
+        let other = address("0xab5801a7d398351b8be11c439e05c5b3259aec9b");
+        assert_eq!(
+            decision(&Deployment::create(owner, 0, init_code(other))),
+            Decision::Deny
+        );
+    }
+}
@@ -0,0 +1,994 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Caching the entities of a provider which is slow or rate limited to call,
+//! such as an RPC node read for the `balanceOf` of every address.
+//!
+//! An [`EntityProvider`] fetches an entity as a whole, and may fetch single
+//! attributes of it more cheaply. An [`EntityCache`] keeps what its provider
+//! fetched for a [`Ttl`]: entities for the TTL the cache is made with, and
+//! attributes given their own with [`EntityCache::with_attribute_ttl`] for
+//! that, so that a `balance` can be refetched every block while the rest of
+//! its entity is kept for an hour. A value whose TTL has run out is:
+//! * used while it is refetched on a background thread, if it is still within
+//!   the `stale_while_revalidate` window of its TTL
+//! * otherwise refetched before it is used
+//!
+//! Values can also be invalidated explicitly, such as every `balance` when a
+//! new block arrives with [`EntityCache::invalidate_attributes`].
+//!
+//! [`EntityCache::load_entities`] loads the entities of a request, and their
+//! ancestors, for the [`crate::Authorizer`].
+//!
+//! Cached entities are kept in a [`CacheStore`], in memory by default. A
+//! store shared by several caches, such as Redis, shares what one of them
+//! fetched with all of them.
+//!
+//! ```
+//! # use cedar_policy::{entity_cache::*, Entity, EntityUid, RestrictedExpression};
+//! # use std::collections::{HashMap, HashSet};
+//! # use std::str::FromStr;
+//! # use std::sync::Arc;
+//! # use std::time::Duration;
+//! let cache = Arc::new(
+//!     EntityCache::new(
+//!         |uid: &EntityUid| -> Result<Option<Entity>, ProviderError> {
+//!             // e.g., call `balanceOf` on a node
+//!             let balance = RestrictedExpression::from_str("u256(\"1000\")")?;
+//!             Ok(Some(Entity::new(
+//!                 uid.clone(),
+//!                 HashMap::from([("balance".to_string(), balance)]),
+//!                 HashSet::new(),
+//!             )))
+//!         },
+//!         Ttl::new(Duration::from_secs(3600)),
+//!     )
+//!     .with_attribute_ttl(
+//!         "Address".parse().unwrap(),
+//!         "balance",
+//!         Ttl::new(Duration::from_secs(12)).stale_while_revalidate(Duration::from_secs(12)),
+//!     ),
+//! );
+//! let alice = EntityUid::from_str(r#"Address::"0xabc""#).unwrap();
+//! let entities = cache.load_entities([&alice]).unwrap();
+//! assert!(entities.get(&alice).is_some());
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Debug},
+    sync::{Arc, Mutex, PoisonError, RwLock},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use cedar_policy_core::{ast, entities::JSONValue};
+use ref_cast::RefCast;
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+use thiserror::Error;
+
+use crate::{Entities, EntitiesError, Entity, EntityTypeName, EntityUid, RestrictedExpression};
+
+/// The error of an [`EntityProvider`]
+pub type ProviderError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The error of a [`CacheStore`]
+pub type StoreError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Errors loading entities through an [`EntityCache`]
+#[derive(Debug, Error)]
+pub enum EntityCacheError {
+    /// The provider failed to fetch an entity or attribute
+    #[error("failed to fetch `{uid}`: {error}")]
+    Provider {
+        /// The entity
+        uid: EntityUid,
+        /// The error of the provider
+        error: ProviderError,
+    },
+    /// The store failed to get or set a cached entity
+    #[error("cache store failed: {0}")]
+    Store(StoreError),
+    /// A cached entity could not be encoded for the store, or decoded from it
+    #[error("failed to encode or decode `{key}`: {error}")]
+    Encoding {
+        /// The key of the entity in the store
+        key: String,
+        /// The error encoding or decoding it
+        error: StoreError,
+    },
+    /// The entities loaded do not make an `Entities`
+    #[error(transparent)]
+    Entities(#[from] EntitiesError),
+}
+
+/// Where the entities of an [`EntityCache`] come from, such as a database or
+/// the view functions of contracts
+///
+/// Functions from an [`EntityUid`] to an entity are providers.
+pub trait EntityProvider: Send + Sync {
+    /// Fetch the entity `uid`, or `None` if there is no such entity
+    ///
+    /// # Errors
+    ///
+    /// If the entity cannot be fetched.
+    fn entity(&self, uid: &EntityUid) -> Result<Option<Entity>, ProviderError>;
+
+    /// Fetch the attribute `attr` of the entity `uid`, or `None` if the
+    /// entity has no such attribute. By default, this fetches the whole
+    /// entity; providers which can fetch one attribute more cheaply, such as
+    /// with a single contract call, should override it.
+    ///
+    /// # Errors
+    ///
+    /// If the attribute cannot be fetched.
+    fn attribute(
+        &self,
+        uid: &EntityUid,
+        attr: &str,
+    ) -> Result<Option<RestrictedExpression>, ProviderError> {
+        Ok(self
+            .entity(uid)?
+            .and_then(|entity| entity.0.get(attr).cloned().map(RestrictedExpression)))
+    }
+}
+
+impl<F> EntityProvider for F
+where
+    F: Fn(&EntityUid) -> Result<Option<Entity>, ProviderError> + Send + Sync,
+{
+    fn entity(&self, uid: &EntityUid) -> Result<Option<Entity>, ProviderError> {
+        self(uid)
+    }
+}
+
+/// Where cached values are kept, as strings under string keys
+///
+/// Stores may drop a value once its TTL has run out, or earlier; the caches
+/// check how fresh what they get is themselves.
+pub trait CacheStore: Send + Sync {
+    /// The value at `key`, or `None` if there is none
+    ///
+    /// # Errors
+    ///
+    /// If the store cannot be read.
+    fn get(&self, key: &str) -> Result<Option<String>, StoreError>;
+
+    /// Set the value at `key` to `value`, to be kept for at least `ttl`
+    ///
+    /// # Errors
+    ///
+    /// If the store cannot be written.
+    fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), StoreError>;
+
+    /// Remove the value at `key`, if there is one
+    ///
+    /// # Errors
+    ///
+    /// If the store cannot be written.
+    fn delete(&self, key: &str) -> Result<(), StoreError>;
+
+    /// The keys starting with `prefix`
+    ///
+    /// # Errors
+    ///
+    /// If the store cannot be read.
+    fn keys(&self, prefix: &str) -> Result<Vec<String>, StoreError>;
+}
+
+impl<S: CacheStore + ?Sized> CacheStore for Arc<S> {
+    fn get(&self, key: &str) -> Result<Option<String>, StoreError> {
+        (**self).get(key)
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), StoreError> {
+        (**self).set(key, value, ttl)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), StoreError> {
+        (**self).delete(key)
+    }
+
+    fn keys(&self, prefix: &str) -> Result<Vec<String>, StoreError> {
+        (**self).keys(prefix)
+    }
+}
+
+/// A [`CacheStore`] in the memory of this process
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    /// The values, and when they expire, if ever
+    values: Mutex<HashMap<String, (String, Option<Instant>)>>,
+}
+
+impl MemoryStore {
+    /// An empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn values(&self) -> std::sync::MutexGuard<'_, HashMap<String, (String, Option<Instant>)>> {
+        let mut values = self.values.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        values.retain(|_, (_, expires)| expires.is_none_or(|expires| expires > now));
+        values
+    }
+}
+
+impl CacheStore for MemoryStore {
+    fn get(&self, key: &str) -> Result<Option<String>, StoreError> {
+        Ok(self.values().get(key).map(|(value, _)| value.clone()))
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), StoreError> {
+        let expires = Instant::now().checked_add(ttl);
+        self.values()
+            .insert(key.to_string(), (value.to_string(), expires));
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), StoreError> {
+        self.values().remove(key);
+        Ok(())
+    }
+
+    fn keys(&self, prefix: &str) -> Result<Vec<String>, StoreError> {
+        Ok(self
+            .values()
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+}
+
+/// How long a cached value is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ttl {
+    /// How long the value is used after it is fetched
+    pub fresh: Duration,
+    /// How long after that the value is still used while it is refetched in
+    /// the background
+    pub stale_while_revalidate: Duration,
+}
+
+impl Ttl {
+    /// Use values for `fresh`, and refetch them before using them after that
+    pub fn new(fresh: Duration) -> Self {
+        Self {
+            fresh,
+            stale_while_revalidate: Duration::ZERO,
+        }
+    }
+
+    /// Also use values for `window` after they stop being fresh, while they
+    /// are refetched in the background
+    #[must_use]
+    pub fn stale_while_revalidate(self, window: Duration) -> Self {
+        Self {
+            stale_while_revalidate: window,
+            ..self
+        }
+    }
+
+    /// How long the value is used for at all
+    fn total(self) -> Duration {
+        self.fresh.saturating_add(self.stale_while_revalidate)
+    }
+}
+
+/// How usable a cached value is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Freshness {
+    Fresh,
+    /// Usable while it is refetched in the background
+    Stale,
+    /// To be refetched before it is used
+    Expired,
+}
+
+impl Freshness {
+    /// How usable a value fetched at `fetched`, or invalidated if `None`, is
+    /// with `ttl`
+    fn of(fetched: Option<u64>, ttl: Ttl) -> Self {
+        match fetched.map(|fetched| Duration::from_millis(now().saturating_sub(fetched))) {
+            Some(age) if age < ttl.fresh => Self::Fresh,
+            Some(age) if age < ttl.total() => Self::Stale,
+            _ => Self::Expired,
+        }
+    }
+}
+
+/// The milliseconds since the Unix epoch, which unlike an [`Instant`] mean
+/// the same to every process sharing a store
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| {
+            u64::try_from(since.as_millis()).unwrap_or(u64::MAX)
+        })
+}
+
+/// A cached attribute, or its absence
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedAttr {
+    value: Option<JSONValue>,
+    /// `None` once invalidated
+    fetched: Option<u64>,
+}
+
+/// A cached entity, or its absence, as it is kept in the store. Values are
+/// kept in the JSON format of entities, which keeps extension values such as
+/// `u256`s as they are.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedEntity {
+    /// Whether the provider has the entity
+    exists: bool,
+    /// `None` once invalidated
+    fetched: Option<u64>,
+    parents: HashSet<ast::EntityUID>,
+    tags: HashMap<SmolStr, JSONValue>,
+    attrs: HashMap<SmolStr, CachedAttr>,
+}
+
+impl CachedEntity {
+    fn new(entity: Option<Entity>) -> Result<Self, StoreError> {
+        let fetched = Some(now());
+        Ok(match entity {
+            None => Self {
+                exists: false,
+                fetched,
+                parents: HashSet::new(),
+                tags: HashMap::new(),
+                attrs: HashMap::new(),
+            },
+            Some(Entity(entity)) => Self {
+                exists: true,
+                fetched,
+                parents: entity.ancestors().cloned().collect(),
+                tags: entity
+                    .tags()
+                    .map(|(key, value)| Ok((key.into(), JSONValue::from_expr(value)?)))
+                    .collect::<Result<_, StoreError>>()?,
+                attrs: entity
+                    .attrs()
+                    .map(|(key, value)| {
+                        let value = Some(JSONValue::from_expr(value)?);
+                        Ok((key.into(), CachedAttr { value, fetched }))
+                    })
+                    .collect::<Result<_, StoreError>>()?,
+            },
+        })
+    }
+
+    /// The entity `uid`, or `None` if the provider has no such entity
+    fn entity(&self, uid: &EntityUid) -> Result<Option<Entity>, StoreError> {
+        if !self.exists {
+            return Ok(None);
+        }
+        let attrs = self
+            .attrs
+            .iter()
+            .filter_map(|(key, attr)| Some((key, attr.value.clone()?)))
+            .map(|(key, value)| Ok((key.clone(), value.into_expr()?)))
+            .collect::<Result<_, StoreError>>()?;
+        let tags = self
+            .tags
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), value.clone().into_expr()?)))
+            .collect::<Result<_, StoreError>>()?;
+        Ok(Some(Entity(ast::Entity::new_with_tags(
+            uid.0.clone(),
+            attrs,
+            self.parents.clone(),
+            tags,
+        ))))
+    }
+}
+
+/// The prefix of the keys of cached entities in the store
+const KEY_PREFIX: &str = "entity:";
+
+/// The key of the cached entity `uid` in the store
+fn key(uid: &EntityUid) -> String {
+    format!("{KEY_PREFIX}{uid}")
+}
+
+/// What a background refresh fetches: an entity, or one of its attributes
+type RefreshKey = (EntityUid, Option<SmolStr>);
+
+type Listener<T> = Box<dyn Fn(&T) + Send + Sync>;
+
+/// Caches the entities of an [`EntityProvider`]. See the
+/// [module documentation](self) for when values are refetched.
+pub struct EntityCache {
+    provider: Box<dyn EntityProvider>,
+    ttl: Ttl,
+    attribute_ttls: HashMap<(EntityTypeName, SmolStr), Ttl>,
+    store: Box<dyn CacheStore>,
+    /// The refreshes running in the background, so that each value is only
+    /// refreshed once at a time
+    refreshing: Mutex<HashSet<RefreshKey>>,
+    error_listeners: RwLock<Vec<Listener<EntityCacheError>>>,
+}
+
+impl EntityCache {
+    /// Cache the entities of `provider`, and their attributes, for `ttl`, in
+    /// a [`MemoryStore`]
+    pub fn new(provider: impl EntityProvider + 'static, ttl: Ttl) -> Self {
+        Self {
+            provider: Box::new(provider),
+            ttl,
+            attribute_ttls: HashMap::new(),
+            store: Box::new(MemoryStore::new()),
+            refreshing: Mutex::new(HashSet::new()),
+            error_listeners: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Cache the attribute `attr` of entities of type `entity_type` for
+    /// `ttl`, refetching it with [`EntityProvider::attribute`] when that runs
+    /// out. The attribute is also refetched along with its entity.
+    #[must_use]
+    pub fn with_attribute_ttl(
+        mut self,
+        entity_type: EntityTypeName,
+        attr: impl Into<SmolStr>,
+        ttl: Ttl,
+    ) -> Self {
+        self.attribute_ttls.insert((entity_type, attr.into()), ttl);
+        self
+    }
+
+    /// Keep the cached entities in `store` instead of in memory
+    #[must_use]
+    pub fn with_store(mut self, store: impl CacheStore + 'static) -> Self {
+        self.store = Box::new(store);
+        self
+    }
+
+    /// Call `listener` with the errors of background refreshes, which have no
+    /// caller to return them to. The stale value is kept until it expires.
+    pub fn on_error(&self, listener: impl Fn(&EntityCacheError) + Send + Sync + 'static) {
+        self.error_listeners
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Box::new(listener));
+    }
+
+    /// The entity `uid`, or `None` if the provider has no such entity,
+    /// fetching what is not cached or has expired
+    ///
+    /// # Errors
+    ///
+    /// If the provider fails to fetch what has to be fetched now, or the
+    /// store fails.
+    pub fn entity(self: &Arc<Self>, uid: &EntityUid) -> Result<Option<Entity>, EntityCacheError> {
+        let key = key(uid);
+        let cached = self.cached(&key)?;
+        let mut cached = match cached
+            .as_ref()
+            .map(|cached| Freshness::of(cached.fetched, self.ttl))
+        {
+            Some(Freshness::Fresh) => cached,
+            Some(Freshness::Stale) => {
+                self.refresh_in_background(uid, None);
+                cached
+            }
+            Some(Freshness::Expired) | None => None,
+        }
+        .map_or_else(|| self.fetch_entity(uid), Ok)?;
+        let attrs: Vec<_> = cached
+            .attrs
+            .iter()
+            .filter_map(
+                |(attr, cached_attr)| match self.attribute_ttl(uid.type_name(), attr) {
+                    Some(ttl) => Some((attr.clone(), Freshness::of(cached_attr.fetched, ttl))),
+                    None if cached_attr.fetched.is_none() => {
+                        Some((attr.clone(), Freshness::Expired))
+                    }
+                    None => None,
+                },
+            )
+            .collect();
+        for (attr, freshness) in attrs {
+            match freshness {
+                Freshness::Fresh => (),
+                Freshness::Stale => self.refresh_in_background(uid, Some(attr)),
+                Freshness::Expired => {
+                    let cached_attr = self.fetch_attribute(uid, &attr)?;
+                    cached.attrs.insert(attr, cached_attr);
+                }
+            }
+        }
+        cached
+            .entity(uid)
+            .map_err(|error| EntityCacheError::Encoding { key, error })
+    }
+
+    /// The entities `uids`, and all their ancestors, leaving out those the
+    /// provider does not have
+    ///
+    /// # Errors
+    ///
+    /// If the provider fails to fetch what has to be fetched now, the store
+    /// fails, or the hierarchy of the entities is cyclic.
+    pub fn load_entities<'a>(
+        self: &Arc<Self>,
+        uids: impl IntoIterator<Item = &'a EntityUid>,
+    ) -> Result<Entities, EntityCacheError> {
+        let mut pending: Vec<EntityUid> = uids.into_iter().cloned().collect();
+        let mut seen: HashSet<EntityUid> = pending.iter().cloned().collect();
+        let mut entities = Vec::new();
+        while let Some(uid) = pending.pop() {
+            if let Some(entity) = self.entity(&uid)? {
+                for parent in entity.0.ancestors() {
+                    let parent = EntityUid::ref_cast(parent);
+                    if seen.insert(parent.clone()) {
+                        pending.push(parent.clone());
+                    }
+                }
+                entities.push(entity);
+            }
+        }
+        Ok(Entities::from_entities(entities)?)
+    }
+
+    /// Refetch the entity `uid`, and all its attributes, before it is next
+    /// used
+    ///
+    /// # Errors
+    ///
+    /// If the store fails.
+    pub fn invalidate(&self, uid: &EntityUid) -> Result<(), EntityCacheError> {
+        self.store
+            .delete(&key(uid))
+            .map_err(EntityCacheError::Store)
+    }
+
+    /// Refetch the attribute `attr` of the entity `uid` before it is next
+    /// used
+    ///
+    /// # Errors
+    ///
+    /// If the store fails.
+    pub fn invalidate_attribute(
+        &self,
+        uid: &EntityUid,
+        attr: &str,
+    ) -> Result<(), EntityCacheError> {
+        self.invalidate_attribute_at(&key(uid), attr)
+    }
+
+    /// Refetch the attribute `attr` of every entity of type `entity_type`
+    /// before it is next used, such as the balances of addresses when a new
+    /// block arrives
+    ///
+    /// # Errors
+    ///
+    /// If the store fails.
+    pub fn invalidate_attributes(
+        &self,
+        entity_type: &EntityTypeName,
+        attr: &str,
+    ) -> Result<(), EntityCacheError> {
+        let prefix = format!("{KEY_PREFIX}{entity_type}::\"");
+        for key in self.store.keys(&prefix).map_err(EntityCacheError::Store)? {
+            self.invalidate_attribute_at(&key, attr)?;
+        }
+        Ok(())
+    }
+
+    /// Refetch every entity before it is next used
+    ///
+    /// # Errors
+    ///
+    /// If the store fails.
+    pub fn clear(&self) -> Result<(), EntityCacheError> {
+        for key in self
+            .store
+            .keys(KEY_PREFIX)
+            .map_err(EntityCacheError::Store)?
+        {
+            self.store.delete(&key).map_err(EntityCacheError::Store)?;
+        }
+        Ok(())
+    }
+
+    fn invalidate_attribute_at(&self, key: &str, attr: &str) -> Result<(), EntityCacheError> {
+        if let Some(mut cached) = self.cached(key)? {
+            if let Some(cached_attr) = cached.attrs.get_mut(attr) {
+                cached_attr.fetched = None;
+                self.save(key, &cached)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn cached(&self, key: &str) -> Result<Option<CachedEntity>, EntityCacheError> {
+        self.store
+            .get(key)
+            .map_err(EntityCacheError::Store)?
+            .map(|json| {
+                serde_json::from_str(&json).map_err(|error| EntityCacheError::Encoding {
+                    key: key.to_string(),
+                    error: error.into(),
+                })
+            })
+            .transpose()
+    }
+
+    fn save(&self, key: &str, cached: &CachedEntity) -> Result<(), EntityCacheError> {
+        let json = serde_json::to_string(cached).map_err(|error| EntityCacheError::Encoding {
+            key: key.to_string(),
+            error: error.into(),
+        })?;
+        self.store
+            .set(key, &json, self.ttl.total())
+            .map_err(EntityCacheError::Store)
+    }
+
+    fn attribute_ttl(&self, entity_type: &EntityTypeName, attr: &str) -> Option<Ttl> {
+        self.attribute_ttls
+            .get(&(entity_type.clone(), SmolStr::new(attr)))
+            .copied()
+    }
+
+    /// Fetch the entity `uid`, and cache it
+    fn fetch_entity(&self, uid: &EntityUid) -> Result<CachedEntity, EntityCacheError> {
+        let entity = self
+            .provider
+            .entity(uid)
+            .map_err(|error| EntityCacheError::Provider {
+                uid: uid.clone(),
+                error,
+            })?;
+        let key = key(uid);
+        let cached = CachedEntity::new(entity).map_err(|error| EntityCacheError::Encoding {
+            key: key.clone(),
+            error,
+        })?;
+        self.save(&key, &cached)?;
+        Ok(cached)
+    }
+
+    /// Fetch the attribute `attr` of the entity `uid`, and cache it if the
+    /// entity still is
+    fn fetch_attribute(&self, uid: &EntityUid, attr: &str) -> Result<CachedAttr, EntityCacheError> {
+        let value =
+            self.provider
+                .attribute(uid, attr)
+                .map_err(|error| EntityCacheError::Provider {
+                    uid: uid.clone(),
+                    error,
+                })?;
+        let key = key(uid);
+        let value = value
+            .map(|value| JSONValue::from_expr(value.0.as_borrowed()))
+            .transpose()
+            .map_err(|error| EntityCacheError::Encoding {
+                key: key.clone(),
+                error: error.into(),
+            })?;
+        let cached_attr = CachedAttr {
+            value,
+            fetched: Some(now()),
+        };
+        if let Some(mut cached) = self.cached(&key)? {
+            cached.attrs.insert(attr.into(), cached_attr.clone());
+            self.save(&key, &cached)?;
+        }
+        Ok(cached_attr)
+    }
+
+    /// Refetch the entity `uid`, or its attribute `attr`, on a background
+    /// thread, unless that is already being done
+    fn refresh_in_background(self: &Arc<Self>, uid: &EntityUid, attr: Option<SmolStr>) {
+        let key = (uid.clone(), attr);
+        if !self
+            .refreshing
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(key.clone())
+        {
+            return;
+        }
+        let cache = Arc::clone(self);
+        thread::spawn(move || {
+            let (uid, attr) = &key;
+            let refreshed = attr.as_ref().map_or_else(
+                || cache.fetch_entity(uid).map(|_| ()),
+                |attr| cache.fetch_attribute(uid, attr).map(|_| ()),
+            );
+            cache
+                .refreshing
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .remove(&key);
+            if let Err(error) = refreshed {
+                for listener in cache
+                    .error_listeners
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .iter()
+                {
+                    listener(&error);
+                }
+            }
+        });
+    }
+}
+
+impl Debug for EntityCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EntityCache")
+            .field("ttl", &self.ttl)
+            .field("attribute_ttls", &self.attribute_ttls)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Decision, EvalResult, PolicySet, Request};
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+
+    /// An address whose balance is read from `balance`, in the `whales` group,
+    /// counting its calls
+    #[derive(Default)]
+    struct Node {
+        balance: AtomicI64,
+        entity_calls: AtomicUsize,
+        attribute_calls: AtomicUsize,
+    }
+
+    /// A [`Node`] shared with the test
+    struct Shared(Arc<Node>);
+
+    impl EntityProvider for Shared {
+        fn entity(&self, uid: &EntityUid) -> Result<Option<Entity>, ProviderError> {
+            let node = &self.0;
+            node.entity_calls.fetch_add(1, Ordering::SeqCst);
+            if uid.type_name().to_string() == "Group" {
+                return Ok(Some(Entity::with_uid(uid.clone())));
+            }
+            if uid != &address() {
+                return Ok(None);
+            }
+            Ok(Some(Entity::new(
+                uid.clone(),
+                HashMap::from([
+                    (
+                        "balance".to_string(),
+                        RestrictedExpression::new_long(node.balance.load(Ordering::SeqCst)),
+                    ),
+                    (
+                        "label".to_string(),
+                        RestrictedExpression::new_string("a".into()),
+                    ),
+                ]),
+                HashSet::from([EntityUid::from_str(r#"Group::"whales""#)?]),
+            )))
+        }
+
+        fn attribute(
+            &self,
+            _uid: &EntityUid,
+            attr: &str,
+        ) -> Result<Option<RestrictedExpression>, ProviderError> {
+            let node = &self.0;
+            node.attribute_calls.fetch_add(1, Ordering::SeqCst);
+            Ok((attr == "balance")
+                .then(|| RestrictedExpression::new_long(node.balance.load(Ordering::SeqCst))))
+        }
+    }
+
+    fn address() -> EntityUid {
+        EntityUid::from_str(r#"Address::"0xabc""#).expect("valid uid")
+    }
+
+    fn balance(cache: &Arc<EntityCache>) -> EvalResult {
+        cache
+            .entity(&address())
+            .expect("fetches")
+            .expect("exists")
+            .attr("balance")
+            .expect("has balance")
+            .expect("evaluates")
+    }
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    fn cache(node: &Arc<Node>, balance_ttl: Ttl) -> Arc<EntityCache> {
+        Arc::new(
+            EntityCache::new(Shared(Arc::clone(node)), Ttl::new(HOUR)).with_attribute_ttl(
+                EntityTypeName::from_str("Address").expect("valid type"),
+                "balance",
+                balance_ttl,
+            ),
+        )
+    }
+
+    #[test]
+    fn caches_entities() {
+        let node = Arc::new(Node::default());
+        let cache = cache(&node, Ttl::new(HOUR));
+        node.balance.store(1, Ordering::SeqCst);
+        assert_eq!(balance(&cache), EvalResult::Long(1));
+        node.balance.store(2, Ordering::SeqCst);
+        assert_eq!(balance(&cache), EvalResult::Long(1));
+        assert_eq!(node.entity_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(node.attribute_calls.load(Ordering::SeqCst), 0);
+
+        // missing entities are cached too
+        let missing = EntityUid::from_str(r#"Address::"0xdef""#).expect("valid uid");
+        assert!(cache.entity(&missing).expect("fetches").is_none());
+        assert!(cache.entity(&missing).expect("fetches").is_none());
+        assert_eq!(node.entity_calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn refetches_attributes_with_their_own_ttl() {
+        let node = Arc::new(Node::default());
+        let cache = cache(&node, Ttl::new(Duration::ZERO));
+        node.balance.store(1, Ordering::SeqCst);
+        assert_eq!(balance(&cache), EvalResult::Long(1));
+        node.balance.store(2, Ordering::SeqCst);
+        assert_eq!(balance(&cache), EvalResult::Long(2));
+        assert_eq!(node.entity_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(node.attribute_calls.load(Ordering::SeqCst), 2);
+        // the other attributes are kept
+        let entity = cache.entity(&address()).expect("fetches").expect("exists");
+        assert_eq!(
+            entity.attr("label").expect("has label"),
+            Ok(EvalResult::String("a".to_string()))
+        );
+    }
+
+    #[test]
+    fn uses_stale_attributes_while_revalidating() {
+        let node = Arc::new(Node::default());
+        let cache = cache(&node, Ttl::new(Duration::ZERO).stale_while_revalidate(HOUR));
+        node.balance.store(1, Ordering::SeqCst);
+        assert_eq!(balance(&cache), EvalResult::Long(1));
+        node.balance.store(2, Ordering::SeqCst);
+        // the stale balance is used while it is refetched
+        assert_eq!(balance(&cache), EvalResult::Long(1));
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while cache
+            .refreshing
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+            > 0
+            || node.attribute_calls.load(Ordering::SeqCst) == 0
+        {
+            assert!(Instant::now() < deadline, "the refresh never finished");
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(balance(&cache), EvalResult::Long(2));
+        assert_eq!(node.entity_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn invalidates() {
+        let node = Arc::new(Node::default());
+        let cache = cache(&node, Ttl::new(HOUR));
+        node.balance.store(1, Ordering::SeqCst);
+        assert_eq!(balance(&cache), EvalResult::Long(1));
+
+        node.balance.store(2, Ordering::SeqCst);
+        cache
+            .invalidate_attributes(
+                &EntityTypeName::from_str("Address").expect("valid type"),
+                "balance",
+            )
+            .expect("invalidates");
+        assert_eq!(balance(&cache), EvalResult::Long(2));
+        assert_eq!(node.attribute_calls.load(Ordering::SeqCst), 1);
+
+        node.balance.store(3, Ordering::SeqCst);
+        cache
+            .invalidate_attribute(&address(), "balance")
+            .expect("invalidates");
+        assert_eq!(balance(&cache), EvalResult::Long(3));
+        assert_eq!(node.attribute_calls.load(Ordering::SeqCst), 2);
+
+        node.balance.store(4, Ordering::SeqCst);
+        cache.invalidate(&address()).expect("invalidates");
+        assert_eq!(balance(&cache), EvalResult::Long(4));
+        assert_eq!(node.entity_calls.load(Ordering::SeqCst), 2);
+
+        node.balance.store(5, Ordering::SeqCst);
+        cache.clear().expect("clears");
+        assert_eq!(balance(&cache), EvalResult::Long(5));
+        assert_eq!(node.entity_calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn loads_entities_with_ancestors() {
+        let node = Arc::new(Node::default());
+        let cache = cache(&node, Ttl::new(HOUR));
+        let entities = cache.load_entities([&address()]).expect("loads");
+        assert_eq!(entities.iter().count(), 2);
+        let policies =
+            PolicySet::from_str(r#"permit(principal in Group::"whales", action, resource);"#)
+                .expect("policies parse");
+        let request = Request::new(Some(address()), None, None, Context::empty());
+        assert_eq!(
+            Authorizer::new()
+                .is_authorized(&request, &policies, &entities)
+                .decision(),
+            Decision::Allow
+        );
+    }
+
+    #[test]
+    fn shares_entities_through_a_store() {
+        let store = Arc::new(MemoryStore::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let instance = || {
+            let calls = Arc::clone(&calls);
+            Arc::new(
+                EntityCache::new(
+                    move |uid: &EntityUid| -> Result<Option<Entity>, ProviderError> {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        Ok(Some(Entity::new(
+                            uid.clone(),
+                            HashMap::from([(
+                                "balance".to_string(),
+                                RestrictedExpression::from_str(r#"u256("1000")"#)?,
+                            )]),
+                            HashSet::new(),
+                        )))
+                    },
+                    Ttl::new(HOUR),
+                )
+                .with_store(Arc::clone(&store)),
+            )
+        };
+        let (first, second) = (instance(), instance());
+        let fetched = first.entity(&address()).expect("fetches").expect("exists");
+        let shared = second.entity(&address()).expect("fetches").expect("exists");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        // extension values are kept as they are
+        assert_eq!(fetched.attr("balance"), shared.attr("balance"));
+        assert!(matches!(
+            shared.attr("balance"),
+            Some(Ok(EvalResult::ExtensionValue(_)))
+        ));
+
+        second.invalidate(&address()).expect("invalidates");
+        first.entity(&address()).expect("fetches");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn reports_errors_of_the_provider() {
+        let cache = Arc::new(EntityCache::new(
+            |_: &EntityUid| -> Result<Option<Entity>, ProviderError> { Err("rate limited".into()) },
+            Ttl::new(HOUR),
+        ));
+        let error = cache.entity(&address()).expect_err("fails");
+        assert_eq!(
+            error.to_string(),
+            r#"failed to fetch `Address::"0xabc"`: rate limited"#
+        );
+    }
+}
@@ -0,0 +1,338 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Authorizing event logs, so that indexing pipelines can decide with
+//! policies which events to persist or alert on.
+//!
+//! An [`EventMapper`] knows the ABI of some events, and maps a log of one of
+//! them into a request in which
+//! * the principal is the canonical UID of the contract emitting it (see
+//!   [`EntityUid::contract`]), or, with [`EventMapper::principal`], of the
+//!   account in one of its indexed `address` parameters (see
+//!   [`EntityUid::account`]),
+//! * the action is the name of the event, like `Action::"Transfer"`,
+//! * the resource is the canonical UID of the contract emitting it, and
+//! * the context holds its decoded parameters by name (or by position, for
+//!   unnamed parameters): addresses as `address`es, unsigned integers as
+//!   `u256`s, signed integers as `Long`s, byte strings as `bytes`, arrays as
+//!   sets, and tuples as records of their elements by position.
+//!
+//! ```
+//! # use cedar_policy::event_log::*;
+//! # use cedar_policy::{Authorizer, Entities, PolicySet};
+//! # use ethers::abi::{AbiParser, Token};
+//! # use ethers::types::{Log, H256, U256};
+//! let transfer = AbiParser::default()
+//!     .parse_event("event Transfer(address indexed from, address indexed to, uint256 value)")
+//!     .unwrap();
+//! let mapper = EventMapper::new(1).with_event(transfer.clone());
+//! let policies: PolicySet = r#"
+//!     permit(principal, action == Action::"Transfer", resource)
+//!     when { context.value >= u256("1000000000000") };"#
+//!     .parse()
+//!     .unwrap();
+//! let log = Log {
+//!     address: "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48".parse().unwrap(),
+//!     topics: vec![
+//!         transfer.signature(),
+//!         H256::from("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed".parse::<ethers::types::Address>().unwrap()),
+//!         H256::from("0xab5801a7d398351b8be11c439e05c5b3259aec9b".parse::<ethers::types::Address>().unwrap()),
+//!     ],
+//!     data: ethers::abi::encode(&[Token::Uint(U256::exp10(13))]).into(),
+//!     ..Log::default()
+//! };
+//! let request = mapper.request(&log).unwrap();
+//! let response = Authorizer::new().is_authorized(&request, &policies, &Entities::empty());
+//! // a large transfer, to alert on, naming the policy which matched
+//! let matched: Vec<String> = response.diagnostics().reason().map(ToString::to_string).collect();
+//! assert_eq!(matched, ["policy0"]);
+//! ```
+
+use std::collections::HashMap;
+
+use ethers::abi::{self, Event, RawLog, Token};
+use ethers::types::{Log, H256};
+use thiserror::Error;
+
+use crate::abi_value::token_value;
+use crate::{Context, EntityUid, Request, RestrictedExpression};
+
+/// Errors mapping an event log into a request
+#[derive(Debug, Error)]
+pub enum EventLogError {
+    /// The log has no topics, as of an anonymous event, so which event it is
+    /// is unknown
+    #[error("the log has no topics")]
+    Anonymous,
+    /// The mapper does not know the event
+    #[error("the event with topic0 {0:?} is unknown")]
+    UnknownEvent(H256),
+    /// The log does not decode as the event
+    #[error("the log does not match the ABI of `{event}`: {source}")]
+    Decode {
+        /// The name of the event
+        event: String,
+        /// Why it does not decode
+        source: abi::Error,
+    },
+    /// A parameter has no Cedar value
+    #[error("the parameter `{param}` of `{event}` is unsupported: {reason}")]
+    Unsupported {
+        /// The name of the event
+        event: String,
+        /// The name of the parameter
+        param: String,
+        /// Why it is unsupported
+        reason: String,
+    },
+    /// The event has no `address` parameter of the name chosen for the
+    /// principal
+    #[error("the event `{event}` has no `address` parameter `{param}` for the principal")]
+    NoPrincipal {
+        /// The name of the event
+        event: String,
+        /// The name of the parameter
+        param: String,
+    },
+}
+
+/// Maps the logs of known events into requests
+#[derive(Debug, Clone)]
+pub struct EventMapper {
+    chain_id: u64,
+    events: HashMap<H256, Event>,
+    principal: Option<String>,
+}
+
+impl EventMapper {
+    /// A mapper of the logs of contracts on the chain with id `chain_id`,
+    /// which knows no events
+    pub fn new(chain_id: u64) -> Self {
+        Self {
+            chain_id,
+            events: HashMap::new(),
+            principal: None,
+        }
+    }
+
+    /// Map the logs of `event`, which must not be anonymous, recognized by
+    /// the hash of its signature in their first topic
+    #[must_use]
+    pub fn with_event(mut self, event: Event) -> Self {
+        self.events.insert(event.signature(), event);
+        self
+    }
+
+    /// Map the logs of all the events of `abi` which are not anonymous
+    #[must_use]
+    pub fn with_abi(self, abi: &abi::Abi) -> Self {
+        abi.events()
+            .filter(|event| !event.anonymous)
+            .cloned()
+            .fold(self, Self::with_event)
+    }
+
+    /// Make the principal the account in the indexed `address` parameter
+    /// `param`, such as the `from` of a `Transfer`, rather than the emitter.
+    /// Mapping the log of an event without that parameter fails.
+    #[must_use]
+    pub fn principal(mut self, param: impl Into<String>) -> Self {
+        self.principal = Some(param.into());
+        self
+    }
+
+    /// The request for `log`
+    ///
+    /// # Errors
+    ///
+    /// If the log is not of a known event, does not decode as it, has a
+    /// parameter with no Cedar value, or has no parameter for the principal.
+    pub fn request(&self, log: &Log) -> Result<Request, EventLogError> {
+        let topic0 = log.topics.first().ok_or(EventLogError::Anonymous)?;
+        let event = self
+            .events
+            .get(topic0)
+            .ok_or(EventLogError::UnknownEvent(*topic0))?;
+        let decoded = event
+            .parse_log(RawLog {
+                topics: log.topics.clone(),
+                data: log.data.to_vec(),
+            })
+            .map_err(|source| EventLogError::Decode {
+                event: event.name.clone(),
+                source,
+            })?;
+        let emitter = EntityUid::contract(self.chain_id, log.address);
+        let principal = match &self.principal {
+            None => emitter.clone(),
+            Some(param) => {
+                let address = decoded
+                    .params
+                    .iter()
+                    .zip(&event.inputs)
+                    .find_map(|(decoded, input)| match &decoded.value {
+                        Token::Address(address) if input.indexed && &decoded.name == param => {
+                            Some(*address)
+                        }
+                        _ => None,
+                    })
+                    .ok_or_else(|| EventLogError::NoPrincipal {
+                        event: event.name.clone(),
+                        param: param.clone(),
+                    })?;
+                EntityUid::account(self.chain_id, address)
+            }
+        };
+        let params = decoded
+            .params
+            .into_iter()
+            .enumerate()
+            .map(|(index, param)| {
+                let name = if param.name.is_empty() {
+                    index.to_string()
+                } else {
+                    param.name
+                };
+                let value =
+                    token_value(param.value).map_err(|reason| EventLogError::Unsupported {
+                        event: event.name.clone(),
+                        param: name.clone(),
+                        reason,
+                    })?;
+                Ok((name, value))
+            })
+            .collect::<Result<Vec<(String, RestrictedExpression)>, _>>()?;
+        Ok(Request::new(
+            Some(principal),
+            Some(EntityUid::from_known_type("Action", &event.name)),
+            Some(emitter),
+            Context::from_pairs(params),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Decision, Entities, PolicySet};
+    use ethers::abi::AbiParser;
+    use ethers::types::{Address, U256};
+
+    fn address(s: &str) -> Address {
+        s.parse().expect("valid address")
+    }
+
+    fn transfer() -> Event {
+        AbiParser::default()
+            .parse_event("event Transfer(address indexed from, address indexed to, uint256 value)")
+            .expect("valid event")
+    }
+
+    const USDC: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+    const ALICE: &str = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed";
+    const BOB: &str = "0xab5801a7d398351b8be11c439e05c5b3259aec9b";
+
+    fn transfer_log(from: &str, to: &str, value: u64) -> Log {
+        Log {
+            address: address(USDC),
+            topics: vec![
+                transfer().signature(),
+                H256::from(address(from)),
+                H256::from(address(to)),
+            ],
+            data: abi::encode(&[Token::Uint(U256::from(value))]).into(),
+            ..Log::default()
+        }
+    }
+
+    fn decide(policies: &str, request: &Request) -> Decision {
+        let policies: PolicySet = policies.parse().expect("valid policies");
+        Authorizer::new()
+            .is_authorized(request, &policies, &Entities::empty())
+            .decision()
+    }
+
+    #[test]
+    fn maps_logs_into_requests() {
+        let mapper = EventMapper::new(1).with_event(transfer());
+        let request = mapper
+            .request(&transfer_log(ALICE, BOB, 500))
+            .expect("maps the log");
+        let usdc = EntityUid::contract(1, address(USDC));
+        assert_eq!(request.principal(), Some(&usdc));
+        assert_eq!(
+            request.action(),
+            Some(&EntityUid::from_known_type("Action", "Transfer"))
+        );
+        assert_eq!(request.resource(), Some(&usdc));
+        let policy = format!(
+            r#"permit(principal, action == Action::"Transfer", resource)
+            when {{
+                context.from == address("{ALICE}") &&
+                context.to == address("{BOB}") &&
+                context.value == u256("500")
+            }};"#
+        );
+        assert_eq!(decide(&policy, &request), Decision::Allow);
+    }
+
+    #[test]
+    fn indexed_principals() {
+        let mapper = EventMapper::new(10).with_event(transfer()).principal("to");
+        let request = mapper
+            .request(&transfer_log(ALICE, BOB, 500))
+            .expect("maps the log");
+        assert_eq!(
+            request.principal(),
+            Some(&EntityUid::account(10, address(BOB)))
+        );
+        // `value` is not indexed, and `sender` is not a parameter
+        for param in ["value", "sender"] {
+            assert!(matches!(
+                EventMapper::new(10)
+                    .with_event(transfer())
+                    .principal(param)
+                    .request(&transfer_log(ALICE, BOB, 500)),
+                Err(EventLogError::NoPrincipal { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn unknown_and_malformed_logs() {
+        let abi = AbiParser::default()
+            .parse_str(
+                "event Approval(address indexed owner, address indexed spender, uint256 value)",
+            )
+            .expect("valid abi");
+        let mapper = EventMapper::new(1).with_abi(&abi);
+        assert!(matches!(
+            mapper.request(&transfer_log(ALICE, BOB, 500)),
+            Err(EventLogError::UnknownEvent(topic)) if topic == transfer().signature()
+        ));
+        assert!(matches!(
+            mapper.request(&Log::default()),
+            Err(EventLogError::Anonymous)
+        ));
+        let mapper = mapper.with_event(transfer());
+        let mut log = transfer_log(ALICE, BOB, 500);
+        log.topics.pop();
+        assert!(matches!(
+            mapper.request(&log),
+            Err(EventLogError::Decode { .. })
+        ));
+    }
+}
@@ -0,0 +1,571 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Explaining authorization decisions.
+//!
+//! [`explain`] evaluates each policy of a policy set for a request the way the
+//! [`Authorizer`](crate::Authorizer) does, and records for each policy whether
+//! its principal, action and resource constraints matched, what its `when` and
+//! `unless` conditions evaluated to, and, when they were false or failed, the
+//! sub-expression responsible. The [`Display`](std::fmt::Display) of an
+//! [`Explanation`] shows this as a tree, followed by how the policies combined
+//! into the decision.
+
+use crate::{Decision, Effect, Entities, EvaluationError, PolicyId, PolicySet, Request};
+use cedar_policy_core::ast::{self, Expr, ExprKind, Literal, SlotEnv, Value};
+use cedar_policy_core::evaluator::Evaluator;
+use cedar_policy_core::extensions::Extensions;
+use cedar_policy_core::parser::SourceInfo;
+use ref_cast::RefCast;
+use std::fmt::{self, Display};
+use std::ops::Range;
+
+/// What a scope constraint or condition evaluated to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// It evaluated to `true`
+    True,
+    /// It evaluated to `false`
+    False,
+    /// Evaluating it failed with this error
+    Error(String),
+}
+
+impl Outcome {
+    fn of(result: Result<Value, EvaluationError>) -> Self {
+        match result {
+            Ok(Value::Lit(Literal::Bool(true))) => Self::True,
+            Ok(Value::Lit(Literal::Bool(false))) => Self::False,
+            Ok(value) => Self::Error(format!("expected a boolean, got `{value}`")),
+            Err(e) => Self::Error(e.to_string()),
+        }
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::True => write!(f, "true"),
+            Self::False => write!(f, "false"),
+            Self::Error(e) => write!(f, "error: {e}"),
+        }
+    }
+}
+
+/// An expression of a policy, with its location in the policy source text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubExpression {
+    text: String,
+    range: Option<Range<usize>>,
+}
+
+impl SubExpression {
+    fn new(text: impl Display, source_info: Option<&SourceInfo>) -> Self {
+        Self {
+            text: text.to_string(),
+            range: source_info.map(|info| info.range_start()..info.range_end()),
+        }
+    }
+
+    /// The expression, in Cedar syntax
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The byte range of the expression in the policy set text, as for
+    /// [`Policy::source_location`](crate::Policy::source_location), if it is
+    /// known
+    pub fn range(&self) -> Option<Range<usize>> {
+        self.range.clone()
+    }
+}
+
+impl Display for SubExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)?;
+        if let Some(range) = &self.range {
+            write!(f, " (at bytes {}..{})", range.start, range.end)?;
+        }
+        Ok(())
+    }
+}
+
+/// How a principal, action or resource constraint of a policy evaluated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScopeExplanation {
+    constraint: SubExpression,
+    outcome: Outcome,
+}
+
+impl ScopeExplanation {
+    /// The constraint, e.g., `principal in UserGroup::"admins"`, or just
+    /// `principal` if it is unconstrained
+    pub fn constraint(&self) -> &SubExpression {
+        &self.constraint
+    }
+
+    /// Whether the constraint matched the request
+    pub fn outcome(&self) -> &Outcome {
+        &self.outcome
+    }
+}
+
+/// How the `when` and `unless` conditions of a policy evaluated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConditionExplanation {
+    outcome: Outcome,
+    cause: Option<SubExpression>,
+}
+
+impl ConditionExplanation {
+    /// What the conditions evaluated to, which is `true` for a policy without
+    /// any
+    pub fn outcome(&self) -> &Outcome {
+        &self.outcome
+    }
+
+    /// For conditions which were false, the innermost sub-expression whose
+    /// being false made them false, e.g., the operand of `&&` which was
+    /// `false`. For conditions which failed, the innermost sub-expression
+    /// whose evaluation failed.
+    pub fn cause(&self) -> Option<&SubExpression> {
+        self.cause.as_ref()
+    }
+}
+
+/// How a policy evaluated for a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyExplanation {
+    id: PolicyId,
+    effect: Effect,
+    principal: ScopeExplanation,
+    action: ScopeExplanation,
+    resource: ScopeExplanation,
+    condition: Option<ConditionExplanation>,
+}
+
+impl PolicyExplanation {
+    /// The id of the policy
+    pub fn id(&self) -> &PolicyId {
+        &self.id
+    }
+
+    /// The effect of the policy
+    pub fn effect(&self) -> Effect {
+        self.effect
+    }
+
+    /// How the principal constraint evaluated
+    pub fn principal(&self) -> &ScopeExplanation {
+        &self.principal
+    }
+
+    /// How the action constraint evaluated
+    pub fn action(&self) -> &ScopeExplanation {
+        &self.action
+    }
+
+    /// How the resource constraint evaluated
+    pub fn resource(&self) -> &ScopeExplanation {
+        &self.resource
+    }
+
+    /// How the conditions evaluated, or `None` if they were not evaluated
+    /// because the scope did not match, as the authorizer does not evaluate
+    /// them then either
+    pub fn condition(&self) -> Option<&ConditionExplanation> {
+        self.condition.as_ref()
+    }
+
+    /// Whether the policy is satisfied (`True`), does not apply to the request
+    /// (`False`), or failed, in which case the authorizer skips it
+    pub fn outcome(&self) -> Outcome {
+        // the scope and conditions are evaluated in order, stopping at the
+        // first which is not true
+        [&self.principal, &self.action, &self.resource]
+            .into_iter()
+            .map(|scope| &scope.outcome)
+            .chain(self.condition.as_ref().map(|condition| &condition.outcome))
+            .find(|outcome| **outcome != Outcome::True)
+            .cloned()
+            .unwrap_or(Outcome::True)
+    }
+}
+
+/// Why the authorizer makes its decision for a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    decision: Decision,
+    policies: Vec<PolicyExplanation>,
+}
+
+impl Explanation {
+    /// The decision for the request
+    pub fn decision(&self) -> Decision {
+        self.decision
+    }
+
+    /// How each policy evaluated, in the order of their ids
+    pub fn policies(&self) -> impl Iterator<Item = &PolicyExplanation> {
+        self.policies.iter()
+    }
+
+    /// The ids of the policies whose outcome is `outcome` and, if it is
+    /// given, whose effect is `effect`
+    fn ids(&self, effect: Option<Effect>, outcome: &Outcome) -> String {
+        self.policies
+            .iter()
+            .filter(|policy| {
+                effect.is_none_or(|effect| policy.effect == effect)
+                    && match outcome {
+                        Outcome::Error(_) => matches!(policy.outcome(), Outcome::Error(_)),
+                        outcome => &policy.outcome() == outcome,
+                    }
+            })
+            .map(|policy| format!("`{}`", policy.id))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for policy in &self.policies {
+            let outcome = match policy.outcome() {
+                Outcome::True => "satisfied".to_string(),
+                Outcome::False => "not satisfied".to_string(),
+                Outcome::Error(_) => "skipped because of an error".to_string(),
+            };
+            writeln!(f, "{} `{}`: {outcome}", policy.effect, policy.id)?;
+            for scope in [&policy.principal, &policy.action, &policy.resource] {
+                writeln!(f, "├── {}: {}", scope.constraint, scope.outcome)?;
+            }
+            match &policy.condition {
+                None => writeln!(f, "└── conditions: not evaluated")?,
+                Some(condition) => {
+                    writeln!(f, "└── conditions: {}", condition.outcome)?;
+                    if let Some(cause) = &condition.cause {
+                        let what = match condition.outcome {
+                            Outcome::Error(_) => "failed",
+                            _ => "false",
+                        };
+                        writeln!(f, "    └── {what}: {cause}")?;
+                    }
+                }
+            }
+        }
+        let permits = self.ids(Some(Effect::Permit), &Outcome::True);
+        let forbids = self.ids(Some(Effect::Forbid), &Outcome::True);
+        write!(f, "decision: {:?}, ", self.decision)?;
+        if !forbids.is_empty() {
+            write!(f, "because forbid {forbids} overrides any permit policy")?;
+        } else if !permits.is_empty() {
+            write!(
+                f,
+                "because permit {permits} is satisfied and no forbid policy is"
+            )?;
+        } else {
+            write!(f, "because no permit policy is satisfied")?;
+        }
+        let skipped = self.ids(None, &Outcome::Error(String::new()));
+        if !skipped.is_empty() {
+            write!(f, "; {skipped} skipped because of errors")?;
+        }
+        writeln!(f)
+    }
+}
+
+/// Evaluates the parts of policies
+struct Explainer<'e> {
+    evaluator: Evaluator<'e>,
+}
+
+impl Explainer<'_> {
+    fn eval(&self, expr: &Expr, slots: &SlotEnv) -> Outcome {
+        Outcome::of(self.evaluator.interpret(expr, slots))
+    }
+
+    /// The sub-expressions of `expr` which evaluating it evaluates, in order
+    fn evaluated_children<'a>(&self, expr: &'a Expr, slots: &SlotEnv) -> Vec<&'a Expr> {
+        match expr.expr_kind() {
+            ExprKind::Lit(_) | ExprKind::Var(_) | ExprKind::Slot(_) | ExprKind::Unknown { .. } => {
+                vec![]
+            }
+            ExprKind::If {
+                test_expr,
+                then_expr,
+                else_expr,
+            } => match self.eval(test_expr, slots) {
+                Outcome::True => vec![test_expr, then_expr],
+                Outcome::False => vec![test_expr, else_expr],
+                Outcome::Error(_) => vec![test_expr],
+            },
+            ExprKind::And { left, right } => match self.eval(left, slots) {
+                Outcome::True => vec![left, right],
+                _ => vec![left],
+            },
+            ExprKind::Or { left, right } => match self.eval(left, slots) {
+                Outcome::False => vec![left, right],
+                _ => vec![left],
+            },
+            ExprKind::UnaryApp { arg, .. }
+            | ExprKind::MulByConst { arg, .. }
+            | ExprKind::GetAttr { expr: arg, .. }
+            | ExprKind::HasAttr { expr: arg, .. }
+            | ExprKind::Like { expr: arg, .. } => vec![arg],
+            ExprKind::BinaryApp { arg1, arg2, .. } => vec![arg1, arg2],
+            ExprKind::ExtensionFunctionApp { args, .. } | ExprKind::Set(args) => {
+                args.iter().collect()
+            }
+            ExprKind::Record { pairs } => pairs.iter().map(|(_, value)| value).collect(),
+        }
+    }
+
+    /// The innermost sub-expression of `expr`, which evaluates to `false`,
+    /// whose being `false` makes `expr` `false`
+    fn cause_of_false<'a>(&self, expr: &'a Expr, slots: &SlotEnv) -> &'a Expr {
+        match expr.expr_kind() {
+            ExprKind::And { left, right } => {
+                if self.eval(left, slots) == Outcome::False {
+                    self.cause_of_false(left, slots)
+                } else {
+                    self.cause_of_false(right, slots)
+                }
+            }
+            ExprKind::If {
+                test_expr,
+                then_expr,
+                else_expr,
+            } => match self.eval(test_expr, slots) {
+                Outcome::True => self.cause_of_false(then_expr, slots),
+                _ => self.cause_of_false(else_expr, slots),
+            },
+            _ => expr,
+        }
+    }
+
+    /// The innermost sub-expression of `expr`, which fails to evaluate, whose
+    /// failure makes evaluating `expr` fail
+    fn cause_of_error<'a>(&self, expr: &'a Expr, slots: &SlotEnv) -> &'a Expr {
+        self.evaluated_children(expr, slots)
+            .into_iter()
+            .find(|child| self.evaluator.interpret(child, slots).is_err())
+            .map_or(expr, |child| self.cause_of_error(child, slots))
+    }
+
+    fn explain_policy(&self, policy: &ast::Policy) -> PolicyExplanation {
+        let slots = policy.env();
+        let source_info = policy.source_info();
+        let scope = |constraint: &dyn Display, expr: Expr, source_info: Option<&SourceInfo>| {
+            ScopeExplanation {
+                constraint: SubExpression::new(constraint, source_info),
+                outcome: self.eval(&expr, slots),
+            }
+        };
+        let principal_constraint = policy.principal_constraint();
+        let principal = scope(
+            &principal_constraint,
+            principal_constraint.as_expr(),
+            source_info.principal(),
+        );
+        let action = scope(
+            policy.action_constraint(),
+            policy.action_constraint().as_expr(),
+            source_info.action(),
+        );
+        let resource_constraint = policy.resource_constraint();
+        let resource = scope(
+            &resource_constraint,
+            resource_constraint.as_expr(),
+            source_info.resource(),
+        );
+        let condition = [&principal, &action, &resource]
+            .iter()
+            .all(|scope| scope.outcome == Outcome::True)
+            .then(|| {
+                let expr = policy.non_head_constraints();
+                let outcome = self.eval(expr, slots);
+                let cause = match outcome {
+                    Outcome::True => None,
+                    Outcome::False => Some(self.cause_of_false(expr, slots)),
+                    Outcome::Error(_) => Some(self.cause_of_error(expr, slots)),
+                };
+                ConditionExplanation {
+                    outcome,
+                    cause: cause
+                        .map(|cause| SubExpression::new(cause, cause.source_info().as_ref())),
+                }
+            });
+        PolicyExplanation {
+            id: PolicyId::ref_cast(policy.id()).clone(),
+            effect: policy.effect(),
+            principal,
+            action,
+            resource,
+            condition,
+        }
+    }
+}
+
+/// Explain the decision for `request` against `policies` with `entities`,
+/// evaluating each policy the way the [`Authorizer`](crate::Authorizer) does.
+///
+/// # Errors
+///
+/// If the context of the request cannot be evaluated, in which case the
+/// authorizer denies the request without evaluating any policy.
+pub fn explain(
+    request: &Request,
+    policies: &PolicySet,
+    entities: &Entities,
+) -> Result<Explanation, EvaluationError> {
+    let extensions = Extensions::all_available();
+    let explainer = Explainer {
+        evaluator: Evaluator::new(&request.0, &entities.0, &extensions)?,
+    };
+    let mut policies = policies
+        .policies()
+        .map(|policy| explainer.explain_policy(policy.ast()))
+        .collect::<Vec<_>>();
+    policies.sort_by(|a, b| a.id.as_ref().cmp(b.id.as_ref()));
+    let satisfied = |effect| {
+        policies
+            .iter()
+            .any(|policy| policy.effect == effect && policy.outcome() == Outcome::True)
+    };
+    let decision = if satisfied(Effect::Permit) && !satisfied(Effect::Forbid) {
+        Decision::Allow
+    } else {
+        Decision::Deny
+    };
+    Ok(Explanation { decision, policies })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Context, EntityUid};
+    use std::str::FromStr;
+
+    fn explain_src(src: &str, context: serde_json::Value) -> Explanation {
+        let policies = PolicySet::from_str(src).expect("should parse");
+        let uid = |s: &str| Some(EntityUid::from_str(s).unwrap());
+        let request = Request::new(
+            uid(r#"User::"alice""#),
+            uid(r#"Action::"view""#),
+            uid(r#"Photo::"a.jpg""#),
+            Context::from_json_value(context, None).unwrap(),
+        );
+        explain(&request, &policies, &Entities::empty()).expect("should explain")
+    }
+
+    fn policy<'a>(explanation: &'a Explanation, id: &str) -> &'a PolicyExplanation {
+        explanation
+            .policies()
+            .find(|policy| policy.id().as_ref() == id)
+            .expect("policy should be explained")
+    }
+
+    #[test]
+    fn scope() {
+        let explanation = explain_src(
+            r#"permit(principal == User::"bob", action, resource) when { context.x };"#,
+            serde_json::json!({}),
+        );
+        assert_eq!(explanation.decision(), Decision::Deny);
+        let policy = policy(&explanation, "policy0");
+        assert_eq!(policy.principal().outcome(), &Outcome::False);
+        assert_eq!(
+            policy.principal().constraint().text(),
+            r#"principal == User::"bob""#
+        );
+        assert_eq!(policy.principal().constraint().range(), Some(7..31));
+        assert_eq!(policy.action().outcome(), &Outcome::True);
+        assert_eq!(policy.action().constraint().text(), "action");
+        // the conditions would fail, but are not evaluated
+        assert_eq!(policy.condition(), None);
+        assert_eq!(policy.outcome(), Outcome::False);
+    }
+
+    #[test]
+    fn cause_of_false() {
+        let src =
+            "permit(principal, action, resource) when { context.a && (context.b && context.c) };";
+        let explanation = explain_src(src, serde_json::json!({ "a": true, "b": false, "c": true }));
+        let condition = policy(&explanation, "policy0").condition().unwrap();
+        assert_eq!(condition.outcome(), &Outcome::False);
+        let cause = condition.cause().unwrap();
+        assert_eq!(
+            cause.range().and_then(|range| src.get(range)),
+            Some("context.b")
+        );
+        assert_eq!(explanation.decision(), Decision::Deny);
+
+        let explanation = explain_src(src, serde_json::json!({ "a": true, "b": true, "c": true }));
+        let condition = policy(&explanation, "policy0").condition().unwrap();
+        assert_eq!(condition.outcome(), &Outcome::True);
+        assert_eq!(condition.cause(), None);
+        assert_eq!(explanation.decision(), Decision::Allow);
+    }
+
+    #[test]
+    fn cause_of_error() {
+        let src = "permit(principal, action, resource) when { context.a || context.n + 1 > context.missing };";
+        let explanation = explain_src(src, serde_json::json!({ "a": false, "n": 1 }));
+        let policy = policy(&explanation, "policy0");
+        assert!(matches!(policy.outcome(), Outcome::Error(_)));
+        let cause = policy.condition().unwrap().cause().unwrap();
+        assert_eq!(
+            cause.range().and_then(|range| src.get(range)),
+            Some("context.missing")
+        );
+        // the short-circuited operand is not blamed
+        let explanation = explain_src(src, serde_json::json!({ "a": true, "n": 1 }));
+        assert_eq!(
+            explanation.policies().next().unwrap().outcome(),
+            Outcome::True
+        );
+    }
+
+    #[test]
+    fn combining() {
+        let src = "
+            permit(principal, action, resource);
+            forbid(principal, action, resource) when { context.blocked };
+            forbid(principal, action, resource) when { context.missing };
+        ";
+        let explanation = explain_src(src, serde_json::json!({ "blocked": false }));
+        assert_eq!(explanation.decision(), Decision::Allow);
+        let text = explanation.to_string();
+        assert!(
+            text.ends_with(
+                "decision: Allow, because permit `policy0` is satisfied and no forbid policy is; `policy2` skipped because of errors\n"
+            ),
+            "{text}"
+        );
+        assert!(text.contains("forbid `policy1`: not satisfied\n"), "{text}");
+        assert!(
+            text.contains("    └── false: context[\"blocked\"] (at bytes"),
+            "{text}"
+        );
+
+        let explanation = explain_src(src, serde_json::json!({ "blocked": true }));
+        assert_eq!(explanation.decision(), Decision::Deny);
+        assert!(explanation
+            .to_string()
+            .ends_with("because forbid `policy1` overrides any permit policy; `policy2` skipped because of errors\n"));
+    }
+}
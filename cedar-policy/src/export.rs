@@ -0,0 +1,758 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Exporting policy sets and schemas for upstream Cedar and Amazon Verified
+//! Permissions, which lack the extensions of this fork.
+//!
+//! [`export_policies`] rewrites the policies of a policy set where there is an
+//! equivalent in upstream Cedar:
+//! * `u256` literals which fit in a `decimal` become `decimal` literals, and
+//!   the `u256` comparisons become `decimal` comparisons
+//! * `address` literals become lowercase strings
+//! * `bytes` literals become `0x`-prefixed lowercase hex strings, and
+//!   `bytesStartsWith` with a literal prefix becomes `like`
+//!
+//! so the policies give the same decisions if the entities and contexts
+//! they are evaluated with hold values converted the same way. Policies using
+//! anything else upstream Cedar lacks, like `u256` arithmetic, regular
+//! expressions or entity tags, are left out, along with the links of left out
+//! templates.
+//!
+//! [`export_schema`] converts the types of a schema to match: `u256` becomes
+//! `decimal`, and `address` and `bytes` become `String`. Attribute defaults
+//! and entity tags are removed, and attributes, and common types, of types
+//! with no equivalent are left out.
+//!
+//! Both report what they left out or changed as [`ExportIssue`]s.
+
+use std::fmt::{self, Display};
+
+use cedar_policy_core::ast::{
+    self, BinaryOp, Expr, ExprKind, Literal, Name, PatternElem, RestrictedExpr,
+};
+use cedar_policy_core::evaluator::RestrictedEvaluator;
+use cedar_policy_core::extensions::Extensions;
+use ref_cast::RefCast;
+use serde_json::{Map, Value};
+
+use crate::{PolicyId, PolicySet, SchemaError, SchemaFragment};
+
+/// The largest integer a `decimal` can hold
+const DECIMAL_MAX: u64 = (i64::MAX / 10_000).unsigned_abs();
+
+/// What an [`ExportIssue`] is about
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ExportSubject {
+    /// A policy, template or template-linked policy
+    Policy(PolicyId),
+    /// An element of a schema, like `Vault::Account.balance` for an attribute
+    Schema(String),
+}
+
+impl Display for ExportSubject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Policy(id) => write!(f, "policy `{}`", id.as_ref()),
+            Self::Schema(path) => write!(f, "`{path}`"),
+        }
+    }
+}
+
+/// Something an export left out, or changed in a way to be aware of
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportIssue {
+    /// What it is about
+    pub subject: ExportSubject,
+    /// Whether it was left out, rather than changed
+    pub dropped: bool,
+    /// Why
+    pub message: String,
+}
+
+impl ExportIssue {
+    fn dropped(subject: ExportSubject, message: impl Into<String>) -> Self {
+        Self {
+            subject,
+            dropped: true,
+            message: message.into(),
+        }
+    }
+
+    fn changed(subject: ExportSubject, message: impl Into<String>) -> Self {
+        Self {
+            subject,
+            dropped: false,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for ExportIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = if self.dropped { "left out" } else { "changed" };
+        write!(f, "{} {what}: {}", self.subject, self.message)
+    }
+}
+
+/// The result of an export, and what it left out or changed
+#[derive(Debug, Clone)]
+pub struct Exported<T> {
+    /// The exported policy set or schema
+    pub value: T,
+    /// What was left out or changed, in the order of the policy ids or of
+    /// the schema
+    pub issues: Vec<ExportIssue>,
+}
+
+/// Rewrite `policies` to use only what upstream Cedar has, leaving out the
+/// policies, templates and links which cannot be
+pub fn export_policies(policies: &PolicySet) -> Exported<PolicySet> {
+    let mut exported = ast::PolicySet::new();
+    let mut issues = Vec::new();
+    let mut templates: Vec<_> = policies.ast.all_templates().collect();
+    templates.sort_by(|a, b| a.id().as_ref().cmp(b.id().as_ref()));
+    for template in templates {
+        let id = PolicyId::ref_cast(template.id()).clone();
+        let is_static = policies
+            .ast
+            .get(template.id())
+            .is_some_and(ast::Policy::is_static);
+        let added = export_template(template).and_then(|template| {
+            if is_static {
+                let policy = ast::StaticPolicy::try_from(template).map_err(|e| e.to_string())?;
+                exported.add_static(policy)
+            } else {
+                exported.add_template(template)
+            }
+            .map_err(|e| e.to_string())
+        });
+        if let Err(message) = added {
+            issues.push(ExportIssue::dropped(ExportSubject::Policy(id), message));
+        }
+    }
+    let mut links: Vec<_> = policies
+        .ast
+        .policies()
+        .filter(|policy| !policy.is_static())
+        .collect();
+    links.sort_by(|a, b| a.id().as_ref().cmp(b.id().as_ref()));
+    for link in links {
+        let template_id = link.template().id();
+        let linked = if exported.get_template(template_id).is_some() {
+            exported
+                .link(
+                    template_id.clone(),
+                    link.id().clone(),
+                    link.env()
+                        .iter()
+                        .map(|(slot, euid)| (*slot, euid.clone()))
+                        .collect(),
+                )
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        } else {
+            Err(format!(
+                "it links the template `{}`, which was left out",
+                template_id.as_ref()
+            ))
+        };
+        if let Err(message) = linked {
+            issues.push(ExportIssue::dropped(
+                ExportSubject::Policy(PolicyId::ref_cast(link.id()).clone()),
+                message,
+            ));
+        }
+    }
+    Exported {
+        value: PolicySet::from_ast(exported),
+        issues,
+    }
+}
+
+/// `template`, with its condition rewritten by [`export_expr`]
+fn export_template(template: &ast::Template) -> Result<ast::Template, String> {
+    let condition = export_expr(template.non_head_constraints())?;
+    Ok(ast::Template::new(
+        template.id().clone(),
+        template
+            .annotations()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+        template.effect(),
+        template.principal_constraint().clone(),
+        template.action_constraint().clone(),
+        template.resource_constraint().clone(),
+        condition,
+    ))
+}
+
+/// The value the extension constructor `fn_name` makes from the string `arg`,
+/// as displayed
+fn constructed_value(fn_name: &Name, arg: &str) -> Result<String, String> {
+    let expr = RestrictedExpr::call_extension_fn(fn_name.clone(), vec![RestrictedExpr::val(arg)]);
+    let extensions = Extensions::all_available();
+    RestrictedEvaluator::new(&extensions)
+        .interpret(expr.as_borrowed())
+        .map(|value| value.to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// The string `expr` is, if it is a string literal
+fn string_literal(expr: &Expr) -> Option<&str> {
+    match expr.expr_kind() {
+        ExprKind::Lit(Literal::String(s)) => Some(s),
+        _ => None,
+    }
+}
+
+/// The literal argument of a call of the constructor `fn_name` with `args`
+fn literal_arg<'a>(fn_name: &Name, args: &'a [Expr]) -> Result<&'a str, String> {
+    match args {
+        [arg] => string_literal(arg).ok_or_else(|| {
+            format!("`{fn_name}` of an expression, rather than of a string literal, has no lossless equivalent in upstream Cedar")
+        }),
+        _ => Err(format!("`{fn_name}` takes one argument")),
+    }
+}
+
+/// `expr`, rewritten to use only what upstream Cedar has, or why it cannot be
+fn export_expr(expr: &Expr) -> Result<Expr, String> {
+    Ok(match expr.expr_kind() {
+        ExprKind::Lit(_) | ExprKind::Var(_) | ExprKind::Slot(_) | ExprKind::Unknown { .. } => {
+            expr.clone()
+        }
+        ExprKind::If {
+            test_expr,
+            then_expr,
+            else_expr,
+        } => Expr::ite(
+            export_expr(test_expr)?,
+            export_expr(then_expr)?,
+            export_expr(else_expr)?,
+        ),
+        ExprKind::And { left, right } => Expr::and(export_expr(left)?, export_expr(right)?),
+        ExprKind::Or { left, right } => Expr::or(export_expr(left)?, export_expr(right)?),
+        ExprKind::UnaryApp { op, arg } => Expr::unary_app(*op, export_expr(arg)?),
+        ExprKind::BinaryApp { op, arg1, arg2 } => match op {
+            BinaryOp::HasTag | BinaryOp::GetTag => {
+                return Err(format!(
+                    "entity tags (`{}`) have no equivalent in upstream Cedar",
+                    if *op == BinaryOp::HasTag {
+                        "hasTag"
+                    } else {
+                        "getTag"
+                    }
+                ))
+            }
+            _ => Expr::binary_app(*op, export_expr(arg1)?, export_expr(arg2)?),
+        },
+        ExprKind::MulByConst { arg, constant } => Expr::mul(export_expr(arg)?, *constant),
+        ExprKind::ExtensionFunctionApp { fn_name, args } => export_call(fn_name, args)?,
+        ExprKind::GetAttr { expr, attr } => Expr::get_attr(export_expr(expr)?, attr.clone()),
+        ExprKind::HasAttr { expr, attr } => Expr::has_attr(export_expr(expr)?, attr.clone()),
+        ExprKind::Like { expr, pattern } => Expr::like(export_expr(expr)?, pattern.iter().copied()),
+        ExprKind::Set(elements) => Expr::set(
+            elements
+                .iter()
+                .map(export_expr)
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        ExprKind::Record { pairs } => Expr::record(
+            pairs
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), export_expr(value)?)))
+                .collect::<Result<Vec<_>, String>>()?,
+        ),
+    })
+}
+
+/// The call of the extension function `fn_name` with `args`, rewritten by
+/// [`export_expr`]
+fn export_call(fn_name: &Name, args: &[Expr]) -> Result<Expr, String> {
+    let call = |name: &str, args: &[Expr]| {
+        let name = Name::parse_unqualified_name(name).map_err(|e| e.to_string())?;
+        let args = args.iter().map(export_expr).collect::<Result<_, _>>()?;
+        Ok(Expr::call_extension_fn(name, args))
+    };
+    match fn_name.to_string().as_str() {
+        "u256" => {
+            let value = constructed_value(fn_name, literal_arg(fn_name, args)?)?;
+            match value.parse::<u64>() {
+                Ok(n) if n <= DECIMAL_MAX => call("decimal", &[Expr::val(format!("{n}.0"))]),
+                _ => Err(format!(
+                    "`u256` value {value} is larger than the largest `decimal`, {DECIMAL_MAX}"
+                )),
+            }
+        }
+        "u256LessThan" => call("lessThan", args),
+        "u256LessThanOrEqual" => call("lessThanOrEqual", args),
+        "u256GreaterThan" => call("greaterThan", args),
+        "u256GreaterThanOrEqual" => call("greaterThanOrEqual", args),
+        name @ ("u256Add" | "u256Sub" | "u256Mul") => Err(format!(
+            "`u256` arithmetic (`{name}`) has no equivalent for `decimal` in upstream Cedar"
+        )),
+        "address" => Ok(Expr::val(
+            constructed_value(fn_name, literal_arg(fn_name, args)?)?.to_lowercase(),
+        )),
+        "bytes" => Ok(Expr::val(constructed_value(
+            fn_name,
+            literal_arg(fn_name, args)?,
+        )?)),
+        "bytesStartsWith" => match args {
+            [value, prefix] => {
+                let prefix = export_expr(prefix)?;
+                let prefix = string_literal(&prefix).ok_or_else(|| {
+                    "`bytesStartsWith` with a prefix other than a `bytes` literal has no equivalent in upstream Cedar".to_string()
+                })?;
+                Ok(Expr::like(
+                    export_expr(value)?,
+                    prefix
+                        .chars()
+                        .map(PatternElem::Char)
+                        .chain(std::iter::once(PatternElem::Wildcard)),
+                ))
+            }
+            _ => Err("`bytesStartsWith` takes two arguments".to_string()),
+        },
+        name @ "bytesLength" => Err(format!("`{name}` has no equivalent in upstream Cedar")),
+        "regex" | "matchesRegex" => {
+            Err("regular expressions have no equivalent in upstream Cedar".to_string())
+        }
+        name => call(name, args),
+    }
+}
+
+/// Convert the types of `schema` to match the policies [`export_policies`]
+/// exports, giving the schema in the JSON format
+///
+/// # Errors
+///
+/// If the schema cannot be written in the JSON format.
+pub fn export_schema(schema: &SchemaFragment) -> Result<Exported<Value>, SchemaError> {
+    let mut json = schema.to_json_value()?;
+    let mut issues = Vec::new();
+    if let Some(namespaces) = json.as_object_mut() {
+        for (namespace, definition) in namespaces.iter_mut() {
+            let prefix = if namespace.is_empty() {
+                String::new()
+            } else {
+                format!("{namespace}::")
+            };
+            export_namespace(&prefix, definition, &mut issues);
+        }
+    }
+    Ok(Exported {
+        value: json,
+        issues,
+    })
+}
+
+/// Convert the types of the namespace `definition`, whose names start with
+/// `prefix`
+fn export_namespace(prefix: &str, definition: &mut Value, issues: &mut Vec<ExportIssue>) {
+    if let Some(common_types) = definition
+        .get_mut("commonTypes")
+        .and_then(Value::as_object_mut)
+    {
+        common_types.retain(|name, ty| {
+            let path = format!("{prefix}{name}");
+            match export_type(&path, ty, issues) {
+                Ok(()) => true,
+                Err(message) => {
+                    issues.push(ExportIssue::dropped(ExportSubject::Schema(path), message));
+                    false
+                }
+            }
+        });
+    }
+    if let Some(entity_types) = definition
+        .get_mut("entityTypes")
+        .and_then(Value::as_object_mut)
+    {
+        for (name, entity_type) in entity_types.iter_mut() {
+            let path = format!("{prefix}{name}");
+            let Some(entity_type) = entity_type.as_object_mut() else {
+                continue;
+            };
+            if entity_type.remove("tags").is_some() {
+                issues.push(ExportIssue::dropped(
+                    ExportSubject::Schema(format!("{path} tags")),
+                    "entity tags have no equivalent in upstream Cedar",
+                ));
+            }
+            if let Some(shape) = entity_type.get_mut("shape") {
+                export_type_or_empty(&path, shape, issues);
+            }
+        }
+    }
+    if let Some(actions) = definition.get_mut("actions").and_then(Value::as_object_mut) {
+        for (name, action) in actions.iter_mut() {
+            if let Some(context) = action.pointer_mut("/appliesTo/context") {
+                let path = format!("{prefix}Action::\"{}\" context", name.escape_debug());
+                export_type_or_empty(&path, context, issues);
+            }
+        }
+    }
+}
+
+/// Convert `ty` with [`export_type`], or make it an empty record if it has
+/// no equivalent
+fn export_type_or_empty(path: &str, ty: &mut Value, issues: &mut Vec<ExportIssue>) {
+    if let Err(message) = export_type(path, ty, issues) {
+        issues.push(ExportIssue::dropped(
+            ExportSubject::Schema(path.to_string()),
+            message,
+        ));
+        *ty = serde_json::json!({ "type": "Record", "attributes": {} });
+    }
+}
+
+/// Convert the type `ty`, at `path`, and the types in it, leaving out the
+/// attributes whose types have no equivalent. Errors if `ty` itself has none.
+fn export_type(path: &str, ty: &mut Value, issues: &mut Vec<ExportIssue>) -> Result<(), String> {
+    let Some(object) = ty.as_object_mut() else {
+        return Ok(());
+    };
+    match object.get("type").and_then(Value::as_str) {
+        Some("Extension") => match object.get("name").and_then(Value::as_str) {
+            Some("u256") => {
+                object.insert("name".to_string(), Value::from("decimal"));
+                issues.push(ExportIssue::changed(
+                    ExportSubject::Schema(path.to_string()),
+                    format!("`u256` became `decimal`, which holds integers up to {DECIMAL_MAX}"),
+                ));
+            }
+            Some(name @ ("address" | "bytes")) => {
+                let message = format!(
+                    "`{name}` became `String`, which holds {} hex with a `0x` prefix",
+                    if name == "address" {
+                        "lowercase 40-digit"
+                    } else {
+                        "lowercase"
+                    }
+                );
+                object.insert("type".to_string(), Value::from("String"));
+                object.remove("name");
+                issues.push(ExportIssue::changed(
+                    ExportSubject::Schema(path.to_string()),
+                    message,
+                ));
+            }
+            Some("decimal" | "ipaddr") | None => (),
+            Some(name) => {
+                return Err(format!(
+                    "the `{name}` extension type has no equivalent in upstream Cedar"
+                ))
+            }
+        },
+        Some("Set") => {
+            if let Some(element) = object.get_mut("element") {
+                export_type(path, element, issues)?;
+            }
+        }
+        Some("Record") => {
+            if let Some(attributes) = object.get_mut("attributes").and_then(Value::as_object_mut) {
+                export_attributes(path, attributes, issues);
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Convert the types of the record `attributes` at `path`, leaving out the
+/// attributes whose types have no equivalent, and removing defaults
+fn export_attributes(
+    path: &str,
+    attributes: &mut Map<String, Value>,
+    issues: &mut Vec<ExportIssue>,
+) {
+    let mut kept = Map::new();
+    for (name, mut attribute) in std::mem::take(attributes) {
+        let path = format!("{path}.{name}");
+        if let Some(attribute) = attribute.as_object_mut() {
+            if attribute.remove("default").is_some() {
+                issues.push(ExportIssue::changed(
+                    ExportSubject::Schema(path.clone()),
+                    "attribute defaults have no equivalent in upstream Cedar, so entities must give the attribute where they relied on its default",
+                ));
+            }
+        }
+        match export_type(&path, &mut attribute, issues) {
+            Ok(()) => {
+                kept.insert(name, attribute);
+            }
+            Err(message) => issues.push(ExportIssue::dropped(ExportSubject::Schema(path), message)),
+        }
+    }
+    *attributes = kept;
+}
+
+// the tests use the extensions of the fork, and `decimal` to evaluate exports
+#[cfg(all(
+    test,
+    feature = "u256",
+    feature = "decimal",
+    feature = "address",
+    feature = "bytes",
+    feature = "regex"
+))]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Entities, EntityUid, Request};
+    use std::str::FromStr;
+
+    fn export(src: &str) -> Exported<PolicySet> {
+        export_policies(&PolicySet::from_str(src).expect("policies parse"))
+    }
+
+    fn exported_policy(src: &str) -> String {
+        let exported = export(src);
+        assert_eq!(exported.issues, []);
+        exported
+            .value
+            .policies()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn converts_extension_literals() {
+        let policy = exported_policy(
+            r#"permit(principal, action, resource) when {
+                principal.balance >= u256("1000") &&
+                principal.wallet == address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed") &&
+                context.calldata.bytesStartsWith(bytes("0xA9059CBB"))
+            };"#,
+        );
+        assert!(
+            policy.contains(r#".greaterThanOrEqual(decimal("1000.0"))"#),
+            "{policy}"
+        );
+        assert!(
+            policy.contains(r#"== "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed""#),
+            "{policy}"
+        );
+        assert!(policy.contains(r#" like "0xa9059cbb*""#), "{policy}");
+        assert!(PolicySet::from_str(&policy).is_ok(), "{policy}");
+    }
+
+    #[test]
+    fn exported_policies_give_same_decisions() {
+        let src = r#"permit(principal, action, resource) when { principal.balance > u256("10") };"#;
+        let policies = PolicySet::from_str(src).expect("policies parse");
+        let exported = export_policies(&policies).value;
+        // the same entity, with its u256 balance converted to a decimal
+        let entities = |balance: &str| {
+            Entities::from_json_str(
+                &format!(
+                    r#"[{{ "uid": {{ "type": "Address", "id": "0xabc" }}, "attrs": {{ "balance": {balance} }}, "parents": [] }}]"#
+                ),
+                None,
+            )
+            .expect("entities parse")
+        };
+        let request = Request::new(
+            EntityUid::from_str(r#"Address::"0xabc""#).ok(),
+            None,
+            None,
+            Context::empty(),
+        );
+        let authorizer = Authorizer::new();
+        for (u256, decimal) in [("5", "5.0"), ("11", "11.0")] {
+            assert_eq!(
+                authorizer
+                    .is_authorized(
+                        &request,
+                        &policies,
+                        &entities(&format!(
+                            r#"{{ "__extn": {{ "fn": "u256", "arg": "{u256}" }} }}"#
+                        )),
+                    )
+                    .decision(),
+                authorizer
+                    .is_authorized(
+                        &request,
+                        &exported,
+                        &entities(&format!(
+                            r#"{{ "__extn": {{ "fn": "decimal", "arg": "{decimal}" }} }}"#
+                        )),
+                    )
+                    .decision(),
+            );
+        }
+    }
+
+    #[test]
+    fn leaves_out_unconvertible_policies() {
+        let exported = export(
+            r#"
+            @id("arithmetic")
+            permit(principal, action, resource) when { principal.balance.u256Add(u256("1")) > u256("5") };
+            @id("large")
+            permit(principal, action, resource) when { principal.balance > u256("1000000000000000000") };
+            @id("regex")
+            permit(principal, action, resource) when { context.name.matchesRegex(regex("^a")) };
+            @id("tags")
+            permit(principal, action, resource) when { principal.hasTag("admin") };
+            @id("template")
+            permit(principal == ?principal, action, resource) when { resource.size.bytesLength() > 2 };
+            @id("kept")
+            permit(principal == ?principal, action, resource);
+            "#,
+        );
+        let mut dropped: Vec<_> = exported
+            .issues
+            .iter()
+            .filter(|issue| issue.dropped)
+            .map(|issue| issue.subject.to_string())
+            .collect();
+        dropped.sort();
+        assert_eq!(
+            dropped,
+            [
+                "policy `policy0`",
+                "policy `policy1`",
+                "policy `policy2`",
+                "policy `policy3`",
+                "policy `policy4`"
+            ]
+        );
+        assert_eq!(exported.value.policies().count(), 0);
+        assert_eq!(exported.value.templates().count(), 1);
+    }
+
+    #[test]
+    fn keeps_links_of_converted_templates() {
+        let mut policies = PolicySet::from_str(
+            r#"permit(principal == ?principal, action, resource) when { principal.limit < u256("5") };
+               permit(principal == ?principal, action, resource) when { principal.hasTag("x") };"#,
+        )
+        .expect("policies parse");
+        for (template, link) in [("policy0", "link0"), ("policy1", "link1")] {
+            policies
+                .link(
+                    PolicyId::from_str(template).expect("valid id"),
+                    PolicyId::from_str(link).expect("valid id"),
+                    std::iter::once((
+                        crate::SlotId::principal(),
+                        EntityUid::from_str(r#"Address::"0xabc""#).expect("valid uid"),
+                    ))
+                    .collect(),
+                )
+                .expect("links");
+        }
+        let exported = export_policies(&policies);
+        let ids: Vec<_> = exported
+            .value
+            .policies()
+            .map(|policy| policy.id().to_string())
+            .collect();
+        assert_eq!(ids, ["link0"]);
+        let dropped: Vec<_> = exported.issues.iter().map(ToString::to_string).collect();
+        assert_eq!(dropped.len(), 2);
+        assert!(dropped
+            .iter()
+            .any(|issue| issue.contains("`link1`") && issue.contains("`policy1`")));
+    }
+
+    #[test]
+    fn converts_schema_types() {
+        let schema = SchemaFragment::from_json_value(serde_json::json!({
+            "Vault": {
+                "commonTypes": {
+                    "Pattern": { "type": "Extension", "name": "regex" }
+                },
+                "entityTypes": {
+                    "Account": {
+                        "shape": {
+                            "type": "Record",
+                            "attributes": {
+                                "balance": { "type": "Extension", "name": "u256" },
+                                "owners": { "type": "Set", "element": { "type": "Extension", "name": "address" } },
+                                "limit": { "type": "Long", "required": false, "default": 5 },
+                                "pattern": { "type": "Extension", "name": "regex" }
+                            }
+                        },
+                        "tags": { "type": "String" }
+                    }
+                },
+                "actions": {
+                    "withdraw": {
+                        "appliesTo": {
+                            "principalTypes": ["Account"],
+                            "resourceTypes": ["Account"],
+                            "context": {
+                                "type": "Record",
+                                "attributes": {
+                                    "calldata": { "type": "Extension", "name": "bytes" }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }))
+        .expect("schema parses");
+        let exported = export_schema(&schema).expect("exports");
+        let account = exported
+            .value
+            .pointer("/Vault/entityTypes/Account")
+            .expect("has account");
+        assert_eq!(
+            account.pointer("/shape/attributes/balance/name"),
+            Some(&serde_json::json!("decimal"))
+        );
+        assert_eq!(
+            account.pointer("/shape/attributes/owners/element"),
+            Some(&serde_json::json!({ "type": "String" }))
+        );
+        assert_eq!(account.pointer("/shape/attributes/limit/default"), None);
+        assert_eq!(account.pointer("/shape/attributes/pattern"), None);
+        assert_eq!(account.get("tags"), None);
+        assert_eq!(exported.value.pointer("/Vault/commonTypes/Pattern"), None);
+        assert_eq!(
+            exported
+                .value
+                .pointer("/Vault/actions/withdraw/appliesTo/context/attributes/calldata/type"),
+            Some(&serde_json::json!("String"))
+        );
+        // the exported schema is still a schema
+        assert!(SchemaFragment::from_json_value(exported.value).is_ok());
+
+        let issues: Vec<_> = exported
+            .issues
+            .iter()
+            .map(|issue| (issue.subject.to_string(), issue.dropped))
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                ("`Vault::Pattern`".to_string(), true),
+                ("`Vault::Account tags`".to_string(), true),
+                ("`Vault::Account.balance`".to_string(), false),
+                ("`Vault::Account.limit`".to_string(), false),
+                ("`Vault::Account.owners`".to_string(), false),
+                ("`Vault::Account.pattern`".to_string(), true),
+                (
+                    "`Vault::Action::\"withdraw\" context.calldata`".to_string(),
+                    false
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,658 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Testing Foundry scripts against policies, so that protocol teams can check
+//! in CI that their deploy scripts are authorized under their production
+//! policies.
+//!
+//! Running a script with `forge script --broadcast`, such as against a local
+//! Anvil node (`anvil & forge script script/Deploy.s.sol --fork-url
+//! http://localhost:8545 --broadcast`), records the transactions it sent in a
+//! broadcast artifact, `broadcast/<script>/<chain id>/run-latest.json`, which
+//! [`Broadcast`] reads. Each transaction maps into a request in which
+//! * the principal is the canonical UID of the sender (see
+//!   [`EntityUid::account`]),
+//! * for a deployment, with `CREATE` or through the deterministic deployment
+//!   proxy, the action is `Action::"deploy"`, the resource is the canonical
+//!   UID of the deployed contract (see [`EntityUid::contract`]), and the
+//!   context is that of [`Deployment::context`],
+//! * for a call, the action is the selector of the function called, like
+//!   `Action::"0xa9059cbb"`, or `Action::"receive"` for a plain transfer, the
+//!   resource is the canonical UID of the contract called, and the context
+//!   holds the calldata as `data`, as `bytes`, and the signature of the
+//!   function as `function`, if the artifact gives it, and
+//! * the context also holds the `value` sent, as a `u256`, and the name of
+//!   the contract deployed or called as `contractName`, if the artifact gives
+//!   it.
+//!
+//! A [`PolicyHarness`] authorizes the transactions of a broadcast, and asserts
+//! on their decisions. Since Anvil has the chain id [`ANVIL_CHAIN_ID`], which
+//! production policies do not name, [`PolicyHarness::on_chain`] maps the
+//! transactions onto the chain the script is for.
+//!
+//! ```
+//! # use cedar_policy::forge::*;
+//! # use cedar_policy::{Entities, PolicySet};
+//! let broadcast = Broadcast::from_json_str(r#"{
+//!     "chain": 31337,
+//!     "transactions": [{
+//!         "hash": "0x2f4d8d1dd5b18d1cb8cbd2e5a1c5e0e1d7a8b9c0d1e2f3a4b5c6d7e8f9a0b1c2",
+//!         "transactionType": "CREATE",
+//!         "contractName": "Counter",
+//!         "transaction": {
+//!             "from": "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed",
+//!             "value": "0x0",
+//!             "input": "0x600080600a8239f3",
+//!             "nonce": "0x0"
+//!         }
+//!     }]
+//! }"#).unwrap();
+//! let policies: PolicySet = r#"
+//!     permit(
+//!         principal == Account::"eip155:1:0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+//!         action == Action::"deploy",
+//!         resource
+//!     ) when { context.contractName == "Counter" };"#
+//!     .parse()
+//!     .unwrap();
+//! PolicyHarness::new(policies, Entities::empty())
+//!     .on_chain(1)
+//!     .assert_authorized(&broadcast);
+//! ```
+
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+
+use cedar_policy_core::extensions::u256::parse_u256;
+use ethers::types::{Address, Bytes, H256, U256};
+use ethers::utils::hex;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::deployment::Deployment;
+use crate::{
+    Authorizer, Decision, Entities, EntityUid, PolicySet, RecordBuilder, Request, Response,
+    RestrictedExpression,
+};
+
+/// The chain id of Anvil, unless it is started with `--chain-id`
+pub const ANVIL_CHAIN_ID: u64 = 31337;
+
+/// The attribute of the context holding the value sent with a transaction
+pub const VALUE_ATTRIBUTE: &str = "value";
+
+/// The attribute of the context holding the calldata of a call
+pub const DATA_ATTRIBUTE: &str = "data";
+
+/// The attribute of the context holding the signature of the function called
+pub const FUNCTION_ATTRIBUTE: &str = "function";
+
+/// The attribute of the context holding the name of the contract deployed or
+/// called
+pub const CONTRACT_NAME_ATTRIBUTE: &str = "contractName";
+
+/// Errors reading broadcast artifacts and mapping their transactions into
+/// requests
+#[derive(Debug, Error)]
+pub enum ForgeError {
+    /// The artifact could not be read
+    #[error("failed to read `{}`: {source}", path.display())]
+    Io {
+        /// The path of the artifact
+        path: PathBuf,
+        /// Why it could not be read
+        source: std::io::Error,
+    },
+    /// The artifact is not a broadcast artifact
+    #[error("failed to parse the broadcast artifact: {0}")]
+    Json(#[from] serde_json::Error),
+    /// A transaction has no recipient, and is not a deployment
+    #[error("transaction {index} has no recipient")]
+    NoRecipient {
+        /// The index of the transaction in the broadcast
+        index: usize,
+    },
+    /// A deployment has no nonce, so the address of the contract is unknown
+    #[error("transaction {index} deploys a contract but has no nonce")]
+    NoNonce {
+        /// The index of the transaction in the broadcast
+        index: usize,
+    },
+    /// A value sent does not fit in a `u256`, or a nonce in a `u64`
+    #[error("transaction {index} has an out of range `{field}`")]
+    OutOfRange {
+        /// The index of the transaction in the broadcast
+        index: usize,
+        /// The field out of range
+        field: &'static str,
+    },
+}
+
+/// The fields of a transaction sent by a script which requests are made of
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawTransaction {
+    /// The sender
+    pub from: Address,
+    /// The recipient, which is `None` for a contract-creation transaction
+    #[serde(default)]
+    pub to: Option<Address>,
+    /// The value sent
+    #[serde(default)]
+    pub value: Option<U256>,
+    /// The calldata, or the init code of a contract-creation transaction,
+    /// which older versions of Foundry name `data`
+    #[serde(default, alias = "data")]
+    pub input: Bytes,
+    /// The nonce of the sender
+    #[serde(default)]
+    pub nonce: Option<U256>,
+}
+
+/// A transaction sent by a script, as recorded in a broadcast artifact
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BroadcastTransaction {
+    /// The hash of the transaction, if it was sent
+    #[serde(default)]
+    pub hash: Option<H256>,
+    /// `CREATE`, `CREATE2` or `CALL`
+    pub transaction_type: String,
+    /// The name of the contract deployed or called, if Foundry knows it
+    #[serde(default)]
+    pub contract_name: Option<String>,
+    /// The signature of the function called, if Foundry knows it
+    #[serde(default)]
+    pub function: Option<String>,
+    /// The transaction
+    pub transaction: RawTransaction,
+}
+
+impl Display for BroadcastTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.transaction_type)?;
+        match (&self.contract_name, &self.function) {
+            (Some(contract), Some(function)) => write!(f, " {contract}.{function}")?,
+            (Some(contract), None) => write!(f, " {contract}")?,
+            (None, Some(function)) => write!(f, " {function}")?,
+            (None, None) => {}
+        }
+        if let Some(hash) = self.hash {
+            write!(f, " ({hash:?})")?;
+        }
+        Ok(())
+    }
+}
+
+/// The transactions sent by a run of a script, as recorded in a broadcast
+/// artifact
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Broadcast {
+    /// The chain id of the node the script ran against
+    pub chain: u64,
+    /// The transactions, in the order they were sent
+    pub transactions: Vec<BroadcastTransaction>,
+}
+
+impl Broadcast {
+    /// Parse a broadcast artifact
+    ///
+    /// # Errors
+    ///
+    /// If `json` is not a broadcast artifact.
+    pub fn from_json_str(json: &str) -> Result<Self, ForgeError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Read the broadcast artifact at `path`
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read or is not a broadcast artifact.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ForgeError> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).map_err(|source| ForgeError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::from_json_str(&json)
+    }
+
+    /// Read the artifact of the latest run of the script in the file named
+    /// `script`, like `Deploy.s.sol`, against the chain with id `chain_id`,
+    /// in the Foundry project at `root`
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read or is not a broadcast artifact.
+    pub fn latest(root: impl AsRef<Path>, script: &str, chain_id: u64) -> Result<Self, ForgeError> {
+        Self::from_file(
+            root.as_ref()
+                .join("broadcast")
+                .join(script)
+                .join(chain_id.to_string())
+                .join("run-latest.json"),
+        )
+    }
+
+    /// The requests for the transactions, in order, on the chain with id
+    /// `chain_id`
+    ///
+    /// # Errors
+    ///
+    /// If a transaction has no recipient and is not a deployment, is a
+    /// deployment with no nonce, or has an out of range value or nonce.
+    pub fn requests(&self, chain_id: u64) -> Result<Vec<Request>, ForgeError> {
+        self.transactions
+            .iter()
+            .enumerate()
+            .map(|(index, transaction)| request(chain_id, index, transaction))
+            .collect()
+    }
+}
+
+/// The request for the `index`th transaction of a broadcast, `transaction`,
+/// on the chain with id `chain_id`
+fn request(
+    chain_id: u64,
+    index: usize,
+    transaction: &BroadcastTransaction,
+) -> Result<Request, ForgeError> {
+    let out_of_range = |field| ForgeError::OutOfRange { index, field };
+    let raw = &transaction.transaction;
+    let value = parse_u256(&raw.value.unwrap_or_default().to_string())
+        .map(RestrictedExpression::new_u256)
+        .ok_or_else(|| out_of_range("value"))?;
+    let nonce = match raw.nonce {
+        Some(nonce) if nonce > U256::from(u64::MAX) => return Err(out_of_range("nonce")),
+        Some(nonce) => Some(nonce.as_u64()),
+        None => None,
+    };
+    let deployment = match (raw.to, nonce) {
+        (None, None) => return Err(ForgeError::NoNonce { index }),
+        (to, nonce) => {
+            Deployment::from_transaction(raw.from, nonce.unwrap_or_default(), to, &raw.input)
+        }
+    };
+    let (action, resource, context) = match (deployment, raw.to) {
+        (Some(deployment), _) => (
+            EntityUid::from_known_type("Action", "deploy"),
+            EntityUid::contract(chain_id, deployment.address()),
+            deployment.context(),
+        ),
+        (None, Some(to)) => {
+            let selector = raw.input.get(..4).map_or_else(
+                || "receive".to_string(),
+                |selector| format!("0x{}", hex::encode(selector)),
+            );
+            let context = RecordBuilder::new()
+                .attr(DATA_ATTRIBUTE, RestrictedExpression::new_bytes(&raw.input));
+            let context = match &transaction.function {
+                Some(function) => context.attr(FUNCTION_ATTRIBUTE, function.as_str()),
+                None => context,
+            };
+            (
+                EntityUid::from_known_type("Action", &selector),
+                EntityUid::contract(chain_id, to),
+                context,
+            )
+        }
+        (None, None) => return Err(ForgeError::NoRecipient { index }),
+    };
+    let context = context.attr(VALUE_ATTRIBUTE, value);
+    let context = match &transaction.contract_name {
+        Some(name) => context.attr(CONTRACT_NAME_ATTRIBUTE, name.as_str()),
+        None => context,
+    };
+    Ok(Request::new(
+        Some(EntityUid::account(chain_id, raw.from)),
+        Some(action),
+        Some(resource),
+        context.build_context(),
+    ))
+}
+
+/// The decision on a transaction of a broadcast
+#[derive(Debug)]
+pub struct TransactionDecision {
+    /// The index of the transaction in the broadcast
+    pub index: usize,
+    /// The transaction
+    pub transaction: BroadcastTransaction,
+    /// The request for it
+    pub request: Request,
+    /// The response to the request
+    pub response: Response,
+}
+
+impl TransactionDecision {
+    /// Whether the transaction is allowed
+    pub fn is_allowed(&self) -> bool {
+        self.response.decision() == Decision::Allow
+    }
+}
+
+impl Display for TransactionDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "transaction {}, {}: {:?}",
+            self.index,
+            self.transaction,
+            self.response.decision()
+        )?;
+        let reasons: Vec<String> = self
+            .response
+            .diagnostics()
+            .reason()
+            .map(ToString::to_string)
+            .collect();
+        if !reasons.is_empty() {
+            write!(f, " by {}", reasons.join(", "))?;
+        }
+        for error in self.response.diagnostics().errors() {
+            write!(f, "\n  error: {error}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Authorizes the transactions of broadcasts against a policy set, and
+/// asserts on their decisions
+#[derive(Debug)]
+pub struct PolicyHarness {
+    policies: PolicySet,
+    entities: Entities,
+    chain_id: Option<u64>,
+}
+
+impl PolicyHarness {
+    /// A harness authorizing transactions against `policies` and `entities`,
+    /// on the chain of each broadcast
+    pub fn new(policies: PolicySet, entities: Entities) -> Self {
+        Self {
+            policies,
+            entities,
+            chain_id: None,
+        }
+    }
+
+    /// Authorize transactions as if on the chain with id `chain_id`, such as
+    /// those of a run against Anvil as if on the chain the script is for
+    #[must_use]
+    pub fn on_chain(mut self, chain_id: u64) -> Self {
+        self.chain_id = Some(chain_id);
+        self
+    }
+
+    /// The decisions on the transactions of `broadcast`, in order
+    ///
+    /// # Errors
+    ///
+    /// If a transaction cannot be mapped into a request, as by
+    /// [`Broadcast::requests`].
+    pub fn decisions(&self, broadcast: &Broadcast) -> Result<Vec<TransactionDecision>, ForgeError> {
+        let requests = broadcast.requests(self.chain_id.unwrap_or(broadcast.chain))?;
+        let authorizer = Authorizer::new();
+        Ok(broadcast
+            .transactions
+            .iter()
+            .zip(requests)
+            .enumerate()
+            .map(|(index, (transaction, request))| {
+                let response = authorizer.is_authorized(&request, &self.policies, &self.entities);
+                TransactionDecision {
+                    index,
+                    transaction: transaction.clone(),
+                    request,
+                    response,
+                }
+            })
+            .collect())
+    }
+
+    /// Assert that every transaction of `broadcast` is allowed
+    ///
+    /// # Panics
+    ///
+    /// If a transaction is denied or cannot be mapped into a request, with a
+    /// message listing the denied transactions.
+    pub fn assert_authorized(&self, broadcast: &Broadcast) {
+        let denied: Vec<String> = self
+            .decisions(broadcast)
+            .unwrap_or_else(|e| panic!("failed to authorize the broadcast: {e}"))
+            .iter()
+            .filter(|decision| !decision.is_allowed())
+            .map(ToString::to_string)
+            .collect();
+        assert!(
+            denied.is_empty(),
+            "{} of {} transactions are denied:\n{}",
+            denied.len(),
+            broadcast.transactions.len(),
+            denied.join("\n")
+        );
+    }
+
+    /// Assert that the `index`th transaction of `broadcast` has the decision
+    /// `expected`
+    ///
+    /// # Panics
+    ///
+    /// If the transaction has another decision, does not exist, or cannot be
+    /// mapped into a request.
+    pub fn assert_decision(&self, broadcast: &Broadcast, index: usize, expected: Decision) {
+        let decisions = self
+            .decisions(broadcast)
+            .unwrap_or_else(|e| panic!("failed to authorize the broadcast: {e}"));
+        let decision = decisions.get(index).unwrap_or_else(|| {
+            panic!(
+                "the broadcast has {} transactions, not {}",
+                decisions.len(),
+                index + 1
+            )
+        });
+        assert!(
+            decision.response.decision() == expected,
+            "expected {expected:?}, but got {decision}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::deployment::DETERMINISTIC_DEPLOYMENT_PROXY;
+
+    const DEPLOYER: &str = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed";
+    const INIT_CODE: &str = "0x600080600a8239f3";
+    const SALT: &str = "0x0000000000000000000000000000000000000000000000000000000000000001";
+
+    fn address(s: &str) -> Address {
+        s.parse().expect("valid address")
+    }
+
+    fn counter() -> Address {
+        Deployment::create(address(DEPLOYER), 0, hex::decode(INIT_CODE).expect("hex")).address()
+    }
+
+    fn broadcast() -> Broadcast {
+        let json = format!(
+            r#"{{
+                "chain": {ANVIL_CHAIN_ID},
+                "transactions": [
+                    {{
+                        "hash": null,
+                        "transactionType": "CREATE",
+                        "contractName": "Counter",
+                        "function": null,
+                        "arguments": null,
+                        "transaction": {{
+                            "from": "{DEPLOYER}",
+                            "value": "0x0",
+                            "input": "{INIT_CODE}",
+                            "nonce": "0x0",
+                            "chainId": "0x7a69"
+                        }},
+                        "additionalContracts": []
+                    }},
+                    {{
+                        "transactionType": "CREATE2",
+                        "contractName": "Counter",
+                        "transaction": {{
+                            "from": "{DEPLOYER}",
+                            "to": "{DETERMINISTIC_DEPLOYMENT_PROXY}",
+                            "data": "{SALT}{}",
+                            "nonce": "0x1"
+                        }}
+                    }},
+                    {{
+                        "transactionType": "CALL",
+                        "contractName": "Counter",
+                        "function": "setNumber(uint256)",
+                        "transaction": {{
+                            "from": "{DEPLOYER}",
+                            "to": "{:?}",
+                            "value": "0x0",
+                            "input": "0x3fb5c1cb000000000000000000000000000000000000000000000000000000000000002a",
+                            "nonce": "0x2"
+                        }}
+                    }},
+                    {{
+                        "transactionType": "CALL",
+                        "transaction": {{
+                            "from": "{DEPLOYER}",
+                            "to": "{:?}",
+                            "value": "0xde0b6b3a7640000",
+                            "input": "0x",
+                            "nonce": "0x3"
+                        }}
+                    }}
+                ],
+                "receipts": [],
+                "libraries": [],
+                "timestamp": 1700000000
+            }}"#,
+            INIT_CODE.trim_start_matches("0x"),
+            counter(),
+            counter(),
+        );
+        Broadcast::from_json_str(&json).expect("valid broadcast")
+    }
+
+    fn harness(policies: &str) -> PolicyHarness {
+        PolicyHarness::new(policies.parse().expect("valid policies"), Entities::empty())
+    }
+
+    #[test]
+    fn maps_transactions_into_requests() {
+        let broadcast = broadcast();
+        let requests = broadcast.requests(1).expect("maps the transactions");
+        let deployer = EntityUid::account(1, address(DEPLOYER));
+        assert!(requests
+            .iter()
+            .all(|request| request.principal() == Some(&deployer)));
+        let actions: Vec<_> = requests.iter().map(Request::action).collect();
+        assert_eq!(
+            actions,
+            [
+                Some(&EntityUid::from_known_type("Action", "deploy")),
+                Some(&EntityUid::from_known_type("Action", "deploy")),
+                Some(&EntityUid::from_known_type("Action", "0x3fb5c1cb")),
+                Some(&EntityUid::from_known_type("Action", "receive")),
+            ]
+        );
+        assert_eq!(
+            requests.first().and_then(Request::resource),
+            Some(&EntityUid::contract(1, counter()))
+        );
+        let create2 = Deployment::create2(
+            address(DETERMINISTIC_DEPLOYMENT_PROXY),
+            SALT.parse().expect("valid salt"),
+            hex::decode(INIT_CODE).expect("hex"),
+        );
+        assert_eq!(
+            requests.get(1).and_then(Request::resource),
+            Some(&EntityUid::contract(1, create2.address()))
+        );
+    }
+
+    #[test]
+    fn asserts_decisions() {
+        let harness = harness(
+            r#"permit(principal, action == Action::"deploy", resource)
+            when { context.contractName == "Counter" && context.deploymentMethod == "create" };
+            permit(principal, action == Action::"0x3fb5c1cb", resource)
+            when { context.function == "setNumber(uint256)" && context.data.bytesLength() == 36 };
+            permit(principal, action == Action::"receive", resource)
+            when { context.value <= u256("1000000000000000000") };"#,
+        );
+        let broadcast = broadcast();
+        let decisions = harness.decisions(&broadcast).expect("authorizes");
+        let allowed: Vec<bool> = decisions
+            .iter()
+            .map(TransactionDecision::is_allowed)
+            .collect();
+        assert_eq!(allowed, [true, false, true, true]);
+        harness.assert_decision(&broadcast, 1, Decision::Deny);
+        let result = std::panic::catch_unwind(|| harness.assert_authorized(&broadcast));
+        let message = result
+            .expect_err("a transaction is denied")
+            .downcast::<String>()
+            .expect("a message");
+        assert!(
+            message.contains("1 of 4 transactions are denied"),
+            "{message}"
+        );
+        assert!(
+            message.contains("transaction 1, CREATE2 Counter"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn on_chain() {
+        let policy = format!(
+            r#"permit(principal == {}, action, resource);"#,
+            EntityUid::account(1, address(DEPLOYER))
+        );
+        let broadcast = broadcast();
+        // the run was against Anvil, whose accounts the policies do not name
+        harness(&policy).assert_decision(&broadcast, 0, Decision::Deny);
+        harness(&policy).on_chain(1).assert_authorized(&broadcast);
+    }
+
+    #[test]
+    fn invalid_broadcasts() {
+        let mut broadcast = broadcast();
+        if let Some(transaction) = broadcast.transactions.first_mut() {
+            transaction.transaction.nonce = None;
+        }
+        assert!(matches!(
+            broadcast.requests(1),
+            Err(ForgeError::NoNonce { index: 0 })
+        ));
+        assert!(matches!(
+            Broadcast::from_json_str(r#"{ "chain": 1 }"#),
+            Err(ForgeError::Json(_))
+        ));
+        assert!(matches!(
+            Broadcast::latest("/nonexistent", "Deploy.s.sol", 1),
+            Err(ForgeError::Io { path, .. })
+                if path == Path::new("/nonexistent/broadcast/Deploy.s.sol/1/run-latest.json")
+        ));
+    }
+}
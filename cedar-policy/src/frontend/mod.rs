@@ -15,5 +15,6 @@
  */
 
 pub mod is_authorized;
+pub mod parse;
 pub mod utils;
 pub mod validate;
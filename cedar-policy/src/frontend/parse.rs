@@ -0,0 +1,140 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! This module exposes a JSON-based function to check that policies parse,
+//! used by other language FFI's
+#![allow(clippy::module_name_repetitions)]
+use super::utils::{InterfaceResult, PolicySpecification};
+use crate::{Policy, PolicySet};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+fn parse(call: &ParseCall) -> Result<ParseAnswer, Vec<String>> {
+    match &call.policy_set {
+        PolicySpecification::Concatenated(policies_str) => {
+            let policy_set =
+                PolicySet::from_str(policies_str).map_err(|e| e.errors_as_strings())?;
+            let mut policies: Vec<String> = policy_set
+                .policies()
+                .map(|policy| policy.id().to_string())
+                .collect();
+            let mut templates: Vec<String> = policy_set
+                .templates()
+                .map(|template| template.id().to_string())
+                .collect();
+            policies.sort();
+            templates.sort();
+            Ok(ParseAnswer {
+                policies,
+                templates,
+            })
+        }
+        PolicySpecification::Map(policy_set_input) => {
+            let mut policies = Vec::new();
+            let mut errors = Vec::new();
+            for (id, policy_text) in policy_set_input {
+                match Policy::parse(Some(id.clone()), policy_text) {
+                    Ok(_) => policies.push(id.clone()),
+                    Err(pes) => errors.extend(
+                        std::iter::once(format!("couldn't parse policy with id `{id}`"))
+                            .chain(pes.errors_as_strings()),
+                    ),
+                }
+            }
+            if errors.is_empty() {
+                policies.sort();
+                Ok(ParseAnswer {
+                    policies,
+                    templates: Vec::new(),
+                })
+            } else {
+                Err(errors)
+            }
+        }
+    }
+}
+
+/// public string-based function checking that a policy set parses. The
+/// policy set is given as in `json_validate`, and the result lists the ids of
+/// its policies and templates.
+pub fn json_parse(input: &str) -> InterfaceResult {
+    serde_json::from_str::<ParseCall>(input).map_or_else(
+        |e| InterfaceResult::fail_internally(format!("error parsing call: {e:}")),
+        |call| match parse(&call) {
+            Ok(answer) => InterfaceResult::succeed(answer),
+            Err(errors) => InterfaceResult::fail_bad_request(errors),
+        },
+    )
+}
+
+#[derive(Serialize, Deserialize)]
+struct ParseCall {
+    #[serde(rename = "policySet")]
+    policy_set: PolicySpecification,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+struct ParseAnswer {
+    /// Ids of the static and template-linked policies
+    policies: Vec<String>,
+    /// Ids of the templates
+    templates: Vec<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_concatenated() {
+        let call = serde_json::json!({
+            "policySet": r#"
+                forbid (principal, action == Action::"0x095ea7b3", resource)
+                when { context.args.arg_1 == u256("0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff") };
+                forbid (principal == ?principal, action, resource);
+            "#
+        });
+        match json_parse(&call.to_string()) {
+            InterfaceResult::Success { result } => assert_eq!(
+                serde_json::from_str::<ParseAnswer>(&result).unwrap(),
+                ParseAnswer {
+                    policies: vec!["policy0".to_string()],
+                    templates: vec!["policy1".to_string()],
+                }
+            ),
+            result @ InterfaceResult::Failure { .. } => {
+                panic!("expected the policies to parse, not {result:?}")
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_failure() {
+        let call = serde_json::json!({
+            "policySet": { "ID0": "permit(principal, action, resource) when { principal has };" }
+        });
+        match json_parse(&call.to_string()) {
+            InterfaceResult::Failure {
+                is_internal: false,
+                errors,
+            } => assert_eq!(
+                errors.first().map(String::as_str),
+                Some("couldn't parse policy with id `ID0`")
+            ),
+            result => panic!("expected a parse failure, not {result:?}"),
+        }
+    }
+}
@@ -71,19 +71,26 @@ fn validate(call: &ValidateCall) -> Result<ValidateAnswer, String> {
         .map_err(|e| format!("couldn't construct schema - {e}"))?;
     let validator = Validator::new(schema);
 
-    let notes: Vec<ValidationNote> = validator
-        .validate(
-            &policy_set,
-            cedar_policy_validator::ValidationMode::default(),
-        )
+    let result = validator.validate(
+        &policy_set,
+        cedar_policy_validator::ValidationMode::default(),
+    );
+    let notes: Vec<ValidationNote> = result
         .validation_errors()
         .map(|error| ValidationNote {
             policy_id: error.location().policy_id().to_string(),
             note: format!("{}", error.error_kind()),
         })
         .collect();
+    let warnings: Vec<ValidationNote> = result
+        .validation_warnings()
+        .map(|warning| ValidationNote {
+            policy_id: warning.location().to_string(),
+            note: format!("{}", warning.kind()),
+        })
+        .collect();
 
-    Ok(ValidateAnswer::Success { notes })
+    Ok(ValidateAnswer::Success { notes, warnings })
 }
 
 /// public string-based validation function
@@ -137,8 +144,14 @@ struct ValidationNote {
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 enum ValidateAnswer {
-    ParseFailed { errors: Vec<String> },
-    Success { notes: Vec<ValidationNote> },
+    ParseFailed {
+        errors: Vec<String>,
+    },
+    Success {
+        notes: Vec<ValidationNote>,
+        #[serde(default)]
+        warnings: Vec<ValidationNote>,
+    },
 }
 
 #[cfg(test)]
@@ -276,7 +289,7 @@ mod test {
 "#.to_string();
 
         let result = json_validate(&call_json);
-        assert_validates_with_notes(result, 4);
+        assert_validates_with_notes(result, 2, 2);
     }
 
     #[test]
@@ -376,7 +389,7 @@ mod test {
 "#.to_string();
 
         let result = json_validate(&call_json);
-        assert_validates_with_notes(result, 2);
+        assert_validates_with_notes(result, 1, 1);
     }
 
     #[test]
@@ -428,7 +441,11 @@ mod test {
         }
     }
 
-    fn assert_validates_with_notes(result: InterfaceResult, expected_num_notes: usize) {
+    fn assert_validates_with_notes(
+        result: InterfaceResult,
+        expected_num_notes: usize,
+        expected_num_warnings: usize,
+    ) {
         match result {
             InterfaceResult::Success { result } => {
                 let parsed_result: ValidateAnswer = serde_json::from_str(result.as_str()).unwrap();
@@ -436,8 +453,9 @@ mod test {
                     ValidateAnswer::ParseFailed { .. } => {
                         panic!("expected parse to succeed, but got {parsed_result:?}")
                     }
-                    ValidateAnswer::Success { notes, .. } => {
+                    ValidateAnswer::Success { notes, warnings } => {
                         assert_eq!(notes.len(), expected_num_notes);
+                        assert_eq!(warnings.len(), expected_num_warnings);
                     }
                 }
             }
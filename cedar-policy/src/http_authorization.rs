@@ -0,0 +1,619 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Authorizing HTTP requests with a policy set, as tower middleware, so that
+//! the API of a dApp backend can be guarded by the same kind of policies as
+//! its transactions.
+//!
+//! An HTTP request is mapped to a Cedar request by [`HttpAuthorizer::request`]:
+//! * principal: `Address::"<address>"`, with the address in lowercase, if the
+//!   request has [`Claims`] with an address, such as one recovered from a
+//!   Sign-In with Ethereum message, or else `User::"<subject>"` if the claims
+//!   have a subject. The principal is unspecified if there are no claims.
+//! * action: `Action::"<method>"`, like `Action::"GET"`
+//! * resource: `Route::"<path>"`, like `Route::"/vaults/1"`
+//! * context: a record with the `method` and `path`, the non-empty `segments`
+//!   of the path, the `query` parameters as a record of strings, and the
+//!   `claims`, holding the attributes of the claims along with their
+//!   `address` and `subject`
+//!
+//! [`AuthorizationLayer`] wraps a service with an [`AuthorizationService`],
+//! which calls the service with the requests which are allowed, adding
+//! [`Authorized`] to their extensions, and answers the others with `403
+//! Forbidden` and the decision and diagnostics in JSON. The claims are read
+//! from the extensions of the request, so the authentication layer must run
+//! before this one.
+//!
+//! With the `axum` feature, [`Authorized`] is also an axum extractor, for
+//! guarding single handlers rather than whole services. It authorizes the
+//! request with the [`HttpAuthorizer`] in the state of the router, held in an
+//! `Arc`, and rejects the requests which are denied with `Forbidden`. A
+//! request which was already allowed by an [`AuthorizationService`] is not
+//! authorized again.
+//!
+//! ```
+//! # use cedar_policy::{http_authorization::*, Entities, PolicySet};
+//! let policies: PolicySet = r#"
+//!     permit (principal, action == Action::"GET", resource);
+//!     permit (principal == Address::"0x7c3250001bc0abeeef91f52e9054a9f951190132", action, resource)
+//!     when { context.segments.contains("vaults") };
+//! "#.parse().unwrap();
+//! let authorizer = HttpAuthorizer::new(policies, Entities::empty());
+//! let mut request = http::Request::post("/vaults/1/withdraw").body(()).unwrap();
+//! assert!(!authorizer.is_authorized(&request).allowed());
+//! request.extensions_mut().insert(Claims {
+//!     address: Some("0x7C3250001bC0ABEEeF91f52e9054A9f951190132".to_string()),
+//!     ..Claims::default()
+//! });
+//! assert!(authorizer.is_authorized(&request).allowed());
+//! // wraps a service, like `tower::Layer::layer`
+//! let _layer = authorizer.into_layer();
+//! ```
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+};
+
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+use tower_service::Service;
+
+use crate::{
+    frontend::is_authorized::InterfaceResponse, Authorizer, Context, ContextJsonError, Decision,
+    Entities, EntityUid, PolicyId, PolicySet, Request, Response, Schema,
+};
+
+/// Claims about the client of a request, established by authentication. The
+/// authentication layer inserts them into the extensions of the request.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Claims {
+    /// The Ethereum address of the client, such as one recovered from a
+    /// Sign-In with Ethereum message
+    pub address: Option<String>,
+    /// The subject of the client, such as the `sub` of a JWT
+    pub subject: Option<String>,
+    /// Other attributes of the client, in the JSON format of entity
+    /// attributes, which are available in `context.claims`
+    pub attrs: Map<String, Value>,
+}
+
+/// Added to the extensions of requests which are allowed, so that handlers can
+/// see why, e.g., with axum's `Extension` extractor.
+///
+/// With the `axum` feature, this is an extractor itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Authorized {
+    /// Ids of the policies which allowed the request
+    pub reasons: Vec<PolicyId>,
+}
+
+impl Authorized {
+    /// The reasons of `decision`, if it allows the request
+    fn from_decision(decision: &HttpDecision) -> Option<Self> {
+        match decision {
+            HttpDecision::Response(response) if response.decision() == Decision::Allow => {
+                Some(Self {
+                    reasons: response.diagnostics().reason().cloned().collect(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Errors mapping an HTTP request to a Cedar request
+#[derive(Debug, Error)]
+pub enum HttpAuthorizationError {
+    /// The context does not match the schema
+    #[error("invalid context: {0}")]
+    Context(#[from] ContextJsonError),
+}
+
+/// The result of authorizing an HTTP request
+#[derive(Debug)]
+pub enum HttpDecision {
+    /// The Cedar request was authorized, and this is the response
+    Response(Response),
+    /// The HTTP request could not be mapped to a Cedar request, so it is
+    /// denied
+    Invalid(HttpAuthorizationError),
+}
+
+impl HttpDecision {
+    /// Whether the request is allowed
+    pub fn allowed(&self) -> bool {
+        matches!(self, Self::Response(response) if response.decision() == Decision::Allow)
+    }
+
+    /// The decision and diagnostics in JSON, in the format of
+    /// [`InterfaceResponse`]
+    pub fn to_json(&self) -> Value {
+        let response = match self {
+            Self::Response(response) => InterfaceResponse::from(response.clone()),
+            Self::Invalid(err) => InterfaceResponse::new(
+                Decision::Deny,
+                std::iter::empty().collect(),
+                std::iter::once(err.to_string()).collect(),
+            ),
+        };
+        serde_json::to_value(response).unwrap_or_else(|e| json!({ "error": e.to_string() }))
+    }
+
+    /// `403 Forbidden`, with the decision and diagnostics in JSON
+    fn forbidden<B: From<String>>(&self) -> http::Response<B> {
+        let mut forbidden = http::Response::new(B::from(self.to_json().to_string()));
+        *forbidden.status_mut() = http::StatusCode::FORBIDDEN;
+        forbidden.headers_mut().insert(
+            http::header::CONTENT_TYPE,
+            http::HeaderValue::from_static("application/json"),
+        );
+        forbidden
+    }
+}
+
+/// Authorizes HTTP requests against a policy set and entities. See the
+/// [module documentation](self) for how HTTP requests are mapped to Cedar
+/// requests.
+#[derive(Debug)]
+pub struct HttpAuthorizer {
+    authorizer: Authorizer,
+    policies: PolicySet,
+    entities: Entities,
+    schema: Option<Schema>,
+}
+
+impl HttpAuthorizer {
+    /// An authorizer of requests against `policies` and `entities`
+    pub fn new(policies: PolicySet, entities: Entities) -> Self {
+        Self {
+            authorizer: Authorizer::new(),
+            policies,
+            entities,
+            schema: None,
+        }
+    }
+
+    /// Parse the contexts of requests with `schema`, which makes the claims
+    /// attributes follow the types declared for the context of each action.
+    /// Requests whose method is not an action of the schema are denied.
+    #[must_use]
+    pub fn with_schema(mut self, schema: Schema) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// The Cedar request for `request`
+    ///
+    /// # Errors
+    ///
+    /// If the context does not match the schema.
+    pub fn request<B>(
+        &self,
+        request: &http::Request<B>,
+    ) -> Result<Request, HttpAuthorizationError> {
+        self.request_of(request.method(), request.uri(), request.extensions())
+    }
+
+    /// The Cedar request for the HTTP request with `method`, `uri` and
+    /// `extensions`
+    fn request_of(
+        &self,
+        method: &http::Method,
+        uri: &http::Uri,
+        extensions: &http::Extensions,
+    ) -> Result<Request, HttpAuthorizationError> {
+        let claims = extensions.get::<Claims>();
+        let principal = match claims {
+            Some(Claims {
+                address: Some(address),
+                ..
+            }) => Some(EntityUid::from_known_type(
+                "Address",
+                &address.to_lowercase(),
+            )),
+            Some(Claims {
+                subject: Some(subject),
+                ..
+            }) => Some(EntityUid::from_known_type("User", subject)),
+            _ => None,
+        };
+        let method = method.as_str();
+        let path = uri.path();
+        let action = EntityUid::from_known_type("Action", method);
+        let resource = EntityUid::from_known_type("Route", path);
+
+        let query: Map<String, Value> = uri
+            .query()
+            .map(|query| {
+                form_urlencoded::parse(query.as_bytes())
+                    .map(|(key, value)| (key.into_owned(), Value::String(value.into_owned())))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut claims_json = claims
+            .map(|claims| claims.attrs.clone())
+            .unwrap_or_default();
+        if let Some(claims) = claims {
+            if let Some(address) = &claims.address {
+                claims_json.insert("address".to_string(), json!(address));
+            }
+            if let Some(subject) = &claims.subject {
+                claims_json.insert("subject".to_string(), json!(subject));
+            }
+        }
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let context = Context::from_json_value(
+            json!({
+                "method": method,
+                "path": path,
+                "segments": segments,
+                "query": query,
+                "claims": claims_json,
+            }),
+            self.schema.as_ref().map(|schema| (schema, &action)),
+        )?;
+        Ok(Request::new(
+            principal,
+            Some(action),
+            Some(resource),
+            context,
+        ))
+    }
+
+    /// Authorize `request`, which is denied if it cannot be mapped to a Cedar
+    /// request
+    pub fn is_authorized<B>(&self, request: &http::Request<B>) -> HttpDecision {
+        self.decide(self.request(request))
+    }
+
+    /// The decision on `request`
+    fn decide(&self, request: Result<Request, HttpAuthorizationError>) -> HttpDecision {
+        match request {
+            Ok(request) => HttpDecision::Response(self.authorizer.is_authorized(
+                &request,
+                &self.policies,
+                &self.entities,
+            )),
+            Err(err) => HttpDecision::Invalid(err),
+        }
+    }
+
+    /// A layer enforcing the decisions of this authorizer
+    pub fn into_layer(self) -> AuthorizationLayer {
+        AuthorizationLayer {
+            authorizer: Arc::new(self),
+        }
+    }
+}
+
+/// Wraps services with an [`AuthorizationService`]
+///
+/// [`AuthorizationLayer::layer`] has the signature of `tower::Layer::layer`,
+/// so the layer can be used with
+/// `tower::layer::layer_fn(|service| layer.layer(service))`.
+#[derive(Debug, Clone)]
+pub struct AuthorizationLayer {
+    authorizer: Arc<HttpAuthorizer>,
+}
+
+impl AuthorizationLayer {
+    /// Wrap `inner`, so that it only gets requests which are allowed
+    pub fn layer<S>(&self, inner: S) -> AuthorizationService<S> {
+        AuthorizationService {
+            authorizer: Arc::clone(&self.authorizer),
+            inner,
+        }
+    }
+}
+
+/// A service which calls `inner` with the requests which are allowed, and
+/// answers the others with `403 Forbidden`
+#[derive(Debug, Clone)]
+pub struct AuthorizationService<S> {
+    authorizer: Arc<HttpAuthorizer>,
+    inner: S,
+}
+
+impl<S, B, ResBody> Service<http::Request<B>> for AuthorizationService<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<ResBody>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+    ResBody: From<String> + Send + 'static,
+{
+    type Response = http::Response<ResBody>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<B>) -> Self::Future {
+        let decision = self.authorizer.is_authorized(&request);
+        match Authorized::from_decision(&decision) {
+            Some(authorized) => {
+                request.extensions_mut().insert(authorized);
+                Box::pin(self.inner.call(request))
+            }
+            None => Box::pin(std::future::ready(Ok(decision.forbidden()))),
+        }
+    }
+}
+
+/// The rejection of the [`Authorized`] extractor, which answers with `403
+/// Forbidden` and the decision and diagnostics in JSON
+#[cfg(feature = "axum")]
+#[derive(Debug)]
+pub struct Forbidden(Box<HttpDecision>);
+
+#[cfg(feature = "axum")]
+impl Forbidden {
+    /// The decision denying the request
+    pub fn decision(&self) -> &HttpDecision {
+        &self.0
+    }
+}
+
+#[cfg(feature = "axum")]
+impl axum_core::response::IntoResponse for Forbidden {
+    fn into_response(self) -> axum_core::response::Response {
+        self.0
+            .forbidden::<String>()
+            .map(axum_core::body::Body::from)
+    }
+}
+
+#[cfg(feature = "axum")]
+impl<S> axum_core::extract::FromRequestParts<S> for Authorized
+where
+    Arc<HttpAuthorizer>: axum_core::extract::FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Forbidden;
+
+    async fn from_request_parts(
+        parts: &mut http::request::Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        if let Some(authorized) = parts.extensions.get::<Self>() {
+            return Ok(authorized.clone());
+        }
+        let http_authorizer =
+            <Arc<HttpAuthorizer> as axum_core::extract::FromRef<S>>::from_ref(state);
+        let decision = http_authorizer.decide(http_authorizer.request_of(
+            &parts.method,
+            &parts.uri,
+            &parts.extensions,
+        ));
+        match Self::from_decision(&decision) {
+            Some(authorized) => {
+                parts.extensions.insert(authorized.clone());
+                Ok(authorized)
+            }
+            None => Err(Forbidden(Box::new(decision))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::Infallible;
+    use std::str::FromStr;
+
+    /// A service answering with the reasons it was authorized for
+    #[derive(Clone)]
+    struct Echo;
+
+    impl Service<http::Request<()>> for Echo {
+        type Response = http::Response<String>;
+        type Error = Infallible;
+        type Future = std::future::Ready<Result<Self::Response, Infallible>>;
+
+        fn poll_ready(&mut self, _: &mut TaskContext<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http::Request<()>) -> Self::Future {
+            let reasons = request
+                .extensions()
+                .get::<Authorized>()
+                .map(|authorized| {
+                    authorized
+                        .reasons
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(",")
+                })
+                .unwrap_or_default();
+            std::future::ready(Ok(http::Response::new(reasons)))
+        }
+    }
+
+    const OWNER: &str = "0x7c3250001bc0abeeef91f52e9054a9f951190132";
+
+    fn service() -> AuthorizationService<Echo> {
+        let policies = format!(
+            r#"
+            permit (principal, action == Action::"GET", resource);
+            permit (principal == Address::"{OWNER}", action == Action::"POST", resource)
+            when {{ context.segments.contains("vaults") && context.query.amount == "10" && context.claims.role == "owner" }};
+            "#
+        );
+        HttpAuthorizer::new(policies.parse().unwrap(), Entities::empty())
+            .into_layer()
+            .layer(Echo)
+    }
+
+    fn request(method: &str, uri: &str, claims: Option<Claims>) -> http::Request<()> {
+        let mut request = http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(())
+            .unwrap();
+        if let Some(claims) = claims {
+            request.extensions_mut().insert(claims);
+        }
+        request
+    }
+
+    fn owner() -> Claims {
+        Claims {
+            address: Some("0x7C3250001bC0ABEEeF91f52e9054A9f951190132".to_string()),
+            subject: None,
+            attrs: std::iter::once(("role".to_string(), json!("owner"))).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn allowed_requests_reach_the_service() {
+        let mut service = service();
+        let response = service
+            .call(request("GET", "/vaults/1", None))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+        assert_eq!(response.body(), "policy0");
+        let response = service
+            .call(request(
+                "POST",
+                "/vaults/1/withdraw?amount=10",
+                Some(owner()),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+        assert_eq!(response.body(), "policy1");
+    }
+
+    #[tokio::test]
+    async fn denied_requests_are_forbidden() {
+        let mut service = service();
+        for request in [
+            request("POST", "/vaults/1/withdraw?amount=10", None),
+            request("POST", "/vaults/1/withdraw?amount=11", Some(owner())),
+            request(
+                "POST",
+                "/vaults/1/withdraw?amount=10",
+                Some(Claims {
+                    address: None,
+                    subject: Some("alice".to_string()),
+                    ..owner()
+                }),
+            ),
+        ] {
+            let response = service.call(request).await.unwrap();
+            assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+            assert_eq!(
+                response.headers().get(http::header::CONTENT_TYPE),
+                Some(&http::HeaderValue::from_static("application/json"))
+            );
+            let body: Value = serde_json::from_str(response.body()).unwrap();
+            assert_eq!(body.get("decision"), Some(&json!("Deny")));
+        }
+    }
+
+    #[test]
+    fn schema() {
+        let schema = Schema::from_str_natural(
+            r#"
+            entity Address;
+            entity Route;
+            action "GET" appliesTo {
+              principal: [Address],
+              resource: [Route],
+              context: {
+                method: String,
+                path: String,
+                segments: Set<String>,
+                query: {},
+                claims: { address: String, limit: u256 }
+              }
+            };
+            "#,
+        )
+        .unwrap();
+        let authorizer = HttpAuthorizer::new(
+            r#"permit (principal, action, resource) when { context.claims.limit.u256LessThan(u256("1000")) };"#
+                .parse()
+                .unwrap(),
+            Entities::empty(),
+        )
+        .with_schema(schema);
+        let claims = Claims {
+            address: Some(OWNER.to_string()),
+            subject: None,
+            attrs: std::iter::once(("limit".to_string(), json!("100"))).collect(),
+        };
+        // the schema makes the limit a `u256`
+        assert!(authorizer
+            .is_authorized(&request("GET", "/vaults", Some(claims.clone())))
+            .allowed());
+        let decision = authorizer.is_authorized(&request("DELETE", "/vaults", Some(claims)));
+        assert!(matches!(
+            decision,
+            HttpDecision::Invalid(HttpAuthorizationError::Context(_))
+        ));
+        assert_eq!(decision.to_json().get("decision"), Some(&json!("Deny")));
+    }
+
+    #[cfg(feature = "axum")]
+    #[tokio::test]
+    async fn extractor() {
+        use axum_core::{extract::FromRequestParts, response::IntoResponse};
+
+        let authorizer = Arc::new(HttpAuthorizer::new(
+            format!(r#"permit (principal == Address::"{OWNER}", action, resource);"#)
+                .parse()
+                .unwrap(),
+            Entities::empty(),
+        ));
+        let (mut parts, ()) = request("POST", "/vaults/1", Some(owner())).into_parts();
+        let authorized = Authorized::from_request_parts(&mut parts, &authorizer)
+            .await
+            .unwrap();
+        assert_eq!(
+            authorized.reasons,
+            vec![PolicyId::from_str("policy0").unwrap()]
+        );
+        assert_eq!(parts.extensions.get::<Authorized>(), Some(&authorized));
+
+        let (mut parts, ()) = request("POST", "/vaults/1", None).into_parts();
+        let forbidden = Authorized::from_request_parts(&mut parts, &authorizer)
+            .await
+            .unwrap_err();
+        assert!(!forbidden.decision().allowed());
+        let response = forbidden.into_response();
+        assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.headers().get(http::header::CONTENT_TYPE),
+            Some(&http::HeaderValue::from_static("application/json"))
+        );
+
+        // requests allowed by the layer are not authorized again
+        let (mut parts, ()) = request("POST", "/vaults/1", None).into_parts();
+        parts.extensions.insert(Authorized { reasons: vec![] });
+        let authorized = Authorized::from_request_parts(&mut parts, &authorizer)
+            .await
+            .unwrap();
+        assert!(authorized.reasons.is_empty());
+    }
+}
@@ -21,6 +21,10 @@
 //! code in this file is used for two of those interfaces: the API in this
 //! `Cedar` package, and a special integration test in the `CedarDRT` package
 //! that uses the definitional implementation via `CustomCedarImpl`.
+//!
+//! The tests are run with [`crate::conformance`], and this module panics on
+//! their failures. To run tests in this format outside of those packages,
+//! without panicking, use [`crate::conformance`] directly.
 
 // This is test code that is under `src/` only so that it can be shared between
 // packages, so it's appropriate to exclude it from coverage.
@@ -33,73 +37,16 @@
 #![allow(clippy::expect_used)]
 
 use crate::{
-    frontend::is_authorized::InterfaceResponse, Authorizer, Context, Decision, Entities, EntityUid,
-    Policy, PolicyId, PolicySet, Request, Schema, ValidationMode, Validator,
+    conformance::{ConformanceError, TestCase},
+    Decision, ImportError, Policy, PolicyId, PolicySet,
 };
-use serde::Deserialize;
 use std::{
     env,
     path::{Path, PathBuf},
     str::FromStr,
 };
 
-/// JSON representation of our integration test file format
-#[derive(Debug, Deserialize)]
-#[serde(deny_unknown_fields)]
-pub struct JsonTest {
-    /// Filename of the policies to use (in pure Cedar syntax)
-    policies: String,
-    /// Filename of a JSON file representing the entity hierarchy
-    entities: String,
-    /// Filename of a JSON file containing the schema.
-    schema: String,
-    /// Whether the given policies are expected to pass the validator with this
-    /// schema, or not
-    should_validate: bool,
-    /// Requests to perform on that data, along with their expected results
-    /// Alias for backwards compatibility
-    #[serde(alias = "queries")]
-    requests: Vec<JsonRequest>,
-}
-
-/// JSON representation of a single request, along with its expected result,
-/// in our integration test file format
-#[derive(Debug, Deserialize)]
-#[serde(deny_unknown_fields)]
-pub struct JsonRequest {
-    /// Description for the request
-    desc: String,
-    /// Principal for the request, in either explicit or implicit `__entity` form
-    ///
-    /// Examples:
-    /// * `{ "__entity": { "type": "User", "id": "123abc" } }`
-    /// * `{ "type": "User", "id": "123abc" }`
-    #[serde(default)]
-    principal: Option<serde_json::Value>,
-    /// Action for the request, in either explicit or implicit `__entity` form
-    ///
-    /// Examples:
-    /// * `{ "__entity": { "type": "Action", "id": "view" } }`
-    /// * `{ "type": "Action", "id": "view" }`
-    #[serde(default)]
-    action: Option<serde_json::Value>,
-    /// Resource for the request, in either explicit or implicit `__entity` form
-    ///
-    /// Examples:
-    /// * `{ "__entity": { "type": "User", "id": "123abc" } }`
-    /// * `{ "type": "User", "id": "123abc" }`
-    #[serde(default)]
-    resource: Option<serde_json::Value>,
-    /// Context for the request. This should be a JSON object, not any other kind
-    /// of JSON value
-    context: serde_json::Value,
-    /// Expected decision for the request
-    decision: Decision,
-    /// Expected "reasons" for the request
-    reasons: Vec<String>,
-    /// Expected error/warning messages for the request
-    errors: Vec<String>,
-}
+pub use crate::conformance::{CustomCedarImpl, IntegrationTestValidationResult};
 
 /// For relative paths, return the absolute path, assuming that the path
 /// is relative to the root of the `CedarIntegrationTests` repo.
@@ -124,48 +71,6 @@ pub fn resolve_integration_test_path(path: impl AsRef<Path>) -> PathBuf {
     }
 }
 
-/// Data structure for the validation result of an integration test. Unlike a
-/// definitional authorization response, a definitional validation result isn't
-/// feasible to convert to its production analogue, so instead, we define a
-/// simple data structure to which both can be converted that is sufficient for
-/// the checks we want to perform.
-#[derive(Debug)]
-pub struct IntegrationTestValidationResult {
-    /// Whether the test inputs passed validation.
-    pub validation_passed: bool,
-    /// Information about validation errors that may be shown to the user for
-    /// diagnostic purposes. As the name suggests, it's customary to use the
-    /// `Debug` representation of the original data structure.
-    pub validation_errors_debug: String,
-}
-
-/// A custom Cedar implementation (authorizer + validator) on which to run
-/// integration tests instead of the `Cedar` API.
-pub trait CustomCedarImpl {
-    /// Custom authorizer entry point.
-    fn is_authorized(
-        &self,
-        q: &cedar_policy_core::ast::Request,
-        p: &cedar_policy_core::ast::PolicySet,
-        e: &cedar_policy_core::entities::Entities,
-    ) -> InterfaceResponse;
-
-    /// Custom validator entry point.
-    ///
-    /// The fact that this API takes ownership of `schema` is a quirk that
-    /// parallels the equivalent production code pattern, in which construction
-    /// of the `Validator` would take ownership of the schema. Indeed, the only
-    /// current implementation of this method is based on
-    /// `DefinitionalValidator::validate`, which seems to have replicated this
-    /// quirk even though it has no apparent implementation need to take
-    /// ownership of the schema.
-    fn validate(
-        &self,
-        schema: cedar_policy_validator::ValidatorSchema,
-        policies: &cedar_policy_core::ast::PolicySet,
-    ) -> IntegrationTestValidationResult;
-}
-
 /// Given the filename of a JSON file describing an integration test, perform
 /// the test. If a custom Cedar implementation is provided, then use it for the
 /// test, otherwise perform the test on the `Cedar` API.
@@ -174,173 +79,68 @@ pub trait CustomCedarImpl {
 /// cedar-integration-tests folder.
 /// Absolute paths are handled without modification.
 /// # Panics
-/// When integration test data cannot be found
-#[allow(clippy::too_many_lines)]
+/// When integration test data cannot be found, or the test fails
 pub fn perform_integration_test_from_json_custom(
     jsonfile: impl AsRef<Path>,
     custom_impl_opt: Option<&dyn CustomCedarImpl>,
 ) {
     let jsonfile = resolve_integration_test_path(jsonfile);
     eprintln!("File path: {jsonfile:?}");
-    let jsonstr = std::fs::read_to_string(jsonfile.as_path())
-        .unwrap_or_else(|e| panic!("error reading from file {}: {e}", jsonfile.display()));
-    let test: JsonTest = serde_json::from_str(&jsonstr)
-        .unwrap_or_else(|e| panic!("error parsing {}: {e}", jsonfile.display()));
-    let policy_file = resolve_integration_test_path(&test.policies);
-    let policies_text = std::fs::read_to_string(policy_file)
-        .unwrap_or_else(|e| panic!("error loading policy file {}: {e}", &test.policies));
-    // If parsing fails we don't want to quit immediately. Instead we want to
-    // check that the expected decision is "Deny" and that the parse error is
-    // of the expected type.
-    let policies_res = PolicySet::from_str(&policies_text);
-    if let Err(parse_errs) = policies_res {
-        // We may see a `NotAFunction` parse error for auto-generated policies:
-        // See the comment in the `ExtensionFunctionApp` case of the `Display`
-        // implementation for `Expr` in ast/exprs.rs.
-        for json_request in test.requests {
-            assert_eq!(
-                json_request.decision,
-                Decision::Deny,
-                "test {} failed for request \"{}\" \n Parse errors should only occur for deny",
+    let test = TestCase::from_file(&jsonfile).unwrap_or_else(|e| panic!("{e}"));
+    let inputs = match test.load_inputs(resolve_integration_test_path("")) {
+        Ok(inputs) => inputs,
+        // If parsing fails we don't want to quit immediately. Instead we want
+        // to check that the expected decision is "Deny" and that the parse
+        // error is of the expected type.
+        Err(ConformanceError::Policies {
+            source: ImportError::Parse { errs, .. },
+            ..
+        }) => {
+            // We may see a `NotAFunction` parse error for auto-generated policies:
+            // See the comment in the `ExtensionFunctionApp` case of the `Display`
+            // implementation for `Expr` in ast/exprs.rs.
+            for request in &test.requests {
+                assert_eq!(
+                    request.decision,
+                    Decision::Deny,
+                    "test {} failed for request \"{}\" \n Parse errors should only occur for deny",
+                    jsonfile.display(),
+                    &request.desc
+                );
+            }
+            assert!(
+                errs.to_string().contains("not a function"),
+                "unexpected parse errors in test {}: {errs}",
                 jsonfile.display(),
-                &json_request.desc
             );
+            return;
         }
-        assert!(
-            parse_errs
-                .errors_as_strings()
-                .iter()
-                .any(|s| s.ends_with("not a function")),
-            "unexpected parse errors in test {}: {}",
-            jsonfile.display(),
-            parse_errs,
-        );
-        return;
-    }
-    let policies = policies_res
-        .unwrap_or_else(|e| panic!("error parsing policy in file {}: {e}", &test.policies));
-    let schema_file = resolve_integration_test_path(&test.schema);
-    let schema_text = std::fs::read_to_string(schema_file)
-        .unwrap_or_else(|e| panic!("error loading schema file {}: {e}", &test.schema));
-    let schema = Schema::from_str(&schema_text)
-        .unwrap_or_else(|e| panic!("error parsing schema in {}: {e}", &test.schema));
-    let entity_file = resolve_integration_test_path(&test.entities);
-    let entities_json = std::fs::OpenOptions::new()
-        .read(true)
-        .open(entity_file)
-        .unwrap_or_else(|e| panic!("error opening entity file {}: {e}", &test.entities));
-    let entities = Entities::from_json_file(&entities_json, Some(&schema))
-        .unwrap_or_else(|e| panic!("error parsing entities in {}: {e}", &test.entities));
-
-    let validation_result = if let Some(custom_impl) = custom_impl_opt {
-        custom_impl.validate(schema.clone().0, &policies.ast)
-    } else {
-        let validator = Validator::new(schema.clone());
-        let api_result = validator.validate(&policies, ValidationMode::default());
-        IntegrationTestValidationResult {
-            validation_passed: api_result.validation_passed(),
-            validation_errors_debug: format!(
-                "{:?}",
-                api_result.validation_errors().collect::<Vec<_>>()
-            ),
-        }
+        Err(e) => panic!("in test {}: {e}", jsonfile.display()),
     };
-    if test.should_validate {
-        assert!(
-            validation_result.validation_passed,
-            "Unexpected validation errors in {}: {}",
-            jsonfile.display(),
-            validation_result.validation_errors_debug
-        );
-    } else {
-        assert!(
-            !validation_result.validation_passed,
-            "Expected that validation would fail in {}, but it did not.",
-            jsonfile.display(),
-        );
-    }
-
-    for json_request in test.requests {
-        let principal = json_request.principal.map(|json| {
-            EntityUid::from_json(json).unwrap_or_else(|e| {
-                panic!(
-                    "Failed to parse principal for request \"{}\" in {}: {e}",
-                    json_request.desc,
-                    jsonfile.display()
-                )
-            })
-        });
-        let action = json_request.action.map(|json| {
-            EntityUid::from_json(json).unwrap_or_else(|e| {
-                panic!(
-                    "Failed to parse action for request \"{}\" in {}: {e}",
-                    json_request.desc,
-                    jsonfile.display()
-                )
-            })
-        });
-        let resource = json_request.resource.map(|json| {
-            EntityUid::from_json(json).unwrap_or_else(|e| {
-                panic!(
-                    "Failed to parse resource for request \"{}\" in {}: {e}",
-                    json_request.desc,
-                    jsonfile.display()
-                )
-            })
-        });
-        let context_schema = action.as_ref().map(|a| (&schema, a));
-        let context = Context::from_json_value(json_request.context, context_schema)
-            .unwrap_or_else(|e| {
-                panic!(
-                    "error parsing context for request \"{}\" in {}: {e}",
-                    json_request.desc,
-                    jsonfile.display()
-                )
-            });
-        let request = Request::new(principal, action, resource, context);
-        let response = if let Some(custom_impl) = custom_impl_opt {
-            custom_impl.is_authorized(&request.0, &policies.ast, &entities.0)
-        } else {
-            Authorizer::new()
-                .is_authorized(&request, &policies, &entities)
-                .into()
-        };
-
-        let expected_response = InterfaceResponse::new(
-            json_request.decision,
-            json_request
-                .reasons
-                .into_iter()
-                .map(|s| PolicyId::from_str(&s).unwrap())
-                .collect(),
-            json_request.errors.into_iter().collect(),
-        );
-
-        assert_eq!(
-            response,
-            expected_response,
-            "test {} failed for request \"{}\"",
-            jsonfile.display(),
-            &json_request.desc
-        );
-
-        // test that EST roundtrip works for this policy set
-        // we can't test that the roundtrip produces the same policies exactly
-        // (because the roundtrip is lossy), but we can at least test that it
-        // roundtrips without errors
-        let ests = policies
-            .policies()
-            .map(|p| p.to_json().expect("should convert to JSON successfully"));
-
-        PolicySet::from_policies(ests.enumerate().map(|(i, est)| {
-            let id = PolicyId::from_str(&format!("policy{i}")).expect("id should be valid");
-            Policy::from_json(Some(id), est.clone()).unwrap_or_else(|e| {
-                panic!("in test {}, failed to build policy from JSON successfully: {e}\n\ntext policy was:\n{}\n\nJSON policy was: {}\n",
-                jsonfile.display(), policies.policies().nth(i).unwrap(), serde_json::to_string_pretty(&est).unwrap())
-            })
-        }))
-        .expect("should convert to PolicySet successfully");
-    }
+    let report = test.run_inputs(&inputs, custom_impl_opt);
+    assert!(
+        report.passed(),
+        "test {} failed:\n{report}",
+        jsonfile.display()
+    );
+
+    // test that EST roundtrip works for this policy set
+    // we can't test that the roundtrip produces the same policies exactly
+    // (because the roundtrip is lossy), but we can at least test that it
+    // roundtrips without errors
+    let policies = &inputs.policies;
+    let ests = policies
+        .policies()
+        .map(|p| p.to_json().expect("should convert to JSON successfully"));
+
+    PolicySet::from_policies(ests.enumerate().map(|(i, est)| {
+        let id = PolicyId::from_str(&format!("policy{i}")).expect("id should be valid");
+        Policy::from_json(Some(id), est.clone()).unwrap_or_else(|e| {
+            panic!("in test {}, failed to build policy from JSON successfully: {e}\n\ntext policy was:\n{}\n\nJSON policy was: {}\n",
+            jsonfile.display(), policies.policies().nth(i).unwrap(), serde_json::to_string_pretty(&est).unwrap())
+        })
+    }))
+    .expect("should convert to PolicySet successfully");
 }
 
 /// Specialization of `perform_integration_test_from_json_custom` that performs
@@ -34,8 +34,157 @@
 mod api;
 pub use api::*;
 
+/// Loading policy sets split across files with `import`
+mod policy_files;
+pub use policy_files::*;
+
+/// Editing policy set text without losing its comments and layout
+mod policy_text;
+pub use policy_text::*;
+
+/// Where the templates and links of a policy set came from
+mod provenance;
+pub use provenance::*;
+
+/// The Cedar values of ABI-encoded values
+#[cfg(any(feature = "deployment", feature = "event-logs"))]
+mod abi_value;
+
 /// Frontend utilities, see comments in the module itself
 pub mod frontend;
 
+/// Linting policies for style and common mistakes
+pub mod lint;
+
+/// Explaining how the policies of a policy set combine into a decision
+pub mod explain;
+
+/// Analyzing which principals and resources gain or lose access under a
+/// change to a policy set
+pub mod analysis;
+
+/// The dependency graph of a policy set, between its templates, policies,
+/// entity types and actions
+pub mod policy_graph;
+
+/// Capturing everything one evaluation consulted into a capsule which can be
+/// replayed to reproduce its decision
+pub mod capsule;
+
+/// Catalogs of the templates of a policy set and of their slots, for
+/// generating forms to link them
+pub mod template_catalog;
+
+/// Role grants on resources to principals, modelled as template links
+pub mod roles;
+
+/// Exporting policy sets and schemas for upstream Cedar
+pub mod export;
+
+/// Importing the rules of Rego modules as policies
+pub mod rego;
+
+/// Canonical JSON of policy sets, entities and requests, for hashing
+pub mod canonical;
+
+/// Running conformance tests in the format of the integration tests
+pub mod conformance;
+
+/// Generators of random schemas, entities, policies and requests
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary;
+
+/// Authorizing HTTP requests, as tower middleware
+#[cfg(feature = "http")]
+pub mod http_authorization;
+
+/// An audit log of authorization decisions
+#[cfg(feature = "audit")]
+pub mod audit;
+
+/// Policy and entity sources reloaded into a running authorizer
+#[cfg(feature = "sources")]
+pub mod sources;
+
+/// Requests signed by their principal
+#[cfg(feature = "signed-requests")]
+pub mod signed_request;
+
+/// Decisions which must be approved by a threshold of approvers
+#[cfg(feature = "approvals")]
+pub mod approvals;
+
+/// Spending limits read from a store of the amounts spent
+#[cfg(feature = "quota")]
+pub mod quota;
+
+/// Schema fragments declaring the standard contexts of requests
+#[cfg(feature = "context-schemas")]
+pub mod context_schemas;
+
+/// Policies and entities partitioned by tenant
+#[cfg(feature = "tenancy")]
+pub mod tenancy;
+
+/// Session key permissions, converted to and from policies
+#[cfg(feature = "session-keys")]
+pub mod session_key;
+
+/// Screening the entities of requests with risk and compliance services
+#[cfg(feature = "screening")]
+pub mod screening;
+
+/// Categorizing transactions for coarse policies
+#[cfg(feature = "classify")]
+pub mod classify;
+
+/// Authorizing the deployment of contracts
+#[cfg(feature = "deployment")]
+pub mod deployment;
+
+/// Authorizing event logs for indexers
+#[cfg(feature = "event-logs")]
+pub mod event_log;
+
+/// Authorizing JSON-RPC read requests for RPC gateways
+#[cfg(feature = "rpc")]
+pub mod rpc;
+
+/// Testing the transactions of Foundry scripts against policies
+#[cfg(feature = "forge")]
+pub mod forge;
+
+/// Revisions of a policy set, with rollback and an auditable history
+#[cfg(feature = "policy-store")]
+pub mod policy_store;
+
+/// A policy set, its links and its schema in a single verified file
+#[cfg(feature = "bundle")]
+pub mod bundle;
+
+/// Metrics for the authorization path
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
+/// Caching the entities of a provider, with TTLs
+#[cfg(feature = "entity-cache")]
+pub mod entity_cache;
+
+/// Caching authorization decisions
+#[cfg(feature = "decision-cache")]
+pub mod decision_cache;
+
+/// A Redis store for entity and decision caches
+#[cfg(feature = "redis-store")]
+pub mod redis_store;
+
+/// SQL-backed entity store
+#[cfg(feature = "sql")]
+pub mod sql_entity_store;
+
+/// Protobuf messages for entities, requests, and responses
+#[cfg(feature = "protobufs")]
+pub mod proto;
+
 #[cfg(feature = "integration_testing")]
 pub mod integration_testing;
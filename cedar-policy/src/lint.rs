@@ -0,0 +1,664 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Linting policies for style and common mistakes.
+//!
+//! A [`Linter`] runs a set of [`LintRule`]s over each static policy and
+//! template of a policy set, and reports what they find as [`Lint`]s. Each
+//! rule has a name, such as `unconditional-permit`, and a [`Severity`], which
+//! may be changed to report its lints as errors or to turn it off. Besides the
+//! built-in rules in this module, a linter can run any type implementing
+//! [`LintRule`].
+
+use crate::{Effect, PolicyId, PolicySet, Template};
+use cedar_policy_core::ast::{self, BinaryOp, ExprKind, Literal, UnaryOp};
+use std::borrow::Cow;
+use std::ops::Range;
+use thiserror::Error;
+
+/// How a [`Linter`] reports the findings of a rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The rule is not run
+    Allow,
+    /// Findings are reported as warnings
+    Warning,
+    /// Findings are reported as errors, e.g., to fail a CI check
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Allow => write!(f, "allow"),
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found in a policy or template by a [`LintRule`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    message: String,
+    range: Option<Range<usize>>,
+}
+
+impl Finding {
+    /// A problem with the policy as a whole
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            range: None,
+        }
+    }
+
+    /// A problem with the part of the policy at the byte range `range` of the
+    /// policy set text, such as one given by [`Template::source_location`]
+    pub fn at(message: impl Into<String>, range: Range<usize>) -> Self {
+        Self {
+            message: message.into(),
+            range: Some(range),
+        }
+    }
+
+    /// A problem at `range`, or with the policy as a whole if that is unknown
+    fn located(message: impl Into<String>, range: Option<Range<usize>>) -> Self {
+        Self {
+            message: message.into(),
+            range,
+        }
+    }
+}
+
+/// A check which a [`Linter`] runs on each static policy and template
+pub trait LintRule {
+    /// The name of the rule, in kebab-case, e.g., `require-annotations`. A
+    /// linter runs at most one rule with each name.
+    fn name(&self) -> &str;
+
+    /// Check a static policy or template, returning the problems found
+    fn check(&self, policy: &Template) -> Vec<Finding>;
+}
+
+/// A problem found by a [`Linter`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    rule: String,
+    severity: Severity,
+    policy_id: PolicyId,
+    finding: Finding,
+}
+
+impl Lint {
+    /// The name of the rule which found the problem
+    pub fn rule(&self) -> &str {
+        &self.rule
+    }
+
+    /// The severity of the rule which found the problem, either
+    /// [`Severity::Warning`] or [`Severity::Error`]
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// The id of the static policy or template with the problem
+    pub fn policy_id(&self) -> &PolicyId {
+        &self.policy_id
+    }
+
+    /// A description of the problem
+    pub fn message(&self) -> &str {
+        &self.finding.message
+    }
+
+    /// The byte range of the policy set text with the problem, if the rule
+    /// gave one and the policy was parsed from text
+    pub fn range(&self) -> Option<Range<usize>> {
+        self.finding.range.clone()
+    }
+}
+
+impl std::fmt::Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} `{}` on policy `{}`: {}",
+            self.severity, self.rule, self.policy_id, self.finding.message
+        )
+    }
+}
+
+/// Error configuring a [`Linter`] rule which it does not have
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("unknown lint rule `{0}`")]
+pub struct UnknownLintRule(String);
+
+/// Runs [`LintRule`]s over policy sets
+pub struct Linter {
+    rules: Vec<(Box<dyn LintRule>, Severity)>,
+}
+
+impl std::fmt::Debug for Linter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(
+                self.rules
+                    .iter()
+                    .map(|(rule, severity)| (rule.name(), severity)),
+            )
+            .finish()
+    }
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Linter {
+    /// A linter with all of the built-in rules, each reporting warnings:
+    /// [`ComplexUnless`], [`RequireAnnotations`] for `@id` and `@owner`,
+    /// [`UnconditionalPermit`] and, with the `address` feature,
+    /// [`UnchecksummedAddress`]
+    pub fn new() -> Self {
+        Self::empty()
+            .with_rule(ComplexUnless)
+            .with_rule(RequireAnnotations::default())
+            .with_rule(UnconditionalPermit)
+            .with_address_rules()
+    }
+
+    #[cfg(feature = "address")]
+    fn with_address_rules(self) -> Self {
+        self.with_rule(UnchecksummedAddress)
+    }
+
+    #[cfg(not(feature = "address"))]
+    fn with_address_rules(self) -> Self {
+        self
+    }
+
+    /// A linter with no rules
+    pub fn empty() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Add `rule`, reporting warnings. This replaces any rule with the same
+    /// name, e.g., to configure a built-in rule differently.
+    #[must_use]
+    pub fn with_rule(mut self, rule: impl LintRule + 'static) -> Self {
+        let rule: Box<dyn LintRule> = Box::new(rule);
+        match self.rules.iter_mut().find(|(r, _)| r.name() == rule.name()) {
+            Some(entry) => *entry = (rule, Severity::Warning),
+            None => self.rules.push((rule, Severity::Warning)),
+        }
+        self
+    }
+
+    /// Set the severity of the rule named `name`
+    ///
+    /// # Errors
+    ///
+    /// If there is no rule named `name`.
+    pub fn with_severity(
+        mut self,
+        name: &str,
+        severity: Severity,
+    ) -> Result<Self, UnknownLintRule> {
+        match self.rules.iter_mut().find(|(r, _)| r.name() == name) {
+            Some((_, s)) => {
+                *s = severity;
+                Ok(self)
+            }
+            None => Err(UnknownLintRule(name.to_string())),
+        }
+    }
+
+    /// The names of the rules of this linter, in the order they are run
+    pub fn rule_names(&self) -> impl Iterator<Item = &str> {
+        self.rules.iter().map(|(rule, _)| rule.name())
+    }
+
+    /// Run the rules which are not [`Severity::Allow`] on each static policy
+    /// and template of `policies`. Template-linked policies are checked
+    /// through their templates. The lints are ordered by policy id, and then
+    /// by the order of the rules.
+    pub fn lint(&self, policies: &PolicySet) -> Vec<Lint> {
+        let mut templates = policies
+            .policies()
+            .filter(|p| p.is_static())
+            .map(|p| Cow::Owned(p.static_template()))
+            .chain(policies.templates().map(Cow::Borrowed))
+            .collect::<Vec<_>>();
+        templates.sort_by_key(|t| t.id().to_string());
+        let mut lints = Vec::new();
+        for template in &templates {
+            for (rule, severity) in &self.rules {
+                if *severity == Severity::Allow {
+                    continue;
+                }
+                lints.extend(rule.check(template).into_iter().map(|finding| Lint {
+                    rule: rule.name().to_string(),
+                    severity: *severity,
+                    policy_id: template.id().clone(),
+                    finding,
+                }));
+            }
+        }
+        lints
+    }
+}
+
+/// The byte range of an expression in the policy set text
+fn expr_range(expr: &ast::Expr) -> Option<Range<usize>> {
+    expr.source_info()
+        .as_ref()
+        .map(|info| info.range_start()..info.range_end())
+}
+
+/// Flags `forbid` policies with a double negative in their conditions.
+///
+/// This is an `unless` clause, or other negated condition, which itself
+/// contains a negation, as in
+/// `unless { !context.verified }` or `unless { context.chain != "mainnet" }`.
+/// Such double negatives are easy to misread, so the policy may forbid the
+/// opposite of what was meant; a `when` clause without the inner negation
+/// says the same more plainly.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ComplexUnless;
+
+impl LintRule for ComplexUnless {
+    fn name(&self) -> &'static str {
+        "complex-unless"
+    }
+
+    fn check(&self, policy: &Template) -> Vec<Finding> {
+        if policy.effect() != Effect::Forbid {
+            return Vec::new();
+        }
+        let condition = policy.ast().non_head_constraints();
+        let mut conjuncts = vec![condition];
+        let mut findings = Vec::new();
+        while let Some(expr) = conjuncts.pop() {
+            match expr.expr_kind() {
+                ExprKind::And { left, right } => {
+                    conjuncts.push(right);
+                    conjuncts.push(left);
+                }
+                ExprKind::UnaryApp {
+                    op: UnaryOp::Not,
+                    arg,
+                } if arg.subexpressions().any(|e| {
+                    matches!(
+                        e.expr_kind(),
+                        ExprKind::UnaryApp {
+                            op: UnaryOp::Not,
+                            ..
+                        }
+                    )
+                }) =>
+                {
+                    let message =
+                        "this condition negates an expression which contains a negation; \
+                                   consider a `when` clause without the double negative";
+                    findings.push(Finding::located(message, expr_range(expr)));
+                }
+                _ => (),
+            }
+        }
+        findings
+    }
+}
+
+/// Flags policies without each of a set of annotations, e.g., so that every
+/// policy has an `@id` to refer to it by and an `@owner` to ask about it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequireAnnotations {
+    keys: Vec<String>,
+}
+
+impl RequireAnnotations {
+    /// Require the annotations with these keys, e.g., `"id"` for `@id`
+    pub fn new(keys: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            keys: keys.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl Default for RequireAnnotations {
+    /// Require `@id` and `@owner`
+    fn default() -> Self {
+        Self::new(["id", "owner"])
+    }
+}
+
+impl LintRule for RequireAnnotations {
+    fn name(&self) -> &'static str {
+        "require-annotations"
+    }
+
+    fn check(&self, policy: &Template) -> Vec<Finding> {
+        let location = policy.source_location();
+        let range = location
+            .range_start()
+            .zip(location.range_end())
+            .map(|(start, end)| start..end);
+        self.keys
+            .iter()
+            .filter(|key| policy.annotation(key).is_none())
+            .map(|key| Finding::located(format!("missing the `@{key}` annotation"), range.clone()))
+            .collect()
+    }
+}
+
+/// Flags `permit` policies without `when` or `unless` clauses, which allow
+/// every request their scope matches
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UnconditionalPermit;
+
+impl LintRule for UnconditionalPermit {
+    fn name(&self) -> &'static str {
+        "unconditional-permit"
+    }
+
+    fn check(&self, policy: &Template) -> Vec<Finding> {
+        let unconditional = matches!(
+            policy.ast().non_head_constraints().expr_kind(),
+            ExprKind::Lit(Literal::Bool(true))
+        );
+        if policy.effect() != Effect::Permit || !unconditional {
+            return Vec::new();
+        }
+        let message =
+            "this permit policy has no conditions, so it allows every request its scope matches";
+        let location = policy.source_location();
+        let range = location
+            .range_start()
+            .zip(location.range_end())
+            .map(|(start, end)| start..end);
+        vec![Finding::located(message, range)]
+    }
+}
+
+/// Flags equality tests on addresses which may not do what was meant.
+///
+/// These are an `address(...)` whose string is not in the mixed-case EIP-55
+/// checksum form, so a typo in it goes unnoticed, and a string which looks
+/// like an address, which `==` compares case-sensitively
+#[cfg(feature = "address")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UnchecksummedAddress;
+
+#[cfg(feature = "address")]
+impl UnchecksummedAddress {
+    /// Whether `s` is `0x` followed by 40 hex digits
+    fn looks_like_address(s: &str) -> bool {
+        s.strip_prefix("0x").is_some_and(|digits| {
+            digits.len() == 40 && digits.bytes().all(|b| b.is_ascii_hexdigit())
+        })
+    }
+
+    /// The checksummed form of the address `s`, if it is a valid address
+    fn checksummed(s: &str) -> Option<String> {
+        use cedar_policy_core::ast::{Name, RestrictedExpr};
+        use cedar_policy_core::evaluator::RestrictedEvaluator;
+        use cedar_policy_core::extensions::Extensions;
+
+        let name = Name::parse_unqualified_name("address").ok()?;
+        let expr = RestrictedExpr::call_extension_fn(name, vec![RestrictedExpr::val(s)]);
+        let extensions = Extensions::all_available();
+        RestrictedEvaluator::new(&extensions)
+            .interpret(expr.as_borrowed())
+            .ok()
+            .map(|value| value.to_string())
+    }
+
+    fn check_operand(expr: &ast::Expr) -> Option<String> {
+        match expr.expr_kind() {
+            ExprKind::Lit(Literal::String(s)) if Self::looks_like_address(s) => Some(format!(
+                "`\"{s}\"` is compared as a string, which is case-sensitive; \
+                 compare `address(\"{s}\")` values instead"
+            )),
+            ExprKind::ExtensionFunctionApp { fn_name, args }
+                if fn_name.to_string() == "address" =>
+            {
+                match args.as_slice() {
+                    [arg] => match arg.expr_kind() {
+                        ExprKind::Lit(Literal::String(s)) => Self::checksummed(s)
+                            .filter(|checksummed| checksummed != s.as_str())
+                            .map(|checksummed| {
+                                format!(
+                                    "`{s}` is not checksummed, so a typo in it would not be \
+                                     caught; write it as `{checksummed}`"
+                                )
+                            }),
+                        _ => None,
+                    },
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "address")]
+impl LintRule for UnchecksummedAddress {
+    fn name(&self) -> &'static str {
+        "unchecksummed-address"
+    }
+
+    fn check(&self, policy: &Template) -> Vec<Finding> {
+        let condition = policy.ast().non_head_constraints();
+        let mut findings = condition
+            .subexpressions()
+            .filter_map(|expr| match expr.expr_kind() {
+                ExprKind::BinaryApp {
+                    op: BinaryOp::Eq,
+                    arg1,
+                    arg2,
+                } => Some([arg1, arg2]),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|operand| {
+                Self::check_operand(operand)
+                    .map(|message| Finding::located(message, expr_range(operand)))
+            })
+            .collect::<Vec<_>>();
+        findings.sort_by_key(|finding| finding.range.as_ref().map(|range| range.start));
+        findings
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    fn lint(src: &str) -> Vec<(String, String)> {
+        let policies = PolicySet::from_str(src).expect("should parse");
+        Linter::new()
+            .with_severity("require-annotations", Severity::Allow)
+            .unwrap()
+            .lint(&policies)
+            .into_iter()
+            .map(|lint| (lint.rule().to_string(), lint.policy_id().to_string()))
+            .collect()
+    }
+
+    fn rules(src: &str) -> Vec<String> {
+        lint(src)
+            .into_iter()
+            .map(|(rule, _)| rule)
+            .collect::<Vec<_>>()
+    }
+
+    #[test]
+    fn complex_unless() {
+        assert_eq!(
+            rules(r#"forbid(principal, action, resource) unless { !context.verified };"#),
+            vec!["complex-unless"]
+        );
+        assert_eq!(
+            rules(
+                r#"forbid(principal, action, resource)
+                when { context.amount > 100 }
+                unless { context.chain != "mainnet" || context.verified };"#
+            ),
+            vec!["complex-unless"]
+        );
+        assert!(
+            rules(r#"forbid(principal, action, resource) unless { context.verified };"#).is_empty()
+        );
+        assert!(rules(
+            r#"forbid(principal, action, resource) when { context.chain != "mainnet" };"#
+        )
+        .is_empty());
+        // only `forbid` policies are checked
+        assert_eq!(
+            rules(r#"permit(principal, action, resource) unless { !context.blocked };"#),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn require_annotations() {
+        let policies = PolicySet::from_str(
+            r#"
+            @id("treasury") @owner("ops")
+            permit(principal, action, resource) when { context.verified };
+            @id("admins")
+            permit(principal, action, resource) when { context.admin };
+            "#,
+        )
+        .unwrap();
+        let lints = Linter::empty()
+            .with_rule(RequireAnnotations::default())
+            .lint(&policies);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].policy_id().to_string(), "policy1");
+        assert_eq!(lints[0].message(), "missing the `@owner` annotation");
+        assert_eq!(
+            lints[0].to_string(),
+            "warning `require-annotations` on policy `policy1`: missing the `@owner` annotation"
+        );
+
+        let lints = Linter::empty()
+            .with_rule(RequireAnnotations::new(["id"]))
+            .lint(&policies);
+        assert!(lints.is_empty());
+    }
+
+    #[test]
+    fn unconditional_permit() {
+        let src = "permit(principal, action, resource);\n\
+                   permit(principal, action, resource) when { context.verified };\n\
+                   forbid(principal, action, resource);";
+        assert_eq!(
+            lint(src),
+            vec![("unconditional-permit".into(), "policy0".into())]
+        );
+        let policies = PolicySet::from_str(src).unwrap();
+        let lint = &Linter::empty()
+            .with_rule(UnconditionalPermit)
+            .lint(&policies)[0];
+        assert_eq!(
+            lint.range(),
+            Some(0.."permit(principal, action, resource);".len())
+        );
+        // templates are checked too
+        assert_eq!(
+            rules("permit(principal == ?principal, action, resource);"),
+            vec!["unconditional-permit"]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "address")]
+    fn unchecksummed_address() {
+        let src = r#"permit(principal, action, resource) when {
+            context.to == address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed") &&
+            context.from == address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed") &&
+            context.token == "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"
+        };"#;
+        let policies = PolicySet::from_str(src).unwrap();
+        let lints = Linter::empty()
+            .with_rule(UnchecksummedAddress)
+            .lint(&policies);
+        assert_eq!(lints.len(), 2);
+        assert_eq!(
+            lints[0].message(),
+            "`0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed` is not checksummed, so a typo in it \
+             would not be caught; write it as `0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed`"
+        );
+        let range = lints[0].range().unwrap();
+        assert_eq!(
+            &src[range],
+            r#"address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed")"#
+        );
+        assert!(lints[1].message().contains("compared as a string"));
+    }
+
+    #[test]
+    fn configure_rules() {
+        struct NoWildcardResource;
+
+        impl LintRule for NoWildcardResource {
+            fn name(&self) -> &'static str {
+                "no-wildcard-resource"
+            }
+
+            fn check(&self, policy: &Template) -> Vec<Finding> {
+                match policy.resource_constraint() {
+                    crate::TemplateResourceConstraint::Any => {
+                        vec![Finding::new("the policy applies to every resource")]
+                    }
+                    _ => Vec::new(),
+                }
+            }
+        }
+
+        let policies = PolicySet::from_str("permit(principal, action, resource);").unwrap();
+        let linter = Linter::new()
+            .with_rule(NoWildcardResource)
+            .with_severity("unconditional-permit", Severity::Error)
+            .unwrap()
+            .with_severity("require-annotations", Severity::Allow)
+            .unwrap();
+        let lints = linter
+            .lint(&policies)
+            .into_iter()
+            .map(|lint| (lint.rule().to_string(), lint.severity()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lints,
+            vec![
+                ("unconditional-permit".to_string(), Severity::Error),
+                ("no-wildcard-resource".to_string(), Severity::Warning)
+            ]
+        );
+        assert_eq!(
+            Linter::new()
+                .with_severity("no-such-rule", Severity::Error)
+                .unwrap_err(),
+            UnknownLintRule("no-such-rule".into())
+        );
+    }
+}
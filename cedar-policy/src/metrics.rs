@@ -0,0 +1,339 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Metrics for the authorization path.
+//!
+//! [`Authorizer::is_authorized_with_metrics`](crate::Authorizer::is_authorized_with_metrics)
+//! reports each request to a [`Metrics`], which embedders implement to feed
+//! the metrics system they already use. [`PrometheusMetrics`] is an
+//! implementation which keeps the metrics in memory and renders them in the
+//! Prometheus text format, as served by the `/metrics` endpoint of the CLI's
+//! `server` command:
+//! * `cedar_authorization_decisions_total`: requests, by `decision`
+//! * `cedar_policy_evaluation_errors_total`: policies which failed to
+//!   evaluate, by `policy_id`
+//! * `cedar_authorization_duration_seconds`: a histogram of how long requests
+//!   took to authorize
+//! * `cedar_entity_cache_lookups_total`: requests, by whether the attributes of
+//!   their entities had already been computed by
+//!   [`Entities::evaluate`](crate::Entities::evaluate) (`result="hit"`) or had
+//!   to be computed for the request (`result="miss"`)
+//!
+//! ```
+//! # use cedar_policy::{metrics::PrometheusMetrics, Authorizer, Context, Entities, PolicySet, Request};
+//! let policies: PolicySet = r#"permit(principal, action, resource);"#.parse().unwrap();
+//! let request = Request::new(None, None, None, Context::empty());
+//! let metrics = PrometheusMetrics::new();
+//! Authorizer::new().is_authorized_with_metrics(&request, &policies, &Entities::empty(), &metrics);
+//! assert!(metrics
+//!     .render()
+//!     .contains(r#"cedar_authorization_decisions_total{decision="allow"} 1"#));
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+    time::Duration,
+};
+
+use crate::{Decision, PolicyId};
+
+/// Receives the metrics of each request authorized with
+/// [`Authorizer::is_authorized_with_metrics`](crate::Authorizer::is_authorized_with_metrics).
+/// Every method does nothing by default.
+pub trait Metrics: Send + Sync {
+    /// Called with the decision of each request
+    fn record_decision(&self, _decision: Decision) {}
+
+    /// Called for each policy which failed to evaluate
+    fn record_evaluation_error(&self, _policy_id: &PolicyId) {}
+
+    /// Called with how long each request took to authorize
+    fn record_latency(&self, _latency: Duration) {}
+
+    /// Called for each request with whether the attributes of its entities
+    /// had already been computed by [`Entities::evaluate`](crate::Entities::evaluate)
+    fn record_cache_lookup(&self, _hit: bool) {}
+}
+
+/// The upper bounds of the buckets of the latency histogram
+const LATENCY_BUCKETS: [Duration; 12] = [
+    Duration::from_micros(10),
+    Duration::from_micros(50),
+    Duration::from_micros(100),
+    Duration::from_micros(250),
+    Duration::from_micros(500),
+    Duration::from_millis(1),
+    Duration::from_micros(2500),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(25),
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+];
+
+/// [`Metrics`] kept in memory and rendered in the Prometheus text format
+#[derive(Debug, Default)]
+pub struct PrometheusMetrics {
+    allows: AtomicU64,
+    denies: AtomicU64,
+    /// Evaluation errors by policy id
+    errors: Mutex<BTreeMap<String, u64>>,
+    /// The number of requests in each bucket of [`LATENCY_BUCKETS`], not
+    /// counting those in earlier buckets
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    latency_count: AtomicU64,
+    latency_sum_nanos: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+}
+
+impl PrometheusMetrics {
+    /// Create metrics with every count zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The fraction of requests whose entity attributes had already been
+    /// computed, or `None` if there have been no requests
+    #[allow(clippy::cast_precision_loss)]
+    pub fn cache_hit_rate(&self) -> Option<f64> {
+        let hits = self.cache_hits.load(Ordering::Relaxed);
+        let total = hits + self.cache_misses.load(Ordering::Relaxed);
+        (total > 0).then(|| hits as f64 / total as f64)
+    }
+
+    /// The metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        // writing to a `String` cannot fail
+        let _ = self.write(&mut out);
+        out
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn write(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(
+            out,
+            "# HELP cedar_authorization_decisions_total Authorization requests, by decision"
+        )?;
+        writeln!(out, "# TYPE cedar_authorization_decisions_total counter")?;
+        for (decision, count) in [("allow", &self.allows), ("deny", &self.denies)] {
+            writeln!(
+                out,
+                "cedar_authorization_decisions_total{{decision=\"{decision}\"}} {}",
+                count.load(Ordering::Relaxed)
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP cedar_policy_evaluation_errors_total Policies which failed to evaluate, by policy id"
+        )?;
+        writeln!(out, "# TYPE cedar_policy_evaluation_errors_total counter")?;
+        for (policy_id, count) in self
+            .errors
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+        {
+            writeln!(
+                out,
+                "cedar_policy_evaluation_errors_total{{policy_id=\"{}\"}} {count}",
+                escape_label(policy_id)
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP cedar_authorization_duration_seconds How long authorization requests took"
+        )?;
+        writeln!(out, "# TYPE cedar_authorization_duration_seconds histogram")?;
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(&self.latency_buckets) {
+            cumulative += count.load(Ordering::Relaxed);
+            writeln!(
+                out,
+                "cedar_authorization_duration_seconds_bucket{{le=\"{}\"}} {cumulative}",
+                bound.as_secs_f64()
+            )?;
+        }
+        let count = self.latency_count.load(Ordering::Relaxed);
+        writeln!(
+            out,
+            "cedar_authorization_duration_seconds_bucket{{le=\"+Inf\"}} {count}"
+        )?;
+        writeln!(
+            out,
+            "cedar_authorization_duration_seconds_sum {}",
+            self.latency_sum_nanos.load(Ordering::Relaxed) as f64 / 1e9
+        )?;
+        writeln!(out, "cedar_authorization_duration_seconds_count {count}")?;
+
+        writeln!(
+            out,
+            "# HELP cedar_entity_cache_lookups_total Authorization requests, by whether the attributes of their entities were already computed"
+        )?;
+        writeln!(out, "# TYPE cedar_entity_cache_lookups_total counter")?;
+        for (result, count) in [("hit", &self.cache_hits), ("miss", &self.cache_misses)] {
+            writeln!(
+                out,
+                "cedar_entity_cache_lookups_total{{result=\"{result}\"}} {}",
+                count.load(Ordering::Relaxed)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Metrics for PrometheusMetrics {
+    fn record_decision(&self, decision: Decision) {
+        let count = match decision {
+            Decision::Allow => &self.allows,
+            Decision::Deny => &self.denies,
+        };
+        count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_evaluation_error(&self, policy_id: &PolicyId) {
+        *self
+            .errors
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(policy_id.as_ref().to_string())
+            .or_default() += 1;
+    }
+
+    fn record_latency(&self, latency: Duration) {
+        if let Some(bucket) = LATENCY_BUCKETS
+            .iter()
+            .zip(&self.latency_buckets)
+            .find_map(|(bound, bucket)| (latency <= *bound).then_some(bucket))
+        {
+            bucket.fetch_add(1, Ordering::Relaxed);
+        }
+        self.latency_count.fetch_add(1, Ordering::Relaxed);
+        self.latency_sum_nanos.fetch_add(
+            u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+    }
+
+    fn record_cache_lookup(&self, hit: bool) {
+        let count = if hit {
+            &self.cache_hits
+        } else {
+            &self.cache_misses
+        };
+        count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Escape `value` for use as a label value in the Prometheus text format
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Entities, EntityUid, PolicySet, Request};
+    use std::str::FromStr;
+
+    fn authorize(policies: &str, entities: &Entities, metrics: &dyn Metrics) -> Decision {
+        let policies = PolicySet::from_str(policies).unwrap_or_else(|_| PolicySet::new());
+        let request = Request::new(
+            EntityUid::from_str(r#"Address::"0xabc""#).ok(),
+            EntityUid::from_str(r#"Action::"transfer""#).ok(),
+            None,
+            Context::empty(),
+        );
+        Authorizer::new()
+            .is_authorized_with_metrics(&request, &policies, entities, metrics)
+            .decision()
+    }
+
+    #[test]
+    fn records_decisions_errors_and_cache_lookups() {
+        let metrics = PrometheusMetrics::new();
+        let policies = r#"
+            permit(principal, action == Action::"transfer", resource);
+            forbid(principal, action, resource) when { principal.nonce > 5 };
+        "#;
+        let empty = Entities::empty();
+        assert_eq!(authorize(policies, &empty, &metrics), Decision::Allow);
+        assert_eq!(authorize("", &empty, &metrics), Decision::Deny);
+        let evaluated = Entities::empty()
+            .evaluate()
+            .expect("no attributes to evaluate");
+        assert_eq!(authorize("", &evaluated, &metrics), Decision::Deny);
+
+        let rendered = metrics.render();
+        for line in [
+            r#"cedar_authorization_decisions_total{decision="allow"} 1"#,
+            r#"cedar_authorization_decisions_total{decision="deny"} 2"#,
+            r#"cedar_policy_evaluation_errors_total{policy_id="policy1"} 1"#,
+            r#"cedar_authorization_duration_seconds_bucket{le="+Inf"} 3"#,
+            "cedar_authorization_duration_seconds_count 3",
+            r#"cedar_entity_cache_lookups_total{result="hit"} 1"#,
+            r#"cedar_entity_cache_lookups_total{result="miss"} 2"#,
+        ] {
+            assert!(
+                rendered.lines().any(|l| l == line),
+                "missing `{line}` in:\n{rendered}"
+            );
+        }
+        assert_eq!(metrics.cache_hit_rate(), Some(1.0 / 3.0));
+    }
+
+    #[test]
+    fn latency_buckets_are_cumulative() {
+        let metrics = PrometheusMetrics::new();
+        metrics.record_latency(Duration::from_micros(5));
+        metrics.record_latency(Duration::from_micros(200));
+        metrics.record_latency(Duration::from_secs(2));
+        let rendered = metrics.render();
+        for line in [
+            r#"cedar_authorization_duration_seconds_bucket{le="0.00001"} 1"#,
+            r#"cedar_authorization_duration_seconds_bucket{le="0.00025"} 2"#,
+            r#"cedar_authorization_duration_seconds_bucket{le="1"} 2"#,
+            r#"cedar_authorization_duration_seconds_bucket{le="+Inf"} 3"#,
+            "cedar_authorization_duration_seconds_count 3",
+        ] {
+            assert!(
+                rendered.lines().any(|l| l == line),
+                "missing `{line}` in:\n{rendered}"
+            );
+        }
+        assert_eq!(metrics.cache_hit_rate(), None);
+    }
+
+    #[test]
+    fn escapes_policy_ids() {
+        let metrics = PrometheusMetrics::new();
+        metrics.record_evaluation_error(&PolicyId::from_str("a \"b\"\n").expect("any id is valid"));
+        assert!(metrics
+            .render()
+            .contains(r#"cedar_policy_evaluation_errors_total{policy_id="a \"b\"\n"} 1"#));
+    }
+}
@@ -0,0 +1,294 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Loading a policy set which is split across files.
+//!
+//! A policy file can start with `import "path.cedar";` statements, which add
+//! the policies of the imported files to the policy set and let the file use
+//! the names they define with `def`. Import paths are relative to the
+//! directory of the importing file, and must lead to a file in the directory
+//! of the file the policy set is loaded from, or one below it.
+
+use crate::{Origin, PolicySet};
+pub use cedar_policy_core::parser::ImportError;
+use cedar_policy_core::parser::{self, PolicyFiles};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+impl PolicySet {
+    /// Load the policy set in the file at `path`, along with the files it
+    /// imports, and the files they import, and so on. Each file is loaded
+    /// once, even if several files import it.
+    ///
+    /// Only files in the directory of `path`, or below it, can be imported,
+    /// after following symbolic links.
+    ///
+    /// The policies of the file at `path` have the same ids as for
+    /// [`PolicySet::from_str`](std::str::FromStr::from_str), `policy0`,
+    /// `policy1`, ..., while those of an imported file are prefixed with its
+    /// path relative to the directory of `path`, as in
+    /// `tokens/usdc.cedar:policy0`.
+    ///
+    /// The [`Provenance`](crate::Provenance) of each template and policy records the path of
+    /// the file it is in, and its span in that file.
+    ///
+    /// # Errors
+    ///
+    /// If a file cannot be read or is outside the directory of `path`, does
+    /// not parse or has errors in its imports or definitions, or files import
+    /// each other in a cycle.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ImportError> {
+        let (mut files, root) = Files::new(path.as_ref())?;
+        let (texts, origins, pset) = parser::parse_policyset_files_with_origins(&root, &mut files)?;
+        let mut pset = Self::from_texts_and_ast(&texts, pset);
+        let origins = origins
+            .iter()
+            .map(|(id, file)| (id.as_ref(), file))
+            .collect::<HashMap<&str, _>>();
+        pset.record_origins(|id| {
+            origins
+                .get(id.as_ref())
+                .map(|file| Origin::File(files.dir.join(file)))
+        });
+        Ok(pset)
+    }
+
+    /// The paths of the file at `path` and of the files
+    /// [`PolicySet::from_file`] reads for its imports, e.g., to load the
+    /// policy set again when one of them changes. If the policy set does not
+    /// load, these are the files read before the error, including the one
+    /// which could not be read or does not parse.
+    pub fn source_files(path: impl AsRef<Path>) -> Vec<PathBuf> {
+        let path = path.as_ref();
+        let Ok((mut files, root)) = Files::new(path) else {
+            return vec![path.to_path_buf()];
+        };
+        // the files read are wanted whether or not the policy set loads
+        let _ = parser::parse_policyset_files_with_origins(&root, &mut files);
+        files.read.iter().map(|name| files.dir.join(name)).collect()
+    }
+}
+
+/// Policy files in the file system, named by their paths relative to `dir`
+struct Files {
+    dir: PathBuf,
+    /// `dir` with symbolic links resolved, which every file must be in
+    canonical_dir: PathBuf,
+    /// The names of the files read so far
+    read: Vec<String>,
+}
+
+impl Files {
+    /// The files of the policy set in the file at `path`, along with the name
+    /// of that file
+    fn new(path: &Path) -> Result<(Self, String), ImportError> {
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let root = path
+            .file_name()
+            .map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy())
+            .into_owned();
+        let canonical_dir = match Path::new(".").join(&dir).canonicalize() {
+            Ok(canonical_dir) => canonical_dir,
+            Err(error) => return Err(ImportError::Read { file: root, error }),
+        };
+        let files = Self {
+            dir,
+            canonical_dir,
+            read: Vec::new(),
+        };
+        Ok((files, root))
+    }
+}
+
+impl PolicyFiles for Files {
+    fn resolve(&self, importer: &str, path: &str) -> String {
+        let importer_dir = Path::new(importer)
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+        let mut resolved = PathBuf::new();
+        for component in importer_dir.join(path).components() {
+            match component {
+                Component::CurDir => (),
+                Component::ParentDir
+                    if matches!(
+                        resolved.components().next_back(),
+                        Some(Component::Normal(_))
+                    ) =>
+                {
+                    resolved.pop();
+                }
+                component => resolved.push(component),
+            }
+        }
+        // name a file reached through symbolic links by its real path, so
+        // that it is loaded once however it is imported. Files outside `dir`
+        // keep their name, and are rejected by `read`.
+        self.dir
+            .join(&resolved)
+            .canonicalize()
+            .ok()
+            .and_then(|path| {
+                path.strip_prefix(&self.canonical_dir)
+                    .ok()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| resolved.to_string_lossy().into_owned())
+    }
+
+    fn read(&mut self, name: &str) -> std::io::Result<String> {
+        self.read.push(name.to_string());
+        let path = self.dir.join(name).canonicalize()?;
+        if !path.starts_with(&self.canonical_dir) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "the file is outside the directory of the policy set",
+            ));
+        }
+        std::fs::read_to_string(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::PolicyId;
+    use cool_asserts::assert_matches;
+    use std::str::FromStr;
+
+    fn write(dir: &Path, name: &str, text: &str) {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, text).unwrap();
+    }
+
+    #[test]
+    fn load_policy_files() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "tokens/mainnet.cedar",
+            r#"def USDC = Token::"0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";"#,
+        );
+        write(
+            dir.path(),
+            "vaults/main.cedar",
+            r#"import "../tokens/mainnet.cedar";
+            permit(principal, action, resource == Vault::"main")
+            when { context.token == USDC };"#,
+        );
+        write(
+            dir.path(),
+            "policies.cedar",
+            r#"import "vaults/main.cedar";
+            import "./tokens/mainnet.cedar";
+            forbid(principal, action, resource) unless { context.token == USDC };"#,
+        );
+
+        let pset = PolicySet::from_file(dir.path().join("policies.cedar")).expect("should load");
+        let mut ids = pset
+            .policies()
+            .map(|p| p.id().to_string())
+            .collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, vec!["policy0", "vaults/main.cedar:policy0"]);
+        let policy = pset
+            .policy(&PolicyId::from_str("vaults/main.cedar:policy0").unwrap())
+            .unwrap();
+        assert!(policy
+            .to_string()
+            .contains(r#"Token::"0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48""#));
+        assert!(policy.to_json().is_ok());
+        let provenance = pset.provenance(policy.id()).unwrap();
+        assert_eq!(
+            provenance.origin,
+            Origin::File(dir.path().join("vaults/main.cedar"))
+        );
+        assert_eq!(provenance.span, Some(46..142));
+
+        assert_matches!(
+            PolicySet::from_file(dir.path().join("missing.cedar")),
+            Err(ImportError::Read { file, .. }) => assert_eq!(file, "missing.cedar")
+        );
+
+        let mut files = PolicySet::source_files(dir.path().join("policies.cedar"));
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                dir.path().join("policies.cedar"),
+                dir.path().join("tokens/mainnet.cedar"),
+                dir.path().join("vaults/main.cedar"),
+            ]
+        );
+        write(
+            dir.path(),
+            "vaults/main.cedar",
+            r#"import "../tokens/missing.cedar";"#,
+        );
+        let mut files = PolicySet::source_files(dir.path().join("policies.cedar"));
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                dir.path().join("policies.cedar"),
+                dir.path().join("tokens/missing.cedar"),
+                dir.path().join("vaults/main.cedar"),
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_imports_outside_the_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "secret.cedar",
+            "permit(principal, action, resource);",
+        );
+        write(
+            dir.path(),
+            "policies/escape.cedar",
+            r#"import "../secret.cedar";"#,
+        );
+        assert_matches!(
+            PolicySet::from_file(dir.path().join("policies/escape.cedar")),
+            Err(ImportError::Read { file, error }) => {
+                assert_eq!(file, "../secret.cedar");
+                assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+            }
+        );
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(
+                dir.path().join("secret.cedar"),
+                dir.path().join("policies/link.cedar"),
+            )
+            .unwrap();
+            write(
+                dir.path(),
+                "policies/link_escape.cedar",
+                r#"import "link.cedar";"#,
+            );
+            assert_matches!(
+                PolicySet::from_file(dir.path().join("policies/link_escape.cedar")),
+                Err(ImportError::Read { error, .. }) => {
+                    assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+                }
+            );
+        }
+    }
+}
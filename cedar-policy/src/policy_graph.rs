@@ -0,0 +1,426 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The dependency graph of a policy set, so that auditors can see at a glance
+//! how a large policy set is put together.
+//!
+//! A [`PolicyGraph`] has a node for each template, static or template-linked
+//! policy, entity type and action of a policy set, and edges
+//! * from each template-linked policy to its template,
+//! * from each template and static policy to the entity types and actions
+//!   named in its scope, and to those of the entity literals in its
+//!   conditions, and
+//! * from each template-linked policy to the entity types of the entities it
+//!   is linked with.
+//!
+//! It exports as JSON, for a web UI, and in the Graphviz DOT language.
+//!
+//! ```
+//! # use cedar_policy::policy_graph::*;
+//! # use cedar_policy::PolicySet;
+//! let policies: PolicySet = r#"
+//!     permit(principal in Group::"signers", action == Action::"transfer", resource);"#
+//!     .parse()
+//!     .unwrap();
+//! let graph = PolicyGraph::new(&policies);
+//! let policy = Node::Policy("policy0".to_string());
+//! assert!(graph.edges().any(|edge| edge.from == policy
+//!     && edge.relation == Relation::Principal
+//!     && edge.to == Node::EntityType("Group".to_string())));
+//! println!("{}", graph.to_dot());
+//! ```
+
+use std::collections::BTreeSet;
+use std::fmt::{self, Display};
+
+use cedar_policy_core::ast::{self, EntityUID, ExprKind, Literal};
+
+use crate::PolicySet;
+
+/// A node of a [`PolicyGraph`]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Node {
+    /// The template with the id
+    Template(String),
+    /// The static or template-linked policy with the id
+    Policy(String),
+    /// The entity type with the name
+    EntityType(String),
+    /// The action with the UID, like `Action::"transfer"`
+    Action(String),
+}
+
+impl Node {
+    /// The kind of node: `template`, `policy`, `entityType` or `action`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Template(_) => "template",
+            Self::Policy(_) => "policy",
+            Self::EntityType(_) => "entityType",
+            Self::Action(_) => "action",
+        }
+    }
+
+    /// The id of the template or policy, the name of the entity type, or the
+    /// UID of the action
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Template(name)
+            | Self::Policy(name)
+            | Self::EntityType(name)
+            | Self::Action(name) => name,
+        }
+    }
+
+    /// The node of the entity type of `uid`, or of `uid` if it is an action
+    fn of_uid(uid: &EntityUID) -> Self {
+        if uid.is_action() {
+            Self::Action(uid.to_string())
+        } else {
+            Self::EntityType(uid.entity_type().to_string())
+        }
+    }
+}
+
+impl Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.kind(), self.name())
+    }
+}
+
+/// How the source of an edge of a [`PolicyGraph`] depends on its target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Relation {
+    /// The policy is linked from the template
+    Template,
+    /// The target is named in the principal constraint, or linked into the
+    /// `?principal` slot
+    Principal,
+    /// The target is named in the action constraint
+    Action,
+    /// The target is named in the resource constraint, or linked into the
+    /// `?resource` slot
+    Resource,
+    /// The target is named by an entity literal in the conditions
+    Condition,
+}
+
+impl Relation {
+    /// The name of the relation: `template`, `principal`, `action`,
+    /// `resource` or `condition`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Template => "template",
+            Self::Principal => "principal",
+            Self::Action => "action",
+            Self::Resource => "resource",
+            Self::Condition => "condition",
+        }
+    }
+}
+
+impl Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An edge of a [`PolicyGraph`], from a template or policy to what it
+/// depends on
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Edge {
+    /// The template or policy
+    pub from: Node,
+    /// How it depends on `to`
+    pub relation: Relation,
+    /// The template, entity type or action it depends on
+    pub to: Node,
+}
+
+/// The dependency graph of a policy set
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PolicyGraph {
+    nodes: BTreeSet<Node>,
+    edges: BTreeSet<Edge>,
+}
+
+impl PolicyGraph {
+    /// The dependency graph of `policies`
+    pub fn new(policies: &PolicySet) -> Self {
+        let mut graph = Self::default();
+        for template in policies.templates() {
+            graph.add_template(Node::Template(template.id().to_string()), template.ast());
+        }
+        for policy in policies.policies() {
+            let node = Node::Policy(policy.id().to_string());
+            let ast = policy.ast();
+            if ast.is_static() {
+                graph.add_template(node, ast.template());
+            } else {
+                graph.add_edge(
+                    node.clone(),
+                    Relation::Template,
+                    Node::Template(ast.template().id().to_string()),
+                );
+                for (slot, uid) in ast.env() {
+                    let relation = if slot.is_principal() {
+                        Relation::Principal
+                    } else {
+                        Relation::Resource
+                    };
+                    graph.add_edge(node.clone(), relation, Node::of_uid(uid));
+                }
+                graph.nodes.insert(node);
+            }
+        }
+        graph
+    }
+
+    fn add_edge(&mut self, from: Node, relation: Relation, to: Node) {
+        self.nodes.insert(from.clone());
+        self.nodes.insert(to.clone());
+        self.edges.insert(Edge { from, relation, to });
+    }
+
+    /// Add `node`, with the edges of the scope and conditions of `template`
+    fn add_template(&mut self, node: Node, template: &ast::Template) {
+        let scope = template
+            .principal_constraint()
+            .as_inner()
+            .iter_euids()
+            .map(|uid| (Relation::Principal, uid))
+            .chain(
+                template
+                    .action_constraint()
+                    .iter_euids()
+                    .map(|uid| (Relation::Action, uid)),
+            )
+            .chain(
+                template
+                    .resource_constraint()
+                    .as_inner()
+                    .iter_euids()
+                    .map(|uid| (Relation::Resource, uid)),
+            );
+        let literals =
+            template
+                .non_head_constraints()
+                .subexpressions()
+                .filter_map(|expr| match expr.expr_kind() {
+                    ExprKind::Lit(Literal::EntityUID(uid)) => {
+                        Some((Relation::Condition, uid.as_ref()))
+                    }
+                    _ => None,
+                });
+        for (relation, uid) in scope.chain(literals) {
+            self.add_edge(node.clone(), relation, Node::of_uid(uid));
+        }
+        self.nodes.insert(node);
+    }
+
+    /// The nodes, sorted by kind and then by name
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.nodes.iter()
+    }
+
+    /// The edges, sorted by their source, relation and target
+    pub fn edges(&self) -> impl Iterator<Item = &Edge> {
+        self.edges.iter()
+    }
+
+    /// The templates and policies which depend on `node` directly, such as
+    /// those naming an entity type, or linked from a template
+    pub fn dependents<'a>(&'a self, node: &'a Node) -> impl Iterator<Item = &'a Node> {
+        self.edges
+            .iter()
+            .filter(move |edge| &edge.to == node)
+            .map(|edge| &edge.from)
+    }
+
+    /// The graph as JSON: an object with the `nodes`, each with its `id`
+    /// (like `policy:policy0`), `kind` and `name`, and the `edges`, each
+    /// `from` the id of a template or policy `to` the id of what it depends
+    /// on, with their `relation`
+    pub fn to_json(&self) -> serde_json::Value {
+        let nodes: Vec<serde_json::Value> = self
+            .nodes
+            .iter()
+            .map(|node| {
+                serde_json::json!({
+                    "id": node.to_string(),
+                    "kind": node.kind(),
+                    "name": node.name(),
+                })
+            })
+            .collect();
+        let edges: Vec<serde_json::Value> = self
+            .edges
+            .iter()
+            .map(|edge| {
+                serde_json::json!({
+                    "from": edge.from.to_string(),
+                    "to": edge.to.to_string(),
+                    "relation": edge.relation.as_str(),
+                })
+            })
+            .collect();
+        serde_json::json!({ "nodes": nodes, "edges": edges })
+    }
+
+    /// The graph in the Graphviz DOT language, with templates as dashed
+    /// boxes, policies as boxes, entity types as ellipses and actions as
+    /// diamonds, and edges labeled with their relation
+    pub fn to_dot(&self) -> String {
+        fn quote(s: &str) -> String {
+            format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+        let nodes = self.nodes.iter().map(|node| {
+            let shape = match node {
+                Node::Template(_) => "box, style=dashed",
+                Node::Policy(_) => "box",
+                Node::EntityType(_) => "ellipse",
+                Node::Action(_) => "diamond",
+            };
+            format!(
+                "  {} [label={}, shape={shape}];\n",
+                quote(&node.to_string()),
+                quote(node.name())
+            )
+        });
+        let edges = self.edges.iter().map(|edge| {
+            format!(
+                "  {} -> {} [label={}];\n",
+                quote(&edge.from.to_string()),
+                quote(&edge.to.to_string()),
+                quote(edge.relation.as_str())
+            )
+        });
+        std::iter::once("digraph policies {\n".to_string())
+            .chain(nodes)
+            .chain(edges)
+            .chain(std::iter::once("}\n".to_string()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{EntityUid, SlotId};
+    use std::collections::HashMap;
+
+    fn policies() -> PolicySet {
+        let mut policies: PolicySet = r#"
+            permit(principal in Group::"signers", action in [Action::"transfer", Action::"approve"], resource)
+            when { context.token == Token::"usdc" };
+            forbid(principal, action, resource == Vault::"frozen");
+            permit(principal == ?principal, action == Action::"transfer", resource in ?resource);"#
+            .parse()
+            .expect("valid policies");
+        let links = HashMap::from([
+            (
+                SlotId::principal(),
+                r#"Wallet::"0xabc""#.parse::<EntityUid>().expect("valid uid"),
+            ),
+            (
+                SlotId::resource(),
+                r#"Vault::"main""#.parse::<EntityUid>().expect("valid uid"),
+            ),
+        ]);
+        policies
+            .link(
+                "policy2".parse().expect("valid id"),
+                "link0".parse().expect("valid id"),
+                links,
+            )
+            .expect("links");
+        policies
+    }
+
+    fn edge(from: Node, relation: Relation, to: Node) -> Edge {
+        Edge { from, relation, to }
+    }
+
+    fn policy(id: &str) -> Node {
+        Node::Policy(id.to_string())
+    }
+
+    fn entity_type(name: &str) -> Node {
+        Node::EntityType(name.to_string())
+    }
+
+    #[test]
+    fn dependencies() {
+        let graph = PolicyGraph::new(&policies());
+        let edges: Vec<&Edge> = graph.edges().collect();
+        let template = Node::Template("policy2".to_string());
+        let transfer = Node::Action(r#"Action::"transfer""#.to_string());
+        assert_eq!(
+            edges,
+            [
+                &edge(template.clone(), Relation::Action, transfer.clone()),
+                &edge(policy("link0"), Relation::Template, template.clone()),
+                &edge(policy("link0"), Relation::Principal, entity_type("Wallet")),
+                &edge(policy("link0"), Relation::Resource, entity_type("Vault")),
+                &edge(policy("policy0"), Relation::Principal, entity_type("Group")),
+                &edge(
+                    policy("policy0"),
+                    Relation::Action,
+                    Node::Action(r#"Action::"approve""#.to_string())
+                ),
+                &edge(policy("policy0"), Relation::Action, transfer.clone()),
+                &edge(policy("policy0"), Relation::Condition, entity_type("Token")),
+                &edge(policy("policy1"), Relation::Resource, entity_type("Vault")),
+            ]
+        );
+        let vault = entity_type("Vault");
+        let dependents: Vec<&Node> = graph.dependents(&vault).collect();
+        assert_eq!(dependents, [&policy("link0"), &policy("policy1")]);
+        let dependents: Vec<&Node> = graph.dependents(&transfer).collect();
+        assert_eq!(dependents, [&template, &policy("policy0")]);
+        assert_eq!(graph.nodes().count(), 10);
+    }
+
+    #[test]
+    fn exports() {
+        let graph = PolicyGraph::new(&policies());
+        let json = graph.to_json();
+        assert_eq!(
+            json.get("nodes")
+                .and_then(serde_json::Value::as_array)
+                .map(Vec::len),
+            Some(10)
+        );
+        assert!(json
+            .get("edges")
+            .and_then(serde_json::Value::as_array)
+            .is_some_and(|edges| edges.contains(&serde_json::json!({
+                "from": "policy:link0",
+                "to": "template:policy2",
+                "relation": "template",
+            }))));
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph policies {\n"), "{dot}");
+        assert!(
+            dot.contains(r#"  "template:policy2" [label="policy2", shape=box, style=dashed];"#),
+            "{dot}"
+        );
+        assert!(
+            dot.contains(r#"  "policy:policy0" -> "action:Action::\"approve\"" [label="action"];"#),
+            "{dot}"
+        );
+    }
+}
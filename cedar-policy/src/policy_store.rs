@@ -0,0 +1,670 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A history of the revisions of a policy set, for governance workflows which
+//! must keep an auditable trail of every change to the policies.
+//!
+//! A [`VersionedPolicyStore`] records each version of a policy set as an
+//! immutable [`Revision`], with its author, message and timestamp, and the
+//! hash of the revision before it. The hash of a revision is the SHA-256 hash
+//! of the canonical JSON (see [`crate::canonical`]) of all of these and of the
+//! policies, so changing any revision in the history changes the hash of every
+//! revision after it.
+//!
+//! Revisions are never removed: [`VersionedPolicyStore::rollback`] records a
+//! new revision with the policies of an earlier one. The history serializes to
+//! JSON with [`VersionedPolicyStore::to_json`], and
+//! [`VersionedPolicyStore::from_json_value`] checks every hash when loading
+//! it.
+//!
+//! [`VersionedPolicyStore::authorize_as_of`] authorizes a request against the
+//! revision which was current at a time, or against a given revision, to
+//! answer whether a request would have been allowed under the policies of
+//! then. Pass it the entities of then too, such as those of an entities
+//! snapshot written at the time.
+//!
+//! ```
+//! # use cedar_policy::{policy_store::*, PolicySet};
+//! let mut store = VersionedPolicyStore::new();
+//! let limit: PolicySet = "permit(principal, action, resource) when { context.amount < 100 };"
+//!     .parse()
+//!     .unwrap();
+//! let first = store
+//!     .commit(limit, RevisionMeta::new("alice", "limit transfers to 100"))
+//!     .unwrap()
+//!     .hash()
+//!     .to_string();
+//! let raised: PolicySet = "permit(principal, action, resource) when { context.amount < 1000 };"
+//!     .parse()
+//!     .unwrap();
+//! store
+//!     .commit(raised, RevisionMeta::new("bob", "raise the limit to 1000"))
+//!     .unwrap();
+//! store
+//!     .rollback(&first, RevisionMeta::new("alice", "revert the raise"))
+//!     .unwrap();
+//! assert_eq!(store.history().count(), 3);
+//! assert!(store.current().unwrap().to_string().contains("< 100"));
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::canonical::canonical_json;
+use crate::{
+    Authorizer, Entities, PolicyId, PolicySet, PolicySetFromJsonError, PolicyToJsonError, Request,
+    Response,
+};
+
+/// Errors of a [`VersionedPolicyStore`]
+#[derive(Debug, Error)]
+pub enum PolicyStoreError {
+    /// No revision has the given hash
+    #[error("no revision has the hash `{0}`")]
+    UnknownRevision(String),
+    /// The policies of a revision could not be converted to JSON
+    #[error(transparent)]
+    ToJson(#[from] PolicyToJsonError),
+    /// The policies of a serialized revision are invalid
+    #[error(transparent)]
+    FromJson(Box<PolicySetFromJsonError>),
+    /// A serialized history is not valid JSON, or not in the expected format
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// The hash of a serialized revision does not match its contents
+    #[error("revision `{hash}` does not match its hash `{expected}`")]
+    HashMismatch {
+        /// The hash recorded for the revision
+        hash: String,
+        /// The hash of its contents
+        expected: String,
+    },
+    /// The parent of a serialized revision is not the revision before it
+    #[error("the parent of revision `{hash}` is not the revision before it")]
+    BrokenChain {
+        /// The hash of the revision
+        hash: String,
+    },
+    /// No revision had been made yet at a time
+    #[error("no revision had been made at {0} ms since the Unix epoch")]
+    NoRevisionAt(u64),
+}
+
+/// A point in the history of a [`VersionedPolicyStore`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsOf {
+    /// The revision with this hash
+    Revision(String),
+    /// The revision which was current at this time, in milliseconds since
+    /// the Unix epoch: the one with the latest timestamp at or before it, or
+    /// the last made of those with that timestamp
+    TimestampMs(u64),
+}
+
+/// Who made a revision, why, and when
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevisionMeta {
+    /// Who made the revision
+    pub author: String,
+    /// Why the revision was made
+    pub message: String,
+    /// When the revision was made, in milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+}
+
+impl RevisionMeta {
+    /// A revision made now by `author`, for the reason given in `message`
+    pub fn new(author: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            author: author.into(),
+            message: message.into(),
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| {
+                    u64::try_from(since.as_millis()).unwrap_or(u64::MAX)
+                }),
+        }
+    }
+
+    /// The same revision, made at `timestamp_ms` milliseconds since the Unix
+    /// epoch instead
+    #[must_use]
+    pub fn at(self, timestamp_ms: u64) -> Self {
+        Self {
+            timestamp_ms,
+            ..self
+        }
+    }
+}
+
+/// An immutable version of a policy set in a [`VersionedPolicyStore`]
+#[derive(Debug, Clone)]
+pub struct Revision {
+    hash: String,
+    parent: Option<String>,
+    meta: RevisionMeta,
+    policies: PolicySet,
+    /// The JSON of `policies`, as hashed
+    policies_json: serde_json::Value,
+}
+
+impl Revision {
+    /// The SHA-256 hash of the revision, in hex, which identifies it
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// The hash of the revision before this one, or `None` for the first
+    pub fn parent(&self) -> Option<&str> {
+        self.parent.as_deref()
+    }
+
+    /// Who made the revision, why, and when
+    pub fn meta(&self) -> &RevisionMeta {
+        &self.meta
+    }
+
+    /// The policy set of this revision
+    pub fn policies(&self) -> &PolicySet {
+        &self.policies
+    }
+}
+
+/// The change to one template or policy between two revisions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyChange {
+    /// The template or policy was added
+    Added(PolicyId),
+    /// The template or policy was removed
+    Removed(PolicyId),
+    /// The template or policy was changed, or a link was linked to other
+    /// values
+    Modified(PolicyId),
+}
+
+/// The serialized form of a revision
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RevisionJson {
+    hash: String,
+    parent: Option<String>,
+    #[serde(flatten)]
+    meta: RevisionMeta,
+    policies: serde_json::Value,
+}
+
+/// The serialized form of a history
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HistoryJson {
+    revisions: Vec<RevisionJson>,
+}
+
+/// The hash of a revision with these contents
+fn revision_hash(
+    parent: Option<&str>,
+    meta: &RevisionMeta,
+    policies_json: &serde_json::Value,
+) -> String {
+    let contents = serde_json::json!({
+        "parent": parent,
+        "author": meta.author,
+        "message": meta.message,
+        "timestampMs": meta.timestamp_ms,
+        "policies": policies_json,
+    });
+    hex::encode(Sha256::digest(canonical_json(&contents).as_bytes()))
+}
+
+/// The text of every template and policy of `policies`, by id. A static
+/// policy and its template have the same id and text, so they are one entry.
+fn policy_texts(policies: &PolicySet) -> BTreeMap<&str, (&PolicyId, String)> {
+    policies
+        .templates()
+        .map(|template| (template.id(), template.to_string()))
+        .chain(
+            policies
+                .policies()
+                .map(|policy| (policy.id(), policy.to_string())),
+        )
+        .map(|(id, text)| (id.as_ref(), (id, text)))
+        .collect()
+}
+
+/// A history of the revisions of a policy set
+#[derive(Debug, Clone, Default)]
+pub struct VersionedPolicyStore {
+    /// The revisions, oldest first, each the parent of the next
+    revisions: Vec<Revision>,
+}
+
+impl VersionedPolicyStore {
+    /// A store without any revisions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `policies` as the new current revision, made as described by
+    /// `meta`
+    ///
+    /// # Errors
+    ///
+    /// If a policy or template cannot be converted to JSON to be hashed.
+    pub fn commit(
+        &mut self,
+        policies: PolicySet,
+        meta: RevisionMeta,
+    ) -> Result<&Revision, PolicyStoreError> {
+        let policies_json = policies.to_json()?;
+        let parent = self.head().map(|head| head.hash.clone());
+        let hash = revision_hash(parent.as_deref(), &meta, &policies_json);
+        Ok(self.push(Revision {
+            hash,
+            parent,
+            meta,
+            policies,
+            policies_json,
+        }))
+    }
+
+    /// Record the policies of the revision `hash` as the new current
+    /// revision, made as described by `meta`. The revisions after `hash` stay
+    /// in the history.
+    ///
+    /// # Errors
+    ///
+    /// If there is no revision `hash`.
+    pub fn rollback(
+        &mut self,
+        hash: &str,
+        meta: RevisionMeta,
+    ) -> Result<&Revision, PolicyStoreError> {
+        let target = self.revision_or_err(hash)?;
+        let policies = target.policies.clone();
+        let policies_json = target.policies_json.clone();
+        let parent = self.head().map(|head| head.hash.clone());
+        let hash = revision_hash(parent.as_deref(), &meta, &policies_json);
+        Ok(self.push(Revision {
+            hash,
+            parent,
+            meta,
+            policies,
+            policies_json,
+        }))
+    }
+
+    /// Add `revision` to the end of the history, returning it
+    fn push(&mut self, revision: Revision) -> &Revision {
+        self.revisions.push(revision);
+        // PANIC SAFETY: a revision was just pushed
+        #[allow(clippy::expect_used)]
+        self.revisions.last().expect("the history is not empty")
+    }
+
+    /// The current revision, or `None` if nothing was committed
+    pub fn head(&self) -> Option<&Revision> {
+        self.revisions.last()
+    }
+
+    /// The policy set of the current revision
+    pub fn current(&self) -> Option<&PolicySet> {
+        self.head().map(Revision::policies)
+    }
+
+    /// The revision `hash`
+    pub fn revision(&self, hash: &str) -> Option<&Revision> {
+        self.revisions.iter().find(|revision| revision.hash == hash)
+    }
+
+    fn revision_or_err(&self, hash: &str) -> Result<&Revision, PolicyStoreError> {
+        self.revision(hash)
+            .ok_or_else(|| PolicyStoreError::UnknownRevision(hash.to_string()))
+    }
+
+    /// The revision at `at`
+    ///
+    /// # Errors
+    ///
+    /// If there is no revision with the hash, or no revision had been made
+    /// yet at the time.
+    pub fn revision_as_of(&self, at: &AsOf) -> Result<&Revision, PolicyStoreError> {
+        match at {
+            AsOf::Revision(hash) => self.revision_or_err(hash),
+            // timestamps come from the committers' clocks, so they need not
+            // increase along the history; of the revisions with the latest
+            // timestamp, `max_by_key` picks the last one made
+            AsOf::TimestampMs(timestamp_ms) => self
+                .revisions
+                .iter()
+                .filter(|revision| revision.meta.timestamp_ms <= *timestamp_ms)
+                .max_by_key(|revision| revision.meta.timestamp_ms)
+                .ok_or(PolicyStoreError::NoRevisionAt(*timestamp_ms)),
+        }
+    }
+
+    /// Authorize `request` against the policies of the revision at `at`, with
+    /// `entities`, and return the response along with the revision, to record
+    /// which policies the answer is based on
+    ///
+    /// # Errors
+    ///
+    /// As for [`Self::revision_as_of`].
+    pub fn authorize_as_of(
+        &self,
+        authorizer: &Authorizer,
+        request: &Request,
+        entities: &Entities,
+        at: &AsOf,
+    ) -> Result<(Response, &Revision), PolicyStoreError> {
+        let revision = self.revision_as_of(at)?;
+        let response = authorizer.is_authorized(request, &revision.policies, entities);
+        Ok((response, revision))
+    }
+
+    /// All revisions, oldest first
+    pub fn history(&self) -> impl Iterator<Item = &Revision> {
+        self.revisions.iter()
+    }
+
+    /// The changes to the templates and policies from the revision `from` to
+    /// the revision `to`, sorted by kind and then by id
+    ///
+    /// # Errors
+    ///
+    /// If there is no revision `from` or `to`.
+    pub fn diff(&self, from: &str, to: &str) -> Result<Vec<PolicyChange>, PolicyStoreError> {
+        let old = policy_texts(&self.revision_or_err(from)?.policies);
+        let new = policy_texts(&self.revision_or_err(to)?.policies);
+        let ids: BTreeSet<&str> = old.keys().chain(new.keys()).copied().collect();
+        let (mut added, mut removed, mut modified) = (Vec::new(), Vec::new(), Vec::new());
+        for id in ids {
+            match (old.get(id), new.get(id)) {
+                (None, Some((id, _))) => added.push(PolicyChange::Added((*id).clone())),
+                (Some((id, _)), None) => removed.push(PolicyChange::Removed((*id).clone())),
+                (Some((id, old)), Some((_, new))) if old != new => {
+                    modified.push(PolicyChange::Modified((*id).clone()));
+                }
+                _ => (),
+            }
+        }
+        added.append(&mut removed);
+        added.append(&mut modified);
+        Ok(added)
+    }
+
+    /// The history as JSON: an object whose `revisions` are the revisions,
+    /// oldest first, each with its hash, parent, metadata and policies in the
+    /// format of [`PolicySet::to_json`]
+    pub fn to_json(&self) -> serde_json::Value {
+        let history = HistoryJson {
+            revisions: self
+                .revisions
+                .iter()
+                .map(|revision| RevisionJson {
+                    hash: revision.hash.clone(),
+                    parent: revision.parent.clone(),
+                    meta: revision.meta.clone(),
+                    policies: revision.policies_json.clone(),
+                })
+                .collect(),
+        };
+        // the history serializes to an object, so there is no error to return
+        serde_json::to_value(history).unwrap_or_default()
+    }
+
+    /// Load a history written by [`Self::to_json`], checking that the hash of
+    /// every revision matches its contents and that its parent is the
+    /// revision before it
+    ///
+    /// # Errors
+    ///
+    /// If the JSON is not a history, a policy set in it is invalid, or a
+    /// revision does not match its hash or parent.
+    pub fn from_json_value(json: serde_json::Value) -> Result<Self, PolicyStoreError> {
+        let history: HistoryJson = serde_json::from_value(json)?;
+        let mut store = Self::new();
+        for revision in history.revisions {
+            let parent = store.head().map(|head| head.hash.clone());
+            if revision.parent != parent {
+                return Err(PolicyStoreError::BrokenChain {
+                    hash: revision.hash,
+                });
+            }
+            let expected = revision_hash(parent.as_deref(), &revision.meta, &revision.policies);
+            if revision.hash != expected {
+                return Err(PolicyStoreError::HashMismatch {
+                    hash: revision.hash,
+                    expected,
+                });
+            }
+            let policies = PolicySet::from_json_value(revision.policies.clone())
+                .map_err(|error| PolicyStoreError::FromJson(Box::new(error)))?;
+            store.revisions.push(Revision {
+                hash: revision.hash,
+                parent,
+                meta: revision.meta,
+                policies,
+                policies_json: revision.policies,
+            });
+        }
+        Ok(store)
+    }
+
+    /// Load a history from the text of its JSON; see
+    /// [`Self::from_json_value`]
+    ///
+    /// # Errors
+    ///
+    /// As for [`Self::from_json_value`].
+    pub fn from_json_str(json: &str) -> Result<Self, PolicyStoreError> {
+        Self::from_json_value(serde_json::from_str(json)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    fn policies(src: &str) -> PolicySet {
+        PolicySet::from_str(src).expect("policies are valid")
+    }
+
+    fn store() -> VersionedPolicyStore {
+        let mut store = VersionedPolicyStore::new();
+        store
+            .commit(
+                policies(
+                    r#"permit(principal, action, resource) when { context.amount < 100 };
+                    forbid(principal == Account::"frozen", action, resource);
+                    permit(principal == Account::"owner", action, resource);"#,
+                ),
+                RevisionMeta::new("alice", "initial limits").at(1),
+            )
+            .expect("commits");
+        store
+            .commit(
+                policies(
+                    r#"permit(principal, action, resource) when { context.amount < 1000 };
+                    forbid(principal == Account::"frozen", action, resource);"#,
+                ),
+                RevisionMeta::new("bob", "raise the limit").at(2),
+            )
+            .expect("commits");
+        store
+    }
+
+    #[test]
+    fn records_revisions() {
+        let mut store = store();
+        let revisions = store.history().collect::<Vec<_>>();
+        assert_eq!(revisions.len(), 2);
+        let (first, second) = (revisions[0].hash().to_string(), revisions[1].hash());
+        assert_eq!(revisions[0].parent(), None);
+        assert_eq!(revisions[1].parent(), Some(first.as_str()));
+        assert_eq!(revisions[1].meta().author, "bob");
+        assert_eq!(store.head().map(Revision::hash), Some(second));
+        assert_ne!(first, second);
+
+        let second = second.to_string();
+        let rollback = store
+            .rollback(&first, RevisionMeta::new("alice", "revert").at(3))
+            .expect("rolls back")
+            .hash()
+            .to_string();
+        assert_eq!(store.history().count(), 3);
+        assert_eq!(
+            store.head().and_then(Revision::parent),
+            Some(second.as_str())
+        );
+        assert_eq!(store.diff(&first, &rollback).expect("diffs"), vec![]);
+        assert!(matches!(
+            store.rollback("0xmissing", RevisionMeta::new("alice", "revert")),
+            Err(PolicyStoreError::UnknownRevision(_))
+        ));
+    }
+
+    #[test]
+    fn diffs_revisions() {
+        let store = store();
+        let hashes = store
+            .history()
+            .map(|revision| revision.hash().to_string())
+            .collect::<Vec<_>>();
+        let id = |id: &str| PolicyId::from_str(id).expect("valid id");
+        assert_eq!(
+            store.diff(&hashes[0], &hashes[1]).expect("diffs"),
+            vec![
+                PolicyChange::Removed(id("policy2")),
+                PolicyChange::Modified(id("policy0"))
+            ]
+        );
+        assert_eq!(
+            store.diff(&hashes[1], &hashes[0]).expect("diffs"),
+            vec![
+                PolicyChange::Added(id("policy2")),
+                PolicyChange::Modified(id("policy0"))
+            ]
+        );
+        assert_eq!(store.diff(&hashes[1], &hashes[1]).expect("diffs"), vec![]);
+    }
+
+    #[test]
+    fn authorizes_as_of_revisions() {
+        let mut store = store();
+        let hashes = store
+            .history()
+            .map(|revision| revision.hash().to_string())
+            .collect::<Vec<_>>();
+        store
+            .rollback(&hashes[0], RevisionMeta::new("alice", "revert").at(5))
+            .expect("rolls back");
+        let transfer = |amount: i64| {
+            Request::new(
+                Some(r#"Account::"alice""#.parse().expect("valid UID")),
+                Some(r#"Action::"transfer""#.parse().expect("valid UID")),
+                Some(r#"Vault::"main""#.parse().expect("valid UID")),
+                crate::Context::from_json_value(serde_json::json!({ "amount": amount }), None)
+                    .expect("valid context"),
+            )
+        };
+        let decision = |amount: i64, at: AsOf| {
+            let (response, revision) = store
+                .authorize_as_of(
+                    &Authorizer::new(),
+                    &transfer(amount),
+                    &Entities::empty(),
+                    &at,
+                )
+                .expect("authorizes");
+            (response.decision(), revision.meta().timestamp_ms)
+        };
+        // the limit was raised to 1000 at 2, and reverted to 100 at 5
+        assert_eq!(
+            decision(500, AsOf::TimestampMs(1)),
+            (crate::Decision::Deny, 1)
+        );
+        assert_eq!(
+            decision(500, AsOf::TimestampMs(4)),
+            (crate::Decision::Allow, 2)
+        );
+        assert_eq!(
+            decision(500, AsOf::TimestampMs(5)),
+            (crate::Decision::Deny, 5)
+        );
+        assert_eq!(
+            decision(500, AsOf::Revision(hashes[1].clone())),
+            (crate::Decision::Allow, 2)
+        );
+        assert!(matches!(
+            store.revision_as_of(&AsOf::TimestampMs(0)),
+            Err(PolicyStoreError::NoRevisionAt(0))
+        ));
+
+        assert!(matches!(
+            store.revision_as_of(&AsOf::Revision("0xmissing".to_string())),
+            Err(PolicyStoreError::UnknownRevision(_))
+        ));
+
+        // a revision made by a committer whose clock is behind does not hide
+        // the revisions after its timestamp
+        store
+            .rollback(&hashes[1], RevisionMeta::new("bob", "restore").at(3))
+            .expect("rolls back");
+        let timestamp = |at: u64| {
+            store
+                .revision_as_of(&AsOf::TimestampMs(at))
+                .expect("has a revision")
+                .meta()
+                .timestamp_ms
+        };
+        assert_eq!(timestamp(6), 5);
+        assert_eq!(timestamp(4), 3);
+        assert_eq!(timestamp(2), 2);
+    }
+
+    #[test]
+    fn round_trips_history() {
+        let store = store();
+        let json = store.to_json();
+        let loaded = VersionedPolicyStore::from_json_value(json.clone()).expect("loads");
+        assert_eq!(
+            loaded.history().map(Revision::hash).collect::<Vec<_>>(),
+            store.history().map(Revision::hash).collect::<Vec<_>>()
+        );
+        assert_eq!(loaded.to_json(), json);
+
+        let mut tampered = json.clone();
+        tampered["revisions"][0]["author"] = "mallory".into();
+        assert!(matches!(
+            VersionedPolicyStore::from_json_value(tampered),
+            Err(PolicyStoreError::HashMismatch { .. })
+        ));
+        let mut reordered = json;
+        if let Some(revisions) = reordered["revisions"].as_array_mut() {
+            revisions.reverse();
+        }
+        assert!(matches!(
+            VersionedPolicyStore::from_json_value(reordered),
+            Err(PolicyStoreError::BrokenChain { .. })
+        ));
+    }
+}
@@ -0,0 +1,345 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Editing the text of a policy set without losing its comments and layout.
+//!
+//! A [`PolicySetText`] keeps the text a policy set was parsed from. Edits
+//! replace byte ranges of the text, such as those given by
+//! [`Policy::source_location`] and [`Template::principal_constraint_location`],
+//! so everything outside the edited ranges is written back exactly as it was.
+//! After each edit the text is parsed again, and an edit which would leave
+//! invalid policies is rejected without changing anything.
+
+use crate::{ParseErrors, PolicyId, PolicySet, SourceLocation};
+use std::ops::Range;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// The text of a policy set, together with the policies parsed from it
+#[derive(Debug, Clone)]
+pub struct PolicySetText {
+    text: String,
+    policies: PolicySet,
+}
+
+/// Errors editing a [`PolicySetText`]
+#[derive(Debug, Error)]
+pub enum PolicyTextError {
+    /// The policy set text does not contain a policy or template with this id
+    #[error("the policy set text does not contain a policy or template with id `{0}`")]
+    UnknownPolicy(PolicyId),
+    /// An edit range is outside the text, or does not start and end on
+    /// character boundaries
+    #[error("invalid edit range {}..{}", .0.start, .0.end)]
+    InvalidRange(Range<usize>),
+    /// Two edit ranges overlap
+    #[error("edit ranges {}..{} and {}..{} overlap", .0.start, .0.end, .1.start, .1.end)]
+    OverlappingEdits(Range<usize>, Range<usize>),
+    /// The edited text is not a valid policy set
+    #[error("the edited policy set does not parse: {0}")]
+    Parse(#[from] ParseErrors),
+}
+
+impl PolicySetText {
+    /// Parse the text of a policy set. Policy ids are assigned as for
+    /// [`PolicySet::from_str`], so they depend on the position of each policy
+    /// in the text: adding or removing a policy changes the ids of the
+    /// policies after it.
+    ///
+    /// # Errors
+    ///
+    /// If `text` is not a valid policy set.
+    pub fn parse(text: impl Into<String>) -> Result<Self, ParseErrors> {
+        let text = text.into();
+        let policies = PolicySet::from_str(&text)?;
+        Ok(Self { text, policies })
+    }
+
+    /// Get the current text, including any edits
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Get the policies parsed from the current text
+    pub fn policy_set(&self) -> &PolicySet {
+        &self.policies
+    }
+
+    /// Consume this, returning the current text
+    pub fn into_text(self) -> String {
+        self.text
+    }
+
+    /// Replace the text in the byte range `range` with `replacement`
+    ///
+    /// # Errors
+    ///
+    /// If `range` is not a range of character boundaries in the text, or the
+    /// edited text does not parse.
+    pub fn replace(
+        &mut self,
+        range: Range<usize>,
+        replacement: &str,
+    ) -> Result<(), PolicyTextError> {
+        self.apply_edits([(range, replacement)])
+    }
+
+    /// Replace the text in each byte range with its replacement. The ranges
+    /// are offsets into the current text, and must not overlap.
+    ///
+    /// # Errors
+    ///
+    /// If a range is not a range of character boundaries in the text, two
+    /// ranges overlap, or the edited text does not parse.
+    pub fn apply_edits<'a>(
+        &mut self,
+        edits: impl IntoIterator<Item = (Range<usize>, &'a str)>,
+    ) -> Result<(), PolicyTextError> {
+        let mut edits = edits.into_iter().collect::<Vec<_>>();
+        edits.sort_by_key(|(range, _)| (range.start, range.end));
+        for (range, _) in &edits {
+            if range.start > range.end
+                || !self.text.is_char_boundary(range.start)
+                || !self.text.is_char_boundary(range.end)
+            {
+                return Err(PolicyTextError::InvalidRange(range.clone()));
+            }
+        }
+        for pair in edits.windows(2) {
+            if let [(first, _), (second, _)] = pair {
+                if first.end > second.start {
+                    return Err(PolicyTextError::OverlappingEdits(
+                        first.clone(),
+                        second.clone(),
+                    ));
+                }
+            }
+        }
+
+        let mut text = self.text.clone();
+        // apply the edits from the end of the text, so that the ranges of the
+        // ones before are unaffected
+        for (range, replacement) in edits.into_iter().rev() {
+            text.replace_range(range, replacement);
+        }
+        *self = Self::parse(text)?;
+        Ok(())
+    }
+
+    /// Replace the text of the static policy or template `id`, including its
+    /// annotations, with `text`
+    ///
+    /// # Errors
+    ///
+    /// If there is no static policy or template `id`, or the edited text does
+    /// not parse.
+    pub fn replace_policy(&mut self, id: &PolicyId, text: &str) -> Result<(), PolicyTextError> {
+        let range = self.policy_range(id)?;
+        self.replace(range, text)
+    }
+
+    /// Remove the static policy or template `id`, along with the whitespace
+    /// following it. Comments before the policy are kept.
+    ///
+    /// # Errors
+    ///
+    /// If there is no static policy or template `id`, or the remaining text
+    /// does not parse.
+    pub fn remove_policy(&mut self, id: &PolicyId) -> Result<(), PolicyTextError> {
+        let range = self.policy_range(id)?;
+        let rest = &self.text[range.end..];
+        let end = range.end + (rest.len() - rest.trim_start().len());
+        self.replace(range.start..end, "")
+    }
+
+    /// Add `text`, which may contain any number of policies, at the end of the
+    /// policy set, separated from the existing policies by a blank line
+    ///
+    /// # Errors
+    ///
+    /// If the text with `text` added does not parse.
+    pub fn add_policy(&mut self, text: &str) -> Result<(), PolicyTextError> {
+        let mut addition = String::new();
+        if !self.text.trim().is_empty() {
+            if !self.text.ends_with('\n') {
+                addition.push('\n');
+            }
+            addition.push('\n');
+        }
+        addition.push_str(text.trim_end());
+        addition.push('\n');
+        let end = self.text.len();
+        self.replace(end..end, &addition)
+    }
+
+    /// The byte range of the static policy or template `id` in the text
+    fn policy_range(&self, id: &PolicyId) -> Result<Range<usize>, PolicyTextError> {
+        let range = |loc: SourceLocation<'_>| loc.range_start().zip(loc.range_end());
+        self.policies
+            .policy(id)
+            .filter(|policy| policy.is_static())
+            .and_then(|policy| range(policy.source_location()))
+            .or_else(|| {
+                self.policies
+                    .template(id)
+                    .and_then(|template| range(template.source_location()))
+            })
+            .map(|(start, end)| start..end)
+            .ok_or_else(|| PolicyTextError::UnknownPolicy(id.clone()))
+    }
+}
+
+impl FromStr for PolicySetText {
+    type Err = ParseErrors;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::parse(text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cool_asserts::assert_matches;
+
+    const SRC: &str = r#"// Limits for the treasury vault
+@id("withdraw-limit")
+permit(
+    principal in Team::"treasury",   // signers
+    action == Action::"withdraw",
+    resource == Vault::"main"
+) when {
+    context.amount < 100 // bump when the budget changes
+};
+
+// Nobody touches the cold wallet
+forbid(principal, action, resource == Vault::"cold");
+"#;
+
+    fn id(id: &str) -> PolicyId {
+        PolicyId::from_str(id).unwrap()
+    }
+
+    #[test]
+    fn edit_keeps_comments_and_layout() {
+        let mut text = PolicySetText::parse(SRC).expect("should parse");
+        let policy = text.policy_set().policy(&id("policy0")).unwrap();
+        let start = policy.source_location().range_start().unwrap();
+        let offset = SRC[start..].find("100").unwrap() + start;
+        text.replace(offset..offset + 3, "250")
+            .expect("edit should apply");
+        assert_eq!(text.text(), SRC.replace("< 100", "< 250"));
+        assert!(text
+            .policy_set()
+            .policy(&id("policy0"))
+            .unwrap()
+            .to_string()
+            .contains("< 250"));
+
+        let template = text.policy_set().policy(&id("policy0")).unwrap();
+        let principal = template.principal_constraint_location();
+        let action = template.action_constraint_location();
+        text.apply_edits([
+            (
+                action.range_start().unwrap()..action.range_end().unwrap(),
+                r#"action in [Action::"withdraw", Action::"sweep"]"#,
+            ),
+            (
+                principal.range_start().unwrap()..principal.range_end().unwrap(),
+                "principal",
+            ),
+        ])
+        .expect("edits should apply");
+        assert_eq!(
+            text.text(),
+            SRC.replace("< 100", "< 250")
+                .replace(r#"principal in Team::"treasury""#, "principal")
+                .replace(
+                    r#"action == Action::"withdraw""#,
+                    r#"action in [Action::"withdraw", Action::"sweep"]"#
+                )
+        );
+    }
+
+    #[test]
+    fn add_replace_and_remove_policies() {
+        let mut text = PolicySetText::parse(SRC).expect("should parse");
+        text.add_policy("permit(principal, action, resource);")
+            .expect("should add");
+        assert_eq!(
+            text.text(),
+            format!("{SRC}\npermit(principal, action, resource);\n")
+        );
+        assert_eq!(text.policy_set().policies().count(), 3);
+
+        text.remove_policy(&id("policy0")).expect("should remove");
+        assert_eq!(
+            text.text(),
+            "// Limits for the treasury vault\n// Nobody touches the cold wallet\n\
+            forbid(principal, action, resource == Vault::\"cold\");\n\n\
+            permit(principal, action, resource);\n"
+        );
+
+        text.replace_policy(
+            &id("policy1"),
+            "permit(principal, action, resource) when { false };",
+        )
+        .expect("should replace");
+        assert!(text
+            .text()
+            .ends_with("\n\npermit(principal, action, resource) when { false };\n"));
+    }
+
+    #[test]
+    fn definitions_are_kept() {
+        let src = "def VAULT = Vault::\"main\";\n\npermit(principal, action, resource == VAULT);\n";
+        let mut text = PolicySetText::parse(src).expect("should parse");
+        text.add_policy("forbid(principal, action, resource) unless { resource == VAULT };")
+            .expect("should add");
+        assert!(text.text().starts_with("def VAULT = Vault::\"main\";\n"));
+        let policy = text.policy_set().policy(&id("policy1")).unwrap();
+        assert!(policy.to_string().contains(r#"resource == Vault::"main""#));
+        // the JSON form has the definition expanded
+        assert_eq!(
+            policy.to_json().unwrap()["conditions"][0]["body"]["=="]["right"]["Value"]["__entity"]
+                ["id"],
+            "main"
+        );
+    }
+
+    #[test]
+    fn invalid_edits() {
+        let mut text = PolicySetText::parse(SRC).expect("should parse");
+        assert_matches!(
+            text.remove_policy(&id("policy7")),
+            Err(PolicyTextError::UnknownPolicy(_))
+        );
+        assert_matches!(
+            text.replace(0..SRC.len() + 1, ""),
+            Err(PolicyTextError::InvalidRange(_))
+        );
+        assert_matches!(
+            text.apply_edits([(0..10, ""), (5..12, "")]),
+            Err(PolicyTextError::OverlappingEdits(_, _))
+        );
+        assert_matches!(
+            text.replace_policy(&id("policy1"), "forbid(principal, action resource);"),
+            Err(PolicyTextError::Parse(_))
+        );
+        // a rejected edit leaves the text unchanged
+        assert_eq!(text.text(), SRC);
+    }
+}
@@ -0,0 +1,701 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Protobuf messages for entities, requests, and authorization responses,
+//! for exchanging them with non-Rust services.
+//!
+//! The messages are described by `protobuf_schema/cedar.proto` in this crate,
+//! which other languages can compile with `protoc` as usual. Values are sent
+//! as restricted expressions, so extension values such as `u256` are carried
+//! as the constructor call that produces them and round-trip exactly.
+//!
+//! Convert from the corresponding [`crate`] types with `From`, and back with
+//! `TryFrom`; encode and decode with the re-exported [`Message`] trait:
+//!
+//! ```
+//! # use cedar_policy::{Entities, proto::{self, Message}};
+//! let entities = Entities::from_json_str(
+//!     r#"[{"uid": {"type": "User", "id": "alice"}, "attrs": {}, "parents": []}]"#,
+//!     None,
+//! )
+//! .unwrap();
+//! let bytes = proto::Entities::from(&entities).encode_to_vec();
+//! let decoded = Entities::try_from(proto::Entities::decode(bytes.as_slice()).unwrap()).unwrap();
+//! assert_eq!(decoded.iter().count(), 1);
+//! ```
+
+use crate::{EntitiesError, ParseErrors};
+use cedar_policy_core::ast;
+use cedar_policy_core::entities::TCComputation;
+use smol_str::SmolStr;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use thiserror::Error;
+
+pub use prost::Message;
+
+/// An entity UID. An empty `type_name` denotes an unspecified entity.
+#[derive(Clone, PartialEq, Eq, Hash, prost::Message)]
+pub struct EntityUid {
+    /// Fully qualified entity type, e.g. `Namespace::User`
+    #[prost(string, tag = "1")]
+    pub type_name: String,
+    /// Entity id
+    #[prost(string, tag = "2")]
+    pub id: String,
+}
+
+/// A restricted expression
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Value {
+    /// The value itself; `None` only in malformed messages
+    #[prost(oneof = "value::Kind", tags = "1, 2, 3, 4, 5, 6, 7")]
+    pub kind: Option<value::Kind>,
+}
+
+/// Nested types for [`Value`]
+pub mod value {
+    /// The kinds of [`super::Value`]
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Kind {
+        /// Boolean literal
+        #[prost(bool, tag = "1")]
+        Bool(bool),
+        /// Long literal
+        #[prost(int64, tag = "2")]
+        Long(i64),
+        /// String literal
+        #[prost(string, tag = "3")]
+        String(String),
+        /// Entity reference
+        #[prost(message, tag = "4")]
+        Entity(super::EntityUid),
+        /// Set of values
+        #[prost(message, tag = "5")]
+        Set(super::Set),
+        /// Record of values
+        #[prost(message, tag = "6")]
+        Record(super::Record),
+        /// Extension function call, e.g. `u256("42")`
+        #[prost(message, tag = "7")]
+        Extension(super::ExtensionCall),
+    }
+}
+
+/// A set of values
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Set {
+    /// Elements of the set
+    #[prost(message, repeated, tag = "1")]
+    pub elements: Vec<Value>,
+}
+
+/// A record of values
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Record {
+    /// Fields of the record
+    #[prost(map = "string, message", tag = "1")]
+    pub attrs: HashMap<String, Value>,
+}
+
+/// A call to an extension function
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ExtensionCall {
+    /// Name of the extension function, e.g. `u256`
+    #[prost(string, tag = "1")]
+    pub fn_name: String,
+    /// Arguments to the function
+    #[prost(message, repeated, tag = "2")]
+    pub args: Vec<Value>,
+}
+
+/// An entity
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Entity {
+    /// UID of the entity; required
+    #[prost(message, optional, tag = "1")]
+    pub uid: Option<EntityUid>,
+    /// Attributes of the entity
+    #[prost(map = "string, message", tag = "2")]
+    pub attrs: HashMap<String, Value>,
+    /// Parents of the entity
+    #[prost(message, repeated, tag = "3")]
+    pub parents: Vec<EntityUid>,
+    /// Tags of the entity
+    #[prost(map = "string, message", tag = "4")]
+    pub tags: HashMap<String, Value>,
+}
+
+/// A collection of entities
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Entities {
+    /// The entities
+    #[prost(message, repeated, tag = "1")]
+    pub entities: Vec<Entity>,
+}
+
+/// An authorization request. An absent component is unknown, for use with
+/// partial evaluation.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Request {
+    /// Principal of the request
+    #[prost(message, optional, tag = "1")]
+    pub principal: Option<EntityUid>,
+    /// Action of the request
+    #[prost(message, optional, tag = "2")]
+    pub action: Option<EntityUid>,
+    /// Resource of the request
+    #[prost(message, optional, tag = "3")]
+    pub resource: Option<EntityUid>,
+    /// Context of the request
+    #[prost(message, optional, tag = "4")]
+    pub context: Option<Record>,
+}
+
+/// An authorization decision
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum Decision {
+    /// No decision, the default of an absent field, which is rejected when
+    /// converting to [`crate::Decision`]
+    Unspecified = 0,
+    /// The request is allowed
+    Allow = 1,
+    /// The request is denied
+    Deny = 2,
+}
+
+/// An authorization response. Errors are sent as human-readable messages, so
+/// there is no conversion back to [`crate::Response`].
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct Response {
+    /// The decision
+    #[prost(enumeration = "Decision", tag = "1")]
+    pub decision: i32,
+    /// IDs of the policies that determined the decision
+    #[prost(string, repeated, tag = "2")]
+    pub reasons: Vec<String>,
+    /// Errors encountered while evaluating policies
+    #[prost(string, repeated, tag = "3")]
+    pub errors: Vec<String>,
+}
+
+/// Errors when converting protobuf messages to Cedar types
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ProtobufError {
+    /// A required field was absent
+    #[error("missing required field `{0}`")]
+    MissingField(&'static str),
+    /// An entity type or extension function name failed to parse
+    #[error("invalid name `{name}`: {err}")]
+    InvalidName {
+        /// The name that failed to parse
+        name: String,
+        /// The parse error
+        err: ParseErrors,
+    },
+    /// The decoded entities could not be assembled into an
+    /// [`crate::Entities`]
+    #[error(transparent)]
+    Entities(#[from] EntitiesError),
+    /// A decision was unspecified or unknown
+    #[error("unspecified or unknown decision `{0}`")]
+    InvalidDecision(i32),
+}
+
+impl From<&ast::EntityUID> for EntityUid {
+    fn from(uid: &ast::EntityUID) -> Self {
+        Self {
+            type_name: match uid.entity_type() {
+                ast::EntityType::Concrete(name) => name.to_string(),
+                ast::EntityType::Unspecified => String::new(),
+            },
+            id: AsRef::<str>::as_ref(uid.eid()).to_string(),
+        }
+    }
+}
+
+impl TryFrom<EntityUid> for ast::EntityUID {
+    type Error = ProtobufError;
+    fn try_from(uid: EntityUid) -> Result<Self, Self::Error> {
+        let eid = ast::Eid::new(uid.id);
+        if uid.type_name.is_empty() {
+            Ok(Self::unspecified_from_eid(eid))
+        } else {
+            Ok(Self::from_components(parse_name(uid.type_name)?, eid))
+        }
+    }
+}
+
+impl From<&crate::EntityUid> for EntityUid {
+    fn from(uid: &crate::EntityUid) -> Self {
+        Self::from(&uid.0)
+    }
+}
+
+impl TryFrom<EntityUid> for crate::EntityUid {
+    type Error = ProtobufError;
+    fn try_from(uid: EntityUid) -> Result<Self, Self::Error> {
+        ast::EntityUID::try_from(uid).map(crate::EntityUid)
+    }
+}
+
+impl From<ast::BorrowedRestrictedExpr<'_>> for Value {
+    fn from(expr: ast::BorrowedRestrictedExpr<'_>) -> Self {
+        value_from_restricted(&expr)
+    }
+}
+
+/// Convert an `Expr` which is known to be a restricted expression
+fn value_from_restricted(expr: &ast::Expr) -> Value {
+    let kind = match expr.expr_kind() {
+        ast::ExprKind::Lit(ast::Literal::Bool(b)) => value::Kind::Bool(*b),
+        ast::ExprKind::Lit(ast::Literal::Long(i)) => value::Kind::Long(*i),
+        ast::ExprKind::Lit(ast::Literal::String(s)) => value::Kind::String(s.to_string()),
+        ast::ExprKind::Lit(ast::Literal::EntityUID(uid)) => {
+            value::Kind::Entity(EntityUid::from(uid.as_ref()))
+        }
+        ast::ExprKind::Set(elements) => value::Kind::Set(Set {
+            elements: elements.iter().map(value_from_restricted).collect(),
+        }),
+        ast::ExprKind::Record { pairs } => value::Kind::Record(Record {
+            attrs: pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), value_from_restricted(v)))
+                .collect(),
+        }),
+        ast::ExprKind::ExtensionFunctionApp { fn_name, args } => {
+            value::Kind::Extension(ExtensionCall {
+                fn_name: fn_name.to_string(),
+                args: args.iter().map(value_from_restricted).collect(),
+            })
+        }
+        e => panic!("internal invariant violation: expected restricted expression, got {e:?}"),
+    };
+    Value { kind: Some(kind) }
+}
+
+impl TryFrom<Value> for ast::RestrictedExpr {
+    type Error = ProtobufError;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value.kind.ok_or(ProtobufError::MissingField("kind"))? {
+            value::Kind::Bool(b) => Ok(Self::val(b)),
+            value::Kind::Long(i) => Ok(Self::val(i)),
+            value::Kind::String(s) => Ok(Self::val(s)),
+            value::Kind::Entity(uid) => Ok(Self::val(ast::EntityUID::try_from(uid)?)),
+            value::Kind::Set(set) => Ok(Self::set(
+                set.elements
+                    .into_iter()
+                    .map(Self::try_from)
+                    .collect::<Result<Vec<_>, _>>()?,
+            )),
+            value::Kind::Record(record) => Ok(Self::record(restricted_map(record.attrs)?)),
+            value::Kind::Extension(call) => Ok(Self::call_extension_fn(
+                parse_name(call.fn_name)?,
+                call.args
+                    .into_iter()
+                    .map(Self::try_from)
+                    .collect::<Result<_, _>>()?,
+            )),
+        }
+    }
+}
+
+impl From<&crate::RestrictedExpression> for Value {
+    fn from(expr: &crate::RestrictedExpression) -> Self {
+        Self::from(expr.0.as_borrowed())
+    }
+}
+
+impl TryFrom<Value> for crate::RestrictedExpression {
+    type Error = ProtobufError;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        ast::RestrictedExpr::try_from(value).map(crate::RestrictedExpression)
+    }
+}
+
+/// The parents are those `entity` records: all its ancestors only if the
+/// transitive closure of the hierarchy was computed eagerly. Convert the
+/// [`crate::Entities`] it belongs to for all of them.
+impl From<&ast::Entity> for Entity {
+    fn from(entity: &ast::Entity) -> Self {
+        Self {
+            uid: Some(EntityUid::from(&entity.uid())),
+            attrs: entity
+                .attrs()
+                .map(|(k, v)| (k.to_string(), Value::from(v)))
+                .collect(),
+            parents: entity.ancestors().map(EntityUid::from).collect(),
+            tags: entity
+                .tags()
+                .map(|(k, v)| (k.to_string(), Value::from(v)))
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<Entity> for ast::Entity {
+    type Error = ProtobufError;
+    fn try_from(entity: Entity) -> Result<Self, Self::Error> {
+        Ok(Self::new_with_tags(
+            entity
+                .uid
+                .ok_or(ProtobufError::MissingField("uid"))?
+                .try_into()?,
+            restricted_map(entity.attrs)?.collect(),
+            entity
+                .parents
+                .into_iter()
+                .map(ast::EntityUID::try_from)
+                .collect::<Result<_, _>>()?,
+            restricted_map(entity.tags)?.collect(),
+        ))
+    }
+}
+
+impl From<&crate::Entity> for Entity {
+    fn from(entity: &crate::Entity) -> Self {
+        Self::from(&entity.0)
+    }
+}
+
+impl TryFrom<Entity> for crate::Entity {
+    type Error = ProtobufError;
+    fn try_from(entity: Entity) -> Result<Self, Self::Error> {
+        ast::Entity::try_from(entity).map(crate::Entity)
+    }
+}
+
+impl From<&crate::Entities> for Entities {
+    fn from(entities: &crate::Entities) -> Self {
+        Self {
+            entities: entities
+                .iter()
+                .map(|entity| Entity {
+                    parents: entities
+                        .transitive_ancestors(entity)
+                        .map(EntityUid::from)
+                        .collect(),
+                    ..Entity::from(entity)
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<Entities> for crate::Entities {
+    type Error = ProtobufError;
+    /// The transitive closure of the entity hierarchy is computed, as with
+    /// [`crate::Entities::from_entities`]
+    fn try_from(entities: Entities) -> Result<Self, Self::Error> {
+        let entities = entities
+            .entities
+            .into_iter()
+            .map(ast::Entity::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self(cedar_policy_core::entities::Entities::from_entities(
+            entities,
+            TCComputation::ComputeNow,
+        )?))
+    }
+}
+
+impl From<&crate::Request> for Request {
+    fn from(request: &crate::Request) -> Self {
+        let entry = |entry: &ast::EntityUIDEntry| match entry {
+            ast::EntityUIDEntry::Concrete(uid) => Some(EntityUid::from(uid.as_ref())),
+            ast::EntityUIDEntry::Unknown => None,
+        };
+        Self {
+            principal: entry(request.0.principal()),
+            action: entry(request.0.action()),
+            resource: entry(request.0.resource()),
+            context: request.0.context().map(|context| Record {
+                attrs: context
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), Value::from(v)))
+                    .collect(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<Request> for crate::Request {
+    type Error = ProtobufError;
+    fn try_from(request: Request) -> Result<Self, Self::Error> {
+        let entry = |uid: Option<EntityUid>| -> Result<_, ProtobufError> {
+            Ok(match uid {
+                Some(uid) => ast::EntityUIDEntry::Concrete(Arc::new(uid.try_into()?)),
+                None => ast::EntityUIDEntry::Unknown,
+            })
+        };
+        Ok(Self(ast::Request::new_with_unknowns(
+            entry(request.principal)?,
+            entry(request.action)?,
+            entry(request.resource)?,
+            request
+                .context
+                .map(|context| restricted_map(context.attrs).map(ast::Context::from_pairs))
+                .transpose()?,
+        )))
+    }
+}
+
+impl From<crate::Decision> for Decision {
+    fn from(decision: crate::Decision) -> Self {
+        match decision {
+            crate::Decision::Allow => Self::Allow,
+            crate::Decision::Deny => Self::Deny,
+        }
+    }
+}
+
+impl TryFrom<Decision> for crate::Decision {
+    type Error = ProtobufError;
+    fn try_from(decision: Decision) -> Result<Self, Self::Error> {
+        match decision {
+            Decision::Allow => Ok(Self::Allow),
+            Decision::Deny => Ok(Self::Deny),
+            Decision::Unspecified => Err(ProtobufError::InvalidDecision(decision as i32)),
+        }
+    }
+}
+
+impl Response {
+    /// The decision of the response, rejecting an unspecified or unknown one
+    /// rather than defaulting it
+    ///
+    /// # Errors
+    ///
+    /// If the decision field is absent, unspecified or not a known decision.
+    pub fn checked_decision(&self) -> Result<crate::Decision, ProtobufError> {
+        Decision::try_from(self.decision)
+            .map_err(|_| ProtobufError::InvalidDecision(self.decision))
+            .and_then(crate::Decision::try_from)
+    }
+}
+
+impl From<&crate::Response> for Response {
+    fn from(response: &crate::Response) -> Self {
+        Self {
+            decision: Decision::from(response.decision()) as i32,
+            reasons: response
+                .diagnostics()
+                .reason()
+                .map(ToString::to_string)
+                .collect(),
+            errors: response
+                .diagnostics()
+                .errors()
+                .map(ToString::to_string)
+                .collect(),
+        }
+    }
+}
+
+fn parse_name(name: String) -> Result<ast::Name, ProtobufError> {
+    ast::Name::from_str(&name).map_err(|err| ProtobufError::InvalidName { name, err })
+}
+
+fn restricted_map(
+    map: HashMap<String, Value>,
+) -> Result<impl Iterator<Item = (SmolStr, ast::RestrictedExpr)>, ProtobufError> {
+    let pairs = map
+        .into_iter()
+        .map(|(k, v)| Ok((SmolStr::from(k), ast::RestrictedExpr::try_from(v)?)))
+        .collect::<Result<Vec<_>, ProtobufError>>()?;
+    Ok(pairs.into_iter())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Context, EntityId, EntityTypeName};
+
+    fn roundtrip_entities(entities: &crate::Entities) -> crate::Entities {
+        let bytes = Entities::from(entities).encode_to_vec();
+        crate::Entities::try_from(Entities::decode(bytes.as_slice()).expect("should decode"))
+            .expect("should convert")
+    }
+
+    fn sorted_json(entities: &crate::Entities) -> Vec<serde_json::Value> {
+        let serde_json::Value::Array(mut json) =
+            entities.0.to_json_value().expect("should serialize")
+        else {
+            panic!("entities should serialize to an array")
+        };
+        json.sort_by_key(|e| e["uid"].to_string());
+        json
+    }
+
+    #[test]
+    fn entities_roundtrip() {
+        let entities = crate::Entities::from_json_value(
+            serde_json::json!([
+                {
+                    "uid": {"type": "Ns::User", "id": "alice"},
+                    "attrs": {
+                        "age": 42,
+                        "name": "Alice",
+                        "admin": false,
+                        "friends": [{"__entity": {"type": "Ns::User", "id": "bob"}}],
+                        "addr": {"ip": {"__extn": {"fn": "ip", "arg": "10.0.0.1"}}}
+                    },
+                    "parents": [{"type": "Ns::Group", "id": "admins"}],
+                    "tags": {"dept": "eng"}
+                },
+                { "uid": {"type": "Ns::Group", "id": "admins"}, "attrs": {}, "parents": [] }
+            ]),
+            None,
+        )
+        .expect("should parse");
+        let decoded = roundtrip_entities(&entities);
+        assert_eq!(sorted_json(&decoded), sorted_json(&entities));
+    }
+
+    #[test]
+    fn lazily_closed_entities_roundtrip() {
+        let json = serde_json::json!([
+            { "uid": {"type": "User", "id": "alice"}, "attrs": {}, "parents": [{"type": "Group", "id": "admins"}] },
+            { "uid": {"type": "Group", "id": "admins"}, "attrs": {}, "parents": [{"type": "Group", "id": "staff"}] }
+        ]);
+        let lazy = crate::Entities::from_json_value_with_options(
+            json.clone(),
+            None,
+            crate::EntitiesOptions::new().with_tc_computation(crate::TcComputation::ComputeLazily),
+        )
+        .expect("should parse");
+        let eager = crate::Entities::from_json_value(json, None).expect("should parse");
+        let parents = |entities: &crate::Entities| {
+            Entities::from(entities)
+                .entities
+                .into_iter()
+                .map(|entity| (format!("{:?}", entity.uid), entity.parents.len()))
+                .collect::<HashMap<_, _>>()
+        };
+        assert_eq!(parents(&lazy), parents(&eager));
+        assert_eq!(sorted_json(&roundtrip_entities(&lazy)), sorted_json(&eager));
+    }
+
+    #[test]
+    #[cfg(feature = "u256")]
+    fn u256_roundtrip() {
+        let max = "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+        let entities = crate::Entities::from_json_value(
+            serde_json::json!([{
+                "uid": {"type": "Account", "id": "a"},
+                "attrs": {"balance": {"__extn": {"fn": "u256", "arg": max}}},
+                "parents": []
+            }]),
+            None,
+        )
+        .expect("should parse");
+        let decoded = roundtrip_entities(&entities);
+        let uid = crate::EntityUid::from_type_name_and_id(
+            EntityTypeName::from_str("Account").unwrap(),
+            EntityId::from_str("a").unwrap(),
+        );
+        let balance = decoded.get(&uid).unwrap().0.get("balance").unwrap().clone();
+        assert_eq!(
+            balance.to_string(),
+            format!("u256(\"{max}\")"),
+            "u256 value should round-trip exactly"
+        );
+    }
+
+    #[test]
+    fn request_roundtrip() {
+        let uid = |ty: &str, id: &str| {
+            crate::EntityUid::from_type_name_and_id(
+                EntityTypeName::from_str(ty).unwrap(),
+                EntityId::from_str(id).unwrap(),
+            )
+        };
+        let request = crate::Request::new(
+            Some(uid("User", "alice")),
+            Some(uid("Action", "view")),
+            None,
+            Context::from_json_str(r#"{"mfa": true}"#, None).unwrap(),
+        );
+        let bytes = Request::from(&request).encode_to_vec();
+        let decoded = Request::decode(bytes.as_slice()).expect("should decode");
+        assert_eq!(
+            decoded.principal,
+            Some(EntityUid::from(&uid("User", "alice")))
+        );
+        assert_eq!(
+            decoded.resource.as_ref().map(|uid| uid.type_name.as_str()),
+            Some(""),
+            "unspecified resource should have an empty type name"
+        );
+        let decoded = crate::Request::try_from(decoded).expect("should convert");
+        assert_eq!(decoded.principal(), Some(&uid("User", "alice")));
+        assert_eq!(decoded.resource(), None);
+        assert_eq!(decoded.0.to_string(), request.0.to_string());
+    }
+
+    #[test]
+    fn invalid_messages() {
+        assert!(matches!(
+            crate::Entity::try_from(Entity::default()),
+            Err(ProtobufError::MissingField("uid"))
+        ));
+        let bad_name = EntityUid {
+            type_name: "not a name".into(),
+            id: "x".into(),
+        };
+        assert!(matches!(
+            crate::EntityUid::try_from(bad_name),
+            Err(ProtobufError::InvalidName { .. })
+        ));
+        assert!(matches!(
+            crate::RestrictedExpression::try_from(Value::default()),
+            Err(ProtobufError::MissingField("kind"))
+        ));
+    }
+
+    #[test]
+    fn absent_decision_is_rejected() {
+        let decoded = Response::decode(Response::default().encode_to_vec().as_slice())
+            .expect("should decode");
+        assert!(matches!(
+            decoded.checked_decision(),
+            Err(ProtobufError::InvalidDecision(0))
+        ));
+        let unknown = Response {
+            decision: 7,
+            ..Response::default()
+        };
+        assert!(matches!(
+            unknown.checked_decision(),
+            Err(ProtobufError::InvalidDecision(7))
+        ));
+        for decision in [crate::Decision::Allow, crate::Decision::Deny] {
+            let response = Response {
+                decision: Decision::from(decision) as i32,
+                ..Response::default()
+            };
+            let decoded =
+                Response::decode(response.encode_to_vec().as_slice()).expect("should decode");
+            assert_eq!(
+                decoded.checked_decision().expect("should convert"),
+                decision
+            );
+        }
+    }
+}
@@ -0,0 +1,191 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Recording where the templates and links of a policy set came from.
+//!
+//! A [`PolicySet`] keeps the [`Provenance`] of each of its templates and
+//! policies: the [`Origin`] of the text it was parsed from, such as a file, a
+//! URL or a contract, and where in that text it is. Policy sets loaded with
+//! [`PolicySet::from_file`] or from a [`crate::sources::PolicySource`] record
+//! it themselves; for others, use [`PolicySet::record_origin`] or
+//! [`PolicySet::set_provenance`]. A template-linked policy has the provenance
+//! of its template unless another is set for it.
+//!
+//! Provenance is reported by validation errors and warnings, and can be looked
+//! up for the policies which determined an authorization decision with
+//! [`Diagnostics::reason_provenance`](crate::Diagnostics::reason_provenance).
+
+use crate::{PolicyId, PolicySet};
+use std::fmt::{self, Display};
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// Where the text of a policy set came from
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Origin {
+    /// A file
+    File(PathBuf),
+    /// An HTTP(S) URL, or an IPFS path
+    Url(String),
+    /// A contract, such as one returning the policy set from a view function
+    Contract {
+        /// The address of the contract
+        address: String,
+        /// The transaction which set the policy set, if known
+        tx_hash: Option<String>,
+    },
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "`{}`", path.display()),
+            Self::Url(url) => write!(f, "`{url}`"),
+            Self::Contract { address, tx_hash } => {
+                write!(f, "the contract at `{address}`")?;
+                if let Some(tx_hash) = tx_hash {
+                    write!(f, " (transaction `{tx_hash}`)")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Where a template or policy came from
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Provenance {
+    /// The origin of the text it was parsed from
+    pub origin: Origin,
+    /// The range of bytes it spans in that text, including its annotations
+    pub span: Option<Range<usize>>,
+}
+
+impl Provenance {
+    /// Provenance from `origin`, without a span
+    pub fn new(origin: Origin) -> Self {
+        Self { origin, span: None }
+    }
+}
+
+impl Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.origin)?;
+        if let Some(span) = &self.span {
+            write!(f, " at offset {}-{}", span.start, span.end)?;
+        }
+        Ok(())
+    }
+}
+
+impl PolicySet {
+    /// Get where the template or policy `id` came from, if that was recorded.
+    /// A template-linked policy without provenance of its own has that of its
+    /// template.
+    pub fn provenance(&self, id: &PolicyId) -> Option<&Provenance> {
+        self.provenance.get(id).or_else(|| {
+            self.policy(id)
+                .and_then(crate::Policy::template_id)
+                .and_then(|template_id| self.provenance.get(template_id))
+        })
+    }
+
+    /// Record where the template or policy `id` came from, replacing what was
+    /// recorded before
+    pub fn set_provenance(&mut self, id: PolicyId, provenance: Provenance) {
+        self.provenance.insert(id, provenance);
+    }
+
+    /// Record that every template and static policy without provenance came
+    /// from `origin`, spanning the range of that text it was parsed from
+    pub fn record_origin(&mut self, origin: &Origin) {
+        self.record_origins(|_| Some(origin.clone()));
+    }
+
+    /// Record, for every template and static policy without provenance, the
+    /// origin given by `origin_of` for its id, if any
+    pub(crate) fn record_origins(&mut self, origin_of: impl Fn(&PolicyId) -> Option<Origin>) {
+        let templates = self
+            .templates()
+            .map(|t| (t.id().clone(), t.source_location()));
+        let policies = self
+            .policies()
+            .filter(|p| p.template_id().is_none())
+            .map(|p| (p.id().clone(), p.source_location()));
+        let spans = templates
+            .chain(policies)
+            .filter(|(id, _)| !self.provenance.contains_key(id))
+            .map(|(id, location)| {
+                let span = location
+                    .range_start()
+                    .zip(location.range_end())
+                    .map(|(start, end)| start..end);
+                (id, span)
+            })
+            .collect::<Vec<_>>();
+        for (id, span) in spans {
+            if let Some(origin) = origin_of(&id) {
+                self.provenance.insert(id, Provenance { origin, span });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{EntityUid, SlotId};
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    #[test]
+    fn links_inherit_provenance() {
+        let mut pset = PolicySet::from_str(
+            r#"permit(principal, action, resource);
+            permit(principal == ?principal, action, resource);"#,
+        )
+        .unwrap();
+        pset.record_origin(&Origin::Url("https://example.com/policies.cedar".into()));
+        let template = PolicyId::from_str("policy1").unwrap();
+        assert_eq!(
+            pset.provenance(&template).unwrap().to_string(),
+            "`https://example.com/policies.cedar` at offset 49-99"
+        );
+
+        let link = PolicyId::from_str("alice").unwrap();
+        pset.link(
+            template.clone(),
+            link.clone(),
+            HashMap::from([(
+                SlotId::principal(),
+                EntityUid::from_str(r#"User::"alice""#).unwrap(),
+            )]),
+        )
+        .unwrap();
+        assert_eq!(pset.provenance(&link), pset.provenance(&template));
+
+        let contract = Provenance::new(Origin::Contract {
+            address: "0x52908400098527886E0F7030069857D2E4169EE7".into(),
+            tx_hash: Some("0xabc".into()),
+        });
+        pset.set_provenance(link.clone(), contract.clone());
+        assert_eq!(pset.provenance(&link), Some(&contract));
+        assert_eq!(
+            contract.to_string(),
+            "the contract at `0x52908400098527886E0F7030069857D2E4169EE7` (transaction `0xabc`)"
+        );
+    }
+}
@@ -0,0 +1,549 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Spending limits, for policies which cap how much a principal may spend in
+//! a day or a week.
+//!
+//! The `quota` extension adds the functions `spentToday(entity)` and
+//! `spentThisWeek(entity)`, which give the amount an entity spent in the
+//! current UTC day or week (starting on Monday) as a `u256`. The evaluator
+//! holds no state, so they only work with a [`QuotaAuthorizer`], which reads
+//! the amounts from a [`QuotaStore`]. When it allows a request, it reserves
+//! the `u256` amount in its context, `context.amount` by default, as spent by
+//! the principal. The store reserves it only if the principal has spent
+//! nothing since the decision was made, so requests racing for the same
+//! quota, even in other processes, cannot all be allowed; the losers are
+//! decided again.
+//!
+//! ```
+//! # use cedar_policy::{quota::*, Context, Decision, Entities, PolicySet, Request, RestrictedExpression};
+//! # use cedar_policy::extensions::u256::U256;
+//! let policies: PolicySet = r#"permit(principal, action == Action::"transfer", resource)
+//!     when { spentToday(principal) + context.amount <= u256("1000") };"#
+//!     .parse()
+//!     .unwrap();
+//! let authorizer = QuotaAuthorizer::new(policies, Entities::empty(), InMemoryQuotaStore::new());
+//! let transfer = |amount: u64| {
+//!     Request::new(
+//!         Some(r#"User::"alice""#.parse().unwrap()),
+//!         Some(r#"Action::"transfer""#.parse().unwrap()),
+//!         Some(r#"Vault::"treasury""#.parse().unwrap()),
+//!         Context::from_pairs([(
+//!             "amount".to_string(),
+//!             RestrictedExpression::new_u256(U256::from(amount)),
+//!         )]),
+//!     )
+//! };
+//! let decide = |amount| authorizer.is_authorized(&transfer(amount)).unwrap().decision();
+//! assert_eq!(decide(600), Decision::Allow);
+//! assert_eq!(decide(600), Decision::Deny);
+//! assert_eq!(decide(400), Decision::Allow);
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use cedar_policy_core::ast;
+use cedar_policy_core::evaluator::RestrictedEvaluator;
+use cedar_policy_core::extensions::quota::{self as quota_extension, SpentFn};
+use cedar_policy_core::extensions::u256::U256;
+use cedar_policy_core::extensions::Extensions;
+use thiserror::Error;
+
+pub use cedar_policy_core::extensions::quota::QuotaWindow;
+
+use crate::{Authorizer, Decision, Entities, EntityUid, PolicySet, Request, Response};
+
+/// How many times a request is decided again when the amounts its principal
+/// spent changed between deciding and reserving
+const MAX_ATTEMPTS: usize = 8;
+
+/// Errors of the quota store or the accounting of amounts spent
+#[derive(Debug, Error)]
+pub enum QuotaError {
+    /// The store failed to read or reserve an amount
+    #[error("quota store failed: {0}")]
+    Store(String),
+    /// The amount in the context of an allowed request is not a `u256`, so it
+    /// cannot be reserved
+    #[error("the amount `{0}` in the context is not a u256")]
+    InvalidAmount(String),
+    /// Other requests of the principal kept spending between deciding the
+    /// request and reserving its amount
+    #[error("the amounts spent by `{0}` changed each time the request was decided")]
+    Contended(EntityUid),
+}
+
+/// A store of the amounts entities spent, which the evaluator consults
+/// through the functions of the `quota` extension
+pub trait QuotaStore: Send + Sync + 'static {
+    /// The amount `entity` spent in the current `window`
+    ///
+    /// # Errors
+    ///
+    /// If the store fails to read it.
+    fn spent(&self, entity: &EntityUid, window: QuotaWindow) -> Result<U256, QuotaError>;
+
+    /// Record that `entity` spent `amount` now, unless that takes the amount
+    /// it spent in one of the windows of `limits` over the limit given for
+    /// the window. Checking and recording must be atomic, also for stores
+    /// shared between processes. Returns whether the amount was recorded.
+    ///
+    /// # Errors
+    ///
+    /// If the store fails to read or record the amounts.
+    fn try_reserve(
+        &self,
+        entity: &EntityUid,
+        amount: U256,
+        limits: &[(QuotaWindow, U256)],
+    ) -> Result<bool, QuotaError>;
+}
+
+/// A [`QuotaStore`] in memory, which forgets amounts spent before the current
+/// week
+#[derive(Debug, Default)]
+pub struct InMemoryQuotaStore {
+    spending: Mutex<HashMap<EntityUid, Vec<(u64, U256)>>>,
+}
+
+impl InMemoryQuotaStore {
+    /// An empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The amount `entity` spent in the `window` containing `now_ms`, in
+    /// milliseconds since the Unix epoch
+    pub fn spent_at(&self, entity: &EntityUid, window: QuotaWindow, now_ms: u64) -> U256 {
+        let spending = self.spending.lock().unwrap_or_else(PoisonError::into_inner);
+        total(spending.get(entity).into_iter().flatten(), window, now_ms)
+    }
+
+    /// Record that `entity` spent `amount` at `timestamp_ms`, in milliseconds
+    /// since the Unix epoch
+    pub fn record_at(&self, entity: &EntityUid, amount: U256, timestamp_ms: u64) {
+        self.try_reserve_at(entity, amount, &[], timestamp_ms);
+    }
+
+    /// Like [`QuotaStore::try_reserve`], at `timestamp_ms`, in milliseconds
+    /// since the Unix epoch
+    pub fn try_reserve_at(
+        &self,
+        entity: &EntityUid,
+        amount: U256,
+        limits: &[(QuotaWindow, U256)],
+        timestamp_ms: u64,
+    ) -> bool {
+        let week = QuotaWindow::Week.start_ms(timestamp_ms);
+        let mut spending = self.spending.lock().unwrap_or_else(PoisonError::into_inner);
+        let amounts = spending.entry(entity.clone()).or_default();
+        amounts.retain(|(timestamp, _)| *timestamp >= week);
+        let within_limits = limits.iter().all(|(window, limit)| {
+            total(amounts.iter(), *window, timestamp_ms)
+                .checked_add(amount)
+                .is_some_and(|total| total <= *limit)
+        });
+        if within_limits {
+            amounts.push((timestamp_ms, amount));
+        }
+        drop(spending);
+        within_limits
+    }
+}
+
+/// The sum of the `amounts` spent in the `window` containing `now_ms`
+fn total<'a>(
+    amounts: impl Iterator<Item = &'a (u64, U256)>,
+    window: QuotaWindow,
+    now_ms: u64,
+) -> U256 {
+    let start = window.start_ms(now_ms);
+    amounts
+        .filter(|(timestamp, _)| (start..=now_ms).contains(timestamp))
+        .fold(U256::from(0u64), |total, (_, amount)| {
+            total.saturating_add(*amount)
+        })
+}
+
+impl QuotaStore for InMemoryQuotaStore {
+    fn spent(&self, entity: &EntityUid, window: QuotaWindow) -> Result<U256, QuotaError> {
+        Ok(self.spent_at(entity, window, now()))
+    }
+
+    fn try_reserve(
+        &self,
+        entity: &EntityUid,
+        amount: U256,
+        limits: &[(QuotaWindow, U256)],
+    ) -> Result<bool, QuotaError> {
+        Ok(self.try_reserve_at(entity, amount, limits, now()))
+    }
+}
+
+/// The milliseconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| {
+            u64::try_from(since.as_millis()).unwrap_or(u64::MAX)
+        })
+}
+
+/// Authorizes requests against a policy set and entities, reading the
+/// amounts spent from a [`QuotaStore`] and reserving those of the requests it
+/// allows
+#[derive(Debug)]
+pub struct QuotaAuthorizer<S> {
+    policies: PolicySet,
+    entities: Entities,
+    store: Arc<S>,
+    amount_attribute: String,
+}
+
+impl<S: QuotaStore> QuotaAuthorizer<S> {
+    /// Authorize requests against `policies` and `entities`, with the amounts
+    /// spent in `store`
+    pub fn new(policies: PolicySet, entities: Entities, store: S) -> Self {
+        Self {
+            policies,
+            entities,
+            store: Arc::new(store),
+            amount_attribute: "amount".to_string(),
+        }
+    }
+
+    /// Reserve the amount in the attribute `attribute` of the context of the
+    /// requests allowed, rather than in `amount`
+    #[must_use]
+    pub fn amount_attribute(mut self, attribute: impl Into<String>) -> Self {
+        self.amount_attribute = attribute.into();
+        self
+    }
+
+    /// The policies, which may call `spentToday` and `spentThisWeek`
+    pub fn policies(&self) -> &PolicySet {
+        &self.policies
+    }
+
+    /// The entities requests are authorized with
+    pub fn entities(&self) -> &Entities {
+        &self.entities
+    }
+
+    /// The store of the amounts spent
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Authorize `request`. If it is allowed, the amount in its context, if
+    /// any, is reserved as spent by its principal, provided the principal
+    /// spent nothing since the amounts the decision read; otherwise the
+    /// request is decided again.
+    ///
+    /// # Errors
+    ///
+    /// If the amount of an allowed request is invalid or cannot be reserved,
+    /// in which case the decision must not be acted on.
+    pub fn is_authorized(&self, request: &Request) -> Result<Response, QuotaError> {
+        let Some(principal) = request.principal() else {
+            return Ok(self.decide(request, None).0);
+        };
+        for _ in 0..MAX_ATTEMPTS {
+            let (response, read) = self.decide(request, Some(principal));
+            if response.decision() != Decision::Allow {
+                return Ok(response);
+            }
+            let Some(amount) = self.amount(request)? else {
+                return Ok(response);
+            };
+            // the principal may spend no more than it had when the decision
+            // was made, plus this amount
+            let limits = read
+                .into_iter()
+                .map(|(window, spent)| (window, spent.saturating_add(amount)))
+                .collect::<Vec<_>>();
+            if self.store.try_reserve(principal, amount, &limits)? {
+                return Ok(response);
+            }
+        }
+        Err(QuotaError::Contended(principal.clone()))
+    }
+
+    /// Decide `request`, with the least amounts `principal` spent in each
+    /// window that the policies read
+    fn decide(
+        &self,
+        request: &Request,
+        principal: Option<&EntityUid>,
+    ) -> (Response, HashMap<QuotaWindow, U256>) {
+        let read = Arc::new(Mutex::new(HashMap::<QuotaWindow, U256>::new()));
+        let spent: SpentFn = {
+            let store = Arc::clone(&self.store);
+            let principal = principal.map(|principal| principal.0.clone());
+            let read = Arc::clone(&read);
+            Arc::new(move |uid: &ast::EntityUID, window| {
+                let spent = store
+                    .spent(&EntityUid(uid.clone()), window)
+                    .map_err(|e| e.to_string())?;
+                if principal.as_ref() == Some(uid) {
+                    read.lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .entry(window)
+                        .and_modify(|least| *least = (*least).min(spent))
+                        .or_insert(spent);
+                }
+                Ok(spent)
+            })
+        };
+        let response =
+            Authorizer::with_extension_overrides(Arc::new([quota_extension::extension_with(
+                spent,
+            )]))
+            .is_authorized(request, &self.policies, &self.entities);
+        let read = std::mem::take(&mut *read.lock().unwrap_or_else(PoisonError::into_inner));
+        (response, read)
+    }
+
+    /// The amount in the context of `request`, if it has one
+    fn amount(&self, request: &Request) -> Result<Option<U256>, QuotaError> {
+        let Some((_, amount)) = request
+            .0
+            .context()
+            .into_iter()
+            .flat_map(ast::Context::iter)
+            .find(|(attr, _)| *attr == self.amount_attribute)
+        else {
+            return Ok(None);
+        };
+        RestrictedEvaluator::new(&Extensions::all_available())
+            .interpret(amount)
+            .ok()
+            .and_then(|amount| U256::try_from(&amount).ok())
+            .map(Some)
+            .ok_or_else(|| QuotaError::InvalidAmount(self.amount_attribute.clone()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Context, RestrictedExpression};
+
+    const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+    fn transfer(principal: &str, amount: RestrictedExpression) -> Request {
+        Request::new(
+            Some(principal.parse().expect("valid uid")),
+            Some(r#"Action::"transfer""#.parse().expect("valid uid")),
+            Some(r#"Vault::"treasury""#.parse().expect("valid uid")),
+            Context::from_pairs([("value".to_string(), amount)]),
+        )
+    }
+
+    fn u256(amount: u64) -> U256 {
+        U256::from(amount)
+    }
+
+    #[test]
+    fn enforces_daily_and_weekly_limits() {
+        let policies: PolicySet = r#"permit(principal, action == Action::"transfer", resource)
+            when {
+                spentToday(principal) + context.value <= u256("100") &&
+                spentThisWeek(principal) + context.value <= u256("150")
+            };"#
+        .parse()
+        .expect("valid policies");
+        let store = InMemoryQuotaStore::new();
+        let alice = EntityUid::from_strs("User", "alice");
+        // 100 spent by alice yesterday counts towards the week, unless today
+        // is a Monday
+        let now = now();
+        let yesterday = now - DAY_MS;
+        store.record_at(&alice, u256(100), yesterday);
+        let monday = QuotaWindow::Week.start_ms(now) == QuotaWindow::Day.start_ms(now);
+
+        let authorizer =
+            QuotaAuthorizer::new(policies, Entities::empty(), store).amount_attribute("value");
+        let decide = |principal: &str, amount: u64| {
+            authorizer
+                .is_authorized(&transfer(
+                    principal,
+                    RestrictedExpression::new_u256(u256(amount)),
+                ))
+                .expect("authorizes")
+                .decision()
+        };
+        assert_eq!(decide(r#"User::"alice""#, 40), Decision::Allow);
+        let expected = if monday {
+            Decision::Allow
+        } else {
+            Decision::Deny
+        };
+        assert_eq!(decide(r#"User::"alice""#, 20), expected);
+        assert_eq!(decide(r#"User::"bob""#, 100), Decision::Allow);
+        assert_eq!(decide(r#"User::"bob""#, 1), Decision::Deny);
+        assert_eq!(
+            authorizer.store().spent_at(
+                &EntityUid::from_strs("User", "bob"),
+                QuotaWindow::Day,
+                super::now()
+            ),
+            u256(100)
+        );
+    }
+
+    /// A store in which another process spends `racing` for each of the
+    /// first `races` reservations, just before they are made
+    struct RacingStore {
+        store: InMemoryQuotaStore,
+        racing: U256,
+        races: Mutex<usize>,
+    }
+
+    impl QuotaStore for RacingStore {
+        fn spent(&self, entity: &EntityUid, window: QuotaWindow) -> Result<U256, QuotaError> {
+            self.store.spent(entity, window)
+        }
+
+        fn try_reserve(
+            &self,
+            entity: &EntityUid,
+            amount: U256,
+            limits: &[(QuotaWindow, U256)],
+        ) -> Result<bool, QuotaError> {
+            let mut races = self.races.lock().unwrap_or_else(PoisonError::into_inner);
+            if *races > 0 {
+                *races -= 1;
+                self.store.record_at(entity, self.racing, now());
+            }
+            drop(races);
+            self.store.try_reserve(entity, amount, limits)
+        }
+    }
+
+    #[test]
+    fn decides_again_when_spending_races() {
+        let policies: PolicySet = r#"permit(principal, action, resource)
+            when { spentToday(principal) + context.value <= u256("1000") };"#
+            .parse()
+            .expect("valid policies");
+        let alice = EntityUid::from_strs("User", "alice");
+        let request = transfer(
+            r#"User::"alice""#,
+            RestrictedExpression::new_u256(u256(600)),
+        );
+
+        // another 600 is spent between deciding and reserving, so the request
+        // is decided again, and denied
+        let store = RacingStore {
+            store: InMemoryQuotaStore::new(),
+            racing: u256(600),
+            races: Mutex::new(1),
+        };
+        let authorizer = QuotaAuthorizer::new(policies.clone(), Entities::empty(), store)
+            .amount_attribute("value");
+        let response = authorizer.is_authorized(&request).expect("authorizes");
+        assert_eq!(response.decision(), Decision::Deny);
+        assert_eq!(
+            authorizer
+                .store()
+                .store
+                .spent_at(&alice, QuotaWindow::Day, now()),
+            u256(600)
+        );
+
+        // small amounts spent in between each time never settle the decision
+        let store = RacingStore {
+            store: InMemoryQuotaStore::new(),
+            racing: u256(1),
+            races: Mutex::new(MAX_ATTEMPTS),
+        };
+        let authorizer =
+            QuotaAuthorizer::new(policies, Entities::empty(), store).amount_attribute("value");
+        assert!(matches!(
+            authorizer.is_authorized(&request),
+            Err(QuotaError::Contended(principal)) if principal == alice
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_amounts() {
+        let policies: PolicySet = r#"permit(principal, action, resource);"#
+            .parse()
+            .expect("valid policies");
+        let authorizer =
+            QuotaAuthorizer::new(policies, Entities::empty(), InMemoryQuotaStore::new())
+                .amount_attribute("value");
+        for amount in [
+            RestrictedExpression::new_long(100),
+            RestrictedExpression::new_string("100".into()),
+        ] {
+            assert!(matches!(
+                authorizer.is_authorized(&transfer(r#"User::"alice""#, amount)),
+                Err(QuotaError::InvalidAmount(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn spent_without_quota_authorizer() {
+        let policies: PolicySet = r#"permit(principal, action, resource)
+            when { spentToday(principal) < u256("100") };"#
+            .parse()
+            .expect("valid policies");
+        let response = Authorizer::new().is_authorized(
+            &transfer(r#"User::"alice""#, RestrictedExpression::new_u256(u256(1))),
+            &policies,
+            &Entities::empty(),
+        );
+        assert_eq!(response.decision(), Decision::Deny);
+        assert_eq!(response.diagnostics().errors().count(), 1);
+    }
+
+    #[test]
+    fn in_memory_windows() {
+        let store = InMemoryQuotaStore::new();
+        let alice = EntityUid::from_strs("User", "alice");
+        // Monday, 5 January 1970
+        let monday = 4 * DAY_MS;
+        store.record_at(&alice, u256(5), monday - 1);
+        store.record_at(&alice, u256(7), monday + 1);
+        store.record_at(&alice, u256(11), monday + DAY_MS);
+        assert_eq!(
+            store.spent_at(&alice, QuotaWindow::Day, monday + DAY_MS),
+            u256(11)
+        );
+        assert_eq!(
+            store.spent_at(&alice, QuotaWindow::Week, monday + DAY_MS),
+            u256(18)
+        );
+        let limits = [(QuotaWindow::Day, u256(20)), (QuotaWindow::Week, u256(30))];
+        assert!(store.try_reserve_at(&alice, u256(9), &limits, monday + DAY_MS));
+        assert!(!store.try_reserve_at(&alice, u256(4), &limits, monday + DAY_MS));
+        assert!(!store.try_reserve_at(
+            &alice,
+            U256::MAX,
+            &[(QuotaWindow::Day, U256::MAX)],
+            monday + DAY_MS
+        ));
+        assert_eq!(
+            store.spent_at(&alice, QuotaWindow::Week, monday + DAY_MS),
+            u256(27)
+        );
+    }
+}
@@ -0,0 +1,363 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A [`CacheStore`] in Redis, so that the entity and decision caches of a
+//! fleet of instances share what any of them fetched or decided.
+//!
+//! Whoever can write to the Redis database can change what the caches read,
+//! so a `DecisionCache` using a database which is not trusted as much as the
+//! policies should authenticate its decisions with `with_mac_key`.
+//!
+//! ```no_run
+//! # use cedar_policy::{entity_cache::*, redis_store::RedisStore, Entity, EntityUid};
+//! # use std::time::Duration;
+//! let store = RedisStore::open("redis://cache.internal:6379/")
+//!     .unwrap()
+//!     .with_prefix("pdp:");
+//! let cache = EntityCache::new(
+//!     |uid: &EntityUid| -> Result<Option<Entity>, ProviderError> { Ok(None) },
+//!     Ttl::new(Duration::from_secs(12)),
+//! )
+//! .with_store(store);
+//! ```
+
+use std::{
+    fmt::{self, Debug},
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+use redis::{Client, Connection, RedisError, RedisResult};
+
+use crate::entity_cache::{CacheStore, StoreError};
+
+/// How many keys are asked for at a time when listing keys
+const SCAN_COUNT: usize = 100;
+
+/// A [`CacheStore`] in Redis. Values are set with a TTL in milliseconds, and
+/// keys are listed with `SCAN`.
+pub struct RedisStore {
+    client: Client,
+    /// Put before every key, to share a Redis database with other users
+    prefix: String,
+    /// Connected on first use, and again after an error
+    connection: Mutex<Option<Connection>>,
+}
+
+impl RedisStore {
+    /// A store in the Redis at `url`, such as `redis://localhost:6379/0`. It
+    /// is only connected to when it is first used.
+    ///
+    /// # Errors
+    ///
+    /// If `url` is not a Redis URL.
+    pub fn open(url: &str) -> Result<Self, RedisError> {
+        Ok(Self {
+            client: Client::open(url)?,
+            prefix: String::new(),
+            connection: Mutex::new(None),
+        })
+    }
+
+    /// Put `prefix` before every key
+    #[must_use]
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Run `query` on the connection, connecting first if there is none, and
+    /// dropping it if the query fails so that the next one reconnects
+    fn query<T>(
+        &self,
+        query: impl FnOnce(&mut Connection) -> RedisResult<T>,
+    ) -> Result<T, StoreError> {
+        let mut connection = self
+            .connection
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let result = match connection.as_mut() {
+            Some(connection) => query(connection),
+            None => self.client.get_connection().and_then(|mut connected| {
+                let result = query(&mut connected);
+                *connection = Some(connected);
+                result
+            }),
+        };
+        if result.is_err() {
+            *connection = None;
+        }
+        drop(connection);
+        Ok(result?)
+    }
+}
+
+impl CacheStore for RedisStore {
+    fn get(&self, key: &str) -> Result<Option<String>, StoreError> {
+        let key = format!("{}{key}", self.prefix);
+        self.query(|connection| redis::cmd("GET").arg(&key).query(connection))
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), StoreError> {
+        let key = format!("{}{key}", self.prefix);
+        // Redis rejects a TTL of zero
+        let millis = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX).max(1);
+        self.query(|connection| {
+            redis::cmd("SET")
+                .arg(&key)
+                .arg(value)
+                .arg("PX")
+                .arg(millis)
+                .query(connection)
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), StoreError> {
+        let key = format!("{}{key}", self.prefix);
+        self.query(|connection| redis::cmd("DEL").arg(&key).query(connection))
+    }
+
+    fn keys(&self, prefix: &str) -> Result<Vec<String>, StoreError> {
+        let pattern = format!("{}*", escape_glob(&format!("{}{prefix}", self.prefix)));
+        let mut keys = Vec::new();
+        let mut cursor = 0_u64;
+        loop {
+            let (next, batch): (u64, Vec<String>) = self.query(|connection| {
+                redis::cmd("SCAN")
+                    .arg(cursor)
+                    .arg("MATCH")
+                    .arg(&pattern)
+                    .arg("COUNT")
+                    .arg(SCAN_COUNT)
+                    .query(connection)
+            })?;
+            keys.extend(
+                batch
+                    .into_iter()
+                    .filter_map(|key| key.strip_prefix(&self.prefix).map(ToString::to_string)),
+            );
+            if next == 0 {
+                // `SCAN` may return a key more than once
+                keys.sort();
+                keys.dedup();
+                return Ok(keys);
+            }
+            cursor = next;
+        }
+    }
+}
+
+impl Debug for RedisStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisStore")
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+/// `text` with the special characters of Redis glob patterns escaped, so that
+/// it only matches itself
+fn escape_glob(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::decision_cache::DecisionCache;
+    use crate::entity_cache::{EntityCache, ProviderError, Ttl};
+    use crate::{
+        Context, Decision, Entities, Entity, EntityUid, EvalResult, PolicySet, Request,
+        RestrictedExpression,
+    };
+    use std::collections::{HashMap, HashSet};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    /// The values of a fake Redis, and the TTLs they were set with
+    type Values = Arc<Mutex<HashMap<String, (String, Option<String>)>>>;
+
+    /// Read a command, an array of bulk strings, from `reader`
+    fn read_command(reader: &mut impl BufRead) -> Option<Vec<String>> {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let count: usize = line.trim_end().strip_prefix('*')?.parse().ok()?;
+        let mut args = Vec::new();
+        for _ in 0..count {
+            line.clear();
+            reader.read_line(&mut line).ok()?;
+            let len: usize = line.trim_end().strip_prefix('$')?.parse().ok()?;
+            let mut arg = vec![0; len + 2];
+            reader.read_exact(&mut arg).ok()?;
+            arg.truncate(len);
+            args.push(String::from_utf8(arg).ok()?);
+        }
+        Some(args)
+    }
+
+    fn bulk(text: &str) -> String {
+        format!("${}\r\n{text}\r\n", text.len())
+    }
+
+    /// Answer the commands of `stream` from `values`, as Redis would
+    fn serve(stream: TcpStream, values: &Values) {
+        let mut writer = stream.try_clone().expect("clones");
+        let mut reader = BufReader::new(stream);
+        while let Some(args) = read_command(&mut reader) {
+            let mut values = values.lock().expect("not poisoned");
+            let arg = |i: usize| args.get(i).cloned().unwrap_or_default();
+            let reply = match arg(0).as_str() {
+                "GET" => values
+                    .get(&arg(1))
+                    .map_or_else(|| "$-1\r\n".to_string(), |(value, _)| bulk(value)),
+                "SET" => {
+                    let ttl = (arg(3) == "PX").then(|| arg(4));
+                    values.insert(arg(1), (arg(2), ttl));
+                    "+OK\r\n".to_string()
+                }
+                "DEL" => format!(":{}\r\n", usize::from(values.remove(&arg(1)).is_some())),
+                "SCAN" => {
+                    let prefix = arg(3).trim_end_matches('*').replace('\\', "");
+                    let keys: Vec<_> = values
+                        .keys()
+                        .filter(|key| key.starts_with(&prefix))
+                        .map(|key| bulk(key))
+                        .collect();
+                    format!("*2\r\n{}*{}\r\n{}", bulk("0"), keys.len(), keys.concat())
+                }
+                _ => "+OK\r\n".to_string(),
+            };
+            writer.write_all(reply.as_bytes()).expect("writes");
+        }
+    }
+
+    /// A fake Redis on a local port, and its values
+    fn fake_redis() -> (String, Values) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("binds");
+        let url = format!("redis://{}/", listener.local_addr().expect("has address"));
+        let values = Values::default();
+        let served = Arc::clone(&values);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let values = Arc::clone(&served);
+                thread::spawn(move || serve(stream, &values));
+            }
+        });
+        (url, values)
+    }
+
+    fn address() -> EntityUid {
+        EntityUid::from_str(r#"Address::"0xabc""#).expect("valid uid")
+    }
+
+    #[test]
+    fn shares_entities_between_caches() {
+        let (url, values) = fake_redis();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let instance = || {
+            let calls = Arc::clone(&calls);
+            Arc::new(
+                EntityCache::new(
+                    move |uid: &EntityUid| -> Result<Option<Entity>, ProviderError> {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        Ok(Some(Entity::new(
+                            uid.clone(),
+                            HashMap::from([(
+                                "balance".to_string(),
+                                RestrictedExpression::from_str(r#"u256("1000")"#)?,
+                            )]),
+                            HashSet::new(),
+                        )))
+                    },
+                    Ttl::new(Duration::from_secs(60)),
+                )
+                .with_store(
+                    RedisStore::open(&url)
+                        .expect("valid url")
+                        .with_prefix("pdp:"),
+                ),
+            )
+        };
+        let (first, second) = (instance(), instance());
+        let fetched = first.entity(&address()).expect("fetches").expect("exists");
+        let shared = second.entity(&address()).expect("fetches").expect("exists");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(fetched.attr("balance"), shared.attr("balance"));
+        assert!(matches!(
+            shared.attr("balance"),
+            Some(Ok(EvalResult::ExtensionValue(_)))
+        ));
+        assert_eq!(
+            values
+                .lock()
+                .expect("not poisoned")
+                .get(r#"pdp:entity:Address::"0xabc""#)
+                .and_then(|(_, ttl)| ttl.clone()),
+            Some("60000".to_string())
+        );
+
+        second.clear().expect("clears");
+        assert!(values.lock().expect("not poisoned").is_empty());
+        first.entity(&address()).expect("fetches");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn shares_decisions_between_caches() {
+        let (url, values) = fake_redis();
+        let instance = || {
+            DecisionCache::new(Duration::from_secs(12))
+                .with_store(RedisStore::open(&url).expect("valid url"))
+        };
+        let policies =
+            PolicySet::from_str("permit(principal, action, resource);").expect("policies parse");
+        let request = Request::new(Some(address()), None, None, Context::empty());
+        let decided = instance().is_authorized(&request, &policies, &Entities::empty(), "1");
+        assert_eq!(decided.decision(), Decision::Allow);
+        assert_eq!(values.lock().expect("not poisoned").len(), 1);
+        // the second instance reuses the decision, without the policies
+        let reused = instance().is_authorized(&request, &PolicySet::new(), &Entities::empty(), "1");
+        assert_eq!(reused.decision(), Decision::Allow);
+    }
+
+    #[test]
+    fn reports_unreachable_servers() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("binds");
+        let url = format!("redis://{}/", listener.local_addr().expect("has address"));
+        drop(listener);
+        let store = RedisStore::open(&url).expect("valid url");
+        assert!(store.get("key").is_err());
+    }
+
+    #[test]
+    fn escapes_glob_patterns() {
+        assert_eq!(
+            escape_glob(r#"entity:A::"x*[y]?\""#),
+            r#"entity:A::"x\*\[y\]\?\\""#
+        );
+    }
+}
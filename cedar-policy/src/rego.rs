@@ -0,0 +1,1211 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Importing the rules of Rego modules, the policies of the Open Policy Agent,
+//! as Cedar policies.
+//!
+//! [`import_rego`] converts the `allow` and `deny` rules of a module which
+//! compare attributes of `input` with literals, like
+//!
+//! ```text
+//! package transfers
+//!
+//! default allow := false
+//!
+//! allow if {
+//!     input.action == "transfer"
+//!     input.principal.role == "treasurer"
+//!     input.context.amount <= 1000000000000000000000
+//!     not input.principal.suspended
+//! }
+//!
+//! deny contains "sanctioned recipient" if {
+//!     input.context.recipient in {"0x8589427373d6d84e98730d7795d8f6f8731fda16"}
+//! }
+//! ```
+//!
+//! into `permit` and `forbid` policies:
+//! * `input.principal`, `input.resource` and `input.context` become the
+//!   variables of the same names, and other attributes of `input` become
+//!   attributes of `context`
+//! * comparisons of `input.action` with strings become constraints on the
+//!   action, like `action == Action::"transfer"`
+//! * integers too large for a `Long` become `u256` literals, and numbers with
+//!   up to four decimal places `decimal` literals
+//! * `x[_] == y` becomes `x.contains(y)`, and `startswith`, `endswith` and
+//!   `contains` with a literal pattern become `like`
+//! * the message of a `deny` rule becomes a `@reason` annotation
+//!
+//! Rules using anything else, like variables, references to other rules or to
+//! `data`, or other built-in functions, are left out. Both those and the
+//! conversions to be aware of are reported as [`ImportIssue`]s.
+
+use std::fmt::{self, Display};
+
+use crate::{Policy, PolicySet};
+
+/// The largest `u256`, which has 78 digits
+const U256_MAX: &str =
+    "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+
+/// The largest integer a `decimal` can hold
+const DECIMAL_MAX: u64 = (i64::MAX / 10_000).unsigned_abs();
+
+/// Something an import left out, or changed in a way to be aware of
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportIssue {
+    /// The line of the Rego module it is about, from 1
+    pub line: usize,
+    /// Whether the rule was left out, rather than changed
+    pub dropped: bool,
+    /// Why
+    pub message: String,
+}
+
+impl ImportIssue {
+    fn dropped(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            dropped: true,
+            message: message.into(),
+        }
+    }
+
+    fn changed(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            dropped: false,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for ImportIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = if self.dropped { "left out" } else { "changed" };
+        write!(f, "line {} {what}: {}", self.line, self.message)
+    }
+}
+
+/// The policies imported from a Rego module, and what was left out or changed
+#[derive(Debug, Clone)]
+pub struct Imported {
+    /// The policies, with ids like `allow0` and `deny1` numbering the rules of
+    /// each name in order
+    pub policies: PolicySet,
+    /// The Cedar text of the policies, in the order of their rules
+    pub text: String,
+    /// What was left out or changed, in the order of the module
+    pub issues: Vec<ImportIssue>,
+}
+
+/// Convert the `allow` and `deny` rules of the Rego module `src` to `permit`
+/// and `forbid` policies, leaving out the rules which cannot be
+pub fn import_rego(src: &str) -> Imported {
+    let mut policies = PolicySet::new();
+    let mut texts = Vec::new();
+    let mut issues = Vec::new();
+    let mut allows = 0;
+    let mut denies = 0;
+    for statement in statements(lex(src)) {
+        let Some(line) = statement.first().map(|lexed| lexed.line) else {
+            continue;
+        };
+        let mut parser = Parser::new(&statement);
+        match parser.statement() {
+            Ok(None) => (),
+            Ok(Some(rule)) => {
+                let id = if rule.forbid {
+                    denies += 1;
+                    format!("deny{}", denies - 1)
+                } else {
+                    allows += 1;
+                    format!("allow{}", allows - 1)
+                };
+                let text = rule.to_cedar(&id);
+                let added = Policy::parse(Some(id), &text)
+                    .map_err(|e| format!("the converted policy does not parse: {e}"))
+                    .and_then(|policy| policies.add(policy).map_err(|e| e.to_string()));
+                match added {
+                    Ok(()) => {
+                        issues.extend(
+                            parser
+                                .moved
+                                .iter()
+                                .map(|name| {
+                                    format!("`input.{name}` became an attribute of `context`")
+                                })
+                                .map(|message| ImportIssue::changed(line, message)),
+                        );
+                        texts.push(text);
+                    }
+                    Err(message) => issues.push(ImportIssue::dropped(line, message)),
+                }
+            }
+            Err(message) => issues.push(ImportIssue::dropped(line, message)),
+        }
+    }
+    Imported {
+        policies,
+        text: texts.join("\n\n"),
+        issues,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(String),
+    Punct(&'static str),
+    Newline,
+    /// Text which is not a token, and why
+    Invalid(String),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ident(s) | Self::Num(s) => write!(f, "{s}"),
+            Self::Str(s) => write!(f, "{s:?}"),
+            Self::Punct(p) => write!(f, "{p}"),
+            Self::Newline => write!(f, "end of line"),
+            Self::Invalid(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+/// A token and the line it is on
+#[derive(Debug, Clone)]
+struct Lexed {
+    token: Token,
+    line: usize,
+}
+
+/// The tokens of `src`, with runs of newlines as one [`Token::Newline`]
+fn lex(src: &str) -> Vec<Lexed> {
+    let mut tokens: Vec<Lexed> = Vec::new();
+    let mut chars = src.chars().peekable();
+    let mut line = 1;
+    while let Some(c) = chars.next() {
+        let start = line;
+        let token = match c {
+            '\n' => {
+                line += 1;
+                if matches!(
+                    tokens.last(),
+                    None | Some(Lexed {
+                        token: Token::Newline,
+                        ..
+                    })
+                ) {
+                    continue;
+                }
+                Token::Newline
+            }
+            c if c.is_whitespace() => continue,
+            '#' => {
+                while chars.next_if(|c| *c != '\n').is_some() {}
+                continue;
+            }
+            '"' => lex_string(&mut chars),
+            '`' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        None => break Token::Invalid("an unterminated string".to_string()),
+                        Some('`') => break Token::Str(s),
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            s.push(c);
+                        }
+                    }
+                }
+            }
+            c if c.is_ascii_digit() => {
+                let mut s = c.to_string();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '.') {
+                    s.push(c);
+                }
+                Token::Num(s)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut s = c.to_string();
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                    s.push(c);
+                }
+                Token::Ident(s)
+            }
+            c => {
+                let followed_by_eq = chars.next_if_eq(&'=').is_some();
+                match (c, followed_by_eq) {
+                    (':', true) => Token::Punct(":="),
+                    ('=', true) => Token::Punct("=="),
+                    ('!', true) => Token::Punct("!="),
+                    ('<', true) => Token::Punct("<="),
+                    ('>', true) => Token::Punct(">="),
+                    (_, true) => Token::Invalid(format!("an unexpected `{c}=`")),
+                    (':', false) => Token::Punct(":"),
+                    ('=', false) => Token::Punct("="),
+                    ('<', false) => Token::Punct("<"),
+                    ('>', false) => Token::Punct(">"),
+                    ('{', false) => Token::Punct("{"),
+                    ('}', false) => Token::Punct("}"),
+                    ('[', false) => Token::Punct("["),
+                    (']', false) => Token::Punct("]"),
+                    ('(', false) => Token::Punct("("),
+                    (')', false) => Token::Punct(")"),
+                    (',', false) => Token::Punct(","),
+                    (';', false) => Token::Punct(";"),
+                    ('.', false) => Token::Punct("."),
+                    ('+', false) => Token::Punct("+"),
+                    ('-', false) => Token::Punct("-"),
+                    ('*', false) => Token::Punct("*"),
+                    ('/', false) => Token::Punct("/"),
+                    ('%', false) => Token::Punct("%"),
+                    ('|', false) => Token::Punct("|"),
+                    ('&', false) => Token::Punct("&"),
+                    (c, false) => Token::Invalid(format!("an unexpected `{c}`")),
+                }
+            }
+        };
+        tokens.push(Lexed { token, line: start });
+    }
+    tokens
+}
+
+/// The rest of a string whose opening `"` was just read from `chars`
+fn lex_string(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Token {
+    let mut s = String::new();
+    loop {
+        let c = match chars.next() {
+            None | Some('\n') => return Token::Invalid("an unterminated string".to_string()),
+            Some('"') => return Token::Str(s),
+            Some('\\') => match chars.next() {
+                Some('"') => '"',
+                Some('\\') => '\\',
+                Some('/') => '/',
+                Some('b') => '\u{8}',
+                Some('f') => '\u{c}',
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some('u') => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                        Some(c) => c,
+                        None => return Token::Invalid(format!("an invalid escape `\\u{hex}`")),
+                    }
+                }
+                _ => return Token::Invalid("an invalid escape in a string".to_string()),
+            },
+            Some(c) => c,
+        };
+        s.push(c);
+    }
+}
+
+/// Split `tokens` into the statements of a module, which end at the end of a
+/// line outside brackets, unless the next line continues them with `{` or
+/// `else`
+fn statements(tokens: Vec<Lexed>) -> Vec<Vec<Lexed>> {
+    let mut statements = Vec::new();
+    let mut current = Vec::new();
+    let mut depth = 0_usize;
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(lexed) = tokens.next() {
+        match &lexed.token {
+            Token::Punct("{" | "[" | "(") => depth += 1,
+            Token::Punct("}" | "]" | ")") => depth = depth.saturating_sub(1),
+            Token::Newline if depth == 0 => {
+                let continues = tokens.peek().is_some_and(|next| match &next.token {
+                    Token::Punct(p) => *p == "{",
+                    Token::Ident(name) => name == "else",
+                    _ => false,
+                });
+                if !continues && !current.is_empty() {
+                    statements.push(std::mem::take(&mut current));
+                }
+                continue;
+            }
+            _ => (),
+        }
+        current.push(lexed);
+    }
+    if !current.is_empty() {
+        statements.push(current);
+    }
+    statements
+}
+
+/// An `allow` or `deny` rule, converted
+#[derive(Debug)]
+struct Rule {
+    forbid: bool,
+    /// The message of a `deny` rule
+    reason: Option<String>,
+    /// The constraint on the action, if any
+    action: Option<String>,
+    conditions: Vec<String>,
+}
+
+impl Rule {
+    /// The Cedar text of the rule, as a policy with id `id`
+    fn to_cedar(&self, id: &str) -> String {
+        let mut lines = vec![format!("@id(\"{}\")", id.escape_debug())];
+        if let Some(reason) = &self.reason {
+            lines.push(format!("@reason(\"{}\")", reason.escape_debug()));
+        }
+        lines.push(format!(
+            "{}(principal, {}, resource)",
+            if self.forbid { "forbid" } else { "permit" },
+            self.action.as_deref().unwrap_or("action"),
+        ));
+        if !self.conditions.is_empty() {
+            lines.push(format!(
+                "when {{\n  {}\n}}",
+                self.conditions.join(" &&\n  ")
+            ));
+        }
+        lines.join("\n") + ";"
+    }
+}
+
+/// A conjunct of the body of a rule, converted
+enum Conjunct {
+    /// A constraint on the action, like `action == Action::"transfer"`
+    Action(String),
+    Condition(String),
+}
+
+/// An operand of an expression of a rule body, converted
+enum Term {
+    /// A Cedar expression which may be an operand of a comparison
+    Expr(String),
+    /// A Cedar `like` expression, which must be parenthesized as an operand
+    Like(String),
+    /// A string, which may be the name of an action
+    Str(String),
+    /// `input.action`
+    Action,
+    /// The elements of the Cedar set expression, as with `x[_]`
+    Elements(String),
+    /// A set or array literal
+    Set(Vec<Self>),
+}
+
+impl Term {
+    /// The Cedar expression of the term as an operand
+    fn operand(self) -> Result<String, String> {
+        match self {
+            Self::Expr(expr) => Ok(expr),
+            Self::Like(expr) => Ok(format!("({expr})")),
+            Self::Str(s) => Ok(format!("\"{}\"", s.escape_debug())),
+            Self::Action => Err(
+                "`input.action` is only converted when compared with strings with `==`, `!=` or `in`"
+                    .to_string(),
+            ),
+            Self::Elements(_) => Err(
+                "iteration with `[_]` is only converted when the elements are compared with `==`"
+                    .to_string(),
+            ),
+            Self::Set(elements) => Ok(format!(
+                "[{}]",
+                elements
+                    .into_iter()
+                    .map(Self::operand)
+                    .collect::<Result<Vec<_>, _>>()?
+                    .join(", ")
+            )),
+        }
+    }
+
+    /// The Cedar expression of the term, which is the whole of a condition
+    fn condition(self) -> Result<String, String> {
+        match self {
+            Self::Like(expr) => Ok(expr),
+            term => term.operand(),
+        }
+    }
+}
+
+/// Whether `name` can be written after a `.` in Cedar, rather than in `[...]`
+fn is_cedar_ident(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !matches!(
+            name,
+            "true" | "false" | "if" | "then" | "else" | "in" | "like" | "has" | "is"
+        )
+}
+
+/// The Cedar `like` pattern matching `s` literally, with `prefix` and
+/// `suffix` wildcards
+fn like_pattern(s: &str, prefix: bool, suffix: bool) -> String {
+    let mut pattern = String::from(if prefix { "\"*" } else { "\"" });
+    for c in s.chars() {
+        if c == '*' {
+            pattern.push_str("\\*");
+        } else {
+            pattern.extend(c.escape_debug());
+        }
+    }
+    pattern.push_str(if suffix { "*\"" } else { "\"" });
+    pattern
+}
+
+/// The Cedar literal of the number `digits`, negated if `negative`
+fn number(digits: &str, negative: bool) -> Result<String, String> {
+    let sign = if negative { "-" } else { "" };
+    if digits.contains(['e', 'E']) {
+        return Err(format!("the number {digits} has an exponent"));
+    }
+    if let Some((whole, fraction)) = digits.split_once('.') {
+        return match whole.parse::<u64>() {
+            Ok(n)
+                if n <= DECIMAL_MAX
+                    && (1..=4).contains(&fraction.len())
+                    && fraction.chars().all(|c| c.is_ascii_digit()) =>
+            {
+                Ok(format!("decimal(\"{sign}{n}.{fraction}\")"))
+            }
+            _ => Err(format!(
+                "the number {digits} is not a `decimal`, which has at most four decimal places and at most {DECIMAL_MAX} before them"
+            )),
+        };
+    }
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("the number {digits} is not an integer"));
+    }
+    if format!("{sign}{digits}").parse::<i64>().is_ok() {
+        return Ok(format!("{sign}{digits}"));
+    }
+    let digits = digits.trim_start_matches('0');
+    if negative {
+        Err(format!(
+            "the integer -{digits} is smaller than the smallest `Long`"
+        ))
+    } else if digits.len() < U256_MAX.len()
+        || (digits.len() == U256_MAX.len() && digits <= U256_MAX)
+    {
+        Ok(format!("u256(\"{digits}\")"))
+    } else {
+        Err(format!(
+            "the integer {digits} is larger than the largest `u256`"
+        ))
+    }
+}
+
+/// Converts a statement of a module, given as its tokens
+struct Parser<'a> {
+    tokens: &'a [Lexed],
+    pos: usize,
+    /// The `has` tests of the attributes referred to by the conjunct being
+    /// converted, which guard it under `not`
+    guards: Vec<String>,
+    /// The attributes of `input` which became attributes of `context`
+    moved: Vec<String>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(tokens: &'a [Lexed]) -> Self {
+        Self {
+            tokens,
+            pos: 0,
+            guards: Vec::new(),
+            moved: Vec::new(),
+        }
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos).map(|lexed| &lexed.token)
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.peek();
+        self.pos += 1;
+        token
+    }
+
+    fn eat_punct(&mut self, punct: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Punct(p)) if *p == punct);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_ident(&mut self, name: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Ident(ident)) if ident == name);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_newline(&mut self) -> bool {
+        let found = self.peek() == Some(&Token::Newline);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.eat_newline() {}
+    }
+
+    fn expect_punct(&mut self, punct: &str) -> Result<(), String> {
+        if self.eat_punct(punct) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn ident(&mut self) -> Result<&'a str, String> {
+        match self.peek() {
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// Why the next token cannot be converted
+    fn unexpected(&self) -> String {
+        match self.peek() {
+            Some(Token::Invalid(message)) => format!("{message} is not valid Rego"),
+            Some(token) => format!("the unexpected `{token}` is not converted"),
+            None => "the rule ends unexpectedly".to_string(),
+        }
+    }
+
+    /// The rule of the statement, or none if it is one which needs no policy
+    fn statement(&mut self) -> Result<Option<Rule>, String> {
+        if self.eat_ident("package") {
+            return Ok(None);
+        }
+        if self.eat_ident("import") {
+            let mut path = Vec::new();
+            while let Some(token) = self.next() {
+                path.push(token.to_string());
+            }
+            let path = path.concat();
+            return if path == "rego.v1" || path.starts_with("future.keywords") {
+                Ok(None)
+            } else {
+                Err(format!("`import {path}` has no equivalent in Cedar"))
+            };
+        }
+        if self.eat_ident("default") {
+            let name = self.ident()?;
+            if !(self.eat_punct("=") || self.eat_punct(":=")) {
+                return Err(self.unexpected());
+            }
+            return match (name, self.peek()) {
+                ("allow" | "deny", Some(Token::Ident(value))) if value == "false" => Ok(None),
+                ("allow" | "deny", _) => Err(format!(
+                    "`default {name}` other than `false` has no equivalent, as Cedar denies requests no policy permits"
+                )),
+                _ => Err(format!(
+                    "only `allow` and `deny` rules are converted, not `{name}`"
+                )),
+            };
+        }
+        self.rule().map(Some)
+    }
+
+    fn rule(&mut self) -> Result<Rule, String> {
+        let name = self.ident()?;
+        let forbid = match name {
+            "allow" => false,
+            "deny" => true,
+            _ => {
+                return Err(format!(
+                    "only `allow` and `deny` rules are converted, not `{name}`"
+                ))
+            }
+        };
+        // the message of `deny[msg]` or `deny contains msg`, as a variable or
+        // a string
+        let message = if self.eat_punct("[") {
+            let message = self.next().cloned();
+            self.expect_punct("]")?;
+            message
+        } else if self.eat_ident("contains") {
+            self.next().cloned()
+        } else {
+            None
+        };
+        let mut reason = match &message {
+            None => None,
+            Some(Token::Str(s)) if forbid => Some(s.clone()),
+            Some(Token::Ident(_)) if forbid => None,
+            Some(_) if forbid => {
+                return Err("only `deny` messages which are strings are converted".to_string())
+            }
+            Some(_) => {
+                return Err("`allow` rules which are sets have no equivalent in Cedar".to_string())
+            }
+        };
+        if (self.eat_punct("=") || self.eat_punct(":=")) && !self.eat_ident("true") {
+            return Err(format!(
+                "`{name}` rules with a value other than `true` have no equivalent in Cedar"
+            ));
+        }
+        let has_if = self.eat_ident("if");
+        let mut action = None;
+        let mut conditions = Vec::new();
+        let mut add = |conjunct| match conjunct {
+            Conjunct::Action(constraint) if action.is_none() => action = Some(constraint),
+            Conjunct::Action(constraint) | Conjunct::Condition(constraint) => {
+                conditions.push(constraint);
+            }
+        };
+        if self.eat_punct("{") {
+            loop {
+                while self.eat_punct(";") || self.eat_newline() {}
+                if self.eat_punct("}") {
+                    break;
+                }
+                if let Some(var) = self.assigned() {
+                    match &message {
+                        Some(Token::Ident(message)) if message == var => match self.next() {
+                            Some(Token::Str(s)) => reason = Some(s.clone()),
+                            _ => {
+                                return Err("only `deny` messages which are strings are converted"
+                                    .to_string())
+                            }
+                        },
+                        _ => {
+                            return Err(format!("the variable `{var}` has no equivalent in Cedar"))
+                        }
+                    }
+                } else {
+                    add(self.conjunct()?);
+                }
+                if !matches!(self.peek(), Some(Token::Newline | Token::Punct(";" | "}"))) {
+                    return Err(self.unexpected());
+                }
+            }
+            if self.eat_ident("else") {
+                return Err("`else` has no equivalent in Cedar".to_string());
+            }
+        } else if has_if && self.peek().is_some() {
+            add(self.conjunct()?);
+        }
+        if let Some(Token::Ident(var)) = &message {
+            if reason.is_none() {
+                return Err(format!(
+                    "the message `{var}` of the `deny` rule is never given"
+                ));
+            }
+        }
+        if self.peek().is_some() {
+            return Err(self.unexpected());
+        }
+        Ok(Rule {
+            forbid,
+            reason,
+            action,
+            conditions,
+        })
+    }
+
+    /// The variable the next conjunct assigns to, like `msg` in
+    /// `msg := "..."`, skipping past the assignment operator
+    fn assigned(&mut self) -> Option<&'a str> {
+        match (
+            self.peek(),
+            self.tokens.get(self.pos + 1).map(|lexed| &lexed.token),
+        ) {
+            (Some(Token::Ident(var)), Some(Token::Punct(":="))) => {
+                self.pos += 2;
+                Some(var)
+            }
+            (Some(Token::Ident(var)), Some(Token::Punct("="))) if var != "input" => {
+                self.pos += 2;
+                Some(var)
+            }
+            _ => None,
+        }
+    }
+
+    fn conjunct(&mut self) -> Result<Conjunct, String> {
+        self.guards.clear();
+        if let Some(Token::Ident(keyword)) = self.peek() {
+            if matches!(keyword.as_str(), "some" | "every") {
+                return Err(format!("`{keyword}` has no equivalent in Cedar"));
+            }
+        }
+        if self.eat_ident("not") {
+            let constraint = match self.comparison()? {
+                Conjunct::Action(constraint) | Conjunct::Condition(constraint) => constraint,
+            };
+            let mut guards = std::mem::take(&mut self.guards);
+            guards.dedup();
+            guards.push(constraint);
+            return Ok(Conjunct::Condition(format!("!({})", guards.join(" && "))));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Conjunct, String> {
+        let left = self.sum()?;
+        let op = match self.peek() {
+            Some(Token::Punct(op @ ("==" | "=" | "!=" | "<" | "<=" | ">" | ">="))) => *op,
+            Some(Token::Ident(op)) if op == "in" => "in",
+            Some(Token::Ident(op)) if op == "with" => {
+                return Err("`with` has no equivalent in Cedar".to_string())
+            }
+            Some(Token::Punct(":=")) => {
+                return Err("variables have no equivalent in Cedar".to_string())
+            }
+            _ => return left.condition().map(Conjunct::Condition),
+        };
+        self.pos += 1;
+        let right = self.sum()?;
+        let action = |s: &str| format!("Action::\"{}\"", s.escape_debug());
+        Ok(match (op, left, right) {
+            ("==" | "=", Term::Action, Term::Str(s)) | ("==" | "=", Term::Str(s), Term::Action) => {
+                Conjunct::Action(format!("action == {}", action(&s)))
+            }
+            ("!=", Term::Action, Term::Str(s)) | ("!=", Term::Str(s), Term::Action) => {
+                Conjunct::Condition(format!("action != {}", action(&s)))
+            }
+            ("in", Term::Action, Term::Set(elements)) => {
+                let names = elements
+                    .into_iter()
+                    .map(|element| match element {
+                        Term::Str(s) => Ok(action(&s)),
+                        _ => Err(
+                            "`input.action` is only converted when compared with strings"
+                                .to_string(),
+                        ),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Conjunct::Action(format!("action in [{}]", names.join(", ")))
+            }
+            ("==" | "=", Term::Elements(set), value) | ("==" | "=", value, Term::Elements(set)) => {
+                Conjunct::Condition(format!("{set}.contains({})", value.operand()?))
+            }
+            ("in", value, set) => {
+                Conjunct::Condition(format!("{}.contains({})", set.operand()?, value.operand()?))
+            }
+            (op, left, right) => Conjunct::Condition(format!(
+                "{} {} {}",
+                left.operand()?,
+                if op == "=" { "==" } else { op },
+                right.operand()?
+            )),
+        })
+    }
+
+    fn sum(&mut self) -> Result<Term, String> {
+        let mut sum = self.product()?;
+        loop {
+            let op = if self.eat_punct("+") {
+                "+"
+            } else if self.eat_punct("-") {
+                "-"
+            } else {
+                return Ok(sum);
+            };
+            let right = self.product()?;
+            sum = Term::Expr(format!("{} {op} {}", sum.operand()?, right.operand()?));
+        }
+    }
+
+    fn product(&mut self) -> Result<Term, String> {
+        let mut product = self.unary()?;
+        loop {
+            if self.eat_punct("*") {
+                let right = self.unary()?;
+                product = Term::Expr(format!("{} * {}", product.operand()?, right.operand()?));
+            } else if matches!(self.peek(), Some(Token::Punct("/" | "%"))) {
+                return Err("division has no equivalent in Cedar".to_string());
+            } else {
+                return Ok(product);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Term, String> {
+        if !self.eat_punct("-") {
+            return self.primary();
+        }
+        match self.peek() {
+            Some(Token::Num(digits)) => {
+                self.pos += 1;
+                number(digits, true).map(Term::Expr)
+            }
+            _ => Ok(Term::Expr(format!("-({})", self.unary()?.operand()?))),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Term, String> {
+        let Some(token) = self.peek() else {
+            return Err(self.unexpected());
+        };
+        match token {
+            Token::Str(s) => {
+                self.pos += 1;
+                Ok(Term::Str(s.clone()))
+            }
+            Token::Num(digits) => {
+                self.pos += 1;
+                number(digits, false).map(Term::Expr)
+            }
+            Token::Punct("(") => {
+                self.pos += 1;
+                self.skip_newlines();
+                let term = self.sum()?.operand()?;
+                self.skip_newlines();
+                self.expect_punct(")")?;
+                Ok(Term::Expr(format!("({term})")))
+            }
+            Token::Punct(open @ ("[" | "{")) => {
+                let close = if *open == "[" { "]" } else { "}" };
+                self.pos += 1;
+                let mut elements = Vec::new();
+                loop {
+                    self.skip_newlines();
+                    if self.eat_punct(close) {
+                        break;
+                    }
+                    elements.push(self.sum()?);
+                    self.skip_newlines();
+                    if self.eat_punct(":") {
+                        return Err("objects have no equivalent in this conversion".to_string());
+                    }
+                    if !self.eat_punct(",") {
+                        self.skip_newlines();
+                        self.expect_punct(close)?;
+                        break;
+                    }
+                }
+                Ok(Term::Set(elements))
+            }
+            Token::Ident(name) => match name.as_str() {
+                "true" | "false" => {
+                    self.pos += 1;
+                    Ok(Term::Expr(name.clone()))
+                }
+                "null" => Err("`null` has no equivalent in Cedar".to_string()),
+                "input" => {
+                    self.pos += 1;
+                    self.reference()
+                }
+                "data" => Err("references to `data` have no equivalent in Cedar".to_string()),
+                _ => self.call(),
+            },
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// The rest of a reference to `input`, whose `input` was just read
+    fn reference(&mut self) -> Result<Term, String> {
+        let mut expr: Option<String> = None;
+        loop {
+            let attr = if self.eat_punct(".") {
+                self.ident()?.to_string()
+            } else if self.eat_punct("[") {
+                let attr = match self.next() {
+                    Some(Token::Str(s)) => s.clone(),
+                    Some(Token::Ident(var)) if var == "_" => {
+                        self.expect_punct("]")?;
+                        let Some(set) = expr else {
+                            return Err("iteration over `input` has no equivalent".to_string());
+                        };
+                        if matches!(self.peek(), Some(Token::Punct("." | "["))) {
+                            return Err(
+                                "iteration with `[_]` is only converted at the end of a reference"
+                                    .to_string(),
+                            );
+                        }
+                        return Ok(Term::Elements(set));
+                    }
+                    Some(Token::Num(_)) => {
+                        return Err("indexing into arrays has no equivalent in Cedar".to_string())
+                    }
+                    Some(Token::Ident(var)) => {
+                        return Err(format!("the variable `{var}` has no equivalent in Cedar"))
+                    }
+                    _ => return Err(self.unexpected()),
+                };
+                self.expect_punct("]")?;
+                attr
+            } else {
+                break;
+            };
+            expr = Some(match expr {
+                None => match attr.as_str() {
+                    "action" if !matches!(self.peek(), Some(Token::Punct("." | "["))) => {
+                        return Ok(Term::Action)
+                    }
+                    "principal" | "resource" | "context" | "action" => attr,
+                    _ => {
+                        if !self.moved.contains(&attr) {
+                            self.moved.push(attr.clone());
+                        }
+                        self.attribute("context", &attr)
+                    }
+                },
+                Some(object) => self.attribute(&object, &attr),
+            });
+        }
+        expr.map(Term::Expr)
+            .ok_or_else(|| "`input` as a whole has no equivalent in Cedar".to_string())
+    }
+
+    /// The attribute `attr` of `object`, whose `has` test is added to the
+    /// guards
+    fn attribute(&mut self, object: &str, attr: &str) -> String {
+        if is_cedar_ident(attr) {
+            self.guards.push(format!("{object} has {attr}"));
+            format!("{object}.{attr}")
+        } else {
+            let attr = attr.escape_debug();
+            self.guards.push(format!("{object} has \"{attr}\""));
+            format!("{object}[\"{attr}\"]")
+        }
+    }
+
+    /// A call of a built-in function, whose name is next
+    fn call(&mut self) -> Result<Term, String> {
+        let mut name = self.ident()?.to_string();
+        while self.eat_punct(".") {
+            name = format!("{name}.{}", self.ident()?);
+        }
+        if !self.eat_punct("(") {
+            return Err(format!(
+                "the variable or rule `{name}` has no equivalent in Cedar"
+            ));
+        }
+        let mut args = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.eat_punct(")") {
+                break;
+            }
+            args.push(self.sum()?);
+            self.skip_newlines();
+            if !self.eat_punct(",") {
+                self.expect_punct(")")?;
+                break;
+            }
+        }
+        let (prefix, suffix) = match name.as_str() {
+            "startswith" => (false, true),
+            "endswith" => (true, false),
+            "contains" => (true, true),
+            _ => {
+                return Err(format!(
+                    "the built-in function `{name}` has no equivalent in Cedar"
+                ))
+            }
+        };
+        let mut args = args.into_iter();
+        match (args.next(), args.next(), args.next()) {
+            (Some(s), Some(Term::Str(pattern)), None) => Ok(Term::Like(format!(
+                "{} like {}",
+                s.operand()?,
+                like_pattern(&pattern, prefix, suffix)
+            ))),
+            _ => Err(format!(
+                "`{name}` is only converted with two arguments, the second a string literal"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Decision, Entities, EntityUid, Request};
+    use std::str::FromStr;
+
+    fn converted(src: &str) -> String {
+        let imported = import_rego(src);
+        assert_eq!(imported.issues, []);
+        imported.text
+    }
+
+    #[test]
+    fn converts_allow_rules() {
+        assert_eq!(
+            converted(
+                r#"
+package transfers
+
+import rego.v1
+
+default allow := false
+
+# treasurers may move funds
+allow if {
+    input.action == "transfer"
+    input.principal.role == "treasurer"
+    input.context.amount <= 1000
+    not input.principal.suspended
+}
+
+allow { input.principal.roles[_] == "admin"; startswith(input.resource.name, "vault*") }
+"#
+            ),
+            r#"@id("allow0")
+permit(principal, action == Action::"transfer", resource)
+when {
+  principal.role == "treasurer" &&
+  context.amount <= 1000 &&
+  !(principal has suspended && principal.suspended)
+};
+
+@id("allow1")
+permit(principal, action, resource)
+when {
+  principal.roles.contains("admin") &&
+  resource.name like "vault\**"
+};"#
+        );
+    }
+
+    #[test]
+    fn converts_deny_rules_with_messages() {
+        assert_eq!(
+            converted(
+                r#"
+deny contains "sanctioned" if input.context.recipient in {"0xabc", "0xdef"}
+
+deny[msg] {
+    input.action in ["withdraw", "transfer"]
+    input.context.value > 100000000000000000000
+    msg := "too large"
+}
+"#
+            ),
+            r#"@id("deny0")
+@reason("sanctioned")
+forbid(principal, action, resource)
+when {
+  ["0xabc", "0xdef"].contains(context.recipient)
+};
+
+@id("deny1")
+@reason("too large")
+forbid(principal, action in [Action::"withdraw", Action::"transfer"], resource)
+when {
+  context.value > u256("100000000000000000000")
+};"#
+        );
+    }
+
+    #[test]
+    fn reports_moved_attributes() {
+        let imported = import_rego("allow if { input.amount < 1.5 }");
+        assert_eq!(
+            imported.text,
+            "@id(\"allow0\")\npermit(principal, action, resource)\nwhen {\n  context.amount < decimal(\"1.5\")\n};"
+        );
+        assert_eq!(
+            imported.issues,
+            [ImportIssue::changed(
+                1,
+                "`input.amount` became an attribute of `context`"
+            )]
+        );
+    }
+
+    #[test]
+    fn leaves_out_unconvertible_rules() {
+        let imported = import_rego(
+            r#"
+package p
+import data.lists
+
+default allow = true
+
+is_admin { input.principal.admin }
+
+allow { is_admin }
+allow { some x in input.principal.roles; x == "a" }
+allow { count(input.principal.roles) > 1 }
+allow { input.principal.roles[0] == "a" }
+allow { data.lists.admins[_] == input.principal.name }
+allow { input.a == "b" } else = false { true }
+deny[msg] { input.x; msg := sprintf("%v", [input.x]) }
+allow { input.principal.verified }
+"#,
+        );
+        let dropped: Vec<_> = imported
+            .issues
+            .iter()
+            .filter(|issue| issue.dropped)
+            .map(|issue| issue.line)
+            .collect();
+        assert_eq!(dropped, [3, 5, 7, 9, 10, 11, 12, 13, 14, 15]);
+        assert_eq!(imported.policies.policies().count(), 1);
+        assert!(imported
+            .policies
+            .policy(&crate::PolicyId::from_str("allow0").expect("valid id"))
+            .is_some());
+    }
+
+    #[test]
+    fn imported_policies_authorize() {
+        let imported = import_rego(
+            r#"
+allow if {
+    input.action == "transfer"
+    input.principal.limit >= input.context.amount
+}
+deny if input.principal.blocked
+"#,
+        );
+        assert_eq!(imported.issues, []);
+        let entities = Entities::from_json_str(
+            r#"[
+                { "uid": { "type": "User", "id": "alice" }, "attrs": { "limit": 10, "blocked": false }, "parents": [] },
+                { "uid": { "type": "User", "id": "bob" }, "attrs": { "limit": 10, "blocked": true }, "parents": [] }
+            ]"#,
+            None,
+        )
+        .expect("entities parse");
+        let decision = |user: &str, action: &str, amount: i64| {
+            let request = Request::new(
+                EntityUid::from_str(&format!("User::\"{user}\"")).ok(),
+                EntityUid::from_str(&format!("Action::\"{action}\"")).ok(),
+                None,
+                Context::from_json_value(serde_json::json!({ "amount": amount }), None)
+                    .expect("context parses"),
+            );
+            Authorizer::new()
+                .is_authorized(&request, &imported.policies, &entities)
+                .decision()
+        };
+        assert_eq!(decision("alice", "transfer", 5), Decision::Allow);
+        assert_eq!(decision("alice", "transfer", 50), Decision::Deny);
+        assert_eq!(decision("alice", "approve", 5), Decision::Deny);
+        assert_eq!(decision("bob", "transfer", 5), Decision::Deny);
+    }
+}
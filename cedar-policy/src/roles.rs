@@ -0,0 +1,405 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Role grants on top of template links, for RBAC-style admin APIs.
+//!
+//! A role is a template with the [`ROLE_ANNOTATION`] annotation naming it and
+//! the slots `?principal` and `?resource`, like the one [`define_role`] adds:
+//! ```cedar
+//! @role("viewer")
+//! permit(principal in ?principal, action in [Action::"view"], resource in ?resource);
+//! ```
+//! Granting a role on a resource to a principal, with [`grant`], links the
+//! template of the role, as a policy whose id is given by [`grant_id`], so
+//! that granting twice fails and [`revoke`] finds the link again. [`grants`],
+//! [`grants_for`] and [`grants_on`] list the grants of a policy set, from its
+//! links, so there is no bookkeeping to keep in step with them.
+//!
+//! ```
+//! # use cedar_policy::{roles, EntityUid, PolicySet};
+//! let mut policies = PolicySet::new();
+//! let view: EntityUid = r#"Action::"view""#.parse().unwrap();
+//! roles::define_role(&mut policies, "viewer", [view]).unwrap();
+//!
+//! let alice: EntityUid = r#"User::"alice""#.parse().unwrap();
+//! let vault: EntityUid = r#"Vault::"main""#.parse().unwrap();
+//! roles::grant(&mut policies, "viewer", &alice, &vault).unwrap();
+//! let grants = roles::grants_for(&policies, &alice);
+//! assert_eq!(grants.len(), 1);
+//! assert_eq!(grants[0].role, "viewer");
+//! assert_eq!(grants[0].resource, vault);
+//!
+//! roles::revoke(&mut policies, "viewer", &alice, &vault).unwrap();
+//! assert!(roles::grants_for(&policies, &alice).is_empty());
+//! ```
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::{
+    EntityUid, ParseErrors, Policy, PolicyId, PolicySet, PolicySetError, SlotId, Template,
+};
+
+/// The annotation naming the role of a template
+pub const ROLE_ANNOTATION: &str = "role";
+
+/// Errors defining, granting or revoking roles
+#[derive(Debug, Error)]
+pub enum RoleError {
+    /// No template of the policy set is annotated with the role
+    #[error("no template defines the role `{0}`")]
+    UnknownRole(String),
+    /// A template of the policy set already defines the role
+    #[error("the role `{0}` is already defined")]
+    RoleExists(String),
+    /// The role is already granted on the resource to the principal
+    #[error("`{0}` is already granted")]
+    AlreadyGranted(PolicyId),
+    /// The role is not granted on the resource to the principal
+    #[error("`{0}` is not granted")]
+    NotGranted(PolicyId),
+    /// The template of a role does not parse
+    #[error(transparent)]
+    Parse(#[from] ParseErrors),
+    /// The policy set rejected a template or a link
+    #[error(transparent)]
+    PolicySet(#[from] PolicySetError),
+}
+
+/// A role granted on a resource to a principal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grant {
+    /// The role
+    pub role: String,
+    /// The principal, or group of principals, the role is granted to
+    pub principal: EntityUid,
+    /// The resource, or group of resources, the role is granted on
+    pub resource: EntityUid,
+    /// The id of the template-linked policy of the grant
+    pub policy_id: PolicyId,
+}
+
+/// The id of the template of the role `role` added by [`define_role`]
+pub fn role_template_id(role: &str) -> PolicyId {
+    policy_id(&format!("role:{role}"))
+}
+
+/// The id of the template-linked policy granting `role` on `resource` to
+/// `principal`
+pub fn grant_id(role: &str, principal: &EntityUid, resource: &EntityUid) -> PolicyId {
+    policy_id(&format!("grant:{role}:{principal}:{resource}"))
+}
+
+/// Add to `policies` the standard template of the role `role`, and return
+/// its id
+///
+/// The template permits `actions`, or every action if there are none, to the
+/// principals in `?principal` on the resources in `?resource`.
+///
+/// # Errors
+///
+/// If a template of `policies` already defines the role, or the id of the
+/// template is taken.
+pub fn define_role(
+    policies: &mut PolicySet,
+    role: &str,
+    actions: impl IntoIterator<Item = EntityUid>,
+) -> Result<PolicyId, RoleError> {
+    if role_template(policies, role).is_some() {
+        return Err(RoleError::RoleExists(role.to_string()));
+    }
+    let actions = actions
+        .into_iter()
+        .map(|action| action.to_string())
+        .collect::<Vec<_>>();
+    let action = if actions.is_empty() {
+        "action".to_string()
+    } else {
+        format!("action in [{}]", actions.join(", "))
+    };
+    let id = role_template_id(role);
+    let template = Template::parse(
+        Some(id.to_string()),
+        format!(
+            "@{ROLE_ANNOTATION}(\"{}\")\npermit(principal in ?principal, {action}, resource in ?resource);",
+            role.escape_default()
+        ),
+    )?;
+    policies.add_template(template)?;
+    Ok(id)
+}
+
+/// The names of the roles defined in `policies`, sorted
+pub fn roles(policies: &PolicySet) -> Vec<String> {
+    let mut roles = policies
+        .templates()
+        .filter_map(|template| template.annotation(ROLE_ANNOTATION))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    roles.sort();
+    roles.dedup();
+    roles
+}
+
+/// Grant `role` on `resource` to `principal`, by linking the template of the
+/// role
+///
+/// # Errors
+///
+/// If no template defines the role, the role is already granted, or the
+/// policy set rejects the link, e.g., because the entity types of the slots
+/// of the template were declared and `principal` or `resource` is of another
+/// type.
+pub fn grant(
+    policies: &mut PolicySet,
+    role: &str,
+    principal: &EntityUid,
+    resource: &EntityUid,
+) -> Result<Grant, RoleError> {
+    let template_id =
+        role_template(policies, role).ok_or_else(|| RoleError::UnknownRole(role.to_string()))?;
+    let id = grant_id(role, principal, resource);
+    if policies.policy(&id).is_some() {
+        return Err(RoleError::AlreadyGranted(id));
+    }
+    policies.link(
+        template_id,
+        id.clone(),
+        HashMap::from([
+            (SlotId::principal(), principal.clone()),
+            (SlotId::resource(), resource.clone()),
+        ]),
+    )?;
+    Ok(Grant {
+        role: role.to_string(),
+        principal: principal.clone(),
+        resource: resource.clone(),
+        policy_id: id,
+    })
+}
+
+/// Revoke `role` on `resource` from `principal`, by removing the link made
+/// by [`grant`]
+///
+/// # Errors
+///
+/// If the role is not granted on the resource to the principal.
+pub fn revoke(
+    policies: &mut PolicySet,
+    role: &str,
+    principal: &EntityUid,
+    resource: &EntityUid,
+) -> Result<Grant, RoleError> {
+    let id = grant_id(role, principal, resource);
+    let grant = policies
+        .policy(&id)
+        .and_then(|policy| as_grant(policies, policy))
+        .ok_or_else(|| RoleError::NotGranted(id.clone()))?;
+    policies.unlink(id)?;
+    Ok(grant)
+}
+
+/// The grants of `policies`: its links of templates of roles, whether made
+/// by [`grant`] or not, sorted by role, principal and resource
+pub fn grants(policies: &PolicySet) -> Vec<Grant> {
+    let mut grants = policies
+        .policies()
+        .filter_map(|policy| as_grant(policies, policy))
+        .collect::<Vec<_>>();
+    grants.sort_by(|a, b| {
+        (&a.role, &a.principal, &a.resource).cmp(&(&b.role, &b.principal, &b.resource))
+    });
+    grants
+}
+
+/// The grants of `policies` to `principal`, not counting the grants to the
+/// groups it is in
+pub fn grants_for(policies: &PolicySet, principal: &EntityUid) -> Vec<Grant> {
+    grants(policies)
+        .into_iter()
+        .filter(|grant| grant.principal == *principal)
+        .collect()
+}
+
+/// The grants of `policies` on `resource`, not counting the grants on the
+/// groups it is in
+pub fn grants_on(policies: &PolicySet, resource: &EntityUid) -> Vec<Grant> {
+    grants(policies)
+        .into_iter()
+        .filter(|grant| grant.resource == *resource)
+        .collect()
+}
+
+/// The id of the template defining `role`
+fn role_template(policies: &PolicySet, role: &str) -> Option<PolicyId> {
+    policies
+        .templates()
+        .find(|template| template.annotation(ROLE_ANNOTATION) == Some(role))
+        .map(|template| template.id().clone())
+}
+
+/// The grant `policy` makes, if it links the template of a role
+fn as_grant(policies: &PolicySet, policy: &Policy) -> Option<Grant> {
+    let role = policies
+        .template(policy.template_id()?)?
+        .annotation(ROLE_ANNOTATION)?;
+    let mut links = policy.template_links()?;
+    Some(Grant {
+        role: role.to_string(),
+        principal: links.remove(&SlotId::principal())?,
+        resource: links.remove(&SlotId::resource())?,
+        policy_id: policy.id().clone(),
+    })
+}
+
+/// A policy id, which any string is
+fn policy_id(id: &str) -> PolicyId {
+    // PANIC SAFETY: parsing a `PolicyId` never fails
+    #[allow(clippy::expect_used)]
+    PolicyId::from_str(id).expect("any string is a policy id")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Decision, Entities, Request};
+
+    fn uid(uid: &str) -> EntityUid {
+        uid.parse().expect("valid UID")
+    }
+
+    fn is_allowed(policies: &PolicySet, principal: &str, action: &str, resource: &str) -> bool {
+        let request = Request::new(
+            Some(uid(principal)),
+            Some(uid(action)),
+            Some(uid(resource)),
+            Context::empty(),
+        );
+        Authorizer::new()
+            .is_authorized(&request, policies, &Entities::empty())
+            .decision()
+            == Decision::Allow
+    }
+
+    #[test]
+    fn grants_are_links() {
+        let mut policies = PolicySet::new();
+        define_role(
+            &mut policies,
+            "operator",
+            [uid(r#"Action::"pause""#), uid(r#"Action::"unpause""#)],
+        )
+        .expect("defines");
+        define_role(&mut policies, "owner", []).expect("defines");
+        assert!(matches!(
+            define_role(&mut policies, "owner", []),
+            Err(RoleError::RoleExists(_))
+        ));
+        assert_eq!(roles(&policies), ["operator", "owner"]);
+
+        let (alice, bob) = (uid(r#"User::"alice""#), uid(r#"User::"bob""#));
+        let vault = uid(r#"Vault::"main""#);
+        grant(&mut policies, "operator", &alice, &vault).expect("grants");
+        grant(&mut policies, "owner", &bob, &vault).expect("grants");
+        assert!(matches!(
+            grant(&mut policies, "operator", &alice, &vault),
+            Err(RoleError::AlreadyGranted(_))
+        ));
+        assert!(matches!(
+            grant(&mut policies, "auditor", &alice, &vault),
+            Err(RoleError::UnknownRole(_))
+        ));
+        assert!(is_allowed(
+            &policies,
+            r#"User::"alice""#,
+            r#"Action::"pause""#,
+            r#"Vault::"main""#
+        ));
+        assert!(!is_allowed(
+            &policies,
+            r#"User::"alice""#,
+            r#"Action::"withdraw""#,
+            r#"Vault::"main""#
+        ));
+        assert!(is_allowed(
+            &policies,
+            r#"User::"bob""#,
+            r#"Action::"withdraw""#,
+            r#"Vault::"main""#
+        ));
+
+        let on_vault = grants_on(&policies, &vault);
+        assert_eq!(
+            on_vault.iter().map(|g| g.role.as_str()).collect::<Vec<_>>(),
+            ["operator", "owner"]
+        );
+        assert_eq!(
+            grants_for(&policies, &bob)[0].policy_id,
+            grant_id("owner", &bob, &vault)
+        );
+
+        let revoked = revoke(&mut policies, "operator", &alice, &vault).expect("revokes");
+        assert_eq!(revoked.principal, alice);
+        assert!(grants_for(&policies, &alice).is_empty());
+        assert!(!is_allowed(
+            &policies,
+            r#"User::"alice""#,
+            r#"Action::"pause""#,
+            r#"Vault::"main""#
+        ));
+        assert!(matches!(
+            revoke(&mut policies, "operator", &alice, &vault),
+            Err(RoleError::NotGranted(_))
+        ));
+        // a revoked role can be granted again
+        grant(&mut policies, "operator", &alice, &vault).expect("grants again");
+    }
+
+    #[test]
+    fn roles_can_be_handwritten() {
+        let mut policies: PolicySet = r#"
+            @role("signer")
+            permit(principal == ?principal, action == Action::"sign", resource in ?resource);
+            permit(principal == ?principal, action, resource == ?resource);"#
+            .parse()
+            .expect("parses");
+        let (alice, safe) = (uid(r#"User::"alice""#), uid(r#"Safe::"treasury""#));
+        grant(&mut policies, "signer", &alice, &safe).expect("grants");
+        // links of templates which are not roles are not grants
+        policies
+            .link(
+                PolicyId::from_str("policy1").expect("valid id"),
+                PolicyId::from_str("other").expect("valid id"),
+                HashMap::from([
+                    (SlotId::principal(), alice.clone()),
+                    (SlotId::resource(), safe.clone()),
+                ]),
+            )
+            .expect("links");
+        assert_eq!(
+            grants(&policies),
+            [Grant {
+                role: "signer".to_string(),
+                principal: alice,
+                resource: safe,
+                policy_id: PolicyId::from_str(r#"grant:signer:User::"alice":Safe::"treasury""#)
+                    .expect("valid id"),
+            }]
+        );
+    }
+}
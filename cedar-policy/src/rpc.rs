@@ -0,0 +1,477 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Authorizing JSON-RPC read requests, so that the operator of an RPC gateway
+//! can govern read access, such as which API keys may query which contracts,
+//! with the same policy set as writes.
+//!
+//! An [`RpcMapper`] maps a call of `eth_call`, `eth_getLogs` or
+//! `eth_getStorageAt` into requests in which
+//! * the principal is given by the gateway, such as `ApiKey::"key-1"`,
+//! * the action is the method, like `Action::"eth_call"`,
+//! * the resource is the canonical UID of the contract read (see
+//!   [`EntityUid::contract`]), and
+//! * the context holds the `block` tag or number, and
+//!   * for `eth_call`, the `data` as `bytes`, its `selector`, like
+//!     `"0xa9059cbb"`, if it has one, and the `from` address, if it has one,
+//!   * for `eth_getLogs`, the `topic0`s filtered on, as a set of `bytes`, and
+//!     the `blockRange`, the number of blocks after `fromBlock` up to
+//!     `toBlock`, when both are numbers,
+//!   * for `eth_getStorageAt`, the `slot`, as a `u256`.
+//!
+//! An `eth_getLogs` of several contracts gives a request for each, and one
+//! of any contract a single request with an unspecified resource. A call is
+//! allowed when all of its requests are, which [`RpcMapper::is_authorized`]
+//! decides, along with the calls of a batch.
+//!
+//! ```
+//! # use cedar_policy::{rpc::*, Decision, Entities, EntityUid, PolicySet};
+//! let policies: PolicySet = r#"
+//!     permit(
+//!         principal == ApiKey::"key-1",
+//!         action in [Action::"eth_call", Action::"eth_getLogs"],
+//!         resource == Contract::"eip155:1:0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"
+//!     );"#
+//!     .parse()
+//!     .unwrap();
+//! let mapper = RpcMapper::new(1);
+//! let call = serde_json::json!({
+//!     "jsonrpc": "2.0",
+//!     "id": 1,
+//!     "method": "eth_call",
+//!     "params": [{
+//!         "to": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+//!         "data": "0x70a082310000000000000000000000005aaeb6053f3e94c9b9a09f33669435e7ef1beaed"
+//!     }, "latest"]
+//! });
+//! let key: EntityUid = r#"ApiKey::"key-1""#.parse().unwrap();
+//! let decision = mapper.is_authorized(Some(&key), &call, &policies, &Entities::empty());
+//! assert_eq!(decision.unwrap(), Decision::Allow);
+//! ```
+
+use cedar_policy_core::extensions::u256::parse_u256;
+use ethers::types::{Address, Bytes, H256, U256};
+use ethers::utils::hex;
+use serde::Deserialize;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::{
+    Authorizer, Context, Decision, Entities, EntityUid, PolicySet, Request, RestrictedExpression,
+};
+
+/// Errors mapping a JSON-RPC call into requests
+#[derive(Debug, Error)]
+pub enum RpcError {
+    /// The JSON is not of a JSON-RPC call
+    #[error("invalid JSON-RPC call: {0}")]
+    Json(#[from] serde_json::Error),
+    /// The method is not one of the read methods mapped
+    #[error("the method `{0}` is unsupported")]
+    UnsupportedMethod(String),
+    /// The parameters are invalid for the method
+    #[error("invalid parameters of `{method}`: {reason}")]
+    InvalidParams {
+        /// The method
+        method: String,
+        /// Why they are invalid
+        reason: String,
+    },
+}
+
+/// A JSON-RPC call
+#[derive(Debug, Deserialize)]
+struct RpcCall {
+    method: String,
+    #[serde(default)]
+    params: Vec<Value>,
+}
+
+/// The transaction of an `eth_call`
+#[derive(Debug, Deserialize)]
+struct CallParams {
+    to: Option<Address>,
+    from: Option<Address>,
+    #[serde(alias = "input")]
+    data: Option<Bytes>,
+}
+
+/// One value, or an array of them, as in the filters of `eth_getLogs`
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    fn into_vec(self) -> Vec<T> {
+        match self {
+            Self::One(value) => vec![value],
+            Self::Many(values) => values,
+        }
+    }
+}
+
+/// The filter of an `eth_getLogs`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LogFilter {
+    address: Option<OneOrMany<Address>>,
+    #[serde(default)]
+    topics: Vec<Option<OneOrMany<Option<H256>>>>,
+    from_block: Option<String>,
+    to_block: Option<String>,
+    block_hash: Option<H256>,
+}
+
+/// The number of the block `tag`, if it is a number rather than a tag like
+/// `latest`
+fn block_number(tag: &str) -> Option<u64> {
+    u64::from_str_radix(tag.strip_prefix("0x")?, 16).ok()
+}
+
+/// The context attribute for the block tag or number `block`, if there is one
+fn block_attr(block: Option<&Value>) -> Option<(String, RestrictedExpression)> {
+    block
+        .and_then(Value::as_str)
+        .map(|block| ("block".to_string(), block.into()))
+}
+
+/// Maps JSON-RPC read calls into requests
+#[derive(Debug, Clone)]
+pub struct RpcMapper {
+    chain_id: u64,
+}
+
+impl RpcMapper {
+    /// A mapper of calls of the RPC of the chain with id `chain_id`
+    pub fn new(chain_id: u64) -> Self {
+        Self { chain_id }
+    }
+
+    /// The requests of `call`, a JSON-RPC call, by `principal`, all of which
+    /// must be allowed to allow it
+    ///
+    /// # Errors
+    ///
+    /// If `call` is not a JSON-RPC call of `eth_call`, `eth_getLogs` or
+    /// `eth_getStorageAt` with valid parameters.
+    pub fn requests(
+        &self,
+        principal: Option<&EntityUid>,
+        call: &Value,
+    ) -> Result<Vec<Request>, RpcError> {
+        let call = RpcCall::deserialize(call)?;
+        let params = |index: usize| call.params.get(index);
+        let invalid = |reason: &str| RpcError::InvalidParams {
+            method: call.method.clone(),
+            reason: reason.to_string(),
+        };
+        let (resources, context): (Vec<Option<EntityUid>>, Vec<_>) = match call.method.as_str() {
+            "eth_call" => {
+                let tx = CallParams::deserialize(
+                    params(0).ok_or_else(|| invalid("missing the transaction"))?,
+                )
+                .map_err(|e| invalid(&e.to_string()))?;
+                let to = tx.to.ok_or_else(|| invalid("missing `to`"))?;
+                let data = tx.data.unwrap_or_default();
+                let context = [
+                    block_attr(params(1)),
+                    tx.from.map(|from| ("from".to_string(), from.into())),
+                    data.get(..4).map(|selector| {
+                        (
+                            "selector".to_string(),
+                            format!("0x{}", hex::encode(selector)).into(),
+                        )
+                    }),
+                    Some(("data".to_string(), RestrictedExpression::new_bytes(data))),
+                ];
+                (
+                    vec![Some(EntityUid::contract(self.chain_id, to))],
+                    context.into_iter().flatten().collect(),
+                )
+            }
+            "eth_getLogs" => {
+                let filter =
+                    LogFilter::deserialize(params(0).ok_or_else(|| invalid("missing the filter"))?)
+                        .map_err(|e| invalid(&e.to_string()))?;
+                let resources = match filter.address.map(OneOrMany::into_vec) {
+                    Some(addresses) if !addresses.is_empty() => addresses
+                        .into_iter()
+                        .map(|address| Some(EntityUid::contract(self.chain_id, address)))
+                        .collect(),
+                    _ => vec![None],
+                };
+                let topic0 = filter
+                    .topics
+                    .into_iter()
+                    .next()
+                    .flatten()
+                    .map(OneOrMany::into_vec)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .flatten()
+                    .map(RestrictedExpression::from);
+                let block = filter
+                    .block_hash
+                    .map(|hash| format!("{hash:?}"))
+                    .or_else(|| filter.from_block.clone());
+                let block_range = filter
+                    .from_block
+                    .as_deref()
+                    .and_then(block_number)
+                    .zip(filter.to_block.as_deref().and_then(block_number))
+                    .and_then(|(from, to)| to.checked_sub(from))
+                    .and_then(|range| i64::try_from(range).ok());
+                let context = [
+                    block.map(|block| ("block".to_string(), block.into())),
+                    Some(("topic0".to_string(), RestrictedExpression::new_set(topic0))),
+                    block_range.map(|range| ("blockRange".to_string(), range.into())),
+                ];
+                (resources, context.into_iter().flatten().collect())
+            }
+            "eth_getStorageAt" => {
+                let address =
+                    Address::deserialize(params(0).ok_or_else(|| invalid("missing the address"))?)
+                        .map_err(|e| invalid(&e.to_string()))?;
+                let slot = U256::deserialize(params(1).ok_or_else(|| invalid("missing the slot"))?)
+                    .map_err(|e| invalid(&e.to_string()))?;
+                let slot = parse_u256(&slot.to_string())
+                    .map(RestrictedExpression::new_u256)
+                    .ok_or_else(|| invalid("the slot is not a u256"))?;
+                let context = [block_attr(params(2)), Some(("slot".to_string(), slot))];
+                (
+                    vec![Some(EntityUid::contract(self.chain_id, address))],
+                    context.into_iter().flatten().collect(),
+                )
+            }
+            _ => return Err(RpcError::UnsupportedMethod(call.method)),
+        };
+        Ok(resources
+            .into_iter()
+            .map(|resource| {
+                Request::new(
+                    principal.cloned(),
+                    Some(EntityUid::from_known_type("Action", &call.method)),
+                    resource,
+                    Context::from_pairs(context.clone()),
+                )
+            })
+            .collect())
+    }
+
+    /// Whether `principal` may make `call`, a JSON-RPC call or a batch of
+    /// them, against `policies` and `entities`: it is allowed if all of its
+    /// requests are
+    ///
+    /// # Errors
+    ///
+    /// If any call of the batch cannot be mapped into requests.
+    pub fn is_authorized(
+        &self,
+        principal: Option<&EntityUid>,
+        call: &Value,
+        policies: &PolicySet,
+        entities: &Entities,
+    ) -> Result<Decision, RpcError> {
+        let calls = match call {
+            Value::Array(calls) => calls.iter().collect(),
+            call => vec![call],
+        };
+        let authorizer = Authorizer::new();
+        for call in calls {
+            for request in self.requests(principal, call)? {
+                if authorizer
+                    .is_authorized(&request, policies, entities)
+                    .decision()
+                    == Decision::Deny
+                {
+                    return Ok(Decision::Deny);
+                }
+            }
+        }
+        Ok(Decision::Allow)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    const USDC: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+    const WETH: &str = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+    const TRANSFER: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+
+    fn key() -> EntityUid {
+        r#"ApiKey::"key-1""#.parse().expect("valid uid")
+    }
+
+    fn contract(address: &str) -> EntityUid {
+        EntityUid::contract(1, address.parse().expect("valid address"))
+    }
+
+    fn decide(policies: &str, call: &Value) -> Decision {
+        let policies: PolicySet = policies.parse().expect("valid policies");
+        RpcMapper::new(1)
+            .is_authorized(Some(&key()), call, &policies, &Entities::empty())
+            .expect("maps the call")
+    }
+
+    #[test]
+    fn eth_call() {
+        let call = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_call",
+            "params": [{
+                "from": "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed",
+                "to": USDC,
+                "input": "0x70a082310000000000000000000000005aaeb6053f3e94c9b9a09f33669435e7ef1beaed"
+            }, "latest"]
+        });
+        let requests = RpcMapper::new(1)
+            .requests(Some(&key()), &call)
+            .expect("maps the call");
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].principal(), Some(&key()));
+        assert_eq!(
+            requests[0].action(),
+            Some(&EntityUid::from_known_type("Action", "eth_call"))
+        );
+        assert_eq!(requests[0].resource(), Some(&contract(USDC)));
+        // only `balanceOf`
+        let policy = r#"permit(principal, action == Action::"eth_call", resource)
+            when { context.selector == "0x70a08231" && context.block == "latest" };"#;
+        assert_eq!(decide(policy, &call), Decision::Allow);
+        let mut call = call;
+        call["params"][0]["input"] = json!("0x18160ddd");
+        assert_eq!(decide(policy, &call), Decision::Deny);
+    }
+
+    #[test]
+    fn eth_get_logs() {
+        let policy = format!(
+            r#"permit(principal, action == Action::"eth_getLogs", resource)
+            when {{
+                context has blockRange && context.blockRange <= 1000 &&
+                context.topic0.contains(bytes("{TRANSFER}"))
+            }};
+            forbid(principal, action, resource == {})
+            unless {{ principal == ApiKey::"key-2" }};"#,
+            contract(WETH)
+        );
+        let call = |address: Value, to_block: &str| {
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "eth_getLogs",
+                "params": [{
+                    "address": address,
+                    "fromBlock": "0x100",
+                    "toBlock": to_block,
+                    "topics": [[TRANSFER], null]
+                }]
+            })
+        };
+        assert_eq!(
+            decide(&policy, &call(json!(USDC), "0x200")),
+            Decision::Allow
+        );
+        assert_eq!(
+            decide(&policy, &call(json!(USDC), "latest")),
+            Decision::Deny
+        );
+        assert_eq!(
+            decide(&policy, &call(json!(USDC), "0x10000")),
+            Decision::Deny
+        );
+        // every contract must be allowed
+        assert_eq!(
+            decide(&policy, &call(json!([USDC, WETH]), "0x200")),
+            Decision::Deny
+        );
+        let requests = RpcMapper::new(1)
+            .requests(Some(&key()), &call(json!([USDC, WETH]), "0x200"))
+            .expect("maps the call");
+        assert_eq!(requests.len(), 2);
+        // the logs of any contract
+        let requests = RpcMapper::new(1)
+            .requests(Some(&key()), &call(Value::Null, "0x200"))
+            .expect("maps the call");
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].resource(), None);
+    }
+
+    #[test]
+    fn eth_get_storage_at_and_batches() {
+        let policy = r#"permit(principal, action == Action::"eth_getStorageAt", resource)
+            when { context.slot == u256("5") };
+            permit(principal, action == Action::"eth_call", resource);"#;
+        let storage = |slot: &str| {
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "eth_getStorageAt",
+                "params": [USDC, slot, "latest"]
+            })
+        };
+        assert_eq!(decide(policy, &storage("0x5")), Decision::Allow);
+        assert_eq!(decide(policy, &storage("0x6")), Decision::Deny);
+        let eth_call = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "eth_call",
+            "params": [{ "to": USDC }, "latest"]
+        });
+        assert_eq!(
+            decide(policy, &json!([storage("0x5"), eth_call])),
+            Decision::Allow
+        );
+        assert_eq!(
+            decide(policy, &json!([storage("0x6"), eth_call])),
+            Decision::Deny
+        );
+    }
+
+    #[test]
+    fn invalid_calls() {
+        let mapper = RpcMapper::new(1);
+        assert!(matches!(
+            mapper.requests(Some(&key()), &json!({ "method": "eth_sendRawTransaction", "params": ["0x"] })),
+            Err(RpcError::UnsupportedMethod(method)) if method == "eth_sendRawTransaction"
+        ));
+        assert!(matches!(
+            mapper.requests(
+                Some(&key()),
+                &json!({ "method": "eth_call", "params": [{ "data": "0x" }] })
+            ),
+            Err(RpcError::InvalidParams { .. })
+        ));
+        assert!(matches!(
+            mapper.requests(
+                Some(&key()),
+                &json!({ "method": "eth_getStorageAt", "params": ["0x1"] })
+            ),
+            Err(RpcError::InvalidParams { .. })
+        ));
+        assert!(matches!(
+            mapper.requests(Some(&key()), &json!([])),
+            Err(RpcError::Json(_))
+        ));
+    }
+}
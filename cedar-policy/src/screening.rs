@@ -0,0 +1,589 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Screening the entities of a request with external risk and compliance
+//! services, such as address screening and sanctions lists, as part of
+//! deciding it.
+//!
+//! A [`RiskProvider`] screens entities in batches, giving values such as a
+//! `riskScore` or `sanctioned` flag for each. A [`ScreeningAuthorizer`]
+//! screens the principal and resource of a request, and the entities in the
+//! attributes of its context, such as the recipient of a transfer, also when
+//! nested in records and sets. The values are set as attributes or tags of
+//! the entities (see [`Placement`]) for the request only, replacing any of the
+//! same name, without copying the entities.
+//!
+//! What happens when the provider fails, or leaves an entity out of its
+//! result, is set by its [`FailureMode`]:
+//! * [`FailureMode::Closed`], the default, denies the request
+//! * [`FailureMode::Open`] authorizes it without the values of the entities
+//!   which could not be screened, so policies which read them error and are
+//!   skipped
+//!
+//! Either way, [`ScreenedResponse::screening_failure`] tells why.
+//!
+//! ```
+//! # use cedar_policy::{screening::*, Context, Decision, Entities, EntityUid, PolicySet, Request, RestrictedExpression};
+//! # use std::collections::HashMap;
+//! let policies: PolicySet = r#"
+//!     permit(principal, action, resource);
+//!     forbid(principal, action, resource) when { context.to.sanctioned };"#
+//!     .parse()
+//!     .unwrap();
+//! let provider = |uids: &[EntityUid]| -> Result<Screenings, ProviderError> {
+//!     // e.g., call a sanctions screening API with all the addresses at once
+//!     Ok(uids
+//!         .iter()
+//!         .map(|uid| {
+//!             let sanctioned = uid.id().as_ref() == "0xbad";
+//!             let values = HashMap::from([(
+//!                 "sanctioned".to_string(),
+//!                 RestrictedExpression::new_bool(sanctioned),
+//!             )]);
+//!             (uid.clone(), values)
+//!         })
+//!         .collect())
+//! };
+//! let authorizer = ScreeningAuthorizer::new(policies, Entities::empty(), provider);
+//! let transfer = |to: &str| {
+//!     Request::new(
+//!         Some(r#"User::"alice""#.parse().unwrap()),
+//!         Some(r#"Action::"transfer""#.parse().unwrap()),
+//!         Some(r#"Token::"usdc""#.parse().unwrap()),
+//!         Context::from_pairs([(
+//!             "to".to_string(),
+//!             RestrictedExpression::new_entity_uid(format!(r#"User::"{to}""#).parse().unwrap()),
+//!         )]),
+//!     )
+//! };
+//! let decide = |to| authorizer.is_authorized(&transfer(to)).decision();
+//! assert_eq!(decide("0xgood"), Decision::Allow);
+//! assert_eq!(decide("0xbad"), Decision::Deny);
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use cedar_policy_core::ast::{self, Expr, ExprKind, Literal};
+use cedar_policy_core::evaluator::EntityOverlay;
+use ref_cast::RefCast;
+use smol_str::SmolStr;
+
+use crate::{
+    Authorizer, Decision, Entities, EntityTypeName, EntityUid, PolicySet, Request, Response,
+    RestrictedExpression,
+};
+
+/// The error of a [`RiskProvider`]
+pub type ProviderError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The values screening gave for each entity, by name
+pub type Screenings = HashMap<EntityUid, HashMap<String, RestrictedExpression>>;
+
+/// An external risk or compliance service which screens entities, such as
+/// accounts against a sanctions list
+///
+/// Functions from a slice of [`EntityUid`]s to their [`Screenings`] are
+/// providers.
+pub trait RiskProvider: Send + Sync {
+    /// Screen `uids`, giving the values to set on each of them. An entity
+    /// screened with nothing to set is given no values; entities missing
+    /// from the result count as not screened.
+    ///
+    /// # Errors
+    ///
+    /// If the entities cannot be screened.
+    fn screen(&self, uids: &[EntityUid]) -> Result<Screenings, ProviderError>;
+}
+
+impl<F> RiskProvider for F
+where
+    F: Fn(&[EntityUid]) -> Result<Screenings, ProviderError> + Send + Sync,
+{
+    fn screen(&self, uids: &[EntityUid]) -> Result<Screenings, ProviderError> {
+        self(uids)
+    }
+}
+
+/// Where the values of screening are set on entities
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Placement {
+    /// As attributes, read like `principal.riskScore`
+    #[default]
+    Attributes,
+    /// As tags, read like `principal.getTag("riskScore")`
+    Tags,
+}
+
+/// What happens to a request when its entities cannot be screened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FailureMode {
+    /// Deny the request
+    #[default]
+    Closed,
+    /// Authorize the request without the values of the entities which were
+    /// not screened
+    Open,
+}
+
+/// The response to a request, and why its entities could not be screened, if
+/// they could not
+#[derive(Debug)]
+pub struct ScreenedResponse {
+    response: Response,
+    screening_failure: Option<String>,
+}
+
+impl ScreenedResponse {
+    /// The response to the request
+    pub fn response(&self) -> &Response {
+        &self.response
+    }
+
+    /// The decision on the request
+    pub fn decision(&self) -> Decision {
+        self.response.decision()
+    }
+
+    /// Why the entities of the request could not be screened, if they could
+    /// not
+    pub fn screening_failure(&self) -> Option<&str> {
+        self.screening_failure.as_deref()
+    }
+}
+
+/// Authorizes requests against a policy set and entities, with the values a
+/// [`RiskProvider`] gives for the entities of each request
+#[derive(Debug)]
+pub struct ScreeningAuthorizer<P> {
+    authorizer: Authorizer,
+    policies: PolicySet,
+    entities: Entities,
+    provider: P,
+    placement: Placement,
+    failure_mode: FailureMode,
+    batch_size: usize,
+    entity_types: Option<HashSet<EntityTypeName>>,
+}
+
+impl<P: RiskProvider> ScreeningAuthorizer<P> {
+    /// Authorize requests against `policies` and `entities`, screening their
+    /// entities with `provider`
+    pub fn new(policies: PolicySet, entities: Entities, provider: P) -> Self {
+        Self {
+            authorizer: Authorizer::new(),
+            policies,
+            entities,
+            provider,
+            placement: Placement::default(),
+            failure_mode: FailureMode::default(),
+            batch_size: 100,
+            entity_types: None,
+        }
+    }
+
+    /// Set the values of screening on entities as `placement`, rather than as
+    /// attributes
+    #[must_use]
+    pub fn placement(mut self, placement: Placement) -> Self {
+        self.placement = placement;
+        self
+    }
+
+    /// Handle failures of the provider with `failure_mode`, rather than by
+    /// denying the request
+    #[must_use]
+    pub fn failure_mode(mut self, failure_mode: FailureMode) -> Self {
+        self.failure_mode = failure_mode;
+        self
+    }
+
+    /// Screen at most `batch_size` entities in each call to the provider,
+    /// rather than 100. A `batch_size` of 0 is taken as 1.
+    #[must_use]
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Screen only the entities of these types, such as `Account`, rather
+    /// than those of any type
+    #[must_use]
+    pub fn entity_types(mut self, entity_types: impl IntoIterator<Item = EntityTypeName>) -> Self {
+        self.entity_types = Some(entity_types.into_iter().collect());
+        self
+    }
+
+    /// The policies which decide the requests once their entities are
+    /// screened
+    pub fn policies(&self) -> &PolicySet {
+        &self.policies
+    }
+
+    /// The entities requests are authorized with, before screening
+    pub fn entities(&self) -> &Entities {
+        &self.entities
+    }
+
+    /// The provider entities are screened with
+    pub fn provider(&self) -> &P {
+        &self.provider
+    }
+
+    /// Screen the entities of `request` and authorize it with their values
+    pub fn is_authorized(&self, request: &Request) -> ScreenedResponse {
+        self.is_authorized_batch(std::slice::from_ref(request))
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| ScreenedResponse {
+                response: Response::new(Decision::Deny, HashSet::new(), Vec::new()),
+                screening_failure: None,
+            })
+    }
+
+    /// Screen the entities of all of `requests` together, each only once,
+    /// and authorize each of them with their values
+    pub fn is_authorized_batch(&self, requests: &[Request]) -> Vec<ScreenedResponse> {
+        let subjects: Vec<Vec<EntityUid>> = requests.iter().map(|r| self.subjects(r)).collect();
+        let mut uids: Vec<EntityUid> = Vec::new();
+        let mut seen = HashSet::new();
+        for uid in subjects.iter().flatten() {
+            if seen.insert(uid) {
+                uids.push(uid.clone());
+            }
+        }
+
+        let mut screenings = Screenings::new();
+        let mut failures: HashMap<&EntityUid, String> = HashMap::new();
+        for batch in uids.chunks(self.batch_size) {
+            match self.provider.screen(batch) {
+                Ok(mut screened) => {
+                    for uid in batch {
+                        match screened.remove(uid) {
+                            Some(values) => {
+                                screenings.insert(uid.clone(), values);
+                            }
+                            None => {
+                                failures.insert(uid, format!("the provider left out `{uid}`"));
+                            }
+                        }
+                    }
+                }
+                Err(error) => {
+                    let error = error.to_string();
+                    failures.extend(batch.iter().map(|uid| (uid, error.clone())));
+                }
+            }
+        }
+        let overlay = self.overlay(screenings);
+
+        subjects
+            .iter()
+            .zip(requests)
+            .map(|(subjects, request)| {
+                let screening_failure = subjects
+                    .iter()
+                    .find_map(|uid| failures.get(uid))
+                    .map(|error| format!("failed to screen the entities of the request: {error}"));
+                let response = match (&screening_failure, self.failure_mode) {
+                    (Some(_), FailureMode::Closed) => {
+                        Response::new(Decision::Deny, HashSet::new(), Vec::new())
+                    }
+                    _ => self.authorizer.is_authorized_overlaid(
+                        request,
+                        &self.policies,
+                        &self.entities,
+                        &overlay,
+                    ),
+                };
+                ScreenedResponse {
+                    response,
+                    screening_failure,
+                }
+            })
+            .collect()
+    }
+
+    /// The entities of `request` to screen: its principal and resource, and
+    /// the entities in the attributes of its context, at any depth
+    fn subjects(&self, request: &Request) -> Vec<EntityUid> {
+        let mut subjects: Vec<EntityUid> = request
+            .principal()
+            .into_iter()
+            .chain(request.resource())
+            .cloned()
+            .collect();
+        for (_, value) in request.0.context().into_iter().flat_map(ast::Context::iter) {
+            entity_uids(&value, &mut subjects);
+        }
+        subjects.retain(|uid| {
+            self.entity_types
+                .as_ref()
+                .is_none_or(|types| types.contains(uid.type_name()))
+        });
+        subjects
+    }
+
+    /// The values of `screenings`, set as attributes or tags
+    fn overlay(&self, screenings: Screenings) -> EntityOverlay {
+        let mut overlay = EntityOverlay::new();
+        for (uid, values) in screenings {
+            for (name, value) in values {
+                let name = SmolStr::new(name);
+                match self.placement {
+                    Placement::Attributes => overlay.set_attr(uid.0.clone(), name, value.0),
+                    Placement::Tags => overlay.set_tag(uid.0.clone(), name, value.0),
+                }
+            }
+        }
+        overlay
+    }
+}
+
+/// Add the entities in `expr`, which may be nested in records and sets, to
+/// `uids`
+fn entity_uids(expr: &Expr, uids: &mut Vec<EntityUid>) {
+    match expr.expr_kind() {
+        ExprKind::Lit(Literal::EntityUID(uid)) => uids.push(EntityUid::ref_cast(uid).clone()),
+        ExprKind::Set(elements) => {
+            for element in elements.iter() {
+                entity_uids(element, uids);
+            }
+        }
+        ExprKind::Record { pairs } => {
+            for (_, value) in pairs.iter() {
+                entity_uids(value, uids);
+            }
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Context, Entity};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn uid(s: &str) -> EntityUid {
+        s.parse().expect("valid uid")
+    }
+
+    fn transfer(from: &str, to: &str) -> Request {
+        Request::new(
+            Some(uid(from)),
+            Some(uid(r#"Action::"transfer""#)),
+            Some(uid(r#"Token::"usdc""#)),
+            Context::from_pairs([(
+                "to".to_string(),
+                RestrictedExpression::new_entity_uid(uid(to)),
+            )]),
+        )
+    }
+
+    /// Scores each account by the length of its id, counting the calls
+    fn scoring(calls: Arc<AtomicUsize>) -> impl RiskProvider {
+        move |uids: &[EntityUid]| -> Result<Screenings, ProviderError> {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Ok(uids
+                .iter()
+                .map(|uid| {
+                    let score = i64::try_from(uid.id().as_ref().len()).unwrap_or(i64::MAX);
+                    let values = HashMap::from([(
+                        "riskScore".to_string(),
+                        RestrictedExpression::new_long(score),
+                    )]);
+                    (uid.clone(), values)
+                })
+                .collect())
+        }
+    }
+
+    fn failing(uids: &[EntityUid]) -> Result<Screenings, ProviderError> {
+        Err(format!("screening service unavailable for {} entities", uids.len()).into())
+    }
+
+    #[test]
+    fn attributes_and_tags() {
+        let policies: PolicySet = r#"
+            permit(principal, action, resource)
+            when { principal.riskScore < 5 && context.to.riskScore < 5 };"#
+            .parse()
+            .expect("valid policies");
+        // existing attributes are kept, and screened ones replaced
+        let entities = Entities::from_entities([Entity::new(
+            uid(r#"Account::"bob""#),
+            HashMap::from([
+                (
+                    "name".to_string(),
+                    RestrictedExpression::new_string("Bob".into()),
+                ),
+                ("riskScore".to_string(), RestrictedExpression::new_long(100)),
+            ]),
+            HashSet::new(),
+        )])
+        .expect("valid entities");
+        let calls = Arc::new(AtomicUsize::new(0));
+        let authorizer = ScreeningAuthorizer::new(policies, entities, scoring(Arc::clone(&calls)));
+        let response =
+            authorizer.is_authorized(&transfer(r#"Account::"bob""#, r#"Account::"eve""#));
+        assert_eq!(response.decision(), Decision::Allow);
+        assert_eq!(response.screening_failure(), None);
+        let response =
+            authorizer.is_authorized(&transfer(r#"Account::"bob""#, r#"Account::"mallory""#));
+        assert_eq!(response.decision(), Decision::Deny);
+
+        let policies: PolicySet = r#"
+            permit(principal, action, resource)
+            when { principal.getTag("riskScore") < 5 };"#
+            .parse()
+            .expect("valid policies");
+        let authorizer =
+            ScreeningAuthorizer::new(policies, Entities::empty(), scoring(Arc::clone(&calls)))
+                .placement(Placement::Tags);
+        let response =
+            authorizer.is_authorized(&transfer(r#"Account::"bob""#, r#"Account::"mallory""#));
+        assert_eq!(response.decision(), Decision::Allow);
+    }
+
+    #[test]
+    fn batches() {
+        let policies: PolicySet =
+            "permit(principal, action, resource) when { principal.riskScore < 5 };"
+                .parse()
+                .expect("valid policies");
+        let calls = Arc::new(AtomicUsize::new(0));
+        let authorizer =
+            ScreeningAuthorizer::new(policies, Entities::empty(), scoring(Arc::clone(&calls)))
+                .batch_size(2)
+                .entity_types(["Account".parse().expect("valid type")]);
+        // bob, eve, alice and mallory are screened once each, in two batches;
+        // tokens are not screened
+        let responses = authorizer.is_authorized_batch(&[
+            transfer(r#"Account::"bob""#, r#"Account::"eve""#),
+            transfer(r#"Account::"alice""#, r#"Account::"bob""#),
+            transfer(r#"Account::"mallory""#, r#"Account::"eve""#),
+        ]);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        let decisions: Vec<_> = responses.iter().map(ScreenedResponse::decision).collect();
+        assert_eq!(decisions, [Decision::Allow, Decision::Deny, Decision::Deny]);
+    }
+
+    #[test]
+    fn screens_nested_entities() {
+        let policies: PolicySet = r#"
+            permit(principal, action, resource)
+            when { context.payment.to.riskScore < 5 };
+            forbid(principal, action, resource)
+            when { context.payment.cc.contains(Account::"mallory") && Account::"mallory".riskScore > 5 };"#
+            .parse()
+            .expect("valid policies");
+        let calls = Arc::new(AtomicUsize::new(0));
+        let authorizer =
+            ScreeningAuthorizer::new(policies, Entities::empty(), scoring(Arc::clone(&calls)))
+                .entity_types(["Account".parse().expect("valid type")]);
+        let payment = |cc: &str| {
+            Request::new(
+                Some(uid(r#"Account::"bob""#)),
+                Some(uid(r#"Action::"transfer""#)),
+                Some(uid(r#"Token::"usdc""#)),
+                Context::from_pairs([(
+                    "payment".to_string(),
+                    RestrictedExpression::new_record([
+                        (
+                            "to".to_string(),
+                            RestrictedExpression::new_entity_uid(uid(r#"Account::"eve""#)),
+                        ),
+                        (
+                            "cc".to_string(),
+                            RestrictedExpression::new_set([RestrictedExpression::new_entity_uid(
+                                uid(cc),
+                            )]),
+                        ),
+                    ]),
+                )]),
+            )
+        };
+        assert_eq!(
+            authorizer
+                .is_authorized(&payment(r#"Account::"alice""#))
+                .decision(),
+            Decision::Allow
+        );
+        assert_eq!(
+            authorizer
+                .is_authorized(&payment(r#"Account::"mallory""#))
+                .decision(),
+            Decision::Deny
+        );
+    }
+
+    #[test]
+    fn entities_left_out_fail() {
+        let policies: PolicySet = r#"
+            permit(principal, action, resource);
+            forbid(principal, action, resource) when { context.to.riskScore > 50 };"#
+            .parse()
+            .expect("valid policies");
+        // the provider leaves eve out
+        let partial = |uids: &[EntityUid]| -> Result<Screenings, ProviderError> {
+            Ok(uids
+                .iter()
+                .filter(|uid| uid.id().as_ref() != "eve")
+                .map(|uid| (uid.clone(), HashMap::new()))
+                .collect())
+        };
+        let request = transfer(r#"Account::"bob""#, r#"Account::"eve""#);
+
+        let closed = ScreeningAuthorizer::new(policies.clone(), Entities::empty(), partial);
+        let response = closed.is_authorized(&request);
+        assert_eq!(response.decision(), Decision::Deny);
+        assert!(response
+            .screening_failure()
+            .is_some_and(|failure| failure.contains(r#"Account::"eve""#)));
+
+        let open = ScreeningAuthorizer::new(policies, Entities::empty(), partial)
+            .failure_mode(FailureMode::Open);
+        let response = open.is_authorized(&request);
+        assert_eq!(response.decision(), Decision::Allow);
+        assert!(response.screening_failure().is_some());
+    }
+
+    #[test]
+    fn failure_modes() {
+        let policies: PolicySet = r#"
+            permit(principal, action, resource);
+            forbid(principal, action, resource) when { context.to.riskScore > 50 };"#
+            .parse()
+            .expect("valid policies");
+        let request = transfer(r#"Account::"bob""#, r#"Account::"eve""#);
+
+        let closed = ScreeningAuthorizer::new(policies.clone(), Entities::empty(), failing);
+        let response = closed.is_authorized(&request);
+        assert_eq!(response.decision(), Decision::Deny);
+        assert!(response
+            .screening_failure()
+            .is_some_and(|failure| failure.contains("unavailable")));
+
+        // the forbid policy errors without the score, so it is skipped
+        let open = ScreeningAuthorizer::new(policies, Entities::empty(), failing)
+            .failure_mode(FailureMode::Open);
+        let response = open.is_authorized(&request);
+        assert_eq!(response.decision(), Decision::Allow);
+        assert!(response.screening_failure().is_some());
+        assert_eq!(response.response().diagnostics().errors().count(), 1);
+    }
+}
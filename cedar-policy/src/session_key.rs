@@ -0,0 +1,611 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Session key permissions, for smart accounts which let a session key make
+//! some calls on behalf of the account, like the permissions of ERC-7715 and
+//! the session key modules enforcing them on chain.
+//!
+//! [`SessionKeyPermissions`] summarizes what a session key may do: which
+//! contracts it may call, which functions of them, how much value it may send
+//! with each call, and when it expires. It converts to and from JSON, and to
+//! and from a policy set, for requests of calls made by the session key:
+//! * the principal is the canonical UID of the session key, like
+//!   `Account::"eip155:1:0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"` (see
+//!   [`EntityUid::account`]),
+//! * the action is the selector of the function called, like
+//!   `Action::"0xa9059cbb"`,
+//! * the resource is the canonical UID of the contract called (see
+//!   [`EntityUid::contract`]), and
+//! * the context holds the `value` sent, as a `u256`, and the `timestamp` of
+//!   the block, in seconds since the Unix epoch, as a `Long`.
+//!
+//! [`SessionKeyPermissions::to_policy_set`] gives a `permit` policy for each
+//! target, and [`SessionKeyPermissions::from_policy_set`] reads permissions
+//! back from policies of that form, failing on any policy which allows more
+//! than a session key module could enforce.
+//!
+//! ```
+//! # use cedar_policy::session_key::*;
+//! let permissions = SessionKeyPermissions::from_json_str(r#"{
+//!     "chainId": 1,
+//!     "signer": "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed",
+//!     "expiry": 1735689600,
+//!     "targets": [{
+//!         "target": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+//!         "selectors": ["0xa9059cbb"],
+//!         "valueCap": "0x0"
+//!     }]
+//! }"#).unwrap();
+//! let policies = permissions.to_policy_set().unwrap();
+//! assert_eq!(SessionKeyPermissions::from_policy_set(&policies).unwrap(), permissions);
+//! ```
+
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use cedar_policy_core::ast::{self, BinaryOp, Expr, ExprKind, Literal, Var};
+use ethers::types::{Address, U256};
+use ethers::utils::hex;
+use ref_cast::RefCast;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{EntityUid, Policy, PolicyId, PolicySet};
+
+/// The attribute of the context holding the value sent with a call
+pub const VALUE_ATTRIBUTE: &str = "value";
+
+/// The attribute of the context holding the timestamp of the block
+pub const TIMESTAMP_ATTRIBUTE: &str = "timestamp";
+
+/// Errors converting session key permissions
+#[derive(Debug, Error)]
+pub enum SessionKeyError {
+    /// A selector is not four bytes in hex
+    #[error("`{0}` is not a function selector")]
+    InvalidSelector(String),
+    /// The JSON is not of session key permissions
+    #[error("invalid session key permissions: {0}")]
+    Json(#[from] serde_json::Error),
+    /// The expiry does not fit in a `Long`
+    #[error("the expiry {0} is too large")]
+    InvalidExpiry(u64),
+    /// The policies could not be generated
+    #[error("failed to generate the policies: {0}")]
+    Generate(String),
+    /// A policy allows more than session key permissions can express
+    #[error("policy `{policy}` is not a session key permission: {reason}")]
+    Unsupported {
+        /// The policy
+        policy: PolicyId,
+        /// What it allows which cannot be expressed
+        reason: String,
+    },
+    /// The policies do not agree on the session key, chain or expiry, or
+    /// allow calls to a target in more than one policy
+    #[error("the policies {0}")]
+    Inconsistent(String),
+}
+
+/// A function selector: the first four bytes of the Keccak-256 hash of the
+/// signature of a function, written as `0x`-prefixed hex
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Selector(pub [u8; 4]);
+
+impl Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{}", hex::encode(self.0))
+    }
+}
+
+impl FromStr for Selector {
+    type Err = SessionKeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.strip_prefix("0x").unwrap_or(s);
+        hex::decode(digits)
+            .ok()
+            .and_then(|bytes| <[u8; 4]>::try_from(bytes).ok())
+            .map(Self)
+            .ok_or_else(|| SessionKeyError::InvalidSelector(s.to_string()))
+    }
+}
+
+impl TryFrom<String> for Selector {
+    type Error = SessionKeyError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Selector> for String {
+    fn from(selector: Selector) -> Self {
+        selector.to_string()
+    }
+}
+
+/// What a session key may call on one contract
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct TargetPermission {
+    /// The address of the contract
+    pub target: Address,
+    /// The selectors of the functions which may be called, or none if any
+    /// function may be
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub selectors: Vec<Selector>,
+    /// The largest value which may be sent with a call, if it is capped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_cap: Option<U256>,
+}
+
+/// What a session key may do on one chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SessionKeyPermissions {
+    /// The EIP-155 id of the chain
+    pub chain_id: u64,
+    /// The address of the session key
+    pub signer: Address,
+    /// When the session key expires, in seconds since the Unix epoch, if it
+    /// does
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<u64>,
+    /// The contracts the session key may call
+    pub targets: Vec<TargetPermission>,
+}
+
+impl SessionKeyPermissions {
+    /// Parse permissions from their JSON
+    ///
+    /// # Errors
+    ///
+    /// If the JSON is not of session key permissions.
+    pub fn from_json_str(json: &str) -> Result<Self, SessionKeyError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// The JSON of these permissions, as read by [`Self::from_json_str`]
+    ///
+    /// # Errors
+    ///
+    /// If the permissions cannot be serialized.
+    pub fn to_json_string(&self) -> Result<String, SessionKeyError> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// The policies allowing what these permissions allow: a `permit` policy
+    /// for each target, with the id `target` followed by its index
+    ///
+    /// # Errors
+    ///
+    /// If the expiry is too large to compare the timestamp of a block with.
+    pub fn to_policy_set(&self) -> Result<PolicySet, SessionKeyError> {
+        let principal = EntityUid::account(self.chain_id, self.signer);
+        let mut conditions = Vec::new();
+        if let Some(expiry) = self.expiry {
+            let expiry =
+                i64::try_from(expiry).map_err(|_| SessionKeyError::InvalidExpiry(expiry))?;
+            conditions.push(format!("context.{TIMESTAMP_ATTRIBUTE} < {expiry}"));
+        }
+        let mut policies = PolicySet::new();
+        for (index, target) in self.targets.iter().enumerate() {
+            let action = match target.selectors.as_slice() {
+                [] => "action".to_string(),
+                [selector] => format!(r#"action == Action::"{selector}""#),
+                selectors => format!(
+                    "action in [{}]",
+                    selectors
+                        .iter()
+                        .map(|selector| format!(r#"Action::"{selector}""#))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            };
+            let resource = EntityUid::contract(self.chain_id, target.target);
+            let conditions = target
+                .value_cap
+                .map(|cap| format!(r#"context.{VALUE_ATTRIBUTE} <= u256("{cap}")"#))
+                .into_iter()
+                .chain(conditions.iter().cloned())
+                .collect::<Vec<_>>();
+            let when = if conditions.is_empty() {
+                String::new()
+            } else {
+                format!("\nwhen {{ {} }}", conditions.join(" && "))
+            };
+            let text = format!(
+                "permit(\n  principal == {principal},\n  {action},\n  resource == {resource}\n){when};"
+            );
+            let policy = Policy::parse(Some(format!("target{index}")), text)
+                .map_err(|e| SessionKeyError::Generate(e.to_string()))?;
+            policies
+                .add(policy)
+                .map_err(|e| SessionKeyError::Generate(e.to_string()))?;
+        }
+        Ok(policies)
+    }
+
+    /// Read the permissions that `policies` allow, which must all be `permit`
+    /// policies of the form [`Self::to_policy_set`] gives, for the same
+    /// session key, chain and expiry. The targets are ordered by address.
+    ///
+    /// # Errors
+    ///
+    /// If a policy allows more than permissions can express, or the policies
+    /// are inconsistent.
+    pub fn from_policy_set(policies: &PolicySet) -> Result<Self, SessionKeyError> {
+        let mut summaries = policies
+            .ast
+            .policies()
+            .map(|policy| PolicySummary::of(policy).map(|summary| (policy.id(), summary)))
+            .collect::<Result<Vec<_>, _>>()?;
+        summaries.sort_by(|(a, _), (b, _)| a.as_ref().cmp(b.as_ref()));
+        let mut summaries = summaries.into_iter().map(|(_, summary)| summary);
+        let Some(first) = summaries.next() else {
+            return Err(SessionKeyError::Inconsistent(
+                "allow nothing: there are none".to_string(),
+            ));
+        };
+        let mut permissions = Self {
+            chain_id: first.chain_id,
+            signer: first.signer,
+            expiry: first.expiry,
+            targets: vec![first.target],
+        };
+        for summary in summaries {
+            if (summary.chain_id, summary.signer) != (permissions.chain_id, permissions.signer) {
+                return Err(SessionKeyError::Inconsistent(
+                    "allow calls by more than one session key".to_string(),
+                ));
+            }
+            if summary.expiry != permissions.expiry {
+                return Err(SessionKeyError::Inconsistent(
+                    "expire at different times".to_string(),
+                ));
+            }
+            if permissions
+                .targets
+                .iter()
+                .any(|target| target.target == summary.target.target)
+            {
+                return Err(SessionKeyError::Inconsistent(format!(
+                    "allow calls to `{:?}` in more than one policy",
+                    summary.target.target
+                )));
+            }
+            permissions.targets.push(summary.target);
+        }
+        permissions.targets.sort_by_key(|target| target.target);
+        Ok(permissions)
+    }
+}
+
+/// What a single policy allows
+struct PolicySummary {
+    chain_id: u64,
+    signer: Address,
+    expiry: Option<u64>,
+    target: TargetPermission,
+}
+
+impl PolicySummary {
+    fn of(policy: &ast::Policy) -> Result<Self, SessionKeyError> {
+        let unsupported = |reason: &str| SessionKeyError::Unsupported {
+            policy: PolicyId::ref_cast(policy.id()).clone(),
+            reason: reason.to_string(),
+        };
+        if policy.effect() != ast::Effect::Permit {
+            return Err(unsupported("it is not a `permit` policy"));
+        }
+        let signer = match policy.principal_constraint().into_inner() {
+            ast::PrincipalOrResourceConstraint::Eq(ast::EntityReference::EUID(uid)) => {
+                uid.account_address().ok()
+            }
+            _ => None,
+        }
+        .ok_or_else(|| unsupported("its principal is not the UID of an account"))?;
+        let target = match policy.resource_constraint().into_inner() {
+            ast::PrincipalOrResourceConstraint::Eq(ast::EntityReference::EUID(uid)) => {
+                uid.contract_address().ok()
+            }
+            _ => None,
+        }
+        .ok_or_else(|| unsupported("its resource is not the UID of a contract"))?;
+        if target.chain_id != signer.chain_id {
+            return Err(unsupported(
+                "its principal and resource are on different chains",
+            ));
+        }
+        let actions = match policy.action_constraint() {
+            ast::ActionConstraint::Any => Vec::new(),
+            ast::ActionConstraint::Eq(uid) => vec![uid.clone()],
+            ast::ActionConstraint::In(uids) => uids.clone(),
+        };
+        let selectors = actions
+            .iter()
+            .map(|uid| match uid.entity_type() {
+                ast::EntityType::Concrete(ty) if ty.to_string() == "Action" => {
+                    uid.eid().to_string().parse().ok()
+                }
+                _ => None,
+            })
+            .collect::<Option<Vec<Selector>>>()
+            .ok_or_else(|| unsupported("its actions are not function selectors"))?;
+        let mut value_cap: Option<U256> = None;
+        let mut expiry: Option<u64> = None;
+        for conjunct in conjuncts(policy.non_head_constraints()) {
+            if let Some(cap) = value_cap_of(conjunct) {
+                value_cap = Some(value_cap.map_or(cap, |other| other.min(cap)));
+            } else if let Some(at) = expiry_of(conjunct) {
+                expiry = Some(expiry.map_or(at, |other| other.min(at)));
+            } else if !matches!(conjunct.expr_kind(), ExprKind::Lit(Literal::Bool(true))) {
+                return Err(unsupported(&format!(
+                    "its condition `{conjunct}` is neither a cap on `context.{VALUE_ATTRIBUTE}` nor an expiry of `context.{TIMESTAMP_ATTRIBUTE}`"
+                )));
+            }
+        }
+        Ok(Self {
+            chain_id: signer.chain_id,
+            signer: signer.address,
+            expiry,
+            target: TargetPermission {
+                target: target.address,
+                selectors,
+                value_cap,
+            },
+        })
+    }
+}
+
+/// The operands of the `&&`s of `expr`
+fn conjuncts(expr: &Expr) -> Vec<&Expr> {
+    match expr.expr_kind() {
+        ExprKind::And { left, right } => {
+            let mut left = conjuncts(left);
+            left.extend(conjuncts(right));
+            left
+        }
+        _ => vec![expr],
+    }
+}
+
+/// Whether `expr` is `context.<attr>`
+fn is_context_attr(expr: &Expr, attribute: &str) -> bool {
+    matches!(expr.expr_kind(), ExprKind::GetAttr { expr, attr }
+        if attr == attribute && matches!(expr.expr_kind(), ExprKind::Var(Var::Context)))
+}
+
+/// The cap of `context.value <= u256("<cap>")`
+fn value_cap_of(expr: &Expr) -> Option<U256> {
+    let ExprKind::ExtensionFunctionApp { fn_name, args } = expr.expr_kind() else {
+        return None;
+    };
+    let [value, cap] = args.as_slice() else {
+        return None;
+    };
+    if fn_name.to_string() != "u256LessThanOrEqual" || !is_context_attr(value, VALUE_ATTRIBUTE) {
+        return None;
+    }
+    let ExprKind::ExtensionFunctionApp { fn_name, args } = cap.expr_kind() else {
+        return None;
+    };
+    match (fn_name.to_string().as_str(), args.as_slice()) {
+        ("u256", [literal]) => match literal.expr_kind() {
+            ExprKind::Lit(Literal::String(cap)) => cap.strip_prefix("0x").map_or_else(
+                || U256::from_dec_str(cap).ok(),
+                |hex| U256::from_str_radix(hex, 16).ok(),
+            ),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The expiry of `context.timestamp < <expiry>`
+fn expiry_of(expr: &Expr) -> Option<u64> {
+    match expr.expr_kind() {
+        ExprKind::BinaryApp {
+            op: BinaryOp::Less,
+            arg1,
+            arg2,
+        } if is_context_attr(arg1, TIMESTAMP_ATTRIBUTE) => match arg2.expr_kind() {
+            ExprKind::Lit(Literal::Long(expiry)) => u64::try_from(*expiry).ok(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Decision, Entities, Request, RestrictedExpression};
+
+    fn address(s: &str) -> Address {
+        s.parse().expect("valid address")
+    }
+
+    fn permissions() -> SessionKeyPermissions {
+        SessionKeyPermissions {
+            chain_id: 10,
+            signer: address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"),
+            expiry: Some(1_735_689_600),
+            targets: vec![
+                TargetPermission {
+                    target: address("0x0b2c639c533813f4aa9d7837caf62653d097ff85"),
+                    selectors: vec![
+                        "0xa9059cbb".parse().expect("valid selector"),
+                        "0x095ea7b3".parse().expect("valid selector"),
+                    ],
+                    value_cap: Some(U256::zero()),
+                },
+                TargetPermission {
+                    target: address("0x4200000000000000000000000000000000000006"),
+                    selectors: Vec::new(),
+                    value_cap: Some(U256::exp10(18)),
+                },
+            ],
+        }
+    }
+
+    fn call(target: &str, selector: &str, value: &str, timestamp: i64) -> Request {
+        Request::new(
+            Some(EntityUid::account(
+                10,
+                address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"),
+            )),
+            Some(
+                format!(r#"Action::"{selector}""#)
+                    .parse()
+                    .expect("valid uid"),
+            ),
+            Some(EntityUid::contract(10, address(target))),
+            Context::from_pairs([
+                (
+                    VALUE_ATTRIBUTE.to_string(),
+                    RestrictedExpression::from_str(&format!(r#"u256("{value}")"#))
+                        .expect("valid u256"),
+                ),
+                (
+                    TIMESTAMP_ATTRIBUTE.to_string(),
+                    RestrictedExpression::new_long(timestamp),
+                ),
+            ]),
+        )
+    }
+
+    #[test]
+    fn policies_enforce_permissions() {
+        let policies = permissions().to_policy_set().expect("generates");
+        let decide = |request: &Request| {
+            Authorizer::new()
+                .is_authorized(request, &policies, &Entities::empty())
+                .decision()
+        };
+        let usdc = "0x0b2c639c533813f4aa9d7837caf62653d097ff85";
+        let weth = "0x4200000000000000000000000000000000000006";
+        assert_eq!(
+            decide(&call(usdc, "0xa9059cbb", "0", 1_700_000_000)),
+            Decision::Allow
+        );
+        // another function, a value, or after the expiry
+        assert_eq!(
+            decide(&call(usdc, "0x23b872dd", "0", 1_700_000_000)),
+            Decision::Deny
+        );
+        assert_eq!(
+            decide(&call(usdc, "0xa9059cbb", "1", 1_700_000_000)),
+            Decision::Deny
+        );
+        assert_eq!(
+            decide(&call(usdc, "0xa9059cbb", "0", 1_735_689_600)),
+            Decision::Deny
+        );
+        // any function of WETH, with up to one ether
+        assert_eq!(
+            decide(&call(
+                weth,
+                "0xd0e30db0",
+                "1000000000000000000",
+                1_700_000_000
+            )),
+            Decision::Allow
+        );
+        assert_eq!(
+            decide(&call(
+                weth,
+                "0xd0e30db0",
+                "1000000000000000001",
+                1_700_000_000
+            )),
+            Decision::Deny
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        let permissions = permissions();
+        let policies = permissions.to_policy_set().expect("generates");
+        assert_eq!(
+            SessionKeyPermissions::from_policy_set(&policies).expect("reads"),
+            permissions
+        );
+        let json = permissions.to_json_string().expect("serializes");
+        assert_eq!(
+            SessionKeyPermissions::from_json_str(&json).expect("parses"),
+            permissions
+        );
+
+        // hand-written policies of the same form are read too
+        let policies: PolicySet = r#"
+            permit(
+                principal == Account::"eip155:10:0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+                action,
+                resource == Contract::"eip155:10:0x4200000000000000000000000000000000000006"
+            ) when { context.timestamp < 1735689600 && context.value <= 0x0de0b6b3a7640000 };
+            permit(
+                principal == Account::"eip155:10:0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+                action in [Action::"0xa9059cbb", Action::"0x095ea7b3"],
+                resource == Contract::"eip155:10:0x0b2C639c533813f4Aa9D7837CAf62653d097Ff85"
+            ) when { context.value <= u256("0") && context.timestamp < 1735689600 };"#
+            .parse()
+            .expect("valid policies");
+        assert_eq!(
+            SessionKeyPermissions::from_policy_set(&policies).expect("reads"),
+            permissions
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_policies() {
+        let account = r#"Account::"eip155:10:0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed""#;
+        let contract = r#"Contract::"eip155:10:0x4200000000000000000000000000000000000006""#;
+        for policy in [
+            format!("forbid(principal == {account}, action, resource == {contract});"),
+            format!("permit(principal, action, resource == {contract});"),
+            format!(r#"permit(principal == {account}, action, resource in Group::"tokens");"#),
+            format!(
+                r#"permit(principal == {account}, action == Action::"transfer", resource == {contract});"#
+            ),
+            format!(
+                "permit(principal == {account}, action, resource == {contract}) when {{ context.value <= u256(\"1\") || true }};"
+            ),
+        ] {
+            let policies: PolicySet = policy.parse().expect("valid policy");
+            assert!(
+                matches!(
+                    SessionKeyPermissions::from_policy_set(&policies),
+                    Err(SessionKeyError::Unsupported { .. })
+                ),
+                "{policy}"
+            );
+        }
+
+        let policies: PolicySet = format!(
+            "permit(principal == {account}, action, resource == {contract});
+            permit(principal == {account}, action, resource == {contract}) when {{ context.timestamp < 5 }};"
+        )
+        .parse()
+        .expect("valid policies");
+        assert!(matches!(
+            SessionKeyPermissions::from_policy_set(&policies),
+            Err(SessionKeyError::Inconsistent(_))
+        ));
+    }
+}
@@ -0,0 +1,722 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Requests signed by their principal, so that a remote authorizer can trust
+//! that the caller did not spoof the identity of the principal.
+//!
+//! A [`SignedRequest`] holds the JSON of a request, the [`RequestClaims`]
+//! under which it was signed, and an EIP-712 signature of its
+//! [`signing_hash`]. The signed struct is
+//! ```text
+//! SignedRequest(bytes32 request,string audience,bytes32 nonce,uint64 issuedAt,uint64 expiresAt)
+//! ```
+//! where `request` is the Keccak-256 hash of the canonical JSON of the request
+//! (see [`crate::canonical::request_json`]), which does not depend on how the
+//! JSON was written, in the [`SigningDomain`] of the authorizer, so that a
+//! signature is only valid for one authorizer, one audience and one period.
+//!
+//! A [`RequestVerifier`] recovers the signer and checks that it is the
+//! principal, which must be of one of the principal types of the verifier:
+//! either the canonical UID of an account on the chain of the domain, such as
+//! `Account::"eip155:1:0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"` (see
+//! [`EntityUid::account`]), or an entity of another principal type whose id is
+//! an address. It rejects requests for another audience, expired or not yet
+//! issued, valid for longer than its maximum lifetime, or whose nonce it has
+//! already accepted before they expire.
+//!
+//! ```
+//! # use std::time::Duration;
+//! # use cedar_policy::{signed_request::*, Context, EntityUid, Request};
+//! # use ethers::signers::{LocalWallet, Signer};
+//! let wallet: LocalWallet = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+//!     .parse()
+//!     .unwrap();
+//! let request = Request::new(
+//!     Some(EntityUid::account(1, wallet.address())),
+//!     Some(r#"Action::"withdraw""#.parse().unwrap()),
+//!     Some(r#"Vault::"treasury""#.parse().unwrap()),
+//!     Context::empty(),
+//! );
+//! let domain = SigningDomain::new("treasury-authorizer", "1", 1);
+//! let claims = RequestClaims::new("https://authz.example.com", Duration::from_secs(60));
+//! let signed = SignedRequest::sign(&request, claims, &domain, &wallet).unwrap();
+//! // the envelope is sent as JSON, and verified by the authorizer
+//! let verifier = RequestVerifier::new(domain, "https://authz.example.com");
+//! let received = SignedRequest::from_json_str(&signed.to_json_string()).unwrap();
+//! let verified = verifier.verify(&received, None).unwrap();
+//! assert_eq!(verified.principal(), request.principal());
+//! // the same envelope is not accepted twice
+//! assert!(matches!(
+//!     verifier.verify(&received, None),
+//!     Err(SignedRequestError::Replayed(_))
+//! ));
+//! ```
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ethers::abi::{self, Token};
+use ethers::core::rand;
+use ethers::signers::LocalWallet;
+use ethers::types::transaction::eip712::EIP712Domain;
+use ethers::types::{Address, Signature, H256, U256};
+use ethers::utils::keccak256;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::canonical::{self, CanonicalJsonError};
+use crate::{Context, EntityTypeName, EntityUid, Request, Schema};
+
+/// The EIP-712 type of the signed struct
+pub const SIGNED_REQUEST_TYPE: &str =
+    "SignedRequest(bytes32 request,string audience,bytes32 nonce,uint64 issuedAt,uint64 expiresAt)";
+
+/// The entity type of the canonical UIDs of accounts, the default principal
+/// type of a [`RequestVerifier`]
+pub const ACCOUNT_TYPE: &str = "Account";
+
+/// Errors signing or verifying a [`SignedRequest`]
+#[derive(Debug, Error)]
+pub enum SignedRequestError {
+    /// The envelope or the request in it is not in the expected format
+    #[error("invalid signed request: {0}")]
+    Format(String),
+    /// The request could not be converted to canonical JSON to be hashed
+    #[error(transparent)]
+    Canonical(#[from] CanonicalJsonError),
+    /// The signature is malformed, or no signer can be recovered from it
+    #[error("invalid signature: {0}")]
+    Signature(String),
+    /// The request was signed for another audience than the verifier
+    #[error("the request was signed for `{found}`, not `{expected}`")]
+    WrongAudience {
+        /// The audience of the verifier
+        expected: String,
+        /// The audience the request was signed for
+        found: String,
+    },
+    /// The request expired, at the given time in seconds since the Unix epoch
+    #[error("the request expired at {0}")]
+    Expired(u64),
+    /// The request was issued in the future, at the given time in seconds
+    /// since the Unix epoch
+    #[error("the request is issued at {0}, in the future")]
+    NotYetValid(u64),
+    /// The request is valid for longer than the verifier allows
+    #[error("the request is valid for {lifetime:?}, longer than {max:?}")]
+    LifetimeTooLong {
+        /// How long the request is valid for
+        lifetime: Duration,
+        /// The longest the verifier allows
+        max: Duration,
+    },
+    /// A request with the same nonce was already accepted
+    #[error("the nonce `{0:?}` was already used")]
+    Replayed(H256),
+    /// The request has no principal to compare the signer to
+    #[error("the request has no principal")]
+    NoPrincipal,
+    /// The principal is not of a principal type of the verifier, or has no
+    /// address
+    #[error("the principal `{0}` is not an address of an accepted principal type")]
+    PrincipalNotAnAddress(EntityUid),
+    /// The request was signed by another address than its principal
+    #[error("the request was signed by `{signer:?}`, not by its principal `{principal}`")]
+    SignerMismatch {
+        /// The address which signed the request
+        signer: Address,
+        /// The principal of the request
+        principal: EntityUid,
+    },
+}
+
+/// The EIP-712 domain of the signatures of requests, naming the authorizer
+/// they are for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigningDomain {
+    /// The name of the authorizer
+    pub name: String,
+    /// The version of the authorizer
+    pub version: String,
+    /// The chain of the domain, which is also the only chain the canonical
+    /// UIDs of accounts are accepted on
+    pub chain_id: u64,
+    /// The contract verifying the signatures, if any
+    pub verifying_contract: Option<Address>,
+}
+
+impl SigningDomain {
+    /// The domain named `name` at version `version` on the chain `chain_id`
+    pub fn new(name: impl Into<String>, version: impl Into<String>, chain_id: u64) -> Self {
+        Self {
+            name: name.into(),
+            version: version.into(),
+            chain_id,
+            verifying_contract: None,
+        }
+    }
+
+    /// The same domain, with a verifying contract
+    #[must_use]
+    pub fn with_verifying_contract(mut self, contract: Address) -> Self {
+        self.verifying_contract = Some(contract);
+        self
+    }
+
+    /// The EIP-712 domain separator
+    pub fn separator(&self) -> H256 {
+        H256(
+            EIP712Domain {
+                name: Some(self.name.clone()),
+                version: Some(self.version.clone()),
+                chain_id: Some(U256::from(self.chain_id)),
+                verifying_contract: self.verifying_contract,
+                salt: None,
+            }
+            .separator(),
+        )
+    }
+}
+
+/// What a signature of a request is bound to besides the request: the
+/// audience it is for, a nonce, and when it was issued and expires, in
+/// seconds since the Unix epoch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestClaims {
+    /// The authorizer the request is for, such as its URL
+    pub audience: String,
+    /// A nonce, unique to the request
+    pub nonce: H256,
+    /// When the request was issued
+    pub issued_at: u64,
+    /// When the request expires
+    pub expires_at: u64,
+}
+
+impl RequestClaims {
+    /// Claims for `audience`, with a random nonce, issued now and valid for
+    /// `lifetime`
+    pub fn new(audience: impl Into<String>, lifetime: Duration) -> Self {
+        let issued_at = unix_now();
+        Self {
+            audience: audience.into(),
+            nonce: H256(rand::random()),
+            issued_at,
+            expires_at: issued_at.saturating_add(lifetime.as_secs()),
+        }
+    }
+}
+
+/// The JSON of a request and the claims it was signed with, together with an
+/// EIP-712 signature of their signing hash
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SignedRequest {
+    /// The request: an object of its `principal`, `action` and `resource`,
+    /// each an entity reference like `{"type": "User", "id": "alice"}` or
+    /// `null` if unspecified, and its `context`
+    pub request: serde_json::Value,
+    /// The claims the request was signed with
+    pub claims: RequestClaims,
+    /// The 65-byte signature, in hex
+    pub signature: String,
+}
+
+/// The signing hash of `request` with `claims` in `domain`: the EIP-712 hash
+/// of the [`SIGNED_REQUEST_TYPE`] struct
+///
+/// # Errors
+///
+/// If a value of the context cannot be converted to JSON.
+pub fn signing_hash(
+    request: &Request,
+    claims: &RequestClaims,
+    domain: &SigningDomain,
+) -> Result<H256, CanonicalJsonError> {
+    let struct_hash = keccak256(abi::encode(&[
+        Token::FixedBytes(keccak256(SIGNED_REQUEST_TYPE).to_vec()),
+        Token::FixedBytes(keccak256(canonical::request_json(request)?).to_vec()),
+        Token::FixedBytes(keccak256(&claims.audience).to_vec()),
+        Token::FixedBytes(claims.nonce.as_bytes().to_vec()),
+        Token::Uint(U256::from(claims.issued_at)),
+        Token::Uint(U256::from(claims.expires_at)),
+    ]));
+    Ok(typed_data_hash(domain, struct_hash))
+}
+
+/// The EIP-712 hash of the struct whose hash is `struct_hash` in `domain`
+pub(crate) fn typed_data_hash(domain: &SigningDomain, struct_hash: [u8; 32]) -> H256 {
+    let mut digest = Vec::with_capacity(66);
+    digest.extend_from_slice(b"\x19\x01");
+    digest.extend_from_slice(domain.separator().as_bytes());
+    digest.extend_from_slice(&struct_hash);
+    H256(keccak256(digest))
+}
+
+impl SignedRequest {
+    /// Sign `request` with `claims` in `domain` with `wallet`, whose address
+    /// should be the principal of the request
+    ///
+    /// # Errors
+    ///
+    /// If the request cannot be converted to JSON, or signing fails.
+    pub fn sign(
+        request: &Request,
+        claims: RequestClaims,
+        domain: &SigningDomain,
+        wallet: &LocalWallet,
+    ) -> Result<Self, SignedRequestError> {
+        let hash = signing_hash(request, &claims, domain)?;
+        let signature = wallet
+            .sign_hash(hash)
+            .map_err(|e| SignedRequestError::Signature(e.to_string()))?;
+        let request = serde_json::from_str(&canonical::request_json(request)?)
+            .map_err(|e| SignedRequestError::Format(e.to_string()))?;
+        Ok(Self {
+            request,
+            claims,
+            signature: format!("0x{signature}"),
+        })
+    }
+
+    /// Parse an envelope from its JSON: an object of the `request`, the
+    /// `claims` and the `signature`
+    ///
+    /// # Errors
+    ///
+    /// If the JSON is not an envelope.
+    pub fn from_json_str(json: &str) -> Result<Self, SignedRequestError> {
+        serde_json::from_str(json).map_err(|e| SignedRequestError::Format(e.to_string()))
+    }
+
+    /// The JSON of this envelope, as read by [`Self::from_json_str`]
+    pub fn to_json_string(&self) -> String {
+        serde_json::json!({
+            "request": self.request,
+            "claims": self.claims,
+            "signature": self.signature,
+        })
+        .to_string()
+    }
+
+    /// The request in this envelope, without verifying the signature. If
+    /// `schema` is given, the context is parsed and checked against the
+    /// context type of the action in it.
+    ///
+    /// # Errors
+    ///
+    /// If the request is not in the expected format.
+    pub fn unverified_request(
+        &self,
+        schema: Option<&Schema>,
+    ) -> Result<Request, SignedRequestError> {
+        let invalid = |e: &dyn std::fmt::Display| SignedRequestError::Format(e.to_string());
+        let serde_json::Value::Object(fields) = &self.request else {
+            return Err(SignedRequestError::Format(
+                "the request is not an object".to_string(),
+            ));
+        };
+        if let Some(field) = fields
+            .keys()
+            .find(|key| !["principal", "action", "resource", "context"].contains(&key.as_str()))
+        {
+            return Err(SignedRequestError::Format(format!(
+                "unexpected field `{field}` in the request"
+            )));
+        }
+        let uid = |field: &str| match fields.get(field) {
+            None | Some(serde_json::Value::Null) => Ok(None),
+            Some(json) => EntityUid::from_json(serde_json::json!({ "__entity": json }))
+                .map(Some)
+                .map_err(|e| invalid(&e)),
+        };
+        let (principal, action, resource) = (uid("principal")?, uid("action")?, uid("resource")?);
+        let context = match fields.get("context") {
+            None | Some(serde_json::Value::Null) => Context::empty(),
+            Some(json) => {
+                let schema = schema.zip(action.as_ref());
+                Context::from_json_value(json.clone(), schema).map_err(|e| invalid(&e))?
+            }
+        };
+        Ok(Request::new(principal, action, resource, context))
+    }
+}
+
+/// Verifies [`SignedRequest`]s for one audience in one [`SigningDomain`],
+/// remembering the nonces of the requests it accepted until they expire
+#[derive(Debug)]
+pub struct RequestVerifier {
+    domain: SigningDomain,
+    audience: String,
+    principal_types: Vec<EntityTypeName>,
+    max_lifetime: Duration,
+    clock_skew: Duration,
+    /// The nonces accepted, with when their requests expire
+    accepted: Mutex<HashMap<H256, u64>>,
+}
+
+impl RequestVerifier {
+    /// A verifier of requests signed in `domain` for `audience`, whose
+    /// principals are the canonical UIDs of accounts on the chain of the
+    /// domain, valid for at most five minutes, allowing thirty seconds of
+    /// clock skew
+    pub fn new(domain: SigningDomain, audience: impl Into<String>) -> Self {
+        Self {
+            domain,
+            audience: audience.into(),
+            principal_types: EntityTypeName::from_str(ACCOUNT_TYPE).into_iter().collect(),
+            max_lifetime: Duration::from_mins(5),
+            clock_skew: Duration::from_secs(30),
+            accepted: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The same verifier, also accepting principals of the type
+    /// `principal_type` whose id is an address
+    #[must_use]
+    pub fn with_principal_type(mut self, principal_type: EntityTypeName) -> Self {
+        if !self.principal_types.contains(&principal_type) {
+            self.principal_types.push(principal_type);
+        }
+        self
+    }
+
+    /// The same verifier, rejecting requests valid for longer than
+    /// `max_lifetime`
+    #[must_use]
+    pub fn with_max_lifetime(mut self, max_lifetime: Duration) -> Self {
+        self.max_lifetime = max_lifetime;
+        self
+    }
+
+    /// The same verifier, allowing the clocks of signers to be off by up to
+    /// `clock_skew`
+    #[must_use]
+    pub fn with_clock_skew(mut self, clock_skew: Duration) -> Self {
+        self.clock_skew = clock_skew;
+        self
+    }
+
+    /// The domain requests are signed in
+    pub fn domain(&self) -> &SigningDomain {
+        &self.domain
+    }
+
+    /// The request in `signed`, once its claims are checked and its signature
+    /// is verified to be of its signing hash by its principal. If `schema` is
+    /// given, the context is parsed and checked against the context type of
+    /// the action in it. Its nonce is then remembered until it expires, so
+    /// the request is accepted only once.
+    ///
+    /// # Errors
+    ///
+    /// If the request is not in the expected format, is for another
+    /// audience, is expired, not yet issued or valid for too long, the
+    /// signature is invalid, the signer is not the principal, or the nonce
+    /// was already used.
+    pub fn verify(
+        &self,
+        signed: &SignedRequest,
+        schema: Option<&Schema>,
+    ) -> Result<Request, SignedRequestError> {
+        self.verify_at(signed, schema, unix_now())
+    }
+
+    /// [`Self::verify`], as if it was `now` seconds since the Unix epoch
+    ///
+    /// # Errors
+    ///
+    /// As [`Self::verify`].
+    pub fn verify_at(
+        &self,
+        signed: &SignedRequest,
+        schema: Option<&Schema>,
+        now: u64,
+    ) -> Result<Request, SignedRequestError> {
+        let request = signed.unverified_request(schema)?;
+        let claims = &signed.claims;
+        if claims.audience != self.audience {
+            return Err(SignedRequestError::WrongAudience {
+                expected: self.audience.clone(),
+                found: claims.audience.clone(),
+            });
+        }
+        let skew = self.clock_skew.as_secs();
+        if claims.issued_at > now.saturating_add(skew) {
+            return Err(SignedRequestError::NotYetValid(claims.issued_at));
+        }
+        if claims.expires_at.saturating_add(skew) < now {
+            return Err(SignedRequestError::Expired(claims.expires_at));
+        }
+        let lifetime = Duration::from_secs(claims.expires_at.saturating_sub(claims.issued_at));
+        if lifetime > self.max_lifetime {
+            return Err(SignedRequestError::LifetimeTooLong {
+                lifetime,
+                max: self.max_lifetime,
+            });
+        }
+        let signature = Signature::from_str(&signed.signature)
+            .map_err(|e| SignedRequestError::Signature(e.to_string()))?;
+        let recovered = signature
+            .recover(signing_hash(&request, claims, &self.domain)?)
+            .map_err(|e| SignedRequestError::Signature(e.to_string()))?;
+        let principal = request.principal().ok_or(SignedRequestError::NoPrincipal)?;
+        let address = self
+            .principal_address(principal)
+            .ok_or_else(|| SignedRequestError::PrincipalNotAnAddress(principal.clone()))?;
+        if address != recovered {
+            return Err(SignedRequestError::SignerMismatch {
+                signer: recovered,
+                principal: principal.clone(),
+            });
+        }
+        // only remember the nonces of valid signatures, so that others cannot
+        // use them up
+        let replayed = {
+            let mut accepted = self
+                .accepted
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            accepted.retain(|_, expires_at| expires_at.saturating_add(skew) >= now);
+            accepted.insert(claims.nonce, claims.expires_at).is_some()
+        };
+        if replayed {
+            return Err(SignedRequestError::Replayed(claims.nonce));
+        }
+        Ok(request)
+    }
+
+    /// The address of `principal`, if it is of a principal type of this
+    /// verifier: that of the canonical UID of an account on the chain of the
+    /// domain, or its id if that is an address
+    fn principal_address(&self, principal: &EntityUid) -> Option<Address> {
+        if !self.principal_types.contains(principal.type_name()) {
+            return None;
+        }
+        if principal.type_name().to_string() == ACCOUNT_TYPE {
+            let account = principal.account_address().ok()?;
+            return (account.chain_id == self.domain.chain_id).then_some(account.address);
+        }
+        Address::from_str(principal.id().as_ref()).ok()
+    }
+}
+
+/// The address of `uid`: that of the canonical UID of an account, or its id
+/// if that is an address
+pub(crate) fn entity_address(uid: &EntityUid) -> Option<Address> {
+    uid.account_address()
+        .map(|account| account.address)
+        .or_else(|_| Address::from_str(uid.id().as_ref()))
+        .ok()
+}
+
+/// The current time, in seconds since the Unix epoch
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::RestrictedExpression;
+    use ethers::signers::Signer;
+
+    const AUDIENCE: &str = "https://authz.example.com";
+
+    fn wallet(key: &str) -> LocalWallet {
+        key.parse().expect("valid key")
+    }
+
+    fn request(principal: EntityUid) -> Request {
+        Request::new(
+            Some(principal),
+            Some(r#"Action::"withdraw""#.parse().expect("valid uid")),
+            Some(r#"Vault::"treasury""#.parse().expect("valid uid")),
+            Context::from_pairs([
+                ("amount".to_string(), RestrictedExpression::new_long(100)),
+                (
+                    "memo".to_string(),
+                    RestrictedExpression::new_string("rent".into()),
+                ),
+            ]),
+        )
+    }
+
+    fn domain() -> SigningDomain {
+        SigningDomain::new("treasury-authorizer", "1", 1)
+    }
+
+    fn verifier() -> RequestVerifier {
+        RequestVerifier::new(domain(), AUDIENCE)
+    }
+
+    fn claims() -> RequestClaims {
+        RequestClaims::new(AUDIENCE, Duration::from_secs(60))
+    }
+
+    fn sign(principal: EntityUid, wallet: &LocalWallet) -> SignedRequest {
+        SignedRequest::sign(&request(principal), claims(), &domain(), wallet).expect("signs")
+    }
+
+    const KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+    const OTHER_KEY: &str = "0000000000000000000000000000000000000000000000000000000000000001";
+
+    #[test]
+    fn verifies_signer() {
+        let wallet = wallet(KEY);
+        let signed = sign(EntityUid::account(1, wallet.address()), &wallet);
+        let verified = verifier().verify(&signed, None).expect("verifies");
+        assert_eq!(
+            verified.principal(),
+            Some(&EntityUid::account(1, wallet.address()))
+        );
+
+        // an entity of a configured principal type whose id is the address,
+        // in any case, is the principal too
+        let principal = EntityUid::from_str(&format!(
+            r#"Address::"{}""#,
+            format!("{:?}", wallet.address())
+                .to_uppercase()
+                .replace("0X", "0x")
+        ))
+        .expect("valid uid");
+        let signed = sign(principal, &wallet);
+        assert!(matches!(
+            verifier().verify(&signed, None),
+            Err(SignedRequestError::PrincipalNotAnAddress(_))
+        ));
+        let verifier = verifier()
+            .with_principal_type(EntityTypeName::from_str("Address").expect("valid type"));
+        assert!(verifier.verify(&signed, None).is_ok());
+    }
+
+    #[test]
+    fn verification_ignores_json_layout() {
+        let wallet = wallet(KEY);
+        let signed = sign(EntityUid::account(1, wallet.address()), &wallet);
+        // reordering the keys of the request keeps its canonical JSON
+        let mut reordered = serde_json::Map::new();
+        for key in ["context", "resource", "action", "principal"] {
+            reordered.insert(key.to_string(), signed.request[key].clone());
+        }
+        let reordered = SignedRequest {
+            request: serde_json::Value::Object(reordered),
+            ..signed
+        };
+        assert!(verifier().verify(&reordered, None).is_ok());
+    }
+
+    #[test]
+    fn rejects_spoofed_principals() {
+        let wallet = wallet(KEY);
+        let other = self::wallet(OTHER_KEY);
+        let signed = sign(EntityUid::account(1, other.address()), &wallet);
+        assert!(matches!(
+            verifier().verify(&signed, None),
+            Err(SignedRequestError::SignerMismatch { signer, .. }) if signer == wallet.address()
+        ));
+
+        let mut tampered = sign(EntityUid::account(1, wallet.address()), &wallet);
+        tampered.request["context"]["amount"] = 1_000_000.into();
+        assert!(matches!(
+            verifier().verify(&tampered, None),
+            Err(SignedRequestError::SignerMismatch { .. })
+        ));
+
+        let signed = sign(r#"User::"alice""#.parse().expect("valid uid"), &wallet);
+        assert!(matches!(
+            verifier().verify(&signed, None),
+            Err(SignedRequestError::PrincipalNotAnAddress(_))
+        ));
+
+        // an account of another chain than the domain is not accepted
+        let signed = sign(EntityUid::account(10, wallet.address()), &wallet);
+        assert!(matches!(
+            verifier().verify(&signed, None),
+            Err(SignedRequestError::PrincipalNotAnAddress(_))
+        ));
+
+        let mut malformed = signed;
+        malformed.signature = "0x1234".to_string();
+        assert!(matches!(
+            verifier().verify(&malformed, None),
+            Err(SignedRequestError::Signature(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_replays() {
+        let wallet = wallet(KEY);
+        let principal = EntityUid::account(1, wallet.address());
+        let signed = sign(principal.clone(), &wallet);
+        let verifier = verifier();
+        assert!(verifier.verify(&signed, None).is_ok());
+        assert!(matches!(
+            verifier.verify(&signed, None),
+            Err(SignedRequestError::Replayed(nonce)) if nonce == signed.claims.nonce
+        ));
+        // once it expires, it is rejected as expired rather than remembered
+        let later = signed.claims.expires_at + 3600;
+        assert!(matches!(
+            verifier.verify_at(&signed, None, later),
+            Err(SignedRequestError::Expired(_))
+        ));
+
+        // another domain or audience does not accept the signature
+        let elsewhere = RequestVerifier::new(SigningDomain::new("other", "1", 1), AUDIENCE);
+        assert!(matches!(
+            elsewhere.verify(&sign(principal.clone(), &wallet), None),
+            Err(SignedRequestError::SignerMismatch { .. })
+        ));
+        let elsewhere = RequestVerifier::new(domain(), "https://other.example.com");
+        assert!(matches!(
+            elsewhere.verify(&sign(principal.clone(), &wallet), None),
+            Err(SignedRequestError::WrongAudience { .. })
+        ));
+
+        // changing the claims invalidates the signature
+        let mut extended = sign(principal.clone(), &wallet);
+        extended.claims.expires_at += 120;
+        assert!(matches!(
+            verifier.verify(&extended, None),
+            Err(SignedRequestError::SignerMismatch { .. })
+        ));
+
+        let mut long = claims();
+        long.expires_at = long.issued_at + 3600;
+        let signed = SignedRequest::sign(&request(principal.clone()), long, &domain(), &wallet)
+            .expect("signs");
+        assert!(matches!(
+            verifier.verify(&signed, None),
+            Err(SignedRequestError::LifetimeTooLong { .. })
+        ));
+
+        let mut future = claims();
+        future.issued_at += 3600;
+        future.expires_at += 3600;
+        let signed =
+            SignedRequest::sign(&request(principal), future, &domain(), &wallet).expect("signs");
+        assert!(matches!(
+            verifier.verify(&signed, None),
+            Err(SignedRequestError::NotYetValid(_))
+        ));
+    }
+}
@@ -0,0 +1,2036 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Policy sets and entities which are read from sources that can change, and
+//! reloaded into a running authorizer without restarting it.
+//!
+//! A [`Source`] fetches the text of a policy set or of entities, along with
+//! its version, and skips the fetch when the version has not changed. The
+//! sources are:
+//! * [`FileSource`]: a file, whose version is the hash of its contents
+//! * `UrlSource`, behind the `sources-remote` feature: an HTTP(S) URL, such as
+//!   an S3 or GCS object, whose version is its `ETag` if it has one
+//! * `IpfsSource`, behind the `sources-remote` feature: an `/ipfs/` or
+//!   `/ipns/` path, fetched through a gateway
+//! * `ContractSource`, behind the `sources-remote` feature: the `string`
+//!   returned by a view function of a contract, read with `eth_call`
+//!
+//! [`from_uri`] makes the source of a URI, like `s3://bucket/policies.cedar`
+//! or a path, and `SignedSource`, behind the `sources-signed` feature, checks
+//! that the text of a source was signed by the address which publishes it.
+//! `EncryptedSource`, behind the `sources-encrypted` feature, decrypts the
+//! text of a source encrypted with age or AES-256-GCM, in memory only, for
+//! policies which should not be stored in plaintext.
+//!
+//! A [`PolicySource`] or [`EntitySource`] parses what its source fetches.
+//! [`ReloadingAuthorizer`] authorizes requests against the policies and
+//! entities last loaded from them. [`ReloadingAuthorizer::refresh`] fetches
+//! both sources, and if either changed and both still parse, swaps them in as
+//! a whole, so that requests being authorized keep the snapshot they started
+//! with, and then calls the listeners added with
+//! [`ReloadingAuthorizer::on_change`]. If a source fails to fetch or parse,
+//! the snapshot loaded before is kept.
+//!
+//! [`ReloadingAuthorizer::watch`] refreshes on a background thread, every
+//! interval and whenever [`Watcher::trigger`] is called, such as when a
+//! webhook or a contract event announces a change.
+//!
+//! ```
+//! # use cedar_policy::{sources::*, Context, Decision, Request};
+//! # use std::sync::Arc;
+//! # let dir = tempfile::tempdir().unwrap();
+//! # let policies_file = dir.path().join("policies.cedar");
+//! # let entities_file = dir.path().join("entities.json");
+//! std::fs::write(&policies_file, "forbid(principal, action, resource);").unwrap();
+//! std::fs::write(&entities_file, "[]").unwrap();
+//! let authorizer = ReloadingAuthorizer::new(
+//!     PolicySource::new(FileSource::new(&policies_file)),
+//!     EntitySource::new(FileSource::new(&entities_file)),
+//! )
+//! .unwrap();
+//! let request = Request::new(None, None, None, Context::empty());
+//! assert_eq!(authorizer.is_authorized(&request).decision(), Decision::Deny);
+//!
+//! std::fs::write(&policies_file, "permit(principal, action, resource);").unwrap();
+//! assert!(authorizer.refresh().unwrap().is_some());
+//! assert_eq!(authorizer.is_authorized(&request).decision(), Decision::Allow);
+//! ```
+
+use std::{
+    fmt::{self, Debug, Display},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{mpsc, Arc, PoisonError, RwLock},
+    thread,
+    time::Duration,
+};
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::{
+    Authorizer, Entities, EntitiesError, Origin, ParseErrors, PolicySet, Request, Response, Schema,
+};
+
+/// Errors fetching or parsing a source
+#[derive(Debug, Error)]
+pub enum SourceError {
+    /// A file could not be read
+    #[error("failed to read `{}`: {error}", .path.display())]
+    Io {
+        /// The path of the file
+        path: PathBuf,
+        /// The underlying error
+        error: std::io::Error,
+    },
+    /// A URL could not be fetched, or a contract could not be called
+    #[error("failed to fetch {origin}: {message}")]
+    Fetch {
+        /// The source
+        origin: String,
+        /// What went wrong
+        message: String,
+    },
+    /// The policies of a source do not parse
+    #[error("failed to parse the policies of {origin}: {error}")]
+    Policies {
+        /// The source
+        origin: String,
+        /// The parse errors
+        error: Box<ParseErrors>,
+    },
+    /// The entities of a source do not parse
+    #[error("failed to parse the entities of {origin}: {error}")]
+    Entities {
+        /// The source
+        origin: String,
+        /// The underlying error
+        error: Box<EntitiesError>,
+    },
+    /// The text of a `SignedSource` is not signed by its signer
+    #[error("failed to verify the signature of {origin}: {message}")]
+    Signature {
+        /// The source
+        origin: String,
+        /// What went wrong
+        message: String,
+    },
+    /// The text of an `EncryptedSource` could not be decrypted
+    #[error("failed to decrypt {origin}: {message}")]
+    Decryption {
+        /// The source
+        origin: String,
+        /// What went wrong
+        message: String,
+    },
+}
+
+/// The text of a source at some version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fetched {
+    /// The text
+    pub text: String,
+    /// The version of the text, which changes whenever the text does
+    pub version: String,
+}
+
+/// Where the text of a policy set or of entities comes from
+pub trait Source: Display + Send + Sync {
+    /// Fetch the text, or `None` if its version is still `current`
+    ///
+    /// # Errors
+    ///
+    /// If the text cannot be fetched.
+    fn fetch(&self, current: Option<&str>) -> Result<Option<Fetched>, SourceError>;
+
+    /// Where the text comes from, recorded as the [`Origin`] of the policies
+    /// loaded from it
+    fn origin(&self) -> Option<Origin> {
+        None
+    }
+}
+
+impl<S: Source + ?Sized> Source for Box<S> {
+    fn fetch(&self, current: Option<&str>) -> Result<Option<Fetched>, SourceError> {
+        (**self).fetch(current)
+    }
+
+    fn origin(&self) -> Option<Origin> {
+        (**self).origin()
+    }
+}
+
+/// The source at `uri`:
+/// * an `s3://<bucket>/<key>` or `gs://<bucket>/<key>` URI, or an `http://` or
+///   `https://` URL, is a `UrlSource` (see `UrlSource::from_uri`)
+/// * an `ipfs://` or `ipns://` URI is an `IpfsSource` fetched through the
+///   default gateway
+/// * a `file://` URI, or anything else, is the path of a [`FileSource`]
+///
+/// # Errors
+///
+/// If the URI has another scheme, or a remote scheme without the
+/// `sources-remote` feature, or the HTTP client cannot be created.
+pub fn from_uri(uri: &str) -> Result<Box<dyn Source>, SourceError> {
+    let Some((scheme, rest)) = uri.split_once("://") else {
+        return Ok(Box::new(FileSource::new(uri)));
+    };
+    match scheme {
+        "file" => Ok(Box::new(FileSource::new(rest))),
+        #[cfg(feature = "sources-remote")]
+        "s3" | "gs" | "http" | "https" => Ok(Box::new(UrlSource::from_uri(uri)?)),
+        #[cfg(feature = "sources-remote")]
+        "ipfs" | "ipns" => Ok(Box::new(IpfsSource::new(uri)?)),
+        #[cfg(not(feature = "sources-remote"))]
+        "s3" | "gs" | "http" | "https" | "ipfs" | "ipns" => Err(SourceError::Fetch {
+            origin: format!("`{uri}`"),
+            message: format!("fetching `{scheme}://` URIs needs the `sources-remote` feature"),
+        }),
+        _ => Err(SourceError::Fetch {
+            origin: format!("`{uri}`"),
+            message: format!("unsupported URI scheme `{scheme}`"),
+        }),
+    }
+}
+
+/// The version of `text` when it has no other: its SHA-256 hash
+fn hash_version(text: &str) -> String {
+    format!("sha256:{}", hex::encode(Sha256::digest(text.as_bytes())))
+}
+
+/// `text` at its hash version, or `None` if that is `current`
+fn fetched_by_hash(text: String, current: Option<&str>) -> Option<Fetched> {
+    let version = hash_version(&text);
+    (current != Some(version.as_str())).then_some(Fetched { text, version })
+}
+
+/// A file, whose version is the hash of its contents
+#[derive(Debug, Clone)]
+pub struct FileSource {
+    path: PathBuf,
+}
+
+impl FileSource {
+    /// The file at `path`
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl Display for FileSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}`", self.path.display())
+    }
+}
+
+impl Source for FileSource {
+    fn fetch(&self, current: Option<&str>) -> Result<Option<Fetched>, SourceError> {
+        let text = std::fs::read_to_string(&self.path).map_err(|error| SourceError::Io {
+            path: self.path.clone(),
+            error,
+        })?;
+        Ok(fetched_by_hash(text, current))
+    }
+
+    fn origin(&self) -> Option<Origin> {
+        Some(Origin::File(self.path.clone()))
+    }
+}
+
+/// A source of a policy set, in the Cedar syntax
+pub struct PolicySource {
+    source: Box<dyn Source>,
+}
+
+impl PolicySource {
+    /// The policy set fetched from `source`
+    pub fn new(source: impl Source + 'static) -> Self {
+        Self {
+            source: Box::new(source),
+        }
+    }
+
+    /// The policy set and its version, or `None` if the version is still
+    /// `current`. The policy set records the origin of the source as the
+    /// provenance of its policies.
+    ///
+    /// # Errors
+    ///
+    /// If the policy set cannot be fetched or does not parse.
+    pub fn load(&self, current: Option<&str>) -> Result<Option<(PolicySet, String)>, SourceError> {
+        self.source
+            .fetch(current)?
+            .map(|fetched| {
+                PolicySet::from_str(&fetched.text)
+                    .map(|mut policies| {
+                        if let Some(origin) = self.source.origin() {
+                            policies.record_origin(&origin);
+                        }
+                        (policies, fetched.version)
+                    })
+                    .map_err(|error| SourceError::Policies {
+                        origin: self.source.to_string(),
+                        error: Box::new(error),
+                    })
+            })
+            .transpose()
+    }
+}
+
+impl Debug for PolicySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PolicySource({})", self.source)
+    }
+}
+
+/// A source of entities, in the JSON format
+pub struct EntitySource {
+    source: Box<dyn Source>,
+    schema: Option<Schema>,
+}
+
+impl EntitySource {
+    /// The entities fetched from `source`
+    pub fn new(source: impl Source + 'static) -> Self {
+        Self {
+            source: Box::new(source),
+            schema: None,
+        }
+    }
+
+    /// Parse the entities with `schema`
+    #[must_use]
+    pub fn with_schema(mut self, schema: Schema) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// The entities and their version, or `None` if the version is still
+    /// `current`
+    ///
+    /// # Errors
+    ///
+    /// If the entities cannot be fetched or do not parse.
+    pub fn load(&self, current: Option<&str>) -> Result<Option<(Entities, String)>, SourceError> {
+        self.source
+            .fetch(current)?
+            .map(|fetched| {
+                Entities::from_json_str(&fetched.text, self.schema.as_ref())
+                    .map(|entities| (entities, fetched.version))
+                    .map_err(|error| SourceError::Entities {
+                        origin: self.source.to_string(),
+                        error: Box::new(error),
+                    })
+            })
+            .transpose()
+    }
+}
+
+impl Debug for EntitySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EntitySource({})", self.source)
+    }
+}
+
+/// The policies and entities loaded at some point, with their versions
+#[derive(Debug)]
+pub struct Snapshot {
+    /// The policy set
+    pub policies: PolicySet,
+    /// The version of the policy set
+    pub policy_version: String,
+    /// The entities
+    pub entities: Entities,
+    /// The version of the entities
+    pub entity_version: String,
+}
+
+/// What a refresh changed. A version is `Some` if that source changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// The new version of the policy set
+    pub policy_version: Option<String>,
+    /// The new version of the entities
+    pub entity_version: Option<String>,
+}
+
+type Listener<T> = Box<dyn Fn(&T) + Send + Sync>;
+
+/// Authorizes requests against the policies and entities last loaded from a
+/// [`PolicySource`] and an [`EntitySource`]
+pub struct ReloadingAuthorizer {
+    authorizer: Authorizer,
+    policies: PolicySource,
+    entities: EntitySource,
+    /// Replaced as a whole by `refresh`
+    snapshot: RwLock<Arc<Snapshot>>,
+    change_listeners: RwLock<Vec<Listener<Change>>>,
+    error_listeners: RwLock<Vec<Listener<SourceError>>>,
+}
+
+impl ReloadingAuthorizer {
+    /// Authorize requests against the policies and entities of `policies` and
+    /// `entities`, loading them now
+    ///
+    /// # Errors
+    ///
+    /// If either source cannot be fetched or does not parse.
+    pub fn new(policies: PolicySource, entities: EntitySource) -> Result<Self, SourceError> {
+        let (policy_set, policy_version) = policies.load(None)?.unwrap_or_default();
+        let (entity_set, entity_version) = entities.load(None)?.unwrap_or_default();
+        Ok(Self {
+            authorizer: Authorizer::new(),
+            policies,
+            entities,
+            snapshot: RwLock::new(Arc::new(Snapshot {
+                policies: policy_set,
+                policy_version,
+                entities: entity_set,
+                entity_version,
+            })),
+            change_listeners: RwLock::new(Vec::new()),
+            error_listeners: RwLock::new(Vec::new()),
+        })
+    }
+
+    /// The policies and entities currently used
+    pub fn snapshot(&self) -> Arc<Snapshot> {
+        self.snapshot
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Authorize `request` against the current snapshot
+    pub fn is_authorized(&self, request: &Request) -> Response {
+        let snapshot = self.snapshot();
+        self.authorizer
+            .is_authorized(request, &snapshot.policies, &snapshot.entities)
+    }
+
+    /// Call `listener` with every change swapped in by a refresh
+    pub fn on_change(&self, listener: impl Fn(&Change) + Send + Sync + 'static) {
+        self.change_listeners
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Box::new(listener));
+    }
+
+    /// Call `listener` with the errors of the refreshes of a [`Watcher`],
+    /// which has no caller to return them to
+    pub fn on_error(&self, listener: impl Fn(&SourceError) + Send + Sync + 'static) {
+        self.error_listeners
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Box::new(listener));
+    }
+
+    /// Fetch both sources, and if either changed, swap the new snapshot in
+    /// and call the change listeners. Returns what changed, if anything.
+    ///
+    /// # Errors
+    ///
+    /// If either source cannot be fetched or does not parse, in which case
+    /// the current snapshot is kept.
+    pub fn refresh(&self) -> Result<Option<Change>, SourceError> {
+        let current = self.snapshot();
+        let policies = self.policies.load(Some(&current.policy_version))?;
+        let entities = self.entities.load(Some(&current.entity_version))?;
+        if policies.is_none() && entities.is_none() {
+            return Ok(None);
+        }
+        let change = Change {
+            policy_version: policies.as_ref().map(|(_, version)| version.clone()),
+            entity_version: entities.as_ref().map(|(_, version)| version.clone()),
+        };
+        let (policies, policy_version) =
+            policies.unwrap_or_else(|| (current.policies.clone(), current.policy_version.clone()));
+        let (entities, entity_version) =
+            entities.unwrap_or_else(|| (current.entities.clone(), current.entity_version.clone()));
+        *self
+            .snapshot
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Arc::new(Snapshot {
+            policies,
+            policy_version,
+            entities,
+            entity_version,
+        });
+        for listener in self
+            .change_listeners
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+        {
+            listener(&change);
+        }
+        Ok(Some(change))
+    }
+
+    /// Refresh on a background thread every `interval`, and whenever the
+    /// returned [`Watcher`] is triggered, until it is dropped. Errors are
+    /// passed to the listeners added with [`Self::on_error`].
+    pub fn watch(self: &Arc<Self>, interval: Duration) -> Watcher {
+        let (trigger, triggered) = mpsc::channel();
+        let authorizer = Arc::clone(self);
+        let thread = thread::spawn(move || {
+            // both a trigger and a timeout refresh, until the watcher is dropped
+            while let Ok(()) | Err(mpsc::RecvTimeoutError::Timeout) =
+                triggered.recv_timeout(interval)
+            {
+                if let Err(error) = authorizer.refresh() {
+                    for listener in authorizer
+                        .error_listeners
+                        .read()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .iter()
+                    {
+                        listener(&error);
+                    }
+                }
+            }
+        });
+        Watcher {
+            trigger: Some(trigger),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Debug for ReloadingAuthorizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReloadingAuthorizer")
+            .field("policies", &self.policies)
+            .field("entities", &self.entities)
+            .field("snapshot", &self.snapshot())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Refreshes a [`ReloadingAuthorizer`] on a background thread, until it is
+/// dropped
+#[derive(Debug)]
+pub struct Watcher {
+    trigger: Option<mpsc::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Watcher {
+    /// Refresh now, rather than at the end of the interval, such as when a
+    /// source has announced a change
+    pub fn trigger(&self) {
+        if let Some(trigger) = &self.trigger {
+            // the thread only stops once this is dropped
+            let _ = trigger.send(());
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        // disconnecting the channel stops the thread after its current refresh
+        drop(self.trigger.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A source whose text must be signed by an address, such as the key a CI
+/// pipeline publishes policies with, checked on every fetch
+///
+/// Each text is signed together with a sequence number, which the publisher
+/// increases with every text it signs. The signature is fetched from a second
+/// source, such as the object of the URL of the text followed by `.sig`, and
+/// is the sequence number followed by whitespace and an EIP-191
+/// (`personal_sign`) signature, in hex, of the sequence number, a newline and
+/// the text, as written by [`SignedSource::sign`]. A text which is not signed
+/// by the signer fails to fetch, so that the text loaded before is kept.
+///
+/// So that an old text cannot be replayed with its old signature, a text
+/// whose sequence number is below that of the text last fetched, or below the
+/// minimum given with [`SignedSource::with_min_sequence`], fails to fetch too,
+/// as does a different text with the same sequence number.
+#[cfg(feature = "sources-signed")]
+pub struct SignedSource {
+    source: Box<dyn Source>,
+    signature: Box<dyn Source>,
+    signer: ethers::types::Address,
+    min_sequence: u64,
+    /// The sequence number and hash of the text last fetched
+    fetched: std::sync::Mutex<Option<(u64, Vec<u8>)>>,
+}
+
+/// The message signed for `text` with the sequence number `sequence`
+#[cfg(feature = "sources-signed")]
+fn signed_message(sequence: u64, text: &str) -> String {
+    format!("{sequence}\n{text}")
+}
+
+#[cfg(feature = "sources-signed")]
+impl SignedSource {
+    /// The text of `source`, which must be signed by `signer` with the
+    /// signature fetched from `signature`
+    pub fn new(
+        source: impl Source + 'static,
+        signature: impl Source + 'static,
+        signer: ethers::types::Address,
+    ) -> Self {
+        Self {
+            source: Box::new(source),
+            signature: Box::new(signature),
+            signer,
+            min_sequence: 0,
+            fetched: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Reject texts whose sequence number is below `sequence`, such as the
+    /// sequence number of the text fetched before a restart, from
+    /// [`SignedSource::sequence`]
+    #[must_use]
+    pub fn with_min_sequence(mut self, sequence: u64) -> Self {
+        self.min_sequence = sequence;
+        self
+    }
+
+    /// The signature of `text` by `wallet` with the sequence number
+    /// `sequence`, to publish along with it
+    ///
+    /// # Errors
+    ///
+    /// If signing fails.
+    pub fn sign(
+        text: &str,
+        sequence: u64,
+        wallet: &ethers::signers::LocalWallet,
+    ) -> Result<String, SourceError> {
+        wallet
+            .sign_hash(ethers::utils::hash_message(signed_message(sequence, text)))
+            .map(|signature| format!("{sequence} 0x{signature}"))
+            .map_err(|e| SourceError::Signature {
+                origin: "the text".to_string(),
+                message: e.to_string(),
+            })
+    }
+
+    /// The signer the text must be signed by
+    pub fn signer(&self) -> ethers::types::Address {
+        self.signer
+    }
+
+    /// The sequence number of the text last fetched, if any
+    pub fn sequence(&self) -> Option<u64> {
+        self.fetched
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .map(|(sequence, _)| *sequence)
+    }
+}
+
+#[cfg(feature = "sources-signed")]
+impl Display for SignedSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(feature = "sources-signed")]
+impl Debug for SignedSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SignedSource({}, signed by {:?} in {})",
+            self.source, self.signer, self.signature
+        )
+    }
+}
+
+#[cfg(feature = "sources-signed")]
+impl Source for SignedSource {
+    fn fetch(&self, current: Option<&str>) -> Result<Option<Fetched>, SourceError> {
+        let error = |message: String| SourceError::Signature {
+            origin: self.to_string(),
+            message,
+        };
+        let Some(fetched) = self.source.fetch(current)? else {
+            return Ok(None);
+        };
+        let signature = self
+            .signature
+            .fetch(None)?
+            .ok_or_else(|| error(format!("{} returned no signature", self.signature)))?;
+        let (sequence, signature) = signature
+            .text
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| {
+                error("malformed signature: expected a sequence number and a signature".into())
+            })?;
+        let sequence: u64 = sequence
+            .parse()
+            .map_err(|e| error(format!("malformed sequence number `{sequence}`: {e}")))?;
+        let signer = ethers::types::Signature::from_str(signature.trim())
+            .map_err(|e| error(format!("malformed signature: {e}")))?
+            .recover(ethers::utils::hash_message(signed_message(
+                sequence,
+                &fetched.text,
+            )))
+            .map_err(|e| error(e.to_string()))?;
+        if signer != self.signer {
+            return Err(error(format!(
+                "signed by `{signer:?}`, not by `{:?}`",
+                self.signer
+            )));
+        }
+        if sequence < self.min_sequence {
+            return Err(error(format!(
+                "sequence number {sequence} is below the minimum {}",
+                self.min_sequence
+            )));
+        }
+        let hash = Sha256::digest(&fetched.text).to_vec();
+        let mut last = self.fetched.lock().unwrap_or_else(PoisonError::into_inner);
+        match last.as_ref() {
+            Some((last, _)) if sequence < *last => {
+                return Err(error(format!(
+                    "sequence number {sequence} rolls back from {last}"
+                )));
+            }
+            Some((last, last_hash)) if sequence == *last && hash != *last_hash => {
+                return Err(error(format!(
+                    "sequence number {sequence} was already used for another text"
+                )));
+            }
+            _ => {}
+        }
+        *last = Some((sequence, hash));
+        drop(last);
+        Ok(Some(fetched))
+    }
+
+    fn origin(&self) -> Option<Origin> {
+        self.source.origin()
+    }
+}
+
+/// A key the text of an [`EncryptedSource`] is decrypted with
+///
+/// A key parses from an age X25519 identity, `AGE-SECRET-KEY-1…`, or from a
+/// 256-bit AES key, in hex or base64.
+#[cfg(feature = "sources-encrypted")]
+pub enum DecryptionKey {
+    /// An age identity, for texts encrypted to its recipient with
+    /// `age --armor`
+    Age(age::x25519::Identity),
+    /// An AES key, for texts which are the base64 of a 96-bit nonce followed
+    /// by the AES-256-GCM ciphertext and tag
+    Aes256Gcm([u8; 32]),
+}
+
+#[cfg(feature = "sources-encrypted")]
+impl DecryptionKey {
+    /// The key in the environment variable `var`
+    ///
+    /// # Errors
+    ///
+    /// If the variable is not set, or is not a key.
+    pub fn from_env(var: &str) -> Result<Self, SourceError> {
+        std::env::var(var)
+            .map_err(|e| SourceError::Decryption {
+                origin: format!("`${var}`"),
+                message: e.to_string(),
+            })?
+            .parse()
+    }
+
+    /// `text` encrypted with the key, in the format it decrypts, to publish
+    ///
+    /// # Errors
+    ///
+    /// If encrypting fails.
+    pub fn encrypt(&self, text: &str) -> Result<String, SourceError> {
+        use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+        use base64::Engine;
+
+        let error = |message: String| SourceError::Decryption {
+            origin: "the text".to_string(),
+            message,
+        };
+        match self {
+            Self::Age(identity) => age::encrypt_and_armor(&identity.to_public(), text.as_bytes())
+                .map_err(|e| error(e.to_string())),
+            Self::Aes256Gcm(key) => {
+                let cipher = aes_gcm::Aes256Gcm::new(key.into());
+                let nonce = aes_gcm::Aes256Gcm::generate_nonce(&mut OsRng);
+                let mut sealed = nonce.to_vec();
+                sealed.extend(
+                    cipher
+                        .encrypt(&nonce, text.as_bytes())
+                        .map_err(|e| error(e.to_string()))?,
+                );
+                Ok(base64::engine::general_purpose::STANDARD.encode(sealed))
+            }
+        }
+    }
+
+    /// The plaintext of `text`
+    fn decrypt(&self, text: &str) -> Result<String, String> {
+        use aes_gcm::aead::{Aead, KeyInit};
+        use base64::Engine;
+
+        let plaintext = match self {
+            Self::Age(identity) => {
+                age::decrypt(identity, text.as_bytes()).map_err(|e| e.to_string())?
+            }
+            Self::Aes256Gcm(key) => {
+                let sealed = base64::engine::general_purpose::STANDARD
+                    .decode(text.trim())
+                    .map_err(|e| format!("malformed ciphertext: {e}"))?;
+                if sealed.len() < 12 {
+                    return Err("malformed ciphertext: too short".to_string());
+                }
+                let (nonce, ciphertext) = sealed.split_at(12);
+                aes_gcm::Aes256Gcm::new(key.into())
+                    .decrypt(nonce.into(), ciphertext)
+                    .map_err(|_| "wrong key, or the ciphertext was modified".to_string())?
+            }
+        };
+        String::from_utf8(plaintext).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(feature = "sources-encrypted")]
+impl FromStr for DecryptionKey {
+    type Err = SourceError;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        use base64::Engine;
+
+        let error = |message: String| SourceError::Decryption {
+            origin: "the key".to_string(),
+            message,
+        };
+        let key = key.trim();
+        if key.starts_with("AGE-SECRET-KEY-") {
+            return key
+                .parse()
+                .map(Self::Age)
+                .map_err(|e: &str| error(e.to_string()));
+        }
+        let bytes = hex::decode(key.strip_prefix("0x").unwrap_or(key))
+            .or_else(|_| base64::engine::general_purpose::STANDARD.decode(key))
+            .map_err(|_| error("not an age identity, nor a key in hex or base64".to_string()))?;
+        bytes
+            .try_into()
+            .map(Self::Aes256Gcm)
+            .map_err(|bytes: Vec<u8>| {
+                error(format!("an AES key has 32 bytes, not {}", bytes.len()))
+            })
+    }
+}
+
+#[cfg(feature = "sources-encrypted")]
+impl Debug for DecryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Age(identity) => write!(f, "DecryptionKey::Age({})", identity.to_public()),
+            Self::Aes256Gcm(_) => write!(f, "DecryptionKey::Aes256Gcm(..)"),
+        }
+    }
+}
+
+/// A source whose text is encrypted, such as guard policies which reveal
+/// operational thresholds
+///
+/// The text is decrypted in memory whenever it changes, and never written
+/// anywhere: a `UrlSource` with a cache directory caches the encrypted text.
+/// The key is either given, or fetched from a callback, such as one which
+/// asks a KMS to decrypt a data key, on every decryption, so that it is not
+/// kept in memory in between.
+#[cfg(feature = "sources-encrypted")]
+pub struct EncryptedSource {
+    source: Box<dyn Source>,
+    key: Box<dyn Fn() -> Result<DecryptionKey, SourceError> + Send + Sync>,
+}
+
+#[cfg(feature = "sources-encrypted")]
+impl EncryptedSource {
+    /// The text of `source`, decrypted with `key`
+    pub fn new(source: impl Source + 'static, key: DecryptionKey) -> Self {
+        let key = Arc::new(key);
+        Self::with_key_provider(source, move || match &*key {
+            DecryptionKey::Age(identity) => Ok(DecryptionKey::Age(identity.clone())),
+            DecryptionKey::Aes256Gcm(key) => Ok(DecryptionKey::Aes256Gcm(*key)),
+        })
+    }
+
+    /// The text of `source`, decrypted with the key returned by `key` when
+    /// the text changes
+    pub fn with_key_provider(
+        source: impl Source + 'static,
+        key: impl Fn() -> Result<DecryptionKey, SourceError> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            source: Box::new(source),
+            key: Box::new(key),
+        }
+    }
+}
+
+#[cfg(feature = "sources-encrypted")]
+impl Display for EncryptedSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(feature = "sources-encrypted")]
+impl Debug for EncryptedSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EncryptedSource({})", self.source)
+    }
+}
+
+#[cfg(feature = "sources-encrypted")]
+impl Source for EncryptedSource {
+    fn fetch(&self, current: Option<&str>) -> Result<Option<Fetched>, SourceError> {
+        let Some(fetched) = self.source.fetch(current)? else {
+            return Ok(None);
+        };
+        let text =
+            (self.key)()?
+                .decrypt(&fetched.text)
+                .map_err(|message| SourceError::Decryption {
+                    origin: self.to_string(),
+                    message,
+                })?;
+        Ok(Some(Fetched {
+            text,
+            version: fetched.version,
+        }))
+    }
+
+    fn origin(&self) -> Option<Origin> {
+        self.source.origin()
+    }
+}
+
+#[cfg(feature = "sources-remote")]
+pub use remote::*;
+
+/// Sources fetched over the network
+#[cfg(feature = "sources-remote")]
+mod remote {
+    use super::{
+        fetched_by_hash, fmt, hash_version, Digest, Display, Duration, Fetched, Origin, Source,
+        SourceError,
+    };
+    use crate::SnapshotInfo;
+    use reqwest::{blocking::Client, header, StatusCode};
+    use serde::{Deserialize, Serialize};
+    use sha2::Sha256;
+    use sha3::Keccak256;
+    use std::path::{Path, PathBuf};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// How long a fetch may take
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    fn client(origin: &str) -> Result<Client, SourceError> {
+        Client::builder()
+            .timeout(TIMEOUT)
+            .build()
+            .map_err(|e| SourceError::Fetch {
+                origin: origin.to_string(),
+                message: e.to_string(),
+            })
+    }
+
+    /// An HTTP(S) URL, whose version is its `ETag` if it has one, or else the
+    /// hash of its contents. A fetch sends the `ETag` in `If-None-Match`, so
+    /// an unchanged object is not downloaded again.
+    ///
+    /// This uses a blocking HTTP client, which must not be used from within an
+    /// async runtime, such as in a tokio task.
+    #[derive(Debug, Clone)]
+    pub struct UrlSource {
+        url: String,
+        client: Client,
+        cache_dir: Option<PathBuf>,
+    }
+
+    impl UrlSource {
+        /// The object at `url`
+        ///
+        /// # Errors
+        ///
+        /// If the HTTP client cannot be created.
+        pub fn new(url: impl Into<String>) -> Result<Self, SourceError> {
+            let url = url.into();
+            let client = client(&format!("`{url}`"))?;
+            Ok(Self {
+                url,
+                client,
+                cache_dir: None,
+            })
+        }
+
+        /// The object at `uri`: an `s3://<bucket>/<key>` or `gs://<bucket>/<key>`
+        /// URI (see [`Self::s3`] and [`Self::gcs`]), or an `http://` or
+        /// `https://` URL
+        ///
+        /// # Errors
+        ///
+        /// If the URI has another scheme or no key, or the HTTP client cannot
+        /// be created.
+        pub fn from_uri(uri: &str) -> Result<Self, SourceError> {
+            let error = |message: &str| SourceError::Fetch {
+                origin: format!("`{uri}`"),
+                message: message.to_string(),
+            };
+            let bucket_and_key = |rest: &str| {
+                rest.split_once('/')
+                    .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty())
+                    .map(|(bucket, key)| (bucket.to_string(), key.to_string()))
+                    .ok_or_else(|| error("expected a bucket and a key"))
+            };
+            if let Some(rest) = uri.strip_prefix("s3://") {
+                let (bucket, key) = bucket_and_key(rest)?;
+                Self::s3(&bucket, &key)
+            } else if let Some(rest) = uri.strip_prefix("gs://") {
+                let (bucket, key) = bucket_and_key(rest)?;
+                Self::gcs(&bucket, &key)
+            } else if uri.starts_with("http://") || uri.starts_with("https://") {
+                Self::new(uri)
+            } else {
+                Err(error(
+                    "expected an `s3://`, `gs://`, `http://` or `https://` URI",
+                ))
+            }
+        }
+
+        /// Keep the object last fetched, with its `ETag`, in the directory
+        /// `dir`, so that another process, such as the next run of a command,
+        /// downloads it again only if it changed. A cache which cannot be read
+        /// or written is ignored.
+        #[must_use]
+        pub fn with_cache_dir(mut self, dir: impl AsRef<Path>) -> Self {
+            self.cache_dir = Some(dir.as_ref().to_path_buf());
+            self
+        }
+
+        /// The object `key` of the S3 bucket `bucket`, which must be readable
+        /// without credentials, such as through a bucket policy. For a
+        /// private object, use [`Self::new`] with a presigned URL.
+        ///
+        /// # Errors
+        ///
+        /// If the HTTP client cannot be created.
+        pub fn s3(bucket: &str, key: &str) -> Result<Self, SourceError> {
+            Self::new(format!("https://{bucket}.s3.amazonaws.com/{key}"))
+        }
+
+        /// The object `key` of the GCS bucket `bucket`, which must be readable
+        /// without credentials. For a private object, use [`Self::new`] with a
+        /// signed URL.
+        ///
+        /// # Errors
+        ///
+        /// If the HTTP client cannot be created.
+        pub fn gcs(bucket: &str, key: &str) -> Result<Self, SourceError> {
+            Self::new(format!("https://storage.googleapis.com/{bucket}/{key}"))
+        }
+
+        /// The URL
+        pub fn url(&self) -> &str {
+            &self.url
+        }
+    }
+
+    impl Display for UrlSource {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "`{}`", self.url)
+        }
+    }
+
+    /// An object kept in the cache directory of a [`UrlSource`]
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Cached {
+        etag: String,
+        text: String,
+    }
+
+    impl UrlSource {
+        /// The file the object is kept in, named by the hash of the URL
+        fn cache_file(&self) -> Option<PathBuf> {
+            let name = hex::encode(Sha256::digest(self.url.as_bytes()));
+            self.cache_dir
+                .as_ref()
+                .map(|dir| dir.join(format!("{name}.json")))
+        }
+
+        fn cached(&self) -> Option<Cached> {
+            let json = std::fs::read_to_string(self.cache_file()?).ok()?;
+            serde_json::from_str(&json).ok()
+        }
+
+        fn cache(&self, cached: &Cached) {
+            if let (Some(dir), Some(file)) = (&self.cache_dir, self.cache_file()) {
+                let _ = std::fs::create_dir_all(dir).and_then(|()| {
+                    std::fs::write(file, serde_json::to_string(cached).unwrap_or_default())
+                });
+            }
+        }
+    }
+
+    impl Source for UrlSource {
+        fn fetch(&self, current: Option<&str>) -> Result<Option<Fetched>, SourceError> {
+            let error = |message: String| SourceError::Fetch {
+                origin: self.to_string(),
+                message,
+            };
+            // an object in the cache is only used if the server says it is
+            // still current
+            let cached = self.cached();
+            let sent = current
+                .and_then(|current| current.strip_prefix("etag:"))
+                .or_else(|| cached.as_ref().map(|cached| cached.etag.as_str()))
+                .map(str::to_string);
+            let mut request = self.client.get(&self.url);
+            if let Some(etag) = &sent {
+                request = request.header(header::IF_NONE_MATCH, etag);
+            }
+            let response = request.send().map_err(|e| error(e.to_string()))?;
+            if response.status() == StatusCode::NOT_MODIFIED {
+                return Ok(cached
+                    .filter(|cached| Some(&cached.etag) == sent.as_ref())
+                    .map(|cached| Fetched {
+                        version: format!("etag:{}", cached.etag),
+                        text: cached.text,
+                    })
+                    .filter(|fetched| current != Some(fetched.version.as_str())));
+            }
+            if !response.status().is_success() {
+                return Err(error(format!("the server responded {}", response.status())));
+            }
+            let etag = response
+                .headers()
+                .get(header::ETAG)
+                .and_then(|etag| etag.to_str().ok())
+                .map(str::to_string);
+            let text = response.text().map_err(|e| error(e.to_string()))?;
+            let Some(etag) = etag else {
+                return Ok(fetched_by_hash(text, current));
+            };
+            let cached = Cached { etag, text };
+            self.cache(&cached);
+            let version = format!("etag:{}", cached.etag);
+            Ok((current != Some(version.as_str())).then_some(Fetched {
+                text: cached.text,
+                version,
+            }))
+        }
+
+        fn origin(&self) -> Option<Origin> {
+            Some(Origin::Url(self.url.clone()))
+        }
+    }
+
+    /// The public gateway used by [`IpfsSource::new`]
+    pub const DEFAULT_IPFS_GATEWAY: &str = "https://ipfs.io";
+
+    /// A path in IPFS, like `/ipfs/<cid>/policies.cedar` or
+    /// `/ipns/<name>/policies.cedar`, fetched through a gateway
+    ///
+    /// An `/ipfs/` path is immutable, so it is only fetched once, and its
+    /// version is the path itself; an `/ipns/` path is fetched like a
+    /// [`UrlSource`].
+    #[derive(Debug, Clone)]
+    pub struct IpfsSource {
+        path: String,
+        url: UrlSource,
+    }
+
+    impl IpfsSource {
+        /// `path`, which may also be given as `ipfs://<cid>/...` or
+        /// `ipns://<name>/...`, fetched through [`DEFAULT_IPFS_GATEWAY`]
+        ///
+        /// # Errors
+        ///
+        /// If the path is neither an `/ipfs/` nor an `/ipns/` path, or the HTTP
+        /// client cannot be created.
+        pub fn new(path: &str) -> Result<Self, SourceError> {
+            Self::with_gateway(path, DEFAULT_IPFS_GATEWAY)
+        }
+
+        /// `path`, fetched through the gateway at `gateway`, like
+        /// `http://127.0.0.1:8080` for a local node
+        ///
+        /// # Errors
+        ///
+        /// If the path is neither an `/ipfs/` nor an `/ipns/` path, or the HTTP
+        /// client cannot be created.
+        pub fn with_gateway(path: &str, gateway: &str) -> Result<Self, SourceError> {
+            let path = if let Some(rest) = path.strip_prefix("ipfs://") {
+                format!("/ipfs/{rest}")
+            } else if let Some(rest) = path.strip_prefix("ipns://") {
+                format!("/ipns/{rest}")
+            } else if path.starts_with("/ipfs/") || path.starts_with("/ipns/") {
+                path.to_string()
+            } else {
+                return Err(SourceError::Fetch {
+                    origin: format!("`{path}`"),
+                    message: "expected an `/ipfs/` or `/ipns/` path".to_string(),
+                });
+            };
+            let url = UrlSource::new(format!("{}{path}", gateway.trim_end_matches('/')))?;
+            Ok(Self { path, url })
+        }
+
+        /// The URL the path is fetched from
+        pub fn url(&self) -> &str {
+            self.url.url()
+        }
+    }
+
+    impl Display for IpfsSource {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "`{}`", self.path)
+        }
+    }
+
+    impl Source for IpfsSource {
+        fn fetch(&self, current: Option<&str>) -> Result<Option<Fetched>, SourceError> {
+            if !self.path.starts_with("/ipfs/") {
+                return self.url.fetch(current);
+            }
+            let version = format!("ipfs:{}", self.path);
+            if current == Some(version.as_str()) {
+                return Ok(None);
+            }
+            Ok(self.url.fetch(None)?.map(|fetched| Fetched {
+                text: fetched.text,
+                version,
+            }))
+        }
+
+        fn origin(&self) -> Option<Origin> {
+            Some(Origin::Url(self.path.clone()))
+        }
+    }
+
+    /// The `string` returned by a view function of a contract, such as
+    /// `policies()`, read with `eth_call` at the latest block. Its version is
+    /// the hash of the string.
+    #[derive(Debug, Clone)]
+    pub struct ContractSource {
+        rpc_url: String,
+        address: String,
+        function: String,
+        snapshot_header: bool,
+        client: Client,
+    }
+
+    impl ContractSource {
+        /// The string returned by `function`, a signature without arguments
+        /// like `policies()`, of the contract at `address`, read through the
+        /// JSON-RPC endpoint at `rpc_url`
+        ///
+        /// # Errors
+        ///
+        /// If the HTTP client cannot be created.
+        pub fn new(
+            rpc_url: impl Into<String>,
+            address: impl Into<String>,
+            function: impl Into<String>,
+        ) -> Result<Self, SourceError> {
+            let address = address.into();
+            let client = client(&format!("the contract at `{address}`"))?;
+            Ok(Self {
+                rpc_url: rpc_url.into(),
+                address,
+                function: function.into(),
+                snapshot_header: false,
+                client,
+            })
+        }
+
+        /// Read the string at a block whose chain id, number and hash are
+        /// written, with the time of the fetch, into the `snapshot` header of
+        /// the string, which must be entities JSON. The header is then
+        /// surfaced by [`Entities::snapshot_info`](crate::Entities::snapshot_info).
+        ///
+        /// The version is still the hash of the string alone, so the header
+        /// tells the block the entities were last fetched at, not every block
+        /// they are unchanged at.
+        #[must_use]
+        pub fn with_snapshot_header(mut self) -> Self {
+            self.snapshot_header = true;
+            self
+        }
+
+        /// The call data of the function: its selector
+        fn call_data(&self) -> String {
+            let hash = Keccak256::digest(self.function.as_bytes());
+            format!("0x{}", hex::encode(hash.get(..4).unwrap_or_default()))
+        }
+    }
+
+    impl Display for ContractSource {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "`{}` of the contract at `{}`",
+                self.function, self.address
+            )
+        }
+    }
+
+    impl ContractSource {
+        fn error(&self, message: String) -> SourceError {
+            SourceError::Fetch {
+                origin: self.to_string(),
+                message,
+            }
+        }
+
+        /// The result of calling `method` of the node with `params`
+        fn rpc(
+            &self,
+            method: &str,
+            params: &serde_json::Value,
+        ) -> Result<serde_json::Value, SourceError> {
+            let call = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            });
+            let mut response: serde_json::Value = self
+                .client
+                .post(&self.rpc_url)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(call.to_string())
+                .send()
+                .and_then(reqwest::blocking::Response::error_for_status)
+                .map_err(|e| self.error(e.to_string()))?
+                .json()
+                .map_err(|e| self.error(e.to_string()))?;
+            if let Some(rpc_error) = response.get("error") {
+                return Err(self.error(format!("the node returned {rpc_error}")));
+            }
+            match response.get_mut("result").map(serde_json::Value::take) {
+                None | Some(serde_json::Value::Null) => {
+                    Err(self.error(format!("the node returned no result for `{method}`")))
+                }
+                Some(result) => Ok(result),
+            }
+        }
+
+        /// The latest block, as its header
+        fn latest_block(&self) -> Result<SnapshotInfo, SourceError> {
+            let quantity = |value: Option<&serde_json::Value>| {
+                value
+                    .and_then(serde_json::Value::as_str)
+                    .and_then(|hex| u64::from_str_radix(hex.trim_start_matches("0x"), 16).ok())
+            };
+            let chain_id = self.rpc("eth_chainId", &serde_json::json!([]))?;
+            let block = self.rpc(
+                "eth_getBlockByNumber",
+                &serde_json::json!(["latest", false]),
+            )?;
+            let block_number = quantity(block.get("number"))
+                .ok_or_else(|| self.error("the node returned a block without a number".into()))?;
+            let fetched_at_ms = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .and_then(|elapsed| u64::try_from(elapsed.as_millis()).ok());
+            Ok(SnapshotInfo {
+                chain_id: quantity(Some(&chain_id)),
+                block_number: Some(block_number),
+                block_hash: block
+                    .get("hash")
+                    .and_then(serde_json::Value::as_str)
+                    .map(str::to_string),
+                fetched_at_ms,
+            })
+        }
+    }
+
+    impl Source for ContractSource {
+        fn fetch(&self, current: Option<&str>) -> Result<Option<Fetched>, SourceError> {
+            let snapshot = self
+                .snapshot_header
+                .then(|| self.latest_block())
+                .transpose()?;
+            let block = snapshot
+                .as_ref()
+                .and_then(|info| info.block_number)
+                .map_or_else(|| "latest".to_string(), |number| format!("0x{number:x}"));
+            let result = self.rpc(
+                "eth_call",
+                &serde_json::json!([{ "to": self.address, "data": self.call_data() }, block]),
+            )?;
+            let result = result
+                .as_str()
+                .ok_or_else(|| self.error("the result is not a string".to_string()))?;
+            let bytes = hex::decode(result.trim_start_matches("0x"))
+                .map_err(|e| self.error(format!("the result is not hex: {e}")))?;
+            let text = decode_string(&bytes).map_err(|e| self.error(e))?;
+            let Some(snapshot) = snapshot else {
+                return Ok(fetched_by_hash(text, current));
+            };
+            let version = hash_version(&text);
+            if current == Some(version.as_str()) {
+                return Ok(None);
+            }
+            Ok(Some(Fetched {
+                text: with_header(text, &snapshot),
+                version,
+            }))
+        }
+
+        fn origin(&self) -> Option<Origin> {
+            Some(Origin::Contract {
+                address: self.address.clone(),
+                tx_hash: None,
+            })
+        }
+    }
+
+    /// The entities JSON `text` with `snapshot` as its header, or `text` as is
+    /// if it is not entities JSON, to fail to load as such
+    fn with_header(text: String, snapshot: &SnapshotInfo) -> String {
+        let snapshot = serde_json::json!(snapshot);
+        match serde_json::from_str(&text) {
+            Ok(serde_json::Value::Array(entities)) => {
+                serde_json::json!({ "snapshot": snapshot, "entities": entities }).to_string()
+            }
+            Ok(serde_json::Value::Object(mut document)) => {
+                document.insert("snapshot".to_string(), snapshot);
+                serde_json::Value::Object(document).to_string()
+            }
+            _ => text,
+        }
+    }
+
+    /// Decode the ABI encoding of the `string` returned by a function: the
+    /// offset of the string, its length, and its bytes
+    pub(super) fn decode_string(bytes: &[u8]) -> Result<String, String> {
+        let word = |at: usize| -> Result<usize, String> {
+            let word = bytes
+                .get(at..at + 32)
+                .ok_or_else(|| "the result is too short".to_string())?;
+            // no string fits in memory if any of the high bytes are set
+            let (high, low) = word.split_at(24);
+            if high.iter().any(|byte| *byte != 0) {
+                return Err("the result is too long".to_string());
+            }
+            let mut be = [0; 8];
+            be.copy_from_slice(low);
+            usize::try_from(u64::from_be_bytes(be)).map_err(|e| e.to_string())
+        };
+        let offset = word(0)?;
+        let len = word(offset)?;
+        let start = offset + 32;
+        let string = bytes
+            .get(start..start.saturating_add(len))
+            .ok_or_else(|| "the result is too short".to_string())?;
+        String::from_utf8(string.to_vec()).map_err(|e| format!("the string is not UTF-8: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Context, Decision, EntityUid};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    fn files(policies: &str, entities: &str) -> (tempfile::TempDir, PathBuf, PathBuf) {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let policies_file = dir.path().join("policies.cedar");
+        let entities_file = dir.path().join("entities.json");
+        std::fs::write(&policies_file, policies).expect("writes");
+        std::fs::write(&entities_file, entities).expect("writes");
+        (dir, policies_file, entities_file)
+    }
+
+    fn authorizer(policies_file: &Path, entities_file: &Path) -> ReloadingAuthorizer {
+        ReloadingAuthorizer::new(
+            PolicySource::new(FileSource::new(policies_file)),
+            EntitySource::new(FileSource::new(entities_file)),
+        )
+        .expect("sources load")
+    }
+
+    fn request() -> Request {
+        Request::new(
+            EntityUid::from_str(r#"Address::"0xabc""#).ok(),
+            EntityUid::from_str(r#"Action::"withdraw""#).ok(),
+            None,
+            Context::empty(),
+        )
+    }
+
+    const POLICY: &str = "permit(principal, action, resource) when { principal.limit > 5 };";
+
+    #[test]
+    fn refresh_swaps_changed_sources() {
+        let (_dir, policies_file, entities_file) = files(
+            POLICY,
+            r#"[{ "uid": { "type": "Address", "id": "0xabc" }, "attrs": { "limit": 1 }, "parents": [] }]"#,
+        );
+        let authorizer = authorizer(&policies_file, &entities_file);
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&changes);
+        authorizer.on_change(move |change| {
+            recorded
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(change.clone());
+        });
+        assert_eq!(
+            authorizer.is_authorized(&request()).decision(),
+            Decision::Deny
+        );
+        assert_eq!(authorizer.refresh().expect("refreshes"), None);
+
+        let before = authorizer.snapshot();
+        assert_eq!(
+            before
+                .policies
+                .provenance(&crate::PolicyId::from_str("policy0").expect("valid id")),
+            Some(&crate::Provenance {
+                origin: Origin::File(policies_file.clone()),
+                span: Some(0..POLICY.len()),
+            })
+        );
+        std::fs::write(
+            &entities_file,
+            r#"[{ "uid": { "type": "Address", "id": "0xabc" }, "attrs": { "limit": 10 }, "parents": [] }]"#,
+        )
+        .expect("writes");
+        let change = authorizer
+            .refresh()
+            .expect("refreshes")
+            .expect("the entities changed");
+        assert_eq!(change.policy_version, None);
+        assert_eq!(
+            change.entity_version.as_deref(),
+            Some(authorizer.snapshot().entity_version.as_str())
+        );
+        assert_eq!(
+            authorizer.is_authorized(&request()).decision(),
+            Decision::Allow
+        );
+        // a snapshot taken before is not changed by the swap
+        assert_eq!(
+            Authorizer::new()
+                .is_authorized(&request(), &before.policies, &before.entities)
+                .decision(),
+            Decision::Deny
+        );
+        assert_eq!(
+            changes
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .as_slice(),
+            [change]
+        );
+    }
+
+    #[test]
+    fn refresh_keeps_snapshot_on_error() {
+        let (_dir, policies_file, entities_file) = files(POLICY, "[]");
+        let authorizer = authorizer(&policies_file, &entities_file);
+        let version = authorizer.snapshot().policy_version.clone();
+        std::fs::write(&policies_file, "permit(").expect("writes");
+        assert!(matches!(
+            authorizer.refresh(),
+            Err(SourceError::Policies { .. })
+        ));
+        std::fs::remove_file(&entities_file).expect("removes");
+        assert!(matches!(
+            authorizer.refresh(),
+            Err(SourceError::Policies { .. })
+        ));
+        std::fs::write(&policies_file, POLICY).expect("writes");
+        assert!(matches!(authorizer.refresh(), Err(SourceError::Io { .. })));
+        assert_eq!(authorizer.snapshot().policy_version, version);
+    }
+
+    #[test]
+    fn watcher_refreshes_when_triggered() {
+        let (_dir, policies_file, entities_file) =
+            files("forbid(principal, action, resource);", "[]");
+        let authorizer = Arc::new(authorizer(&policies_file, &entities_file));
+        let (sender, changes) = mpsc::channel();
+        authorizer.on_change(move |change| {
+            let _ = sender.send(change.clone());
+        });
+        let errors = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&errors);
+        authorizer.on_error(move |_| {
+            counted.fetch_add(1, Ordering::Relaxed);
+        });
+        // an interval long enough that only triggers refresh
+        let watcher = authorizer.watch(Duration::from_mins(5));
+        std::fs::write(&policies_file, "permit(principal, action, resource);").expect("writes");
+        watcher.trigger();
+        let change = changes
+            .recv_timeout(Duration::from_secs(10))
+            .expect("the watcher refreshed");
+        assert!(change.policy_version.is_some());
+        assert_eq!(
+            authorizer.is_authorized(&request()).decision(),
+            Decision::Allow
+        );
+        std::fs::write(&policies_file, "permit(").expect("writes");
+        watcher.trigger();
+        drop(watcher);
+        assert_eq!(errors.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn sources_from_uris() {
+        let (_dir, policies_file, _) = files("permit(principal, action, resource);", "[]");
+        for uri in [
+            policies_file.display().to_string(),
+            format!("file://{}", policies_file.display()),
+        ] {
+            let source = PolicySource::new(from_uri(&uri).expect("a path"));
+            assert!(source.load(None).expect("loads").is_some());
+        }
+        assert!(matches!(
+            from_uri("ftp://example.com/policies.cedar"),
+            Err(SourceError::Fetch { .. })
+        ));
+    }
+
+    #[cfg(feature = "sources-signed")]
+    #[test]
+    fn signed_sources_check_the_signer() {
+        use ethers::signers::{LocalWallet, Signer};
+
+        let wallet: LocalWallet =
+            "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+                .parse()
+                .expect("valid key");
+        let text = "permit(principal, action, resource);";
+        let (dir, policies_file, _) = files(text, "[]");
+        let signature_file = dir.path().join("policies.cedar.sig");
+        std::fs::write(
+            &signature_file,
+            SignedSource::sign(text, 2, &wallet).expect("signs"),
+        )
+        .expect("writes");
+        let signed = |signer| {
+            PolicySource::new(SignedSource::new(
+                FileSource::new(&policies_file),
+                FileSource::new(&signature_file),
+                signer,
+            ))
+        };
+        assert!(signed(wallet.address())
+            .load(None)
+            .expect("signed by the signer")
+            .is_some());
+        assert!(matches!(
+            signed(ethers::types::Address::zero()).load(None),
+            Err(SourceError::Signature { .. })
+        ));
+        std::fs::write(&policies_file, "forbid(principal, action, resource);").expect("writes");
+        assert!(matches!(
+            signed(wallet.address()).load(None),
+            Err(SourceError::Signature { .. })
+        ));
+    }
+
+    #[cfg(feature = "sources-signed")]
+    #[test]
+    fn signed_sources_reject_rollbacks() {
+        use ethers::signers::{LocalWallet, Signer};
+
+        let wallet: LocalWallet =
+            "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+                .parse()
+                .expect("valid key");
+        let (dir, policies_file, _) = files("", "[]");
+        let signature_file = dir.path().join("policies.cedar.sig");
+        let publish = |text: &str, sequence| {
+            std::fs::write(&policies_file, text).expect("writes");
+            std::fs::write(
+                &signature_file,
+                SignedSource::sign(text, sequence, &wallet).expect("signs"),
+            )
+            .expect("writes");
+        };
+        let source = SignedSource::new(
+            FileSource::new(&policies_file),
+            FileSource::new(&signature_file),
+            wallet.address(),
+        );
+        let rejected = |source: &SignedSource| matches!(source.fetch(None), Err(SourceError::Signature { message, .. }) if message.contains("sequence number"));
+
+        publish("permit(principal, action, resource);", 2);
+        assert!(source.fetch(None).expect("signed").is_some());
+        assert_eq!(source.sequence(), Some(2));
+        // the same text may be fetched again
+        assert!(source.fetch(None).expect("signed").is_some());
+
+        // an older text, with its valid signature, is rejected
+        publish("forbid(principal, action, resource);", 1);
+        assert!(rejected(&source));
+        // as is another text with the same sequence number
+        publish("forbid(principal, action, resource);", 2);
+        assert!(rejected(&source));
+        publish("forbid(principal, action, resource);", 3);
+        assert!(source.fetch(None).expect("signed").is_some());
+        assert_eq!(source.sequence(), Some(3));
+
+        // a restarted source is given the sequence number fetched before
+        let restarted = SignedSource::new(
+            FileSource::new(&policies_file),
+            FileSource::new(&signature_file),
+            wallet.address(),
+        )
+        .with_min_sequence(4);
+        assert!(rejected(&restarted));
+
+        // the signature covers the sequence number
+        std::fs::write(
+            &signature_file,
+            SignedSource::sign("forbid(principal, action, resource);", 3, &wallet)
+                .expect("signs")
+                .replacen('3', "9", 1),
+        )
+        .expect("writes");
+        assert!(matches!(
+            source.fetch(None),
+            Err(SourceError::Signature { message, .. }) if message.contains("signed by")
+        ));
+    }
+
+    #[cfg(feature = "sources-encrypted")]
+    #[test]
+    fn encrypted_sources_decrypt_in_memory() {
+        use age::secrecy::ExposeSecret;
+
+        let text = "permit(principal, action, resource);";
+        let aes = "0x000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+        let (dir, policies_file, _) = files(text, "[]");
+        let encrypted_file = dir.path().join("policies.cedar.enc");
+        for key in [
+            age::x25519::Identity::generate()
+                .to_string()
+                .expose_secret()
+                .to_string(),
+            aes.to_string(),
+        ] {
+            let key: DecryptionKey = key.parse().expect("a key");
+            std::fs::write(&encrypted_file, key.encrypt(text).expect("encrypts")).expect("writes");
+            assert!(!std::fs::read_to_string(&encrypted_file)
+                .expect("reads")
+                .contains("permit"));
+            let fetched = EncryptedSource::new(FileSource::new(&encrypted_file), key)
+                .fetch(None)
+                .expect("decrypts")
+                .expect("is new");
+            assert_eq!(fetched.text, text);
+        }
+
+        // the key is asked for on every decryption, and a wrong key fails
+        let asked = Arc::new(AtomicUsize::new(0));
+        let source = {
+            let asked = Arc::clone(&asked);
+            EncryptedSource::with_key_provider(FileSource::new(&encrypted_file), move || {
+                asked.fetch_add(1, Ordering::Relaxed);
+                DecryptionKey::from_env("CEDAR_TEST_POLICY_BUNDLE_KEY")
+            })
+        };
+        std::env::set_var("CEDAR_TEST_POLICY_BUNDLE_KEY", aes);
+        let fetched = source.fetch(None).expect("decrypts").expect("is new");
+        assert!(source
+            .fetch(Some(&fetched.version))
+            .expect("fetches")
+            .is_none());
+        std::env::set_var("CEDAR_TEST_POLICY_BUNDLE_KEY", &aes.replace("1f", "1e"));
+        assert!(matches!(
+            source.fetch(None),
+            Err(SourceError::Decryption { .. })
+        ));
+        assert_eq!(asked.load(Ordering::Relaxed), 2);
+        assert!(matches!(
+            EncryptedSource::new(FileSource::new(&policies_file), aes.parse().expect("a key"))
+                .fetch(None),
+            Err(SourceError::Decryption { .. })
+        ));
+        assert!("0x0001".parse::<DecryptionKey>().is_err());
+        assert!("AGE-SECRET-KEY-1NOPE".parse::<DecryptionKey>().is_err());
+    }
+
+    #[cfg(feature = "sources-remote")]
+    mod remote {
+        use super::super::*;
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+
+        /// Serve one response per element of `responses`, each given the
+        /// request head and body, and return the URL of the server
+        fn serve(responses: Vec<fn(&str, &str) -> String>) -> String {
+            let listener = TcpListener::bind("127.0.0.1:0").expect("binds");
+            let url = format!("http://{}", listener.local_addr().expect("has address"));
+            thread::spawn(move || {
+                for respond in responses {
+                    let Ok((stream, _)) = listener.accept() else {
+                        return;
+                    };
+                    let mut reader = BufReader::new(stream);
+                    let mut head = String::new();
+                    let mut length = 0;
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                            break;
+                        }
+                        if let Some(value) =
+                            line.to_ascii_lowercase().strip_prefix("content-length:")
+                        {
+                            length = value.trim().parse().unwrap_or(0);
+                        }
+                        head.push_str(&line);
+                    }
+                    let mut body = vec![0; length];
+                    let _ = reader.read_exact(&mut body);
+                    let response = respond(&head, &String::from_utf8_lossy(&body));
+                    let _ = reader.get_mut().write_all(response.as_bytes());
+                }
+            });
+            url
+        }
+
+        fn ok(headers: &str, body: &str) -> String {
+            format!(
+                "HTTP/1.1 200 OK\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+        }
+
+        #[test]
+        fn url_source_uses_etags() {
+            let url = serve(vec![
+                |_, _| ok("ETag: \"v1\"\r\n", "permit(principal, action, resource);"),
+                |head, _| {
+                    if head.to_ascii_lowercase().contains("if-none-match: \"v1\"") {
+                        "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n".to_string()
+                    } else {
+                        "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n"
+                            .to_string()
+                    }
+                },
+                |_, _| {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_string()
+                },
+            ]);
+            let source = UrlSource::new(format!("{url}/policies.cedar")).expect("client builds");
+            let fetched = source.fetch(None).expect("fetches").expect("is new");
+            assert_eq!(fetched.version, "etag:\"v1\"");
+            assert_eq!(source.fetch(Some(&fetched.version)).expect("fetches"), None);
+            assert!(matches!(source.fetch(None), Err(SourceError::Fetch { .. })));
+        }
+
+        #[test]
+        fn remote_locations() {
+            assert_eq!(
+                UrlSource::s3("vault-policies", "prod/policies.cedar")
+                    .expect("client builds")
+                    .url(),
+                "https://vault-policies.s3.amazonaws.com/prod/policies.cedar"
+            );
+            assert_eq!(
+                UrlSource::gcs("vault-policies", "policies.cedar")
+                    .expect("client builds")
+                    .url(),
+                "https://storage.googleapis.com/vault-policies/policies.cedar"
+            );
+            assert_eq!(
+                IpfsSource::with_gateway("ipfs://bafy/policies.cedar", "http://127.0.0.1:8080/")
+                    .expect("valid path")
+                    .url(),
+                "http://127.0.0.1:8080/ipfs/bafy/policies.cedar"
+            );
+            assert!(IpfsSource::new("https://example.com").is_err());
+            assert_eq!(
+                UrlSource::from_uri("s3://vault-policies/prod/policies.cedar")
+                    .expect("an S3 URI")
+                    .url(),
+                "https://vault-policies.s3.amazonaws.com/prod/policies.cedar"
+            );
+            assert_eq!(
+                UrlSource::from_uri("gs://vault-policies/policies.cedar")
+                    .expect("a GCS URI")
+                    .url(),
+                "https://storage.googleapis.com/vault-policies/policies.cedar"
+            );
+            assert!(UrlSource::from_uri("s3://vault-policies").is_err());
+            assert!(UrlSource::from_uri("ftp://example.com/policies.cedar").is_err());
+            assert_eq!(
+                from_uri("ipfs://bafy/policies.cedar")
+                    .expect("an IPFS URI")
+                    .to_string(),
+                "`/ipfs/bafy/policies.cedar`"
+            );
+        }
+
+        #[test]
+        fn url_source_caches_by_etag() {
+            let url = serve(vec![
+                |_, _| ok("ETag: \"v1\"\r\n", "permit(principal, action, resource);"),
+                |head, _| {
+                    if head.to_ascii_lowercase().contains("if-none-match: \"v1\"") {
+                        "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n".to_string()
+                    } else {
+                        ok("ETag: \"v2\"\r\n", "forbid(principal, action, resource);")
+                    }
+                },
+            ]);
+            let dir = tempfile::tempdir().expect("temp dir");
+            let source = || {
+                UrlSource::new(format!("{url}/policies.cedar"))
+                    .expect("client builds")
+                    .with_cache_dir(dir.path())
+            };
+            let fetched = source().fetch(None).expect("fetches").expect("is new");
+            // another process has nothing loaded, and gets the cached text
+            let cached = source().fetch(None).expect("fetches").expect("is cached");
+            assert_eq!(cached, fetched);
+            assert_eq!(cached.version, "etag:\"v1\"");
+        }
+
+        #[test]
+        fn ipfs_paths_are_fetched_once() {
+            let url = serve(vec![|_, _| ok("", "[]")]);
+            let source =
+                IpfsSource::with_gateway("/ipfs/bafy/entities.json", &url).expect("valid path");
+            let fetched = source.fetch(None).expect("fetches").expect("is new");
+            assert_eq!(fetched.version, "ipfs:/ipfs/bafy/entities.json");
+            // the server has stopped, so this would fail if it fetched
+            assert_eq!(
+                source.fetch(Some(&fetched.version)).expect("not fetched"),
+                None
+            );
+        }
+
+        /// The ABI encoding of `s` as the only return value
+        fn encode_string(s: &str) -> String {
+            let mut bytes = vec![0; 64];
+            if let Some(offset) = bytes.get_mut(31) {
+                *offset = 32;
+            }
+            bytes.splice(56..64, (s.len() as u64).to_be_bytes());
+            bytes.extend(s.as_bytes());
+            bytes.resize(64 + s.len().div_ceil(32) * 32, 0);
+            format!("0x{}", hex::encode(bytes))
+        }
+
+        #[test]
+        fn decodes_abi_strings() {
+            let policy = "permit(principal, action, resource);";
+            let encoded = hex::decode(encode_string(policy).trim_start_matches("0x")).expect("hex");
+            assert_eq!(decode_string(&encoded).as_deref(), Ok(policy));
+            assert!(decode_string(encoded.get(..40).unwrap_or_default()).is_err());
+        }
+
+        #[test]
+        fn contract_source_calls_view_function() {
+            let url = serve(vec![|_, body| {
+                let call: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+                // the selector of `policies()`
+                let result =
+                    if call.pointer("/params/0/data") == Some(&serde_json::json!("0x702e7e1f")) {
+                        encode_string("permit(principal, action, resource);")
+                    } else {
+                        format!("unexpected call {call}")
+                    };
+                ok(
+                    "",
+                    &serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": result }).to_string(),
+                )
+            }]);
+            let source = ContractSource::new(
+                url,
+                "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+                "policies()",
+            )
+            .expect("client builds");
+            let (policies, _) = PolicySource::new(source)
+                .load(None)
+                .expect("loads")
+                .expect("is new");
+            assert_eq!(policies.policies().count(), 1);
+        }
+
+        #[test]
+        fn contract_source_writes_snapshot_header() {
+            fn respond(_: &str, body: &str) -> String {
+                let call: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+                let result = match call.get("method").and_then(serde_json::Value::as_str) {
+                    Some("eth_chainId") => serde_json::json!("0x1"),
+                    Some("eth_getBlockByNumber") => {
+                        serde_json::json!({ "number": "0x121eac0", "hash": "0xabc" })
+                    }
+                    // the entities must be read at the block of the header
+                    Some("eth_call")
+                        if call.pointer("/params/1") == Some(&serde_json::json!("0x121eac0")) =>
+                    {
+                        serde_json::json!(encode_string(
+                            r#"[{ "uid": { "type": "User", "id": "alice" }, "attrs": {}, "parents": [] }]"#
+                        ))
+                    }
+                    _ => serde_json::json!(format!("unexpected call {call}")),
+                };
+                ok(
+                    "",
+                    &serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": result }).to_string(),
+                )
+            }
+            let url = serve(vec![respond, respond, respond]);
+            let source = ContractSource::new(
+                url,
+                "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+                "entities()",
+            )
+            .expect("client builds")
+            .with_snapshot_header();
+            let (entities, _) = EntitySource::new(source)
+                .load(None)
+                .expect("loads")
+                .expect("is new");
+            assert_eq!(entities.iter().count(), 1);
+            let info = entities.snapshot_info().expect("has a header");
+            assert_eq!(info.chain_id, Some(1));
+            assert_eq!(info.block_number, Some(19_000_000));
+            assert_eq!(info.block_hash.as_deref(), Some("0xabc"));
+            assert!(info.fetched_at_ms.is_some());
+        }
+    }
+}
@@ -0,0 +1,515 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! SQL-backed entity store, for services whose entity data already lives in
+//! Postgres or SQLite.
+//!
+//! Entities are stored in three tables, created by
+//! [`SqlEntityStore::create_schema`] (see [`SCHEMA`] for the exact DDL):
+//!
+//! * `cedar_entities (entity_type, entity_id)`: one row per entity
+//! * `cedar_entity_attrs (entity_type, entity_id, attr, value)`: one row per
+//!   attribute, where `value` is the attribute value in the entities JSON
+//!   format (so extension values such as `u256` round-trip losslessly)
+//! * `cedar_entity_parents (child_type, child_id, parent_type, parent_id)`:
+//!   one row per parent edge. Edges need not be transitively closed, and
+//!   [`SqlEntityStore::insert_entities`] stores only the direct parents of
+//!   each entity; ancestors are computed with a recursive query.
+//!
+//! The store is not consulted during evaluation. Instead, callers load the
+//! slice of the hierarchy relevant to a request with
+//! [`SqlEntityStore::load_entities`] and pass the resulting [`Entities`] to
+//! the [`crate::Authorizer`] as usual.
+
+use crate::{Entities, EntitiesError, EntityId, EntityTypeName, EntityUid, ParseErrors, Schema};
+use cedar_policy_core::entities::{JSONValue, JsonSerializationError};
+use serde_json::json;
+use sqlx::any::{AnyArguments, AnyPoolOptions, AnyRow};
+use sqlx::query::Query;
+use sqlx::{Any, AnyPool, Row};
+use std::collections::HashMap;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// DDL for the tables used by [`SqlEntityStore`]. Works on both Postgres and
+/// `SQLite`.
+pub const SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS cedar_entities (
+        entity_type TEXT NOT NULL,
+        entity_id TEXT NOT NULL,
+        PRIMARY KEY (entity_type, entity_id)
+    )",
+    "CREATE TABLE IF NOT EXISTS cedar_entity_attrs (
+        entity_type TEXT NOT NULL,
+        entity_id TEXT NOT NULL,
+        attr TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (entity_type, entity_id, attr),
+        FOREIGN KEY (entity_type, entity_id) REFERENCES cedar_entities (entity_type, entity_id)
+    )",
+    "CREATE TABLE IF NOT EXISTS cedar_entity_parents (
+        child_type TEXT NOT NULL,
+        child_id TEXT NOT NULL,
+        parent_type TEXT NOT NULL,
+        parent_id TEXT NOT NULL,
+        PRIMARY KEY (child_type, child_id, parent_type, parent_id),
+        FOREIGN KEY (child_type, child_id) REFERENCES cedar_entities (entity_type, entity_id)
+    )",
+];
+
+/// Recursive query returning all ancestors of the entity `($1, $2)`.
+/// `UNION` (rather than `UNION ALL`) makes this terminate on cyclic data.
+const ANCESTORS_QUERY: &str = "WITH RECURSIVE ancestors (entity_type, entity_id) AS (
+        SELECT parent_type, parent_id FROM cedar_entity_parents
+        WHERE child_type = $1 AND child_id = $2
+        UNION
+        SELECT p.parent_type, p.parent_id FROM cedar_entity_parents p
+        JOIN ancestors a ON p.child_type = a.entity_type AND p.child_id = a.entity_id
+    )
+    SELECT entity_type, entity_id FROM ancestors";
+
+/// Recursive common table expression `wanted`, of the `count` entities
+/// `($1, $2)`, `($3, $4)`, ... and all of their ancestors
+fn wanted_cte(count: usize) -> String {
+    let values = (0..count)
+        .map(|i| format!("(${}, ${})", 2 * i + 1, 2 * i + 2))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "WITH RECURSIVE wanted (entity_type, entity_id) AS (
+            VALUES {values}
+            UNION
+            SELECT p.parent_type, p.parent_id FROM cedar_entity_parents p
+            JOIN wanted w ON p.child_type = w.entity_type AND p.child_id = w.entity_id
+        )"
+    )
+}
+
+/// Each entity, once for each of its direct parents, or once with `NULL`s if
+/// it has none
+const ENTITIES_QUERY: &str =
+    "SELECT e.entity_type, e.entity_id, p.parent_type, p.parent_id FROM cedar_entities e
+    LEFT JOIN cedar_entity_parents p ON p.child_type = e.entity_type AND p.child_id = e.entity_id";
+
+/// The attributes of each entity
+const ATTRS_QUERY: &str =
+    "SELECT e.entity_type, e.entity_id, e.attr, e.value FROM cedar_entity_attrs e";
+
+/// Restricts [`ENTITIES_QUERY`] and [`ATTRS_QUERY`] to the `wanted` entities
+const JOIN_WANTED: &str =
+    "JOIN wanted w ON e.entity_type = w.entity_type AND e.entity_id = w.entity_id";
+
+/// Errors that can occur when using a [`SqlEntityStore`]
+#[derive(Debug, Error)]
+pub enum SqlEntityStoreError {
+    /// Error reported by the database or driver
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    /// A stored attribute value was not valid JSON
+    #[error("attribute `{attr}` of entity `{uid}` is not valid JSON: {err}")]
+    InvalidAttrValue {
+        /// Entity the attribute belongs to
+        uid: String,
+        /// Name of the attribute
+        attr: String,
+        /// Underlying JSON error
+        err: serde_json::Error,
+    },
+    /// A stored entity type or id is not valid in Cedar
+    #[error("stored entity `{entity_type}::{entity_id:?}` is invalid: {err}")]
+    InvalidUid {
+        /// Stored entity type
+        entity_type: String,
+        /// Stored entity id
+        entity_id: String,
+        /// Underlying parse error
+        err: ParseErrors,
+    },
+    /// An entity could not be serialized for storage
+    #[error(transparent)]
+    Serialization(#[from] JsonSerializationError),
+    /// The stored rows could not be turned into an `Entities`
+    #[error(transparent)]
+    Entities(#[from] EntitiesError),
+}
+
+/// Entity store over a Postgres or `SQLite` database. See the
+/// [module documentation](self) for the table layout.
+#[derive(Debug, Clone)]
+pub struct SqlEntityStore {
+    pool: AnyPool,
+}
+
+impl SqlEntityStore {
+    /// Create a store over an existing connection pool
+    pub fn new(pool: AnyPool) -> Self {
+        Self { pool }
+    }
+
+    /// Connect to the database at `url`, e.g. `postgres://...` or
+    /// `sqlite::memory:`
+    ///
+    /// # Errors
+    ///
+    /// If the database cannot be connected to.
+    pub async fn connect(url: &str) -> Result<Self, SqlEntityStoreError> {
+        sqlx::any::install_default_drivers();
+        let pool = AnyPoolOptions::new().connect(url).await?;
+        Ok(Self::new(pool))
+    }
+
+    /// Create the entity tables if they do not already exist
+    ///
+    /// # Errors
+    ///
+    /// If the database reports an error.
+    pub async fn create_schema(&self) -> Result<(), SqlEntityStoreError> {
+        for stmt in SCHEMA {
+            sqlx::query(stmt).execute(&self.pool).await?;
+        }
+        Ok(())
+    }
+
+    /// Insert (or replace) all of the entities in `entities`, with their
+    /// direct parents
+    ///
+    /// # Errors
+    ///
+    /// If an attribute value cannot be serialized, or the database reports an
+    /// error, in which case none of the entities are inserted.
+    pub async fn insert_entities(&self, entities: &Entities) -> Result<(), SqlEntityStoreError> {
+        let hierarchy = entities.hierarchy();
+        let mut tx = self.pool.begin().await?;
+        for entity in entities.iter() {
+            let uid = entity.uid();
+            let parents = hierarchy.get(&uid);
+            let uid = &uid.0;
+            let ty = uid.entity_type().to_string();
+            let id: &str = uid.eid().as_ref();
+            sqlx::query("DELETE FROM cedar_entity_attrs WHERE entity_type = $1 AND entity_id = $2")
+                .bind(&ty)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM cedar_entity_parents WHERE child_type = $1 AND child_id = $2")
+                .bind(&ty)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM cedar_entities WHERE entity_type = $1 AND entity_id = $2")
+                .bind(&ty)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("INSERT INTO cedar_entities (entity_type, entity_id) VALUES ($1, $2)")
+                .bind(&ty)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            for (attr, value) in entity.0.attrs() {
+                let value = serde_json::to_string(&JSONValue::from_expr(value)?)
+                    .map_err(JsonSerializationError::from)?;
+                sqlx::query(
+                    "INSERT INTO cedar_entity_attrs (entity_type, entity_id, attr, value) VALUES ($1, $2, $3, $4)",
+                )
+                .bind(&ty)
+                .bind(id)
+                .bind(attr)
+                .bind(value)
+                .execute(&mut *tx)
+                .await?;
+            }
+            for parent in parents.into_iter().flatten() {
+                sqlx::query(
+                    "INSERT INTO cedar_entity_parents (child_type, child_id, parent_type, parent_id) VALUES ($1, $2, $3, $4)",
+                )
+                .bind(&ty)
+                .bind(id)
+                .bind(parent.type_name().to_string())
+                .bind(parent.id().as_ref())
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Get all ancestors of `uid`, following parent edges transitively
+    ///
+    /// # Errors
+    ///
+    /// If the database reports an error, or a stored ancestor is not a valid
+    /// entity UID.
+    pub async fn ancestors(&self, uid: &EntityUid) -> Result<Vec<EntityUid>, SqlEntityStoreError> {
+        let rows = sqlx::query(ANCESTORS_QUERY)
+            .bind(uid.type_name().to_string())
+            .bind(uid.id().as_ref())
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter()
+            .map(|row| {
+                let ty: String = row.try_get(0)?;
+                let id: String = row.try_get(1)?;
+                uid_from_parts(&ty, &id)
+            })
+            .collect()
+    }
+
+    /// Load the given entities together with all of their ancestors, which is
+    /// exactly the slice of the hierarchy needed to authorize a request
+    /// mentioning those entities. They are loaded with two queries, however
+    /// many there are.
+    ///
+    /// Entities that do not exist in the store are silently omitted, just as
+    /// they would be absent from an entities JSON file.
+    ///
+    /// If a `schema` is provided, the loaded entities are checked against it
+    /// as they would be by [`Entities::from_json_value`].
+    ///
+    /// # Errors
+    ///
+    /// If the database reports an error, the stored entities are invalid, or
+    /// they do not conform to `schema`.
+    pub async fn load_entities(
+        &self,
+        uids: impl IntoIterator<Item = &EntityUid>,
+        schema: Option<&Schema>,
+    ) -> Result<Entities, SqlEntityStoreError> {
+        let uids = uids.into_iter().collect::<Vec<_>>();
+        if uids.is_empty() {
+            return Ok(Entities::from_json_value(
+                serde_json::Value::Array(Vec::new()),
+                schema,
+            )?);
+        }
+        let cte = wanted_cte(uids.len());
+        let entities_query = format!("{cte} {ENTITIES_QUERY} {JOIN_WANTED}");
+        let attrs_query = format!("{cte} {ATTRS_QUERY} {JOIN_WANTED}");
+        let bind_uids = |query| {
+            uids.iter()
+                .fold(query, |query: Query<'_, Any, AnyArguments<'_>>, uid| {
+                    query
+                        .bind(uid.type_name().to_string())
+                        .bind(uid.id().as_ref().to_string())
+                })
+        };
+        let entities = bind_uids(sqlx::query(&entities_query))
+            .fetch_all(&self.pool)
+            .await?;
+        let attrs = bind_uids(sqlx::query(&attrs_query))
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(Entities::from_json_value(
+            entities_json(&entities, &attrs)?,
+            schema,
+        )?)
+    }
+
+    /// Load every entity in the store
+    ///
+    /// # Errors
+    ///
+    /// If the database reports an error, the stored entities are invalid, or
+    /// they do not conform to `schema`.
+    pub async fn load_all(&self, schema: Option<&Schema>) -> Result<Entities, SqlEntityStoreError> {
+        let entities = sqlx::query(ENTITIES_QUERY).fetch_all(&self.pool).await?;
+        let attrs = sqlx::query(ATTRS_QUERY).fetch_all(&self.pool).await?;
+        Ok(Entities::from_json_value(
+            entities_json(&entities, &attrs)?,
+            schema,
+        )?)
+    }
+}
+
+/// The entities JSON of the entities in the rows of [`ENTITIES_QUERY`], with
+/// their attributes in the rows of [`ATTRS_QUERY`]
+fn entities_json(
+    entities: &[AnyRow],
+    attrs: &[AnyRow],
+) -> Result<serde_json::Value, SqlEntityStoreError> {
+    let mut parents: HashMap<(String, String), Vec<serde_json::Value>> = HashMap::new();
+    for row in entities {
+        let key = (row.try_get(0)?, row.try_get(1)?);
+        let parent_type: Option<String> = row.try_get(2)?;
+        let parent_id: Option<String> = row.try_get(3)?;
+        let entry = parents.entry(key).or_default();
+        if let (Some(parent_type), Some(parent_id)) = (parent_type, parent_id) {
+            entry.push(json!({ "type": parent_type, "id": parent_id }));
+        }
+    }
+    let mut entity_attrs: HashMap<(String, String), serde_json::Map<String, serde_json::Value>> =
+        HashMap::new();
+    for row in attrs {
+        let key: (String, String) = (row.try_get(0)?, row.try_get(1)?);
+        let attr: String = row.try_get(2)?;
+        let value = parse_attr_value(&key, &attr, &row.try_get::<String, _>(3)?)?;
+        entity_attrs.entry(key).or_default().insert(attr, value);
+    }
+    Ok(parents
+        .into_iter()
+        .map(|((ty, id), parents)| {
+            let attrs = entity_attrs
+                .remove(&(ty.clone(), id.clone()))
+                .unwrap_or_default();
+            json!({ "uid": { "type": ty, "id": id }, "attrs": attrs, "parents": parents })
+        })
+        .collect())
+}
+
+/// Parse a stored attribute value
+fn parse_attr_value(
+    (ty, id): &(String, String),
+    attr: &str,
+    value: &str,
+) -> Result<serde_json::Value, SqlEntityStoreError> {
+    serde_json::from_str(value).map_err(|err| SqlEntityStoreError::InvalidAttrValue {
+        uid: format!("{ty}::{id:?}"),
+        attr: attr.to_string(),
+        err,
+    })
+}
+
+/// Build an `EntityUid` from a stored type and id
+fn uid_from_parts(ty: &str, id: &str) -> Result<EntityUid, SqlEntityStoreError> {
+    let invalid = |err| SqlEntityStoreError::InvalidUid {
+        entity_type: ty.to_string(),
+        entity_id: id.to_string(),
+        err,
+    };
+    Ok(EntityUid::from_type_name_and_id(
+        EntityTypeName::from_str(ty).map_err(invalid)?,
+        EntityId::from_str(id).map_err(invalid)?,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    async fn store() -> SqlEntityStore {
+        sqlx::any::install_default_drivers();
+        let pool = AnyPoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let store = SqlEntityStore::new(pool);
+        store.create_schema().await.unwrap();
+        store
+    }
+
+    fn sample_entities() -> Entities {
+        Entities::from_json_value(
+            json!([
+                {
+                    "uid": { "type": "User", "id": "alice" },
+                    "attrs": { "balance": { "__extn": { "fn": "u256", "arg": "1000" } }, "age": 30 },
+                    "parents": [{ "type": "Group", "id": "signers" }]
+                },
+                {
+                    "uid": { "type": "Group", "id": "signers" },
+                    "attrs": {},
+                    "parents": [{ "type": "Group", "id": "dao" }]
+                },
+                {
+                    "uid": { "type": "Group", "id": "dao" },
+                    "attrs": {},
+                    "parents": []
+                },
+                {
+                    "uid": { "type": "User", "id": "bob" },
+                    "attrs": {},
+                    "parents": []
+                }
+            ]),
+            None,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn round_trip_all() {
+        let store = store().await;
+        let entities = sample_entities();
+        store.insert_entities(&entities).await.unwrap();
+        let loaded = store.load_all(None).await.unwrap();
+        assert_eq!(loaded, entities);
+
+        // alice is in `dao` only through `signers`
+        let parents = sqlx::query(
+            "SELECT parent_id FROM cedar_entity_parents WHERE child_type = 'User' AND child_id = 'alice'",
+        )
+        .fetch_all(&store.pool)
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| row.try_get::<String, _>(0).unwrap())
+        .collect::<Vec<_>>();
+        assert_eq!(parents, vec!["signers".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn load_request_slice() {
+        let store = store().await;
+        store.insert_entities(&sample_entities()).await.unwrap();
+        let alice = EntityUid::from_str(r#"User::"alice""#).unwrap();
+        let dao = EntityUid::from_str(r#"Group::"dao""#).unwrap();
+        let bob = EntityUid::from_str(r#"User::"bob""#).unwrap();
+
+        let mut ancestors = store.ancestors(&alice).await.unwrap();
+        ancestors.sort();
+        assert_eq!(
+            ancestors,
+            vec![
+                dao.clone(),
+                EntityUid::from_str(r#"Group::"signers""#).unwrap()
+            ]
+        );
+
+        let loaded = store.load_entities([&alice], None).await.unwrap();
+        assert_eq!(loaded.iter().count(), 3);
+        assert!(loaded.is_ancestor_of(&dao, &alice));
+        assert!(loaded.get(&bob).is_none());
+        assert!(loaded.get(&alice).unwrap().attr("balance").unwrap().is_ok());
+
+        let missing = EntityUid::from_str(r#"User::"carol""#).unwrap();
+        let loaded = store
+            .load_entities([&alice, &bob, &missing], None)
+            .await
+            .unwrap();
+        assert_eq!(loaded, sample_entities());
+        assert_eq!(
+            store.load_entities([], None).await.unwrap(),
+            Entities::empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn reinsert_replaces() {
+        let store = store().await;
+        store.insert_entities(&sample_entities()).await.unwrap();
+        let updated = Entities::from_json_value(
+            json!([{ "uid": { "type": "User", "id": "alice" }, "attrs": { "age": 31 }, "parents": [] }]),
+            None,
+        )
+        .unwrap();
+        store.insert_entities(&updated).await.unwrap();
+        let alice = EntityUid::from_str(r#"User::"alice""#).unwrap();
+        let loaded = store.load_entities([&alice], None).await.unwrap();
+        assert_eq!(loaded, updated);
+    }
+}
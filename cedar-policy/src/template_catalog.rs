@@ -0,0 +1,394 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Catalogs of the templates of a policy set and of their slots, with enough
+//! to generate a form for linking them, such as a "create policy" form of an
+//! admin UI.
+//!
+//! For each template, a [`TemplateCatalog`] gives its description, from its
+//! `@description` annotation, and for each of its slots
+//! * whether the slot is in an `==` or an `in` constraint,
+//! * the entity types it may be linked to, as found by
+//!   [`Template::slot_types`](crate::Template::slot_types) against a schema,
+//! * its description, from the `@principal_description` or
+//!   `@resource_description` annotation, and
+//! * example values, from the `@principal_examples` or `@resource_examples`
+//!   annotation, a comma-separated list of entity UIDs, and then from the
+//!   entities of the expected types in an entity store, up to
+//!   [`MAX_ENTITY_EXAMPLES`] of them.
+//!
+//! ```
+//! # use cedar_policy::template_catalog::*;
+//! # use cedar_policy::{Entities, PolicySet, Schema};
+//! let policies: PolicySet = r#"
+//!     @description("Let an account withdraw from a vault")
+//!     @principal_description("The account allowed to withdraw")
+//!     @resource_examples("Vault::\"main\"")
+//!     permit(principal == ?principal, action == Action::"withdraw", resource in ?resource);"#
+//!     .parse()
+//!     .unwrap();
+//! let schema = Schema::from_str_natural(r#"
+//!     entity Account;
+//!     entity Vault;
+//!     action withdraw appliesTo { principal: Account, resource: Vault };"#)
+//!     .unwrap();
+//! let entities = Entities::from_json_value(
+//!     serde_json::json!([{ "uid": { "type": "Account", "id": "alice" }, "attrs": {}, "parents": [] }]),
+//!     None,
+//! )
+//! .unwrap();
+//! let catalog = TemplateCatalog::new(&policies, Some(&schema), Some(&entities)).unwrap();
+//! let template = catalog.template("policy0").unwrap();
+//! assert_eq!(template.description.as_deref(), Some("Let an account withdraw from a vault"));
+//! let principal = &template.slots[0];
+//! assert_eq!(principal.operator, SlotOperator::Eq);
+//! assert_eq!(principal.entity_types, ["Account"]);
+//! assert_eq!(principal.examples, [r#"Account::"alice""#]);
+//! let resource = &template.slots[1];
+//! assert_eq!(resource.operator, SlotOperator::In);
+//! assert_eq!(resource.examples, [r#"Vault::"main""#]);
+//! ```
+
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{
+    Effect, Entities, EntityUid, PolicyId, PolicySet, Schema, SlotId, Template,
+    TemplatePrincipalConstraint, TemplateResourceConstraint,
+};
+
+/// The annotation describing a template
+pub const DESCRIPTION_ANNOTATION: &str = "description";
+
+/// The annotation describing the `?principal` slot of a template
+pub const PRINCIPAL_DESCRIPTION_ANNOTATION: &str = "principal_description";
+
+/// The annotation describing the `?resource` slot of a template
+pub const RESOURCE_DESCRIPTION_ANNOTATION: &str = "resource_description";
+
+/// The annotation listing example values of the `?principal` slot of a
+/// template
+pub const PRINCIPAL_EXAMPLES_ANNOTATION: &str = "principal_examples";
+
+/// The annotation listing example values of the `?resource` slot of a
+/// template
+pub const RESOURCE_EXAMPLES_ANNOTATION: &str = "resource_examples";
+
+/// How many entities of an entity store are given as examples of a slot
+pub const MAX_ENTITY_EXAMPLES: usize = 3;
+
+/// Errors building a [`TemplateCatalog`]
+#[derive(Debug, Error)]
+pub enum CatalogError {
+    /// The catalog annotations of a template are malformed
+    #[error("invalid catalog annotations on template `{template}`: {message}")]
+    Annotation {
+        /// The template with the annotations
+        template: PolicyId,
+        /// What is wrong with them
+        message: String,
+    },
+}
+
+/// Whether a slot is in an `==` or an `in` constraint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum SlotOperator {
+    /// The slot is in an `==` constraint, so it is the principal or resource
+    /// of the requests the linked policy applies to
+    #[serde(rename = "==")]
+    Eq,
+    /// The slot is in an `in` constraint, so it is an ancestor of the
+    /// principal or resource of the requests the linked policy applies to
+    #[serde(rename = "in")]
+    In,
+}
+
+/// A slot of a template
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SlotInfo {
+    /// The slot, like `?principal`
+    pub slot: String,
+    /// The constraint the slot is in
+    pub operator: SlotOperator,
+    /// The entity types the slot may be linked to, sorted, or none if no
+    /// schema was given
+    pub entity_types: Vec<String>,
+    /// The description of the slot, from its annotation
+    pub description: Option<String>,
+    /// Example values of the slot: those of its annotation, and then
+    /// entities of its entity types
+    pub examples: Vec<String>,
+}
+
+/// A template and its slots
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateInfo {
+    /// The id of the template
+    pub id: String,
+    /// The effect of the template, `permit` or `forbid`
+    pub effect: String,
+    /// The description of the template, from its annotation
+    pub description: Option<String>,
+    /// The slots of the template, `?principal` before `?resource`
+    pub slots: Vec<SlotInfo>,
+}
+
+/// The templates of a policy set, sorted by id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateCatalog {
+    templates: Vec<TemplateInfo>,
+}
+
+impl TemplateCatalog {
+    /// The catalog of the templates of `policies`, with the entity types of
+    /// their slots found against `schema`, and examples of them taken from
+    /// `entities`
+    ///
+    /// # Errors
+    ///
+    /// If an example in an annotation is not an entity UID, or is not of an
+    /// entity type its slot may be linked to.
+    pub fn new(
+        policies: &PolicySet,
+        schema: Option<&Schema>,
+        entities: Option<&Entities>,
+    ) -> Result<Self, CatalogError> {
+        let mut templates = policies
+            .templates()
+            .map(|template| template_info(template, schema, entities))
+            .collect::<Result<Vec<_>, _>>()?;
+        templates.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(Self { templates })
+    }
+
+    /// The templates, sorted by id
+    pub fn templates(&self) -> impl Iterator<Item = &TemplateInfo> {
+        self.templates.iter()
+    }
+
+    /// The template with the id, if any
+    pub fn template(&self, id: &str) -> Option<&TemplateInfo> {
+        self.templates.iter().find(|template| template.id == id)
+    }
+
+    /// The catalog as JSON: an array of the templates, each an object of
+    /// `id`, `effect`, `description` and `slots`, and each slot an object of
+    /// `slot`, `operator`, `entityTypes`, `description` and `examples`
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!(self.templates)
+    }
+}
+
+fn template_info(
+    template: &Template,
+    schema: Option<&Schema>,
+    entities: Option<&Entities>,
+) -> Result<TemplateInfo, CatalogError> {
+    let slot_types = schema.map(|schema| template.slot_types(schema));
+    let operators = [
+        (
+            SlotId::principal(),
+            match template.principal_constraint() {
+                TemplatePrincipalConstraint::Eq(None) => Some(SlotOperator::Eq),
+                TemplatePrincipalConstraint::In(None) => Some(SlotOperator::In),
+                _ => None,
+            },
+            PRINCIPAL_DESCRIPTION_ANNOTATION,
+            PRINCIPAL_EXAMPLES_ANNOTATION,
+        ),
+        (
+            SlotId::resource(),
+            match template.resource_constraint() {
+                TemplateResourceConstraint::Eq(None) => Some(SlotOperator::Eq),
+                TemplateResourceConstraint::In(None) => Some(SlotOperator::In),
+                _ => None,
+            },
+            RESOURCE_DESCRIPTION_ANNOTATION,
+            RESOURCE_EXAMPLES_ANNOTATION,
+        ),
+    ];
+    let mut slots = Vec::new();
+    for (slot, operator, description, examples) in operators {
+        let Some(operator) = operator else {
+            continue;
+        };
+        let entity_types: BTreeSet<String> = slot_types
+            .as_ref()
+            .and_then(|types| types.get(&slot))
+            .into_iter()
+            .flatten()
+            .map(ToString::to_string)
+            .collect();
+        let invalid = |message: String| CatalogError::Annotation {
+            template: template.id().clone(),
+            message,
+        };
+        let mut listed = Vec::new();
+        for example in template
+            .annotation(examples)
+            .into_iter()
+            .flat_map(|examples| examples.split(','))
+            .map(str::trim)
+        {
+            let uid = EntityUid::from_str(example)
+                .map_err(|e| invalid(format!("`{example}` is not an entity UID: {e}")))?;
+            if schema.is_some() && !entity_types.contains(&uid.type_name().to_string()) {
+                return Err(invalid(format!(
+                    "`{uid}` is not of an entity type `{slot}` may be linked to"
+                )));
+            }
+            listed.push(uid.to_string());
+        }
+        let mut stored: Vec<String> = entities
+            .into_iter()
+            .flat_map(Entities::iter)
+            .map(crate::Entity::uid)
+            .filter(|uid| entity_types.contains(&uid.type_name().to_string()))
+            .map(|uid| uid.to_string())
+            .filter(|uid| !listed.contains(uid))
+            .collect();
+        stored.sort();
+        listed.extend(stored.into_iter().take(MAX_ENTITY_EXAMPLES));
+        slots.push(SlotInfo {
+            slot: slot.to_string(),
+            operator,
+            entity_types: entity_types.into_iter().collect(),
+            description: template.annotation(description).map(str::to_string),
+            examples: listed,
+        });
+    }
+    Ok(TemplateInfo {
+        id: template.id().to_string(),
+        effect: match template.effect() {
+            Effect::Permit => "permit",
+            Effect::Forbid => "forbid",
+        }
+        .to_string(),
+        description: template
+            .annotation(DESCRIPTION_ANNOTATION)
+            .map(str::to_string),
+        slots,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn policies(src: &str) -> PolicySet {
+        src.parse().expect("valid policies")
+    }
+
+    fn schema() -> Schema {
+        Schema::from_str_natural(
+            r"
+            entity Account in [Team];
+            entity Team;
+            entity Vault;
+            action withdraw appliesTo { principal: Account, resource: Vault };",
+        )
+        .expect("valid schema")
+    }
+
+    #[test]
+    fn catalogs_slots() {
+        let policies = policies(
+            r#"
+            permit(principal, action, resource);
+            @description("Let a team withdraw from a vault")
+            @principal_description("The team")
+            @principal_examples("Team::\"ops\", Team::\"treasury\"")
+            @resource_description("The vault")
+            permit(principal in ?principal, action == Action::"withdraw", resource == ?resource);
+            forbid(principal == ?principal, action, resource);"#,
+        );
+        let entities = Entities::from_json_value(
+            serde_json::json!([
+                { "uid": { "type": "Vault", "id": "main" }, "attrs": {}, "parents": [] },
+                { "uid": { "type": "Team", "id": "ops" }, "attrs": {}, "parents": [] },
+                { "uid": { "type": "Team", "id": "audit" }, "attrs": {}, "parents": [] },
+            ]),
+            None,
+        )
+        .expect("valid entities");
+        let catalog =
+            TemplateCatalog::new(&policies, Some(&schema()), Some(&entities)).expect("valid");
+        assert_eq!(
+            catalog.to_json(),
+            serde_json::json!([
+                {
+                    "id": "policy1",
+                    "effect": "permit",
+                    "description": "Let a team withdraw from a vault",
+                    "slots": [
+                        {
+                            "slot": "?principal",
+                            "operator": "in",
+                            "entityTypes": ["Account", "Team"],
+                            "description": "The team",
+                            "examples": [r#"Team::"ops""#, r#"Team::"treasury""#, r#"Team::"audit""#]
+                        },
+                        {
+                            "slot": "?resource",
+                            "operator": "==",
+                            "entityTypes": ["Vault"],
+                            "description": "The vault",
+                            "examples": [r#"Vault::"main""#]
+                        }
+                    ]
+                },
+                {
+                    "id": "policy2",
+                    "effect": "forbid",
+                    "description": null,
+                    "slots": [
+                        {
+                            "slot": "?principal",
+                            "operator": "==",
+                            "entityTypes": ["Account"],
+                            "description": null,
+                            "examples": []
+                        }
+                    ]
+                }
+            ])
+        );
+
+        let catalog = TemplateCatalog::new(&policies, None, Some(&entities)).expect("valid");
+        let slot = &catalog.template("policy1").expect("a template").slots[0];
+        assert!(slot.entity_types.is_empty());
+        assert_eq!(slot.examples, [r#"Team::"ops""#, r#"Team::"treasury""#]);
+    }
+
+    #[test]
+    fn rejects_bad_examples() {
+        for examples in ["not a uid", r#"Vault::\"main\""#] {
+            let policies = policies(&format!(
+                r#"@principal_examples("{examples}")
+                permit(principal == ?principal, action == Action::"withdraw", resource);"#
+            ));
+            assert!(matches!(
+                TemplateCatalog::new(&policies, Some(&schema()), None),
+                Err(CatalogError::Annotation { .. })
+            ));
+        }
+    }
+}
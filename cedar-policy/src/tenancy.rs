@@ -0,0 +1,599 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Policies, templates and entities partitioned by tenant, so that a hosted
+//! policy service can serve many customers from one process.
+//!
+//! A [`TenantStore`] holds a policy set and an entity store for each tenant,
+//! and every operation on it takes the [`TenantHandle`] of the tenant it acts
+//! on, which only the store hands out. The entity types of a tenant are those
+//! in its namespace: the namespace whose first component is the [`TenantId`],
+//! like `acme::User` or `acme::Action` for the tenant `acme`. Any policy,
+//! template, link, entity or request naming an entity of another namespace is
+//! rejected with [`TenancyError::CrossTenantReference`], so no tenant can
+//! write policies about, or be authorized against, the entities of another.
+//!
+//! ```
+//! # use cedar_policy::tenancy::*;
+//! # use cedar_policy::{Authorizer, Context, Decision, EntityUid, Policy, Request};
+//! # use std::str::FromStr;
+//! let mut store = TenantStore::new();
+//! let acme = store.create_tenant("acme".parse().unwrap()).unwrap();
+//! let globex = store.create_tenant("globex".parse().unwrap()).unwrap();
+//!
+//! let policy = Policy::parse(
+//!     Some("transfers".into()),
+//!     r#"permit(principal == acme::User::"alice", action == acme::Action::"transfer", resource);"#,
+//! )
+//! .unwrap();
+//! store.add_policy(&acme, policy.clone()).unwrap();
+//! assert!(matches!(
+//!     store.add_policy(&globex, policy),
+//!     Err(TenancyError::CrossTenantReference { .. })
+//! ));
+//!
+//! let request = Request::new(
+//!     Some(EntityUid::from_str(r#"acme::User::"alice""#).unwrap()),
+//!     Some(EntityUid::from_str(r#"acme::Action::"transfer""#).unwrap()),
+//!     Some(EntityUid::from_str(r#"acme::Wallet::"treasury""#).unwrap()),
+//!     Context::empty(),
+//! );
+//! let authorizer = Authorizer::new();
+//! assert!(matches!(
+//!     store.is_authorized(&acme, &authorizer, &request),
+//!     Ok(response) if response.decision() == Decision::Allow
+//! ));
+//! // the request names entities of `acme`, so it is rejected for `globex`
+//! assert!(store.is_authorized(&globex, &authorizer, &request).is_err());
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use cedar_policy_core::ast::{self, ExprKind, Literal};
+use smol_str::SmolStr;
+use thiserror::Error;
+
+use crate::{
+    Authorizer, Entities, EntitiesError, Entity, EntityUid, Policy, PolicyId, PolicySet,
+    PolicySetError, Request, Response, SlotId, Template,
+};
+
+/// Errors of a [`TenantStore`]
+#[derive(Debug, Error)]
+pub enum TenancyError {
+    /// A tenant id is not an identifier
+    #[error("`{0}` is not a valid tenant id: it must be an identifier")]
+    InvalidTenantId(String),
+    /// A tenant with the id already exists
+    #[error("tenant `{0}` already exists")]
+    DuplicateTenant(TenantId),
+    /// The tenant of a handle has been removed
+    #[error("tenant `{0}` does not exist")]
+    UnknownTenant(TenantId),
+    /// A policy, template, link, entity or request of a tenant names an
+    /// entity outside of the namespace of the tenant
+    #[error("`{uid}` is not an entity of tenant `{tenant}`")]
+    CrossTenantReference {
+        /// The tenant
+        tenant: TenantId,
+        /// The entity outside of its namespace
+        uid: EntityUid,
+    },
+    /// A policy, template or link could not be added to the policy set of
+    /// the tenant
+    #[error(transparent)]
+    Policies(#[from] PolicySetError),
+    /// Entities could not be added to the entity store of the tenant
+    #[error(transparent)]
+    Entities(#[from] EntitiesError),
+}
+
+/// The id of a tenant, which is also the first component of the namespace of
+/// its entity types
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TenantId(SmolStr);
+
+impl TenantId {
+    /// The id as a string
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether `uid` is an entity of this tenant, in its namespace. The
+    /// unspecified entity belongs to every tenant.
+    fn owns(&self, uid: &ast::EntityUID) -> bool {
+        match uid.entity_type() {
+            ast::EntityType::Unspecified => true,
+            ast::EntityType::Concrete(name) => {
+                name.namespace_components().next().map(AsRef::as_ref) == Some(self.as_str())
+            }
+        }
+    }
+}
+
+impl FromStr for TenantId {
+    type Err = TenancyError;
+
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        id.parse::<ast::Id>()
+            .map(|_| Self(id.into()))
+            .map_err(|_| TenancyError::InvalidTenantId(id.to_string()))
+    }
+}
+
+impl Display for TenantId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The handle of a tenant of a [`TenantStore`], which every operation on the
+/// tenant takes.
+///
+/// Handles are only created by the store, for tenants which exist; one
+/// outliving the removal of its tenant is rejected with
+/// [`TenancyError::UnknownTenant`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TenantHandle {
+    id: TenantId,
+}
+
+impl TenantHandle {
+    /// The id of the tenant
+    pub fn id(&self) -> &TenantId {
+        &self.id
+    }
+}
+
+/// The policies and entities of one tenant
+#[derive(Debug, Clone, Default)]
+struct Tenant {
+    policies: PolicySet,
+    entities: Entities,
+}
+
+/// Policy sets and entity stores partitioned by tenant
+#[derive(Debug, Clone, Default)]
+pub struct TenantStore {
+    tenants: HashMap<TenantId, Tenant>,
+}
+
+impl TenantStore {
+    /// Create a store with no tenants
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a tenant with no policies or entities, returning its handle
+    ///
+    /// # Errors
+    ///
+    /// If there already is a tenant `id`.
+    pub fn create_tenant(&mut self, id: TenantId) -> Result<TenantHandle, TenancyError> {
+        if self.tenants.contains_key(&id) {
+            return Err(TenancyError::DuplicateTenant(id));
+        }
+        self.tenants.insert(id.clone(), Tenant::default());
+        Ok(TenantHandle { id })
+    }
+
+    /// The handle of the tenant `id`, if it exists
+    pub fn tenant(&self, id: &TenantId) -> Option<TenantHandle> {
+        self.tenants
+            .contains_key(id)
+            .then(|| TenantHandle { id: id.clone() })
+    }
+
+    /// The ids of the tenants, in no particular order
+    pub fn tenants(&self) -> impl Iterator<Item = &TenantId> {
+        self.tenants.keys()
+    }
+
+    /// Remove a tenant, with its policies and entities
+    ///
+    /// # Errors
+    ///
+    /// If the tenant was already removed.
+    pub fn remove_tenant(&mut self, tenant: TenantHandle) -> Result<(), TenancyError> {
+        self.tenants
+            .remove(&tenant.id)
+            .map(|_| ())
+            .ok_or(TenancyError::UnknownTenant(tenant.id))
+    }
+
+    /// The policy set of a tenant
+    ///
+    /// # Errors
+    ///
+    /// If the tenant was removed.
+    pub fn policies(&self, tenant: &TenantHandle) -> Result<&PolicySet, TenancyError> {
+        Ok(&self.get(tenant)?.policies)
+    }
+
+    /// The entities of a tenant
+    ///
+    /// # Errors
+    ///
+    /// If the tenant was removed.
+    pub fn entities(&self, tenant: &TenantHandle) -> Result<&Entities, TenancyError> {
+        Ok(&self.get(tenant)?.entities)
+    }
+
+    /// Add a static or template-linked policy to a tenant
+    ///
+    /// # Errors
+    ///
+    /// If the policy names an entity of another tenant, or cannot be added to
+    /// the policy set of the tenant, such as for having the id of another.
+    pub fn add_policy(
+        &mut self,
+        tenant: &TenantHandle,
+        policy: Policy,
+    ) -> Result<(), TenancyError> {
+        let ast = policy.ast();
+        check(
+            &tenant.id,
+            template_uids(ast.template()).chain(ast.env().values()),
+        )?;
+        self.get_mut(tenant)?.policies.add(policy)?;
+        Ok(())
+    }
+
+    /// Add a template to a tenant
+    ///
+    /// # Errors
+    ///
+    /// If the template names an entity of another tenant, or cannot be added
+    /// to the policy set of the tenant.
+    pub fn add_template(
+        &mut self,
+        tenant: &TenantHandle,
+        template: Template,
+    ) -> Result<(), TenancyError> {
+        check(&tenant.id, template_uids(template.ast()))?;
+        self.get_mut(tenant)?.policies.add_template(template)?;
+        Ok(())
+    }
+
+    /// Link a template of a tenant, as [`PolicySet::link`] does
+    ///
+    /// # Errors
+    ///
+    /// If a value in `vals` is an entity of another tenant, or the link fails
+    /// as described for [`PolicySet::link`].
+    pub fn link(
+        &mut self,
+        tenant: &TenantHandle,
+        template_id: PolicyId,
+        new_id: PolicyId,
+        vals: HashMap<SlotId, EntityUid>,
+    ) -> Result<(), TenancyError> {
+        check(&tenant.id, vals.values().map(|uid| &uid.0))?;
+        self.get_mut(tenant)?
+            .policies
+            .link(template_id, new_id, vals)?;
+        Ok(())
+    }
+
+    /// Replace the entities of a tenant
+    ///
+    /// # Errors
+    ///
+    /// If any of the entities, their ancestors, or an entity in their
+    /// attributes or tags, is an entity of another tenant.
+    pub fn set_entities(
+        &mut self,
+        tenant: &TenantHandle,
+        entities: Entities,
+    ) -> Result<(), TenancyError> {
+        for entity in entities.iter() {
+            let ancestors = entities.transitive_ancestors(entity).map(|uid| &uid.0);
+            check_entity(&tenant.id, entity, ancestors)?;
+        }
+        self.get_mut(tenant)?.entities = entities;
+        Ok(())
+    }
+
+    /// Add entities to those of a tenant, as [`Entities::add_entities`] does
+    ///
+    /// # Errors
+    ///
+    /// If any of the entities names an entity of another tenant, as for
+    /// [`Self::set_entities`], or they cannot be added, such as for being
+    /// there already. The entities of the tenant are then left as they were.
+    pub fn add_entities(
+        &mut self,
+        tenant: &TenantHandle,
+        entities: impl IntoIterator<Item = Entity>,
+    ) -> Result<(), TenancyError> {
+        let entities = entities
+            .into_iter()
+            // Checking the parents the entities record is enough: any other
+            // ancestor is an ancestor of an entity of the tenant, so checked
+            // already, or of another of these entities
+            .map(|entity| check_entity(&tenant.id, &entity, entity.0.ancestors()).map(|()| entity))
+            .collect::<Result<Vec<_>, _>>()?;
+        let state = self.get_mut(tenant)?;
+        state.entities = state.entities.clone().add_entities(entities)?;
+        Ok(())
+    }
+
+    /// Authorize `request` against the policies and entities of a tenant
+    ///
+    /// # Errors
+    ///
+    /// If the principal, action or resource of the request, or an entity in
+    /// its context, is an entity of another tenant.
+    pub fn is_authorized(
+        &self,
+        tenant: &TenantHandle,
+        authorizer: &Authorizer,
+        request: &Request,
+    ) -> Result<Response, TenancyError> {
+        let state = self.get(tenant)?;
+        let scope = [request.principal(), request.action(), request.resource()]
+            .into_iter()
+            .flatten()
+            .map(|uid| &uid.0);
+        let context = request
+            .0
+            .context()
+            .into_iter()
+            .flat_map(ast::Context::iter)
+            .flat_map(|(_, value)| literal_uids(value.into()));
+        check(&tenant.id, scope.chain(context))?;
+        Ok(authorizer.is_authorized(request, &state.policies, &state.entities))
+    }
+
+    fn get(&self, tenant: &TenantHandle) -> Result<&Tenant, TenancyError> {
+        self.tenants
+            .get(&tenant.id)
+            .ok_or_else(|| TenancyError::UnknownTenant(tenant.id.clone()))
+    }
+
+    fn get_mut(&mut self, tenant: &TenantHandle) -> Result<&mut Tenant, TenancyError> {
+        self.tenants
+            .get_mut(&tenant.id)
+            .ok_or_else(|| TenancyError::UnknownTenant(tenant.id.clone()))
+    }
+}
+
+/// Fail on the first of `uids` which is not an entity of `tenant`
+fn check<'a>(
+    tenant: &TenantId,
+    uids: impl IntoIterator<Item = &'a ast::EntityUID>,
+) -> Result<(), TenancyError> {
+    uids.into_iter()
+        .find(|uid| !tenant.owns(uid))
+        .map_or(Ok(()), |uid| {
+            Err(TenancyError::CrossTenantReference {
+                tenant: tenant.clone(),
+                uid: EntityUid(uid.clone()),
+            })
+        })
+}
+
+/// Fail if `entity`, one of `ancestors`, or an entity in its attributes or
+/// tags, is not an entity of `tenant`
+fn check_entity<'a>(
+    tenant: &TenantId,
+    entity: &Entity,
+    ancestors: impl Iterator<Item = &'a ast::EntityUID>,
+) -> Result<(), TenancyError> {
+    let uid = entity.0.uid();
+    let values = entity.0.attrs().chain(entity.0.tags());
+    check(tenant, [&uid])?;
+    check(tenant, ancestors)?;
+    check(
+        tenant,
+        values.flat_map(|(_, value)| literal_uids(value.into())),
+    )
+}
+
+/// The entities named in the scope and conditions of `template`
+fn template_uids(template: &ast::Template) -> impl Iterator<Item = &ast::EntityUID> {
+    template
+        .principal_constraint()
+        .as_inner()
+        .iter_euids()
+        .chain(template.action_constraint().iter_euids())
+        .chain(template.resource_constraint().as_inner().iter_euids())
+        .chain(literal_uids(template.non_head_constraints()))
+}
+
+/// The entity literals of `expr`
+fn literal_uids(expr: &ast::Expr) -> impl Iterator<Item = &ast::EntityUID> {
+    expr.subexpressions()
+        .filter_map(|expr| match expr.expr_kind() {
+            ExprKind::Lit(Literal::EntityUID(uid)) => Some(uid.as_ref()),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Context, Decision, EntitiesOptions, RestrictedExpression, TcComputation};
+
+    fn uid(src: &str) -> EntityUid {
+        EntityUid::from_str(src).expect("valid UID")
+    }
+
+    fn store() -> (TenantStore, TenantHandle, TenantHandle) {
+        let mut store = TenantStore::new();
+        let acme = store
+            .create_tenant("acme".parse().expect("valid id"))
+            .expect("new tenant");
+        let globex = store
+            .create_tenant("globex".parse().expect("valid id"))
+            .expect("new tenant");
+        (store, acme, globex)
+    }
+
+    #[test]
+    fn tenant_ids() {
+        assert!("acme".parse::<TenantId>().is_ok());
+        assert!(matches!(
+            "acme::corp".parse::<TenantId>(),
+            Err(TenancyError::InvalidTenantId(_))
+        ));
+        assert!("".parse::<TenantId>().is_err());
+
+        let (mut store, acme, _) = store();
+        assert!(matches!(
+            store.create_tenant(acme.id().clone()),
+            Err(TenancyError::DuplicateTenant(_))
+        ));
+        assert_eq!(store.tenant(acme.id()), Some(acme.clone()));
+        store.remove_tenant(acme.clone()).expect("exists");
+        assert!(store.tenant(acme.id()).is_none());
+        assert!(matches!(
+            store.policies(&acme),
+            Err(TenancyError::UnknownTenant(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_cross_tenant_references() {
+        let (mut store, acme, globex) = store();
+        let condition = Policy::parse(
+            None,
+            r#"permit(principal, action, resource) when { resource in globex::Vault::"main" };"#,
+        )
+        .expect("valid policy");
+        assert!(matches!(
+            store.add_policy(&acme, condition.clone()),
+            Err(TenancyError::CrossTenantReference { uid: other, .. }) if other == uid(r#"globex::Vault::"main""#)
+        ));
+        store.add_policy(&globex, condition).expect("own entity");
+
+        let template = Template::parse(
+            Some("owners".into()),
+            r#"permit(principal == ?principal, action == acme::Action::"withdraw", resource);"#,
+        )
+        .expect("valid template");
+        assert!(store.add_template(&globex, template.clone()).is_err());
+        store.add_template(&acme, template).expect("own action");
+        let link = |principal: &str| HashMap::from([(SlotId::principal(), uid(principal))]);
+        assert!(store
+            .link(
+                &acme,
+                PolicyId::from_str("owners").expect("valid id"),
+                PolicyId::from_str("bob").expect("valid id"),
+                link(r#"globex::User::"bob""#),
+            )
+            .is_err());
+        store
+            .link(
+                &acme,
+                PolicyId::from_str("owners").expect("valid id"),
+                PolicyId::from_str("alice").expect("valid id"),
+                link(r#"acme::User::"alice""#),
+            )
+            .expect("own principal");
+        assert_eq!(store.policies(&acme).expect("exists").policies().count(), 1);
+        assert_eq!(
+            store.policies(&globex).expect("exists").policies().count(),
+            1
+        );
+
+        let alice = Entity::new(
+            uid(r#"acme::User::"alice""#),
+            HashMap::from([(
+                "backup".to_string(),
+                RestrictedExpression::from(uid(r#"globex::User::"bob""#)),
+            )]),
+            Default::default(),
+        );
+        assert!(store.add_entities(&acme, [alice]).is_err());
+        let alice = Entity::new(
+            uid(r#"acme::User::"alice""#),
+            HashMap::new(),
+            [uid(r#"acme::Group::"admins""#)].into_iter().collect(),
+        );
+        store
+            .add_entities(&acme, [alice.clone()])
+            .expect("own entities");
+        assert!(store.add_entities(&globex, [alice]).is_err());
+        assert!(store
+            .entities(&globex)
+            .expect("exists")
+            .iter()
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn rejects_cross_tenant_ancestors_with_lazy_tc() {
+        let (mut store, acme, _) = store();
+        let entity = |src: &str, parent: &str| {
+            Entity::new(
+                uid(src),
+                HashMap::new(),
+                [uid(parent)].into_iter().collect(),
+            )
+        };
+        let entities = Entities::from_entities_with_options(
+            [
+                entity(r#"acme::User::"alice""#, r#"acme::Group::"admins""#),
+                entity(r#"acme::Group::"admins""#, r#"globex::Group::"staff""#),
+            ],
+            EntitiesOptions::new().with_tc_computation(TcComputation::ComputeLazily),
+        )
+        .expect("valid entities");
+        assert!(matches!(
+            store.set_entities(&acme, entities),
+            Err(TenancyError::CrossTenantReference { uid: other, .. }) if other == uid(r#"globex::Group::"staff""#)
+        ));
+    }
+
+    #[test]
+    fn authorizes_within_tenant() {
+        let (mut store, acme, globex) = store();
+        let policy = Policy::parse(
+            None,
+            r#"permit(principal, action == acme::Action::"withdraw", resource);"#,
+        )
+        .expect("valid policy");
+        store.add_policy(&acme, policy).expect("own action");
+        let authorizer = Authorizer::new();
+        let request = |context: Context| {
+            Request::new(
+                Some(uid(r#"acme::User::"alice""#)),
+                Some(uid(r#"acme::Action::"withdraw""#)),
+                Some(uid(r#"acme::Vault::"main""#)),
+                context,
+            )
+        };
+        let response = store
+            .is_authorized(&acme, &authorizer, &request(Context::empty()))
+            .expect("own request");
+        assert_eq!(response.decision(), Decision::Allow);
+        assert!(matches!(
+            store.is_authorized(&globex, &authorizer, &request(Context::empty())),
+            Err(TenancyError::CrossTenantReference { .. })
+        ));
+        let context = Context::from_pairs([(
+            "approver".to_string(),
+            RestrictedExpression::from(uid(r#"globex::User::"bob""#)),
+        )]);
+        assert!(store
+            .is_authorized(&acme, &authorizer, &request(context))
+            .is_err());
+    }
+}
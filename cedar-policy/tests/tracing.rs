@@ -0,0 +1,193 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Integration tests for the spans emitted with the `tracing` feature
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use cedar_policy::{Authorizer, Context, Entities, EntityUid, PolicySet, Request};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// A span, with the values recorded for its fields
+#[derive(Debug)]
+struct RecordedSpan {
+    name: &'static str,
+    fields: BTreeMap<String, String>,
+}
+
+/// A subscriber which records every span
+#[derive(Debug, Default)]
+struct Recorder {
+    spans: Mutex<Vec<RecordedSpan>>,
+}
+
+impl Recorder {
+    /// The fields of the first span named `name`
+    fn fields(&self, name: &str) -> BTreeMap<String, String> {
+        self.spans
+            .lock()
+            .map(|spans| {
+                spans
+                    .iter()
+                    .find(|span| span.name == name)
+                    .map(|span| span.fields.clone())
+                    .unwrap_or_default()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The names of the spans, in the order they were created
+    fn names(&self) -> Vec<&'static str> {
+        self.spans
+            .lock()
+            .map(|spans| spans.iter().map(|span| span.name).collect())
+            .unwrap_or_default()
+    }
+}
+
+struct FieldVisitor<'a>(&'a mut BTreeMap<String, String>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{value:?}"));
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = BTreeMap::new();
+        span.record(&mut FieldVisitor(&mut fields));
+        let mut spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        spans.push(RecordedSpan {
+            name: span.metadata().name(),
+            fields,
+        });
+        // ids must be nonzero, so span `n` has the id `n + 1`
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, id: &Id, values: &Record<'_>) {
+        let mut spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        let index = usize::try_from(id.into_u64() - 1).unwrap_or(usize::MAX);
+        if let Some(span) = spans.get_mut(index) {
+            values.record(&mut FieldVisitor(&mut span.fields));
+        }
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, _: &Event<'_>) {}
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+/// Authorize `Address::"0xabc"` doing `Action::"transfer"` with the
+/// `policies`, recording the spans
+fn authorize(policies: &str) -> Result<Arc<Recorder>, Box<dyn std::error::Error>> {
+    let recorder = Arc::new(Recorder::default());
+    tracing::subscriber::with_default(Arc::clone(&recorder), || {
+        let policies = PolicySet::from_str(policies)?;
+        let entities = Entities::from_json_str(
+            r#"[{ "uid": { "type": "Address", "id": "0xabc" }, "attrs": { "balance": 10 }, "parents": [] }]"#,
+            None,
+        )?;
+        let request = Request::new(
+            Some(EntityUid::from_str(r#"Address::"0xabc""#)?),
+            Some(EntityUid::from_str(r#"Action::"transfer""#)?),
+            Some(EntityUid::from_str(r#"Contract::"0xdef""#)?),
+            Context::empty(),
+        );
+        Authorizer::new().is_authorized(&request, &policies, &entities);
+        Ok::<_, Box<dyn std::error::Error>>(())
+    })?;
+    Ok(recorder)
+}
+
+#[test]
+fn spans_for_each_stage() {
+    let recorder = authorize(
+        r#"permit(principal, action == Action::"transfer", resource) when { principal.balance > 5 };"#,
+    )
+    .expect("request should be authorized");
+    let names = recorder.names();
+    for name in [
+        "parse_policies",
+        "parse_entities",
+        "build_entities",
+        "is_authorized",
+        "evaluate_entity_attrs",
+        "evaluate_policy",
+    ] {
+        assert!(names.contains(&name), "missing span {name} in {names:?}");
+    }
+
+    let authorization = recorder.fields("is_authorized");
+    assert_eq!(
+        authorization.get("principal").map(String::as_str),
+        Some(r#"Address::"0xabc""#)
+    );
+    assert_eq!(
+        authorization.get("decision").map(String::as_str),
+        Some("Allow")
+    );
+    assert_eq!(
+        authorization.get("reasons").map(String::as_str),
+        Some("policy0")
+    );
+    assert_eq!(authorization.get("errors").map(String::as_str), Some("0"));
+
+    let evaluation = recorder.fields("evaluate_policy");
+    assert_eq!(
+        evaluation.get("policy_id").map(String::as_str),
+        Some("policy0")
+    );
+    assert_eq!(evaluation.get("effect").map(String::as_str), Some("permit"));
+    assert_eq!(evaluation.get("result").map(String::as_str), Some("true"));
+}
+
+#[test]
+fn span_records_policy_error() {
+    let recorder =
+        authorize(r#"forbid(principal, action, resource) when { principal.nonce > 5 };"#)
+            .expect("request should be authorized");
+    let evaluation = recorder.fields("evaluate_policy");
+    assert_eq!(evaluation.get("result").map(String::as_str), Some("error"));
+    assert!(evaluation
+        .get("error")
+        .is_some_and(|error| error.contains("nonce")));
+    assert_eq!(
+        recorder
+            .fields("is_authorized")
+            .get("errors")
+            .map(String::as_str),
+        Some("1")
+    );
+}
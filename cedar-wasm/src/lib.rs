@@ -0,0 +1,68 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! JavaScript bindings to the JSON interface of `cedar_policy::frontend`, for
+//! building with `wasm-pack` and evaluating policies in browsers and at the
+//! edge with the same engine, and the same `u256` and `address` extensions,
+//! as a backend.
+//!
+//! Each function takes a JSON string in the format of the corresponding
+//! `frontend` function and returns the JSON of its
+//! [`InterfaceResult`](cedar_policy::frontend::utils::InterfaceResult): an
+//! object with `"success": "true"` and the JSON of the answer in `result`, or
+//! with `"success": "false"` and `isInternal` and `errors`.
+#![forbid(unsafe_code)]
+#![warn(rust_2018_idioms, clippy::pedantic, clippy::nursery)]
+#![deny(missing_docs, missing_debug_implementations)]
+#![allow(clippy::must_use_candidate)]
+
+use cedar_policy::frontend::{
+    is_authorized::json_is_authorized, parse::json_parse, utils::InterfaceResult,
+    validate::json_validate,
+};
+use wasm_bindgen::prelude::*;
+
+/// The JSON of `result`
+fn to_json(result: &InterfaceResult) -> String {
+    serde_json::to_string(result).unwrap_or_else(|e| {
+        // serializing an `InterfaceResult` cannot fail, but report it in the
+        // same format if it does
+        format!(
+            r#"{{"success":"false","isInternal":true,"errors":["error serializing result: {e}"]}}"#
+        )
+    })
+}
+
+/// Check that a policy set parses, as described by
+/// `cedar_policy::frontend::parse::json_parse`
+#[wasm_bindgen]
+pub fn parse(input: &str) -> String {
+    to_json(&json_parse(input))
+}
+
+/// Validate a policy set against a schema, as described by
+/// `cedar_policy::frontend::validate::json_validate`
+#[wasm_bindgen]
+pub fn validate(input: &str) -> String {
+    to_json(&json_validate(input))
+}
+
+/// Authorize a request, as described by
+/// `cedar_policy::frontend::is_authorized::json_is_authorized`
+#[wasm_bindgen(js_name = isAuthorized)]
+pub fn is_authorized(input: &str) -> String {
+    to_json(&json_is_authorized(input))
+}
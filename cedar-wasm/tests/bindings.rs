@@ -0,0 +1,108 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use cedar_wasm::{is_authorized, parse, validate};
+use serde_json::{json, Value};
+
+const POLICIES: &str = r#"
+forbid (principal, action == Action::"0x095ea7b3", resource)
+when { context.args.arg_1.u256GreaterThanOrEqual(u256("0x8000000000000000000000000000000000000000000000000000000000000000")) };
+permit (principal, action, resource);
+"#;
+
+/// The `result` of a successful call
+fn result(output: &str) -> Value {
+    let output: Value = serde_json::from_str(output).unwrap_or_default();
+    assert_eq!(output.get("success"), Some(&json!("true")), "{output}");
+    output
+        .get("result")
+        .and_then(Value::as_str)
+        .and_then(|result| serde_json::from_str(result).ok())
+        .unwrap_or_default()
+}
+
+fn approval(amount: &str) -> String {
+    json!({
+        "principal": { "type": "Address", "id": "0x7c3250001bc0abeeef91f52e9054a9f951190132" },
+        "action": { "type": "Action", "id": "0x095ea7b3" },
+        "resource": { "type": "Address", "id": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48" },
+        "context": {
+            "args": {
+                "arg_0": { "__extn": { "fn": "address", "arg": "0x7c3250001bc0abeeef91f52e9054a9f951190132" } },
+                "arg_1": { "__extn": { "fn": "u256", "arg": amount } }
+            }
+        },
+        "slice": { "policies": POLICIES, "entities": [] }
+    })
+    .to_string()
+}
+
+#[test]
+fn authorizes_with_extensions() {
+    let allowed = result(&is_authorized(&approval("100")));
+    assert_eq!(allowed.pointer("/response/decision"), Some(&json!("Allow")));
+    let denied = result(&is_authorized(&approval(
+        "0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+    )));
+    assert_eq!(denied.pointer("/response/decision"), Some(&json!("Deny")));
+    assert_eq!(
+        denied.pointer("/response/diagnostics/reason"),
+        Some(&json!(["policy0"]))
+    );
+}
+
+#[test]
+fn parses_and_validates() {
+    let parsed = result(&parse(&json!({ "policySet": POLICIES }).to_string()));
+    assert_eq!(
+        parsed.pointer("/policies"),
+        Some(&json!(["policy0", "policy1"]))
+    );
+    let schema = json!({
+        "": {
+            "entityTypes": { "Address": {} },
+            "actions": {
+                "0x095ea7b3": {
+                    "appliesTo": {
+                        "principalTypes": ["Address"],
+                        "resourceTypes": ["Address"],
+                        "context": {
+                            "type": "Record",
+                            "attributes": {
+                                "args": {
+                                    "type": "Record",
+                                    "attributes": {
+                                        "arg_0": { "type": "Extension", "name": "address" },
+                                        "arg_1": { "type": "Extension", "name": "u256" }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    });
+    let validated = result(&validate(
+        &json!({ "schema": schema, "policySet": POLICIES }).to_string(),
+    ));
+    assert_eq!(validated.pointer("/notes"), Some(&json!([])));
+
+    let failed: Value =
+        serde_json::from_str(&parse(&json!({ "policySet": "permit(" }).to_string())).unwrap();
+    assert_eq!(failed.pointer("/success"), Some(&json!("false")));
+    assert_eq!(failed.pointer("/isInternal"), Some(&json!(false)));
+}